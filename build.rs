@@ -7,4 +7,20 @@ fn main() {
             "multicast-paging-utility.gresource",
         );
     }
+
+    // Embed the commit for `capabilities`; left unset outside a git checkout
+    let hash = std::process::Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+    if let Some(hash) = hash {
+        println!("cargo:rustc-env=MPU_GIT_HASH={}", hash);
+        for path in [".git/HEAD", ".git/refs"] {
+            if std::path::Path::new(path).exists() {
+                println!("cargo:rerun-if-changed={}", path);
+            }
+        }
+    }
 }
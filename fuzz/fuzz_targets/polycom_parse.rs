@@ -0,0 +1,21 @@
+//! `PolycomPacket::parse` on arbitrary datagrams: it must return, never
+//! panic. Run with `cargo +nightly fuzz run polycom_parse` from the
+//! repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::net::{Ipv4Addr, SocketAddr};
+
+// The utility is a binary crate, so the parsers are built in from their
+// source, as siblings the way they are in src/network
+#[allow(dead_code)]
+#[path = "../../src/network/polycom.rs"]
+mod polycom;
+
+fuzz_target!(|data: &[u8]| {
+    let source = SocketAddr::from((Ipv4Addr::LOCALHOST, 5001));
+    if let Ok(packet) = polycom::PolycomPacket::parse(data, source) {
+        assert!(packet.audio_frame.map_or(0, |frame| frame.len()) <= data.len());
+    }
+});
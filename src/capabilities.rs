@@ -0,0 +1,237 @@
+//! Machine-readable description of what this build supports.
+//!
+//! Each module registers its own entries (codecs, protocols, payload types,
+//! commands), so `capabilities` and `--version --json` stay in step with the
+//! code instead of a hand-maintained list. Consumers should key on
+//! `schema_version`: fields may be added within a version, but never renamed
+//! or removed.
+
+use crate::codec::subprocess::CodecSupport;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Bumped when an existing field changes meaning or is removed
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Capabilities of the running binary
+#[derive(Debug, Serialize)]
+pub struct Capabilities {
+    pub schema_version: u32,
+    pub name: &'static str,
+    pub version: &'static str,
+    /// Commit the binary was built from, when built from a git checkout
+    pub git_hash: Option<&'static str>,
+    /// Optional cargo features compiled in
+    pub build_features: Vec<&'static str>,
+    pub platform: Platform,
+    /// External tools found at runtime
+    pub dependencies: BTreeMap<&'static str, bool>,
+    /// What the ffmpeg found can decode and encode, by ffmpeg codec name
+    pub ffmpeg_codecs: BTreeMap<&'static str, CodecSupport>,
+    pub codecs: Vec<CodecCapability>,
+    pub protocols: Vec<ProtocolCapability>,
+    pub payload_types: Vec<PayloadTypeCapability>,
+    pub commands: Vec<CommandCapability>,
+    /// Flags accepted by every command
+    pub global_flags: Vec<String>,
+    /// Features that behave differently or are missing on this platform
+    pub limitations: Vec<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Platform {
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub family: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CodecCapability {
+    /// Name accepted by `--codec`
+    pub name: &'static str,
+    pub description: &'static str,
+    pub sample_rate: u32,
+    pub channels: u8,
+    pub rtp_clock_rate: u32,
+    /// Whether rate and channels can be set with `name:rate:channels`
+    pub configurable: bool,
+    /// External tools the codec needs at runtime
+    pub requires: Vec<&'static str>,
+    /// Whether an encoder could be created just now
+    pub can_encode: bool,
+    /// Whether a decoder could be created just now
+    pub can_decode: bool,
+    /// Whether an encoder and decoder could be created just now
+    pub usable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unavailable_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProtocolCapability {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Commands that send or receive this protocol
+    pub commands: Vec<&'static str>,
+    /// Codec names usable with this protocol
+    pub codecs: Vec<&'static str>,
+}
+
+/// A run of RTP payload types that decode with the same codec
+#[derive(Debug, Serialize)]
+pub struct PayloadTypeCapability {
+    pub first: u8,
+    pub last: u8,
+    pub codec: &'static str,
+    /// Dynamic types are assumed to be this codec unless `--codec` is given
+    pub dynamic: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommandCapability {
+    pub name: String,
+    pub about: String,
+    /// Long flags grouped by feature (their help heading)
+    pub flags: BTreeMap<String, Vec<String>>,
+}
+
+impl Capabilities {
+    /// Empty registry for this build and platform
+    pub fn new() -> Self {
+        let mut build_features = Vec::new();
+        if cfg!(feature = "gui") {
+            build_features.push("gui");
+        }
+        Self {
+            schema_version: SCHEMA_VERSION,
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            git_hash: option_env!("MPU_GIT_HASH"),
+            build_features,
+            platform: Platform {
+                os: std::env::consts::OS,
+                arch: std::env::consts::ARCH,
+                family: std::env::consts::FAMILY,
+            },
+            dependencies: BTreeMap::new(),
+            ffmpeg_codecs: BTreeMap::new(),
+            codecs: Vec::new(),
+            protocols: Vec::new(),
+            payload_types: Vec::new(),
+            commands: Vec::new(),
+            global_flags: Vec::new(),
+            limitations: Vec::new(),
+        }
+    }
+
+    /// Registry populated by every module
+    pub fn collect() -> Self {
+        let mut capabilities = Self::new();
+        crate::codec::register_capabilities(&mut capabilities);
+        crate::network::register_capabilities(&mut capabilities);
+        crate::cli::register_capabilities(&mut capabilities);
+        capabilities
+    }
+
+    pub fn add_dependency(&mut self, name: &'static str, available: bool) {
+        self.dependencies.insert(name, available);
+    }
+
+    pub fn add_ffmpeg_codec(&mut self, name: &'static str, support: CodecSupport) {
+        self.ffmpeg_codecs.insert(name, support);
+    }
+
+    pub fn add_codec(&mut self, codec: CodecCapability) {
+        self.codecs.push(codec);
+    }
+
+    pub fn add_protocol(&mut self, protocol: ProtocolCapability) {
+        self.protocols.push(protocol);
+    }
+
+    /// Record that `payload_type` decodes as `codec`, merging adjacent types
+    pub fn add_payload_type(&mut self, payload_type: u8, codec: &'static str, dynamic: bool) {
+        if let Some(last) = self.payload_types.last_mut() {
+            if last.codec == codec && last.dynamic == dynamic && last.last.checked_add(1) == Some(payload_type) {
+                last.last = payload_type;
+                return;
+            }
+        }
+        self.payload_types.push(PayloadTypeCapability {
+            first: payload_type,
+            last: payload_type,
+            codec,
+            dynamic,
+        });
+    }
+
+    pub fn add_command(&mut self, command: CommandCapability) {
+        self.commands.push(command);
+    }
+
+    pub fn add_limitation(&mut self, limitation: &'static str) {
+        self.limitations.push(limitation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(value: &serde_json::Value) -> Vec<&str> {
+        value.as_object().unwrap().keys().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn test_schema_is_stable() {
+        let value = serde_json::to_value(Capabilities::collect()).unwrap();
+        assert_eq!(
+            keys(&value),
+            [
+                "build_features",
+                "codecs",
+                "commands",
+                "dependencies",
+                "ffmpeg_codecs",
+                "git_hash",
+                "global_flags",
+                "limitations",
+                "name",
+                "payload_types",
+                "platform",
+                "protocols",
+                "schema_version",
+                "version",
+            ]
+        );
+        assert_eq!(value["schema_version"], 1);
+
+        let g722 = value["codecs"].as_array().unwrap().iter().find(|c| c["name"] == "g722").unwrap();
+        for key in ["name", "description", "sample_rate", "channels", "rtp_clock_rate", "configurable", "requires", "can_encode", "can_decode", "usable"] {
+            assert!(g722.get(key).is_some(), "codec entry is missing {}", key);
+        }
+        assert_eq!(g722["requires"], serde_json::json!(["ffmpeg"]));
+        assert_eq!(g722["can_encode"], value["ffmpeg_codecs"]["g722"]["encode"]);
+        assert_eq!(g722["can_decode"], value["ffmpeg_codecs"]["g722"]["decode"]);
+
+        let polycom = value["protocols"].as_array().unwrap().iter().find(|p| p["name"] == "polycom").unwrap();
+        assert_eq!(keys(polycom), ["codecs", "commands", "description", "name"]);
+
+        let transmit = value["commands"].as_array().unwrap().iter().find(|c| c["name"] == "transmit").unwrap();
+        assert!(transmit["flags"]["Impairments"].as_array().unwrap().contains(&"--drop-percent".into()));
+        assert!(transmit["flags"]["Options"].as_array().unwrap().contains(&"--file".into()));
+    }
+
+    #[test]
+    fn test_payload_types_are_merged() {
+        let mut capabilities = Capabilities::new();
+        capabilities.add_payload_type(0, "g711ulaw", false);
+        capabilities.add_payload_type(10, "l16", false);
+        capabilities.add_payload_type(11, "l16", false);
+        for pt in 96..=127 {
+            capabilities.add_payload_type(pt, "opus", true);
+        }
+        let runs: Vec<_> = capabilities.payload_types.iter().map(|p| (p.first, p.last, p.codec)).collect();
+        assert_eq!(runs, [(0, 0, "g711ulaw"), (10, 11, "l16"), (96, 127, "opus")]);
+    }
+}
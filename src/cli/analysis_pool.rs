@@ -0,0 +1,313 @@
+//! Running the audio analyzer's FFTs off the receive loop.
+//!
+//! Every analysis window of every active endpoint takes a 512-point FFT,
+//! which with dozens of endpoints costs the receive loop more than anything
+//! but decoding. With `--analysis-threads`, the per-sample figures (level,
+//! peak, zero crossings, clipping, glitches) are still worked out as each
+//! packet arrives, but the windows are copied out and sent in batches to a
+//! pool of worker threads. Each endpoint is pinned to one worker, which
+//! takes batches in the order they were sent, and the analyzer adds the
+//! results in that order, so a page's stats come out exactly as inline
+//! analysis leaves them. The last batch is waited for when the page ends;
+//! until then the live dominant frequency runs up to a batch behind.
+
+use crate::cli::audio_analyzer::{FftEngine, FftFrame, FFT_SIZE};
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::warn;
+
+/// Windows sent to a worker at a time. At 8kHz this holds the live
+/// dominant frequency back about 130ms
+pub const BATCH_WINDOWS: usize = 8;
+
+/// Default `--analysis-threads`: half the cores, and no more than there are
+/// endpoints to keep busy. A single core leaves FFTs inline
+pub fn default_threads(endpoints: usize) -> usize {
+    let cores = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    (cores / 2).min(endpoints)
+}
+
+/// Worker threads running FFTs for the endpoints' analyzers. With no
+/// threads there are no workers, and analysis stays inline
+pub struct AnalysisPool {
+    workers: Vec<Sender<FftBatch>>,
+    /// Round-robin position for the next endpoint
+    next: AtomicUsize,
+}
+
+impl AnalysisPool {
+    pub fn new(threads: usize) -> Self {
+        let workers = (0..threads)
+            .filter_map(|i| {
+                let (jobs, batches) = crossbeam_channel::unbounded();
+                let spawned = std::thread::Builder::new()
+                    .name(format!("analysis-{}", i))
+                    .spawn(move || run_worker(&batches));
+                match spawned {
+                    Ok(_) => Some(jobs),
+                    Err(e) => {
+                        warn!("Unable to start analysis thread: {}", e);
+                        None
+                    }
+                }
+            })
+            .collect();
+        Self { workers, next: AtomicUsize::new(0) }
+    }
+
+    /// The worker for a new endpoint, taking them in turn; `None` when
+    /// analysis is inline
+    pub fn worker(&self) -> Option<AnalysisWorker> {
+        if self.workers.is_empty() {
+            return None;
+        }
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        Some(AnalysisWorker { jobs: self.workers[index].clone() })
+    }
+}
+
+/// One endpoint's way to its worker thread. The thread stops once the pool
+/// and every worker handle are gone
+#[derive(Clone)]
+pub struct AnalysisWorker {
+    jobs: Sender<FftBatch>,
+}
+
+/// Windows for a worker to FFT, and where to send what it finds
+struct FftBatch {
+    /// Windows end to end, each oldest sample first
+    samples: Vec<i16>,
+    /// Whether each window's frame was voiced
+    voiced: Vec<bool>,
+    sample_rate: u32,
+    min_frequency_hz: f64,
+    results: Sender<FftBatchResult>,
+}
+
+/// A batch's FFTs, in the order its windows were queued
+#[derive(Debug)]
+pub struct FftBatchResult {
+    pub frames: Vec<FftFrame>,
+    /// Scaled magnitudes of the batch's FFTs, added up
+    pub spectrum_sum: Vec<f32>,
+}
+
+fn run_worker(batches: &Receiver<FftBatch>) {
+    let mut engine = FftEngine::new();
+    for batch in batches {
+        let mut spectrum_sum = vec![0.0; FFT_SIZE / 2];
+        let frames = batch
+            .samples
+            .chunks_exact(FFT_SIZE)
+            .zip(&batch.voiced)
+            .map(|(window, &voiced)| FftFrame {
+                voiced,
+                ..engine.run(window, &[], batch.sample_rate, batch.min_frequency_hz, &mut spectrum_sum)
+            })
+            .collect();
+        // Gone if the analyzer has moved on to another page
+        let _ = batch.results.send(FftBatchResult { frames, spectrum_sum });
+    }
+}
+
+/// An analyzer's side of the pool: the batch being filled, and the batches
+/// sent and not yet back, oldest first
+pub struct PooledFft {
+    worker: AnalysisWorker,
+    samples: Vec<i16>,
+    voiced: Vec<bool>,
+    pending: VecDeque<Receiver<FftBatchResult>>,
+}
+
+impl PooledFft {
+    pub fn new(worker: AnalysisWorker) -> Self {
+        Self {
+            worker,
+            samples: Vec::with_capacity(BATCH_WINDOWS * FFT_SIZE),
+            voiced: Vec::with_capacity(BATCH_WINDOWS),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Add a window, given oldest first in the two parts a ring buffer
+    /// holds it in. True once the batch is full
+    pub fn queue(&mut self, oldest: &[i16], newest: &[i16], voiced: bool) -> bool {
+        self.samples.extend_from_slice(oldest);
+        self.samples.extend_from_slice(newest);
+        self.voiced.push(voiced);
+        self.voiced.len() >= BATCH_WINDOWS
+    }
+
+    /// Send the windows queued so far to the worker
+    pub fn dispatch(&mut self, sample_rate: u32, min_frequency_hz: f64) {
+        if self.voiced.is_empty() {
+            return;
+        }
+        // Each batch gets its own channel, so one the worker never answers
+        // (its thread gone) is seen as disconnected rather than waited on
+        let (results, receiver) = crossbeam_channel::bounded(1);
+        let batch = FftBatch {
+            samples: std::mem::replace(&mut self.samples, Vec::with_capacity(BATCH_WINDOWS * FFT_SIZE)),
+            voiced: std::mem::take(&mut self.voiced),
+            sample_rate,
+            min_frequency_hz,
+            results,
+        };
+        if self.worker.jobs.send(batch).is_ok() {
+            self.pending.push_back(receiver);
+        }
+    }
+
+    /// Batches the worker has finished, in order, without waiting for the
+    /// rest
+    pub fn try_collect(&mut self) -> Vec<FftBatchResult> {
+        let mut results = Vec::new();
+        while let Some(receiver) = self.pending.front() {
+            match receiver.try_recv() {
+                Ok(result) => results.push(result),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {}
+            }
+            self.pending.pop_front();
+        }
+        results
+    }
+
+    /// Every batch sent, waiting for the worker to finish them
+    pub fn collect_all(&mut self) -> Vec<FftBatchResult> {
+        self.pending.drain(..).filter_map(|receiver| receiver.recv().ok()).collect()
+    }
+
+    /// Drop windows queued or sent, for a new page
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.voiced.clear();
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::audio_analyzer::{AudioAnalyzer, AudioStats};
+    use std::time::{Duration, Instant};
+
+    /// A page's worth of a tone sliding from 300 Hz to 3 kHz under noise,
+    /// with a pause, so every accumulator has something to count
+    fn page(seed: u32) -> Vec<i16> {
+        let mut state = seed.wrapping_mul(2_654_435_761).max(1);
+        (0..8000 * 3)
+            .map(|i| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                let t = f64::from(i) / 8000.0;
+                let freq = 300.0 + 900.0 * t + f64::from(seed % 7) * 40.0;
+                let tone = if (1.2..1.5).contains(&t) { 0.0 } else { (2.0 * std::f64::consts::PI * freq * t).sin() * 9000.0 };
+                (tone + f64::from(state % 2000) - 1000.0) as i16
+            })
+            .collect()
+    }
+
+    /// Run every endpoint's page through its analyzer a 20ms packet at a
+    /// time, interleaved as a receive loop would take them. Returns the
+    /// stats and the packets handled per second
+    fn run(pages: &[Vec<i16>], pool: &AnalysisPool) -> (Vec<AudioStats>, f64) {
+        let mut analyzers: Vec<AudioAnalyzer> =
+            pages.iter().map(|_| AudioAnalyzer::new(8000).with_worker(pool.worker())).collect();
+        let mut stats: Vec<AudioStats> = pages.iter().map(|_| AudioStats::new()).collect();
+        let started = Instant::now();
+        let mut packets = 0;
+        for offset in (0..pages[0].len()).step_by(160) {
+            for ((page, analyzer), stats) in pages.iter().zip(&mut analyzers).zip(&mut stats) {
+                let packet = &page[offset..offset + 160];
+                stats.update(&analyzer.analyze(packet), packet.len() as u64);
+                packets += 1;
+            }
+        }
+        for (analyzer, stats) in analyzers.iter_mut().zip(&mut stats) {
+            analyzer.finish(stats);
+        }
+        (stats, f64::from(packets) / started.elapsed().as_secs_f64())
+    }
+
+    /// What the page reports, to compare exactly
+    fn figures(stats: &AudioStats) -> String {
+        format!(
+            "{} {:?} {:?} {:?} {:?}",
+            serde_json::to_string(stats).unwrap(),
+            stats.spectral_shape(),
+            stats.band_split(),
+            stats.pitch_stability(),
+            stats.level_spread_db()
+        )
+    }
+
+    #[test]
+    fn test_pooled_stats_match_inline() {
+        let pages: Vec<Vec<i16>> = (0..6).map(page).collect();
+        let (inline, _) = run(&pages, &AnalysisPool::new(0));
+        let (pooled, _) = run(&pages, &AnalysisPool::new(3));
+        for (inline, pooled) in inline.iter().zip(&pooled) {
+            assert!(inline.dominant_freq_hz > 0.0);
+            assert!(inline.spectral_shape().is_some());
+            assert_eq!(figures(inline), figures(pooled));
+        }
+    }
+
+    #[test]
+    fn test_pooled_live_reading_and_reset() {
+        let pool = AnalysisPool::new(1);
+        let mut analyzer = AudioAnalyzer::new(8000).with_worker(pool.worker());
+        let tone: Vec<i16> =
+            (0..8000).map(|i| ((2.0 * std::f64::consts::PI * 1000.0 * f64::from(i) / 8000.0).sin() * 10000.0) as i16).collect();
+
+        // Batches come back while later packets are analyzed
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut stats = AudioStats::new();
+        for packet in tone.chunks(160).cycle() {
+            stats.update(&analyzer.analyze(packet), 160);
+            if analyzer.dominant_freq_hz() > 0.0 || Instant::now() > deadline {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!((analyzer.dominant_freq_hz() - 1000.0).abs() < 20.0);
+        assert!(analyzer.take_spectrum().is_some());
+
+        // A new page starts clean, with nothing of the last one to finish
+        analyzer.reset();
+        assert!(analyzer.dominant_freq_hz().abs() < 1e-9);
+        let mut fresh = AudioStats::new();
+        analyzer.finish(&mut fresh);
+        assert_eq!(fresh.freq_bin_count(), 0);
+        assert!(AnalysisPool::new(0).worker().is_none());
+    }
+
+    /// Packets per second through 32 endpoints' analysis with FFTs inline
+    /// and on the pool. Only a machine with cores to spare can show the
+    /// pool ahead; elsewhere this just reports the figures. A timing
+    /// comparison, so it only runs when asked for:
+    /// `cargo test pool_throughput -- --ignored --nocapture`
+    #[test]
+    #[ignore = "wall-clock throughput comparison; run with --ignored"]
+    fn test_pool_throughput() {
+        const ENDPOINTS: u32 = 32;
+        let pages: Vec<Vec<i16>> = (0..ENDPOINTS).map(page).collect();
+        let threads = default_threads(ENDPOINTS as usize);
+        let pool = AnalysisPool::new(threads);
+
+        // Best of a few, so a busy machine doesn't decide it
+        let (mut inline, mut pooled) = (0.0f64, 0.0f64);
+        for _ in 0..3 {
+            inline = inline.max(run(&pages, &AnalysisPool::new(0)).1);
+            pooled = pooled.max(run(&pages, &pool).1);
+        }
+        println!("{} endpoints: inline {:.0} packets/s, {} analysis threads {:.0} packets/s", ENDPOINTS, inline, threads, pooled);
+        if threads >= 2 {
+            assert!(pooled > inline, "inline {:.0} packets/s, pooled {:.0} packets/s", inline, pooled);
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Operator annotations: timestamped notes made while `monitor` or `test` runs.
+//!
+//! During live troubleshooting the operator narrates what they are doing
+//! ("unplugged the switch uplink"), so it can be lined up with the metrics
+//! afterwards. A note comes from an `annotate <text>` line on the control
+//! socket, or from pressing `a` when stdin is a terminal. Either way it is
+//! stamped with the time it was started and written to annotations.jsonl in
+//! the output directory. `test` also writes it into metrics.jsonl between the
+//! snapshots, where `review --timeline` shows it.
+//!
+//! To catch the key, the terminal is put in a mode with no line buffering
+//! and no echo (`terminal.rs`), so keys pressed don't land in the middle of the `\r` stats
+//! line. Ctrl-C, Ctrl-\ and Ctrl-Z still work: the terminal is restored
+//! before the signal is raised. While a note is typed the terminal is back
+//! in its usual mode and the stats line is paused.
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// Written to the output directory as notes are made
+pub const ANNOTATIONS_FILE: &str = "annotations.jsonl";
+
+/// Where a note came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnotationSource {
+    /// The `a` key
+    Keyboard,
+    /// An `annotate` line on the control socket
+    Control,
+}
+
+impl AnnotationSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Keyboard => "keyboard",
+            Self::Control => "control",
+        }
+    }
+}
+
+impl fmt::Display for AnnotationSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// One operator note; in metrics.jsonl it is told apart from the snapshots
+/// by `"type": "annotation"`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename = "annotation")]
+pub struct Annotation {
+    pub timestamp: DateTime<Utc>,
+    pub text: String,
+    pub source: AnnotationSource,
+}
+
+impl Annotation {
+    /// A note made now
+    pub fn now(text: &str, source: AnnotationSource) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            text: text.trim().to_string(),
+            source,
+        }
+    }
+}
+
+/// annotations.jsonl, created with the first note
+pub struct AnnotationLog {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+}
+
+impl AnnotationLog {
+    pub fn new(directory: &Path) -> Self {
+        Self {
+            path: directory.join(ANNOTATIONS_FILE),
+            writer: None,
+        }
+    }
+
+    /// Append a note; each is flushed, as notes are rare and precious
+    pub fn write(&mut self, annotation: &Annotation) -> io::Result<()> {
+        let writer = match self.writer {
+            Some(ref mut writer) => writer,
+            None => self.writer.insert(BufWriter::new(File::options().create(true).append(true).open(&self.path)?)),
+        };
+        writeln!(writer, "{}", serde_json::to_string(annotation).map_err(io::Error::other)?)?;
+        writer.flush()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Listening for the `a` key; the terminal is restored when this is dropped
+pub struct Keyboard {
+    #[cfg(unix)]
+    #[allow(dead_code)] // held for its Drop, which restores the terminal
+    terminal: unix::Terminal,
+}
+
+impl Keyboard {
+    /// Start listening if stdin is a terminal. `paused` is set while a note
+    /// is being typed, for the stats line to hold off
+    pub fn listen(paused: Arc<AtomicBool>, on_note: impl Fn(Annotation) + Send + 'static) -> Option<Self> {
+        #[cfg(unix)]
+        {
+            unix::Terminal::listen(paused, on_note).map(|terminal| Self { terminal })
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (paused, on_note);
+            None
+        }
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::{Annotation, AnnotationSource};
+    use crate::cli::terminal::Modes;
+    use std::io::{self, Read, Write};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    const CTRL_C: u8 = 0x03;
+    const CTRL_BACKSLASH: u8 = 0x1c;
+    const CTRL_Z: u8 = 0x1a;
+
+    pub(super) struct Terminal {
+        modes: Modes,
+        /// Set once dropped, so the listener leaves the terminal alone
+        closed: Arc<AtomicBool>,
+    }
+
+    impl Terminal {
+        pub(super) fn listen(paused: Arc<AtomicBool>, on_note: impl Fn(Annotation) + Send + 'static) -> Option<Self> {
+            // Signal keys are raised by hand once the terminal is restored
+            let modes = Modes::capture()?;
+            modes.keys();
+
+            let closed = Arc::new(AtomicBool::new(false));
+            let listener_closed = Arc::clone(&closed);
+            std::thread::spawn(move || read_keys(modes, &listener_closed, &paused, on_note));
+            Some(Self { modes, closed })
+        }
+    }
+
+    impl Drop for Terminal {
+        fn drop(&mut self) {
+            self.closed.store(true, Ordering::SeqCst);
+            self.modes.restore();
+        }
+    }
+
+    fn read_keys(modes: Modes, closed: &AtomicBool, paused: &AtomicBool, on_note: impl Fn(Annotation)) {
+        let mut key = [0u8; 1];
+        while !closed.load(Ordering::SeqCst) {
+            if !matches!(io::stdin().lock().read(&mut key), Ok(1)) {
+                break;
+            }
+            let signal = match key[0] {
+                b'a' | b'A' => {
+                    if let Some(note) = prompt(modes, closed, paused) {
+                        on_note(note);
+                    }
+                    continue;
+                }
+                CTRL_C => libc::SIGINT,
+                CTRL_BACKSLASH => libc::SIGQUIT,
+                CTRL_Z => libc::SIGTSTP,
+                _ => continue,
+            };
+            modes.restore();
+            // SAFETY: raising a signal in our own process
+            unsafe {
+                libc::raise(signal);
+            }
+            if signal != libc::SIGTSTP {
+                // Test mode carries on to write its summary; the keys are no longer ours
+                break;
+            }
+            // Resumed after Ctrl-Z
+            if !closed.load(Ordering::SeqCst) {
+                modes.keys();
+            }
+        }
+    }
+
+    /// Read a note's text with the terminal back to normal; `None` if it's empty
+    fn prompt(modes: Modes, closed: &AtomicBool, paused: &AtomicBool) -> Option<Annotation> {
+        let mut note = Annotation::now("", AnnotationSource::Keyboard);
+        paused.store(true, Ordering::SeqCst);
+        modes.restore();
+        // Off the stats line, which is drawn with \r
+        print!("\nAnnotation: ");
+        io::stdout().flush().ok();
+        let mut line = String::new();
+        let read = io::stdin().read_line(&mut line);
+        if !closed.load(Ordering::SeqCst) {
+            modes.keys();
+        }
+        paused.store(false, Ordering::SeqCst);
+        note.text = line.trim().to_string();
+        (read.is_ok() && !note.text.is_empty()).then_some(note)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_annotation_record() {
+        let note = Annotation {
+            timestamp: "2026-01-15T10:30:02.250Z".parse().unwrap(),
+            text: "unplugged the uplink".to_string(),
+            source: AnnotationSource::Control,
+        };
+        let line = serde_json::to_string(&note).unwrap();
+        assert_eq!(
+            line,
+            r#"{"type":"annotation","timestamp":"2026-01-15T10:30:02.250Z","text":"unplugged the uplink","source":"control"}"#
+        );
+        assert_eq!(serde_json::from_str::<Annotation>(&line).unwrap(), note);
+        // A metrics snapshot is not a note
+        assert!(serde_json::from_str::<Annotation>(r#"{"timestamp":"2026-01-15T10:30:02Z","endpoint":"224.0.1.1:5004"}"#).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = AnnotationLog::new(dir.path());
+        assert!(!log.path().exists(), "created with the first note");
+        log.write(&note).unwrap();
+        log.write(&Annotation::now("  plugged it back  ", AnnotationSource::Keyboard)).unwrap();
+        let notes: Vec<Annotation> = std::fs::read_to_string(log.path())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0], note);
+        assert_eq!(notes[1].text, "plugged it back");
+        assert_eq!(notes[1].source, AnnotationSource::Keyboard);
+    }
+}
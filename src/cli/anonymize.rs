@@ -0,0 +1,547 @@
+//! Anonymized copies of test results (`review --anonymize`)
+//!
+//! Results escalated to a vendor can't carry the announcements themselves,
+//! or say who sent them, but the degradation still has to show. This copies
+//! a results directory with each recording replaced by noise following the
+//! recording's level 100ms at a time, and silent wherever the recording
+//! was: gaps and concealment written as silence stay where they were. The
+//! level comes from the audio analyzer, so the copy measures as the
+//! original did; the speech is gone.
+//!
+//! Every IPv4 address in the JSON files and file names, dotted or written
+//! with underscores as in recording names, becomes a stand-in from a
+//! reserved range, the same one everywhere, so pages still line up with
+//! their endpoints across files. Caller IDs get stand-ins the same way.
+//! Packet captures and payload files hold the audio as received, and other
+//! binary files can't be scrubbed, so they are left out. summary.json is
+//! re-hashed for the new recordings and records what was done.
+
+use crate::cli::audio_analyzer::AudioAnalyzer;
+use crate::cli::finalize::RecordingKind;
+use crate::cli::integrity;
+use crate::cli::recorder::{self, RecorderError, WavRecorder};
+use crate::cli::test::{self, TestSummary};
+use crate::network::impairment::{time_seed, XorShift};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Each recording's level is followed over windows this long
+pub const ENVELOPE_WINDOW_MS: u32 = 100;
+
+/// Digital silence at least this long is a gap, kept in the copy; shorter
+/// runs of zero samples are the audio crossing zero
+const MIN_GAP_MS: u32 = 2;
+
+/// Stands in for a range pattern's addresses, which have no single stand-in
+const MASKED_RANGE: &str = "x.x.x.x";
+
+#[derive(Error, Debug)]
+pub enum AnonymizeError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("WAV error: {0}")]
+    Wav(#[from] hound::Error),
+
+    #[error("Recorder error: {0}")]
+    Recorder(#[from] RecorderError),
+
+    #[error("{0} isn't empty; the anonymized copy needs a directory of its own")]
+    OutputNotEmpty(PathBuf),
+
+    #[error("The anonymized copy can't go inside the results it is made from")]
+    OutputInsideResults,
+}
+
+/// What `review --anonymize` did, kept in the copy's summary.json
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Anonymization {
+    pub anonymized_at: DateTime<Utc>,
+    /// Recordings replaced with noise following their level
+    pub recordings: usize,
+    /// Window the level was followed over
+    pub envelope_window_ms: u32,
+    /// IPv4 addresses given stand-ins
+    pub addresses: usize,
+    /// Caller IDs given stand-ins
+    pub caller_ids: usize,
+    /// Files left out, relative to the results directory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub omitted: Vec<String>,
+}
+
+/// How a file is carried into the copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    Recording,
+    Json,
+    JsonLines,
+    /// Anything else; scrubbed as text if it is text, left out if not
+    Other,
+    /// Audio or packets as received
+    Omitted,
+}
+
+impl FileKind {
+    fn of(path: &Path) -> Self {
+        let name = path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+        if name.ends_with(".payloads.bin") || name.ends_with(".payloads.jsonl") {
+            return Self::Omitted;
+        }
+        match path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase).as_deref() {
+            Some("wav") => Self::Recording,
+            Some("json") => Self::Json,
+            Some("jsonl") => Self::JsonLines,
+            Some("pcap" | "pcapng") => Self::Omitted,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Copy the results in `directory` to `output`, anonymized
+pub fn anonymize(directory: &Path, output: &Path) -> Result<Anonymization, AnonymizeError> {
+    fs::create_dir_all(output)?;
+    if fs::read_dir(output)?.next().is_some() {
+        return Err(AnonymizeError::OutputNotEmpty(output.to_path_buf()));
+    }
+    let source = directory.canonicalize()?;
+    let destination = output.canonicalize()?;
+    if destination.starts_with(&source) {
+        return Err(AnonymizeError::OutputInsideResults);
+    }
+
+    let mut files = Vec::new();
+    list_files(&source, Path::new(""), &mut files)?;
+
+    // Every address has to be known before stand-ins are given out, so
+    // none is handed an address that appears elsewhere in the results
+    let mut names = Pseudonyms::default();
+    for relative in &files {
+        names.collect(&relative.to_string_lossy());
+        if !matches!(FileKind::of(relative), FileKind::Recording | FileKind::Omitted) {
+            names.collect(&String::from_utf8_lossy(&fs::read(source.join(relative))?));
+        }
+    }
+    names.assign();
+
+    let mut rng = XorShift::new(time_seed());
+    let mut report = Anonymization {
+        anonymized_at: Utc::now(),
+        recordings: 0,
+        envelope_window_ms: ENVELOPE_WINDOW_MS,
+        addresses: names.addresses.len(),
+        caller_ids: 0,
+        omitted: Vec::new(),
+    };
+    for relative in &files {
+        let from = source.join(relative);
+        let to = destination.join(names.replace_path(relative));
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match FileKind::of(relative) {
+            FileKind::Recording => {
+                anonymize_recording(&from, &to, &mut rng)?;
+                report.recordings += 1;
+            }
+            FileKind::Json => {
+                let mut value: Value = serde_json::from_reader(BufReader::new(File::open(&from)?))?;
+                names.scrub(&mut value);
+                serde_json::to_writer_pretty(BufWriter::new(File::create(&to)?), &value)?;
+            }
+            FileKind::JsonLines => {
+                let mut writer = BufWriter::new(File::create(&to)?);
+                for line in BufReader::new(File::open(&from)?).lines() {
+                    let line = line?;
+                    match serde_json::from_str::<Value>(&line) {
+                        Ok(mut value) => {
+                            names.scrub(&mut value);
+                            writeln!(writer, "{}", value)?;
+                        }
+                        Err(_) => writeln!(writer, "{}", names.replace(&line))?,
+                    }
+                }
+                writer.flush()?;
+            }
+            FileKind::Other => match String::from_utf8(fs::read(&from)?) {
+                Ok(text) => fs::write(&to, names.replace(&text))?,
+                Err(_) => report.omitted.push(relative.to_string_lossy().into_owned()),
+            },
+            FileKind::Omitted => report.omitted.push(relative.to_string_lossy().into_owned()),
+        }
+    }
+
+    // The recordings changed, so their checksums did too
+    let mut summary: TestSummary = serde_json::from_reader(BufReader::new(File::open(destination.join("summary.json"))?))?;
+    for page in &mut summary.pages {
+        if page.integrity.is_some() {
+            page.integrity = integrity::fingerprint(&destination.join(&page.recording_file)).ok();
+        }
+        page.recording_files.remove(&RecordingKind::Payloads);
+    }
+    report.caller_ids = names.callers.len();
+    summary.test_metadata.anonymization = Some(report.clone());
+    test::write_summary(&destination, &summary)?;
+    Ok(report)
+}
+
+/// Files under `directory`, relative to it, in name order
+fn list_files(directory: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(directory.join(relative))?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            list_files(directory, &path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Write `source` to `target` as noise following its level
+fn anonymize_recording(source: &Path, target: &Path, rng: &mut XorShift) -> Result<(), AnonymizeError> {
+    let mut reader = hound::WavReader::open(source)?;
+    let spec = reader.spec();
+    let channels = u8::try_from(spec.channels).map_err(|_| hound::Error::Unsupported)?;
+    let mut analyzer = AudioAnalyzer::with_channels(spec.sample_rate, channels);
+    let mut recorder = WavRecorder::new(target, spec.sample_rate, channels)?;
+    if let Some(start) = recorder::read_start_time(source)? {
+        recorder.set_start_time(start);
+    }
+
+    let channels = usize::from(channels.max(1));
+    let window_len = (spec.sample_rate * ENVELOPE_WINDOW_MS / 1000).max(1) as usize * channels;
+    let min_gap = (spec.sample_rate * MIN_GAP_MS / 1000).max(1) as usize;
+    let mut samples = reader.samples::<i16>();
+    let mut window = Vec::with_capacity(window_len);
+    loop {
+        window.clear();
+        for sample in samples.by_ref().take(window_len) {
+            window.push(sample?);
+        }
+        if window.is_empty() {
+            break;
+        }
+        let level = analyzer.analyze(&window).rms_db;
+        recorder.write_samples(&envelope_noise(&window, channels, level, min_gap, rng))?;
+    }
+    recorder.finalize()?;
+    Ok(())
+}
+
+/// Noise with the RMS level `rms_db` over `window`, silent where the window
+/// has a gap. Every channel gets the same noise, so a downmix measures the
+/// same level
+fn envelope_noise(window: &[i16], channels: usize, rms_db: f64, min_gap: usize, rng: &mut XorShift) -> Vec<i16> {
+    let frames: Vec<&[i16]> = window.chunks(channels).collect();
+    let mut gap = vec![false; frames.len()];
+    let mut run_start = None;
+    for i in 0..=frames.len() {
+        let silent = frames.get(i).is_some_and(|frame| frame.iter().all(|&s| s == 0));
+        match (silent, run_start) {
+            (true, None) => run_start = Some(i),
+            (false, Some(start)) => {
+                if i - start >= min_gap {
+                    gap[start..i].fill(true);
+                }
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+
+    let noise: Vec<f64> = gap.iter().map(|&gap| if gap { 0.0 } else { rng.next_f64() * 2.0 - 1.0 }).collect();
+    let power = noise.iter().map(|n| n * n).sum::<f64>() / noise.len() as f64;
+    let target = if rms_db.is_finite() { 32768.0 * 10f64.powf(rms_db / 20.0) } else { 0.0 };
+    let gain = if power > 0.0 { target / power.sqrt() } else { 0.0 };
+    noise
+        .iter()
+        .flat_map(|n| std::iter::repeat_n((n * gain).round().clamp(-32768.0, 32767.0) as i16, channels))
+        .collect()
+}
+
+/// Stand-ins for the addresses and caller IDs in a set of results
+#[derive(Default)]
+struct Pseudonyms {
+    addresses: BTreeMap<Ipv4Addr, Ipv4Addr>,
+    callers: BTreeMap<String, String>,
+}
+
+impl Pseudonyms {
+    /// Note the dotted addresses in `text`
+    fn collect(&mut self, text: &str) {
+        for address in find_addresses(text) {
+            if !address.is_unspecified() {
+                self.addresses.insert(address, address);
+            }
+        }
+    }
+
+    /// Give each address collected a stand-in: groups from 239.255.0.0/16,
+    /// anything else from 198.18.0.0/15, skipping addresses in the results
+    fn assign(&mut self) {
+        let originals: BTreeSet<Ipv4Addr> = self.addresses.keys().copied().collect();
+        let mut next_group = u32::from(Ipv4Addr::new(239, 255, 0, 1));
+        let mut next_host = u32::from(Ipv4Addr::new(198, 18, 0, 1));
+        for (original, stand_in) in &mut self.addresses {
+            let next = if original.is_multicast() { &mut next_group } else { &mut next_host };
+            while originals.contains(&Ipv4Addr::from(*next)) {
+                *next += 1;
+            }
+            *stand_in = Ipv4Addr::from(*next);
+            *next += 1;
+        }
+    }
+
+    fn caller(&mut self, caller_id: &str) -> String {
+        let next = self.callers.len() + 1;
+        self.callers.entry(caller_id.to_string()).or_insert_with(|| format!("caller-{}", next)).clone()
+    }
+
+    /// Replace the addresses in every string and key of `value`, and any
+    /// `caller_id`
+    fn scrub(&mut self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.replace(text),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.scrub(item)),
+            Value::Object(map) => {
+                *map = std::mem::take(map)
+                    .into_iter()
+                    .map(|(key, mut value)| {
+                        match value {
+                            Value::String(ref mut id) if key == "caller_id" => *id = self.caller(id),
+                            _ => self.scrub(&mut value),
+                        }
+                        (self.replace(&key), value)
+                    })
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+
+    fn replace_path(&self, path: &Path) -> PathBuf {
+        path.iter().map(|part| self.replace(&part.to_string_lossy())).collect()
+    }
+
+    /// `text` with every address collected in its stand-in, written the same
+    /// way, and range patterns masked
+    fn replace(&self, text: &str) -> String {
+        let mut replaced = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+            replaced.push_str(&rest[..start]);
+            let run_len = rest[start..].find(|c: char| !is_run_char(c)).unwrap_or(rest.len() - start);
+            let run = &rest[start..start + run_len];
+            if run.contains('{') && run.contains('.') {
+                replaced.push_str(MASKED_RANGE);
+            } else {
+                replaced.push_str(&self.replace_run(run));
+            }
+            rest = &rest[start + run_len..];
+        }
+        replaced.push_str(rest);
+        replaced
+    }
+
+    fn replace_run(&self, run: &str) -> String {
+        let groups = digit_groups(run);
+        let mut replaced = String::with_capacity(run.len());
+        let mut copied = 0;
+        let mut i = 0;
+        while i < groups.len() {
+            let stand_in = address_at(run, &groups[i..])
+                .and_then(|(address, separator)| Some((self.addresses.get(&address)?, separator)));
+            match stand_in {
+                Some((stand_in, separator)) => {
+                    replaced.push_str(&run[copied..groups[i].0]);
+                    let octets = stand_in.octets().map(|octet| octet.to_string());
+                    replaced.push_str(&octets.join(&separator.to_string()));
+                    copied = groups[i + 3].1;
+                    i += 4;
+                }
+                None => i += 1,
+            }
+        }
+        replaced.push_str(&run[copied..]);
+        replaced
+    }
+}
+
+/// Characters that can make up an address as written in the results, and
+/// the braces and dashes of a range pattern
+fn is_run_char(c: char) -> bool {
+    c.is_ascii_digit() || matches!(c, '.' | '_' | '{' | '}' | '-')
+}
+
+/// Byte ranges of the runs of digits in `run`
+fn digit_groups(run: &str) -> Vec<(usize, usize)> {
+    let mut groups = Vec::new();
+    let mut start = None;
+    for (i, c) in run.char_indices().chain(std::iter::once((run.len(), ' '))) {
+        match (c.is_ascii_digit(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                groups.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    groups
+}
+
+/// The address made by the first four of `groups`, if they are octets
+/// joined by one separator, '.' or '_'
+fn address_at(run: &str, groups: &[(usize, usize)]) -> Option<(Ipv4Addr, char)> {
+    let groups = groups.get(..4)?;
+    let separator = run[groups[0].1..groups[1].0].chars().next()?;
+    let mut octets = [0u8; 4];
+    for (i, &(start, end)) in groups.iter().enumerate() {
+        let digits = &run[start..end];
+        if i > 0 && &run[groups[i - 1].1..start] != separator.encode_utf8(&mut [0; 4]) {
+            return None;
+        }
+        if digits.len() > 1 && digits.starts_with('0') {
+            return None;
+        }
+        octets[i] = digits.parse().ok()?;
+    }
+    matches!(separator, '.' | '_').then_some((Ipv4Addr::from(octets), separator))
+}
+
+/// The dotted addresses in `text`
+fn find_addresses(text: &str) -> Vec<Ipv4Addr> {
+    let mut addresses = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+        let run_len = rest[start..].find(|c: char| !is_run_char(c)).unwrap_or(rest.len() - start);
+        let run = &rest[start..start + run_len];
+        let groups = digit_groups(run);
+        let mut i = 0;
+        while i < groups.len() {
+            match address_at(run, &groups[i..]) {
+                Some((address, '.')) => {
+                    addresses.push(address);
+                    i += 4;
+                }
+                _ => i += 1,
+            }
+        }
+        rest = &rest[start + run_len..];
+    }
+    addresses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn window_levels(samples: &[i16], sample_rate: u32) -> Vec<f64> {
+        let mut analyzer = AudioAnalyzer::new(sample_rate);
+        samples
+            .chunks((sample_rate * ENVELOPE_WINDOW_MS / 1000) as usize)
+            .map(|window| analyzer.analyze(window).rms_db)
+            .collect()
+    }
+
+    fn read(path: &Path) -> Vec<i16> {
+        hound::WavReader::open(path).unwrap().samples::<i16>().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn test_recording_keeps_envelope_not_audio() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("page.wav");
+        let target = dir.path().join("anonymized.wav");
+
+        // Three syllables of a swept tone, with a 300ms gap in the middle
+        let sample_rate = 8000;
+        let samples: Vec<i16> = (0..24000)
+            .map(|i| {
+                let t = i as f64 / f64::from(sample_rate);
+                if (12000..14400).contains(&i) {
+                    return 0;
+                }
+                let syllable = (std::f64::consts::PI * t * 1.5).sin().abs();
+                (12000.0 * syllable * (2.0 * std::f64::consts::PI * (300.0 + 200.0 * t) * t).sin()) as i16
+            })
+            .collect();
+        let start = Utc::now();
+        let mut recorder = WavRecorder::new(&source, sample_rate, 1).unwrap();
+        recorder.set_start_time(start);
+        recorder.write_samples(&samples).unwrap();
+        recorder.finalize().unwrap();
+
+        anonymize_recording(&source, &target, &mut XorShift::new(7)).unwrap();
+        let anonymized = read(&target);
+        assert_eq!(anonymized.len(), samples.len());
+        assert_eq!(
+            recorder::read_start_time(&target).unwrap().map(|t| t.timestamp_millis()),
+            Some(start.timestamp_millis())
+        );
+        assert!(anonymized[12000..14400].iter().all(|&s| s == 0), "The gap should stay silent");
+
+        for (original, copy) in window_levels(&samples, sample_rate).iter().zip(window_levels(&anonymized, sample_rate)) {
+            if original.is_finite() {
+                assert!((original - copy).abs() < 0.5, "Level {} became {}", original, copy);
+            } else {
+                assert!(copy.is_infinite());
+            }
+        }
+
+        let mean = |s: &[i16]| s.iter().map(|&v| f64::from(v)).sum::<f64>() / s.len() as f64;
+        let (ma, mb) = (mean(&samples), mean(&anonymized));
+        let (mut ab, mut aa, mut bb) = (0.0, 0.0, 0.0);
+        for (&a, &b) in samples.iter().zip(&anonymized) {
+            let (a, b) = (f64::from(a) - ma, f64::from(b) - mb);
+            ab += a * b;
+            aa += a * a;
+            bb += b * b;
+        }
+        let correlation = ab / (aa * bb).sqrt();
+        assert!(correlation.abs() < 0.05, "Correlation with the original is {}", correlation);
+    }
+
+    #[test]
+    fn test_addresses_replaced_consistently() {
+        let mut names = Pseudonyms::default();
+        names.collect(r#"{"endpoint": "224.0.1.1:5004", "source": "198.18.0.1", "interface": "0.0.0.0"}"#);
+        names.collect("[10.1.2.3:4000] Sequence gap");
+        names.assign();
+
+        // The stand-ins skip an address that is in the results
+        assert_eq!(names.replace("224.0.1.1:5004 from 10.1.2.3"), "239.255.0.1:5004 from 198.18.0.2");
+        assert_eq!(names.replace("198.18.0.1, 0.0.0.0"), "198.18.0.3, 0.0.0.0");
+        assert_eq!(names.replace_path(Path::new("page_0001_224_0_1_1_5004.wav")), Path::new("page_0001_239_255_0_1_5004.wav"));
+        assert_eq!(names.replace("224.0.1.{1-10}:5004"), "x.x.x.x:5004");
+        assert_eq!(names.replace("2024-06-01T14:32:07.250Z page 12.5s"), "2024-06-01T14:32:07.250Z page 12.5s");
+
+        let mut value = serde_json::json!({
+            "224.0.1.1:5004": {"caller_id": "Front Desk", "note": "from 10.1.2.3"},
+            "pages": [{"caller_id": "Front Desk"}, {"caller_id": "Dock"}],
+        });
+        names.scrub(&mut value);
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "239.255.0.1:5004": {"caller_id": "caller-1", "note": "from 198.18.0.2"},
+                "pages": [{"caller_id": "caller-1"}, {"caller_id": "caller-2"}],
+            })
+        );
+    }
+}
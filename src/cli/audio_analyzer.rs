@@ -2,9 +2,19 @@
 
 #![allow(dead_code)]
 
-use rustfft::{FftPlanner, num_complex::Complex};
+use crate::cli::analysis_pool::{AnalysisWorker, FftBatchResult, PooledFft};
+use crate::cli::chime::{BuiltinChime, ChimeStats};
+use crate::cli::loudness::{LoudnessBlock, LoudnessMeter, LoudnessStats};
+use crate::cli::transcoding::{BandFrame, BandSplit, BandStats};
+use crate::cli::watermark::{WatermarkDetection, WatermarkMeter, WatermarkStats};
+use crate::utils::streaming::RunningStats;
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
 use serde::Serialize;
 use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
 
 // ============================================================================
 // Audio Analysis Constants
@@ -12,12 +22,22 @@ use std::collections::HashMap;
 
 /// FFT size for frequency analysis. 512 samples provides a good balance between
 /// frequency resolution (~15.6 Hz bins at 8kHz) and latency (~64ms at 8kHz).
-const FFT_SIZE: usize = 512;
+pub(crate) const FFT_SIZE: usize = 512;
+
+/// Minimum number of new samples between FFT runs. A 20ms packet at 8kHz
+/// (160 samples) still gets an FFT per packet, while 1ms AES67 packets
+/// (48 samples) share one FFT across every few packets.
+const FFT_HOP: usize = FFT_SIZE / 4;
 
 /// Frequency bin width in Hz for grouping similar frequencies.
 /// Frequencies within this range are considered the same dominant frequency.
 const FREQ_BIN_WIDTH_HZ: f64 = 50.0;
 
+/// Dominant-frequency bins kept per page. Noisy content moves the dominant
+/// frequency around, so once twice this many bins exist only the busiest are
+/// kept; a real tone is far ahead of the noise by then.
+const MAX_FREQ_BINS: usize = 32;
+
 /// Glitch detection threshold as sample-to-sample jump size.
 /// ~61% of full scale for 16-bit audio. Real glitches from packet loss or
 /// buffer issues typically cause jumps exceeding this threshold, while
@@ -39,10 +59,79 @@ const MIN_FREQUENCY_HZ: f64 = 50.0;
 /// Minimum FFT magnitude to consider a frequency significant.
 const MIN_FFT_MAGNITUDE: f32 = 0.01;
 
+/// Sample jump counted as a glitch under the music profile. Music has
+/// sharper transients than speech, which would otherwise count as glitches.
+const MUSIC_GLITCH_THRESHOLD: i16 = 26000;
+
+/// Silence threshold under the music profile; quiet passages aren't silence
+const MUSIC_SILENCE_THRESHOLD_DB: f64 = -60.0;
+
+/// Lowest dominant frequency under the music profile, so bass lines count
+const MUSIC_MIN_FREQUENCY_HZ: f64 = 30.0;
+
 // ============================================================================
 // Data Structures
 // ============================================================================
 
+/// Thresholds the analyzer judges audio by. Live monitoring uses `Speech`;
+/// `review --reanalyze` can run recordings again under another profile.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AnalysisProfile {
+    /// Paging announcements (the built-in thresholds)
+    #[default]
+    Speech,
+    /// Music on hold and pre-recorded programme material
+    Music,
+}
+
+impl AnalysisProfile {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Speech => "speech",
+            Self::Music => "music",
+        }
+    }
+
+    const fn glitch_threshold(self) -> i16 {
+        match self {
+            Self::Speech => GLITCH_THRESHOLD,
+            Self::Music => MUSIC_GLITCH_THRESHOLD,
+        }
+    }
+
+    const fn silence_threshold_db(self) -> f64 {
+        match self {
+            Self::Speech => SILENCE_THRESHOLD_DB,
+            Self::Music => MUSIC_SILENCE_THRESHOLD_DB,
+        }
+    }
+
+    const fn min_frequency_hz(self) -> f64 {
+        match self {
+            Self::Speech => MIN_FREQUENCY_HZ,
+            Self::Music => MUSIC_MIN_FREQUENCY_HZ,
+        }
+    }
+}
+
+impl fmt::Display for AnalysisProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for AnalysisProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "speech" => Ok(Self::Speech),
+            "music" => Ok(Self::Music),
+            _ => Err(format!("unknown analysis profile {:?}: expected speech or music", s)),
+        }
+    }
+}
+
 /// Audio analysis results for a frame of audio
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct AudioAnalysis {
@@ -64,6 +153,41 @@ pub struct AudioAnalysis {
     pub repeated_samples: u64,
     /// Whether the frame appears to be silence
     pub is_silence: bool,
+    /// Shape of the spectrum, for frames where an FFT ran
+    pub spectral_shape: Option<SpectralShape>,
+    /// The spectrum split at 4 kHz, for wideband frames where an FFT ran
+    pub band_frame: Option<BandFrame>,
+    /// Loudness blocks the frame completed
+    pub loudness: Vec<LoudnessBlock>,
+    /// Watermark soft bits for the 10ms hops the frame completed
+    pub watermark: Vec<f32>,
+    /// FFTs of earlier frames that came back from the analysis pool while
+    /// this one was analyzed
+    pub pooled: Vec<FftFrame>,
+}
+
+/// What one FFT of the analysis window found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct FftFrame {
+    pub dominant_freq_hz: f64,
+    pub spectral_shape: Option<SpectralShape>,
+    pub band_frame: Option<BandFrame>,
+    /// Whether the frame that completed the window was above silence with
+    /// a finite level, so its shape counts toward the page's
+    pub voiced: bool,
+}
+
+/// Where a window's energy sits across the spectrum, above the profile's
+/// minimum frequency
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct SpectralShape {
+    /// Power-weighted mean frequency
+    pub centroid_hz: f64,
+    /// Power-weighted standard deviation around the centroid
+    pub bandwidth_hz: f64,
+    /// Geometric over arithmetic mean power: near 0 for a tone, near 1 for
+    /// white noise
+    pub flatness: f64,
 }
 
 /// Accumulates audio statistics across a page
@@ -105,8 +229,31 @@ pub struct AudioStats {
     dc_sum: f64,
     /// Frequency bins using `HashMap` for O(1) lookup.
     /// Key is frequency bin index (freq / `FREQ_BIN_WIDTH_HZ` as i32).
+    /// Compacted to the top `MAX_FREQ_BINS` as it grows.
     #[serde(skip)]
     freq_bins: HashMap<i32, u32>,
+    /// Frames with a dominant frequency, counted into `freq_bins`
+    #[serde(skip)]
+    freq_frames: u64,
+    /// RMS dB of non-silent frames, for the level's spread
+    #[serde(skip)]
+    level: RunningStats,
+    /// Spectral shape of non-silent frames
+    #[serde(skip)]
+    centroid: RunningStats,
+    #[serde(skip)]
+    bandwidth: RunningStats,
+    #[serde(skip)]
+    flatness: RunningStats,
+    /// Wideband frames split at 4 kHz, for the transcoding check
+    #[serde(skip)]
+    bands: BandStats,
+    #[serde(skip)]
+    loudness: LoudnessStats,
+    #[serde(skip)]
+    watermark: WatermarkStats,
+    #[serde(skip)]
+    chime: ChimeStats,
 }
 
 impl AudioStats {
@@ -144,14 +291,28 @@ impl AudioStats {
         self.total_glitches += analysis.glitch_count;
         self.total_repeated += analysis.repeated_samples;
 
+        let voiced = !analysis.is_silence && analysis.rms_db.is_finite();
         if analysis.is_silence {
             self.silent_frames += 1;
+        } else if voiced {
+            self.level.push(analysis.rms_db);
         }
 
-        // Track dominant frequencies using binned HashMap for O(1) lookup
-        if analysis.dominant_freq_hz > 0.0 {
-            let bin = (analysis.dominant_freq_hz / FREQ_BIN_WIDTH_HZ) as i32;
-            *self.freq_bins.entry(bin).or_insert(0) += 1;
+        for block in &analysis.loudness {
+            self.loudness.add(block);
+        }
+        for &soft_bit in &analysis.watermark {
+            self.watermark.add(soft_bit);
+        }
+
+        self.add_fft(&FftFrame {
+            dominant_freq_hz: analysis.dominant_freq_hz,
+            spectral_shape: analysis.spectral_shape,
+            band_frame: analysis.band_frame,
+            voiced,
+        });
+        for frame in &analysis.pooled {
+            self.add_fft(frame);
         }
 
         // Update averages (use rms_count for RMS to avoid NaN from infinite values)
@@ -163,12 +324,86 @@ impl AudioStats {
         self.avg_zero_crossing_rate = self.zcr_sum / self.frame_count as f64;
         self.avg_dc_offset_percent = self.dc_sum / self.frame_count as f64;
 
-        // Find most common dominant frequency (convert bin back to Hz)
-        if let Some((&bin, _)) = self.freq_bins.iter().max_by_key(|(_, &count)| count) {
+        self.update_dominant_freq();
+    }
+
+    /// Add FFTs the analysis pool returned after the frames they belong
+    /// to were counted
+    pub fn add_pooled(&mut self, frames: &[FftFrame]) {
+        for frame in frames {
+            self.add_fft(frame);
+        }
+        self.update_dominant_freq();
+    }
+
+    fn add_fft(&mut self, frame: &FftFrame) {
+        if frame.voiced {
+            if let Some(shape) = frame.spectral_shape {
+                self.centroid.push(shape.centroid_hz);
+                self.bandwidth.push(shape.bandwidth_hz);
+                self.flatness.push(shape.flatness);
+            }
+            if let Some(ref band_frame) = frame.band_frame {
+                self.bands.add(band_frame);
+            }
+        }
+
+        self.chime.add(frame.dominant_freq_hz, frame.voiced);
+
+        // Track dominant frequencies using binned HashMap for O(1) lookup
+        if frame.dominant_freq_hz > 0.0 {
+            let bin = (frame.dominant_freq_hz / FREQ_BIN_WIDTH_HZ) as i32;
+            *self.freq_bins.entry(bin).or_insert(0) += 1;
+            self.freq_frames += 1;
+            if self.freq_bins.len() > 2 * MAX_FREQ_BINS {
+                self.compact_freq_bins();
+            }
+        }
+    }
+
+    /// Find most common dominant frequency (convert bin back to Hz). A tie
+    /// goes to the lower bin, rather than to the map's iteration order
+    fn update_dominant_freq(&mut self) {
+        if let Some((&bin, _)) = self.freq_bins.iter().max_by_key(|&(&bin, &count)| (count, std::cmp::Reverse(bin))) {
             self.dominant_freq_hz = (bin as f64 + 0.5) * FREQ_BIN_WIDTH_HZ;
         }
     }
 
+    /// Integrated loudness, maximum momentary loudness and loudness range
+    pub fn loudness(&self) -> &LoudnessStats {
+        &self.loudness
+    }
+
+    /// The `transmit --watermark` ID the page carried, if one was found
+    pub fn watermark(&self) -> Option<WatermarkDetection> {
+        self.watermark.detect()
+    }
+
+    /// The built-in `transmit --chime` the page opened with, if any
+    pub fn chime(&self) -> Option<BuiltinChime> {
+        self.chime.detect()
+    }
+
+    /// Count watermark hops of audio that was skipped rather than analyzed
+    pub fn add_skipped(&mut self, watermark: &[f32]) {
+        for &soft_bit in watermark {
+            self.watermark.add(soft_bit);
+        }
+    }
+
+    /// Keep only the `MAX_FREQ_BINS` busiest frequency bins
+    fn compact_freq_bins(&mut self) {
+        let mut bins: Vec<(i32, u32)> = self.freq_bins.drain().collect();
+        bins.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        bins.truncate(MAX_FREQ_BINS);
+        self.freq_bins.extend(bins);
+    }
+
+    /// Dominant-frequency bins currently held, for `--memory-report`
+    pub fn freq_bin_count(&self) -> usize {
+        self.freq_bins.len()
+    }
+
     /// Get clipping percentage
     pub fn clipping_percent(&self) -> f64 {
         if self.total_samples == 0 {
@@ -186,62 +421,358 @@ impl AudioStats {
             100.0 * self.silent_frames as f64 / self.frame_count as f64
         }
     }
+
+    /// Standard deviation of the RMS level across non-silent frames, in dB.
+    /// Speech rises and falls with every syllable; tones and noise hold steady
+    pub fn level_spread_db(&self) -> Option<f64> {
+        (self.level.count >= 2).then(|| self.level.std_dev())
+    }
+
+    /// Mean spectral shape of non-silent frames
+    pub fn spectral_shape(&self) -> Option<SpectralShape> {
+        (self.flatness.count > 0).then_some(SpectralShape {
+            centroid_hz: self.centroid.mean,
+            bandwidth_hz: self.bandwidth.mean,
+            flatness: self.flatness.mean,
+        })
+    }
+
+    /// Non-silent wideband frames split at 4 kHz, once there are enough
+    pub fn band_split(&self) -> Option<BandSplit> {
+        self.bands.split()
+    }
+
+    /// Share of frames (0-1) whose dominant frequency is within a bin of the
+    /// most common one. Near 1 for a steady tone; a melody or voice moves
+    pub fn pitch_stability(&self) -> Option<f64> {
+        if self.freq_frames == 0 {
+            return None;
+        }
+        let around = |bin: i32| -> u32 { (bin - 1..=bin + 1).filter_map(|b| self.freq_bins.get(&b)).sum() };
+        let steadiest = self.freq_bins.keys().map(|&bin| around(bin)).max().unwrap_or(0);
+        Some((f64::from(steadiest) / self.freq_frames as f64).min(1.0))
+    }
+}
+
+/// Magnitude spectrum averaged over the FFTs run since it was last taken
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum {
+    pub sample_rate: u32,
+    pub fft_size: usize,
+    /// Mean magnitude of bins 0 to Nyquist (exclusive), windowed and normalized
+    /// so a full-scale sine peaks at 1.0
+    pub magnitudes: Vec<f32>,
+}
+
+impl Spectrum {
+    /// Width of one bin in Hz
+    #[must_use]
+    pub fn bin_width_hz(&self) -> f64 {
+        self.sample_rate as f64 / self.fft_size as f64
+    }
+
+    /// Center frequency of a bin in Hz
+    #[must_use]
+    pub fn bin_frequency(&self, bin: usize) -> f64 {
+        bin as f64 * self.bin_width_hz()
+    }
+
+    /// Bin with the largest magnitude within a frequency range
+    #[must_use]
+    pub fn peak_bin(&self, min_hz: f64, max_hz: f64) -> Option<usize> {
+        (0..self.magnitudes.len())
+            .filter(|&bin| (min_hz..=max_hz).contains(&self.bin_frequency(bin)))
+            .max_by(|&a, &b| self.magnitudes[a].total_cmp(&self.magnitudes[b]))
+    }
+
+    /// Magnitudes in dBFS, floored at -120 dB
+    #[must_use]
+    pub fn magnitudes_db(&self) -> Vec<f32> {
+        self.magnitudes
+            .iter()
+            .map(|&m| if m > 1e-6 { 20.0 * m.log10() } else { -120.0 })
+            .collect()
+    }
+}
+
+/// The FFT behind the dominant frequency, spectral shape and band split,
+/// with its buffers. Inline analysis and each analysis pool worker hold
+/// one, so a window comes out the same wherever it is run
+pub struct FftEngine {
+    fft: Arc<dyn Fft<f32>>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    /// Hann window for better frequency resolution
+    window: Vec<f32>,
+}
+
+impl FftEngine {
+    #[must_use]
+    pub fn new() -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        let scratch_len = fft.get_inplace_scratch_len();
+        let window = (0..FFT_SIZE)
+            .map(|i| {
+                0.5 * (1.0 - (2.0 * std::f32::consts::PI * i as f32 / (FFT_SIZE - 1) as f32).cos())
+            })
+            .collect();
+        Self {
+            fft,
+            buffer: vec![Complex::new(0.0, 0.0); FFT_SIZE],
+            scratch: vec![Complex::new(0.0, 0.0); scratch_len],
+            window,
+        }
+    }
+
+    /// FFT a window of `FFT_SIZE` samples, given oldest first in two parts
+    /// as a ring buffer holds them, adding its magnitudes to `spectrum_sum`.
+    /// The frame comes back unvoiced; that is the caller's to say
+    pub fn run(
+        &mut self,
+        oldest: &[i16],
+        newest: &[i16],
+        sample_rate: u32,
+        min_frequency_hz: f64,
+        spectrum_sum: &mut [f32],
+    ) -> FftFrame {
+        FftFrame {
+            dominant_freq_hz: self.dominant_frequency(oldest, newest, sample_rate, min_frequency_hz, spectrum_sum),
+            spectral_shape: self.spectral_shape(sample_rate, min_frequency_hz),
+            band_frame: self.band_frame(sample_rate),
+            voiced: false,
+        }
+    }
+
+    /// Compute dominant frequency of the window using FFT
+    fn dominant_frequency(
+        &mut self,
+        oldest: &[i16],
+        newest: &[i16],
+        sample_rate: u32,
+        min_frequency_hz: f64,
+        spectrum_sum: &mut [f32],
+    ) -> f64 {
+        // Apply window and convert to complex (normalize to [-1.0, 1.0])
+        for (i, &sample) in oldest.iter().chain(newest).enumerate() {
+            let windowed = sample as f32 * self.window[i] / i16::MAX as f32;
+            self.buffer[i] = Complex::new(windowed, 0.0);
+        }
+
+        // Perform FFT
+        self.fft.process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        // Find peak magnitude (only look at positive frequencies up to Nyquist)
+        let nyquist = FFT_SIZE / 2;
+        let mut max_magnitude: f32 = 0.0;
+        let mut max_bin = 0;
+
+        // Accumulate the spectrum, scaled so a full-scale sine reads 1.0
+        // (the Hann window halves the peak of an FFT_SIZE/2 bin)
+        let scale = 4.0 / FFT_SIZE as f32;
+        for (sum, bin) in spectrum_sum.iter_mut().zip(&self.buffer[..nyquist]) {
+            *sum += bin.norm() * scale;
+        }
+
+        // Skip bin 0 (DC) and very low frequencies below the profile's minimum
+        let min_bin = (min_frequency_hz * FFT_SIZE as f64 / sample_rate as f64) as usize;
+
+        for i in min_bin..nyquist {
+            let magnitude = self.buffer[i].norm();
+            if magnitude > max_magnitude {
+                max_magnitude = magnitude;
+                max_bin = i;
+            }
+        }
+
+        // Convert bin to frequency
+        let freq = max_bin as f64 * sample_rate as f64 / FFT_SIZE as f64;
+
+        // Only return if magnitude is significant
+        if max_magnitude > MIN_FFT_MAGNITUDE {
+            freq
+        } else {
+            0.0
+        }
+    }
+
+    /// Shape of the spectrum left in `buffer` by the last FFT, from the
+    /// profile's minimum frequency to Nyquist; `None` if it holds no energy
+    fn spectral_shape(&self, sample_rate: u32, min_frequency_hz: f64) -> Option<SpectralShape> {
+        let min_bin = ((min_frequency_hz * FFT_SIZE as f64 / sample_rate as f64) as usize).max(1);
+        let bin_hz = sample_rate as f64 / FFT_SIZE as f64;
+        let powers: Vec<f64> = self.buffer[min_bin..FFT_SIZE / 2]
+            .iter()
+            .map(|bin| f64::from(bin.norm_sqr()))
+            .collect();
+        let total: f64 = powers.iter().sum();
+        if total <= f64::from(MIN_FFT_MAGNITUDE).powi(2) {
+            return None;
+        }
+
+        let frequency = |i: usize| (min_bin + i) as f64 * bin_hz;
+        let centroid_hz = powers.iter().enumerate().map(|(i, p)| frequency(i) * p).sum::<f64>() / total;
+        let variance = powers
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (frequency(i) - centroid_hz).powi(2) * p)
+            .sum::<f64>()
+            / total;
+        // Floor each bin so one empty bin doesn't zero the geometric mean
+        let n = powers.len() as f64;
+        let floor = total / n * 1e-10;
+        let log_mean = powers.iter().map(|&p| p.max(floor).ln()).sum::<f64>() / n;
+        Some(SpectralShape {
+            centroid_hz,
+            bandwidth_hz: variance.sqrt(),
+            flatness: log_mean.exp() / (total / n),
+        })
+    }
+
+    /// The last FFT split at 4 kHz; `None` below 16 kHz, where there is
+    /// nothing above it to find
+    fn band_frame(&self, sample_rate: u32) -> Option<BandFrame> {
+        if sample_rate < 16000 {
+            return None;
+        }
+        let powers: Vec<f64> = self.buffer[..FFT_SIZE / 2].iter().map(|bin| f64::from(bin.norm_sqr())).collect();
+        BandFrame::measure(&powers, sample_rate as f64 / FFT_SIZE as f64)
+    }
+}
+
+impl Default for FftEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Real-time audio analyzer
 pub struct AudioAnalyzer {
     sample_rate: u32,
     fft_size: usize,
-    fft_planner: FftPlanner<f32>,
-    fft_buffer: Vec<Complex<f32>>,
-    fft_scratch: Vec<Complex<f32>>,
-    window: Vec<f32>,
+    fft: FftEngine,
+    /// Where windows are sent to be FFT'd, with `--analysis-threads`;
+    /// `None` runs them inline
+    pool: Option<PooledFft>,
+    /// Dominant frequency of the latest FFT to finish
+    latest_freq_hz: f64,
     last_sample: Option<i16>,
     /// Threshold for glitch detection (sample jump size)
     glitch_threshold: i16,
     /// Threshold for silence detection (RMS dB)
     silence_threshold_db: f64,
-    /// Sample buffer for accumulating samples across RTP packets for FFT analysis.
+    /// Lowest frequency considered for the dominant frequency
+    min_frequency_hz: f64,
+    /// Ring buffer accumulating samples across RTP packets for FFT analysis.
     /// RTP packets are typically 160 samples (20ms at 8kHz), but FFT needs 512.
     sample_buffer: Vec<i16>,
+    /// Next write position in `sample_buffer`
+    buffer_pos: usize,
+    /// Number of valid samples in `sample_buffer` (saturates at `fft_size`)
+    buffered: usize,
+    /// Samples added since the last FFT
+    since_fft: usize,
+    /// Interleaved channel count of incoming frames; analysis runs on the mono mix
+    channels: usize,
+    /// Reused buffer for the mono mix of multichannel frames
+    mix_buffer: Vec<i16>,
+    /// Sum of FFT magnitudes per bin since the spectrum was last taken
+    spectrum_sum: Vec<f32>,
+    /// Number of FFTs in `spectrum_sum`
+    spectrum_count: u32,
+    /// K-weighted loudness, measured on every channel rather than the mix
+    loudness: LoudnessMeter,
+    /// Watermark tones, measured on the mix
+    watermark: WatermarkMeter,
 }
 
 impl AudioAnalyzer {
     /// Create a new audio analyzer for the given sample rate
     #[must_use]
     pub fn new(sample_rate: u32) -> Self {
-        let mut fft_planner = FftPlanner::new();
-        let fft = fft_planner.plan_fft_forward(FFT_SIZE);
-        let scratch_len = fft.get_inplace_scratch_len();
-
-        // Create Hann window for better frequency resolution
-        let window: Vec<f32> = (0..FFT_SIZE)
-            .map(|i| {
-                0.5 * (1.0 - (2.0 * std::f32::consts::PI * i as f32 / (FFT_SIZE - 1) as f32).cos())
-            })
-            .collect();
+        Self::with_channels(sample_rate, 1)
+    }
 
+    /// Create an analyzer for interleaved multichannel audio, which is
+    /// mixed down to mono before analysis
+    #[must_use]
+    pub fn with_channels(sample_rate: u32, channels: u8) -> Self {
         Self {
             sample_rate,
             fft_size: FFT_SIZE,
-            fft_planner,
-            fft_buffer: vec![Complex::new(0.0, 0.0); FFT_SIZE],
-            fft_scratch: vec![Complex::new(0.0, 0.0); scratch_len],
-            window,
+            fft: FftEngine::new(),
+            pool: None,
+            latest_freq_hz: 0.0,
             last_sample: None,
             glitch_threshold: GLITCH_THRESHOLD,
             silence_threshold_db: SILENCE_THRESHOLD_DB,
-            sample_buffer: Vec::with_capacity(FFT_SIZE),
+            min_frequency_hz: MIN_FREQUENCY_HZ,
+            sample_buffer: vec![0; FFT_SIZE],
+            buffer_pos: 0,
+            buffered: 0,
+            since_fft: 0,
+            channels: channels.max(1) as usize,
+            mix_buffer: Vec::new(),
+            spectrum_sum: vec![0.0; FFT_SIZE / 2],
+            spectrum_count: 0,
+            loudness: LoudnessMeter::new(sample_rate, channels),
+            watermark: WatermarkMeter::new(sample_rate),
         }
     }
 
-    /// Analyze a frame of 16-bit PCM audio samples
+    /// Judge audio by `profile`'s thresholds instead of the speech ones
+    #[must_use]
+    pub fn with_profile(mut self, profile: AnalysisProfile) -> Self {
+        self.glitch_threshold = profile.glitch_threshold();
+        self.silence_threshold_db = profile.silence_threshold_db();
+        self.min_frequency_hz = profile.min_frequency_hz();
+        self
+    }
+
+    /// Run FFTs on an analysis pool worker rather than inline; `None`
+    /// leaves them inline
+    #[must_use]
+    pub fn with_worker(mut self, worker: Option<AnalysisWorker>) -> Self {
+        self.pool = worker.map(PooledFft::new);
+        self
+    }
+
+    /// Analyze a frame of 16-bit PCM audio samples (interleaved if multichannel)
     pub fn analyze(&mut self, samples: &[i16]) -> AudioAnalysis {
+        let mut loudness = Vec::new();
+        self.loudness.push(samples, &mut loudness);
+
+        let mut analysis = if self.channels == 1 {
+            self.analyze_mono(samples)
+        } else {
+            let mut mix = std::mem::take(&mut self.mix_buffer);
+            mix.clear();
+            mix.extend(samples.chunks_exact(self.channels).map(|frame| {
+                let sum: i32 = frame.iter().map(|&s| s as i32).sum();
+                (sum / self.channels as i32) as i16
+            }));
+            let analysis = self.analyze_mono(&mix);
+            self.mix_buffer = mix;
+            analysis
+        };
+        analysis.loudness = loudness;
+        analysis
+    }
+
+    /// Account for `frames` of audio lost or never sent, which only the
+    /// watermark needs to stay on the RTP timeline. Returns its soft bits
+    pub fn skip(&mut self, frames: u64) -> Vec<f32> {
+        let mut watermark = Vec::new();
+        self.watermark.skip(frames, &mut watermark);
+        watermark
+    }
+
+    fn analyze_mono(&mut self, samples: &[i16]) -> AudioAnalysis {
         if samples.is_empty() {
             return AudioAnalysis::default();
         }
 
         let mut analysis = AudioAnalysis::default();
+        self.watermark.push(samples, &mut analysis.watermark);
 
         // Calculate RMS and peak
         let mut sum_squares: f64 = 0.0;
@@ -328,75 +859,204 @@ impl AudioAnalyzer {
 
         // Accumulate samples for FFT analysis
         // RTP packets are typically 160 samples, but FFT needs 512
-        self.sample_buffer.extend_from_slice(samples);
-
-        // FFT for dominant frequency (only if we have enough buffered samples)
-        if self.sample_buffer.len() >= self.fft_size {
-            // Take the last fft_size samples for analysis
-            let start = self.sample_buffer.len() - self.fft_size;
-            let fft_samples: Vec<i16> = self.sample_buffer[start..].to_vec();
-            analysis.dominant_freq_hz = self.compute_dominant_frequency(&fft_samples);
-
-            // Keep only the last fft_size samples to maintain sliding window
-            // and prevent unbounded growth
-            if start > 0 {
-                self.sample_buffer.drain(0..start);
+        self.push_samples(samples);
+
+        // FFT for dominant frequency once the window is full, at most once per hop
+        if self.buffered == self.fft_size && self.since_fft >= FFT_HOP {
+            self.since_fft = 0;
+            // The oldest sample sits at the write position once the ring is full
+            let (newest, oldest) = self.sample_buffer.split_at(self.buffer_pos);
+            if let Some(ref mut pool) = self.pool {
+                let voiced = !analysis.is_silence && analysis.rms_db.is_finite();
+                if pool.queue(oldest, newest, voiced) {
+                    pool.dispatch(self.sample_rate, self.min_frequency_hz);
+                }
+            } else {
+                let frame = self.fft.run(oldest, newest, self.sample_rate, self.min_frequency_hz, &mut self.spectrum_sum);
+                self.spectrum_count += 1;
+                self.latest_freq_hz = frame.dominant_freq_hz;
+                analysis.dominant_freq_hz = frame.dominant_freq_hz;
+                analysis.spectral_shape = frame.spectral_shape;
+                analysis.band_frame = frame.band_frame;
             }
         }
 
+        if let Some(ref mut pool) = self.pool {
+            let results = pool.try_collect();
+            analysis.pooled = self.merge_pooled(results);
+        }
+
         analysis
     }
 
-    /// Compute dominant frequency using FFT
-    fn compute_dominant_frequency(&mut self, samples: &[i16]) -> f64 {
-        // Take the last fft_size samples
-        let start = samples.len().saturating_sub(self.fft_size);
-        let fft_samples = &samples[start..start + self.fft_size];
-
-        // Apply window and convert to complex (normalize to [-1.0, 1.0])
-        for (i, &sample) in fft_samples.iter().enumerate() {
-            let windowed = sample as f32 * self.window[i] / i16::MAX as f32;
-            self.fft_buffer[i] = Complex::new(windowed, 0.0);
+    /// Fold the spectra of batches back from the pool into the running one,
+    /// returning their frames in order
+    fn merge_pooled(&mut self, results: Vec<FftBatchResult>) -> Vec<FftFrame> {
+        let mut frames = Vec::new();
+        for result in results {
+            for (sum, magnitude) in self.spectrum_sum.iter_mut().zip(&result.spectrum_sum) {
+                *sum += magnitude;
+            }
+            self.spectrum_count += result.frames.len() as u32;
+            frames.extend(result.frames);
         }
+        if let Some(frame) = frames.last() {
+            self.latest_freq_hz = frame.dominant_freq_hz;
+        }
+        frames
+    }
 
-        // Perform FFT
-        let fft = self.fft_planner.plan_fft_forward(self.fft_size);
-        fft.process_with_scratch(&mut self.fft_buffer, &mut self.fft_scratch);
+    /// Wait for the FFTs still on the analysis pool and add them to
+    /// `stats`, so the page's figures come out as inline analysis leaves
+    /// them. Nothing to do when FFTs run inline
+    pub fn finish(&mut self, stats: &mut AudioStats) {
+        let Some(ref mut pool) = self.pool else {
+            return;
+        };
+        pool.dispatch(self.sample_rate, self.min_frequency_hz);
+        let results = pool.collect_all();
+        let frames = self.merge_pooled(results);
+        stats.add_pooled(&frames);
+    }
 
-        // Find peak magnitude (only look at positive frequencies up to Nyquist)
-        let nyquist = self.fft_size / 2;
-        let mut max_magnitude: f32 = 0.0;
-        let mut max_bin = 0;
+    /// Dominant frequency of the latest FFT, for live readings. Up to a
+    /// batch behind the audio when FFTs run on the analysis pool
+    pub fn dominant_freq_hz(&self) -> f64 {
+        self.latest_freq_hz
+    }
 
-        // Skip bin 0 (DC) and very low frequencies below MIN_FREQUENCY_HZ
-        let min_bin = (MIN_FREQUENCY_HZ * self.fft_size as f64 / self.sample_rate as f64) as usize;
+    /// Append samples to the FFT ring buffer, keeping the last `fft_size`
+    fn push_samples(&mut self, samples: &[i16]) {
+        let samples = &samples[samples.len().saturating_sub(self.fft_size)..];
+        let first = samples.len().min(self.fft_size - self.buffer_pos);
+        self.sample_buffer[self.buffer_pos..self.buffer_pos + first].copy_from_slice(&samples[..first]);
+        self.sample_buffer[..samples.len() - first].copy_from_slice(&samples[first..]);
 
-        for i in min_bin..nyquist {
-            let magnitude = self.fft_buffer[i].norm();
-            if magnitude > max_magnitude {
-                max_magnitude = magnitude;
-                max_bin = i;
-            }
+        self.buffer_pos = (self.buffer_pos + samples.len()) % self.fft_size;
+        self.buffered = (self.buffered + samples.len()).min(self.fft_size);
+        self.since_fft += samples.len();
+    }
+
+    /// Take the spectrum averaged since the last call, if any FFTs have run
+    pub fn take_spectrum(&mut self) -> Option<Spectrum> {
+        if self.spectrum_count == 0 {
+            return None;
         }
 
-        // Convert bin to frequency
-        let freq = max_bin as f64 * self.sample_rate as f64 / self.fft_size as f64;
+        let count = self.spectrum_count as f32;
+        let magnitudes = self.spectrum_sum.iter().map(|sum| sum / count).collect();
+        self.spectrum_sum.fill(0.0);
+        self.spectrum_count = 0;
 
-        // Only return if magnitude is significant
-        if max_magnitude > MIN_FFT_MAGNITUDE {
-            freq
-        } else {
-            0.0
-        }
+        Some(Spectrum {
+            sample_rate: self.sample_rate,
+            fft_size: self.fft_size,
+            magnitudes,
+        })
     }
 
     /// Reset state for a new page
     pub fn reset(&mut self) {
         self.last_sample = None;
-        self.sample_buffer.clear();
+        self.buffer_pos = 0;
+        self.buffered = 0;
+        self.since_fft = 0;
+        self.spectrum_sum.fill(0.0);
+        self.spectrum_count = 0;
+        self.latest_freq_hz = 0.0;
+        if let Some(ref mut pool) = self.pool {
+            pool.reset();
+        }
+        self.loudness.reset();
+        self.watermark.reset();
+    }
+}
+
+/// Run the analyzer over interleaved samples in 20ms frames, as if they had
+/// arrived in packets
+pub fn analyze_samples(samples: &[i16], sample_rate: u32, channels: u8, profile: AnalysisProfile) -> AudioStats {
+    let mut analysis = OfflineAnalysis::new(sample_rate, channels, profile);
+    analysis.push(samples);
+    analysis.finish()
+}
+
+/// [`analyze_samples`] for audio read a block at a time, so a long file
+/// needn't be held in memory to be measured
+pub struct OfflineAnalysis {
+    analyzer: AudioAnalyzer,
+    stats: AudioStats,
+    frame: Vec<i16>,
+    frame_len: usize,
+}
+
+impl OfflineAnalysis {
+    pub fn new(sample_rate: u32, channels: u8, profile: AnalysisProfile) -> Self {
+        let frame_len = offline_frame_len(sample_rate, channels);
+        Self {
+            analyzer: AudioAnalyzer::with_channels(sample_rate, channels).with_profile(profile),
+            stats: AudioStats::new(),
+            frame: Vec::with_capacity(frame_len),
+            frame_len,
+        }
+    }
+
+    /// Add interleaved samples, analyzing each 20ms frame they complete
+    pub fn push(&mut self, mut samples: &[i16]) {
+        while !samples.is_empty() {
+            let take = (self.frame_len - self.frame.len()).min(samples.len());
+            self.frame.extend_from_slice(&samples[..take]);
+            samples = &samples[take..];
+            if self.frame.len() == self.frame_len {
+                self.analyze_frame();
+            }
+        }
+    }
+
+    /// The stats, with a final partial frame analyzed as it is
+    pub fn finish(mut self) -> AudioStats {
+        if !self.frame.is_empty() {
+            self.analyze_frame();
+        }
+        self.stats
+    }
+
+    fn analyze_frame(&mut self) {
+        let analysis = self.analyzer.analyze(&self.frame);
+        self.stats.update(&analysis, self.frame.len() as u64);
+        self.frame.clear();
     }
 }
 
+/// Analyze a 16-bit WAV recording the way `analyze_samples` does, reading
+/// it a frame at a time
+pub fn analyze_wav(path: &Path, profile: AnalysisProfile) -> Result<AudioStats, hound::Error> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let channels = u8::try_from(spec.channels).map_err(|_| hound::Error::Unsupported)?;
+    let mut analyzer = AudioAnalyzer::with_channels(spec.sample_rate, channels).with_profile(profile);
+    let mut stats = AudioStats::new();
+    let frame_len = offline_frame_len(spec.sample_rate, channels);
+    let mut frame = Vec::with_capacity(frame_len);
+    let mut samples = reader.samples::<i16>();
+    loop {
+        frame.clear();
+        for sample in samples.by_ref().take(frame_len) {
+            frame.push(sample?);
+        }
+        if frame.is_empty() {
+            break;
+        }
+        let analysis = analyzer.analyze(&frame);
+        stats.update(&analysis, frame.len() as u64);
+    }
+    Ok(stats)
+}
+
+/// Interleaved samples in 20ms of audio
+fn offline_frame_len(sample_rate: u32, channels: u8) -> usize {
+    (sample_rate / 50).max(1) as usize * usize::from(channels.max(1))
+}
+
 /// Format a frequency for display
 pub fn format_frequency(freq: f64) -> String {
     if freq <= 0.0 {
@@ -445,6 +1105,53 @@ mod tests {
         assert!((analysis.dominant_freq_hz - 1000.0).abs() < 100.0);
     }
 
+    #[test]
+    fn test_music_profile_thresholds() {
+        assert_eq!("Music".parse(), Ok(AnalysisProfile::Music));
+        assert!("jazz".parse::<AnalysisProfile>().unwrap_err().contains("expected speech or music"));
+
+        // A -55dB passage and a 22000 jump: silence and a glitch to speech, neither to music
+        let quiet = vec![58i16; 160];
+        let jump = [-11000i16, 11000];
+        let mut speech = AudioAnalyzer::new(8000);
+        let mut music = AudioAnalyzer::new(8000).with_profile(AnalysisProfile::Music);
+        assert!(speech.analyze(&quiet).is_silence);
+        assert!(!music.analyze(&quiet).is_silence);
+        assert_eq!(speech.analyze(&jump).glitch_count, 1);
+        assert_eq!(music.analyze(&jump).glitch_count, 0);
+    }
+
+    #[test]
+    fn test_analyze_wav_matches_samples() {
+        let samples: Vec<i16> = (0..16000)
+            .map(|i| (8000.0 * (2.0 * std::f64::consts::PI * 440.0 * f64::from(i) / 8000.0).sin()) as i16)
+            .collect();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tone.wav");
+        let mut recorder = crate::cli::recorder::WavRecorder::new(&path, 8000, 1).unwrap();
+        recorder.write_samples(&samples).unwrap();
+        recorder.finalize().unwrap();
+
+        let from_wav = analyze_wav(&path, AnalysisProfile::Speech).unwrap();
+        let direct = analyze_samples(&samples, 8000, 1, AnalysisProfile::Speech);
+        assert_eq!(from_wav.frame_count, 100);
+        assert_eq!(from_wav.total_samples, direct.total_samples);
+        assert!((from_wav.avg_rms_db - direct.avg_rms_db).abs() < 1e-9);
+        assert!((from_wav.dominant_freq_hz - 425.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_freq_bins_bounded_under_noise() {
+        let mut stats = AudioStats::new();
+        // A 1kHz tone a third of the time, wandering noise the rest
+        for i in 0..30_000u32 {
+            let dominant_freq_hz = if i % 3 == 0 { 1000.0 } else { f64::from(100 + (i * 7919) % 20_000) };
+            stats.update(&AudioAnalysis { dominant_freq_hz, ..AudioAnalysis::default() }, 160);
+            assert!(stats.freq_bin_count() <= 2 * MAX_FREQ_BINS);
+        }
+        assert!((stats.dominant_freq_hz - 1025.0).abs() < 1e-9);
+    }
+
     #[test]
     fn test_clipping_detection() {
         let mut analyzer = AudioAnalyzer::new(8000);
@@ -469,4 +1176,78 @@ mod tests {
         let analysis = analyzer.analyze(&samples);
         assert!(analysis.glitch_count >= 1);
     }
+
+    #[test]
+    fn test_small_stereo_frames() {
+        // 1ms AES67 packets: 48 stereo frames each, 1kHz on both channels
+        let mut analyzer = AudioAnalyzer::with_channels(48000, 2);
+        let mut stats = AudioStats::new();
+        let mut frequencies = Vec::new();
+
+        for packet in 0..100 {
+            let samples: Vec<i16> = (0..48)
+                .flat_map(|i| {
+                    let t = (packet * 48 + i) as f64 / 48000.0;
+                    let s = (10000.0 * (2.0 * std::f64::consts::PI * 1000.0 * t).sin()) as i16;
+                    [s, s]
+                })
+                .collect();
+            let analysis = analyzer.analyze(&samples);
+            stats.update(&analysis, samples.len() as u64);
+            if analysis.dominant_freq_hz > 0.0 {
+                frequencies.push(analysis.dominant_freq_hz);
+            }
+        }
+
+        // One FFT per hop rather than per packet
+        assert!(!frequencies.is_empty() && frequencies.len() < 50);
+        assert!(frequencies.iter().all(|f| (f - 1000.0).abs() < 100.0));
+        assert!((stats.dominant_freq_hz - 1000.0).abs() < 100.0);
+        // 10000 peak sine = -13.3 dBFS RMS
+        assert!((stats.avg_rms_db + 13.3).abs() < 1.0);
+        assert!((stats.avg_zero_crossing_rate - 2000.0).abs() < 200.0);
+    }
+
+    /// 60 Hz hum under a 1 kHz tone, as seen through a ground loop
+    fn hum_mixture(sample_rate: u32, samples: usize) -> Vec<i16> {
+        (0..samples)
+            .map(|i| {
+                let t = i as f64 / sample_rate as f64;
+                let hum = 6000.0 * (2.0 * std::f64::consts::PI * 60.0 * t).sin();
+                let tone = 12000.0 * (2.0 * std::f64::consts::PI * 1000.0 * t).sin();
+                (hum + tone) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_spectrum_bins_map_to_frequency() {
+        for sample_rate in [8000, 16000] {
+            let mut analyzer = AudioAnalyzer::new(sample_rate);
+            assert!(analyzer.take_spectrum().is_none());
+
+            let samples = hum_mixture(sample_rate, sample_rate as usize / 2);
+            for frame in samples.chunks(sample_rate as usize / 50) {
+                analyzer.analyze(frame);
+            }
+
+            let spectrum = analyzer.take_spectrum().expect("FFTs should have run");
+            assert_eq!(spectrum.magnitudes.len(), FFT_SIZE / 2);
+            assert!((spectrum.bin_width_hz() - sample_rate as f64 / FFT_SIZE as f64).abs() < 1e-9);
+
+            // Both components land within a bin of their true frequency
+            let tone = spectrum.peak_bin(500.0, 2000.0).unwrap();
+            assert!((spectrum.bin_frequency(tone) - 1000.0).abs() <= spectrum.bin_width_hz());
+            let hum = spectrum.peak_bin(30.0, 200.0).unwrap();
+            assert!((spectrum.bin_frequency(hum) - 60.0).abs() <= spectrum.bin_width_hz());
+
+            // Levels are relative to full scale: 12000/32767 = -8.7 dBFS
+            let db = spectrum.magnitudes_db();
+            assert!((db[tone] + 8.7).abs() < 2.0, "tone at {} dB", db[tone]);
+            assert!(db[hum] > db[spectrum.peak_bin(2500.0, 3500.0).unwrap()] + 40.0);
+
+            // Taking the spectrum resets the average
+            assert!(analyzer.take_spectrum().is_none());
+        }
+    }
 }
@@ -0,0 +1,1117 @@
+//! Audio file input for the transmit commands.
+//!
+//! Files are normally probed and decoded with symphonia. Headerless files
+//! (G.711 bytes or raw 16-bit PCM) have nothing to probe, so their layout
+//! comes from `--input-format`, `--input-rate` and `--input-channels`, or is
+//! inferred from a `.ulaw`/`.alaw` extension. Either way the channels are
+//! decoded separately, then mixed down to mono or narrowed to the one
+//! `--channel-select` names, and resampled before encoding.
+//!
+//! The decoded audio is metered before it is sent, so a silent export or a
+//! muted track is refused instead of paged (`--allow-silent` overrides).
+
+use crate::cli::audio_analyzer::{analyze_samples, format_db, AnalysisProfile, AudioStats, OfflineAnalysis};
+use crate::codec::{G711AlawCodec, G711UlawCodec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use symphonia::core::audio::{AudioBufferRef, Signal};
+use symphonia::core::codecs::{Decoder, DecoderOptions};
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::{MediaSourceStream, MediaSourceStreamOptions};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use thiserror::Error;
+
+/// Sample rate assumed for headerless files unless `--input-rate` is given
+pub const DEFAULT_RAW_RATE: u32 = 8000;
+
+/// Average level below which a file is refused as silent, unless `--min-rms-db` is given
+pub const DEFAULT_MIN_RMS_DBFS: f64 = -45.0;
+
+/// A file with more of its 20ms frames than this below the analyzer's
+/// silence threshold is refused as silent
+const MAX_SILENT_PERCENT: f64 = 95.0;
+
+/// Peak above which the encoded audio is likely to clip
+const CLIPPING_PEAK_DBFS: f64 = -0.5;
+
+#[derive(Error, Debug)]
+pub enum AudioInputError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Unsupported audio format: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Audio decode error: {0}")]
+    AudioDecode(String),
+
+    #[error("Invalid input format: {0} (expected ulaw, alaw, s16le or s16be)")]
+    InvalidFormat(String),
+
+    #[error("Invalid raw input: {0}")]
+    InvalidRawInput(String),
+
+    #[error("Invalid channel selection: {0} (expected left, right, mix or a channel number from 1)")]
+    InvalidChannelSelect(String),
+
+    #[error("Channel {requested} requested but the file has {channels} channel(s)")]
+    ChannelOutOfRange { requested: ChannelSelect, channels: usize },
+}
+
+/// Sample encoding of a headerless file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawFormat {
+    /// G.711 u-law, one byte per sample
+    Ulaw,
+    /// G.711 A-law, one byte per sample
+    Alaw,
+    /// Signed 16-bit little-endian PCM
+    S16le,
+    /// Signed 16-bit big-endian PCM
+    S16be,
+}
+
+impl RawFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ulaw => "ulaw",
+            Self::Alaw => "alaw",
+            Self::S16le => "s16le",
+            Self::S16be => "s16be",
+        }
+    }
+
+    fn bytes_per_sample(self) -> usize {
+        match self {
+            Self::Ulaw | Self::Alaw => 1,
+            Self::S16le | Self::S16be => 2,
+        }
+    }
+
+    /// Format implied by a file extension, for the formats that have one
+    fn from_extension(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        match ext.as_str() {
+            "ulaw" | "mulaw" | "ul" => Some(Self::Ulaw),
+            "alaw" | "al" => Some(Self::Alaw),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RawFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for RawFormat {
+    type Err = AudioInputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "ulaw" | "mulaw" | "pcmu" => Ok(Self::Ulaw),
+            "alaw" | "pcma" => Ok(Self::Alaw),
+            "s16le" => Ok(Self::S16le),
+            "s16be" => Ok(Self::S16be),
+            _ => Err(AudioInputError::InvalidFormat(s.to_string())),
+        }
+    }
+}
+
+/// Which channel of a multichannel file to send
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChannelSelect {
+    /// Average every channel
+    #[default]
+    Mix,
+    /// One channel, counted from 0 (left is 0, right 1)
+    Index(usize),
+}
+
+impl ChannelSelect {
+    /// Narrow planar channels to the selection
+    fn apply(self, channels: &[Vec<i16>]) -> Result<Vec<i16>, AudioInputError> {
+        match self {
+            Self::Mix => Ok(mix_down(channels)),
+            Self::Index(index) => channels.get(index).cloned().ok_or(AudioInputError::ChannelOutOfRange {
+                requested: self,
+                channels: channels.len(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ChannelSelect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mix => f.write_str("mix"),
+            Self::Index(0) => f.write_str("left"),
+            Self::Index(1) => f.write_str("right"),
+            Self::Index(index) => write!(f, "{}", index + 1),
+        }
+    }
+}
+
+impl FromStr for ChannelSelect {
+    type Err = AudioInputError;
+
+    /// `left`, `right`, `mix`, or a channel number counted from 1
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "mix" => Ok(Self::Mix),
+            "left" => Ok(Self::Index(0)),
+            "right" => Ok(Self::Index(1)),
+            number => match number.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(Self::Index(n - 1)),
+                _ => Err(AudioInputError::InvalidChannelSelect(s.to_string())),
+            },
+        }
+    }
+}
+
+/// How to read the input file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioInput {
+    /// Headerless sample format; `None` probes the file (or infers from its extension)
+    pub format: Option<RawFormat>,
+    /// Sample rate of headerless input
+    pub sample_rate: u32,
+    /// Interleaved channels of headerless input
+    pub channels: u16,
+    /// The channel to send, or the mix of all of them
+    pub channel: ChannelSelect,
+}
+
+impl Default for AudioInput {
+    fn default() -> Self {
+        Self {
+            format: None,
+            sample_rate: DEFAULT_RAW_RATE,
+            channels: 1,
+            channel: ChannelSelect::Mix,
+        }
+    }
+}
+
+impl AudioInput {
+    /// Headerless format for `path`: the one given, or one inferred from its extension
+    pub fn raw_format(&self, path: &Path) -> Option<RawFormat> {
+        self.format.or_else(|| RawFormat::from_extension(path))
+    }
+
+    /// Notice for a file read as headerless because of its extension
+    pub fn inferred_notice(&self, path: &Path) -> Option<String> {
+        if self.format.is_some() {
+            return None;
+        }
+        let format = RawFormat::from_extension(path)?;
+        Some(format!(
+            "Reading {} as headerless {} at {} Hz, {} channel(s) (from its extension; use --input-format to override)",
+            path.display(),
+            format,
+            self.sample_rate,
+            self.channels
+        ))
+    }
+}
+
+/// Levels of the audio about to be sent
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct AudioLevels {
+    /// Average of the 20ms frames' RMS levels; digital silence is -inf,
+    /// which JSON writes as null
+    #[serde(deserialize_with = "null_as_silence")]
+    #[schemars(with = "Option<f64>")]
+    pub rms_dbfs: f64,
+    #[serde(deserialize_with = "null_as_silence")]
+    #[schemars(with = "Option<f64>")]
+    pub peak_dbfs: f64,
+    /// 20ms frames below the analyzer's silence threshold
+    pub silent_percent: f64,
+}
+
+fn null_as_silence<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::NEG_INFINITY))
+}
+
+impl AudioLevels {
+    /// Meter samples the way the monitors' analyzer would
+    pub fn measure(samples: &[i16], sample_rate: u32) -> Self {
+        Self::from_stats(&analyze_samples(samples, sample_rate, 1, AnalysisProfile::Speech))
+    }
+
+    fn from_stats(stats: &AudioStats) -> Self {
+        Self {
+            rms_dbfs: stats.avg_rms_db,
+            peak_dbfs: stats.max_peak_db,
+            silent_percent: stats.silence_percent(),
+        }
+    }
+
+    /// Why the audio is too quiet to page, if it is
+    pub fn silence(&self, min_rms_dbfs: f64) -> Option<String> {
+        if self.rms_dbfs < min_rms_dbfs {
+            Some(format!(
+                "The audio is effectively silent: average level {} is below {}",
+                format_db(self.rms_dbfs),
+                format_db(min_rms_dbfs)
+            ))
+        } else if self.silent_percent > MAX_SILENT_PERCENT {
+            Some(format!(
+                "The audio is effectively silent: {:.0}% of it is below the silence threshold",
+                self.silent_percent
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Warning for a peak close enough to full scale to clip once encoded
+    pub fn clipping(&self) -> Option<String> {
+        (self.peak_dbfs > CLIPPING_PEAK_DBFS).then(|| {
+            format!(
+                "Peak level {} is within {}dB of full scale; the audio may clip after encoding",
+                format_db(self.peak_dbfs),
+                -CLIPPING_PEAK_DBFS
+            )
+        })
+    }
+}
+
+impl fmt::Display for AudioLevels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "average {}, peak {}, {:.0}% silent",
+            format_db(self.rms_dbfs),
+            format_db(self.peak_dbfs),
+            self.silent_percent
+        )
+    }
+}
+
+/// Refusing audio too quiet to be heard
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelCheck {
+    /// Average level below which the audio is refused
+    pub min_rms_dbfs: f64,
+    /// Send silent audio anyway, with a warning
+    pub allow_silent: bool,
+}
+
+impl Default for LevelCheck {
+    fn default() -> Self {
+        Self {
+            min_rms_dbfs: DEFAULT_MIN_RMS_DBFS,
+            allow_silent: false,
+        }
+    }
+}
+
+/// A file's levels and length, from reading it through once
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileScan {
+    pub levels: AudioLevels,
+    /// Samples at the rate the file was read at
+    pub samples: usize,
+}
+
+/// Meter a file and count its samples a block at a time, as
+/// [`AudioLevels::measure`] would over all of [`read_audio_file`]
+pub fn scan_file(path: &Path, target_rate: u32, input: &AudioInput) -> Result<FileScan, AudioInputError> {
+    let mut reader = AudioReader::open(path, target_rate, input)?;
+    let mut analysis = OfflineAnalysis::new(target_rate, 1, AnalysisProfile::Speech);
+    let mut samples = 0;
+    while let Some(block) = reader.next_block()? {
+        analysis.push(&block);
+        samples += block.len();
+    }
+    Ok(FileScan {
+        levels: AudioLevels::from_stats(&analysis.finish()),
+        samples,
+    })
+}
+
+/// Read a file again just to measure it, for frames cached before their
+/// levels were kept
+pub fn measure_file(path: &Path, target_rate: u32, input: &AudioInput) -> Result<AudioLevels, AudioInputError> {
+    Ok(scan_file(path, target_rate, input)?.levels)
+}
+
+/// Read an audio file and return the selected channel (or the mono mix) at
+/// the target sample rate
+pub fn read_audio_file(path: &Path, target_rate: u32, input: &AudioInput) -> Result<Vec<i16>, AudioInputError> {
+    let (samples, source_rate) = read_audio(path, target_rate, input)?;
+    if samples.is_empty() || source_rate == target_rate {
+        Ok(samples)
+    } else {
+        Ok(simple_resample(&samples, source_rate, target_rate))
+    }
+}
+
+/// Read an audio file and return the selected channel (or the mono mix) at
+/// its own sample rate, which is returned with it (`default_rate` if the
+/// file doesn't say)
+pub fn read_audio(path: &Path, default_rate: u32, input: &AudioInput) -> Result<(Vec<i16>, u32), AudioInputError> {
+    let (channels, source_rate) = read_channels(path, default_rate, input)?;
+    Ok((input.channel.apply(&channels)?, source_rate))
+}
+
+/// Decode every channel of a file at its own rate, which is returned with
+/// them (`default_rate` if the file doesn't say)
+fn read_channels(path: &Path, default_rate: u32, input: &AudioInput) -> Result<(Vec<Vec<i16>>, u32), AudioInputError> {
+    if let Some(format) = input.raw_format(path) {
+        let bytes = std::fs::read(path)?;
+        return Ok((decode_raw(&bytes, format, input.sample_rate, input.channels)?, input.sample_rate));
+    }
+
+    let mut track = DecodedTrack::open(path, default_rate)?;
+    let mut channels: Vec<Vec<i16>> = Vec::new();
+    while let Some(packet) = track.next_packet()? {
+        if channels.len() < packet.len() {
+            channels.resize(packet.len(), Vec::new());
+        }
+        for (channel, samples) in channels.iter_mut().zip(packet) {
+            channel.extend(samples);
+        }
+    }
+    Ok((channels, track.sample_rate))
+}
+
+/// The audio track of a file symphonia can probe
+struct DecodedTrack {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    /// The track's own rate (`default_rate` if it doesn't say)
+    sample_rate: u32,
+}
+
+impl DecodedTrack {
+    fn open(path: &Path, default_rate: u32) -> Result<Self, AudioInputError> {
+        let file = File::open(path)?;
+        let mss = MediaSourceStream::new(Box::new(file), MediaSourceStreamOptions::default());
+
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension() {
+            hint.with_extension(&ext.to_string_lossy());
+        }
+
+        let probed = symphonia::default::get_probe()
+            .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+            .map_err(|e| AudioInputError::UnsupportedFormat(e.to_string()))?;
+
+        let format = probed.format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
+            .ok_or_else(|| AudioInputError::UnsupportedFormat("No audio track found".into()))?;
+
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .map_err(|e| AudioInputError::UnsupportedFormat(e.to_string()))?;
+
+        Ok(Self {
+            track_id: track.id,
+            sample_rate: track.codec_params.sample_rate.unwrap_or(default_rate),
+            format,
+            decoder,
+        })
+    }
+
+    /// The next packet's samples, one `Vec` per channel, or `None` at the end
+    fn next_packet(&mut self) -> Result<Option<Vec<Vec<i16>>>, AudioInputError> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(p) => p,
+                Err(symphonia::core::errors::Error::IoError(_)) => return Ok(None), // EOF
+                Err(e) => return Err(AudioInputError::AudioDecode(e.to_string())),
+            };
+
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let decoded = self
+                .decoder
+                .decode(&packet)
+                .map_err(|e| AudioInputError::AudioDecode(e.to_string()))?;
+
+            // Symphonia buffers are planar: one slice per channel
+            let count = decoded.spec().channels.count();
+            return Ok(Some((0..count).map(|index| convert_to_i16(&decoded, index)).collect()));
+        }
+    }
+
+    /// Back to the first packet; false if the format can't seek
+    fn rewind(&mut self) -> bool {
+        let start = SeekTo::TimeStamp { ts: 0, track_id: self.track_id };
+        if self.format.seek(SeekMode::Accurate, start).is_err() {
+            return false;
+        }
+        self.decoder.reset();
+        true
+    }
+}
+
+/// Frames of headerless input read per block
+const RAW_BLOCK_FRAMES: usize = 4096;
+
+/// Where an [`AudioReader`] gets its channels from
+enum BlockSource {
+    Raw {
+        file: BufReader<File>,
+        format: RawFormat,
+        channels: u16,
+    },
+    Decoded(DecodedTrack),
+}
+
+impl BlockSource {
+    fn open(path: &Path, default_rate: u32, input: &AudioInput) -> Result<(Self, u32), AudioInputError> {
+        if let Some(format) = input.raw_format(path) {
+            // Check the layout before reading any of it
+            decode_raw(&[], format, input.sample_rate, input.channels)?;
+            let file = BufReader::new(File::open(path)?);
+            return Ok((Self::Raw { file, format, channels: input.channels }, input.sample_rate));
+        }
+        let track = DecodedTrack::open(path, default_rate)?;
+        let rate = track.sample_rate;
+        Ok((Self::Decoded(track), rate))
+    }
+
+    /// The next block of planar channels, or `None` at the end
+    fn next_channels(&mut self, sample_rate: u32) -> Result<Option<Vec<Vec<i16>>>, AudioInputError> {
+        match self {
+            Self::Raw { file, format, channels } => {
+                let mut bytes = vec![0; RAW_BLOCK_FRAMES * format.bytes_per_sample() * usize::from(*channels)];
+                let mut filled = 0;
+                while filled < bytes.len() {
+                    match file.read(&mut bytes[filled..]) {
+                        Ok(0) => break,
+                        Ok(n) => filled += n,
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                if filled == 0 {
+                    return Ok(None);
+                }
+                decode_raw(&bytes[..filled], *format, sample_rate, *channels).map(Some)
+            }
+            Self::Decoded(track) => track.next_packet(),
+        }
+    }
+}
+
+/// A file read a block at a time: the selected channel (or the mono mix)
+/// at the target rate, as [`read_audio_file`] returns it in one piece. A
+/// long page needn't be held in memory to be sent
+pub struct AudioReader {
+    path: PathBuf,
+    input: AudioInput,
+    source: BlockSource,
+    source_rate: u32,
+    target_rate: u32,
+    resampler: LinearResampler,
+    finished: bool,
+}
+
+impl AudioReader {
+    pub fn open(path: &Path, target_rate: u32, input: &AudioInput) -> Result<Self, AudioInputError> {
+        let (source, source_rate) = BlockSource::open(path, target_rate, input)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            input: *input,
+            source,
+            source_rate,
+            target_rate,
+            resampler: LinearResampler::new(source_rate, target_rate),
+            finished: false,
+        })
+    }
+
+    /// The next block of samples (possibly empty while the resampler
+    /// catches up), or `None` once the file is done
+    pub fn next_block(&mut self) -> Result<Option<Vec<i16>>, AudioInputError> {
+        if self.finished {
+            return Ok(None);
+        }
+        let Some(channels) = self.source.next_channels(self.source_rate)? else {
+            self.finished = true;
+            return Ok(Some(self.resampler.finish()));
+        };
+        Ok(Some(self.resampler.push(&self.input.channel.apply(&channels)?)))
+    }
+
+    /// Start the file again, for a looping page. Formats that can't seek
+    /// are opened again instead
+    pub fn rewind(&mut self) -> Result<(), AudioInputError> {
+        let rewound = match &mut self.source {
+            BlockSource::Raw { file, .. } => {
+                file.seek(SeekFrom::Start(0))?;
+                true
+            }
+            BlockSource::Decoded(track) => track.rewind(),
+        };
+        if !rewound {
+            self.source = BlockSource::open(&self.path, self.target_rate, &self.input)?.0;
+        }
+        self.resampler = LinearResampler::new(self.source_rate, self.target_rate);
+        self.finished = false;
+        Ok(())
+    }
+}
+
+/// Average planar channels into one
+fn mix_down(channels: &[Vec<i16>]) -> Vec<i16> {
+    match channels {
+        [] => Vec::new(),
+        [only] => only.clone(),
+        _ => {
+            let len = channels.iter().map(Vec::len).min().unwrap_or(0);
+            (0..len)
+                .map(|i| {
+                    let sum: i32 = channels.iter().map(|channel| i32::from(channel[i])).sum();
+                    (sum / channels.len() as i32) as i16
+                })
+                .collect()
+        }
+    }
+}
+
+/// Decode headerless samples into planar channels
+fn decode_raw(bytes: &[u8], format: RawFormat, sample_rate: u32, channels: u16) -> Result<Vec<Vec<i16>>, AudioInputError> {
+    if sample_rate == 0 {
+        return Err(AudioInputError::InvalidRawInput("sample rate must be above 0".into()));
+    }
+    if channels == 0 {
+        return Err(AudioInputError::InvalidRawInput("channel count must be above 0".into()));
+    }
+
+    // A trailing partial sample (or partial frame) is ignored
+    let samples: Vec<i16> = bytes
+        .chunks_exact(format.bytes_per_sample())
+        .map(|chunk| match format {
+            RawFormat::Ulaw => G711UlawCodec::decode_sample(chunk[0]),
+            RawFormat::Alaw => G711AlawCodec::decode_sample(chunk[0]),
+            RawFormat::S16le => i16::from_le_bytes([chunk[0], chunk[1]]),
+            RawFormat::S16be => i16::from_be_bytes([chunk[0], chunk[1]]),
+        })
+        .collect();
+
+    let count = usize::from(channels);
+    let mut planar = vec![Vec::with_capacity(samples.len() / count); count];
+    for frame in samples.chunks_exact(count) {
+        for (channel, &sample) in planar.iter_mut().zip(frame) {
+            channel.push(sample);
+        }
+    }
+    Ok(planar)
+}
+
+/// Convert one channel of an audio buffer to i16 samples
+fn convert_to_i16(buffer: &AudioBufferRef, channel: usize) -> Vec<i16> {
+    match buffer {
+        AudioBufferRef::S8(buf) => buf
+            .chan(channel)
+            .iter()
+            .map(|&s| (s as i16) * 256)
+            .collect(),
+        AudioBufferRef::S16(buf) => buf.chan(channel).to_vec(),
+        AudioBufferRef::S32(buf) => buf.chan(channel).iter().map(|&s| (s >> 16) as i16).collect(),
+        AudioBufferRef::F32(buf) => buf
+            .chan(channel)
+            .iter()
+            .map(|&s| (s * 32767.0).clamp(-32768.0, 32767.0) as i16)
+            .collect(),
+        AudioBufferRef::F64(buf) => buf
+            .chan(channel)
+            .iter()
+            .map(|&s| (s * 32767.0).clamp(-32768.0, 32767.0) as i16)
+            .collect(),
+        AudioBufferRef::U8(buf) => buf
+            .chan(channel)
+            .iter()
+            .map(|&s| (s as i16 - 128) * 256)
+            .collect(),
+        AudioBufferRef::U16(buf) => buf
+            .chan(channel)
+            .iter()
+            .map(|&s| (s as i32 - 32768) as i16)
+            .collect(),
+        AudioBufferRef::U24(buf) => buf
+            .chan(channel)
+            .iter()
+            .map(|&s| ((s.inner() as i32 - 8_388_608) >> 8) as i16)
+            .collect(),
+        AudioBufferRef::S24(buf) => buf
+            .chan(channel)
+            .iter()
+            .map(|&s| (s.inner() >> 8) as i16)
+            .collect(),
+        AudioBufferRef::U32(buf) => buf
+            .chan(channel)
+            .iter()
+            // Convert unsigned 32-bit to signed 16-bit: subtract 2^31 to center, then shift
+            .map(|&s| ((s as i64 - (1_i64 << 31)) >> 16) as i16)
+            .collect(),
+    }
+}
+
+/// Simple linear interpolation resampling
+fn simple_resample(samples: &[i16], from_rate: u32, to_rate: u32) -> Vec<i16> {
+    let ratio = from_rate as f64 / to_rate as f64;
+    let new_len = (samples.len() as f64 / ratio) as usize;
+
+    (0..new_len)
+        .map(|i| {
+            let pos = i as f64 * ratio;
+            let idx = pos.floor() as usize;
+            let frac = pos.fract();
+
+            if idx + 1 >= samples.len() {
+                samples[idx.min(samples.len() - 1)]
+            } else {
+                let a = samples[idx] as f64;
+                let b = samples[idx + 1] as f64;
+                (a + (b - a) * frac) as i16
+            }
+        })
+        .collect()
+}
+
+/// [`simple_resample`] a block at a time, with the same output as
+/// resampling the whole stream at once
+#[derive(Debug, Clone)]
+struct LinearResampler {
+    /// Input samples per output sample; `None` passes input straight through
+    ratio: Option<f64>,
+    /// Input from the one the next output sample starts at
+    pending: Vec<i16>,
+    /// Stream position of `pending[0]`
+    base: usize,
+    /// Output samples made so far
+    produced: usize,
+}
+
+impl LinearResampler {
+    fn new(from_rate: u32, to_rate: u32) -> Self {
+        Self {
+            ratio: (from_rate != to_rate).then(|| f64::from(from_rate) / f64::from(to_rate)),
+            pending: Vec::new(),
+            base: 0,
+            produced: 0,
+        }
+    }
+
+    /// Add a block of input, returning the output it completes
+    fn push(&mut self, samples: &[i16]) -> Vec<i16> {
+        let Some(ratio) = self.ratio else {
+            return samples.to_vec();
+        };
+        self.pending.extend_from_slice(samples);
+        let end = self.base + self.pending.len();
+        let mut output = Vec::new();
+        loop {
+            let pos = self.produced as f64 * ratio;
+            let idx = pos.floor() as usize;
+            if idx + 1 >= end {
+                break;
+            }
+            let a = f64::from(self.pending[idx - self.base]);
+            let b = f64::from(self.pending[idx + 1 - self.base]);
+            output.push((a + (b - a) * pos.fract()) as i16);
+            self.produced += 1;
+        }
+        let keep_from = ((self.produced as f64 * ratio).floor() as usize).min(end);
+        self.pending.drain(..keep_from - self.base);
+        self.base = keep_from;
+        output
+    }
+
+    /// The rest of the output, now the stream's length is known
+    fn finish(&mut self) -> Vec<i16> {
+        let Some(ratio) = self.ratio else {
+            return Vec::new();
+        };
+        let len = self.base + self.pending.len();
+        let new_len = (len as f64 / ratio) as usize;
+        let mut output = Vec::new();
+        while self.produced < new_len {
+            let idx = (self.produced as f64 * ratio).floor() as usize;
+            output.push(self.pending[idx.min(len - 1) - self.base]);
+            self.produced += 1;
+        }
+        output
+    }
+}
+
+/// Zero crossings each side of a sample in the [`resample`] kernel, at the
+/// lower of the two rates
+const RESAMPLE_ZERO_CROSSINGS: f64 = 16.0;
+
+/// Share of the lower rate's Nyquist frequency [`resample`] passes; the rest
+/// is the filter's transition band
+const RESAMPLE_PASSBAND: f64 = 0.92;
+
+/// Band-limited resampling with a Blackman-windowed sinc. Slower than
+/// [`simple_resample`], but downsampling doesn't fold the top octave back
+/// into the audio (16 kHz G.722 to 8 kHz, say)
+pub fn resample(samples: &[i16], from_rate: u32, to_rate: u32) -> Vec<i16> {
+    if from_rate == to_rate || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let kernel = SincKernel::new(from_rate, to_rate);
+    let new_len = (samples.len() as f64 / kernel.step) as usize;
+    (0..new_len).map(|i| kernel.sample_at(samples, i as f64 * kernel.step)).collect()
+}
+
+/// The [`resample`] filter between two rates
+#[derive(Debug, Clone, Copy)]
+struct SincKernel {
+    /// Input samples per output sample
+    step: f64,
+    /// Cutoff relative to the input's Nyquist frequency
+    cutoff: f64,
+    /// Input samples each side of an output sample that contribute to it
+    half_width: f64,
+}
+
+impl SincKernel {
+    fn new(from_rate: u32, to_rate: u32) -> Self {
+        let step = f64::from(from_rate) / f64::from(to_rate);
+        let cutoff = RESAMPLE_PASSBAND * (1.0 / step).min(1.0);
+        Self { step, cutoff, half_width: RESAMPLE_ZERO_CROSSINGS / cutoff }
+    }
+
+    /// The output sample centred on input position `center`; input beyond
+    /// either end of `samples` counts as silence
+    fn sample_at(&self, samples: &[i16], center: f64) -> i16 {
+        let first = (center - self.half_width).ceil().max(0.0) as usize;
+        let last = ((center + self.half_width).floor() as usize).min(samples.len() - 1);
+        let mut sum = 0.0;
+        for (k, &sample) in samples.iter().enumerate().take(last + 1).skip(first) {
+            let x = k as f64 - center;
+            let phase = std::f64::consts::PI * (x / self.half_width + 1.0);
+            let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+            let arg = std::f64::consts::PI * self.cutoff * x;
+            let sinc = if arg.abs() < 1e-12 { 1.0 } else { arg.sin() / arg };
+            sum += f64::from(sample) * self.cutoff * sinc * window;
+        }
+        sum.round().clamp(-32768.0, 32767.0) as i16
+    }
+}
+
+/// [`resample`] for audio that arrives a block at a time, as from a live
+/// stream. Each output sample is made once the input it needs has arrived,
+/// so blocks join without a click; that holds the output back by about
+/// 2ms. Matches resampling the whole stream at once.
+#[derive(Debug, Clone)]
+pub struct StreamResampler {
+    kernel: Option<SincKernel>,
+    /// Input not yet finished with
+    pending: Vec<i16>,
+    /// Position of the next output sample in `pending`
+    position: f64,
+}
+
+impl StreamResampler {
+    pub fn new(from_rate: u32, to_rate: u32) -> Self {
+        Self {
+            kernel: (from_rate != to_rate).then(|| SincKernel::new(from_rate, to_rate)),
+            pending: Vec::new(),
+            position: 0.0,
+        }
+    }
+
+    /// Add a block of input, returning the output it completes
+    pub fn push(&mut self, samples: &[i16]) -> Vec<i16> {
+        let Some(kernel) = self.kernel else {
+            return samples.to_vec();
+        };
+        self.pending.extend_from_slice(samples);
+        let mut output = Vec::new();
+        while self.position + kernel.half_width <= (self.pending.len() - 1) as f64 {
+            output.push(kernel.sample_at(&self.pending, self.position));
+            self.position += kernel.step;
+        }
+        // Keep only the input the next output sample still reaches back to
+        let consumed = ((self.position - kernel.half_width).ceil().max(0.0) as usize).min(self.pending.len());
+        self.pending.drain(..consumed);
+        self.position -= consumed as f64;
+        output
+    }
+
+    /// The output still held back, as if the stream ended in silence
+    pub fn finish(&mut self) -> Vec<i16> {
+        let Some(kernel) = self.kernel else {
+            return Vec::new();
+        };
+        let end = self.pending.len() as f64;
+        let mut output = Vec::new();
+        while self.position < end && !self.pending.is_empty() {
+            output.push(kernel.sample_at(&self.pending, self.position));
+            self.position += kernel.step;
+        }
+        self.pending.clear();
+        self.position = 0.0;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::AudioEncoder;
+    use std::io::Write;
+
+    #[test]
+    fn test_resample_band_limits() {
+        let tone = |frequency: f64, rate: u32| -> Vec<i16> {
+            (0..rate)
+                .map(|i| (10000.0 * (2.0 * std::f64::consts::PI * frequency * f64::from(i) / f64::from(rate)).sin()) as i16)
+                .collect()
+        };
+        let rms = |samples: &[i16]| {
+            // Skip the filter's run-in at each end
+            let middle = &samples[samples.len() / 10..samples.len() * 9 / 10];
+            (middle.iter().map(|&s| f64::from(s).powi(2)).sum::<f64>() / middle.len() as f64).sqrt()
+        };
+
+        // 1 kHz passes at its level; 6 kHz is above 8 kHz's Nyquist and is removed, not folded to 2 kHz
+        let passed = resample(&tone(1000.0, 16000), 16000, 8000);
+        assert_eq!(passed.len(), 8000);
+        assert!((rms(&passed) / (10000.0 / 2f64.sqrt()) - 1.0).abs() < 0.02, "{}", rms(&passed));
+        let aliased = simple_resample(&tone(6000.0, 16000), 16000, 8000);
+        let filtered = resample(&tone(6000.0, 16000), 16000, 8000);
+        assert!(rms(&aliased) > 3000.0, "{}", rms(&aliased));
+        assert!(rms(&filtered) < 10.0, "{}", rms(&filtered));
+
+        // Upsampling keeps the level too
+        let up = resample(&tone(1000.0, 8000), 8000, 48000);
+        assert_eq!(up.len(), 48000);
+        assert!((rms(&up) / (10000.0 / 2f64.sqrt()) - 1.0).abs() < 0.02, "{}", rms(&up));
+    }
+
+    #[test]
+    fn test_stream_resampler_matches_whole_stream() {
+        let tone: Vec<i16> = (0..8000)
+            .map(|i| (10000.0 * (2.0 * std::f64::consts::PI * 1000.0 * f64::from(i) / 8000.0).sin()) as i16)
+            .collect();
+        let whole = resample(&tone, 8000, 16000);
+
+        // 20ms packets, as a live stream delivers them
+        let mut stream = StreamResampler::new(8000, 16000);
+        let mut joined: Vec<i16> = tone.chunks(160).flat_map(|block| stream.push(block)).collect();
+        joined.extend(stream.finish());
+        assert_eq!(joined.len(), whole.len());
+        assert!(joined.iter().zip(&whole).all(|(a, b)| (a - b).abs() <= 1));
+
+        // Same rate passes straight through
+        let mut same = StreamResampler::new(8000, 8000);
+        assert_eq!(same.push(&tone[..160]), tone[..160].to_vec());
+        assert!(same.finish().is_empty());
+    }
+
+    #[test]
+    fn test_simple_resample() {
+        let samples: Vec<i16> = vec![0, 100, 200, 300, 400, 500, 600, 700];
+
+        // Downsample 2:1
+        let resampled = simple_resample(&samples, 16000, 8000);
+        assert_eq!(resampled.len(), 4);
+
+        // Upsample 1:2
+        let resampled = simple_resample(&samples, 8000, 16000);
+        assert_eq!(resampled.len(), 16);
+    }
+
+    #[test]
+    fn test_parse_raw_format() {
+        assert_eq!("ulaw".parse::<RawFormat>().unwrap(), RawFormat::Ulaw);
+        assert_eq!("PCMA".parse::<RawFormat>().unwrap(), RawFormat::Alaw);
+        assert_eq!("s16be".parse::<RawFormat>().unwrap(), RawFormat::S16be);
+        assert!("s24le".parse::<RawFormat>().is_err());
+    }
+
+    #[test]
+    fn test_ulaw_file_inferred_from_extension() {
+        let tone: Vec<i16> = (0..1600)
+            .map(|i| ((i as f64 * 2.0 * std::f64::consts::PI * 1000.0 / 8000.0).sin() * 8000.0) as i16)
+            .collect();
+        let bytes = G711UlawCodec::new().encode(&tone).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.ulaw");
+        std::fs::File::create(&path).unwrap().write_all(&bytes).unwrap();
+
+        let input = AudioInput::default();
+        assert!(input.inferred_notice(&path).unwrap().contains("ulaw"));
+
+        let samples = read_audio_file(&path, 8000, &input).unwrap();
+        assert_eq!(samples.len(), tone.len());
+        // u-law keeps samples within a few percent of the original
+        for (decoded, original) in samples.iter().zip(&tone) {
+            assert!((i32::from(*decoded) - i32::from(*original)).abs() <= 300);
+        }
+
+        // Resampled to the encoder's rate like any other file
+        assert_eq!(read_audio_file(&path, 16000, &input).unwrap().len(), 3200);
+
+        // An explicit format wins over the extension
+        let explicit = AudioInput { format: Some(RawFormat::Alaw), ..input };
+        assert!(explicit.inferred_notice(&path).is_none());
+        assert_eq!(explicit.raw_format(&path), Some(RawFormat::Alaw));
+    }
+
+    #[test]
+    fn test_raw_pcm_endianness_and_channels() {
+        let bytes = [0x01, 0x00, 0x03, 0x00, 0xff, 0x7f];
+        assert_eq!(decode_raw(&bytes, RawFormat::S16le, 8000, 1).unwrap(), [vec![1, 3, 32767]]);
+        assert_eq!(decode_raw(&bytes, RawFormat::S16be, 8000, 1).unwrap(), [vec![256, 768, -129]]);
+
+        // Stereo frames are split into channels; the odd trailing sample is dropped
+        let stereo = decode_raw(&bytes, RawFormat::S16le, 8000, 2).unwrap();
+        assert_eq!(stereo, [vec![1], vec![3]]);
+        assert_eq!(mix_down(&stereo), [2]);
+
+        assert!(decode_raw(&bytes, RawFormat::S16le, 0, 1).is_err());
+        assert!(decode_raw(&bytes, RawFormat::S16le, 8000, 0).is_err());
+    }
+
+    /// One second of 500 Hz on the left and 2 kHz on the right
+    fn write_stereo_wav(path: &Path) {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for i in 0..8000 {
+            let t = f64::from(i) / 8000.0;
+            writer.write_sample((8000.0 * (t * 2.0 * std::f64::consts::PI * 500.0).sin()) as i16).unwrap();
+            writer.write_sample((8000.0 * (t * 2.0 * std::f64::consts::PI * 2000.0).sin()) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    /// Zero crossings per second, twice the frequency of a pure tone
+    fn crossings(samples: &[i16]) -> usize {
+        samples.windows(2).filter(|pair| (pair[0] < 0) != (pair[1] < 0)).count()
+    }
+
+    #[test]
+    fn test_channel_select() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zones.wav");
+        write_stereo_wav(&path);
+
+        let read = |channel: &str| {
+            let input = AudioInput {
+                channel: channel.parse().unwrap(),
+                ..AudioInput::default()
+            };
+            read_audio_file(&path, 8000, &input)
+        };
+        let left = read("left").unwrap();
+        let right = read("2").unwrap();
+        assert_eq!(left.len(), 8000);
+        assert!((crossings(&left) as i64 - 1000).abs() < 10, "{}", crossings(&left));
+        assert!((crossings(&right) as i64 - 4000).abs() < 10, "{}", crossings(&right));
+
+        // The mix keeps every frame rather than folding channel 0 onto itself
+        assert_eq!(read("mix").unwrap().len(), 8000);
+
+        assert!(matches!(
+            read("3"),
+            Err(AudioInputError::ChannelOutOfRange { channels: 2, .. })
+        ));
+        assert!("0".parse::<ChannelSelect>().is_err());
+        assert!("centre".parse::<ChannelSelect>().is_err());
+        assert_eq!(ChannelSelect::Index(1).to_string(), "right");
+        assert_eq!(ChannelSelect::Index(4).to_string(), "5");
+    }
+
+    /// Every block a reader gives, joined
+    fn read_blocks(reader: &mut AudioReader) -> Vec<i16> {
+        let mut samples = Vec::new();
+        while let Some(block) = reader.next_block().unwrap() {
+            samples.extend(block);
+        }
+        samples
+    }
+
+    #[test]
+    fn test_reader_matches_whole_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zones.wav");
+        write_stereo_wav(&path);
+
+        // Resampled block by block exactly as in one piece, down and up
+        for (rate, channel) in [(8000, "right"), (16000, "mix"), (6000, "left")] {
+            let input = AudioInput {
+                channel: channel.parse().unwrap(),
+                ..AudioInput::default()
+            };
+            let whole = read_audio_file(&path, rate, &input).unwrap();
+            let mut reader = AudioReader::open(&path, rate, &input).unwrap();
+            assert_eq!(read_blocks(&mut reader), whole, "{}Hz {}", rate, channel);
+
+            // A looping page starts again from the top
+            reader.rewind().unwrap();
+            assert_eq!(read_blocks(&mut reader), whole, "{}Hz {} rewound", rate, channel);
+        }
+
+        // Headerless input is read in blocks of its own
+        let raw = dir.path().join("page.pcm");
+        let tone: Vec<i16> = (0..20_000).map(|i| ((i % 50) * 400 - 10_000) as i16).collect();
+        let bytes: Vec<u8> = tone.iter().flat_map(|s| s.to_le_bytes()).collect();
+        std::fs::write(&raw, bytes).unwrap();
+        let input = AudioInput {
+            format: Some(RawFormat::S16le),
+            ..AudioInput::default()
+        };
+        let mut reader = AudioReader::open(&raw, 16000, &input).unwrap();
+        assert_eq!(read_blocks(&mut reader), read_audio_file(&raw, 16000, &input).unwrap());
+        reader.rewind().unwrap();
+        assert_eq!(read_blocks(&mut reader).len(), 40_000);
+
+        let scan = scan_file(&path, 8000, &AudioInput::default()).unwrap();
+        assert_eq!(scan.samples, 8000);
+        let whole = read_audio_file(&path, 8000, &AudioInput::default()).unwrap();
+        assert_eq!(scan.levels, AudioLevels::measure(&whole, 8000));
+    }
+
+    #[test]
+    fn test_mostly_silent_audio() {
+        // Half a second of tone in 20s of digital silence: the tone alone sets
+        // the average, but nearly every frame is silent
+        let mut samples = vec![0i16; 160_000];
+        for (i, sample) in samples.iter_mut().take(4000).enumerate() {
+            *sample = if i % 8 < 4 { 8000 } else { -8000 };
+        }
+        let levels = AudioLevels::measure(&samples, 8000);
+        assert!(levels.rms_dbfs > DEFAULT_MIN_RMS_DBFS, "{}", levels);
+        assert!(levels.silence(DEFAULT_MIN_RMS_DBFS).unwrap().contains("98% of it"));
+        assert!(levels.clipping().is_none());
+
+        // Digital silence has no level at all, which JSON writes as null
+        let silent = AudioLevels::measure(&[0; 8000], 8000);
+        assert!(silent.rms_dbfs.is_infinite() && silent.rms_dbfs < 0.0);
+        assert!(silent.silence(DEFAULT_MIN_RMS_DBFS).unwrap().contains("-inf is below"));
+        let json = serde_json::to_value(silent).unwrap();
+        assert!(json["rms_dbfs"].is_null());
+        assert_eq!(serde_json::from_value::<AudioLevels>(json).unwrap(), silent);
+    }
+}
@@ -0,0 +1,355 @@
+//! Piping each page's decoded audio to an external command.
+//!
+//! With `--pipe-audio-cmd`, `monitor` and `test` start the given command for
+//! every page they record and write the page's audio to its stdin as it is
+//! decoded: 16-bit little-endian PCM, channels interleaved, the same samples
+//! as the WAV recording. `{rate}` and `{channels}` in the command are
+//! replaced with the page's. Whatever the command prints is kept as the
+//! page's `pipe_output` with its exit status, so a speech recognizer reading
+//! stdin leaves a transcript of each announcement without this tool
+//! bundling one.
+//!
+//! The command never holds up the page. Audio goes to a writer thread
+//! through a bounded queue, as sends go out of `forward` without waiting:
+//! when the command falls [`PIPE_QUEUE_CHUNKS`] behind, what it couldn't
+//! take is dropped and counted. At page end its stdin is closed and it is
+//! waited for alongside the recording on the finalize queue, for at most
+//! [`PIPE_EXIT_TIMEOUT`] before it is killed. A command that can't be
+//! started, fails or times out is reported, and the page is recorded and
+//! analysed as usual.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Decoded chunks queued for the command: five seconds of 20ms packets
+pub const PIPE_QUEUE_CHUNKS: usize = 250;
+
+/// How long a command has after its page ends to finish and exit
+pub const PIPE_EXIT_TIMEOUT: Duration = Duration::from_mins(1);
+
+/// Output kept per page; a command printing more is cut off here
+const MAX_OUTPUT_BYTES: usize = 1 << 20;
+
+/// How long the rest of the output has once the command has exited. A
+/// process it left behind can hold its stdout open
+const OUTPUT_GRACE: Duration = Duration::from_secs(2);
+
+/// Placeholders a command may use
+const PLACEHOLDERS: [&str; 2] = ["rate", "channels"];
+
+#[derive(Error, Debug)]
+pub enum AudioPipeError {
+    #[error("--pipe-audio-cmd is empty")]
+    Empty,
+
+    #[error("Unknown placeholder {{{0}}} in --pipe-audio-cmd; it takes {{rate}} and {{channels}}")]
+    UnknownPlaceholder(String),
+
+    #[error("Unable to start `{command}`: {source}")]
+    Spawn { command: String, source: io::Error },
+}
+
+/// A `--pipe-audio-cmd` template, run through the shell for each page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipeCommand {
+    template: String,
+}
+
+impl FromStr for PipeCommand {
+    type Err = AudioPipeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Err(AudioPipeError::Empty);
+        }
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            let Some(end) = rest[start..].find('}') else {
+                break;
+            };
+            let name = &rest[start + 1..start + end];
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(AudioPipeError::UnknownPlaceholder(name.to_string()));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        Ok(Self { template: s.to_string() })
+    }
+}
+
+impl PipeCommand {
+    /// The command line for a page of `rate` Hz, `channels`-channel audio
+    pub fn render(&self, rate: u32, channels: u8) -> String {
+        self.template.replace("{rate}", &rate.to_string()).replace("{channels}", &channels.to_string())
+    }
+
+    /// Start the command for a page
+    pub fn start(&self, rate: u32, channels: u8) -> Result<AudioPipe, AudioPipeError> {
+        AudioPipe::start(self.render(rate, channels), channels)
+    }
+}
+
+/// The shell command line `command` runs as
+fn shell(command: &str) -> Command {
+    #[cfg(windows)]
+    let mut shell = Command::new("cmd");
+    #[cfg(windows)]
+    shell.arg("/C");
+    #[cfg(not(windows))]
+    let mut shell = Command::new("sh");
+    #[cfg(not(windows))]
+    shell.arg("-c");
+    shell.arg(command);
+    shell
+}
+
+/// What a page's `--pipe-audio-cmd` made of its audio
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PipeOutput {
+    /// The command run, placeholders filled in
+    pub command: String,
+    /// What it wrote to stdout, such as a transcript
+    pub output: String,
+    /// The output went past 1MB, or was still coming after the command
+    /// exited, and was cut off
+    #[serde(default)]
+    pub output_truncated: bool,
+    /// Exit code; absent when it was killed or ended by a signal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Still running a minute after the page ended, and killed
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Samples written to its stdin, all channels counted
+    pub samples_piped: u64,
+    /// Samples it didn't keep up with, or that came after it stopped reading
+    #[serde(default)]
+    pub samples_dropped: u64,
+}
+
+impl PipeOutput {
+    /// Whether the command ran to a clean exit
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == Some(0) && !self.timed_out
+    }
+}
+
+/// A page's command, taking the page's audio as it is decoded
+pub struct AudioPipe {
+    command: String,
+    channels: u8,
+    child: Option<Child>,
+    queue: Option<SyncSender<Vec<i16>>>,
+    /// Samples the writer thread has written to the command's stdin
+    written: Arc<AtomicU64>,
+    output: Arc<Mutex<Vec<u8>>>,
+    /// Says whether the output was cut off, once stdout closes
+    output_done: Receiver<bool>,
+    samples_queued: u64,
+    samples_dropped: u64,
+}
+
+impl AudioPipe {
+    /// Run `command` through the shell with its stdin and stdout piped
+    pub fn start(command: String, channels: u8) -> Result<Self, AudioPipeError> {
+        let mut child = shell(&command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|source| AudioPipeError::Spawn { command: command.clone(), source })?;
+        let (mut stdin, mut stdout) = (child.stdin.take(), child.stdout.take());
+
+        let (queue, chunks) = mpsc::sync_channel::<Vec<i16>>(PIPE_QUEUE_CHUNKS);
+        let written = Arc::new(AtomicU64::new(0));
+        let counter = written.clone();
+        thread::spawn(move || {
+            let Some(ref mut stdin) = stdin else {
+                return;
+            };
+            for samples in chunks {
+                let bytes: Vec<u8> = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+                // The command stopped reading; later audio is dropped
+                if stdin.write_all(&bytes).is_err() {
+                    break;
+                }
+                counter.fetch_add(samples.len() as u64, Ordering::Relaxed);
+            }
+        });
+
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let (done, output_done) = mpsc::channel();
+        let kept = output.clone();
+        thread::spawn(move || {
+            let Some(ref mut stdout) = stdout else {
+                let _ = done.send(false);
+                return;
+            };
+            let mut buffer = [0u8; 8192];
+            let mut truncated = false;
+            // Read to the end even past the limit, so the command isn't blocked on a full pipe
+            while let Ok(read @ 1..) = stdout.read(&mut buffer) {
+                let Ok(mut kept) = kept.lock() else {
+                    break;
+                };
+                let room = MAX_OUTPUT_BYTES.saturating_sub(kept.len());
+                kept.extend_from_slice(&buffer[..read.min(room)]);
+                truncated |= read > room;
+            }
+            let _ = done.send(truncated);
+        });
+
+        Ok(Self {
+            command,
+            channels,
+            child: Some(child),
+            queue: Some(queue),
+            written,
+            output,
+            output_done,
+            samples_queued: 0,
+            samples_dropped: 0,
+        })
+    }
+
+    /// Pass decoded samples on, or count them dropped if the command is
+    /// too far behind or has stopped reading
+    pub fn push(&mut self, samples: &[i16]) {
+        if samples.is_empty() {
+            return;
+        }
+        let queued = self.queue.as_ref().is_some_and(|queue| queue.try_send(samples.to_vec()).is_ok());
+        if queued {
+            self.samples_queued += samples.len() as u64;
+        } else {
+            self.samples_dropped += samples.len() as u64;
+        }
+    }
+
+    /// Pass on `frames` frames of silence (all channels), as the recording gets
+    pub fn push_silence(&mut self, frames: u64) {
+        if frames > 0 {
+            self.push(&vec![0; (frames * u64::from(self.channels)) as usize]);
+        }
+    }
+
+    /// Close the command's stdin and wait for it to exit, killing it after
+    /// [`PIPE_EXIT_TIMEOUT`]. Blocks, so it runs on the finalize queue
+    pub fn finish(self) -> PipeOutput {
+        self.finish_within(PIPE_EXIT_TIMEOUT)
+    }
+
+    fn finish_within(mut self, timeout: Duration) -> PipeOutput {
+        // Once the writer has caught up, it drops stdin
+        drop(self.queue.take());
+        let mut timed_out = false;
+        let status = self.child.take().and_then(|mut child| {
+            let deadline = Instant::now() + timeout;
+            loop {
+                match child.try_wait() {
+                    Ok(Some(status)) => return Some(status),
+                    Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+                    Ok(None) => {
+                        timed_out = true;
+                        let _ = child.kill();
+                        return child.wait().ok();
+                    }
+                    Err(_) => return None,
+                }
+            }
+        });
+
+        let output_truncated = self.output_done.recv_timeout(OUTPUT_GRACE).unwrap_or(true);
+        let output = self.output.lock().map(|output| String::from_utf8_lossy(&output).into_owned()).unwrap_or_default();
+        let samples_piped = self.written.load(Ordering::Relaxed);
+        PipeOutput {
+            command: self.command.clone(),
+            output,
+            output_truncated,
+            exit_code: status.and_then(|status| status.code()).filter(|_| !timed_out),
+            timed_out,
+            samples_piped,
+            samples_dropped: self.samples_dropped + self.samples_queued.saturating_sub(samples_piped),
+        }
+    }
+}
+
+impl Drop for AudioPipe {
+    /// A page dropped without finishing doesn't leave its command running
+    fn drop(&mut self) {
+        drop(self.queue.take());
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_placeholders() {
+        let command: PipeCommand = "asr --stdin --rate {rate} --channels {channels}".parse().unwrap();
+        assert_eq!(command.render(16000, 1), "asr --stdin --rate 16000 --channels 1");
+        assert!(matches!("asr {language}".parse::<PipeCommand>(), Err(AudioPipeError::UnknownPlaceholder(name)) if name == "language"));
+        assert!(matches!(" ".parse::<PipeCommand>(), Err(AudioPipeError::Empty)));
+    }
+
+    #[test]
+    fn test_pipes_samples_and_keeps_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("piped.raw");
+        let command: PipeCommand = format!("cat > {} && echo {{rate}}Hz {{channels}}ch", path.display()).parse().unwrap();
+        let mut pipe = command.start(8000, 2).unwrap();
+        pipe.push(&[1, -2, 300, -400]);
+        pipe.push_silence(2);
+        pipe.push(&[i16::MAX, i16::MIN]);
+        let output = pipe.finish();
+
+        assert!(output.succeeded(), "{:?}", output);
+        assert_eq!(output.output, "8000Hz 2ch\n");
+        assert_eq!((output.samples_piped, output.samples_dropped), (10, 0));
+        let bytes = std::fs::read(&path).unwrap();
+        let samples: Vec<i16> = bytes.chunks(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]])).collect();
+        assert_eq!(samples, [1, -2, 300, -400, 0, 0, 0, 0, i16::MAX, i16::MIN]);
+    }
+
+    #[test]
+    fn test_failure_and_timeout_are_reported() {
+        let output = "cat > /dev/null; echo partial; exit 3".parse::<PipeCommand>().unwrap().start(8000, 1).unwrap().finish();
+        assert_eq!((output.exit_code, output.timed_out, output.output.as_str()), (Some(3), false, "partial\n"));
+        assert!(!output.succeeded());
+
+        // A command that stops reading: the audio after that is dropped, not queued forever
+        let mut pipe = "exec sleep 30".parse::<PipeCommand>().unwrap().start(8000, 1).unwrap();
+        let chunk = vec![0; 160 * 64];
+        for _ in 0..PIPE_QUEUE_CHUNKS * 4 {
+            pipe.push(&chunk);
+        }
+        let started = Instant::now();
+        let output = pipe.finish_within(Duration::from_millis(200));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(output.timed_out && output.exit_code.is_none());
+        assert!(output.samples_dropped > 0);
+        assert_eq!(output.samples_piped + output.samples_dropped, (PIPE_QUEUE_CHUNKS * 4 * 160 * 64) as u64);
+    }
+
+    #[test]
+    fn test_missing_command_leaves_an_exit_code() {
+        // The shell starts; the command it runs doesn't exist
+        let output = "no-such-command-mpu {rate}".parse::<PipeCommand>().unwrap().start(8000, 1).unwrap().finish();
+        assert_eq!(output.exit_code, Some(127));
+        assert_eq!(output.command, "no-such-command-mpu 8000");
+    }
+}
@@ -0,0 +1,736 @@
+//! Relaying pages from a standard RTP multicast group to Polycom phones.
+//!
+//! Some sites have a SIP paging gateway that only sends plain RTP, while
+//! their newer phones only listen for Polycom group paging. `bridge` joins
+//! the RTP group and, for each page it sees there, sends a live Polycom
+//! page: the Alert phase as soon as the first packet arrives, a Transmit
+//! packet for every 20ms of audio, re-encoded for the channel's codec, and
+//! the End phase once the source has been quiet for `--idle`.
+//!
+//! Packets go through the same decoding as `monitor`: lost audio is
+//! concealed where the codec can, and audio the sender never sent becomes
+//! silence. Playout waits for `--latency` of audio to be queued, so jitter
+//! in the source doesn't starve the phones. A queue that runs dry anyway
+//! and then gets more audio is an underrun; playout pauses until the
+//! buffer has filled again. The
+//! Alert phase also delays the audio by its own length (about a second
+//! with the usual 31 Alerts), which is included in the reported latency.
+
+use crate::cli::audio_input::StreamResampler;
+use crate::cli::logging::limited;
+use crate::cli::monitor::{output_json, skipped_audio, JsonEvent, PageStats};
+use crate::cli::polycom_transmit::{
+    check_channel_interlock, generate_host_serial, PolycomTransmitError, ALERT_TO_AUDIO_MS, AUDIO_TO_END_MS,
+};
+use crate::cli::timeout::Timeout;
+use crate::codec::{
+    create_decoder_for_payload_type, create_decoder_for_spec, create_encoder, AudioDecoder, AudioEncoder, CodecError,
+    CodecSpec, CodecType,
+};
+use crate::network::polycom::CONTROL_PACKET_INTERVAL_MS;
+use crate::network::{
+    create_transmit_socket, polycom_codec_type, CallerIdEncoding, Dscp, MulticastError, MulticastInterface,
+    MulticastSocket, PolycomCodec, PolycomError, PolycomHeader, PolycomPacketBuilder, RtpPacket,
+};
+use crate::utils::streaming::RunningStats;
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::{self, IsTerminal};
+use std::net::{SocketAddr, SocketAddrV4};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Longest wait for a packet between pages, so `--timeout` is still checked
+const IDLE_POLL: Duration = Duration::from_millis(100);
+
+/// Frames encoded at a time for a codec that goes through ffmpeg, which is
+/// started once per batch; the batch adds its length to the latency
+const FFMPEG_BATCH_FRAMES: usize = 10;
+
+#[derive(Error, Debug)]
+pub enum BridgeError {
+    #[error("Multicast error: {0}")]
+    Multicast(#[from] MulticastError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Codec error: {0}")]
+    Codec(#[from] CodecError),
+
+    #[error("Polycom protocol error: {0}")]
+    Protocol(#[from] PolycomError),
+
+    #[error(transparent)]
+    Transmit(#[from] PolycomTransmitError),
+}
+
+/// Options for the bridge command
+pub struct BridgeOptions {
+    /// RTP group and port pages arrive on
+    pub source: SocketAddrV4,
+    /// Interface the RTP group is joined on
+    pub interface: Option<MulticastInterface>,
+    /// Codec of the RTP stream, where its payload type doesn't say
+    pub source_codec: Option<CodecSpec>,
+    /// Polycom group and port pages are relayed to
+    pub destination: SocketAddrV4,
+    /// Channel number (1-50)
+    pub channel: u8,
+    pub codec: PolycomCodec,
+    pub caller_id: String,
+    pub caller_id_encoding: CallerIdEncoding,
+    pub caller_id_pad: usize,
+    pub ttl: u8,
+    pub dscp: Option<Dscp>,
+    pub alert_count: u32,
+    pub end_count: u32,
+    /// Audio queued before playout starts
+    pub latency: Duration,
+    /// Time without packets after which the source page has ended
+    pub idle: Duration,
+    /// Relay to priority/emergency channels without asking
+    pub allow_priority: bool,
+    pub timeout: Timeout,
+    pub json: bool,
+    pub quiet: bool,
+}
+
+impl BridgeOptions {
+    /// Frames of audio playout waits for, at least one
+    fn latency_frames(&self) -> usize {
+        let frame_ms = u128::from(self.codec.frame_duration_ms());
+        (self.latency.as_millis().div_ceil(frame_ms) as usize).max(1)
+    }
+}
+
+/// What a bridged page did
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct BridgeReport {
+    /// Sender of the RTP page
+    pub source: String,
+    pub ssrc: u32,
+    pub source_codec: String,
+    /// Codec of the Polycom page
+    pub codec: String,
+    pub channel: u8,
+    /// Audio frames sent as Transmit packets
+    pub frames_bridged: u64,
+    /// From the first RTP packet to the last End packet
+    pub duration_secs: f64,
+    /// Mean time from an RTP packet's arrival to the Transmit packet that
+    /// finished carrying its audio
+    pub latency_ms: f64,
+    pub max_latency_ms: f64,
+    /// Times the queue ran dry and the source then carried on
+    pub underruns: u64,
+    pub packets_lost: u64,
+    /// Payloads the decoder rejected, sent as silence
+    pub decode_errors: u64,
+    /// Packets from other streams while this page was being relayed
+    pub ignored_packets: u64,
+}
+
+/// Encoded audio waiting to be sent
+#[derive(Debug)]
+struct QueuedFrame {
+    payload: Vec<u8>,
+    /// Arrival of the RTP packet that completed it
+    arrived: Instant,
+}
+
+/// Where a bridged page is in its Polycom phases
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// `sent` Alerts so far; the next is due at `at`
+    Alert { sent: u32, at: Instant },
+    /// Waiting, from `from`, for the queue to fill before playing out
+    Priming { from: Instant },
+    /// Playing out; the next frame is due at `at`
+    Playing { at: Instant },
+    /// `sent` End packets so far; the next is due at `at`
+    Ending { sent: u32, at: Instant },
+    Done,
+}
+
+/// What the caller does next for a page
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    /// Send this packet, then ask again
+    Send(Vec<u8>),
+    /// Nothing is due before this, unless a packet arrives
+    Wait(Instant),
+    /// The End phase has been sent
+    Done,
+}
+
+/// One RTP page being relayed as a Polycom page
+struct BridgedPage {
+    ssrc: u32,
+    source: SocketAddr,
+    source_codec: CodecType,
+    decoder: Box<dyn AudioDecoder>,
+    stats: PageStats,
+    resampler: StreamResampler,
+    encoder: Box<dyn AudioEncoder>,
+    /// Decoded audio at the Polycom rate, not yet encoded
+    pcm: Vec<i16>,
+    /// Samples encoded at a time: one frame, or a batch for ffmpeg
+    encode_samples: usize,
+    frame_bytes: usize,
+    queue: VecDeque<QueuedFrame>,
+    builder: PolycomPacketBuilder,
+    phase: Phase,
+    alert_count: u32,
+    end_count: u32,
+    latency_frames: usize,
+    frame_duration: Duration,
+    idle: Duration,
+    started: Instant,
+    last_packet: Instant,
+    /// The source has been quiet for `idle`; what's queued is all there is
+    source_ended: bool,
+    /// The queue ran dry with the source still going; an underrun if more comes
+    starved: bool,
+    frames_bridged: u64,
+    underruns: u64,
+    decode_errors: u64,
+    ignored_packets: u64,
+    /// Milliseconds from arrival to playout
+    latency: RunningStats,
+}
+
+impl BridgedPage {
+    /// Start relaying the page `packet` opens
+    fn start(packet: &RtpPacket, options: &BridgeOptions, host_serial: [u8; 4]) -> Result<Self, CodecError> {
+        let payload_type = packet.header.payload_type;
+        let (spec, decoder) = if let Some(spec) = options.source_codec {
+            (spec, create_decoder_for_spec(spec)?)
+        } else {
+            let codec =
+                CodecType::from_payload_type(payload_type).ok_or(CodecError::UnsupportedPayloadType(payload_type))?;
+            (CodecSpec::new(codec), create_decoder_for_payload_type(payload_type)?)
+        };
+        let codec = polycom_codec_type(options.codec);
+        let encoder = create_encoder(codec)?;
+        let frame_samples = encoder.frame_size();
+        let batch = if codec == CodecType::G722 { FFMPEG_BATCH_FRAMES } else { 1 };
+
+        let mut builder =
+            PolycomPacketBuilder::new(options.channel, host_serial, options.caller_id.clone(), options.codec);
+        builder.set_caller_id_format(options.caller_id_encoding, options.caller_id_pad);
+
+        let now = packet.received_at;
+        let mut page = Self {
+            ssrc: packet.header.ssrc,
+            source: packet.source,
+            source_codec: spec.codec,
+            resampler: StreamResampler::new(decoder.sample_rate(), encoder.sample_rate()),
+            decoder,
+            stats: PageStats::for_codec(spec),
+            encoder,
+            pcm: Vec::new(),
+            encode_samples: frame_samples * batch,
+            frame_bytes: options.codec.frame_size(),
+            queue: VecDeque::new(),
+            builder,
+            phase: if options.alert_count == 0 {
+                Phase::Priming { from: now }
+            } else {
+                Phase::Alert { sent: 0, at: now }
+            },
+            alert_count: options.alert_count,
+            end_count: options.end_count,
+            latency_frames: options.latency_frames(),
+            frame_duration: Duration::from_millis(u64::from(options.codec.frame_duration_ms())),
+            idle: options.idle,
+            started: now,
+            last_packet: now,
+            source_ended: false,
+            starved: false,
+            frames_bridged: 0,
+            underruns: 0,
+            decode_errors: 0,
+            ignored_packets: 0,
+            latency: RunningStats::default(),
+        };
+        page.push(packet)?;
+        Ok(page)
+    }
+
+    /// Decode a packet of the page and queue its audio
+    fn push(&mut self, packet: &RtpPacket) -> Result<(), CodecError> {
+        self.last_packet = packet.received_at;
+        if self.starved {
+            self.underruns += 1;
+            self.starved = false;
+        }
+        if let Some(discontinuity) = self.stats.update(packet) {
+            debug!("Bridge source {}: {:?}", self.source, discontinuity);
+        }
+
+        // Keep the page on the RTP timeline across lost audio and audio nobody sent
+        let (concealed, silent_samples) = skipped_audio(self.decoder.as_mut(), &self.stats);
+        self.feed(&concealed);
+        self.feed(&vec![0; silent_samples as usize * usize::from(self.decoder.channels())]);
+
+        match self.decoder.decode(&packet.payload) {
+            Ok(samples) => self.feed(&samples),
+            Err(e) => {
+                debug!("Bridge source {}: undecodable payload: {}", self.source, e);
+                self.decode_errors += 1;
+                let rate = f64::from(self.decoder.sample_rate());
+                let missing = (self.stats.nominal_packet_secs() * rate).round() as usize;
+                self.feed(&vec![0; missing * usize::from(self.decoder.channels())]);
+            }
+        }
+        self.encode_ready(packet.received_at, false)
+    }
+
+    /// Mix decoded audio to mono and bring it to the Polycom rate
+    fn feed(&mut self, samples: &[i16]) {
+        if samples.is_empty() {
+            return;
+        }
+        let channels = usize::from(self.decoder.channels().max(1));
+        let mono: Vec<i16> = if channels == 1 {
+            samples.to_vec()
+        } else {
+            samples
+                .chunks(channels)
+                .map(|frame| (frame.iter().map(|&s| i32::from(s)).sum::<i32>() / frame.len() as i32) as i16)
+                .collect()
+        };
+        let resampled = self.resampler.push(&mono);
+        self.pcm.extend(resampled);
+    }
+
+    /// Encode whole batches of audio, or with `finish` everything left,
+    /// padded with silence to a whole frame
+    fn encode_ready(&mut self, arrived: Instant, finish: bool) -> Result<(), CodecError> {
+        if finish && !self.pcm.is_empty() {
+            let frame_samples = self.encoder.frame_size();
+            let padded = self.pcm.len().div_ceil(frame_samples) * frame_samples;
+            self.pcm.resize(padded, 0);
+        }
+        while self.pcm.len() >= self.encode_samples || (finish && !self.pcm.is_empty()) {
+            let take = self.encode_samples.min(self.pcm.len());
+            let block: Vec<i16> = self.pcm.drain(..take).collect();
+            let encoded = self.encoder.encode(&block)?;
+            for payload in encoded.chunks(self.frame_bytes) {
+                let mut payload = payload.to_vec();
+                payload.resize(self.frame_bytes, 0);
+                self.queue.push_back(QueuedFrame { payload, arrived });
+            }
+        }
+        Ok(())
+    }
+
+    /// When the source has been quiet for `idle`, queue what the decoder
+    /// and resampler still hold; the page ends once that has been played
+    fn check_idle(&mut self, now: Instant) -> Result<(), CodecError> {
+        if now.saturating_duration_since(self.last_packet) < self.idle {
+            return Ok(());
+        }
+        self.end_source()
+    }
+
+    /// Take the source page as ended and queue the audio still held back
+    fn end_source(&mut self) -> Result<(), CodecError> {
+        if self.source_ended {
+            return Ok(());
+        }
+        self.source_ended = true;
+        let flushed = self.decoder.flush()?;
+        self.feed(&flushed);
+        let tail = self.resampler.finish();
+        self.pcm.extend(tail);
+        self.encode_ready(self.last_packet, true)
+    }
+
+    /// The next thing to do for the page at `now`
+    fn poll(&mut self, now: Instant) -> Result<Step, PolycomError> {
+        let control_interval = Duration::from_millis(CONTROL_PACKET_INTERVAL_MS);
+        loop {
+            match self.phase {
+                Phase::Alert { sent, at } => {
+                    if now < at {
+                        return Ok(Step::Wait(at));
+                    }
+                    let sent = sent + 1;
+                    self.phase = if sent >= self.alert_count {
+                        Phase::Priming { from: at + Duration::from_millis(ALERT_TO_AUDIO_MS) }
+                    } else {
+                        Phase::Alert { sent, at: at + control_interval }
+                    };
+                    return Ok(Step::Send(self.builder.build_alert()?));
+                }
+                Phase::Priming { from } => {
+                    if now < from {
+                        return Ok(Step::Wait(from));
+                    }
+                    if self.queue.len() >= self.latency_frames || (self.source_ended && !self.queue.is_empty()) {
+                        self.phase = Phase::Playing { at: now };
+                    } else if self.source_ended {
+                        self.phase = Phase::Ending { sent: 0, at: now + Duration::from_millis(AUDIO_TO_END_MS) };
+                    } else {
+                        return Ok(Step::Wait(self.last_packet + self.idle));
+                    }
+                }
+                Phase::Playing { at } => {
+                    if now < at {
+                        return Ok(Step::Wait(at));
+                    }
+                    let Some(frame) = self.queue.pop_front() else {
+                        if self.source_ended {
+                            self.phase = Phase::Ending { sent: 0, at: at + Duration::from_millis(AUDIO_TO_END_MS) };
+                        } else {
+                            self.starved = true;
+                            self.phase = Phase::Priming { from: now };
+                        }
+                        continue;
+                    };
+                    let latency = now.saturating_duration_since(frame.arrived);
+                    self.latency.push(latency.as_secs_f64() * 1000.0);
+                    self.frames_bridged += 1;
+                    self.phase = Phase::Playing { at: at + self.frame_duration };
+                    return Ok(Step::Send(self.builder.build_transmit(&frame.payload)?));
+                }
+                Phase::Ending { sent, at } => {
+                    if sent >= self.end_count {
+                        self.phase = Phase::Done;
+                        continue;
+                    }
+                    if now < at {
+                        return Ok(Step::Wait(at));
+                    }
+                    self.phase = Phase::Ending { sent: sent + 1, at: at + control_interval };
+                    return Ok(Step::Send(self.builder.build_end()?));
+                }
+                Phase::Done => return Ok(Step::Done),
+            }
+        }
+    }
+
+    fn report(&self, options: &BridgeOptions, now: Instant) -> BridgeReport {
+        BridgeReport {
+            source: self.source.to_string(),
+            ssrc: self.ssrc,
+            source_codec: self.source_codec.name().to_string(),
+            codec: options.codec.to_string(),
+            channel: options.channel,
+            frames_bridged: self.frames_bridged,
+            duration_secs: now.saturating_duration_since(self.started).as_secs_f64(),
+            latency_ms: self.latency.mean,
+            max_latency_ms: self.latency.max,
+            underruns: self.underruns,
+            packets_lost: self.stats.packets_lost,
+            decode_errors: self.decode_errors,
+            ignored_packets: self.ignored_packets,
+        }
+    }
+}
+
+/// Run the bridge until `--timeout`, relaying one page at a time
+pub async fn run_bridge(options: BridgeOptions) -> Result<(), BridgeError> {
+    // Priority/emergency channels reach every phone at full volume - require intent
+    let interactive = io::stdin().is_terminal() && !options.quiet && !options.json;
+    check_channel_interlock(options.channel, options.allow_priority, interactive)?;
+    PolycomHeader::check_caller_id(&options.caller_id, options.caller_id_encoding, options.caller_id_pad)?;
+    // A missing encoder is found now rather than at the first page
+    create_encoder(polycom_codec_type(options.codec))?;
+
+    let source = MulticastSocket::bound_to_group(
+        *options.source.ip(),
+        options.source.port(),
+        options.interface.unwrap_or_default(),
+    )
+    .await?;
+    let sender = create_transmit_socket(options.ttl, options.dscp).await?;
+    let host_serial = generate_host_serial();
+
+    if !options.quiet && !options.json {
+        println!("Bridging RTP {} to Polycom {}", options.source, options.destination);
+        println!("  Channel: {}", options.channel);
+        println!("  Codec: {}", options.codec);
+        println!("  Caller ID: {}", options.caller_id);
+        println!("  Latency buffer: {}ms", options.latency.as_millis());
+        println!();
+    }
+
+    let start_time = Instant::now();
+    let mut page: Option<BridgedPage> = None;
+    // The last stream that couldn't be decoded, so it's only reported once
+    let mut refused: Option<u32> = None;
+    let mut buf = vec![0u8; 2048];
+
+    loop {
+        let timed_out = options.timeout.expired(start_time);
+
+        let mut wake = Instant::now() + IDLE_POLL;
+        if let Some(ref mut current) = page {
+            let now = Instant::now();
+            if timed_out {
+                // Finish the page in progress rather than leave phones waiting for its End
+                current.end_source()?;
+            } else {
+                current.check_idle(now)?;
+            }
+            loop {
+                match current.poll(now)? {
+                    Step::Send(packet) => {
+                        sender.send_to(&packet, options.destination).await?;
+                    }
+                    Step::Wait(at) => {
+                        wake = at;
+                        break;
+                    }
+                    Step::Done => {
+                        report_page(&current.report(&options, now), &options);
+                        page = None;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if timed_out && page.is_none() {
+            if options.json {
+                output_json(&JsonEvent::Timeout {
+                    timestamp: Utc::now(),
+                    mode: options.timeout.mode(),
+                    until: options.timeout.until(),
+                });
+            } else if !options.quiet {
+                println!("{}", options.timeout.reached());
+            }
+            return Ok(());
+        }
+
+        let received = tokio::time::timeout_at(wake.into(), source.recv_datagram(&mut buf)).await;
+        let datagram = match received {
+            Ok(Ok(datagram)) => datagram,
+            Ok(Err(e)) => {
+                limited!(warn!("Receive error on {}: {}", options.source, e));
+                continue;
+            }
+            Err(_) => continue,
+        };
+        let Ok(packet) = RtpPacket::parse_with_time(&buf[..datagram.len], datagram.source, datagram.received_at) else {
+            continue;
+        };
+
+        match page {
+            Some(ref mut current) if current.ssrc == packet.header.ssrc && !current.source_ended => {
+                current.push(&packet)?;
+            }
+            Some(ref mut current) => current.ignored_packets += 1,
+            None if timed_out => {}
+            None => match BridgedPage::start(&packet, &options, host_serial) {
+                Ok(started) => {
+                    refused = None;
+                    announce_page(&started, &options);
+                    page = Some(started);
+                }
+                Err(e) => {
+                    if refused != Some(packet.header.ssrc) {
+                        refused = Some(packet.header.ssrc);
+                        let message = format!("Not bridging page from {}: {}", packet.source, e);
+                        if options.json {
+                            output_json(&JsonEvent::Error { message });
+                        } else {
+                            warn!("{}", message);
+                        }
+                    }
+                }
+            },
+        }
+    }
+}
+
+fn announce_page(page: &BridgedPage, options: &BridgeOptions) {
+    if options.json {
+        output_json(&JsonEvent::BridgePageStarted {
+            timestamp: Utc::now(),
+            source: page.source.to_string(),
+            ssrc: page.ssrc,
+            source_codec: page.source_codec.name().to_string(),
+            channel: options.channel,
+        });
+    } else if !options.quiet {
+        println!(
+            "[{}] Page from {} (SSRC 0x{:08x}, {}): relaying on channel {}",
+            Utc::now().format("%H:%M:%S"),
+            page.source,
+            page.ssrc,
+            page.source_codec,
+            options.channel
+        );
+    }
+}
+
+fn report_page(report: &BridgeReport, options: &BridgeOptions) {
+    if options.json {
+        output_json(&JsonEvent::BridgePageEnded { timestamp: Utc::now(), report: report.clone() });
+    } else if !options.quiet {
+        println!(
+            "  Page ended: {} frames in {:.1}s, added latency {:.0}ms (max {:.0}ms), {} underruns, {} lost, {} undecodable",
+            report.frames_bridged,
+            report.duration_secs,
+            report.latency_ms,
+            report.max_latency_ms,
+            report.underruns,
+            report.packets_lost,
+            report.decode_errors
+        );
+        if report.ignored_packets > 0 {
+            println!("  Ignored {} packets from other streams meanwhile", report.ignored_packets);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::polycom::PolycomPacket;
+    use crate::network::PacketType;
+    use std::net::Ipv4Addr;
+
+    fn options() -> BridgeOptions {
+        BridgeOptions {
+            source: SocketAddrV4::new(Ipv4Addr::new(224, 0, 1, 1), 5004),
+            interface: None,
+            source_codec: None,
+            destination: SocketAddrV4::new(Ipv4Addr::new(224, 0, 1, 116), 5001),
+            channel: 26,
+            codec: PolycomCodec::G711U,
+            caller_id: "Bridge".to_string(),
+            caller_id_encoding: CallerIdEncoding::Utf8,
+            caller_id_pad: crate::network::polycom::MIN_CALLER_ID_LEN,
+            ttl: 1,
+            dscp: None,
+            alert_count: 3,
+            end_count: 2,
+            latency: Duration::from_millis(60),
+            idle: Duration::from_millis(200),
+            allow_priority: false,
+            timeout: Timeout::from_args(0, None),
+            json: false,
+            quiet: true,
+        }
+    }
+
+    /// A 20ms A-law packet of a 1 kHz tone, arriving at `at`
+    fn packet(sequence: u16, at: Instant) -> RtpPacket {
+        let mut encoder = create_encoder(CodecType::G711Alaw).unwrap();
+        let samples: Vec<i16> = (0..160)
+            .map(|i| {
+                let t = f64::from(u32::from(sequence) * 160 + i) / 8000.0;
+                (8000.0 * (2.0 * std::f64::consts::PI * 1000.0 * t).sin()) as i16
+            })
+            .collect();
+        let payload = encoder.encode(&samples).unwrap();
+        let data = RtpPacket::build(8, sequence, u32::from(sequence) * 160, 0x1234, &payload, false);
+        RtpPacket::parse_with_time(&data, "10.0.0.5:5004".parse().unwrap(), at).unwrap()
+    }
+
+    /// Run the page at `now` until it waits, returning the kinds of packet sent
+    fn run_until_wait(page: &mut BridgedPage, now: Instant) -> (Vec<PacketType>, Option<Instant>) {
+        let mut sent = Vec::new();
+        loop {
+            match page.poll(now).unwrap() {
+                Step::Send(data) => {
+                    let parsed = PolycomPacket::parse(&data, "10.0.0.9:5001".parse().unwrap()).unwrap();
+                    sent.push(parsed.header.packet_type);
+                }
+                Step::Wait(at) => return (sent, Some(at)),
+                Step::Done => return (sent, None),
+            }
+        }
+    }
+
+    #[test]
+    fn test_bridged_page_phases() {
+        let options = options();
+        let t0 = Instant::now();
+        let frame = Duration::from_millis(20);
+        let mut page = BridgedPage::start(&packet(0, t0), &options, [1, 2, 3, 4]).unwrap();
+        assert_eq!(page.source_codec, CodecType::G711Alaw);
+
+        // The Alerts go out on their own clock while the source keeps coming
+        let mut sent = Vec::new();
+        let mut now = t0;
+        for sequence in 1..50u16 {
+            now = t0 + frame * u32::from(sequence);
+            page.push(&packet(sequence, now)).unwrap();
+            sent.extend(run_until_wait(&mut page, now).0);
+        }
+        assert_eq!(&sent[..3], &[PacketType::Alert; 3]);
+        assert!(sent[3..].iter().all(|&kind| kind == PacketType::Transmit));
+
+        // The source goes quiet: what's queued plays out, then the End phase
+        let mut end = None;
+        while end.is_none() {
+            now += Duration::from_millis(5);
+            page.check_idle(now).unwrap();
+            let (more, wait) = run_until_wait(&mut page, now);
+            sent.extend(more);
+            if wait.is_none() {
+                end = Some(now);
+            }
+        }
+        let transmits = sent.iter().filter(|&&kind| kind == PacketType::Transmit).count();
+        assert_eq!(transmits, 50);
+        assert_eq!(&sent[sent.len() - 2..], &[PacketType::End; 2]);
+
+        let report = page.report(&options, end.unwrap());
+        assert_eq!(report.frames_bridged, 50);
+        assert_eq!(report.underruns, 0);
+        // The Alert phase (2 x 30ms + 64ms) and the 60ms buffer hold the audio back
+        assert!((100.0..300.0).contains(&report.latency_ms), "{}", report.latency_ms);
+    }
+
+    #[test]
+    fn test_stalled_source_underruns() {
+        let options = BridgeOptions { alert_count: 0, ..options() };
+        let t0 = Instant::now();
+        let frame = Duration::from_millis(20);
+        let mut page = BridgedPage::start(&packet(0, t0), &options, [1, 2, 3, 4]).unwrap();
+        let mut now = t0;
+        for sequence in 1..10u16 {
+            now = t0 + frame * u32::from(sequence);
+            page.push(&packet(sequence, now)).unwrap();
+            run_until_wait(&mut page, now);
+        }
+
+        // 100ms without packets, less than --idle: the queue runs dry
+        now += Duration::from_millis(100);
+        page.check_idle(now).unwrap();
+        let (_, wait) = run_until_wait(&mut page, now);
+        assert!(page.queue.is_empty());
+        assert!(!page.source_ended);
+        // Playout waits for the buffer to fill again, or the page to end
+        assert_eq!(wait, Some(page.last_packet + options.idle));
+
+        // The source carries on: that was an underrun, and playout resumes once primed
+        let mut sent = Vec::new();
+        for sequence in 10..13u16 {
+            now += frame;
+            page.push(&packet(sequence, now)).unwrap();
+            sent.extend(run_until_wait(&mut page, now).0);
+        }
+        assert_eq!(page.underruns, 1);
+        assert_eq!(sent, [PacketType::Transmit]);
+
+        // Running dry at the end of the page is not an underrun
+        now += Duration::from_millis(300);
+        page.check_idle(now).unwrap();
+        let (sent, _) = run_until_wait(&mut page, now);
+        assert_eq!(sent.iter().filter(|&&kind| kind == PacketType::Transmit).count(), 2);
+        assert_eq!(sent.last(), Some(&PacketType::End));
+        assert_eq!(page.report(&options, now).underruns, 1);
+    }
+}
@@ -0,0 +1,209 @@
+//! Names for Polycom channels.
+//!
+//! Nobody remembers that channel 26 is the all-call, so `polycom-monitor`
+//! and `polycom-transmit` show a channel as `26 (All Call)` wherever they
+//! print its number, put its name in `channel_name` beside `channel` in
+//! their JSON, and offer `{channel_name}` in recording names. Names come
+//! from `name` in the config file's `[[polycom.channels]]` entries, and
+//! from a `--channel-names` file of `26 = "All Call"` lines, which wins
+//! where both name a channel. Without a name of their own, the priority and
+//! emergency channels are named for what they are; any other channel is
+//! shown by its number alone.
+
+use crate::config::PolycomConfig;
+use crate::network::{is_emergency_channel, is_priority_channel};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ChannelNamesError {
+    #[error("Unable to read channel names from {}: {error}", .path.display())]
+    Read { path: PathBuf, error: std::io::Error },
+
+    #[error("Invalid channel names file {}: {error}", .path.display())]
+    Parse { path: PathBuf, error: toml::de::Error },
+
+    #[error("Channel {channel} named in {origin} is out of range (1 to 50)")]
+    OutOfRange { channel: String, origin: String },
+
+    #[error("Channel {channel} in {origin} has an empty name")]
+    EmptyName { channel: u8, origin: String },
+}
+
+/// The built-in name of a priority or emergency channel
+fn standard_name(channel: u8) -> Option<&'static str> {
+    let ptt = channel <= 25;
+    if is_emergency_channel(channel) {
+        Some(if ptt { "PTT Emergency" } else { "Emergency" })
+    } else if is_priority_channel(channel) {
+        Some(if ptt { "PTT Priority" } else { "Priority" })
+    } else {
+        None
+    }
+}
+
+/// Names given to channels, by number
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelNames {
+    names: BTreeMap<u8, String>,
+}
+
+impl ChannelNames {
+    /// Names from the config file, then from a `--channel-names` file
+    pub fn load(config: &PolycomConfig, file: Option<&Path>) -> Result<Self, ChannelNamesError> {
+        let mut names = Self::default();
+        for defaults in &config.channels {
+            if let Some(ref name) = defaults.name {
+                names.insert(&defaults.channel.to_string(), name, "the config file")?;
+            }
+        }
+        if let Some(path) = file {
+            names.read(path)?;
+        }
+        Ok(names)
+    }
+
+    /// Add the names in a file of `26 = "All Call"` lines
+    fn read(&mut self, path: &Path) -> Result<(), ChannelNamesError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|error| ChannelNamesError::Read { path: path.to_path_buf(), error })?;
+        let entries: BTreeMap<String, String> = toml::from_str(&content)
+            .map_err(|error| ChannelNamesError::Parse { path: path.to_path_buf(), error })?;
+        let origin = path.display().to_string();
+        for (channel, name) in &entries {
+            self.insert(channel, name, &origin)?;
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, channel: &str, name: &str, origin: &str) -> Result<(), ChannelNamesError> {
+        let number = channel
+            .trim()
+            .parse::<u8>()
+            .ok()
+            .filter(|number| (1..=50).contains(number))
+            .ok_or_else(|| ChannelNamesError::OutOfRange { channel: channel.to_string(), origin: origin.to_string() })?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ChannelNamesError::EmptyName { channel: number, origin: origin.to_string() });
+        }
+        self.names.insert(number, name.to_string());
+        Ok(())
+    }
+
+    /// The channel's name: its own, or for a priority or emergency channel
+    /// the standard one
+    pub fn name(&self, channel: u8) -> Option<&str> {
+        self.names.get(&channel).map(String::as_str).or_else(|| standard_name(channel))
+    }
+
+    /// The channel's name, or its number if it has none, for `{channel_name}`
+    pub fn name_or_number(&self, channel: u8) -> String {
+        self.name(channel).map_or_else(|| channel.to_string(), str::to_string)
+    }
+
+    /// The channel as printed: `26 (All Call)`, or `27` without a name
+    pub fn label(&self, channel: u8) -> ChannelLabel<'_> {
+        ChannelLabel { channel, name: self.name(channel) }
+    }
+}
+
+/// A channel number with its name, if it has one
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelLabel<'a> {
+    channel: u8,
+    name: Option<&'a str>,
+}
+
+impl fmt::Display for ChannelLabel<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "{} ({})", self.channel, name),
+            None => write!(f, "{}", self.channel),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    /// A `--channel-names` file holding `content`, in a directory kept
+    /// as long as the file is needed
+    fn names_file(content: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("channels.toml");
+        std::fs::write(&path, content).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn test_config_and_file_names() {
+        let config = Config::parse(
+            r#"
+            [[polycom.channels]]
+            channel = 26
+            name = "All Call"
+            caller_id = "Reception"
+
+            [[polycom.channels]]
+            channel = 31
+            name = "Maintenance"
+            "#,
+        )
+        .unwrap();
+        let (_dir, file) = names_file("# Site overrides\n31 = \"Facilities\"\n\"40\" = \"Warehouse\"\n");
+        let names = ChannelNames::load(&config.polycom, Some(&file)).unwrap();
+
+        assert_eq!(names.name(26), Some("All Call"));
+        // The file wins over the config file
+        assert_eq!(names.name(31), Some("Facilities"));
+        assert_eq!(names.name(40), Some("Warehouse"));
+        assert_eq!(names.label(26).to_string(), "26 (All Call)");
+        assert_eq!(names.name_or_number(40), "Warehouse");
+    }
+
+    #[test]
+    fn test_fallback_to_number_and_standard_names() {
+        let names = ChannelNames::default();
+        assert_eq!(names.name(27), None);
+        assert_eq!(names.label(27).to_string(), "27");
+        assert_eq!(names.name_or_number(27), "27");
+        assert_eq!(names.label(24).to_string(), "24 (PTT Priority)");
+        assert_eq!(names.label(25).to_string(), "25 (PTT Emergency)");
+        assert_eq!(names.label(49).to_string(), "49 (Priority)");
+        assert_eq!(names.label(50).to_string(), "50 (Emergency)");
+
+        // A name of its own replaces the standard one
+        let (_dir, file) = names_file("50 = \"Fire Alarm\"\n");
+        let names = ChannelNames::load(&PolycomConfig::default(), Some(&file)).unwrap();
+        assert_eq!(names.label(50).to_string(), "50 (Fire Alarm)");
+    }
+
+    #[test]
+    fn test_rejects_channels_out_of_range() {
+        let config = PolycomConfig::default();
+        for content in ["51 = \"Nowhere\"", "0 = \"Nowhere\"", "lobby = \"Lobby\""] {
+            let (_dir, file) = names_file(content);
+            let result = ChannelNames::load(&config, Some(&file));
+            assert!(matches!(result, Err(ChannelNamesError::OutOfRange { .. })), "{}: {:?}", content, result);
+        }
+        let (_dir, file) = names_file("26 = \" \"");
+        assert!(matches!(
+            ChannelNames::load(&config, Some(&file)),
+            Err(ChannelNamesError::EmptyName { channel: 26, .. })
+        ));
+        let (_dir, file) = names_file("26 = 5");
+        assert!(matches!(ChannelNames::load(&config, Some(&file)), Err(ChannelNamesError::Parse { .. })));
+
+        // A config entry naming no channel is channel 0
+        let config = Config::parse("[[polycom.channels]]\nname = \"Lobby\"\n").unwrap();
+        assert!(matches!(
+            ChannelNames::load(&config.polycom, None),
+            Err(ChannelNamesError::OutOfRange { ref channel, .. }) if channel == "0"
+        ));
+    }
+}
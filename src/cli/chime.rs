@@ -0,0 +1,322 @@
+//! Pre-announce chimes ahead of a transmitted page.
+//!
+//! `transmit --chime` and `polycom-transmit --chime` send a chime, then
+//! `--chime-gap-ms` of silence, ahead of the page's audio, as real paging
+//! servers do; some speakers only open on the chime's burst of level. The
+//! chime is made at the codec's sample rate and encoded in the same stream
+//! as the page, so it goes out at the level it was written at and a
+//! receiver hears one page. `builtin:ding` and `builtin:westminster` are
+//! synthesized here, each a few decaying sine notes; anything else names an
+//! audio file, read the way a page's file is.
+//!
+//! The built-in notes are long enough, and far enough apart, for the
+//! analyzer's FFT to follow at 8kHz. [`ChimeStats`] keeps the runs of the
+//! dominant frequency at the start of a page, and `monitor` and `test`
+//! report the built-in chime a page opened with.
+
+use crate::cli::audio_input::{read_audio_file, AudioInput, AudioInputError};
+use crate::cli::plan::ChimePlan;
+use crate::utils::sequence::RAMP_MS;
+use std::f64::consts::PI;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Silence between the chime and the page unless `--chime-gap-ms` says
+/// otherwise
+pub const DEFAULT_GAP_MS: u64 = 250;
+
+/// Peak level of a built-in chime's notes, in dBFS
+const LEVEL_DBFS: f64 = -12.0;
+
+/// How far each note decays by its end, in nepers (about 17 dB), for a
+/// struck-bell sound
+const DECAY: f64 = 2.0;
+
+/// How far a dominant frequency may be from a note and still be it: half
+/// an FFT bin at 16kHz, and a little more
+const NOTE_TOLERANCE_HZ: f64 = 20.0;
+
+/// FFTs a run of one frequency needs to count as a note; shorter runs are
+/// where one note gives way to the next
+const MIN_NOTE_FFTS: u32 = 3;
+
+/// Runs of the dominant frequency kept per page. A chime is over within
+/// the first few, and speech soon fills the rest
+const MAX_RUNS: usize = 64;
+
+#[derive(Error, Debug)]
+pub enum ChimeError {
+    #[error("Unknown chime builtin:{0}; the built-in chimes are builtin:ding and builtin:westminster")]
+    UnknownBuiltin(String),
+
+    #[error("Unable to read chime {path}: {error}")]
+    Read { path: String, error: AudioInputError },
+
+    #[error("Chime {0} has no audio")]
+    Empty(String),
+}
+
+/// A note of a built-in chime
+struct Note {
+    freq_hz: f64,
+    ms: u32,
+}
+
+/// E5 then C5
+const DING: &[Note] = &[Note { freq_hz: 659.26, ms: 300 }, Note { freq_hz: 523.25, ms: 450 }];
+
+/// The first phrase of the Westminster quarters: G#4, F#4, E4, B3
+const WESTMINSTER: &[Note] = &[
+    Note { freq_hz: 415.30, ms: 400 },
+    Note { freq_hz: 369.99, ms: 400 },
+    Note { freq_hz: 329.63, ms: 400 },
+    Note { freq_hz: 246.94, ms: 700 },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinChime {
+    Ding,
+    Westminster,
+}
+
+impl BuiltinChime {
+    /// Every built-in, the most notes first, so a chime that opens the
+    /// same way as a shorter one is still told apart
+    pub const ALL: [BuiltinChime; 2] = [BuiltinChime::Westminster, BuiltinChime::Ding];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ding => "ding",
+            Self::Westminster => "westminster",
+        }
+    }
+
+    fn notes(self) -> &'static [Note] {
+        match self {
+            Self::Ding => DING,
+            Self::Westminster => WESTMINSTER,
+        }
+    }
+
+    /// The notes as mono PCM. Each rises and falls over `RAMP_MS` so the
+    /// joins don't click, and decays in between like a struck bell
+    pub fn synthesize(self, sample_rate: u32) -> Vec<i16> {
+        let rate = f64::from(sample_rate);
+        let amplitude = f64::from(i16::MAX) * 10f64.powf(LEVEL_DBFS / 20.0);
+        let ramp = (RAMP_MS / 1000.0 * rate).round() as usize;
+        self.notes()
+            .iter()
+            .flat_map(|note| {
+                let len = (f64::from(note.ms) / 1000.0 * rate).round() as usize;
+                (0..len).map(move |n| {
+                    let from_edge = n.min(len - 1 - n);
+                    let edge = if from_edge < ramp {
+                        0.5 - 0.5 * (PI * from_edge as f64 / ramp as f64).cos()
+                    } else {
+                        1.0
+                    };
+                    let decay = (-DECAY * n as f64 / len as f64).exp();
+                    let t = n as f64 / rate;
+                    (amplitude * edge * decay * (2.0 * PI * note.freq_hz * t).sin()).round() as i16
+                })
+            })
+            .collect()
+    }
+}
+
+/// A `--chime`: a built-in, or an audio file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Chime {
+    Builtin(BuiltinChime),
+    File(PathBuf),
+}
+
+impl Chime {
+    /// The chime as mono PCM at `sample_rate`
+    pub fn samples(&self, sample_rate: u32) -> Result<Vec<i16>, ChimeError> {
+        let samples = match self {
+            Chime::Builtin(chime) => chime.synthesize(sample_rate),
+            Chime::File(path) => read_audio_file(path, sample_rate, &AudioInput::default()).map_err(|error| {
+                ChimeError::Read {
+                    path: path.display().to_string(),
+                    error,
+                }
+            })?,
+        };
+        if samples.is_empty() {
+            return Err(ChimeError::Empty(self.to_string()));
+        }
+        Ok(samples)
+    }
+}
+
+impl fmt::Display for Chime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chime::Builtin(chime) => write!(f, "builtin:{}", chime.name()),
+            Chime::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl FromStr for Chime {
+    type Err = ChimeError;
+
+    /// `builtin:NAME`, or the path of an audio file
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(name) = s.strip_prefix("builtin:") else {
+            return Ok(Chime::File(PathBuf::from(s)));
+        };
+        BuiltinChime::ALL
+            .into_iter()
+            .find(|chime| chime.name() == name)
+            .map(Chime::Builtin)
+            .ok_or_else(|| ChimeError::UnknownBuiltin(name.to_string()))
+    }
+}
+
+/// The chime a page starts with, and the silence before the page's audio
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageChime {
+    pub chime: Chime,
+    pub gap_ms: u64,
+}
+
+impl PageChime {
+    /// The samples to send ahead of the page at `sample_rate`, and how the
+    /// plan shows them
+    pub fn lead_in(&self, sample_rate: u32) -> Result<(Vec<i16>, ChimePlan), ChimeError> {
+        let mut samples = self.chime.samples(sample_rate)?;
+        let plan = ChimePlan {
+            chime: self.chime.to_string(),
+            chime_secs: samples.len() as f64 / f64::from(sample_rate),
+            gap_secs: self.gap_ms as f64 / 1000.0,
+        };
+        let gap = (self.gap_ms * u64::from(sample_rate) / 1000) as usize;
+        samples.resize(samples.len() + gap, 0);
+        Ok((samples, plan))
+    }
+}
+
+/// Runs of the dominant frequency at the start of a page, to tell which
+/// built-in chime, if any, it opened with
+#[derive(Debug, Clone, Default)]
+pub struct ChimeStats {
+    /// Frequency each run started at, and the FFTs in it
+    runs: Vec<(f64, u32)>,
+}
+
+impl ChimeStats {
+    /// Count an FFT's dominant frequency. Silent windows are passed over,
+    /// so the gap after the chime doesn't break the run it follows
+    pub fn add(&mut self, dominant_freq_hz: f64, voiced: bool) {
+        if !voiced || dominant_freq_hz <= 0.0 {
+            return;
+        }
+        if let Some((freq_hz, count)) = self.runs.last_mut() {
+            if (dominant_freq_hz - *freq_hz).abs() <= NOTE_TOLERANCE_HZ {
+                *count += 1;
+                return;
+            }
+        }
+        if self.runs.len() < MAX_RUNS {
+            self.runs.push((dominant_freq_hz, 1));
+        }
+    }
+
+    /// The built-in chime whose notes open the page, in order
+    pub fn detect(&self) -> Option<BuiltinChime> {
+        let mut notes: Vec<f64> =
+            self.runs.iter().filter(|&&(_, count)| count >= MIN_NOTE_FFTS).map(|&(freq_hz, _)| freq_hz).collect();
+        // A note a stray window split in two is still one note
+        notes.dedup_by(|next, previous| (*next - *previous).abs() <= NOTE_TOLERANCE_HZ);
+        BuiltinChime::ALL.into_iter().find(|chime| {
+            let expected = chime.notes();
+            notes.len() >= expected.len()
+                && notes.iter().zip(expected).all(|(heard, note)| (heard - note.freq_hz).abs() <= NOTE_TOLERANCE_HZ)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::audio_analyzer::{AudioAnalyzer, AudioStats};
+
+    fn tone(freq_hz: f64, secs: f64, sample_rate: u32) -> Vec<i16> {
+        let len = (secs * f64::from(sample_rate)) as usize;
+        (0..len)
+            .map(|n| ((2.0 * PI * freq_hz * n as f64 / f64::from(sample_rate)).sin() * 10000.0) as i16)
+            .collect()
+    }
+
+    /// What the analyzer makes of a page, fed a 20ms packet at a time
+    fn heard(samples: &[i16], sample_rate: u32) -> Option<BuiltinChime> {
+        let mut analyzer = AudioAnalyzer::new(sample_rate);
+        let mut stats = AudioStats::new();
+        for packet in samples.chunks(sample_rate as usize / 50) {
+            stats.update(&analyzer.analyze(packet), packet.len() as u64);
+        }
+        analyzer.finish(&mut stats);
+        stats.chime()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("builtin:ding".parse::<Chime>().unwrap(), Chime::Builtin(BuiltinChime::Ding));
+        assert_eq!("builtin:westminster".parse::<Chime>().unwrap(), Chime::Builtin(BuiltinChime::Westminster));
+        assert_eq!("chimes/bell.wav".parse::<Chime>().unwrap(), Chime::File(PathBuf::from("chimes/bell.wav")));
+        assert!(matches!("builtin:gong".parse::<Chime>(), Err(ChimeError::UnknownBuiltin(name)) if name == "gong"));
+        assert_eq!(Chime::Builtin(BuiltinChime::Ding).to_string(), "builtin:ding");
+    }
+
+    #[test]
+    fn test_lead_in() {
+        let chime = PageChime {
+            chime: Chime::Builtin(BuiltinChime::Ding),
+            gap_ms: 250,
+        };
+        let (samples, plan) = chime.lead_in(8000).unwrap();
+        // 750ms of notes, then the gap
+        assert_eq!(samples.len(), 6000 + 2000);
+        assert!(samples[6000..].iter().all(|&s| s == 0));
+        assert!((plan.chime_secs - 0.75).abs() < 1e-9);
+        assert!((plan.gap_secs - 0.25).abs() < 1e-9);
+        assert_eq!(plan.chime, "builtin:ding");
+
+        // Peaks at the chime's level, with no click at the note boundary
+        let peak = samples.iter().map(|s| s.unsigned_abs()).max().unwrap();
+        assert!((f64::from(peak) / f64::from(i16::MAX) - 10f64.powf(LEVEL_DBFS / 20.0)).abs() < 0.01);
+        assert!(samples[2395..2405].iter().all(|s| s.unsigned_abs() < 2000));
+
+        // Made at the codec's rate
+        assert_eq!(chime.lead_in(16000).unwrap().0.len(), 16000);
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let chime = Chime::File(PathBuf::from("/nonexistent/chime.wav"));
+        assert!(matches!(chime.samples(8000), Err(ChimeError::Read { .. })));
+    }
+
+    #[test]
+    fn test_builtin_chimes_detected() {
+        for sample_rate in [8000, 16000] {
+            for chime in BuiltinChime::ALL {
+                let mut page = chime.synthesize(sample_rate);
+                page.resize(page.len() + sample_rate as usize / 4, 0);
+                page.extend(tone(1000.0, 2.0, sample_rate));
+                assert_eq!(heard(&page, sample_rate), Some(chime), "{} at {} Hz", chime.name(), sample_rate);
+            }
+        }
+
+        // A page that opens with anything else
+        assert_eq!(heard(&tone(1000.0, 2.0, 8000), 8000), None);
+        let mut half = BuiltinChime::Westminster.synthesize(8000);
+        half.truncate(8000 * 8 / 10);
+        half.extend(tone(1000.0, 1.0, 8000));
+        assert_eq!(heard(&half, 8000), None);
+    }
+}
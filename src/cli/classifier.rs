@@ -0,0 +1,300 @@
+//! Page content classification: speech, tone, music, noise or silence.
+//!
+//! Nightly reports list hundreds of pages, and the level and frequency
+//! columns don't say which were announcements. This sorts each page by
+//! rules over figures the analyzer already keeps: how much of the page was
+//! silent, how much the level moved, how flat the spectrum was and how
+//! steady the dominant frequency stayed. The rules are checked in order and
+//! the first to match wins, so a result is always reproducible. Their
+//! thresholds come from the `[classifier]` section of the config file, so a
+//! site can adjust them without a rebuild.
+
+use crate::cli::audio_analyzer::AudioStats;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// What a page sounded like
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentClass {
+    Speech,
+    Tone,
+    Music,
+    Noise,
+    Silence,
+}
+
+impl ContentClass {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Speech => "speech",
+            Self::Tone => "tone",
+            Self::Music => "music",
+            Self::Noise => "noise",
+            Self::Silence => "silence",
+        }
+    }
+}
+
+impl fmt::Display for ContentClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ContentClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "speech" => Ok(Self::Speech),
+            "tone" => Ok(Self::Tone),
+            "music" => Ok(Self::Music),
+            "noise" => Ok(Self::Noise),
+            "silence" => Ok(Self::Silence),
+            _ => Err(format!(
+                "unknown content class {:?}: expected speech, tone, music, noise or silence",
+                s
+            )),
+        }
+    }
+}
+
+/// Where the rules draw their lines. Read from `[classifier]` in the config
+/// file; anything left out keeps the value below
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClassifierThresholds {
+    /// Pages at least this silent (percent of frames) are silence
+    pub silence_percent: f64,
+    /// A tone keeps its dominant frequency in at least this share of frames
+    pub tone_min_pitch_stability: f64,
+    /// ... with a spectrum no flatter than this
+    pub tone_max_flatness: f64,
+    /// Noise has a spectrum at least this flat
+    pub noise_min_flatness: f64,
+    /// ... and a level that moves less than this (dB standard deviation)
+    pub noise_max_level_spread_db: f64,
+    /// Speech pauses between words for at least this share of the page (percent)
+    pub speech_min_silence_percent: f64,
+    /// ... and rises and falls with each syllable by at least this much (dB)
+    pub speech_min_level_spread_db: f64,
+}
+
+impl Default for ClassifierThresholds {
+    fn default() -> Self {
+        Self {
+            silence_percent: 90.0,
+            tone_min_pitch_stability: 0.8,
+            tone_max_flatness: 0.05,
+            noise_min_flatness: 0.2,
+            noise_max_level_spread_db: 3.0,
+            speech_min_silence_percent: 10.0,
+            speech_min_level_spread_db: 4.0,
+        }
+    }
+}
+
+/// The figures a page is classified by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContentFeatures {
+    pub silence_percent: f64,
+    /// Standard deviation of the level of non-silent frames, in dB
+    pub level_spread_db: f64,
+    /// Mean spectral flatness of non-silent frames, 0-1
+    pub flatness: f64,
+    /// Share of frames at the most common dominant frequency, 0-1
+    pub pitch_stability: f64,
+}
+
+impl ContentFeatures {
+    /// The features of an analyzed page; `None` if nothing was analyzed
+    pub fn of(stats: &AudioStats) -> Option<Self> {
+        if stats.frame_count == 0 {
+            return None;
+        }
+        Some(Self {
+            silence_percent: stats.silence_percent(),
+            level_spread_db: stats.level_spread_db().unwrap_or(0.0),
+            flatness: stats.spectral_shape().map_or(0.0, |shape| shape.flatness),
+            pitch_stability: stats.pitch_stability().unwrap_or(0.0),
+        })
+    }
+}
+
+/// A page's class and how sure the rules were of it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Classification {
+    pub class: ContentClass,
+    /// 0-1: how far past its thresholds the deciding figure was
+    pub confidence: f64,
+}
+
+/// How far `value` is past `threshold` towards `limit`, as 0.5-1. A value
+/// right on the threshold is a coin toss; one at the limit is certain
+fn margin(value: f64, threshold: f64, limit: f64) -> f64 {
+    let span = limit - threshold;
+    if span.abs() < f64::EPSILON {
+        return 1.0;
+    }
+    0.5 + 0.5 * ((value - threshold) / span).clamp(0.0, 1.0)
+}
+
+/// Classify a page from its features
+#[must_use]
+pub fn classify(features: &ContentFeatures, thresholds: &ClassifierThresholds) -> Classification {
+    let t = thresholds;
+    let f = features;
+    let decided = |class, confidence: f64| Classification { class, confidence: (confidence * 100.0).round() / 100.0 };
+
+    if f.silence_percent >= t.silence_percent {
+        return decided(ContentClass::Silence, margin(f.silence_percent, t.silence_percent, 100.0));
+    }
+    if f.pitch_stability >= t.tone_min_pitch_stability && f.flatness <= t.tone_max_flatness {
+        let pitch = margin(f.pitch_stability, t.tone_min_pitch_stability, 1.0);
+        let purity = margin(t.tone_max_flatness - f.flatness, 0.0, t.tone_max_flatness);
+        return decided(ContentClass::Tone, pitch.min(purity));
+    }
+    if f.flatness >= t.noise_min_flatness && f.level_spread_db <= t.noise_max_level_spread_db {
+        let flat = margin(f.flatness, t.noise_min_flatness, 1.0);
+        let steady = margin(t.noise_max_level_spread_db - f.level_spread_db, 0.0, t.noise_max_level_spread_db);
+        return decided(ContentClass::Noise, flat.min(steady));
+    }
+    if f.silence_percent >= t.speech_min_silence_percent && f.level_spread_db >= t.speech_min_level_spread_db {
+        let pauses = margin(f.silence_percent, t.speech_min_silence_percent, 2.0 * t.speech_min_silence_percent);
+        let syllables = margin(f.level_spread_db, t.speech_min_level_spread_db, 2.0 * t.speech_min_level_spread_db);
+        return decided(ContentClass::Speech, pauses.min(syllables));
+    }
+    if f.flatness >= t.noise_min_flatness {
+        // Flat but uneven: noise that comes and goes, like a keyed radio
+        return decided(ContentClass::Noise, margin(f.flatness, t.noise_min_flatness, 1.0));
+    }
+    // Sustained, tonal but moving: the remaining class, held with less certainty
+    let sustained = margin(t.speech_min_silence_percent - f.silence_percent, 0.0, t.speech_min_silence_percent);
+    decided(ContentClass::Music, 0.5 * sustained + 0.25)
+}
+
+/// Classify an analyzed page; `None` if no audio was analyzed
+#[must_use]
+pub fn classify_stats(stats: &AudioStats, thresholds: &ClassifierThresholds) -> Option<Classification> {
+    ContentFeatures::of(stats).map(|features| classify(&features, thresholds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::audio_analyzer::{analyze_samples, analyze_wav, AnalysisProfile};
+    use std::f64::consts::PI;
+    use std::path::Path;
+
+    /// Deterministic white noise in -1..1
+    fn noise(len: usize, seed: u64) -> Vec<f64> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+                (state >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+            })
+            .collect()
+    }
+
+    fn to_pcm(signal: &[f64], peak: f64) -> Vec<i16> {
+        let max = signal.iter().fold(0.0f64, |m, v| m.max(v.abs())).max(1e-9);
+        signal.iter().map(|v| (v / max * peak) as i16).collect()
+    }
+
+    fn features(samples: &[i16]) -> ContentFeatures {
+        ContentFeatures::of(&analyze_samples(samples, 8000, 1, AnalysisProfile::Speech)).unwrap()
+    }
+
+    fn tone() -> Vec<i16> {
+        let tone: Vec<f64> = (0..24000).map(|i| (2.0 * PI * 1000.0 * f64::from(i) / 8000.0).sin()).collect();
+        to_pcm(&tone, 12000.0)
+    }
+
+    /// White noise through a broad band-pass around 1.8 kHz, like hiss on an open channel
+    fn band_noise() -> Vec<i16> {
+        let (mut x1, mut y1, mut y2) = (0.0, 0.0, 0.0);
+        let r: f64 = 0.55;
+        let c = 2.0 * r * (2.0 * PI * 1800.0 / 8000.0).cos();
+        let band: Vec<f64> = noise(24000, 7)
+            .into_iter()
+            .map(|x| {
+                let y = x - x1 + c * y1 - r * r * y2;
+                x1 = x;
+                y2 = y1;
+                y1 = y;
+                y
+            })
+            .collect();
+        to_pcm(&band, 12000.0)
+    }
+
+    /// A melody of harmonic notes over a bass line, half a second a note
+    fn music() -> Vec<i16> {
+        let notes = [262.0, 330.0, 392.0, 523.0, 440.0, 349.0, 294.0, 392.0];
+        let music: Vec<f64> = (0..32000)
+            .map(|i| {
+                let t = f64::from(i) / 8000.0;
+                let note = notes[(i / 4000) as usize];
+                let melody: f64 = (1..=4).map(|h| (2.0 * PI * note * f64::from(h) * t).sin() / f64::from(h)).sum();
+                let bass = 0.6 * (2.0 * PI * 131.0 * t).sin() + 0.4 * (2.0 * PI * 196.0 * t).sin();
+                melody * (0.7 + 0.3 * (2.0 * PI * 2.0 * t).sin().abs()) + bass
+            })
+            .collect();
+        to_pcm(&music, 12000.0)
+    }
+
+    /// A short spoken phrase, checked in
+    fn speech() -> AudioStats {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/speech_8k.wav");
+        analyze_wav(&path, AnalysisProfile::Speech).unwrap()
+    }
+
+    #[test]
+    fn test_classify_fixtures() {
+        let thresholds = ClassifierThresholds::default();
+        let class = |features: ContentFeatures| classify(&features, &thresholds).class;
+
+        assert_eq!(class(features(&tone())), ContentClass::Tone);
+        assert_eq!(class(features(&band_noise())), ContentClass::Noise);
+        assert_eq!(class(features(&music())), ContentClass::Music);
+        assert_eq!(class(features(&[0i16; 8000])), ContentClass::Silence);
+
+        let spoken = classify_stats(&speech(), &thresholds).unwrap();
+        assert_eq!(spoken.class, ContentClass::Speech);
+        assert!(spoken.confidence > 0.7, "speech at {}", spoken.confidence);
+
+        // The same page always gets the same answer
+        assert_eq!(classify_stats(&speech(), &thresholds), Some(spoken));
+        assert_eq!(classify_stats(&AudioStats::new(), &thresholds), None);
+    }
+
+    #[test]
+    fn test_confidence_follows_margin() {
+        let thresholds = ClassifierThresholds::default();
+        let steady = ContentFeatures { silence_percent: 0.0, level_spread_db: 0.0, flatness: 0.0, pitch_stability: 1.0 };
+        assert_eq!(classify(&steady, &thresholds), Classification { class: ContentClass::Tone, confidence: 1.0 });
+
+        // Barely steady enough to count as a tone
+        let wavering = ContentFeatures { pitch_stability: 0.8, ..steady };
+        assert!((classify(&wavering, &thresholds).confidence - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_thresholds_are_tunable() {
+        let features = features(&band_noise());
+        // A site whose hiss is coloured enough to miss the default flatness
+        let strict = ClassifierThresholds { noise_min_flatness: 0.5, ..ClassifierThresholds::default() };
+        assert_ne!(classify(&features, &strict).class, ContentClass::Noise);
+
+        let spoken = ContentFeatures::of(&speech()).unwrap();
+        let long_pauses = ClassifierThresholds { speech_min_silence_percent: 60.0, ..ClassifierThresholds::default() };
+        assert_eq!(classify(&spoken, &long_pauses).class, ContentClass::Music);
+
+        assert_eq!("Tone".parse(), Ok(ContentClass::Tone));
+        assert!("jingle".parse::<ContentClass>().unwrap_err().contains("expected speech, tone"));
+    }
+}
@@ -0,0 +1,463 @@
+//! Clip extraction from test recordings
+//!
+//! This module provides a command to cut a span out of the page recordings in
+//! a test output directory, addressed either by page number and offsets or by
+//! a wall-clock range. Spans covering several recordings are spliced together
+//! with silence filling the gaps between pages.
+
+use crate::cli::audio_analyzer::{analyze_samples, format_db, format_frequency, AnalysisProfile};
+use crate::cli::recorder::WavRecorder;
+use crate::cli::test::TestSummary;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ClipError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON parse error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Summary file not found: {0}")]
+    SummaryNotFound(PathBuf),
+
+    #[error("WAV error: {0}")]
+    Wav(#[from] hound::Error),
+
+    #[error("Recorder error: {0}")]
+    Recorder(#[from] super::recorder::RecorderError),
+
+    #[error("Page {0} not found in results")]
+    PageNotFound(u32),
+
+    #[error("Invalid time '{0}': expected HH:MM:SS, YYYY-MM-DD HH:MM:SS or RFC 3339")]
+    InvalidTime(String),
+
+    #[error("Invalid range: {0}")]
+    InvalidRange(String),
+
+    #[error("Requested span {from} - {to} is outside all recordings{coverage}")]
+    OutOfRange {
+        from: String,
+        to: String,
+        coverage: String,
+    },
+
+    #[error("Span covers pages from several endpoints ({0}); use --endpoint to choose one")]
+    AmbiguousEndpoint(String),
+
+    #[error("Recordings in the span have different formats ({0})")]
+    MixedFormats(String),
+}
+
+/// How the clip is addressed
+pub enum ClipRange {
+    /// Offsets in seconds from the start of a page recording
+    Page {
+        page: u32,
+        start_secs: f64,
+        end_secs: Option<f64>,
+    },
+    /// Wall-clock times (UTC), parsed with `parse_clip_time`
+    WallClock { from: String, to: String },
+}
+
+pub struct ClipOptions {
+    pub directory: PathBuf,
+    pub range: ClipRange,
+    pub output: PathBuf,
+    pub endpoint: Option<String>,
+    pub analyze: bool,
+}
+
+/// A recording positioned on the wall clock
+struct Segment {
+    path: PathBuf,
+    endpoint: String,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    spec: hound::WavSpec,
+}
+
+impl Segment {
+    fn open(path: PathBuf, endpoint: String, start: DateTime<Utc>) -> Result<Self, ClipError> {
+        let reader = hound::WavReader::open(&path)?;
+        let spec = reader.spec();
+        let end = start + secs_to_duration(f64::from(reader.duration()) / f64::from(spec.sample_rate));
+        Ok(Self {
+            path,
+            endpoint,
+            start,
+            end,
+            spec,
+        })
+    }
+
+    /// Frame index of a wall-clock time within this recording
+    fn frame_at(&self, time: DateTime<Utc>) -> u32 {
+        let offset = duration_to_secs(time - self.start).max(0.0);
+        (offset * f64::from(self.spec.sample_rate)).round() as u32
+    }
+}
+
+/// Run the clip command
+pub fn run_clip(options: ClipOptions) -> Result<(), ClipError> {
+    let summary_path = options.directory.join("summary.json");
+    if !summary_path.exists() {
+        return Err(ClipError::SummaryNotFound(summary_path));
+    }
+
+    let summary: TestSummary = {
+        let file = File::open(&summary_path)?;
+        serde_json::from_reader(file)?
+    };
+
+    let (segments, from, to) = match &options.range {
+        ClipRange::Page { page, start_secs, end_secs } => {
+            let page = summary
+                .find_page(*page, options.endpoint.as_deref())
+                .ok_or(ClipError::PageNotFound(*page))?;
+            let segment = Segment::open(
+                options.directory.join(&page.recording_file),
+                page.endpoint.clone(),
+                page.start_time,
+            )?;
+
+            let length = duration_to_secs(segment.end - segment.start);
+            let end_secs = end_secs.unwrap_or(length);
+            if *start_secs < 0.0 || end_secs <= *start_secs {
+                return Err(ClipError::InvalidRange(format!(
+                    "start {:.3}s must be >= 0 and before end {:.3}s",
+                    start_secs, end_secs
+                )));
+            }
+            if *start_secs >= length {
+                return Err(ClipError::InvalidRange(format!(
+                    "start {:.3}s is past the end of page {} ({:.3}s long)",
+                    start_secs, page.page_number, length
+                )));
+            }
+
+            let from = segment.start + secs_to_duration(*start_secs);
+            let to = segment.start + secs_to_duration(end_secs);
+            (vec![segment], from, to)
+        }
+        ClipRange::WallClock { from, to } => {
+            let date = summary.test_metadata.start_time.date_naive();
+            let from = parse_clip_time(from, date)?;
+            let to = parse_clip_time(to, date)?;
+            if to <= from {
+                return Err(ClipError::InvalidRange(format!(
+                    "end {} must be after start {}",
+                    to.format("%H:%M:%S%.3f"),
+                    from.format("%H:%M:%S%.3f")
+                )));
+            }
+
+            let mut segments = Vec::new();
+            for page in &summary.pages {
+                if options.endpoint.as_ref().is_some_and(|ep| *ep != page.endpoint) {
+                    continue;
+                }
+                let path = options.directory.join(&page.recording_file);
+                if !path.exists() {
+                    continue;
+                }
+                segments.push(Segment::open(path, page.endpoint.clone(), page.start_time)?);
+            }
+            (segments, from, to)
+        }
+    };
+
+    let (spec, samples, clip_start) = extract_span(segments, from, to)?;
+
+    let mut recorder = WavRecorder::new(&options.output, spec.sample_rate, spec.channels as u8)?;
+    recorder.write_samples(&samples)?;
+    recorder.finalize()?;
+
+    let frames = samples.len() / spec.channels as usize;
+    let duration = frames as f64 / f64::from(spec.sample_rate);
+    println!(
+        "Clip saved to {} ({:.3}s from {})",
+        options.output.display(),
+        duration,
+        clip_start.format("%Y-%m-%d %H:%M:%S%.3f UTC")
+    );
+
+    if options.analyze {
+        let stats = analyze_samples(&samples, spec.sample_rate, spec.channels as u8, AnalysisProfile::Speech);
+        println!("  Audio:  Avg RMS: {}, Peak: {}, Dominant Freq: {}",
+            format_db(stats.avg_rms_db),
+            format_db(stats.max_peak_db),
+            format_frequency(stats.dominant_freq_hz)
+        );
+        println!("  Issues: {} glitches, {} clipped samples ({:.2}%), {:.1}% silence",
+            stats.total_glitches,
+            stats.total_clipped,
+            stats.clipping_percent(),
+            stats.silence_percent()
+        );
+    }
+
+    Ok(())
+}
+
+/// Extract `[from, to)` from the recordings, clamped to the recorded coverage.
+/// Returns the format, interleaved samples and the wall-clock start of the clip.
+fn extract_span(
+    mut segments: Vec<Segment>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<(hound::WavSpec, Vec<i16>, DateTime<Utc>), ClipError> {
+    segments.sort_by_key(|s| s.start);
+
+    let coverage = match (segments.first(), segments.iter().map(|s| s.end).max()) {
+        (Some(first), Some(last_end)) => format!(
+            " (recordings cover {} - {})",
+            first.start.format("%Y-%m-%d %H:%M:%S%.3f"),
+            last_end.format("%Y-%m-%d %H:%M:%S%.3f")
+        ),
+        _ => " (no recordings found)".to_string(),
+    };
+
+    segments.retain(|s| s.start < to && s.end > from);
+    let Some(first) = segments.first() else {
+        return Err(ClipError::OutOfRange {
+            from: from.format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            to: to.format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            coverage,
+        });
+    };
+
+    let spec = first.spec;
+    let endpoint = first.endpoint.clone();
+    if let Some(other) = segments.iter().find(|s| s.endpoint != endpoint) {
+        return Err(ClipError::AmbiguousEndpoint(format!("{}, {}", endpoint, other.endpoint)));
+    }
+    if let Some(other) = segments.iter().find(|s| s.spec.sample_rate != spec.sample_rate || s.spec.channels != spec.channels) {
+        return Err(ClipError::MixedFormats(format!(
+            "{}Hz/{}ch vs {}Hz/{}ch",
+            spec.sample_rate, spec.channels, other.spec.sample_rate, other.spec.channels
+        )));
+    }
+
+    let clip_start = from.max(first.start);
+    let clip_end = to.min(segments.iter().map(|s| s.end).max().unwrap_or(to));
+    let rate = f64::from(spec.sample_rate);
+    let channels = spec.channels as usize;
+
+    let mut samples = Vec::new();
+    let mut cursor = clip_start;
+    for segment in &segments {
+        // Fill the gap between pages with silence to keep wall-clock alignment
+        if segment.start > cursor {
+            let gap = (duration_to_secs(segment.start - cursor) * rate).round() as usize;
+            samples.resize(samples.len() + gap * channels, 0);
+            cursor = segment.start;
+        }
+
+        let start_frame = segment.frame_at(cursor);
+        let end_frame = segment.frame_at(clip_end.min(segment.end));
+        if end_frame <= start_frame {
+            continue;
+        }
+
+        let mut reader = hound::WavReader::new(BufReader::new(File::open(&segment.path)?))?;
+        reader.seek(start_frame)?;
+        let count = (end_frame - start_frame) as usize * channels;
+        for sample in reader.samples::<i16>().take(count) {
+            samples.push(sample?);
+        }
+        cursor = clip_end.min(segment.end);
+    }
+
+    Ok((spec, samples, clip_start))
+}
+
+/// Parse a wall-clock time given on the command line (always UTC, like summary.json).
+/// A bare time of day is taken to be on `date`.
+pub fn parse_clip_time(input: &str, date: NaiveDate) -> Result<DateTime<Utc>, ClipError> {
+    let input = input.trim();
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(input, format) {
+            return Ok(time.and_utc());
+        }
+    }
+    if let Ok(time) = NaiveTime::parse_from_str(input, "%H:%M:%S%.f") {
+        return Ok(date.and_time(time).and_utc());
+    }
+
+    Err(ClipError::InvalidTime(input.to_string()))
+}
+
+fn secs_to_duration(secs: f64) -> chrono::Duration {
+    chrono::Duration::microseconds((secs * 1_000_000.0).round() as i64)
+}
+
+pub(crate) fn duration_to_secs(duration: chrono::Duration) -> f64 {
+    duration.num_microseconds().unwrap_or(i64::MAX) as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::path::Path;
+
+    fn write_tone(path: &Path, frequency_hz: f64, duration_secs: f64, sample_rate: u32) {
+        let count = (f64::from(sample_rate) * duration_secs) as usize;
+        let samples: Vec<i16> = (0..count)
+            .map(|i| {
+                let t = i as f64 / f64::from(sample_rate);
+                (0.5 * (2.0 * std::f64::consts::PI * frequency_hz * t).sin() * 32767.0) as i16
+            })
+            .collect();
+        let mut recorder = WavRecorder::new(path, sample_rate, 1).unwrap();
+        recorder.write_samples(&samples).unwrap();
+        recorder.finalize().unwrap();
+    }
+
+    fn page_json(number: u32, start: DateTime<Utc>, file: &str) -> serde_json::Value {
+        serde_json::json!({
+            "page_number": number,
+            "endpoint": "224.0.1.1:5004",
+            "start_time": start,
+            "end_time": start + chrono::Duration::seconds(3),
+            "duration_secs": 3.0,
+            "recording_file": file,
+            "network": {
+                "packets_received": 150, "bytes_received": 24000, "packets_lost": 0,
+                "loss_percent": 0.0, "jitter_ms": 0.0
+            },
+            "audio": {
+                "peak_rms_db": -9.0, "max_peak_db": -6.0, "dominant_freq_hz": 1000.0,
+                "total_glitches": 0, "total_clipped": 0, "clipping_percent": 0.0,
+                "avg_zero_crossing_rate": 2000.0
+            }
+        })
+    }
+
+    fn write_fixture(dir: &Path, pages: &[serde_json::Value]) {
+        let start = Utc.with_ymd_and_hms(2024, 1, 15, 14, 0, 0).unwrap();
+        let summary = serde_json::json!({
+            "test_metadata": {
+                "start_time": start,
+                "end_time": start + chrono::Duration::minutes(60),
+                "duration_secs": 3600.0,
+                "pattern": "224.0.1.1:5004",
+                "endpoints_monitored": 1,
+                "metrics_interval_ms": 500,
+                "timeout_secs": 3600
+            },
+            "pages": pages,
+            "endpoint_totals": {},
+            "errors": []
+        });
+        std::fs::write(dir.join("summary.json"), summary.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_clip_page_offsets() {
+        let dir = tempfile::tempdir().unwrap();
+        let start = Utc.with_ymd_and_hms(2024, 1, 15, 14, 32, 0).unwrap();
+        write_tone(&dir.path().join("page_0001.wav"), 1000.0, 3.0, 8000);
+        write_fixture(dir.path(), &[page_json(1, start, "page_0001.wav")]);
+
+        let output = dir.path().join("clip.wav");
+        run_clip(ClipOptions {
+            directory: dir.path().to_path_buf(),
+            range: ClipRange::Page { page: 1, start_secs: 1.0, end_secs: Some(2.0) },
+            output: output.clone(),
+            endpoint: None,
+            analyze: false,
+        })
+        .unwrap();
+
+        let mut reader = hound::WavReader::open(&output).unwrap();
+        assert_eq!(reader.spec().sample_rate, 8000);
+        assert_eq!(reader.duration(), 8000, "exactly one second of samples");
+
+        let samples: Vec<i16> = reader.samples::<i16>().map(Result::unwrap).collect();
+        let stats = analyze_samples(&samples, 8000, 1, AnalysisProfile::Speech);
+        assert!(
+            (900.0..=1100.0).contains(&stats.dominant_freq_hz),
+            "tone should be preserved, got {}",
+            stats.dominant_freq_hz
+        );
+    }
+
+    #[test]
+    fn test_clip_wall_clock_spans_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = Utc.with_ymd_and_hms(2024, 1, 15, 14, 32, 0).unwrap();
+        let second = first + chrono::Duration::seconds(5);
+        write_tone(&dir.path().join("page_0001.wav"), 1000.0, 3.0, 8000);
+        write_tone(&dir.path().join("page_0002.wav"), 440.0, 3.0, 8000);
+        write_fixture(
+            dir.path(),
+            &[page_json(1, first, "page_0001.wav"), page_json(2, second, "page_0002.wav")],
+        );
+
+        // 14:32:02 - 14:32:06 covers 1s of page 1, a 2s gap and 1s of page 2
+        let output = dir.path().join("clip.wav");
+        run_clip(ClipOptions {
+            directory: dir.path().to_path_buf(),
+            range: ClipRange::WallClock { from: "14:32:02".to_string(), to: "14:32:06".to_string() },
+            output: output.clone(),
+            endpoint: None,
+            analyze: true,
+        })
+        .unwrap();
+
+        let mut reader = hound::WavReader::open(&output).unwrap();
+        assert_eq!(reader.duration(), 4 * 8000);
+        let samples: Vec<i16> = reader.samples::<i16>().map(Result::unwrap).collect();
+        assert!(samples[8000..24000].iter().all(|&s| s == 0), "gap should be silent");
+        assert!(samples[24000..].iter().any(|&s| s != 0));
+    }
+
+    #[test]
+    fn test_clip_out_of_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let start = Utc.with_ymd_and_hms(2024, 1, 15, 14, 32, 0).unwrap();
+        write_tone(&dir.path().join("page_0001.wav"), 1000.0, 3.0, 8000);
+        write_fixture(dir.path(), &[page_json(1, start, "page_0001.wav")]);
+
+        let result = run_clip(ClipOptions {
+            directory: dir.path().to_path_buf(),
+            range: ClipRange::WallClock { from: "15:00:00".to_string(), to: "15:00:20".to_string() },
+            output: dir.path().join("clip.wav"),
+            endpoint: None,
+            analyze: false,
+        });
+        assert!(matches!(result, Err(ClipError::OutOfRange { .. })));
+
+        let result = run_clip(ClipOptions {
+            directory: dir.path().to_path_buf(),
+            range: ClipRange::Page { page: 1, start_secs: 5.0, end_secs: None },
+            output: dir.path().join("clip.wav"),
+            endpoint: None,
+            analyze: false,
+        });
+        assert!(matches!(result, Err(ClipError::InvalidRange(_))));
+    }
+
+    #[test]
+    fn test_parse_clip_time() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+        let expected = Utc.with_ymd_and_hms(2024, 1, 15, 14, 32, 5).unwrap();
+
+        assert_eq!(parse_clip_time("14:32:05", date).unwrap(), expected);
+        assert_eq!(parse_clip_time("2024-01-15 14:32:05", date).unwrap(), expected);
+        assert_eq!(parse_clip_time("2024-01-15T16:32:05+02:00", date).unwrap(), expected);
+        assert!(parse_clip_time("half past two", date).is_err());
+    }
+}
@@ -0,0 +1,664 @@
+//! Checking our own codecs (`codec-check`).
+//!
+//! Before a report of one codec sounding worse than another is blamed on the
+//! network, this rules out our end: a reference signal goes through each
+//! codec's encoder and decoder in-process, the same ones `transmit` and
+//! `monitor` use (ffmpeg's included), and what comes out is compared with
+//! what went in. The reference is a built-in multitone, a tone in each
+//! third-octave band at -18 dBFS overall, or a file. Each codec gets:
+//!
+//! - SNR against the reference at the codec's rate, once the round trip's
+//!   delay is taken out
+//! - The level change
+//! - Bandwidth kept: the top of the highest band, counting up from 100 Hz,
+//!   that comes out within 3 dB of the reference. A reference with nothing
+//!   above a band can't show more, so isn't held to more
+//! - Latency the round trip adds
+//! - Frame sanity: a standard packet time, payloads of the size the codec
+//!   should give, and a frame's worth of audio decoded from each
+//!
+//! A codec passes when it meets its thresholds, which have defaults per
+//! codec and can be changed with `--min-snr`, `--max-level-change` and
+//! `--min-bandwidth`, so the check can run in CI.
+
+use crate::cli::audio_input::{read_audio, resample, AudioInput, AudioInputError};
+use crate::cli::convert::{align, downmix};
+use crate::codec::{create_decoder, create_encoder, encode_all, AudioDecoder, CodecError, CodecType};
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Length of the built-in reference
+const MULTITONE_SECS: f64 = 2.0;
+
+/// Rate the built-in reference is made at
+const MULTITONE_RATE: u32 = 48000;
+
+/// Overall level of the built-in reference
+const MULTITONE_DBFS: f64 = -18.0;
+
+/// Third-octave bands measured, by centre frequency: 100 Hz up to 16 kHz
+const BAND_COUNT: i32 = 23;
+
+/// Bands this far below the reference's loudest are left out of the bandwidth
+const BAND_FLOOR_DB: f64 = 40.0;
+
+/// How far a band may come out from the reference's level and still count as kept
+const BAND_TOLERANCE_DB: f64 = 3.0;
+
+/// Longest round-trip delay looked for
+const MAX_LATENCY_MS: u32 = 40;
+
+/// Audio the delay is found from; the rest is compared at that delay
+const ALIGN_SECS: u32 = 1;
+
+/// Reported in place of an infinite SNR, when the round trip is exact
+pub const EXACT_SNR_DB: f64 = 120.0;
+
+/// Packet times a frame should come to
+const STANDARD_FRAME_MS: [f64; 6] = [10.0, 20.0, 30.0, 40.0, 60.0, 120.0];
+
+/// Largest Opus packet (RFC 6716)
+const MAX_OPUS_BYTES: usize = 1275;
+
+#[derive(Error, Debug)]
+pub enum CodecCheckError {
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Audio input error: {0}")]
+    Input(#[from] AudioInputError),
+
+    #[error("Reference {0} has no audio")]
+    EmptyReference(PathBuf),
+
+    #[error("{failed} of {total} codecs failed the check")]
+    Failed { failed: usize, total: usize },
+}
+
+/// The least a codec must do to pass
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Thresholds {
+    pub min_snr_db: f64,
+    /// Either way
+    pub max_level_change_db: f64,
+    pub min_bandwidth_hz: f64,
+}
+
+impl Thresholds {
+    /// What a working encoder and decoder of `codec` comfortably manage.
+    /// Opus is perceptual, so its waveform SNR is low however good it sounds;
+    /// its threshold only catches one that's broken
+    pub fn default_for(codec: CodecType) -> Self {
+        let (min_snr_db, max_level_change_db, min_bandwidth_hz) = match codec {
+            CodecType::G711Ulaw | CodecType::G711Alaw => (30.0, 1.0, 3000.0),
+            CodecType::G722 => (15.0, 2.0, 6000.0),
+            CodecType::Opus => (3.0, 3.0, 8000.0),
+            CodecType::L16 => (90.0, 0.1, 3000.0),
+            CodecType::L24 => (90.0, 0.1, 15000.0),
+        };
+        Self { min_snr_db, max_level_change_db, min_bandwidth_hz }
+    }
+}
+
+/// A threshold from the command line: `DB` for every codec, or `CODEC=DB`
+/// for one, which wins over a value for every codec
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThresholdArg {
+    pub codec: Option<CodecType>,
+    pub value: f64,
+}
+
+impl FromStr for ThresholdArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (codec, value) = match s.split_once('=') {
+            Some((name, value)) => {
+                let codec = CodecType::from_str(name.trim()).ok_or_else(|| format!("unknown codec '{}'", name.trim()))?;
+                (Some(codec), value)
+            }
+            None => (None, s),
+        };
+        let value = value.trim().parse::<f64>().map_err(|_| format!("'{}' is not a number", value.trim()))?;
+        if !value.is_finite() {
+            return Err(format!("'{}' is not a number", s));
+        }
+        Ok(Self { codec, value })
+    }
+}
+
+pub struct CodecCheckOptions {
+    /// Codecs asked for; every codec if empty
+    pub codecs: Vec<CodecType>,
+    /// Reference audio in place of the built-in multitone
+    pub reference: Option<PathBuf>,
+    pub audio_input: AudioInput,
+    pub min_snr: Vec<ThresholdArg>,
+    pub max_level_change: Vec<ThresholdArg>,
+    pub min_bandwidth: Vec<ThresholdArg>,
+    pub json: bool,
+}
+
+impl CodecCheckOptions {
+    /// `codec`'s defaults with the command line's thresholds applied
+    pub fn thresholds(&self, codec: CodecType) -> Thresholds {
+        fn apply(value: &mut f64, args: &[ThresholdArg], codec: CodecType) {
+            let every = args.iter().filter(|arg| arg.codec.is_none());
+            let own = args.iter().filter(|arg| arg.codec == Some(codec));
+            if let Some(arg) = every.chain(own).last() {
+                *value = arg.value;
+            }
+        }
+        let mut thresholds = Thresholds::default_for(codec);
+        apply(&mut thresholds.min_snr_db, &self.min_snr, codec);
+        apply(&mut thresholds.max_level_change_db, &self.max_level_change, codec);
+        apply(&mut thresholds.min_bandwidth_hz, &self.min_bandwidth, codec);
+        thresholds
+    }
+}
+
+/// Audio the codecs are checked with
+#[derive(Debug, Clone)]
+pub struct Reference {
+    /// "multitone", or the file's path
+    pub name: String,
+    pub samples: Vec<i16>,
+    pub rate: u32,
+}
+
+impl Reference {
+    /// A tone at the centre of each third-octave band, with phases spread
+    /// (Schroeder's) so the peaks stay well short of clipping
+    pub fn multitone() -> Self {
+        let count = f64::from(BAND_COUNT);
+        let amplitude = 32768.0 * 10f64.powf(MULTITONE_DBFS / 20.0) / (count / 2.0).sqrt();
+        let length = (MULTITONE_SECS * f64::from(MULTITONE_RATE)) as usize;
+        let samples = (0..length)
+            .map(|i| {
+                let t = i as f64 / f64::from(MULTITONE_RATE);
+                let sum: f64 = (0..BAND_COUNT)
+                    .map(|k| {
+                        let phase = -std::f64::consts::PI * f64::from(k) * f64::from(k - 1) / count;
+                        (2.0 * std::f64::consts::PI * band_centre(k) * t + phase).sin()
+                    })
+                    .sum();
+                (amplitude * sum).round().clamp(-32768.0, 32767.0) as i16
+            })
+            .collect();
+        Self { name: "multitone".to_string(), samples, rate: MULTITONE_RATE }
+    }
+
+    pub fn from_file(path: PathBuf, input: &AudioInput) -> Result<Self, CodecCheckError> {
+        let (samples, rate) = read_audio(&path, input.sample_rate, input)?;
+        if samples.is_empty() {
+            return Err(CodecCheckError::EmptyReference(path));
+        }
+        Ok(Self { name: path.display().to_string(), samples, rate })
+    }
+}
+
+/// How a codec came out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Pass,
+    Fail,
+    /// Its encoder or decoder can't be made here, e.g. ffmpeg is missing
+    Unavailable,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pass => "pass",
+            Self::Fail => "FAIL",
+            Self::Unavailable => "unavailable",
+        })
+    }
+}
+
+/// What the round trip did to the reference
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Measurement {
+    pub snr_db: f64,
+    pub level_change_db: f64,
+    pub bandwidth_hz: f64,
+    /// Top of the highest band the reference has
+    pub reference_bandwidth_hz: f64,
+    pub latency_ms: f64,
+    pub frame_ms: f64,
+    pub frame_samples: usize,
+    pub frame_bytes_min: usize,
+    pub frame_bytes_max: usize,
+    /// What's wrong with the frames, if anything
+    pub frame_problems: Vec<String>,
+}
+
+/// One codec's result
+#[derive(Debug, Clone, Serialize)]
+pub struct CodecCheck {
+    pub codec: &'static str,
+    pub outcome: Outcome,
+    pub sample_rate: u32,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub measurement: Option<Measurement>,
+    pub thresholds: Thresholds,
+    /// Thresholds missed and frame problems
+    pub failures: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// `codec-check --json`
+#[derive(Debug, Serialize)]
+pub struct CodecCheckReport<'a> {
+    pub reference: &'a str,
+    pub reference_rate: u32,
+    pub checks: &'a [CodecCheck],
+    pub passed: usize,
+    pub failed: usize,
+    pub unavailable: usize,
+}
+
+/// Run the codec-check command
+pub fn run_codec_check(options: CodecCheckOptions) -> Result<(), CodecCheckError> {
+    let reference = match options.reference {
+        Some(ref path) => Reference::from_file(path.clone(), &options.audio_input)?,
+        None => Reference::multitone(),
+    };
+    let codecs = if options.codecs.is_empty() { CodecType::ALL.to_vec() } else { options.codecs.clone() };
+    let checks: Vec<CodecCheck> =
+        codecs.iter().map(|&codec| check_codec(codec, &reference, options.thresholds(codec))).collect();
+
+    let count = |outcome: Outcome| checks.iter().filter(|check| check.outcome == outcome).count();
+    // A codec that can't be checked only fails the run if it was asked for
+    let unavailable = count(Outcome::Unavailable);
+    let failed = count(Outcome::Fail) + if options.codecs.is_empty() { 0 } else { unavailable };
+    let report = CodecCheckReport {
+        reference: &reference.name,
+        reference_rate: reference.rate,
+        checks: &checks,
+        passed: count(Outcome::Pass),
+        failed,
+        unavailable,
+    };
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("Reference: {} ({} Hz)", reference.name, reference.rate);
+        println!();
+        print_table(&checks);
+        println!();
+        println!("{} passed, {} failed, {} unavailable", report.passed, count(Outcome::Fail), unavailable);
+    }
+
+    if failed > 0 {
+        return Err(CodecCheckError::Failed { failed, total: checks.len() });
+    }
+    Ok(())
+}
+
+/// Round-trip `reference` through `codec`'s own encoder and decoder
+pub fn check_codec(codec: CodecType, reference: &Reference, thresholds: Thresholds) -> CodecCheck {
+    let pair = create_encoder(codec).and_then(|encoder| Ok((encoder, create_decoder(codec)?)));
+    // The rate transmit encodes at, which for L16 isn't the codec's usual one
+    let sample_rate = pair.as_ref().map_or(codec.sample_rate(), |(encoder, _)| encoder.sample_rate());
+    let result = pair.and_then(|(encoder, mut decoder)| {
+        measure(codec, encoder.frame_size(), encoder.sample_rate(), decoder.as_mut(), reference)
+    });
+    let mut check = CodecCheck {
+        codec: codec.cli_name(),
+        outcome: Outcome::Fail,
+        sample_rate,
+        measurement: None,
+        thresholds,
+        failures: Vec::new(),
+        error: None,
+    };
+    match result {
+        Ok(measurement) => judge(&mut check, measurement),
+        Err(e @ CodecError::BackendUnavailable { .. }) => {
+            check.outcome = Outcome::Unavailable;
+            check.error = Some(e.to_string());
+        }
+        Err(e) => check.error = Some(e.to_string()),
+    }
+    check
+}
+
+/// Pass or fail a measurement against the check's thresholds
+pub fn judge(check: &mut CodecCheck, measurement: Measurement) {
+    let thresholds = check.thresholds;
+    if measurement.snr_db < thresholds.min_snr_db {
+        check.failures.push(format!("SNR {:.1} dB is below {:.1} dB", measurement.snr_db, thresholds.min_snr_db));
+    }
+    if measurement.level_change_db.abs() > thresholds.max_level_change_db {
+        check.failures.push(format!(
+            "level changed {:+.1} dB, more than {:.1} dB",
+            measurement.level_change_db, thresholds.max_level_change_db
+        ));
+    }
+    if measurement.bandwidth_hz < thresholds.min_bandwidth_hz.min(measurement.reference_bandwidth_hz) {
+        check.failures.push(format!(
+            "bandwidth {:.0} Hz is below {:.0} Hz",
+            measurement.bandwidth_hz, thresholds.min_bandwidth_hz
+        ));
+    }
+    check.failures.extend(measurement.frame_problems.iter().cloned());
+    check.outcome = if check.failures.is_empty() { Outcome::Pass } else { Outcome::Fail };
+    check.measurement = Some(measurement);
+}
+
+/// Encode `reference` as `transmit` would, decode it a frame at a time with
+/// `decoder`, and compare. `frame_size` and `rate` are the encoder's
+pub fn measure(
+    codec: CodecType,
+    frame_size: usize,
+    rate: u32,
+    decoder: &mut dyn AudioDecoder,
+    reference: &Reference,
+) -> Result<Measurement, CodecError> {
+    let original = resample(&reference.samples, reference.rate, rate);
+    let frames = encode_all(codec, &original)?;
+
+    let channels = usize::from(decoder.channels().max(1));
+    let mut decoded = Vec::with_capacity(original.len() * channels);
+    for frame in &frames {
+        decoded.extend(decoder.decode(frame)?);
+    }
+    decoded.extend(decoder.flush()?);
+    let decoded = downmix(decoded, channels);
+    let frame_problems = frame_problems(codec, frame_size, rate, &frames, decoded.len());
+
+    let (lag, aligned) = align_to(&original, &decoded, rate);
+    let (_, snr_db) = align(&original, &aligned, 0);
+    let level_change_db = rms_db(&aligned) - rms_db(&original);
+
+    let sizes = frames.iter().map(Vec::len);
+    let (bandwidth_hz, reference_bandwidth_hz) =
+        bandwidth_hz(&reference.samples, reference.rate, &aligned, rate, level_change_db);
+    Ok(Measurement {
+        snr_db: snr_db.min(EXACT_SNR_DB),
+        level_change_db,
+        bandwidth_hz,
+        reference_bandwidth_hz,
+        latency_ms: lag as f64 * 1000.0 / f64::from(rate),
+        frame_ms: frame_size as f64 * 1000.0 / f64::from(rate),
+        frame_samples: frame_size,
+        frame_bytes_min: sizes.clone().min().unwrap_or(0),
+        frame_bytes_max: sizes.max().unwrap_or(0),
+        frame_problems,
+    })
+}
+
+/// `decoded` with the delay the round trip added taken out, and cut or
+/// padded to the length of `original`, and that delay in samples. The delay
+/// is found from the start, up to `MAX_LATENCY_MS`
+pub fn align_to(original: &[i16], decoded: &[i16], rate: u32) -> (usize, Vec<i16>) {
+    let max_lag = (rate * MAX_LATENCY_MS / 1000) as usize;
+    let align_len = original.len().min((rate * ALIGN_SECS) as usize);
+    let (lag, _) = align(&original[..align_len], decoded, max_lag);
+    let mut aligned = decoded.get(lag..).unwrap_or_default().to_vec();
+    aligned.resize(original.len(), 0);
+    (lag, aligned)
+}
+
+/// What's wrong with the encoded frames, and with how much audio came back
+fn frame_problems(codec: CodecType, frame_size: usize, rate: u32, frames: &[Vec<u8>], decoded: usize) -> Vec<String> {
+    let mut problems = Vec::new();
+    let frame_ms = frame_size as f64 * 1000.0 / f64::from(rate);
+    if !STANDARD_FRAME_MS.iter().any(|&ms| (ms - frame_ms).abs() < 1e-6) {
+        problems.push(format!("frame of {} samples is {:.2} ms, not a standard packet time", frame_size, frame_ms));
+    }
+
+    let expected = match codec {
+        CodecType::G711Ulaw | CodecType::G711Alaw => Some(frame_size),
+        CodecType::G722 => Some(frame_size / 2),
+        CodecType::L16 => Some(frame_size * 2),
+        CodecType::L24 => Some(frame_size * 3),
+        CodecType::Opus => None,
+    };
+    let wrong = frames.iter().filter(|frame| match expected {
+        Some(bytes) => frame.len() != bytes,
+        None => frame.is_empty() || frame.len() > MAX_OPUS_BYTES,
+    });
+    if let Some(frame) = wrong.clone().next() {
+        let should = expected.map_or_else(|| format!("1 to {}", MAX_OPUS_BYTES), |bytes| bytes.to_string());
+        problems.push(format!("{} of {} frames were {} bytes, not {}", wrong.count(), frames.len(), frame.len(), should));
+    }
+
+    // A decoder may hold some back, but no more than a frame
+    let sent = frames.len() * frame_size;
+    if decoded.abs_diff(sent) > frame_size {
+        problems.push(format!("decoder gave {} samples for {} frames of {}", decoded, frames.len(), frame_size));
+    }
+    problems
+}
+
+fn rms_db(samples: &[i16]) -> f64 {
+    let power = samples.iter().map(|&s| f64::from(s).powi(2)).sum::<f64>() / samples.len().max(1) as f64;
+    10.0 * (power / 32768.0f64.powi(2)).max(1e-12).log10()
+}
+
+/// Centre of third-octave band `k`, counting from 100 Hz
+fn band_centre(k: i32) -> f64 {
+    100.0 * 2f64.powf(f64::from(k) / 3.0)
+}
+
+/// Top of the highest band, counting up from 100 Hz, that `decoded` keeps
+/// within `BAND_TOLERANCE_DB` of `reference`, once the overall level
+/// change is taken out, and the top of the highest band the reference has.
+/// Bands the reference hardly has are passed over
+fn bandwidth_hz(
+    reference: &[i16],
+    reference_rate: u32,
+    decoded: &[i16],
+    rate: u32,
+    level_change_db: f64,
+) -> (f64, f64) {
+    let before = band_levels(reference, reference_rate);
+    let after = band_levels(decoded, rate);
+    let loudest = before.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let band_top = |k: i32| band_centre(k) * 2f64.powf(1.0 / 6.0);
+    let present: Vec<i32> = (0..BAND_COUNT).filter(|&k| before[k as usize] >= loudest - BAND_FLOOR_DB).collect();
+
+    let kept = present
+        .iter()
+        .take_while(|&&k| (after[k as usize] - before[k as usize] - level_change_db).abs() <= BAND_TOLERANCE_DB)
+        .last()
+        .map_or(0.0, |&k| band_top(k));
+    (kept, present.last().map_or(0.0, |&k| band_top(k)))
+}
+
+/// Mean-square level of each third-octave band in dB, from a Hann-windowed
+/// averaged spectrum. Bands above the Nyquist frequency are silent
+fn band_levels(samples: &[i16], rate: u32) -> Vec<f64> {
+    let size = (rate as usize / 8).next_power_of_two();
+    let window: Vec<f64> =
+        (0..size).map(|i| 0.5 - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / size as f64).cos()).collect();
+    let window_power: f64 = window.iter().map(|w| w * w).sum();
+    let fft = FftPlanner::<f64>::new().plan_fft_forward(size);
+
+    let mut power = vec![0.0; size / 2];
+    let mut blocks = 0;
+    for start in (0..=samples.len().saturating_sub(size)).step_by(size / 2) {
+        let mut buffer: Vec<Complex<f64>> = (0..size)
+            .map(|i| Complex::new(samples.get(start + i).map_or(0.0, |&s| f64::from(s)) * window[i], 0.0))
+            .collect();
+        fft.process(&mut buffer);
+        for (bin, value) in power.iter_mut().zip(&buffer) {
+            *bin += value.norm_sqr();
+        }
+        blocks += 1;
+    }
+
+    let bin_hz = f64::from(rate) / size as f64;
+    let scale = 2.0 / (f64::from(blocks.max(1)) * size as f64 * window_power) / 32768.0f64.powi(2);
+    (0..BAND_COUNT)
+        .map(|k| {
+            let (low, high) = (band_centre(k) / 2f64.powf(1.0 / 6.0), band_centre(k) * 2f64.powf(1.0 / 6.0));
+            let band: f64 = (1..power.len())
+                .filter(|&bin| (low..high).contains(&(bin as f64 * bin_hz)))
+                .map(|bin| power[bin])
+                .sum();
+            10.0 * (band * scale).max(1e-12).log10()
+        })
+        .collect()
+}
+
+fn print_table(checks: &[CodecCheck]) {
+    println!(
+        "{:<10} {:>6} {:>9} {:>9} {:>10} {:>9} {:>14}  Result",
+        "Codec", "Rate", "SNR", "Level", "Bandwidth", "Latency", "Frame"
+    );
+    for check in checks {
+        match check.measurement {
+            Some(ref m) => println!(
+                "{:<10} {:>6} {:>6.1} dB {:>+6.1} dB {:>7.0} Hz {:>6.1} ms {:>14}  {}",
+                check.codec,
+                check.sample_rate,
+                m.snr_db,
+                m.level_change_db,
+                m.bandwidth_hz,
+                m.latency_ms,
+                frame_text(m),
+                check.outcome
+            ),
+            None => println!("{:<10} {:>6} {:>56}  {}", check.codec, check.sample_rate, "-", check.outcome),
+        }
+        for failure in check.failures.iter().chain(&check.error) {
+            println!("{:<10} {}", "", failure);
+        }
+    }
+}
+
+/// "20 ms, 160 B", or a byte range for a variable-rate codec
+fn frame_text(m: &Measurement) -> String {
+    if m.frame_bytes_min == m.frame_bytes_max {
+        format!("{} ms, {} B", m.frame_ms, m.frame_bytes_max)
+    } else {
+        format!("{} ms, {}-{} B", m.frame_ms, m.frame_bytes_min, m.frame_bytes_max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::G711UlawCodec;
+
+    /// A 1 kHz tone at -12 dBFS
+    fn tone(rate: u32) -> Reference {
+        let samples = (0..rate)
+            .map(|i| (8200.0 * (2.0 * std::f64::consts::PI * 1000.0 * f64::from(i) / f64::from(rate)).sin()) as i16)
+            .collect();
+        Reference { name: "tone".to_string(), samples, rate }
+    }
+
+    #[test]
+    fn test_g711_tone() {
+        for codec in [CodecType::G711Ulaw, CodecType::G711Alaw] {
+            let check = check_codec(codec, &tone(8000), Thresholds::default_for(codec));
+            let m = check.measurement.as_ref().unwrap();
+            assert_eq!(check.outcome, Outcome::Pass, "{:?}", check.failures);
+            assert!(m.snr_db > 30.0, "{}: {}", codec.name(), m.snr_db);
+            assert!(m.level_change_db.abs() < 0.5);
+            assert!(m.latency_ms.abs() < 1e-9, "{}", m.latency_ms);
+            assert!((m.frame_ms - 20.0).abs() < 1e-9);
+            assert_eq!((m.frame_samples, m.frame_bytes_min, m.frame_bytes_max), (160, 160, 160));
+        }
+    }
+
+    #[test]
+    fn test_multitone_bandwidth() {
+        let reference = Reference::multitone();
+        let peak = reference.samples.iter().map(|s| s.unsigned_abs()).max().unwrap();
+        assert!(peak < 30000, "{}", peak);
+        assert!((rms_db(&reference.samples) - MULTITONE_DBFS).abs() < 0.5);
+
+        // G.711 keeps the telephone band, L24 nearly everything
+        let check = check_codec(CodecType::G711Ulaw, &reference, Thresholds::default_for(CodecType::G711Ulaw));
+        assert_eq!(check.outcome, Outcome::Pass, "{:?}", check.failures);
+        let bandwidth = check.measurement.unwrap().bandwidth_hz;
+        assert!((3000.0..4000.0).contains(&bandwidth), "{}", bandwidth);
+
+        let check = check_codec(CodecType::L24, &reference, Thresholds::default_for(CodecType::L24));
+        assert_eq!(check.outcome, Outcome::Pass, "{:?}", check.failures);
+        let m = check.measurement.unwrap();
+        assert!(m.bandwidth_hz > 15000.0, "{}", m.bandwidth_hz);
+        assert!(m.snr_db >= EXACT_SNR_DB, "{}", m.snr_db);
+    }
+
+    /// Decodes every other sample, as a decoder run at the wrong rate would
+    struct BrokenDecoder(G711UlawCodec);
+
+    impl AudioDecoder for BrokenDecoder {
+        fn decode(&mut self, input: &[u8]) -> Result<Vec<i16>, CodecError> {
+            Ok(self.0.decode(input)?.into_iter().step_by(2).collect())
+        }
+
+        fn sample_rate(&self) -> u32 {
+            8000
+        }
+
+        fn channels(&self) -> u8 {
+            1
+        }
+
+        fn codec_type(&self) -> CodecType {
+            CodecType::G711Ulaw
+        }
+    }
+
+    #[test]
+    fn test_broken_decoder_flagged() {
+        let codec = CodecType::G711Ulaw;
+        let mut decoder = BrokenDecoder(G711UlawCodec::new());
+        let measurement = measure(codec, 160, 8000, &mut decoder, &tone(8000)).unwrap();
+        let mut check = CodecCheck {
+            codec: codec.cli_name(),
+            outcome: Outcome::Pass,
+            sample_rate: 8000,
+            measurement: None,
+            thresholds: Thresholds::default_for(codec),
+            failures: Vec::new(),
+            error: None,
+        };
+        judge(&mut check, measurement);
+
+        assert_eq!(check.outcome, Outcome::Fail);
+        assert!(check.failures.iter().any(|f| f.starts_with("SNR")), "{:?}", check.failures);
+        assert!(check.failures.iter().any(|f| f.starts_with("decoder gave 4000 samples")), "{:?}", check.failures);
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let options = CodecCheckOptions {
+            codecs: Vec::new(),
+            reference: None,
+            audio_input: AudioInput::default(),
+            min_snr: vec!["g722=12".parse().unwrap(), "25".parse().unwrap()],
+            max_level_change: Vec::new(),
+            min_bandwidth: vec!["pcmu = 3400".parse().unwrap()],
+            json: false,
+        };
+        // A codec's own value wins over one for every codec, in either order
+        let defaults = Thresholds::default_for;
+        assert_eq!(options.thresholds(CodecType::G722), Thresholds { min_snr_db: 12.0, ..defaults(CodecType::G722) });
+        assert_eq!(options.thresholds(CodecType::G711Alaw), Thresholds { min_snr_db: 25.0, ..defaults(CodecType::G711Alaw) });
+        assert_eq!(
+            options.thresholds(CodecType::G711Ulaw),
+            Thresholds { min_snr_db: 25.0, min_bandwidth_hz: 3400.0, ..defaults(CodecType::G711Ulaw) }
+        );
+
+        assert!("mp3=10".parse::<ThresholdArg>().is_err());
+        assert!("loud".parse::<ThresholdArg>().is_err());
+        assert!("inf".parse::<ThresholdArg>().is_err());
+        assert_eq!("-3".parse::<ThresholdArg>(), Ok(ThresholdArg { codec: None, value: -3.0 }));
+    }
+}
@@ -0,0 +1,219 @@
+//! Shell completions, and the value parsers that feed them.
+//!
+//! `completions <shell>` writes a completion script generated from the clap
+//! definitions. Arguments with a fixed set of values use the parsers below
+//! rather than free-form strings, so the script can offer those values and a
+//! typo is rejected while the arguments are parsed, with a suggestion. The
+//! codec names come from `CodecType::ALL`, the list the capabilities registry
+//! is built from, so a new codec is offered without touching this file.
+
+use crate::codec::{CodecSpec, CodecType};
+use crate::config::closest;
+use crate::network::{is_emergency_channel, is_priority_channel, PolycomCodec};
+use clap::builder::{PossibleValue, TypedValueParser};
+use clap::error::ErrorKind;
+use clap::{Arg, Command};
+use clap_complete::Shell;
+use std::ffi::OsStr;
+use std::io::Write;
+
+/// Write the completion script for `shell`
+pub fn write_completions(shell: Shell, out: &mut dyn Write) {
+    let mut cmd = <super::Cli as clap::CommandFactory>::command();
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, name, out);
+}
+
+/// An invalid value error in clap's format, naming the argument
+fn invalid_value(cmd: &Command, arg: Option<&Arg>, value: &str, problem: &str) -> clap::Error {
+    let arg = arg.map_or_else(|| "...".to_string(), ToString::to_string);
+    clap::Error::raw(
+        ErrorKind::InvalidValue,
+        format!("invalid value '{}' for '{}': {}\n", value, arg, problem),
+    )
+    .with_cmd(cmd)
+}
+
+/// "unknown codec", with the nearest name if there's one close enough
+fn unknown_codec(name: &str, names: impl Iterator<Item = &'static str> + Clone) -> String {
+    match closest(&name.to_lowercase(), names.clone()) {
+        Some(suggestion) => format!("unknown codec; did you mean '{}'?", suggestion),
+        None => format!("unknown codec (expected one of: {})", names.collect::<Vec<_>>().join(", ")),
+    }
+}
+
+fn codec_names() -> impl Iterator<Item = &'static str> + Clone {
+    CodecType::ALL.into_iter().map(|codec| codec.cli_name())
+}
+
+fn codec_values() -> Box<dyn Iterator<Item = PossibleValue>> {
+    Box::new(CodecType::ALL.into_iter().map(|codec| PossibleValue::new(codec.cli_name()).help(codec.name())))
+}
+
+/// `--codec` on `monitor` and `test`: a codec name, or linear PCM with its
+/// rate and channels (`l24:48000:2`)
+#[derive(Debug, Clone, Copy)]
+pub struct CodecSpecParser;
+
+impl TypedValueParser for CodecSpecParser {
+    type Value = CodecSpec;
+
+    fn parse_ref(&self, cmd: &Command, arg: Option<&Arg>, value: &OsStr) -> Result<CodecSpec, clap::Error> {
+        let text = value.to_string_lossy();
+        CodecSpec::parse(&text).ok_or_else(|| {
+            let name = text.split(':').next().unwrap_or_default().trim();
+            let problem = if CodecType::from_str(name).is_some() {
+                "only linear PCM takes a rate and channels, as codec:rate:channels (e.g. l24:48000:2)".to_string()
+            } else {
+                unknown_codec(name, codec_names())
+            };
+            invalid_value(cmd, arg, &text, &problem)
+        })
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(codec_values())
+    }
+}
+
+/// `--codec` on `transmit`: a codec name alone
+#[derive(Debug, Clone, Copy)]
+pub struct CodecTypeParser;
+
+impl TypedValueParser for CodecTypeParser {
+    type Value = CodecType;
+
+    fn parse_ref(&self, cmd: &Command, arg: Option<&Arg>, value: &OsStr) -> Result<CodecType, clap::Error> {
+        let text = value.to_string_lossy();
+        CodecType::from_str(&text).ok_or_else(|| invalid_value(cmd, arg, &text, &unknown_codec(&text, codec_names())))
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(codec_values())
+    }
+}
+
+/// `--codec` on `polycom-transmit`. The name is checked here and kept as
+/// given; the command parses it again with its aliases.
+#[derive(Debug, Clone, Copy)]
+pub struct PolycomCodecParser;
+
+impl TypedValueParser for PolycomCodecParser {
+    type Value = String;
+
+    fn parse_ref(&self, cmd: &Command, arg: Option<&Arg>, value: &OsStr) -> Result<String, clap::Error> {
+        let text = value.to_string_lossy();
+        if PolycomCodec::from_name(&text).is_none() {
+            let names = PolycomCodec::ALL.into_iter().map(|codec| codec.cli_name());
+            return Err(invalid_value(cmd, arg, &text, &unknown_codec(&text, names)));
+        }
+        Ok(text.into_owned())
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(
+            PolycomCodec::ALL.into_iter().map(|codec| PossibleValue::new(codec.cli_name()).help(codec.name())),
+        ))
+    }
+}
+
+/// A Polycom channel, 1-50, offered with what each one is
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelParser;
+
+impl ChannelParser {
+    fn describe(channel: u8) -> String {
+        let group = if channel <= 25 { "PTT" } else { "paging" };
+        if is_emergency_channel(channel) {
+            format!("{} emergency", group)
+        } else if is_priority_channel(channel) {
+            format!("{} priority", group)
+        } else {
+            group.to_string()
+        }
+    }
+}
+
+impl TypedValueParser for ChannelParser {
+    type Value = u8;
+
+    fn parse_ref(&self, cmd: &Command, arg: Option<&Arg>, value: &OsStr) -> Result<u8, clap::Error> {
+        let text = value.to_string_lossy();
+        text.trim()
+            .parse::<u8>()
+            .ok()
+            .filter(|channel| (1..=50).contains(channel))
+            .ok_or_else(|| invalid_value(cmd, arg, &text, "expected a channel from 1 to 50"))
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new((1..=50u8).map(|channel| PossibleValue::new(channel.to_string()).help(Self::describe(channel)))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Commands};
+    use clap::Parser;
+
+    fn script(shell: Shell) -> String {
+        let mut out = Vec::new();
+        write_completions(shell, &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_bash_script_offers_values() {
+        let bash = script(Shell::Bash);
+        for codec in CodecType::ALL {
+            assert!(bash.contains(codec.cli_name()), "{} missing", codec.cli_name());
+        }
+        assert!(bash.contains("\"g722 g711u g711a\""));
+        assert!(bash.contains("polycom-transmit"));
+
+        // Descriptions reach shells that can show them
+        let fish = script(Shell::Fish);
+        assert!(fish.contains("PTT emergency"));
+        assert!(fish.contains("G.711 A-law"));
+    }
+
+    #[test]
+    fn test_invalid_codec_fails_parsing() {
+        let error = Cli::try_parse_from(["mpu", "transmit", "-f", "a.wav", "-a", "224.0.1.1", "--codec", "g771ulaw"])
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidValue);
+        assert!(error.to_string().contains("did you mean 'g711ulaw'?"), "{}", error);
+
+        let error = Cli::try_parse_from(["mpu", "monitor", "-a", "224.0.1.1", "--codec", "g711ulaw:16000"])
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("only linear PCM"), "{}", error);
+        let error = Cli::try_parse_from(["mpu", "polycom-transmit", "-f", "a.wav", "--codec", "g7222"])
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("did you mean 'g722'?"), "{}", error);
+        let error = Cli::try_parse_from(["mpu", "polycom-transmit", "-f", "a.wav", "--channel", "51"])
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("1 to 50"), "{}", error);
+    }
+
+    #[test]
+    fn test_valid_values_parse() {
+        let cli = Cli::try_parse_from(["mpu", "monitor", "-a", "224.0.1.1", "--codec", "L24:48000:2"]).unwrap();
+        let Some(Commands::Monitor { codec: Some(spec), .. }) = cli.command else {
+            panic!("expected monitor");
+        };
+        assert_eq!((spec.codec, spec.sample_rate, spec.channels), (CodecType::L24, 48000, 2));
+
+        let cli = Cli::try_parse_from(["mpu", "polycom-transmit", "-f", "a.wav", "--codec", "PCMU", "--channel", "49"]).unwrap();
+        let Some(Commands::PolycomTransmit { codec, channel, .. }) = cli.command else {
+            panic!("expected polycom-transmit");
+        };
+        assert_eq!((codec.as_str(), channel), ("PCMU", 49));
+        assert_eq!(ChannelParser::describe(49), "paging priority");
+        assert_eq!(ChannelParser::describe(3), "PTT");
+    }
+}
@@ -0,0 +1,457 @@
+//! Runtime control of the monitored endpoint set
+//!
+//! Long-running monitor/test sessions can change which endpoints they listen
+//! to without a restart. Changes arrive either as SIGHUP (re-parse the
+//! configured pattern or endpoints file) or as line-based commands on a unix
+//! control socket:
+//!
+//! ```text
+//! add 224.0.5.1:5004
+//! remove 224.0.{1-4}.1:5004
+//! reload
+//! status
+//! annotate unplugged the switch uplink
+//! ```
+//!
+//! Each command gets a single-line reply: `ok`, `error: ...`, or for `status`
+//! a JSON array describing the current endpoint table. `annotate` (or `note`)
+//! records the rest of the line as an operator note; see [`super::annotation`].
+
+use super::annotation::{Annotation, AnnotationSource, Keyboard};
+use crate::utils::range_parser::{
+    parse_endpoint_list, parse_endpoint_spec, parse_range, EndpointListError, EndpointOptions, MulticastEndpoint,
+    RangeParseError,
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, warn};
+
+#[derive(Error, Debug)]
+pub enum ControlError {
+    #[error("Invalid address pattern: {0}")]
+    InvalidPattern(#[from] RangeParseError),
+
+    #[error("Invalid endpoints file {path}: {source}")]
+    InvalidEndpointsFile { path: String, source: EndpointListError },
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Unknown control command: {0}")]
+    UnknownCommand(String),
+
+    #[error("An annotation needs some text")]
+    EmptyAnnotation,
+
+    #[error("Control socket {} is in use by another instance", .0.display())]
+    SocketInUse(PathBuf),
+
+    #[cfg(not(unix))]
+    #[error("Control sockets are not supported on this platform")]
+    Unsupported,
+}
+
+/// A change requested at runtime
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlCommand {
+    /// Start monitoring these endpoints
+    Add(Vec<MulticastEndpoint>),
+    /// Stop monitoring these endpoints (after any active page finishes)
+    Remove(Vec<MulticastEndpoint>),
+    /// Re-parse the configured pattern/file and apply the difference
+    Reload,
+    /// Report the current endpoint table
+    Status,
+    /// Record an operator note
+    Annotate(Annotation),
+}
+
+/// A command together with the channel its reply should be sent on
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    reply: Option<oneshot::Sender<String>>,
+}
+
+impl ControlRequest {
+    /// Send the reply line back to the requester (if anyone is listening)
+    pub fn respond(self, reply: String) {
+        if let Some(tx) = self.reply {
+            tx.send(reply).ok();
+        }
+    }
+}
+
+/// One row of the endpoint table reported by `status`
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointStatus {
+    pub address: String,
+    pub port: u16,
+    pub page_active: bool,
+    pub packets_received: u64,
+    pub pending_removal: bool,
+    /// Stray bursts that never reached the page threshold
+    pub suppressed_bursts: u64,
+    /// Packets turned away by `--ssrc` or `--ignore-ssrc`
+    pub filtered_packets: u64,
+}
+
+/// Receiving side of the control mechanism, owned by the monitor loop
+pub struct ControlHandle {
+    rx: mpsc::UnboundedReceiver<ControlRequest>,
+    tx: mpsc::UnboundedSender<ControlRequest>,
+    socket_path: Option<PathBuf>,
+}
+
+impl ControlHandle {
+    /// Start listening for SIGHUP and, if a path is given, control socket connections
+    pub fn spawn(socket_path: Option<&Path>, default_port: u16) -> Result<Self, ControlError> {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        spawn_sighup_listener(tx.clone());
+
+        if let Some(path) = socket_path {
+            spawn_socket_listener(path, default_port, tx.clone())?;
+        }
+
+        Ok(Self {
+            rx,
+            tx,
+            socket_path: socket_path.map(Path::to_path_buf),
+        })
+    }
+
+    /// Get the next pending request without blocking
+    pub fn try_next(&mut self) -> Option<ControlRequest> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Take notes typed after pressing `a`, if stdin is a terminal; they
+    /// arrive as `annotate` requests. Listening stops when the guard drops
+    pub fn listen_keyboard(&self, paused: Arc<AtomicBool>) -> Option<Keyboard> {
+        let tx = self.tx.clone();
+        Keyboard::listen(paused, move |annotation| {
+            tx.send(ControlRequest {
+                command: ControlCommand::Annotate(annotation),
+                reply: None,
+            })
+            .ok();
+        })
+    }
+}
+
+impl Drop for ControlHandle {
+    fn drop(&mut self) {
+        if let Some(ref path) = self.socket_path {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
+
+/// Parse a single control command line
+pub fn parse_command(line: &str, default_port: u16) -> Result<ControlCommand, ControlError> {
+    // A note is free text, so it's the rest of the line rather than one word
+    let trimmed = line.trim();
+    let (verb, text) = trimmed.split_once(char::is_whitespace).unwrap_or((trimmed, ""));
+    if verb.eq_ignore_ascii_case("annotate") || verb.eq_ignore_ascii_case("note") {
+        if text.trim().is_empty() {
+            return Err(ControlError::EmptyAnnotation);
+        }
+        return Ok(ControlCommand::Annotate(Annotation::now(text, AnnotationSource::Control)));
+    }
+
+    let mut parts = line.split_whitespace();
+    let verb = parts.next().unwrap_or_default();
+    let arg = parts.next();
+
+    match (verb.to_lowercase().as_str(), arg) {
+        ("add", Some(pattern)) => Ok(ControlCommand::Add(parse_endpoints(pattern, default_port)?)),
+        ("remove", Some(pattern)) => Ok(ControlCommand::Remove(parse_endpoints(pattern, default_port)?)),
+        ("reload", None) => Ok(ControlCommand::Reload),
+        ("status", None) => Ok(ControlCommand::Status),
+        _ => Err(ControlError::UnknownCommand(line.trim().to_string())),
+    }
+}
+
+/// Parse an address pattern, appending the default port if none is given
+fn parse_endpoints(pattern: &str, default_port: u16) -> Result<Vec<MulticastEndpoint>, ControlError> {
+    let pattern = if pattern.contains(':') {
+        pattern.to_string()
+    } else {
+        format!("{}:{}", pattern, default_port)
+    };
+    Ok(parse_range(&pattern)?)
+}
+
+/// The configured endpoints, with any options given for them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResolvedEndpoints {
+    pub endpoints: Vec<MulticastEndpoint>,
+    /// Only endpoints that were given options appear here
+    pub overrides: HashMap<MulticastEndpoint, EndpointOptions>,
+}
+
+impl ResolvedEndpoints {
+    /// Options for `endpoint`; empty unless it was given some
+    pub fn options(&self, endpoint: &MulticastEndpoint) -> EndpointOptions {
+        self.overrides.get(endpoint).copied().unwrap_or_default()
+    }
+
+    fn push(&mut self, endpoints: Vec<MulticastEndpoint>, options: EndpointOptions, seen: &mut HashSet<MulticastEndpoint>) {
+        for ep in endpoints {
+            if seen.insert(ep) {
+                self.endpoints.push(ep);
+                if !options.is_empty() {
+                    self.overrides.insert(ep, options);
+                }
+            }
+        }
+    }
+}
+
+/// Resolve the configured endpoint source into a list of endpoints.
+///
+/// A pattern starting with `@` names a file with one pattern per line
+/// (blank lines and `#` comments are ignored); anything else is parsed as a
+/// range pattern directly. Either way a pattern may be followed by
+/// per-endpoint options such as `idle=10s min_packets=3`. An endpoint listed
+/// twice keeps the options of its first line.
+pub fn resolve_endpoints(pattern: &str, default_port: u16) -> Result<ResolvedEndpoints, ControlError> {
+    let mut resolved = ResolvedEndpoints::default();
+    let mut seen = HashSet::new();
+
+    let Some(path) = pattern.strip_prefix('@') else {
+        let (pattern, options) = parse_endpoint_spec(pattern)?;
+        resolved.push(parse_endpoints(pattern, default_port)?, options, &mut seen);
+        return Ok(resolved);
+    };
+
+    let invalid = |source| ControlError::InvalidEndpointsFile { path: path.to_string(), source };
+    let content = std::fs::read_to_string(path)?;
+    for (line, pattern, options) in parse_endpoint_list(&content).map_err(invalid)? {
+        let endpoints = match parse_endpoints(pattern, default_port) {
+            Ok(endpoints) => endpoints,
+            Err(ControlError::InvalidPattern(source)) => return Err(invalid(EndpointListError { line, source })),
+            Err(e) => return Err(e),
+        };
+        resolved.push(endpoints, options, &mut seen);
+    }
+    Ok(resolved)
+}
+
+#[cfg(unix)]
+fn spawn_sighup_listener(tx: mpsc::UnboundedSender<ControlRequest>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let Ok(mut hangup) = signal(SignalKind::hangup()) else {
+        warn!("Unable to install SIGHUP handler");
+        return;
+    };
+
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            debug!("SIGHUP received, reloading endpoints");
+            let request = ControlRequest {
+                command: ControlCommand::Reload,
+                reply: None,
+            };
+            if tx.send(request).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn spawn_sighup_listener(_tx: mpsc::UnboundedSender<ControlRequest>) {}
+
+#[cfg(unix)]
+fn spawn_socket_listener(
+    path: &Path,
+    default_port: u16,
+    tx: mpsc::UnboundedSender<ControlRequest>,
+) -> Result<(), ControlError> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixListener;
+
+    // Remove a stale socket left behind by a previous run, but not one
+    // another instance is still answering on
+    if path.exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(ControlError::SocketInUse(path.to_path_buf()));
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) if accept_error_is_fatal(&e) => {
+                    warn!("Control socket stopped accepting connections: {}", e);
+                    break;
+                }
+                Err(e) => {
+                    // Out of descriptors, say: give them a moment to come back
+                    warn!("Unable to accept a control connection: {}", e);
+                    tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+            let tx = tx.clone();
+
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    let reply = match parse_command(&line, default_port) {
+                        Ok(command) => {
+                            let (reply_tx, reply_rx) = oneshot::channel();
+                            let request = ControlRequest {
+                                command,
+                                reply: Some(reply_tx),
+                            };
+                            if tx.send(request).is_err() {
+                                break;
+                            }
+                            reply_rx.await.unwrap_or_else(|_| "error: monitor stopped".to_string())
+                        }
+                        Err(e) => format!("error: {}", e),
+                    };
+
+                    if writer.write_all(format!("{}\n", reply).as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+/// How long to wait before accepting again after a failed accept
+#[cfg(unix)]
+const ACCEPT_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Whether a failed accept means the listening socket itself is broken, so
+/// retrying can't help
+#[cfg(unix)]
+fn accept_error_is_fatal(error: &io::Error) -> bool {
+    matches!(
+        error.raw_os_error(),
+        Some(libc::EBADF | libc::EINVAL | libc::ENOTSOCK | libc::EOPNOTSUPP | libc::EFAULT)
+    )
+}
+
+#[cfg(not(unix))]
+fn spawn_socket_listener(
+    _path: &Path,
+    _default_port: u16,
+    _tx: mpsc::UnboundedSender<ControlRequest>,
+) -> Result<(), ControlError> {
+    Err(ControlError::Unsupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_parse_command() {
+        let cmd = parse_command("add 224.0.5.1:5004", 5004).unwrap();
+        assert_eq!(
+            cmd,
+            ControlCommand::Add(vec![MulticastEndpoint { address: Ipv4Addr::new(224, 0, 5, 1), port: 5004 }])
+        );
+
+        // Default port and ranges
+        let ControlCommand::Remove(eps) = parse_command("remove 224.0.{1-3}.1", 6000).unwrap() else {
+            panic!("expected remove");
+        };
+        assert_eq!(eps.len(), 3);
+        assert!(eps.iter().all(|ep| ep.port == 6000));
+
+        assert_eq!(parse_command("status", 5004).unwrap(), ControlCommand::Status);
+        assert_eq!(parse_command("RELOAD", 5004).unwrap(), ControlCommand::Reload);
+        assert!(parse_command("add", 5004).is_err());
+        assert!(parse_command("frobnicate 1", 5004).is_err());
+
+        // Notes keep the rest of the line
+        let ControlCommand::Annotate(note) = parse_command("annotate  unplugged the  uplink ", 5004).unwrap() else {
+            panic!("expected annotate");
+        };
+        assert_eq!(note.text, "unplugged the  uplink");
+        assert_eq!(note.source, AnnotationSource::Control);
+        assert!(matches!(parse_command("Note x", 5004).unwrap(), ControlCommand::Annotate(n) if n.text == "x"));
+        assert!(matches!(parse_command("annotate", 5004), Err(ControlError::EmptyAnnotation)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_socket_in_use_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+
+        // A socket file nobody is listening on is left over, and replaced
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let (tx, _rx) = mpsc::unbounded_channel();
+        spawn_socket_listener(&path, 5004, tx.clone()).unwrap();
+
+        // One another instance is answering on is kept
+        let err = spawn_socket_listener(&path, 5004, tx).unwrap_err();
+        assert!(matches!(err, ControlError::SocketInUse(ref p) if *p == path), "{}", err);
+        assert!(path.exists());
+        assert!(tokio::net::UnixStream::connect(&path).await.is_ok());
+
+        assert!(accept_error_is_fatal(&io::Error::from_raw_os_error(libc::EBADF)));
+        assert!(!accept_error_is_fatal(&io::Error::from_raw_os_error(libc::EMFILE)));
+    }
+
+    #[test]
+    fn test_resolve_endpoints_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("endpoints.txt");
+        std::fs::write(&path, "# zones\n224.0.1.1:5004\n\n224.0.1.{1-2}\n").unwrap();
+
+        let eps = resolve_endpoints(&format!("@{}", path.display()), 5004).unwrap().endpoints;
+        assert_eq!(eps.len(), 2, "duplicates should be collapsed");
+
+        let eps = resolve_endpoints("224.0.1.1", 5006).unwrap().endpoints;
+        assert_eq!(eps, vec![MulticastEndpoint { address: Ipv4Addr::new(224, 0, 1, 1), port: 5006 }]);
+    }
+
+    #[test]
+    fn test_resolve_endpoint_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("endpoints.txt");
+        std::fs::write(&path, "224.0.1.1:5004 idle=10s\n224.0.1.{1-2} min_packets=3\n").unwrap();
+
+        let resolved = resolve_endpoints(&format!("@{}", path.display()), 5004).unwrap();
+        let [first, second] = resolved.endpoints[..] else {
+            panic!("expected two endpoints, got {:?}", resolved.endpoints);
+        };
+        assert_eq!(resolved.options(&first).to_string(), "idle=10s");
+        assert_eq!(resolved.options(&second).to_string(), "min_packets=3");
+
+        let resolved = resolve_endpoints("224.0.1.{1-2}:5004 idle=500ms", 5004).unwrap();
+        assert!(resolved.endpoints.iter().all(|ep| resolved.options(ep).idle_ms == Some(500)));
+
+        std::fs::write(&path, "224.0.1.1:5004\n\n224.0.1.300:5004\n").unwrap();
+        let err = resolve_endpoints(&format!("@{}", path.display()), 5004).unwrap_err();
+        assert!(err.to_string().ends_with("line 3: IP octet must be between 0 and 255, got 300"), "{}", err);
+    }
+}
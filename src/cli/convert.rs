@@ -0,0 +1,471 @@
+//! Offline conversion of audio files and test recordings
+//!
+//! `convert` reads a file (anything `transmit` accepts) or every recording in
+//! a test output directory, resamples it with a band-limited filter,
+//! optionally passes it through a codec and back to hear what a phone would,
+//! and writes WAV or headerless G.711. Directories get a `conversions.json`
+//! manifest listing what was written.
+
+use crate::cli::audio_input::{read_audio, resample, AudioInput, AudioInputError, DEFAULT_RAW_RATE};
+use crate::cli::test::TestSummary;
+use crate::codec::{create_decoder, create_encoder, encode_all, CodecError, CodecType};
+use serde::Serialize;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Manifest written into the output directory when converting a test directory
+pub const MANIFEST_FILE: &str = "conversions.json";
+
+/// How far either way the round trip's delay is searched for when measuring SNR
+const MAX_ROUNDTRIP_DELAY_MS: u32 = 20;
+
+#[derive(Error, Debug)]
+pub enum ConvertError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("WAV error: {0}")]
+    Wav(#[from] hound::Error),
+
+    #[error("Audio input error: {0}")]
+    Input(#[from] AudioInputError),
+
+    #[error("Codec error: {0}")]
+    Codec(#[from] CodecError),
+
+    #[error("Input not found: {0}")]
+    InputNotFound(PathBuf),
+
+    #[error("{0} has no summary.json; give a single file or a test output directory")]
+    SummaryNotFound(PathBuf),
+
+    #[error("{} is {} Hz, but {} output is always {} Hz", .output.display(), .rate, .format, DEFAULT_RAW_RATE)]
+    RawRate { output: PathBuf, format: OutputFormat, rate: u32 },
+
+    #[error("{0} of {1} recordings failed to convert")]
+    Failed(usize, usize),
+}
+
+/// What `convert` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// 16-bit mono WAV
+    Wav,
+    /// Headerless G.711 u-law
+    UlawRaw,
+    /// Headerless G.711 A-law
+    AlawRaw,
+}
+
+impl OutputFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Wav => "wav",
+            Self::UlawRaw => "ulaw-raw",
+            Self::AlawRaw => "alaw-raw",
+        }
+    }
+
+    /// File extension used for recordings converted from a directory
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Wav => "wav",
+            Self::UlawRaw => "ulaw",
+            Self::AlawRaw => "alaw",
+        }
+    }
+
+    /// The G.711 codec headerless output is written with
+    fn raw_codec(self) -> Option<CodecType> {
+        match self {
+            Self::Wav => None,
+            Self::UlawRaw => Some(CodecType::G711Ulaw),
+            Self::AlawRaw => Some(CodecType::G711Alaw),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "wav" => Ok(Self::Wav),
+            "ulaw-raw" | "ulaw" => Ok(Self::UlawRaw),
+            "alaw-raw" | "alaw" => Ok(Self::AlawRaw),
+            _ => Err(format!("unknown output format '{}' (expected wav, ulaw-raw or alaw-raw)", s)),
+        }
+    }
+}
+
+pub struct ConvertOptions {
+    /// An audio file, or a test output directory
+    pub input: PathBuf,
+    /// The output file, or a directory for a directory input
+    pub output: PathBuf,
+    pub audio_input: AudioInput,
+    /// Output sample rate; the input's own (or the round-trip codec's) if not given
+    pub rate: Option<u32>,
+    pub format: OutputFormat,
+    /// Encode and decode through this codec before writing
+    pub codec_roundtrip: Option<CodecType>,
+    pub quiet: bool,
+}
+
+/// One converted file, as listed in the manifest
+#[derive(Debug, Clone, Serialize)]
+pub struct Conversion {
+    pub source: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_number: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    pub output: PathBuf,
+    pub source_rate: u32,
+    pub rate: u32,
+    pub samples: usize,
+    pub duration_secs: f64,
+    /// Codec the audio was round-tripped through
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roundtrip: Option<&'static str>,
+    /// Signal to round-trip noise ratio, in dB
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snr_db: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// `conversions.json`
+#[derive(Debug, Serialize)]
+pub struct ConversionManifest {
+    pub source_directory: PathBuf,
+    pub format: OutputFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codec_roundtrip: Option<&'static str>,
+    pub conversions: Vec<Conversion>,
+}
+
+/// Run the convert command
+pub fn run_convert(options: ConvertOptions) -> Result<(), ConvertError> {
+    if !options.input.exists() {
+        return Err(ConvertError::InputNotFound(options.input.clone()));
+    }
+    if options.input.is_dir() {
+        return convert_directory(&options);
+    }
+
+    let conversion = convert_file(&options, &options.input, &options.output)?;
+    if !options.quiet {
+        print_conversion(&conversion);
+    }
+    Ok(())
+}
+
+/// Convert every recording referenced by a test directory's summary.json
+fn convert_directory(options: &ConvertOptions) -> Result<(), ConvertError> {
+    let summary_path = options.input.join("summary.json");
+    if !summary_path.exists() {
+        return Err(ConvertError::SummaryNotFound(options.input.clone()));
+    }
+    let summary: TestSummary = serde_json::from_reader(File::open(&summary_path)?)?;
+    fs::create_dir_all(&options.output)?;
+
+    let mut conversions = Vec::with_capacity(summary.pages.len());
+    for page in &summary.pages {
+        let source = options.input.join(&page.recording_file);
+        // Endpoints' subdirectories are kept, since names may only be unique within one
+        let output = options.output.join(Path::new(&page.recording_file).with_extension(options.format.extension()));
+        if let Some(dir) = output.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let conversion = match convert_file(options, &source, &output) {
+            Ok(conversion) => conversion,
+            Err(e) => Conversion {
+                source: source.clone(),
+                page_number: None,
+                endpoint: None,
+                output,
+                source_rate: 0,
+                rate: 0,
+                samples: 0,
+                duration_secs: 0.0,
+                roundtrip: options.codec_roundtrip.as_ref().map(CodecType::name),
+                snr_db: None,
+                error: Some(e.to_string()),
+            },
+        };
+        let conversion = Conversion {
+            page_number: Some(page.page_number),
+            endpoint: Some(page.endpoint.clone()),
+            ..conversion
+        };
+        if !options.quiet {
+            print_conversion(&conversion);
+        }
+        conversions.push(conversion);
+    }
+
+    let failed = conversions.iter().filter(|c| c.error.is_some()).count();
+    let total = conversions.len();
+    let manifest = ConversionManifest {
+        source_directory: options.input.clone(),
+        format: options.format,
+        codec_roundtrip: options.codec_roundtrip.as_ref().map(CodecType::name),
+        conversions,
+    };
+    let manifest_path = options.output.join(MANIFEST_FILE);
+    serde_json::to_writer_pretty(File::create(&manifest_path)?, &manifest)?;
+    if !options.quiet {
+        println!("Converted {} of {} recordings; manifest written to {}", total - failed, total, manifest_path.display());
+    }
+
+    if failed > 0 {
+        return Err(ConvertError::Failed(failed, total));
+    }
+    Ok(())
+}
+
+fn convert_file(options: &ConvertOptions, source: &Path, output: &Path) -> Result<Conversion, ConvertError> {
+    let (samples, source_rate) = read_audio(source, options.audio_input.sample_rate, &options.audio_input)?;
+
+    let (samples, rate, snr_db) = match options.codec_roundtrip {
+        Some(codec) => {
+            let (decoded, codec_rate, snr_db) = roundtrip(codec, &samples, source_rate)?;
+            (decoded, codec_rate, Some(snr_db))
+        }
+        None => (samples, source_rate, None),
+    };
+
+    let target_rate = options.rate.unwrap_or(if options.format == OutputFormat::Wav { rate } else { DEFAULT_RAW_RATE });
+    if options.format != OutputFormat::Wav && target_rate != DEFAULT_RAW_RATE {
+        return Err(ConvertError::RawRate {
+            output: output.to_path_buf(),
+            format: options.format,
+            rate: target_rate,
+        });
+    }
+    let samples = resample(&samples, rate, target_rate);
+
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    write_output(output, options.format, &samples, target_rate)?;
+
+    Ok(Conversion {
+        source: source.to_path_buf(),
+        page_number: None,
+        endpoint: None,
+        output: output.to_path_buf(),
+        source_rate,
+        rate: target_rate,
+        samples: samples.len(),
+        duration_secs: samples.len() as f64 / f64::from(target_rate),
+        roundtrip: options.codec_roundtrip.as_ref().map(CodecType::name),
+        snr_db,
+        error: None,
+    })
+}
+
+/// Pass audio through a codec's encoder and decoder. Returns the decoded
+/// audio at the codec's rate, that rate, and the SNR against the input
+pub fn roundtrip(codec: CodecType, samples: &[i16], rate: u32) -> Result<(Vec<i16>, u32, f64), ConvertError> {
+    let codec_rate = create_encoder(codec)?.sample_rate();
+    let original = resample(samples, rate, codec_rate);
+
+    let mut decoder = create_decoder(codec)?;
+    let channels = usize::from(decoder.channels().max(1));
+    let mut decoded = Vec::with_capacity(original.len() * channels);
+    for frame in encode_all(codec, &original)? {
+        decoded.extend(decoder.decode(&frame)?);
+    }
+    decoded.extend(decoder.flush()?);
+
+    let mut decoded = downmix(decoded, channels);
+    decoded.resize(original.len(), 0);
+
+    let max_lag = (codec_rate * MAX_ROUNDTRIP_DELAY_MS / 1000) as usize;
+    let snr_db = snr_db(&original, &decoded, max_lag);
+    Ok((decoded, codec_rate, snr_db))
+}
+
+/// Interleaved decoder output as mono. A stereo decoder (Opus) gives
+/// interleaved copies of the mono input
+pub fn downmix(decoded: Vec<i16>, channels: usize) -> Vec<i16> {
+    if channels <= 1 {
+        return decoded;
+    }
+    decoded
+        .chunks(channels)
+        .map(|frame| (frame.iter().map(|&s| i32::from(s)).sum::<i32>() / channels as i32) as i16)
+        .collect()
+}
+
+/// Signal to noise ratio of `processed` against `original`, at whichever
+/// delay up to `max_lag` samples lines them up best
+pub fn snr_db(original: &[i16], processed: &[i16], max_lag: usize) -> f64 {
+    align(original, processed, max_lag).1
+}
+
+/// The delay, up to `max_lag` samples, at which `processed` best matches
+/// `original`, and the signal to noise ratio there
+pub fn align(original: &[i16], processed: &[i16], max_lag: usize) -> (usize, f64) {
+    (0..=max_lag.min(processed.len()))
+        .map(|lag| {
+            let pairs = original.iter().zip(&processed[lag..]);
+            let (signal, noise) = pairs.fold((0.0, 0.0), |(signal, noise), (&a, &b)| {
+                let a = f64::from(a);
+                (signal + a * a, noise + (a - f64::from(b)).powi(2))
+            });
+            let snr = if noise == 0.0 {
+                f64::INFINITY
+            } else if signal == 0.0 {
+                f64::NEG_INFINITY
+            } else {
+                10.0 * (signal / noise).log10()
+            };
+            (lag, snr)
+        })
+        .fold((0, f64::NEG_INFINITY), |best, next| if next.1 > best.1 { next } else { best })
+}
+
+fn write_output(path: &Path, format: OutputFormat, samples: &[i16], rate: u32) -> Result<(), ConvertError> {
+    if let Some(codec) = format.raw_codec() {
+        fs::write(path, create_encoder(codec)?.encode(samples)?)?;
+        return Ok(());
+    }
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for &sample in samples {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+fn print_conversion(conversion: &Conversion) {
+    if let Some(error) = &conversion.error {
+        println!("{}: FAILED: {}", conversion.source.display(), error);
+        return;
+    }
+    println!(
+        "{} ({} Hz) -> {} ({} Hz, {:.2}s)",
+        conversion.source.display(),
+        conversion.source_rate,
+        conversion.output.display(),
+        conversion.rate,
+        conversion.duration_secs
+    );
+    if let (Some(codec), Some(snr_db)) = (conversion.roundtrip, conversion.snr_db) {
+        println!("  Round trip through {}: SNR {:.1} dB", codec, snr_db);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::audio_analyzer::{analyze_samples, AnalysisProfile};
+    use tempfile::TempDir;
+
+    fn tone(frequency: f64, rate: u32, secs: f64) -> Vec<i16> {
+        (0..(f64::from(rate) * secs) as usize)
+            .map(|i| (8000.0 * (2.0 * std::f64::consts::PI * frequency * i as f64 / f64::from(rate)).sin()) as i16)
+            .collect()
+    }
+
+    fn write_wav(path: &Path, samples: &[i16], rate: u32) {
+        write_output(path, OutputFormat::Wav, samples, rate).unwrap();
+    }
+
+    fn options(input: PathBuf, output: PathBuf) -> ConvertOptions {
+        ConvertOptions {
+            input,
+            output,
+            audio_input: AudioInput::default(),
+            rate: Some(8000),
+            format: OutputFormat::Wav,
+            codec_roundtrip: None,
+            quiet: true,
+        }
+    }
+
+    #[test]
+    fn test_downsample_keeps_frequency() {
+        let dir = TempDir::new().unwrap();
+        let input = dir.path().join("tone.wav");
+        let output = dir.path().join("out.wav");
+        write_wav(&input, &tone(1000.0, 16000, 1.0), 16000);
+
+        run_convert(options(input, output.clone())).unwrap();
+
+        let mut reader = hound::WavReader::open(&output).unwrap();
+        assert_eq!(reader.spec().sample_rate, 8000);
+        let samples: Vec<i16> = reader.samples::<i16>().map(Result::unwrap).collect();
+        assert_eq!(samples.len(), 8000);
+        let frequency = analyze_samples(&samples, 8000, 1, AnalysisProfile::Speech).dominant_freq_hz;
+        assert!((frequency - 1000.0).abs() < 50.0, "{}", frequency);
+    }
+
+    #[test]
+    fn test_g711_roundtrip_noise_floor() {
+        let input = tone(440.0, 8000, 1.0);
+
+        // 8-bit companding leaves roughly 35-40 dB of SNR on a tone this loud
+        for codec in [CodecType::G711Ulaw, CodecType::G711Alaw] {
+            let (decoded, rate, snr) = roundtrip(codec, &input, 8000).unwrap();
+            assert_eq!(rate, 8000);
+            assert_eq!(decoded.len(), input.len());
+            assert!((30.0..45.0).contains(&snr), "{}: {}", codec.name(), snr);
+        }
+
+        // Lossless L16 reproduces it exactly
+        let (_, _, snr) = roundtrip(CodecType::L16, &input, 8000).unwrap();
+        assert!(snr.is_infinite());
+    }
+
+    #[test]
+    fn test_raw_output() {
+        let dir = TempDir::new().unwrap();
+        let input = dir.path().join("tone.wav");
+        write_wav(&input, &tone(440.0, 16000, 0.5), 16000);
+
+        let output = dir.path().join("out.ulaw");
+        let mut convert = options(input.clone(), output.clone());
+        convert.rate = None;
+        convert.format = OutputFormat::UlawRaw;
+        run_convert(convert).unwrap();
+        assert_eq!(fs::read(&output).unwrap().len(), 4000, "one byte per 8 kHz sample");
+
+        let mut convert = options(input, dir.path().join("out.alaw"));
+        convert.rate = Some(16000);
+        convert.format = OutputFormat::AlawRaw;
+        assert!(matches!(run_convert(convert), Err(ConvertError::RawRate { rate: 16000, .. })));
+    }
+
+    #[test]
+    fn test_output_format_parse() {
+        assert_eq!("wav".parse::<OutputFormat>(), Ok(OutputFormat::Wav));
+        assert_eq!("ULAW-RAW".parse::<OutputFormat>(), Ok(OutputFormat::UlawRaw));
+        assert_eq!("alaw".parse::<OutputFormat>(), Ok(OutputFormat::AlawRaw));
+        assert!("mp3".parse::<OutputFormat>().is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//! What becomes of a packet whose audio is damaged.
+//!
+//! A payload the decoder rejects, or one cut short in transit, would
+//! otherwise just be missing from the recording: the recording comes out
+//! shorter than the page and nothing says why. `--on-decode-error` on
+//! `monitor` and `test` chooses what happens instead. Whatever the policy,
+//! the damage is counted in the page's `decode_errors` and
+//! `truncated_payloads`.
+
+use crate::codec::AudioDecoder;
+use std::fmt;
+use std::str::FromStr;
+use tracing::debug;
+
+/// `--on-decode-error`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecodeErrorPolicy {
+    /// Leave the audio out, shortening the recording
+    Skip,
+    /// Record the packet's nominal length of concealment (silence, or Opus
+    /// packet loss concealment) so the recording stays on the stream's timeline
+    #[default]
+    Silence,
+    /// End the page, noting the error in its summary
+    AbortPage,
+}
+
+impl DecodeErrorPolicy {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Skip => "skip",
+            Self::Silence => "silence",
+            Self::AbortPage => "abort-page",
+        }
+    }
+
+    /// What to record in place of `secs` of audio lost to `problem`: the
+    /// samples to fill with (none for `skip`), or for `abort-page` the note
+    /// to end the page with
+    pub fn repair(self, decoder: &mut dyn AudioDecoder, secs: f64, problem: impl FnOnce() -> String) -> Result<Vec<i16>, String> {
+        match self {
+            Self::Skip => Ok(Vec::new()),
+            Self::AbortPage => Err(problem()),
+            Self::Silence => {
+                let frames = (secs * f64::from(decoder.sample_rate())).round() as usize;
+                Ok(decoder.conceal(frames).unwrap_or_else(|e| {
+                    debug!("Concealment failed, recording silence: {}", e);
+                    vec![0; frames * usize::from(decoder.channels())]
+                }))
+            }
+        }
+    }
+}
+
+impl fmt::Display for DecodeErrorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for DecodeErrorPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "skip" => Ok(Self::Skip),
+            "silence" => Ok(Self::Silence),
+            "abort-page" | "abort" => Ok(Self::AbortPage),
+            _ => Err(format!(
+                "unknown decode error policy {:?}: expected skip, silence or abort-page",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{create_decoder, CodecType};
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!("silence".parse(), Ok(DecodeErrorPolicy::Silence));
+        assert_eq!("Abort-Page".parse(), Ok(DecodeErrorPolicy::AbortPage));
+        assert_eq!("skip".parse::<DecodeErrorPolicy>().unwrap().to_string(), "skip");
+        assert!("drop".parse::<DecodeErrorPolicy>().unwrap_err().contains("expected skip"));
+    }
+
+    #[test]
+    fn test_repair_by_policy() {
+        let mut decoder = create_decoder(CodecType::G711Ulaw).unwrap();
+        let problem = || "decode error".to_string();
+        assert_eq!(DecodeErrorPolicy::Silence.repair(decoder.as_mut(), 0.02, problem), Ok(vec![0; 160]));
+        assert_eq!(DecodeErrorPolicy::Skip.repair(decoder.as_mut(), 0.02, problem), Ok(Vec::new()));
+        assert_eq!(
+            DecodeErrorPolicy::AbortPage.repair(decoder.as_mut(), 0.02, problem),
+            Err("decode error".to_string())
+        );
+    }
+}
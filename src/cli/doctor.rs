@@ -0,0 +1,711 @@
+//! Environment checks (`doctor`).
+//!
+//! Most trouble reports come down to the host rather than the tool: no
+//! ffmpeg for G.722, a firewall or strict reverse-path filtering dropping
+//! multicast, an output directory that can't be written, too few file
+//! descriptors for the endpoints planned, or a clock nobody synchronizes.
+//! Each of these is a [`Check`], which reports a [`Finding`]: pass, warn or
+//! fail, what it found and what to do about it. [`run_checks`] runs a list
+//! of them, so another command can run a list of its own the same way.
+//!
+//! Checks that read another tool's output (`timedatectl`, `chronyc`,
+//! `firewall-cmd`, `ufw`) run it through a [`Runner`], and the ones that
+//! read files take their paths, so tests can stand in for both.
+
+use crate::cli::fd_limit::{self, FdLimit};
+use crate::codec::subprocess::FfmpegSupport;
+use crate::codec::{ffmpeg_support, FFMPEG_ENV};
+use crate::network::multicast::join_group;
+use crate::network::MulticastInterface;
+use serde::Serialize;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Group joined by the multicast checks. Administratively scoped, and the
+/// loopback datagram is sent with TTL 0, so nothing leaves the host
+pub const SCRATCH_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 77, 77);
+
+/// How long the loopback check waits for its own datagram
+const LOOPBACK_WAIT: Duration = Duration::from_secs(1);
+
+/// File descriptors a run holds besides its endpoints' (standard streams,
+/// logs, metrics files, the runtime's own)
+const BASE_FDS: u64 = 64;
+
+/// File descriptors per endpoint: its socket, recording, timing file and
+/// spectrum file
+const FDS_PER_ENDPOINT: u64 = 4;
+
+/// Present while systemd-timesyncd has the clock synchronized
+const TIMESYNC_MARKER: &str = "/run/systemd/timesync/synchronized";
+
+/// Per-interface IPv4 settings
+const IPV4_CONF: &str = "/proc/sys/net/ipv4/conf";
+
+#[derive(Error, Debug)]
+pub enum DoctorError {
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("{failed} of {total} checks failed")]
+    Failed { failed: usize, total: usize },
+}
+
+/// How a check came out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass,
+    /// Worth fixing, but runs can go ahead
+    Warn,
+    /// Runs will fail or miss pages until it's fixed
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pass => write!(f, "PASS"),
+            Self::Warn => write!(f, "WARN"),
+            Self::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+/// What a check found
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub status: Status,
+    pub detail: String,
+    /// What to do about a warning or failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Finding {
+    pub fn pass(detail: impl Into<String>) -> Self {
+        Self { status: Status::Pass, detail: detail.into(), hint: None }
+    }
+
+    pub fn warn(detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { status: Status::Warn, detail: detail.into(), hint: Some(hint.into()) }
+    }
+
+    pub fn fail(detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { status: Status::Fail, detail: detail.into(), hint: Some(hint.into()) }
+    }
+}
+
+/// One thing about the host that can stop a run working
+pub trait Check {
+    /// Short name, shown in the table
+    fn name(&self) -> &'static str;
+
+    fn run(&self) -> Finding;
+}
+
+/// A check's name with what it found
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    #[serde(flatten)]
+    pub finding: Finding,
+}
+
+/// Run each check in turn
+pub fn run_checks(checks: &[Box<dyn Check>]) -> Vec<CheckResult> {
+    checks
+        .iter()
+        .map(|check| CheckResult { name: check.name(), finding: check.run() })
+        .collect()
+}
+
+/// Runs a program with arguments, returning what it printed if it succeeded
+pub type Runner = Box<dyn Fn(&str, &[&str]) -> Option<String>>;
+
+/// Runs the real program
+pub fn system_runner() -> Runner {
+    Box::new(|program: &str, args: &[&str]| {
+        let output = Command::new(program).args(args).stdin(Stdio::null()).stderr(Stdio::null()).output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    })
+}
+
+/// Whether ffmpeg can decode and encode G.722, and the G.711 codecs it can
+/// stand in for
+pub struct FfmpegCheck {
+    pub support: FfmpegSupport,
+}
+
+impl Check for FfmpegCheck {
+    fn name(&self) -> &'static str {
+        "ffmpeg"
+    }
+
+    fn run(&self) -> Finding {
+        if !self.support.available {
+            return Finding::warn(
+                "ffmpeg not found; G.722 can't be sent or received",
+                format!("Install ffmpeg (apt install ffmpeg, winget install ffmpeg), or point {} at it", FFMPEG_ENV),
+            );
+        }
+        let describe = |name: &str, decode: bool, encode: bool| {
+            let can = match (decode, encode) {
+                (true, true) => "decode and encode",
+                (true, false) => "decode only",
+                (false, true) => "encode only",
+                (false, false) => "missing",
+            };
+            format!("{} {}", name, can)
+        };
+        let (g722, mulaw, alaw) = (self.support.g722, self.support.pcm_mulaw, self.support.pcm_alaw);
+        let detail = [
+            describe("g722", g722.decode, g722.encode),
+            describe("pcm_mulaw", mulaw.decode, mulaw.encode),
+            describe("pcm_alaw", alaw.decode, alaw.encode),
+        ]
+        .join(", ");
+        if g722.decode && g722.encode {
+            Finding::pass(detail)
+        } else {
+            Finding::warn(detail, format!("Install a full ffmpeg build, or point {} at one", FFMPEG_ENV))
+        }
+    }
+}
+
+/// A UDP socket on an ephemeral port, joined to [`SCRATCH_GROUP`]
+fn scratch_socket(interface: MulticastInterface) -> io::Result<UdpSocket> {
+    let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))?;
+    join_group(&socket, SCRATCH_GROUP, interface)?;
+    Ok(socket)
+}
+
+fn interface_name(interface: MulticastInterface) -> String {
+    if interface == MulticastInterface::default() {
+        "the default interface".to_string()
+    } else {
+        interface.to_string()
+    }
+}
+
+/// Whether a group can be joined at all
+pub struct MulticastJoinCheck {
+    pub interface: MulticastInterface,
+}
+
+impl Check for MulticastJoinCheck {
+    fn name(&self) -> &'static str {
+        "Multicast join"
+    }
+
+    fn run(&self) -> Finding {
+        match scratch_socket(self.interface) {
+            Ok(_) => Finding::pass(format!("Joined {} on {}", SCRATCH_GROUP, interface_name(self.interface))),
+            Err(e) => Finding::fail(
+                format!("Can't join {} on {}: {}", SCRATCH_GROUP, interface_name(self.interface), e),
+                "Check that --interface names an interface that is up with an IPv4 address, \
+                 and that there is a multicast route (ip route show 224.0.0.0/4)",
+            ),
+        }
+    }
+}
+
+/// Whether a datagram sent to a joined group comes back. A firewall that
+/// drops inbound multicast stops it, as it would a page
+pub struct LoopbackCheck {
+    pub interface: MulticastInterface,
+}
+
+impl LoopbackCheck {
+    /// The port the datagram came back on, or what went wrong
+    fn round_trip(&self) -> io::Result<Option<u16>> {
+        let receiver = scratch_socket(self.interface)?;
+        receiver.set_multicast_loop_v4(true)?;
+        let port = receiver.local_addr()?.port();
+
+        let sender = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))?;
+        sender.set_multicast_loop_v4(true)?;
+        sender.set_multicast_ttl_v4(0)?;
+        if let MulticastInterface::Address(address) = self.interface {
+            if !address.is_unspecified() {
+                socket2::SockRef::from(&sender).set_multicast_if_v4(&address)?;
+            }
+        }
+        let token = format!("mpu-doctor {}", std::process::id());
+        sender.send_to(token.as_bytes(), SocketAddrV4::new(SCRATCH_GROUP, port))?;
+
+        let deadline = Instant::now() + LOOPBACK_WAIT;
+        let mut buf = [0u8; 64];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            receiver.set_read_timeout(Some(remaining))?;
+            match receiver.recv(&mut buf) {
+                Ok(len) if &buf[..len] == token.as_bytes() => return Ok(Some(port)),
+                Ok(_) => {}
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Check for LoopbackCheck {
+    fn name(&self) -> &'static str {
+        "Loopback receive"
+    }
+
+    fn run(&self) -> Finding {
+        match self.round_trip() {
+            Ok(Some(port)) => Finding::pass(format!("Received our own datagram on {}:{}", SCRATCH_GROUP, port)),
+            Ok(None) => Finding::fail(
+                format!("Our own datagram to {} never arrived", SCRATCH_GROUP),
+                "A firewall is probably dropping inbound multicast; allow UDP to 224.0.0.0/4 \
+                 (firewall-cmd --permanent --add-rich-rule='rule family=ipv4 destination address=224.0.0.0/4 accept')",
+            ),
+            Err(e) => Finding::fail(
+                format!("Couldn't send to {} and receive it back: {}", SCRATCH_GROUP, e),
+                "Check that multicast works on the interface (see the multicast join check)",
+            ),
+        }
+    }
+}
+
+/// Whether recordings can be written where a run will put them
+pub struct OutputDirCheck {
+    pub dir: PathBuf,
+}
+
+impl Check for OutputDirCheck {
+    fn name(&self) -> &'static str {
+        "Output directory"
+    }
+
+    fn run(&self) -> Finding {
+        let hint = "Choose another --output, or give the user running the tool write access to it";
+        if self.dir.exists() && !self.dir.is_dir() {
+            return Finding::fail(format!("{} is not a directory", self.dir.display()), hint);
+        }
+        // Runs create the directory, so a missing one needs a writable parent
+        let Some(existing) = self.dir.ancestors().find(|dir| dir.as_os_str().is_empty() || dir.is_dir()) else {
+            return Finding::fail(format!("No part of {} exists", self.dir.display()), hint);
+        };
+        let existing = if existing.as_os_str().is_empty() { Path::new(".") } else { existing };
+        if let Err(e) = probe_writable(existing) {
+            return Finding::fail(format!("Can't write to {}: {}", existing.display(), e), hint);
+        }
+        if existing == self.dir {
+            Finding::pass(format!("{} is writable", self.dir.display()))
+        } else {
+            Finding::pass(format!("{} will be created in {}, which is writable", self.dir.display(), existing.display()))
+        }
+    }
+}
+
+/// Create and remove a file in `dir`
+fn probe_writable(dir: &Path) -> io::Result<()> {
+    let probe = dir.join(format!(".mpu-doctor-{}", std::process::id()));
+    OpenOptions::new().write(true).create_new(true).open(&probe)?;
+    fs::remove_file(probe)
+}
+
+/// Whether the open file limit covers the endpoints a run will monitor
+pub struct FileLimitCheck {
+    pub endpoints: usize,
+    /// `None` where there's no per-process limit
+    pub limits: Option<FdLimit>,
+}
+
+impl FileLimitCheck {
+    /// File descriptors a run on this many endpoints needs
+    pub fn needed(endpoints: usize) -> u64 {
+        BASE_FDS + FDS_PER_ENDPOINT * endpoints as u64
+    }
+}
+
+impl Check for FileLimitCheck {
+    fn name(&self) -> &'static str {
+        "Open file limit"
+    }
+
+    fn run(&self) -> Finding {
+        let Some(FdLimit { soft, hard }) = self.limits else {
+            return Finding::pass("No per-process open file limit on this platform");
+        };
+        let needed = Self::needed(self.endpoints);
+        let detail = format!("{} open files allowed; {} endpoints need about {}", soft, self.endpoints, needed);
+        if soft >= needed {
+            Finding::pass(detail)
+        } else if hard >= needed {
+            Finding::warn(detail, format!("Raise it with `ulimit -n {}` before the run (the hard limit is {})", needed, hard))
+        } else {
+            Finding::fail(
+                format!("{}, and the hard limit is {}", detail, hard),
+                "Raise the hard limit: LimitNOFILE= in the service's unit, or nofile in /etc/security/limits.conf",
+            )
+        }
+    }
+}
+
+/// Whether something keeps the wall clock, which page times come from, in step
+pub struct ClockSyncCheck {
+    /// See [`TIMESYNC_MARKER`]
+    pub marker: PathBuf,
+    pub runner: Runner,
+}
+
+/// `timedatectl show --property=NTPSynchronized --value`: `yes` or `no`
+fn parse_timedatectl(output: &str) -> Option<bool> {
+    match output.trim() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// `chronyc tracking`: its `Leap status` line reads `Not synchronised` until
+/// chrony has a source
+fn parse_chronyc(output: &str) -> Option<bool> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("Leap status"))
+        .and_then(|rest| rest.split_once(':'))
+        .map(|(_, status)| status.trim() != "Not synchronised")
+}
+
+impl Check for ClockSyncCheck {
+    fn name(&self) -> &'static str {
+        "Clock sync"
+    }
+
+    fn run(&self) -> Finding {
+        let hint = "Page start and end times come from the wall clock; run NTP (chrony or systemd-timesyncd)";
+        if self.marker.exists() {
+            return Finding::pass("systemd-timesyncd has the clock synchronized");
+        }
+        let timedatectl = (self.runner)("timedatectl", &["show", "--property=NTPSynchronized", "--value"]);
+        if let Some(synced) = timedatectl.as_deref().and_then(parse_timedatectl) {
+            return if synced {
+                Finding::pass("timedatectl reports the clock synchronized")
+            } else {
+                Finding::warn("timedatectl reports the clock isn't synchronized", hint)
+            };
+        }
+        let chronyc = (self.runner)("chronyc", &["tracking"]);
+        match chronyc.as_deref().and_then(parse_chronyc) {
+            Some(true) => Finding::pass("chrony has the clock synchronized"),
+            Some(false) => Finding::warn("chrony isn't synchronized to a source", hint),
+            None => Finding::warn("Couldn't tell whether the clock is synchronized (no timedatectl or chronyc)", hint),
+        }
+    }
+}
+
+/// Whether a host firewall is running that may drop multicast. The loopback
+/// check shows whether it actually does
+pub struct FirewallCheck {
+    pub runner: Runner,
+}
+
+impl Check for FirewallCheck {
+    fn name(&self) -> &'static str {
+        "Firewall"
+    }
+
+    fn run(&self) -> Finding {
+        let firewalld = (self.runner)("firewall-cmd", &["--state"]).is_some_and(|out| out.trim() == "running");
+        if firewalld {
+            return Finding::warn(
+                "firewalld is running",
+                "Allow multicast: firewall-cmd --permanent --add-rich-rule='rule family=ipv4 \
+                 destination address=224.0.0.0/4 accept' && firewall-cmd --reload",
+            );
+        }
+        let ufw = (self.runner)("ufw", &["status"]).is_some_and(|out| out.contains("Status: active"));
+        if ufw {
+            return Finding::warn("ufw is active", "Allow multicast: ufw allow proto udp to 224.0.0.0/4");
+        }
+        Finding::pass("No firewalld or ufw running")
+    }
+}
+
+/// Kernel settings that drop multicast or limit IGMP: strict reverse-path
+/// filtering and a forced IGMP version
+pub struct MulticastSysctlCheck {
+    /// See [`IPV4_CONF`]
+    pub conf_dir: PathBuf,
+}
+
+impl MulticastSysctlCheck {
+    fn read(&self, interface: &str, setting: &str) -> Option<u32> {
+        fs::read_to_string(self.conf_dir.join(interface).join(setting)).ok()?.trim().parse().ok()
+    }
+}
+
+impl Check for MulticastSysctlCheck {
+    fn name(&self) -> &'static str {
+        "Multicast sysctls"
+    }
+
+    fn run(&self) -> Finding {
+        let Ok(entries) = fs::read_dir(&self.conf_dir) else {
+            return Finding::pass(format!("No {}; nothing to check on this platform", self.conf_dir.display()));
+        };
+        let mut interfaces: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name != "all" && name != "default" && name != "lo")
+            .collect();
+        interfaces.sort();
+
+        // The kernel uses the larger of `all` and the interface's own value
+        let all = self.read("all", "rp_filter").unwrap_or(0);
+        let strict: Vec<&str> = interfaces
+            .iter()
+            .filter(|name| all.max(self.read(name, "rp_filter").unwrap_or(0)) == 1)
+            .map(String::as_str)
+            .collect();
+        let forced_all = self.read("all", "force_igmp_version").unwrap_or(0);
+        let forced: Vec<String> = interfaces
+            .iter()
+            .filter_map(|name| {
+                let version = match self.read(name, "force_igmp_version").unwrap_or(0) {
+                    0 => forced_all,
+                    version => version,
+                };
+                (version == 1 || version == 2).then(|| format!("{} (v{})", name, version))
+            })
+            .collect();
+
+        let mut problems = Vec::new();
+        let mut hints = Vec::new();
+        if !strict.is_empty() {
+            problems.push(format!("strict reverse-path filtering on {}", strict.join(", ")));
+            hints.push("sysctl -w net.ipv4.conf.all.rp_filter=2 (and each interface's) so multicast from other subnets isn't dropped");
+        }
+        if !forced.is_empty() {
+            problems.push(format!("IGMP version forced on {}", forced.join(", ")));
+            hints.push("sysctl -w net.ipv4.conf.all.force_igmp_version=0 unless the network needs it");
+        }
+        if problems.is_empty() {
+            Finding::pass("Loose or no reverse-path filtering, IGMP version not forced")
+        } else {
+            let mut detail = problems.join("; ");
+            if let Some(first) = detail.get_mut(..1) {
+                first.make_ascii_uppercase();
+            }
+            Finding::warn(detail, hints.join("; "))
+        }
+    }
+}
+
+/// Options for `doctor`
+pub struct DoctorOptions {
+    pub interface: MulticastInterface,
+    /// Where recordings will go
+    pub output: PathBuf,
+    /// Endpoints a run will monitor
+    pub endpoints: usize,
+    pub json: bool,
+}
+
+/// Every check `doctor` runs
+pub fn doctor_checks(options: &DoctorOptions) -> Vec<Box<dyn Check>> {
+    vec![
+        Box::new(FfmpegCheck { support: ffmpeg_support() }),
+        Box::new(MulticastJoinCheck { interface: options.interface }),
+        Box::new(LoopbackCheck { interface: options.interface }),
+        Box::new(OutputDirCheck { dir: options.output.clone() }),
+        Box::new(FileLimitCheck { endpoints: options.endpoints, limits: fd_limit::current_limit().ok().flatten() }),
+        Box::new(ClockSyncCheck { marker: PathBuf::from(TIMESYNC_MARKER), runner: system_runner() }),
+        Box::new(FirewallCheck { runner: system_runner() }),
+        Box::new(MulticastSysctlCheck { conf_dir: PathBuf::from(IPV4_CONF) }),
+    ]
+}
+
+#[derive(Serialize)]
+struct DoctorReport<'a> {
+    checks: &'a [CheckResult],
+    passed: usize,
+    warnings: usize,
+    failures: usize,
+}
+
+/// Run the checks and print them. Fails if any check failed; warnings don't
+pub fn run_doctor(options: DoctorOptions) -> Result<(), DoctorError> {
+    let results = run_checks(&doctor_checks(&options));
+    let count = |status: Status| results.iter().filter(|result| result.finding.status == status).count();
+    let report = DoctorReport {
+        checks: &results,
+        passed: count(Status::Pass),
+        warnings: count(Status::Warn),
+        failures: count(Status::Fail),
+    };
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_results(&results);
+        println!();
+        println!("{} passed, {} warnings, {} failed", report.passed, report.warnings, report.failures);
+    }
+
+    if report.failures > 0 {
+        return Err(DoctorError::Failed { failed: report.failures, total: results.len() });
+    }
+    Ok(())
+}
+
+fn print_results(results: &[CheckResult]) {
+    let width = results.iter().map(|result| result.name.len()).max().unwrap_or(0);
+    for result in results {
+        println!("{:<width$}  {}  {}", result.name, result.finding.status, result.finding.detail, width = width);
+        if let Some(ref hint) = result.finding.hint {
+            println!("{:<width$}        {}", "", hint, width = width);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers for the programs named, as if the rest weren't installed
+    fn runner(answers: &'static [(&'static str, &'static str)]) -> Runner {
+        Box::new(move |program: &str, _: &[&str]| answers.iter().find(|(name, _)| *name == program).map(|(_, out)| (*out).to_string()))
+    }
+
+    #[test]
+    fn test_ffmpeg_check() {
+        let full = "Codecs:\n D..... = Decoding supported\n -------\n DEA..S g722    G.722 ADPCM\n \
+                    DEAIL. pcm_mulaw    PCM mu-law\n DEAIL. pcm_alaw     PCM A-law\n";
+        let finding = FfmpegCheck { support: FfmpegSupport::parse_codecs(full) }.run();
+        assert_eq!(finding.status, Status::Pass);
+        assert_eq!(finding.detail, "g722 decode and encode, pcm_mulaw decode and encode, pcm_alaw decode and encode");
+
+        let minimal = "Codecs:\n -------\n D.A..S g722    G.722 ADPCM\n";
+        let finding = FfmpegCheck { support: FfmpegSupport::parse_codecs(minimal) }.run();
+        assert_eq!(finding.status, Status::Warn);
+        assert!(finding.detail.starts_with("g722 decode only, pcm_mulaw missing"), "{}", finding.detail);
+
+        let finding = FfmpegCheck { support: FfmpegSupport::default() }.run();
+        assert_eq!(finding.status, Status::Warn);
+        assert!(finding.hint.unwrap().contains(FFMPEG_ENV));
+    }
+
+    #[test]
+    fn test_output_dir_check() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(OutputDirCheck { dir: dir.path().to_path_buf() }.run().status, Status::Pass);
+
+        let missing = OutputDirCheck { dir: dir.path().join("runs/today") }.run();
+        assert_eq!(missing.status, Status::Pass);
+        assert!(missing.detail.contains("will be created"), "{}", missing.detail);
+        // The probe is cleaned up
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let file = dir.path().join("summary.json");
+        fs::write(&file, "{}").unwrap();
+        let finding = OutputDirCheck { dir: file }.run();
+        assert_eq!(finding.status, Status::Fail);
+        assert!(finding.detail.ends_with("is not a directory"));
+    }
+
+    #[test]
+    fn test_file_limit_check() {
+        let check = |endpoints| FileLimitCheck { endpoints, limits: Some(FdLimit { soft: 1024, hard: 4096 }) }.run().status;
+        assert_eq!(FileLimitCheck::needed(100), 464);
+        assert_eq!(check(100), Status::Pass);
+        assert_eq!(check(500), Status::Warn);
+        assert_eq!(check(2000), Status::Fail);
+        assert_eq!(FileLimitCheck { endpoints: 2000, limits: None }.run().status, Status::Pass);
+    }
+
+    #[test]
+    fn test_clock_sync_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("synchronized");
+        let check = |answers: &'static [(&'static str, &'static str)]| ClockSyncCheck { marker: marker.clone(), runner: runner(answers) }.run().status;
+
+        assert_eq!(check(&[("timedatectl", "yes\n")]), Status::Pass);
+        assert_eq!(check(&[("timedatectl", "no\n")]), Status::Warn);
+        const CHRONY_SYNCED: &str = "Reference ID    : C0A80001 (ntp.example)\nStratum         : 3\nLeap status     : Normal\n";
+        assert_eq!(check(&[("chronyc", CHRONY_SYNCED)]), Status::Pass);
+        const CHRONY_UNSYNCED: &str = "Reference ID    : 00000000 ()\nStratum         : 0\nLeap status     : Not synchronised\n";
+        assert_eq!(check(&[("chronyc", CHRONY_UNSYNCED)]), Status::Warn);
+        assert_eq!(check(&[]), Status::Warn);
+
+        // timesyncd's marker is enough on its own
+        fs::write(&marker, "").unwrap();
+        assert_eq!(check(&[("timedatectl", "no\n")]), Status::Pass);
+    }
+
+    #[test]
+    fn test_firewall_check() {
+        let check = |answers: &'static [(&'static str, &'static str)]| FirewallCheck { runner: runner(answers) }.run();
+        assert_eq!(check(&[("firewall-cmd", "running\n")]).status, Status::Warn);
+        assert_eq!(check(&[("firewall-cmd", "not running\n"), ("ufw", "Status: active\n")]).detail, "ufw is active");
+        assert_eq!(check(&[("ufw", "Status: inactive\n")]).status, Status::Pass);
+        assert_eq!(check(&[]).status, Status::Pass);
+    }
+
+    #[test]
+    fn test_multicast_sysctl_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let set = |interface: &str, setting: &str, value: &str| {
+            fs::create_dir_all(dir.path().join(interface)).unwrap();
+            fs::write(dir.path().join(interface).join(setting), format!("{}\n", value)).unwrap();
+        };
+        let check = || MulticastSysctlCheck { conf_dir: dir.path().to_path_buf() }.run();
+        for interface in ["all", "default", "lo", "eth0", "eth1"] {
+            set(interface, "rp_filter", "0");
+            set(interface, "force_igmp_version", "0");
+        }
+        assert_eq!(check().status, Status::Pass);
+
+        set("eth1", "rp_filter", "1");
+        set("lo", "rp_filter", "1");
+        let finding = check();
+        assert_eq!(finding.status, Status::Warn);
+        assert_eq!(finding.detail, "Strict reverse-path filtering on eth1");
+
+        // `all` applies to every interface
+        set("all", "rp_filter", "1");
+        set("eth0", "force_igmp_version", "2");
+        assert_eq!(
+            check().detail,
+            "Strict reverse-path filtering on eth0, eth1; IGMP version forced on eth0 (v2)"
+        );
+
+        let missing = MulticastSysctlCheck { conf_dir: dir.path().join("absent") }.run();
+        assert_eq!(missing.status, Status::Pass);
+    }
+
+    #[test]
+    fn test_run_checks() {
+        struct Fixed(Finding);
+        impl Check for Fixed {
+            fn name(&self) -> &'static str {
+                "Fixed"
+            }
+            fn run(&self) -> Finding {
+                self.0.clone()
+            }
+        }
+        let checks: Vec<Box<dyn Check>> = vec![
+            Box::new(Fixed(Finding::pass("fine"))),
+            Box::new(Fixed(Finding::fail("broken", "fix it"))),
+        ];
+        let results = run_checks(&checks);
+        assert_eq!(results.len(), 2);
+        let json = serde_json::to_value(&results[1]).unwrap();
+        assert_eq!(json, serde_json::json!({"name": "Fixed", "status": "fail", "detail": "broken", "hint": "fix it"}));
+        assert!(serde_json::to_value(&results[0]).unwrap().get("hint").is_none());
+    }
+}
@@ -0,0 +1,248 @@
+//! Receiving fairly from every socket of a monitor run.
+//!
+//! `monitor` and `test` read all their sockets from one task. Draining each
+//! socket until it is empty lets a flooded port hold up every other one:
+//! their packets wait in the kernel's buffers, and their page-start and
+//! idle checks run late. Instead each round takes at most `DRAIN_BUDGET`
+//! datagrams from each socket without blocking, and only waits, for up to
+//! `IDLE_WAIT`, when every socket is empty. The datagrams are read in
+//! batches (see `MulticastSocket::try_recv_batch`), so on Linux a busy
+//! socket's round is usually a single `recvmmsg` call. With `--verbose` each
+//! socket's rounds are logged every `LOG_INTERVAL`; a socket that keeps using
+//! its whole budget is one that can't be kept up with.
+
+use crate::network::{BufSlot, Datagram, MulticastSocket};
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddrV4;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Datagrams taken from one socket before moving on to the next
+pub const DRAIN_BUDGET: usize = 64;
+
+/// Longest wait for a datagram, so the caller's timers are still checked
+/// when nothing arrives
+pub const IDLE_WAIT: Duration = Duration::from_millis(10);
+
+/// Largest datagram read whole. 20ms of 48kHz L24, as `transmit` sends it,
+/// is 2892 bytes with its header; a longer datagram is cut short
+pub const DATAGRAM_BYTES: usize = 8192;
+
+/// How often each socket's figures are logged with `--verbose`
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// What a round reads from: a `MulticastSocket`, or in tests a wrapper
+/// around one that fails on purpose
+pub trait BatchSocket {
+    /// See `MulticastSocket::try_recv_batch`
+    fn try_recv_batch(&self, slots: &mut [BufSlot]) -> io::Result<usize>;
+
+    fn poll_recv_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+
+    /// Group and port the socket receives on, for logging
+    fn destination(&self) -> SocketAddrV4;
+}
+
+impl BatchSocket for MulticastSocket {
+    fn try_recv_batch(&self, slots: &mut [BufSlot]) -> io::Result<usize> {
+        MulticastSocket::try_recv_batch(self, slots)
+    }
+
+    fn poll_recv_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        MulticastSocket::poll_recv_ready(self, cx)
+    }
+
+    fn destination(&self) -> SocketAddrV4 {
+        MulticastSocket::destination(self)
+    }
+}
+
+/// One socket's rounds since they were last logged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrainStats {
+    pub rounds: u64,
+    pub packets: u64,
+    /// Batch reads that found datagrams
+    pub reads: u64,
+    /// Most datagrams taken in one round
+    pub max_burst: usize,
+    /// Rounds that stopped at the budget with datagrams possibly still waiting
+    pub budget_reached: u64,
+}
+
+impl DrainStats {
+    fn record(&mut self, burst: usize, reads: u64, budget: usize) {
+        self.rounds += 1;
+        self.reads += reads;
+        self.packets += burst as u64;
+        self.max_burst = self.max_burst.max(burst);
+        if burst >= budget {
+            self.budget_reached += 1;
+        }
+    }
+}
+
+/// Round-robin receiving across a changing set of sockets
+#[derive(Debug)]
+pub struct FairReceiver {
+    budget: usize,
+    slots: Vec<BufSlot>,
+    stats: BTreeMap<SocketAddrV4, DrainStats>,
+    last_log: Instant,
+}
+
+impl FairReceiver {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget: budget.max(1),
+            slots: BufSlot::batch(budget.max(1), DATAGRAM_BYTES),
+            stats: BTreeMap::new(),
+            last_log: Instant::now(),
+        }
+    }
+
+    /// Wait for any socket to have a datagram (up to `IDLE_WAIT`), then take
+    /// up to the budget from each in turn. `handle` gets each datagram with
+    /// its bytes, or the socket's receive error, after which that socket is
+    /// left until the next round. An error from `handle` ends the round.
+    pub async fn round<'a, K, S, E, I>(
+        &mut self,
+        sockets: I,
+        mut handle: impl FnMut(K, &S, io::Result<(Datagram, &[u8])>) -> Result<(), E>,
+    ) -> Result<(), E>
+    where
+        K: Copy,
+        S: BatchSocket + 'a,
+        I: IntoIterator<Item = (K, &'a S)> + Clone,
+    {
+        let all: Vec<&S> = sockets.clone().into_iter().map(|(_, socket)| socket).collect();
+        wait_readable(&all).await;
+
+        for (key, socket) in sockets {
+            let (mut burst, mut reads) = (0, 0);
+            let mut result = Ok(());
+            while burst < self.budget && result.is_ok() {
+                match socket.try_recv_batch(&mut self.slots[..self.budget - burst]) {
+                    Ok(0) => break,
+                    Ok(count) => {
+                        burst += count;
+                        reads += 1;
+                        for (datagram, data) in self.slots[..count].iter().filter_map(BufSlot::received) {
+                            result = handle(key, socket, Ok((datagram, data)));
+                            if result.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        result = handle(key, socket, Err(e));
+                        break;
+                    }
+                }
+            }
+            self.stats.entry(socket.destination()).or_default().record(burst, reads, self.budget);
+            result?;
+        }
+
+        if self.last_log.elapsed() >= LOG_INTERVAL {
+            self.log();
+        }
+        Ok(())
+    }
+
+    /// Log each socket's figures at debug level and start them afresh
+    fn log(&mut self) {
+        for (destination, stats) in std::mem::take(&mut self.stats) {
+            if stats.packets == 0 {
+                continue;
+            }
+            tracing::debug!(
+                "Receive on {}: {} packets in {} rounds ({:.1} per round, {:.1} per read), largest burst {}, budget of {} used up {} times",
+                destination,
+                stats.packets,
+                stats.rounds,
+                stats.packets as f64 / stats.rounds as f64,
+                stats.packets as f64 / stats.reads.max(1) as f64,
+                stats.max_burst,
+                self.budget,
+                stats.budget_reached
+            );
+        }
+        self.last_log = Instant::now();
+    }
+}
+
+/// Wait until one of `sockets` has a datagram to read, or `IDLE_WAIT` passes
+async fn wait_readable<S: BatchSocket>(sockets: &[&S]) {
+    // Readiness is only refreshed when the runtime polls for I/O. A task that
+    // always finds the flooded socket ready would never let it, and the other
+    // sockets would look empty however long their datagrams had waited
+    tokio::task::yield_now().await;
+    let ready = std::future::poll_fn(|cx| {
+        if sockets.iter().any(|socket| socket.poll_recv_ready(cx).is_ready()) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    });
+    // Timing out just means nothing arrived
+    let _ = tokio::time::timeout(IDLE_WAIT, ready).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, UdpSocket};
+
+    /// A flood on one port mustn't hold up the other port's packets: the
+    /// quiet port's packet is handled in the first round, long before the
+    /// flood's backlog is through
+    #[tokio::test]
+    async fn test_flooded_socket_does_not_starve_others() {
+        const FLOOD: u32 = 3 * DRAIN_BUDGET as u32;
+        let flooded = MulticastSocket::new(0).await.unwrap();
+        let quiet = MulticastSocket::new(0).await.unwrap();
+        let (flooded_port, quiet_port) = (flooded.port(), quiet.port());
+
+        // Everything is waiting before the first round, the quiet packet last
+        let sender = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        for i in 0..FLOOD {
+            sender.send_to(&i.to_be_bytes(), (Ipv4Addr::LOCALHOST, flooded_port)).unwrap();
+        }
+        sender.send_to(b"quiet", (Ipv4Addr::LOCALHOST, quiet_port)).unwrap();
+
+        let sockets = [(flooded_port, &flooded), (quiet_port, &quiet)];
+        let mut receiver = FairReceiver::new(DRAIN_BUDGET);
+        let mut handled = Vec::new();
+        loop {
+            let before = handled.len();
+            receiver
+                .round(sockets, |key, _, received| {
+                    received?;
+                    handled.push(key);
+                    Ok::<(), io::Error>(())
+                })
+                .await
+                .unwrap();
+            if handled.len() == before {
+                break;
+            }
+        }
+
+        let quiet_at = handled.iter().position(|&key| key == quiet_port).expect("quiet packet handled");
+        let flood_handled = handled.iter().filter(|&&key| key == flooded_port).count();
+        assert!(flood_handled > 2 * DRAIN_BUDGET, "{} of {} flood packets queued", flood_handled, FLOOD);
+        assert_eq!(quiet_at, DRAIN_BUDGET, "handled after one round's budget of the flood");
+        assert!(handled[quiet_at + 1..].iter().all(|&key| key == flooded_port));
+
+        let flood = receiver.stats[&flooded.destination()];
+        assert_eq!(flood.max_burst, DRAIN_BUDGET);
+        assert!(flood.budget_reached > 0);
+        if cfg!(target_os = "linux") {
+            // Whole batches at a time, not a read per datagram
+            assert!(flood.reads * 4 < flood.packets, "{} reads for {} packets", flood.reads, flood.packets);
+        }
+        assert_eq!(receiver.stats[&quiet.destination()].max_burst, 1);
+    }
+}
@@ -0,0 +1,14 @@
+//! Exit statuses, for scripts that need more than success or failure.
+//!
+//! | Status | Meaning |
+//! |--------|---------|
+//! | 0 | Success |
+//! | 1 | Any other error; the message is on stderr |
+//! | 2 | Bad command line, or a config file named with `--config` that can't be used |
+//! | 3 | `--count` pages weren't received before the run ended |
+
+/// Bad command line or config file; the status clap uses for usage errors
+pub const USAGE: i32 = 2;
+
+/// `monitor` or `polycom-monitor --count` ended with fewer pages than asked for
+pub const PAGES_NOT_MET: i32 = 3;
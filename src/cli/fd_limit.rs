@@ -0,0 +1,237 @@
+//! Checking a run's open files against the process limit before it starts.
+//!
+//! `monitor` and `test` open a socket per port (per endpoint for `test`),
+//! and files for every page in progress. A pattern that expands to
+//! hundreds of ports can need more than the process may have open, and
+//! the failure would otherwise come part way through setup, as a bare "Too
+//! many open files". So before any socket is opened the worst case is
+//! planned: the sockets, every endpoint recording a page at once, the
+//! run's own files, and a margin for the standard streams and the runtime.
+//!
+//! A plan over the soft `RLIMIT_NOFILE` raises the soft limit to the hard
+//! one. If that is still too few, the run stops before it starts, saying
+//! how many are needed and how to allow more. `--max-file-descriptors`
+//! sets the soft limit instead. Systems without the limit (Windows) skip
+//! the check.
+
+use std::fmt;
+use std::io;
+#[cfg(unix)]
+use std::ptr;
+use thiserror::Error;
+use tracing::debug;
+
+/// Kept for the standard streams, the runtime's own descriptors, and files
+/// a library opens now and then
+pub const RESERVED_DESCRIPTORS: u64 = 16;
+
+#[derive(Error)]
+pub enum FdLimitError {
+    #[error("{} needs up to {} open files ({}), but only {available} are allowed", .command, .plan.total(), .plan)]
+    TooFew {
+        command: &'static str,
+        plan: FdPlan,
+        /// The soft limit
+        available: u64,
+        hard: u64,
+    },
+
+    #[error("--max-file-descriptors {requested} is above the hard limit of {hard}")]
+    AboveHardLimit { requested: u64, hard: u64 },
+
+    #[error("Unable to read or set the open file limit: {0}")]
+    Io(#[from] io::Error),
+}
+
+// main reports errors with Debug formatting; keep the numbers readable there
+impl fmt::Debug for FdLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl FdLimitError {
+    /// How to allow the run the files it needs
+    pub fn hint(&self) -> Option<String> {
+        let Self::TooFew { plan, hard, .. } = self else {
+            return None;
+        };
+        let required = plan.total();
+        Some(if required > *hard {
+            format!(
+                "Raise the hard open file limit ({}) to at least {}: as root with `ulimit -Hn {}`, in \
+                 /etc/security/limits.conf, or with LimitNOFILE= for a systemd service. Or monitor fewer ports",
+                hard, required, required
+            )
+        } else {
+            format!("Allow more open files with `ulimit -n {}`, or --max-file-descriptors {}", required, required)
+        })
+    }
+}
+
+/// The most descriptors a run can have open at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FdPlan {
+    pub sockets: u64,
+    /// Every endpoint recording a page at once, with its side files
+    pub page_files: u64,
+    /// The run's own files and sockets, and the reserved margin
+    pub other: u64,
+}
+
+impl FdPlan {
+    /// A plan for `sockets` receive sockets, `endpoints` that can each have
+    /// a page in progress with `files_per_page` files open, and `extra`
+    /// files and sockets of the run's own
+    pub fn new(sockets: usize, endpoints: usize, files_per_page: u64, extra: u64) -> Self {
+        Self {
+            sockets: sockets as u64,
+            page_files: endpoints as u64 * files_per_page,
+            other: extra + RESERVED_DESCRIPTORS,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.sockets + self.page_files + self.other
+    }
+}
+
+impl fmt::Display for FdPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} sockets, {} page files, {} other", self.sockets, self.page_files, self.other)
+    }
+}
+
+/// The process's open file limits; `u64::MAX` for none
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FdLimit {
+    pub soft: u64,
+    pub hard: u64,
+}
+
+/// Make sure the run `plan` describes can open everything it needs. The
+/// soft limit is set to `requested` first, or else raised to the hard limit
+/// if the plan needs it. Returns the limit the run has, or `None` where
+/// there is no such limit
+pub fn ensure(command: &'static str, plan: FdPlan, requested: Option<u64>) -> Result<Option<FdLimit>, FdLimitError> {
+    let Some(mut limit) = current_limit()? else {
+        return Ok(None);
+    };
+    if let Some(requested) = requested {
+        if requested > limit.hard {
+            return Err(FdLimitError::AboveHardLimit { requested, hard: limit.hard });
+        }
+        limit = set_soft_limit(requested)?;
+    } else if plan.total() > limit.soft && limit.soft < limit.hard {
+        // An unlimited hard limit can't always be the soft one (macOS)
+        let target = if limit.hard == u64::MAX { plan.total() } else { limit.hard };
+        match set_soft_limit(target) {
+            Ok(raised) => {
+                debug!("Raised the open file limit from {} to {}", limit.soft, raised.soft);
+                limit = raised;
+            }
+            Err(e) => debug!("Unable to raise the open file limit to {}: {}", target, e),
+        }
+    }
+    if plan.total() > limit.soft {
+        return Err(FdLimitError::TooFew { command, plan, available: limit.soft, hard: limit.hard });
+    }
+    Ok(Some(limit))
+}
+
+/// Whether an error is the process or system running out of descriptors
+pub fn is_exhausted(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        matches!(error.raw_os_error(), Some(libc::EMFILE | libc::ENFILE))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = error;
+        false
+    }
+}
+
+/// The process's open file limits, or `None` where there is no such limit
+#[cfg(unix)]
+pub fn current_limit() -> io::Result<Option<FdLimit>> {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // SAFETY: `limit` is a valid rlimit for the call to fill in
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, ptr::addr_of_mut!(limit)) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some(FdLimit { soft: from_rlim(limit.rlim_cur), hard: from_rlim(limit.rlim_max) }))
+}
+
+#[cfg(not(unix))]
+pub fn current_limit() -> io::Result<Option<FdLimit>> {
+    Ok(None)
+}
+
+#[cfg(unix)]
+fn set_soft_limit(soft: u64) -> io::Result<FdLimit> {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // SAFETY: `limit` is a valid rlimit for both calls
+    unsafe {
+        if libc::getrlimit(libc::RLIMIT_NOFILE, ptr::addr_of_mut!(limit)) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        limit.rlim_cur = to_rlim(soft);
+        if libc::setrlimit(libc::RLIMIT_NOFILE, ptr::addr_of!(limit)) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(FdLimit { soft: from_rlim(limit.rlim_cur), hard: from_rlim(limit.rlim_max) })
+}
+
+#[cfg(not(unix))]
+fn set_soft_limit(_soft: u64) -> io::Result<FdLimit> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+// rlim_t is 64 bits on every unix the tool builds for
+#[cfg(unix)]
+fn from_rlim(value: libc::rlim_t) -> u64 {
+    if value == libc::RLIM_INFINITY {
+        u64::MAX
+    } else {
+        value
+    }
+}
+
+#[cfg(unix)]
+fn to_rlim(value: u64) -> libc::rlim_t {
+    if value == u64::MAX {
+        libc::RLIM_INFINITY
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_and_messages() {
+        let plan = FdPlan::new(100, 100, 2, 3);
+        assert_eq!(plan, FdPlan { sockets: 100, page_files: 200, other: 3 + RESERVED_DESCRIPTORS });
+        assert_eq!(plan.total(), 319);
+
+        let error = FdLimitError::TooFew { command: "monitor", plan, available: 256, hard: 4096 };
+        assert_eq!(
+            error.to_string(),
+            "monitor needs up to 319 open files (100 sockets, 200 page files, 19 other), but only 256 are allowed"
+        );
+        assert_eq!(error.hint().unwrap(), "Allow more open files with `ulimit -n 319`, or --max-file-descriptors 319");
+        let error = FdLimitError::TooFew { command: "monitor", plan, available: 256, hard: 256 };
+        assert!(error.hint().unwrap().starts_with("Raise the hard open file limit (256) to at least 319"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exhausted() {
+        assert!(is_exhausted(&io::Error::from_raw_os_error(libc::EMFILE)));
+        assert!(!is_exhausted(&io::Error::from_raw_os_error(libc::ENOSPC)));
+    }
+}
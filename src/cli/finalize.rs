@@ -0,0 +1,333 @@
+//! Finishing recordings off the receive loop.
+//!
+//! Ending a page rewrites its WAV header, appends the start time and then
+//! reads the whole file back to fingerprint it (see `integrity`). When a
+//! schedule ends pages on dozens of endpoints at once, doing all of that in
+//! the receive loop stalls it for seconds, and pages starting on other
+//! endpoints lose their first packets. Page end instead hands the page's open
+//! files to a [`FinalizeQueue`] as a [`PendingRecording`]. The queue finishes
+//! them on blocking tasks, [`FINALIZE_CONCURRENCY`] at a time. The page is
+//! reported at once from its in-memory figures, and the fingerprint follows
+//! when its file is done.
+//!
+//! Besides the WAV recording, a page may have files beside it (a timing
+//! file, recorded payloads), each a [`RecordingKind`]. One of those failing
+//! to finish is reported on its own and leaves the recording alone. So is
+//! the page's `--pipe-audio-cmd`, which is waited for here too.
+//!
+//! Figures worked out from the whole page once it has ended, rather than
+//! packet by packet, go along with its files: test mode hands over the
+//! page's packet timeline, and its jitter buffer simulation is run here and
+//! merged into the page's summary with the fingerprint. Long multi-endpoint
+//! tests then do this work as their pages end, and the end of the run only
+//! waits for the pages still in the queue.
+
+use crate::cli::audio_pipe::{AudioPipe, PipeOutput};
+use crate::cli::integrity::{self, IntegrityError, RecordingIntegrity};
+use crate::cli::jitter_buffer::{JitterBufferResult, PacketTimeline};
+use crate::cli::payloads::PayloadWriter;
+use crate::cli::recorder::{RecorderError, WavRecorder};
+use crate::cli::timing::{timing_path, TimingWriter};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::net::SocketAddrV4;
+use std::path::PathBuf;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+/// Recordings finished at once
+pub const FINALIZE_CONCURRENCY: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum FinalizeError {
+    #[error("Recorder error: {0}")]
+    Recorder(#[from] RecorderError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Unable to fingerprint: {0}")]
+    Integrity(#[from] IntegrityError),
+
+    #[error("Finalizing stopped: {0}")]
+    Task(String),
+}
+
+/// A file a page is recorded to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingKind {
+    /// The decoded audio
+    Wav,
+    /// Encoded payloads as received (`--record-payloads`); the index is
+    /// beside it with a `.jsonl` extension
+    Payloads,
+    /// Sample offsets against receive times (`--timing-file`)
+    Timing,
+}
+
+/// An ended page's files, still open
+pub struct PendingRecording {
+    /// Endpoint the page was on
+    pub endpoint: SocketAddrV4,
+    pub page: u32,
+    recording: WavRecorder,
+    /// Talk-spurt segment still being written (`--split-spurts`)
+    segment: Option<WavRecorder>,
+    timing: Option<(PathBuf, TimingWriter)>,
+    payloads: Option<PayloadWriter>,
+    pipe: Option<AudioPipe>,
+    /// Packet arrivals, for the jitter buffer simulation
+    timeline: Option<PacketTimeline>,
+}
+
+impl PendingRecording {
+    pub fn new(endpoint: SocketAddrV4, page: u32, recording: WavRecorder) -> Self {
+        Self {
+            endpoint,
+            page,
+            recording,
+            segment: None,
+            timing: None,
+            payloads: None,
+            pipe: None,
+            timeline: None,
+        }
+    }
+
+    pub fn with_segment(mut self, segment: Option<WavRecorder>) -> Self {
+        self.segment = segment;
+        self
+    }
+
+    pub fn with_timing(mut self, timing: Option<TimingWriter>) -> Self {
+        self.timing = timing.map(|timing| (timing_path(self.recording.path()), timing));
+        self
+    }
+
+    pub fn with_payloads(mut self, payloads: Option<PayloadWriter>) -> Self {
+        self.payloads = payloads;
+        self
+    }
+
+    pub fn with_pipe(mut self, pipe: Option<AudioPipe>) -> Self {
+        self.pipe = pipe;
+        self
+    }
+
+    pub fn with_timeline(mut self, timeline: Option<PacketTimeline>) -> Self {
+        self.timeline = timeline;
+        self
+    }
+
+    /// The page's files by kind. Payloads that couldn't all be written are
+    /// left out.
+    pub fn files(&self) -> BTreeMap<RecordingKind, PathBuf> {
+        let mut files = BTreeMap::from([(RecordingKind::Wav, self.recording.path().to_path_buf())]);
+        if let Some((ref path, _)) = self.timing {
+            files.insert(RecordingKind::Timing, path.clone());
+        }
+        if let Some(payloads) = self.payloads.as_ref().filter(|payloads| !payloads.failed()) {
+            files.insert(RecordingKind::Payloads, payloads.path().to_path_buf());
+        }
+        files
+    }
+
+    /// Close every file and fingerprint the recording, simulate the jitter
+    /// buffers, and wait for the page's command. Blocks on all of it, so the
+    /// queue runs it on a blocking task.
+    pub fn finish(self) -> FinishedRecording {
+        let jitter_buffer_analysis = self.timeline.as_ref().map(PacketTimeline::analyze);
+        let mut files = self.files();
+        let mut file_errors = Vec::new();
+        if let Some((path, timing)) = self.timing {
+            if let Err(e) = timing.finish() {
+                files.remove(&RecordingKind::Timing);
+                file_errors.push(format!("{}: {}", path.display(), e));
+            }
+        }
+        if let Some(payloads) = self.payloads {
+            let path = payloads.path().to_path_buf();
+            if let Err(e) = payloads.finish() {
+                files.remove(&RecordingKind::Payloads);
+                file_errors.push(format!("{}: {}", path.display(), e));
+            }
+        }
+
+        let path = self.recording.path().to_path_buf();
+        let result = (|| {
+            if let Some(segment) = self.segment {
+                segment.finalize()?;
+            }
+            self.recording.finalize()?;
+            Ok(integrity::fingerprint(&path)?)
+        })();
+        FinishedRecording {
+            endpoint: self.endpoint,
+            page: self.page,
+            path,
+            result,
+            files,
+            file_errors,
+            pipe_output: self.pipe.map(AudioPipe::finish),
+            jitter_buffer_analysis,
+        }
+    }
+}
+
+/// A recording the queue has finished with
+#[derive(Debug)]
+pub struct FinishedRecording {
+    pub endpoint: SocketAddrV4,
+    pub page: u32,
+    pub path: PathBuf,
+    pub result: Result<RecordingIntegrity, FinalizeError>,
+    /// Every file of the page that was finished, by kind
+    pub files: BTreeMap<RecordingKind, PathBuf>,
+    /// Files beside the recording that couldn't be finished
+    pub file_errors: Vec<String>,
+    /// What the page's `--pipe-audio-cmd` printed
+    pub pipe_output: Option<PipeOutput>,
+    /// The page's jitter buffer simulation, if it was handed a timeline
+    pub jitter_buffer_analysis: Option<Vec<JitterBufferResult>>,
+}
+
+/// Finishes recordings on blocking tasks, a few at a time, oldest first
+pub struct FinalizeQueue {
+    concurrency: usize,
+    waiting: VecDeque<PendingRecording>,
+    running: usize,
+    done_tx: mpsc::UnboundedSender<FinishedRecording>,
+    done_rx: mpsc::UnboundedReceiver<FinishedRecording>,
+}
+
+impl FinalizeQueue {
+    pub fn new(concurrency: usize) -> Self {
+        let (done_tx, done_rx) = mpsc::unbounded_channel();
+        Self {
+            concurrency: concurrency.max(1),
+            waiting: VecDeque::new(),
+            running: 0,
+            done_tx,
+            done_rx,
+        }
+    }
+
+    /// Queue a recording, starting it straight away if there's room
+    pub fn push(&mut self, pending: PendingRecording) {
+        self.waiting.push_back(pending);
+        self.start_waiting();
+    }
+
+    /// Recordings finished since the last call, without waiting
+    pub fn poll(&mut self) -> Vec<FinishedRecording> {
+        let mut finished = Vec::new();
+        while let Ok(recording) = self.done_rx.try_recv() {
+            finished.push(recording);
+        }
+        self.running -= finished.len();
+        self.start_waiting();
+        finished
+    }
+
+    /// Wait for every queued recording to be finished
+    pub async fn drain(&mut self) -> Vec<FinishedRecording> {
+        let mut finished = self.poll();
+        while self.running > 0 {
+            // The queue holds a sender, so the channel never closes
+            let Some(recording) = self.done_rx.recv().await else {
+                break;
+            };
+            finished.push(recording);
+            self.running -= 1;
+            self.start_waiting();
+        }
+        finished
+    }
+
+    fn start_waiting(&mut self) {
+        while self.running < self.concurrency {
+            let Some(pending) = self.waiting.pop_front() else {
+                break;
+            };
+            self.running += 1;
+            let (endpoint, page, path) = (pending.endpoint, pending.page, pending.recording.path().to_path_buf());
+            let task = tokio::task::spawn_blocking(move || pending.finish());
+            let done = self.done_tx.clone();
+            tokio::spawn(async move {
+                // A panicking task still has to be accounted for
+                let finished = task.await.unwrap_or_else(|e| FinishedRecording {
+                    endpoint,
+                    page,
+                    path,
+                    result: Err(FinalizeError::Task(e.to_string())),
+                    files: BTreeMap::new(),
+                    file_errors: Vec::new(),
+                    pipe_output: None,
+                    jitter_buffer_analysis: None,
+                });
+                let _ = done.send(finished);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(dir: &std::path::Path, page: u32, seconds: usize) -> PendingRecording {
+        let path = dir.join(format!("page_{:04}.wav", page));
+        let mut recorder = WavRecorder::new(&path, 8000, 1).unwrap();
+        recorder.write_samples(&vec![1000; 8000 * seconds]).unwrap();
+        PendingRecording::new("224.0.1.1:5004".parse().unwrap(), page, recorder)
+    }
+
+    #[tokio::test]
+    async fn test_mass_page_end_stays_off_the_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        // Ten seconds of audio on each of 40 endpoints, all ending together
+        let recordings: Vec<PendingRecording> = (1..=40).map(|page| pending(dir.path(), page, 10)).collect();
+
+        let mut queue = FinalizeQueue::new(FINALIZE_CONCURRENCY);
+        let heartbeat = tokio::spawn(async {});
+        for recording in recordings {
+            queue.push(recording);
+        }
+        // Handing them over is all the loop does: a few start on blocking
+        // threads and the rest wait their turn
+        assert_eq!(queue.running, FINALIZE_CONCURRENCY);
+        assert_eq!(queue.waiting.len(), 40 - FINALIZE_CONCURRENCY);
+        // The one loop thread is free for other work (a page starting now)
+        // while they are still being finished
+        heartbeat.await.unwrap();
+        assert!(queue.poll().len() <= FINALIZE_CONCURRENCY);
+        assert!(!queue.waiting.is_empty());
+
+        let finished = queue.drain().await;
+        assert_eq!(finished.len(), 40);
+        assert!(queue.waiting.is_empty() && queue.running == 0);
+        for recording in finished {
+            let integrity = recording.result.unwrap();
+            assert_eq!(integrity.sample_count, 80_000);
+            let reader = hound::WavReader::open(&recording.path).unwrap();
+            assert_eq!(reader.duration(), 80_000, "{} is complete", recording.path.display());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failure_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let recording = pending(dir.path(), 1, 1);
+        // Gone before it can be fingerprinted
+        std::fs::remove_file(dir.path().join("page_0001.wav")).unwrap();
+
+        let mut queue = FinalizeQueue::new(1);
+        queue.push(recording);
+        let finished = queue.drain().await;
+        assert_eq!((finished[0].endpoint.to_string().as_str(), finished[0].page), ("224.0.1.1:5004", 1));
+        assert!(finished[0].result.is_err());
+    }
+}
@@ -0,0 +1,231 @@
+//! Guessing which kind of device sent a page from its first packet.
+//!
+//! Senders differ in details RFC 3550 leaves open: whether the SSRC,
+//! starting sequence number and timestamp are random, whether the first
+//! packet carries the marker bit, which port it is sent from. The first
+//! packet of every page is kept as a `SenderFingerprint` and compared with
+//! `SENDER_HEURISTICS`. The guess is the entry with the largest share of its
+//! features present, as long as that share reaches `MIN_CONFIDENCE`. The
+//! table is deliberately small; add an entry when a device is identified.
+
+use crate::network::RtpPacket;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Share of an entry's features that must match for it to be reported
+pub const MIN_CONFIDENCE: f64 = 0.6;
+
+/// Raw fields of a page's first packet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SenderFingerprint {
+    pub ssrc: u32,
+    pub initial_sequence: u16,
+    pub initial_timestamp: u32,
+    pub payload_type: u8,
+    pub marker: bool,
+    /// Datagram length, RTP header included
+    pub packet_size: usize,
+    /// Source address and port
+    pub source: String,
+    /// IP TTL it arrived with, where the platform reports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u8>,
+    /// Best match in the heuristics table, if any is close enough
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub likely_sender: Option<SenderGuess>,
+}
+
+/// A device the fingerprint resembles
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SenderGuess {
+    pub sender: String,
+    /// Share of the entry's features present, 0-1
+    pub confidence: f64,
+    /// The features that matched
+    pub matched: Vec<String>,
+}
+
+impl fmt::Display for SenderGuess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:.0}%: {})", self.sender, self.confidence * 100.0, self.matched.join(", "))
+    }
+}
+
+/// Something about a first packet that points towards a sender
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)] // Not every feature is used by the table yet
+pub enum Feature {
+    /// The SSRC has its top bit set
+    SsrcHighBit,
+    SequenceStartsAt(u16),
+    TimestampStartsAt(u32),
+    Marker(bool),
+    PayloadType(u8),
+    PacketSize(usize),
+    SourcePort(u16),
+    Ttl(u8),
+}
+
+impl Feature {
+    fn matches(self, fingerprint: &SenderFingerprint) -> bool {
+        match self {
+            Self::SsrcHighBit => fingerprint.ssrc & 0x8000_0000 != 0,
+            Self::SequenceStartsAt(sequence) => fingerprint.initial_sequence == sequence,
+            Self::TimestampStartsAt(timestamp) => fingerprint.initial_timestamp == timestamp,
+            Self::Marker(marker) => fingerprint.marker == marker,
+            Self::PayloadType(payload_type) => fingerprint.payload_type == payload_type,
+            Self::PacketSize(size) => fingerprint.packet_size == size,
+            Self::SourcePort(port) => fingerprint.source.rsplit_once(':').is_some_and(|(_, p)| p == port.to_string()),
+            Self::Ttl(ttl) => fingerprint.ttl == Some(ttl),
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SsrcHighBit => f.write_str("SSRC high bit set"),
+            Self::SequenceStartsAt(sequence) => write!(f, "sequence starts at {}", sequence),
+            Self::TimestampStartsAt(timestamp) => write!(f, "timestamp starts at {}", timestamp),
+            Self::Marker(true) => f.write_str("marker on first packet"),
+            Self::Marker(false) => f.write_str("no marker on first packet"),
+            Self::PayloadType(payload_type) => write!(f, "payload type {}", payload_type),
+            Self::PacketSize(size) => write!(f, "{}-byte packets", size),
+            Self::SourcePort(port) => write!(f, "sent from port {}", port),
+            Self::Ttl(ttl) => write!(f, "TTL {}", ttl),
+        }
+    }
+}
+
+/// One entry of the heuristics table
+#[derive(Debug, Clone, Copy)]
+pub struct SenderHeuristic {
+    pub sender: &'static str,
+    pub features: &'static [Feature],
+}
+
+/// Known senders and what gives them away
+pub const SENDER_HEURISTICS: &[SenderHeuristic] = &[
+    SenderHeuristic {
+        sender: "multicast-paging-utility transmit",
+        features: &[Feature::SequenceStartsAt(0), Feature::TimestampStartsAt(0), Feature::Marker(false)],
+    },
+    SenderHeuristic {
+        sender: "Cisco IOS gateway",
+        features: &[Feature::SsrcHighBit, Feature::SequenceStartsAt(0)],
+    },
+];
+
+impl SenderFingerprint {
+    /// Fingerprint the first packet of a page, with its best guess from `SENDER_HEURISTICS`
+    pub fn from_packet(packet: &RtpPacket) -> Self {
+        let mut fingerprint = Self {
+            ssrc: packet.header.ssrc,
+            initial_sequence: packet.header.sequence_number,
+            initial_timestamp: packet.header.timestamp,
+            payload_type: packet.header.payload_type,
+            marker: packet.header.marker,
+            packet_size: packet.size,
+            source: packet.source.to_string(),
+            ttl: packet.ttl,
+            likely_sender: None,
+        };
+        fingerprint.likely_sender = fingerprint.best_guess(SENDER_HEURISTICS);
+        fingerprint
+    }
+
+    /// The entry with the largest share of its features present, preferring
+    /// the one with more of them on a tie, then the earlier one
+    pub fn best_guess(&self, table: &[SenderHeuristic]) -> Option<SenderGuess> {
+        let mut best: Option<SenderGuess> = None;
+        for heuristic in table.iter().filter(|heuristic| !heuristic.features.is_empty()) {
+            let matched: Vec<String> = heuristic
+                .features
+                .iter()
+                .filter(|feature| feature.matches(self))
+                .map(ToString::to_string)
+                .collect();
+            let confidence = matched.len() as f64 / heuristic.features.len() as f64;
+            if confidence < MIN_CONFIDENCE {
+                continue;
+            }
+            let better = best.as_ref().is_none_or(|best| {
+                confidence > best.confidence || (confidence >= best.confidence && matched.len() > best.matched.len())
+            });
+            if better {
+                best = Some(SenderGuess {
+                    sender: heuristic.sender.to_string(),
+                    confidence,
+                    matched,
+                });
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(ssrc: u32, sequence: u16, timestamp: u32, marker: bool) -> SenderFingerprint {
+        let data = RtpPacket::build(0, sequence, timestamp, ssrc, &[0xFF; 160], marker);
+        SenderFingerprint::from_packet(&RtpPacket::parse(&data, "192.168.1.20:16384".parse().unwrap()).unwrap())
+    }
+
+    #[test]
+    fn test_fingerprint_fields() {
+        let fp = fingerprint(0x1234_5678, 4711, 99_000, true);
+        assert_eq!(fp.ssrc, 0x1234_5678);
+        assert_eq!(fp.initial_sequence, 4711);
+        assert_eq!(fp.initial_timestamp, 99_000);
+        assert_eq!(fp.payload_type, 0);
+        assert!(fp.marker);
+        assert_eq!(fp.packet_size, 172);
+        assert_eq!(fp.source, "192.168.1.20:16384");
+        // Random starting points and a marker: nothing to go on
+        assert_eq!(fp.likely_sender, None);
+    }
+
+    #[test]
+    fn test_guesses_from_table() {
+        let ours = fingerprint(0x8000_0001, 0, 0, false).likely_sender.unwrap();
+        // The Cisco entry matches fully too, but on fewer features
+        assert_eq!(ours.sender, "multicast-paging-utility transmit");
+        assert!((ours.confidence - 1.0).abs() < 1e-9);
+
+        let cisco = fingerprint(0x9ABC_DEF0, 0, 123_456, true).likely_sender.unwrap();
+        assert_eq!(cisco.sender, "Cisco IOS gateway");
+        assert_eq!(cisco.matched, ["SSRC high bit set", "sequence starts at 0"]);
+
+        // Two of three of our features beats half of Cisco's
+        let partial = fingerprint(0x1000_0000, 0, 0, true).likely_sender.unwrap();
+        assert_eq!(partial.sender, "multicast-paging-utility transmit");
+        assert!((partial.confidence - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(
+            partial.to_string(),
+            "multicast-paging-utility transmit (67%: sequence starts at 0, timestamp starts at 0)"
+        );
+    }
+
+    #[test]
+    fn test_custom_table() {
+        let table = [
+            SenderHeuristic { sender: "empty", features: &[] },
+            SenderHeuristic {
+                sender: "Desk phone",
+                features: &[Feature::SourcePort(16384), Feature::PacketSize(172), Feature::PayloadType(0), Feature::Ttl(64)],
+            },
+        ];
+        let fp = fingerprint(1, 10, 10, false);
+        let guess = fp.best_guess(&table).unwrap();
+        assert_eq!(guess.sender, "Desk phone");
+        // No TTL without the receive path reporting one
+        assert!((guess.confidence - 0.75).abs() < 1e-9);
+
+        let with_ttl = SenderFingerprint { ttl: Some(64), ..fp };
+        assert!((with_ttl.best_guess(&table).unwrap().confidence - 1.0).abs() < 1e-9);
+        assert_eq!(with_ttl.best_guess(&table[..1]), None);
+    }
+}
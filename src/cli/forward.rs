@@ -0,0 +1,464 @@
+//! Forwarding monitored pages on as a new RTP stream.
+//!
+//! With `--forward`, `monitor` re-sends each page it hears to another
+//! destination, unicast or a group on another network, so a page on an
+//! isolated paging VLAN can be listened to live from a desk. Every page
+//! goes out as a stream of its own, with a fresh SSRC and sequence and
+//! timestamps from zero, each packet sent as its source packet arrives.
+//!
+//! When no codec is given, or it is the page's own, payloads are passed
+//! through untouched and keep their place on the source's timeline.
+//! Otherwise the page is decoded and re-encoded: lost audio is concealed
+//! and audio the sender never sent becomes silence, as in the recording.
+//! G.722 is encoded by ffmpeg ten frames at a time, so it goes out in
+//! 200ms bursts.
+//!
+//! Forwarding never holds up the page. Sends don't wait, so a send that
+//! fails is counted and its packet dropped, and a page that can't be
+//! converted is still recorded and analysed, just not forwarded.
+
+use crate::cli::audio_input::StreamResampler;
+use crate::cli::convert::downmix;
+use crate::cli::monitor::{skipped_audio, PageStats};
+use crate::cli::transmit::rand_ssrc;
+use crate::codec::{create_decoder_for_spec, create_encoder, AudioDecoder, AudioEncoder, CodecError, CodecSpec, CodecType};
+use crate::network::{create_transmit_socket, RtpPacket};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, SocketAddrV4, UdpSocket};
+use std::str::FromStr;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Codecs a page can be converted to
+const CONVERT_CODECS: [CodecType; 4] = [CodecType::G711Ulaw, CodecType::G711Alaw, CodecType::G722, CodecType::Opus];
+
+/// Frames encoded at a time for a codec that goes through ffmpeg, which is
+/// started once per batch
+const FFMPEG_BATCH_FRAMES: usize = 10;
+
+#[derive(Error, Debug)]
+pub enum ForwardError {
+    #[error("Invalid forward destination: {0}")]
+    InvalidTarget(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Where `--forward` sends pages, and the codec to convert them to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForwardTarget {
+    pub destination: SocketAddrV4,
+    /// `None` passes every page through in its own codec
+    pub codec: Option<CodecType>,
+}
+
+impl FromStr for ForwardTarget {
+    type Err = ForwardError;
+
+    /// Parse `address:port[,codec]`, e.g. `10.1.2.3:5004,g711ulaw`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| ForwardError::InvalidTarget(format!("{} ({})", s, reason));
+
+        let (destination, codec) = match s.split_once(',') {
+            Some((destination, codec)) => (destination, Some(codec.trim())),
+            None => (s, None),
+        };
+        let destination = destination.trim().parse().map_err(|_| invalid("expected ADDRESS:PORT"))?;
+        let codec = match codec {
+            Some(name) => {
+                let codec = CodecType::from_str(name).ok_or_else(|| invalid("unknown codec"))?;
+                if !CONVERT_CODECS.contains(&codec) {
+                    return Err(invalid("pages can be converted to g711ulaw, g711alaw, g722 or opus"));
+                }
+                Some(codec)
+            }
+            None => None,
+        };
+        Ok(Self { destination, codec })
+    }
+}
+
+/// How a page was forwarded, for its summary
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct ForwardSummary {
+    pub destination: String,
+    /// Codec the page was forwarded in
+    pub codec: String,
+    /// The page's own codec, where it was decoded and re-encoded; absent
+    /// when payloads were passed through
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub converted_from: Option<String>,
+    /// SSRC of the forwarded stream
+    pub ssrc: u32,
+    pub packets_forwarded: u64,
+    /// Payload bytes, without RTP headers
+    pub bytes_forwarded: u64,
+    /// Packets that couldn't be sent, and were dropped
+    pub send_failures: u64,
+    /// Why forwarding stopped before the page ended
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl fmt::Display for ForwardSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} packets ({} bytes) to {} as {}", self.packets_forwarded, self.bytes_forwarded, self.destination, self.codec)?;
+        match self.converted_from {
+            Some(ref from) => write!(f, ", converted from {}", from)?,
+            None => write!(f, ", passed through")?,
+        }
+        if self.send_failures > 0 {
+            write!(f, ", {} send failures", self.send_failures)?;
+        }
+        if let Some(ref error) = self.error {
+            write!(f, "; stopped: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// The socket pages are forwarded from, shared by every endpoint
+#[derive(Clone)]
+pub struct Forwarder {
+    target: ForwardTarget,
+    socket: Arc<UdpSocket>,
+}
+
+impl Forwarder {
+    pub async fn open(target: ForwardTarget, ttl: u8) -> Result<Self, ForwardError> {
+        // Still non-blocking, but sent on directly, so a send doesn't wait
+        // for the runtime to have seen the socket become writable
+        let socket = create_transmit_socket(ttl, None).await?.into_std()?;
+        Ok(Self { target, socket: Arc::new(socket) })
+    }
+
+    /// Start forwarding the page `packet` opens, which is in `page_codec`
+    pub fn start(&self, packet: &RtpPacket, page_codec: CodecSpec) -> PageForwarder {
+        let mut page = PageForwarder {
+            socket: Arc::clone(&self.socket),
+            destination: self.target.destination,
+            ssrc: rand_ssrc(),
+            sequence: 0,
+            codec: page_codec.codec,
+            converted_from: None,
+            mode: Mode::PassThrough { first_timestamp: packet.header.timestamp },
+            packets: 0,
+            bytes: 0,
+            send_failures: 0,
+            error: None,
+        };
+        if let Some(codec) = self.target.codec.filter(|&codec| codec != page_codec.codec) {
+            page.codec = codec;
+            page.converted_from = Some(page_codec.codec);
+            match Conversion::new(page_codec, codec) {
+                Ok(conversion) => page.mode = Mode::Convert(Box::new(conversion)),
+                Err(e) => page.stop(format!("can't convert {} to {}: {}", page_codec.codec, codec, e)),
+            }
+        }
+        page
+    }
+}
+
+/// How a page's audio gets to the forwarded stream
+enum Mode {
+    /// Payloads as received, timestamps offset from the page's first
+    PassThrough { first_timestamp: u32 },
+    Convert(Box<Conversion>),
+    /// Forwarding gave up on the page
+    Stopped,
+}
+
+/// Decoding a page and encoding it in another codec
+struct Conversion {
+    /// The forwarder's own, so the monitor's decoder is left alone
+    decoder: Box<dyn AudioDecoder>,
+    resampler: StreamResampler,
+    encoder: Box<dyn AudioEncoder>,
+    /// Decoded audio at the encoder's rate, not yet encoded
+    pcm: Vec<i16>,
+    /// Samples encoded at a time: one frame, or a batch for ffmpeg
+    encode_samples: usize,
+    /// RTP timestamp ticks per encoded frame
+    frame_ticks: u32,
+    /// Timestamp of the next encoded frame
+    timestamp: u32,
+}
+
+impl Conversion {
+    fn new(from: CodecSpec, to: CodecType) -> Result<Self, CodecError> {
+        let decoder = create_decoder_for_spec(from)?;
+        let encoder = create_encoder(to)?;
+        let frame_samples = encoder.frame_size();
+        let batch = if to == CodecType::G722 { FFMPEG_BATCH_FRAMES } else { 1 };
+        let frame_ticks = (frame_samples as u64 * u64::from(to.rtp_clock_rate()) / u64::from(encoder.sample_rate())) as u32;
+        Ok(Self {
+            resampler: StreamResampler::new(decoder.sample_rate(), encoder.sample_rate()),
+            decoder,
+            encoder,
+            pcm: Vec::new(),
+            encode_samples: frame_samples * batch,
+            frame_ticks,
+            timestamp: 0,
+        })
+    }
+
+    /// Decode a packet, with whatever was skipped before it, and return
+    /// the payloads it completes
+    fn push(&mut self, packet: &RtpPacket, stats: &PageStats) -> Result<Vec<Vec<u8>>, CodecError> {
+        let channels = usize::from(self.decoder.channels().max(1));
+        let (concealed, silent_frames) = skipped_audio(self.decoder.as_mut(), stats);
+        self.feed(concealed);
+        self.feed(vec![0; silent_frames as usize * channels]);
+
+        match self.decoder.decode(&packet.payload) {
+            Ok(samples) => self.feed(samples),
+            // A backend that isn't there won't be for the rest of the page
+            Err(e @ CodecError::BackendUnavailable { .. }) => return Err(e),
+            Err(_) => {
+                let rate = f64::from(self.decoder.sample_rate());
+                let missing = (stats.nominal_packet_secs() * rate).round() as usize;
+                self.feed(vec![0; missing * channels]);
+            }
+        }
+        self.encode_ready(false)
+    }
+
+    /// The audio the decoder and resampler still hold, padded to a whole frame
+    fn finish(&mut self) -> Result<Vec<Vec<u8>>, CodecError> {
+        let flushed = self.decoder.flush()?;
+        self.feed(flushed);
+        let tail = self.resampler.finish();
+        self.pcm.extend(tail);
+        self.encode_ready(true)
+    }
+
+    /// Mix decoded audio to mono and bring it to the encoder's rate
+    fn feed(&mut self, samples: Vec<i16>) {
+        if samples.is_empty() {
+            return;
+        }
+        let mono = downmix(samples, usize::from(self.decoder.channels()));
+        let resampled = self.resampler.push(&mono);
+        self.pcm.extend(resampled);
+    }
+
+    /// Encode whole batches, or with `finish` everything left
+    fn encode_ready(&mut self, finish: bool) -> Result<Vec<Vec<u8>>, CodecError> {
+        let frame_samples = self.encoder.frame_size();
+        if finish {
+            let padded = self.pcm.len().div_ceil(frame_samples) * frame_samples;
+            self.pcm.resize(padded, 0);
+        }
+        let mut payloads = Vec::new();
+        while self.pcm.len() >= self.encode_samples || (finish && !self.pcm.is_empty()) {
+            let take = self.encode_samples.min(self.pcm.len());
+            let block: Vec<i16> = self.pcm.drain(..take).collect();
+            let encoded = self.encoder.encode(&block)?;
+            // A batch comes back as its frames back to back, all one size
+            let frames = take / frame_samples;
+            payloads.extend(encoded.chunks(encoded.len().div_ceil(frames).max(1)).map(<[u8]>::to_vec));
+        }
+        Ok(payloads)
+    }
+}
+
+/// One page being forwarded
+pub struct PageForwarder {
+    socket: Arc<UdpSocket>,
+    destination: SocketAddrV4,
+    ssrc: u32,
+    sequence: u16,
+    /// Codec the page goes out in
+    codec: CodecType,
+    converted_from: Option<CodecType>,
+    mode: Mode,
+    packets: u64,
+    bytes: u64,
+    send_failures: u64,
+    error: Option<String>,
+}
+
+impl PageForwarder {
+    /// Forward a packet of the page, after `stats` has taken it in. Returns
+    /// what to warn about, once per problem: the first send that failed,
+    /// or why forwarding stopped
+    pub fn forward(&mut self, packet: &RtpPacket, stats: &PageStats) -> Option<String> {
+        let failures = self.send_failures;
+        match self.mode {
+            Mode::PassThrough { first_timestamp } => {
+                let timestamp = packet.header.timestamp.wrapping_sub(first_timestamp);
+                self.send(packet.header.payload_type, timestamp, &packet.payload, packet.header.marker);
+            }
+            Mode::Convert(ref mut conversion) => match conversion.push(packet, stats) {
+                Ok(payloads) => self.send_converted(payloads),
+                Err(e) => {
+                    self.stop(format!("can't convert to {}: {}", self.codec, e));
+                    return self.error.clone();
+                }
+            },
+            Mode::Stopped => return None,
+        }
+        self.first_failure(failures)
+    }
+
+    /// Send what a conversion still holds, and summarise the page
+    pub fn finish(mut self) -> ForwardSummary {
+        if let Mode::Convert(ref mut conversion) = self.mode {
+            match conversion.finish() {
+                Ok(payloads) => self.send_converted(payloads),
+                Err(e) => self.stop(format!("can't convert to {}: {}", self.codec, e)),
+            }
+        }
+        ForwardSummary {
+            destination: self.destination.to_string(),
+            codec: self.codec.name().to_string(),
+            converted_from: self.converted_from.map(|codec| codec.name().to_string()),
+            ssrc: self.ssrc,
+            packets_forwarded: self.packets,
+            bytes_forwarded: self.bytes,
+            send_failures: self.send_failures,
+            error: self.error,
+        }
+    }
+
+    /// Why forwarding stopped at the start of the page, if it did
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    fn send_converted(&mut self, payloads: Vec<Vec<u8>>) {
+        let Mode::Convert(ref mut conversion) = self.mode else {
+            return;
+        };
+        let payload_type = self.codec.payload_type();
+        let mut frames = Vec::with_capacity(payloads.len());
+        for payload in payloads {
+            frames.push((conversion.timestamp, payload));
+            conversion.timestamp = conversion.timestamp.wrapping_add(conversion.frame_ticks);
+        }
+        for (timestamp, payload) in frames {
+            // The marker opens the stream's one talk spurt
+            let marker = self.sequence == 0;
+            self.send(payload_type, timestamp, &payload, marker);
+        }
+    }
+
+    fn send(&mut self, payload_type: u8, timestamp: u32, payload: &[u8], marker: bool) {
+        let data = RtpPacket::build(payload_type, self.sequence, timestamp, self.ssrc, payload, marker);
+        self.sequence = self.sequence.wrapping_add(1);
+        match self.socket.send_to(&data, SocketAddr::V4(self.destination)) {
+            Ok(_) => {
+                self.packets += 1;
+                self.bytes += payload.len() as u64;
+            }
+            Err(_) => self.send_failures += 1,
+        }
+    }
+
+    /// A warning for the page's first failed send, if this packet had it
+    fn first_failure(&self, failures_before: u64) -> Option<String> {
+        (failures_before == 0 && self.send_failures > 0)
+            .then(|| format!("Unable to forward to {}; dropping packets that can't be sent", self.destination))
+    }
+
+    fn stop(&mut self, reason: String) {
+        self.mode = Mode::Stopped;
+        self.error = Some(reason);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn page_packet(payload_type: u8, seq: u16, timestamp: u32, payload: Vec<u8>) -> RtpPacket {
+        let data = RtpPacket::build(payload_type, seq, timestamp, 0x1234, &payload, seq == 1000);
+        RtpPacket::parse(&data, "10.0.0.1:5004".parse().unwrap()).unwrap()
+    }
+
+    async fn forward_page(target: &str, payload_type: u8, packets: u16) -> (Vec<RtpPacket>, ForwardSummary) {
+        let listener = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener.set_read_timeout(Some(std::time::Duration::from_secs(2))).unwrap();
+        let target: ForwardTarget = format!("{}{}", listener.local_addr().unwrap(), target).parse().unwrap();
+        let forwarder = Forwarder::open(target, 1).await.unwrap();
+
+        let first = page_packet(payload_type, 1000, 50_000, vec![0xff; 160]);
+        let codec = CodecSpec::from_payload_type(payload_type).unwrap();
+        let mut stats = PageStats::for_codec(codec);
+        let mut page = forwarder.start(&first, codec);
+        for i in 0..packets {
+            let packet = page_packet(payload_type, 1000 + i, 50_000 + u32::from(i) * 160, vec![0xff; 160]);
+            stats.update(&packet);
+            assert_eq!(page.forward(&packet, &stats), None);
+        }
+        let summary = page.finish();
+
+        let mut received = Vec::new();
+        let mut buf = [0u8; 2048];
+        for _ in 0..summary.packets_forwarded {
+            let (len, from) = listener.recv_from(&mut buf).unwrap();
+            received.push(RtpPacket::parse(&buf[..len], from).unwrap());
+        }
+        (received, summary)
+    }
+
+    #[test]
+    fn test_parse_target() {
+        let target: ForwardTarget = "10.1.2.3:5004".parse().unwrap();
+        assert_eq!(target, ForwardTarget { destination: SocketAddrV4::new(Ipv4Addr::new(10, 1, 2, 3), 5004), codec: None });
+        let target: ForwardTarget = "239.1.1.1:6000, PCMA".parse().unwrap();
+        assert_eq!(target.codec, Some(CodecType::G711Alaw));
+
+        for bad in ["10.1.2.3", "10.1.2.3:5004,mp3", "10.1.2.3:5004,l16", "host:5004"] {
+            assert!(matches!(bad.parse::<ForwardTarget>(), Err(ForwardError::InvalidTarget(_))), "{}", bad);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pass_through_restamps() {
+        let (received, summary) = forward_page("", 0, 20).await;
+
+        assert_eq!(summary.packets_forwarded, 20);
+        assert_eq!(summary.bytes_forwarded, 20 * 160);
+        assert_eq!(summary.converted_from, None);
+        for (i, packet) in received.iter().enumerate() {
+            assert_eq!(packet.header.ssrc, summary.ssrc);
+            assert_eq!(packet.header.sequence_number, i as u16);
+            assert_eq!(packet.header.timestamp, i as u32 * 160);
+            assert_eq!(packet.header.payload_type, 0);
+            assert_eq!(packet.header.marker, i == 0);
+            assert_eq!(packet.payload, vec![0xff; 160]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conversion_reencodes() {
+        let (received, summary) = forward_page(",g711alaw", 0, 20).await;
+
+        assert_eq!(summary.codec, "G.711 A-law");
+        assert_eq!(summary.converted_from.as_deref(), Some("G.711 u-law"));
+        assert_eq!(summary.error, None);
+        // Same rate both sides, so a frame out for every packet in
+        assert_eq!(summary.packets_forwarded, 20);
+        for (i, packet) in received.iter().enumerate() {
+            assert_eq!(packet.header.payload_type, 8);
+            assert_eq!(packet.header.sequence_number, i as u16);
+            assert_eq!(packet.header.timestamp, i as u32 * 160);
+            assert_eq!(packet.header.marker, i == 0);
+            assert_eq!(packet.payload.len(), 160);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_matching_codec_passes_through() {
+        let (_, summary) = forward_page(",g711ulaw", 0, 5).await;
+
+        assert_eq!(summary.converted_from, None);
+        assert_eq!(summary.packets_forwarded, 5);
+    }
+}
@@ -0,0 +1,702 @@
+//! On-disk cache of encoded frames for the transmit commands.
+//!
+//! Paging the same announcement dozens of times a day re-decodes and
+//! re-encodes it every time, which for G.722 means a trip through ffmpeg.
+//! With `--cache-dir` the encoded frames are kept in one file per source,
+//! codec and encoder setup, and a later run with the same key skips both the
+//! decode and the encode.
+//!
+//! An entry is a small container:
+//!
+//! ```text
+//! "MPUFRAME" | header length (u32 LE) | header JSON | frames
+//! ```
+//!
+//! where each frame is a u32 LE length followed by its bytes. The header
+//! records the key, where the audio came from and a SHA-256 of the frame
+//! section, which is checked on every read so a damaged entry is re-encoded
+//! rather than sent. The key includes a SHA-256 of the source file, so an
+//! edited file misses; storing its new frames evicts the entries it
+//! superseded.
+
+use crate::cli::audio_input::{AudioInput, AudioLevels};
+use crate::cli::CacheAction;
+use crate::codec::CodecType;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use thiserror::Error;
+use tracing::{debug, warn};
+use schemars::JsonSchema;
+
+/// Container version written to the header; other versions are ignored
+pub const CACHE_VERSION: u32 = 1;
+
+/// Encoder names used in keys
+pub const FFMPEG: &str = "ffmpeg";
+pub const BUILTIN: &str = "builtin";
+
+const MAGIC: &[u8; 8] = b"MPUFRAME";
+const EXTENSION: &str = "frames";
+
+/// Longest header or frame accepted when reading, to refuse garbage early
+const MAX_CHUNK: u32 = 16 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum FrameCacheError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("{0} is not a frame cache entry")]
+    NotAnEntry(PathBuf),
+
+    #[error("Unsupported frame cache version {0} (expected {CACHE_VERSION})")]
+    UnsupportedVersion(u32),
+
+    #[error("Frame cache entry {0} is damaged: {1}")]
+    Corrupt(String, String),
+
+    #[error("No cache entry matches {0}")]
+    NoMatch(String),
+
+    #[error("{0} matches more than one cache entry; give more of the ID")]
+    Ambiguous(String),
+}
+
+/// What an entry's frames depend on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheKey {
+    /// SHA-256 of the source file, hex
+    pub source_sha256: String,
+    pub codec: String,
+    /// Which implementation encoded the frames, [`FFMPEG`] or [`BUILTIN`]
+    pub encoder: String,
+    pub sample_rate: u32,
+    /// Samples per frame
+    pub frame_samples: usize,
+    /// How the source was read (headerless layout)
+    pub input: String,
+}
+
+impl CacheKey {
+    /// Entry file name stem: the first 128 bits of a SHA-256 of the key
+    pub fn id(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        hex(&Sha256::digest(json)[..16])
+    }
+
+    /// Same settings, whatever the source's contents
+    fn same_settings(&self, other: &Self) -> bool {
+        Self {
+            source_sha256: String::new(),
+            ..self.clone()
+        } == Self {
+            source_sha256: String::new(),
+            ..other.clone()
+        }
+    }
+}
+
+/// How a transmit command turns a file into frames
+#[derive(Debug, Clone, Copy)]
+pub struct EncodeSpec<'a> {
+    pub source: &'a Path,
+    pub codec: CodecType,
+    pub encoder: &'static str,
+    pub sample_rate: u32,
+    pub frame_samples: usize,
+    pub input: AudioInput,
+}
+
+impl EncodeSpec<'_> {
+    /// Key for the source as it is on disk now
+    pub fn key(&self) -> io::Result<CacheKey> {
+        // Probed files were once folded to mono wrongly, so their key names the
+        // selection too and entries from before the fix are never read
+        let input = match self.input.format {
+            Some(format) => format!(
+                "{} {}Hz {}ch {}",
+                format, self.input.sample_rate, self.input.channels, self.input.channel
+            ),
+            None => format!("probed {}", self.input.channel),
+        };
+        Ok(CacheKey {
+            source_sha256: hash_file(self.source)?,
+            codec: self.codec.cli_name().to_string(),
+            encoder: self.encoder.to_string(),
+            sample_rate: self.sample_rate,
+            frame_samples: self.frame_samples,
+            input,
+        })
+    }
+}
+
+/// A file encoded into frames
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodedAudio {
+    pub frames: Vec<Vec<u8>>,
+    /// Samples the frames were encoded from, before padding the last one
+    pub samples: usize,
+    /// Levels of those samples; absent from entries stored before they were kept
+    pub levels: Option<AudioLevels>,
+}
+
+impl EncodedAudio {
+    #[must_use]
+    pub fn with_levels(self, levels: AudioLevels) -> Self {
+        Self {
+            levels: Some(levels),
+            ..self
+        }
+    }
+}
+
+/// Everything an entry records besides its frames
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheHeader {
+    pub version: u32,
+    pub key: CacheKey,
+    /// Absolute path of the source when the entry was stored
+    pub source: String,
+    pub source_size: u64,
+    pub created: DateTime<Utc>,
+    pub samples: usize,
+    pub frame_count: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub levels: Option<AudioLevels>,
+    /// SHA-256 of the frame section, hex
+    pub frames_sha256: String,
+}
+
+impl CacheHeader {
+    pub fn audio_secs(&self) -> f64 {
+        self.samples as f64 / f64::from(self.key.sample_rate.max(1))
+    }
+}
+
+/// An entry found in the cache directory
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub id: String,
+    pub path: PathBuf,
+    pub file_size: u64,
+    pub header: CacheHeader,
+}
+
+/// Whether an entry's source still has the contents it was encoded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceStatus {
+    Current,
+    Changed,
+    Missing,
+}
+
+impl SourceStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Current => "current",
+            Self::Changed => "changed",
+            Self::Missing => "missing",
+        }
+    }
+}
+
+impl CacheEntry {
+    pub fn source_status(&self) -> SourceStatus {
+        match hash_file(Path::new(&self.header.source)) {
+            Ok(hash) if hash == self.header.key.source_sha256 => SourceStatus::Current,
+            Ok(_) => SourceStatus::Changed,
+            Err(_) => SourceStatus::Missing,
+        }
+    }
+
+    pub fn remove(&self) -> Result<(), FrameCacheError> {
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+/// Cache hits and misses of one transmit, for its report
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, JsonSchema)]
+pub struct CacheUsage {
+    pub hits: u32,
+    pub misses: u32,
+    /// Time spent decoding and encoding on misses
+    pub encode_secs: f64,
+}
+
+/// A directory of encoded files
+pub struct FrameCache {
+    dir: PathBuf,
+}
+
+impl FrameCache {
+    /// Use `dir`, creating it if needed
+    pub fn open(dir: &Path) -> Result<Self, FrameCacheError> {
+        fs::create_dir_all(dir)?;
+        Ok(Self { dir: dir.to_path_buf() })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn entry_path(&self, id: &str) -> PathBuf {
+        self.dir.join(id).with_extension(EXTENSION)
+    }
+
+    /// Frames stored under `key`, checked against their hash.
+    /// None if there is no entry for it.
+    pub fn get(&self, key: &CacheKey) -> Result<Option<EncodedAudio>, FrameCacheError> {
+        let id = key.id();
+        let file = match File::open(self.entry_path(&id)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut reader = BufReader::new(file);
+        let header = read_header(&mut reader, &self.entry_path(&id))?;
+        if header.key != *key {
+            return Ok(None);
+        }
+
+        let corrupt = |reason: &str| FrameCacheError::Corrupt(id.clone(), reason.to_string());
+        let mut hasher = Sha256::new();
+        let mut frames = Vec::with_capacity(header.frame_count);
+        for _ in 0..header.frame_count {
+            let len = read_u32(&mut reader).map_err(|_| corrupt("truncated"))?;
+            if len > MAX_CHUNK {
+                return Err(corrupt("frame length out of range"));
+            }
+            let mut frame = vec![0u8; len as usize];
+            reader.read_exact(&mut frame).map_err(|_| corrupt("truncated"))?;
+            hasher.update(len.to_le_bytes());
+            hasher.update(&frame);
+            frames.push(frame);
+        }
+        if reader.read(&mut [0u8; 1])? != 0 {
+            return Err(corrupt("trailing data"));
+        }
+        if hex(&hasher.finalize()) != header.frames_sha256 {
+            return Err(corrupt("frame hash mismatch"));
+        }
+        Ok(Some(EncodedAudio {
+            frames,
+            samples: header.samples,
+            levels: header.levels,
+        }))
+    }
+
+    /// Store frames under `key`, replacing the file atomically, then evict
+    /// entries for the same source and settings made from older contents.
+    /// Returns how many were evicted.
+    pub fn put(&self, key: &CacheKey, source: &Path, audio: &EncodedAudio) -> Result<usize, FrameCacheError> {
+        let mut hasher = Sha256::new();
+        for frame in &audio.frames {
+            hasher.update((frame.len() as u32).to_le_bytes());
+            hasher.update(frame);
+        }
+        let header = CacheHeader {
+            version: CACHE_VERSION,
+            key: key.clone(),
+            source: fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf()).display().to_string(),
+            source_size: fs::metadata(source).map(|m| m.len()).unwrap_or(0),
+            created: Utc::now(),
+            samples: audio.samples,
+            frame_count: audio.frames.len(),
+            levels: audio.levels,
+            frames_sha256: hex(&hasher.finalize()),
+        };
+
+        let id = key.id();
+        let path = self.entry_path(&id);
+        let temp = path.with_extension(format!("{}.tmp", EXTENSION));
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        let header_json = serde_json::to_vec(&header)?;
+        writer.write_all(MAGIC)?;
+        writer.write_all(&(header_json.len() as u32).to_le_bytes())?;
+        writer.write_all(&header_json)?;
+        for frame in &audio.frames {
+            writer.write_all(&(frame.len() as u32).to_le_bytes())?;
+            writer.write_all(frame)?;
+        }
+        let file = writer.into_inner().map_err(io::IntoInnerError::into_error)?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temp, &path)?;
+
+        let mut evicted = 0;
+        for entry in self.entries()? {
+            let superseded = entry.id != id
+                && entry.header.source == header.source
+                && entry.header.key.same_settings(key);
+            if superseded {
+                entry.remove()?;
+                evicted += 1;
+            }
+        }
+        Ok(evicted)
+    }
+
+    /// Every readable entry, oldest first. Files that aren't entries are
+    /// skipped with a warning.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>, FrameCacheError> {
+        let mut entries = Vec::new();
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let path = dir_entry?.path();
+            if path.extension().is_none_or(|ext| ext != EXTENSION) {
+                continue;
+            }
+            let read = File::open(&path)
+                .map_err(FrameCacheError::from)
+                .and_then(|file| read_header(&mut BufReader::new(file), &path));
+            match read {
+                Ok(header) => entries.push(CacheEntry {
+                    id: path.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+                    file_size: fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+                    path,
+                    header,
+                }),
+                Err(e) => warn!("Skipping {}: {}", path.display(), e),
+            }
+        }
+        entries.sort_by_key(|entry| entry.header.created);
+        Ok(entries)
+    }
+
+    /// The one entry whose ID starts with `prefix`
+    pub fn find(&self, prefix: &str) -> Result<CacheEntry, FrameCacheError> {
+        let mut matches = self.entries()?.into_iter().filter(|entry| entry.id.starts_with(prefix));
+        let entry = matches.next().ok_or_else(|| FrameCacheError::NoMatch(prefix.to_string()))?;
+        if matches.next().is_some() {
+            return Err(FrameCacheError::Ambiguous(prefix.to_string()));
+        }
+        Ok(entry)
+    }
+}
+
+/// Frames for `spec`, from the cache when it has them. Otherwise `encode`
+/// runs and its frames are stored for next time. Cache problems are logged
+/// and fall back to encoding; they never stop a page.
+pub fn encode_cached<E>(
+    cache: Option<&FrameCache>,
+    spec: &EncodeSpec,
+    usage: &mut CacheUsage,
+    encode: impl FnOnce() -> Result<EncodedAudio, E>,
+) -> Result<EncodedAudio, E> {
+    let Some(cache) = cache else {
+        return encode();
+    };
+    let key = match spec.key() {
+        Ok(key) => Some(key),
+        Err(e) => {
+            warn!("Frame cache: can't hash {}: {}", spec.source.display(), e);
+            None
+        }
+    };
+    if let Some(key) = &key {
+        match cache.get(key) {
+            Ok(Some(audio)) => {
+                debug!("Frame cache hit for {} ({})", spec.source.display(), key.id());
+                usage.hits += 1;
+                return Ok(audio);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Frame cache: {}; encoding again", e),
+        }
+    }
+
+    let start = Instant::now();
+    let audio = encode()?;
+    usage.misses += 1;
+    usage.encode_secs += start.elapsed().as_secs_f64();
+    if let Some(key) = &key {
+        match cache.put(key, spec.source, &audio) {
+            Ok(evicted) => {
+                debug!("Frame cache stored {} ({})", spec.source.display(), key.id());
+                if evicted > 0 {
+                    debug!("Frame cache evicted {} entries for older contents", evicted);
+                }
+            }
+            Err(e) => warn!("Frame cache: can't store {}: {}", spec.source.display(), e),
+        }
+    }
+    Ok(audio)
+}
+
+/// Digits of an entry ID shown by `cache list`
+const SHORT_ID: usize = 12;
+
+/// An entry as listed by `cache list --json`
+#[derive(Serialize)]
+struct ListedEntry<'a> {
+    id: &'a str,
+    file_size: u64,
+    status: SourceStatus,
+    #[serde(flatten)]
+    header: &'a CacheHeader,
+}
+
+/// Run the cache command
+pub fn run_cache(dir: &Path, action: &CacheAction) -> Result<(), FrameCacheError> {
+    if !dir.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not a directory", dir.display())).into());
+    }
+    let cache = FrameCache::open(dir)?;
+    match action {
+        CacheAction::List { json } => list_entries(&cache, *json),
+        CacheAction::Evict { ids, stale, all } => evict_entries(&cache, ids, *stale, *all),
+    }
+}
+
+fn list_entries(cache: &FrameCache, json: bool) -> Result<(), FrameCacheError> {
+    let entries = cache.entries()?;
+    let statuses: Vec<SourceStatus> = entries.iter().map(CacheEntry::source_status).collect();
+
+    if json {
+        let listed: Vec<ListedEntry> = entries
+            .iter()
+            .zip(&statuses)
+            .map(|(entry, &status)| ListedEntry {
+                id: &entry.id,
+                file_size: entry.file_size,
+                status,
+                header: &entry.header,
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&listed)?);
+        return Ok(());
+    }
+
+    let total: u64 = entries.iter().map(|entry| entry.file_size).sum();
+    println!(
+        "Frame cache {}: {} entries, {}",
+        cache.dir().display(),
+        entries.len(),
+        format_size(total)
+    );
+    if entries.is_empty() {
+        return Ok(());
+    }
+    println!();
+    println!(
+        "  {:<SHORT_ID$}  {:<18} {:>7} {:>8} {:>10}  {:<16}  {:<8} Source",
+        "ID", "Codec", "Frames", "Audio", "Size", "Created", "Status"
+    );
+    for (entry, status) in entries.iter().zip(&statuses) {
+        let header = &entry.header;
+        println!(
+            "  {:<SHORT_ID$}  {:<18} {:>7} {:>7.1}s {:>10}  {:<16}  {:<8} {}",
+            &entry.id[..entry.id.len().min(SHORT_ID)],
+            format!("{} ({})", header.key.codec, header.key.encoder),
+            header.frame_count,
+            header.audio_secs(),
+            format_size(entry.file_size),
+            header.created.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+            status.name(),
+            header.source
+        );
+    }
+    Ok(())
+}
+
+fn evict_entries(cache: &FrameCache, ids: &[String], stale: bool, all: bool) -> Result<(), FrameCacheError> {
+    let mut chosen: Vec<CacheEntry> = Vec::new();
+    if all {
+        chosen = cache.entries()?;
+    } else {
+        // Resolve every ID before removing anything, so a typo evicts nothing
+        for id in ids {
+            chosen.push(cache.find(id)?);
+        }
+        if stale {
+            chosen.extend(
+                cache
+                    .entries()?
+                    .into_iter()
+                    .filter(|entry| entry.source_status() != SourceStatus::Current),
+            );
+        }
+    }
+    chosen.sort_by(|a, b| a.id.cmp(&b.id));
+    chosen.dedup_by(|a, b| a.id == b.id);
+
+    let mut freed = 0;
+    for entry in &chosen {
+        entry.remove()?;
+        freed += entry.file_size;
+        println!("Evicted {} ({})", &entry.id[..entry.id.len().min(SHORT_ID)], entry.header.source);
+    }
+    println!("{} entries evicted, {} freed", chosen.len(), format_size(freed));
+    Ok(())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_header(reader: &mut impl Read, path: &Path) -> Result<CacheHeader, FrameCacheError> {
+    let not_an_entry = || FrameCacheError::NotAnEntry(path.to_path_buf());
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic).map_err(|_| not_an_entry())?;
+    if &magic != MAGIC {
+        return Err(not_an_entry());
+    }
+    let len = read_u32(reader).map_err(|_| not_an_entry())?;
+    if len > MAX_CHUNK {
+        return Err(not_an_entry());
+    }
+    let mut json = vec![0u8; len as usize];
+    reader.read_exact(&mut json).map_err(|_| not_an_entry())?;
+    let header: CacheHeader = serde_json::from_slice(&json)?;
+    if header.version != CACHE_VERSION {
+        return Err(FrameCacheError::UnsupportedVersion(header.version));
+    }
+    Ok(header)
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut BufReader::new(File::open(path)?), &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut hex, b| {
+        let _ = write!(hex, "{:02x}", b);
+        hex
+    })
+}
+
+/// Human-readable byte count
+pub fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{} B", bytes),
+        1024..1_048_576 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(source: &Path) -> EncodeSpec<'_> {
+        EncodeSpec {
+            source,
+            codec: CodecType::G711Ulaw,
+            encoder: BUILTIN,
+            sample_rate: 8000,
+            frame_samples: 160,
+            input: AudioInput::default(),
+        }
+    }
+
+    fn audio(fill: u8) -> EncodedAudio {
+        EncodedAudio {
+            frames: (0..50).map(|i| vec![fill.wrapping_add(i); 160]).collect(),
+            samples: 7990,
+            levels: None,
+        }
+    }
+
+    #[test]
+    fn test_second_encode_is_a_hit() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("page.wav");
+        fs::write(&source, b"first contents").unwrap();
+        let cache = FrameCache::open(&dir.path().join("cache")).unwrap();
+
+        let mut usage = CacheUsage::default();
+        let first = encode_cached(Some(&cache), &spec(&source), &mut usage, || Ok::<_, io::Error>(audio(1))).unwrap();
+        let second =
+            encode_cached(Some(&cache), &spec(&source), &mut usage, || -> Result<_, io::Error> { panic!("should be cached") })
+                .unwrap();
+        assert_eq!(first, second);
+        assert_eq!((usage.hits, usage.misses), (1, 1));
+
+        // Another codec setup is a separate entry
+        let other = EncodeSpec {
+            encoder: FFMPEG,
+            ..spec(&source)
+        };
+        assert_ne!(other.key().unwrap().id(), spec(&source).key().unwrap().id());
+        encode_cached(Some(&cache), &other, &mut usage, || Ok::<_, io::Error>(audio(2))).unwrap();
+        assert_eq!(usage.misses, 2);
+
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].header.frame_count, 50);
+        assert!((entries[0].header.audio_secs() - 0.99875).abs() < 1e-9);
+        assert_eq!(entries[0].source_status(), SourceStatus::Current);
+    }
+
+    #[test]
+    fn test_changed_source_misses_and_evicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("page.wav");
+        let cache = FrameCache::open(dir.path()).unwrap();
+        let mut usage = CacheUsage::default();
+
+        fs::write(&source, b"first contents").unwrap();
+        let other = EncodeSpec {
+            encoder: FFMPEG,
+            ..spec(&source)
+        };
+        encode_cached(Some(&cache), &spec(&source), &mut usage, || Ok::<_, io::Error>(audio(1))).unwrap();
+        encode_cached(Some(&cache), &other, &mut usage, || Ok::<_, io::Error>(audio(1))).unwrap();
+        let old_id = spec(&source).key().unwrap().id();
+
+        fs::write(&source, b"second contents").unwrap();
+        assert_eq!(cache.find(&old_id[..8]).unwrap().source_status(), SourceStatus::Changed);
+        let fresh = encode_cached(Some(&cache), &spec(&source), &mut usage, || Ok::<_, io::Error>(audio(9))).unwrap();
+        assert_eq!(fresh, audio(9));
+        assert_eq!((usage.hits, usage.misses), (0, 3));
+
+        // The old entry with the same settings is gone; the other encoder's stays
+        let ids: Vec<String> = cache.entries().unwrap().into_iter().map(|e| e.id).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(!ids.contains(&old_id));
+        assert!(matches!(cache.find(&old_id), Err(FrameCacheError::NoMatch(_))));
+
+        fs::remove_file(&source).unwrap();
+        assert!(cache.entries().unwrap().iter().all(|e| e.source_status() == SourceStatus::Missing));
+    }
+
+    #[test]
+    fn test_damaged_entry_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("page.wav");
+        fs::write(&source, b"contents").unwrap();
+        let cache = FrameCache::open(dir.path()).unwrap();
+        let key = spec(&source).key().unwrap();
+        cache.put(&key, &source, &audio(1)).unwrap();
+
+        let path = cache.entry_path(&key.id());
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(cache.get(&key), Err(FrameCacheError::Corrupt(_, _))));
+
+        // A transmit re-encodes and repairs it
+        let mut usage = CacheUsage::default();
+        encode_cached(Some(&cache), &spec(&source), &mut usage, || Ok::<_, io::Error>(audio(1))).unwrap();
+        assert_eq!(usage.misses, 1);
+        assert_eq!(cache.get(&key).unwrap(), Some(audio(1)));
+
+        fs::write(dir.path().join("stray.frames"), b"not an entry").unwrap();
+        assert_eq!(cache.entries().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,580 @@
+//! Encoding a page while it is sent, instead of before.
+//!
+//! A long page held as samples and then as frames takes hundreds of
+//! megabytes. A `FrameStream` reads the file a block at a time (see
+//! `AudioReader`), encodes it on a thread of its own and keeps at most
+//! `PRIMING_FRAMES` frames queued ahead of the send loop, so memory stays
+//! flat however long the file is. G.722, and G.711 where the page is encoded
+//! through ffmpeg, go through one ffmpeg process per pass that is fed the
+//! samples as they are read. A looping page rewinds the reader at the end
+//! of each pass rather than keeping the frames it sent. A `--chime` is
+//! held as samples and sent ahead of the file on every pass.
+//!
+//! `--cache-dir` still encodes the whole file up front: its entries are the
+//! complete frame list, and a hit is sent from memory.
+
+use crate::cli::audio_input::{AudioInputError, AudioReader};
+use crate::cli::frame_cache::{self, EncodeSpec};
+use crate::cli::watermark::Watermarker;
+use crate::codec::subprocess::write_pcm;
+use crate::codec::{create_encoder, CodecError, CodecType, FfmpegStreamEncoder};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
+use thiserror::Error;
+
+/// Frames encoded ahead of the send loop (three seconds of 20ms frames),
+/// enough to ride out a slow read without holding much of the page
+pub const PRIMING_FRAMES: usize = 150;
+
+#[derive(Error, Debug)]
+pub enum FrameStreamError {
+    #[error(transparent)]
+    Input(#[from] AudioInputError),
+
+    #[error("Codec error: {0}")]
+    Codec(#[from] CodecError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// How a [`FrameStream`] runs through its source
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StreamSettings {
+    /// Start the source again after each pass, for a looping page
+    pub loop_audio: bool,
+    /// Frames a pass stops at, for a duration cap
+    pub limit: Option<usize>,
+    /// ID and level (dBFS) to mark into the audio before it is encoded
+    pub watermark: Option<(u16, f64)>,
+}
+
+enum Item {
+    Frame(Vec<u8>),
+    EndOfPass,
+    Failed(FrameStreamError),
+}
+
+/// A page's frames, encoded on a thread of their own a little ahead of
+/// being sent
+pub struct FrameStream {
+    frames: Receiver<Item>,
+    peeked: Option<Vec<u8>>,
+    /// This pass is over; the next starts at [`FrameStream::finish_pass`]
+    ended: bool,
+}
+
+impl FrameStream {
+    /// Encode the file `spec` describes the way [`frame_cache::encode_cached`]
+    /// would, with `spec.encoder` choosing between ffmpeg and the built-in
+    /// encoder. `lead_in`, samples at `spec.sample_rate` (a `--chime` and
+    /// its gap), goes ahead of the file on every pass. The file is opened,
+    /// and ffmpeg checked, before this returns
+    pub fn encode(spec: &EncodeSpec, lead_in: Vec<i16>, settings: StreamSettings) -> Result<Self, FrameStreamError> {
+        let reader = AudioReader::open(spec.source, spec.sample_rate, &spec.input)?;
+        let ffmpeg = spec.encoder == frame_cache::FFMPEG;
+        if ffmpeg {
+            FfmpegStreamEncoder::check(spec.codec)?;
+        } else {
+            create_encoder(spec.codec)?;
+        }
+        let source = EncodedSource {
+            reader,
+            lead_in,
+            codec: spec.codec,
+            ffmpeg,
+            frame_samples: spec.frame_samples,
+            sample_rate: spec.sample_rate,
+            watermark: settings.watermark,
+        };
+        Ok(Self::start(source, settings))
+    }
+
+    /// Send a file of pre-encoded frames as it is, padding the last one
+    pub fn raw(path: &Path, frame_bytes: usize, settings: StreamSettings) -> Result<Self, FrameStreamError> {
+        let source = RawSource {
+            file: BufReader::new(File::open(path)?),
+            frame_bytes,
+        };
+        Ok(Self::start(source, settings))
+    }
+
+    fn start(source: impl Source, settings: StreamSettings) -> Self {
+        let (sender, frames) = sync_channel(PRIMING_FRAMES);
+        // The thread ends at the next frame once the stream is dropped
+        thread::spawn(move || run(source, &sender, settings));
+        Self {
+            frames,
+            peeked: None,
+            ended: false,
+        }
+    }
+
+    /// The next frame of this pass, or `None` once the pass is done
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, FrameStreamError> {
+        if let Some(frame) = self.peeked.take() {
+            return Ok(Some(frame));
+        }
+        if self.ended {
+            return Ok(None);
+        }
+        match self.frames.recv() {
+            Ok(Item::Frame(frame)) => Ok(Some(frame)),
+            Ok(Item::EndOfPass) | Err(_) => {
+                self.ended = true;
+                Ok(None)
+            }
+            Ok(Item::Failed(e)) => {
+                self.ended = true;
+                Err(e)
+            }
+        }
+    }
+
+    /// The first frame of the pass without taking it, to size packets by
+    pub fn peek(&mut self) -> Result<Option<&[u8]>, FrameStreamError> {
+        if self.peeked.is_none() {
+            self.peeked = self.next_frame()?;
+        }
+        Ok(self.peeked.as_deref())
+    }
+
+    /// Skip what is left of this pass, and move on to the next
+    pub fn finish_pass(&mut self) -> Result<(), FrameStreamError> {
+        while self.next_frame()?.is_some() {}
+        self.ended = false;
+        Ok(())
+    }
+}
+
+/// Frames to send a pass of: encoded before sending, or as it goes
+pub enum FrameSource {
+    Encoded { frames: Vec<Vec<u8>>, next: usize },
+    Streamed(FrameStream),
+}
+
+impl FrameSource {
+    pub fn encoded(frames: Vec<Vec<u8>>) -> Self {
+        Self::Encoded { frames, next: 0 }
+    }
+
+    /// The next frame of this pass, or `None` once the pass is done
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, FrameStreamError> {
+        match self {
+            Self::Encoded { frames, next } => {
+                let frame = frames.get(*next).cloned();
+                *next += usize::from(frame.is_some());
+                Ok(frame)
+            }
+            Self::Streamed(stream) => stream.next_frame(),
+        }
+    }
+
+    /// Skip what is left of this pass, so the next one starts at the top
+    pub fn finish_pass(&mut self) -> Result<(), FrameStreamError> {
+        match self {
+            Self::Encoded { next, .. } => {
+                *next = 0;
+                Ok(())
+            }
+            Self::Streamed(stream) => stream.finish_pass(),
+        }
+    }
+}
+
+/// What a stream's thread reads its passes from
+trait Source: Send + 'static {
+    /// Send one pass of frames, until the file ends or `pass` is full
+    fn send_pass(&mut self, pass: &mut PassSender) -> Result<(), FrameStreamError>;
+
+    /// Back to the start of the file for another pass
+    fn rewind(&mut self) -> Result<(), FrameStreamError>;
+}
+
+/// The queue as one pass sees it
+struct PassSender<'a> {
+    frames: &'a SyncSender<Item>,
+    remaining: usize,
+    /// The stream was dropped
+    closed: bool,
+}
+
+impl PassSender<'_> {
+    /// Queue a frame, waiting for room. False once the pass has all the
+    /// frames it takes, or nobody is reading them
+    fn send(&mut self, frame: Vec<u8>) -> bool {
+        if self.remaining == 0 || self.closed {
+            return false;
+        }
+        if self.frames.send(Item::Frame(frame)).is_err() {
+            self.closed = true;
+            return false;
+        }
+        self.remaining -= 1;
+        self.remaining > 0
+    }
+
+    fn full(&self) -> bool {
+        self.remaining == 0 || self.closed
+    }
+}
+
+/// A stream's thread: every pass, then an end marker after each
+fn run(mut source: impl Source, frames: &SyncSender<Item>, settings: StreamSettings) {
+    loop {
+        let mut pass = PassSender {
+            frames,
+            remaining: settings.limit.unwrap_or(usize::MAX),
+            closed: false,
+        };
+        let result = source.send_pass(&mut pass);
+        if pass.closed {
+            return;
+        }
+        if let Err(e) = result {
+            let _ = frames.send(Item::Failed(e));
+            return;
+        }
+        if frames.send(Item::EndOfPass).is_err() || !settings.loop_audio {
+            return;
+        }
+        if let Err(e) = source.rewind() {
+            let _ = frames.send(Item::Failed(e));
+            return;
+        }
+    }
+}
+
+/// A file decoded, resampled, marked and encoded a block at a time
+struct EncodedSource {
+    reader: AudioReader,
+    /// Sent ahead of the file each pass
+    lead_in: Vec<i16>,
+    codec: CodecType,
+    ffmpeg: bool,
+    frame_samples: usize,
+    sample_rate: u32,
+    watermark: Option<(u16, f64)>,
+}
+
+impl EncodedSource {
+    /// Each pass is marked from the top, as a page encoded whole would be
+    fn watermarker(&self) -> Option<Watermarker> {
+        self.watermark
+            .map(|(id, level)| Watermarker::new(self.sample_rate, id, level))
+    }
+
+    /// The blocks of a pass: the lead-in, then the file
+    fn blocks(&mut self) -> PassBlocks<'_> {
+        PassBlocks {
+            lead_in: (!self.lead_in.is_empty()).then(|| self.lead_in.clone()),
+            reader: &mut self.reader,
+        }
+    }
+
+    fn send_builtin(&mut self, pass: &mut PassSender) -> Result<(), FrameStreamError> {
+        let mut encoder = create_encoder(self.codec)?;
+        let mut marker = self.watermarker();
+        let frame_samples = self.frame_samples;
+        let mut blocks = self.blocks();
+        let mut pending: Vec<i16> = Vec::new();
+        while let Some(mut block) = blocks.next_block()? {
+            if let Some(marker) = marker.as_mut() {
+                marker.mix(&mut block);
+            }
+            pending.extend(block);
+            let whole = pending.len() / frame_samples * frame_samples;
+            for frame in pending[..whole].chunks(frame_samples) {
+                if !pass.send(encoder.encode(frame)?) {
+                    return Ok(());
+                }
+            }
+            pending.drain(..whole);
+        }
+        if !pending.is_empty() {
+            pending.resize(frame_samples, 0);
+            pass.send(encoder.encode(&pending)?);
+        }
+        Ok(())
+    }
+
+    /// Feed ffmpeg from a second thread while this one reads its frames
+    /// back, so neither pipe fills and stalls the other
+    fn send_ffmpeg(&mut self, pass: &mut PassSender) -> Result<(), FrameStreamError> {
+        let mut encoder = FfmpegStreamEncoder::spawn(self.codec, self.frame_samples)?;
+        let mut input = encoder
+            .take_input()
+            .ok_or_else(|| CodecError::EncodeError("Failed to open ffmpeg stdin".into()))?;
+        let mut marker = self.watermarker();
+        let mut blocks = self.blocks();
+
+        thread::scope(|scope| {
+            let writer = scope.spawn(move || -> Result<usize, FrameStreamError> {
+                let mut written = 0;
+                while let Some(mut block) = blocks.next_block()? {
+                    if let Some(marker) = marker.as_mut() {
+                        marker.mix(&mut block);
+                    }
+                    match write_pcm(&mut input, &block) {
+                        Ok(()) => written += block.len(),
+                        // ffmpeg was stopped because the pass is full
+                        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => break,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                Ok(written)
+            });
+
+            let mut read = Ok(());
+            loop {
+                match encoder.next_frame() {
+                    Ok(Some(frame)) => {
+                        if !pass.send(frame) {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        read = Err(e);
+                        break;
+                    }
+                }
+            }
+
+            let join = |writer: thread::ScopedJoinHandle<'_, Result<usize, FrameStreamError>>| {
+                writer
+                    .join()
+                    .unwrap_or_else(|_| Err(CodecError::EncodeError("ffmpeg writer panicked".into()).into()))
+            };
+            if pass.full() || read.is_err() {
+                // Stops ffmpeg, and with it the writer
+                drop(encoder);
+                join(writer)?;
+                return read.map_err(FrameStreamError::from);
+            }
+            let written = join(writer)?;
+            match encoder.finish() {
+                // ffmpeg refuses an empty stream, which just has no frames
+                Err(_) if written == 0 => Ok(()),
+                result => result.map_err(FrameStreamError::from),
+            }
+        })
+    }
+}
+
+/// One pass's read through an [`EncodedSource`]
+struct PassBlocks<'a> {
+    lead_in: Option<Vec<i16>>,
+    reader: &'a mut AudioReader,
+}
+
+impl PassBlocks<'_> {
+    fn next_block(&mut self) -> Result<Option<Vec<i16>>, AudioInputError> {
+        match self.lead_in.take() {
+            Some(block) => Ok(Some(block)),
+            None => self.reader.next_block(),
+        }
+    }
+}
+
+impl Source for EncodedSource {
+    fn send_pass(&mut self, pass: &mut PassSender) -> Result<(), FrameStreamError> {
+        if self.ffmpeg {
+            self.send_ffmpeg(pass)
+        } else {
+            self.send_builtin(pass)
+        }
+    }
+
+    fn rewind(&mut self) -> Result<(), FrameStreamError> {
+        Ok(self.reader.rewind()?)
+    }
+}
+
+/// A file of pre-encoded frames, sent as they are
+struct RawSource {
+    file: BufReader<File>,
+    frame_bytes: usize,
+}
+
+impl Source for RawSource {
+    fn send_pass(&mut self, pass: &mut PassSender) -> Result<(), FrameStreamError> {
+        loop {
+            let mut frame = vec![0u8; self.frame_bytes];
+            let mut filled = 0;
+            while filled < frame.len() {
+                match self.file.read(&mut frame[filled..]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            // A partial last frame keeps its zero padding
+            if filled == 0 || !pass.send(frame) || filled < self.frame_bytes {
+                return Ok(());
+            }
+        }
+    }
+
+    fn rewind(&mut self) -> Result<(), FrameStreamError> {
+        self.file.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::audio_input::{read_audio_file, AudioInput};
+    use crate::cli::watermark;
+    use crate::codec::encode_all;
+
+    /// `secs` of a 16kHz tone sweeping up and down
+    fn write_wav(path: &Path, secs: u32) {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 16000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for i in 0..secs * 16000 {
+            writer.write_sample(((i % 80) as i16 - 40) * 300).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    fn ulaw_spec(path: &Path) -> EncodeSpec<'_> {
+        EncodeSpec {
+            source: path,
+            codec: CodecType::G711Ulaw,
+            encoder: frame_cache::BUILTIN,
+            sample_rate: 8000,
+            frame_samples: 160,
+            input: AudioInput::default(),
+        }
+    }
+
+    fn pass(stream: &mut FrameStream) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+        while let Some(frame) = stream.next_frame().unwrap() {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    #[test]
+    fn test_stream_matches_whole_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.wav");
+        write_wav(&path, 5);
+        let samples = read_audio_file(&path, 8000, &AudioInput::default()).unwrap();
+        let whole = encode_all(CodecType::G711Ulaw, &samples).unwrap();
+
+        let mut stream = FrameStream::encode(&ulaw_spec(&path), Vec::new(), StreamSettings::default()).unwrap();
+        assert_eq!(stream.peek().unwrap(), Some(whole[0].as_slice()));
+        assert_eq!(pass(&mut stream), whole);
+        // One pass only
+        stream.finish_pass().unwrap();
+        assert_eq!(stream.next_frame().unwrap(), None);
+
+        // Marked as the whole file would be, though mixed a block at a time
+        let mut marked = samples.clone();
+        watermark::embed(&mut marked, 8000, 513, -30.0);
+        let settings = StreamSettings {
+            watermark: Some((513, -30.0)),
+            ..StreamSettings::default()
+        };
+        let mut stream = FrameStream::encode(&ulaw_spec(&path), Vec::new(), settings).unwrap();
+        assert_eq!(pass(&mut stream), encode_all(CodecType::G711Ulaw, &marked).unwrap());
+
+        // A capped, looping page repeats its first frames each pass
+        let settings = StreamSettings {
+            loop_audio: true,
+            limit: Some(100),
+            watermark: None,
+        };
+        let mut stream = FrameStream::encode(&ulaw_spec(&path), Vec::new(), settings).unwrap();
+        for _ in 0..3 {
+            assert_eq!(pass(&mut stream), whole[..100]);
+            stream.finish_pass().unwrap();
+        }
+        // Leaving a pass part way skips the rest of it
+        stream.next_frame().unwrap();
+        stream.finish_pass().unwrap();
+        assert_eq!(pass(&mut stream), whole[..100]);
+    }
+
+    #[test]
+    fn test_lead_in_starts_every_pass() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.wav");
+        write_wav(&path, 2);
+        // Not a whole number of frames, so the file's frames straddle it
+        let lead_in: Vec<i16> = (0..1250).map(|i| ((i % 40) as i16 - 20) * 500).collect();
+        let samples = [lead_in.clone(), read_audio_file(&path, 8000, &AudioInput::default()).unwrap()].concat();
+        let whole = encode_all(CodecType::G711Ulaw, &samples).unwrap();
+
+        let settings = StreamSettings {
+            loop_audio: true,
+            ..StreamSettings::default()
+        };
+        let mut stream = FrameStream::encode(&ulaw_spec(&path), lead_in, settings).unwrap();
+        for _ in 0..2 {
+            assert_eq!(pass(&mut stream), whole);
+            stream.finish_pass().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_raw_stream() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.ulaw");
+        let bytes: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mut stream = FrameStream::raw(&path, 160, StreamSettings::default()).unwrap();
+        let frames = pass(&mut stream);
+        assert_eq!(frames.len(), 7);
+        assert_eq!(frames.concat()[..1000], bytes[..]);
+        assert!(frames[6][40..].iter().all(|&b| b == 0));
+    }
+
+    /// Resident memory of this process, in bytes
+    #[cfg(target_os = "linux")]
+    fn rss_bytes() -> usize {
+        let status = std::fs::read_to_string("/proc/self/status").unwrap();
+        let line = status.lines().find(|line| line.starts_with("VmRSS:")).unwrap();
+        let kb: usize = line.split_whitespace().nth(1).unwrap().parse().unwrap();
+        kb * 1024
+    }
+
+    /// Thirty minutes of audio is 58MB as 16kHz samples and twice that again
+    /// encoded whole; streamed, memory barely moves
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_long_file_memory_stays_flat() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("long.wav");
+        write_wav(&path, 30 * 60);
+
+        let before = rss_bytes();
+        let mut peak = before;
+        let mut stream = FrameStream::encode(&ulaw_spec(&path), Vec::new(), StreamSettings::default()).unwrap();
+        let mut frames = 0;
+        while let Some(frame) = stream.next_frame().unwrap() {
+            assert_eq!(frame.len(), 160);
+            frames += 1;
+            if frames % 1000 == 0 {
+                peak = peak.max(rss_bytes());
+            }
+        }
+        assert_eq!(frames, 30 * 60 * 50);
+        let growth = peak.saturating_sub(before);
+        assert!(growth < 50 << 20, "RSS grew {}MB", growth >> 20);
+    }
+}
@@ -0,0 +1,445 @@
+//! Leave/re-join cycling to catch IGMP fast-leave misconfiguration.
+//!
+//! A switch with IGMP fast-leave (immediate leave) stops forwarding a group
+//! to a port as soon as it sees one Leave on it, without a group-specific
+//! query first. That is fine on a port with one receiver, but on a port
+//! toward another switch, a hub or a VM host, one speaker leaving cuts the
+//! page off for every other receiver behind it until they next report.
+//!
+//! `igmp-cycle` holds one socket on the group and watches its arrivals
+//! while a second socket on the same port joins and leaves over and over.
+//! After each leave, the primary socket's flow should carry on undisturbed;
+//! a gap of more than twice the nominal packet spacing in the window before
+//! the re-join counts as an interruption. The same gaps are measured while
+//! the second socket is joined as a baseline, so a lossy stream isn't
+//! mistaken for fast-leave.
+//!
+//! The operating system only sends an IGMP Leave when the last socket on an
+//! interface leaves a group. With both sockets on one interface the switch
+//! never sees a Leave and the check covers the local stack only; to test the
+//! switch, put the cycling socket on a second interface on the same segment
+//! with `--cycle-interface`.
+
+use crate::cli::monitor::{output_json, JsonEvent};
+use crate::cli::transmit::rand_ssrc;
+use crate::network::{create_transmit_socket, MulticastError, MulticastInterface, MulticastSocket, RtpPacket};
+use chrono::Utc;
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::time::{self, MissedTickBehavior};
+use schemars::JsonSchema;
+
+/// Arrivals used to measure the nominal packet spacing before cycling starts
+const WARMUP_PACKETS: usize = 25;
+
+/// Spacing of the helper stream sent with `--transmit`
+const HELPER_SPACING: Duration = Duration::from_millis(20);
+
+/// A gap longer than this many nominal spacings is an interruption
+const GAP_FACTOR: u32 = 2;
+
+#[derive(Error, Debug)]
+pub enum IgmpCycleError {
+    #[error("Multicast error: {0}")]
+    Multicast(#[from] MulticastError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("No stream on {0} within {1}s; start a page or use --transmit")]
+    NoStream(SocketAddrV4, u64),
+
+    #[error("{verdict}: flow interrupted after {interrupted} of {cycles} leaves")]
+    NotPassed { verdict: Verdict, interrupted: usize, cycles: usize },
+}
+
+/// Options for the leave/re-join check
+pub struct IgmpCycleOptions {
+    pub address: Ipv4Addr,
+    pub port: u16,
+    /// Interface the primary socket joins on
+    pub interface: Option<MulticastInterface>,
+    /// Interface the cycling socket joins on; defaults to `interface`
+    pub cycle_interface: Option<MulticastInterface>,
+    pub cycles: u32,
+    /// Time spent left, and then joined, in each cycle
+    pub interval: Duration,
+    /// Send our own 20ms RTP stream to the group
+    pub transmit: bool,
+    pub ttl: u8,
+    /// How long to wait for a stream before giving up
+    pub wait: Duration,
+    pub json: bool,
+    pub quiet: bool,
+}
+
+/// How the primary socket's flow fared in one window
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+pub struct CycleResult {
+    pub cycle: u32,
+    /// Packets that arrived in the window
+    pub packets: usize,
+    /// Longest time without a packet, counting from the last one before the
+    /// window to the window's end
+    pub max_gap_ms: f64,
+    pub interrupted: bool,
+}
+
+/// Overall outcome
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    /// The flow carried on through every leave
+    Pass,
+    /// The flow stopped after leaves but not while joined
+    Fail,
+    /// The flow had gaps while joined too, so leaves can't be blamed
+    Inconclusive,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pass => write!(f, "PASS"),
+            Self::Fail => write!(f, "FAIL"),
+            Self::Inconclusive => write!(f, "INCONCLUSIVE"),
+        }
+    }
+}
+
+/// Everything measured over a run
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct CycleReport {
+    pub address: String,
+    pub port: u16,
+    pub spacing_ms: f64,
+    pub threshold_ms: f64,
+    /// One entry per leave
+    pub cycles: Vec<CycleResult>,
+    /// Joined windows with an interruption
+    pub baseline_interruptions: usize,
+    pub baseline_max_gap_ms: f64,
+    /// Both sockets joined on the same interface, so no Leave reached the switch
+    pub same_interface: bool,
+    pub verdict: Verdict,
+}
+
+impl CycleReport {
+    pub fn interrupted(&self) -> usize {
+        self.cycles.iter().filter(|c| c.interrupted).count()
+    }
+}
+
+/// Median time between arrivals
+fn nominal_spacing(arrivals: &[Instant]) -> Option<Duration> {
+    let mut gaps: Vec<Duration> = arrivals.windows(2).map(|w| w[1].saturating_duration_since(w[0])).collect();
+    if gaps.is_empty() {
+        return None;
+    }
+    gaps.sort_unstable();
+    Some(gaps[gaps.len() / 2])
+}
+
+/// Packets in `[from, to)` and the longest stretch without one, starting at
+/// the last arrival before `from` (or `from`) and ending at `to`
+fn window_gap(arrivals: &[Instant], from: Instant, to: Instant) -> (usize, Duration) {
+    let start = arrivals.partition_point(|&t| t < from);
+    let end = arrivals.partition_point(|&t| t < to);
+    let mut previous = start.checked_sub(1).map_or(from, |i| arrivals[i]);
+    let mut max_gap = Duration::ZERO;
+    for &arrival in arrivals[start..end].iter().chain(std::iter::once(&to)) {
+        max_gap = max_gap.max(arrival.saturating_duration_since(previous));
+        previous = arrival;
+    }
+    (end - start, max_gap)
+}
+
+fn verdict(interrupted: usize, baseline_interruptions: usize) -> Verdict {
+    match (interrupted, baseline_interruptions) {
+        (0, _) => Verdict::Pass,
+        (_, 0) => Verdict::Fail,
+        _ => Verdict::Inconclusive,
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Where the cycling socket is in its join/leave sequence
+enum Phase {
+    /// Waiting for enough of the stream to measure its spacing
+    Warmup,
+    /// Joined since the given time
+    Joined(Instant),
+    /// Left at the given time
+    Left(Instant),
+}
+
+/// Run the leave/re-join check. Anything but a pass is an error, so the
+/// exit status tells scripts the outcome.
+pub async fn run_igmp_cycle(options: IgmpCycleOptions) -> Result<CycleReport, IgmpCycleError> {
+    let destination = SocketAddrV4::new(options.address, options.port);
+    let interface = options.interface.unwrap_or_default();
+    let cycle_interface = options.cycle_interface.unwrap_or(interface);
+    let same_interface = cycle_interface == interface;
+
+    let primary = MulticastSocket::bound_to_group(options.address, options.port, interface).await?;
+    let mut cycler = MulticastSocket::with_interface(options.port, cycle_interface).await?;
+
+    if !options.json && !options.quiet {
+        println!("IGMP leave/re-join check on {}", destination);
+        println!("  Primary socket: joined on {}", interface);
+        println!(
+            "  Cycling socket: joined on {}, {} cycles of {:.1}s left / {:.1}s joined",
+            cycle_interface,
+            options.cycles,
+            options.interval.as_secs_f64(),
+            options.interval.as_secs_f64()
+        );
+        if same_interface {
+            println!("  Note: both sockets share an interface, so the host sends no IGMP Leave;");
+            println!("        this checks the local stack only. Use --cycle-interface to test the switch.");
+        }
+        if options.transmit {
+            println!("  Sending a helper stream every {}ms", HELPER_SPACING.as_millis());
+        }
+        println!();
+    }
+
+    let sender = if options.transmit {
+        Some(create_transmit_socket(options.ttl, None).await?)
+    } else {
+        None
+    };
+    let mut helper = time::interval(HELPER_SPACING);
+    helper.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let ssrc = rand_ssrc();
+    let mut sequence: u16 = 0;
+
+    let started = Instant::now();
+    let mut arrivals: Vec<Instant> = Vec::new();
+    let mut phase = Phase::Warmup;
+    let mut threshold = Duration::MAX;
+    let mut spacing = Duration::ZERO;
+    let mut cycles: Vec<CycleResult> = Vec::new();
+    let mut baseline_interruptions = 0;
+    let mut baseline_max_gap = Duration::ZERO;
+    let mut primary_buf = [0u8; 2048];
+    let mut cycler_buf = [0u8; 2048];
+
+    loop {
+        let deadline = match phase {
+            Phase::Warmup => started + options.wait,
+            Phase::Joined(since) | Phase::Left(since) => since + options.interval,
+        };
+
+        tokio::select! {
+            result = primary.recv_from(&mut primary_buf) => {
+                result?;
+                arrivals.push(Instant::now());
+                if matches!(phase, Phase::Warmup) && arrivals.len() >= WARMUP_PACKETS {
+                    spacing = nominal_spacing(&arrivals).unwrap_or(HELPER_SPACING);
+                    threshold = spacing * GAP_FACTOR;
+                    if !options.json && !options.quiet {
+                        println!(
+                            "Stream found: nominal spacing {:.1}ms, interruption threshold {:.1}ms",
+                            millis(spacing),
+                            millis(threshold)
+                        );
+                    }
+                    cycler.join(options.address)?;
+                    phase = Phase::Joined(Instant::now());
+                }
+            }
+            // The cycling socket only needs to hold membership; drop what it receives
+            _ = cycler.recv_from(&mut cycler_buf) => {}
+            _ = helper.tick(), if sender.is_some() => {
+                if let Some(sender) = &sender {
+                    let timestamp = u32::from(sequence).wrapping_mul(160);
+                    let packet = RtpPacket::build(0, sequence, timestamp, ssrc, &[0xFF; 160], false);
+                    sender.send_to(&packet, SocketAddr::V4(destination)).await?;
+                    sequence = sequence.wrapping_add(1);
+                }
+            }
+            () = time::sleep_until(deadline.into()) => {
+                let now = Instant::now();
+                match phase {
+                    Phase::Warmup => {
+                        return Err(IgmpCycleError::NoStream(destination, options.wait.as_secs()));
+                    }
+                    Phase::Joined(since) => {
+                        let (_, gap) = window_gap(&arrivals, since, now);
+                        baseline_max_gap = baseline_max_gap.max(gap);
+                        if gap > threshold {
+                            baseline_interruptions += 1;
+                        }
+                        cycler.leave(options.address)?;
+                        phase = Phase::Left(now);
+                    }
+                    Phase::Left(since) => {
+                        let (packets, gap) = window_gap(&arrivals, since, now);
+                        let result = CycleResult {
+                            cycle: cycles.len() as u32 + 1,
+                            packets,
+                            max_gap_ms: millis(gap),
+                            interrupted: gap > threshold,
+                        };
+                        if options.json {
+                            output_json(&JsonEvent::IgmpCycle {
+                                timestamp: Utc::now(),
+                                address: options.address.to_string(),
+                                port: options.port,
+                                result,
+                            });
+                        } else if !options.quiet {
+                            println!(
+                                "  Cycle {:>3}: {:>5} packets after leave, max gap {:>7.1}ms  {}",
+                                result.cycle,
+                                result.packets,
+                                result.max_gap_ms,
+                                if result.interrupted { "INTERRUPTED" } else { "ok" }
+                            );
+                        }
+                        cycles.push(result);
+                        if cycles.len() >= options.cycles as usize {
+                            break;
+                        }
+                        cycler.join(options.address)?;
+                        phase = Phase::Joined(now);
+                    }
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                if matches!(phase, Phase::Warmup) {
+                    return Err(IgmpCycleError::NoStream(destination, started.elapsed().as_secs()));
+                }
+                break;
+            }
+        }
+    }
+
+    let interrupted = cycles.iter().filter(|c| c.interrupted).count();
+    let report = CycleReport {
+        address: options.address.to_string(),
+        port: options.port,
+        spacing_ms: millis(spacing),
+        threshold_ms: millis(threshold),
+        cycles,
+        baseline_interruptions,
+        baseline_max_gap_ms: millis(baseline_max_gap),
+        same_interface,
+        verdict: verdict(interrupted, baseline_interruptions),
+    };
+
+    if options.json {
+        output_json(&JsonEvent::IgmpCycleReport {
+            timestamp: Utc::now(),
+            report: report.clone(),
+        });
+    } else if !options.quiet {
+        print_report(&report);
+    }
+
+    match report.verdict {
+        Verdict::Pass => Ok(report),
+        verdict => Err(IgmpCycleError::NotPassed {
+            verdict,
+            interrupted: report.interrupted(),
+            cycles: report.cycles.len(),
+        }),
+    }
+}
+
+fn print_report(report: &CycleReport) {
+    let leaves = report.cycles.len();
+    println!();
+    println!(
+        "Baseline (joined): max gap {:.1}ms, {} interrupted windows",
+        report.baseline_max_gap_ms, report.baseline_interruptions
+    );
+    match report.verdict {
+        Verdict::Pass => println!("Verdict: {} - no interruption after {} leaves", report.verdict, leaves),
+        Verdict::Fail => {
+            println!(
+                "Verdict: {} - flow stopped after {} of {} leaves",
+                report.verdict,
+                report.interrupted(),
+                leaves
+            );
+            println!("  The switch looks to be using IGMP fast-leave on a port with more than one receiver.");
+        }
+        Verdict::Inconclusive => {
+            println!(
+                "Verdict: {} - {} of {} leaves interrupted, but gaps also occurred while joined",
+                report.verdict,
+                report.interrupted(),
+                leaves
+            );
+            println!("  The stream is too irregular to blame the leaves.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Arrivals every 20ms from `base`, skipping the listed packet indices
+    fn arrivals(base: Instant, count: u64, missing: impl Fn(u64) -> bool) -> Vec<Instant> {
+        (0..count)
+            .filter(|&i| !missing(i))
+            .map(|i| base + Duration::from_millis(i * 20))
+            .collect()
+    }
+
+    #[test]
+    fn test_nominal_spacing_ignores_outliers() {
+        let base = Instant::now();
+        let mut times = arrivals(base, 50, |i| i == 10);
+        times.push(base + Duration::from_secs(5));
+        assert_eq!(nominal_spacing(&times), Some(Duration::from_millis(20)));
+        assert_eq!(nominal_spacing(&times[..1]), None);
+    }
+
+    #[test]
+    fn test_window_gap() {
+        let base = Instant::now();
+        let ms = |n: u64| base + Duration::from_millis(n);
+
+        // Steady flow through the window
+        let steady = arrivals(base, 100, |_| false);
+        let (packets, gap) = window_gap(&steady, ms(500), ms(1000));
+        assert_eq!(packets, 25);
+        assert_eq!(gap, Duration::from_millis(20));
+
+        // The flow stops 60ms after a leave at 500ms and comes back at 900ms
+        let cut = arrivals(base, 100, |i| (28..45).contains(&i));
+        let (packets, gap) = window_gap(&cut, ms(500), ms(1000));
+        assert_eq!(packets, 8);
+        assert_eq!(gap, Duration::from_millis(360));
+
+        // The flow never comes back; the silence runs to the window's end
+        let stopped = arrivals(base, 26, |_| false);
+        let (packets, gap) = window_gap(&stopped, ms(500), ms(1000));
+        assert_eq!(packets, 1);
+        assert_eq!(gap, Duration::from_millis(500));
+
+        // A gap spanning the start of the window counts from the last packet before it
+        let spanning = arrivals(base, 100, |i| (24..27).contains(&i));
+        let (_, gap) = window_gap(&spanning, ms(500), ms(1000));
+        assert_eq!(gap, Duration::from_millis(80));
+    }
+
+    #[test]
+    fn test_verdict() {
+        assert_eq!(verdict(0, 0), Verdict::Pass);
+        assert_eq!(verdict(0, 3), Verdict::Pass);
+        assert_eq!(verdict(4, 0), Verdict::Fail);
+        assert_eq!(verdict(4, 1), Verdict::Inconclusive);
+    }
+}
@@ -0,0 +1,432 @@
+//! Watching IGMP during a test (`test --observe-igmp`).
+//!
+//! When a test detects no pages, the first question is whether the join
+//! got anywhere. A raw IGMP socket sees the membership reports this host
+//! sends for the test's groups and the queries that arrive from the
+//! network's querier, which between them say which side to look at: joins
+//! going out with a querier answering point at the sender, joins going out
+//! with no querier point at switch snooping dropping the group. Version 2
+//! reports go to the group itself and loop back to its members. Version 3
+//! reports go to 224.0.0.22, which the watch joins for the purpose; reports
+//! from other hosts there are told apart by their source address.
+//!
+//! The raw socket needs `CAP_NET_RAW` or root. Without it the watch is
+//! empty and says why, and the test runs as it would have anyway. The
+//! socket behind the watch is an [`IgmpSource`], so tests can stand in for
+//! it.
+
+use crate::network::multicast::{join_group, resolve_source_address};
+use crate::network::MulticastInterface;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Read};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use thiserror::Error;
+use tracing::debug;
+
+/// IP protocol number of IGMP
+const IPPROTO_IGMP: i32 = 2;
+
+/// Where IGMP version 3 hosts send their reports (RFC 3376)
+const IGMPV3_REPORTS: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 22);
+
+/// IGMP message types
+const MEMBERSHIP_QUERY: u8 = 0x11;
+const V1_REPORT: u8 = 0x12;
+const V2_REPORT: u8 = 0x16;
+const V3_REPORT: u8 = 0x22;
+
+/// Version 3 group record types that leave the group: INCLUDE mode with no
+/// sources, and blocking sources
+const MODE_IS_INCLUDE: u8 = 1;
+const CHANGE_TO_INCLUDE: u8 = 3;
+const BLOCK_OLD_SOURCES: u8 = 6;
+
+#[derive(Error, Debug)]
+pub enum IgmpWatchError {
+    #[error("watching IGMP needs CAP_NET_RAW or root")]
+    NotPermitted,
+
+    #[error("Socket error: {0}")]
+    Socket(#[from] io::Error),
+}
+
+/// An IGMP message, as far as the watch cares
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IgmpMessage {
+    /// A membership query; general if `group` is unspecified
+    Query { querier: Ipv4Addr, group: Ipv4Addr },
+    /// A membership report, with the groups it keeps joined
+    Report { reporter: Ipv4Addr, groups: Vec<Ipv4Addr> },
+}
+
+/// Read an IGMP message from a raw socket's datagram, IP header and all.
+/// `None` for leaves, other message types, and anything truncated
+pub fn parse(datagram: &[u8]) -> Option<IgmpMessage> {
+    let header_len = usize::from(datagram.first()? & 0x0F) * 4;
+    let source = address(datagram.get(12..16)?)?;
+    let igmp = datagram.get(header_len..)?;
+    match *igmp.first()? {
+        MEMBERSHIP_QUERY => Some(IgmpMessage::Query {
+            querier: source,
+            group: address(igmp.get(4..8)?)?,
+        }),
+        V1_REPORT | V2_REPORT => Some(IgmpMessage::Report {
+            reporter: source,
+            groups: vec![address(igmp.get(4..8)?)?],
+        }),
+        V3_REPORT => {
+            let records = u16::from_be_bytes(igmp.get(6..8)?.try_into().ok()?);
+            let mut offset = 8;
+            let mut groups = Vec::new();
+            for _ in 0..records {
+                let record = igmp.get(offset..offset + 8)?;
+                let (kind, aux_words, sources) = (record[0], record[1], u16::from_be_bytes([record[2], record[3]]));
+                let leaving = kind == BLOCK_OLD_SOURCES || (matches!(kind, MODE_IS_INCLUDE | CHANGE_TO_INCLUDE) && sources == 0);
+                if !leaving {
+                    groups.push(address(&record[4..8])?);
+                }
+                offset += 8 + 4 * usize::from(sources) + 4 * usize::from(aux_words);
+            }
+            Some(IgmpMessage::Report { reporter: source, groups })
+        }
+        _ => None,
+    }
+}
+
+fn address(bytes: &[u8]) -> Option<Ipv4Addr> {
+    <[u8; 4]>::try_from(bytes).ok().map(Ipv4Addr::from)
+}
+
+/// Where the watch's messages come from
+pub trait IgmpSource: Send {
+    /// The next message waiting, without blocking
+    fn try_next(&mut self) -> Option<IgmpMessage>;
+}
+
+/// A raw IGMP socket, joined to 224.0.0.22 so version 3 reports loop back to it
+pub struct RawIgmpSocket {
+    socket: Socket,
+    buf: Vec<u8>,
+}
+
+impl RawIgmpSocket {
+    pub fn open(interface: MulticastInterface) -> Result<Self, IgmpWatchError> {
+        let socket = Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::from(IPPROTO_IGMP))).map_err(|e| match e.kind() {
+            io::ErrorKind::PermissionDenied => IgmpWatchError::NotPermitted,
+            _ => IgmpWatchError::Socket(e),
+        })?;
+        socket.set_nonblocking(true)?;
+        join_group(&socket, IGMPV3_REPORTS, interface)?;
+        Ok(Self { socket, buf: vec![0; 1500] })
+    }
+}
+
+impl IgmpSource for RawIgmpSocket {
+    fn try_next(&mut self) -> Option<IgmpMessage> {
+        loop {
+            let len = match (&self.socket).read(&mut self.buf) {
+                Ok(len) => len,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return None,
+                Err(e) => {
+                    debug!("Error reading the IGMP socket: {}", e);
+                    return None;
+                }
+            };
+            if let Some(message) = self.buf.get(..len).and_then(parse) {
+                return Some(message);
+            }
+        }
+    }
+}
+
+/// What was seen of one group
+#[derive(Debug, Clone, Copy, Default)]
+struct GroupActivity {
+    reports: u64,
+    last_report: Option<DateTime<Utc>>,
+    /// A query for this group in particular
+    queried: bool,
+}
+
+/// IGMP seen over a test run
+pub struct IgmpWatch {
+    source: Box<dyn IgmpSource>,
+    /// Why there is no source
+    unavailable: Option<String>,
+    /// Addresses this host reports from; any reporter counts if empty
+    local: HashSet<Ipv4Addr>,
+    groups: HashMap<Ipv4Addr, GroupActivity>,
+    /// Sender of the latest query
+    querier: Option<Ipv4Addr>,
+    general_query: bool,
+}
+
+impl IgmpWatch {
+    pub fn new(source: Box<dyn IgmpSource>, local: HashSet<Ipv4Addr>) -> Self {
+        Self {
+            source,
+            unavailable: None,
+            local,
+            groups: HashMap::new(),
+            querier: None,
+            general_query: false,
+        }
+    }
+
+    /// Watch with a raw socket, opened before `groups` are joined so their
+    /// first reports are seen. Without one the watch stays empty, and the
+    /// reason is reported rather than failing the run
+    pub fn open(groups: impl IntoIterator<Item = Ipv4Addr>, interface: Option<MulticastInterface>) -> Self {
+        let local = match interface {
+            Some(MulticastInterface::Address(address)) if !address.is_unspecified() => HashSet::from([address]),
+            // Whatever the routes to the groups go out from
+            _ => groups
+                .into_iter()
+                .filter_map(|group| resolve_source_address(SocketAddrV4::new(group, 9)).ok())
+                .collect(),
+        };
+        let (source, unavailable): (Box<dyn IgmpSource>, _) = match RawIgmpSocket::open(interface.unwrap_or_default()) {
+            Ok(socket) => (Box::new(socket), None),
+            Err(e) => (Box::new(NoSource), Some(e.to_string())),
+        };
+        Self { unavailable, ..Self::new(source, local) }
+    }
+
+    /// Why nothing is being watched, if it isn't
+    pub fn unavailable(&self) -> Option<&str> {
+        self.unavailable.as_deref()
+    }
+
+    /// Take in whatever has arrived, as of `now`
+    pub fn poll(&mut self, now: DateTime<Utc>) {
+        while let Some(message) = self.source.try_next() {
+            self.observe(message, now);
+        }
+    }
+
+    fn observe(&mut self, message: IgmpMessage, at: DateTime<Utc>) {
+        match message {
+            IgmpMessage::Query { querier, group } => {
+                self.querier = Some(querier);
+                if group.is_unspecified() {
+                    self.general_query = true;
+                } else {
+                    self.groups.entry(group).or_default().queried = true;
+                }
+            }
+            IgmpMessage::Report { reporter, groups } => {
+                if !self.local.is_empty() && !self.local.contains(&reporter) {
+                    return;
+                }
+                for group in groups {
+                    let activity = self.groups.entry(group).or_default();
+                    activity.reports += 1;
+                    activity.last_report = Some(at);
+                }
+            }
+        }
+    }
+
+    /// What was seen for each of `endpoints`, by name and group
+    pub fn summary(&self, endpoints: impl IntoIterator<Item = (String, Ipv4Addr)>) -> IgmpObservation {
+        if let Some(ref reason) = self.unavailable {
+            return IgmpObservation {
+                unavailable: Some(reason.clone()),
+                querier: None,
+                endpoints: BTreeMap::new(),
+            };
+        }
+        let endpoints = endpoints
+            .into_iter()
+            .map(|(endpoint, group)| {
+                let activity = self.groups.get(&group).copied().unwrap_or_default();
+                let igmp = EndpointIgmp {
+                    reports_sent: activity.reports,
+                    querier_observed: self.general_query || activity.queried,
+                    last_report: activity.last_report,
+                };
+                (endpoint, igmp)
+            })
+            .collect();
+        IgmpObservation {
+            unavailable: None,
+            querier: self.querier,
+            endpoints,
+        }
+    }
+}
+
+/// Stands in where the raw socket couldn't be opened
+struct NoSource;
+
+impl IgmpSource for NoSource {
+    fn try_next(&mut self) -> Option<IgmpMessage> {
+        None
+    }
+}
+
+/// IGMP seen over the run, kept in the summary's metadata
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct IgmpObservation {
+    /// Why IGMP couldn't be watched, such as missing privileges; nothing
+    /// else is filled in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unavailable: Option<String>,
+    /// Sender of the latest query seen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub querier: Option<Ipv4Addr>,
+    #[serde(default)]
+    pub endpoints: BTreeMap<String, EndpointIgmp>,
+}
+
+impl IgmpObservation {
+    /// What the IGMP seen says about each endpoint, for a run that
+    /// detected no pages
+    pub fn diagnostics(&self) -> Vec<String> {
+        if let Some(ref reason) = self.unavailable {
+            return vec![format!("IGMP health: not observed ({})", reason)];
+        }
+        self.endpoints
+            .iter()
+            .map(|(endpoint, igmp)| format!("IGMP health for {}: {}", endpoint, igmp.assessment()))
+            .collect()
+    }
+}
+
+/// IGMP seen for one endpoint's group
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct EndpointIgmp {
+    /// Membership reports this host sent for the group, first joins and
+    /// answers to queries alike
+    pub reports_sent: u64,
+    /// A general query, or one for this group, arrived
+    pub querier_observed: bool,
+    /// When the latest report was seen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_report: Option<DateTime<Utc>>,
+}
+
+impl EndpointIgmp {
+    /// Where to look for a group that got no pages
+    pub fn assessment(&self) -> &'static str {
+        match (self.reports_sent > 0, self.querier_observed) {
+            (true, true) => "joins sent, querier present; sender-side problem likely",
+            (true, false) => "joins sent, no querier observed; switch snooping likely dropping group",
+            (false, true) => "querier present but no joins seen; the join may not have left this host",
+            (false, false) => "no joins or querier observed; check the interface the group was joined on",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Hands out a fixed run of messages
+    struct Scripted(VecDeque<IgmpMessage>);
+
+    impl IgmpSource for Scripted {
+        fn try_next(&mut self) -> Option<IgmpMessage> {
+            self.0.pop_front()
+        }
+    }
+
+    const LOCAL: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 2);
+    const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 123, 99);
+
+    fn diagnostics(messages: Vec<IgmpMessage>) -> Vec<String> {
+        let mut watch = IgmpWatch::new(Box::new(Scripted(messages.into())), HashSet::from([LOCAL]));
+        watch.poll(Utc::now());
+        watch.summary([("224.0.123.99:5004".to_string(), GROUP)]).diagnostics()
+    }
+
+    /// An IPv4 header with the router alert option, as IGMP is sent
+    fn datagram(source: Ipv4Addr, igmp: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x46, 0xC0, 0, 0, 0, 0, 0, 0, 1, 2, 0, 0];
+        data.extend_from_slice(&source.octets());
+        data.extend_from_slice(&[224, 0, 0, 22, 0x94, 0x04, 0, 0]);
+        data.extend_from_slice(igmp);
+        data
+    }
+
+    #[test]
+    fn test_parse() {
+        // A Linux host joining 224.0.123.99, and 224.0.0.22 alongside it
+        let v3 = [
+            0x22, 0x00, 0x9a, 0x82, 0x00, 0x00, 0x00, 0x02, 0x04, 0x00, 0x00, 0x00, 224, 0, 123, 99, 0x04, 0x00, 0x00, 0x00, 224, 0, 0, 22,
+        ];
+        assert_eq!(
+            parse(&datagram(LOCAL, &v3)),
+            Some(IgmpMessage::Report { reporter: LOCAL, groups: vec![GROUP, IGMPV3_REPORTS] })
+        );
+        // Leaving: TO_INCLUDE with no sources
+        let leave = [0x22, 0, 0, 0, 0, 0, 0, 1, 0x03, 0, 0, 0, 224, 0, 123, 99];
+        assert_eq!(parse(&datagram(LOCAL, &leave)), Some(IgmpMessage::Report { reporter: LOCAL, groups: vec![] }));
+
+        let v2 = [0x16, 0, 0, 0, 224, 0, 123, 99];
+        assert_eq!(parse(&datagram(LOCAL, &v2)), Some(IgmpMessage::Report { reporter: LOCAL, groups: vec![GROUP] }));
+        let querier = Ipv4Addr::new(192, 0, 2, 1);
+        let general = [0x11, 100, 0, 0, 0, 0, 0, 0];
+        assert_eq!(
+            parse(&datagram(querier, &general)),
+            Some(IgmpMessage::Query { querier, group: Ipv4Addr::UNSPECIFIED })
+        );
+
+        // Cut short, or not IGMP the watch reads
+        assert_eq!(parse(&datagram(LOCAL, &v3[..20])), None);
+        assert_eq!(parse(&datagram(LOCAL, &[0x17, 0, 0, 0, 224, 0, 123, 99])), None);
+        assert_eq!(parse(&[0x45]), None);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let querier = Ipv4Addr::new(192, 0, 2, 1);
+        let report = IgmpMessage::Report { reporter: LOCAL, groups: vec![GROUP] };
+        let general = IgmpMessage::Query { querier, group: Ipv4Addr::UNSPECIFIED };
+
+        let both = diagnostics(vec![report.clone(), general.clone(), report.clone()]);
+        assert_eq!(both, ["IGMP health for 224.0.123.99:5004: joins sent, querier present; sender-side problem likely"]);
+
+        let no_querier = diagnostics(vec![report.clone()]);
+        assert_eq!(
+            no_querier,
+            ["IGMP health for 224.0.123.99:5004: joins sent, no querier observed; switch snooping likely dropping group"]
+        );
+
+        // Another host's report for the group isn't this one's join; a
+        // query for the group in particular is a querier all the same
+        let elsewhere = IgmpMessage::Report { reporter: Ipv4Addr::new(192, 0, 2, 9), groups: vec![GROUP] };
+        let specific = IgmpMessage::Query { querier, group: GROUP };
+        assert_eq!(
+            diagnostics(vec![elsewhere, specific]),
+            ["IGMP health for 224.0.123.99:5004: querier present but no joins seen; the join may not have left this host"]
+        );
+        assert_eq!(
+            diagnostics(vec![]),
+            ["IGMP health for 224.0.123.99:5004: no joins or querier observed; check the interface the group was joined on"]
+        );
+
+        let mut unprivileged = IgmpWatch::new(Box::new(NoSource), HashSet::new());
+        unprivileged.unavailable = Some(IgmpWatchError::NotPermitted.to_string());
+        assert_eq!(
+            unprivileged.summary([("224.0.123.99:5004".to_string(), GROUP)]).diagnostics(),
+            ["IGMP health: not observed (watching IGMP needs CAP_NET_RAW or root)"]
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let at = Utc::now();
+        let mut watch = IgmpWatch::new(Box::new(Scripted(VecDeque::new())), HashSet::new());
+        watch.observe(IgmpMessage::Report { reporter: LOCAL, groups: vec![GROUP] }, at);
+        let summary = watch.summary([("a".to_string(), GROUP), ("b".to_string(), Ipv4Addr::new(224, 0, 123, 98))]);
+        assert_eq!(summary.querier, None);
+        assert_eq!(summary.endpoints["a"], EndpointIgmp { reports_sent: 1, querier_observed: false, last_report: Some(at) });
+        assert_eq!(summary.endpoints["b"].reports_sent, 0);
+    }
+}
@@ -0,0 +1,408 @@
+//! Live packet inspection (`--inspect`)
+//!
+//! When a stream won't parse, the receive loops only log it at debug level.
+//! With `--inspect`, `monitor` and `polycom-monitor` also print a line for
+//! each datagram: where it came from, its size and the time since the one
+//! before, and a decode attempt. Version 2 packets are read as RTP, the
+//! Polycom op codes as Polycom, and anything else is shown as hex and ASCII.
+//! Decoding here never fails and never touches page processing, which sees
+//! the same datagram afterwards.
+
+use crate::codec::CodecSpec;
+use crate::network::polycom::{AudioHeader, PacketType, PolycomHeader, OP_ALERT, OP_END, OP_TRANSMIT};
+use crate::network::rtp::RtpPacket;
+use chrono::{DateTime, Local};
+use std::fmt::Write as _;
+use std::net::{IpAddr, SocketAddr, SocketAddrV4};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Bytes of an unrecognized datagram shown as hex
+const HEXDUMP_BYTES: usize = 64;
+
+/// Which datagrams `--inspect-filter` lets through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InspectFilter {
+    /// `src=ADDR`
+    Address(IpAddr),
+    /// `src=ADDR:PORT`
+    Socket(SocketAddr),
+}
+
+impl InspectFilter {
+    pub fn matches(self, source: SocketAddr) -> bool {
+        match self {
+            Self::Address(address) => source.ip() == address,
+            Self::Socket(socket) => source == socket,
+        }
+    }
+}
+
+impl FromStr for InspectFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s
+            .trim()
+            .strip_prefix("src=")
+            .ok_or_else(|| format!("expected src=ADDR or src=ADDR:PORT, got '{}'", s))?;
+        if let Ok(socket) = value.parse::<SocketAddr>() {
+            return Ok(Self::Socket(socket));
+        }
+        value
+            .parse::<IpAddr>()
+            .map(Self::Address)
+            .map_err(|_| format!("'{}' is not an address or address:port", value))
+    }
+}
+
+/// What `--inspect` shows
+#[derive(Debug, Clone, Default)]
+pub struct InspectOptions {
+    pub filter: Option<InspectFilter>,
+    /// Packets shown at most, per second
+    pub rate: Option<u32>,
+    /// Stop inspecting after this many packets
+    pub count: Option<u64>,
+}
+
+/// Prints datagrams as they arrive, for `--inspect`
+pub struct Inspector {
+    options: InspectOptions,
+    last_arrival: Option<Instant>,
+    shown: u64,
+    /// Start of the current second for `rate`, and packets shown in it
+    window: Option<(Instant, u32)>,
+    /// Packets left out by `rate` since the last one shown
+    skipped: u64,
+    /// Write to stderr, so `--json` output stays clean
+    to_stderr: bool,
+}
+
+impl Inspector {
+    pub fn new(options: InspectOptions, to_stderr: bool) -> Self {
+        Self {
+            options,
+            last_arrival: None,
+            shown: 0,
+            window: None,
+            skipped: 0,
+            to_stderr,
+        }
+    }
+
+    /// Print a received datagram, if the filter, rate and count allow
+    pub fn inspect(&mut self, data: &[u8], source: SocketAddr, destination: SocketAddrV4, received_at: Instant, wall_time: SystemTime) {
+        for line in self.lines(data, source, destination, received_at, wall_time) {
+            if self.to_stderr {
+                eprintln!("{}", line);
+            } else {
+                println!("{}", line);
+            }
+        }
+    }
+
+    /// The lines [`Self::inspect`] prints; none when the datagram is filtered out
+    pub fn lines(
+        &mut self,
+        data: &[u8],
+        source: SocketAddr,
+        destination: SocketAddrV4,
+        received_at: Instant,
+        wall_time: SystemTime,
+    ) -> Vec<String> {
+        if self.options.count.is_some_and(|count| self.shown >= count) {
+            return Vec::new();
+        }
+        if self.options.filter.is_some_and(|filter| !filter.matches(source)) {
+            return Vec::new();
+        }
+
+        let delta = self.last_arrival.map(|last| received_at.saturating_duration_since(last));
+        self.last_arrival = Some(received_at);
+        if !self.within_rate(received_at) {
+            self.skipped += 1;
+            return Vec::new();
+        }
+
+        let time: DateTime<Local> = wall_time.into();
+        let mut header = format!(
+            "{} {} -> {} {}B {}",
+            time.format("%H:%M:%S%.3f"),
+            source,
+            destination,
+            data.len(),
+            delta.map_or_else(|| "first".to_string(), |delta| format!("+{:.1}ms", delta.as_secs_f64() * 1000.0))
+        );
+        if self.skipped > 0 {
+            let _ = write!(header, " ({} not shown)", self.skipped);
+            self.skipped = 0;
+        }
+
+        let mut description = describe(data).into_iter();
+        let mut lines = vec![format!("{} {}", header, description.next().unwrap_or_default())];
+        lines.extend(description.map(|line| format!("    {}", line)));
+
+        self.shown += 1;
+        if self.options.count == Some(self.shown) {
+            lines.push(format!("Inspected {} packets; monitoring continues without --inspect output", self.shown));
+        }
+        lines
+    }
+
+    /// Whether `rate` leaves room for another packet in the current second
+    fn within_rate(&mut self, now: Instant) -> bool {
+        let Some(rate) = self.options.rate else {
+            return true;
+        };
+        match &mut self.window {
+            Some((start, shown)) if now.saturating_duration_since(*start) < Duration::from_secs(1) => {
+                *shown += 1;
+                *shown <= rate
+            }
+            window => {
+                *window = Some((now, 1));
+                true
+            }
+        }
+    }
+}
+
+/// A decode attempt of one datagram. The first line is the summary; the rest
+/// (if any) are a hex dump
+pub fn describe(data: &[u8]) -> Vec<String> {
+    match data.first() {
+        Some(&first) if first >> 6 == 2 => describe_rtp(data),
+        Some(&(OP_ALERT | OP_TRANSMIT | OP_END)) => describe_polycom(data),
+        _ => {
+            let mut lines = vec!["not RTP or Polycom".to_string()];
+            lines.extend(hexdump(data));
+            lines
+        }
+    }
+}
+
+fn describe_rtp(data: &[u8]) -> Vec<String> {
+    // The source is only kept on the parsed packet, so any address will do
+    let packet = match RtpPacket::parse(data, SocketAddr::from(([0, 0, 0, 0], 0))) {
+        Ok(packet) => packet,
+        Err(e) => {
+            let mut lines = vec![format!("RTP v2, malformed: {}", e)];
+            lines.extend(hexdump(data));
+            return lines;
+        }
+    };
+
+    let header = &packet.header;
+    let mut line = format!("RTP pt={}", header.payload_type);
+    if let Some(spec) = CodecSpec::from_payload_type(header.payload_type) {
+        let _ = write!(line, " ({})", spec);
+    }
+    let _ = write!(
+        line,
+        " seq={} ts={} ssrc=0x{:08x}",
+        header.sequence_number, header.timestamp, header.ssrc
+    );
+    if header.marker {
+        line.push_str(" M");
+    }
+    if header.csrc_count > 0 {
+        let _ = write!(line, " csrc={}", header.csrc_count);
+    }
+    if header.extension {
+        line.push_str(" ext");
+    }
+    if header.padding {
+        line.push_str(" pad");
+    }
+    let _ = write!(line, " payload={}B", packet.payload.len());
+    vec![line]
+}
+
+fn describe_polycom(data: &[u8]) -> Vec<String> {
+    let (header, header_len) = match PolycomHeader::parse(data) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            let mut lines = vec![format!("Polycom op 0x{:02x}, malformed: {}", data[0], e)];
+            lines.extend(hexdump(data));
+            return lines;
+        }
+    };
+
+    let kind = match header.packet_type {
+        PacketType::Alert => "ALERT",
+        PacketType::Transmit => "TRANSMIT",
+        PacketType::End => "END",
+    };
+    let serial = header.host_serial.map(|b| format!("{:02x}", b)).join(":");
+    let mut line = format!("Polycom {} ch={} serial={} caller={:?}", kind, header.channel, serial, header.caller_id);
+    if header.packet_type != PacketType::Transmit {
+        return vec![line];
+    }
+
+    let payload = &data[header_len..];
+    match AudioHeader::parse(payload) {
+        Ok(audio) => {
+            let audio_len = payload.len() - AudioHeader::len();
+            let _ = write!(
+                line,
+                " codec={} flags=0x{:02x} count={} audio={}B ({} frames)",
+                audio.codec,
+                audio.flags,
+                audio.sample_count,
+                audio_len,
+                audio_len / audio.codec.frame_size()
+            );
+            vec![line]
+        }
+        Err(e) => {
+            let _ = write!(line, ", audio header malformed: {}", e);
+            let mut lines = vec![line];
+            lines.extend(hexdump(payload));
+            lines
+        }
+    }
+}
+
+/// The first [`HEXDUMP_BYTES`] bytes, 16 to a line with their ASCII
+fn hexdump(data: &[u8]) -> Vec<String> {
+    let shown = &data[..data.len().min(HEXDUMP_BYTES)];
+    let mut lines: Vec<String> = shown
+        .chunks(16)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let mut hex = String::with_capacity(49);
+            for (i, byte) in chunk.iter().enumerate() {
+                if i == 8 {
+                    hex.push(' ');
+                }
+                let _ = write!(hex, "{:02x} ", byte);
+            }
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { char::from(b) } else { '.' })
+                .collect();
+            format!("{:04x}  {:<49} |{}|", row * 16, hex, ascii)
+        })
+        .collect();
+    if data.len() > shown.len() {
+        lines.push(format!("... {} more bytes", data.len() - shown.len()));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::polycom::{PolycomCodec, PolycomPacketBuilder};
+
+    fn source(host: u8) -> SocketAddr {
+        SocketAddr::from(([10, 1, 2, host], 40000))
+    }
+
+    fn destination() -> SocketAddrV4 {
+        "224.0.1.1:5004".parse().unwrap()
+    }
+
+    #[test]
+    fn test_describe_rtp() {
+        let data = RtpPacket::build(0, 1234, 160_000, 0x1234_5678, &[0xff; 160], true);
+        assert_eq!(
+            describe(&data),
+            ["RTP pt=0 (G.711 u-law) seq=1234 ts=160000 ssrc=0x12345678 M payload=160B"]
+        );
+
+        // Version 2, but the CSRC list runs past the end
+        let mut truncated = data[..12].to_vec();
+        truncated[0] |= 0x02;
+        assert_eq!(
+            describe(&truncated),
+            [
+                "RTP v2, malformed: Packet truncated: expected 20 bytes, got 12",
+                "0000  82 80 04 d2 00 02 71 00  12 34 56 78              |......q..4Vx|",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_describe_polycom() {
+        let mut builder = PolycomPacketBuilder::new(26, [0xde, 0xad, 0xbe, 0xef], "MPS-IP".to_string(), PolycomCodec::G722);
+        assert_eq!(
+            describe(&builder.build_alert().unwrap()),
+            [r#"Polycom ALERT ch=26 serial=de:ad:be:ef caller="MPS-IP""#]
+        );
+        builder.build_transmit(&[0x55; 160]).unwrap();
+        let mut transmit = builder.build_transmit(&[0x55; 160]).unwrap();
+        // The builder starts its count at random
+        transmit[22..26].copy_from_slice(&160u32.to_be_bytes());
+        assert_eq!(
+            describe(&transmit),
+            [r#"Polycom TRANSMIT ch=26 serial=de:ad:be:ef caller="MPS-IP" codec=G.722 flags=0x00 count=160 audio=320B (2 frames)"#]
+        );
+        assert_eq!(
+            describe(&builder.build_end().unwrap()),
+            [r#"Polycom END ch=26 serial=de:ad:be:ef caller="MPS-IP""#]
+        );
+    }
+
+    #[test]
+    fn test_describe_garbage() {
+        let mut data = b"GET / HTTP/1.1\r\nHost: paging\r\n\r\n".to_vec();
+        data.extend([0u8; 40]);
+        assert_eq!(
+            describe(&data),
+            [
+                "not RTP or Polycom",
+                "0000  47 45 54 20 2f 20 48 54  54 50 2f 31 2e 31 0d 0a  |GET / HTTP/1.1..|",
+                "0010  48 6f 73 74 3a 20 70 61  67 69 6e 67 0d 0a 0d 0a  |Host: paging....|",
+                "0020  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|",
+                "0030  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|",
+                "... 8 more bytes",
+            ]
+        );
+        assert_eq!(describe(&[]), ["not RTP or Polycom"]);
+    }
+
+    #[test]
+    fn test_inspector_filter_rate_and_count() {
+        let options = InspectOptions {
+            filter: Some("src=10.1.2.3".parse().unwrap()),
+            rate: Some(2),
+            count: Some(3),
+        };
+        let mut inspector = Inspector::new(options, false);
+        let base = Instant::now();
+        let wall = SystemTime::now();
+        let data = RtpPacket::build(0, 1, 0, 1, &[0; 160], false);
+        let mut lines = |host: u8, ms: u64| {
+            inspector.lines(&data, source(host), destination(), base + Duration::from_millis(ms), wall)
+        };
+
+        let first = lines(3, 0);
+        assert_eq!(first.len(), 1);
+        assert!(first[0].contains("10.1.2.3:40000 -> 224.0.1.1:5004 172B first RTP pt=0"), "{}", first[0]);
+        assert!(lines(4, 10).is_empty(), "other sources are filtered out");
+        assert!(lines(3, 20)[0].contains(" +20.0ms RTP"));
+        // Two a second: the third and fourth within the second are skipped
+        assert!(lines(3, 40).is_empty());
+        assert!(lines(3, 60).is_empty());
+        let resumed = lines(3, 1000);
+        assert!(resumed[0].contains(" +940.0ms (2 not shown) RTP"), "{}", resumed[0]);
+        assert_eq!(resumed[1], "Inspected 3 packets; monitoring continues without --inspect output");
+        assert!(lines(3, 2000).is_empty(), "stops after --inspect-count");
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(
+            "src=10.1.2.3".parse::<InspectFilter>(),
+            Ok(InspectFilter::Address("10.1.2.3".parse().unwrap()))
+        );
+        assert_eq!(
+            "src=10.1.2.3:5004".parse::<InspectFilter>(),
+            Ok(InspectFilter::Socket("10.1.2.3:5004".parse().unwrap()))
+        );
+        assert!("dst=10.1.2.3".parse::<InspectFilter>().is_err());
+        assert!("src=paging".parse::<InspectFilter>().is_err());
+    }
+}
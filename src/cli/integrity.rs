@@ -0,0 +1,171 @@
+//! Recording fingerprints for chain of custody.
+//!
+//! Each finished recording gets a SHA-256 of the file as written plus a
+//! SHA-256 of its audio alone, taken over the samples as 16-bit
+//! little-endian PCM. The file hash proves the recording is byte-for-byte
+//! what was captured. The audio hash still matches after a container-only
+//! change, e.g. a tool rewriting the WAV header or adding a metadata chunk,
+//! so a mismatch can be told apart from edited audio. Both hashes come from
+//! a single read of the file.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use thiserror::Error;
+use schemars::JsonSchema;
+
+#[derive(Error, Debug)]
+pub enum IntegrityError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("WAV error: {0}")]
+    Wav(#[from] hound::Error),
+
+    #[error("Recording is not 16-bit PCM ({0} bits)")]
+    UnsupportedFormat(u16),
+}
+
+/// Fingerprint of a finished recording, as stored in summary.json
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct RecordingIntegrity {
+    /// SHA-256 of the WAV file, hex
+    pub sha256: String,
+    /// SHA-256 of the samples as 16-bit little-endian PCM, hex
+    pub pcm_sha256: String,
+    pub file_size: u64,
+    /// Samples across all channels
+    pub sample_count: u64,
+}
+
+/// Result of checking a recording against its stored fingerprint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    Intact,
+    /// The file changed; `audio_intact` when only the container did
+    Modified { audio_intact: bool },
+    Missing,
+    Unreadable(String),
+}
+
+/// Passes everything read through to a hash of the whole file
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.bytes += n as u64;
+        Ok(n)
+    }
+}
+
+/// Hash a finished recording
+pub fn fingerprint(path: &Path) -> Result<RecordingIntegrity, IntegrityError> {
+    let file = HashingReader {
+        inner: File::open(path)?,
+        hasher: Sha256::new(),
+        bytes: 0,
+    };
+    let mut reader = hound::WavReader::new(BufReader::new(file))?;
+    let spec = reader.spec();
+    if spec.sample_format != hound::SampleFormat::Int || spec.bits_per_sample != 16 {
+        return Err(IntegrityError::UnsupportedFormat(spec.bits_per_sample));
+    }
+
+    let mut pcm = Sha256::new();
+    let mut sample_count = 0u64;
+    for sample in reader.samples::<i16>() {
+        pcm.update(sample?.to_le_bytes());
+        sample_count += 1;
+    }
+
+    // Whatever follows the samples (e.g. trailing chunks) is part of the file too
+    let mut file = reader.into_inner();
+    io::copy(&mut file, &mut io::sink())?;
+    let file = file.into_inner();
+
+    Ok(RecordingIntegrity {
+        sha256: hex(&file.hasher.finalize()),
+        pcm_sha256: hex(&pcm.finalize()),
+        file_size: file.bytes,
+        sample_count,
+    })
+}
+
+/// Re-hash a recording and compare it with its stored fingerprint
+pub fn verify(path: &Path, expected: &RecordingIntegrity) -> Verification {
+    if !path.exists() {
+        return Verification::Missing;
+    }
+    match fingerprint(path) {
+        Ok(actual) if actual == *expected => Verification::Intact,
+        Ok(actual) => Verification::Modified {
+            audio_intact: actual.pcm_sha256 == expected.pcm_sha256,
+        },
+        Err(e) => Verification::Unreadable(e.to_string()),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut hex, b| {
+        let _ = write!(hex, "{:02x}", b);
+        hex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::recorder::WavRecorder;
+    use std::fs;
+
+    fn record(path: &Path, samples: &[i16]) {
+        let mut recorder = WavRecorder::new(path, 8000, 1).unwrap();
+        recorder.write_samples(samples).unwrap();
+        recorder.finalize().unwrap();
+    }
+
+    #[test]
+    fn test_fingerprint_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.wav");
+        let samples: Vec<i16> = (0..8000).map(|i| (i % 200 - 100) as i16 * 100).collect();
+        record(&path, &samples);
+
+        let integrity = fingerprint(&path).unwrap();
+        assert_eq!(integrity.file_size, fs::metadata(&path).unwrap().len());
+        assert_eq!(integrity.sample_count, 8000);
+        assert_eq!(integrity.sha256.len(), 64);
+        assert_eq!(verify(&path, &integrity), Verification::Intact);
+
+        // The audio hash covers only the samples
+        let mut pcm = Sha256::new();
+        for sample in &samples {
+            pcm.update(sample.to_le_bytes());
+        }
+        assert_eq!(integrity.pcm_sha256, hex(&pcm.finalize()));
+
+        // A trailing chunk changes the file but not the audio
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.extend_from_slice(b"LIST\x04\x00\x00\x00INFO");
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(verify(&path, &integrity), Verification::Modified { audio_intact: true });
+
+        // One changed sample byte changes both
+        let last = bytes.len() - 13;
+        bytes[last] ^= 0x01;
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(verify(&path, &integrity), Verification::Modified { audio_intact: false });
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(verify(&path, &integrity), Verification::Missing);
+    }
+}
@@ -0,0 +1,239 @@
+//! Receiver playout buffer simulation.
+//!
+//! Raw jitter doesn't say whether a speaker will glitch. This replays a
+//! page's packet arrival times against their RTP timestamps through a fixed
+//! playout buffer at several depths, and counts the packets that would have
+//! arrived after their playout deadline.
+//!
+//! The simulated receiver starts playing the first packet to arrive `depth`
+//! after its arrival and then plays out on the RTP clock, never adapting.
+//! A late packet is discarded, so its frame is heard as a gap. Packets lost
+//! on the network are not counted; they show up in the loss figures instead.
+
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use schemars::JsonSchema;
+
+/// Buffer depths simulated for every page (ms)
+pub const SIMULATED_DEPTHS_MS: [u32; 5] = [20, 40, 60, 80, 120];
+
+/// Packets kept per page, about an hour of 20ms packets. Later packets are
+/// not simulated.
+pub const MAX_RECORDED_PACKETS: usize = 180_000;
+
+/// What a playout buffer of one depth would have done
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct JitterBufferResult {
+    pub depth_ms: u32,
+    /// Packets that arrived after their playout time
+    pub late_packets: u64,
+    /// Buffer underruns (runs of consecutive late packets)
+    pub underruns: u64,
+    /// Audio replaced by gaps because of late packets
+    pub gap_secs: f64,
+}
+
+/// One received packet: arrival time since the page started, and RTP timestamp
+#[derive(Debug, Clone, Copy)]
+struct PacketTiming {
+    arrival_us: u64,
+    timestamp: u32,
+}
+
+/// Arrival timing of a page's packets, recorded for the simulation
+#[derive(Debug)]
+pub struct PacketTimeline {
+    clock_rate: u32,
+    start: Option<Instant>,
+    /// Runs of packets with a continuous RTP timestamp (split at stream resets)
+    segments: Vec<Vec<PacketTiming>>,
+    recorded: usize,
+    truncated: bool,
+}
+
+impl PacketTimeline {
+    pub fn new(clock_rate: u32) -> Self {
+        Self {
+            clock_rate: clock_rate.max(1),
+            start: None,
+            segments: vec![Vec::new()],
+            recorded: 0,
+            truncated: false,
+        }
+    }
+
+    /// Read timestamps at a different rate, once a sender's clock is known
+    pub fn set_clock_rate(&mut self, clock_rate: u32) {
+        self.clock_rate = clock_rate.max(1);
+    }
+
+    /// Record a packet as it arrives
+    pub fn record(&mut self, received_at: Instant, timestamp: u32) {
+        let start = *self.start.get_or_insert(received_at);
+        let arrival_us = received_at.saturating_duration_since(start).as_micros() as u64;
+        self.push(arrival_us, timestamp);
+    }
+
+    fn push(&mut self, arrival_us: u64, timestamp: u32) {
+        if self.recorded >= MAX_RECORDED_PACKETS {
+            self.truncated = true;
+            return;
+        }
+        self.recorded += 1;
+        if let Some(segment) = self.segments.last_mut() {
+            segment.push(PacketTiming { arrival_us, timestamp });
+        }
+    }
+
+    /// The RTP timestamp restarted (e.g. sender reboot); simulate what follows separately
+    pub fn resync(&mut self) {
+        if self.segments.last().is_some_and(|s| !s.is_empty()) {
+            self.segments.push(Vec::new());
+        }
+    }
+
+    /// Packets recorded so far, for `--memory-report`
+    pub fn recorded(&self) -> usize {
+        self.recorded
+    }
+
+    /// Whether packets were left out because the page was too long
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Simulate every depth in `SIMULATED_DEPTHS_MS`
+    pub fn analyze(&self) -> Vec<JitterBufferResult> {
+        if self.recorded == 0 {
+            return Vec::new();
+        }
+        SIMULATED_DEPTHS_MS.iter().map(|&depth| self.simulate(depth)).collect()
+    }
+
+    /// Simulate a fixed playout buffer of `depth_ms`
+    pub fn simulate(&self, depth_ms: u32) -> JitterBufferResult {
+        let mut result = JitterBufferResult {
+            depth_ms,
+            late_packets: 0,
+            underruns: 0,
+            gap_secs: 0.0,
+        };
+        for segment in &self.segments {
+            simulate_segment(segment, self.clock_rate, depth_ms, &mut result);
+        }
+        result
+    }
+}
+
+fn simulate_segment(packets: &[PacketTiming], clock_rate: u32, depth_ms: u32, result: &mut JitterBufferResult) {
+    let Some(first) = packets.first() else {
+        return;
+    };
+
+    // Unwrap timestamps relative to the first packet to arrive
+    let mut frames: Vec<(i64, u64)> = Vec::with_capacity(packets.len());
+    let mut previous = first.timestamp;
+    let mut extended = 0i64;
+    for packet in packets {
+        extended += i64::from(packet.timestamp.wrapping_sub(previous) as i32);
+        previous = packet.timestamp;
+        frames.push((extended, packet.arrival_us));
+    }
+
+    // Play order, keeping the earliest copy of duplicates
+    frames.sort_unstable();
+    frames.dedup_by_key(|&mut (ts, _)| ts);
+
+    // Each late packet costs one frame of audio
+    let frame_ticks = frames.windows(2).map(|w| w[1].0 - w[0].0).min().unwrap_or(0);
+    let frame_secs = frame_ticks as f64 / f64::from(clock_rate);
+
+    let origin_us = first.arrival_us as f64 + f64::from(depth_ms) * 1000.0;
+    let mut in_underrun = false;
+    for &(ts, arrival_us) in &frames {
+        let playout_us = origin_us + ts as f64 * 1_000_000.0 / f64::from(clock_rate);
+        let late = arrival_us as f64 > playout_us;
+        if late {
+            result.late_packets += 1;
+            result.gap_secs += frame_secs;
+            if !in_underrun {
+                result.underruns += 1;
+            }
+        }
+        in_underrun = late;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 20ms packets at 8kHz, each arriving on time plus `delay_ms(index)`
+    fn timeline(count: u32, delay_ms: impl Fn(u32) -> u64) -> PacketTimeline {
+        let mut timeline = PacketTimeline::new(8000);
+        let mut packets: Vec<(u64, u32)> = (0..count)
+            .map(|i| (u64::from(i) * 20_000 + delay_ms(i) * 1000, 1_000_000 + i * 160))
+            .collect();
+        packets.sort_unstable();
+        for (arrival_us, timestamp) in packets {
+            timeline.push(arrival_us, timestamp);
+        }
+        timeline
+    }
+
+    #[test]
+    fn test_one_delayed_packet() {
+        let timeline = timeline(100, |i| if i == 50 { 100 } else { 0 });
+        let results = timeline.analyze();
+        assert_eq!(results.iter().map(|r| r.depth_ms).collect::<Vec<_>>(), SIMULATED_DEPTHS_MS);
+
+        let at = |depth| results.iter().find(|r| r.depth_ms == depth).unwrap();
+        assert_eq!(at(60).late_packets, 1);
+        assert_eq!(at(60).underruns, 1);
+        assert!((at(60).gap_secs - 0.02).abs() < 1e-9);
+        assert_eq!(at(80).late_packets, 1);
+        assert_eq!(at(120).late_packets, 0);
+        assert!(at(120).gap_secs.abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_steady_stream_never_underruns() {
+        // 10ms of jitter on every other packet fits in the smallest buffer
+        let results = timeline(500, |i| if i % 2 == 0 { 10 } else { 0 }).analyze();
+        assert!(results.iter().all(|r| r.late_packets == 0));
+    }
+
+    #[test]
+    fn test_late_run_is_one_underrun() {
+        // Packets 20-22 held up by a 50ms stall and released together
+        let timeline = timeline(100, |i| if (20..23).contains(&i) { 50 - u64::from(i - 20) * 20 } else { 0 });
+        let result = timeline.simulate(20);
+        assert_eq!(result.late_packets, 2);
+        assert_eq!(result.underruns, 1);
+    }
+
+    #[test]
+    fn test_wraparound_duplicates_and_resync() {
+        let mut timeline = PacketTimeline::new(8000);
+        for i in 0..50u32 {
+            timeline.push(u64::from(i) * 20_000, (u32::MAX - 800).wrapping_add(i * 160));
+        }
+        // A duplicate arriving much later is not a late packet
+        timeline.push(2_000_000, u32::MAX - 800);
+        timeline.resync();
+        for i in 0..50u32 {
+            timeline.push(3_000_000 + u64::from(i) * 20_000, 42 + i * 160);
+        }
+        assert_eq!(timeline.simulate(20).late_packets, 0);
+    }
+
+    #[test]
+    fn test_recording_is_capped() {
+        let mut timeline = PacketTimeline::new(8000);
+        for i in 0..=MAX_RECORDED_PACKETS as u32 {
+            timeline.push(u64::from(i) * 20_000, i * 160);
+        }
+        assert!(timeline.is_truncated());
+        assert_eq!(timeline.simulate(20).late_packets, 0);
+    }
+}
@@ -0,0 +1,298 @@
+//! Log output: the console, an optional JSON log file, and rate limiting.
+//!
+//! `-v` and `-q` set one level for everything. `--log-filter` adds
+//! `EnvFilter` directives on top, so one module can be turned up without
+//! the rest, as in `info,multicast_paging_utility::network::polycom=debug`;
+//! a bare level in the directive replaces the one `-v`/`-q` chose.
+//!
+//! `--log-file` writes every event that passes the filter to a file, one
+//! JSON object per line, alongside the console. `-q` only quiets the
+//! console, so warnings still reach the file.
+//!
+//! Warnings a sender can trigger on every packet (decode errors, packets
+//! that don't parse, receive errors) go through [`limited!`]. Each call
+//! site has a token bucket: a burst of [`RATE_LIMIT_BURST`] messages, then
+//! [`RATE_LIMIT_PER_SEC`] a second. The next message let through after
+//! some were dropped is preceded by a "Suppressed N similar messages" line.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+use thiserror::Error;
+use tracing::level_filters::LevelFilter;
+use tracing::Subscriber;
+use tracing_subscriber::filter::ParseError;
+use tracing_subscriber::fmt::writer::{BoxMakeWriter, MakeWriter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Messages a call site may log at once before it is limited
+pub const RATE_LIMIT_BURST: u32 = 10;
+
+/// Messages a limited call site may log a second
+pub const RATE_LIMIT_PER_SEC: f64 = 1.0;
+
+#[derive(Error)]
+pub enum LoggingError {
+    #[error("Unable to open log file {}: {source}", .path.display())]
+    LogFile { path: PathBuf, source: io::Error },
+
+    #[error("Invalid --log-filter: {0}")]
+    Filter(#[from] ParseError),
+}
+
+// main reports errors with Debug formatting; keep the path readable there
+impl fmt::Debug for LoggingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Where logs go and how much of them
+#[derive(Debug, Default)]
+pub struct LogOptions {
+    pub verbose: bool,
+    pub quiet: bool,
+    /// `EnvFilter` directives applied over the `-v`/`-q` level
+    pub filter: Option<String>,
+    /// JSON log file, written as well as the console
+    pub file: Option<PathBuf>,
+    /// Send console logs to stderr, for commands whose stdout is JSON events
+    pub stderr: bool,
+}
+
+/// Set up the console and log file for the rest of the run
+pub fn init(options: &LogOptions) -> Result<(), LoggingError> {
+    let console_level = if options.verbose {
+        LevelFilter::DEBUG
+    } else if options.quiet {
+        LevelFilter::ERROR
+    } else {
+        LevelFilter::INFO
+    };
+    let writer = if options.stderr {
+        BoxMakeWriter::new(io::stderr)
+    } else {
+        BoxMakeWriter::new(io::stdout)
+    };
+    let console = tracing_subscriber::fmt::layer()
+        .with_target(false)
+        .with_writer(writer)
+        .with_filter(build_filter(console_level, options.filter.as_deref())?);
+
+    let file = match options.file {
+        Some(ref path) => {
+            let level = if options.verbose { LevelFilter::DEBUG } else { LevelFilter::INFO };
+            let filter = build_filter(level, options.filter.as_deref())?;
+            Some(json_layer(Mutex::new(open_log_file(path)?)).with_filter(filter))
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry().with(console).with(file).init();
+    Ok(())
+}
+
+/// A filter at `level`, with `directive` applied over it
+pub fn build_filter(level: LevelFilter, directive: Option<&str>) -> Result<EnvFilter, ParseError> {
+    // A later directive for the same target replaces an earlier one
+    let directives = match directive {
+        Some(directive) => format!("{},{}", level, directive),
+        None => level.to_string(),
+    };
+    EnvFilter::builder().parse(directives)
+}
+
+/// Check a `--log-filter` directive as it is parsed
+pub fn parse_log_filter(directive: &str) -> Result<String, String> {
+    build_filter(LevelFilter::INFO, Some(directive)).map_err(|e| e.to_string())?;
+    Ok(directive.to_string())
+}
+
+/// Open the log file at `path` to append to
+fn open_log_file(path: &Path) -> Result<File, LoggingError> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|source| LoggingError::LogFile { path: path.to_path_buf(), source })
+}
+
+/// Writes events as JSON lines: `timestamp`, `level`, `target`, the event's
+/// `fields` (the text under `message`), and the spans it was logged in
+fn json_layer<S, W>(writer: W) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt::layer().json().with_writer(writer)
+}
+
+/// A token bucket for one call site
+pub struct RateLimit {
+    burst: u32,
+    per_sec: f64,
+    state: Mutex<Option<Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    suppressed: u64,
+}
+
+impl RateLimit {
+    pub const fn new(burst: u32, per_sec: f64) -> Self {
+        Self { burst, per_sec, state: Mutex::new(None) }
+    }
+
+    /// Whether a message at `now` may be logged. If so, returns how many
+    /// were suppressed since the last one let through
+    pub fn admit(&self, now: Instant) -> Option<u64> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let bucket = state.get_or_insert(Bucket {
+            tokens: f64::from(self.burst),
+            refilled: now,
+            suppressed: 0,
+        });
+        let elapsed = now.saturating_duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_sec).min(f64::from(self.burst));
+        bucket.refilled = now;
+        if bucket.tokens < 1.0 {
+            bucket.suppressed += 1;
+            return None;
+        }
+        bucket.tokens -= 1.0;
+        Some(std::mem::take(&mut bucket.suppressed))
+    }
+}
+
+/// Log through `warn!`, `debug!` or another `tracing` macro, limited per
+/// call site (see the module docs): `limited!(warn!("Decode error: {}", e))`
+macro_rules! limited {
+    ($level:ident!($($arg:tt)+)) => {{
+        static LIMIT: $crate::cli::logging::RateLimit = $crate::cli::logging::RateLimit::new(
+            $crate::cli::logging::RATE_LIMIT_BURST,
+            $crate::cli::logging::RATE_LIMIT_PER_SEC,
+        );
+        if let Some(suppressed) = LIMIT.admit(std::time::Instant::now()) {
+            if suppressed > 0 {
+                tracing::$level!("Suppressed {} similar messages", suppressed);
+            }
+            tracing::$level!($($arg)+);
+        }
+    }};
+}
+pub(crate) use limited;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// A log file that can be read back
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn lines(&self) -> Vec<Value> {
+            let text = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+            text.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+        }
+    }
+
+    /// The JSON lines logged by `log` through a `directive` filter at `level`
+    fn capture(level: LevelFilter, directive: Option<&str>, log: impl FnOnce()) -> Vec<Value> {
+        let buffer = SharedBuffer::default();
+        let filter = build_filter(level, directive).unwrap();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry().with(json_layer(move || writer.clone()).with_filter(filter));
+        tracing::subscriber::with_default(subscriber, log);
+        buffer.lines()
+    }
+
+    #[test]
+    fn test_filter_routes_module_verbosity() {
+        let log = || {
+            tracing::debug!(target: "multicast_paging_utility::network::polycom", "polycom detail");
+            tracing::debug!(target: "multicast_paging_utility::cli::monitor", "monitor detail");
+            tracing::info!(target: "multicast_paging_utility::cli::monitor", count = 3, "monitor info");
+        };
+        let messages = |lines: Vec<Value>| -> Vec<String> {
+            lines.iter().map(|line| line["fields"]["message"].as_str().unwrap().to_string()).collect()
+        };
+
+        assert_eq!(messages(capture(LevelFilter::INFO, None, log)), ["monitor info"]);
+        let polycom = Some("multicast_paging_utility::network::polycom=debug");
+        assert_eq!(messages(capture(LevelFilter::INFO, polycom, log)), ["polycom detail", "monitor info"]);
+        // -q's level still applies elsewhere
+        assert_eq!(messages(capture(LevelFilter::ERROR, polycom, log)), ["polycom detail"]);
+        // A bare level replaces -v/-q's
+        assert!(capture(LevelFilter::DEBUG, Some("warn"), log).is_empty());
+
+        let line = &capture(LevelFilter::INFO, None, log)[0];
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["target"], "multicast_paging_utility::cli::monitor");
+        assert_eq!(line["fields"]["count"], 3);
+        assert!(line["timestamp"].as_str().unwrap().ends_with('Z'));
+
+        // Events keep the spans they were logged in
+        let lines = capture(LevelFilter::INFO, None, || {
+            let _page = tracing::info_span!("page", endpoint = "224.0.1.1:5004").entered();
+            tracing::info!("in a page");
+        });
+        assert_eq!(lines[0]["span"]["name"], "page");
+        assert_eq!(lines[0]["spans"][0]["endpoint"], "224.0.1.1:5004");
+
+        assert!(parse_log_filter("info,multicast_paging_utility::network::polycom=debug").is_ok());
+        assert!(parse_log_filter("polycom=loud").is_err());
+    }
+
+    #[test]
+    fn test_rate_limit_suppresses_and_summarizes() {
+        let limit = RateLimit::new(3, 1.0);
+        let start = Instant::now();
+        let admitted: Vec<_> = (0..10).map(|_| limit.admit(start)).collect();
+        assert_eq!(admitted[..3], [Some(0); 3]);
+        assert!(admitted[3..].iter().all(Option::is_none));
+
+        // A second later one more gets through, with the count of those dropped
+        assert_eq!(limit.admit(start + Duration::from_millis(500)), None);
+        assert_eq!(limit.admit(start + Duration::from_secs(1)), Some(8));
+        assert_eq!(limit.admit(start + Duration::from_secs(1)), None);
+        // The bucket refills to the burst, no further
+        let later = start + Duration::from_secs(30);
+        assert_eq!((0..5).filter_map(|_| limit.admit(later)).collect::<Vec<_>>(), [1, 0, 0]);
+    }
+
+    #[test]
+    fn test_limited_call_site() {
+        let lines = capture(LevelFilter::INFO, None, || {
+            for i in 0..100 {
+                limited!(warn!("Decode error {}", i));
+            }
+        });
+        assert_eq!(lines.len(), RATE_LIMIT_BURST as usize);
+        assert_eq!(lines[0]["fields"]["message"], "Decode error 0");
+        assert_eq!(lines[0]["level"], "WARN");
+    }
+}
@@ -0,0 +1,364 @@
+//! Loudness after ITU-R BS.1770 and EBU Tech 3342.
+//!
+//! The analyzer K-weights each channel and takes the mean square, summed
+//! over the channels, of every 100ms step. Each step completes a 400ms
+//! momentary block once there are four, and a 3s short-term block once
+//! there are thirty. [`LoudnessStats`] keeps a page's blocks in histograms,
+//! so a long page costs no more memory than a short one.
+//!
+//! Integrated loudness is the mean of the momentary blocks above -70 LUFS
+//! and then above 10 LU below that mean. Loudness range is the spread from
+//! the 10th to the 95th percentile of the short-term blocks above -70 LUFS
+//! and 20 LU below their mean.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// Blocks are stepped on by 100ms, so momentary blocks overlap by 75%
+const STEPS_PER_SECOND: u32 = 10;
+
+/// 400ms momentary blocks
+const MOMENTARY_STEPS: usize = 4;
+
+/// 3s short-term blocks
+const SHORT_TERM_STEPS: usize = 30;
+
+/// Blocks at or below this are left out of both measurements
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+
+/// Integrated loudness leaves out blocks this far below the gated mean
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+/// Loudness range leaves out short-term blocks this far below their mean
+const RANGE_RELATIVE_GATE_LU: f64 = -20.0;
+
+/// Histogram resolution. Only the gates and percentiles are rounded to it;
+/// the integrated loudness is taken from each bin's summed power
+const BIN_LU: f64 = 0.1;
+
+/// Bins from the absolute gate to +10 LUFS, above full scale for stereo
+const BINS: usize = 800;
+
+/// One second-order section, normalized so a0 is 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Biquad {
+    pub b: [f64; 3],
+    /// a1 and a2
+    pub a: [f64; 2],
+}
+
+impl Biquad {
+    /// BS.1770's first stage: a +4 dB high shelf for the acoustic effect of
+    /// the head. The analog prototype is mapped to `sample_rate` with the
+    /// bilinear transform, prewarped at the shelf frequency
+    pub fn pre_filter(sample_rate: u32) -> Self {
+        const F0: f64 = 1_681.974_450_955_533;
+        const GAIN_DB: f64 = 3.999_843_853_973_347;
+        const Q: f64 = 0.707_175_236_955_419_6;
+        let k = (std::f64::consts::PI * F0 / f64::from(sample_rate)).tan();
+        let vh = 10f64.powf(GAIN_DB / 20.0);
+        let vb = vh.powf(0.499_666_774_154_541_6);
+        let a0 = 1.0 + k / Q + k * k;
+        Self {
+            b: [(vh + vb * k / Q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / Q + k * k) / a0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / Q + k * k) / a0],
+        }
+    }
+
+    /// BS.1770's second stage: the RLB high-pass at 38Hz
+    pub fn rlb_filter(sample_rate: u32) -> Self {
+        const F0: f64 = 38.135_470_876_024_44;
+        const Q: f64 = 0.500_327_037_323_877_3;
+        let k = (std::f64::consts::PI * F0 / f64::from(sample_rate)).tan();
+        let a0 = 1.0 + k / Q + k * k;
+        Self {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / Q + k * k) / a0],
+        }
+    }
+
+    /// Filter one sample, transposed direct form II
+    fn process(&self, state: &mut [f64; 2], x: f64) -> f64 {
+        let y = self.b[0] * x + state[0];
+        state[0] = self.b[1] * x - self.a[0] * y + state[1];
+        state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// The K-weighting filter for a sample rate: pre-filter, then RLB
+pub fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    [Biquad::pre_filter(sample_rate), Biquad::rlb_filter(sample_rate)]
+}
+
+/// Loudness of a K-weighted mean square, summed over the channels
+pub fn lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// What a 100ms step completed: the K-weighted mean square, summed over
+/// the channels, of the windows ending with it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LoudnessBlock {
+    /// The last 400ms
+    pub momentary: f64,
+    /// The last 3s, once that much has been heard
+    pub short_term: Option<f64>,
+}
+
+/// The analyzer's half: filters the interleaved samples and reports the
+/// blocks each frame completes. Every channel is weighted 1, which is
+/// BS.1770's weight for mono, left, right and centre.
+#[derive(Debug, Clone)]
+pub struct LoudnessMeter {
+    filters: [Biquad; 2],
+    channels: usize,
+    /// Filter state per channel and stage
+    state: Vec<[[f64; 2]; 2]>,
+    /// Frames in a 100ms step
+    step_len: usize,
+    /// Sum of squares over the channels in the current step
+    step_sum: f64,
+    step_frames: usize,
+    /// Mean squares of the last `SHORT_TERM_STEPS` steps, oldest first
+    recent: VecDeque<f64>,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32, channels: u8) -> Self {
+        let channels = usize::from(channels.max(1));
+        Self {
+            filters: k_weighting(sample_rate),
+            channels,
+            state: vec![[[0.0; 2]; 2]; channels],
+            step_len: (sample_rate / STEPS_PER_SECOND).max(1) as usize,
+            step_sum: 0.0,
+            step_frames: 0,
+            recent: VecDeque::with_capacity(SHORT_TERM_STEPS),
+        }
+    }
+
+    /// Measure interleaved 16-bit samples, adding the blocks they complete to `blocks`
+    pub fn push(&mut self, samples: &[i16], blocks: &mut Vec<LoudnessBlock>) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (&sample, state) in frame.iter().zip(&mut self.state) {
+                let x = f64::from(sample) / 32768.0;
+                let shelved = self.filters[0].process(&mut state[0], x);
+                let weighted = self.filters[1].process(&mut state[1], shelved);
+                self.step_sum += weighted * weighted;
+            }
+            self.step_frames += 1;
+            if self.step_frames == self.step_len {
+                blocks.extend(self.end_step());
+            }
+        }
+    }
+
+    fn end_step(&mut self) -> Option<LoudnessBlock> {
+        if self.recent.len() == SHORT_TERM_STEPS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(self.step_sum / self.step_len as f64);
+        self.step_sum = 0.0;
+        self.step_frames = 0;
+
+        let steps = self.recent.len();
+        if steps < MOMENTARY_STEPS {
+            return None;
+        }
+        let momentary = self.recent.range(steps - MOMENTARY_STEPS..).sum::<f64>() / MOMENTARY_STEPS as f64;
+        let short_term = (steps == SHORT_TERM_STEPS).then(|| self.recent.iter().sum::<f64>() / SHORT_TERM_STEPS as f64);
+        Some(LoudnessBlock { momentary, short_term })
+    }
+
+    /// Start again for a new page
+    pub fn reset(&mut self) {
+        self.state.fill([[0.0; 2]; 2]);
+        self.step_sum = 0.0;
+        self.step_frames = 0;
+        self.recent.clear();
+    }
+}
+
+/// Blocks above the absolute gate, by loudness: the count and summed power
+/// in each bin
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    bins: Vec<(u32, f64)>,
+}
+
+impl Histogram {
+    fn add(&mut self, power: f64) {
+        let loudness = lufs(power);
+        if loudness <= ABSOLUTE_GATE_LUFS {
+            return;
+        }
+        if self.bins.is_empty() {
+            self.bins = vec![(0, 0.0); BINS];
+        }
+        let bin = (((loudness - ABSOLUTE_GATE_LUFS) / BIN_LU) as usize).min(BINS - 1);
+        self.bins[bin].0 += 1;
+        self.bins[bin].1 += power;
+    }
+
+    fn centre(bin: usize) -> f64 {
+        ABSOLUTE_GATE_LUFS + (bin as f64 + 0.5) * BIN_LU
+    }
+
+    /// Mean power of the bins from `first`, and how many blocks that is
+    fn mean_power(&self, first: usize) -> Option<(f64, u32)> {
+        let (count, power) = self.bins.iter().skip(first).fold((0, 0.0), |(n, p), &(count, power)| (n + count, p + power));
+        (count > 0).then(|| (power / f64::from(count), count))
+    }
+
+    /// First bin above a gate `gate_lu` below the mean of every block
+    fn relative_gate(&self, gate_lu: f64) -> Option<usize> {
+        let (power, _) = self.mean_power(0)?;
+        let gate = lufs(power) + gate_lu;
+        Some((0..BINS).find(|&bin| Self::centre(bin) > gate).unwrap_or(BINS))
+    }
+
+    /// Loudness of the `p` quantile of the blocks from bin `first`
+    fn percentile(&self, first: usize, count: u32, p: f64) -> f64 {
+        let rank = (f64::from(count - 1) * p).round() as u32;
+        let mut seen = 0;
+        for (bin, &(n, _)) in self.bins.iter().enumerate().skip(first) {
+            seen += n;
+            if seen > rank {
+                return Self::centre(bin);
+            }
+        }
+        Self::centre(BINS - 1)
+    }
+}
+
+/// A page's loudness, from the blocks its audio completed
+#[derive(Debug, Clone, Default)]
+pub struct LoudnessStats {
+    momentary: Histogram,
+    short_term: Histogram,
+    max_momentary: Option<f64>,
+}
+
+impl LoudnessStats {
+    pub fn add(&mut self, block: &LoudnessBlock) {
+        self.momentary.add(block.momentary);
+        if let Some(short_term) = block.short_term {
+            self.short_term.add(short_term);
+        }
+        self.max_momentary = Some(self.max_momentary.map_or(block.momentary, |max| max.max(block.momentary)));
+    }
+
+    /// Gated loudness over the page, in LUFS; `None` if nothing was above
+    /// the absolute gate, or the page was shorter than a momentary block
+    pub fn integrated_lufs(&self) -> Option<f64> {
+        let first = self.momentary.relative_gate(RELATIVE_GATE_LU)?;
+        let (power, _) = self.momentary.mean_power(first)?;
+        Some(lufs(power))
+    }
+
+    /// Loudest 400ms of the page, in LUFS
+    pub fn max_momentary_lufs(&self) -> Option<f64> {
+        self.max_momentary.map(lufs).filter(|loudness| loudness.is_finite())
+    }
+
+    /// Loudness range in LU; `None` for pages under 3s or too quiet to gate
+    pub fn loudness_range_lu(&self) -> Option<f64> {
+        let first = self.short_term.relative_gate(RANGE_RELATIVE_GATE_LU)?;
+        let (_, count) = self.short_term.mean_power(first)?;
+        Some(self.short_term.percentile(first, count, 0.95) - self.short_term.percentile(first, count, 0.10))
+    }
+}
+
+/// Integrated loudness for display, with the range if there is one
+pub fn format_loudness(integrated_lufs: f64, lra: Option<f64>) -> String {
+    match lra {
+        Some(lra) => format!("{:.1} LUFS, range {:.1} LU", integrated_lufs, lra),
+        None => format!("{:.1} LUFS", integrated_lufs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sine at `lufs` loudness on one channel, near enough: K-weighting
+    /// adds about 0.69 dB at 1kHz, which the -0.691 in `lufs` takes back
+    fn tone(sample_rate: u32, channels: u8, level_lufs: f64, secs: f64) -> Vec<i16> {
+        let amplitude = 10f64.powf((level_lufs + 3.0103) / 20.0) * 32768.0;
+        let frames = (f64::from(sample_rate) * secs) as usize;
+        (0..frames)
+            .flat_map(|i| {
+                let t = i as f64 / f64::from(sample_rate);
+                let sample = (amplitude * (2.0 * std::f64::consts::PI * 1000.0 * t).sin()) as i16;
+                std::iter::repeat_n(sample, usize::from(channels))
+            })
+            .collect()
+    }
+
+    fn measure(samples: &[i16], sample_rate: u32, channels: u8) -> LoudnessStats {
+        let mut meter = LoudnessMeter::new(sample_rate, channels);
+        let mut stats = LoudnessStats::default();
+        let mut blocks = Vec::new();
+        // In 20ms packets, as the analyzer sees them
+        for packet in samples.chunks((sample_rate / 50) as usize * usize::from(channels)) {
+            blocks.clear();
+            meter.push(packet, &mut blocks);
+            for block in &blocks {
+                stats.add(block);
+            }
+        }
+        stats
+    }
+
+    #[test]
+    fn test_k_weighting_matches_bs1770_at_48k() {
+        // The coefficients tabled in BS.1770-4 for 48kHz
+        let [pre, rlb] = k_weighting(48000);
+        let expected_pre = ([1.535_124_859_586_97, -2.691_696_189_406_38, 1.198_392_810_852_85], [-1.690_659_293_182_41, 0.732_480_774_215_85]);
+        let expected_rlb = ([1.0, -2.0, 1.0], [-1.990_047_454_833_98, 0.990_072_250_366_21]);
+        for (filter, (b, a)) in [(pre, expected_pre), (rlb, expected_rlb)] {
+            for (got, want) in filter.b.iter().chain(&filter.a).zip(b.iter().chain(&a)) {
+                assert!((got - want).abs() < 1e-8, "{:?}", filter);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reference_tone_reads_minus_23() {
+        for sample_rate in [8000, 16000, 44100, 48000] {
+            let stats = measure(&tone(sample_rate, 1, -23.0, 5.0), sample_rate, 1);
+            let integrated = stats.integrated_lufs().unwrap();
+            assert!((integrated - -23.0).abs() <= 0.5, "{}Hz: {}", sample_rate, integrated);
+            let max = stats.max_momentary_lufs().unwrap();
+            assert!((max - -23.0).abs() <= 0.5, "{}Hz: {}", sample_rate, max);
+            // A steady tone has no range to speak of
+            assert!(stats.loudness_range_lu().unwrap() < 0.5, "{}Hz", sample_rate);
+        }
+        // Both channels count: the same tone in stereo is 3 LU louder
+        let stats = measure(&tone(48000, 2, -23.0, 5.0), 48000, 2);
+        assert!((stats.integrated_lufs().unwrap() - -20.0).abs() <= 0.5);
+    }
+
+    #[test]
+    fn test_gating() {
+        // 10s at -20 then 10s at -40: the quiet half is below the relative
+        // gate, so the page reads as its loud half
+        let mut samples = tone(16000, 1, -20.0, 10.0);
+        samples.extend(tone(16000, 1, -40.0, 10.0));
+        let stats = measure(&samples, 16000, 1);
+        assert!((stats.integrated_lufs().unwrap() - -20.0).abs() <= 0.5);
+        // ... but both halves are within 20 LU, so the range spans them
+        let range = stats.loudness_range_lu().unwrap();
+        assert!((range - 20.0).abs() <= 1.0, "{}", range);
+
+        // Silence is below the absolute gate, and too short for a block is nothing
+        let silence = measure(&vec![0; 16000 * 5], 16000, 1);
+        assert_eq!(silence.integrated_lufs(), None);
+        assert_eq!(silence.max_momentary_lufs(), None);
+        assert_eq!(silence.loudness_range_lu(), None);
+        let short = measure(&tone(16000, 1, -23.0, 0.3), 16000, 1);
+        assert_eq!(short.integrated_lufs(), None);
+        assert_eq!(short.loudness_range_lu(), None);
+    }
+}
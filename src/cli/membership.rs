@@ -0,0 +1,145 @@
+//! Noticing multicast memberships the kernel dropped.
+//!
+//! When the monitoring interface bounces (a DHCP renew, a re-seated cable)
+//! the kernel drops its IGMP memberships without telling the sockets, and
+//! they never receive again. The monitor re-issues each port's joins every
+//! `--rejoin-interval`: a join still in place is refused and left alone, one
+//! that succeeds had been lost, and one that fails means the interface is
+//! still down. Ports with a stalled page or a known outage are checked every
+//! `RECHECK`, so a page cut off by the outage is ended promptly instead of
+//! waiting out the idle timeout.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How often a port with a stalled page, or one that's down, is checked
+pub const RECHECK: Duration = Duration::from_secs(1);
+
+/// What a membership check found
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Membership {
+    /// Every group was still joined
+    Healthy,
+    /// The joins failed; the interface is down
+    Down,
+    /// The joins failed again during a known outage
+    StillDown,
+    /// Lost memberships were restored. The outage is timed from when the
+    /// port was last known to work, so it may be overstated by up to one
+    /// check interval.
+    Recovered { downtime: Duration },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PortHealth {
+    checked_at: Instant,
+    /// Last check, or packet, that showed the port working
+    healthy_at: Instant,
+    down_since: Option<Instant>,
+}
+
+/// Per-port membership checks for one monitor run
+#[derive(Debug)]
+pub struct MembershipWatch {
+    interval: Duration,
+    ports: HashMap<u16, PortHealth>,
+}
+
+impl MembershipWatch {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            ports: HashMap::new(),
+        }
+    }
+
+    fn health(&mut self, port: u16, now: Instant) -> &mut PortHealth {
+        self.ports.entry(port).or_insert(PortHealth {
+            checked_at: now,
+            healthy_at: now,
+            down_since: None,
+        })
+    }
+
+    /// Whether `port` should be checked now. `stalled` is whether a page on
+    /// it has gone quiet.
+    pub fn due(&mut self, port: u16, stalled: bool, now: Instant) -> bool {
+        let interval = self.interval;
+        let health = self.health(port, now);
+        let since = now.saturating_duration_since(health.checked_at);
+        since >= interval || ((stalled || health.down_since.is_some()) && since >= RECHECK)
+    }
+
+    /// Record a check of `port`: the number of groups `rejoin` restored, or
+    /// `None` if it failed. `last_traffic` is the latest packet on the port.
+    pub fn record(&mut self, port: u16, restored: Option<usize>, last_traffic: Option<Instant>, now: Instant) -> Membership {
+        let health = self.health(port, now);
+        health.checked_at = now;
+        let working_at = last_traffic.map_or(health.healthy_at, |traffic| traffic.max(health.healthy_at));
+        let Some(restored) = restored else {
+            if health.down_since.is_some() {
+                return Membership::StillDown;
+            }
+            health.down_since = Some(working_at);
+            return Membership::Down;
+        };
+        health.healthy_at = now;
+        match health.down_since.take() {
+            Some(since) => Membership::Recovered {
+                downtime: now.saturating_duration_since(since),
+            },
+            None if restored > 0 => Membership::Recovered {
+                downtime: now.saturating_duration_since(working_at),
+            },
+            None => Membership::Healthy,
+        }
+    }
+
+    /// Forget ports that are no longer monitored
+    pub fn retain(&mut self, keep: impl Fn(u16) -> bool) {
+        self.ports.retain(|&port, _| keep(port));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checks_are_due_by_interval_or_stall() {
+        let start = Instant::now();
+        let mut watch = MembershipWatch::new(Duration::from_mins(1));
+        assert!(!watch.due(5004, false, start));
+        assert!(!watch.due(5004, true, start + Duration::from_millis(500)));
+        assert!(watch.due(5004, true, start + RECHECK));
+        assert!(!watch.due(5004, false, start + Duration::from_secs(59)));
+        assert!(watch.due(5004, false, start + Duration::from_mins(1)));
+
+        // A port that's down is re-checked as often as a stalled one
+        assert_eq!(watch.record(5004, None, None, start + RECHECK), Membership::Down);
+        assert!(watch.due(5004, false, start + RECHECK * 2));
+    }
+
+    #[test]
+    fn test_outage_and_recovery() {
+        let start = Instant::now();
+        let secs = |n| start + Duration::from_secs(n);
+        let mut watch = MembershipWatch::new(Duration::from_mins(1));
+        assert_eq!(watch.record(5004, Some(0), None, secs(60)), Membership::Healthy);
+
+        // Timed from the last packet, which is later than the last check
+        assert_eq!(watch.record(5004, None, Some(secs(70)), secs(75)), Membership::Down);
+        assert_eq!(watch.record(5004, None, None, secs(76)), Membership::StillDown);
+        assert_eq!(
+            watch.record(5004, Some(2), None, secs(82)),
+            Membership::Recovered { downtime: Duration::from_secs(12) }
+        );
+        assert_eq!(watch.record(5004, Some(0), None, secs(83)), Membership::Healthy);
+
+        // Memberships lost without a failed join in between
+        assert_eq!(
+            watch.record(5004, Some(1), None, secs(143)),
+            Membership::Recovered { downtime: Duration::from_mins(1) }
+        );
+    }
+}
@@ -1,25 +1,120 @@
-use clap::{Parser, Subcommand};
+use crate::cli::audio_analyzer::AnalysisProfile;
+use crate::cli::audio_input::{AudioInput, ChannelSelect, LevelCheck, RawFormat, DEFAULT_MIN_RMS_DBFS, DEFAULT_RAW_RATE};
+use crate::cli::audio_pipe::PipeCommand;
+use crate::cli::channel_names::{ChannelNames, ChannelNamesError};
+use crate::cli::chime::Chime;
+use crate::cli::completions::{ChannelParser, CodecSpecParser, CodecTypeParser, PolycomCodecParser};
+use crate::cli::codec_check::ThresholdArg;
+use crate::cli::convert::OutputFormat;
+use crate::cli::decode_policy::DecodeErrorPolicy;
+use crate::cli::forward::ForwardTarget;
+use crate::cli::inspect::{InspectFilter, InspectOptions};
+use crate::cli::page_count::PageCount;
+use crate::cli::page_threshold::PageThreshold;
+use crate::cli::path_compare::EndpointPair;
+use crate::cli::receipt::ReceiptWait;
+use crate::cli::schema::SchemaKind;
+use crate::cli::ssrc_filter::{parse_ssrc, SsrcFilter};
+use crate::cli::timeout::parse_until_arg;
+use crate::cli::timing::TimingGranularity;
+use crate::cli::verify_chain::CodecSelection;
+use crate::network::{CallerIdEncoding, Dscp, DropBurst, ImpairmentConfig, MulticastInterface};
+use crate::utils::filename::{NameTemplate, Naming};
+use crate::utils::schedule::{Schedule, TimeWindow};
+use crate::utils::sequence::Sequence;
+use crate::capabilities::{Capabilities, CommandCapability};
+use crate::codec::{CodecSpec, CodecType};
+use crate::config::PolycomConfig;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use chrono::{DateTime, Utc};
+use clap_complete::Shell;
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 use std::path::PathBuf;
+use std::time::Duration;
 
+pub mod analysis_pool;
+pub mod annotation;
+pub mod anonymize;
 pub mod audio_analyzer;
+pub mod audio_input;
+pub mod audio_pipe;
+pub mod bridge;
+pub mod channel_names;
+pub mod chime;
+pub mod classifier;
+pub mod clip;
+pub mod codec_check;
+pub mod completions;
+pub mod control;
+pub mod convert;
+pub mod decode_policy;
+pub mod doctor;
+pub mod drain;
+pub mod exit_code;
+pub mod fd_limit;
+pub mod finalize;
+pub mod fingerprint;
+pub mod forward;
+pub mod frame_cache;
+pub mod frame_stream;
+pub mod igmp_cycle;
+pub mod igmp_watch;
+pub mod inspect;
+pub mod integrity;
+pub mod jitter_buffer;
+pub mod logging;
+pub mod loudness;
+pub mod membership;
 pub mod monitor;
+pub mod page_count;
+pub mod page_threshold;
+pub mod path_compare;
+pub mod payloads;
+pub mod playback;
+pub mod plan;
 pub mod polycom_monitor;
 pub mod polycom_transmit;
+pub mod quality;
+pub mod reanalysis;
+pub mod receipt;
 pub mod recorder;
 pub mod review;
+pub mod review_browser;
+pub mod schema;
+pub mod settings;
+pub mod spectrum;
+pub mod ssrc_filter;
+pub mod stats_report;
+pub mod status_page;
+pub mod stitch;
+pub mod terminal;
 pub mod test;
+pub mod timeline;
+pub mod timeout;
+pub mod timing;
+pub mod transcoding;
+pub mod trends;
 pub mod transmit;
+pub mod verify_chain;
+pub mod watchdog;
+pub mod watermark;
 
 // Re-exports for convenient access
+pub use bridge::run_bridge;
+pub use clip::run_clip;
+pub use convert::run_convert;
+pub use igmp_cycle::run_igmp_cycle;
 pub use polycom_monitor::run_polycom_monitor;
-pub use polycom_transmit::run_polycom_transmit;
+pub use polycom_transmit::{run_polycom_transmit, RtpLeg};
 pub use review::run_review;
 pub use test::run_test;
-pub use transmit::run_transmit;
+pub use transmit::{run_transmit, Zone};
 
 #[derive(Parser)]
 #[command(name = "multicast-paging-utility")]
 #[command(author, version, about = "Multicast paging system testing utility")]
+#[command(disable_version_flag = true)]
 #[derive(Default)]
 #[command(long_about = "A utility for testing and troubleshooting multicast paging systems.\n\n\
     Supports monitoring multicast addresses for RTP audio streams, \
@@ -35,6 +130,33 @@ pub struct Cli {
     /// Suppress non-essential output
     #[arg(short, long, global = true)]
     pub quiet: bool,
+
+    /// Log filter directives applied over -v/-q, e.g.
+    /// `info,multicast_paging_utility::network::polycom=debug`
+    #[arg(long, global = true, value_name = "DIRECTIVES", value_parser = logging::parse_log_filter)]
+    pub log_filter: Option<String>,
+
+    /// Also write logs to this file as JSON lines. -q doesn't apply to it
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Print version
+    #[arg(short = 'V', long)]
+    pub version: bool,
+
+    /// With --version, print the full capabilities document as JSON
+    #[arg(long, requires = "version")]
+    pub json: bool,
+
+    /// Print the command's settings and where each came from (command line,
+    /// MPU_* environment variables, .env, config file or default), then exit
+    #[arg(long, global = true)]
+    pub print_effective_config: bool,
+
+    /// Config file to take defaults from (default: the standard location,
+    /// see `validate-config`). Must exist when given
+    #[arg(long, global = true, value_name = "FILE", env = settings::CONFIG_ENV)]
+    pub config: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
@@ -51,6 +173,7 @@ pub enum Commands {
         ///   224.0.1.1                - single address (uses --port)
         ///   224.0.{1-10}.1:5004      - range of addresses
         ///   224.0.1.1:{5000-5010}    - range of ports
+        ///   @endpoints.txt           - one pattern per line, re-read on SIGHUP
         #[arg(short, long)]
         address: String,
 
@@ -58,58 +181,300 @@ pub enum Commands {
         #[arg(short, long, default_value = "5004")]
         port: u16,
 
-        /// Network interface IP address to bind for multicast reception.
+        /// Network interface to join multicast groups on, by IP address or index.
         /// Use this when multicast traffic arrives on a specific interface.
-        /// Example: --interface 192.168.12.65 (for br0 bridge)
+        /// Example: --interface 192.168.12.65 (for br0 bridge), or --interface 12
+        /// (an index from `ip link` or `Get-NetAdapter`).
         /// If not specified, the system default interface is used.
         #[arg(short, long)]
-        interface: Option<String>,
+        interface: Option<MulticastInterface>,
 
-        /// Force specific codec (auto-detect if not specified)
-        /// Options: g711ulaw, g711alaw, g722, opus, l16
-        #[arg(short, long)]
-        codec: Option<String>,
+        /// Force specific codec (auto-detect if not specified).
+        /// Linear PCM takes `codec:rate:channels`, e.g. l24:48000:2 for AES67
+        #[arg(short, long, value_parser = CodecSpecParser)]
+        codec: Option<CodecSpec>,
 
-        /// Output file prefix for recording (WAV format).
-        /// For multiple endpoints, files are named: `prefix_224.0.1.1_5004.wav`
+        /// Output file prefix for recording (WAV format). Each page gets
+        /// its own file: `prefix_0001_20240115_103000.wav`, or
+        /// `prefix_224.0.1.1_5004_0001_20240115_103000.wav` for multiple
+        /// endpoints. With --name-template, the directory each page is
+        /// recorded into
         #[arg(short, long)]
         output: Option<PathBuf>,
 
+        /// Record every page of an endpoint to the same file, each
+        /// overwriting the last, as before pages were named apart
+        #[arg(long, requires = "output", conflicts_with = "name_template")]
+        overwrite: bool,
+
         /// Timeout in seconds (0 = indefinite)
         #[arg(short, long, default_value = "0")]
         timeout: u64,
 
+        /// Stop at this time instead of after --timeout: 17:30 (today, local
+        /// time), or a date and time such as 2024-06-01T17:30:00
+        #[arg(long, value_parser = parse_until_arg)]
+        until: Option<DateTime<Utc>>,
+
         /// Output format in JSON (for automated testing)
         #[arg(long)]
         json: bool,
+
+        /// Unix socket for runtime control (add/remove/reload/status/annotate).
+        /// SIGHUP also reloads the address pattern or @file
+        #[arg(long)]
+        control_socket: Option<PathBuf>,
+
+        /// Write spectrum snapshots of each page to this directory
+        /// (one JSONL file per page, every 500ms)
+        #[arg(long)]
+        spectrum_dir: Option<PathBuf>,
+
+        /// Write every received datagram, including ones that fail to parse,
+        /// to this pcap file for Wireshark. The file name may use {site} and
+        /// {start}, as in --name-template
+        #[arg(long, value_name = "FILE")]
+        pcap: Option<PathBuf>,
+
+        /// Seconds between stats reports
+        #[arg(long, value_name = "SECS", default_value = "1", value_parser = clap::value_parser!(u64).range(1..))]
+        stats_interval: u64,
+
+        /// Only report stats for endpoints with a page in progress. Without
+        /// it, --json also reports idle endpoints, with zero figures
+        #[arg(long)]
+        stats_active_only: bool,
+
+        /// Debugging: log the size of each endpoint's accumulators every
+        /// SECS seconds (default 60), so memory growth on long runs shows up
+        #[arg(long, value_name = "SECS", num_args = 0..=1, default_missing_value = "60", value_parser = clap::value_parser!(u64).range(1..))]
+        memory_report: Option<u64>,
+
+        /// Seconds between checks that the multicast groups are still joined.
+        /// An interface going down drops them; they are re-joined when it's
+        /// back, and a page it cut off ends with an error note. 0 disables
+        #[arg(long, value_name = "SECS", default_value = "60")]
+        rejoin_interval: u64,
+
+        /// Rebuild a port's socket, re-joining its groups, when its reads
+        /// keep failing or it has gone this many seconds without a clean
+        /// one. Rebuilds back off up to a minute apart, and a port still
+        /// failing after 8 ends the run. 0 disables
+        #[arg(long, value_name = "SECS", default_value = "30")]
+        watchdog: u64,
+
+        /// With --json, emit a heartbeat event every SECS seconds (default
+        /// 30), so a supervisor can tell the receive loop is still running
+        #[arg(long, value_name = "SECS", num_args = 0..=1, default_missing_value = "30", value_parser = clap::value_parser!(u64).range(1..), requires = "json")]
+        heartbeat: Option<u64>,
+
+        /// What a packet the decoder rejects, or a payload cut short in
+        /// transit, becomes in the recording: skip (left out), silence (the
+        /// packet's length of silence, or Opus loss concealment, keeping the
+        /// recording in time) or abort-page (end the page with an error)
+        #[arg(long, value_name = "POLICY", default_value = "silence")]
+        on_decode_error: DecodeErrorPolicy,
+
+        /// DSCP pages should arrive with (0-63, or a name such as ef). Pages
+        /// with packets marked otherwise are warned about
+        #[arg(long, value_name = "DSCP")]
+        expect_dscp: Option<Dscp>,
+
+        /// Write <recording>.timing.jsonl beside each recording, mapping its
+        /// sample offsets to packet receive times (UTC) for `review --at`:
+        /// a row per packet, or per this many milliseconds
+        #[arg(long, value_name = "packet|MS", requires = "output")]
+        timing_file: Option<TimingGranularity>,
+
+        /// Also keep each page's RTP payloads as received, for codec
+        /// debugging: <recording>.payloads.bin, indexed by
+        /// <recording>.payloads.jsonl
+        #[arg(long, requires = "output")]
+        record_payloads: bool,
+
+        /// Send each page on as it arrives, as a new RTP stream to
+        /// ADDRESS:PORT, unicast or a group on another network. Payloads
+        /// pass through as received; with a codec, e.g.
+        /// 10.1.2.3:5004,g711ulaw, pages in any other codec are re-encoded
+        #[arg(long, value_name = "ADDRESS:PORT[,CODEC]")]
+        forward: Option<ForwardTarget>,
+
+        /// Multicast TTL of forwarded packets
+        #[arg(long, default_value = "32", requires = "forward")]
+        forward_ttl: u8,
+
+        /// Run this shell command for each recorded page and write the
+        /// page's audio to its stdin as it arrives (16-bit little-endian
+        /// PCM), e.g. a speech recognizer. {rate} and {channels} are
+        /// replaced with the page's; what it prints is reported with the
+        /// recording
+        #[arg(long, value_name = "COMMAND", requires = "output")]
+        pipe_audio_cmd: Option<PipeCommand>,
+
+        /// Run with this soft open file limit. By default it is raised to
+        /// the hard limit when the ports and recordings could need more
+        #[arg(long, value_name = "N")]
+        max_file_descriptors: Option<u64>,
+
+        /// Read G.722 timestamps as 16kHz, for senders that don't keep the
+        /// 8kHz clock of RFC 3551. A sender found ticking at twice the rate
+        /// is switched over with a warning anyway
+        #[arg(long)]
+        g722_nonstandard_timestamps: bool,
+
+        /// Serve a read-only status page (/) and its JSON (/api/status) on
+        /// ADDRESS:PORT, or a port alone for localhost. It has no
+        /// authentication, so only bind it wider on a trusted network
+        #[arg(long, value_name = "ADDRESS:PORT", value_parser = status_page::parse_status_listen)]
+        status_listen: Option<SocketAddr>,
+
+        #[command(flatten)]
+        page_threshold: PageThresholdArgs,
+
+        #[command(flatten)]
+        ssrc_filter: SsrcFilterArgs,
+
+        #[command(flatten)]
+        count: CountArgs,
+
+        #[command(flatten)]
+        timing: TimingArgs,
+
+        #[command(flatten)]
+        repair: RepairArgs,
+
+        #[command(flatten)]
+        inspect: InspectArgs,
+
+        #[command(flatten)]
+        naming: NamingArgs,
     },
 
     /// Transmit an audio file as a multicast page
     Transmit {
-        /// Audio file to transmit (WAV format)
-        #[arg(short, long)]
-        file: PathBuf,
+        /// Audio file to transmit (WAV, MP3, FLAC, etc., or headerless
+        /// .ulaw/.alaw; see --input-format)
+        #[arg(short, long, required_unless_present = "sequence", conflicts_with = "sequence")]
+        file: Option<PathBuf>,
+
+        /// Send a synthesized test sequence instead of a file, e.g.
+        /// "tone:1000:10s:-20db; sweep:100-8000:15s:-12db; silence:5s".
+        /// Segments are tone:HZ:DURATION:LEVEL, sweep:FROM-TO:DURATION:LEVEL
+        /// (logarithmic) and silence:DURATION; @FILE reads the spec from a file
+        #[arg(long, value_name = "SPEC", conflicts_with_all = ["multi_zone", "cache_dir"])]
+        sequence: Option<Sequence>,
+
+        /// Print the parsed --sequence, segment by segment, and exit
+        #[arg(long, requires = "sequence")]
+        describe: bool,
 
         /// Destination multicast address
-        #[arg(short, long)]
-        address: String,
+        #[arg(short, long, required_unless_present_any = ["multi_zone", "describe"])]
+        address: Option<String>,
 
         /// Destination UDP port
         #[arg(short, long, default_value = "5004")]
         port: u16,
 
+        /// Page each channel of the file to its own group at once, e.g.
+        /// left=224.0.1.1:5004,right=224.0.1.2:5004. Channels are as for
+        /// --channel-select. Replaces --address; no impairments
+        #[arg(
+            long,
+            value_name = "CHANNEL=ADDRESS:PORT",
+            value_delimiter = ',',
+            conflicts_with_all = ["address", "channel_select"]
+        )]
+        multi_zone: Vec<Zone>,
+
         /// Codec to use for encoding
-        /// Options: g711ulaw, g711alaw, opus, l16
-        #[arg(short, long, default_value = "g711ulaw")]
-        codec: String,
+        #[arg(short, long, default_value = "g711ulaw", value_parser = CodecTypeParser)]
+        codec: CodecType,
 
         /// Multicast TTL (Time To Live)
         #[arg(long, default_value = "32")]
         ttl: u8,
 
+        /// DSCP to mark packets with: 0-63 or a name such as ef (46, usual
+        /// for voice), af41 or cs5. Default: the system's, normally 0
+        #[arg(long, value_name = "DSCP")]
+        dscp: Option<Dscp>,
+
+        /// Send with this SSRC (hex) rather than a random one, so a monitor
+        /// can take only this stream with its own --ssrc. With --multi-zone
+        /// the zones take it and the SSRCs after it
+        #[arg(long, value_name = "HEX", value_parser = parse_ssrc)]
+        ssrc: Option<u32>,
+
         /// Loop the audio file continuously
         #[arg(long)]
         r#loop: bool,
+
+        /// Print line-delimited JSON events (started, progress, report, then
+        /// completed or failed) instead of progress output
+        #[arg(long)]
+        json: bool,
+
+        /// Decode the file, check the destination and print the plan without
+        /// sending anything. Exits nonzero if the page would not go out
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Keep encoded frames in this directory and reuse them while the
+        /// file is unchanged, skipping the decode and encode. See `cache`
+        #[arg(long, value_name = "DIR")]
+        cache_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[command(flatten)]
+        levels: LevelArgs,
+
+        #[command(flatten)]
+        impairment: ImpairmentArgs,
+
+        #[command(flatten)]
+        schedule: ScheduleArgs,
+
+        /// Run at real-time priority (`SCHED_FIFO`) with memory locked, so
+        /// packets go out on time on a loaded box. Linux; needs privileges
+        #[arg(long, help_heading = "Timing")]
+        realtime: bool,
+
+        /// Keep recent packets and resend the ones a `--repair` receiver
+        /// asks for, on the port + 2. Non-standard; impairments don't apply
+        /// to what is resent
+        #[arg(long, conflicts_with = "multi_zone", help_heading = "Repair")]
+        repair: bool,
+
+        /// Packets kept for resending
+        #[arg(long, value_name = "PACKETS", default_value = "500", value_parser = clap::value_parser!(u64).range(1..), requires = "repair", help_heading = "Repair")]
+        repair_buffer: u64,
+
+        /// Advance G.722 timestamps at 16kHz rather than the 8kHz of RFC
+        /// 3551, for receivers that expect it
+        #[arg(long)]
+        g722_nonstandard_timestamps: bool,
+
+        /// Mix a quiet tone carrying this 16-bit ID into the page, for
+        /// `monitor` and `test` to report. Pages need 1.6s of audio to carry it
+        #[arg(long, value_name = "ID", conflicts_with = "cache_dir", help_heading = "Watermark")]
+        watermark: Option<u16>,
+
+        /// Level of the watermark tone, -50 to -10
+        #[arg(long, value_name = "DBFS", default_value_t = watermark::DEFAULT_LEVEL_DBFS, value_parser = watermark::parse_level, allow_negative_numbers = true, requires = "watermark", help_heading = "Watermark")]
+        watermark_level: f64,
+
+        /// Start the page with a chime: builtin:ding, builtin:westminster or
+        /// an audio file. It is encoded with the page, so it plays at the
+        /// level it was made at
+        #[arg(long, value_name = "CHIME", conflicts_with = "cache_dir", help_heading = "Chime")]
+        chime: Option<Chime>,
+
+        /// Silence between the chime and the audio, in milliseconds
+        #[arg(long, value_name = "MS", default_value_t = chime::DEFAULT_GAP_MS, requires = "chime", help_heading = "Chime")]
+        chime_gap_ms: u64,
     },
 
     /// Run automated testing mode for CI/CD integration.
@@ -125,38 +490,151 @@ pub enum Commands {
         #[arg(short, long, default_value = "5004")]
         port: u16,
 
-        /// Network interface IP address to bind for multicast reception.
+        /// Network interface to join multicast groups on, by IP address or index.
         /// Use this when multicast traffic arrives on a specific interface.
-        /// Example: --interface 192.168.12.65 (for br0 bridge)
+        /// Example: --interface 192.168.12.65 (for br0 bridge), or --interface 12
+        /// (an index from `ip link` or `Get-NetAdapter`).
         /// If not specified, the system default interface is used.
         #[arg(short, long)]
-        interface: Option<String>,
+        interface: Option<MulticastInterface>,
 
-        /// Force specific codec (auto-detect if not specified)
-        /// Options: g711ulaw, g711alaw, g722, opus, l16
-        #[arg(short, long)]
-        codec: Option<String>,
+        /// Force specific codec (auto-detect if not specified).
+        /// Linear PCM takes `codec:rate:channels`, e.g. l24:48000:2 for AES67
+        #[arg(short, long, value_parser = CodecSpecParser)]
+        codec: Option<CodecSpec>,
 
         /// Output directory for test results (required).
         /// Will contain: metrics.jsonl, summary.json, and page recordings
         #[arg(short, long)]
         output: PathBuf,
 
-        /// Test timeout in seconds (required, must be > 0)
-        #[arg(short, long)]
-        timeout: u64,
+        /// Test timeout in seconds (required unless --until is given, must be > 0)
+        #[arg(short, long, required_unless_present = "until")]
+        timeout: Option<u64>,
+
+        /// Stop at this time instead of after --timeout: 17:30 (today, local
+        /// time), or a date and time such as 2024-06-01T17:30:00
+        #[arg(long, value_parser = parse_until_arg)]
+        until: Option<DateTime<Utc>>,
 
         /// Metrics sampling interval in milliseconds
         #[arg(long, default_value = "500")]
         metrics_interval: u64,
+
+        /// Unix socket for runtime control (add/remove/reload/status/annotate).
+        /// SIGHUP also reloads the address pattern or @file
+        #[arg(long)]
+        control_socket: Option<PathBuf>,
+
+        /// Write spectrum snapshots of each page to this directory,
+        /// sampled at the metrics interval (review with --spectrogram)
+        #[arg(long)]
+        spectrum_dir: Option<PathBuf>,
+
+        /// Write every received datagram, including ones that fail to parse,
+        /// to this pcap file for Wireshark. The file name may use {site} and
+        /// {start}, as in --name-template
+        #[arg(long, value_name = "FILE")]
+        pcap: Option<PathBuf>,
+
+        #[command(flatten)]
+        page_threshold: PageThresholdArgs,
+
+        #[command(flatten)]
+        ssrc_filter: SsrcFilterArgs,
+
+        #[command(flatten)]
+        timing: TimingArgs,
+
+        #[command(flatten)]
+        repair: RepairArgs,
+
+        /// Keep running time-of-day aggregates in this JSON file, updated as
+        /// each page ends. Reused across runs (view with review --trends)
+        #[arg(long, value_name = "FILE")]
+        aggregate: Option<PathBuf>,
+
+        /// Debugging: log the size of each endpoint's accumulators every
+        /// SECS seconds (default 60), so memory growth on long runs shows up
+        #[arg(long, value_name = "SECS", num_args = 0..=1, default_missing_value = "60", value_parser = clap::value_parser!(u64).range(1..))]
+        memory_report: Option<u64>,
+
+        /// Also record each talk-spurt (a run of audio the sender started
+        /// with the RTP marker bit) to its own WAV, named after the page's
+        /// recording with the spurt number appended
+        #[arg(long)]
+        split_spurts: bool,
+
+        /// What a packet the decoder rejects, or a payload cut short in
+        /// transit, becomes in the recording: skip (left out), silence (the
+        /// packet's length of silence, or Opus loss concealment, keeping the
+        /// recording in time) or abort-page (end the page with an error)
+        #[arg(long, value_name = "POLICY", default_value = "silence")]
+        on_decode_error: DecodeErrorPolicy,
+
+        /// DSCP pages should arrive with (0-63, or a name such as ef). Pages
+        /// with packets marked otherwise are warned about
+        #[arg(long, value_name = "DSCP")]
+        expect_dscp: Option<Dscp>,
+
+        /// Write <recording>.timing.jsonl beside each page recording, mapping
+        /// its sample offsets to packet receive times (UTC) for `review --at`:
+        /// a row per packet, or per this many milliseconds
+        #[arg(long, value_name = "packet|MS")]
+        timing_file: Option<TimingGranularity>,
+
+        /// Also keep each page's RTP payloads as received, for codec
+        /// debugging: <recording>.payloads.bin, indexed by
+        /// <recording>.payloads.jsonl
+        #[arg(long)]
+        record_payloads: bool,
+
+        /// Run this shell command for each page and write the page's audio
+        /// to its stdin as it arrives (16-bit little-endian PCM), e.g. a
+        /// speech recognizer. {rate} and {channels} are replaced with the
+        /// page's; what it prints is kept in the page's summary
+        #[arg(long, value_name = "COMMAND")]
+        pipe_audio_cmd: Option<PipeCommand>,
+
+        /// Check a redundant pair of paths: A=224.0.1.1:5004,B=224.0.1.2:5004.
+        /// Pages seen on both at the same time are compared for level,
+        /// polarity and spectrum at the end of the test. Repeat for more
+        /// pairs; both endpoints must be among those monitored
+        #[arg(long, value_name = "A=ADDR:PORT,B=ADDR:PORT")]
+        compare_endpoints: Vec<EndpointPair>,
+
+        /// Run with this soft open file limit. By default it is raised to
+        /// the hard limit when the ports and recordings could need more
+        #[arg(long, value_name = "N")]
+        max_file_descriptors: Option<u64>,
+
+        /// Read G.722 timestamps as 16kHz, for senders that don't keep the
+        /// 8kHz clock of RFC 3551. A sender found ticking at twice the rate
+        /// is switched over with a warning anyway
+        #[arg(long)]
+        g722_nonstandard_timestamps: bool,
+
+        /// Watch IGMP with a raw socket: the membership reports this host
+        /// sends for the groups, and the queries that arrive. Kept in the
+        /// summary, and used to say where to look if no page is detected.
+        /// Needs `CAP_NET_RAW` or root; without it the test runs unwatched
+        #[arg(long)]
+        observe_igmp: bool,
+
+        #[command(flatten)]
+        naming: NamingArgs,
     },
 
     /// Review test results from a previous test run.
     /// Displays formatted metrics and can play back recorded audio.
     Review {
         /// Directory containing test results (with summary.json)
-        #[arg(short, long)]
-        directory: PathBuf,
+        #[arg(short, long, required_unless_present = "trends")]
+        directory: Option<PathBuf>,
+
+        /// Show the hour-of-day table from a test --aggregate file instead
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["directory", "play", "metrics", "page", "jitter_sim", "spectrogram", "verify", "reanalyze"])]
+        trends: Option<PathBuf>,
 
         /// Play back recorded audio files
         #[arg(short, long)]
@@ -166,16 +644,230 @@ pub enum Commands {
         #[arg(short, long)]
         metrics: bool,
 
-        /// Show details for a specific page number
+        /// Show details for a specific page, by the id in the pages list
+        #[arg(long)]
+        page: Option<u32>,
+
+        /// Take --page as this endpoint's (ADDR:PORT) own page number
+        /// instead of the run-wide id
+        #[arg(long, requires = "page")]
+        endpoint: Option<String>,
+
+        /// Show --page's metrics over time from metrics.jsonl, with the
+        /// worst interval marked
+        #[arg(long, requires = "page")]
+        timeline: bool,
+
+        /// Print --page's metrics snapshots as JSON, in time order, for plotting
+        #[arg(long, requires = "page", conflicts_with_all = ["play", "metrics", "jitter_sim", "spectrogram", "verify", "timeline"])]
+        timeline_json: bool,
+
+        /// Show how receivers with 20-120ms playout buffers would have fared
+        #[arg(long)]
+        jitter_sim: bool,
+
+        /// Render the spectrogram of --page from its spectrum file.
+        /// --directory may also name a spectrum .jsonl file directly
         #[arg(long)]
+        spectrogram: bool,
+
+        /// Directory holding spectrum files (defaults to --directory)
+        #[arg(long, requires = "spectrogram")]
+        spectrum_dir: Option<PathBuf>,
+
+        /// Write the spectrogram as a PNG image instead of printing it
+        #[arg(long, requires = "spectrogram")]
+        png: Option<PathBuf>,
+
+        /// Re-hash every recording (or just --page) against the checksums in
+        /// summary.json. Exits nonzero if any changed or are missing
+        #[arg(long, conflicts_with_all = ["play", "spectrogram"])]
+        verify: bool,
+
+        /// Run every recording (or just --page) through the audio analyzer
+        /// again and compare the result with the figures captured during the
+        /// test. Needs no audio hardware
+        #[arg(long, conflicts_with_all = ["play", "spectrogram", "verify", "timeline", "timeline_json"])]
+        reanalyze: bool,
+
+        /// Thresholds for --reanalyze: speech (as captured) or music
+        #[arg(long, value_name = "PROFILE", default_value = "speech", requires = "reanalyze")]
+        analysis_profile: AnalysisProfile,
+
+        /// Write the --reanalyze comparison to reanalysis.json in --directory
+        /// and print it as JSON instead of tables
+        #[arg(long, requires = "reanalyze")]
+        json: bool,
+
+        /// Find the moment of --page that was playing at this wall-clock
+        /// time (UTC), from its timing file: 14:32:07.250,
+        /// "2024-06-01 14:32:07.250" or RFC 3339. With --play, plays the
+        /// audio around it
+        #[arg(long, value_name = "TIME", requires = "page", conflicts_with_all = ["timeline", "timeline_json", "jitter_sim", "spectrogram", "verify", "reanalyze"])]
+        at: Option<String>,
+
+        /// Seconds either side of --at to clip or play
+        #[arg(long, value_name = "SECS", default_value = "2", requires = "at")]
+        around: f64,
+
+        /// Write the audio around --at to this WAV file
+        #[arg(long, value_name = "FILE", requires = "at")]
+        clip: Option<PathBuf>,
+
+        /// Copy the results to --output for sharing, with each recording
+        /// replaced by noise following its level and every address and
+        /// caller ID replaced by a stand-in
+        #[arg(long, requires = "output", conflicts_with_all = ["play", "spectrogram", "verify", "reanalyze", "timeline", "timeline_json", "at"])]
+        anonymize: bool,
+
+        /// Directory for the --anonymize copy; must be new or empty
+        #[arg(short, long, requires = "anonymize")]
+        output: Option<PathBuf>,
+
+        #[command(flatten)]
+        browse: BrowseArgs,
+    },
+
+    /// Extract a clip from test recordings.
+    /// Select by page number and offsets, or by a wall-clock range (UTC).
+    Clip {
+        /// Directory containing test results (with summary.json)
+        #[arg(short, long)]
+        directory: PathBuf,
+
+        /// Output WAV file
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Page to clip from, by its id in summary.json, or with --endpoint
+        /// by that endpoint's own page number
+        #[arg(long, required_unless_present = "from", conflicts_with_all = ["from", "to"])]
         page: Option<u32>,
+
+        /// Start offset into the page in seconds
+        #[arg(long, default_value = "0", requires = "page")]
+        start: f64,
+
+        /// End offset into the page in seconds (default: end of page)
+        #[arg(long, requires = "page")]
+        end: Option<f64>,
+
+        /// Wall-clock start time (UTC): 14:32:00, "2024-01-15 14:32:00" or RFC 3339
+        #[arg(long, requires = "to")]
+        from: Option<String>,
+
+        /// Wall-clock end time (UTC), same formats as --from
+        #[arg(long, requires = "from")]
+        to: Option<String>,
+
+        /// Only use recordings from this endpoint (e.g. 224.0.1.1:5004)
+        #[arg(long)]
+        endpoint: Option<String>,
+
+        /// Analyze the clipped audio and print its statistics
+        #[arg(long)]
+        analyze: bool,
+    },
+
+    /// Resample and transcode audio offline: a file, or every recording in
+    /// a test output directory (with a conversions.json manifest)
+    Convert {
+        /// Audio file (anything transmit accepts) or test output directory
+        #[arg(short, long)]
+        input: PathBuf,
+
+        /// Output file, or directory when --input is a directory
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Output sample rate (default: the input's, or 8000 for raw G.711)
+        #[arg(short, long)]
+        rate: Option<u32>,
+
+        /// Output format: wav, ulaw-raw or alaw-raw
+        #[arg(short, long, default_value = "wav")]
+        format: OutputFormat,
+
+        /// Encode and decode through this codec first, to hear what a phone
+        /// would play; prints the SNR of the round trip
+        #[arg(long, value_name = "CODEC", value_parser = CodecTypeParser)]
+        codec_roundtrip: Option<CodecType>,
+
+        #[command(flatten)]
+        input_args: InputArgs,
+    },
+
+    /// Check our own codecs: a reference signal through each codec's encoder
+    /// and decoder in-process, compared with what went in. Prints each
+    /// codec's SNR, level change, bandwidth kept, added latency and frame
+    /// sizes, and exits non-zero if one misses its thresholds
+    CodecCheck {
+        /// Codec to check; repeat for several (default: every codec, skipping
+        /// any this host can't run)
+        #[arg(long = "codec", value_name = "CODEC", value_parser = CodecTypeParser)]
+        codecs: Vec<CodecType>,
+
+        /// Audio file (anything transmit accepts) to use in place of the
+        /// built-in multitone
+        #[arg(long, value_name = "FILE")]
+        reference: Option<PathBuf>,
+
+        /// Lowest SNR in dB, for every codec or as CODEC=DB for one; repeatable
+        #[arg(long, value_name = "[CODEC=]DB")]
+        min_snr: Vec<ThresholdArg>,
+
+        /// Largest level change in dB either way, for every codec or as
+        /// CODEC=DB for one; repeatable
+        #[arg(long, value_name = "[CODEC=]DB")]
+        max_level_change: Vec<ThresholdArg>,
+
+        /// Lowest bandwidth kept in Hz, for every codec or as CODEC=HZ for
+        /// one; repeatable
+        #[arg(long, value_name = "[CODEC=]HZ")]
+        min_bandwidth: Vec<ThresholdArg>,
+
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        input_args: InputArgs,
+    },
+
+    /// Check the whole chain, codec by codec: a reference multitone sent
+    /// with this build's own transmit over multicast loopback (TTL 0) and
+    /// received, decoded, analyzed and recorded by its own test. Prints a
+    /// codec by check table and exits non-zero if a check fails
+    VerifyChain {
+        /// Codecs to check: all, or a comma-separated list. A codec this
+        /// host can't run is skipped
+        #[arg(long = "codec", value_name = "all|CODEC[,CODEC...]", default_value = "all")]
+        codecs: CodecSelection,
+
+        /// Multicast group to send the reference to
+        #[arg(long, default_value_t = verify_chain::DEFAULT_ADDRESS)]
+        address: Ipv4Addr,
+
+        /// Port for the first codec; each codec after it takes the next
+        #[arg(long, default_value_t = verify_chain::DEFAULT_PORT)]
+        port: u16,
+
+        /// Keep each codec's recordings, summary and logs here (default: a
+        /// temporary directory, kept only if a check fails)
+        #[arg(long, value_name = "DIR")]
+        output: Option<PathBuf>,
+
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
     },
 
     /// Transmit audio using Polycom PTT/Group Paging protocol.
     /// This is a proprietary protocol used by Polycom phones,
     /// NOT standard RTP multicast paging.
     PolycomTransmit {
-        /// Audio file to transmit (WAV, MP3, FLAC, etc.)
+        /// Audio file to transmit (WAV, MP3, FLAC, etc., or headerless
+        /// .ulaw/.alaw; see --input-format)
         #[arg(short, long)]
         file: PathBuf,
 
@@ -190,21 +882,37 @@ pub enum Commands {
         /// Channel number (1-50).
         /// PTT: 1-25 (24=Priority, 25=Emergency)
         /// Paging: 26-50 (49=Priority, 50=Emergency)
-        #[arg(short, long, default_value = "26")]
+        #[arg(short, long, default_value = "26", value_parser = ChannelParser, hide_possible_values = true)]
         channel: u8,
 
         /// Codec to use: g722 (16kHz, recommended), g711u, g711a (8kHz)
-        #[arg(long, default_value = "g722")]
+        #[arg(long, default_value = "g722", value_parser = PolycomCodecParser, hide_possible_values = true)]
         codec: String,
 
-        /// Caller ID string (displayed on receiving phones)
-        #[arg(long, default_value = "MPS-IP")]
-        caller_id: String,
+        /// Caller ID string (displayed on receiving phones).
+        /// Defaults to the channel's configured caller ID, or "MPS-IP"
+        #[arg(long)]
+        caller_id: Option<String>,
+
+        /// Caller ID encoding: utf8, latin1 or ascii. Characters the encoding
+        /// can't carry are refused rather than replaced
+        #[arg(long, default_value = "utf8")]
+        caller_id_encoding: CallerIdEncoding,
+
+        /// Length in bytes the caller ID field is padded to. 13 gives the
+        /// 20-byte header Polycom phones send; some devices use 16
+        #[arg(long, default_value = "13", value_parser = clap::value_parser!(u8).range(1..))]
+        caller_id_pad: u8,
 
         /// Multicast TTL (Time To Live)
         #[arg(long, default_value = "32")]
         ttl: u8,
 
+        /// DSCP to mark packets with: 0-63 or a name such as ef (46, usual
+        /// for voice), af41 or cs5. Default: the system's, normally 0
+        #[arg(long, value_name = "DSCP")]
+        dscp: Option<Dscp>,
+
         /// Loop the audio file continuously
         #[arg(long)]
         r#loop: bool,
@@ -243,8 +951,76 @@ pub enum Commands {
 
         /// File is raw pre-encoded audio (not WAV).
         /// Use with ffmpeg to encode: ffmpeg -i input.wav -ar 16000 -acodec g722 -f g722 output.raw
-        #[arg(long)]
+        #[arg(long, conflicts_with = "input_format")]
         raw: bool,
+
+        /// Allow transmitting on priority/emergency channels (24, 25, 49, 50)
+        /// without an interactive confirmation
+        #[arg(long)]
+        allow_priority: bool,
+
+        /// Maximum transmission length in seconds (overrides config).
+        /// Not applied to emergency channels
+        #[arg(long)]
+        max_duration: Option<u64>,
+
+        /// Print line-delimited JSON events (started, progress, report, then
+        /// completed or failed) instead of progress output
+        #[arg(long)]
+        json: bool,
+
+        /// Encode the file, check the channel and destination and print the
+        /// plan without sending anything. Exits nonzero if the page would not go out
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Keep encoded frames (page and --also-rtp legs) in this directory and
+        /// reuse them while the file is unchanged, skipping the decode and encode.
+        /// See `cache`
+        #[arg(long, value_name = "DIR", conflicts_with = "raw")]
+        cache_dir: Option<PathBuf>,
+
+        /// Also send the audio as an RTP stream to address:port[,codec]
+        /// (codec defaults to g711ulaw). Packets go out on the same 20ms
+        /// ticks as the page; alert/end and impairments are Polycom-only.
+        /// Repeat for more destinations
+        #[arg(long, value_name = "ADDRESS:PORT[,CODEC]")]
+        also_rtp: Vec<RtpLeg>,
+
+        /// After the last End phase, wait up to SECS for N delivery receipts
+        /// from `polycom-monitor --receipt`, and fail if fewer arrive
+        #[arg(long, value_name = "N:SECS", help_heading = "Receipts")]
+        await_receipts: Option<ReceiptWait>,
+
+        /// Where receipts arrive: a multicast group to join, or any other
+        /// address to listen on all interfaces at its port
+        #[arg(long, value_name = "ADDRESS:PORT", default_value = "0.0.0.0:5099", help_heading = "Receipts")]
+        receipt_listen: SocketAddrV4,
+
+        /// Start the page with a chime: builtin:ding, builtin:westminster or
+        /// an audio file. It is encoded with the page, so it plays at the
+        /// level it was made at
+        #[arg(long, value_name = "CHIME", conflicts_with_all = ["cache_dir", "raw"], help_heading = "Chime")]
+        chime: Option<Chime>,
+
+        /// Silence between the chime and the audio, in milliseconds
+        #[arg(long, value_name = "MS", default_value_t = chime::DEFAULT_GAP_MS, requires = "chime", help_heading = "Chime")]
+        chime_gap_ms: u64,
+
+        #[command(flatten)]
+        channel_names: ChannelNamesArgs,
+
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[command(flatten)]
+        levels: LevelArgs,
+
+        #[command(flatten)]
+        impairment: ImpairmentArgs,
+
+        #[command(flatten)]
+        schedule: ScheduleArgs,
     },
 
     /// Monitor for Polycom PTT/Group Paging traffic.
@@ -277,9 +1053,658 @@ pub enum Commands {
         #[arg(short, long, default_value = "0")]
         timeout: u64,
 
+        /// Stop at this time instead of after --timeout: 17:30 (today, local
+        /// time), or a date and time such as 2024-06-01T17:30:00
+        #[arg(long, value_parser = parse_until_arg)]
+        until: Option<DateTime<Utc>>,
+
         /// Output format in JSON (for automated testing)
         #[arg(long)]
         json: bool,
+
+        /// Write every received datagram, including ones that fail to parse,
+        /// to this pcap file for Wireshark. The file name may use {site} and
+        /// {start}, as in --name-template
+        #[arg(long, value_name = "FILE")]
+        pcap: Option<PathBuf>,
+
+        /// Exit with an error as soon as a page's Alert phase is too slow
+        /// (spacing over 45ms) or too short (under 20 Alerts), e.g. in CI
+        /// against a device under test
+        #[arg(long)]
+        abort_on_bad_alert: bool,
+
+        /// Send a JSON delivery receipt (channel, caller ID, duration, packet
+        /// and lost-frame counts) to this address for each completed page,
+        /// for `polycom-transmit --await-receipts`
+        #[arg(long, value_name = "ADDRESS:PORT")]
+        receipt: Option<SocketAddrV4>,
+
+        /// Check each audio packet's redundant copy of the frame before
+        /// against the frame itself, and count the losses it repaired; the
+        /// figures are in each page's summary and the run's
+        #[arg(long)]
+        verify_redundancy: bool,
+
+        #[command(flatten)]
+        count: CountArgs,
+
+        #[command(flatten)]
+        inspect: InspectArgs,
+
+        #[command(flatten)]
+        naming: NamingArgs,
+
+        #[command(flatten)]
+        channel_names: ChannelNamesArgs,
+    },
+
+    /// Relay pages from a standard RTP multicast group to Polycom phones.
+    /// Each RTP page is re-encoded and sent live as a Polycom page: Alert
+    /// when it starts, audio as it arrives, End when it stops
+    Bridge {
+        /// RTP multicast group to relay pages from
+        #[arg(short, long)]
+        address: String,
+
+        /// UDP port of the RTP group
+        #[arg(short, long, default_value = "5004")]
+        port: u16,
+
+        /// Network interface to join the RTP group on, by IP address or index
+        #[arg(short, long)]
+        interface: Option<MulticastInterface>,
+
+        /// Codec of the RTP stream (detected from the payload type if not
+        /// specified). Linear PCM takes `codec:rate:channels`
+        #[arg(long, value_parser = CodecSpecParser)]
+        source_codec: Option<CodecSpec>,
+
+        /// Polycom group and port to page on
+        #[arg(long, value_name = "ADDRESS:PORT", default_value = "224.0.1.116:5001")]
+        to: SocketAddrV4,
+
+        /// Channel number (1-50) to page on
+        #[arg(short, long, default_value = "26", value_parser = ChannelParser, hide_possible_values = true)]
+        channel: u8,
+
+        /// Codec of the Polycom page: g722 (16kHz), g711u, g711a (8kHz)
+        #[arg(long, default_value = "g722", value_parser = PolycomCodecParser, hide_possible_values = true)]
+        codec: String,
+
+        /// Caller ID string (displayed on receiving phones).
+        /// Defaults to the channel's configured caller ID, or "MPS-IP"
+        #[arg(long)]
+        caller_id: Option<String>,
+
+        /// Caller ID encoding: utf8, latin1 or ascii
+        #[arg(long, default_value = "utf8")]
+        caller_id_encoding: CallerIdEncoding,
+
+        /// Length in bytes the caller ID field is padded to
+        #[arg(long, default_value = "13", value_parser = clap::value_parser!(u8).range(1..))]
+        caller_id_pad: u8,
+
+        /// Multicast TTL (Time To Live)
+        #[arg(long, default_value = "32")]
+        ttl: u8,
+
+        /// DSCP to mark packets with: 0-63 or a name such as ef
+        #[arg(long, value_name = "DSCP")]
+        dscp: Option<Dscp>,
+
+        /// Number of Alert packets to send at the start of each page
+        #[arg(long, default_value = "31")]
+        alert_count: u32,
+
+        /// Number of End packets to send at the end of each page
+        #[arg(long, default_value = "12")]
+        end_count: u32,
+
+        /// Audio to buffer, in ms, before playing out to the phones. More
+        /// rides out more jitter in the source at the cost of delay
+        #[arg(long, value_name = "MS", default_value = "60")]
+        latency: u64,
+
+        /// Time in ms without packets after which the source page has ended
+        #[arg(long, value_name = "MS", default_value = "500")]
+        idle: u64,
+
+        /// Allow relaying to priority/emergency channels (24, 25, 49, 50)
+        /// without an interactive confirmation
+        #[arg(long)]
+        allow_priority: bool,
+
+        /// Timeout in seconds (0 = indefinite). A page in progress is
+        /// finished first
+        #[arg(short, long, default_value = "0")]
+        timeout: u64,
+
+        /// Stop at this time instead of after --timeout
+        #[arg(long, value_parser = parse_until_arg)]
+        until: Option<DateTime<Utc>>,
+
+        /// Print line-delimited JSON events for each page
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Check for IGMP fast-leave: cycle a second socket's membership of a
+    /// group while watching a stream on it, and report whether the flow
+    /// stops after each leave. Needs a stream on the group (a live page, or
+    /// --transmit for our own)
+    IgmpCycle {
+        /// Multicast group to test
+        #[arg(short, long)]
+        address: String,
+
+        /// UDP port of the stream
+        #[arg(short, long, default_value = "5004")]
+        port: u16,
+
+        /// Interface the watching socket joins on, by IP address or index
+        #[arg(short, long)]
+        interface: Option<MulticastInterface>,
+
+        /// Interface the cycling socket joins and leaves on (default: --interface).
+        /// The host only sends an IGMP Leave when its last socket on an interface
+        /// leaves, so use a second interface on the same segment to test the switch
+        #[arg(long, value_name = "INTERFACE")]
+        cycle_interface: Option<MulticastInterface>,
+
+        /// Number of leave/re-join cycles
+        #[arg(short, long, default_value = "10", value_parser = clap::value_parser!(u32).range(1..))]
+        cycles: u32,
+
+        /// Milliseconds spent left, and then joined, in each cycle
+        #[arg(long, default_value = "2000", value_parser = clap::value_parser!(u64).range(100..))]
+        interval: u64,
+
+        /// Send our own 20ms RTP stream to the group to watch
+        #[arg(long)]
+        transmit: bool,
+
+        /// Multicast TTL for --transmit
+        #[arg(long, default_value = "32")]
+        ttl: u8,
+
+        /// Seconds to wait for a stream before giving up
+        #[arg(short, long, default_value = "30")]
+        timeout: u64,
+
+        /// Print each cycle and the report as JSON events
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Check the host for common problems: ffmpeg codecs, multicast join and
+    /// loopback receive, the output directory, the open file limit, clock
+    /// sync, firewalls and multicast sysctls. Prints what to do about each
+    /// warning or failure, and exits non-zero only if a check fails
+    Doctor {
+        /// Interface to join the scratch group on, by IP address or index
+        #[arg(short, long)]
+        interface: Option<MulticastInterface>,
+
+        /// Directory recordings will be written to (default: the current directory)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Endpoints a run will monitor, for the open file limit check
+        #[arg(long, default_value = "1")]
+        endpoints: usize,
+
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List or evict the encoded frames kept by the transmit commands' --cache-dir
+    Cache {
+        /// Cache directory, as given to --cache-dir
+        #[arg(long, value_name = "DIR")]
+        cache_dir: PathBuf,
+
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+
+    /// Write a commented starter config file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
     },
+
+    /// Check the config file (--config, or the one the commands read):
+    /// print "OK" and the effective configuration, or every problem found
+    /// and exit non-zero
+    ValidateConfig,
+
+    /// Print the codecs, protocols and options this build supports, as JSON.
+    /// Codec availability is probed at runtime (e.g. G.722 needs ffmpeg).
+    Capabilities,
+
+    /// Print the JSON Schema of the --json event stream or a summary file.
+    /// Every event and summary carries the `schema_version` it follows
+    Schema {
+        /// events, test-summary (summary.json), polycom-summary or
+        /// reanalysis (review --reanalyze --json)
+        #[arg(default_value = "events")]
+        kind: SchemaKind,
+    },
+
+    /// Print a shell completion script, with codec names and Polycom
+    /// channels offered as values. For bash:
+    ///   source <(multicast-paging-utility completions bash)
+    Completions {
+        /// bash, zsh, fish, elvish or powershell
+        shell: Shell,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum CacheAction {
+    /// List entries with their codec, size and whether the source has changed
+    List {
+        /// Print entries as a JSON array
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Remove entries by ID (a unique prefix is enough)
+    Evict {
+        /// Entry IDs or ID prefixes, as shown by `cache list`
+        #[arg(required_unless_present_any = ["stale", "all"])]
+        ids: Vec<String>,
+
+        /// Also remove every entry whose source file changed or is gone
+        #[arg(long)]
+        stale: bool,
+
+        /// Remove every entry
+        #[arg(long, conflicts_with_all = ["ids", "stale"])]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum ConfigAction {
+    /// Write a commented starter config file to --config, or the standard location
+    Init {
+        /// Replace a file that is already there
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Register every subcommand with its long flags, grouped by help heading
+pub fn register_capabilities(capabilities: &mut Capabilities) {
+    let cli = Cli::command();
+    capabilities.global_flags = cli
+        .get_arguments()
+        .filter(|arg| arg.is_global_set())
+        .filter_map(|arg| arg.get_long().map(|long| format!("--{}", long)))
+        .collect();
+
+    for command in cli.get_subcommands() {
+        let mut flags: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (heading, flag) in long_flags(command) {
+            flags.entry(heading).or_default().push(flag);
+        }
+        capabilities.add_command(CommandCapability {
+            name: command.get_name().to_string(),
+            about: command.get_about().map(|s| s.to_string()).unwrap_or_default(),
+            flags,
+        });
+    }
+
+    if cfg!(not(unix)) {
+        capabilities.add_limitation("--control-socket and SIGHUP reload need Unix; endpoints are fixed at startup");
+        capabilities.add_limitation("Annotations (annotate, the a key) need Unix");
+    }
+}
+
+/// Visible long flags of a command with their help heading
+fn long_flags(command: &clap::Command) -> impl Iterator<Item = (String, String)> + '_ {
+    command
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set())
+        .filter_map(|arg| {
+            let heading = arg.get_help_heading().unwrap_or("Options").to_string();
+            arg.get_long().map(|long| (heading, format!("--{}", long)))
+        })
+}
+
+/// Network impairment simulation shared by the transmit commands.
+/// Applied to audio packets after they are built, so sequence numbers
+/// and timestamps stay correct.
+#[derive(Args, Clone, Default)]
+pub struct ImpairmentArgs {
+    /// Randomly drop this percentage of audio packets
+    #[arg(long, default_value = "0", help_heading = "Impairments")]
+    pub drop_percent: f64,
+
+    /// Drop N consecutive packets, starting on average P times per second (e.g. 5@0.2)
+    #[arg(long, value_name = "N@P", help_heading = "Impairments")]
+    pub drop_burst: Option<DropBurst>,
+
+    /// Add up to this many milliseconds of random delay to each packet's send time
+    #[arg(long, default_value = "0", help_heading = "Impairments")]
+    pub jitter_ms: f64,
+
+    /// Send this percentage of packets twice
+    #[arg(long, default_value = "0", help_heading = "Impairments")]
+    pub duplicate_percent: f64,
+
+    /// Delay this percentage of packets by one slot
+    #[arg(long, default_value = "0", help_heading = "Impairments")]
+    pub reorder_percent: f64,
+
+    /// Seed for reproducible impairments (time-based by default)
+    #[arg(long, help_heading = "Impairments")]
+    pub impair_seed: Option<u64>,
+}
+
+/// Headerless input files shared by the transmit commands.
+/// Symphonia is skipped entirely when a format is given.
+#[derive(Args, Clone, Default)]
+pub struct InputArgs {
+    /// Read the file as headerless samples: ulaw, alaw, s16le or s16be.
+    /// Inferred (with a notice) for .ulaw and .alaw files
+    #[arg(long, value_name = "FORMAT", help_heading = "Input")]
+    pub input_format: Option<RawFormat>,
+
+    /// Sample rate of headerless input
+    #[arg(long, default_value_t = DEFAULT_RAW_RATE, help_heading = "Input")]
+    pub input_rate: u32,
+
+    /// Interleaved channels in headerless input (mixed down to mono)
+    #[arg(long, default_value = "1", help_heading = "Input")]
+    pub input_channels: u16,
+
+    /// Channel of a multichannel file to send: left, right, mix, or a
+    /// channel number from 1. Errors if the file doesn't have it
+    #[arg(long, value_name = "CHANNEL", default_value = "mix", help_heading = "Input")]
+    pub channel_select: ChannelSelect,
+}
+
+/// Refusing silent audio, shared by the transmit commands. The levels are
+/// measured after the file is read, before anything is sent.
+#[derive(Args, Clone)]
+pub struct LevelArgs {
+    /// Send audio even if it measures as silent
+    #[arg(long, help_heading = "Input")]
+    pub allow_silent: bool,
+
+    /// Average level (dBFS) below which the audio counts as silent
+    #[arg(long, value_name = "DBFS", default_value_t = DEFAULT_MIN_RMS_DBFS, allow_negative_numbers = true, help_heading = "Input")]
+    pub min_rms_db: f64,
+}
+
+/// Page detection shared by `monitor` and `test`. A new stream is held back
+/// until it reaches both thresholds, so stray packets don't become pages.
+#[derive(Args, Clone)]
+pub struct PageThresholdArgs {
+    /// Packets a new stream needs before it counts as a page
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..), help_heading = "Page detection")]
+    pub min_page_packets: u32,
+
+    /// Milliseconds between the first and latest packet a new stream needs
+    /// before it counts as a page
+    #[arg(long, default_value = "0", help_heading = "Page detection")]
+    pub min_page_ms: u64,
+
+    /// Carry a page on when its sender changes SSRC, if the new stream
+    /// comes from the same address and port within this many milliseconds
+    /// of the old one's last packet. Off unless given; see `cli::stitch`
+    #[arg(long, value_name = "MS", help_heading = "Page detection")]
+    pub stitch_gap_ms: Option<u64>,
+}
+
+impl PageThresholdArgs {
+    pub fn stitch_gap(&self) -> Option<Duration> {
+        self.stitch_gap_ms.map(Duration::from_millis)
+    }
+}
+
+/// Streams picked out by SSRC, shared by `monitor` and `test`; see
+/// `cli::ssrc_filter`.
+#[derive(Args, Clone, Default)]
+pub struct SsrcFilterArgs {
+    /// Only take packets with this SSRC (hex, e.g. 1A2B3C4D); repeat for
+    /// more. Others are counted as filtered and never start a page
+    #[arg(long = "ssrc", value_name = "HEX", value_parser = parse_ssrc, help_heading = "Page detection")]
+    pub allow: Vec<u32>,
+
+    /// Drop packets with this SSRC (hex), counting them as filtered;
+    /// repeat for more
+    #[arg(long, value_name = "HEX", value_parser = parse_ssrc, help_heading = "Page detection")]
+    pub ignore_ssrc: Vec<u32>,
 }
 
+impl SsrcFilterArgs {
+    pub fn filter(&self) -> SsrcFilter {
+        SsrcFilter::new(self.allow.clone(), self.ignore_ssrc.clone())
+    }
+}
+
+/// The page browser of `review --interactive`; see `cli::review_browser`.
+#[derive(Args, Clone, Default)]
+pub struct BrowseArgs {
+    /// Browse the pages from the keyboard: arrows to move, Enter for
+    /// details, m for metrics, t for tones, p to play, / to filter by
+    /// endpoint or loss>N, q to quit. Without a terminal, prints the
+    /// usual review
+    #[arg(long, conflicts_with_all = ["play", "metrics", "page", "jitter_sim", "spectrogram", "verify", "reanalyze", "anonymize"])]
+    pub interactive: bool,
+}
+
+/// Names shown beside Polycom channel numbers, shared by
+/// `polycom-transmit` and `polycom-monitor`; see `cli::channel_names`.
+#[derive(Args, Clone, Default)]
+pub struct ChannelNamesArgs {
+    /// File of channel names, one `26 = "All Call"` line each, shown
+    /// beside the channel number; adds to and overrides the config file's
+    #[arg(long, value_name = "FILE")]
+    pub channel_names: Option<PathBuf>,
+}
+
+impl ChannelNamesArgs {
+    pub fn load(&self, config: &PolycomConfig) -> Result<ChannelNames, ChannelNamesError> {
+        ChannelNames::load(config, self.channel_names.as_deref())
+    }
+}
+
+/// Ending after a number of pages, shared by `monitor` and
+/// `polycom-monitor`; see `cli::page_count`.
+#[derive(Args, Clone, Default)]
+pub struct CountArgs {
+    /// Stop once N pages have ended, after finishing any page still in
+    /// progress elsewhere. If the run times out first, exit with status 3
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), help_heading = "Count")]
+    pub count: Option<u32>,
+
+    /// Wait for N pages on every endpoint (on every channel in --channels,
+    /// for polycom-monitor) rather than N in all
+    #[arg(long, requires = "count", help_heading = "Count")]
+    pub count_per_endpoint: bool,
+
+    /// Stop as soon as the Nth page ends, cutting off pages in progress elsewhere
+    #[arg(long, requires = "count", help_heading = "Count")]
+    pub strict_count: bool,
+}
+
+impl CountArgs {
+    pub fn page_count(&self) -> Option<PageCount> {
+        self.count.map(|pages| PageCount {
+            pages,
+            per_endpoint: self.count_per_endpoint,
+            strict: self.strict_count,
+        })
+    }
+}
+
+/// Measurement timing shared by `monitor` and `test`. Received packets are
+/// timed by the kernel where it can, whether or not these are set.
+#[derive(Args, Clone, Default)]
+pub struct TimingArgs {
+    /// Run at real-time priority (`SCHED_FIFO`) with memory locked, so the
+    /// tool's own scheduling delays stay out of jitter figures.
+    /// Linux; needs `CAP_SYS_NICE` or root, and warns without it
+    #[arg(long, help_heading = "Timing")]
+    pub realtime: bool,
+
+    /// Busy-poll receive sockets for up to this many microseconds before
+    /// sleeping (`SO_BUSY_POLL`), trading CPU for wakeup latency.
+    /// Linux; needs `CAP_NET_ADMIN` and driver support
+    #[arg(long, value_name = "MICROS", help_heading = "Timing")]
+    pub busy_poll_us: Option<u32>,
+
+    /// Threads running the audio analysis FFTs, off the receive loop.
+    /// 0 runs them inline. Default: half the cores, at most one per endpoint
+    #[arg(long, value_name = "N", help_heading = "Timing")]
+    pub analysis_threads: Option<usize>,
+}
+
+/// Loss repair shared by `monitor` and `test`. Works only with
+/// `transmit --repair`; see `network::repair`.
+#[derive(Args, Clone, Default)]
+pub struct RepairArgs {
+    /// Ask the sender to resend lost packets and record them in place.
+    /// Non-standard: only `transmit --repair` answers
+    #[arg(long, help_heading = "Repair")]
+    pub repair: bool,
+
+    /// Milliseconds a gap may stay open before it is asked for. Packets are
+    /// held back up to four times this, and the gap given up after that
+    #[arg(long, value_name = "MS", default_value = "50", value_parser = clap::value_parser!(u64).range(1..), requires = "repair", help_heading = "Repair")]
+    pub repair_window: u64,
+}
+
+impl RepairArgs {
+    pub fn window(&self) -> Option<Duration> {
+        self.repair.then(|| Duration::from_millis(self.repair_window))
+    }
+}
+
+/// Live packet decoding shared by `monitor` and `polycom-monitor`; see
+/// `cli::inspect`.
+#[derive(Args, Clone, Default)]
+pub struct InspectArgs {
+    /// Print each received datagram with a decode attempt: RTP or Polycom
+    /// header fields, or a hex dump of anything else. Pages are still
+    /// processed as usual
+    #[arg(long, help_heading = "Inspect")]
+    pub inspect: bool,
+
+    /// Only inspect datagrams from this source: src=ADDR or src=ADDR:PORT
+    #[arg(long, value_name = "FILTER", requires = "inspect", help_heading = "Inspect")]
+    pub inspect_filter: Option<InspectFilter>,
+
+    /// Stop inspecting after this many datagrams
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), requires = "inspect", help_heading = "Inspect")]
+    pub inspect_count: Option<u64>,
+
+    /// Inspect at most this many datagrams a second; the rest are counted
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), requires = "inspect", help_heading = "Inspect")]
+    pub inspect_rate: Option<u32>,
+}
+
+impl InspectArgs {
+    pub fn options(&self) -> Option<InspectOptions> {
+        self.inspect.then_some(InspectOptions {
+            filter: self.inspect_filter,
+            rate: self.inspect_rate,
+            count: self.inspect_count,
+        })
+    }
+}
+
+/// Recording and capture naming shared by the receiving commands.
+/// See `utils::filename` for the template syntax.
+#[derive(Args, Clone, Default)]
+pub struct NamingArgs {
+    /// Name each recording from a template, e.g.
+    /// "{site}_{endpoint}_{page:04}_{start:%Y%m%dT%H%M%S}.wav".
+    /// Tokens: {site}, {endpoint}, {address}, {port}, {page}, {start},
+    /// {ssrc}, {channel}, {caller} and the channel's name, `{channel_name}`.
+    /// Existing files are never overwritten; a taken name gets -1, -2, ...
+    /// appended
+    #[arg(long, value_name = "TEMPLATE", help_heading = "Naming")]
+    pub name_template: Option<NameTemplate>,
+
+    /// Site code for {site}, in recording names and in a --pcap file name
+    #[arg(long, help_heading = "Naming")]
+    pub site: Option<String>,
+}
+
+/// Quiet-hours guard shared by the transmit commands.
+/// Checked before each page, including every `--loop` iteration.
+#[derive(Args, Clone, Default)]
+pub struct ScheduleArgs {
+    /// Only transmit inside this local-time window, e.g. "08:00-17:00",
+    /// "Mon-Fri 08:00-17:00" or "22:00-06:00" (overnight). Repeatable
+    #[arg(long, value_name = "WINDOW", help_heading = "Schedule")]
+    pub allowed_window: Vec<TimeWindow>,
+
+    /// Wait for the next allowed window to open instead of refusing
+    #[arg(long, requires = "allowed_window", help_heading = "Schedule")]
+    pub wait_for_window: bool,
+}
+
+impl ScheduleArgs {
+    pub fn schedule(&self) -> Schedule {
+        Schedule::new(self.allowed_window.clone())
+    }
+}
+
+impl From<InputArgs> for AudioInput {
+    fn from(args: InputArgs) -> Self {
+        Self {
+            format: args.input_format,
+            sample_rate: args.input_rate,
+            channels: args.input_channels,
+            channel: args.channel_select,
+        }
+    }
+}
+
+impl From<LevelArgs> for LevelCheck {
+    fn from(args: LevelArgs) -> Self {
+        Self {
+            min_rms_dbfs: args.min_rms_db,
+            allow_silent: args.allow_silent,
+        }
+    }
+}
+
+impl From<PageThresholdArgs> for PageThreshold {
+    fn from(args: PageThresholdArgs) -> Self {
+        Self {
+            min_packets: args.min_page_packets,
+            min_duration: Duration::from_millis(args.min_page_ms),
+        }
+    }
+}
+
+impl From<NamingArgs> for Naming {
+    fn from(args: NamingArgs) -> Self {
+        Self {
+            template: args.name_template,
+            site: args.site,
+        }
+    }
+}
+
+impl From<ImpairmentArgs> for ImpairmentConfig {
+    fn from(args: ImpairmentArgs) -> Self {
+        Self {
+            drop_percent: args.drop_percent,
+            drop_burst: args.drop_burst,
+            jitter_ms: args.jitter_ms,
+            duplicate_percent: args.duplicate_percent,
+            reorder_percent: args.reorder_percent,
+            seed: args.impair_seed,
+        }
+    }
+}
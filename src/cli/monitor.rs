@@ -1,16 +1,64 @@
-use crate::codec::{create_decoder_for_payload_type, AudioDecoder, CodecType};
-use crate::network::{MulticastSocket, RtpPacket, PayloadType};
+use crate::codec::{
+    create_decoder_for_payload_type, create_decoder_for_spec, g722, opus, AudioDecoder, CodecError, CodecSpec, CodecType,
+    UnavailableDecoder,
+};
+use crate::network::repair::{RepairBuffer, RepairClient, RepairSummary, RetransmitStats};
+use crate::network::rtp::{extend_sequence, extend_timestamp};
+use crate::network::{Dscp, ImpairmentStats, MulticastInterface, MulticastSocket, PcapWriter, RtpPacket, PayloadType};
+use crate::cli::analysis_pool::{self, AnalysisPool, AnalysisWorker};
 use crate::cli::audio_analyzer::{AudioAnalyzer, AudioStats, AudioAnalysis, format_frequency, format_db};
+use crate::cli::loudness::format_loudness;
+use crate::cli::control::{resolve_endpoints, ResolvedEndpoints, ControlCommand, ControlError, ControlHandle, ControlRequest, EndpointStatus};
+use crate::cli::annotation::{Annotation, AnnotationLog, AnnotationSource};
+use crate::cli::audio_pipe::{AudioPipe, PipeCommand, PipeOutput};
+use crate::cli::bridge::BridgeReport;
+use crate::cli::membership::{self, Membership, MembershipWatch};
+use crate::cli::page_count::{CountProgress, CountSummary, PageCount, PageCounter};
+use crate::cli::page_threshold::{PageThreshold, PendingPage};
+use crate::cli::decode_policy::DecodeErrorPolicy;
+use crate::cli::fd_limit::{self, FdLimitError, FdPlan};
+use crate::cli::drain::{FairReceiver, DRAIN_BUDGET};
+use crate::cli::finalize::{FinalizeQueue, FinishedRecording, PendingRecording, RecordingKind, FINALIZE_CONCURRENCY};
+use crate::cli::fingerprint::SenderFingerprint;
+use crate::cli::forward::{ForwardError, ForwardSummary, ForwardTarget, Forwarder, PageForwarder};
+use crate::cli::integrity::RecordingIntegrity;
+use crate::cli::inspect::{InspectOptions, Inspector};
+use crate::cli::logging::limited;
+use crate::cli::payloads::{payloads_path, PayloadWriter};
 use crate::cli::recorder::WavRecorder;
-use crate::utils::range_parser::{parse_range, MulticastEndpoint, RangeParseError};
-use chrono::{DateTime, Utc};
-use serde::Serialize;
-use std::collections::HashMap;
-use std::io::{self, Write};
-use std::net::Ipv4Addr;
-use std::path::PathBuf;
+use crate::cli::spectrum::SpectrumWriter;
+use crate::cli::ssrc_filter::SsrcFilter;
+use crate::cli::stitch::{self, SsrcChange};
+use crate::cli::timing::{timing_path, TimingGranularity, TimingWriter};
+use crate::cli::stats_report::{LastPage, ReportSettings, SharedStats, StatsBoard, StatsReporter, StatsSnapshot};
+use crate::cli::status_page::{self, StatusPageError, StatusServer};
+use crate::cli::plan::{ChimePlan, TransmitPlan};
+use crate::cli::igmp_cycle::{CycleReport, CycleResult};
+use crate::cli::frame_cache::CacheUsage;
+use crate::cli::quality::{self, MosBreakdown, QualityInputs};
+use crate::cli::polycom_transmit::RtpLegReport;
+use crate::cli::transcoding::{BandSplit, TranscodingThresholds};
+use crate::cli::transmit::WindowWait;
+use crate::cli::watermark::WatermarkDetection;
+use crate::cli::watchdog::{Rebuild, Restart, RestartReason, SocketErrorRecord, SocketWatchdog, MAX_RESTARTS};
+use crate::cli::schema::VersionedEvent;
+use crate::cli::timeout::{Timeout, TimeoutMode};
+use crate::cli::receipt::ReceivedReceipt;
+use crate::utils::clock::{ClockWatch, SystemClock};
+use crate::utils::filename::{unique_name, FilenameError, NameFields, Naming, Token};
+use crate::utils::range_parser::{parse_range, EndpointOptions, MulticastEndpoint, RangeParseError};
+use chrono::{DateTime, Local, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 use thiserror::Error;
+use tracing::{info, warn};
 
 #[derive(Error, Debug)]
 pub enum MonitorError {
@@ -27,13 +75,255 @@ pub enum MonitorError {
     Io(#[from] io::Error),
 
     #[error("Codec error: {0}")]
-    Codec(#[from] crate::codec::CodecError),
+    Codec(#[from] CodecError),
 
     #[error("Recorder error: {0}")]
     Recorder(#[from] super::recorder::RecorderError),
 
+    #[error("Control error: {0}")]
+    Control(#[from] ControlError),
+
+    #[error("Naming error: {0}")]
+    Naming(#[from] FilenameError),
+
+    #[error("Forward error: {0}")]
+    Forward(#[from] ForwardError),
+
+    #[error("{0}")]
+    FdLimit(#[from] FdLimitError),
+
+    #[error("{0}")]
+    StatusPage(#[from] StatusPageError),
+
     #[error("No endpoints to monitor")]
     NoEndpoints,
+
+    #[error("Only {captured} of {requested} pages received before the run ended")]
+    CountNotMet { captured: u32, requested: u32 },
+
+    #[error("Receiving on port {port} still failing after {attempts} socket rebuilds: {last_error}")]
+    SocketFailed { port: u16, attempts: u32, last_error: String },
+}
+
+/// Tokens a monitor recording name can use
+const RECORDING_TOKENS: &[Token] = &[
+    Token::Site,
+    Token::Endpoint,
+    Token::Address,
+    Token::Port,
+    Token::Page,
+    Token::Start,
+    Token::Ssrc,
+];
+
+/// Forward sequence jump at or beyond which the sender is assumed to have restarted
+const SEQUENCE_RESET_THRESHOLD: u16 = 1000;
+
+/// Backward sequence jump treated as a late (reordered) packet rather than a restart
+const REORDER_WINDOW: u16 = 100;
+
+/// Packet spacing assumed until the stream's own frame spacing is seen
+const DEFAULT_PACKET_SPACING_SECS: f64 = 0.02;
+
+/// Most audio one missing sequence number can account for (an Opus packet's limit)
+const MAX_PACKET_SECS: f64 = 0.12;
+
+/// How far a timestamp jump may exceed the wait for its packet and still be silence
+const DTX_TOLERANCE_SECS: f64 = 0.5;
+
+/// Talk-spurts kept per page; a sender marking every packet would otherwise grow the list without bound
+const MAX_SPURTS: usize = 1000;
+
+/// Share of a page's packets that may differ from the usual payload size
+/// before the page is flagged (a short final frame is normal)
+const PAYLOAD_DEVIATION_WARN_PERCENT: f64 = 1.0;
+
+/// Audio a packet carries, in RTP timestamp ticks
+type PacketDuration = fn(&[u8]) -> Option<u32>;
+
+/// Counts of the time between consecutive packet arrivals
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct GapHistogram {
+    #[serde(rename = "under_30ms")]
+    pub under_30ms: u64,
+    #[serde(rename = "30_to_60ms")]
+    pub from_30_to_60ms: u64,
+    #[serde(rename = "60_to_120ms")]
+    pub from_60_to_120ms: u64,
+    #[serde(rename = "120_to_300ms")]
+    pub from_120_to_300ms: u64,
+    #[serde(rename = "over_300ms")]
+    pub over_300ms: u64,
+}
+
+impl GapHistogram {
+    pub fn record(&mut self, gap_ms: f64) {
+        let bucket = if gap_ms < 30.0 {
+            &mut self.under_30ms
+        } else if gap_ms < 60.0 {
+            &mut self.from_30_to_60ms
+        } else if gap_ms < 120.0 {
+            &mut self.from_60_to_120ms
+        } else if gap_ms < 300.0 {
+            &mut self.from_120_to_300ms
+        } else {
+            &mut self.over_300ms
+        };
+        *bucket += 1;
+    }
+}
+
+/// Arriving IP TTL over a page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct TtlSummary {
+    pub min: u8,
+    pub max: u8,
+    pub most_common: u8,
+}
+
+impl fmt::Display for TtlSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "{}-{} (mostly {})", self.min, self.max, self.most_common)
+        }
+    }
+}
+
+/// Arriving DSCP over a page, against `--expect-dscp` if given
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct DscpSummary {
+    pub most_common: Dscp,
+    /// Packets per DSCP value
+    pub histogram: BTreeMap<u8, u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<Dscp>,
+    /// Packets marked other than `expected`
+    #[serde(default)]
+    pub unexpected_packets: u64,
+}
+
+impl DscpSummary {
+    /// Packets whose DSCP was reported
+    fn packets(&self) -> u64 {
+        self.histogram.values().sum()
+    }
+
+    /// Why the marking doesn't match `--expect-dscp`, if it doesn't
+    pub fn mismatch(&self) -> Option<String> {
+        let expected = self.expected.filter(|_| self.unexpected_packets > 0)?;
+        Some(format!(
+            "{} of {} packets arrived without the expected DSCP {} ({})",
+            self.unexpected_packets,
+            self.packets(),
+            expected,
+            self
+        ))
+    }
+}
+
+impl fmt::Display for DscpSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.histogram.len() <= 1 {
+            return write!(f, "{}", self.most_common);
+        }
+        let total = self.packets() as f64;
+        // Most packets first
+        let mut values: Vec<(&u8, &u64)> = self.histogram.iter().collect();
+        values.sort_by(|a, b| b.1.cmp(a.1));
+        let values: Vec<String> = values
+            .into_iter()
+            .filter_map(|(&value, &count)| Some(format!("{} {:.1}%", Dscp::new(value)?, count as f64 * 100.0 / total)))
+            .collect();
+        f.write_str(&values.join(", "))
+    }
+}
+
+/// Payload sizes over a page. A sender that pads or truncates the odd packet
+/// corrupts the decoded audio while loss and jitter look clean
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct PayloadSizes {
+    /// Most common payload size in bytes
+    pub modal_bytes: usize,
+    /// Packets of any other size
+    pub deviating_packets: u64,
+    /// Payloads that aren't a whole number of samples or, for G.711, milliseconds
+    pub misaligned_packets: u64,
+    /// Packets per payload size in bytes
+    pub histogram: BTreeMap<usize, u64>,
+}
+
+impl PayloadSizes {
+    pub fn deviation_percent(&self) -> f64 {
+        let packets: u64 = self.histogram.values().sum();
+        if packets == 0 {
+            0.0
+        } else {
+            100.0 * self.deviating_packets as f64 / packets as f64
+        }
+    }
+
+    /// A description of the page's odd-sized payloads, if there are enough to matter
+    pub fn anomaly(&self) -> Option<String> {
+        if self.misaligned_packets == 0 && self.deviation_percent() <= PAYLOAD_DEVIATION_WARN_PERCENT {
+            return None;
+        }
+        let mut parts = Vec::new();
+        if self.deviating_packets > 0 {
+            let others: Vec<String> = self
+                .histogram
+                .iter()
+                .filter(|&(&size, _)| size != self.modal_bytes)
+                .map(|(size, count)| format!("{} bytes x{}", size, count))
+                .collect();
+            parts.push(format!(
+                "{} packets ({:.1}%) differ from the usual {} bytes: {}",
+                self.deviating_packets,
+                self.deviation_percent(),
+                self.modal_bytes,
+                others.join(", ")
+            ));
+        }
+        if self.misaligned_packets > 0 {
+            parts.push(format!("{} not whole frames for the codec", self.misaligned_packets));
+        }
+        Some(parts.join("; "))
+    }
+}
+
+/// A discontinuity in an RTP stream detected by `PageStats::update`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamDiscontinuity {
+    /// Sequence numbers jumped (e.g. sender reboot); loss/jitter were re-baselined
+    SequenceReset { old_sequence: u16, new_sequence: u16 },
+    /// The same SSRC arrived from a second source address
+    SsrcCollision {
+        ssrc: u32,
+        original_source: SocketAddr,
+        new_source: SocketAddr,
+    },
+    /// A TTL not seen before in this page arrived: a second sender or a path change
+    TtlVaried {
+        source: SocketAddr,
+        previous_ttl: u8,
+        ttl: u8,
+    },
+    /// A G.722 sender's timestamps tick at `clock_rate` rather than RFC
+    /// 3551's `expected_clock_rate`; they are counted at that rate from here on
+    NonstandardTimestamps { clock_rate: u32, expected_clock_rate: u32 },
+}
+
+/// A talk-spurt: a run of audio the sender started with the RTP marker bit
+/// after suppressing silence
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TalkSpurt {
+    /// Arrival of the marked packet, in seconds after the page's first packet
+    pub offset_secs: f64,
+    /// Silence before the spurt implied by the RTP timestamp jump; absent
+    /// when the spurt opens the page or follows a sequence reset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preceding_gap_ms: Option<f64>,
 }
 
 /// Statistics for a monitored page
@@ -44,44 +334,574 @@ pub struct PageStats {
     pub packets_lost: u64,
     pub jitter_ms: f64,
     pub duration_secs: f64,
+    pub resets: u64,
+    pub ssrc_collisions: u64,
+    /// Longest time between two consecutive packets (the idle that ends the page is not a gap)
+    pub max_gap_ms: f64,
+    pub gap_histogram: GapHistogram,
+    /// Time the sender sent nothing (Opus DTX); not loss, and not a freeze
+    pub dtx_gap_secs: f64,
+    /// Packets the decoder rejected
+    pub decode_errors: u64,
+    /// Packets that decoded to fewer samples than their payload size implies
+    pub short_decodes: u64,
+    /// Payloads carrying less audio than the usual spacing to the next
+    /// packet: cut short in transit (G.711 and linear PCM)
+    pub truncated_payloads: u64,
+    /// Packets with the RTP marker bit set (each starts a talk-spurt)
+    pub marked_packets: u64,
+    /// Packets carrying RTP padding after their payload, as Cisco IOS
+    /// gateways send
+    pub padded_packets: u64,
+    /// Padding over those packets, count bytes included
+    pub padding_bytes: u64,
+    #[serde(skip)]
+    spurts: Vec<TalkSpurt>,
+    #[serde(skip)]
+    first_arrival: Option<Instant>,
+    #[serde(skip)]
+    source: Option<SocketAddr>,
+    #[serde(skip)]
+    colliding_sources: Vec<SocketAddr>,
+    /// Sequence number of the latest packet in order, extended across wraps
     #[serde(skip)]
-    last_sequence: Option<u16>,
+    last_sequence: Option<u64>,
+    /// Its timestamp, extended across wraps
     #[serde(skip)]
-    last_timestamp: Option<u32>,
+    last_timestamp: Option<u64>,
+    /// Extended timestamp and arrival of the first packet since the stream
+    /// started or reset, which clock drift is measured from
+    #[serde(skip)]
+    clock_origin: Option<(u64, Instant)>,
     #[serde(skip)]
     last_arrival: Option<Instant>,
     #[serde(skip)]
     jitter_accumulator: f64,
+    /// RTP timestamp clock rate; 0 means the 8kHz telephony default
+    #[serde(skip)]
+    clock_rate: u32,
+    /// Arrival of the previous packet, kept across sequence resets
+    #[serde(skip)]
+    previous_arrival: Option<Instant>,
+    /// Timestamp step between consecutive packets, in seconds (0 until seen)
+    #[serde(skip)]
+    packet_spacing_secs: f64,
+    /// Time between arrivals beyond the packet spacing, summed over the page
+    #[serde(skip)]
+    excess_gap_secs: f64,
+    /// The part of `excess_gap_secs` added by the latest packet
+    #[serde(skip)]
+    last_excess_gap_secs: f64,
+    /// Reads a packet's duration, for codecs whose frame size varies (Opus)
+    #[serde(skip)]
+    packet_duration: Option<PacketDuration>,
+    /// Duration of the previous packet, in RTP ticks
+    #[serde(skip)]
+    last_duration: Option<u32>,
+    /// RTP ticks before the latest packet that no received packet carried
+    #[serde(skip)]
+    skipped_ticks: u32,
+    /// The part of `skipped_ticks` lost in transit rather than never sent
+    #[serde(skip)]
+    lost_ticks: u32,
+    /// Packets lost just before the latest one
+    #[serde(skip)]
+    missing_packets: u16,
+    /// RTP ticks missing from the end of the previous packet's payload
+    #[serde(skip)]
+    truncated_ticks: u32,
+    /// Payload bytes per RTP tick, for codecs where the two map directly
+    #[serde(skip)]
+    bytes_per_tick: Option<usize>,
+    /// RTP ticks of audio in the previous packet's payload, where known from its size
+    #[serde(skip)]
+    last_payload_ticks: Option<u32>,
+    /// Packets per arriving TTL (empty when the platform doesn't report it)
+    #[serde(skip)]
+    ttl_counts: BTreeMap<u8, u64>,
+    #[serde(skip)]
+    last_ttl: Option<u8>,
+    /// Packets per arriving DSCP (empty when the platform doesn't report it)
+    #[serde(skip)]
+    dscp_counts: BTreeMap<u8, u64>,
+    /// Packets per payload size in bytes
+    #[serde(skip)]
+    payload_sizes: BTreeMap<usize, u64>,
+    /// See [`CodecSpec::payload_granularity`]
+    #[serde(skip)]
+    payload_granularity: Option<usize>,
+    #[serde(skip)]
+    misaligned_payloads: u64,
+    /// See [`CodecSpec::bytes_per_sample`]
+    #[serde(skip)]
+    bytes_per_sample: Option<usize>,
+    /// 2 for a G.722 stream whose timestamps tick at its 16kHz sample rate
+    /// rather than the 8kHz RFC 3551 gives it; its timestamp steps are
+    /// halved. 0 or 1 for any other stream
+    #[serde(skip)]
+    timestamp_divisor: u32,
+    /// Watch a G.722 stream's first consecutive packets for a 16kHz clock
+    #[serde(skip)]
+    detect_double_clock: bool,
 }
 
 impl PageStats {
-    pub fn update(&mut self, packet: &RtpPacket) {
+    /// Stats for a stream whose RTP timestamps tick at `clock_rate` Hz
+    pub fn with_clock_rate(clock_rate: u32) -> Self {
+        Self {
+            clock_rate,
+            ..Self::default()
+        }
+    }
+
+    /// Stats for a stream of `spec`. Opus packets are timed from their TOC
+    /// byte and G.722 packets from their size, so silence the sender didn't
+    /// send (DTX) can be told from loss
+    pub fn for_codec(spec: CodecSpec) -> Self {
+        let packet_duration: Option<PacketDuration> = match spec.codec {
+            CodecType::Opus => Some(opus::packet_duration),
+            CodecType::G722 => Some(g722::packet_duration),
+            _ => None,
+        };
+        Self {
+            packet_duration,
+            detect_double_clock: spec.codec == CodecType::G722,
+            payload_granularity: spec.payload_granularity(),
+            bytes_per_sample: spec.bytes_per_sample(),
+            bytes_per_tick: spec.bytes_per_sample().map(|width| width * usize::from(spec.channels)),
+            ..Self::with_clock_rate(spec.rtp_clock_rate())
+        }
+    }
+
+    /// With `nonstandard`, count a G.722 stream's timestamps as ticking at
+    /// 16kHz from the start (`--g722-nonstandard-timestamps`) rather than
+    /// waiting to see
+    pub fn with_g722_nonstandard_timestamps(mut self, nonstandard: bool) -> Self {
+        if nonstandard && self.detect_double_clock {
+            self.timestamp_divisor = 2;
+            self.detect_double_clock = false;
+        }
+        self
+    }
+
+    /// Rate the stream's RTP timestamps actually tick at
+    pub fn timestamp_clock_rate(&self) -> u32 {
+        let clock_rate = if self.clock_rate == 0 { 8000 } else { self.clock_rate };
+        clock_rate * self.timestamp_divisor.max(1)
+    }
+
+    /// Ticks of the stream's RTP clock from the latest packet to one stamped
+    /// `timestamp`, read across a wrap; negative for a step back. A 16kHz
+    /// G.722 clock is counted in RFC 3551's 8kHz ticks
+    fn timestamp_step(&self, timestamp: u32) -> Option<i64> {
+        let last = self.last_timestamp?;
+        let step = extend_timestamp(Some(last), timestamp) as i64 - last as i64;
+        Some(step / i64::from(self.timestamp_divisor.max(1)))
+    }
+
+    /// Whether a packet comes from the address this stream's SSRC was first seen from
+    pub fn is_stream_source(&self, source: SocketAddr) -> bool {
+        self.source.is_none_or(|s| s == source)
+    }
+
+    /// Account for a packet, returning any discontinuity it revealed. A
+    /// packet from a second source with the stream's SSRC counts only as a
+    /// collision; the caller drops it (see [`Self::is_stream_source`])
+    pub fn update(&mut self, packet: &RtpPacket) -> Option<StreamDiscontinuity> {
+        // Same SSRC from a different sender - keep its packets out of every figure
+        let source = *self.source.get_or_insert(packet.source);
+        if packet.source != source {
+            if self.colliding_sources.contains(&packet.source) {
+                return None;
+            }
+            self.colliding_sources.push(packet.source);
+            self.ssrc_collisions += 1;
+            return Some(StreamDiscontinuity::SsrcCollision {
+                ssrc: packet.header.ssrc,
+                original_source: source,
+                new_source: packet.source,
+            });
+        }
+
         self.packets_received += 1;
         self.bytes_received += packet.payload.len() as u64;
+        self.record_payload_size(packet.payload.len());
+        if packet.padding_len > 0 {
+            self.padded_packets += 1;
+            self.padding_bytes += packet.padding_len as u64;
+        }
+        self.skipped_ticks = 0;
+        self.lost_ticks = 0;
+        self.missing_packets = 0;
+        self.truncated_ticks = 0;
+        let ttl_varied = self.record_ttl(packet);
+        if let Some(dscp) = packet.dscp {
+            *self.dscp_counts.entry(dscp.value()).or_insert(0) += 1;
+        }
+
+        self.record_arrival(packet.received_at);
+        let first_arrival = *self.first_arrival.get_or_insert(packet.received_at);
+        let spacing_secs = self.packet_spacing_secs;
 
-        // Calculate packet loss
+        let mut discontinuity = ttl_varied;
+
+        // Calculate packet loss, classifying large jumps as stream resets
+        let sequence = extend_sequence(self.last_sequence, packet.header.sequence_number);
         if let Some(last_seq) = self.last_sequence {
-            let expected = last_seq.wrapping_add(1);
-            if packet.header.sequence_number != expected {
-                let gap = packet.header.sequence_number.wrapping_sub(last_seq);
-                if gap > 1 && gap < 1000 {
-                    self.packets_lost += (gap - 1) as u64;
+            let gap = sequence as i64 - last_seq as i64;
+            if (-i64::from(REORDER_WINDOW)..=0).contains(&gap) {
+                // Duplicate or late packet - don't move the sequence baseline
+                return None;
+            } else if gap < 0 || gap >= i64::from(SEQUENCE_RESET_THRESHOLD) {
+                self.resets += 1;
+                self.last_timestamp = None;
+                self.clock_origin = None;
+                self.last_arrival = None;
+                discontinuity = Some(StreamDiscontinuity::SequenceReset {
+                    old_sequence: last_seq as u16,
+                    new_sequence: packet.header.sequence_number,
+                });
+            } else {
+                let gap = gap as u16;
+                if gap == 1 && self.detect_double_clock && !packet.header.marker {
+                    discontinuity = discontinuity.or(self.detect_double_clock(packet));
+                }
+                let silence_advanced_sequence = self.account_skipped_audio(packet, gap);
+                if gap > 1 {
+                    if !silence_advanced_sequence {
+                        self.packets_lost += u64::from(gap - 1);
+                        self.missing_packets = gap - 1;
+                    }
+                } else if let Some(timestamp_step) = self.timestamp_step(packet.header.timestamp) {
+                    // Consecutive packets: their timestamp step (or, where known,
+                    // the previous packet's duration) is the nominal spacing
+                    let step = self.last_duration.unwrap_or_else(|| u32::try_from(timestamp_step).unwrap_or(0));
+                    let clock_rate = if self.clock_rate == 0 { 8000 } else { self.clock_rate };
+                    // A marked packet's step includes the silence before it
+                    if step > 0 && step < clock_rate && !packet.header.marker {
+                        self.packet_spacing_secs = f64::from(step) / f64::from(clock_rate);
+                    }
+                    // The usual step after a payload carrying less than that: it was cut short
+                    let spacing = (self.packet_spacing_secs * f64::from(clock_rate)).round() as u32;
+                    if let Some(covered) = self.last_payload_ticks.filter(|&covered| covered < step && step == spacing) {
+                        self.truncated_payloads += 1;
+                        self.truncated_ticks = step - covered;
+                    }
                 }
             }
         }
 
         // Calculate jitter (RFC 3550 algorithm)
-        if let (Some(last_ts), Some(last_arrival)) = (self.last_timestamp, self.last_arrival) {
-            let arrival_diff = packet.received_at.duration_since(last_arrival).as_secs_f64() * 8000.0;
-            let ts_diff = packet.header.timestamp.wrapping_sub(last_ts) as f64;
+        if let (Some(step), Some(last_arrival)) = (self.timestamp_step(packet.header.timestamp), self.last_arrival) {
+            let clock_rate = if self.clock_rate == 0 { 8000.0 } else { self.clock_rate as f64 };
+            let arrival_diff = packet.received_at.duration_since(last_arrival).as_secs_f64() * clock_rate;
+            let ts_diff = step as f64;
             let d = (arrival_diff - ts_diff).abs();
             self.jitter_accumulator += (d - self.jitter_accumulator) / 16.0;
-            self.jitter_ms = self.jitter_accumulator / 8.0; // Convert to ms
+            self.jitter_ms = self.jitter_accumulator * 1000.0 / clock_rate; // Convert to ms
+        }
+
+        if packet.header.marker {
+            self.record_spurt(packet, first_arrival, spacing_secs);
         }
 
-        self.last_sequence = Some(packet.header.sequence_number);
-        self.last_timestamp = Some(packet.header.timestamp);
+        let timestamp = extend_timestamp(self.last_timestamp, packet.header.timestamp);
+        self.clock_origin.get_or_insert((timestamp, packet.received_at));
+        self.last_sequence = Some(sequence);
+        self.last_timestamp = Some(timestamp);
         self.last_arrival = Some(packet.received_at);
+        self.last_duration = self.packet_duration.and_then(|duration| duration(&packet.payload));
+        self.last_payload_ticks = self
+            .bytes_per_tick
+            .filter(|&width| width > 0)
+            .map(|width| u32::try_from(packet.payload.len() / width).unwrap_or(u32::MAX));
+
+        discontinuity
+    }
+
+    /// Count a marked packet and note where its spurt starts. The silence
+    /// before it is the timestamp jump beyond one packet's worth of audio
+    /// (the previous packet's duration where known, else the page's spacing)
+    fn record_spurt(&mut self, packet: &RtpPacket, first_arrival: Instant, spacing_secs: f64) {
+        self.marked_packets += 1;
+        if self.spurts.len() >= MAX_SPURTS {
+            return;
+        }
+        let clock_rate = if self.clock_rate == 0 { 8000.0 } else { f64::from(self.clock_rate) };
+        let preceding_gap_ms = self.timestamp_step(packet.header.timestamp).filter(|&step| step >= 0).map(|step| {
+            let spacing = if spacing_secs > 0.0 { spacing_secs } else { DEFAULT_PACKET_SPACING_SECS };
+            let nominal = self.last_duration.map_or(spacing * clock_rate, f64::from);
+            ((step as f64 - nominal) / clock_rate * 1000.0).max(0.0)
+        });
+        self.spurts.push(TalkSpurt {
+            offset_secs: packet.received_at.saturating_duration_since(first_arrival).as_secs_f64(),
+            preceding_gap_ms,
+        });
+    }
+
+    /// Decide, from the first pair of consecutive packets, whether a G.722
+    /// sender ticks its RTP clock at the 16kHz sample rate: its timestamps
+    /// then step about twice what the previous packet carried
+    fn detect_double_clock(&mut self, packet: &RtpPacket) -> Option<StreamDiscontinuity> {
+        let (Some(step), Some(duration)) = (self.timestamp_step(packet.header.timestamp), self.last_duration) else {
+            return None;
+        };
+        self.detect_double_clock = false;
+        if step.abs_diff(2 * i64::from(duration)) > u64::from(duration / 10) {
+            return None;
+        }
+        self.timestamp_divisor = 2;
+        Some(StreamDiscontinuity::NonstandardTimestamps {
+            clock_rate: self.timestamp_clock_rate(),
+            expected_clock_rate: self.timestamp_clock_rate() / 2,
+        })
+    }
+
+    /// Talk-spurts the sender marked, in arrival order
+    pub fn spurts(&self) -> &[TalkSpurt] {
+        &self.spurts
+    }
+
+    /// Where packet durations are known, find the audio between the previous
+    /// packet's end and this packet's timestamp. What the `gap - 1` missing
+    /// packets could have carried is loss; the rest is time the sender sent
+    /// nothing. Returns true when that silence also advanced the sequence
+    /// number, so the missing numbers aren't lost packets.
+    fn account_skipped_audio(&mut self, packet: &RtpPacket, gap: u16) -> bool {
+        let (Some(step), Some(last_duration), Some(last_arrival)) =
+            (self.timestamp_step(packet.header.timestamp), self.last_duration, self.last_arrival)
+        else {
+            return false;
+        };
+        // Nothing skipped, or a step back
+        let skipped = match u32::try_from(step - i64::from(last_duration)) {
+            Ok(skipped) if skipped > 0 => skipped,
+            _ => return false,
+        };
+
+        // A jump well beyond the wait for this packet is a timestamp discontinuity, not silence
+        let clock_rate = if self.clock_rate == 0 { 8000.0 } else { f64::from(self.clock_rate) };
+        let skipped_secs = f64::from(skipped) / clock_rate;
+        let waited_secs = packet.received_at.saturating_duration_since(last_arrival).as_secs_f64();
+        if skipped_secs > waited_secs + DTX_TOLERANCE_SECS {
+            return false;
+        }
+        self.skipped_ticks = skipped;
+
+        let missing = u32::from(gap - 1);
+        let advanced_sequence = missing > 0 && skipped_secs > f64::from(missing) * MAX_PACKET_SECS;
+        let silent_ticks = if advanced_sequence {
+            skipped
+        } else {
+            skipped.saturating_sub(missing * last_duration)
+        };
+        self.lost_ticks = skipped - silent_ticks;
+        let silent_secs = f64::from(silent_ticks) / clock_rate;
+        self.dtx_gap_secs += silent_secs;
+        // Waiting through silence isn't a freeze
+        self.excess_gap_secs -= silent_secs.min(self.last_excess_gap_secs);
+        advanced_sequence
+    }
+
+    /// Audio before the latest packet that no received packet carried (DTX
+    /// or loss), where packet durations are known. Recorders fill it with
+    /// silence to stay on the RTP timeline
+    pub fn skipped_secs(&self) -> f64 {
+        let clock_rate = if self.clock_rate == 0 { 8000.0 } else { f64::from(self.clock_rate) };
+        f64::from(self.skipped_ticks) / clock_rate
+    }
+
+    /// The part of `skipped_secs` that was lost in transit rather than not
+    /// sent, which a decoder can conceal
+    pub fn lost_secs(&self) -> f64 {
+        let clock_rate = if self.clock_rate == 0 { 8000.0 } else { f64::from(self.clock_rate) };
+        f64::from(self.lost_ticks) / clock_rate
+    }
+
+    /// Audio the packets lost just before the latest one carried: the lost
+    /// part of `skipped_secs` where packet durations are known, else the
+    /// packets at the usual spacing
+    pub fn missing_secs(&self) -> f64 {
+        match self.lost_secs() {
+            secs if secs > 0.0 => secs,
+            _ => f64::from(self.missing_packets) * self.nominal_packet_secs(),
+        }
+    }
+
+    /// Audio missing from the end of the previous packet's payload, found
+    /// when the latest packet arrived the usual step after it
+    pub fn truncated_secs(&self) -> f64 {
+        let clock_rate = if self.clock_rate == 0 { 8000.0 } else { f64::from(self.clock_rate) };
+        f64::from(self.truncated_ticks) / clock_rate
+    }
+
+    /// How far arrivals have fallen behind the sender's RTP clock since the
+    /// stream started or last reset: growing when the sender's clock runs
+    /// slow against ours, shrinking when it runs fast. Measured to the
+    /// latest packet, so its network delay is included. `None` until the
+    /// clock has moved
+    pub fn clock_drift_ms(&self) -> Option<f64> {
+        let (origin, origin_arrival) = self.clock_origin?;
+        let (timestamp, arrival) = (self.last_timestamp?, self.last_arrival?);
+        if timestamp == origin {
+            return None;
+        }
+        let rtp_secs = (timestamp as i64 - origin as i64) as f64 / f64::from(self.timestamp_clock_rate());
+        let arrival_secs = arrival.saturating_duration_since(origin_arrival).as_secs_f64();
+        Some((arrival_secs - rtp_secs) * 1000.0)
+    }
+
+    /// Audio one packet of the stream carries: the latest packet's where its
+    /// duration is known, else the spacing between packets
+    pub fn nominal_packet_secs(&self) -> f64 {
+        match self.last_packet_secs() {
+            secs if secs > 0.0 => secs,
+            _ if self.packet_spacing_secs > 0.0 => self.packet_spacing_secs,
+            _ => DEFAULT_PACKET_SPACING_SECS,
+        }
+    }
+
+    /// Where the page's packets come from, once one has arrived
+    pub fn source(&self) -> Option<SocketAddr> {
+        self.source
+    }
+
+    /// Carry the stats on to a new stream from the same sender, with
+    /// `--stitch-gap-ms`. Its sequence numbers and timestamps start a fresh
+    /// baseline, as after a reset, though none is counted. The wait between
+    /// the streams is the sender's and stays out of the gap figures
+    pub fn stitch(&mut self) {
+        self.last_sequence = None;
+        self.last_timestamp = None;
+        self.clock_origin = None;
+        self.last_arrival = None;
+        self.previous_arrival = None;
+        self.last_duration = None;
+        self.last_payload_ticks = None;
+    }
+
+    /// Duration of the audio in the latest packet, where known. Page
+    /// durations run to its end rather than its arrival
+    pub fn last_packet_secs(&self) -> f64 {
+        let clock_rate = if self.clock_rate == 0 { 8000.0 } else { f64::from(self.clock_rate) };
+        self.last_duration.map_or(0.0, |ticks| f64::from(ticks) / clock_rate)
+    }
+
+    fn record_payload_size(&mut self, bytes: usize) {
+        *self.payload_sizes.entry(bytes).or_insert(0) += 1;
+        if self.payload_granularity.is_some_and(|granularity| granularity > 0 && !bytes.is_multiple_of(granularity)) {
+            self.misaligned_payloads += 1;
+        }
+    }
+
+    /// Account for decoding a `payload_bytes` payload: the samples the
+    /// decoder returned, or `None` if it failed
+    pub fn record_decode(&mut self, payload_bytes: usize, samples: Option<usize>) {
+        match (samples, self.bytes_per_sample) {
+            (None, _) => self.decode_errors += 1,
+            (Some(samples), Some(width)) if samples < payload_bytes / width => self.short_decodes += 1,
+            _ => {}
+        }
+    }
+
+    /// Mean padding of the padded packets, in bytes; `None` if none were
+    pub fn avg_padding_bytes(&self) -> Option<f64> {
+        (self.padded_packets > 0).then(|| self.padding_bytes as f64 / self.padded_packets as f64)
+    }
+
+    /// Payload sizes over the page, against the most common one
+    pub fn payload_sizes(&self) -> PayloadSizes {
+        // Ties go to the smallest size
+        let mut modal = (0, 0);
+        for (&size, &count) in &self.payload_sizes {
+            if count > modal.1 {
+                modal = (size, count);
+            }
+        }
+        PayloadSizes {
+            modal_bytes: modal.0,
+            deviating_packets: self.packets_received - modal.1,
+            misaligned_packets: self.misaligned_payloads,
+            histogram: self.payload_sizes.clone(),
+        }
+    }
+
+    /// Count the packet's TTL, reporting the first packet of the page with a new one
+    fn record_ttl(&mut self, packet: &RtpPacket) -> Option<StreamDiscontinuity> {
+        let ttl = packet.ttl?;
+        let count = self.ttl_counts.entry(ttl).or_insert(0);
+        *count += 1;
+        let first_of_its_ttl = *count == 1;
+        let previous_ttl = self.last_ttl.replace(ttl)?;
+        (first_of_its_ttl && previous_ttl != ttl).then_some(StreamDiscontinuity::TtlVaried {
+            source: packet.source,
+            previous_ttl,
+            ttl,
+        })
+    }
+
+    /// Arriving TTL over the page, if the platform reported it
+    pub fn ttl(&self) -> Option<TtlSummary> {
+        let (&min, _) = self.ttl_counts.first_key_value()?;
+        let (&max, _) = self.ttl_counts.last_key_value()?;
+        // Ties go to the lowest TTL
+        let mut most_common = (min, 0);
+        for (&ttl, &count) in &self.ttl_counts {
+            if count > most_common.1 {
+                most_common = (ttl, count);
+            }
+        }
+        Some(TtlSummary {
+            min,
+            max,
+            most_common: most_common.0,
+        })
+    }
+
+    /// Arriving DSCP over the page, if the platform reported it, checked
+    /// against `expected`
+    pub fn dscp(&self, expected: Option<Dscp>) -> Option<DscpSummary> {
+        // Ties go to the lowest value
+        let (&most_common, _) = self.dscp_counts.iter().rev().max_by_key(|(_, &count)| count)?;
+        let unexpected_packets = expected.map_or(0, |expected| {
+            self.dscp_counts
+                .iter()
+                .filter(|(&value, _)| value != expected.value())
+                .map(|(_, &count)| count)
+                .sum()
+        });
+        Some(DscpSummary {
+            most_common: Dscp::new(most_common)?,
+            histogram: self.dscp_counts.clone(),
+            expected,
+            unexpected_packets,
+        })
+    }
+
+    fn record_arrival(&mut self, received_at: Instant) {
+        if let Some(previous) = self.previous_arrival {
+            let gap_secs = received_at.saturating_duration_since(previous).as_secs_f64();
+            self.max_gap_ms = self.max_gap_ms.max(gap_secs * 1000.0);
+            self.gap_histogram.record(gap_secs * 1000.0);
+
+            let spacing = if self.packet_spacing_secs > 0.0 {
+                self.packet_spacing_secs
+            } else {
+                DEFAULT_PACKET_SPACING_SECS
+            };
+            self.last_excess_gap_secs = (gap_secs - spacing).max(0.0);
+            self.excess_gap_secs += self.last_excess_gap_secs;
+        }
+        self.previous_arrival = Some(received_at);
+    }
+
+    /// Share of a page of `duration_secs` that was not spent waiting for late
+    /// or missing packets. 100% for a page too short to measure
+    pub fn continuity_percent(&self, duration_secs: f64) -> f64 {
+        if duration_secs <= 0.0 {
+            return 100.0;
+        }
+        100.0 * (1.0 - self.excess_gap_secs / duration_secs).clamp(0.0, 1.0)
     }
 
     pub fn loss_percent(&self) -> f64 {
@@ -94,7 +914,7 @@ impl PageStats {
 }
 
 /// JSON event types for automated testing
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(tag = "event")]
 pub enum JsonEvent {
     #[serde(rename = "monitoring_started")]
@@ -104,6 +924,12 @@ pub enum JsonEvent {
         timestamp: DateTime<Utc>,
         #[serde(skip_serializing_if = "Option::is_none")]
         endpoint_count: Option<usize>,
+        /// Settings given for this endpoint in place of the command-wide ones
+        #[serde(skip_serializing_if = "Option::is_none")]
+        overrides: Option<EndpointOptions>,
+        /// SSRCs taken and dropped, with `--ssrc` and `--ignore-ssrc`
+        #[serde(skip_serializing_if = "Option::is_none")]
+        ssrc_filter: Option<SsrcFilter>,
     },
     #[serde(rename = "page_started")]
     PageStarted {
@@ -113,22 +939,42 @@ pub enum JsonEvent {
         source: String,
         codec: String,
         ssrc: u32,
+        /// Whether `codec` came from `--codec` rather than the payload type
+        codec_forced: bool,
+        payload_type_observed: u8,
+        /// The forced codec and the payload type name different codecs
+        payload_type_mismatch: bool,
+        /// Raw fields of the first packet, with a guess at the device
+        sender_fingerprint: SenderFingerprint,
     },
     #[serde(rename = "stats")]
     Stats {
         address: String,
         port: u16,
+        /// False for an idle endpoint's report, whose figures are all zero
+        page_active: bool,
         duration_secs: f64,
         packets: u64,
         bytes: u64,
         jitter_ms: f64,
         loss_percent: f64,
-        // Audio analysis
+        max_gap_ms: f64,
+        gap_histogram: GapHistogram,
+        continuity_percent: f64,
+        /// Arriving TTL; null where the platform doesn't report it
+        ttl: Option<TtlSummary>,
+        // Audio analysis; silence is -inf, which JSON writes as null
+        #[schemars(with = "Option<f64>")]
         rms_db: f64,
+        #[schemars(with = "Option<f64>")]
         peak_db: f64,
         dominant_freq_hz: f64,
         glitches: u64,
         clipped: u64,
+        /// Packets the decoder rejected so far in the page
+        decode_errors: u64,
+        /// Payloads cut short in transit so far in the page
+        truncated_payloads: u64,
     },
     #[serde(rename = "page_ended")]
     PageEnded {
@@ -137,89 +983,615 @@ pub enum JsonEvent {
         duration_secs: f64,
         total_packets: u64,
         total_bytes: u64,
-        // Audio analysis summary
+        max_gap_ms: f64,
+        gap_histogram: GapHistogram,
+        continuity_percent: f64,
+        ttl: Option<TtlSummary>,
+        /// Arriving DSCP; null where the platform doesn't report it
+        dscp: Option<DscpSummary>,
+        /// Time the sender sent nothing (Opus DTX)
+        dtx_gap_secs: f64,
+        /// How far arrivals fell behind the sender's RTP clock over the
+        /// page; absent for a page too short to tell
+        #[serde(skip_serializing_if = "Option::is_none")]
+        clock_drift_ms: Option<f64>,
+        payload_sizes: PayloadSizes,
+        decode_errors: u64,
+        short_decodes: u64,
+        truncated_payloads: u64,
+        /// Packets with RTP padding after the payload
+        padded_packets: u64,
+        /// Mean padding of those packets, in bytes; absent if none were padded
+        #[serde(skip_serializing_if = "Option::is_none")]
+        avg_padding_bytes: Option<f64>,
+        // Audio analysis summary; -inf levels are written as null
+        #[schemars(with = "Option<f64>")]
         peak_rms_db: f64,
+        #[schemars(with = "Option<f64>")]
         avg_rms_db: f64,
+        #[schemars(with = "Option<f64>")]
         max_peak_db: f64,
         dominant_freq_hz: f64,
         total_glitches: u64,
         total_clipped: u64,
         clipping_percent: f64,
         avg_zero_crossing_rate: f64,
+        /// Gated loudness over the page (ITU-R BS.1770); absent for pages too
+        /// short or quiet to measure
+        #[serde(skip_serializing_if = "Option::is_none")]
+        integrated_lufs: Option<f64>,
+        /// Loudest 400ms of the page
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max_momentary_lufs: Option<f64>,
+        /// Loudness range in LU (EBU Tech 3342); absent for pages under 3s
+        #[serde(skip_serializing_if = "Option::is_none")]
+        lra: Option<f64>,
+        /// The `transmit --watermark` ID found in the page
+        #[serde(skip_serializing_if = "Option::is_none")]
+        watermark: Option<WatermarkDetection>,
+        /// The built-in `transmit --chime` the page opened with
+        #[serde(skip_serializing_if = "Option::is_none")]
+        chime: Option<String>,
+        /// The page's split at 4 kHz; absent below 16 kHz and for pages
+        /// too short to measure
+        #[serde(skip_serializing_if = "Option::is_none")]
+        band_split: Option<BandSplit>,
+        /// A wideband codec carrying audio that looks to have been through
+        /// an 8 kHz μ-law hop
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        suspected_narrowband_transcoding: bool,
+        /// Estimated listening quality, 1-5; absent if no decoder could be created
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mos_estimate: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mos_breakdown: Option<MosBreakdown>,
+        /// Losses asked for again and how many came back, with --repair
+        #[serde(skip_serializing_if = "Option::is_none")]
+        repair: Option<RepairSummary>,
+        /// How the page was sent on, with --forward
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        forward: Option<Box<ForwardSummary>>,
+        /// Where the page moved to a new SSRC, with --stitch-gap-ms
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        ssrc_changes: Vec<SsrcChange>,
+        /// Why the page was cut short, such as the interface going down
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        /// The run ended (timeout or Ctrl+C) while the page was still active
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        truncated: bool,
     },
     #[serde(rename = "recording_saved")]
     RecordingSaved {
         address: String,
         port: u16,
+        /// The WAV recording
         path: String,
+        /// Every file of the page, the recording included
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        files: BTreeMap<RecordingKind, String>,
     },
-    #[serde(rename = "error")]
-    Error { message: String },
-    #[serde(rename = "timeout")]
-    Timeout,
-}
-
-/// Options for monitoring a single endpoint (for future API use)
-#[allow(dead_code)]
-pub struct MonitorOptions {
-    pub address: Ipv4Addr,
-    pub port: u16,
-    pub interface: Option<Ipv4Addr>,
-    pub codec: Option<CodecType>,
-    pub output: Option<PathBuf>,
-    pub timeout: Duration,
-    pub json: bool,
-    pub quiet: bool,
-}
-
-/// Options for monitoring with range support
-pub struct MonitorRangeOptions {
-    pub pattern: String,
-    pub default_port: u16,
-    pub interface: Option<Ipv4Addr>,
-    pub codec: Option<CodecType>,
-    pub output: Option<PathBuf>,
-    pub timeout: Duration,
-    pub json: bool,
-    pub quiet: bool,
-}
-
-/// State for a single monitored endpoint
-struct EndpointState {
-    address: Ipv4Addr,
-    port: u16,
-    stats: PageStats,
-    audio_stats: AudioStats,
-    audio_analyzer: Option<AudioAnalyzer>,
-    current_audio: AudioAnalysis,
-    decoder: Option<Box<dyn AudioDecoder>>,
-    recorder: Option<WavRecorder>,
-    page_active: bool,
-    page_start: Option<Instant>,
-    last_packet: Option<Instant>,
-    ssrc: Option<u32>,
-    output_path: Option<PathBuf>,
-}
-
-impl EndpointState {
-    fn new(address: Ipv4Addr, port: u16, output_path: Option<PathBuf>) -> Self {
-        Self {
-            address,
-            port,
-            stats: PageStats::default(),
-            audio_stats: AudioStats::new(),
-            audio_analyzer: None,
-            current_audio: AudioAnalysis::default(),
-            decoder: None,
-            recorder: None,
-            page_active: false,
+    /// A recording is complete and fingerprinted; follows its `page_ended`
+    /// once the file has been finished in the background
+    #[serde(rename = "recording_finalized")]
+    RecordingFinalized {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        path: String,
+        #[serde(flatten)]
+        integrity: RecordingIntegrity,
+        /// What `--pipe-audio-cmd` printed for the page, and how it exited
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pipe_output: Option<PipeOutput>,
+    },
+    #[serde(rename = "endpoint_added")]
+    EndpointAdded {
+        address: String,
+        port: u16,
+        timestamp: DateTime<Utc>,
+    },
+    #[serde(rename = "endpoint_removed")]
+    EndpointRemoved {
+        address: String,
+        port: u16,
+        timestamp: DateTime<Utc>,
+    },
+    /// A port's multicast memberships were lost and have been restored
+    #[serde(rename = "interface_recovered")]
+    InterfaceRecovered {
+        timestamp: DateTime<Utc>,
+        port: u16,
+        groups: Vec<String>,
+        /// From when the port last worked; may be overstated by up to one
+        /// `--rejoin-interval`
+        downtime_secs: f64,
+    },
+    /// A port's socket kept failing, or stopped receiving, and was rebuilt
+    #[serde(rename = "socket_restarted")]
+    SocketRestarted {
+        timestamp: DateTime<Utc>,
+        port: u16,
+        groups: Vec<String>,
+        reason: RestartReason,
+        /// Rebuilds of this port in a row, from 1
+        attempt: u32,
+        /// Errors that led here, oldest first
+        errors: Vec<SocketErrorRecord>,
+    },
+    /// The receive loop is still running; every `--heartbeat` seconds
+    #[serde(rename = "heartbeat")]
+    Heartbeat {
+        timestamp: DateTime<Utc>,
+        uptime_secs: f64,
+        /// Datagrams received since the run began
+        packets_received: u64,
+        sockets: usize,
+        active_pages: usize,
+    },
+    #[serde(rename = "stream_reset")]
+    StreamReset {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        ssrc: u32,
+        old_sequence: u16,
+        new_sequence: u16,
+    },
+    /// The page carried on under a new SSRC, with --stitch-gap-ms
+    #[serde(rename = "ssrc_changed")]
+    SsrcChanged {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        source: String,
+        #[serde(flatten)]
+        change: SsrcChange,
+    },
+    #[serde(rename = "ssrc_collision")]
+    SsrcCollision {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        ssrc: u32,
+        original_source: String,
+        new_source: String,
+    },
+    #[serde(rename = "ttl_varied")]
+    TtlVaried {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        source: String,
+        previous_ttl: u8,
+        ttl: u8,
+    },
+    /// A G.722 sender ticking its RTP clock at 16kHz rather than RFC 3551's 8kHz
+    #[serde(rename = "nonstandard_timestamps")]
+    NonstandardTimestamps {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        ssrc: u32,
+        clock_rate: u32,
+        expected_clock_rate: u32,
+    },
+    #[serde(rename = "transmit_report")]
+    TransmitReport {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        codec: String,
+        packets_sent: u64,
+        duration_secs: f64,
+        /// The `--chime` each page started with
+        #[serde(skip_serializing_if = "Option::is_none")]
+        chime: Option<ChimePlan>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        impairments: Option<ImpairmentStats>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        window_waits: Vec<WindowWait>,
+        /// Extra RTP destinations sent alongside a Polycom page
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        also_rtp: Vec<RtpLegReport>,
+        /// Frame cache hits and misses, with --cache-dir
+        #[serde(skip_serializing_if = "Option::is_none")]
+        frame_cache: Option<CacheUsage>,
+        /// NACKs answered, with --repair
+        #[serde(skip_serializing_if = "Option::is_none")]
+        retransmits: Option<RetransmitStats>,
+    },
+    #[serde(rename = "transmit_started")]
+    TransmitStarted {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        command: &'static str,
+        file: String,
+        codec: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        channel: Option<u8>,
+        /// The Polycom channel's name, if it has one
+        #[serde(skip_serializing_if = "Option::is_none")]
+        channel_name: Option<String>,
+        /// `--dscp` the packets are marked with
+        #[serde(skip_serializing_if = "Option::is_none")]
+        dscp: Option<Dscp>,
+        /// Audio frames in one pass through the file
+        frames: usize,
+        /// One pass, including Polycom Alert and End phases
+        estimated_secs: f64,
+        loop_audio: bool,
+    },
+    #[serde(rename = "transmit_progress")]
+    TransmitProgress {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        /// Pass through the file, from 1; only above 1 with --loop
+        pass: u32,
+        /// Frames sent in this pass
+        frames_sent: usize,
+        frames: usize,
+        percent: f64,
+        /// How late the latest frame went out against its schedule
+        drift_ms: f64,
+        max_drift_ms: f64,
+    },
+    #[serde(rename = "polycom_phase")]
+    PolycomPhase {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        pass: u32,
+        /// "alert", "audio" or "end", reported as each one finishes
+        phase: &'static str,
+        packets: u32,
+    },
+    #[serde(rename = "transmit_completed")]
+    TransmitCompleted {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        passes: u32,
+        /// Audio frames over all passes
+        frames_sent: u64,
+        packets_sent: u64,
+        duration_secs: f64,
+        max_drift_ms: f64,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<String>,
+    },
+    #[serde(rename = "transmit_failed")]
+    TransmitFailed {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        /// Stable cause such as `file_not_found` or `outside_window`
+        reason: &'static str,
+        message: String,
+        frames_sent: u64,
+    },
+    #[serde(rename = "transmit_plan")]
+    TransmitPlan {
+        timestamp: DateTime<Utc>,
+        #[serde(flatten)]
+        plan: TransmitPlan,
+    },
+    /// Delivery receipts collected by `polycom-transmit --await-receipts`
+    #[serde(rename = "receipts")]
+    Receipts {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        expected: usize,
+        receipts: Vec<ReceivedReceipt>,
+    },
+    #[serde(rename = "transmit_refused")]
+    TransmitRefused {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        reason: String,
+        next_window: String,
+    },
+    #[serde(rename = "igmp_cycle")]
+    IgmpCycle {
+        timestamp: DateTime<Utc>,
+        address: String,
+        port: u16,
+        #[serde(flatten)]
+        result: CycleResult,
+    },
+    #[serde(rename = "igmp_cycle_report")]
+    IgmpCycleReport {
+        timestamp: DateTime<Utc>,
+        #[serde(flatten)]
+        report: CycleReport,
+    },
+    /// `bridge` started relaying an RTP page
+    #[serde(rename = "bridge_page_started")]
+    BridgePageStarted {
+        timestamp: DateTime<Utc>,
+        source: String,
+        ssrc: u32,
+        source_codec: String,
+        channel: u8,
+    },
+    #[serde(rename = "bridge_page_ended")]
+    BridgePageEnded {
+        timestamp: DateTime<Utc>,
+        #[serde(flatten)]
+        report: BridgeReport,
+    },
+    #[serde(rename = "annotation")]
+    Annotation {
+        timestamp: DateTime<Utc>,
+        text: String,
+        source: AnnotationSource,
+    },
+    #[serde(rename = "error")]
+    Error { message: String },
+    /// The run reached its `--timeout` or `--until`
+    #[serde(rename = "timeout")]
+    Timeout {
+        timestamp: DateTime<Utc>,
+        mode: TimeoutMode,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        until: Option<DateTime<Utc>>,
+    },
+    /// The system clock was stepped; timestamps before and after it are
+    /// `step_secs` apart from where the monotonic clock puts them
+    #[serde(rename = "clock_step")]
+    ClockStep {
+        timestamp: DateTime<Utc>,
+        /// Negative if the clock went back
+        step_secs: f64,
+    },
+    #[serde(rename = "count_summary")]
+    CountSummary {
+        timestamp: DateTime<Utc>,
+        #[serde(flatten)]
+        summary: CountSummary,
+    },
+}
+
+/// Options for monitoring a single endpoint (for future API use)
+#[allow(dead_code)]
+pub struct MonitorOptions {
+    pub address: Ipv4Addr,
+    pub port: u16,
+    pub interface: Option<MulticastInterface>,
+    pub codec: Option<CodecSpec>,
+    pub output: Option<PathBuf>,
+    pub timeout: Timeout,
+    pub json: bool,
+    pub quiet: bool,
+}
+
+/// How often spectrum snapshots are written with `--spectrum-dir`
+/// Default `--rejoin-interval`
+pub const DEFAULT_REJOIN_INTERVAL: Duration = Duration::from_mins(1);
+
+const SPECTRUM_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Options for monitoring with range support
+pub struct MonitorRangeOptions {
+    pub pattern: String,
+    pub default_port: u16,
+    pub interface: Option<MulticastInterface>,
+    pub codec: Option<CodecSpec>,
+    pub output: Option<PathBuf>,
+    /// Record every page of an endpoint over the same file, rather than a
+    /// file per page
+    pub overwrite: bool,
+    pub timeout: Timeout,
+    pub json: bool,
+    pub quiet: bool,
+    /// Unix socket accepting runtime add/remove/status commands
+    pub control_socket: Option<PathBuf>,
+    /// Directory for per-page spectrum snapshots
+    pub spectrum_dir: Option<PathBuf>,
+    /// Capture of every received datagram
+    pub pcap: Option<PathBuf>,
+    /// What a new stream must reach before it counts as a page
+    pub page_threshold: PageThreshold,
+    /// Longest wait for a new SSRC from the page's sender to carry the page
+    /// on, with `--stitch-gap-ms`
+    pub stitch_gap: Option<Duration>,
+    /// SSRCs to take and to drop, with `--ssrc` and `--ignore-ssrc`
+    pub ssrc_filter: SsrcFilter,
+    /// End the run after this many pages, with `--count`
+    pub count: Option<PageCount>,
+    /// `SO_BUSY_POLL` for receive sockets, in microseconds
+    pub busy_poll_us: Option<u32>,
+    /// Threads running analysis FFTs, with `--analysis-threads`; `None`
+    /// picks from the cores and endpoints
+    pub analysis_threads: Option<usize>,
+    /// Time between stats reports
+    pub stats_interval: Duration,
+    /// Leave idle endpoints out of JSON stats reports
+    pub stats_active_only: bool,
+    /// Recording name template and site; with a template, `output` is a directory
+    pub naming: Naming,
+    /// Log accumulator sizes at this interval, to catch memory growth
+    pub memory_report: Option<Duration>,
+    /// How often to check multicast memberships are still in place
+    pub rejoin_interval: Option<Duration>,
+    /// What damaged audio becomes in the recording
+    pub decode_error_policy: DecodeErrorPolicy,
+    /// With `--repair`, how long a gap may stay open before it is asked for
+    pub repair_window: Option<Duration>,
+    /// Print every received datagram with a decode attempt
+    pub inspect: Option<InspectOptions>,
+    /// DSCP pages should arrive with; others are warned about
+    pub expect_dscp: Option<Dscp>,
+    /// Write a timing file beside each recording, at this granularity
+    pub timing_file: Option<TimingGranularity>,
+    /// Keep each page's payloads as received beside its recording
+    pub record_payloads: bool,
+    /// Pipe each recorded page's audio to this command
+    pub pipe_audio_cmd: Option<PipeCommand>,
+    /// Narrowband transcoding thresholds, from the config file
+    pub transcoding: TranscodingThresholds,
+    /// Rebuild a socket with no healthy read for this long, or that keeps
+    /// failing; `None` leaves sockets as they are
+    pub watchdog: Option<Duration>,
+    /// With `json`, report the receive loop is alive at this interval
+    pub heartbeat: Option<Duration>,
+    /// Send each page on as a new RTP stream
+    pub forward: Option<ForwardTarget>,
+    /// TTL of forwarded packets
+    pub forward_ttl: u8,
+    /// Soft open file limit to run with, in place of raising it as needed
+    pub max_file_descriptors: Option<u64>,
+    /// Read G.722 timestamps as ticking at 16kHz, for senders that don't follow RFC 3551
+    pub g722_nonstandard_timestamps: bool,
+    /// Serve the status page here
+    pub status_listen: Option<SocketAddr>,
+}
+
+/// Silence that ends a page, unless an endpoint is given its own `idle=`
+const PAGE_IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// State for a single monitored endpoint
+struct EndpointState {
+    address: Ipv4Addr,
+    port: u16,
+    stats: PageStats,
+    audio_stats: AudioStats,
+    audio_analyzer: Option<AudioAnalyzer>,
+    current_audio: AudioAnalysis,
+    decoder: Option<Box<dyn AudioDecoder>>,
+    recorder: Option<WavRecorder>,
+    spectrum: Option<SpectrumWriter>,
+    last_spectrum: Option<Instant>,
+    /// Sample offsets and receive times of the recording, with `--timing`
+    timing: Option<TimingWriter>,
+    /// The page's payloads as received, with `--record-payloads`
+    payloads: Option<PayloadWriter>,
+    /// The page's `--pipe-audio-cmd`, taking its audio as the recording does
+    audio_pipe: Option<AudioPipe>,
+    page_active: bool,
+    page_start: Option<Instant>,
+    last_packet: Option<Instant>,
+    ssrc: Option<u32>,
+    output_path: Option<PathBuf>,
+    /// Where the current page is being recorded
+    recording_path: Option<PathBuf>,
+    /// Pages started on this endpoint, for `{page}`
+    page_count: u32,
+    /// Pages ended on this endpoint, for `--count`
+    pages_ended: u32,
+    /// Start of a new stream, held until it reaches the page threshold
+    pending: PendingPage,
+    /// Removal requested while a page was active; drop once it ends
+    pending_removal: bool,
+    /// What the stats reporter sees of this endpoint
+    shared: SharedStats,
+    /// Why the current page is being ended early
+    interrupted: Option<String>,
+    /// The run is ending with the page still active
+    truncated: bool,
+    /// Packets turned away by `--ssrc` or `--ignore-ssrc`
+    filtered_packets: u64,
+    /// Settings given for this endpoint in place of the command-wide ones
+    overrides: EndpointOptions,
+    /// Packets held back for loss repair, with `--repair`
+    repair: Option<RepairBuffer>,
+    /// Where pages are sent on, with `--forward`
+    forwarder: Option<Forwarder>,
+    /// The current page's forwarded stream
+    forwarding: Option<PageForwarder>,
+    /// Recordings of ended pages, for the finalize queue
+    finalizing: Vec<PendingRecording>,
+    /// Where the current page moved to a new SSRC, with `--stitch-gap-ms`
+    ssrc_changes: Vec<SsrcChange>,
+    /// Analysis pool thread that runs this endpoint's FFTs, with
+    /// `--analysis-threads`
+    analysis_worker: Option<AnalysisWorker>,
+}
+
+impl EndpointState {
+    fn new(address: Ipv4Addr, port: u16, output_path: Option<PathBuf>) -> Self {
+        Self {
+            address,
+            port,
+            stats: PageStats::default(),
+            audio_stats: AudioStats::new(),
+            audio_analyzer: None,
+            current_audio: AudioAnalysis::default(),
+            decoder: None,
+            recorder: None,
+            spectrum: None,
+            last_spectrum: None,
+            timing: None,
+            payloads: None,
+            audio_pipe: None,
+            page_active: false,
             page_start: None,
             last_packet: None,
             ssrc: None,
             output_path,
+            recording_path: None,
+            page_count: 0,
+            pages_ended: 0,
+            pending: PendingPage::default(),
+            pending_removal: false,
+            shared: SharedStats::default(),
+            interrupted: None,
+            truncated: false,
+            filtered_packets: 0,
+            overrides: EndpointOptions::default(),
+            repair: None,
+            forwarder: None,
+            forwarding: None,
+            finalizing: Vec::new(),
+            ssrc_changes: Vec::new(),
+            analysis_worker: None,
         }
     }
 
+    fn with_overrides(mut self, overrides: EndpointOptions) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    fn with_repair(mut self, window: Option<Duration>) -> Self {
+        self.repair = window.map(RepairBuffer::new);
+        self
+    }
+
+    fn with_forward(mut self, forwarder: Option<Forwarder>) -> Self {
+        self.forwarder = forwarder;
+        self
+    }
+
+    fn with_analysis(mut self, worker: Option<AnalysisWorker>) -> Self {
+        self.analysis_worker = worker;
+        self
+    }
+
+    fn page_threshold(&self, options: &MonitorRangeOptions) -> PageThreshold {
+        options.page_threshold.with_overrides(&self.overrides)
+    }
+
+    /// The gap before `packet` if it carries the active page on under a
+    /// new SSRC, with `--stitch-gap-ms`
+    fn continuation(&self, packet: &RtpPacket, options: &MonitorRangeOptions) -> Option<Duration> {
+        let page_source = self.stats.source().filter(|_| self.page_active);
+        stitch::continuation(options.stitch_gap, page_source, self.last_packet, packet)
+    }
+
+    /// Whether the active page has gone quiet for long enough to end by `now`
+    fn is_idle(&self, now: Instant) -> bool {
+        let idle_timeout = self.overrides.idle().unwrap_or(PAGE_IDLE_TIMEOUT);
+        self.page_active
+            && self
+                .last_packet
+                .is_some_and(|last| now.saturating_duration_since(last) >= idle_timeout)
+    }
+
     fn reset_page(&mut self) {
         self.page_active = false;
         self.stats = PageStats::default();
@@ -230,21 +1602,77 @@ impl EndpointState {
         }
         self.decoder = None;
         self.recorder = None;
+        self.spectrum = None;
+        self.last_spectrum = None;
+        self.timing = None;
+        self.payloads = None;
+        self.audio_pipe = None;
+        self.forwarding = None;
         self.page_start = None;
         self.ssrc = None;
+        self.recording_path = None;
+        self.interrupted = None;
+        self.truncated = false;
+        self.ssrc_changes.clear();
+    }
+
+    /// Hand the page's figures so far to the stats reporter
+    fn publish_stats(&mut self) {
+        // Based on the last received audio, to avoid counting idle time
+        self.stats.duration_secs = match (self.page_start, self.last_packet) {
+            (Some(start), Some(last)) => last.duration_since(start).as_secs_f64(),
+            (Some(start), None) => start.elapsed().as_secs_f64(),
+            _ => 0.0,
+        };
+        self.shared.publish(StatsSnapshot {
+            page_active: true,
+            removed: false,
+            duration_secs: self.stats.duration_secs,
+            packets: self.stats.packets_received,
+            bytes: self.stats.bytes_received,
+            jitter_ms: self.stats.jitter_ms,
+            loss_percent: self.stats.loss_percent(),
+            max_gap_ms: self.stats.max_gap_ms,
+            gap_histogram: self.stats.gap_histogram,
+            continuity_percent: self.stats.continuity_percent(self.stats.duration_secs),
+            ttl: self.stats.ttl(),
+            rms_db: self.current_audio.rms_db,
+            peak_db: self.current_audio.peak_db,
+            dominant_freq_hz: self.audio_analyzer.as_ref().map_or(0.0, AudioAnalyzer::dominant_freq_hz),
+            glitches: self.audio_stats.total_glitches,
+            clipped: self.audio_stats.total_clipped,
+            decode_errors: self.stats.decode_errors,
+            truncated_payloads: self.stats.truncated_payloads,
+        });
+    }
+}
+
+/// Log accumulator sizes for `--memory-report`
+fn report_memory(endpoint_states: &HashMap<(Ipv4Addr, u16), EndpointState>) {
+    let mut states: Vec<&EndpointState> = endpoint_states.values().collect();
+    states.sort_by_key(|state| (state.address, state.port));
+    for state in states {
+        info!(
+            "Memory {}:{}: {} frequency bins, {} held packets",
+            state.address,
+            state.port,
+            state.audio_stats.freq_bin_count(),
+            state.pending.held()
+        );
     }
+    info!("Memory: {} endpoints", endpoint_states.len());
+}
+
+/// Point the stats board at the current endpoint set
+fn sync_stats_board(board: &StatsBoard, endpoint_states: &HashMap<(Ipv4Addr, u16), EndpointState>) {
+    board.set_endpoints(endpoint_states.iter().map(|(key, state)| (*key, state.shared.clone())));
 }
 
 /// Run the monitor command with range support
 pub async fn run_monitor_range(options: MonitorRangeOptions) -> Result<(), MonitorError> {
-    // Parse the pattern - if it contains ':', use it as-is, otherwise append default port
-    let pattern = if options.pattern.contains(':') {
-        options.pattern.clone()
-    } else {
-        format!("{}:{}", options.pattern, options.default_port)
-    };
-
-    let endpoints = parse_range(&pattern)?;
+    // Parse the pattern (or endpoints file) - the default port is used when none is given
+    let resolved = resolve_endpoints(&options.pattern, options.default_port)?;
+    let endpoints = &resolved.endpoints;
 
     if endpoints.is_empty() {
         return Err(MonitorError::NoEndpoints);
@@ -252,374 +1680,2673 @@ pub async fn run_monitor_range(options: MonitorRangeOptions) -> Result<(), Monit
 
     let endpoint_count = endpoints.len();
     let single_endpoint = endpoint_count == 1;
+    let analysis_pool =
+        AnalysisPool::new(options.analysis_threads.unwrap_or_else(|| analysis_pool::default_threads(endpoint_count)));
+
+    if let Some(ref dir) = options.spectrum_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+    options.naming.check("monitor recording", RECORDING_TOKENS)?;
+    if let (Some(_), Some(dir)) = (&options.naming.template, &options.output) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut pcap = match options.pcap {
+        Some(ref path) => Some(PcapWriter::create(&options.naming.capture_path(path, Local::now())?)?),
+        None => None,
+    };
+    let mut inspector = options.inspect.clone().map(|inspect| Inspector::new(inspect, options.json));
 
     // Group endpoints by port (we need one socket per port)
     let mut ports: HashMap<u16, Vec<Ipv4Addr>> = HashMap::new();
-    for ep in &endpoints {
+    for ep in endpoints {
         ports.entry(ep.port).or_default().push(ep.address);
     }
 
+    // Fail now rather than part way through opening sockets and files
+    fd_limit::ensure("monitor", fd_plan(&options, ports.len(), endpoint_count), options.max_file_descriptors)?;
+
     // Create sockets and join multicast groups
     // Use specified interface if provided, otherwise default to INADDR_ANY
-    let interface = options.interface.unwrap_or(Ipv4Addr::UNSPECIFIED);
+    let interface = options.interface.unwrap_or_default();
     let mut sockets: HashMap<u16, MulticastSocket> = HashMap::new();
     for (&port, addresses) in &ports {
         let mut socket = MulticastSocket::with_interface(port, interface).await?;
+        socket.apply_busy_poll(options.busy_poll_us);
         for &addr in addresses {
             socket.join(addr)?;
         }
         sockets.insert(port, socket);
     }
 
+    let forwarder = match options.forward {
+        Some(target) => Some(Forwarder::open(target, options.forward_ttl).await?),
+        None => None,
+    };
+
     // Create endpoint states
     let mut endpoint_states: HashMap<(Ipv4Addr, u16), EndpointState> = HashMap::new();
-    for ep in &endpoints {
-        let output_path = options.output.as_ref().map(|base| {
-            if single_endpoint {
-                base.clone()
-            } else {
-                // Generate unique filename for each endpoint
-                let stem = base.file_stem().and_then(|s| s.to_str()).unwrap_or("recording");
-                let ext = base.extension().and_then(|s| s.to_str()).unwrap_or("wav");
-                base.with_file_name(format!("{}_{}_{}_{}.{}", stem, ep.address, ep.port, Utc::now().format("%Y%m%d_%H%M%S"), ext))
-            }
-        });
-        endpoint_states.insert((ep.address, ep.port), EndpointState::new(ep.address, ep.port, output_path));
+    for ep in endpoints {
+        let output_path = endpoint_output_path(&options, ep, single_endpoint);
+        let state = EndpointState::new(ep.address, ep.port, output_path)
+            .with_overrides(resolved.options(ep))
+            .with_repair(options.repair_window)
+            .with_forward(forwarder.clone())
+            .with_analysis(analysis_pool.worker());
+        endpoint_states.insert((ep.address, ep.port), state);
     }
 
+    // Listen for SIGHUP and control socket commands
+    let mut control = ControlHandle::spawn(options.control_socket.as_deref(), options.default_port)?;
+    let mut annotations = annotation_directory(&options).map(|dir| AnnotationLog::new(&dir));
+
     // Output monitoring started
     if options.json {
-        for ep in &endpoints {
+        for ep in endpoints {
             output_json(&JsonEvent::MonitoringStarted {
                 address: ep.address.to_string(),
                 port: ep.port,
                 timestamp: Utc::now(),
                 endpoint_count: if single_endpoint { None } else { Some(endpoint_count) },
+                overrides: resolved.overrides.get(ep).copied(),
+                ssrc_filter: options.ssrc_filter.record(),
             });
         }
     } else if !options.quiet {
         if single_endpoint {
             let ep = &endpoints[0];
             println!("Monitoring {}:{}...", ep.address, ep.port);
+            if let Some(overrides) = resolved.overrides.get(ep) {
+                println!("  {}", overrides);
+            }
         } else {
             println!("Monitoring {} endpoints:", endpoint_count);
-            for ep in &endpoints {
-                println!("  {}:{}", ep.address, ep.port);
+            for ep in endpoints {
+                match resolved.overrides.get(ep) {
+                    Some(overrides) => println!("  {}:{} ({})", ep.address, ep.port, overrides),
+                    None => println!("  {}:{}", ep.address, ep.port),
+                }
             }
             println!();
         }
+        if options.ssrc_filter.is_active() {
+            println!("Streams: {}", options.ssrc_filter);
+        }
+        if let Some(ref pcap) = pcap {
+            println!("Capturing packets to {}", pcap.path().display());
+        }
     }
 
+    // Stats are reported from their own task, off the receive path
+    let board = StatsBoard::default();
+    sync_stats_board(&board, &endpoint_states);
+    let _keyboard = control.listen_keyboard(board.pause_flag());
+    let reporter = (options.json || !options.quiet).then(|| {
+        let settings = ReportSettings {
+            interval: options.stats_interval,
+            json: options.json,
+            active_only: options.stats_active_only,
+        };
+        StatsReporter::spawn(board.clone(), settings, io::stdout())
+    });
+    let _status_page = match options.status_listen {
+        Some(addr) => {
+            let server = StatusServer::bind(addr, board.clone()).await?;
+            if !options.json && !options.quiet {
+                println!("Status page at http://{}/", server.local_addr());
+            }
+            Some(server)
+        }
+        None => None,
+    };
+
     let start_time = Instant::now();
-    let mut last_stats_print = Instant::now();
-    let stats_interval = Duration::from_secs(1);
-    let idle_timeout = Duration::from_secs(5);
-    let mut buf = vec![0u8; 2048];
+    let mut receiver = FairReceiver::new(DRAIN_BUDGET);
+    let mut last_memory_report = Instant::now();
+    let mut membership_watch = options.rejoin_interval.map(MembershipWatch::new);
+    let mut watchdog = options.watchdog.map(SocketWatchdog::new);
+    let mut last_heartbeat = Instant::now();
+    let mut packets_received: u64 = 0;
+    let repair_client = match options.repair_window {
+        Some(_) => Some(RepairClient::bind().await?),
+        None => None,
+    };
+    let mut counter = options.count.map(PageCounter::new);
+    let mut clock_watch = ClockWatch::new(&SystemClock);
+    let mut finalize = FinalizeQueue::new(FINALIZE_CONCURRENCY);
 
     loop {
+        finalize_recordings(&mut finalize, &mut endpoint_states, &options);
+
         // Check for overall timeout
-        if options.timeout > Duration::ZERO && start_time.elapsed() >= options.timeout {
+        if options.timeout.expired(start_time) {
+            if let Some(ref mut counter) = counter {
+                counter.update(endpoint_states.iter().map(|(&key, state)| (key, state.pages_ended, state.page_active)));
+            }
+            if let Some(reporter) = reporter {
+                reporter.stop().await;
+            }
             if options.json {
-                output_json(&JsonEvent::Timeout);
+                output_json(&JsonEvent::Timeout {
+                    timestamp: Utc::now(),
+                    mode: options.timeout.mode(),
+                    until: options.timeout.until(),
+                });
             } else if !options.quiet {
-                println!("\nTimeout reached.");
+                println!("\n{}", options.timeout.reached());
             }
             break;
         }
 
-        // Check for page end on all endpoints
+        // Apply any runtime changes to the endpoint set
+        while let Some(request) = control.try_next() {
+            if let ControlCommand::Annotate(ref annotation) = request.command {
+                record_annotation(annotation, annotations.as_mut(), &options);
+                request.respond("ok".to_string());
+                continue;
+            }
+            handle_control(request, &mut sockets, &mut endpoint_states, forwarder.as_ref(), &analysis_pool, &options).await;
+            sync_stats_board(&board, &endpoint_states);
+        }
+
+        if let Some(step) = clock_watch.check(&SystemClock) {
+            if options.json {
+                output_json(&JsonEvent::ClockStep { timestamp: step.at, step_secs: step.step_secs });
+            } else if !options.quiet {
+                println!("\nWarning: system clock stepped by {:+.3}s", step.step_secs);
+            }
+        }
+
+        // Re-join groups the kernel dropped when an interface went down
+        if let Some(ref mut watch) = membership_watch {
+            check_memberships(watch, &mut sockets, &mut endpoint_states, &options).await?;
+        }
+
+        // Check for page end on all endpoints, and drop stray bursts that never became a page
+        let now = Instant::now();
         for state in endpoint_states.values_mut() {
-            if state.page_active {
-                if let Some(last) = state.last_packet {
-                    if last.elapsed() >= idle_timeout {
-                        handle_page_end(state, &options)?;
-                    }
-                }
+            if let Some(ref client) = repair_client {
+                poll_repair(state, client, now, &options)?;
+            }
+            state.pending.expire(state.page_threshold(&options), now);
+            if state.is_idle(now) {
+                flush_repair(state, &options)?;
+                handle_page_end(state, &options)?;
             }
         }
+        finalize_recordings(&mut finalize, &mut endpoint_states, &options);
 
-        // Print periodic stats for active pages
-        if last_stats_print.elapsed() >= stats_interval {
-            for state in endpoint_states.values_mut() {
-                if state.page_active {
-                    // Calculate duration based on last received audio to avoid counting idle time
-                    state.stats.duration_secs = match (state.page_start, state.last_packet) {
-                        (Some(start), Some(last)) => last.duration_since(start).as_secs_f64(),
-                        (Some(start), None) => start.elapsed().as_secs_f64(),
-                        _ => 0.0,
-                    };
-
-                    if options.json {
-                        output_json(&JsonEvent::Stats {
-                            address: state.address.to_string(),
-                            port: state.port,
-                            duration_secs: state.stats.duration_secs,
-                            packets: state.stats.packets_received,
-                            bytes: state.stats.bytes_received,
-                            jitter_ms: state.stats.jitter_ms,
-                            loss_percent: state.stats.loss_percent(),
-                            rms_db: state.current_audio.rms_db,
-                            peak_db: state.current_audio.peak_db,
-                            dominant_freq_hz: state.current_audio.dominant_freq_hz,
-                            glitches: state.audio_stats.total_glitches,
-                            clipped: state.audio_stats.total_clipped,
-                        });
-                    } else if !options.quiet {
-                        let prefix = if single_endpoint {
-                            String::new()
-                        } else {
-                            format!("[{}:{}] ", state.address, state.port)
-                        };
-                        print!(
-                            "\r{}Time: {:02}:{:02} | RMS: {} | Peak: {} | Freq: {} | Glitch: {} | Loss: {:.1}%   ",
-                            prefix,
-                            ((state.stats.duration_secs % 3600.0) / 60.0) as u32,
-                            (state.stats.duration_secs % 60.0) as u32,
-                            format_db(state.current_audio.rms_db),
-                            format_db(state.current_audio.peak_db),
-                            format_frequency(state.current_audio.dominant_freq_hz),
-                            state.audio_stats.total_glitches,
-                            state.stats.loss_percent()
-                        );
-                        io::stdout().flush().ok();
-                    }
+        // Drop endpoints whose removal was waiting for a page to finish
+        let finished: Vec<(Ipv4Addr, u16)> = endpoint_states
+            .iter()
+            .filter(|(_, state)| state.pending_removal && !state.page_active)
+            .map(|(k, _)| *k)
+            .collect();
+        if !finished.is_empty() {
+            for key in finished {
+                finish_removal(key, &mut sockets, &mut endpoint_states, &options);
+            }
+            sync_stats_board(&board, &endpoint_states);
+        }
+
+        // With --count, stop once enough pages have ended and those in progress have finished
+        if let Some(ref mut counter) = counter {
+            let progress = counter.update(endpoint_states.iter().map(|(&key, state)| (key, state.pages_ended, state.page_active)));
+            if progress == CountProgress::Done {
+                if let Some(reporter) = reporter {
+                    reporter.stop().await;
+                }
+                if !options.json && !options.quiet {
+                    println!("\n{} of {} pages received.", counter.captured(), counter.requested());
                 }
+                break;
+            }
+        }
+
+        // Refresh what the stats reporter sees; this never waits on it
+        for state in endpoint_states.values_mut() {
+            if state.page_active {
+                state.publish_stats();
             }
-            last_stats_print = Instant::now();
         }
 
-        // Receive from all sockets - drain all available packets from each socket
-        // to avoid buffered packets causing delayed page-end detection
-        let recv_timeout = Duration::from_millis(10);
+        if options.memory_report.is_some_and(|interval| last_memory_report.elapsed() >= interval) {
+            report_memory(&endpoint_states);
+            last_memory_report = Instant::now();
+        }
 
-        for (&port, socket) in &sockets {
-            // Drain all available packets from this socket
-            loop {
-                let recv_result = tokio::time::timeout(recv_timeout, socket.recv_from(&mut buf)).await;
+        if options.json && options.heartbeat.is_some_and(|interval| last_heartbeat.elapsed() >= interval) {
+            output_json(&JsonEvent::Heartbeat {
+                timestamp: Utc::now(),
+                uptime_secs: start_time.elapsed().as_secs_f64(),
+                packets_received,
+                sockets: sockets.len(),
+                active_pages: endpoint_states.values().filter(|state| state.page_active).count(),
+            });
+            last_heartbeat = Instant::now();
+        }
 
-                let (len, src_addr) = match recv_result {
-                    Ok(Ok((len, addr))) => (len, addr),
-                    Ok(Err(e)) => {
-                        if options.json {
-                            output_json(&JsonEvent::Error {
-                                message: format!("Receive error on port {}: {}", port, e),
-                            });
+        // Receive from all sockets in turn, a budget at a time, so a flooded
+        // port can't delay page-end detection on the others
+        let round_started = Instant::now();
+        receiver
+            .round(&sockets, |&port, socket, received| {
+                let (datagram, data) = match received {
+                    Ok(received) => received,
+                    Err(e) => {
+                        // A socket stuck failing would report every read; the
+                        // watchdog lets through the first of each run
+                        let first = watchdog
+                            .as_mut()
+                            .is_none_or(|watchdog| watchdog.record_error(port, &e, Instant::now(), Utc::now()));
+                        if first {
+                            report_error(format!("Receive error on port {}: {}", port, e), &options);
                         }
-                        break; // Move to next socket on error
+                        return Ok(());
                     }
-                    Err(_) => break, // Timeout - no more packets, move to next socket
                 };
+                packets_received += 1;
+
+                if let Some(ref mut pcap) = pcap {
+                    pcap.write_packet(datagram.wall_time, datagram.source, socket.destination(), datagram.ttl, data);
+                }
+                if let Some(ref mut inspector) = inspector {
+                    inspector.inspect(data, datagram.source, socket.destination(), datagram.received_at, datagram.wall_time);
+                }
 
-                // Parse RTP packet
-                let Ok(packet) = RtpPacket::parse(&buf[..len], src_addr) else {
-                    continue; // Try next packet
+                // Parse RTP packet. The parser returns an error for anything it
+                // can't read rather than panicking, so one bad datagram can't take
+                // down the loop and every endpoint on it
+                let mut packet = match RtpPacket::parse_with_time(data, datagram.source, datagram.received_at) {
+                    Ok(packet) => packet,
+                    Err(e) => {
+                        limited!(debug!("Port {}: ignoring a packet from {}: {}", port, datagram.source, e));
+                        return Ok(()); // Try next packet
+                    }
                 };
+                packet.ttl = datagram.ttl;
+                packet.dscp = datagram.dscp;
 
                 // Find endpoint for this port that either:
                 // 1. Has matching SSRC
                 // 2. Is not currently active (new page)
+                // 3. Has a page the packet's new SSRC carries on (--stitch-gap-ms)
                 let endpoint_key = endpoint_states.iter()
                     .filter(|((_, p), _)| *p == port)
-                    .find(|(_, state)| state.ssrc == Some(packet.header.ssrc) || !state.page_active)
+                    .find(|(_, state)| {
+                        state.ssrc == Some(packet.header.ssrc) || !state.page_active || state.continuation(&packet, &options).is_some()
+                    })
                     .map(|(k, _)| *k);
 
                 if let Some(key) = endpoint_key {
                     if let Some(state) = endpoint_states.get_mut(&key) {
-                        handle_packet(state, &packet, &options)?;
+                        receive_packet(state, packet, &options)?;
                     }
                 }
+                Ok::<(), MonitorError>(())
+            })
+            .await?;
+
+        if let Some(ref mut watchdog) = watchdog {
+            watchdog.record_round(sockets.keys().copied(), round_started, Instant::now());
+            restart_sockets(watchdog, &mut sockets, &options).await?;
+        }
+
+        // Put resent packets back in their streams
+        while let Some(packet) = repair_client.as_ref().and_then(RepairClient::try_recv) {
+            let state = endpoint_states
+                .values_mut()
+                .find(|state| state.repair.as_ref().is_some_and(|repair| repair.ssrc() == Some(packet.header.ssrc)));
+            if let Some(state) = state {
+                let ready = state.repair.as_mut().map(|repair| repair.accept_repair(packet, Instant::now())).unwrap_or_default();
+                for packet in &ready {
+                    handle_packet(state, packet, &options)?;
+                }
             }
         }
     }
 
     // Finalize any active recordings
     for state in endpoint_states.values_mut() {
+        flush_repair(state, &options)?;
         if state.page_active {
+            state.truncated = true;
             handle_page_end(state, &options)?;
         }
-    }
+        state.pending.discard();
+        if state.pending.suppressed > 0 && !options.quiet && !options.json {
+            println!(
+                "[{}:{}] Ignored {} stray burst(s) below the page threshold",
+                state.address, state.port, state.pending.suppressed
+            );
+        }
+        if state.filtered_packets > 0 && !options.quiet && !options.json {
+            println!("[{}:{}] Filtered {} packet(s) by SSRC", state.address, state.port, state.filtered_packets);
+        }
+    }
+    finalize_recordings(&mut finalize, &mut endpoint_states, &options);
+    for finished in finalize.drain().await {
+        report_finalized(finished, &options);
+    }
+
+    if let Some(pcap) = pcap {
+        if !options.quiet && !options.json {
+            println!("Captured {} packets to {}", pcap.packets(), pcap.path().display());
+        }
+    }
+
+    // Pages cut off by the end of the run are recorded, but don't count
+    if let Some(counter) = counter {
+        if options.json {
+            output_json(&JsonEvent::CountSummary { timestamp: Utc::now(), summary: counter.summary() });
+        }
+        if !counter.met() {
+            return Err(MonitorError::CountNotMet { captured: counter.captured(), requested: counter.requested() });
+        }
+    }
 
     Ok(())
 }
 
-fn handle_packet(state: &mut EndpointState, packet: &RtpPacket, options: &MonitorRangeOptions) -> Result<(), MonitorError> {
-    // Check if this is a new page
-    if state.ssrc.is_none() || state.ssrc != Some(packet.header.ssrc) {
-        // New page started
-        state.ssrc = Some(packet.header.ssrc);
-        state.page_start = Some(Instant::now());
-        state.page_active = true;
-        state.stats = PageStats::default();
-
-        // Determine codec
-        let codec_type = options.codec.unwrap_or_else(|| {
-            CodecType::from_payload_type(packet.header.payload_type)
-                .unwrap_or(CodecType::G711Ulaw)
+/// Rebuild each socket the watchdog finds failing or stalled. A port that
+/// is still failing once its rebuilds are used up ends the run.
+async fn restart_sockets<S: Rebuild>(
+    watchdog: &mut SocketWatchdog,
+    sockets: &mut HashMap<u16, S>,
+    options: &MonitorRangeOptions,
+) -> Result<(), MonitorError> {
+    watchdog.retain(|port| sockets.contains_key(&port));
+    let now = Instant::now();
+    for (port, reason) in watchdog.due(now) {
+        let Some(socket) = sockets.get_mut(&port) else {
+            continue;
+        };
+        let errors = watchdog.history(port);
+        let attempt = match watchdog.restart(port, now) {
+            Restart::Attempt(attempt) => attempt,
+            Restart::GiveUp { attempts } => {
+                let last_error = errors
+                    .last()
+                    .map_or_else(|| "no packets or clean reads".to_string(), |record| record.error.clone());
+                let error = MonitorError::SocketFailed { port, attempts, last_error };
+                if options.json {
+                    output_json(&JsonEvent::Error { message: error.to_string() });
+                }
+                return Err(error);
+            }
+        };
+        let groups: Vec<String> = socket.groups().iter().map(ToString::to_string).collect();
+        if let Err(e) = socket.rebuild(options.busy_poll_us).await.map(|fresh| *socket = fresh) {
+            let wait = SocketWatchdog::backoff_after(attempt);
+            report_error(
+                format!("Can't rebuild the socket on port {}: {}; trying again in {}s", port, e, wait.as_secs()),
+                options,
+            );
+            continue;
+        }
+
+        if options.json {
+            output_json(&JsonEvent::SocketRestarted { timestamp: Utc::now(), port, groups, reason, attempt, errors });
+        } else if !options.quiet {
+            let why = match reason {
+                RestartReason::Errors => "kept failing to receive",
+                RestartReason::Stalled => "stopped receiving",
+            };
+            println!("\nPort {}: {}; rebuilt its socket (attempt {} of {})", port, why, attempt, MAX_RESTARTS);
+        }
+    }
+    Ok(())
+}
+
+/// Check the memberships of each port that's due, ending pages the outage
+/// cut off and replacing the socket of a port that has recovered
+async fn check_memberships(
+    watch: &mut MembershipWatch,
+    sockets: &mut HashMap<u16, MulticastSocket>,
+    endpoint_states: &mut HashMap<(Ipv4Addr, u16), EndpointState>,
+    options: &MonitorRangeOptions,
+) -> Result<(), MonitorError> {
+    watch.retain(|port| sockets.contains_key(&port));
+    let now = Instant::now();
+    for (&port, socket) in sockets.iter_mut() {
+        let on_port = || endpoint_states.values().filter(move |state| state.port == port);
+        let stalled = on_port().any(|state| {
+            state.page_active && state.last_packet.is_some_and(|last| now.saturating_duration_since(last) >= membership::RECHECK)
+        });
+        if !watch.due(port, stalled, now) {
+            continue;
+        }
+        let last_traffic = on_port().filter_map(|state| state.last_packet).max();
+        let result = socket.rejoin();
+        let mut groups: Vec<Ipv4Addr> = socket.joined_groups().iter().copied().collect();
+        groups.sort_unstable();
+
+        let reason = match (watch.record(port, result.as_ref().ok().copied(), last_traffic, now), result) {
+            (Membership::Down, Err(e)) => {
+                let message = format!("Multicast joins failed on port {}: {}", port, e);
+                if options.json {
+                    output_json(&JsonEvent::Error { message: message.clone() });
+                } else if !options.quiet {
+                    println!("\n{}; re-trying every {}s", message, membership::RECHECK.as_secs());
+                }
+                format!("interface down ({})", e)
+            }
+            (Membership::Recovered { downtime }, _) => {
+                match socket.reopen().await {
+                    Ok(fresh) => {
+                        fresh.apply_busy_poll(options.busy_poll_us);
+                        *socket = fresh;
+                    }
+                    Err(e) => tracing::warn!("Port {}: keeping the old socket, can't open a new one: {}", port, e),
+                }
+                if options.json {
+                    output_json(&JsonEvent::InterfaceRecovered {
+                        timestamp: Utc::now(),
+                        port,
+                        groups: groups.iter().map(ToString::to_string).collect(),
+                        downtime_secs: downtime.as_secs_f64(),
+                    });
+                } else if !options.quiet {
+                    println!("\nMulticast memberships on port {} restored after {:.1}s", port, downtime.as_secs_f64());
+                }
+                "multicast membership lost".to_string()
+            }
+            _ => continue,
+        };
+
+        // Pages the outage cut off end now, rather than after the idle timeout
+        for state in endpoint_states.values_mut().filter(|state| state.port == port && state.page_active) {
+            state.interrupted = Some(reason.clone());
+            handle_page_end(state, options)?;
+        }
+    }
+    Ok(())
+}
+
+/// The most files and sockets the run can have open at once: a socket per
+/// port, and every endpoint recording a page
+fn fd_plan(options: &MonitorRangeOptions, sockets: usize, endpoints: usize) -> FdPlan {
+    let recording = if options.output.is_some() {
+        1 + u64::from(options.timing_file.is_some())
+            + 2 * u64::from(options.record_payloads)
+            + 2 * u64::from(options.pipe_audio_cmd.is_some())
+    } else {
+        0
+    };
+    let files_per_page = recording + u64::from(options.spectrum_dir.is_some());
+    let extra = [
+        options.pcap.is_some(),
+        options.control_socket.is_some(),
+        annotation_directory(options).is_some(),
+        options.repair_window.is_some(),
+        options.forward.is_some(),
+    ];
+    FdPlan::new(sockets, endpoints, files_per_page, extra.into_iter().filter(|&open| open).count() as u64)
+}
+
+/// Recording prefix for an endpoint, which each page's file is named from;
+/// multi-endpoint runs get one per endpoint. With a name template it's the
+/// directory pages are named into, and with `--overwrite` the file every
+/// page is recorded to.
+fn endpoint_output_path(options: &MonitorRangeOptions, ep: &MulticastEndpoint, single_endpoint: bool) -> Option<PathBuf> {
+    options.output.as_ref().map(|base| {
+        if single_endpoint || options.naming.template.is_some() {
+            base.clone()
+        } else {
+            let stem = base.file_stem().and_then(|s| s.to_str()).unwrap_or("recording");
+            let ext = base.extension().and_then(|s| s.to_str()).unwrap_or("wav");
+            if options.overwrite {
+                base.with_file_name(format!("{}_{}_{}_{}.{}", stem, ep.address, ep.port, Utc::now().format("%Y%m%d_%H%M%S"), ext))
+            } else {
+                base.with_file_name(format!("{}_{}_{}.{}", stem, ep.address, ep.port, ext))
+            }
+        }
+    })
+}
+
+/// Where annotations.jsonl goes: the recordings' directory, if recording
+fn annotation_directory(options: &MonitorRangeOptions) -> Option<PathBuf> {
+    let output = options.output.as_ref()?;
+    if options.naming.template.is_some() {
+        return Some(output.clone());
+    }
+    Some(match output.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    })
+}
+
+/// Echo an operator note and keep it alongside the recordings
+fn record_annotation(annotation: &Annotation, log: Option<&mut AnnotationLog>, options: &MonitorRangeOptions) {
+    if options.json {
+        output_json(&JsonEvent::Annotation {
+            timestamp: annotation.timestamp,
+            text: annotation.text.clone(),
+            source: annotation.source,
         });
+    } else if !options.quiet {
+        println!("\n[{}] Annotation: {}", annotation.timestamp.with_timezone(&Local).format("%H:%M:%S"), annotation.text);
+    }
+    if let Some(log) = log {
+        if let Err(e) = log.write(annotation) {
+            warn!("Unable to write {}: {}", log.path().display(), e);
+        }
+    }
+}
+
+/// Apply a runtime control request and reply to the requester
+async fn handle_control(
+    request: ControlRequest,
+    sockets: &mut HashMap<u16, MulticastSocket>,
+    endpoint_states: &mut HashMap<(Ipv4Addr, u16), EndpointState>,
+    forwarder: Option<&Forwarder>,
+    analysis_pool: &AnalysisPool,
+    options: &MonitorRangeOptions,
+) {
+    let result = match request.command.clone() {
+        ControlCommand::Add(endpoints) => {
+            let resolved = ResolvedEndpoints { endpoints, ..ResolvedEndpoints::default() };
+            add_endpoints(&resolved, sockets, endpoint_states, forwarder, analysis_pool, options).await
+        }
+        ControlCommand::Remove(endpoints) => {
+            remove_endpoints(&endpoints, sockets, endpoint_states, options);
+            Ok(())
+        }
+        ControlCommand::Reload => reload_endpoints(sockets, endpoint_states, forwarder, analysis_pool, options).await,
+        // Taken by the receive loop, which owns annotations.jsonl
+        ControlCommand::Annotate(_) => Ok(()),
+        ControlCommand::Status => {
+            let mut states: Vec<&EndpointState> = endpoint_states.values().collect();
+            states.sort_by_key(|state| (state.address, state.port));
+            let table: Vec<EndpointStatus> = states
+                .into_iter()
+                .map(|state| EndpointStatus {
+                    address: state.address.to_string(),
+                    port: state.port,
+                    page_active: state.page_active,
+                    packets_received: state.stats.packets_received,
+                    pending_removal: state.pending_removal,
+                    suppressed_bursts: state.pending.suppressed,
+                    filtered_packets: state.filtered_packets,
+                })
+                .collect();
+            request.respond(serde_json::to_string(&table).unwrap_or_default());
+            return;
+        }
+    };
+
+    match result {
+        Ok(()) => request.respond("ok".to_string()),
+        Err(e) => {
+            if options.json {
+                output_json(&JsonEvent::Error { message: e.to_string() });
+            } else if !options.quiet {
+                eprintln!("Control command failed: {}", e);
+            }
+            request.respond(format!("error: {}", e));
+        }
+    }
+}
+
+/// Join and start tracking endpoints that are not already monitored
+async fn add_endpoints(
+    resolved: &ResolvedEndpoints,
+    sockets: &mut HashMap<u16, MulticastSocket>,
+    endpoint_states: &mut HashMap<(Ipv4Addr, u16), EndpointState>,
+    forwarder: Option<&Forwarder>,
+    analysis_pool: &AnalysisPool,
+    options: &MonitorRangeOptions,
+) -> Result<(), MonitorError> {
+    let interface = options.interface.unwrap_or_default();
+
+    for ep in &resolved.endpoints {
+        let key = (ep.address, ep.port);
+        if let Some(state) = endpoint_states.get_mut(&key) {
+            // Re-adding cancels a pending removal; existing state is kept
+            state.pending_removal = false;
+            continue;
+        }
+
+        let socket = match sockets.entry(ep.port) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let socket = MulticastSocket::with_interface(ep.port, interface).await?;
+                socket.apply_busy_poll(options.busy_poll_us);
+                entry.insert(socket)
+            }
+        };
+        socket.join(ep.address)?;
 
-        let payload_type = PayloadType::from_pt(packet.header.payload_type);
+        let output_path = endpoint_output_path(options, ep, false);
+        let state = EndpointState::new(ep.address, ep.port, output_path)
+            .with_overrides(resolved.options(ep))
+            .with_repair(options.repair_window)
+            .with_forward(forwarder.cloned())
+            .with_analysis(analysis_pool.worker());
+        endpoint_states.insert(key, state);
 
         if options.json {
-            output_json(&JsonEvent::PageStarted {
+            output_json(&JsonEvent::EndpointAdded {
+                address: ep.address.to_string(),
+                port: ep.port,
                 timestamp: Utc::now(),
-                address: state.address.to_string(),
-                port: state.port,
-                source: packet.source.to_string(),
-                codec: codec_type.name().to_string(),
-                ssrc: packet.header.ssrc,
             });
         } else if !options.quiet {
-            println!("\n[{}:{}] Page started at {}", state.address, state.port, Utc::now().format("%Y-%m-%d %H:%M:%S"));
-            println!("  Source: {}", packet.source);
-            println!(
-                "  Codec: {} ({})",
-                payload_type.name(),
-                if options.codec.is_some() { "forced" } else { "detected" }
-            );
-            println!();
+            println!("\nNow monitoring {}:{}", ep.address, ep.port);
         }
+    }
 
-        // Create decoder
-        state.decoder = Some(create_decoder_for_payload_type(packet.header.payload_type)?);
-
-        // Create audio analyzer with decoder's sample rate
-        let sample_rate = state.decoder.as_ref().unwrap().sample_rate();
-        state.audio_analyzer = Some(AudioAnalyzer::new(sample_rate));
-        state.audio_stats = AudioStats::new();
+    Ok(())
+}
 
-        // Create recorder if output specified
-        if let Some(ref path) = state.output_path {
-            let channels = state.decoder.as_ref().unwrap().channels();
-            state.recorder = Some(WavRecorder::new(path, sample_rate, channels)?);
+/// Stop monitoring endpoints, deferring any with an active page until it ends
+fn remove_endpoints(
+    endpoints: &[MulticastEndpoint],
+    sockets: &mut HashMap<u16, MulticastSocket>,
+    endpoint_states: &mut HashMap<(Ipv4Addr, u16), EndpointState>,
+    options: &MonitorRangeOptions,
+) {
+    for ep in endpoints {
+        let key = (ep.address, ep.port);
+        match endpoint_states.get_mut(&key) {
+            Some(state) if state.page_active => state.pending_removal = true,
+            Some(_) => finish_removal(key, sockets, endpoint_states, options),
+            None => {}
         }
     }
+}
 
-    // Update stats
-    state.stats.update(packet);
-    state.last_packet = Some(Instant::now());
+/// Re-parse the configured pattern and apply the difference, including
+/// changed per-endpoint options
+async fn reload_endpoints(
+    sockets: &mut HashMap<u16, MulticastSocket>,
+    endpoint_states: &mut HashMap<(Ipv4Addr, u16), EndpointState>,
+    forwarder: Option<&Forwarder>,
+    analysis_pool: &AnalysisPool,
+    options: &MonitorRangeOptions,
+) -> Result<(), MonitorError> {
+    let targets = resolve_endpoints(&options.pattern, options.default_port)?;
+    let wanted: HashSet<(Ipv4Addr, u16)> = targets.endpoints.iter().map(|ep| (ep.address, ep.port)).collect();
 
-    // Decode, analyze, and record
-    if let Some(ref mut dec) = state.decoder {
-        if let Ok(samples) = dec.decode(&packet.payload) {
-            // Analyze audio
-            if let Some(ref mut analyzer) = state.audio_analyzer {
-                let analysis = analyzer.analyze(&samples);
-                state.audio_stats.update(&analysis, samples.len() as u64);
-                state.current_audio = analysis;
-            }
+    let removed: Vec<MulticastEndpoint> = endpoint_states
+        .keys()
+        .filter(|key| !wanted.contains(key))
+        .map(|&(address, port)| MulticastEndpoint { address, port })
+        .collect();
 
-            // Record
-            if let Some(ref mut rec) = state.recorder {
-                rec.write_samples(&samples)?;
-            }
+    remove_endpoints(&removed, sockets, endpoint_states, options);
+    for ep in &targets.endpoints {
+        if let Some(state) = endpoint_states.get_mut(&(ep.address, ep.port)) {
+            state.overrides = targets.options(ep);
+        }
+    }
+    add_endpoints(&targets, sockets, endpoint_states, forwarder, analysis_pool, options).await
+}
+
+/// Leave the group and forget the endpoint, closing the socket once it has no groups
+fn finish_removal(
+    key: (Ipv4Addr, u16),
+    sockets: &mut HashMap<u16, MulticastSocket>,
+    endpoint_states: &mut HashMap<(Ipv4Addr, u16), EndpointState>,
+    options: &MonitorRangeOptions,
+) {
+    let (address, port) = key;
+    if let Some(state) = endpoint_states.remove(&key) {
+        state.shared.remove();
+    }
+
+    if let Some(socket) = sockets.get_mut(&port) {
+        socket.leave(address).ok();
+        if socket.joined_groups().is_empty() {
+            sockets.remove(&port);
         }
     }
 
+    if options.json {
+        output_json(&JsonEvent::EndpointRemoved {
+            address: address.to_string(),
+            port,
+            timestamp: Utc::now(),
+        });
+    } else if !options.quiet {
+        println!("\nStopped monitoring {}:{}", address, port);
+    }
+}
+
+/// Handle a packet off the network, through the endpoint's repair buffer
+/// with `--repair`, unless the SSRC filter turns it away
+fn receive_packet(state: &mut EndpointState, packet: RtpPacket, options: &MonitorRangeOptions) -> Result<(), MonitorError> {
+    if !options.ssrc_filter.passes(packet.header.ssrc) {
+        state.filtered_packets += 1;
+        return Ok(());
+    }
+    let Some(ref mut repair) = state.repair else {
+        return handle_packet(state, &packet, options);
+    };
+    for packet in &repair.accept(packet, Instant::now()) {
+        handle_packet(state, packet, options)?;
+    }
+    Ok(())
+}
+
+/// Hand on packets whose gaps have been given up, and ask for the gaps now due
+fn poll_repair(state: &mut EndpointState, client: &RepairClient, now: Instant, options: &MonitorRangeOptions) -> Result<(), MonitorError> {
+    let Some(ref mut repair) = state.repair else {
+        return Ok(());
+    };
+    if let (Some(nack), Some(source)) = (repair.nack(now), repair.source()) {
+        client.send(&nack, source, state.port);
+    }
+    for packet in &repair.poll(now) {
+        handle_packet(state, packet, options)?;
+    }
     Ok(())
 }
 
-fn handle_page_end(state: &mut EndpointState, options: &MonitorRangeOptions) -> Result<(), MonitorError> {
-    // Calculate duration based on last received audio, not current time
-    // This avoids inflating the duration by the idle timeout period
-    let duration = match (state.page_start, state.last_packet) {
-        (Some(start), Some(last)) => last.duration_since(start).as_secs_f64(),
-        (Some(start), None) => start.elapsed().as_secs_f64(),
-        _ => 0.0,
+/// Hand on everything the repair buffer holds, before the page ends
+fn flush_repair(state: &mut EndpointState, options: &MonitorRangeOptions) -> Result<(), MonitorError> {
+    let Some(ref mut repair) = state.repair else {
+        return Ok(());
+    };
+    for packet in &repair.flush() {
+        handle_packet(state, packet, options)?;
+    }
+    Ok(())
+}
+
+fn handle_packet(state: &mut EndpointState, packet: &RtpPacket, options: &MonitorRangeOptions) -> Result<(), MonitorError> {
+    if state.ssrc == Some(packet.header.ssrc) {
+        return process_packet(state, packet, options);
+    }
+    if let Some(gap) = state.continuation(packet, options) {
+        stitch_page(state, packet, gap, options)?;
+        return process_packet(state, packet, options);
+    }
+
+    // A new stream; it becomes a page once it reaches the threshold
+    let Some(held) = state.pending.offer(packet, state.page_threshold(options)) else {
+        return Ok(());
     };
+    start_page(state, &held[0], options)?;
+    for packet in &held {
+        // An aborted page leaves the rest to start the next one
+        if state.ssrc == Some(packet.header.ssrc) {
+            process_packet(state, packet, options)?;
+        } else {
+            handle_packet(state, packet, options)?;
+        }
+    }
+    Ok(())
+}
+
+/// Set up decoding, analysis and recording for a page starting with `packet`
+fn start_page(state: &mut EndpointState, packet: &RtpPacket, options: &MonitorRangeOptions) -> Result<(), MonitorError> {
+    let started_at = arrival_time(packet.received_at);
+    state.ssrc = Some(packet.header.ssrc);
+    state.page_start = Some(packet.received_at);
+    state.page_active = true;
+    state.page_count += 1;
 
+    // Determine codec - dynamic payload types (e.g. AES67 L24) need it forced
+    let codec_spec = options.codec.unwrap_or_else(|| {
+        CodecSpec::from_payload_type(packet.header.payload_type).unwrap_or(CodecSpec::new(CodecType::G711Ulaw))
+    });
+    state.stats = PageStats::for_codec(codec_spec).with_g722_nonstandard_timestamps(options.g722_nonstandard_timestamps);
+
+    let payload_type = PayloadType::from_pt(packet.header.payload_type);
+    let mismatch = options
+        .codec
+        .is_some_and(|spec| forced_codec_mismatch(spec.codec, packet.header.payload_type));
+
+    let fingerprint = SenderFingerprint::from_packet(packet);
     if options.json {
-        output_json(&JsonEvent::PageEnded {
+        output_json(&JsonEvent::PageStarted {
+            timestamp: started_at,
             address: state.address.to_string(),
             port: state.port,
-            duration_secs: duration,
-            total_packets: state.stats.packets_received,
-            total_bytes: state.stats.bytes_received,
-            peak_rms_db: state.audio_stats.peak_rms_db,
-            avg_rms_db: state.audio_stats.avg_rms_db,
-            max_peak_db: state.audio_stats.max_peak_db,
-            dominant_freq_hz: state.audio_stats.dominant_freq_hz,
-            total_glitches: state.audio_stats.total_glitches,
-            total_clipped: state.audio_stats.total_clipped,
-            clipping_percent: state.audio_stats.clipping_percent(),
-            avg_zero_crossing_rate: state.audio_stats.avg_zero_crossing_rate,
+            source: packet.source.to_string(),
+            codec: codec_spec.codec.name().to_string(),
+            ssrc: packet.header.ssrc,
+            codec_forced: options.codec.is_some(),
+            payload_type_observed: packet.header.payload_type,
+            payload_type_mismatch: mismatch,
+            sender_fingerprint: fingerprint,
         });
     } else if !options.quiet {
-        println!("\n[{}:{}] Page ended. Duration: {:.1}s", state.address, state.port, duration);
-        println!("  Network: {} packets, {} bytes, {:.1}% loss, {:.1}ms jitter",
-            state.stats.packets_received,
-            state.stats.bytes_received,
-            state.stats.loss_percent(),
-            state.stats.jitter_ms
-        );
-        println!("  Audio:   Avg RMS: {}, Peak: {}, Dominant Freq: {}",
-            format_db(state.audio_stats.avg_rms_db),
-            format_db(state.audio_stats.max_peak_db),
-            format_frequency(state.audio_stats.dominant_freq_hz)
-        );
-        if state.audio_stats.total_glitches > 0 || state.audio_stats.total_clipped > 0 {
-            println!("  Issues:  {} glitches, {} clipped samples ({:.2}%)",
-                state.audio_stats.total_glitches,
-                state.audio_stats.total_clipped,
-                state.audio_stats.clipping_percent()
+        println!("\n[{}:{}] Page started at {}", state.address, state.port, started_at.format("%Y-%m-%d %H:%M:%S"));
+        println!("  Source: {}", packet.source);
+        if let Some(ref guess) = fingerprint.likely_sender {
+            println!("  Likely sender: {}", guess);
+        }
+        if options.codec.is_some() {
+            println!("  Codec: {} (forced, stream is PT {})", codec_spec, packet.header.payload_type);
+        } else {
+            println!("  Codec: {} (detected)", payload_type.name());
+        }
+        if mismatch {
+            let expected = match codec_spec.codec.payload_type() {
+                96..=127 => "a dynamic payload type".to_string(),
+                pt => format!("PT {}", pt),
+            };
+            println!(
+                "  ⚠ Stream is PT {} ({}) but {} expects {}; decoding as {}",
+                packet.header.payload_type,
+                payload_type.name(),
+                codec_spec.codec,
+                expected,
+                codec_spec.codec
             );
         }
+        println!();
     }
 
-    // Save recording if configured
-    if let Some(rec) = state.recorder.take() {
-        rec.finalize()?;
-        if let Some(ref path) = state.output_path {
-            if options.json {
-                output_json(&JsonEvent::RecordingSaved {
-                    address: state.address.to_string(),
-                    port: state.port,
-                    path: path.to_string_lossy().to_string(),
-                });
-            } else if !options.quiet {
-                println!("  Recording saved to: {}", path.display());
+    // The forced codec wins over the payload type. A codec whose backend is
+    // missing still gets a page, with every packet counted as undecodable
+    let decoder = match options.codec {
+        Some(spec) => create_decoder_for_spec(spec),
+        None => create_decoder_for_payload_type(packet.header.payload_type),
+    };
+    state.decoder = Some(match decoder {
+        Err(CodecError::BackendUnavailable { codec, missing }) => {
+            if !options.quiet && !options.json {
+                println!("  ⚠ {} needs the {}, which isn't available; not decoding this page", codec, missing);
+            }
+            Box::new(UnavailableDecoder::new(codec, missing))
+        }
+        decoder => decoder?,
+    });
+
+    // Create audio analyzer with decoder's sample rate and channel layout
+    let sample_rate = state.decoder.as_ref().unwrap().sample_rate();
+    let channels = state.decoder.as_ref().unwrap().channels();
+    state.audio_analyzer =
+        Some(AudioAnalyzer::with_channels(sample_rate, channels).with_worker(state.analysis_worker.clone()));
+    state.audio_stats = AudioStats::new();
+
+    // Create recorder if output specified (always 16-bit, see README)
+    state.recording_path = page_recording_path(state, started_at, options);
+    let recorder = match state.recording_path.as_deref().map(|path| WavRecorder::new(path, sample_rate, channels)) {
+        // Out of file descriptors: the page is still monitored, just not recorded
+        Some(Err(e)) if e.io_error().is_some_and(fd_limit::is_exhausted) => {
+            let path = state.recording_path.take().unwrap_or_default();
+            report_error(format!("Unable to record page to {}: {}; monitoring it without a recording", path.display(), e), options);
+            None
+        }
+        recorder => recorder.transpose()?,
+    };
+    if let (Some(mut recorder), Some(path)) = (recorder, state.recording_path.as_ref()) {
+        recorder.set_start_time(started_at);
+        state.recorder = Some(recorder);
+        if let Some(granularity) = options.timing_file {
+            state.timing = Some(TimingWriter::create(&timing_path(path), granularity)?);
+        }
+        if options.record_payloads {
+            // The payloads are extra; the page is recorded without them
+            let payloads = payloads_path(path);
+            match PayloadWriter::create(&payloads) {
+                Ok(writer) => state.payloads = Some(writer),
+                Err(e) => report_error(format!("Unable to create {}: {}", payloads.display(), e), options),
+            }
+        }
+        if let Some(ref command) = options.pipe_audio_cmd {
+            // Likewise the command: the page is recorded whether or not it runs
+            match command.start(sample_rate, channels) {
+                Ok(pipe) => state.audio_pipe = Some(pipe),
+                Err(e) => report_error(format!("[{}:{}] {}", state.address, state.port, e), options),
             }
         }
     }
 
-    state.reset_page();
+    if let Some(ref forwarder) = state.forwarder {
+        let forwarding = forwarder.start(packet, codec_spec);
+        if let Some(error) = forwarding.error() {
+            report_error(format!("Not forwarding page: {}", error), options);
+        }
+        state.forwarding = Some(forwarding);
+    }
+
+    if let Some(ref dir) = options.spectrum_dir {
+        let path = dir.join(format!(
+            "spectrum_{}_{}_{}.jsonl",
+            state.address.to_string().replace('.', "_"),
+            state.port,
+            Utc::now().format("%Y%m%d_%H%M%S")
+        ));
+        let endpoint = format!("{}:{}", state.address, state.port);
+        state.spectrum = Some(SpectrumWriter::create(&path, endpoint, None)?);
+        state.last_spectrum = Some(Instant::now());
+    }
     Ok(())
 }
 
-/// Run the monitor command (single endpoint, for backwards compatibility)
-#[allow(dead_code)]
-pub async fn run_monitor(options: MonitorOptions) -> Result<(), MonitorError> {
-    let range_options = MonitorRangeOptions {
-        pattern: format!("{}:{}", options.address, options.port),
-        default_port: options.port,
-        interface: options.interface,
-        codec: options.codec,
-        output: options.output,
-        timeout: options.timeout,
-        json: options.json,
-        quiet: options.quiet,
+/// Carry the active page on to `packet`'s new SSRC, `gap` after the old
+/// one's last packet: the recording gets silence for the wait, beyond the
+/// audio that packet carried
+fn stitch_page(state: &mut EndpointState, packet: &RtpPacket, gap: Duration, options: &MonitorRangeOptions) -> Result<(), MonitorError> {
+    let change = SsrcChange {
+        old_ssrc: state.ssrc.unwrap_or_default(),
+        new_ssrc: packet.header.ssrc,
+        offset_secs: state.page_start.map_or(0.0, |start| packet.received_at.saturating_duration_since(start).as_secs_f64()),
+        gap_ms: gap.as_secs_f64() * 1000.0,
     };
-    run_monitor_range(range_options).await
+    if let Some(ref dec) = state.decoder {
+        let frames = stitch::gap_frames(gap, state.stats.nominal_packet_secs(), dec.sample_rate());
+        if let Some(ref mut rec) = state.recorder {
+            rec.write_silence(frames)?;
+        }
+        if let Some(ref mut pipe) = state.audio_pipe {
+            pipe.push_silence(frames);
+        }
+    }
+    state.stats.stitch();
+    state.ssrc = Some(packet.header.ssrc);
+    state.ssrc_changes.push(change);
+
+    if options.json {
+        output_json(&JsonEvent::SsrcChanged {
+            timestamp: arrival_time(packet.received_at),
+            address: state.address.to_string(),
+            port: state.port,
+            source: packet.source.to_string(),
+            change,
+        });
+    } else if !options.quiet {
+        println!(
+            "\n[{}:{}] SSRC {:08x} -> {:08x} after {:.0}ms; carrying the page on",
+            state.address, state.port, change.old_ssrc, change.new_ssrc, change.gap_ms
+        );
+    }
+    Ok(())
 }
 
-fn output_json(event: &JsonEvent) {
-    if let Ok(json) = serde_json::to_string(event) {
-        println!("{}", json);
+/// Where a page starting now is recorded, named from the template if there is one
+fn page_recording_path(state: &EndpointState, started_at: DateTime<Utc>, options: &MonitorRangeOptions) -> Option<PathBuf> {
+    let output = state.output_path.as_ref()?;
+    let fields = NameFields {
+        endpoint: Some(SocketAddrV4::new(state.address, state.port)),
+        page: Some(state.page_count),
+        start: Some(started_at.with_timezone(&Local)),
+        ssrc: state.ssrc,
+        ..NameFields::default()
+    };
+    Some(match options.naming.recording_name(fields) {
+        Some(name) => output.join(unique_name(output, &name)),
+        None if options.overwrite => output.clone(),
+        None => page_file_path(output, state.page_count, started_at),
+    })
+}
+
+/// A page's own file beside the endpoint's prefix: `prefix.wav` becomes
+/// `prefix_0003_20240115_103000.wav` for page 3, never one already there
+fn page_file_path(prefix: &Path, page: u32, started_at: DateTime<Utc>) -> PathBuf {
+    let stem = prefix.file_stem().and_then(|s| s.to_str()).unwrap_or("recording");
+    let ext = prefix.extension().and_then(|s| s.to_str()).unwrap_or("wav");
+    let name = format!("{}_{:04}_{}.{}", stem, page, started_at.with_timezone(&Local).format("%Y%m%d_%H%M%S"), ext);
+    let dir = prefix.parent().unwrap_or(Path::new(""));
+    dir.join(unique_name(dir, &name))
+}
+
+/// Whether a static payload type names a different codec than the forced
+/// one. Dynamic types (96-127) are assigned out of band, so they never do.
+fn forced_codec_mismatch(forced: CodecType, payload_type: u8) -> bool {
+    if (96..=127).contains(&payload_type) {
+        return false;
     }
+    CodecType::from_payload_type(payload_type) != Some(forced)
 }
 
-/// Parse an address string into an `Ipv4Addr`
-pub fn parse_address(addr: &str) -> Result<Ipv4Addr, MonitorError> {
-    addr.parse()
-        .map_err(|_| MonitorError::InvalidAddress(addr.to_string()))
+/// Wall-clock time a packet was received
+pub(crate) fn arrival_time(received_at: Instant) -> DateTime<Utc> {
+    Utc::now() - chrono::Duration::from_std(received_at.elapsed()).unwrap_or_default()
 }
 
-/// Parse an address pattern (may include ranges) and return endpoints
-#[allow(dead_code)]
-pub fn parse_address_pattern(pattern: &str, default_port: u16) -> Result<Vec<MulticastEndpoint>, MonitorError> {
-    let pattern = if pattern.contains(':') {
-        pattern.to_string()
-    } else {
-        format!("{}:{}", pattern, default_port)
+/// Update stats, then decode, analyze and record one packet of the current page
+fn process_packet(state: &mut EndpointState, packet: &RtpPacket, options: &MonitorRangeOptions) -> Result<(), MonitorError> {
+    // Update stats
+    if let Some(discontinuity) = state.stats.update(packet) {
+        report_discontinuity(state, &discontinuity, options);
+    }
+    state.last_packet = Some(packet.received_at);
+    // A colliding sender's audio would be mixed into the page's
+    if !state.stats.is_stream_source(packet.source) {
+        return Ok(());
+    }
+
+    // Before anything can go wrong with the audio
+    if let Some(ref mut payloads) = state.payloads {
+        let header = &packet.header;
+        if let Err(e) = payloads.record(&packet.payload, header.sequence_number, header.timestamp, header.payload_type) {
+            let message = format!("Error writing {}: {}; the page's later payloads are not saved", payloads.path().display(), e);
+            report_error(message, options);
+        }
+    }
+    if let Some(ref mut forwarding) = state.forwarding {
+        if let Some(warning) = forwarding.forward(packet, &state.stats) {
+            report_error(warning, options);
+        }
+    }
+
+    let Some(ref mut dec) = state.decoder else {
+        return Ok(());
     };
-    Ok(parse_range(&pattern)?)
+
+    if let Some(ref mut analyzer) = state.audio_analyzer {
+        skip_analysis(analyzer, &mut state.audio_stats, &state.stats, dec.sample_rate());
+    }
+
+    // Keep the recording on the RTP timeline across audio nobody sent (Opus DTX) or that was lost
+    if let Some(ref mut rec) = state.recorder {
+        let (concealed, silent_frames) = skipped_audio(dec.as_mut(), &state.stats);
+        rec.write_samples(&concealed)?;
+        rec.write_silence(silent_frames)?;
+        if let Some(ref mut pipe) = state.audio_pipe {
+            pipe.push(&concealed);
+            pipe.push_silence(silent_frames);
+        }
+    }
+
+    // Audio cut from the end of the previous payload
+    let truncated_secs = state.stats.truncated_secs();
+    if truncated_secs > 0.0 {
+        let fill = match options.decode_error_policy.repair(dec.as_mut(), truncated_secs, || truncation_note(truncated_secs)) {
+            Ok(fill) => fill,
+            Err(reason) => {
+                // The packet itself is intact and starts the next page
+                abort_page(state, reason, options)?;
+                return handle_packet(state, packet, options);
+            }
+        };
+        record_audio(state.recorder.as_mut(), state.audio_pipe.as_mut(), &fill)?;
+    }
+
+    // Where this packet's audio starts, now the gap before it is filled
+    if let (Some(timing), Some(rec)) = (state.timing.as_mut(), state.recorder.as_ref()) {
+        let offset = rec.samples_written() / u64::from(dec.channels().max(1));
+        timing.record(offset, arrival_time(packet.received_at), packet.header.sequence_number)?;
+    }
+
+    // Decode, analyze, and record
+    let decoded = dec.decode(&packet.payload);
+    state.stats.record_decode(packet.payload.len(), decoded.as_ref().ok().map(Vec::len));
+    let samples = match decoded {
+        Ok(samples) => samples,
+        // Counted above; there is nothing to conceal it with
+        Err(CodecError::BackendUnavailable { .. }) => return Ok(()),
+        Err(e) => {
+            let fill = match options.decode_error_policy.repair(dec.as_mut(), state.stats.nominal_packet_secs(), || e.to_string()) {
+                Ok(fill) => fill,
+                Err(reason) => return abort_page(state, reason, options),
+            };
+            record_audio(state.recorder.as_mut(), state.audio_pipe.as_mut(), &fill)?;
+            return Ok(());
+        }
+    };
+
+    // Analyze audio
+    if let Some(ref mut analyzer) = state.audio_analyzer {
+        let analysis = analyzer.analyze(&samples);
+        state.audio_stats.update(&analysis, samples.len() as u64);
+        state.current_audio = analysis;
+
+        // Spectrum snapshot
+        if let Some(ref mut spectrum) = state.spectrum {
+            if state.last_spectrum.is_none_or(|t| t.elapsed() >= SPECTRUM_INTERVAL) {
+                spectrum.sample(analyzer)?;
+                state.last_spectrum = Some(Instant::now());
+            }
+        }
+    }
+
+    // Record
+    record_audio(state.recorder.as_mut(), state.audio_pipe.as_mut(), &samples)?;
+
+    Ok(())
+}
+
+/// Write samples to the page's recording and its `--pipe-audio-cmd`
+fn record_audio(recorder: Option<&mut WavRecorder>, pipe: Option<&mut AudioPipe>, samples: &[i16]) -> Result<(), MonitorError> {
+    if let Some(rec) = recorder {
+        rec.write_samples(samples)?;
+    }
+    if let Some(pipe) = pipe {
+        pipe.push(samples);
+    }
+    Ok(())
+}
+
+/// What to record across the audio skipped before the latest packet:
+/// concealment for what was lost, where the codec can, then frames of
+/// silence for what the sender never sent
+pub(crate) fn skipped_audio(decoder: &mut dyn AudioDecoder, stats: &PageStats) -> (Vec<i16>, u64) {
+    let rate = f64::from(decoder.sample_rate());
+    let lost = (stats.lost_secs() * rate).round() as usize;
+    let silent = ((stats.skipped_secs() - stats.lost_secs()) * rate).round() as u64;
+    let concealed = if lost > 0 {
+        decoder.conceal(lost).unwrap_or_else(|_| vec![0; lost * usize::from(decoder.channels())])
+    } else {
+        Vec::new()
+    };
+    (concealed, silent)
+}
+
+/// Keep the page's watermark on the RTP timeline across audio that was
+/// lost or never sent, which isn't analyzed. Unlike [`skipped_audio`] this
+/// counts G.711 loss, whose packets are timed by their spacing
+pub(crate) fn skip_analysis(analyzer: &mut AudioAnalyzer, audio_stats: &mut AudioStats, stats: &PageStats, sample_rate: u32) {
+    let secs = stats.missing_secs() + stats.skipped_secs() - stats.lost_secs();
+    let frames = (secs * f64::from(sample_rate)).round() as u64;
+    if frames > 0 {
+        audio_stats.add_skipped(&analyzer.skip(frames));
+    }
+}
+
+/// Why a page ended under `--on-decode-error abort-page` for a truncated payload
+pub(crate) fn truncation_note(secs: f64) -> String {
+    format!("truncated payload ({:.0}ms of audio missing)", secs * 1000.0)
+}
+
+/// End the page early under `--on-decode-error abort-page`. The rest of
+/// the stream starts a new page
+fn abort_page(state: &mut EndpointState, reason: String, options: &MonitorRangeOptions) -> Result<(), MonitorError> {
+    state.interrupted = Some(reason);
+    handle_page_end(state, options)
+}
+
+fn report_discontinuity(state: &EndpointState, discontinuity: &StreamDiscontinuity, options: &MonitorRangeOptions) {
+    let ssrc = state.ssrc.unwrap_or_default();
+    match *discontinuity {
+        StreamDiscontinuity::SequenceReset { old_sequence, new_sequence } => {
+            if options.json {
+                output_json(&JsonEvent::StreamReset {
+                    timestamp: Utc::now(),
+                    address: state.address.to_string(),
+                    port: state.port,
+                    ssrc,
+                    old_sequence,
+                    new_sequence,
+                });
+            } else if !options.quiet {
+                println!(
+                    "\n[{}:{}] Stream reset: sequence {} -> {}",
+                    state.address, state.port, old_sequence, new_sequence
+                );
+            }
+        }
+        StreamDiscontinuity::SsrcCollision { ssrc, original_source, new_source } => {
+            if options.json {
+                output_json(&JsonEvent::SsrcCollision {
+                    timestamp: Utc::now(),
+                    address: state.address.to_string(),
+                    port: state.port,
+                    ssrc,
+                    original_source: original_source.to_string(),
+                    new_source: new_source.to_string(),
+                });
+            } else if !options.quiet {
+                println!(
+                    "\n[{}:{}] SSRC collision: {:08x} from {} and {}",
+                    state.address, state.port, ssrc, original_source, new_source
+                );
+            }
+        }
+        StreamDiscontinuity::TtlVaried { source, previous_ttl, ttl } => {
+            if options.json {
+                output_json(&JsonEvent::TtlVaried {
+                    timestamp: Utc::now(),
+                    address: state.address.to_string(),
+                    port: state.port,
+                    source: source.to_string(),
+                    previous_ttl,
+                    ttl,
+                });
+            } else if !options.quiet {
+                println!(
+                    "\n[{}:{}] Warning: TTL changed {} -> {} within the page (from {}); a second sender or a flapping path",
+                    state.address, state.port, previous_ttl, ttl, source
+                );
+            }
+        }
+        StreamDiscontinuity::NonstandardTimestamps { clock_rate, expected_clock_rate } => {
+            if options.json {
+                output_json(&JsonEvent::NonstandardTimestamps {
+                    timestamp: Utc::now(),
+                    address: state.address.to_string(),
+                    port: state.port,
+                    ssrc,
+                    clock_rate,
+                    expected_clock_rate,
+                });
+            } else if !options.quiet {
+                println!(
+                    "\n[{}:{}] Warning: G.722 timestamps tick at {}Hz, not the {}Hz of RFC 3551; reading them as sent (--g722-nonstandard-timestamps)",
+                    state.address, state.port, clock_rate, expected_clock_rate
+                );
+            }
+        }
+    }
+}
+
+/// TTL for display; "unknown" where the platform doesn't report it
+pub fn format_ttl(ttl: Option<TtlSummary>) -> String {
+    ttl.map_or_else(|| "unknown".to_string(), |ttl| ttl.to_string())
+}
+
+fn handle_page_end(state: &mut EndpointState, options: &MonitorRangeOptions) -> Result<(), MonitorError> {
+    // FFTs still on the analysis pool belong in the page's figures
+    if let Some(ref mut analyzer) = state.audio_analyzer {
+        analyzer.finish(&mut state.audio_stats);
+    }
+
+    // Calculate duration based on last received audio, not current time
+    // This avoids inflating the duration by the idle timeout period
+    let duration = match (state.page_start, state.last_packet) {
+        (Some(start), Some(last)) => last.duration_since(start).as_secs_f64() + state.stats.last_packet_secs(),
+        (Some(start), None) => start.elapsed().as_secs_f64(),
+        _ => 0.0,
+    };
+    let mos_breakdown = state.decoder.as_ref().map(|decoder| {
+        quality::estimate(
+            decoder.codec_type(),
+            &QualityInputs::from_page(&state.stats, &state.audio_stats, duration),
+        )
+    });
+
+    let band_split = state.audio_stats.band_split();
+    let suspected_narrowband_transcoding = band_split.is_some_and(|split| split.suspect_narrowband(&options.transcoding));
+    let repair = state.repair.as_mut().map(RepairBuffer::take_summary);
+    let forward = state.forwarding.take().map(|forwarding| Box::new(forwarding.finish()));
+    state.pages_ended += 1;
+
+    // Keep the stats reporter off this endpoint until the page's events are out
+    let shared = state.shared.clone();
+    let _reporting = shared.end_page();
+    shared.record_page(LastPage {
+        ended: Utc::now(),
+        duration_secs: duration,
+        packets: state.stats.packets_received,
+        loss_percent: state.stats.loss_percent(),
+        jitter_ms: state.stats.jitter_ms,
+        avg_rms_db: state.audio_stats.avg_rms_db,
+        max_peak_db: state.audio_stats.max_peak_db,
+        mos_estimate: mos_breakdown.as_ref().map(MosBreakdown::mos),
+        error: state.interrupted.clone(),
+    });
+    if let Some(ref reason) = state.interrupted {
+        status_page::record_error(format!("[{}:{}] Page interrupted: {}", state.address, state.port, reason));
+    }
+
+    if options.json {
+        output_json(&JsonEvent::PageEnded {
+            address: state.address.to_string(),
+            port: state.port,
+            duration_secs: duration,
+            total_packets: state.stats.packets_received,
+            total_bytes: state.stats.bytes_received,
+            max_gap_ms: state.stats.max_gap_ms,
+            gap_histogram: state.stats.gap_histogram,
+            continuity_percent: state.stats.continuity_percent(duration),
+            ttl: state.stats.ttl(),
+            dscp: state.stats.dscp(options.expect_dscp),
+            dtx_gap_secs: state.stats.dtx_gap_secs,
+            clock_drift_ms: state.stats.clock_drift_ms(),
+            payload_sizes: state.stats.payload_sizes(),
+            decode_errors: state.stats.decode_errors,
+            short_decodes: state.stats.short_decodes,
+            truncated_payloads: state.stats.truncated_payloads,
+            padded_packets: state.stats.padded_packets,
+            avg_padding_bytes: state.stats.avg_padding_bytes(),
+            peak_rms_db: state.audio_stats.peak_rms_db,
+            avg_rms_db: state.audio_stats.avg_rms_db,
+            max_peak_db: state.audio_stats.max_peak_db,
+            dominant_freq_hz: state.audio_stats.dominant_freq_hz,
+            total_glitches: state.audio_stats.total_glitches,
+            total_clipped: state.audio_stats.total_clipped,
+            clipping_percent: state.audio_stats.clipping_percent(),
+            avg_zero_crossing_rate: state.audio_stats.avg_zero_crossing_rate,
+            integrated_lufs: state.audio_stats.loudness().integrated_lufs(),
+            max_momentary_lufs: state.audio_stats.loudness().max_momentary_lufs(),
+            lra: state.audio_stats.loudness().loudness_range_lu(),
+            watermark: state.audio_stats.watermark(),
+            chime: state.audio_stats.chime().map(|chime| chime.name().to_string()),
+            band_split,
+            suspected_narrowband_transcoding,
+            mos_estimate: mos_breakdown.as_ref().map(MosBreakdown::mos),
+            mos_breakdown,
+            repair,
+            forward,
+            ssrc_changes: state.ssrc_changes.clone(),
+            error: state.interrupted.clone(),
+            truncated: state.truncated,
+        });
+    } else if !options.quiet {
+        println!("\n[{}:{}] Page ended. Duration: {:.1}s", state.address, state.port, duration);
+        if let Some(ref reason) = state.interrupted {
+            println!("  Interrupted: {}", reason);
+        }
+        if state.truncated {
+            println!("  Truncated: the run ended mid-page");
+        }
+        println!("  Network: {} packets, {} bytes, {:.1}% loss, {:.1}ms jitter, {:.0}ms max gap, {:.1}% continuity, TTL {}",
+            state.stats.packets_received,
+            state.stats.bytes_received,
+            state.stats.loss_percent(),
+            state.stats.jitter_ms,
+            state.stats.max_gap_ms,
+            state.stats.continuity_percent(duration),
+            format_ttl(state.stats.ttl())
+        );
+        println!("  Audio:   Avg RMS: {}, Peak: {}, Dominant Freq: {}",
+            format_db(state.audio_stats.avg_rms_db),
+            format_db(state.audio_stats.max_peak_db),
+            format_frequency(state.audio_stats.dominant_freq_hz)
+        );
+        if let Some(integrated) = state.audio_stats.loudness().integrated_lufs() {
+            println!("  Loudness: {}", format_loudness(integrated, state.audio_stats.loudness().loudness_range_lu()));
+        }
+        if let Some(watermark) = state.audio_stats.watermark() {
+            println!("  Watermark: ID {} (confidence {:.2})", watermark.id, watermark.confidence);
+        }
+        if let Some(chime) = state.audio_stats.chime() {
+            println!("  Chime: builtin:{}", chime.name());
+        }
+        if let Some(split) = band_split.filter(|_| suspected_narrowband_transcoding) {
+            println!("  Warning: looks narrowband despite a wideband codec ({:.0} dB above 4 kHz, noise floor {:.1} dB down)",
+                split.high_band_db,
+                split.noise_floor_snr_db
+            );
+        }
+        if state.stats.dtx_gap_secs > 0.0 {
+            println!("  DTX:     {:.1}s of sender silence (not counted as loss)", state.stats.dtx_gap_secs);
+        }
+        if let Some(dscp) = state.stats.dscp(options.expect_dscp) {
+            println!("  DSCP:    {}", dscp);
+            if let Some(mismatch) = dscp.mismatch() {
+                println!("  Warning: {}", mismatch);
+            }
+        }
+        if let Some(repair) = repair {
+            println!("  Repair:  {} lost packets repaired, {} unrepaired ({} NACKs)",
+                repair.packets_repaired,
+                repair.packets_unrepaired,
+                repair.nacks_sent
+            );
+        }
+        if let Some(forward) = forward {
+            println!("  Forward: {}", forward);
+        }
+        if state.stats.resets > 0 || state.stats.ssrc_collisions > 0 {
+            println!("  Stream:  {} resets, {} SSRC collisions",
+                state.stats.resets,
+                state.stats.ssrc_collisions
+            );
+        }
+        for change in &state.ssrc_changes {
+            println!("  Stitched: SSRC {:08x} -> {:08x} at {:.1}s ({:.0}ms gap)",
+                change.old_ssrc,
+                change.new_ssrc,
+                change.offset_secs,
+                change.gap_ms
+            );
+        }
+        if let Some(anomaly) = state.stats.payload_sizes().anomaly() {
+            println!("  Payload: {}", anomaly);
+        }
+        if let Some(avg) = state.stats.avg_padding_bytes() {
+            println!("  Padding: {} packets, {:.1} bytes on average", state.stats.padded_packets, avg);
+        }
+        if state.stats.decode_errors > 0 || state.stats.short_decodes > 0 || state.stats.truncated_payloads > 0 {
+            println!("  Decode:  {} errors, {} short, {} truncated in transit",
+                state.stats.decode_errors,
+                state.stats.short_decodes,
+                state.stats.truncated_payloads
+            );
+        }
+        if state.audio_stats.total_glitches > 0 || state.audio_stats.total_clipped > 0 {
+            println!("  Issues:  {} glitches, {} clipped samples ({:.2}%)",
+                state.audio_stats.total_glitches,
+                state.audio_stats.total_clipped,
+                state.audio_stats.clipping_percent()
+            );
+        }
+        if let Some(ref breakdown) = mos_breakdown {
+            println!("  Quality: MOS {:.2} (R {:.0}; loss -{:.1}, delay -{:.1}, codec -{:.1}, glitches -{:.1}, clipping -{:.1})",
+                breakdown.mos(),
+                breakdown.r_factor,
+                breakdown.loss,
+                breakdown.delay,
+                breakdown.codec,
+                breakdown.glitches,
+                breakdown.clipping
+            );
+        }
+    }
+
+    // The recording is finished off the loop, and reported when it's done.
+    // Without a name template every page goes to the same file, and the
+    // next page would open it while the queue still has it, so it's
+    // finished here
+    if let Some(rec) = state.recorder.take() {
+        let endpoint = SocketAddrV4::new(state.address, state.port);
+        let pending = PendingRecording::new(endpoint, state.page_count, rec)
+            .with_timing(state.timing.take())
+            .with_payloads(state.payloads.take())
+            .with_pipe(state.audio_pipe.take());
+        if state.recording_path == state.output_path {
+            report_finalized(pending.finish(), options);
+        } else {
+            state.finalizing.push(pending);
+        }
+    }
+    if let Some(timing) = state.timing.take() {
+        timing.finish()?;
+    }
+    if let Some(payloads) = state.payloads.take() {
+        payloads.finish()?;
+    }
+
+    state.reset_page();
+    Ok(())
+}
+
+/// Hand ended pages' recordings to the finalize queue, and report those it
+/// has finished
+fn finalize_recordings(
+    queue: &mut FinalizeQueue,
+    endpoint_states: &mut HashMap<(Ipv4Addr, u16), EndpointState>,
+    options: &MonitorRangeOptions,
+) {
+    for state in endpoint_states.values_mut() {
+        for pending in state.finalizing.drain(..) {
+            queue.push(pending);
+        }
+    }
+    for finished in queue.poll() {
+        report_finalized(finished, options);
+    }
+}
+
+/// Report a recording the finalize queue has finished
+fn report_finalized(finished: FinishedRecording, options: &MonitorRangeOptions) {
+    for error in finished.file_errors {
+        report_error(format!("Error finalizing {}", error), options);
+    }
+    let pipe_output = finished.pipe_output;
+    match finished.result {
+        Ok(integrity) => {
+            if options.json {
+                let (address, port) = (finished.endpoint.ip().to_string(), finished.endpoint.port());
+                let path = finished.path.to_string_lossy().to_string();
+                let files = finished
+                    .files
+                    .iter()
+                    .map(|(kind, path)| (*kind, path.to_string_lossy().to_string()))
+                    .collect();
+                output_json(&JsonEvent::RecordingSaved { address: address.clone(), port, path: path.clone(), files });
+                output_json(&JsonEvent::RecordingFinalized { timestamp: Utc::now(), address, port, path, integrity, pipe_output });
+            } else if !options.quiet {
+                println!("[{}] Recording saved to: {}", finished.endpoint, finished.path.display());
+                if let Some(payloads) = finished.files.get(&RecordingKind::Payloads) {
+                    println!("[{}] Payloads saved to: {}", finished.endpoint, payloads.display());
+                }
+                if let Some(pipe) = pipe_output {
+                    report_pipe_output(finished.endpoint, &pipe);
+                }
+            }
+        }
+        Err(e) => report_error(format!("Error finalizing {}: {}", finished.path.display(), e), options),
+    }
+}
+
+/// Print what a page's `--pipe-audio-cmd` printed, and how it went if it failed
+fn report_pipe_output(endpoint: SocketAddrV4, pipe: &PipeOutput) {
+    let output = pipe.output.trim();
+    if !output.is_empty() {
+        println!("[{}] {}", endpoint, output);
+    }
+    if pipe.timed_out {
+        println!("[{}] `{}` timed out", endpoint, pipe.command);
+    } else if !pipe.succeeded() {
+        match pipe.exit_code {
+            Some(code) => println!("[{}] `{}` exited with {}", endpoint, pipe.command, code),
+            None => println!("[{}] `{}` was killed", endpoint, pipe.command),
+        }
+    }
+    if pipe.samples_dropped > 0 {
+        println!("[{}] `{}` fell behind; {} samples were not piped", endpoint, pipe.command, pipe.samples_dropped);
+    }
+}
+
+/// An error that doesn't stop the run: an event with `--json`, otherwise on stderr
+fn report_error(message: String, options: &MonitorRangeOptions) {
+    status_page::record_error(message.as_str());
+    if options.json {
+        output_json(&JsonEvent::Error { message });
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+/// Run the monitor command (single endpoint, for backwards compatibility)
+#[allow(dead_code)]
+pub async fn run_monitor(options: MonitorOptions) -> Result<(), MonitorError> {
+    let range_options = MonitorRangeOptions {
+        pattern: format!("{}:{}", options.address, options.port),
+        default_port: options.port,
+        interface: options.interface,
+        codec: options.codec,
+        output: options.output,
+        overwrite: false,
+        timeout: options.timeout,
+        json: options.json,
+        quiet: options.quiet,
+        control_socket: None,
+        spectrum_dir: None,
+        pcap: None,
+        page_threshold: PageThreshold::default(),
+        stitch_gap: None,
+        ssrc_filter: SsrcFilter::default(),
+        count: None,
+        busy_poll_us: None,
+        analysis_threads: None,
+        stats_interval: Duration::from_secs(1),
+        stats_active_only: false,
+        naming: Naming::default(),
+        memory_report: None,
+        rejoin_interval: Some(DEFAULT_REJOIN_INTERVAL),
+        decode_error_policy: DecodeErrorPolicy::default(),
+        repair_window: None,
+        inspect: None,
+        expect_dscp: None,
+        timing_file: None,
+        record_payloads: false,
+        pipe_audio_cmd: None,
+        transcoding: TranscodingThresholds::default(),
+        watchdog: Some(Duration::from_secs(30)),
+        heartbeat: None,
+        forward: None,
+        forward_ttl: 32,
+        max_file_descriptors: None,
+        g722_nonstandard_timestamps: false,
+        status_listen: None,
+    };
+    run_monitor_range(range_options).await
+}
+
+pub(crate) fn output_json(event: &JsonEvent) {
+    if let Ok(json) = serde_json::to_string(&VersionedEvent::new(event)) {
+        println!("{}", json);
+    }
+}
+
+/// Parse an address string into an `Ipv4Addr`
+pub fn parse_address(addr: &str) -> Result<Ipv4Addr, MonitorError> {
+    addr.parse()
+        .map_err(|_| MonitorError::InvalidAddress(addr.to_string()))
+}
+
+/// Parse an address pattern (may include ranges) and return endpoints
+#[allow(dead_code)]
+pub fn parse_address_pattern(pattern: &str, default_port: u16) -> Result<Vec<MulticastEndpoint>, MonitorError> {
+    let pattern = if pattern.contains(':') {
+        pattern.to_string()
+    } else {
+        format!("{}:{}", pattern, default_port)
+    };
+    Ok(parse_range(&pattern)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::drain::BatchSocket;
+    use crate::network::BufSlot;
+    use std::net::IpAddr;
+
+    fn source(last_octet: u8) -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, last_octet)), 5004)
+    }
+
+    /// Build a 20ms G.711 packet arriving `index` frames after `base`
+    fn packet(seq: u16, index: u32, ssrc: u32, from: SocketAddr, base: Instant) -> RtpPacket {
+        let data = RtpPacket::build(0, seq, index * 160, ssrc, &[0xFF; 160], false);
+        let arrival = base + Duration::from_millis(u64::from(index) * 20);
+        RtpPacket::parse_with_time(&data, from, arrival).unwrap()
+    }
+
+    /// A 20ms G.711 packet arriving `arrival_ms` after `base`
+    fn packet_at(seq: u16, arrival_ms: u64, base: Instant) -> RtpPacket {
+        let data = RtpPacket::build(0, seq, u32::from(seq) * 160, 1, &[0xFF; 160], false);
+        RtpPacket::parse_with_time(&data, source(1), base + Duration::from_millis(arrival_ms)).unwrap()
+    }
+
+    /// Options for the tests: one quiet endpoint with nothing optional,
+    /// recording to `output` if given
+    fn options(output: Option<&Path>) -> MonitorRangeOptions {
+        MonitorRangeOptions {
+            pattern: "224.0.1.1:5004".to_string(),
+            default_port: 5004,
+            interface: None,
+            codec: None,
+            output: output.map(Path::to_path_buf),
+            overwrite: true,
+            timeout: Timeout::Indefinite,
+            json: false,
+            quiet: true,
+            control_socket: None,
+            spectrum_dir: None,
+            pcap: None,
+            page_threshold: PageThreshold::default(),
+            stitch_gap: None,
+            ssrc_filter: SsrcFilter::default(),
+            count: None,
+            busy_poll_us: None,
+            analysis_threads: None,
+            stats_interval: Duration::from_secs(1),
+            stats_active_only: false,
+            naming: Naming::default(),
+            memory_report: None,
+            rejoin_interval: None,
+            decode_error_policy: DecodeErrorPolicy::default(),
+            repair_window: None,
+            inspect: None,
+            expect_dscp: None,
+            timing_file: None,
+            record_payloads: false,
+            pipe_audio_cmd: None,
+            transcoding: TranscodingThresholds::default(),
+            watchdog: None,
+            heartbeat: None,
+            forward: None,
+            forward_ttl: 32,
+            max_file_descriptors: None,
+            g722_nonstandard_timestamps: false,
+            status_listen: None,
+        }
+    }
+
+    #[test]
+    fn test_payload_size_histogram() {
+        let base = Instant::now();
+        let mut stats = PageStats::for_codec(CodecSpec::new(CodecType::G711Ulaw));
+        let mut decoder = create_decoder_for_spec(CodecSpec::new(CodecType::G711Ulaw)).unwrap();
+        for seq in 0..100u16 {
+            let size = match seq {
+                10 | 40 | 70 => 172,
+                99 => 80,
+                _ => 160,
+            };
+            let data = RtpPacket::build(0, seq, u32::from(seq) * 160, 1, &vec![0xFF; size], false);
+            let packet = RtpPacket::parse_with_time(&data, source(1), base + Duration::from_millis(u64::from(seq) * 20)).unwrap();
+            stats.update(&packet);
+            let decoded = decoder.decode(&packet.payload).ok().map(|samples| samples.len());
+            stats.record_decode(packet.payload.len(), decoded);
+        }
+
+        let sizes = stats.payload_sizes();
+        assert_eq!(sizes.modal_bytes, 160);
+        assert_eq!(sizes.deviating_packets, 4);
+        assert_eq!(sizes.histogram, BTreeMap::from([(80, 1), (160, 96), (172, 3)]));
+        // 172 bytes isn't a whole number of milliseconds at 8kHz
+        assert_eq!(sizes.misaligned_packets, 3);
+        let anomaly = sizes.anomaly().expect("misaligned payloads are flagged");
+        assert!(anomaly.contains("80 bytes x1, 172 bytes x3"), "{}", anomaly);
+        assert_eq!(stats.decode_errors, 0);
+
+        let json = serde_json::to_value(&sizes).unwrap();
+        assert_eq!(json["histogram"], serde_json::json!({"80": 1, "160": 96, "172": 3}));
+        assert_eq!(serde_json::from_value::<PayloadSizes>(json).unwrap(), sizes);
+
+        // A lone short final frame is normal
+        let mut clean = PageStats::for_codec(CodecSpec::new(CodecType::G711Ulaw));
+        for seq in 0..200u16 {
+            let size = if seq == 199 { 80 } else { 160 };
+            let data = RtpPacket::build(0, seq, u32::from(seq) * 160, 1, &vec![0xFF; size], false);
+            clean.update(&RtpPacket::parse_with_time(&data, source(1), base).unwrap());
+        }
+        assert_eq!(clean.payload_sizes().deviating_packets, 1);
+        assert_eq!(clean.payload_sizes().anomaly(), None);
+    }
+
+    /// Finish ended pages' recordings in place of the finalize queue
+    fn finish_recordings(state: &mut EndpointState) {
+        for pending in state.finalizing.drain(..) {
+            pending.finish().result.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_decode_errors_counted() {
+        let spec = CodecSpec::parse("l16:8000:2").unwrap();
+        let mut stats = PageStats::for_codec(spec);
+        let mut decoder = create_decoder_for_spec(spec).unwrap();
+        for (seq, size) in [(0u16, 320), (1, 321), (2, 320), (3, 322)] {
+            let data = RtpPacket::build(11, seq, u32::from(seq) * 80, 1, &vec![0; size], false);
+            let packet = RtpPacket::parse_with_time(&data, source(1), Instant::now()).unwrap();
+            stats.update(&packet);
+            let decoded = decoder.decode(&packet.payload).ok().map(|samples| samples.len());
+            stats.record_decode(packet.payload.len(), decoded);
+        }
+        // An odd byte count can't be L16; 322 bytes decodes but splits a stereo frame
+        assert_eq!(stats.decode_errors, 1);
+        assert_eq!(stats.payload_sizes().misaligned_packets, 2);
+
+        // A decoder that drops samples it was given
+        stats.record_decode(320, Some(100));
+        assert_eq!(stats.short_decodes, 1);
+    }
+
+    #[test]
+    fn test_gap_histogram_buckets() {
+        let mut histogram = GapHistogram::default();
+        for gap_ms in [0.0, 29.9, 30.0, 59.9, 60.0, 119.9, 120.0, 299.9, 300.0, 5000.0] {
+            histogram.record(gap_ms);
+        }
+        assert_eq!(
+            histogram,
+            GapHistogram {
+                under_30ms: 2,
+                from_30_to_60ms: 2,
+                from_60_to_120ms: 2,
+                from_120_to_300ms: 2,
+                over_300ms: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_freeze_without_loss() {
+        let base = Instant::now();
+        let mut stats = PageStats::default();
+
+        // 2s of 20ms packets, but packets 50-69 are held for 400ms and then burst out
+        let mut arrival_ms = 0;
+        for seq in 0..100u16 {
+            arrival_ms = match seq {
+                50 => arrival_ms + 420,
+                51..=69 => arrival_ms,
+                _ => arrival_ms + 20,
+            };
+            if seq == 0 {
+                arrival_ms = 0;
+            }
+            stats.update(&packet_at(seq, arrival_ms, base));
+        }
+
+        assert!(stats.loss_percent().abs() < 1e-9);
+        assert!((stats.max_gap_ms - 420.0).abs() < 1.0);
+        assert_eq!(stats.gap_histogram.over_300ms, 1);
+        assert_eq!(stats.gap_histogram.under_30ms, 98);
+
+        // 400ms beyond the 20ms spacing out of a 2s page
+        let duration = 2.0;
+        assert!((stats.continuity_percent(duration) - 80.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_single_packet_page_is_continuous() {
+        let mut stats = PageStats::default();
+        stats.update(&packet_at(7, 0, Instant::now()));
+        assert!(stats.max_gap_ms.abs() < 1e-9);
+        assert_eq!(stats.gap_histogram, GapHistogram::default());
+        assert!((stats.continuity_percent(0.0) - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_ttl_summary_and_variation() {
+        let base = Instant::now();
+        let mut stats = PageStats::default();
+        let with_ttl = |seq: u16, ttl: u8| RtpPacket {
+            ttl: Some(ttl),
+            ..packet_at(seq, u64::from(seq) * 20, base)
+        };
+
+        for seq in 0..10 {
+            assert_eq!(stats.update(&with_ttl(seq, 30)), None);
+        }
+        assert_eq!(stats.ttl(), Some(TtlSummary { min: 30, max: 30, most_common: 30 }));
+        assert_eq!(format_ttl(stats.ttl()), "30");
+
+        // A path change is reported once, not on every flap back and forth
+        assert_eq!(
+            stats.update(&with_ttl(10, 28)),
+            Some(StreamDiscontinuity::TtlVaried { source: source(1), previous_ttl: 30, ttl: 28 })
+        );
+        assert_eq!(stats.update(&with_ttl(11, 30)), None);
+        assert_eq!(stats.update(&with_ttl(12, 28)), None);
+        assert_eq!(stats.ttl(), Some(TtlSummary { min: 28, max: 30, most_common: 30 }));
+        assert_eq!(format_ttl(stats.ttl()), "28-30 (mostly 30)");
+
+        // Platforms without IP_RECVTTL
+        let mut unknown = PageStats::default();
+        unknown.update(&packet_at(0, 0, base));
+        assert_eq!(unknown.ttl(), None);
+        assert_eq!(format_ttl(unknown.ttl()), "unknown");
+    }
+
+    #[test]
+    fn test_dscp_summary_and_mismatch() {
+        let base = Instant::now();
+        let ef: Dscp = "ef".parse().unwrap();
+        let mut stats = PageStats::default();
+        for seq in 0..8 {
+            let dscp = if seq < 6 { ef } else { Dscp::new(0).unwrap() };
+            stats.update(&RtpPacket { dscp: Some(dscp), ..packet_at(seq, u64::from(seq) * 20, base) });
+        }
+
+        let summary = stats.dscp(None).unwrap();
+        assert_eq!(summary.most_common, ef);
+        assert_eq!(summary.to_string(), "46 (EF) 75.0%, 0 (CS0) 25.0%");
+        assert_eq!(summary.mismatch(), None);
+
+        // Re-marked to best effort somewhere along the path
+        let summary = stats.dscp(Some(ef)).unwrap();
+        assert_eq!(summary.unexpected_packets, 2);
+        assert!(summary.mismatch().unwrap().starts_with("2 of 8 packets arrived without the expected DSCP 46 (EF)"));
+
+        // Platforms without IP_RECVTOS
+        let mut unknown = PageStats::default();
+        unknown.update(&packet_at(0, 0, base));
+        assert_eq!(unknown.dscp(Some(ef)), None);
+    }
+
+    /// An Opus packet of `frame_ms` (20, 40 or 60ms SILK) at timestamp `ts`, arriving `arrival_ms` after `base`
+    fn opus_packet(seq: u16, ts: u32, frame_ms: u8, arrival_ms: u64, base: Instant) -> RtpPacket {
+        let config = match frame_ms {
+            20 => 1,
+            40 => 2,
+            _ => 3,
+        };
+        let data = RtpPacket::build(111, seq, ts, 5, &[config << 3, 0xAA, 0x55], false);
+        RtpPacket::parse_with_time(&data, source(1), base + Duration::from_millis(arrival_ms)).unwrap()
+    }
+
+    #[test]
+    fn test_opus_dtx_is_not_loss() {
+        let base = Instant::now();
+        let mut stats = PageStats::for_codec(CodecSpec::new(CodecType::Opus));
+
+        // 1s of 20ms frames, a 2s DTX pause with contiguous sequence numbers, then 1s more
+        for i in 0..50u16 {
+            stats.update(&opus_packet(i, u32::from(i) * 960, 20, u64::from(i) * 20, base));
+        }
+        stats.update(&opus_packet(50, 50 * 960 + 96_000, 20, 3000, base));
+        assert!((stats.skipped_secs() - 2.0).abs() < 1e-9);
+        for i in 51..100u16 {
+            stats.update(&opus_packet(i, u32::from(i) * 960 + 96_000, 20, 2000 + u64::from(i) * 20, base));
+        }
+        assert!(stats.skipped_secs().abs() < 1e-9);
+        assert_eq!(stats.packets_lost, 0);
+        assert!((stats.dtx_gap_secs - 2.0).abs() < 1e-9);
+        assert!((stats.last_packet_secs() - 0.02).abs() < 1e-9);
+        assert!(stats.continuity_percent(4.0) > 99.9, "{}", stats.continuity_percent(4.0));
+
+        // Senders that advance the sequence number through DTX aren't losing packets either
+        let mut advancing = PageStats::for_codec(CodecSpec::new(CodecType::Opus));
+        advancing.update(&opus_packet(0, 0, 20, 0, base));
+        advancing.update(&opus_packet(5, 960 + 96_000, 20, 2020, base));
+        assert_eq!(advancing.packets_lost, 0);
+        assert!((advancing.dtx_gap_secs - 2.0).abs() < 1e-9);
+
+        // 40 and 60ms frames are spaced by their own length; a real loss is still loss
+        let mut varied = PageStats::for_codec(CodecSpec::new(CodecType::Opus));
+        varied.update(&opus_packet(0, 0, 40, 0, base));
+        varied.update(&opus_packet(1, 1920, 60, 40, base));
+        varied.update(&opus_packet(2, 1920 + 2880, 60, 100, base));
+        varied.update(&opus_packet(4, 1920 + 3 * 2880, 60, 220, base));
+        assert_eq!(varied.packets_lost, 1);
+        assert!((varied.skipped_secs() - 0.06).abs() < 1e-9, "the lost packet's audio");
+        assert!((varied.missing_secs() - 0.06).abs() < 1e-9);
+        assert!(varied.dtx_gap_secs.abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_g711_missing_secs() {
+        // G.711 loss isn't skipped audio, but the lost packets are still timed by their spacing
+        let base = Instant::now();
+        let mut stats = PageStats::for_codec(CodecSpec::new(CodecType::G711Ulaw));
+        for seq in [0, 1, 2, 5] {
+            stats.update(&packet_at(seq, u64::from(seq) * 20, base));
+        }
+        assert!(stats.skipped_secs().abs() < 1e-9);
+        assert!((stats.missing_secs() - 0.04).abs() < 1e-9, "{}", stats.missing_secs());
+        stats.update(&packet_at(6, 120, base));
+        assert!(stats.missing_secs().abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_opus_dtx_recorded_as_silence() {
+        use crate::codec::{AudioEncoder, OpusEncoder};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("opus.wav");
+        let options = MonitorRangeOptions { codec: Some(CodecSpec::new(CodecType::Opus)), ..options(Some(&path)) };
+        let mut state = EndpointState::new(Ipv4Addr::new(224, 0, 1, 1), 5004, Some(path.clone()));
+        let mut encoder = OpusEncoder::new_mono(24000).unwrap();
+        let tone: Vec<i16> = (0..960)
+            .map(|i| (8000.0 * (2.0 * std::f64::consts::PI * 1000.0 * f64::from(i) / 48000.0).sin()) as i16)
+            .collect();
+        let base = Instant::now();
+
+        // 1s of tone, 2s with nothing sent, 1s of tone; sequence numbers don't skip
+        for i in 0..100u16 {
+            let (ts, arrival_ms) = if i < 50 {
+                (u32::from(i) * 960, u64::from(i) * 20)
+            } else {
+                (u32::from(i) * 960 + 96_000, 2000 + u64::from(i) * 20)
+            };
+            let data = RtpPacket::build(111, i, ts, 5, &encoder.encode(&tone).unwrap(), false);
+            let packet = RtpPacket::parse_with_time(&data, source(1), base + Duration::from_millis(arrival_ms)).unwrap();
+            handle_packet(&mut state, &packet, &options).unwrap();
+        }
+        assert!(state.stats.loss_percent().abs() < 1e-9);
+        assert!((state.stats.dtx_gap_secs - 2.0).abs() < 1e-9);
+        handle_page_end(&mut state, &options).unwrap();
+        finish_recordings(&mut state);
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        let channels = usize::from(reader.spec().channels);
+        let samples: Vec<i16> = reader.into_samples().map(Result::unwrap).collect();
+        assert_eq!(samples.len(), 4 * 48000 * channels, "full length");
+        assert!(samples[48000 * channels..3 * 48000 * channels].iter().all(|&s| s == 0), "silent middle");
+        assert!(samples[3 * 48000 * channels..].iter().any(|&s| s.abs() > 1000));
+    }
+
+    /// Page counters and recording length from monitoring 50 20ms packets
+    /// under `policy`, `payload` giving each packet's payload
+    struct DamagedPage {
+        pages: u32,
+        decode_errors: u64,
+        truncated_payloads: u64,
+        /// Frames in the last page's recording
+        recorded_frames: usize,
+    }
+
+    fn monitor_damaged(codec: CodecType, policy: DecodeErrorPolicy, payload: impl Fn(u16) -> Vec<u8>) -> DamagedPage {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("damaged.wav");
+        let options = MonitorRangeOptions {
+            codec: Some(CodecSpec::new(codec)),
+            decode_error_policy: policy,
+            ..options(Some(&path))
+        };
+        let mut state = EndpointState::new(Ipv4Addr::new(224, 0, 1, 1), 5004, Some(path.clone()));
+        let ticks = CodecSpec::new(codec).rtp_clock_rate() / 50;
+        let base = Instant::now();
+        for i in 0..50u16 {
+            let data = RtpPacket::build(codec.payload_type(), i, u32::from(i) * ticks, 5, &payload(i), false);
+            let packet = RtpPacket::parse_with_time(&data, source(1), base + Duration::from_millis(u64::from(i) * 20)).unwrap();
+            handle_packet(&mut state, &packet, &options).unwrap();
+        }
+        let (decode_errors, truncated_payloads) = (state.stats.decode_errors, state.stats.truncated_payloads);
+        handle_page_end(&mut state, &options).unwrap();
+        finish_recordings(&mut state);
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        DamagedPage { pages: state.page_count, decode_errors, truncated_payloads, recorded_frames: reader.duration() as usize }
+    }
+
+    #[test]
+    fn test_truncated_payload_by_policy() {
+        // Packet 25 lost half its audio in transit
+        let payload = |i| vec![0xFF; if i == 25 { 80 } else { 160 }];
+
+        let silence = monitor_damaged(CodecType::G711Ulaw, DecodeErrorPolicy::Silence, payload);
+        assert_eq!(silence.truncated_payloads, 1);
+        assert_eq!(silence.recorded_frames, 50 * 160, "kept on the timeline");
+
+        let skip = monitor_damaged(CodecType::G711Ulaw, DecodeErrorPolicy::Skip, payload);
+        assert_eq!(skip.truncated_payloads, 1);
+        assert_eq!(skip.recorded_frames, 50 * 160 - 80);
+
+        // The page ends at the damage; packet 26 starts the next one
+        let abort = monitor_damaged(CodecType::G711Ulaw, DecodeErrorPolicy::AbortPage, payload);
+        assert_eq!(abort.pages, 2);
+        assert_eq!(abort.recorded_frames, 24 * 160);
+    }
+
+    #[test]
+    fn test_truncated_payload_detection() {
+        let base = Instant::now();
+        let mut stats = PageStats::for_codec(CodecSpec::new(CodecType::G711Ulaw));
+        for i in 0..10u32 {
+            let size = if i == 5 { 100 } else { 160 };
+            let data = RtpPacket::build(0, i as u16, i * 160, 1, &vec![0xFF; size], false);
+            stats.update(&RtpPacket::parse_with_time(&data, source(1), base + Duration::from_millis(u64::from(i) * 20)).unwrap());
+            if i == 6 {
+                assert!((stats.truncated_secs() - 0.0075).abs() < 1e-9, "{}", stats.truncated_secs());
+            } else {
+                assert!(stats.truncated_secs().abs() < 1e-9);
+            }
+        }
+        assert_eq!(stats.truncated_payloads, 1);
+
+        // A short final packet is just the end of the page
+        let data = RtpPacket::build(0, 10, 1600, 1, &[0xFF; 80], false);
+        stats.update(&RtpPacket::parse_with_time(&data, source(1), base + Duration::from_millis(200)).unwrap());
+        assert_eq!(stats.truncated_payloads, 1);
+    }
+
+    #[test]
+    fn test_undecodable_payload_by_policy() {
+        // A bare TOC byte is a 20ms CELT frame; Opus rejects an empty payload
+        let payload = |i| if i == 10 { Vec::new() } else { vec![0xF8] };
+
+        let silence = monitor_damaged(CodecType::Opus, DecodeErrorPolicy::Silence, payload);
+        let skip = monitor_damaged(CodecType::Opus, DecodeErrorPolicy::Skip, payload);
+        assert_eq!(silence.decode_errors, 1);
+        assert_eq!(skip.decode_errors, 1);
+        assert_eq!(silence.recorded_frames - skip.recorded_frames, 960, "a packet's worth concealed");
+
+        // The bad packet ends the page; the next one starts another
+        let abort = monitor_damaged(CodecType::Opus, DecodeErrorPolicy::AbortPage, payload);
+        assert_eq!(abort.pages, 2);
+        assert_eq!(abort.decode_errors, 0);
+    }
+
+    #[test]
+    fn test_missing_g722_decoder_counts_undecodable() {
+        // An ffmpeg without the G.722 decoder: the page goes on, nothing is decoded
+        let _stub = crate::codec::subprocess::stub_ffmpeg_support(crate::codec::subprocess::FfmpegSupport {
+            available: true,
+            ..Default::default()
+        });
+        for policy in [DecodeErrorPolicy::Silence, DecodeErrorPolicy::AbortPage] {
+            let page = monitor_damaged(CodecType::G722, policy, |_| vec![0; 160]);
+            assert_eq!(page.pages, 1, "{:?}", policy);
+            assert_eq!(page.decode_errors, 50, "{:?}", policy);
+            assert_eq!(page.recorded_frames, 0, "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn test_sequence_reset_rebaselines() {
+        let base = Instant::now();
+        let mut stats = PageStats::default();
+
+        for i in 0..50 {
+            assert_eq!(stats.update(&packet(30000 + i as u16, i, 1, source(1), base)), None);
+        }
+
+        // Sender reboots: same SSRC, sequence restarts near zero
+        let event = stats.update(&packet(3, 50, 1, source(1), base));
+        assert_eq!(
+            event,
+            Some(StreamDiscontinuity::SequenceReset { old_sequence: 30049, new_sequence: 3 })
+        );
+
+        for i in 51..100 {
+            assert_eq!(stats.update(&packet((i - 47) as u16, i, 1, source(1), base)), None);
+        }
+
+        assert_eq!(stats.resets, 1);
+        assert_eq!(stats.packets_received, 100);
+        assert_eq!(stats.packets_lost, 0);
+        assert!(stats.loss_percent() < 0.01);
+        assert!(stats.jitter_ms < 1.0, "jitter polluted: {}", stats.jitter_ms);
+    }
+
+    #[test]
+    fn test_jitter_uses_stream_clock_rate() {
+        // AES67: 1ms packets, 48 samples each on a 48kHz clock
+        let base = Instant::now();
+        let mut stats = PageStats::with_clock_rate(48000);
+        for i in 0..200u32 {
+            let data = RtpPacket::build(97, i as u16, i * 48, 1, &[0; 288], false);
+            let arrival = base + Duration::from_millis(u64::from(i));
+            stats.update(&RtpPacket::parse_with_time(&data, source(1), arrival).unwrap());
+        }
+        assert!(stats.jitter_ms < 0.1, "jitter: {}", stats.jitter_ms);
+
+        // The same stream measured on the 8kHz default looks badly jittered
+        let mut wrong = PageStats::default();
+        for i in 0..200u32 {
+            let data = RtpPacket::build(97, i as u16, i * 48, 1, &[0; 288], false);
+            let arrival = base + Duration::from_millis(u64::from(i));
+            wrong.update(&RtpPacket::parse_with_time(&data, source(1), arrival).unwrap());
+        }
+        assert!(wrong.jitter_ms > 1.0);
+    }
+
+    #[test]
+    fn test_g722_timestamp_clock() {
+        // 2s of 20ms G.722 packets stepping `ticks` apart
+        fn run(stats: &mut PageStats, ticks: u32) -> Vec<StreamDiscontinuity> {
+            let base = Instant::now();
+            (0..100u32)
+                .filter_map(|i| {
+                    let data = RtpPacket::build(9, i as u16, i * ticks, 1, &[0x55; 160], false);
+                    let arrival = base + Duration::from_millis(u64::from(i) * 20);
+                    stats.update(&RtpPacket::parse_with_time(&data, source(1), arrival).unwrap())
+                })
+                .collect()
+        }
+        let g722 = CodecSpec::new(CodecType::G722);
+
+        // RFC 3551: an 8kHz clock
+        let mut standard = PageStats::for_codec(g722);
+        assert!(run(&mut standard, 160).is_empty());
+        assert_eq!(standard.timestamp_clock_rate(), 8000);
+        assert!(standard.jitter_ms < 0.1, "jitter: {}", standard.jitter_ms);
+        assert!(standard.skipped_secs().abs() < 1e-9);
+
+        // A sender ticking at 16kHz is found from its first two packets
+        let mut doubled = PageStats::for_codec(g722);
+        assert_eq!(
+            run(&mut doubled, 320),
+            [StreamDiscontinuity::NonstandardTimestamps { clock_rate: 16000, expected_clock_rate: 8000 }]
+        );
+        assert_eq!(doubled.timestamp_clock_rate(), 16000);
+        assert!(doubled.jitter_ms < 0.1, "jitter: {}", doubled.jitter_ms);
+        assert!(doubled.skipped_secs().abs() < 1e-9, "no silence between packets");
+        assert!(doubled.dtx_gap_secs.abs() < 1e-9);
+        assert_eq!(doubled.packets_lost, 0);
+
+        // Or read that way from the start, without a warning
+        let mut forced = PageStats::for_codec(g722).with_g722_nonstandard_timestamps(true);
+        assert!(run(&mut forced, 320).is_empty());
+        assert!(forced.jitter_ms < 0.1, "jitter: {}", forced.jitter_ms);
+
+        // Other codecs don't look
+        let mut ulaw = PageStats::for_codec(CodecSpec::new(CodecType::G711Ulaw)).with_g722_nonstandard_timestamps(true);
+        assert!(run(&mut ulaw, 320).is_empty());
+        assert_eq!(ulaw.timestamp_clock_rate(), 8000);
+    }
+
+    #[test]
+    fn test_talk_spurts_from_marker_bits() {
+        // Two spurts of 20ms G.711 frames: the second starts 1.5s after the
+        // first one's last frame ends, with the sequence number running on
+        let base = Instant::now();
+        let mut stats = PageStats::for_codec(CodecSpec::new(CodecType::G711Ulaw));
+        let frame = |seq: u16, ts: u32, arrival_ms: u64, marker: bool| {
+            let data = RtpPacket::build(0, seq, ts, 1, &[0xFF; 160], marker);
+            RtpPacket::parse_with_time(&data, source(1), base + Duration::from_millis(arrival_ms)).unwrap()
+        };
+        for i in 0..50u16 {
+            stats.update(&frame(i, u32::from(i) * 160, u64::from(i) * 20, i == 0));
+        }
+        // 1.5s of silence is 12000 ticks beyond the usual 160
+        for i in 0..25u16 {
+            let ts = 50 * 160 + 12_000 + u32::from(i) * 160;
+            stats.update(&frame(50 + i, ts, 2500 + u64::from(i) * 20, i == 0));
+        }
+
+        assert_eq!(stats.marked_packets, 2);
+        let spurts = stats.spurts();
+        assert_eq!(spurts.len(), 2);
+        assert_eq!(spurts[0], TalkSpurt { offset_secs: 0.0, preceding_gap_ms: None });
+        assert!((spurts[1].offset_secs - 2.5).abs() < 1e-9);
+        assert!((spurts[1].preceding_gap_ms.unwrap() - 1500.0).abs() < 1e-9);
+        assert_eq!(stats.packets_lost, 0);
+        // The jump isn't taken as the stream's packet spacing
+        assert!((stats.packet_spacing_secs - 0.02).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_late_packet_is_not_a_reset() {
+        let base = Instant::now();
+        let mut stats = PageStats::default();
+
+        stats.update(&packet(100, 0, 1, source(1), base));
+        stats.update(&packet(102, 2, 1, source(1), base));
+        assert_eq!(stats.update(&packet(101, 3, 1, source(1), base)), None);
+        stats.update(&packet(103, 4, 1, source(1), base));
+
+        assert_eq!(stats.resets, 0);
+        assert_eq!(stats.packets_lost, 1);
+    }
+
+    #[test]
+    fn test_sequence_and_timestamp_wrap() {
+        // 20ms G.711 packets `interval_us` apart, with both the sequence
+        // number and the timestamp wrapping 100 packets in
+        fn run(interval_us: u64, skip: &[u32], late: Option<u32>) -> PageStats {
+            let base = Instant::now();
+            let mut stats = PageStats::for_codec(CodecSpec::new(CodecType::G711Ulaw));
+            let send = |stats: &mut PageStats, i: u32, arrival_us: u64| {
+                let (seq, ts) = ((65436 + i) as u16, (u32::MAX - 100 * 160 + 1).wrapping_add(i * 160));
+                let data = RtpPacket::build(0, seq, ts, 1, &[0xFF; 160], false);
+                stats.update(&RtpPacket::parse_with_time(&data, source(1), base + Duration::from_micros(arrival_us)).unwrap())
+            };
+            for i in (0..200).filter(|i| !skip.contains(i)) {
+                assert_eq!(send(&mut stats, i, u64::from(i) * interval_us), None);
+                if late == Some(i) {
+                    assert_eq!(send(&mut stats, i - 1, u64::from(i) * interval_us + 1), None);
+                }
+            }
+            stats
+        }
+
+        let steady = run(20_000, &[], None);
+        assert_eq!((steady.packets_received, steady.packets_lost, steady.resets), (200, 0, 0));
+        assert!(steady.jitter_ms < 1e-6, "{}", steady.jitter_ms);
+        assert!(steady.clock_drift_ms().unwrap().abs() < 1e-6);
+        assert_eq!(steady.gap_histogram.under_30ms, 199);
+
+        // A sender clock 100ppm slow falls 2us further behind every packet
+        let slow = run(20_002, &[], None);
+        assert!((slow.clock_drift_ms().unwrap() - 0.398).abs() < 1e-6, "{:?}", slow.clock_drift_ms());
+
+        // Loss right at the wrap, and a packet from before it arriving late
+        let lossy = run(20_000, &[98, 99], Some(100));
+        assert_eq!((lossy.packets_received, lossy.packets_lost, lossy.resets), (199, 2, 0));
+        assert!(lossy.clock_drift_ms().unwrap().abs() < 1e-6);
+
+        // Opus DTX spanning the timestamp wrap is silence, timed exactly
+        let base = Instant::now();
+        let mut opus = PageStats::for_codec(CodecSpec::new(CodecType::Opus));
+        let start = u32::MAX - 960 * 10 + 1;
+        for i in 0..10u16 {
+            opus.update(&opus_packet(65530u16.wrapping_add(i), start + u32::from(i) * 960, 20, u64::from(i) * 20, base));
+        }
+        opus.update(&opus_packet(4, start.wrapping_add(10 * 960 + 96_000), 20, 2200, base));
+        assert_eq!(opus.packets_lost, 0);
+        assert!((opus.dtx_gap_secs - 2.0).abs() < 1e-9, "{}", opus.dtx_gap_secs);
+        assert!(opus.clock_drift_ms().unwrap().abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_ssrc_collision() {
+        let base = Instant::now();
+        let mut stats = PageStats::default();
+
+        for i in 0..20 {
+            stats.update(&packet(i as u16, i, 0xABCD, source(1), base));
+        }
+
+        // A second sender using the same SSRC with unrelated sequence numbers
+        let event = stats.update(&packet(40000, 20, 0xABCD, source(2), base));
+        assert_eq!(
+            event,
+            Some(StreamDiscontinuity::SsrcCollision {
+                ssrc: 0xABCD,
+                original_source: source(1),
+                new_source: source(2),
+            })
+        );
+
+        // Interleaved traffic from both senders is reported once and kept out of loss
+        for i in 21..40 {
+            stats.update(&packet(i as u16, i, 0xABCD, source(1), base));
+            assert_eq!(stats.update(&packet(40000 + i as u16, i, 0xABCD, source(2), base)), None);
+        }
+
+        assert_eq!(stats.ssrc_collisions, 1);
+        assert_eq!(stats.resets, 0);
+        assert_eq!(stats.packets_lost, 1, "only the gap at index 20 on the original stream");
+    }
+
+    #[test]
+    fn test_ssrc_collision_kept_out_of_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.wav");
+        let options = options(Some(&path));
+        let mut state = EndpointState::new(Ipv4Addr::new(224, 0, 1, 1), 5004, Some(path.clone()));
+        let base = Instant::now();
+
+        // The page is silence; a second sender with the same SSRC plays a loud tone over it
+        for i in 0..100u32 {
+            handle_packet(&mut state, &packet(i as u16, i, 0xABCD, source(1), base), &options).unwrap();
+            if i >= 10 {
+                let data = RtpPacket::build(0, 30000 + i as u16, i * 160, 0xABCD, &[0x80; 160], false);
+                let arrival = base + Duration::from_millis(u64::from(i) * 20 + 5);
+                let loud = RtpPacket::parse_with_time(&data, source(2), arrival).unwrap();
+                handle_packet(&mut state, &loud, &options).unwrap();
+            }
+        }
+        assert_eq!(state.stats.ssrc_collisions, 1);
+        assert_eq!(state.stats.packets_received, 100);
+        handle_page_end(&mut state, &options).unwrap();
+        finish_recordings(&mut state);
+
+        let samples: Vec<i16> = hound::WavReader::open(&path).unwrap().samples().map(Result::unwrap).collect();
+        assert_eq!(samples.len(), 100 * 160);
+        assert!(samples.iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn test_handle_packet_reset_keeps_page() {
+        let options = options(None);
+        let mut state = EndpointState::new(Ipv4Addr::new(224, 0, 1, 1), 5004, None);
+        let base = Instant::now();
+
+        for i in 0..10 {
+            handle_packet(&mut state, &packet(50000 + i as u16, i, 7, source(1), base), &options).unwrap();
+        }
+        for i in 10..20 {
+            handle_packet(&mut state, &packet(i as u16, i, 7, source(1), base), &options).unwrap();
+        }
+
+        assert!(state.page_active);
+        assert_eq!(state.ssrc, Some(7));
+        assert_eq!(state.stats.resets, 1);
+        assert_eq!(state.stats.packets_received, 20);
+        assert_eq!(state.stats.packets_lost, 0);
+    }
+
+    #[test]
+    fn test_page_threshold_ignores_strays_and_keeps_first_packets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.wav");
+        let options = MonitorRangeOptions {
+            page_threshold: PageThreshold { min_packets: 5, min_duration: Duration::ZERO },
+            ..options(Some(&path))
+        };
+        let mut state = EndpointState::new(Ipv4Addr::new(224, 0, 1, 1), 5004, Some(path.clone()));
+        let base = Instant::now();
+
+        // Two stray packets never become a page
+        for i in 0..2 {
+            handle_packet(&mut state, &packet(i as u16, i, 9, source(2), base), &options).unwrap();
+        }
+        assert!(!state.page_active);
+        assert!(!path.exists());
+        assert!(state.pending.expire(options.page_threshold, base + Duration::from_secs(2)));
+        assert_eq!(state.pending.suppressed, 1);
+
+        // A real 3 second stream; its first packets are loud, the rest silent
+        for i in 0..150u32 {
+            let payload = if i < 3 { [0x80; 160] } else { [0xFF; 160] };
+            let data = RtpPacket::build(0, i as u16, i * 160, 10, &payload, false);
+            let arrival = base + Duration::from_millis(u64::from(i) * 20);
+            let packet = RtpPacket::parse_with_time(&data, source(1), arrival).unwrap();
+            handle_packet(&mut state, &packet, &options).unwrap();
+        }
+        assert!(state.page_active);
+        assert_eq!(state.stats.packets_received, 150);
+        handle_page_end(&mut state, &options).unwrap();
+        finish_recordings(&mut state);
+
+        let samples: Vec<i16> = hound::WavReader::open(&path).unwrap().samples().map(Result::unwrap).collect();
+        assert_eq!(samples.len(), 150 * 160);
+        assert!(samples[..3 * 160].iter().all(|&s| s.abs() > 30000));
+        assert!(samples[3 * 160..].iter().all(|&s| s == 0));
+        assert_eq!(state.pending.suppressed, 1);
+    }
+
+    #[test]
+    fn test_flood_not_held_up_by_stats_report() {
+        /// A stdout nobody is reading: every write takes 300ms
+        struct Stalled;
+        impl std::io::Write for Stalled {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                std::thread::sleep(Duration::from_millis(300));
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let options = MonitorRangeOptions { json: true, ..options(None) };
+        let settings = ReportSettings {
+            interval: options.stats_interval,
+            json: true,
+            active_only: false,
+        };
+        let mut state = EndpointState::new(Ipv4Addr::new(224, 0, 1, 1), 5004, None);
+        let board = StatsBoard::default();
+        board.set_endpoints([((state.address, state.port), state.shared.clone())]);
+        let base = Instant::now();
+        handle_packet(&mut state, &packet(0, 0, 5, source(1), base), &options).unwrap();
+        state.publish_stats();
+
+        let reporting = {
+            let board = board.clone();
+            std::thread::spawn(move || board.report(&settings, &mut Stalled).unwrap())
+        };
+        std::thread::sleep(Duration::from_millis(20));
+
+        // A burst arriving while the report is stuck on its write
+        let mut worst = Duration::ZERO;
+        for i in 1..2000u16 {
+            let started = Instant::now();
+            handle_packet(&mut state, &packet(i, u32::from(i), 5, source(1), base), &options).unwrap();
+            state.publish_stats();
+            worst = worst.max(started.elapsed());
+        }
+        assert!(worst < Duration::from_millis(50), "receive loop stalled for {:?}", worst);
+        assert_eq!(reporting.join().unwrap(), 1);
+
+        // The next report has caught up with the burst
+        state.publish_stats();
+        let mut out = Vec::new();
+        board.report(&settings, &mut out).unwrap();
+        let event: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(event["packets"], 2000);
+        assert_eq!(event["page_active"], true);
+    }
+
+    #[test]
+    fn test_forced_codec_mismatch() {
+        assert!(forced_codec_mismatch(CodecType::G722, 0));
+        assert!(forced_codec_mismatch(CodecType::Opus, 8));
+        assert!(forced_codec_mismatch(CodecType::G711Ulaw, 3), "unknown static type");
+        assert!(!forced_codec_mismatch(CodecType::G711Ulaw, 0));
+        assert!(!forced_codec_mismatch(CodecType::L16, 10));
+        assert!(!forced_codec_mismatch(CodecType::L24, 97), "dynamic types carry no codec");
+    }
+
+    /// Fraction of the signal's energy at `freq_hz`; `samples` should hold whole periods
+    fn tone_fraction(samples: &[i16], sample_rate: u32, freq_hz: f64) -> f64 {
+        let (mut re, mut im, mut total) = (0.0, 0.0, 0.0);
+        for (i, &sample) in samples.iter().enumerate() {
+            let phase = 2.0 * std::f64::consts::PI * freq_hz * i as f64 / f64::from(sample_rate);
+            let sample = f64::from(sample);
+            re += sample * phase.cos();
+            im += sample * phase.sin();
+            total += sample * sample;
+        }
+        2.0 * (re * re + im * im) / samples.len() as f64 / total
+    }
+
+    #[test]
+    fn test_forced_codec_decodes_mislabeled_stream() {
+        use crate::codec::FfmpegG722Encoder;
+
+        if !crate::codec::ffmpeg_support().g722.encode {
+            println!("Skipping test: ffmpeg not available");
+            return;
+        }
+
+        let tone: Vec<i16> = (0..16000)
+            .map(|i| (16000.0 * (2.0 * std::f64::consts::PI * 1000.0 * f64::from(i) / 16000.0).sin()) as i16)
+            .collect();
+        let frames = FfmpegG722Encoder::new().unwrap().encode_all(&tone).unwrap();
+
+        // G.722 audio sent as PT 0, the way a misconfigured device would
+        let record = |codec: Option<CodecSpec>| -> (u32, Vec<i16>) {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("page.wav");
+            let options = MonitorRangeOptions { codec, ..options(Some(&path)) };
+            let mut state = EndpointState::new(Ipv4Addr::new(224, 0, 1, 1), 5004, Some(path.clone()));
+            let base = Instant::now();
+            for (i, frame) in (0u32..).zip(&frames) {
+                let data = RtpPacket::build(0, i as u16, i * 160, 3, frame, false);
+                let arrival = base + Duration::from_millis(u64::from(i) * 20);
+                let packet = RtpPacket::parse_with_time(&data, source(1), arrival).unwrap();
+                handle_packet(&mut state, &packet, &options).unwrap();
+            }
+            handle_page_end(&mut state, &options).unwrap();
+            finish_recordings(&mut state);
+
+            let reader = hound::WavReader::open(&path).unwrap();
+            (reader.spec().sample_rate, reader.into_samples().map(Result::unwrap).collect())
+        };
+
+        // Skip the first 100ms while the decoder settles, then whole 1kHz periods
+        let steady = |samples: &[i16], sample_rate: u32| -> f64 {
+            let skip = sample_rate as usize / 10;
+            let period = sample_rate as usize / 1000;
+            let len = (samples.len() - skip) / period * period;
+            tone_fraction(&samples[skip..skip + len], sample_rate, 1000.0)
+        };
+
+        let (rate, samples) = record(Some(CodecSpec::new(CodecType::G722)));
+        assert_eq!(rate, 16000);
+        assert!(samples.len() > 8000, "{} samples", samples.len());
+        let forced = steady(&samples, rate);
+        assert!(forced > 0.9, "forced G.722 should be a clean tone ({:.2})", forced);
+
+        let (rate, samples) = record(None);
+        assert_eq!(rate, 8000, "PT 0 is decoded as G.711 without --codec");
+        let detected = steady(&samples, rate);
+        assert!(detected < 0.5, "G.722 bytes decoded as G.711 shouldn't be a tone ({:.2})", detected);
+    }
+
+    #[test]
+    fn test_l16_stereo_payload_type() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.wav");
+        let options = options(Some(&path));
+        let mut state = EndpointState::new(Ipv4Addr::new(224, 0, 1, 1), 5004, Some(path.clone()));
+
+        // A second of PT 10 in 10ms packets: 440Hz on the left, 1kHz on the right
+        let tone = |freq: f64, i: u32| (12000.0 * (2.0 * std::f64::consts::PI * freq * f64::from(i) / 44100.0).sin()) as i16;
+        let base = Instant::now();
+        for packet_index in 0..100u32 {
+            let payload: Vec<u8> = (packet_index * 441..(packet_index + 1) * 441)
+                .flat_map(|i| [tone(440.0, i), tone(1000.0, i)])
+                .flat_map(i16::to_be_bytes)
+                .collect();
+            let data = RtpPacket::build(10, packet_index as u16, packet_index * 441, 9, &payload, false);
+            let arrival = base + Duration::from_millis(u64::from(packet_index) * 10);
+            let packet = RtpPacket::parse_with_time(&data, source(1), arrival).unwrap();
+            handle_packet(&mut state, &packet, &options).unwrap();
+        }
+        // On the 44.1kHz clock, evenly spaced packets have no jitter
+        assert!(state.stats.jitter_ms < 0.01, "{}", state.stats.jitter_ms);
+        assert_eq!(state.stats.truncated_payloads, 0);
+        handle_page_end(&mut state, &options).unwrap();
+        finish_recordings(&mut state);
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!((reader.spec().sample_rate, reader.spec().channels), (44100, 2));
+        assert_eq!(reader.duration(), 44100, "one second long");
+        let samples: Vec<i16> = reader.into_samples().map(Result::unwrap).collect();
+        let left: Vec<i16> = samples.iter().step_by(2).copied().collect();
+        let right: Vec<i16> = samples.iter().skip(1).step_by(2).copied().collect();
+        assert!(tone_fraction(&left, 44100, 440.0) > 0.99);
+        assert!(tone_fraction(&left, 44100, 1000.0) < 0.01);
+        assert!(tone_fraction(&right, 44100, 1000.0) > 0.99);
+        assert!(tone_fraction(&right, 44100, 440.0) < 0.01);
+    }
+
+    /// A socket that fails its reads until rebuilt, as one stuck in an
+    /// error state does
+    struct FaultySocket {
+        inner: MulticastSocket,
+        failing: bool,
+        rebuilds: u32,
+    }
+
+    impl BatchSocket for FaultySocket {
+        fn try_recv_batch(&self, slots: &mut [BufSlot]) -> io::Result<usize> {
+            if self.failing {
+                return Err(io::Error::new(io::ErrorKind::NotConnected, "injected failure"));
+            }
+            self.inner.try_recv_batch(slots)
+        }
+
+        fn poll_recv_ready(&self, cx: &mut std::task::Context<'_>) -> std::task::Poll<io::Result<()>> {
+            self.inner.poll_recv_ready(cx)
+        }
+
+        fn destination(&self) -> SocketAddrV4 {
+            self.inner.destination()
+        }
+    }
+
+    impl Rebuild for FaultySocket {
+        async fn rebuild(&self, busy_poll_us: Option<u32>) -> Result<Self, crate::network::MulticastError> {
+            Ok(Self { inner: self.inner.rebuild(busy_poll_us).await?, failing: false, rebuilds: self.rebuilds + 1 })
+        }
+
+        fn groups(&self) -> Vec<Ipv4Addr> {
+            self.inner.groups()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watchdog_rebuilds_failing_socket() {
+        let port = 15_331;
+        let inner = MulticastSocket::new(port).await.unwrap();
+        let mut sockets = HashMap::from([(port, FaultySocket { inner, failing: true, rebuilds: 0 })]);
+        let sender = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let options = MonitorRangeOptions {
+            pattern: format!("224.0.1.1:{}", port),
+            default_port: port,
+            watchdog: Some(Duration::from_secs(30)),
+            ..options(None)
+        };
+
+        let mut watchdog = SocketWatchdog::new(Duration::from_secs(30));
+        let mut receiver = FairReceiver::new(DRAIN_BUDGET);
+        let (mut errors, mut received) = (0, 0);
+        let started = Instant::now();
+        while received < 10 && started.elapsed() < Duration::from_secs(5) {
+            sender.send_to(b"packet", (Ipv4Addr::LOCALHOST, port)).unwrap();
+            let round_started = Instant::now();
+            receiver
+                .round(&sockets, |&port, _, result| {
+                    match result {
+                        Ok(_) => received += 1,
+                        Err(e) => {
+                            errors += 1;
+                            watchdog.record_error(port, &e, Instant::now(), Utc::now());
+                        }
+                    }
+                    Ok::<(), MonitorError>(())
+                })
+                .await
+                .unwrap();
+            watchdog.record_round(sockets.keys().copied(), round_started, Instant::now());
+            restart_sockets(&mut watchdog, &mut sockets, &options).await.unwrap();
+        }
+
+        // Rebuilt once it had failed ERROR_LIMIT times, and receiving again
+        assert_eq!(errors, crate::cli::watchdog::ERROR_LIMIT);
+        assert_eq!(sockets[&port].rebuilds, 1);
+        assert!(received >= 10, "{} packets after the rebuild", received);
+        assert!(watchdog.due(Instant::now()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_run_ends_at_timeout() {
+        let run = |timeout| {
+            run_monitor_range(MonitorRangeOptions {
+                pattern: "224.0.123.42:15313".to_string(),
+                default_port: 15313,
+                timeout,
+                ..options(None)
+            })
+        };
+
+        let started = Instant::now();
+        run(Timeout::For(Duration::from_millis(200))).await.unwrap();
+        assert!((Duration::from_millis(200)..Duration::from_secs(2)).contains(&started.elapsed()));
+
+        let until = Utc::now() + chrono::Duration::milliseconds(200);
+        run(Timeout::Until(until)).await.unwrap();
+        assert!(Utc::now() >= until);
+
+        // An indefinite run is still going
+        let indefinite = tokio::time::timeout(Duration::from_millis(300), run(Timeout::Indefinite)).await;
+        assert!(indefinite.is_err());
+    }
+
+    #[test]
+    fn test_page_file_path() {
+        use chrono::TimeZone;
+
+        let dir = tempfile::tempdir().unwrap();
+        let prefix = dir.path().join("lobby.wav");
+        let started_at = Local.with_ymd_and_hms(2024, 1, 15, 10, 30, 0).unwrap().with_timezone(&Utc);
+
+        let first = page_file_path(&prefix, 3, started_at);
+        assert_eq!(first, dir.path().join("lobby_0003_20240115_103000.wav"));
+
+        // A page that starts the same second, say after a restart, doesn't overwrite it
+        std::fs::write(&first, b"").unwrap();
+        assert_eq!(page_file_path(&prefix, 3, started_at), dir.path().join("lobby_0003_20240115_103000-1.wav"));
+    }
 }
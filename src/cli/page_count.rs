@@ -0,0 +1,205 @@
+//! `--count`: ending `monitor` or `polycom-monitor` after a number of pages.
+//!
+//! Pages count as they end. Once enough have, pages still in progress on
+//! other endpoints are finished before the run ends, unless `--strict-count`
+//! is given. A page that starts while they finish is cut off when the run
+//! ends, as at a timeout. If the run times out first, it exits with
+//! [`exit_code::PAGES_NOT_MET`](crate::cli::exit_code::PAGES_NOT_MET).
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// How many pages end a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageCount {
+    pub pages: u32,
+    /// `pages` on every endpoint, rather than across them all
+    pub per_endpoint: bool,
+    /// End as soon as the count is reached, cutting off pages in progress
+    pub strict: bool,
+}
+
+impl PageCount {
+    /// Pages asked for from `endpoints` endpoints
+    pub fn requested(self, endpoints: usize) -> u32 {
+        if self.per_endpoint {
+            self.pages.saturating_mul(u32::try_from(endpoints).unwrap_or(u32::MAX))
+        } else {
+            self.pages
+        }
+    }
+}
+
+/// Where a counted run has got to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountProgress {
+    /// Fewer pages than asked for have ended
+    Counting,
+    /// The count is reached; waiting for pages in progress to end
+    Finishing,
+    /// The run can end
+    Done,
+}
+
+/// Pages a `--count` run received against those asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct CountSummary {
+    /// Pages that ended before the run did; pages it cut off aren't counted
+    pub pages_captured: u32,
+    pub pages_requested: u32,
+    /// `pages_requested` is the count times the endpoints monitored
+    pub per_endpoint: bool,
+    /// Whether the count was reached before the run ended
+    pub met: bool,
+}
+
+/// Counts each endpoint's ended pages against a [`PageCount`]
+#[derive(Debug)]
+pub struct PageCounter<K> {
+    count: PageCount,
+    /// Pages ended on each endpoint, including ones no longer monitored
+    ended: HashMap<K, u32>,
+    /// Endpoints monitored at the last update
+    endpoints: usize,
+    reached: bool,
+    /// Endpoints with a page in progress when the count was reached, and
+    /// the pages they had ended by then
+    finishing: HashMap<K, u32>,
+}
+
+impl<K: Copy + Eq + Hash> PageCounter<K> {
+    pub fn new(count: PageCount) -> Self {
+        Self {
+            count,
+            ended: HashMap::new(),
+            endpoints: 0,
+            reached: false,
+            finishing: HashMap::new(),
+        }
+    }
+
+    /// Take each monitored endpoint's pages ended so far and whether it has
+    /// one in progress
+    pub fn update(&mut self, endpoints: impl IntoIterator<Item = (K, u32, bool)>) -> CountProgress {
+        let endpoints: Vec<(K, u32, bool)> = endpoints.into_iter().collect();
+        self.endpoints = endpoints.len();
+        for &(key, ended, _) in &endpoints {
+            self.ended.insert(key, ended);
+        }
+
+        if !self.reached {
+            self.reached = if self.count.per_endpoint {
+                !endpoints.is_empty() && endpoints.iter().all(|&(_, ended, _)| ended >= self.count.pages)
+            } else {
+                self.captured() >= self.count.pages
+            };
+            if !self.reached {
+                return CountProgress::Counting;
+            }
+            if !self.count.strict {
+                self.finishing = endpoints.iter().filter(|&&(_, _, active)| active).map(|&(key, ended, _)| (key, ended)).collect();
+            }
+        }
+
+        // A page is finished once its endpoint has ended another, or has gone
+        self.finishing
+            .retain(|key, at| endpoints.iter().any(|&(monitored, ended, _)| monitored == *key && ended <= *at));
+        if self.finishing.is_empty() {
+            CountProgress::Done
+        } else {
+            CountProgress::Finishing
+        }
+    }
+
+    /// Pages ended so far, across all endpoints
+    pub fn captured(&self) -> u32 {
+        self.ended.values().sum()
+    }
+
+    /// Pages asked for, across the endpoints monitored at the last update
+    pub fn requested(&self) -> u32 {
+        self.count.requested(self.endpoints)
+    }
+
+    /// Whether the count was reached
+    pub fn met(&self) -> bool {
+        self.reached
+    }
+
+    pub fn summary(&self) -> CountSummary {
+        CountSummary {
+            pages_captured: self.captured(),
+            pages_requested: self.requested(),
+            per_endpoint: self.count.per_endpoint,
+            met: self.reached,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter(pages: u32, per_endpoint: bool, strict: bool) -> PageCounter<u8> {
+        PageCounter::new(PageCount { pages, per_endpoint, strict })
+    }
+
+    #[test]
+    fn test_count_across_endpoints() {
+        let mut counter = counter(2, false, false);
+        assert_eq!(counter.update([(1, 0, true), (2, 0, false)]), CountProgress::Counting);
+        assert_eq!(counter.update([(1, 1, false), (2, 0, false)]), CountProgress::Counting);
+        assert!(!counter.met());
+
+        // The second page ends while endpoint 2 is mid-page: finish it
+        assert_eq!(counter.update([(1, 2, false), (2, 0, true)]), CountProgress::Finishing);
+        assert!(counter.met());
+        // A page starting on endpoint 1 meanwhile isn't waited for
+        assert_eq!(counter.update([(1, 2, true), (2, 0, true)]), CountProgress::Finishing);
+        assert_eq!(counter.update([(1, 2, true), (2, 1, false)]), CountProgress::Done);
+        assert_eq!((counter.captured(), counter.requested()), (3, 2));
+    }
+
+    #[test]
+    fn test_count_finishing_page_restarts() {
+        // Endpoint 2 ends its page and starts another between updates
+        let mut counter = counter(1, false, false);
+        assert_eq!(counter.update([(1, 1, false), (2, 0, true)]), CountProgress::Finishing);
+        assert_eq!(counter.update([(1, 1, false), (2, 1, true)]), CountProgress::Done);
+    }
+
+    #[test]
+    fn test_strict_count() {
+        let mut counter = counter(1, false, true);
+        assert_eq!(counter.update([(1, 0, true), (2, 0, true)]), CountProgress::Counting);
+        assert_eq!(counter.update([(1, 1, false), (2, 0, true)]), CountProgress::Done);
+    }
+
+    #[test]
+    fn test_count_per_endpoint() {
+        let mut counter = counter(2, true, false);
+        assert_eq!(counter.update([(1, 3, false), (2, 1, false)]), CountProgress::Counting);
+        assert_eq!(counter.requested(), 4);
+        assert_eq!(counter.update([(1, 3, false), (2, 2, false)]), CountProgress::Done);
+        assert_eq!(counter.captured(), 5);
+
+        // No endpoints is never enough
+        let mut counter = self::counter(1, true, false);
+        assert_eq!(counter.update([]), CountProgress::Counting);
+    }
+
+    #[test]
+    fn test_count_removed_endpoint() {
+        // Pages from an endpoint that has since been removed still count
+        let mut counter = counter(2, false, false);
+        assert_eq!(counter.update([(1, 1, false), (2, 0, true)]), CountProgress::Counting);
+        assert_eq!(counter.update([(2, 1, false)]), CountProgress::Done);
+
+        // And one removed mid-page isn't waited for
+        let mut counter = self::counter(1, false, false);
+        assert_eq!(counter.update([(1, 1, false), (2, 0, true)]), CountProgress::Finishing);
+        assert_eq!(counter.update([(1, 1, false)]), CountProgress::Done);
+    }
+}
@@ -0,0 +1,168 @@
+//! Holding back new streams until they look like a page.
+//!
+//! A switch flooding during a topology change can leak one or two RTP
+//! packets from another VLAN. Without a threshold each of those starts a
+//! page, with its own decoder, recording and a near-zero duration entry in
+//! the summary. With one, the first packets of a new stream are held as a
+//! pending burst and only become a page once there are enough of them. The
+//! held packets are then replayed, so stats and recordings still start with
+//! the very first packet. A burst that doesn't get there is dropped.
+
+use crate::network::RtpPacket;
+use crate::utils::range_parser::EndpointOptions;
+use std::mem;
+use std::time::{Duration, Instant};
+
+/// How long a pending burst may take to reach the threshold, on top of `min_duration`
+pub const PENDING_WINDOW: Duration = Duration::from_secs(1);
+
+/// What a new stream must reach before it counts as a page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageThreshold {
+    /// Packets received from the stream
+    pub min_packets: u32,
+    /// Time between the first and the latest packet
+    pub min_duration: Duration,
+}
+
+impl Default for PageThreshold {
+    /// Every packet starts a page
+    fn default() -> Self {
+        Self {
+            min_packets: 1,
+            min_duration: Duration::ZERO,
+        }
+    }
+}
+
+impl PageThreshold {
+    /// How long after its first packet a burst is given up on
+    pub fn window(&self) -> Duration {
+        self.min_duration + PENDING_WINDOW
+    }
+
+    /// This threshold with any given in an endpoint's options in its place
+    pub fn with_overrides(self, options: &EndpointOptions) -> Self {
+        Self {
+            min_packets: options.min_packets.unwrap_or(self.min_packets),
+            min_duration: options.min_duration().unwrap_or(self.min_duration),
+        }
+    }
+
+    fn is_met(&self, packets: usize, span: Duration) -> bool {
+        packets >= self.min_packets as usize && span >= self.min_duration
+    }
+}
+
+/// Packets of a new stream that hasn't reached the page threshold yet
+#[derive(Debug, Default)]
+pub struct PendingPage {
+    packets: Vec<RtpPacket>,
+    /// Bursts dropped for not reaching the threshold
+    pub suppressed: u64,
+}
+
+impl PendingPage {
+    /// Hold `packet`. Returns every held packet, oldest first, once the
+    /// stream has reached the threshold. A packet from a different SSRC
+    /// drops the burst held so far and starts a new one.
+    pub fn offer(&mut self, packet: &RtpPacket, threshold: PageThreshold) -> Option<Vec<RtpPacket>> {
+        if self.packets.first().is_some_and(|first| first.header.ssrc != packet.header.ssrc) {
+            self.discard();
+        }
+        self.packets.push(packet.clone());
+
+        let span = packet.received_at.saturating_duration_since(self.packets[0].received_at);
+        threshold
+            .is_met(self.packets.len(), span)
+            .then(|| mem::take(&mut self.packets))
+    }
+
+    /// Drop a burst that has had its window to reach the threshold.
+    /// Returns whether one was dropped.
+    pub fn expire(&mut self, threshold: PageThreshold, now: Instant) -> bool {
+        let expired = self
+            .packets
+            .first()
+            .is_some_and(|first| now.saturating_duration_since(first.received_at) >= threshold.window());
+        if expired {
+            self.discard();
+        }
+        expired
+    }
+
+    /// Packets currently held, for `--memory-report`
+    pub fn held(&self) -> usize {
+        self.packets.len()
+    }
+
+    /// Drop whatever is held, e.g. at shutdown
+    pub fn discard(&mut self) {
+        if !self.packets.is_empty() {
+            self.packets.clear();
+            self.suppressed += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    fn packet(seq: u16, ssrc: u32, arrival_ms: u64, base: Instant) -> RtpPacket {
+        let data = RtpPacket::build(0, seq, u32::from(seq) * 160, ssrc, &[0xFF; 160], false);
+        let source: SocketAddr = "192.168.1.1:5004".parse().unwrap();
+        RtpPacket::parse_with_time(&data, source, base + Duration::from_millis(arrival_ms)).unwrap()
+    }
+
+    #[test]
+    fn test_default_threshold_starts_immediately() {
+        let base = Instant::now();
+        let mut pending = PendingPage::default();
+        for seq in 0..3u16 {
+            let held = pending.offer(&packet(seq, 7, 0, base), PageThreshold::default()).unwrap();
+            assert_eq!(held.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_threshold_on_packets_and_duration() {
+        let base = Instant::now();
+        let threshold = PageThreshold {
+            min_packets: 5,
+            min_duration: Duration::from_millis(100),
+        };
+        let mut pending = PendingPage::default();
+
+        // Five packets arrive within 80ms; the sixth makes it 100ms
+        for seq in 0..5u16 {
+            assert!(pending.offer(&packet(seq, 7, u64::from(seq) * 20, base), threshold).is_none());
+        }
+        let held = pending.offer(&packet(5, 7, 100, base), threshold).unwrap();
+        let sequences: Vec<u16> = held.iter().map(|p| p.header.sequence_number).collect();
+        assert_eq!(sequences, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(pending.suppressed, 0);
+    }
+
+    #[test]
+    fn test_stray_bursts_are_suppressed() {
+        let base = Instant::now();
+        let threshold = PageThreshold {
+            min_packets: 5,
+            min_duration: Duration::ZERO,
+        };
+        let mut pending = PendingPage::default();
+
+        // Two strays, then a different stream takes over
+        assert!(pending.offer(&packet(1, 7, 0, base), threshold).is_none());
+        assert!(pending.offer(&packet(2, 7, 20, base), threshold).is_none());
+        assert!(pending.offer(&packet(900, 8, 40, base), threshold).is_none());
+        assert_eq!(pending.suppressed, 1);
+
+        assert!(!pending.expire(threshold, base + Duration::from_millis(500)));
+        assert!(pending.expire(threshold, base + Duration::from_millis(40) + PENDING_WINDOW));
+        pending.discard();
+        assert_eq!(pending.suppressed, 2, "nothing left to discard");
+    }
+}
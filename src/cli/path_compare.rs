@@ -0,0 +1,590 @@
+//! Redundant transmit path comparison (`test --compare-endpoints`).
+//!
+//! Sites with redundant paging controllers send every page to two groups.
+//! A wiring or configuration mistake can leave one path quieter than the
+//! other, or with its polarity inverted, which cancels the audio where
+//! speakers on both paths overlap. At the end of a test, each page on the
+//! first endpoint is paired with the page on the second that overlaps it
+//! most in time, and the two recordings are compared:
+//!
+//! - they are aligned by cross-correlation, searched within
+//!   [`MAX_ALIGN_MS`] of the offset between the pages' start times;
+//! - the correlation at that alignment gives the polarity: strongly
+//!   negative means one path is inverted;
+//! - the level difference is the RMS of the second path over the first,
+//!   over the samples both recordings cover;
+//! - the spectral difference is the largest gap between the paths' octave
+//!   band levels once the level difference is taken out, so an EQ or
+//!   codec mismatch shows up even when the overall level matches.
+//!
+//! Only the first [`MAX_COMPARE_SECS`] of each pair are compared, which is
+//! plenty for the checks and keeps a long page from costing a large FFT.
+
+use crate::cli::audio_analyzer::AudioAnalyzer;
+use crate::cli::test::PageSummary;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::SocketAddrV4;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Alignment searched either side of the offset between page start times
+pub const MAX_ALIGN_MS: f64 = 250.0;
+
+/// Longest stretch of each pair that is compared
+pub const MAX_COMPARE_SECS: f64 = 30.0;
+
+/// Correlation at or below this is a polarity inversion
+const INVERTED_CORRELATION: f64 = -0.5;
+
+/// Correlation weaker than this means the paths aren't carrying the same audio
+const MATCHING_CORRELATION: f64 = 0.5;
+
+/// Level difference thresholds, in dB either way
+const LEVEL_WARN_DB: f64 = 1.0;
+const LEVEL_FAIL_DB: f64 = 3.0;
+
+/// Spectral difference thresholds, in dB
+const SPECTRAL_WARN_DB: f64 = 3.0;
+const SPECTRAL_FAIL_DB: f64 = 6.0;
+
+/// Octave bands compared, by lower edge in Hz; bands above Nyquist are left out
+const BAND_EDGES_HZ: [f64; 6] = [125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0];
+
+/// Bands this far below the loudest one are too quiet to compare
+const BAND_FLOOR_DB: f64 = 40.0;
+
+/// One side of a pair, with the name it is reported under
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabeledEndpoint {
+    pub label: String,
+    pub endpoint: SocketAddrV4,
+}
+
+/// Two endpoints expected to carry the same pages:
+/// `A=224.0.1.1:5004,B=224.0.1.2:5004`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointPair {
+    pub a: LabeledEndpoint,
+    pub b: LabeledEndpoint,
+}
+
+impl FromStr for LabeledEndpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (label, endpoint) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected LABEL=ADDR:PORT, got '{}'", s))?;
+        let label = label.trim();
+        if label.is_empty() {
+            return Err(format!("'{}' has no label before the '='", s));
+        }
+        let endpoint: SocketAddrV4 = endpoint
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{}' is not an ADDR:PORT", endpoint.trim()))?;
+        if !endpoint.ip().is_multicast() {
+            return Err(format!("{} is not a multicast address", endpoint.ip()));
+        }
+        Ok(Self { label: label.to_string(), endpoint })
+    }
+}
+
+impl FromStr for EndpointPair {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let sides: Vec<&str> = s.split(',').collect();
+        let [a, b] = sides[..] else {
+            return Err(format!("expected two endpoints, LABEL=ADDR:PORT,LABEL=ADDR:PORT, got '{}'", s));
+        };
+        let (a, b): (LabeledEndpoint, LabeledEndpoint) = (a.parse()?, b.parse()?);
+        if a.label == b.label {
+            return Err(format!("both endpoints are labelled '{}'", a.label));
+        }
+        if a.endpoint == b.endpoint {
+            return Err(format!("{} is compared with itself", a.endpoint));
+        }
+        Ok(Self { a, b })
+    }
+}
+
+impl fmt::Display for EndpointPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={},{}={}", self.a.label, self.a.endpoint, self.b.label, self.b.endpoint)
+    }
+}
+
+/// How a pair of pages fared; the worst of its checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Verdict {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Verdict {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Pass => "PASS",
+            Self::Warn => "WARN",
+            Self::Fail => "FAIL",
+        }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What the recordings of one page on both paths showed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathMeasurement {
+    /// Where the second recording starts in the first's audio; negative
+    /// when its audio arrived later than the page start times account for
+    pub offset_ms: f64,
+    pub compared_secs: f64,
+    /// Second path's RMS over the first's; negative when it is quieter
+    pub level_difference_db: f64,
+    /// Normalized correlation at the best alignment, -1 to 1
+    pub correlation: f64,
+    /// Largest octave-band difference once the level difference is taken out
+    pub spectral_difference_db: f64,
+}
+
+/// One page seen on both paths, in summary.json's `path_comparisons`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PathComparison {
+    pub a_label: String,
+    pub a_endpoint: String,
+    pub a_page: u32,
+    pub b_label: String,
+    pub b_endpoint: String,
+    /// Absent if no page on the second path overlapped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub b_page: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset_ms: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compared_secs: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level_difference_db: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation: Option<f64>,
+    pub polarity_inverted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spectral_difference_db: Option<f64>,
+    pub verdict: Verdict,
+    /// Why the verdict isn't PASS
+    pub findings: Vec<String>,
+}
+
+impl PathComparison {
+    fn new(pair: &EndpointPair, a_page: u32) -> Self {
+        Self {
+            a_label: pair.a.label.clone(),
+            a_endpoint: pair.a.endpoint.to_string(),
+            a_page,
+            b_label: pair.b.label.clone(),
+            b_endpoint: pair.b.endpoint.to_string(),
+            b_page: None,
+            offset_ms: None,
+            compared_secs: None,
+            level_difference_db: None,
+            correlation: None,
+            polarity_inverted: false,
+            spectral_difference_db: None,
+            verdict: Verdict::Pass,
+            findings: Vec::new(),
+        }
+    }
+
+    fn flag(&mut self, verdict: Verdict, finding: String) {
+        self.verdict = self.verdict.max(verdict);
+        self.findings.push(finding);
+    }
+
+    /// Grade a measurement against the thresholds
+    fn assess(&mut self, measured: &PathMeasurement) {
+        self.offset_ms = Some(measured.offset_ms);
+        self.compared_secs = Some(measured.compared_secs);
+        self.level_difference_db = Some(measured.level_difference_db);
+        self.correlation = Some(measured.correlation);
+        self.spectral_difference_db = Some(measured.spectral_difference_db);
+
+        if measured.correlation <= INVERTED_CORRELATION {
+            self.polarity_inverted = true;
+            self.flag(
+                Verdict::Fail,
+                format!("{} is polarity-inverted against {} (correlation {:.2})", self.b_label, self.a_label, measured.correlation),
+            );
+        } else if measured.correlation.abs() < MATCHING_CORRELATION {
+            self.flag(
+                Verdict::Warn,
+                format!("{} and {} don't carry matching audio (correlation {:.2})", self.a_label, self.b_label, measured.correlation),
+            );
+        }
+
+        let level = measured.level_difference_db.abs();
+        if level > LEVEL_WARN_DB {
+            let verdict = if level > LEVEL_FAIL_DB { Verdict::Fail } else { Verdict::Warn };
+            let direction = if measured.level_difference_db < 0.0 { "quieter" } else { "louder" };
+            self.flag(verdict, format!("{} is {:.1} dB {} than {}", self.b_label, level, direction, self.a_label));
+        }
+
+        if measured.spectral_difference_db > SPECTRAL_WARN_DB {
+            let verdict = if measured.spectral_difference_db > SPECTRAL_FAIL_DB { Verdict::Fail } else { Verdict::Warn };
+            self.flag(verdict, format!("spectra differ by up to {:.1} dB", measured.spectral_difference_db));
+        }
+    }
+}
+
+/// Compare every page on each pair's first endpoint with the page on its
+/// second that overlaps it most. Recordings are found in `directory`
+pub fn compare_paths(directory: &Path, pairs: &[EndpointPair], pages: &[PageSummary]) -> Vec<PathComparison> {
+    let mut comparisons = Vec::new();
+    for pair in pairs {
+        let (a_endpoint, b_endpoint) = (pair.a.endpoint.to_string(), pair.b.endpoint.to_string());
+        for a_page in pages.iter().filter(|page| page.endpoint == a_endpoint) {
+            let mut comparison = PathComparison::new(pair, a_page.page_number);
+            let b_page = pages
+                .iter()
+                .filter(|page| page.endpoint == b_endpoint)
+                .map(|page| (page, overlap_secs(a_page, page)))
+                .filter(|(_, overlap)| *overlap > 0.0)
+                .max_by(|(_, x), (_, y)| x.total_cmp(y))
+                .map(|(page, _)| page);
+            let Some(b_page) = b_page else {
+                comparison.flag(Verdict::Fail, format!("no page on {} overlapped", pair.b.label));
+                comparisons.push(comparison);
+                continue;
+            };
+            comparison.b_page = Some(b_page.page_number);
+
+            let expected_offset_ms = (b_page.start_time - a_page.start_time).num_milliseconds() as f64;
+            match compare_recordings(
+                &directory.join(&a_page.recording_file),
+                &directory.join(&b_page.recording_file),
+                expected_offset_ms,
+            ) {
+                Ok(measured) => comparison.assess(&measured),
+                Err(e) => comparison.flag(Verdict::Warn, format!("recordings not compared: {}", e)),
+            }
+            comparisons.push(comparison);
+        }
+    }
+    comparisons
+}
+
+/// Seconds two pages were both in progress
+fn overlap_secs(a: &PageSummary, b: &PageSummary) -> f64 {
+    let start = a.start_time.max(b.start_time);
+    let end = a.end_time.min(b.end_time);
+    (end - start).num_milliseconds() as f64 / 1000.0
+}
+
+/// Compare two recordings of the same page; the second is expected to start
+/// about `expected_offset_ms` after the first
+pub fn compare_recordings(a: &Path, b: &Path, expected_offset_ms: f64) -> Result<PathMeasurement, String> {
+    let (a, a_rate) = read_mono(a)?;
+    let (b, b_rate) = read_mono(b)?;
+    if a_rate != b_rate {
+        return Err(format!("sample rates differ ({}Hz and {}Hz)", a_rate, b_rate));
+    }
+    measure(&a, &b, a_rate, expected_offset_ms)
+}
+
+/// Up to [`MAX_COMPARE_SECS`] plus the alignment margin of a recording,
+/// channels averaged
+fn read_mono(path: &Path) -> Result<(Vec<f64>, u32), String> {
+    let mut reader = hound::WavReader::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let spec = reader.spec();
+    let channels = usize::from(spec.channels.max(1));
+    let limit = ((MAX_COMPARE_SECS + 2.0 * MAX_ALIGN_MS / 1000.0) * f64::from(spec.sample_rate)) as usize * channels;
+    let samples: Vec<i16> = reader
+        .samples::<i16>()
+        .take(limit)
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mono = samples
+        .chunks(channels)
+        .map(|frame| frame.iter().map(|&s| f64::from(s)).sum::<f64>() / channels as f64)
+        .collect();
+    Ok((mono, spec.sample_rate))
+}
+
+/// Align `b` to `a` and measure the difference between them
+pub fn measure(a: &[f64], b: &[f64], sample_rate: u32, expected_offset_ms: f64) -> Result<PathMeasurement, String> {
+    let rate = f64::from(sample_rate);
+    let expected = (expected_offset_ms * rate / 1000.0).round() as i64;
+    let margin = (MAX_ALIGN_MS * rate / 1000.0).round() as i64;
+    let lag = best_lag(a, b, expected - margin..=expected + margin).ok_or("the recordings don't overlap")?;
+
+    // Sample i of `a` lines up with sample i - lag of `b`
+    let start = lag.max(0) as usize;
+    let end = a.len().min((b.len() as i64 + lag).max(0) as usize).min(start + (MAX_COMPARE_SECS * rate) as usize);
+    if end <= start {
+        return Err("the recordings don't overlap".to_string());
+    }
+    let a = &a[start..end];
+    let b = &b[(start as i64 - lag) as usize..(end as i64 - lag) as usize];
+
+    let energy = |x: &[f64]| x.iter().map(|s| s * s).sum::<f64>();
+    let (a_energy, b_energy) = (energy(a), energy(b));
+    if a_energy == 0.0 || b_energy == 0.0 {
+        return Err("one of the recordings is silent".to_string());
+    }
+    let cross: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let level_difference_db = 10.0 * (b_energy / a_energy).log10();
+
+    Ok(PathMeasurement {
+        offset_ms: lag as f64 * 1000.0 / rate,
+        compared_secs: a.len() as f64 / rate,
+        level_difference_db,
+        correlation: cross / (a_energy * b_energy).sqrt(),
+        spectral_difference_db: spectral_difference(a, b, sample_rate, level_difference_db),
+    })
+}
+
+/// The lag in `lags` with the strongest correlation either way, found with
+/// an FFT so long recordings stay cheap. A lag of `n` pairs sample `i + n`
+/// of `a` with sample `i` of `b`
+fn best_lag(a: &[f64], b: &[f64], lags: std::ops::RangeInclusive<i64>) -> Option<i64> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+    let size = (a.len() + b.len()).next_power_of_two();
+    let mut planner = FftPlanner::<f64>::new();
+    let forward = planner.plan_fft_forward(size);
+    let inverse = planner.plan_fft_inverse(size);
+
+    let spectrum = |x: &[f64]| {
+        let mut buffer: Vec<Complex<f64>> = x.iter().map(|&s| Complex::new(s, 0.0)).collect();
+        buffer.resize(size, Complex::new(0.0, 0.0));
+        forward.process(&mut buffer);
+        buffer
+    };
+    let (a_spectrum, b_spectrum) = (spectrum(a), spectrum(b));
+    let mut correlation: Vec<Complex<f64>> = a_spectrum.iter().zip(&b_spectrum).map(|(x, y)| x * y.conj()).collect();
+    inverse.process(&mut correlation);
+
+    // Circular correlation: negative lags wrap to the end
+    let (low, high) = (*lags.start().max(&-(b.len() as i64 - 1)), *lags.end().min(&(a.len() as i64 - 1)));
+    (low..=high).max_by(|&x, &y| {
+        let at = |lag: i64| correlation[lag.rem_euclid(size as i64) as usize].re.abs();
+        at(x).total_cmp(&at(y))
+    })
+}
+
+/// Largest octave-band level difference between `a` and `b`, after
+/// `level_difference_db` is taken out
+fn spectral_difference(a: &[f64], b: &[f64], sample_rate: u32, level_difference_db: f64) -> f64 {
+    let (Some(a_bands), Some(b_bands)) = (band_levels(a, sample_rate), band_levels(b, sample_rate)) else {
+        return 0.0;
+    };
+    let loudest = a_bands.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    a_bands
+        .iter()
+        .zip(&b_bands)
+        .filter(|(a, _)| **a > loudest - BAND_FLOOR_DB)
+        .map(|(a, b)| (b - a - level_difference_db).abs())
+        .fold(0.0, f64::max)
+}
+
+/// Octave band levels in dB, from the analyzer's averaged spectrum
+fn band_levels(samples: &[f64], sample_rate: u32) -> Option<Vec<f64>> {
+    let mut analyzer = AudioAnalyzer::new(sample_rate);
+    let pcm: Vec<i16> = samples.iter().map(|&s| s.round().clamp(-32768.0, 32767.0) as i16).collect();
+    for frame in pcm.chunks(sample_rate as usize / 50) {
+        analyzer.analyze(frame);
+    }
+    let spectrum = analyzer.take_spectrum()?;
+    let nyquist = f64::from(sample_rate) / 2.0;
+    Some(
+        BAND_EDGES_HZ
+            .iter()
+            .filter(|&&low| low * 2.0 <= nyquist)
+            .map(|&low| {
+                let power: f64 = (0..spectrum.magnitudes.len())
+                    .filter(|&bin| (low..low * 2.0).contains(&spectrum.bin_frequency(bin)))
+                    .map(|bin| f64::from(spectrum.magnitudes[bin]).powi(2))
+                    .sum();
+                10.0 * power.max(1e-12).log10()
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    const RATE: u32 = 8000;
+
+    /// Two seconds of speech-like noise: a few tones with a wandering envelope
+    fn program() -> Vec<f64> {
+        let mut seed = 12345u32;
+        (0..2 * RATE)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let noise = f64::from(seed >> 16) / 32768.0 - 1.0;
+                let t = f64::from(i) / f64::from(RATE);
+                let envelope = 0.6 + 0.4 * (2.0 * std::f64::consts::PI * 3.0 * t).sin();
+                let tones: f64 = [300.0, 700.0, 1500.0].iter().map(|f| (2.0 * std::f64::consts::PI * f * t).sin()).sum();
+                4000.0 * envelope * (tones + noise)
+            })
+            .collect()
+    }
+
+    fn write_wav(path: &Path, samples: &[f64]) {
+        let spec = hound::WavSpec { channels: 1, sample_rate: RATE, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for &s in samples {
+            writer.write_sample(s.round() as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    fn page(number: u32, endpoint: &str, start: &str, end: &str, file: &str) -> PageSummary {
+        serde_json::from_value(serde_json::json!({
+            "page_number": number,
+            "endpoint": endpoint,
+            "start_time": start.parse::<DateTime<Utc>>().unwrap(),
+            "end_time": end.parse::<DateTime<Utc>>().unwrap(),
+            "duration_secs": 2.0,
+            "recording_file": file,
+            "network": {
+                "packets_received": 100,
+                "bytes_received": 32_000,
+                "packets_lost": 0,
+                "loss_percent": 0.0,
+                "jitter_ms": 0.0
+            },
+            "audio": {
+                "peak_rms_db": -12.0,
+                "max_peak_db": -6.0,
+                "dominant_freq_hz": 700.0,
+                "total_glitches": 0,
+                "total_clipped": 0,
+                "clipping_percent": 0.0,
+                "avg_zero_crossing_rate": 2000.0
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_pair() {
+        let pair: EndpointPair = "A=224.0.1.1:5004,B=224.0.1.2:5004".parse().unwrap();
+        assert_eq!(pair.a.label, "A");
+        assert_eq!(pair.b.endpoint, "224.0.1.2:5004".parse().unwrap());
+        assert_eq!(pair.to_string(), "A=224.0.1.1:5004,B=224.0.1.2:5004");
+        let pair: EndpointPair = "primary = 239.1.1.1:6000, backup=239.1.1.2:6000".parse().unwrap();
+        assert_eq!((pair.a.label.as_str(), pair.b.label.as_str()), ("primary", "backup"));
+
+        for (bad, error) in [
+            ("A=224.0.1.1:5004", "expected two endpoints"),
+            ("A=224.0.1.1:5004,B=224.0.1.2", "is not an ADDR:PORT"),
+            ("A=224.0.1.1:5004,224.0.1.2:5004", "expected LABEL=ADDR:PORT"),
+            ("A=224.0.1.1:5004,A=224.0.1.2:5004", "both endpoints are labelled 'A'"),
+            ("A=224.0.1.1:5004,B=224.0.1.1:5004", "compared with itself"),
+            ("A=10.0.0.1:5004,B=224.0.1.2:5004", "not a multicast address"),
+        ] {
+            let err = bad.parse::<EndpointPair>().unwrap_err();
+            assert!(err.contains(error), "{}: {}", bad, err);
+        }
+    }
+
+    #[test]
+    fn test_inverted_quieter_path() {
+        let a = program();
+        // B's audio arrives 12.5ms into its recording, 6dB down and inverted
+        let delay = 100;
+        let b: Vec<f64> = std::iter::repeat_n(0.0, delay).chain(a.iter().map(|s| -0.5 * s)).collect();
+
+        let measured = measure(&a, &b, RATE, 0.0).unwrap();
+        assert!((measured.offset_ms + 12.5).abs() < 1e-9, "{:?}", measured);
+        assert!((measured.level_difference_db + 6.02).abs() < 0.05, "{:?}", measured);
+        assert!(measured.correlation < -0.99, "{:?}", measured);
+        assert!(measured.spectral_difference_db < 0.5, "{:?}", measured);
+
+        let pair: EndpointPair = "A=224.0.1.1:5004,B=224.0.1.2:5004".parse().unwrap();
+        let mut comparison = PathComparison::new(&pair, 1);
+        comparison.assess(&measured);
+        assert!(comparison.polarity_inverted);
+        assert_eq!(comparison.verdict, Verdict::Fail);
+        assert_eq!(comparison.findings.len(), 2, "{:?}", comparison.findings);
+        assert!(comparison.findings[1].starts_with("B is 6.0 dB quieter than A"), "{:?}", comparison.findings);
+
+        // The same audio at the same level passes
+        let mut matched = PathComparison::new(&pair, 1);
+        matched.assess(&measure(&a, &a, RATE, 0.0).unwrap());
+        assert_eq!(matched.verdict, Verdict::Pass);
+        assert!(matched.findings.is_empty());
+    }
+
+    #[test]
+    fn test_spectral_and_unrelated_paths() {
+        let a = program();
+        // First difference: treble up, bass down; same audio otherwise
+        let tilted: Vec<f64> = a.iter().scan(0.0, |previous, &s| {
+            let out = s - *previous;
+            *previous = s;
+            Some(out)
+        }).collect();
+        let measured = measure(&a, &tilted, RATE, 0.0).unwrap();
+        assert!(measured.spectral_difference_db > SPECTRAL_FAIL_DB, "{:?}", measured);
+
+        // Different audio entirely
+        let mut seed = 54321u32;
+        let other: Vec<f64> = a
+            .iter()
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                f64::from(seed >> 16) - 32768.0
+            })
+            .collect();
+        let measured = measure(&a, &other, RATE, 0.0).unwrap();
+        assert!(measured.correlation.abs() < MATCHING_CORRELATION, "{:?}", measured);
+
+        assert!(measure(&a, &vec![0.0; a.len()], RATE, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_compare_recorded_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = program();
+        write_wav(&dir.path().join("a1.wav"), &a);
+        let b: Vec<f64> = a.iter().map(|s| -0.5 * s).collect();
+        write_wav(&dir.path().join("b1.wav"), &b);
+
+        let pages = [
+            page(1, "224.0.1.1:5004", "2026-01-15T10:30:00Z", "2026-01-15T10:30:02Z", "a1.wav"),
+            page(1, "224.0.1.2:5004", "2026-01-15T10:30:00.010Z", "2026-01-15T10:30:02.010Z", "b1.wav"),
+            // Only on A
+            page(2, "224.0.1.1:5004", "2026-01-15T10:31:00Z", "2026-01-15T10:31:02Z", "a2.wav"),
+        ];
+        let pair: EndpointPair = "A=224.0.1.1:5004,B=224.0.1.2:5004".parse().unwrap();
+        let comparisons = compare_paths(dir.path(), &[pair], &pages);
+        assert_eq!(comparisons.len(), 2);
+
+        let first = &comparisons[0];
+        assert_eq!(first.b_page, Some(1));
+        assert!(first.polarity_inverted);
+        assert!((first.level_difference_db.unwrap() + 6.02).abs() < 0.1, "{:?}", first);
+        assert_eq!(first.verdict, Verdict::Fail);
+
+        let missing = &comparisons[1];
+        assert_eq!(missing.b_page, None);
+        assert_eq!(missing.findings, ["no page on B overlapped"]);
+        assert_eq!(missing.verdict, Verdict::Fail);
+    }
+}
@@ -0,0 +1,190 @@
+//! Recording a page's encoded payloads beside its decoded audio.
+//!
+//! With `--record-payloads`, `monitor` and `test` keep each page's RTP
+//! payloads exactly as received, for codec debugging: `page_0001.wav` gets
+//! `page_0001.payloads.bin`, the payloads back to back, and
+//! `page_0001.payloads.jsonl`, one index row per payload giving where it
+//! sits in the `.bin` file and the packet it came in. Payloads are written
+//! before the packet is decoded, so they are there even for a page whose
+//! audio couldn't be decoded. A payload file that can't be written stops
+//! taking payloads but leaves the page's other files alone.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+#[allow(dead_code)]
+pub enum PayloadError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid index row on line {line}: {source}")]
+    InvalidRow { line: usize, source: serde_json::Error },
+
+    #[error("Index row on line {line} runs past the end of the payload file")]
+    OutOfRange { line: usize },
+}
+
+/// Where one payload sits in the payload file, and the packet it came in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayloadIndexRow {
+    /// Byte offset in the payload file
+    pub offset: u64,
+    pub length: u32,
+    pub sequence: u16,
+    /// RTP timestamp
+    pub timestamp: u32,
+    pub payload_type: u8,
+}
+
+/// Writes one page's payloads and their index
+pub struct PayloadWriter {
+    data: BufWriter<File>,
+    index: BufWriter<File>,
+    path: PathBuf,
+    offset: u64,
+    /// The first write that failed; nothing is written after it
+    error: Option<io::ErrorKind>,
+}
+
+impl PayloadWriter {
+    /// Create the payload file at `path` and its index beside it
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            data: BufWriter::new(File::create(path)?),
+            index: BufWriter::new(File::create(payload_index_path(path))?),
+            path: path.to_path_buf(),
+            offset: 0,
+            error: None,
+        })
+    }
+
+    /// Add a packet's payload. Only the first failure is returned; after
+    /// it the writer takes nothing more, so the caller can report it once
+    /// and carry on with the page
+    pub fn record(&mut self, payload: &[u8], sequence: u16, timestamp: u32, payload_type: u8) -> io::Result<()> {
+        if self.error.is_some() {
+            return Ok(());
+        }
+        let row = PayloadIndexRow {
+            offset: self.offset,
+            length: payload.len() as u32,
+            sequence,
+            timestamp,
+            payload_type,
+        };
+        let result = self.data.write_all(payload).and_then(|()| {
+            serde_json::to_writer(&mut self.index, &row)?;
+            writeln!(self.index)
+        });
+        match result {
+            Ok(()) => {
+                self.offset += payload.len() as u64;
+                Ok(())
+            }
+            Err(e) => {
+                self.error = Some(e.kind());
+                Err(e)
+            }
+        }
+    }
+
+    /// Whether a write has failed, leaving the files short
+    pub fn failed(&self) -> bool {
+        self.error.is_some()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(kind) = self.error {
+            return Err(io::Error::new(kind, "payloads after a failed write were not saved"));
+        }
+        self.data.flush()?;
+        self.index.flush()
+    }
+}
+
+/// Payload file for a recording: `page_0001.wav` has `page_0001.payloads.bin`
+pub fn payloads_path(recording: &Path) -> PathBuf {
+    let stem = recording
+        .file_stem()
+        .map_or_else(|| recording.to_string_lossy().into_owned(), |s| s.to_string_lossy().into_owned());
+    recording.with_file_name(format!("{}.payloads.bin", stem))
+}
+
+/// Index for a payload file: `page_0001.payloads.bin` has `page_0001.payloads.jsonl`
+pub fn payload_index_path(payloads: &Path) -> PathBuf {
+    payloads.with_extension("jsonl")
+}
+
+/// Read every payload back with its index row, in the order received.
+/// Kept beside the writer as the reference for the format
+#[allow(dead_code)]
+pub fn read_payloads(path: &Path) -> Result<Vec<(PayloadIndexRow, Vec<u8>)>, PayloadError> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    let reader = BufReader::new(File::open(payload_index_path(path))?);
+    let mut payloads = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: PayloadIndexRow =
+            serde_json::from_str(&line).map_err(|source| PayloadError::InvalidRow { line: i + 1, source })?;
+        let payload = usize::try_from(row.offset)
+            .ok()
+            .and_then(|start| data.get(start..start.checked_add(row.length as usize)?))
+            .ok_or(PayloadError::OutOfRange { line: i + 1 })?;
+        payloads.push((row, payload.to_vec()));
+    }
+    Ok(payloads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = payloads_path(&dir.path().join("page_0001.wav"));
+        assert_eq!(path, dir.path().join("page_0001.payloads.bin"));
+        assert_eq!(payload_index_path(&path), dir.path().join("page_0001.payloads.jsonl"));
+
+        // Payloads of varying size, including an empty one
+        let sent: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i; usize::from(i % 5) * 40]).collect();
+        let mut writer = PayloadWriter::create(&path).unwrap();
+        for (i, payload) in sent.iter().enumerate() {
+            writer.record(payload, 100 + i as u16, i as u32 * 160, 0).unwrap();
+        }
+        assert!(!writer.failed());
+        writer.finish().unwrap();
+
+        let payloads = read_payloads(&path).unwrap();
+        assert_eq!(payloads.len(), 20);
+        for (i, (row, payload)) in payloads.iter().enumerate() {
+            assert_eq!(payload, &sent[i]);
+            assert_eq!((row.sequence, row.timestamp, row.payload_type), (100 + i as u16, i as u32 * 160, 0));
+        }
+        assert_eq!(payloads[3].0.offset, 40 + 80);
+    }
+
+    #[test]
+    fn test_index_past_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.payloads.bin");
+        let mut writer = PayloadWriter::create(&path).unwrap();
+        writer.record(&[1, 2, 3, 4], 1, 0, 0).unwrap();
+        writer.finish().unwrap();
+        std::fs::write(&path, [1, 2]).unwrap();
+
+        assert!(matches!(read_payloads(&path), Err(PayloadError::OutOfRange { line: 1 })));
+    }
+}
@@ -0,0 +1,453 @@
+//! Transmit plans and `--dry-run`.
+//!
+//! `transmit` and `polycom-transmit` build a plan before sending anything:
+//! the file is decoded and encoded, the destination route is resolved, and
+//! everything that would stop the page is collected as a problem. A dry run
+//! prints the plan and stops; otherwise the plan is executed.
+
+use crate::cli::audio_input::{AudioLevels, LevelCheck};
+use crate::cli::monitor::{output_json, JsonEvent};
+use crate::cli::transmit::format_local;
+use crate::network::{resolve_source_address, Dscp};
+use crate::utils::schedule::Schedule;
+use chrono::{Local, Utc};
+use serde::Serialize;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use schemars::JsonSchema;
+
+/// IPv4 and UDP header bytes added to every packet on the wire
+const IP_UDP_OVERHEAD: usize = 28;
+
+/// Everything a transmit command is about to do
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TransmitPlan {
+    pub command: &'static str,
+    pub file: String,
+    pub destination: DestinationPlan,
+    pub codec: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload_type: Option<u8>,
+    pub sample_rate: u32,
+    pub frame_ms: u32,
+    /// Audio frames (one per packet) in each page
+    pub frames: usize,
+    /// Everything sent each page, the chime included
+    pub audio_secs: f64,
+    /// `--chime` and the gap after it, ahead of the file's audio
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chime: Option<ChimePlan>,
+    /// Measured from the decoded file; not for `--raw` input, or for
+    /// `--multi-zone`, whose zones are measured one by one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub levels: Option<AudioLevels>,
+    /// UDP payload of one audio packet (from the first frame for variable-rate codecs)
+    pub packet_bytes: usize,
+    /// Audio packets on the wire, including IP and UDP headers
+    pub bandwidth_kbps: f64,
+    pub loop_audio: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub polycom: Option<PolycomPlan>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub also_rtp: Vec<RtpLegPlan>,
+    /// `--multi-zone` destinations, each sent one channel of the file
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub zones: Vec<ZonePlan>,
+    /// Worth knowing, but the page would still go out
+    pub warnings: Vec<String>,
+    /// Reasons the page would not go out as planned
+    pub problems: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct DestinationPlan {
+    pub address: Ipv4Addr,
+    pub port: u16,
+    pub ttl: u8,
+    /// `--dscp`; absent when packets carry the system default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dscp: Option<Dscp>,
+    pub multicast: bool,
+    /// Local address of the interface the route uses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_address: Option<Ipv4Addr>,
+}
+
+/// Polycom control phases and channel details
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PolycomPlan {
+    pub channel: u8,
+    /// The channel's name (see `cli::channel_names`); absent if it has none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel_name: Option<String>,
+    /// "ptt" or "paging"
+    pub mode: &'static str,
+    /// "priority" or "emergency" for channels that need `--allow-priority`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub classification: Option<&'static str>,
+    pub caller_id: String,
+    pub alert_packets: u32,
+    pub end_packets: u32,
+    pub control_interval_ms: u64,
+    /// Alert packets plus the pause before audio
+    pub alert_secs: f64,
+    /// Pause after audio plus the End packets
+    pub end_secs: f64,
+    /// Alert, audio and End for one page
+    pub page_secs: f64,
+    /// Frames cut by the duration cap
+    pub truncated_frames: usize,
+}
+
+/// The `--chime` a page starts with
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ChimePlan {
+    /// `builtin:NAME`, or the file
+    pub chime: String,
+    pub chime_secs: f64,
+    /// Silence between the chime and the file's audio
+    pub gap_secs: f64,
+}
+
+/// An `--also-rtp` destination
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct RtpLegPlan {
+    pub address: Ipv4Addr,
+    pub port: u16,
+    pub codec: String,
+    pub payload_type: u8,
+    pub packet_bytes: usize,
+    pub bandwidth_kbps: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_address: Option<Ipv4Addr>,
+}
+
+/// A `--multi-zone` destination
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ZonePlan {
+    /// Channel of the file sent there
+    pub channel: String,
+    pub address: Ipv4Addr,
+    pub port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_address: Option<Ipv4Addr>,
+    /// Measured from the channel sent there
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub levels: Option<AudioLevels>,
+}
+
+impl ZonePlan {
+    pub fn new(channel: String, address: Ipv4Addr, port: u16) -> Self {
+        Self {
+            channel,
+            address,
+            port,
+            source_address: None,
+            levels: None,
+        }
+    }
+}
+
+/// Wire bandwidth of one packet every `frame_ms`
+pub fn bandwidth_kbps(packet_bytes: usize, frame_ms: u32) -> f64 {
+    if frame_ms == 0 {
+        return 0.0;
+    }
+    // bits per millisecond is kilobits per second
+    ((packet_bytes + IP_UDP_OVERHEAD) * 8) as f64 / f64::from(frame_ms)
+}
+
+impl DestinationPlan {
+    pub fn new(address: Ipv4Addr, port: u16, ttl: u8, dscp: Option<Dscp>) -> Self {
+        Self {
+            address,
+            port,
+            ttl,
+            dscp,
+            multicast: address.is_multicast(),
+            source_address: None,
+        }
+    }
+}
+
+impl TransmitPlan {
+    /// Check the destination address, TTL and route
+    pub fn check_destination(&mut self) {
+        let destination = &mut self.destination;
+        if !destination.multicast {
+            self.warnings.push(format!(
+                "{} is not a multicast address; only that one host will hear the page",
+                destination.address
+            ));
+        } else if destination.address.octets()[..3] == [224, 0, 0] && destination.ttl > 1 {
+            self.warnings.push(format!(
+                "{} is link-local; routers never forward it, whatever the TTL",
+                destination.address
+            ));
+        }
+        if destination.ttl == 0 {
+            self.problems
+                .push("TTL 0 keeps every packet on this host".to_string());
+        }
+
+        match resolve_source_address(SocketAddrV4::new(destination.address, destination.port)) {
+            Ok(source) => destination.source_address = Some(source),
+            Err(e) => self
+                .problems
+                .push(format!("No route to {}: {}", destination.address, e)),
+        }
+        if self.frames == 0 {
+            self.problems.push("The file contains no audio".to_string());
+        }
+    }
+
+    /// Check an `--also-rtp` leg's route
+    pub fn add_rtp_leg(&mut self, mut leg: RtpLegPlan) {
+        match resolve_source_address(SocketAddrV4::new(leg.address, leg.port)) {
+            Ok(source) => leg.source_address = Some(source),
+            Err(e) => self.problems.push(format!("No route to {}: {}", leg.address, e)),
+        }
+        self.also_rtp.push(leg);
+    }
+
+    /// Check a `--multi-zone` destination's route
+    pub fn add_zone(&mut self, mut zone: ZonePlan) {
+        match resolve_source_address(SocketAddrV4::new(zone.address, zone.port)) {
+            Ok(source) => zone.source_address = Some(source),
+            Err(e) => self.problems.push(format!("No route to {}: {}", zone.address, e)),
+        }
+        self.zones.push(zone);
+    }
+
+    /// Record the audio's levels, warning of clipping and refusing silence
+    /// unless `--allow-silent` was given. Returns why the audio is silent
+    /// when that should stop the page.
+    pub fn check_levels(&mut self, levels: AudioLevels, check: LevelCheck) -> Option<String> {
+        self.levels = Some(levels);
+        self.level_findings("", levels, check)
+    }
+
+    /// `check_levels` for the channel sent to one `--multi-zone` destination
+    pub fn check_zone_levels(&mut self, zone: usize, levels: AudioLevels, check: LevelCheck) -> Option<String> {
+        self.zones[zone].levels = Some(levels);
+        let label = format!("Zone {}: ", self.zones[zone].channel);
+        self.level_findings(&label, levels, check)
+    }
+
+    fn level_findings(&mut self, label: &str, levels: AudioLevels, check: LevelCheck) -> Option<String> {
+        if let Some(clipping) = levels.clipping() {
+            self.warnings.push(format!("{}{}", label, clipping));
+        }
+        let silence = format!("{}{}", label, levels.silence(check.min_rms_dbfs)?);
+        if check.allow_silent {
+            self.warnings.push(format!("{}; sending anyway (--allow-silent)", silence));
+            None
+        } else {
+            self.problems.push(format!("{}; --allow-silent sends it anyway", silence));
+            Some(silence)
+        }
+    }
+
+    /// Check the allowed windows as they stand right now
+    pub fn check_schedule(&mut self, schedule: &Schedule, wait_for_window: bool) {
+        if !schedule.is_restricted() {
+            return;
+        }
+        self.schedule = Some(schedule.to_string());
+
+        let now = Local::now().naive_local();
+        if schedule.is_open(now) {
+            return;
+        }
+        let next = format_local(schedule.next_open(now));
+        if wait_for_window {
+            self.warnings
+                .push(format!("Outside allowed windows; would wait until {}", next));
+        } else {
+            self.problems.push(format!(
+                "Outside allowed windows ({}); next window opens {}",
+                schedule, next
+            ));
+        }
+    }
+
+    /// Print the plan (or emit it as JSON) and report whether it would work
+    pub fn finish_dry_run(&self, json: bool) -> Result<(), Vec<String>> {
+        if json {
+            output_json(&JsonEvent::TransmitPlan {
+                timestamp: Utc::now(),
+                plan: self.clone(),
+            });
+        } else {
+            self.print();
+        }
+
+        if self.problems.is_empty() {
+            Ok(())
+        } else {
+            Err(self.problems.clone())
+        }
+    }
+
+    fn print(&self) {
+        let destination = &self.destination;
+        println!("Dry run: {}", self.command);
+        println!("  File: {}", self.file);
+        println!(
+            "  Audio: {:.2}s, {} frames of {}ms ({}, {} Hz)",
+            self.audio_secs, self.frames, self.frame_ms, self.codec, self.sample_rate
+        );
+        if let Some(ref chime) = self.chime {
+            println!(
+                "  Chime: {}, {:.2}s then {:.2}s of silence before the audio",
+                chime.chime, chime.chime_secs, chime.gap_secs
+            );
+        }
+        if let Some(levels) = self.levels {
+            println!("  Levels: {}", levels);
+        }
+        println!(
+            "  Packets: {} bytes every {}ms, {:.1} kbps on the wire",
+            self.packet_bytes, self.frame_ms, self.bandwidth_kbps
+        );
+        println!(
+            "  Destination: {}:{} ({}), TTL {}{}{}",
+            destination.address,
+            destination.port,
+            if destination.multicast { "multicast" } else { "unicast" },
+            destination.ttl,
+            destination.dscp.map(|dscp| format!(", DSCP {}", dscp)).unwrap_or_default(),
+            destination
+                .source_address
+                .map(|source| format!(", sent from {}", source))
+                .unwrap_or_default()
+        );
+
+        if let Some(ref polycom) = self.polycom {
+            println!(
+                "  Channel: {}{} ({}{}), caller ID \"{}\"",
+                polycom.channel,
+                polycom.channel_name.as_ref().map(|name| format!(" \"{}\"", name)).unwrap_or_default(),
+                polycom.mode,
+                polycom
+                    .classification
+                    .map(|c| format!(", {}", c))
+                    .unwrap_or_default(),
+                polycom.caller_id
+            );
+            println!(
+                "  Alert: {} packets every {}ms, {:.2}s before audio",
+                polycom.alert_packets, polycom.control_interval_ms, polycom.alert_secs
+            );
+            println!(
+                "  End: {} packets every {}ms, {:.2}s after audio",
+                polycom.end_packets, polycom.control_interval_ms, polycom.end_secs
+            );
+            println!("  Page length: {:.2}s", polycom.page_secs);
+        }
+
+        for leg in &self.also_rtp {
+            println!(
+                "  Also RTP: {}:{} ({}, {} bytes, {:.1} kbps){}",
+                leg.address,
+                leg.port,
+                leg.codec,
+                leg.packet_bytes,
+                leg.bandwidth_kbps,
+                leg.source_address
+                    .map(|source| format!(", sent from {}", source))
+                    .unwrap_or_default()
+            );
+        }
+        for zone in &self.zones {
+            println!(
+                "  Zone {}: {}:{}{}{}",
+                zone.channel,
+                zone.address,
+                zone.port,
+                zone.source_address
+                    .map(|source| format!(", sent from {}", source))
+                    .unwrap_or_default(),
+                zone.levels
+                    .map(|levels| format!(" ({})", levels))
+                    .unwrap_or_default()
+            );
+        }
+        if let Some(ref schedule) = self.schedule {
+            println!("  Allowed windows: {}", schedule);
+        }
+        if self.loop_audio {
+            println!("  Loops until interrupted");
+        }
+
+        for warning in &self.warnings {
+            println!("  Warning: {}", warning);
+        }
+        for problem in &self.problems {
+            println!("  Problem: {}", problem);
+        }
+        println!();
+        if self.problems.is_empty() {
+            println!("Dry run OK; nothing was sent");
+        } else {
+            println!("Dry run found {} problem(s); nothing was sent", self.problems.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(address: Ipv4Addr, ttl: u8) -> TransmitPlan {
+        TransmitPlan {
+            command: "transmit",
+            file: "page.wav".to_string(),
+            destination: DestinationPlan::new(address, 5004, ttl, None),
+            codec: "G.711 u-law".to_string(),
+            payload_type: Some(0),
+            sample_rate: 8000,
+            frame_ms: 20,
+            frames: 150,
+            audio_secs: 3.0,
+            chime: None,
+            levels: None,
+            packet_bytes: 172,
+            bandwidth_kbps: bandwidth_kbps(172, 20),
+            loop_audio: false,
+            schedule: None,
+            polycom: None,
+            also_rtp: Vec::new(),
+            zones: Vec::new(),
+            warnings: Vec::new(),
+            problems: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_bandwidth() {
+        // G.711: 160 bytes of audio + 12 RTP + 28 IP/UDP every 20ms
+        assert!((bandwidth_kbps(172, 20) - 80.0).abs() < 1e-9);
+        assert!(bandwidth_kbps(172, 0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_destination_checks() {
+        let mut ok = plan(Ipv4Addr::LOCALHOST, 32);
+        ok.check_destination();
+        assert!(ok.problems.is_empty(), "{:?}", ok.problems);
+        assert_eq!(ok.destination.source_address, Some(Ipv4Addr::LOCALHOST));
+        assert_eq!(ok.warnings.len(), 1, "unicast should warn");
+
+        let mut link_local = plan(Ipv4Addr::new(224, 0, 0, 251), 32);
+        link_local.check_destination();
+        assert!(link_local.warnings.iter().any(|w| w.contains("link-local")));
+
+        let mut broken = plan(Ipv4Addr::LOCALHOST, 0);
+        broken.frames = 0;
+        broken.check_destination();
+        assert!(broken.problems.iter().any(|p| p.contains("TTL 0")));
+        assert!(broken.problems.iter().any(|p| p.contains("no audio")));
+    }
+}
@@ -0,0 +1,140 @@
+//! Audio playback for `review --play`
+//!
+//! This is the only place the audio output is touched, so review and the
+//! analysis paths work on headless machines. The output device is looked up
+//! once, before anything is played.
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PlaybackError {
+    #[error("WAV error: {0}")]
+    Wav(#[from] hound::Error),
+
+    #[error("No audio output device found (--play needs one)")]
+    NoOutputDevice,
+
+    #[error("Unsupported bit depth: {0}")]
+    UnsupportedBitDepth(u16),
+
+    #[error("Audio output error: {0}")]
+    Stream(String),
+}
+
+/// The default audio output
+pub struct Player {
+    device: cpal::Device,
+}
+
+impl Player {
+    /// Open the default output device
+    pub fn open() -> Result<Self, PlaybackError> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or(PlaybackError::NoOutputDevice)?;
+        Ok(Self { device })
+    }
+
+    /// Play a WAV file to the end, showing progress
+    pub fn play(&self, path: &Path) -> Result<(), PlaybackError> {
+        // Open WAV file
+        let mut reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+
+        println!("    Format: {} channels, {}Hz, {}-bit",
+            spec.channels, spec.sample_rate, spec.bits_per_sample);
+
+        // Collect samples
+        let samples: Vec<i16> = if spec.bits_per_sample == 16 {
+            reader.samples::<i16>().filter_map(|s| s.ok()).collect()
+        } else if spec.bits_per_sample == 8 {
+            reader.samples::<i8>()
+                .filter_map(|s| s.ok())
+                .map(|s| (s as i16) << 8)
+                .collect()
+        } else {
+            return Err(PlaybackError::UnsupportedBitDepth(spec.bits_per_sample));
+        };
+
+        if samples.is_empty() {
+            println!("    (empty audio file)");
+            return Ok(());
+        }
+
+        let config = cpal::StreamConfig {
+            channels: spec.channels,
+            sample_rate: cpal::SampleRate(spec.sample_rate),
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        let samples = Arc::new(samples);
+        let position = Arc::new(AtomicUsize::new(0));
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let samples_clone = Arc::clone(&samples);
+        let position_clone = Arc::clone(&position);
+        let finished_clone = Arc::clone(&finished);
+
+        let stream = self.device.build_output_stream(
+            &config,
+            move |data: &mut [i16], _: &cpal::OutputCallbackInfo| {
+                let mut pos = position_clone.load(Ordering::Relaxed);
+                for sample in data.iter_mut() {
+                    if pos < samples_clone.len() {
+                        *sample = samples_clone[pos];
+                        pos += 1;
+                    } else {
+                        *sample = 0;
+                        finished_clone.store(true, Ordering::Relaxed);
+                    }
+                }
+                position_clone.store(pos, Ordering::Relaxed);
+            },
+            |err| eprintln!("Audio stream error: {}", err),
+            None,
+        ).map_err(|e| PlaybackError::Stream(e.to_string()))?;
+
+        stream.play().map_err(|e| PlaybackError::Stream(e.to_string()))?;
+
+        // Calculate duration and show progress
+        let total_samples = samples.len();
+        let duration_secs = total_samples as f64 / (spec.sample_rate as f64 * spec.channels as f64);
+
+        print!("    Playing: [");
+        let bar_width = 40;
+
+        while !finished.load(Ordering::Relaxed) {
+            let pos = position.load(Ordering::Relaxed);
+            let progress = pos as f64 / total_samples as f64;
+            let filled = (progress * bar_width as f64) as usize;
+
+            print!("\r    Playing: [");
+            for i in 0..bar_width {
+                if i < filled {
+                    print!("█");
+                } else {
+                    print!("░");
+                }
+            }
+            let current_time = pos as f64 / (spec.sample_rate as f64 * spec.channels as f64);
+            print!("] {:.1}s / {:.1}s", current_time, duration_secs);
+
+            std::io::stdout().flush().ok();
+
+            std::thread::sleep(Duration::from_millis(100));
+        }
+
+        println!("\r    Playing: [{}] {:.1}s / {:.1}s ✓",
+            "█".repeat(bar_width), duration_secs, duration_secs);
+
+        // Small delay to ensure playback completes
+        std::thread::sleep(Duration::from_millis(100));
+
+        Ok(())
+    }
+}
@@ -3,14 +3,26 @@
 //! Monitors multicast addresses for Polycom PTT/Group Paging traffic
 //! and optionally records received pages to WAV files.
 
-use crate::codec::{create_decoder, CodecType};
+use crate::codec::create_decoder;
+use crate::network::rtp::extend_timestamp;
 use crate::network::{
-    MulticastSocket, PolycomPacket, PolycomSession, PolycomCodec, PacketType,
+    polycom_codec_type, AlertPhaseSummary, BufSlot, MulticastSocket, PcapWriter, PolycomPacket, PolycomSession, PolycomCodec, PacketType,
 };
+use crate::utils::filename::{unique_name, FilenameError, NameFields, Naming, Token};
 use crate::utils::range_parser::{parse_range, MulticastEndpoint, RangeParseError};
+use crate::cli::channel_names::ChannelNames;
+use crate::cli::drain::DRAIN_BUDGET;
+use crate::cli::inspect::{InspectOptions, Inspector};
+use crate::cli::logging::limited;
+use crate::cli::page_count::{CountProgress, CountSummary, PageCount, PageCounter};
+use crate::cli::receipt::{Receipt, ReceiptSender, RECEIPT_KIND};
+use crate::cli::schema::SCHEMA_VERSION;
+use crate::cli::timeout::Timeout;
+use chrono::{DateTime, Local};
+use schemars::JsonSchema;
 use std::collections::HashMap;
 use std::io;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, SocketAddrV4};
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
 use thiserror::Error;
@@ -35,8 +47,33 @@ pub enum PolycomMonitorError {
 
     #[error("No endpoints to monitor")]
     NoEndpoints,
+
+    #[error("Malformed Alert phase on channel {channel}: {problems}")]
+    BadAlertPhase { channel: u8, problems: String },
+
+    #[error("Naming error: {0}")]
+    Naming(#[from] FilenameError),
+
+    #[error("--count-per-endpoint needs --channels to list the channels to wait for")]
+    CountNeedsChannels,
+
+    #[error("Only {captured} of {requested} pages received before the run ended")]
+    CountNotMet { captured: u32, requested: u32 },
 }
 
+/// Tokens a Polycom recording name can use
+const RECORDING_TOKENS: &[Token] = &[
+    Token::Site,
+    Token::Endpoint,
+    Token::Address,
+    Token::Port,
+    Token::Page,
+    Token::Start,
+    Token::Channel,
+    Token::ChannelName,
+    Token::Caller,
+];
+
 /// Options for Polycom monitor command
 pub struct PolycomMonitorOptions {
     /// Multicast address pattern to monitor (supports ranges like 224.0.{1-10}.116:{5001-5010})
@@ -45,27 +82,280 @@ pub struct PolycomMonitorOptions {
     pub default_port: u16,
     /// Channels to monitor (e.g., "26", "26-50", or "all")
     pub channels: String,
+    /// Names shown beside channel numbers
+    pub channel_names: ChannelNames,
     /// Output directory for recordings
     pub output: Option<PathBuf>,
-    /// Timeout (`Duration::MAX` for indefinite)
-    pub timeout: Duration,
+    pub timeout: Timeout,
     /// Output in JSON format
     pub json: bool,
     /// Suppress non-essential output
     pub quiet: bool,
+    /// Capture of every received datagram
+    pub pcap: Option<PathBuf>,
+    /// Fail as soon as a session's Alert phase is too slow or too short
+    pub abort_on_bad_alert: bool,
+    /// Recording name template and site
+    pub naming: Naming,
+    /// Send a delivery receipt here for each completed page
+    pub receipt: Option<SocketAddrV4>,
+    /// End the run after this many pages, with `--count`; each channel is an endpoint
+    pub count: Option<PageCount>,
+    /// Check each redundant frame against the frame it repeats
+    pub verify_redundancy: bool,
+    /// Print every received datagram with a decode attempt
+    pub inspect: Option<InspectOptions>,
 }
 
+/// Largest `sample_count` jump filled as lost frames (5 seconds of 20ms
+/// frames). A bigger jump, either way, is taken as the sender restarting its
+/// count, and the timeline continues from the new value without filling.
+const MAX_CONCEALED_FRAMES: u32 = 250;
+
 /// State for a page being recorded
 struct RecordingState {
     session: PolycomSession,
+    /// Group the page arrived on
+    endpoint: SocketAddrV4,
+    /// Page number within the run, from 1
+    page: u32,
+    started: DateTime<Local>,
     samples: Vec<i16>,
     decoder: Option<Box<dyn crate::codec::AudioDecoder>>,
+    timeline: FrameTimeline,
+    /// Last frame received in order, kept with `--verify-redundancy` to
+    /// check the next packet's redundant copy against
+    last_frame: Option<Vec<u8>>,
+    redundancy: RedundancyStats,
+    stats: PolycomPageStats,
+}
+
+/// How the redundant frames in a page's Transmit packets were used, with
+/// `--verify-redundancy`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, JsonSchema)]
+pub struct RedundancyStats {
+    /// Audio packets carrying a copy of the frame before
+    pub packets_with_redundancy: u32,
+    /// Lost frames filled in from the copy
+    pub repaired_frames: u32,
+    /// Copies identical to the frame already received
+    pub frames_matched: u32,
+    /// Copies that differ from the frame already received; the sender is
+    /// repeating the wrong frame, so a repair would play the wrong audio
+    pub frames_mismatched: u32,
+}
+
+impl RedundancyStats {
+    fn add(&mut self, other: &RedundancyStats) {
+        self.packets_with_redundancy += other.packets_with_redundancy;
+        self.repaired_frames += other.repaired_frames;
+        self.frames_matched += other.frames_matched;
+        self.frames_mismatched += other.frames_mismatched;
+    }
+}
+
+/// Where a received frame falls on the page's `sample_count` timeline
+#[derive(Debug, PartialEq, Eq)]
+enum FramePlacement {
+    /// The frame expected next
+    InOrder,
+    /// This many frames are missing before this one
+    Gap(u32),
+    /// Already played out (duplicate or reordered), dropped
+    Late,
+    /// Count jumped too far to be loss; restarted from this frame
+    Restart,
+}
+
+/// Reconstructs a page's frame timeline from the audio header `sample_count`,
+/// which advances by the codec frame size for every 20ms frame
+#[derive(Debug, Default)]
+struct FrameTimeline {
+    /// `sample_count` of the next frame in sequence, once the first has
+    /// arrived, extended across wraps
+    next: Option<u64>,
+    /// Frames decoded from the current frame of a packet
+    frames_received: u32,
+    /// Lost frames rebuilt from the next packet's redundant copy
+    frames_recovered: u32,
+    /// Lost frames replaced with silence
+    frames_concealed: u32,
+    /// Duplicate or reordered frames dropped
+    frames_late: u32,
+}
+
+impl FrameTimeline {
+    /// Place a frame with the given `sample_count`; `step` is the count per frame
+    fn place(&mut self, sample_count: u32, step: u32) -> FramePlacement {
+        let count = extend_timestamp(self.next, sample_count);
+        let Some(next) = self.next.replace(count + u64::from(step)) else {
+            return FramePlacement::InOrder;
+        };
+
+        let offset = count as i64 - next as i64;
+        let frames = offset.unsigned_abs() / u64::from(step.max(1));
+        if frames > u64::from(MAX_CONCEALED_FRAMES) {
+            return FramePlacement::Restart;
+        }
+        if offset < 0 {
+            self.next = Some(next);
+            self.frames_late += 1;
+            return FramePlacement::Late;
+        }
+
+        if frames == 0 {
+            FramePlacement::InOrder
+        } else {
+            FramePlacement::Gap(frames as u32)
+        }
+    }
+
+    /// Carry on from a frame at `sample_count`, once the count of the frame
+    /// last placed turns out to have been misread
+    fn rebase(&mut self, sample_count: u32, step: u32) {
+        self.next = Some(extend_timestamp(self.next, sample_count) + u64::from(step));
+    }
+
+    /// Frames on the reconstructed timeline
+    fn frames(&self) -> u32 {
+        self.frames_received + self.frames_recovered + self.frames_concealed
+    }
+
+    /// Frames that were not received directly
+    fn frames_reconstructed(&self) -> u32 {
+        self.frames_recovered + self.frames_concealed
+    }
+}
+
+/// A page's Transmit packets measured as `PageStats` measures an RTP
+/// stream, with `sample_count` standing in for the sequence number
+#[derive(Debug, Clone, Default, serde::Serialize, JsonSchema)]
+pub struct PolycomPageStats {
+    /// Transmit packets the `sample_count` sequence says were sent
+    pub packets_expected: u32,
+    /// Transmit packets received, duplicates and late ones included
+    pub packets_received: u32,
+    /// Frames missing from the sequence
+    pub frames_lost: u32,
+    /// Lost frames rebuilt from the next packet's redundant copy
+    pub frames_recovered: u32,
+    /// Interarrival jitter against the nominal frame spacing (RFC 3550)
+    pub jitter_ms: f64,
+    /// Longest time between two Transmit packets
+    pub max_gap_ms: f64,
+    /// Times the count jumped too far to be loss and was followed afresh
+    pub restarts: u32,
+    /// The sender writes `sample_count` little-endian
+    pub little_endian_sender: bool,
+    /// `sample_count` of the last packet as it arrived
+    #[serde(skip)]
+    last_raw: Option<u32>,
+    /// `sample_count` and arrival of the last packet in sequence, the count
+    /// extended across wraps
+    #[serde(skip)]
+    last_in_sequence: Option<(u64, Instant)>,
+    #[serde(skip)]
+    last_arrival: Option<Instant>,
+    #[serde(skip)]
+    jitter_accumulator: f64,
+}
+
+impl PolycomPageStats {
+    /// `sample_count` as the sender meant it. A jump too big to be loss
+    /// that is a frame or a few forward with the bytes swapped gives away a
+    /// sender writing it little-endian, and from then on every count is
+    /// swapped. On the packet that gives it away, also returns the count of
+    /// the packet before, read swapped, for the timeline to carry on from.
+    fn sample_count(&mut self, raw: u32, step: u32) -> (u32, Option<u32>) {
+        let last = self.last_raw.replace(raw);
+        if self.little_endian_sender {
+            return (raw.swap_bytes(), None);
+        }
+        let Some(last) = last else {
+            return (raw, None);
+        };
+        let step = step.max(1);
+        let offset = raw.wrapping_sub(last) as i32;
+        let swapped = raw.swap_bytes().wrapping_sub(last.swap_bytes()) as i32;
+        if offset.unsigned_abs() / step > MAX_CONCEALED_FRAMES
+            && swapped > 0
+            && swapped.unsigned_abs() / step <= MAX_CONCEALED_FRAMES
+        {
+            self.little_endian_sender = true;
+            let previous = last.swap_bytes();
+            self.last_in_sequence = self
+                .last_in_sequence
+                .map(|(last, arrival)| (extend_timestamp(Some(last), previous), arrival));
+            return (raw.swap_bytes(), Some(previous));
+        }
+        (raw, None)
+    }
+
+    /// Count a Transmit packet placed on the timeline at `sample_count`,
+    /// with frames `spacing` apart
+    fn record(
+        &mut self,
+        placement: &FramePlacement,
+        sample_count: u32,
+        step: u32,
+        spacing: Duration,
+        arrival: Instant,
+        redundant: bool,
+    ) {
+        self.packets_received += 1;
+        if let Some(last) = self.last_arrival.replace(arrival) {
+            let gap_ms = arrival.saturating_duration_since(last).as_secs_f64() * 1000.0;
+            self.max_gap_ms = self.max_gap_ms.max(gap_ms);
+        }
+        match *placement {
+            // Already counted when its place was passed over
+            FramePlacement::Late => return,
+            FramePlacement::Gap(missing) => {
+                self.packets_expected += missing;
+                self.frames_lost += missing;
+                if redundant {
+                    self.frames_recovered += 1;
+                }
+            }
+            FramePlacement::Restart => {
+                self.restarts += 1;
+                self.last_in_sequence = None;
+            }
+            FramePlacement::InOrder => {}
+        }
+        self.packets_expected += 1;
+
+        // How far the arrival strayed from where the count puts the frame
+        let count = extend_timestamp(self.last_in_sequence.map(|(count, _)| count), sample_count);
+        if let Some((last_count, last_arrival)) = self.last_in_sequence {
+            let frames = (count.saturating_sub(last_count) / u64::from(step.max(1))) as f64;
+            let transit = arrival.saturating_duration_since(last_arrival).as_secs_f64() - frames * spacing.as_secs_f64();
+            self.jitter_accumulator += (transit.abs() - self.jitter_accumulator) / 16.0;
+            self.jitter_ms = self.jitter_accumulator * 1000.0;
+        }
+        self.last_in_sequence = Some((count, arrival));
+    }
+}
+
+/// Decoded samples in one 20ms frame
+fn samples_per_frame(codec: PolycomCodec) -> usize {
+    (codec.sample_rate() * codec.frame_duration_ms() / 1000) as usize
 }
 
 /// Run the Polycom monitor command
 pub async fn run_polycom_monitor(options: PolycomMonitorOptions) -> Result<(), PolycomMonitorError> {
     // Parse channel filter
     let channel_filter = parse_channel_filter(&options.channels)?;
+    if options.count.is_some_and(|count| count.per_endpoint) && channel_filter.is_empty() {
+        return Err(PolycomMonitorError::CountNeedsChannels);
+    }
+    let counted_channels: Vec<u8> = if channel_filter.is_empty() { (1..=50).collect() } else { channel_filter.clone() };
+    options.naming.check("Polycom recording", RECORDING_TOKENS)?;
+    let pcap_path = options
+        .pcap
+        .as_deref()
+        .map(|path| options.naming.capture_path(path, Local::now()))
+        .transpose()?;
 
     // Parse address pattern into endpoints
     let endpoints = parse_polycom_pattern(&options.pattern, options.default_port)?;
@@ -107,15 +397,28 @@ pub async fn run_polycom_monitor(options: PolycomMonitorOptions) -> Result<(), P
         if let Some(ref output) = options.output {
             println!("  Output: {}", output.display());
         }
+        if let Some(ref pcap) = pcap_path {
+            println!("  Packet capture: {}", pcap.display());
+        }
         println!();
         println!("Listening for Polycom pages... (Ctrl+C to stop)");
         println!();
     }
 
+    let mut pcap = pcap_path.as_deref().map(PcapWriter::create).transpose()?;
+    let mut inspector = options.inspect.clone().map(|inspect| Inspector::new(inspect, options.json));
+    let receipts = match options.receipt {
+        Some(dest) => Some(ReceiptSender::new(dest).await?),
+        None => None,
+    };
+    let mut receipts_sent = 0;
+
     let start_time = Instant::now();
-    let mut buf = vec![0u8; 2048];
+    let mut slots = BufSlot::batch(DRAIN_BUDGET, 2048);
     let mut sessions: HashMap<u8, RecordingState> = HashMap::new();
     let mut completed_pages: Vec<PageSummary> = Vec::new();
+    let mut pages_started = 0;
+    let mut counter = options.count.map(PageCounter::new);
 
     // Session timeout (no packets for this long = session ended)
     let session_timeout_ms = 2000u64;
@@ -126,51 +429,75 @@ pub async fn run_polycom_monitor(options: PolycomMonitorOptions) -> Result<(), P
     let socket = &mut sockets[0];
 
     loop {
+        if let Some(ref receipts) = receipts {
+            send_receipts(receipts, &completed_pages[receipts_sent..]).await;
+            receipts_sent = completed_pages.len();
+        }
+
+        // With --count, stop once enough pages have ended and those in progress have finished
+        if let Some(ref mut counter) = counter {
+            if counter.update(channel_pages(&counted_channels, &sessions, &completed_pages)) == CountProgress::Done {
+                break;
+            }
+        }
+
         // Check timeout
-        if options.timeout != Duration::MAX && start_time.elapsed() > options.timeout {
+        if options.timeout.expired(start_time) {
+            if let Some(ref mut counter) = counter {
+                counter.update(channel_pages(&counted_channels, &sessions, &completed_pages));
+            }
             break;
         }
 
         // Receive with timeout for periodic cleanup
         let recv_result = tokio::time::timeout(
             Duration::from_millis(500),
-            socket.recv_from(&mut buf),
+            socket.recv_batch(&mut slots),
         )
         .await;
 
         match recv_result {
-            Ok(Ok((len, source))) => {
-                // Try to parse as Polycom packet
-                match PolycomPacket::parse(&buf[..len], source) {
-                    Ok(packet) => {
-                        let channel = packet.header.channel;
-
-                        // Check channel filter
-                        if !channel_filter.is_empty() && !channel_filter.contains(&channel) {
-                            continue;
-                        }
+            Ok(Ok(count)) => {
+                for (datagram, data) in slots[..count].iter().filter_map(BufSlot::received) {
+                    if let Some(ref mut pcap) = pcap {
+                        pcap.write_packet(datagram.wall_time, datagram.source, socket.destination(), datagram.ttl, data);
+                    }
+                    if let Some(ref mut inspector) = inspector {
+                        inspector.inspect(data, datagram.source, socket.destination(), datagram.received_at, datagram.wall_time);
+                    }
 
-                        match packet.header.packet_type {
-                            PacketType::Alert => {
-                                handle_alert(&mut sessions, &packet, &options);
-                            }
-                            PacketType::Transmit => {
-                                handle_transmit(&mut sessions, &packet);
+                    // Try to parse as Polycom packet; anything else is an error, never a panic
+                    match PolycomPacket::parse_with_time(data, datagram.source, datagram.received_at) {
+                        Ok(packet) => {
+                            let channel = packet.header.channel;
+
+                            // Check channel filter
+                            if !channel_filter.is_empty() && !channel_filter.contains(&channel) {
+                                continue;
                             }
-                            PacketType::End => {
-                                if let Some(summary) = handle_end(&mut sessions, &packet, &options) {
-                                    completed_pages.push(summary);
+
+                            match packet.header.packet_type {
+                                PacketType::Alert => {
+                                    handle_alert(&mut sessions, &packet, socket.destination(), &mut pages_started, &options);
+                                }
+                                PacketType::Transmit => {
+                                    handle_transmit(&mut sessions, &packet, &options)?;
+                                }
+                                PacketType::End => {
+                                    if let Some(summary) = handle_end(&mut sessions, &packet, &options)? {
+                                        completed_pages.push(summary);
+                                    }
                                 }
                             }
                         }
-                    }
-                    Err(e) => {
-                        debug!("Non-Polycom packet or parse error: {}", e);
+                        Err(e) => {
+                            limited!(debug!("Non-Polycom packet or parse error: {}", e));
+                        }
                     }
                 }
             }
             Ok(Err(e)) => {
-                warn!("Receive error: {}", e);
+                limited!(warn!("Receive error: {}", e));
             }
             Err(_) => {
                 // Timeout - check for stale sessions
@@ -185,48 +512,158 @@ pub async fn run_polycom_monitor(options: PolycomMonitorOptions) -> Result<(), P
             completed_pages.push(summary);
         }
     }
+    if let Some(ref receipts) = receipts {
+        send_receipts(receipts, &completed_pages[receipts_sent..]).await;
+    }
+
+    // Pages cut off by the end of the run are recorded, but don't count
+    let count = counter.as_ref().map(PageCounter::summary);
+    let redundancy = options.verify_redundancy.then(|| {
+        let mut total = RedundancyStats::default();
+        for stats in completed_pages.iter().filter_map(|page| page.redundancy.as_ref()) {
+            total.add(stats);
+        }
+        total
+    });
 
     // Print summary
     if options.json {
-        let summary = serde_json::json!({
-            "total_pages": completed_pages.len(),
-            "pages": completed_pages,
-        });
+        let summary = PolycomSummary {
+            schema_version: SCHEMA_VERSION,
+            total_pages: completed_pages.len(),
+            count,
+            redundancy,
+            pages: completed_pages,
+        };
         println!("{}", serde_json::to_string_pretty(&summary).unwrap_or_default());
     } else if !options.quiet {
         println!();
         println!("=== Summary ===");
         println!("Total pages received: {}", completed_pages.len());
+        if let Some(count) = count {
+            println!("Counted: {} of {} pages", count.pages_captured, count.pages_requested);
+        }
+        if let Some(ref redundancy) = redundancy {
+            print_redundancy("Redundancy", redundancy);
+        }
         for (i, page) in completed_pages.iter().enumerate() {
             println!(
-                "  Page {}: Channel {}, Caller: \"{}\", Duration: {:.1}s, {} audio packets",
+                "  Page {}: Channel {}, Caller: \"{}\", Duration: {:.1}s, Audio: {:.2}s, {} audio packets",
                 i + 1,
-                page.channel,
+                options.channel_names.label(page.channel),
                 page.caller_id,
                 page.duration_secs,
+                page.audio_secs,
                 page.audio_packets
             );
+            if page.frames_recovered + page.frames_concealed > 0 {
+                println!(
+                    "    {} frames received, {} recovered, {} concealed",
+                    page.frames_received, page.frames_recovered, page.frames_concealed
+                );
+            }
+            if let Some(problems) = page.alert_phase.map(|phase| phase.problems()).filter(|p| !p.is_empty()) {
+                println!("    ⚠ Alert phase: {}", problems.join(", "));
+            }
+        }
+        if let Some(ref pcap) = pcap {
+            println!("Captured {} packets to {}", pcap.packets(), pcap.path().display());
         }
     }
 
-    Ok(())
+    match count {
+        Some(count) if !count.met => Err(PolycomMonitorError::CountNotMet {
+            captured: count.pages_captured,
+            requested: count.pages_requested,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Each counted channel's pages ended so far, and whether one is in progress
+fn channel_pages<'a>(
+    channels: &'a [u8],
+    sessions: &'a HashMap<u8, RecordingState>,
+    completed_pages: &'a [PageSummary],
+) -> impl Iterator<Item = (u8, u32, bool)> + 'a {
+    channels.iter().map(move |&channel| {
+        let ended = completed_pages.iter().filter(|page| page.channel == channel).count() as u32;
+        (channel, ended, sessions.contains_key(&channel))
+    })
+}
+
+/// What `--json` prints when monitoring ends
+#[derive(Debug, serde::Serialize, JsonSchema)]
+pub struct PolycomSummary {
+    pub schema_version: u32,
+    pub total_pages: usize,
+    /// Pages counted against `--count`, when it was given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<CountSummary>,
+    /// Redundancy across all pages, with `--verify-redundancy`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redundancy: Option<RedundancyStats>,
+    pub pages: Vec<PageSummary>,
 }
 
 /// Page summary for reporting
-#[derive(Debug, serde::Serialize)]
-struct PageSummary {
+#[derive(Debug, serde::Serialize, JsonSchema)]
+pub struct PageSummary {
     channel: u8,
+    /// The channel's name (see `cli::channel_names`); absent if it has none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel_name: Option<String>,
     caller_id: String,
     codec: String,
     duration_secs: f64,
+    /// Length of the reconstructed audio timeline
+    audio_secs: f64,
     audio_packets: u32,
+    frames_received: u32,
+    frames_recovered: u32,
+    frames_concealed: u32,
+    frames_late: u32,
+    /// Transmit packet loss and timing
+    stats: PolycomPageStats,
+    /// Alert timing; absent if the page never got past its Alerts
+    alert_phase: Option<AlertPhaseSummary>,
     recording_file: Option<String>,
+    /// With `--verify-redundancy`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redundancy: Option<RedundancyStats>,
+}
+
+impl PageSummary {
+    fn receipt(&self) -> Receipt {
+        Receipt {
+            kind: RECEIPT_KIND.to_string(),
+            schema_version: SCHEMA_VERSION,
+            channel: self.channel,
+            caller_id: self.caller_id.clone(),
+            codec: self.codec.clone(),
+            duration_secs: self.duration_secs,
+            audio_packets: self.audio_packets,
+            frames_received: self.frames_received,
+            frames_recovered: self.frames_recovered,
+            frames_concealed: self.frames_concealed,
+            frames_late: self.frames_late,
+        }
+    }
+}
+
+/// Send a `--receipt` for each newly completed page
+async fn send_receipts(receipts: &ReceiptSender, pages: &[PageSummary]) {
+    for page in pages {
+        receipts.send(&page.receipt()).await;
+    }
 }
 
 /// Handle an Alert packet (start of new page)
 fn handle_alert(
     sessions: &mut HashMap<u8, RecordingState>,
     packet: &PolycomPacket,
+    endpoint: SocketAddrV4,
+    pages_started: &mut u32,
     options: &PolycomMonitorOptions,
 ) {
     let channel = packet.header.channel;
@@ -241,11 +678,13 @@ fn handle_alert(
 
     // New session
     let session = PolycomSession::from_alert(packet);
+    *pages_started += 1;
 
     if !options.quiet && !options.json {
         println!(
             "[Channel {}] Page started from \"{}\"",
-            channel, packet.header.caller_id
+            options.channel_names.label(channel),
+            packet.header.caller_id
         );
     }
 
@@ -253,8 +692,15 @@ fn handle_alert(
         channel,
         RecordingState {
             session,
+            endpoint,
+            page: *pages_started,
+            started: Local::now(),
             samples: Vec::new(),
             decoder: None,
+            timeline: FrameTimeline::default(),
+            last_frame: None,
+            redundancy: RedundancyStats::default(),
+            stats: PolycomPageStats::default(),
         },
     );
 
@@ -262,50 +708,182 @@ fn handle_alert(
         "New page on channel {}: caller=\"{}\"",
         channel, packet.header.caller_id
     );
+    debug!(
+        "Channel {} caller ID: {} in a {}-byte field",
+        channel, packet.header.caller_id_encoding, packet.header.caller_id_pad
+    );
+    if !packet.header.caller_id_trailing.is_empty() {
+        debug!(
+            "Channel {} caller ID field has {} bytes after the name: {}",
+            channel,
+            packet.header.caller_id_trailing.len(),
+            hex_bytes(&packet.header.caller_id_trailing)
+        );
+    }
+}
+
+/// Bytes as space-separated hex, for debug output
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// Report a session's Alert phase as it ends, failing on a bad one with
+/// `--abort-on-bad-alert`
+fn report_alert_phase(
+    channel: u8,
+    phase: &AlertPhaseSummary,
+    options: &PolycomMonitorOptions,
+) -> Result<(), PolycomMonitorError> {
+    let problems = phase.problems();
+    if !options.quiet && !options.json {
+        println!(
+            "[Channel {}] Alert phase: {} alerts over {:.0}ms, spacing mean {:.1}ms, max {:.1}ms",
+            options.channel_names.label(channel),
+            phase.count, phase.duration_ms, phase.mean_spacing_ms, phase.max_spacing_ms
+        );
+        if !problems.is_empty() {
+            println!("  ⚠ {}; phones may drop the page", problems.join(", "));
+        }
+    }
+    if options.abort_on_bad_alert && phase.is_malformed() {
+        return Err(PolycomMonitorError::BadAlertPhase {
+            channel,
+            problems: problems.join(", "),
+        });
+    }
+    Ok(())
 }
 
 /// Handle a Transmit packet (audio data)
-fn handle_transmit(sessions: &mut HashMap<u8, RecordingState>, packet: &PolycomPacket) {
+fn handle_transmit(
+    sessions: &mut HashMap<u8, RecordingState>,
+    packet: &PolycomPacket,
+    options: &PolycomMonitorOptions,
+) -> Result<(), PolycomMonitorError> {
     let channel = packet.header.channel;
 
     let Some(state) = sessions.get_mut(&channel) else {
         // Got audio without an Alert - create session anyway
         debug!("Transmit packet without prior Alert on channel {}", channel);
-        return;
+        return Ok(());
     };
 
-    state.session.update(packet);
+    if let Some(phase) = state.session.update(packet) {
+        report_alert_phase(channel, &phase, options)?;
+    }
 
     // Get codec and create decoder if needed
     if let Some(ref audio_header) = packet.audio_header {
         if state.decoder.is_none() {
-            let codec_type = match audio_header.codec {
-                PolycomCodec::G711U => CodecType::G711Ulaw,
-                PolycomCodec::G711A => CodecType::G711Alaw,
-                PolycomCodec::G722 => CodecType::G722,
-            };
-            match create_decoder(codec_type) {
+            match create_decoder(polycom_codec_type(audio_header.codec)) {
                 Ok(d) => state.decoder = Some(d),
                 Err(e) => {
                     warn!("Failed to create decoder: {}", e);
-                    return;
+                    return Ok(());
                 }
             }
         }
 
-        // Decode audio frame (use current frame, ignore redundant)
-        if let Some(ref audio_frame) = packet.audio_frame {
-            if let Some(ref mut decoder) = state.decoder {
-                match decoder.decode(audio_frame) {
-                    Ok(samples) => {
-                        state.samples.extend(samples);
-                    }
-                    Err(e) => {
-                        warn!("Decode error: {}", e);
-                    }
+        let Some(ref audio_frame) = packet.audio_frame else {
+            return Ok(());
+        };
+        if state.decoder.is_none() {
+            return Ok(());
+        }
+        let codec = audio_header.codec;
+        let step = codec.frame_size() as u32;
+
+        let (sample_count, previous) = state.stats.sample_count(audio_header.sample_count, step);
+        if let Some(previous) = previous {
+            warn!("Channel {}: sender writes sample_count little-endian, reading it swapped", channel);
+            state.timeline.rebase(previous, step);
+        }
+        let placement = state.timeline.place(sample_count, step);
+        state.stats.record(
+            &placement,
+            sample_count,
+            step,
+            Duration::from_millis(u64::from(codec.frame_duration_ms())),
+            packet.received_at,
+            packet.redundant_frame.is_some(),
+        );
+        if options.verify_redundancy {
+            verify_redundant_frame(state, packet, &placement, channel);
+        }
+        let Some(ref mut decoder) = state.decoder else {
+            return Ok(());
+        };
+        match placement {
+            FramePlacement::InOrder => {}
+            FramePlacement::Late => {
+                debug!("Dropping late frame on channel {}", channel);
+                return Ok(());
+            }
+            FramePlacement::Restart => {
+                warn!("Sample count on channel {} jumped, restarting timeline", channel);
+            }
+            FramePlacement::Gap(missing) => {
+                // Buffered audio comes before the filler
+                decode_into(decoder.flush(), &mut state.samples);
+
+                // The frame just before this one is carried as the redundant frame
+                let recoverable = u32::from(packet.redundant_frame.is_some());
+                let silent = missing - recoverable;
+                state.samples.resize(state.samples.len() + silent as usize * samples_per_frame(codec), 0);
+                state.timeline.frames_concealed += silent;
+
+                if let Some(ref redundant) = packet.redundant_frame {
+                    decode_into(decoder.decode(redundant), &mut state.samples);
+                    state.timeline.frames_recovered += 1;
                 }
+                debug!(
+                    "Channel {}: {} frame(s) missing, {} recovered from redundancy",
+                    channel, missing, recoverable
+                );
             }
         }
+
+        decode_into(decoder.decode(audio_frame), &mut state.samples);
+        state.timeline.frames_received += 1;
+        if options.verify_redundancy {
+            state.last_frame = Some(audio_frame.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Tally what a packet's redundant frame was used for: a repair after a
+/// gap, or a check against the frame received just before it. Late packets
+/// are dropped unused, so aren't counted.
+fn verify_redundant_frame(state: &mut RecordingState, packet: &PolycomPacket, placement: &FramePlacement, channel: u8) {
+    let Some(ref redundant) = packet.redundant_frame else {
+        return;
+    };
+    if *placement == FramePlacement::Late {
+        return;
+    }
+    let stats = &mut state.redundancy;
+    stats.packets_with_redundancy += 1;
+    match placement {
+        FramePlacement::Gap(_) => stats.repaired_frames += 1,
+        FramePlacement::InOrder => match state.last_frame {
+            Some(ref last) if last == redundant => stats.frames_matched += 1,
+            Some(_) => {
+                stats.frames_mismatched += 1;
+                debug!("Channel {}: redundant frame differs from the frame it repeats", channel);
+            }
+            None => {}
+        },
+        // Nothing received just before to compare with
+        FramePlacement::Restart | FramePlacement::Late => {}
+    }
+}
+
+/// Append decoded samples, logging decode errors
+fn decode_into(result: Result<Vec<i16>, crate::codec::CodecError>, samples: &mut Vec<i16>) {
+    match result {
+        Ok(decoded) => samples.extend(decoded),
+        Err(e) => limited!(warn!("Decode error: {}", e)),
     }
 }
 
@@ -314,31 +892,47 @@ fn handle_end(
     sessions: &mut HashMap<u8, RecordingState>,
     packet: &PolycomPacket,
     options: &PolycomMonitorOptions,
-) -> Option<PageSummary> {
+) -> Result<Option<PageSummary>, PolycomMonitorError> {
     let channel = packet.header.channel;
 
     if let Some(state) = sessions.get_mut(&channel) {
-        state.session.update(packet);
+        // A page can end without any audio, which also ends its Alert phase
+        if let Some(phase) = state.session.update(packet) {
+            report_alert_phase(channel, &phase, options)?;
+        }
 
         // Check if session is complete (received enough End packets)
         if state.session.is_complete() {
             if let Some(state) = sessions.remove(&channel) {
-                return finalize_session(channel, state, options);
+                return Ok(finalize_session(channel, state, options));
             }
         }
     }
 
-    None
+    Ok(None)
 }
 
 /// Finalize a session and optionally save recording
 #[allow(clippy::unnecessary_wraps)] // Option needed: recording can fail
 fn finalize_session(
     channel: u8,
-    state: RecordingState,
+    mut state: RecordingState,
     options: &PolycomMonitorOptions,
 ) -> Option<PageSummary> {
     let duration = state.session.duration();
+
+    // Drain audio still buffered in the decoder, then pad any shortfall so the
+    // recording covers every frame on the timeline
+    if let Some(ref mut decoder) = state.decoder {
+        decode_into(decoder.flush(), &mut state.samples);
+    }
+    let frame_samples = state.session.codec.map_or(0, samples_per_frame);
+    let timeline_samples = state.timeline.frames() as usize * frame_samples;
+    if state.samples.len() < timeline_samples {
+        state.samples.resize(timeline_samples, 0);
+    }
+    let frame_ms = state.session.codec.map_or(20, |c| c.frame_duration_ms());
+    let audio_secs = f64::from(state.timeline.frames() * frame_ms) / 1000.0;
     let codec_name = state
         .session
         .codec
@@ -348,10 +942,37 @@ fn finalize_session(
     if !options.quiet && !options.json {
         println!(
             "[Channel {}] Page ended: {:.1}s, {} audio packets",
-            channel,
+            options.channel_names.label(channel),
             duration.as_secs_f64(),
             state.session.audio_packet_count
         );
+        let stats = &state.stats;
+        if stats.packets_received > 0 {
+            println!(
+                "  Transmit: {} of {} packets, {} frames lost ({} recovered), {:.1}ms jitter, {:.0}ms max gap",
+                stats.packets_received,
+                stats.packets_expected,
+                stats.frames_lost,
+                stats.frames_recovered,
+                stats.jitter_ms,
+                stats.max_gap_ms
+            );
+        }
+        if stats.little_endian_sender {
+            println!("  ⚠ The sender writes sample_count little-endian");
+        }
+        if state.timeline.frames_reconstructed() > 0 {
+            println!(
+                "  Reconstructed {} of {} frames ({} from redundancy, {} as silence)",
+                state.timeline.frames_reconstructed(),
+                state.timeline.frames(),
+                state.timeline.frames_recovered,
+                state.timeline.frames_concealed
+            );
+        }
+        if options.verify_redundancy {
+            print_redundancy("  Redundancy", &state.redundancy);
+        }
     }
 
     // Save recording if output is specified and we have samples
@@ -360,12 +981,25 @@ fn finalize_session(
             None
         } else {
             let sample_rate = state.session.codec.map(|c| c.sample_rate()).unwrap_or(8000);
-            let filename = format!(
-                "polycom_ch{}_{}_{}.wav",
-                channel,
-                state.session.caller_id.replace(|c: char| !c.is_alphanumeric(), "_"),
-                chrono::Local::now().format("%Y%m%d_%H%M%S")
-            );
+            let channel_name = options.channel_names.name_or_number(channel);
+            let fields = NameFields {
+                endpoint: Some(state.endpoint),
+                page: Some(state.page),
+                start: Some(state.started),
+                channel: Some(channel),
+                channel_name: Some(&channel_name),
+                caller: Some(&state.session.caller_id),
+                ..NameFields::default()
+            };
+            let filename = match options.naming.recording_name(fields) {
+                Some(name) => unique_name(output_dir, &name),
+                None => format!(
+                    "polycom_ch{}_{}_{}.wav",
+                    channel,
+                    state.session.caller_id.replace(|c: char| !c.is_alphanumeric(), "_"),
+                    Local::now().format("%Y%m%d_%H%M%S")
+                ),
+            };
             let path = output_dir.join(&filename);
 
             if let Err(e) = save_wav(&path, &state.samples, sample_rate) {
@@ -385,14 +1019,35 @@ fn finalize_session(
 
     Some(PageSummary {
         channel,
+        channel_name: options.channel_names.name(channel).map(str::to_string),
         caller_id: state.session.caller_id,
         codec: codec_name,
         duration_secs: duration.as_secs_f64(),
+        audio_secs,
         audio_packets: state.session.audio_packet_count,
+        frames_received: state.timeline.frames_received,
+        frames_recovered: state.timeline.frames_recovered,
+        frames_concealed: state.timeline.frames_concealed,
+        frames_late: state.timeline.frames_late,
+        stats: state.stats,
+        alert_phase: state.session.alert_phase,
         recording_file,
+        redundancy: options.verify_redundancy.then_some(state.redundancy),
     })
 }
 
+/// A line of `--verify-redundancy` figures, with a warning if the sender
+/// repeated the wrong frames
+fn print_redundancy(label: &str, stats: &RedundancyStats) {
+    println!(
+        "{}: {} packets carried a copy, {} repaired a loss, {} matched, {} mismatched",
+        label, stats.packets_with_redundancy, stats.repaired_frames, stats.frames_matched, stats.frames_mismatched
+    );
+    if stats.frames_mismatched > 0 {
+        println!("  ⚠ Redundant frames that don't match the frame they repeat point to a sender bug");
+    }
+}
+
 /// Cleanup stale sessions that have timed out
 fn cleanup_stale_sessions(
     sessions: &mut HashMap<u8, RecordingState>,
@@ -549,6 +1204,342 @@ fn parse_polycom_pattern(pattern: &str, default_port: u16) -> Result<Vec<Multica
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::network::PolycomPacketBuilder;
+
+    const DESTINATION: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(224, 0, 1, 116), 5001);
+
+    #[test]
+    fn test_frame_timeline_placement() {
+        let mut timeline = FrameTimeline::default();
+        // Starts anywhere, and wraps around
+        assert_eq!(timeline.place(u32::MAX - 159, 160), FramePlacement::InOrder);
+        assert_eq!(timeline.place(0, 160), FramePlacement::InOrder);
+        assert_eq!(timeline.place(480, 160), FramePlacement::Gap(2));
+        assert_eq!(timeline.place(320, 160), FramePlacement::Late);
+        assert_eq!(timeline.place(640, 160), FramePlacement::InOrder);
+        assert_eq!(timeline.place(10_000_000, 160), FramePlacement::Restart);
+        assert_eq!(timeline.place(10_000_160, 160), FramePlacement::InOrder);
+        assert_eq!(timeline.frames_late, 1);
+    }
+
+    #[test]
+    fn test_lost_frames_are_reconstructed() {
+        let options = PolycomMonitorOptions {
+            pattern: "224.0.1.116".to_string(),
+            default_port: 5001,
+            channels: "all".to_string(),
+            channel_names: ChannelNames::default(),
+            output: None,
+            timeout: Timeout::Indefinite,
+            json: false,
+            quiet: true,
+            pcap: None,
+            abort_on_bad_alert: false,
+            naming: Naming::default(),
+            receipt: None,
+            count: None,
+            verify_redundancy: false,
+            inspect: None,
+        };
+        let source = "192.168.1.10:5001".parse().unwrap();
+        let mut builder = PolycomPacketBuilder::new(26, [1, 2, 3, 4], "Test".to_string(), PolycomCodec::G711U);
+        let mut sessions = HashMap::new();
+
+        let alert = PolycomPacket::parse(&builder.build_alert().unwrap(), source).unwrap();
+        handle_alert(&mut sessions, &alert, DESTINATION, &mut 0, &options);
+
+        let mut sent = Vec::new();
+        for i in 0..50 {
+            let packet = builder.build_transmit(&[0xFF; 160]).unwrap();
+            sent.push(packet.clone());
+            // Frame 10 alone, then frames 20-22 in a row
+            if i == 10 || (20..23).contains(&i) {
+                continue;
+            }
+            handle_transmit(&mut sessions, &PolycomPacket::parse(&packet, source).unwrap(), &options).unwrap();
+        }
+        // A duplicate arriving late is dropped
+        handle_transmit(&mut sessions, &PolycomPacket::parse(&sent[30], source).unwrap(), &options).unwrap();
+
+        // One second of audio at 8kHz, despite the missing frames
+        assert_eq!(sessions[&26].samples.len(), 8000);
+
+        let state = sessions.remove(&26).unwrap();
+        let summary = finalize_session(26, state, &options).unwrap();
+        assert_eq!(summary.frames_received, 46);
+        // Frames 10 and 22 come from the redundant copy in the following packet
+        assert_eq!(summary.frames_recovered, 2);
+        assert_eq!(summary.frames_concealed, 2);
+        assert_eq!(summary.frames_late, 1);
+        assert!((summary.audio_secs - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_channel_names_in_summary_and_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = crate::config::Config::parse("[[polycom.channels]]\nchannel = 26\nname = \"All Call\"\n").unwrap();
+        let options = PolycomMonitorOptions {
+            pattern: "224.0.1.116".to_string(),
+            default_port: 5001,
+            channels: "all".to_string(),
+            channel_names: ChannelNames::load(&config.polycom, None).unwrap(),
+            output: Some(dir.path().to_path_buf()),
+            timeout: Timeout::Indefinite,
+            json: true,
+            quiet: true,
+            pcap: None,
+            abort_on_bad_alert: false,
+            naming: Naming { template: Some("{channel_name}_{page}.wav".parse().unwrap()), site: None },
+            receipt: None,
+            count: None,
+            verify_redundancy: false,
+            inspect: None,
+        };
+        let source = "192.168.1.10:5001".parse().unwrap();
+        let mut sessions = HashMap::new();
+        let mut pages_started = 0;
+
+        // A page on the named channel, then one on a channel without a name
+        let mut summaries = Vec::new();
+        for channel in [26, 27] {
+            let mut builder = PolycomPacketBuilder::new(channel, [1, 2, 3, 4], "Test".to_string(), PolycomCodec::G711U);
+            let alert = PolycomPacket::parse(&builder.build_alert().unwrap(), source).unwrap();
+            handle_alert(&mut sessions, &alert, DESTINATION, &mut pages_started, &options);
+            for _ in 0..5 {
+                let packet = builder.build_transmit(&[0xFF; 160]).unwrap();
+                handle_transmit(&mut sessions, &PolycomPacket::parse(&packet, source).unwrap(), &options).unwrap();
+            }
+            let state = sessions.remove(&channel).unwrap();
+            summaries.push(finalize_session(channel, state, &options).unwrap());
+        }
+
+        let json: Vec<serde_json::Value> = summaries.iter().map(|page| serde_json::to_value(page).unwrap()).collect();
+        assert_eq!(json[0]["channel"], 26);
+        assert_eq!(json[0]["channel_name"], "All Call");
+        assert_eq!(json[0]["recording_file"], "All_Call_1.wav");
+        // Without a name, the number stands in
+        assert_eq!(json[1]["channel"], 27);
+        assert!(json[1].get("channel_name").is_none());
+        assert_eq!(json[1]["recording_file"], "27_2.wav");
+        assert!(dir.path().join("All_Call_1.wav").exists());
+    }
+
+    #[test]
+    fn test_verify_redundancy() {
+        let options = PolycomMonitorOptions {
+            pattern: "224.0.1.116".to_string(),
+            default_port: 5001,
+            channels: "all".to_string(),
+            channel_names: ChannelNames::default(),
+            output: None,
+            timeout: Timeout::Indefinite,
+            json: false,
+            quiet: true,
+            pcap: None,
+            abort_on_bad_alert: false,
+            naming: Naming::default(),
+            receipt: None,
+            count: None,
+            verify_redundancy: true,
+            inspect: None,
+        };
+        let source = "192.168.1.10:5001".parse().unwrap();
+        let mut builder = PolycomPacketBuilder::new(26, [1, 2, 3, 4], "Test".to_string(), PolycomCodec::G711U);
+        let mut sessions = HashMap::new();
+
+        let alert = PolycomPacket::parse(&builder.build_alert().unwrap(), source).unwrap();
+        handle_alert(&mut sessions, &alert, DESTINATION, &mut 0, &options);
+
+        for i in 0..50u8 {
+            // Each frame different, so a copy of the wrong one shows
+            let mut packet = PolycomPacket::parse(&builder.build_transmit(&[i; 160]).unwrap(), source).unwrap();
+            match i {
+                10 => continue,
+                30 => packet.redundant_frame = Some(vec![0xAA; 160]),
+                _ => {}
+            }
+            handle_transmit(&mut sessions, &packet, &options).unwrap();
+        }
+
+        // The dropped frame is rebuilt, leaving no gap
+        assert_eq!(sessions[&26].samples.len(), 8000);
+
+        let state = sessions.remove(&26).unwrap();
+        let summary = finalize_session(26, state, &options).unwrap();
+        assert_eq!(summary.frames_concealed, 0);
+        let redundancy = summary.redundancy.unwrap();
+        assert_eq!(redundancy.repaired_frames, 1);
+        // Every packet after the first carries a copy
+        assert_eq!(redundancy.packets_with_redundancy, 48);
+        assert_eq!(redundancy.frames_mismatched, 1);
+        assert_eq!(redundancy.frames_matched, 46);
+    }
+
+    /// Page stats for 50 Transmit packets from `builder` arriving 20ms
+    /// apart, leaving out `dropped` and with frame 25 arriving 8ms late
+    fn transmit_stats(builder: &mut PolycomPacketBuilder, dropped: &[u32]) -> PolycomPageStats {
+        let options = PolycomMonitorOptions {
+            pattern: "224.0.1.116".to_string(),
+            default_port: 5001,
+            channels: "all".to_string(),
+            channel_names: ChannelNames::default(),
+            output: None,
+            timeout: Timeout::Indefinite,
+            json: false,
+            quiet: true,
+            pcap: None,
+            abort_on_bad_alert: false,
+            naming: Naming::default(),
+            receipt: None,
+            count: None,
+            verify_redundancy: false,
+            inspect: None,
+        };
+        let source = "192.168.1.10:5001".parse().unwrap();
+        let mut sessions = HashMap::new();
+        let alert = PolycomPacket::parse(&builder.build_alert().unwrap(), source).unwrap();
+        handle_alert(&mut sessions, &alert, DESTINATION, &mut 0, &options);
+
+        let start = Instant::now();
+        for i in 0..50 {
+            let data = builder.build_transmit(&[0xFF; 160]).unwrap();
+            if dropped.contains(&i) {
+                continue;
+            }
+            let late = if i == 25 { Duration::from_millis(8) } else { Duration::ZERO };
+            let arrival = start + Duration::from_millis(20) * i + late;
+            let packet = PolycomPacket::parse_with_time(&data, source, arrival).unwrap();
+            handle_transmit(&mut sessions, &packet, &options).unwrap();
+        }
+        // Every frame is on the timeline, however the count was written
+        assert_eq!(sessions[&26].timeline.frames(), 50);
+        let state = sessions.remove(&26).unwrap();
+        finalize_session(26, state, &options).unwrap().stats
+    }
+
+    #[test]
+    fn test_page_stats() {
+        let mut builder = PolycomPacketBuilder::new(26, [1, 2, 3, 4], "Test".to_string(), PolycomCodec::G711U);
+        let stats = transmit_stats(&mut builder, &[10, 20, 21, 22]);
+        assert_eq!((stats.packets_expected, stats.packets_received), (50, 46));
+        assert_eq!(stats.frames_lost, 4);
+        // Frames 10 and 22 are carried again by the packet after
+        assert_eq!(stats.frames_recovered, 2);
+        assert_eq!(stats.restarts, 0);
+        assert!(!stats.little_endian_sender);
+        // Three frames missing in a row leave 80ms between packets
+        assert!((stats.max_gap_ms - 80.0).abs() < 1e-6, "{}", stats.max_gap_ms);
+        // The loss doesn't count as jitter; the one late packet does
+        assert!(stats.jitter_ms > 0.1 && stats.jitter_ms < 1.0, "{}", stats.jitter_ms);
+    }
+
+    #[test]
+    fn test_page_stats_little_endian_sender() {
+        let mut builder = PolycomPacketBuilder::new(26, [1, 2, 3, 4], "Test".to_string(), PolycomCodec::G711U);
+        builder.set_little_endian(true);
+        let stats = transmit_stats(&mut builder, &[10]);
+        assert!(stats.little_endian_sender);
+        // Read swapped, the counts are an ordinary sequence with one loss
+        assert_eq!((stats.packets_expected, stats.packets_received), (50, 49));
+        assert_eq!((stats.frames_lost, stats.frames_recovered), (1, 1));
+        assert_eq!(stats.restarts, 0);
+    }
+
+    #[test]
+    fn test_page_stats_wrap_and_restart() {
+        let (mut timeline, mut stats) = (FrameTimeline::default(), PolycomPageStats::default());
+        let start = Instant::now();
+        let spacing = Duration::from_millis(20);
+        // Across the wrap, a frame lost on the way, then the sender restarts
+        for (i, raw) in [u32::MAX - 319, u32::MAX - 159, 160, 9_000_000, 9_000_160].into_iter().enumerate() {
+            let (sample_count, previous) = stats.sample_count(raw, 160);
+            assert_eq!((sample_count, previous), (raw, None));
+            let placement = timeline.place(sample_count, 160);
+            stats.record(&placement, sample_count, 160, spacing, start + spacing * i as u32, false);
+        }
+        assert!(!stats.little_endian_sender);
+        assert_eq!(stats.restarts, 1);
+        assert_eq!((stats.packets_expected, stats.packets_received, stats.frames_lost), (6, 5, 1));
+    }
+
+    #[test]
+    fn test_long_page_across_sample_count_wrap() {
+        // 10s of 20ms frames wrapping halfway, one lost and one arriving late
+        let (mut timeline, mut stats) = (FrameTimeline::default(), PolycomPageStats::default());
+        let start = Instant::now();
+        let spacing = Duration::from_millis(20);
+        let first = u32::MAX - 250 * 160 + 1;
+        let mut frames = Vec::new();
+        for i in (0..500u32).filter(|&i| i != 250) {
+            frames.push((i, spacing * i));
+            if i == 251 {
+                frames.push((249, spacing * i + Duration::from_micros(1)));
+            }
+        }
+        for (i, arrival) in frames {
+            let (sample_count, _) = stats.sample_count(first.wrapping_add(i * 160), 160);
+            let placement = timeline.place(sample_count, 160);
+            if matches!(placement, FramePlacement::InOrder | FramePlacement::Gap(_)) {
+                timeline.frames_received += 1;
+            }
+            if let FramePlacement::Gap(missing) = placement {
+                timeline.frames_concealed += missing;
+            }
+            stats.record(&placement, sample_count, 160, spacing, start + arrival, false);
+        }
+
+        assert_eq!((stats.packets_expected, stats.packets_received), (500, 500));
+        assert_eq!((stats.frames_lost, stats.restarts), (1, 0));
+        assert!(!stats.little_endian_sender);
+        assert!(stats.jitter_ms < 1e-6, "{}", stats.jitter_ms);
+        assert_eq!((timeline.frames(), timeline.frames_late), (500, 1));
+        assert_eq!(timeline.frames() * 20, 10_000, "the page's audio runs exactly 10s");
+    }
+
+    #[test]
+    fn test_abort_on_bad_alert() {
+        let mut options = PolycomMonitorOptions {
+            pattern: "224.0.1.116".to_string(),
+            default_port: 5001,
+            channels: "all".to_string(),
+            channel_names: ChannelNames::default(),
+            output: None,
+            timeout: Timeout::Indefinite,
+            json: false,
+            quiet: true,
+            pcap: None,
+            abort_on_bad_alert: false,
+            naming: Naming::default(),
+            receipt: None,
+            count: None,
+            verify_redundancy: false,
+            inspect: None,
+        };
+        let source = "192.168.1.10:5001".parse().unwrap();
+        let mut builder = PolycomPacketBuilder::new(26, [1, 2, 3, 4], "Test".to_string(), PolycomCodec::G711U);
+
+        // A single Alert before the audio is far too few
+        let mut run = |options: &PolycomMonitorOptions| {
+            let mut sessions = HashMap::new();
+            let alert = PolycomPacket::parse(&builder.build_alert().unwrap(), source).unwrap();
+            handle_alert(&mut sessions, &alert, DESTINATION, &mut 0, options);
+            let audio = PolycomPacket::parse(&builder.build_transmit(&[0xFF; 160]).unwrap(), source).unwrap();
+            let result = handle_transmit(&mut sessions, &audio, options);
+            (result, sessions)
+        };
+
+        let (result, sessions) = run(&options);
+        assert!(result.is_ok());
+        let phase = sessions[&26].session.alert_phase.unwrap();
+        assert!(phase.short);
+
+        options.abort_on_bad_alert = true;
+        let (result, _) = run(&options);
+        assert!(matches!(
+            result,
+            Err(PolycomMonitorError::BadAlertPhase { channel: 26, ref problems }) if problems.contains("only 1 Alert")
+        ));
+    }
 
     #[test]
     fn test_parse_channel_filter_all() {
@@ -2,37 +2,53 @@
 //!
 //! Transmits audio files using the Polycom PTT/Group Paging protocol.
 
-use crate::codec::{FfmpegG711AlawEncoder, FfmpegG711UlawEncoder, FfmpegG722Encoder};
-use crate::network::{create_transmit_socket, PolycomPacketBuilder, PolycomCodec};
-use std::io::{self, Write};
+use crate::codec::{
+    create_encoder, ffmpeg_support, CodecType, FfmpegG711AlawEncoder, FfmpegG711UlawEncoder, FfmpegG722Encoder,
+};
+use crate::cli::audio_input::{
+    measure_file, read_audio_file, scan_file, AudioInput, AudioInputError, AudioLevels, LevelCheck,
+};
+use crate::cli::channel_names::ChannelNames;
+use crate::cli::chime::{ChimeError, PageChime};
+use crate::cli::frame_cache::{self, encode_cached, CacheUsage, EncodeSpec, EncodedAudio, FrameCache, FrameCacheError};
+use crate::cli::frame_stream::{FrameSource, FrameStream, FrameStreamError, StreamSettings};
+use crate::cli::monitor::{output_json, JsonEvent};
+use crate::cli::receipt::{ReceiptListener, ReceiptWait, ReceivedReceipt};
+use crate::cli::plan::{bandwidth_kbps, ChimePlan, DestinationPlan, PolycomPlan, RtpLegPlan, TransmitPlan};
+use crate::cli::transmit::{
+    enforce_schedule, print_cache_usage, rand_ssrc, OutsideWindow, TransmitEvents, WindowWait,
+};
+use crate::utils::schedule::Schedule;
+use crate::network::{
+    create_transmit_socket, is_emergency_channel, is_priority_channel, polycom_codec_type,
+    print_impairment_summary, CallerIdEncoding, Dscp, Impairer, ImpairmentConfig, ImpairmentError,
+    PolycomHeader, PolycomPacketBuilder, PolycomCodec, RtpPacket,
+};
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{self, BufRead, IsTerminal, Write};
 use std::net::{Ipv4Addr, SocketAddrV4};
 use std::path::Path;
+use std::str::FromStr;
 use std::time::{Duration, Instant};
-use symphonia::core::audio::{AudioBufferRef, Signal};
-use symphonia::core::codecs::DecoderOptions;
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::{MediaSourceStream, MediaSourceStreamOptions};
-use symphonia::core::meta::MetadataOptions;
-use symphonia::core::probe::Hint;
 use thiserror::Error;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+use schemars::JsonSchema;
 
 #[derive(Error, Debug)]
 pub enum PolycomTransmitError {
     #[error("File not found: {0}")]
     FileNotFound(String),
 
-    #[error("Unsupported audio format: {0}")]
-    UnsupportedFormat(String),
-
     #[error("Codec error: {0}")]
     Codec(#[from] crate::codec::CodecError),
 
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
 
-    #[error("Audio decode error: {0}")]
-    AudioDecode(String),
+    #[error(transparent)]
+    AudioInput(#[from] AudioInputError),
 
     #[error("Polycom protocol error: {0}")]
     Protocol(#[from] crate::network::PolycomError),
@@ -42,24 +58,231 @@ pub enum PolycomTransmitError {
 
     #[error("Invalid codec: {0}")]
     InvalidCodec(String),
+
+    #[error("Channel {channel} is a {classification} channel; use --allow-priority to transmit on it")]
+    RestrictedChannel {
+        channel: u8,
+        classification: &'static str,
+    },
+
+    #[error("Impairment error: {0}")]
+    Impairment(#[from] ImpairmentError),
+
+    #[error("Frame cache error: {0}")]
+    FrameCache(#[from] FrameCacheError),
+
+    #[error(transparent)]
+    OutsideWindow(#[from] OutsideWindow),
+
+    #[error("Invalid --also-rtp destination: {0}")]
+    InvalidRtpLeg(String),
+
+    #[error("--chime: {0}")]
+    Chime(#[from] ChimeError),
+
+    #[error("{0}. Use --allow-silent to send it anyway")]
+    SilentAudio(String),
+
+    #[error("Dry run found problems: {}", .0.join("; "))]
+    DryRunFailed(Vec<String>),
+
+    #[error("Multicast error: {0}")]
+    Multicast(#[from] crate::network::MulticastError),
+
+    #[error("Only {received} of {expected} delivery receipts arrived")]
+    MissingReceipts { expected: usize, received: usize },
+}
+
+// A streamed file fails the same ways as one encoded up front
+impl From<FrameStreamError> for PolycomTransmitError {
+    fn from(error: FrameStreamError) -> Self {
+        match error {
+            FrameStreamError::Input(e) => Self::AudioInput(e),
+            FrameStreamError::Codec(e) => Self::Codec(e),
+            FrameStreamError::Io(e) => Self::Io(e),
+        }
+    }
+}
+
+impl PolycomTransmitError {
+    /// Stable code for the `transmit_failed` event
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::FileNotFound(_) => "file_not_found",
+            Self::Codec(_) => "codec",
+            Self::Io(_) => "io",
+            Self::AudioInput(_) => "audio_input",
+            Self::Protocol(_) => "protocol",
+            Self::InvalidChannel(_) => "invalid_channel",
+            Self::InvalidCodec(_) => "invalid_codec",
+            Self::RestrictedChannel { .. } => "restricted_channel",
+            Self::Impairment(_) => "invalid_impairment",
+            Self::FrameCache(_) => "frame_cache",
+            Self::OutsideWindow(_) => "outside_window",
+            Self::InvalidRtpLeg(_) => "invalid_rtp_leg",
+            Self::Chime(_) => "chime",
+            Self::SilentAudio(_) => "silent_audio",
+            Self::DryRunFailed(_) => "dry_run_failed",
+            Self::Multicast(_) => "multicast",
+            Self::MissingReceipts { .. } => "missing_receipts",
+        }
+    }
+}
+
+/// Pause between the last Alert packet and the first audio packet.
+/// Polycom uses ~64ms; receivers need it to start audio playback
+pub(crate) const ALERT_TO_AUDIO_MS: u64 = 64;
+
+/// Pause between the last audio packet and the first End packet
+pub(crate) const AUDIO_TO_END_MS: u64 = 50;
+
+/// An RTP destination that carries the same audio as the Polycom page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtpLeg {
+    pub address: Ipv4Addr,
+    pub port: u16,
+    pub codec: CodecType,
+}
+
+impl FromStr for RtpLeg {
+    type Err = PolycomTransmitError;
+
+    /// Parse `address:port[,codec]`; the codec defaults to G.711 u-law
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| PolycomTransmitError::InvalidRtpLeg(format!("{} ({})", s, reason));
+
+        let (endpoint, codec) = match s.split_once(',') {
+            Some((endpoint, codec)) => (endpoint, Some(codec.trim())),
+            None => (s, None),
+        };
+        let (address, port) = endpoint
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| invalid("expected address:port"))?;
+        let address = address.parse().map_err(|_| invalid("bad address"))?;
+        let port = port.parse().map_err(|_| invalid("bad port"))?;
+        let codec = match codec {
+            Some(name) => CodecType::from_str(name).ok_or_else(|| invalid("unknown codec"))?,
+            None => CodecType::G711Ulaw,
+        };
+
+        Ok(Self { address, port, codec })
+    }
+}
+
+/// Per-destination totals for an `--also-rtp` leg
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct RtpLegReport {
+    pub address: String,
+    pub port: u16,
+    pub codec: String,
+    pub packets_sent: u64,
+    /// Largest delay between a Polycom packet and its RTP counterpart
+    pub max_skew_ms: f64,
+}
+
+/// Send state for one RTP leg. Each page gets a fresh SSRC so RTP monitors
+/// split looped pages the same way Polycom monitors do.
+struct RtpLegSender {
+    leg: RtpLeg,
+    /// RTP timestamp ticks per 20ms frame
+    timestamp_step: u32,
+    ssrc: u32,
+    sequence: u16,
+    timestamp: u32,
+    packets_sent: u64,
+    max_skew: Duration,
+}
+
+impl RtpLegSender {
+    /// Check the leg's codec can follow the Polycom frame clock
+    fn new(leg: RtpLeg, frame_duration_ms: u32) -> Result<Self, PolycomTransmitError> {
+        let encoder = create_encoder(leg.codec)?;
+        let frame_ms = encoder.frame_size() as u64 * 1000 / u64::from(encoder.sample_rate());
+        if frame_ms != u64::from(frame_duration_ms) {
+            return Err(PolycomTransmitError::InvalidRtpLeg(format!(
+                "{} uses {}ms frames, the page needs {}ms",
+                leg.codec.name(),
+                frame_ms,
+                frame_duration_ms
+            )));
+        }
+
+        Ok(Self {
+            leg,
+            timestamp_step: leg.codec.rtp_clock_rate() * frame_duration_ms / 1000,
+            ssrc: 0,
+            sequence: 0,
+            timestamp: 0,
+            packets_sent: 0,
+            max_skew: Duration::ZERO,
+        })
+    }
+
+    fn dest(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(self.leg.address, self.leg.port)
+    }
+
+    /// Start a new stream for the next page
+    fn restart(&mut self, index: usize) {
+        self.ssrc = rand_ssrc().wrapping_add(index as u32);
+        self.sequence = 0;
+        self.timestamp = 0;
+    }
+
+    fn next_packet(&mut self, payload: &[u8]) -> Vec<u8> {
+        let packet = RtpPacket::build(
+            self.leg.codec.payload_type(),
+            self.sequence,
+            self.timestamp,
+            self.ssrc,
+            payload,
+            false,
+        );
+        self.sequence = self.sequence.wrapping_add(1);
+        self.timestamp = self.timestamp.wrapping_add(self.timestamp_step);
+        packet
+    }
+
+    fn report(&self) -> RtpLegReport {
+        RtpLegReport {
+            address: self.leg.address.to_string(),
+            port: self.leg.port,
+            codec: self.leg.codec.name().to_string(),
+            packets_sent: self.packets_sent,
+            max_skew_ms: self.max_skew.as_secs_f64() * 1000.0,
+        }
+    }
 }
 
 /// Options for Polycom transmit command
 pub struct PolycomTransmitOptions {
     /// Audio file to transmit
     pub file: std::path::PathBuf,
+    /// Layout of headerless input files
+    pub input: AudioInput,
+    /// When the audio is too quiet to send
+    pub levels: LevelCheck,
     /// Destination multicast address
     pub address: Ipv4Addr,
     /// Destination UDP port
     pub port: u16,
     /// Channel number (1-50)
     pub channel: u8,
+    /// Names shown beside channel numbers
+    pub channel_names: ChannelNames,
     /// Codec to use (g711u or g722)
     pub codec: String,
     /// Caller ID string
     pub caller_id: String,
+    /// How the caller ID is encoded in the header
+    pub caller_id_encoding: CallerIdEncoding,
+    /// Caller ID field length in bytes
+    pub caller_id_pad: usize,
     /// Multicast TTL
     pub ttl: u8,
+    /// DSCP packets are marked with, if not the system default
+    pub dscp: Option<Dscp>,
     /// Loop the audio file
     pub loop_audio: bool,
     /// Suppress non-essential output
@@ -82,22 +305,114 @@ pub struct PolycomTransmitOptions {
     pub little_endian: bool,
     /// File is raw pre-encoded audio (not WAV), bypass encoder
     pub raw: bool,
+    /// Allow transmitting on priority/emergency channels without confirmation
+    pub allow_priority: bool,
+    /// Truncate transmission to this many seconds (ignored on emergency channels)
+    pub max_duration_secs: Option<u64>,
+    /// Emit a JSON report when done
+    pub json: bool,
+    /// Simulated network impairments (audio packets only)
+    pub impairment: ImpairmentConfig,
+    /// Local-time windows in which pages may be sent (unrestricted if empty)
+    pub schedule: Schedule,
+    /// Wait for the next allowed window instead of refusing
+    pub wait_for_window: bool,
+    /// RTP destinations sent the same audio alongside the Polycom page
+    pub also_rtp: Vec<RtpLeg>,
+    /// Validate and print the plan without sending
+    pub dry_run: bool,
+    /// Directory of encoded frames to reuse across runs
+    pub cache_dir: Option<std::path::PathBuf>,
+    /// Receipts to wait for after the last End phase, and where they arrive
+    pub await_receipts: Option<(ReceiptWait, SocketAddrV4)>,
+    /// Chime sent ahead of the audio, if any
+    pub chime: Option<PageChime>,
+}
+
+/// The page and its RTP legs, encoded by the plan phase or ready to be
+/// encoded as they are sent
+struct PreparedPage {
+    codec: PolycomCodec,
+    frames: FrameSource,
+    /// Frames in a pass of the page, after any duration cap
+    frame_count: usize,
+    rtp_legs: Vec<RtpLegSender>,
+    /// Frames for each RTP codec other than the page's own, whose legs are
+    /// sent the page's frames
+    rtp_frames: HashMap<CodecType, FrameSource>,
+    /// The `--chime` ahead of the audio, counted in `frame_count`
+    chime: Option<ChimePlan>,
+    /// Frame cache hits and misses over the page and its legs, with `--cache-dir`
+    cache_usage: Option<CacheUsage>,
 }
 
 /// Run the Polycom transmit command
 pub async fn run_polycom_transmit(options: PolycomTransmitOptions) -> Result<(), PolycomTransmitError> {
+    // A dry run reports through its plan instead
+    let mut events = TransmitEvents::new(options.json && !options.dry_run, options.address, options.port);
+    let result = polycom_transmit(&options, &mut events).await;
+    if let Err(ref error) = result {
+        events.failed(error.reason(), error.to_string());
+    }
+    result
+}
+
+async fn polycom_transmit(
+    options: &PolycomTransmitOptions,
+    events: &mut TransmitEvents,
+) -> Result<(), PolycomTransmitError> {
     // Validate channel
     if options.channel == 0 || options.channel > 50 {
         return Err(PolycomTransmitError::InvalidChannel(options.channel));
     }
 
-    // Validate and parse codec
-    let polycom_codec = match options.codec.to_lowercase().as_str() {
-        "g711u" | "g711ulaw" | "pcmu" => PolycomCodec::G711U,
-        "g711a" | "g711alaw" | "pcma" => PolycomCodec::G711A,
-        "g722" => PolycomCodec::G722,
-        _ => return Err(PolycomTransmitError::InvalidCodec(options.codec.clone())),
-    };
+    // Priority/emergency channels reach every phone at full volume - require intent.
+    // A dry run never prompts; the plan reports a missing --allow-priority instead.
+    if !options.dry_run {
+        let interactive = io::stdin().is_terminal() && !options.quiet;
+        check_channel_interlock(options.channel, options.allow_priority, interactive)?;
+    }
+
+    // A caller ID that doesn't fit would change the header size phones expect
+    if !options.dry_run {
+        PolycomHeader::check_caller_id(&options.caller_id, options.caller_id_encoding, options.caller_id_pad)?;
+    }
+
+    let (plan, page) = plan_polycom_transmit(options)?;
+    if options.dry_run {
+        return plan
+            .finish_dry_run(options.json)
+            .map_err(PolycomTransmitError::DryRunFailed);
+    }
+    for warning in &plan.warnings {
+        warn!("{}", warning);
+    }
+    events.started(&plan);
+    execute_polycom_transmit(options, page, events).await
+}
+
+pub(crate) fn parse_polycom_codec(name: &str) -> Result<PolycomCodec, PolycomTransmitError> {
+    PolycomCodec::from_name(name).ok_or_else(|| PolycomTransmitError::InvalidCodec(name.to_string()))
+}
+
+fn packet_builder(
+    options: &PolycomTransmitOptions,
+    host_serial: [u8; 4],
+    codec: PolycomCodec,
+) -> PolycomPacketBuilder {
+    let mut builder = PolycomPacketBuilder::new(options.channel, host_serial, options.caller_id.clone(), codec);
+    builder.set_skip_redundant(options.no_redundant);
+    builder.set_skip_audio_header(options.no_audio_header);
+    builder.set_little_endian(options.little_endian);
+    builder.set_caller_id_format(options.caller_id_encoding, options.caller_id_pad);
+    builder
+}
+
+/// Encode the page and check everything without sending
+fn plan_polycom_transmit(
+    options: &PolycomTransmitOptions,
+) -> Result<(TransmitPlan, PreparedPage), PolycomTransmitError> {
+    let polycom_codec = parse_polycom_codec(&options.codec)?;
 
     if !options.file.exists() {
         return Err(PolycomTransmitError::FileNotFound(
@@ -105,103 +420,310 @@ pub async fn run_polycom_transmit(options: PolycomTransmitOptions) -> Result<(),
         ));
     }
 
+    options.impairment.validate()?;
+
+    if options.raw && !options.also_rtp.is_empty() {
+        return Err(PolycomTransmitError::InvalidRtpLeg(
+            "--raw audio cannot be re-encoded for RTP".to_string(),
+        ));
+    }
+    // The chime is made at the codec's rate and encoded with the audio;
+    // --raw frames are never encoded, and --cache-dir entries hold the
+    // file's frames alone, so both conflict with --chime
+    let (lead_in, chime) = match &options.chime {
+        Some(chime) => {
+            let (lead_in, plan) = chime.lead_in(polycom_codec.sample_rate())?;
+            (lead_in, Some(plan))
+        }
+        None => (Vec::new(), None),
+    };
+    let frame_ms = polycom_codec.frame_duration_ms();
+    let rtp_legs = options
+        .also_rtp
+        .iter()
+        .map(|&leg| RtpLegSender::new(leg, frame_ms))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let cache = options.cache_dir.as_deref().map(FrameCache::open).transpose()?;
+    let mut cache_usage = CacheUsage::default();
+    // G.722 needs 16kHz audio, G.711 8kHz
+    let codec = polycom_codec_type(polycom_codec);
+    let spec = encode_spec(&options.file, &options.input, codec, polycom_codec.sample_rate(), frame_ms);
+    // Raw frames are sent as they are, so never decoded to be measured. With
+    // a cache the whole page is encoded up front; without one it is only
+    // read through here, and encoded as it is sent
+    let (mut encoded, mut frame_count, levels) = if options.raw {
+        let bytes = std::fs::metadata(&options.file)?.len() as usize;
+        (None, bytes.div_ceil(polycom_codec.frame_size()), None)
+    } else if cache.is_some() {
+        let audio = encode_cached(cache.as_ref(), &spec, &mut cache_usage, || {
+            let samples = read_audio_file(&options.file, spec.sample_rate, &options.input)?;
+            encode_frames(codec, &samples).map(|audio| audio.with_levels(AudioLevels::measure(&samples, spec.sample_rate)))
+        })?;
+        let levels = match audio.levels {
+            Some(levels) => levels,
+            None => measure_file(&options.file, spec.sample_rate, &options.input)?,
+        };
+        let count = audio.frames.len();
+        (Some(audio.frames), count, Some(levels))
+    } else {
+        let scan = scan_file(&options.file, spec.sample_rate, &options.input)?;
+        (None, (lead_in.len() + scan.samples).div_ceil(spec.frame_samples), Some(scan.levels))
+    };
+
+    let mut warnings = Vec::new();
+    let mut problems = Vec::new();
+    if !options.raw {
+        warnings.extend(options.input.inferred_notice(&options.file));
+    }
+
+    // Apply the duration safety cap (emergency pages are never cut short)
+    let mut truncated_frames = 0;
+    if let Some(max_secs) = options.max_duration_secs {
+        if !is_emergency_channel(options.channel) {
+            truncated_frames = apply_duration_cap(&mut frame_count, polycom_codec, max_secs);
+            if truncated_frames > 0 {
+                warnings.push(format!(
+                    "Transmission truncated to {}s ({} frames dropped)",
+                    max_secs, truncated_frames
+                ));
+            }
+        }
+    }
+    let settings = StreamSettings {
+        loop_audio: options.loop_audio,
+        limit: (truncated_frames > 0).then_some(frame_count),
+        watermark: None,
+    };
+    let mut frames = match encoded.take() {
+        Some(mut frames) => {
+            frames.truncate(frame_count);
+            FrameSource::encoded(frames)
+        }
+        None if options.raw => {
+            FrameSource::Streamed(FrameStream::raw(&options.file, polycom_codec.frame_size(), settings)?)
+        }
+        None => FrameSource::Streamed(FrameStream::encode(&spec, lead_in, settings)?),
+    };
+
+    // Each other RTP codec is encoded once and sent frame-for-frame with the page
+    let mut rtp_frames = encode_rtp_frames(
+        options,
+        &rtp_legs,
+        polycom_codec,
+        StreamSettings {
+            limit: Some(frame_count),
+            ..settings
+        },
+        cache.as_ref(),
+        &mut cache_usage,
+    )?;
+
+    let classification = restricted_channel_class(options.channel);
+    if let Some(classification) = classification {
+        if !options.allow_priority {
+            problems.push(format!(
+                "Channel {} is a {} channel; a live run would ask for confirmation or need --allow-priority",
+                options.channel_names.label(options.channel),
+                classification
+            ));
+        }
+    }
+    if let Err(e) = PolycomHeader::check_caller_id(&options.caller_id, options.caller_id_encoding, options.caller_id_pad) {
+        problems.push(e.to_string());
+    }
+    if options.caller_id.contains('\0') {
+        problems.push("Caller ID contains a NUL byte; phones stop displaying it there".to_string());
+    }
+
+    // Size packets with a throwaway builder; the real one starts fresh
+    let silence = vec![0u8; polycom_codec.frame_size()];
+    let packet_bytes = packet_builder(options, [0; 4], polycom_codec)
+        .build_transmit(first_frame(&mut frames)?.as_ref().unwrap_or(&silence))
+        .map_or(0, |packet| packet.len());
+
+    let (alert_packets, alert_ms) = if options.skip_alert {
+        (0, 0)
+    } else {
+        (
+            options.alert_count,
+            u64::from(options.alert_count.saturating_sub(1)) * options.control_interval + ALERT_TO_AUDIO_MS,
+        )
+    };
+    let (end_packets, end_ms) = if options.skip_end {
+        (0, 0)
+    } else {
+        (
+            options.end_count,
+            AUDIO_TO_END_MS + u64::from(options.end_count.saturating_sub(1)) * options.control_interval,
+        )
+    };
+    let audio_secs = frame_count as f64 * f64::from(frame_ms) / 1000.0;
+
+    let mut plan = TransmitPlan {
+        command: "polycom-transmit",
+        file: options.file.display().to_string(),
+        destination: DestinationPlan::new(options.address, options.port, options.ttl, options.dscp),
+        codec: polycom_codec.to_string(),
+        payload_type: None,
+        sample_rate: polycom_codec.sample_rate(),
+        frame_ms,
+        frames: frame_count,
+        audio_secs,
+        chime: chime.clone(),
+        levels: None,
+        packet_bytes,
+        bandwidth_kbps: bandwidth_kbps(packet_bytes, frame_ms),
+        loop_audio: options.loop_audio,
+        schedule: None,
+        polycom: Some(PolycomPlan {
+            channel: options.channel,
+            channel_name: options.channel_names.name(options.channel).map(str::to_string),
+            mode: if options.channel <= 25 { "ptt" } else { "paging" },
+            classification,
+            caller_id: options.caller_id.clone(),
+            alert_packets,
+            end_packets,
+            control_interval_ms: options.control_interval,
+            alert_secs: alert_ms as f64 / 1000.0,
+            end_secs: end_ms as f64 / 1000.0,
+            page_secs: (alert_ms + end_ms) as f64 / 1000.0 + audio_secs,
+            truncated_frames,
+        }),
+        also_rtp: Vec::new(),
+        zones: Vec::new(),
+        warnings,
+        problems,
+    };
+    plan.check_destination();
+    let silent = levels.and_then(|levels| plan.check_levels(levels, options.levels));
+    plan.check_schedule(&options.schedule, options.wait_for_window);
+    for leg in &rtp_legs {
+        let codec = leg.leg.codec;
+        let payload = match rtp_frames.get_mut(&codec) {
+            Some(frames) => first_frame(frames)?,
+            None => first_frame(&mut frames)?,
+        }
+        .unwrap_or_default();
+        let packet_bytes = RtpPacket::build(codec.payload_type(), 0, 0, 0, &payload, false).len();
+        plan.add_rtp_leg(RtpLegPlan {
+            address: leg.leg.address,
+            port: leg.leg.port,
+            codec: codec.name().to_string(),
+            payload_type: codec.payload_type(),
+            packet_bytes,
+            bandwidth_kbps: bandwidth_kbps(packet_bytes, frame_ms),
+            source_address: None,
+        });
+    }
+    // A dry run reports it as a problem instead
+    if let Some(reason) = silent.filter(|_| !options.dry_run) {
+        return Err(PolycomTransmitError::SilentAudio(reason));
+    }
+
+    Ok((
+        plan,
+        PreparedPage {
+            codec: polycom_codec,
+            frames,
+            frame_count,
+            rtp_legs,
+            rtp_frames,
+            chime,
+            cache_usage: cache.is_some().then_some(cache_usage),
+        },
+    ))
+}
+
+/// Send the planned page
+async fn execute_polycom_transmit(
+    options: &PolycomTransmitOptions,
+    page: PreparedPage,
+    events: &mut TransmitEvents,
+) -> Result<(), PolycomTransmitError> {
+    let PreparedPage {
+        codec: polycom_codec,
+        mut frames,
+        frame_count,
+        mut rtp_legs,
+        mut rtp_frames,
+        chime,
+        cache_usage,
+    } = page;
+
     // Create transmit socket
-    let socket = create_transmit_socket(options.ttl).await?;
+    let socket = create_transmit_socket(options.ttl, options.dscp).await?;
     let dest = SocketAddrV4::new(options.address, options.port);
-
-    // Get sample rate for audio decoding (G.722 needs 16kHz, G.711 needs 8kHz)
-    let sample_rate = polycom_codec.sample_rate();
+    // Listening from the start keeps a receipt that beats the last End packet
+    let receipt_listener = match options.await_receipts {
+        Some((_, listen)) => Some(ReceiptListener::open(listen).await?),
+        None => None,
+    };
 
     // Generate a pseudo-random host serial from current time
     let host_serial = generate_host_serial();
-
-    // Create packet builder
-    let mut builder = PolycomPacketBuilder::new(
-        options.channel,
-        host_serial,
-        options.caller_id.clone(),
-        polycom_codec,
-    );
-    builder.set_skip_redundant(options.no_redundant);
-    builder.set_skip_audio_header(options.no_audio_header);
-    builder.set_little_endian(options.little_endian);
+    let mut builder = packet_builder(options, host_serial, polycom_codec);
 
     if !options.quiet {
         println!("Polycom Paging Transmit");
         println!("  File: {}", options.file.display());
         println!("  Destination: {}:{}", options.address, options.port);
-        println!("  Channel: {}", options.channel);
+        println!("  Channel: {}", options.channel_names.label(options.channel));
         println!("  Codec: {}", polycom_codec);
-        println!("  Caller ID: {}", options.caller_id);
+        println!("  Caller ID: {} ({}, {}-byte field)", options.caller_id, options.caller_id_encoding, options.caller_id_pad);
         println!("  TTL: {}", options.ttl);
+        if let Some(dscp) = options.dscp {
+            println!("  DSCP: {}", dscp);
+        }
+        println!(
+            "  Audio: {:.1}s ({} frames)",
+            frame_count as f64 * f64::from(polycom_codec.frame_duration_ms()) / 1000.0,
+            frame_count
+        );
+        if let Some(ref chime) = chime {
+            println!("  Chime: {} ({:.2}s, then {:.2}s of silence)", chime.chime, chime.chime_secs, chime.gap_secs);
+        }
+        for leg in &options.also_rtp {
+            println!("  Also RTP: {}:{} ({})", leg.address, leg.port, leg.codec.name());
+        }
+        if options.schedule.is_restricted() {
+            println!("  Allowed windows: {}", options.schedule);
+        }
+        if let Some(usage) = cache_usage {
+            print_cache_usage(&usage);
+        }
         println!();
     }
 
-    loop {
-        // === Prepare audio frames ===
-        let frame_size = polycom_codec.frame_size();
-        let frame_duration = Duration::from_millis(polycom_codec.frame_duration_ms() as u64);
-
-        let encoded_frames: Vec<Vec<u8>> = if options.raw {
-            // Raw mode: read pre-encoded audio file directly
-            if !options.quiet {
-                print!("  Reading raw audio frames...");
-                io::stdout().flush().ok();
-            }
-
-            let raw_data = std::fs::read(&options.file)?;
-            let frames: Vec<Vec<u8>> = raw_data
-                .chunks(frame_size)
-                .map(|chunk| {
-                    if chunk.len() < frame_size {
-                        let mut padded = chunk.to_vec();
-                        padded.resize(frame_size, 0);
-                        padded
-                    } else {
-                        chunk.to_vec()
-                    }
-                })
-                .collect();
-
-            if !options.quiet {
-                let duration = frames.len() as f64 * polycom_codec.frame_duration_ms() as f64 / 1000.0;
-                println!(" {} frames ({:.1}s)", frames.len(), duration);
-            }
-
-            frames
-        } else {
-            // Normal mode: decode WAV and encode to codec using ffmpeg
-            let samples = read_audio_file(&options.file, sample_rate)?;
-
-            if !options.quiet {
-                let duration = samples.len() as f64 / sample_rate as f64;
-                println!("  Duration: {:.1}s ({} samples)", duration, samples.len());
-                print!("  Encoding audio frames with ffmpeg...");
-                io::stdout().flush().ok();
-            }
-
-            // Use ffmpeg subprocess for all codecs (consistent quality)
-            let frames: Vec<Vec<u8>> = match polycom_codec {
-                PolycomCodec::G722 => {
-                    let mut encoder = FfmpegG722Encoder::new()?;
-                    encoder.encode_all(&samples)?
-                }
-                PolycomCodec::G711U => {
-                    let mut encoder = FfmpegG711UlawEncoder::new()?;
-                    encoder.encode_all(&samples)?
-                }
-                PolycomCodec::G711A => {
-                    let mut encoder = FfmpegG711AlawEncoder::new()?;
-                    encoder.encode_all(&samples)?
-                }
-            };
+    // Impairments apply to audio packets after they are built; control packets are left alone
+    let frame_duration = Duration::from_millis(polycom_codec.frame_duration_ms() as u64);
+    let mut impairer = options
+        .impairment
+        .is_active()
+        .then(|| Impairer::new(options.impairment.clone(), frame_duration));
+    let mut packets_sent: u64 = 0;
+    let mut window_waits: Vec<WindowWait> = Vec::new();
+    let transmit_start = Instant::now();
 
-            if !options.quiet {
-                println!(" {} frames", frames.len());
-            }
+    loop {
+        // Every page, including each loop iteration, must start inside an allowed window
+        if let Some(wait) = enforce_schedule(
+            &options.schedule,
+            options.wait_for_window,
+            options.address,
+            options.port,
+            options.quiet,
+            options.json,
+        )
+        .await?
+        {
+            window_waits.push(wait);
+        }
 
-            frames
-        };
+        for (index, leg) in rtp_legs.iter_mut().enumerate() {
+            leg.restart(index);
+        }
+        events.next_pass();
 
         // === Phase 1: Send Alert packets ===
         if !options.skip_alert {
@@ -223,10 +745,11 @@ pub async fn run_polycom_transmit(options: PolycomTransmitOptions) -> Result<(),
             if !options.quiet {
                 println!(" done");
             }
+            events.phase("alert", options.alert_count);
 
             // Critical: Delay before starting audio (Polycom uses ~64ms)
             // This gives receivers time to initialize audio playback
-            tokio::time::sleep(Duration::from_millis(64)).await;
+            tokio::time::sleep(Duration::from_millis(ALERT_TO_AUDIO_MS)).await;
         } else if !options.quiet {
             println!("  Skipping Alert packets");
         }
@@ -238,36 +761,80 @@ pub async fn run_polycom_transmit(options: PolycomTransmitOptions) -> Result<(),
         }
 
         // Transmit with precise timing - sleep BEFORE each packet to maintain exact 20ms intervals
-        let total_frames = encoded_frames.len();
         let mut next_send_time = Instant::now();
+        let mut i = 0;
 
-        for (i, polycom_frame) in encoded_frames.into_iter().enumerate() {
-            // Wait until the exact time to send this packet
+        while let Some(polycom_frame) = frames.next_frame()? {
+            // Wait until the exact time to send this packet (plus any simulated jitter)
+            let jitter = impairer.as_mut().map_or(Duration::ZERO, Impairer::jitter);
             let now = Instant::now();
-            if next_send_time > now {
-                tokio::time::sleep(next_send_time - now).await;
+            // Pacing drift leaves out the simulated jitter, which is deliberate
+            let drift = now.saturating_duration_since(next_send_time);
+            if next_send_time + jitter > now {
+                tokio::time::sleep(next_send_time + jitter - now).await;
             }
 
             // Build and send packet
             let packet = builder.build_transmit(&polycom_frame)?;
-            socket.send_to(&packet, dest).await?;
+            if let Some(ref mut impairer) = impairer {
+                for wire_packet in impairer.process(packet) {
+                    socket.send_to(&wire_packet, dest).await?;
+                    packets_sent += 1;
+                }
+            } else {
+                socket.send_to(&packet, dest).await?;
+                packets_sent += 1;
+            }
+
+            // RTP legs ride the same deadline; alert and end stay Polycom-only
+            let polycom_sent_at = Instant::now();
+            let mut rtp_tick = HashMap::with_capacity(rtp_frames.len());
+            for (codec, source) in &mut rtp_frames {
+                rtp_tick.insert(*codec, source.next_frame()?);
+            }
+            for leg in &mut rtp_legs {
+                let frame = match rtp_tick.get(&leg.leg.codec) {
+                    Some(frame) => frame.as_deref(),
+                    None => Some(polycom_frame.as_slice()),
+                };
+                if let Some(frame) = frame {
+                    let packet = leg.next_packet(frame);
+                    socket.send_to(&packet, leg.dest()).await?;
+                    leg.packets_sent += 1;
+                    leg.max_skew = leg.max_skew.max(polycom_sent_at.elapsed());
+                }
+            }
+
+            events.frame_sent(i, drift);
 
             // Schedule next packet for exactly 20ms later
             next_send_time += frame_duration;
 
             // Progress update (only every second to minimize output overhead)
             if !options.quiet && (i + 1).is_multiple_of(50) {
-                let progress = 100.0 * (i + 1) as f64 / total_frames as f64;
+                let progress = 100.0 * (i + 1) as f64 / frame_count as f64;
                 print!("\r  Transmitting audio... {:.1}%   ", progress);
                 io::stdout().flush().ok();
             }
+            i += 1;
+        }
+        frames.finish_pass()?;
+        for source in rtp_frames.values_mut() {
+            source.finish_pass()?;
+        }
+
+        // Release a packet still held back for reordering before the End packets
+        if let Some(packet) = impairer.as_mut().and_then(Impairer::flush) {
+            socket.send_to(&packet, dest).await?;
+            packets_sent += 1;
         }
 
         if !options.quiet {
             println!("\r  Transmitting audio... 100.0% - Complete");
         }
 
-        let frames_sent = total_frames as u32;
+        let frames_sent = i as u32;
+        events.phase("audio", frames_sent);
 
         // === Phase 3: Send End packets ===
         if !options.skip_end {
@@ -276,7 +843,7 @@ pub async fn run_polycom_transmit(options: PolycomTransmitOptions) -> Result<(),
                 io::stdout().flush().ok();
             }
 
-            tokio::time::sleep(Duration::from_millis(50)).await;
+            tokio::time::sleep(Duration::from_millis(AUDIO_TO_END_MS)).await;
 
             if !options.quiet {
                 println!(" done");
@@ -297,6 +864,7 @@ pub async fn run_polycom_transmit(options: PolycomTransmitOptions) -> Result<(),
             if !options.quiet {
                 println!(" done");
             }
+            events.phase("end", options.end_count);
         } else if !options.quiet {
             println!("  Skipping End packets");
         }
@@ -318,155 +886,246 @@ pub async fn run_polycom_transmit(options: PolycomTransmitOptions) -> Result<(),
         }
     }
 
+    let impairments = impairer.as_ref().map(Impairer::stats);
+    let also_rtp: Vec<RtpLegReport> = rtp_legs.iter().map(RtpLegSender::report).collect();
+    if options.json {
+        output_json(&JsonEvent::TransmitReport {
+            timestamp: Utc::now(),
+            address: options.address.to_string(),
+            port: options.port,
+            codec: polycom_codec.to_string(),
+            packets_sent,
+            duration_secs: transmit_start.elapsed().as_secs_f64(),
+            chime,
+            impairments,
+            window_waits,
+            also_rtp,
+            frame_cache: cache_usage,
+            retransmits: None,
+        });
+    } else if !options.quiet {
+        if !also_rtp.is_empty() {
+            println!("Destinations:");
+            println!(
+                "  Polycom {}:{} ({}): {} packets",
+                options.address, options.port, polycom_codec, packets_sent
+            );
+            for report in &also_rtp {
+                println!(
+                    "  RTP {}:{} ({}): {} packets, max skew {:.2}ms",
+                    report.address, report.port, report.codec, report.packets_sent, report.max_skew_ms
+                );
+            }
+        }
+        if let Some(ref stats) = impairments {
+            print_impairment_summary(stats);
+        }
+    }
+    if let (Some(listener), Some((wait, _))) = (receipt_listener, options.await_receipts) {
+        let receipts = listener.collect(wait).await;
+        report_receipts(options, wait, &receipts);
+        if receipts.len() < wait.count {
+            return Err(PolycomTransmitError::MissingReceipts {
+                expected: wait.count,
+                received: receipts.len(),
+            });
+        }
+    }
+    events.completed(packets_sent);
+
     Ok(())
 }
 
-/// Read an audio file and return samples at the target sample rate
-fn read_audio_file(path: &Path, target_rate: u32) -> Result<Vec<i16>, PolycomTransmitError> {
-    let file = std::fs::File::open(path)?;
-    let mss = MediaSourceStream::new(Box::new(file), MediaSourceStreamOptions::default());
-
-    let mut hint = Hint::new();
-    if let Some(ext) = path.extension() {
-        hint.with_extension(&ext.to_string_lossy());
+/// Print the receipts that arrived, or emit them as a `receipts` event
+fn report_receipts(options: &PolycomTransmitOptions, wait: ReceiptWait, receipts: &[ReceivedReceipt]) {
+    if options.json {
+        output_json(&JsonEvent::Receipts {
+            timestamp: Utc::now(),
+            address: options.address.to_string(),
+            port: options.port,
+            expected: wait.count,
+            receipts: receipts.to_vec(),
+        });
+    } else if !options.quiet {
+        println!("Receipts: {} of {} expected", receipts.len(), wait.count);
+        for received in receipts {
+            let receipt = &received.receipt;
+            println!(
+                "  {}: channel {}, {:.1}s, {} audio packets, {} frames recovered, {} concealed, {} late",
+                received.source,
+                options.channel_names.label(receipt.channel),
+                receipt.duration_secs,
+                receipt.audio_packets,
+                receipt.frames_recovered,
+                receipt.frames_concealed,
+                receipt.frames_late
+            );
+        }
     }
+}
 
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
-        .map_err(|e| PolycomTransmitError::UnsupportedFormat(e.to_string()))?;
-
-    let mut format = probed.format;
-
-    let track = format
-        .tracks()
-        .iter()
-        .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
-        .ok_or_else(|| PolycomTransmitError::UnsupportedFormat("No audio track found".into()))?;
-
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &DecoderOptions::default())
-        .map_err(|e| PolycomTransmitError::UnsupportedFormat(e.to_string()))?;
-
-    let track_id = track.id;
-    let source_rate = track.codec_params.sample_rate.unwrap_or(target_rate);
-    let channels = track.codec_params.channels.map(|c| c.count()).unwrap_or(1);
+/// Which implementation [`encode_frames`] uses for `codec`
+fn encoder_name(codec: CodecType) -> &'static str {
+    let ffmpeg = ffmpeg_support();
+    match codec {
+        CodecType::G722 => frame_cache::FFMPEG,
+        CodecType::G711Ulaw if ffmpeg.pcm_mulaw.encode => frame_cache::FFMPEG,
+        CodecType::G711Alaw if ffmpeg.pcm_alaw.encode => frame_cache::FFMPEG,
+        _ => frame_cache::BUILTIN,
+    }
+}
 
-    let mut samples: Vec<i16> = Vec::new();
+/// How [`encode_frames`] encodes `file` into frames of `frame_ms`, for the cache
+fn encode_spec<'a>(
+    file: &'a Path,
+    input: &AudioInput,
+    codec: CodecType,
+    sample_rate: u32,
+    frame_ms: u32,
+) -> EncodeSpec<'a> {
+    EncodeSpec {
+        source: file,
+        codec,
+        encoder: encoder_name(codec),
+        sample_rate,
+        frame_samples: (u64::from(sample_rate) * u64::from(frame_ms) / 1000) as usize,
+        input: *input,
+    }
+}
 
-    loop {
-        let packet = match format.next_packet() {
-            Ok(p) => p,
-            Err(symphonia::core::errors::Error::IoError(_)) => break, // EOF
-            Err(e) => return Err(PolycomTransmitError::AudioDecode(e.to_string())),
-        };
+/// Encode a whole file's samples into 20ms frames of `codec`.
+/// G.711 and G.722 go through ffmpeg for consistent quality; G.711 falls
+/// back to the built-in encoder where ffmpeg can't encode it.
+fn encode_frames(codec: CodecType, samples: &[i16]) -> Result<EncodedAudio, PolycomTransmitError> {
+    let ffmpeg = ffmpeg_support();
+    let frames = match codec {
+        CodecType::G722 => FfmpegG722Encoder::new()?.encode_all(samples)?,
+        CodecType::G711Ulaw if ffmpeg.pcm_mulaw.encode => FfmpegG711UlawEncoder::new()?.encode_all(samples)?,
+        CodecType::G711Alaw if ffmpeg.pcm_alaw.encode => FfmpegG711AlawEncoder::new()?.encode_all(samples)?,
+        _ => {
+            let mut encoder = create_encoder(codec)?;
+            let frame_size = encoder.frame_size();
+            let mut frames = Vec::with_capacity(samples.len().div_ceil(frame_size));
+            for chunk in samples.chunks(frame_size) {
+                let mut frame = chunk.to_vec();
+                frame.resize(frame_size, 0);
+                frames.push(encoder.encode(&frame)?);
+            }
+            frames
+        }
+    };
+    Ok(EncodedAudio {
+        frames,
+        samples: samples.len(),
+        levels: None,
+    })
+}
 
-        if packet.track_id() != track_id {
+/// Encode the page once for each codec used by the RTP legs other than the
+/// page's own, whose legs reuse the Polycom frames. Every stream is cut to
+/// the Polycom frame count, the limit of `settings`, so all destinations
+/// carry the same audio, the chime made again at each codec's rate.
+fn encode_rtp_frames(
+    options: &PolycomTransmitOptions,
+    legs: &[RtpLegSender],
+    polycom_codec: PolycomCodec,
+    settings: StreamSettings,
+    cache: Option<&FrameCache>,
+    cache_usage: &mut CacheUsage,
+) -> Result<HashMap<CodecType, FrameSource>, PolycomTransmitError> {
+    let mut by_codec: HashMap<CodecType, FrameSource> = HashMap::new();
+    for leg in legs {
+        let codec = leg.leg.codec;
+        if codec == polycom_codec_type(polycom_codec) || by_codec.contains_key(&codec) {
             continue;
         }
-
-        let decoded = decoder
-            .decode(&packet)
-            .map_err(|e| PolycomTransmitError::AudioDecode(e.to_string()))?;
-
-        // Convert to i16 samples
-        let frame_samples = convert_to_i16(&decoded);
-
-        // Mix to mono if stereo
-        let mono_samples: Vec<i16> = if channels > 1 {
-            frame_samples
-                .chunks(channels)
-                .map(|chunk| {
-                    let sum: i32 = chunk.iter().map(|&s| s as i32).sum();
-                    (sum / channels as i32) as i16
-                })
-                .collect()
+        let sample_rate = create_encoder(codec)?.sample_rate();
+        let (file, input) = (&options.file, &options.input);
+        let spec = encode_spec(file, input, codec, sample_rate, polycom_codec.frame_duration_ms());
+        let frames = if cache.is_some() {
+            let mut frames = encode_cached(cache, &spec, cache_usage, || {
+                encode_frames(codec, &read_audio_file(file, sample_rate, input)?)
+            })?
+            .frames;
+            frames.truncate(settings.limit.unwrap_or(usize::MAX));
+            FrameSource::encoded(frames)
         } else {
-            frame_samples
+            let lead_in = match &options.chime {
+                Some(chime) => chime.lead_in(sample_rate)?.0,
+                None => Vec::new(),
+            };
+            FrameSource::Streamed(FrameStream::encode(&spec, lead_in, settings)?)
         };
-
-        samples.extend(mono_samples);
-    }
-
-    // Resample if needed
-    if source_rate != target_rate {
-        samples = simple_resample(&samples, source_rate, target_rate);
+        by_codec.insert(codec, frames);
     }
+    Ok(by_codec)
+}
 
-    Ok(samples)
+/// A copy of the first frame of a pass, to size packets by
+fn first_frame(frames: &mut FrameSource) -> Result<Option<Vec<u8>>, PolycomTransmitError> {
+    Ok(match frames {
+        FrameSource::Encoded { frames, .. } => frames.first().cloned(),
+        FrameSource::Streamed(stream) => stream.peek()?.map(<[u8]>::to_vec),
+    })
 }
 
-/// Convert audio buffer to i16 samples
-fn convert_to_i16(buffer: &AudioBufferRef) -> Vec<i16> {
-    match buffer {
-        AudioBufferRef::S8(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| (s as i16) * 256)
-            .collect(),
-        AudioBufferRef::S16(buf) => buf.chan(0).to_vec(),
-        AudioBufferRef::S32(buf) => buf.chan(0).iter().map(|&s| (s >> 16) as i16).collect(),
-        AudioBufferRef::F32(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| (s * 32767.0).clamp(-32768.0, 32767.0) as i16)
-            .collect(),
-        AudioBufferRef::F64(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| (s * 32767.0).clamp(-32768.0, 32767.0) as i16)
-            .collect(),
-        AudioBufferRef::U8(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| ((s as i16 - 128) * 256))
-            .collect(),
-        AudioBufferRef::U16(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| (s as i32 - 32768) as i16)
-            .collect(),
-        AudioBufferRef::U24(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| ((s.inner() as i32 - 8_388_608) >> 8) as i16)
-            .collect(),
-        AudioBufferRef::S24(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| (s.inner() >> 8) as i16)
-            .collect(),
-        AudioBufferRef::U32(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| ((s as i64 - (1_i64 << 31)) >> 16) as i16)
-            .collect(),
+/// Get the classification of a channel that needs explicit confirmation, if any
+fn restricted_channel_class(channel: u8) -> Option<&'static str> {
+    if is_emergency_channel(channel) {
+        Some("emergency")
+    } else if is_priority_channel(channel) {
+        Some("priority")
+    } else {
+        None
     }
 }
 
-/// Simple linear interpolation resampling
-fn simple_resample(samples: &[i16], from_rate: u32, to_rate: u32) -> Vec<i16> {
-    let ratio = from_rate as f64 / to_rate as f64;
-    let new_len = (samples.len() as f64 / ratio) as usize;
+/// Refuse to transmit on priority/emergency channels unless explicitly allowed.
+/// When `interactive` is set the operator is asked to confirm instead.
+pub(crate) fn check_channel_interlock(
+    channel: u8,
+    allow_priority: bool,
+    interactive: bool,
+) -> Result<(), PolycomTransmitError> {
+    let Some(classification) = restricted_channel_class(channel) else {
+        return Ok(());
+    };
 
-    (0..new_len)
-        .map(|i| {
-            let pos = i as f64 * ratio;
-            let idx = pos.floor() as usize;
-            let frac = pos.fract();
+    if allow_priority {
+        return Ok(());
+    }
 
-            if idx + 1 >= samples.len() {
-                samples[idx.min(samples.len() - 1)]
-            } else {
-                let a = samples[idx] as f64;
-                let b = samples[idx + 1] as f64;
-                (a + (b - a) * frac) as i16
-            }
-        })
-        .collect()
+    if interactive {
+        print!(
+            "Channel {} is a {} channel and will page every phone at full volume. Continue? [y/N] ",
+            channel, classification
+        );
+        io::stdout().flush().ok();
+
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            return Ok(());
+        }
+    }
+
+    Err(PolycomTransmitError::RestrictedChannel {
+        channel,
+        classification,
+    })
+}
+
+/// Cap a page of `frames` frames at `max_secs` of audio.
+/// Returns the number of frames dropped.
+fn apply_duration_cap(frames: &mut usize, codec: PolycomCodec, max_secs: u64) -> usize {
+    let max_frames = (max_secs * 1000 / codec.frame_duration_ms() as u64) as usize;
+    let dropped = frames.saturating_sub(max_frames);
+    *frames = (*frames).min(max_frames);
+    dropped
 }
 
 /// Generate a pseudo-random host serial (last 4 bytes of MAC)
-fn generate_host_serial() -> [u8; 4] {
+pub(crate) fn generate_host_serial() -> [u8; 4] {
     use std::time::SystemTime;
     let seed = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
@@ -500,15 +1159,118 @@ mod tests {
     }
 
     #[test]
-    fn test_simple_resample() {
-        let samples: Vec<i16> = vec![0, 100, 200, 300, 400, 500, 600, 700];
+    fn test_channel_interlock() {
+        let err = check_channel_interlock(25, false, false).unwrap_err();
+        assert!(matches!(
+            err,
+            PolycomTransmitError::RestrictedChannel { channel: 25, classification: "emergency" }
+        ));
+
+        let err = check_channel_interlock(49, false, false).unwrap_err();
+        assert!(matches!(
+            err,
+            PolycomTransmitError::RestrictedChannel { channel: 49, classification: "priority" }
+        ));
+
+        assert!(check_channel_interlock(25, true, false).is_ok());
+        assert!(check_channel_interlock(26, false, false).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_restricted_channel_rejected_before_transmit() {
+        let options = PolycomTransmitOptions {
+            file: std::path::PathBuf::from("/nonexistent/page.wav"),
+            input: AudioInput::default(),
+            levels: LevelCheck::default(),
+            address: Ipv4Addr::new(224, 0, 1, 116),
+            port: 5001,
+            channel: 25,
+            channel_names: ChannelNames::default(),
+            codec: "g711u".to_string(),
+            caller_id: "MPS-IP".to_string(),
+            caller_id_encoding: CallerIdEncoding::Utf8,
+            caller_id_pad: crate::network::polycom::MIN_CALLER_ID_LEN,
+            ttl: 1,
+            dscp: None,
+            loop_audio: false,
+            quiet: true,
+            alert_count: 31,
+            end_count: 12,
+            control_interval: 30,
+            skip_alert: false,
+            skip_end: false,
+            no_redundant: false,
+            no_audio_header: false,
+            little_endian: false,
+            raw: false,
+            allow_priority: false,
+            max_duration_secs: None,
+            json: false,
+            impairment: ImpairmentConfig::default(),
+            schedule: Schedule::default(),
+            wait_for_window: false,
+            also_rtp: Vec::new(),
+            dry_run: false,
+            cache_dir: None,
+            await_receipts: None,
+            chime: None,
+        };
+
+        let result = run_polycom_transmit(options).await;
+        assert!(matches!(
+            result,
+            Err(PolycomTransmitError::RestrictedChannel { channel: 25, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_rtp_leg() {
+        let leg: RtpLeg = "224.0.1.1:5004".parse().unwrap();
+        assert_eq!(
+            leg,
+            RtpLeg { address: Ipv4Addr::new(224, 0, 1, 1), port: 5004, codec: CodecType::G711Ulaw }
+        );
+
+        let leg: RtpLeg = "239.1.2.3:6000,g722".parse().unwrap();
+        assert_eq!(leg.port, 6000);
+        assert_eq!(leg.codec, CodecType::G722);
+
+        for bad in ["224.0.1.1", "224.0.1.1:port", "nowhere:5004", "224.0.1.1:5004,mp3"] {
+            assert!(
+                matches!(bad.parse::<RtpLeg>(), Err(PolycomTransmitError::InvalidRtpLeg(_))),
+                "{} should be rejected",
+                bad
+            );
+        }
+    }
 
-        // Downsample 2:1
-        let resampled = simple_resample(&samples, 16000, 8000);
-        assert_eq!(resampled.len(), 4);
+    #[test]
+    fn test_rtp_leg_follows_frame_clock() {
+        let leg = RtpLeg { address: Ipv4Addr::new(224, 0, 1, 1), port: 5004, codec: CodecType::G711Ulaw };
+        let mut sender = RtpLegSender::new(leg, 20).unwrap();
+        sender.restart(0);
+        let first = sender.next_packet(&[0xFF; 160]);
+        let second = sender.next_packet(&[0xFF; 160]);
+
+        let source = "192.168.1.10:5004".parse().unwrap();
+        let first = RtpPacket::parse(&first, source).unwrap();
+        let second = RtpPacket::parse(&second, source).unwrap();
+        assert_eq!(second.header.sequence_number, first.header.sequence_number.wrapping_add(1));
+        assert_eq!(second.header.timestamp.wrapping_sub(first.header.timestamp), 160);
+        assert_eq!(first.header.ssrc, second.header.ssrc);
+    }
 
-        // Upsample 1:2
-        let resampled = simple_resample(&samples, 8000, 16000);
-        assert_eq!(resampled.len(), 16);
+    #[test]
+    fn test_duration_cap() {
+        // 10 seconds of G.711 at 20ms per frame
+        let mut frames = 500;
+        let dropped = apply_duration_cap(&mut frames, PolycomCodec::G711U, 3);
+        assert_eq!(frames, 150);
+        assert_eq!(dropped, 350);
+
+        // Shorter than the cap - untouched
+        let mut frames = 100;
+        assert_eq!(apply_duration_cap(&mut frames, PolycomCodec::G722, 3), 0);
+        assert_eq!(frames, 100);
     }
 }
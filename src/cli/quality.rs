@@ -0,0 +1,313 @@
+//! MOS-style quality score from a page's existing metrics.
+//!
+//! A simplified ITU-T G.107 E-model. The transmission rating R starts at
+//! 93.2, the default for a clean narrowband call, and each impairment takes
+//! points off:
+//!
+//! ```text
+//! R = 93.2 - Id - Ie_eff - Ig - Ic
+//! ```
+//!
+//! - `Id` (delay): multicast has no return path, so only the receiver's
+//!   playout delay counts. That is taken as twice the RTP jitter plus 10ms,
+//!   `d`, and costs `d / 40` below 160ms and `(d - 120) / 10` above.
+//! - `Ie_eff` (codec and loss): `Ie + (95 - Ie) * Ppl / (Ppl / BurstR + Bpl)`,
+//!   with the codec's `Ie` and `Bpl` from [`CodecImpairment`], `Ppl` the loss
+//!   percentage and `BurstR` how much burstier the loss was than random.
+//! - `Ig` (glitches): 2 points per glitch per minute, at most 20. Not part of
+//!   the E-model; it stands in for audible faults the network figures miss.
+//! - `Ic` (clipping): 5 points per percent of clipped samples, at most 15.
+//!
+//! R maps to MOS with the G.107 formula, `1 + 0.035R + 7e-6 R(R-60)(100-R)`,
+//! so a clean G.711 page scores about 4.4. Everything comes from figures
+//! already in the page summary; nothing extra is captured.
+
+use crate::cli::audio_analyzer::AudioStats;
+use crate::cli::monitor::{GapHistogram, PageStats};
+use crate::codec::CodecType;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+/// Rating of a clean narrowband call (G.107 defaults)
+const R0: f64 = 93.2;
+
+/// Codec delay added to the playout buffer for the delay term (ms)
+const CODEC_DELAY_MS: f64 = 10.0;
+
+/// MOS from which a page counts as good (R 80, users satisfied)
+pub const GOOD_MOS: f64 = 4.0;
+
+/// MOS below which many users would be dissatisfied (R 70)
+pub const FAIR_MOS: f64 = 3.6;
+
+/// E-model equipment impairment of a codec
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CodecImpairment {
+    /// Impairment with no loss
+    pub ie: f64,
+    /// Robustness against packet loss; higher conceals loss better
+    pub bpl: f64,
+}
+
+impl CodecImpairment {
+    /// G.113 values where they exist. Lost packets are recorded as silence,
+    /// as most paging speakers play them, so PCM codecs use the figures for
+    /// no packet loss concealment. The scale is narrowband: G.722 can't
+    /// score above G.711 here, so its wider audio band isn't credited.
+    /// Opus isn't in G.113; it gets a small coding impairment and the
+    /// loss robustness of a codec with built-in concealment.
+    pub const fn for_codec(codec: CodecType) -> Self {
+        match codec {
+            CodecType::G711Ulaw | CodecType::G711Alaw | CodecType::G722 | CodecType::L16 | CodecType::L24 => {
+                Self { ie: 0.0, bpl: 4.3 }
+            }
+            CodecType::Opus => Self { ie: 5.0, bpl: 20.0 },
+        }
+    }
+}
+
+/// What went into a page's score
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QualityInputs {
+    pub loss_percent: f64,
+    pub jitter_ms: f64,
+    pub packets_lost: u64,
+    /// Separate runs of lost or late packets
+    pub loss_events: u64,
+    pub glitches: u64,
+    pub clipping_percent: f64,
+    pub duration_secs: f64,
+}
+
+impl QualityInputs {
+    /// Inputs for a finished page
+    pub fn from_page(stats: &PageStats, audio: &AudioStats, duration_secs: f64) -> Self {
+        Self {
+            loss_percent: stats.loss_percent(),
+            jitter_ms: stats.jitter_ms,
+            packets_lost: stats.packets_lost,
+            loss_events: Self::loss_events(&stats.gap_histogram),
+            glitches: audio.total_glitches,
+            clipping_percent: audio.clipping_percent(),
+            duration_secs,
+        }
+    }
+
+    /// Gaps of 30ms or more between arrivals, each a run of missing or late
+    /// packets at the usual 20ms packet time
+    fn loss_events(gaps: &GapHistogram) -> u64 {
+        gaps.from_30_to_60ms + gaps.from_60_to_120ms + gaps.from_120_to_300ms + gaps.over_300ms
+    }
+
+    /// `BurstR`: mean run of lost packets against what random loss gives.
+    /// 1 for random loss, and when the gaps can't tell.
+    fn burst_ratio(&self) -> f64 {
+        if self.packets_lost == 0 || self.loss_events == 0 {
+            return 1.0;
+        }
+        let mean_burst = self.packets_lost as f64 / self.loss_events as f64;
+        (mean_burst * (1.0 - self.loss_percent / 100.0)).max(1.0)
+    }
+}
+
+/// R-factor and the points each impairment took off it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct MosBreakdown {
+    pub r_factor: f64,
+    pub delay: f64,
+    pub codec: f64,
+    pub loss: f64,
+    pub glitches: f64,
+    pub clipping: f64,
+    /// `BurstR` used for the loss term; 1 is random loss
+    pub burst_ratio: f64,
+}
+
+impl MosBreakdown {
+    pub fn mos(&self) -> f64 {
+        r_to_mos(self.r_factor)
+    }
+}
+
+/// Score a page
+pub fn estimate(codec: CodecType, inputs: &QualityInputs) -> MosBreakdown {
+    let impairment = CodecImpairment::for_codec(codec);
+
+    let delay_ms = 2.0 * inputs.jitter_ms + CODEC_DELAY_MS;
+    let delay = if delay_ms < 160.0 {
+        delay_ms / 40.0
+    } else {
+        (delay_ms - 120.0) / 10.0
+    };
+
+    let ppl = inputs.loss_percent.clamp(0.0, 100.0);
+    let burst_ratio = inputs.burst_ratio();
+    let ie_eff = impairment.ie + (95.0 - impairment.ie) * ppl / (ppl / burst_ratio + impairment.bpl);
+
+    let minutes = inputs.duration_secs / 60.0;
+    let glitches = if minutes > 0.0 {
+        (2.0 * inputs.glitches as f64 / minutes).min(20.0)
+    } else {
+        0.0
+    };
+    let clipping = (5.0 * inputs.clipping_percent).min(15.0);
+
+    MosBreakdown {
+        r_factor: (R0 - delay - ie_eff - glitches - clipping).clamp(0.0, 100.0),
+        delay,
+        codec: impairment.ie,
+        loss: ie_eff - impairment.ie,
+        glitches,
+        clipping,
+        burst_ratio,
+    }
+}
+
+/// G.107 R-factor to MOS
+pub fn r_to_mos(r: f64) -> f64 {
+    if r <= 0.0 {
+        1.0
+    } else if r >= 100.0 {
+        4.5
+    } else {
+        1.0 + 0.035 * r + 7e-6 * r * (r - 60.0) * (100.0 - r)
+    }
+}
+
+/// Duration-weighted mean of page scores, built up as pages end
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MeanMos {
+    weighted: f64,
+    duration: f64,
+}
+
+impl MeanMos {
+    pub fn add(&mut self, secs: f64, breakdown: &MosBreakdown) {
+        self.weighted += breakdown.mos() * secs;
+        self.duration += secs;
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.duration > 0.0).then(|| self.weighted / self.duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clean() -> QualityInputs {
+        QualityInputs {
+            duration_secs: 60.0,
+            ..QualityInputs::default()
+        }
+    }
+
+    /// Random loss: every lost packet is its own event
+    fn random_loss(loss_percent: f64, packets: u64) -> QualityInputs {
+        let packets_lost = (packets as f64 * loss_percent / 100.0).round() as u64;
+        QualityInputs {
+            loss_percent,
+            packets_lost,
+            loss_events: packets_lost,
+            ..clean()
+        }
+    }
+
+    fn mos(codec: CodecType, inputs: QualityInputs) -> f64 {
+        estimate(codec, &inputs).mos()
+    }
+
+    #[test]
+    fn test_clean_pages() {
+        let g711 = mos(CodecType::G711Ulaw, clean());
+        assert!((g711 - 4.40).abs() < 0.01, "{}", g711);
+        assert!((mos(CodecType::G722, clean()) - g711).abs() < 1e-9);
+        let opus = mos(CodecType::Opus, clean());
+        assert!((opus - 4.29).abs() < 0.01, "{}", opus);
+    }
+
+    #[test]
+    fn test_loss() {
+        let one = mos(CodecType::G711Ulaw, random_loss(1.0, 3000));
+        assert!((one - 3.82).abs() < 0.01, "{}", one);
+        let five = mos(CodecType::G711Ulaw, random_loss(5.0, 3000));
+        assert!(five < 3.5, "{}", five);
+        assert!((five - 2.16).abs() < 0.01, "{}", five);
+
+        // Opus conceals loss better
+        let opus = mos(CodecType::Opus, random_loss(5.0, 3000));
+        assert!((opus - 3.59).abs() < 0.01, "{}", opus);
+    }
+
+    #[test]
+    fn test_bursty_loss_scores_lower() {
+        let random = random_loss(2.0, 3000);
+        let bursty = QualityInputs {
+            loss_events: random.packets_lost / 6,
+            ..random
+        };
+        let breakdown = estimate(CodecType::G711Ulaw, &bursty);
+        assert!((breakdown.burst_ratio - 5.88).abs() < 1e-9);
+        assert!(breakdown.mos() < mos(CodecType::G711Ulaw, random) - 0.1);
+
+        // Late packets without loss don't make loss bursty
+        let late = QualityInputs {
+            loss_events: 40,
+            ..clean()
+        };
+        assert!((estimate(CodecType::G711Ulaw, &late).burst_ratio - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_jitter_glitches_and_clipping() {
+        let jittery = estimate(
+            CodecType::G711Ulaw,
+            &QualityInputs {
+                jitter_ms: 100.0,
+                ..clean()
+            },
+        );
+        assert!((jittery.delay - 9.0).abs() < 1e-9);
+        assert!((jittery.mos() - 4.17).abs() < 0.01, "{}", jittery.mos());
+
+        let faulty = estimate(
+            CodecType::G711Ulaw,
+            &QualityInputs {
+                glitches: 3,
+                clipping_percent: 1.0,
+                ..clean()
+            },
+        );
+        assert!((faulty.glitches - 6.0).abs() < 1e-9);
+        assert!((faulty.clipping - 5.0).abs() < 1e-9);
+        assert!((faulty.r_factor - (R0 - 0.25 - 11.0)).abs() < 1e-9);
+
+        // Penalties are capped, and R never goes below zero
+        let worst = estimate(
+            CodecType::G711Ulaw,
+            &QualityInputs {
+                loss_percent: 100.0,
+                packets_lost: 1000,
+                loss_events: 1,
+                glitches: 1000,
+                clipping_percent: 50.0,
+                ..clean()
+            },
+        );
+        assert!((worst.glitches - 20.0).abs() < 1e-9);
+        assert!((worst.clipping - 15.0).abs() < 1e-9);
+        assert!((worst.mos() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_mean_mos_weights_by_duration() {
+        let good = estimate(CodecType::G711Ulaw, &clean());
+        let bad = estimate(CodecType::G711Ulaw, &random_loss(5.0, 3000));
+        let mut mean = MeanMos::default();
+        assert_eq!(mean.mean(), None);
+        mean.add(30.0, &good);
+        mean.add(10.0, &bad);
+        assert!((mean.mean().unwrap() - (3.0 * good.mos() + bad.mos()) / 4.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,232 @@
+//! Offline re-analysis of test recordings (`review --reanalyze`)
+//!
+//! The audio figures in summary.json are worked out live, with the speech
+//! thresholds. This runs each page's recording through the analyzer again,
+//! optionally under another profile, and compares the result with what was
+//! captured. No audio hardware is involved.
+
+use crate::cli::audio_analyzer::{analyze_wav, AnalysisProfile};
+use crate::cli::classifier::ClassifierThresholds;
+use crate::cli::transcoding::TranscodingThresholds;
+use crate::cli::schema::SCHEMA_VERSION;
+use crate::cli::test::{AudioSummary, PageSummary};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Written to the results directory by `review --reanalyze --json`
+pub const REANALYSIS_FILE: &str = "reanalysis.json";
+
+/// Levels further apart than this (dB) are a difference
+const LEVEL_TOLERANCE_DB: f64 = 1.0;
+
+/// Dominant frequencies further apart than this are a difference; the
+/// analyzer reports them in 50Hz bins
+const FREQUENCY_TOLERANCE_HZ: f64 = 50.0;
+
+/// Zero-crossing rates differing by more than this fraction are a difference
+const ZCR_TOLERANCE: f64 = 0.1;
+
+/// `reanalysis.json`, the comparison written by `review --reanalyze --json`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Reanalysis {
+    pub schema_version: u32,
+    /// Analysis profile the recordings were run under
+    pub profile: String,
+    pub pages: Vec<PageReanalysis>,
+}
+
+/// One page's captured and recomputed audio figures
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PageReanalysis {
+    pub page_number: u32,
+    /// The page's run-wide id, where summary.json has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_id: Option<u32>,
+    pub endpoint: String,
+    pub recording_file: String,
+    /// As written to summary.json during the test
+    pub captured: AudioSummary,
+    /// From the recording; absent if it couldn't be read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reanalyzed: Option<AudioSummary>,
+    /// Why the recording couldn't be read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Fields whose recomputed value is outside tolerance of the captured one
+    pub differences: Vec<String>,
+}
+
+/// Re-analyze the recordings of `pages`, found in `directory`, classifying
+/// them by `classifier` and checking them for a narrowband hop by
+/// `transcoding`
+pub fn reanalyze<'a>(
+    directory: &Path,
+    pages: impl IntoIterator<Item = &'a PageSummary>,
+    profile: AnalysisProfile,
+    classifier: &ClassifierThresholds,
+    transcoding: &TranscodingThresholds,
+) -> Reanalysis {
+    Reanalysis {
+        schema_version: SCHEMA_VERSION,
+        profile: profile.name().to_string(),
+        pages: pages
+            .into_iter()
+            .map(|page| reanalyze_page(directory, page, profile, classifier, transcoding))
+            .collect(),
+    }
+}
+
+fn reanalyze_page(
+    directory: &Path,
+    page: &PageSummary,
+    profile: AnalysisProfile,
+    classifier: &ClassifierThresholds,
+    transcoding: &TranscodingThresholds,
+) -> PageReanalysis {
+    let (reanalyzed, error) = match analyze_wav(&directory.join(&page.recording_file), profile) {
+        Ok(stats) => (Some(AudioSummary::new(&stats, classifier, transcoding)), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    PageReanalysis {
+        page_number: page.page_number,
+        page_id: page.page_id,
+        endpoint: page.endpoint.clone(),
+        recording_file: page.recording_file.clone(),
+        captured: page.audio.clone(),
+        differences: reanalyzed.as_ref().map(|audio| differences(&page.audio, audio)).unwrap_or_default(),
+        reanalyzed,
+        error,
+    }
+}
+
+/// Names of the fields of `reanalyzed` outside tolerance of `captured`
+pub fn differences(captured: &AudioSummary, reanalyzed: &AudioSummary) -> Vec<String> {
+    let level = |a: f64, b: f64| match (a.is_finite(), b.is_finite()) {
+        (true, true) => (a - b).abs() > LEVEL_TOLERANCE_DB,
+        (finite_a, finite_b) => finite_a != finite_b,
+    };
+    let avg = |a: Option<f64>| a.unwrap_or(f64::NEG_INFINITY);
+    let zcr_base = captured.avg_zero_crossing_rate.max(reanalyzed.avg_zero_crossing_rate);
+
+    [
+        ("peak_rms_db", level(captured.peak_rms_db, reanalyzed.peak_rms_db)),
+        ("avg_rms_db", level(avg(captured.avg_rms_db), avg(reanalyzed.avg_rms_db))),
+        ("max_peak_db", level(captured.max_peak_db, reanalyzed.max_peak_db)),
+        (
+            "dominant_freq_hz",
+            (captured.dominant_freq_hz - reanalyzed.dominant_freq_hz).abs() > FREQUENCY_TOLERANCE_HZ,
+        ),
+        ("total_glitches", captured.total_glitches != reanalyzed.total_glitches),
+        ("total_clipped", captured.total_clipped != reanalyzed.total_clipped),
+        (
+            "avg_zero_crossing_rate",
+            zcr_base > 0.0 && (captured.avg_zero_crossing_rate - reanalyzed.avg_zero_crossing_rate).abs() > ZCR_TOLERANCE * zcr_base,
+        ),
+        // Summaries from before loudness metering have nothing to compare
+        (
+            "integrated_lufs",
+            captured.integrated_lufs.is_some() && level(avg(captured.integrated_lufs), avg(reanalyzed.integrated_lufs)),
+        ),
+        // Summaries from before classification have nothing to compare
+        (
+            "content_class",
+            captured.content_class.is_some() && captured.content_class != reanalyzed.content_class,
+        ),
+        // Nor from before the transcoding check
+        (
+            "suspected_narrowband_transcoding",
+            captured.band_split.is_some()
+                && captured.suspected_narrowband_transcoding != reanalyzed.suspected_narrowband_transcoding,
+        ),
+    ]
+    .into_iter()
+    .filter(|&(_, differs)| differs)
+    .map(|(field, _)| field.to_string())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::classifier::ContentClass;
+    use crate::cli::recorder::WavRecorder;
+    use crate::cli::test::TestSummary;
+
+    /// A summary.json for one page recorded from a 1kHz tone at `amplitude`,
+    /// with the audio figures the test run captured for it
+    fn tone_fixture(dir: &Path, amplitude: f64) -> TestSummary {
+        let samples: Vec<i16> = (0..16000)
+            .map(|i| (amplitude * (2.0 * std::f64::consts::PI * 1000.0 * f64::from(i) / 8000.0).sin()) as i16)
+            .collect();
+        let mut recorder = WavRecorder::new(&dir.join("page_0001.wav"), 8000, 1).unwrap();
+        recorder.write_samples(&samples).unwrap();
+        recorder.finalize().unwrap();
+
+        serde_json::from_value(serde_json::json!({
+            "test_metadata": {
+                "start_time": "2026-01-01T00:00:00Z",
+                "end_time": "2026-01-01T00:00:10Z",
+                "duration_secs": 10.0,
+                "pattern": "224.0.1.1:5004",
+                "endpoints_monitored": 1,
+                "metrics_interval_ms": 500,
+                "timeout_secs": 10
+            },
+            "pages": [{
+                "page_number": 1,
+                "endpoint": "224.0.1.1:5004",
+                "start_time": "2026-01-01T00:00:01Z",
+                "end_time": "2026-01-01T00:00:03Z",
+                "duration_secs": 2.0,
+                "recording_file": "page_0001.wav",
+                "network": {
+                    "packets_received": 100, "bytes_received": 16000, "packets_lost": 0,
+                    "loss_percent": 0.0, "jitter_ms": 0.1, "max_gap_ms": 20.0
+                },
+                "audio": {
+                    "peak_rms_db": -15.2, "avg_rms_db": -15.3, "max_peak_db": -12.3,
+                    "dominant_freq_hz": 1025.0, "total_glitches": 0, "total_clipped": 0,
+                    "clipping_percent": 0.0, "avg_zero_crossing_rate": 2000.0,
+                    "content_class": "tone", "content_confidence": 1.0
+                }
+            }],
+            "endpoint_totals": {},
+            "errors": []
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_reanalyze_matches_captured_tone() {
+        let dir = tempfile::tempdir().unwrap();
+        let summary = tone_fixture(dir.path(), 8000.0);
+
+        let result = reanalyze(dir.path(), &summary.pages, AnalysisProfile::Speech, &ClassifierThresholds::default(), &TranscodingThresholds::default());
+        assert_eq!(result.schema_version, SCHEMA_VERSION);
+        assert_eq!(result.profile, "speech");
+        let page = &result.pages[0];
+        let audio = page.reanalyzed.as_ref().unwrap();
+        assert!((audio.dominant_freq_hz - 1025.0).abs() < 1e-9, "{}", audio.dominant_freq_hz);
+        assert!((audio.avg_rms_db.unwrap() - -15.3).abs() < 0.5, "{:?}", audio.avg_rms_db);
+        assert_eq!(audio.content_class, Some(ContentClass::Tone));
+        assert!(page.differences.is_empty(), "{:?}", page.differences);
+    }
+
+    #[test]
+    fn test_reanalyze_reports_differences() {
+        let dir = tempfile::tempdir().unwrap();
+        // The recording is 12dB quieter than the summary says
+        let summary = tone_fixture(dir.path(), 2000.0);
+
+        let result = reanalyze(dir.path(), &summary.pages, AnalysisProfile::Music, &ClassifierThresholds::default(), &TranscodingThresholds::default());
+        assert_eq!(result.profile, "music");
+        assert_eq!(result.pages[0].differences, ["peak_rms_db", "avg_rms_db", "max_peak_db"]);
+
+        std::fs::remove_file(dir.path().join("page_0001.wav")).unwrap();
+        let missing = reanalyze(dir.path(), &summary.pages, AnalysisProfile::Speech, &ClassifierThresholds::default(), &TranscodingThresholds::default());
+        assert!(missing.pages[0].reanalyzed.is_none());
+        assert!(missing.pages[0].error.is_some());
+        assert!(missing.pages[0].differences.is_empty());
+    }
+}
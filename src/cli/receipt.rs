@@ -0,0 +1,230 @@
+//! Delivery receipts between two instances of the tool.
+//!
+//! Polycom phones never acknowledge a page, so `polycom-monitor --receipt`
+//! sends one small JSON datagram for each page it completes, and
+//! `polycom-transmit --await-receipts` collects them after the End phase.
+//! Together they give a closed-loop delivery test across a real network.
+
+use crate::cli::logging::limited;
+use crate::network::{create_transmit_socket, MulticastError, MulticastSocket};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::net::SocketAddrV4;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+/// TTL of receipts sent to a multicast group
+const RECEIPT_TTL: u8 = 32;
+
+/// Marks a datagram as one of our receipts
+pub const RECEIPT_KIND: &str = "polycom_receipt";
+
+/// What a monitor received of one page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Receipt {
+    /// Always `polycom_receipt`
+    pub kind: String,
+    pub schema_version: u32,
+    pub channel: u8,
+    pub caller_id: String,
+    pub codec: String,
+    pub duration_secs: f64,
+    pub audio_packets: u32,
+    pub frames_received: u32,
+    /// Lost frames rebuilt from the next packet's redundant copy
+    pub frames_recovered: u32,
+    /// Lost frames replaced with silence
+    pub frames_concealed: u32,
+    /// Duplicate or reordered frames dropped
+    pub frames_late: u32,
+}
+
+impl Receipt {
+    /// Parse a datagram, or `None` if it isn't a receipt
+    fn parse(data: &[u8]) -> Option<Self> {
+        serde_json::from_slice::<Receipt>(data)
+            .ok()
+            .filter(|receipt| receipt.kind == RECEIPT_KIND)
+    }
+}
+
+/// A receipt and the monitor that sent it
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ReceivedReceipt {
+    pub source: String,
+    #[serde(flatten)]
+    pub receipt: Receipt,
+}
+
+/// Sends a receipt for each completed page (`polycom-monitor --receipt`)
+pub struct ReceiptSender {
+    socket: UdpSocket,
+    dest: SocketAddrV4,
+}
+
+impl ReceiptSender {
+    pub async fn new(dest: SocketAddrV4) -> io::Result<Self> {
+        Ok(Self {
+            socket: create_transmit_socket(RECEIPT_TTL, None).await?,
+            dest,
+        })
+    }
+
+    /// Send a receipt; a failure is logged, never fatal to monitoring
+    pub async fn send(&self, receipt: &Receipt) {
+        let result = match serde_json::to_vec(receipt) {
+            Ok(datagram) => self.socket.send_to(&datagram, self.dest).await.map(|_| ()),
+            Err(e) => Err(io::Error::other(e)),
+        };
+        match result {
+            Ok(()) => debug!("Sent receipt for channel {} to {}", receipt.channel, self.dest),
+            Err(e) => warn!("Failed to send receipt to {}: {}", self.dest, e),
+        }
+    }
+}
+
+/// How many receipts `polycom-transmit` waits for, and for how long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReceiptWait {
+    pub count: usize,
+    pub timeout: Duration,
+}
+
+impl FromStr for ReceiptWait {
+    type Err = String;
+
+    /// `N:SECS`, e.g. `2:10`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || format!("invalid receipt wait {:?}: expected N:SECS, e.g. 1:5", s);
+        let (count, secs) = s.split_once(':').ok_or_else(usage)?;
+        let count: usize = count.trim().parse().map_err(|_| usage())?;
+        let secs: f64 = secs.trim().parse().map_err(|_| usage())?;
+        if count == 0 || !secs.is_finite() || secs <= 0.0 {
+            return Err(format!("invalid receipt wait {:?}: N and SECS must be above 0", s));
+        }
+        Ok(Self {
+            count,
+            timeout: Duration::from_secs_f64(secs),
+        })
+    }
+}
+
+impl fmt::Display for ReceiptWait {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} receipt(s) within {:.1}s", self.count, self.timeout.as_secs_f64())
+    }
+}
+
+/// Collects receipts (`polycom-transmit --await-receipts`). Opened before the
+/// page goes out, so a receipt that arrives early waits in the socket.
+pub struct ReceiptListener {
+    socket: MulticastSocket,
+}
+
+impl ReceiptListener {
+    /// Listen on `addr`'s port, joining its group if it's multicast
+    pub async fn open(addr: SocketAddrV4) -> Result<Self, MulticastError> {
+        let mut socket = MulticastSocket::new(addr.port()).await?;
+        if addr.ip().is_multicast() {
+            socket.join(*addr.ip())?;
+            socket.set_multicast_loop(true)?;
+        }
+        Ok(Self { socket })
+    }
+
+    /// Receipts until `wait.count` have arrived or `wait.timeout` passes
+    pub async fn collect(&self, wait: ReceiptWait) -> Vec<ReceivedReceipt> {
+        let deadline = Instant::now() + wait.timeout;
+        let mut buf = vec![0u8; 2048];
+        let mut receipts = Vec::new();
+        while receipts.len() < wait.count {
+            let Ok(result) = tokio::time::timeout_at(deadline, self.socket.recv_datagram(&mut buf)).await else {
+                break;
+            };
+            match result {
+                Ok(datagram) => {
+                    if let Some(receipt) = Receipt::parse(&buf[..datagram.len]) {
+                        receipts.push(ReceivedReceipt {
+                            source: datagram.source.to_string(),
+                            receipt,
+                        });
+                    } else {
+                        debug!("Ignoring {} bytes from {} that aren't a receipt", datagram.len, datagram.source);
+                    }
+                }
+                Err(e) => limited!(warn!("Receipt receive error: {}", e)),
+            }
+        }
+        receipts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::schema::SCHEMA_VERSION;
+    use std::net::Ipv4Addr;
+
+    fn receipt() -> Receipt {
+        Receipt {
+            kind: RECEIPT_KIND.to_string(),
+            schema_version: SCHEMA_VERSION,
+            channel: 26,
+            caller_id: "MPS-IP".to_string(),
+            codec: "G.711u".to_string(),
+            duration_secs: 3.2,
+            audio_packets: 150,
+            frames_received: 149,
+            frames_recovered: 1,
+            frames_concealed: 0,
+            frames_late: 0,
+        }
+    }
+
+    #[test]
+    fn test_parse_receipt_wait() {
+        assert_eq!(
+            "2:10".parse::<ReceiptWait>(),
+            Ok(ReceiptWait { count: 2, timeout: Duration::from_secs(10) })
+        );
+        assert_eq!("1:0.5".parse::<ReceiptWait>().unwrap().timeout, Duration::from_millis(500));
+        assert!("2".parse::<ReceiptWait>().unwrap_err().contains("expected N:SECS"));
+        assert!("0:5".parse::<ReceiptWait>().unwrap_err().contains("above 0"));
+        assert!("1:-5".parse::<ReceiptWait>().is_err());
+    }
+
+    #[test]
+    fn test_receipt_round_trip() {
+        let datagram = serde_json::to_vec(&receipt()).unwrap();
+        assert_eq!(Receipt::parse(&datagram), Some(receipt()));
+
+        // Other JSON on the port is ignored
+        assert_eq!(Receipt::parse(br#"{"event": "timeout"}"#), None);
+        let mut other = serde_json::to_value(receipt()).unwrap();
+        other["kind"] = "something_else".into();
+        assert_eq!(Receipt::parse(other.to_string().as_bytes()), None);
+    }
+
+    #[tokio::test]
+    async fn test_listener_collects_until_count() {
+        let addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 15_901);
+        let listener = ReceiptListener::open(addr).await.unwrap();
+        let sender = ReceiptSender::new(addr).await.unwrap();
+        sender.send(&receipt()).await;
+        sender.send(&receipt()).await;
+
+        let wait = ReceiptWait { count: 1, timeout: Duration::from_secs(2) };
+        let receipts = listener.collect(wait).await;
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].receipt, receipt());
+
+        // The second is still queued; a third never comes, so the wait times out
+        let wait = ReceiptWait { count: 2, timeout: Duration::from_millis(300) };
+        assert_eq!(listener.collect(wait).await.len(), 1);
+    }
+}
@@ -1,7 +1,8 @@
+use chrono::{DateTime, SecondsFormat, Utc};
 use hound::{WavSpec, WavWriter};
-use std::fs::File;
-use std::io::BufWriter;
-use std::path::Path;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -16,10 +17,26 @@ pub enum RecorderError {
     Io(#[from] std::io::Error),
 }
 
+impl RecorderError {
+    /// The IO error underneath, if there is one
+    pub fn io_error(&self) -> Option<&std::io::Error> {
+        match self {
+            Self::CreateFile(hound::Error::IoError(e)) | Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// INFO field holding the UTC time of a recording's first sample
+const START_TIME_FIELD: &[u8; 4] = b"ICRD";
+
 /// Records audio samples to a WAV file
 pub struct WavRecorder {
     writer: WavWriter<BufWriter<File>>,
+    path: PathBuf,
     samples_written: u64,
+    /// Written to the INFO chunk when the file is finalized
+    start_time: Option<DateTime<Utc>>,
 }
 
 impl WavRecorder {
@@ -36,10 +53,17 @@ impl WavRecorder {
 
         Ok(Self {
             writer,
+            path: path.to_path_buf(),
             samples_written: 0,
+            start_time: None,
         })
     }
 
+    /// Note when the first sample was received, for the INFO chunk
+    pub fn set_start_time(&mut self, start_time: DateTime<Utc>) {
+        self.start_time = Some(start_time);
+    }
+
     /// Write samples to the WAV file
     pub fn write_samples(&mut self, samples: &[i16]) -> Result<(), RecorderError> {
         for &sample in samples {
@@ -51,17 +75,36 @@ impl WavRecorder {
         Ok(())
     }
 
+    /// Write `frames` frames of silence (all channels)
+    pub fn write_silence(&mut self, frames: u64) -> Result<(), RecorderError> {
+        let samples = frames * u64::from(self.writer.spec().channels);
+        for _ in 0..samples {
+            self.writer
+                .write_sample(0i16)
+                .map_err(|e| RecorderError::WriteSamples(e.to_string()))?;
+        }
+        self.samples_written += samples;
+        Ok(())
+    }
+
     /// Finalize the WAV file
     pub fn finalize(self) -> Result<u64, RecorderError> {
         let samples = self.samples_written;
         self.writer
             .finalize()
             .map_err(|e| RecorderError::WriteSamples(e.to_string()))?;
+        if let Some(start_time) = self.start_time {
+            append_start_time(&self.path, start_time)?;
+        }
         Ok(samples)
     }
 
+    /// The file being written
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     /// Get the number of samples written so far
-    #[allow(dead_code)]
     pub fn samples_written(&self) -> u64 {
         self.samples_written
     }
@@ -73,6 +116,69 @@ impl WavRecorder {
     }
 }
 
+/// Append a LIST/INFO chunk with the first sample's time (RFC 3339, to the
+/// millisecond) after the audio, and fix up the RIFF size to include it
+fn append_start_time(path: &Path, start_time: DateTime<Utc>) -> std::io::Result<()> {
+    let mut value = start_time.to_rfc3339_opts(SecondsFormat::Millis, true).into_bytes();
+    value.push(0);
+    if value.len() % 2 == 1 {
+        value.push(0);
+    }
+    let mut chunk = Vec::with_capacity(20 + value.len());
+    chunk.extend_from_slice(b"LIST");
+    chunk.extend_from_slice(&(12 + value.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(b"INFO");
+    chunk.extend_from_slice(START_TIME_FIELD);
+    chunk.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(&value);
+
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let end = file.seek(SeekFrom::End(0))?;
+    file.write_all(&chunk)?;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&((end + chunk.len() as u64 - 8) as u32).to_le_bytes())?;
+    Ok(())
+}
+
+/// The first sample's time from a recording's INFO chunk, if it has one
+pub fn read_start_time(path: &Path) -> std::io::Result<Option<DateTime<Utc>>> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; 12];
+    file.read_exact(&mut header)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+        return Ok(None);
+    }
+    let mut chunk_header = [0u8; 8];
+    while file.read_exact(&mut chunk_header).is_ok() {
+        let size = u32::from_le_bytes([chunk_header[4], chunk_header[5], chunk_header[6], chunk_header[7]]);
+        // Chunks are padded to an even length
+        let padded = u64::from(size) + u64::from(size % 2);
+        if &chunk_header[..4] != b"LIST" {
+            file.seek(SeekFrom::Current(padded as i64))?;
+            continue;
+        }
+        let mut list = vec![0u8; size as usize];
+        file.read_exact(&mut list)?;
+        file.seek(SeekFrom::Current(i64::from(size % 2)))?;
+        if list.len() < 4 || &list[..4] != b"INFO" {
+            continue;
+        }
+        let mut fields = &list[4..];
+        while fields.len() >= 8 {
+            let len = u32::from_le_bytes([fields[4], fields[5], fields[6], fields[7]]) as usize;
+            let value = &fields[8..(8 + len).min(fields.len())];
+            if &fields[..4] == START_TIME_FIELD {
+                let text = String::from_utf8_lossy(value);
+                return Ok(DateTime::parse_from_rfc3339(text.trim_end_matches('\0'))
+                    .ok()
+                    .map(|time| time.with_timezone(&Utc)));
+            }
+            fields = &fields[(8 + len + len % 2).min(fields.len())..];
+        }
+    }
+    Ok(None)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -124,4 +230,51 @@ mod tests {
         assert_eq!(reader.spec().sample_rate, 48000);
         assert_eq!(reader.spec().channels, 2);
     }
+
+    #[test]
+    fn test_write_silence() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("silence.wav");
+
+        let mut recorder = WavRecorder::new(&path, 48000, 2).unwrap();
+        recorder.write_samples(&[500; 4]).unwrap();
+        // Frames, not samples: both channels of each are filled
+        recorder.write_silence(100).unwrap();
+        recorder.write_samples(&[-500; 4]).unwrap();
+
+        assert_eq!(recorder.finalize().unwrap(), 208);
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.duration(), 104);
+        let samples: Vec<i16> = reader.samples().map(Result::unwrap).collect();
+        assert_eq!(samples[..4], [500; 4]);
+        assert!(samples[4..204].iter().all(|&s| s == 0));
+        assert_eq!(samples[204..], [-500; 4]);
+    }
+
+    #[test]
+    fn test_start_time_info_chunk() {
+        use chrono::TimeZone;
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("page.wav");
+        let start = Utc.with_ymd_and_hms(2024, 6, 1, 14, 32, 5).unwrap() + chrono::Duration::milliseconds(250);
+
+        let mut recorder = WavRecorder::new(&path, 8000, 1).unwrap();
+        recorder.set_start_time(start);
+        recorder.write_samples(&[100; 801]).unwrap();
+        recorder.finalize().unwrap();
+
+        assert_eq!(read_start_time(&path).unwrap(), Some(start));
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.duration(), 801);
+        assert!(reader.samples::<i16>().all(|s| s.unwrap() == 100));
+        let riff_size = u32::from_le_bytes(fs::read(&path).unwrap()[4..8].try_into().unwrap());
+        assert_eq!(u64::from(riff_size) + 8, fs::metadata(&path).unwrap().len());
+
+        // Recordings without one
+        let plain = dir.path().join("plain.wav");
+        WavRecorder::new(&plain, 8000, 1).unwrap().finalize().unwrap();
+        assert_eq!(read_start_time(&plain).unwrap(), None);
+    }
 }
@@ -2,14 +2,33 @@
 //!
 //! This module provides a command to review test output directories,
 //! displaying metrics in a formatted way and optionally playing back audio.
-
-use crate::cli::test::{TestSummary, PageSummary, MetricSnapshot};
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+//! Playback (`playback.rs`) is only set up for `--play`, so everything else
+//! works without audio hardware. `--interactive` browses the pages from the
+//! keyboard instead (`review_browser.rs`).
+
+use crate::cli::anonymize::{self, AnonymizeError, Anonymization};
+use crate::cli::audio_analyzer::{format_db, AnalysisProfile};
+use crate::cli::classifier::{ClassifierThresholds, ContentClass};
+use crate::cli::transcoding::TranscodingThresholds;
+use crate::cli::clip::{self, ClipError};
+use crate::cli::integrity::{self, Verification};
+use crate::cli::monitor::format_ttl;
+use crate::cli::path_compare::{PathComparison, Verdict};
+use crate::cli::playback::{PlaybackError, Player};
+use crate::cli::timeout::TimeoutMode;
+use crate::cli::quality;
+use crate::cli::reanalysis::{self, PageReanalysis, Reanalysis, REANALYSIS_FILE};
+use crate::cli::review_browser;
+use crate::cli::recorder::{self, RecorderError, WavRecorder};
+use crate::cli::spectrum::{self, SpectrumError};
+use crate::cli::test::{TestSummary, PageSummary, MetricSnapshot, UnavailableEndpoint};
+use crate::cli::timeline::{self, PageTimeline};
+use crate::cli::timing::{self, TimingError};
+use crate::cli::trends::{EndpointTrend, HourBucket, TrendError, TrendFile, OUTLIER_SIGMA};
+use chrono::{DateTime, Utc};
 use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, IsTerminal};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}};
-use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -23,22 +42,109 @@ pub enum ReviewError {
     #[error("Summary file not found: {0}")]
     SummaryNotFound(PathBuf),
 
-    #[error("Audio error: {0}")]
-    Audio(String),
+    #[error("Playback error: {0}")]
+    Playback(#[from] PlaybackError),
 
     #[error("WAV error: {0}")]
     Wav(#[from] hound::Error),
+
+    #[error("Spectrum error: {0}")]
+    Spectrum(#[from] SpectrumError),
+
+    #[error("--spectrogram needs --page, or --directory naming a spectrum file")]
+    SpectrogramNeedsPage,
+
+    #[error("{0} recording(s) failed verification")]
+    VerificationFailed(usize),
+
+    #[error("Trend aggregate error: {0}")]
+    Trends(#[from] TrendError),
+
+    #[error("--timeline needs --page")]
+    TimelineNeedsPage,
+
+    #[error("Page {0} not found in results")]
+    PageNotFound(u32),
+
+    #[error("Timing file error: {0}")]
+    Timing(#[from] TimingError),
+
+    #[error(transparent)]
+    Clip(#[from] ClipError),
+
+    #[error("Recorder error: {0}")]
+    Recorder(#[from] RecorderError),
+
+    #[error("{time} is not within page {page}'s recording")]
+    NotInPage { time: String, page: u32 },
+
+    #[error("Anonymize error: {0}")]
+    Anonymize(#[from] AnonymizeError),
 }
 
 pub struct ReviewOptions {
     pub directory: PathBuf,
+    /// Show this time-of-day aggregate file instead of a results directory
+    pub trends: Option<PathBuf>,
     pub play_audio: bool,
     pub show_metrics: bool,
     pub page_number: Option<u32>,
+    /// Take `page_number` as this endpoint's (ADDR:PORT) own page number
+    /// rather than the page's id
+    pub endpoint: Option<String>,
+    /// Show how the page's metrics moved over time
+    pub timeline: bool,
+    /// Print the page's metrics snapshots as JSON instead of the review
+    pub timeline_json: bool,
+    /// Show the jitter buffer simulation for each page
+    pub jitter_sim: bool,
+    /// Render the page's spectrum file as a spectrogram
+    pub spectrogram: bool,
+    /// Where spectrum files live (the results directory if not set)
+    pub spectrum_dir: Option<PathBuf>,
+    /// Write the spectrogram to a PNG instead of the terminal
+    pub png: Option<PathBuf>,
+    /// Re-hash recordings against summary.json instead of showing results
+    pub verify: bool,
+    /// Run the recordings through the analyzer again instead of showing results
+    pub reanalyze: bool,
+    /// Thresholds for `reanalyze`
+    pub analysis_profile: AnalysisProfile,
+    /// Content classification thresholds for `reanalyze`, from the config file
+    pub classifier: ClassifierThresholds,
+    /// Narrowband transcoding thresholds for `reanalyze`, from the config file
+    pub transcoding: TranscodingThresholds,
+    /// Write and print the `reanalyze` comparison as JSON
+    pub json: bool,
+    /// Find what the page was playing at this wall-clock time
+    pub at: Option<String>,
+    /// Seconds either side of `at` to clip or play
+    pub around: f64,
+    /// Write the audio around `at` to this file
+    pub clip: Option<PathBuf>,
+    /// Write an anonymized copy of the results here instead of showing them
+    pub anonymize: Option<PathBuf>,
+    /// Browse the pages from the keyboard, if on a terminal
+    pub interactive: bool,
 }
 
 /// Run the review command
 pub fn run_review(options: ReviewOptions) -> Result<(), ReviewError> {
+    if let Some(ref path) = options.trends {
+        return show_trends(path);
+    }
+
+    // A spectrum file can be reviewed on its own, e.g. one written by monitor
+    if options.spectrogram && options.directory.is_file() {
+        return show_spectrogram(&options.directory, options.png.as_deref());
+    }
+    if options.spectrogram && options.page_number.is_none() {
+        return Err(ReviewError::SpectrogramNeedsPage);
+    }
+    if (options.timeline || options.timeline_json) && options.page_number.is_none() {
+        return Err(ReviewError::TimelineNeedsPage);
+    }
+
     let summary_path = options.directory.join("summary.json");
 
     if !summary_path.exists() {
@@ -51,6 +157,55 @@ pub fn run_review(options: ReviewOptions) -> Result<(), ReviewError> {
         serde_json::from_reader(file)?
     };
 
+    if let Some(ref output) = options.anonymize {
+        let anonymization = anonymize::anonymize(&options.directory, output)?;
+        display_anonymization(output, &anonymization);
+        return Ok(());
+    }
+
+    if options.verify {
+        return verify_recordings(&options.directory, &summary, options.page_number, options.endpoint.as_deref());
+    }
+
+    let selected_page = options
+        .page_number
+        .and_then(|page_num| summary.find_page(page_num, options.endpoint.as_deref()));
+
+    if options.reanalyze {
+        let pages = match options.page_number {
+            Some(page_num) => vec![selected_page.ok_or(ReviewError::PageNotFound(page_num))?],
+            None => summary.pages.iter().collect(),
+        };
+        return show_reanalysis(
+            &options.directory,
+            pages,
+            options.analysis_profile,
+            &options.classifier,
+            &options.transcoding,
+            options.json,
+        );
+    }
+
+    if let Some(ref at) = options.at {
+        let page = selected_page.ok_or(ReviewError::PageNotFound(options.page_number.unwrap_or_default()))?;
+        let date = summary.test_metadata.start_time.date_naive();
+        return show_instant(&options, page, clip::parse_clip_time(at, date)?);
+    }
+
+    if options.timeline_json {
+        let page = selected_page.ok_or(ReviewError::PageNotFound(options.page_number.unwrap_or_default()))?;
+        let timeline = read_page_timeline(&options.directory, page)?;
+        println!("{}", serde_json::to_string_pretty(&timeline)?);
+        return Ok(());
+    }
+
+    if options.interactive {
+        if review_browser::browse(&options.directory, &summary)? {
+            return Ok(());
+        }
+        eprintln!("Not a terminal; showing the whole review instead of browsing it");
+    }
+
     // Display header
     println!();
     println!("╔══════════════════════════════════════════════════════════════════╗");
@@ -64,17 +219,30 @@ pub fn run_review(options: ReviewOptions) -> Result<(), ReviewError> {
     // Display pages
     if let Some(page_num) = options.page_number {
         // Show specific page
-        if let Some(page) = summary.pages.iter().find(|p| p.page_number == page_num) {
+        if let Some(page) = selected_page {
             display_page_detail(page);
 
-            if options.play_audio {
-                let audio_path = options.directory.join(&page.recording_file);
-                if audio_path.exists() {
-                    play_audio_file(&audio_path)?;
+            if options.timeline {
+                timeline::print_timeline(&read_page_timeline(&options.directory, page)?);
+            }
+
+            if options.jitter_sim {
+                display_jitter_buffer_analysis(std::slice::from_ref(page));
+            }
+
+            if options.spectrogram {
+                let dir = options.spectrum_dir.as_ref().unwrap_or(&options.directory);
+                let path = spectrum::page_spectrum_path(dir, &page.recording_file);
+                if path.exists() {
+                    show_spectrogram(&path, options.png.as_deref())?;
                 } else {
-                    println!("  ⚠ Audio file not found: {}", page.recording_file);
+                    println!("  ⚠ Spectrum file not found: {}", path.display());
                 }
             }
+
+            if options.play_audio {
+                play_page(&Player::open()?, &options.directory, page)?;
+            }
         } else {
             println!("Page {} not found in results.", page_num);
         }
@@ -82,9 +250,22 @@ pub fn run_review(options: ReviewOptions) -> Result<(), ReviewError> {
         // Show all pages
         display_pages_summary(&summary.pages);
 
+        if options.jitter_sim {
+            display_jitter_buffer_analysis(&summary.pages);
+        }
+
         // Display endpoint totals
         display_endpoint_totals(&summary);
 
+        if !summary.path_comparisons.is_empty() {
+            display_path_comparisons(&summary.path_comparisons);
+        }
+
+        // Display endpoints that couldn't be monitored
+        if !summary.unavailable_endpoints.is_empty() {
+            display_unavailable_endpoints(&summary.unavailable_endpoints);
+        }
+
         // Display errors if any
         if !summary.errors.is_empty() {
             display_errors(&summary.errors);
@@ -97,20 +278,14 @@ pub fn run_review(options: ReviewOptions) -> Result<(), ReviewError> {
 
         // Play audio if requested
         if options.play_audio && !summary.pages.is_empty() {
+            let player = Player::open()?;
             println!();
             println!("┌─────────────────────────────────────────────────────────────────┐");
             println!("│ AUDIO PLAYBACK                                                  │");
             println!("└─────────────────────────────────────────────────────────────────┘");
 
             for page in &summary.pages {
-                let audio_path = options.directory.join(&page.recording_file);
-                if audio_path.exists() {
-                    println!();
-                    println!("  Playing: {} ({:.1}s)", page.recording_file, page.duration_secs);
-                    play_audio_file(&audio_path)?;
-                } else {
-                    println!("  ⚠ Audio file not found: {}", page.recording_file);
-                }
+                play_page(&player, &options.directory, page)?;
             }
         }
     }
@@ -119,6 +294,278 @@ pub fn run_review(options: ReviewOptions) -> Result<(), ReviewError> {
     Ok(())
 }
 
+/// Play a page's recording, or say it's missing
+pub(crate) fn play_page(player: &Player, directory: &Path, page: &PageSummary) -> Result<(), ReviewError> {
+    let audio_path = directory.join(&page.recording_file);
+    if audio_path.exists() {
+        println!();
+        println!("  Playing: {} ({:.1}s)", page.recording_file, page.duration_secs);
+        player.play(&audio_path)?;
+    } else {
+        println!("  ⚠ Audio file not found: {}", page.recording_file);
+    }
+    Ok(())
+}
+
+/// Width of the loss bar in the trends table
+const TREND_BAR_WIDTH: usize = 10;
+
+/// Talk-spurts listed in a page's detail; the rest are counted
+const MAX_SPURTS_SHOWN: usize = 20;
+
+/// Re-analyze the recordings and show them beside the captured figures, or
+/// with `json` write reanalysis.json and print it
+fn show_reanalysis(
+    directory: &Path,
+    pages: Vec<&PageSummary>,
+    profile: AnalysisProfile,
+    classifier: &ClassifierThresholds,
+    transcoding: &TranscodingThresholds,
+    json: bool,
+) -> Result<(), ReviewError> {
+    let result = reanalysis::reanalyze(directory, pages, profile, classifier, transcoding);
+    if json {
+        let text = serde_json::to_string_pretty(&result)?;
+        std::fs::write(directory.join(REANALYSIS_FILE), format!("{}\n", text))?;
+        println!("{}", text);
+        return Ok(());
+    }
+
+    println!();
+    println!("┌─────────────────────────────────────────────────────────────────┐");
+    println!("│ REANALYSIS ({} profile){:>width$} │", result.profile, "", width = 43 - result.profile.len());
+    for page in &result.pages {
+        display_page_reanalysis(page);
+    }
+    println!("└─────────────────────────────────────────────────────────────────┘");
+    print_reanalysis_totals(&result);
+    println!();
+    Ok(())
+}
+
+fn display_page_reanalysis(page: &PageReanalysis) {
+    println!("├─────────────────────────────────────────────────────────────────┤");
+    println!("│ Page {:<4} {:<54} │", page.page_id.unwrap_or(page.page_number), page.endpoint);
+    let Some(ref reanalyzed) = page.reanalyzed else {
+        println!("│   Error: {:<55} │", page.error.as_deref().unwrap_or_default());
+        return;
+    };
+    println!("│   {:<16} {:>12} {:>12} {:<19} │", "", "Captured", "Reanalyzed", "");
+
+    let captured = &page.captured;
+    let avg_rms = |v: Option<f64>| v.map_or_else(|| "N/A".to_string(), format_db);
+    let rows = [
+        ("Peak RMS", "peak_rms_db", format_db(captured.peak_rms_db), format_db(reanalyzed.peak_rms_db)),
+        ("Average RMS", "avg_rms_db", avg_rms(captured.avg_rms_db), avg_rms(reanalyzed.avg_rms_db)),
+        ("Max Peak", "max_peak_db", format_db(captured.max_peak_db), format_db(reanalyzed.max_peak_db)),
+        (
+            "Dominant Freq",
+            "dominant_freq_hz",
+            format_frequency(captured.dominant_freq_hz),
+            format_frequency(reanalyzed.dominant_freq_hz),
+        ),
+        ("Glitches", "total_glitches", captured.total_glitches.to_string(), reanalyzed.total_glitches.to_string()),
+        ("Clipped", "total_clipped", captured.total_clipped.to_string(), reanalyzed.total_clipped.to_string()),
+        (
+            "Avg ZCR",
+            "avg_zero_crossing_rate",
+            format!("{:.0}/s", captured.avg_zero_crossing_rate),
+            format!("{:.0}/s", reanalyzed.avg_zero_crossing_rate),
+        ),
+        (
+            "Integrated",
+            "integrated_lufs",
+            format_lufs(captured.integrated_lufs),
+            format_lufs(reanalyzed.integrated_lufs),
+        ),
+        (
+            "Content",
+            "content_class",
+            captured.content_class.map_or("-", ContentClass::name).to_string(),
+            reanalyzed.content_class.map_or("-", ContentClass::name).to_string(),
+        ),
+    ];
+    for (label, field, before, after) in rows {
+        let row = format!("{:<16} {:>12} {:>12}", label, before, after);
+        if page.differences.iter().any(|d| d == field) {
+            println!("│   {} {:<19} │", paint(row, "33"), "◀ differs");
+        } else {
+            println!("│   {} {:<19} │", row, "");
+        }
+    }
+}
+
+fn print_reanalysis_totals(result: &Reanalysis) {
+    let differing = result.pages.iter().filter(|p| !p.differences.is_empty()).count();
+    let unreadable = result.pages.iter().filter(|p| p.reanalyzed.is_none()).count();
+    println!(
+        "  {} of {} page(s) differ from the captured figures{}",
+        differing,
+        result.pages.len(),
+        if unreadable > 0 { format!(", {} recording(s) unreadable", unreadable) } else { String::new() }
+    );
+}
+
+/// Hour-of-day tables from a `test --aggregate` file
+fn show_trends(path: &Path) -> Result<(), ReviewError> {
+    if !path.exists() {
+        return Err(ReviewError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} not found", path.display()),
+        )));
+    }
+    let trends = TrendFile::load(path)?;
+
+    println!();
+    println!("╔══════════════════════════════════════════════════════════════════╗");
+    println!("║                     TIME-OF-DAY TRENDS                           ║");
+    println!("╚══════════════════════════════════════════════════════════════════╝");
+    println!();
+    if let Some(updated) = trends.updated {
+        println!("Last page: {}", updated.format("%Y-%m-%d %H:%M:%S UTC"));
+        println!();
+    }
+    if trends.endpoints.is_empty() {
+        println!("No pages recorded yet.");
+    }
+
+    for (endpoint, trend) in &trends.endpoints {
+        display_endpoint_trend(endpoint, trend);
+    }
+    println!("Hours are local time. Flags: L = mean loss, G = glitches per minute,");
+    println!("more than {} standard deviations above the endpoint's mean.", OUTLIER_SIGMA);
+    println!();
+    Ok(())
+}
+
+fn display_endpoint_trend(endpoint: &str, trend: &EndpointTrend) {
+    // Bars are scaled to the hour with the most loss
+    let worst_loss = trend
+        .hours
+        .values()
+        .filter_map(|hour| hour.loss_percent.mean())
+        .fold(0.0, f64::max);
+
+    println!("┌─────────────────────────────────────────────────────────────────┐");
+    println!("│ {:<63} │", format!("{} ({} pages)", endpoint, trend.overall.pages));
+    println!("├──────┬──────┬──────┬──────┬───────┬──────┬───────┬──────────────┤");
+    println!("│ {:>5}│ {:>5}│ {:>5}│ {:>5}│ {:>6}│ {:>5}│ {:>6}│ {:<13}│",
+        "Hour", "Pages", "Loss%", "p95", "Jitter", "RMS", "Glit/m", "Loss");
+    println!("├──────┼──────┼──────┼──────┼───────┼──────┼───────┼──────────────┤");
+    for (hour, bucket) in &trend.hours {
+        let flags = trend.flags(bucket);
+        let bar_len = match bucket.loss_percent.mean() {
+            Some(loss) if worst_loss > 0.0 => (loss / worst_loss * TREND_BAR_WIDTH as f64).round() as usize,
+            _ => 0,
+        };
+        let bar = format!(
+            "{:<width$} {}{}",
+            "█".repeat(bar_len),
+            if flags.loss { "L" } else { "" },
+            if flags.glitches { "G" } else { "" },
+            width = TREND_BAR_WIDTH
+        );
+        display_trend_row(&format!("{:02}:00", hour), bucket, &bar);
+    }
+    println!("├──────┼──────┼──────┼──────┼───────┼──────┼───────┼──────────────┤");
+    display_trend_row("All", &trend.overall, "");
+    println!("└──────┴──────┴──────┴──────┴───────┴──────┴───────┴──────────────┘");
+    println!();
+}
+
+fn display_trend_row(label: &str, bucket: &HourBucket, bar: &str) {
+    let figure = |value: Option<f64>, format: fn(f64) -> String| value.map_or_else(|| "-".to_string(), format);
+    println!("│ {:>5}│ {:>5}│ {:>5}│ {:>5}│ {:>6}│ {:>5}│ {:>6}│ {:<13}│",
+        label,
+        bucket.pages,
+        figure(bucket.loss_percent.mean(), |v| format!("{:.2}", v)),
+        figure(bucket.loss_percent.p95(), |v| format!("{:.2}", v)),
+        figure(bucket.jitter_ms.mean(), |v| format!("{:.1}ms", v)),
+        figure(bucket.rms_db.mean(), |v| format!("{:.0}dB", v)),
+        figure(bucket.glitch_rate.mean(), |v| format!("{:.2}", v)),
+        bar
+    );
+}
+
+/// Check every recording (or just `page_number`'s) against the hashes taken when it was written
+fn verify_recordings(directory: &Path, summary: &TestSummary, page_number: Option<u32>, endpoint: Option<&str>) -> Result<(), ReviewError> {
+    let pages: Vec<&PageSummary> = summary
+        .pages
+        .iter()
+        .filter(|page| page_number.is_none_or(|n| page.is_page(n, endpoint)))
+        .collect();
+
+    println!("┌─────────────────────────────────────────────────────────────────┐");
+    println!("│ RECORDING VERIFICATION                                          │");
+    println!("├──────┬──────────────────────────────────────┬───────────────────┤");
+    println!("│ {:>4} │ {:<36} │ {:<17} │", "Page", "Recording", "Status");
+    println!("├──────┼──────────────────────────────────────┼───────────────────┤");
+
+    let mut failed = 0;
+    let mut unchecked = 0;
+    for page in &pages {
+        let status = if let Some(ref expected) = page.integrity {
+            let verification = integrity::verify(&directory.join(&page.recording_file), expected);
+            if verification != Verification::Intact {
+                failed += 1;
+            }
+            match verification {
+                Verification::Intact => "OK",
+                Verification::Modified { audio_intact: true } => "MODIFIED (header)",
+                Verification::Modified { audio_intact: false } => "MODIFIED (audio)",
+                Verification::Missing => "MISSING",
+                Verification::Unreadable(e) => {
+                    println!("  ⚠ {}: {}", page.recording_file, e);
+                    "UNREADABLE"
+                }
+            }
+        } else {
+            unchecked += 1;
+            "no checksum"
+        };
+
+        let file = if page.recording_file.len() > 36 {
+            format!("{}...", &page.recording_file[..33])
+        } else {
+            page.recording_file.clone()
+        };
+        println!("│ {:>4} │ {:<36} │ {:<17} │", page.id(), file, status);
+    }
+
+    println!("└──────┴──────────────────────────────────────┴───────────────────┘");
+    println!(
+        "Verified {} recording(s): {} failed{}",
+        pages.len() - unchecked,
+        failed,
+        if unchecked > 0 {
+            format!(", {} without a checksum", unchecked)
+        } else {
+            String::new()
+        }
+    );
+
+    if failed > 0 {
+        Err(ReviewError::VerificationFailed(failed))
+    } else {
+        Ok(())
+    }
+}
+
+fn display_anonymization(output: &Path, anonymization: &Anonymization) {
+    println!("Anonymized copy written to {}", output.display());
+    println!(
+        "  {} recording(s) replaced with noise following their level ({}ms windows)",
+        anonymization.recordings, anonymization.envelope_window_ms
+    );
+    println!(
+        "  {} address(es) and {} caller ID(s) replaced with stand-ins",
+        anonymization.addresses, anonymization.caller_ids
+    );
+    if !anonymization.omitted.is_empty() {
+        println!("  Left out: {}", anonymization.omitted.join(", "));
+    }
+}
+
 fn display_metadata(summary: &TestSummary) {
     let meta = &summary.test_metadata;
 
@@ -130,58 +577,99 @@ fn display_metadata(summary: &TestSummary) {
     println!("│ Start Time:   {:<50} │", meta.start_time.format("%Y-%m-%d %H:%M:%S UTC"));
     println!("│ End Time:     {:<50} │", meta.end_time.format("%Y-%m-%d %H:%M:%S UTC"));
     println!("│ Duration:     {:<50} │", format!("{:.1}s", meta.duration_secs));
-    println!("│ Timeout:      {:<50} │", format!("{}s", meta.timeout_secs));
+    let timeout = match (meta.timeout_mode, meta.until) {
+        (TimeoutMode::Until, Some(until)) => format!("until {}", until.format("%Y-%m-%d %H:%M:%S UTC")),
+        (TimeoutMode::Indefinite, _) => "none".to_string(),
+        _ => format!("{}s", meta.timeout_secs),
+    };
+    println!("│ Timeout:      {:<50} │", timeout);
+    if let Some(reason) = meta.end_reason {
+        println!("│ Ended By:     {:<50} │", reason);
+    }
     println!("│ Metrics Int:  {:<50} │", format!("{}ms", meta.metrics_interval_ms));
+    if let Some(ref igmp) = meta.igmp {
+        let seen = match (igmp.unavailable.is_some(), igmp.querier) {
+            (true, _) => "not observed".to_string(),
+            (false, Some(querier)) => format!("querier {}", querier),
+            (false, None) => "no querier seen".to_string(),
+        };
+        println!("│ IGMP:         {:<50} │", seen);
+    }
+    if let Some(ref anonymization) = meta.anonymization {
+        let at = anonymization.anonymized_at.format("%Y-%m-%d %H:%M:%S UTC");
+        println!("│ Anonymized:   {:<50} │", format!("{} (audio is noise)", at));
+    }
     println!("└─────────────────────────────────────────────────────────────────┘");
     println!();
 }
 
 fn display_pages_summary(pages: &[PageSummary]) {
-    println!("┌─────────────────────────────────────────────────────────────────┐");
-    println!("│ PAGES DETECTED: {:<48} │", pages.len());
-    println!("├─────────────────────────────────────────────────────────────────┤");
+    // Wider than the other boxes, to fit the content class
+    println!("┌──────────────────────────────────────────────────────────────────────────┐");
+    println!("│ PAGES DETECTED: {:<56} │", pages.len());
+    println!("├──────────────────────────────────────────────────────────────────────────┤");
 
     if pages.is_empty() {
-        println!("│ No pages were detected during the test.                        │");
+        println!("│ No pages were detected during the test.                                  │");
     } else {
-        println!("│ {:>4} │ {:^19} │ {:>7} │ {:>6} │ {:>7} │ {:>6} │",
-            "Page", "Endpoint", "Duration", "Loss%", "Glitch", "RMS");
-        println!("├──────┼─────────────────────┼─────────┼────────┼─────────┼────────┤");
+        println!("│ {:>4}│ {:^16}│ {:>6}│ {:>5}│ {:>5}│ {:>6}│ {:>5}│ {:>3}│ {:<7}│",
+            "Page", "Endpoint", "Length", "Loss%", "Cont%", "Glitch", "RMS", "MOS", "Content");
+        println!("├─────┼─────────────────┼───────┼──────┼──────┼───────┼──────┼────┼────────┤");
 
         for page in pages {
-            let endpoint_short = if page.endpoint.len() > 19 {
-                format!("{}...", &page.endpoint[..16])
+            let endpoint_short = if page.endpoint.len() > 16 {
+                format!("{}...", &page.endpoint[..13])
             } else {
                 page.endpoint.clone()
             };
 
             let avg_rms_str = page.audio.avg_rms_db
-                .map(|v| format!("{:.1}dB", v))
+                .map(|v| format!("{:.0}dB", v))
                 .unwrap_or_else(|| "-".to_string());
-            println!("│ {:>4} │ {:^19} │ {:>6.1}s │ {:>5.1}% │ {:>7} │ {:>7} │",
-                page.page_number,
+            // Summaries written before continuity was measured have none
+            let continuity_str = page.network.continuity_percent
+                .map(|v| format!("{:.1}", v))
+                .unwrap_or_else(|| "-".to_string());
+            // ... or before pages were classified
+            let content_str = page.audio.content_class.map_or("-", ContentClass::name);
+            println!("│ {:>4}│ {:^16}│ {:>5.1}s│ {:>5.1}│ {:>5}│ {:>6}│ {:>5}│ {}│ {:<7}│",
+                page.id(),
                 endpoint_short,
                 page.duration_secs,
                 page.network.loss_percent,
+                continuity_str,
                 page.audio.total_glitches,
-                avg_rms_str
+                avg_rms_str,
+                colour_mos(format!("{:>3}", format_mos(page.mos_estimate)), page.mos_estimate),
+                content_str
             );
         }
     }
 
-    println!("└─────────────────────────────────────────────────────────────────┘");
+    println!("└──────────────────────────────────────────────────────────────────────────┘");
     println!();
 }
 
-fn display_page_detail(page: &PageSummary) {
+pub(crate) fn display_page_detail(page: &PageSummary) {
     println!("┌─────────────────────────────────────────────────────────────────┐");
-    println!("│ PAGE {} DETAILS{:>50} │", page.page_number, "");
+    println!("│ PAGE {:<4} DETAILS{:>46} │", page.id(), "");
     println!("├─────────────────────────────────────────────────────────────────┤");
-    println!("│ Endpoint:     {:<50} │", page.endpoint);
+    // Each endpoint counts its own pages; results with ids list them by id
+    let endpoint = match page.page_id {
+        Some(_) => format!("{} (its page {})", page.endpoint, page.page_number),
+        None => page.endpoint.clone(),
+    };
+    println!("│ Endpoint:     {:<50} │", endpoint);
     println!("│ Start Time:   {:<50} │", page.start_time.format("%Y-%m-%d %H:%M:%S UTC"));
     println!("│ End Time:     {:<50} │", page.end_time.format("%Y-%m-%d %H:%M:%S UTC"));
     println!("│ Duration:     {:<50} │", format!("{:.2}s", page.duration_secs));
     println!("│ Recording:    {:<50} │", page.recording_file);
+    if let Some(error) = &page.error {
+        println!("│ Aborted:      {:<50} │", error);
+    }
+    if page.truncated {
+        println!("│ Truncated:    {:<50} │", "the run ended mid-page");
+    }
     println!("├─────────────────────────────────────────────────────────────────┤");
     println!("│ NETWORK STATS                                                   │");
     println!("│   Packets Received: {:<44} │", page.network.packets_received);
@@ -189,8 +677,43 @@ fn display_page_detail(page: &PageSummary) {
     println!("│   Packets Lost:     {:<44} │", page.network.packets_lost);
     println!("│   Loss Percent:     {:<44} │", format!("{:.2}%", page.network.loss_percent));
     println!("│   Jitter:           {:<44} │", format!("{:.2}ms", page.network.jitter_ms));
+    println!("│   Max Gap:          {:<44} │", format!("{:.0}ms", page.network.max_gap_ms));
+    let gaps = &page.network.gap_histogram;
+    println!("│   Gaps:             {:<44} │", format!(
+        "<30ms {}, 30-60 {}, 60-120 {}, 120-300 {}, >300 {}",
+        gaps.under_30ms, gaps.from_30_to_60ms, gaps.from_60_to_120ms, gaps.from_120_to_300ms, gaps.over_300ms
+    ));
+    if let Some(continuity) = page.network.continuity_percent {
+        println!("│   Continuity:       {:<44} │", format!("{:.1}%", continuity));
+    }
+    if page.network.dtx_gap_secs > 0.0 {
+        println!("│   DTX Silence:      {:<44} │", format!("{:.1}s", page.network.dtx_gap_secs));
+    }
+    if let Some(drift) = page.network.clock_drift_ms {
+        println!("│   Clock Drift:      {:<44} │", format!("{:+.1}ms", drift));
+    }
+    if let Some(avg) = page.network.avg_padding_bytes {
+        println!("│   Padding:          {:<44} │", format!("{} packets, {:.1} bytes on average", page.network.padded_packets, avg));
+    }
+    if page.network.decode_errors > 0 || page.network.truncated_payloads > 0 {
+        println!("│   Damaged Payloads: {:<44} │", format!(
+            "{} undecodable, {} truncated",
+            page.network.decode_errors, page.network.truncated_payloads
+        ));
+    }
+    println!("│   Arriving TTL:     {:<44} │", format_ttl(page.network.ttl));
+    if let Some(dscp) = &page.network.dscp {
+        println!("│   Arriving DSCP:    {:<44} │", dscp.to_string());
+        if let Some(expected) = dscp.expected.filter(|_| dscp.unexpected_packets > 0) {
+            println!("│ ⚠ Not DSCP {:<52} │", format!("{}: {} packets", expected, dscp.unexpected_packets));
+        }
+    }
     println!("├─────────────────────────────────────────────────────────────────┤");
     println!("│ AUDIO ANALYSIS                                                  │");
+    if let Some(class) = page.audio.content_class {
+        let confidence = page.audio.content_confidence.map_or_else(String::new, |c| format!(" ({:.0}% confident)", c * 100.0));
+        println!("│   Content:          {:<44} │", format!("{}{}", class, confidence));
+    }
     println!("│   Peak RMS:         {:<44} │", format!("{:.1}dB", page.audio.peak_rms_db));
     let avg_rms_str = page.audio.avg_rms_db
         .map(|v| format!("{:.1}dB", v))
@@ -202,6 +725,70 @@ fn display_page_detail(page: &PageSummary) {
     println!("│   Total Clipped:    {:<44} │", page.audio.total_clipped);
     println!("│   Clipping:         {:<44} │", format!("{:.3}%", page.audio.clipping_percent));
     println!("│   Avg ZCR:          {:<44} │", format!("{:.0}/s", page.audio.avg_zero_crossing_rate));
+    println!("│   Integrated:       {:<44} │", format_lufs(page.audio.integrated_lufs));
+    println!("│   Max Momentary:    {:<44} │", format_lufs(page.audio.max_momentary_lufs));
+    if let Some(lra) = page.audio.lra {
+        println!("│   Loudness Range:   {:<44} │", format!("{:.1} LU", lra));
+    }
+    if page.marked_packets > 0 {
+        println!("├─────────────────────────────────────────────────────────────────┤");
+        println!("│ TALK-SPURTS                                                     │");
+        println!("│   Marked Packets:   {:<44} │", page.marked_packets);
+        for (i, spurt) in page.spurts.iter().take(MAX_SPURTS_SHOWN).enumerate() {
+            let gap = spurt
+                .preceding_gap_ms
+                .map_or_else(String::new, |gap| format!(" after {:.0}ms silence", gap));
+            println!("│   {:<62}│", format!("{:>3}. at {:.2}s{}", i + 1, spurt.offset_secs, gap));
+        }
+        if page.spurts.len() > MAX_SPURTS_SHOWN {
+            println!("│   {:<62}│", format!("... {} more", page.spurts.len() - MAX_SPURTS_SHOWN));
+        }
+        if let (Some(first), Some(last)) = (page.spurt_files.first(), page.spurt_files.last()) {
+            println!("│   Segments:         {:<44} │", format!("{} files", page.spurt_files.len()));
+            println!("│   {:<62}│", first);
+            if page.spurt_files.len() > 1 {
+                println!("│   {:<62}│", format!("... {}", last));
+            }
+        }
+    }
+    if let Some(ref breakdown) = page.mos_breakdown {
+        println!("├─────────────────────────────────────────────────────────────────┤");
+        println!("│ QUALITY                                                         │");
+        println!("│   MOS Estimate:     {} │", colour_mos(format!("{:<44}", format!("{:.2}", breakdown.mos())), Some(breakdown.mos())));
+        println!("│   R-Factor:         {:<44} │", format!("{:.1}", breakdown.r_factor));
+        println!("│   Delay Penalty:    {:<44} │", format!("-{:.1}", breakdown.delay));
+        println!("│   Codec Penalty:    {:<44} │", format!("-{:.1}", breakdown.codec));
+        println!("│   Loss Penalty:     {:<44} │", format!("-{:.1} (burst ratio {:.2})", breakdown.loss, breakdown.burst_ratio));
+        println!("│   Glitch Penalty:   {:<44} │", format!("-{:.1}", breakdown.glitches));
+        println!("│   Clipping Penalty: {:<44} │", format!("-{:.1}", breakdown.clipping));
+    }
+    println!("└─────────────────────────────────────────────────────────────────┘");
+    println!();
+}
+
+fn display_jitter_buffer_analysis(pages: &[PageSummary]) {
+    println!("┌─────────────────────────────────────────────────────────────────┐");
+    println!("│ JITTER BUFFER SIMULATION (late packets / gap seconds)           │");
+    println!("├─────────────────────────────────────────────────────────────────┤");
+
+    let Some(depths) = pages.iter().map(|p| &p.jitter_buffer_analysis).find(|a| !a.is_empty()) else {
+        println!("│ {:<63} │", "No packet timing recorded for these pages");
+        println!("└─────────────────────────────────────────────────────────────────┘");
+        println!();
+        return;
+    };
+    let header: Vec<String> = depths.iter().map(|r| format!("{:>10}", format!("{}ms", r.depth_ms))).collect();
+    println!("│ {:<10}{:<53} │", "Page", header.concat());
+
+    for page in pages {
+        let cells: Vec<String> = page
+            .jitter_buffer_analysis
+            .iter()
+            .map(|r| format!("{:>10}", format!("{}/{:.2}", r.late_packets, r.gap_secs)))
+            .collect();
+        println!("│ {:<10}{:<53} │", page.id(), cells.concat());
+    }
+
     println!("└─────────────────────────────────────────────────────────────────┘");
     println!();
 }
@@ -214,23 +801,24 @@ fn display_endpoint_totals(summary: &TestSummary) {
     println!("┌─────────────────────────────────────────────────────────────────┐");
     println!("│ ENDPOINT TOTALS                                                 │");
     println!("├─────────────────────────────────────────────────────────────────┤");
-    println!("│ {:^19} │ {:>5} │ {:>10} │ {:>10} │ {:>10} │",
-        "Endpoint", "Pages", "Duration", "Packets", "Bytes");
-    println!("├─────────────────────┼───────┼────────────┼────────────┼────────────┤");
+    println!("│ {:^16} │ {:>5} │ {:>7} │ {:>8} │ {:>9} │ {:>3} │",
+        "Endpoint", "Pages", "Length", "Packets", "Bytes", "MOS");
+    println!("├──────────────────┼───────┼─────────┼──────────┼───────────┼─────┤");
 
     for (endpoint, total) in &summary.endpoint_totals {
-        let endpoint_short = if endpoint.len() > 19 {
-            format!("{}...", &endpoint[..16])
+        let endpoint_short = if endpoint.len() > 16 {
+            format!("{}...", &endpoint[..13])
         } else {
             endpoint.clone()
         };
 
-        println!("│ {:^19} │ {:>5} │ {:>9.1}s │ {:>10} │ {:>10} │",
+        println!("│ {:^16} │ {:>5} │ {:>6.1}s │ {:>8} │ {:>9} │ {} │",
             endpoint_short,
             total.pages_detected,
             total.total_duration_secs,
             total.total_packets,
-            total.total_bytes
+            total.total_bytes,
+            colour_mos(format!("{:>3}", format_mos(total.mos_estimate)), total.mos_estimate)
         );
     }
 
@@ -238,6 +826,97 @@ fn display_endpoint_totals(summary: &TestSummary) {
     println!();
 }
 
+fn display_path_comparisons(comparisons: &[PathComparison]) {
+    let cell = |value: Option<f64>, format: fn(f64) -> String| value.map_or_else(|| "-".to_string(), format);
+
+    println!("┌─────────────────────────────────────────────────────────────────┐");
+    println!("│ REDUNDANT PATHS                                                 │");
+    println!("├─────────────────────────────────────────────────────────────────┤");
+    println!("│ {:^23} │ {:>8} │ {:>6} │ {:>8} │ {:^6} │",
+        "Pages", "Level", "Corr", "Spectral", "Result");
+    println!("├─────────────────────────┼──────────┼────────┼──────────┼────────┤");
+
+    for comparison in comparisons {
+        let b_page = comparison.b_page.map_or_else(|| "-".to_string(), |page| page.to_string());
+        let pages = format!("{} {} / {} {}", comparison.a_label, comparison.a_page, comparison.b_label, b_page);
+        let pages = if pages.chars().count() > 23 {
+            format!("{}...", pages.chars().take(20).collect::<String>())
+        } else {
+            pages
+        };
+        let colour = match comparison.verdict {
+            Verdict::Pass => "32",
+            Verdict::Warn => "33",
+            Verdict::Fail => "31",
+        };
+
+        println!("│ {:<23} │ {:>8} │ {:>6} │ {:>8} │ {} │",
+            pages,
+            cell(comparison.level_difference_db, |db| format!("{:+.1}dB", db)),
+            cell(comparison.correlation, |r| format!("{:+.2}", r)),
+            cell(comparison.spectral_difference_db, |db| format!("{:.1}dB", db)),
+            paint(format!("{:^6}", comparison.verdict.name()), colour)
+        );
+        for finding in &comparison.findings {
+            let line = format!("  {}", finding);
+            let truncated = if line.len() > 63 {
+                format!("{}...", &line[..60])
+            } else {
+                line
+            };
+            println!("│ {:<63} │", truncated);
+        }
+    }
+
+    println!("└─────────────────────────────────────────────────────────────────┘");
+    println!();
+}
+
+fn display_unavailable_endpoints(endpoints: &[UnavailableEndpoint]) {
+    println!("┌─────────────────────────────────────────────────────────────────┐");
+    println!("│ ⚠ UNAVAILABLE ENDPOINTS ({})                                     │", endpoints.len());
+    println!("├─────────────────────────────────────────────────────────────────┤");
+
+    for ep in endpoints {
+        let line = format!("{}: {}", ep.endpoint, ep.reason);
+        let truncated = if line.len() > 63 {
+            format!("{}...", &line[..60])
+        } else {
+            line
+        };
+        println!("│ {:<63} │", truncated);
+    }
+
+    println!("└─────────────────────────────────────────────────────────────────┘");
+    println!();
+}
+
+/// Print a spectrum file as a waterfall, or export it as a PNG
+fn show_spectrogram(path: &Path, png: Option<&Path>) -> Result<(), ReviewError> {
+    let rows = spectrum::read_spectrum_rows(path)?;
+    let first = &rows[0];
+    let page = first.page.map(|p| format!(", page {}", p)).unwrap_or_default();
+
+    if let Some(png) = png {
+        spectrum::write_spectrogram_png(&rows, png)?;
+        println!(
+            "Spectrogram of {}{} ({} snapshots, {} Hz) written to {}",
+            first.endpoint,
+            page,
+            rows.len(),
+            first.sample_rate,
+            png.display()
+        );
+        return Ok(());
+    }
+
+    println!("SPECTROGRAM: {}{} ({} snapshots, {} Hz)", first.endpoint, page, rows.len(), first.sample_rate);
+    println!();
+    print!("{}", spectrum::render_waterfall(&rows, 64));
+    println!();
+    Ok(())
+}
+
 fn display_errors(errors: &[String]) {
     println!("┌─────────────────────────────────────────────────────────────────┐");
     println!("│ ⚠ ERRORS ({})                                                   │", errors.len());
@@ -256,6 +935,87 @@ fn display_errors(errors: &[String]) {
     println!();
 }
 
+/// Find the frame of the page's recording that was playing at `time`, from
+/// its timing file, and clip or play the audio around it if asked
+fn show_instant(options: &ReviewOptions, page: &PageSummary, time: DateTime<Utc>) -> Result<(), ReviewError> {
+    let recording = options.directory.join(&page.recording_file);
+    let mut reader = hound::WavReader::open(&recording)?;
+    let spec = reader.spec();
+    let rate = f64::from(spec.sample_rate);
+    let frames = u64::from(reader.duration());
+
+    let timing_file = timing::timing_path(&recording);
+    let offset = if timing_file.exists() {
+        timing::locate(&timing::read_timing_rows(&timing_file)?, time, spec.sample_rate)
+    } else {
+        // Counted on from the first sample, so gaps and clock drift aren't allowed for
+        let start = recorder::read_start_time(&recording)?.unwrap_or(page.start_time);
+        println!(
+            "  ⚠ No timing file ({}); counting on from the recording's start at {}",
+            timing_file.display(),
+            start.format("%H:%M:%S%.3f")
+        );
+        (time >= start).then(|| (clip::duration_to_secs(time - start) * rate).round() as u64)
+    };
+    let offset = offset.filter(|&offset| offset < frames).ok_or_else(|| ReviewError::NotInPage {
+        time: time.format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+        page: page.id(),
+    })?;
+
+    println!(
+        "Page {} ({}): {} UTC is {:.3}s into {} (frame {})",
+        page.id(),
+        page.endpoint,
+        time.format("%Y-%m-%d %H:%M:%S%.3f"),
+        offset as f64 / rate,
+        page.recording_file,
+        offset
+    );
+    if options.clip.is_none() && !options.play_audio {
+        return Ok(());
+    }
+
+    let around = (options.around.max(0.0) * rate).round() as u64;
+    let first = offset.saturating_sub(around);
+    let last = (offset + around).min(frames);
+    reader.seek(first as u32)?;
+    let count = (last - first) as usize * usize::from(spec.channels);
+    let samples = reader.samples::<i16>().take(count).collect::<Result<Vec<_>, _>>()?;
+
+    // Playback without --clip goes through a temporary file
+    let path = options
+        .clip
+        .clone()
+        .unwrap_or_else(|| std::env::temp_dir().join(format!("mpu_review_page_{}_at.wav", page.id())));
+    let mut writer = WavRecorder::new(&path, spec.sample_rate, spec.channels as u8)?;
+    writer.write_samples(&samples)?;
+    writer.finalize()?;
+    if options.clip.is_some() {
+        println!(
+            "Clip saved to {} ({:.3}s from {:.3}s into the page)",
+            path.display(),
+            (last - first) as f64 / rate,
+            first as f64 / rate
+        );
+    }
+    if options.play_audio {
+        let played = Player::open().and_then(|player| player.play(&path));
+        if options.clip.is_none() {
+            let _ = std::fs::remove_file(&path);
+        }
+        played?;
+    }
+    Ok(())
+}
+
+/// Stream metrics.jsonl for one page's snapshots and the notes made during it
+pub(crate) fn read_page_timeline(directory: &Path, page: &PageSummary) -> Result<PageTimeline, ReviewError> {
+    let file = File::open(directory.join("metrics.jsonl"))?;
+    let mut timeline = PageTimeline::read(BufReader::new(file), &page.endpoint, page.page_number)?;
+    timeline.clip_annotations(page.start_time, page.end_time);
+    Ok(timeline)
+}
+
 fn display_metrics_summary(directory: &Path) -> Result<(), ReviewError> {
     let metrics_path = directory.join("metrics.jsonl");
 
@@ -310,118 +1070,47 @@ fn display_metrics_summary(directory: &Path) -> Result<(), ReviewError> {
     Ok(())
 }
 
-fn format_frequency(freq: f64) -> String {
-    if freq <= 0.0 || !freq.is_finite() {
-        "-".to_string()
-    } else if freq >= 1000.0 {
-        format!("{:.1}kHz", freq / 1000.0)
-    } else {
-        format!("{:.0}Hz", freq)
-    }
+/// MOS to one decimal, "-" for summaries written before it was estimated
+pub(crate) fn format_mos(mos: Option<f64>) -> String {
+    mos.map_or_else(|| "-".to_string(), |v| format!("{:.1}", v))
 }
 
-/// Play a WAV file through the default audio output
-fn play_audio_file(path: &Path) -> Result<(), ReviewError> {
-    // Open WAV file
-    let mut reader = hound::WavReader::open(path)?;
-    let spec = reader.spec();
+/// Loudness to one decimal, "N/A" for pages too short to gate or
+/// summaries written before it was measured
+fn format_lufs(lufs: Option<f64>) -> String {
+    lufs.map_or_else(|| "N/A".to_string(), |v| format!("{:.1} LUFS", v))
+}
 
-    println!("    Format: {} channels, {}Hz, {}-bit",
-        spec.channels, spec.sample_rate, spec.bits_per_sample);
-
-    // Collect samples
-    let samples: Vec<i16> = if spec.bits_per_sample == 16 {
-        reader.samples::<i16>().filter_map(|s| s.ok()).collect()
-    } else if spec.bits_per_sample == 8 {
-        reader.samples::<i8>()
-            .filter_map(|s| s.ok())
-            .map(|s| (s as i16) << 8)
-            .collect()
+/// Colour an already padded MOS cell green, yellow or red
+pub(crate) fn colour_mos(cell: String, mos: Option<f64>) -> String {
+    let Some(mos) = mos else {
+        return cell;
+    };
+    let colour = if mos >= quality::GOOD_MOS {
+        "32"
+    } else if mos >= quality::FAIR_MOS {
+        "33"
     } else {
-        return Err(ReviewError::Audio(format!(
-            "Unsupported bit depth: {}", spec.bits_per_sample
-        )));
+        "31"
     };
+    paint(cell, colour)
+}
 
-    if samples.is_empty() {
-        println!("    (empty audio file)");
-        return Ok(());
+/// Wrap a cell in an ANSI colour. Only on a terminal, and not when
+/// `NO_COLOR` is set, so piped output stays plain.
+fn paint(cell: String, colour: &str) -> String {
+    if !io::stdout().is_terminal() || std::env::var_os("NO_COLOR").is_some() {
+        return cell;
     }
+    format!("\x1b[{}m{}\x1b[0m", colour, cell)
+}
 
-    // Set up audio output
-    let host = cpal::default_host();
-    let device = host.default_output_device()
-        .ok_or_else(|| ReviewError::Audio("No output device found".to_string()))?;
-
-    let config = cpal::StreamConfig {
-        channels: spec.channels,
-        sample_rate: cpal::SampleRate(spec.sample_rate),
-        buffer_size: cpal::BufferSize::Default,
-    };
-
-    let samples = Arc::new(samples);
-    let position = Arc::new(AtomicUsize::new(0));
-    let finished = Arc::new(AtomicBool::new(false));
-
-    let samples_clone = Arc::clone(&samples);
-    let position_clone = Arc::clone(&position);
-    let finished_clone = Arc::clone(&finished);
-
-    let stream = device.build_output_stream(
-        &config,
-        move |data: &mut [i16], _: &cpal::OutputCallbackInfo| {
-            let mut pos = position_clone.load(Ordering::Relaxed);
-            for sample in data.iter_mut() {
-                if pos < samples_clone.len() {
-                    *sample = samples_clone[pos];
-                    pos += 1;
-                } else {
-                    *sample = 0;
-                    finished_clone.store(true, Ordering::Relaxed);
-                }
-            }
-            position_clone.store(pos, Ordering::Relaxed);
-        },
-        |err| eprintln!("Audio stream error: {}", err),
-        None,
-    ).map_err(|e| ReviewError::Audio(e.to_string()))?;
-
-    stream.play().map_err(|e| ReviewError::Audio(e.to_string()))?;
-
-    // Calculate duration and show progress
-    let total_samples = samples.len();
-    let duration_secs = total_samples as f64 / (spec.sample_rate as f64 * spec.channels as f64);
-
-    print!("    Playing: [");
-    let bar_width = 40;
-
-    while !finished.load(Ordering::Relaxed) {
-        let pos = position.load(Ordering::Relaxed);
-        let progress = pos as f64 / total_samples as f64;
-        let filled = (progress * bar_width as f64) as usize;
-
-        print!("\r    Playing: [");
-        for i in 0..bar_width {
-            if i < filled {
-                print!("█");
-            } else {
-                print!("░");
-            }
-        }
-        let current_time = pos as f64 / (spec.sample_rate as f64 * spec.channels as f64);
-        print!("] {:.1}s / {:.1}s", current_time, duration_secs);
-
-        use std::io::Write;
-        std::io::stdout().flush().ok();
-
-        std::thread::sleep(Duration::from_millis(100));
+pub(crate) fn format_frequency(freq: f64) -> String {
+    if freq <= 0.0 || !freq.is_finite() {
+        "-".to_string()
+    } else if freq >= 1000.0 {
+        format!("{:.1}kHz", freq / 1000.0)
+    } else {
+        format!("{:.0}Hz", freq)
     }
-
-    println!("\r    Playing: [{}] {:.1}s / {:.1}s ✓",
-        "█".repeat(bar_width), duration_secs, duration_secs);
-
-    // Small delay to ensure playback completes
-    std::thread::sleep(Duration::from_millis(100));
-
-    Ok(())
 }
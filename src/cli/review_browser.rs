@@ -0,0 +1,571 @@
+//! `review --interactive`: browsing a run's pages from the keyboard.
+//!
+//! Scrolling back through a long run's tables is slow going, so on a
+//! terminal the pages list gets a cursor, moved with the arrow keys (or `j`
+//! and `k`), Page Up and Page Down, Home and End. The other views show the
+//! selected page: Enter its details, `m` its metrics timeline, `t` the tones
+//! found in it, and `p` plays it through the same path as `--play`. In
+//! those views the arrows move to the previous or next page and Esc goes
+//! back to the list. `/` filters the list by endpoint and minimum loss, as
+//! in `/loss>1 224.0.1.1`, and `q` quits. The views are the static review's
+//! own tables, drawn on a cleared screen.
+//!
+//! Only summary.json is read up front. metrics.jsonl is streamed each time
+//! the metrics view is drawn, keeping the selected page's snapshots alone,
+//! so a long run's metrics never sit in memory all at once. Without a
+//! terminal on stdin and stdout, `review` prints its usual output instead.
+
+use crate::cli::classifier::ContentClass;
+use crate::cli::playback::Player;
+use crate::cli::review::{self, ReviewError};
+use crate::cli::terminal::Key;
+use crate::cli::test::{PageSummary, TestSummary};
+use crate::cli::timeline;
+use std::fmt;
+use std::path::Path;
+
+/// Pages the cursor moves by on Page Up and Page Down
+const PAGE_STEP: usize = 10;
+
+/// Lines in the terminal when it doesn't say
+const DEFAULT_ROWS: usize = 24;
+
+/// Lines of the list screen that aren't pages: the title, the column
+/// heads, the key help, the status line and the cursor's line
+const LIST_CHROME: usize = 5;
+
+/// Which pages the list shows
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageFilter {
+    /// Pages whose endpoint contains this
+    pub endpoint: Option<String>,
+    /// Pages that lost more than this percentage of packets
+    pub min_loss_percent: Option<f64>,
+}
+
+impl PageFilter {
+    /// Read a filter typed after `/`: `loss>1` (or `loss>1%`) for pages
+    /// losing more than 1%, any other word for pages whose endpoint
+    /// contains it, or one of each. Nothing at all shows every page
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut filter = Self::default();
+        for term in text.split_whitespace() {
+            if let Some(value) = term.strip_prefix("loss>") {
+                let loss = value
+                    .trim_end_matches('%')
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|loss| loss.is_finite() && *loss >= 0.0)
+                    .ok_or_else(|| format!("'{}' is not a loss percentage", value))?;
+                filter.min_loss_percent = Some(loss);
+            } else if filter.endpoint.is_some() {
+                return Err(format!("'{}': only one endpoint can be given", term));
+            } else {
+                filter.endpoint = Some(term.to_string());
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, page: &PageSummary) -> bool {
+        self.endpoint.as_ref().is_none_or(|endpoint| page.endpoint.contains(endpoint.as_str()))
+            && self.min_loss_percent.is_none_or(|loss| page.network.loss_percent > loss)
+    }
+}
+
+/// `endpoint 224.0.1.1, loss > 1%`
+impl fmt::Display for PageFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.endpoint, self.min_loss_percent) {
+            (None, None) => write!(f, "all pages"),
+            (Some(endpoint), None) => write!(f, "endpoint {}", endpoint),
+            (None, Some(loss)) => write!(f, "loss > {}%", loss),
+            (Some(endpoint), Some(loss)) => write!(f, "endpoint {}, loss > {}%", endpoint, loss),
+        }
+    }
+}
+
+/// What the screen shows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum View {
+    /// The pages, with the cursor on one
+    List,
+    /// The selected page's details
+    Detail,
+    /// The selected page's metrics over time
+    Metrics,
+    /// The tones found in the selected page
+    Tones,
+    /// A filter being typed, over the list
+    Filter(String),
+}
+
+/// What to do after a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Nothing changed
+    None,
+    /// Draw the screen again
+    Draw,
+    /// Play the page at this index of the summary's pages
+    Play(usize),
+    Quit,
+}
+
+/// The browser's state, moved on by keys
+pub struct Browser<'a> {
+    pages: &'a [PageSummary],
+    filter: PageFilter,
+    /// Indexes into `pages` of those the filter lets through, in order
+    shown: Vec<usize>,
+    /// Position of the selected page in `shown`
+    cursor: usize,
+    view: View,
+    /// A line under the list until the next key, such as a bad filter
+    status: Option<String>,
+}
+
+impl<'a> Browser<'a> {
+    pub fn new(pages: &'a [PageSummary]) -> Self {
+        Self {
+            pages,
+            filter: PageFilter::default(),
+            shown: (0..pages.len()).collect(),
+            cursor: 0,
+            view: View::List,
+            status: None,
+        }
+    }
+
+    /// The page under the cursor, if any are shown
+    pub fn selected(&self) -> Option<&'a PageSummary> {
+        self.shown.get(self.cursor).map(|&index| &self.pages[index])
+    }
+
+    pub fn handle(&mut self, key: Key) -> Action {
+        if let View::Filter(ref mut text) = self.view {
+            match key {
+                Key::Char(c) => text.push(c),
+                Key::Backspace => {
+                    text.pop();
+                }
+                Key::Enter => {
+                    let parsed = PageFilter::parse(text);
+                    self.view = View::List;
+                    match parsed {
+                        Ok(filter) => self.set_filter(filter),
+                        Err(error) => self.status = Some(error),
+                    }
+                }
+                Key::Escape => self.view = View::List,
+                Key::Interrupt => return Action::Quit,
+                _ => return Action::None,
+            }
+            return Action::Draw;
+        }
+
+        let status = self.status.take();
+        let last = self.shown.len().saturating_sub(1);
+        match key {
+            Key::Char('q') | Key::Interrupt => return Action::Quit,
+            Key::Up | Key::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.cursor = (self.cursor + 1).min(last),
+            Key::PageUp => self.cursor = self.cursor.saturating_sub(PAGE_STEP),
+            Key::PageDown => self.cursor = (self.cursor + PAGE_STEP).min(last),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = last,
+            Key::Escape if self.view != View::List => self.view = View::List,
+            Key::Enter => self.open(View::Detail),
+            Key::Char('m') => self.toggle(View::Metrics),
+            Key::Char('t') => self.toggle(View::Tones),
+            Key::Char('p') => {
+                if let Some(&index) = self.shown.get(self.cursor) {
+                    return Action::Play(index);
+                }
+            }
+            Key::Char('/') => self.view = View::Filter(String::new()),
+            _ => {
+                self.status = status;
+                return Action::None;
+            }
+        }
+        Action::Draw
+    }
+
+    /// Show a view of the selected page, if there is one
+    fn open(&mut self, view: View) {
+        if self.selected().is_some() {
+            self.view = view;
+        }
+    }
+
+    /// Open `view`, or go back to the list if it's open already
+    fn toggle(&mut self, view: View) {
+        if self.view == view {
+            self.view = View::List;
+        } else {
+            self.open(view);
+        }
+    }
+
+    /// List the pages `filter` lets through, keeping the cursor on the
+    /// selected page if it is one of them
+    fn set_filter(&mut self, filter: PageFilter) {
+        let selected = self.shown.get(self.cursor).copied();
+        self.shown = (0..self.pages.len()).filter(|&index| filter.matches(&self.pages[index])).collect();
+        self.cursor = selected.and_then(|index| self.shown.iter().position(|&i| i == index)).unwrap_or(0);
+        self.filter = filter;
+    }
+
+    /// The list's title, column heads and up to `rows` pages, scrolled to
+    /// keep the cursor in the middle
+    fn list_lines(&self, rows: usize) -> Vec<String> {
+        let mut lines = vec![
+            format!("PAGES: {} of {} ({})", self.shown.len(), self.pages.len(), self.filter),
+            format!(
+                "  {:>4}  {:<21}  {:<8}  {:>7}  {:>6}  {:>3}  {:<7}",
+                "Page", "Endpoint", "Start", "Length", "Loss%", "MOS", "Content"
+            ),
+        ];
+        if self.shown.is_empty() {
+            lines.push("  No pages match.".to_string());
+            return lines;
+        }
+        let rows = rows.max(1);
+        let first = self.cursor.saturating_sub(rows / 2).min(self.shown.len().saturating_sub(rows));
+        for (position, &index) in self.shown.iter().enumerate().skip(first).take(rows) {
+            let page = &self.pages[index];
+            let marker = if position == self.cursor { '>' } else { ' ' };
+            lines.push(format!(
+                "{} {:>4}  {:<21}  {}  {:>6.1}s  {:>6.1}  {}  {:<7}",
+                marker,
+                page.id(),
+                page.endpoint,
+                page.start_time.format("%H:%M:%S"),
+                page.duration_secs,
+                page.network.loss_percent,
+                review::colour_mos(format!("{:>3}", review::format_mos(page.mos_estimate)), page.mos_estimate),
+                page.audio.content_class.map_or("-", ContentClass::name)
+            ));
+        }
+        lines
+    }
+
+    /// Clear the screen and draw the view, fitting the list into `rows`
+    /// lines. The metrics view streams metrics.jsonl for its page
+    fn draw(&self, directory: &Path, rows: usize) {
+        print!("\x1b[H\x1b[2J");
+        let page = self.selected();
+        match (&self.view, page) {
+            (View::Detail, Some(page)) => review::display_page_detail(page),
+            (View::Metrics, Some(page)) => match review::read_page_timeline(directory, page) {
+                Ok(timeline) => timeline::print_timeline(&timeline),
+                Err(error) => println!("  ⚠ No metrics for page {}: {}", page.id(), error),
+            },
+            (View::Tones, Some(page)) => tones_lines(page).iter().for_each(|line| println!("{}", line)),
+            _ => self.list_lines(rows.saturating_sub(LIST_CHROME)).iter().for_each(|line| println!("{}", line)),
+        }
+        match &self.view {
+            View::List => println!("↑/↓ move  Enter details  m metrics  t tones  p play  / filter  q quit"),
+            View::Filter(text) => println!("Filter (endpoint, loss>N): {}", text),
+            _ => println!("↑/↓ previous/next page  Enter details  m metrics  t tones  p play  Esc list  q quit"),
+        }
+        if let Some(ref status) = self.status {
+            println!("⚠ {}", status);
+        }
+    }
+
+    /// Play a page with the terminal as it was, noting a failure under the list
+    fn play(&mut self, directory: &Path, index: usize) {
+        print!("\x1b[H\x1b[2J");
+        let played = Player::open()
+            .map_err(ReviewError::from)
+            .and_then(|player| review::play_page(&player, directory, &self.pages[index]));
+        if let Err(error) = played {
+            self.status = Some(format!("Playback failed: {}", error));
+        }
+    }
+}
+
+/// The tones found in a page: the chime it opened with, the
+/// `transmit --watermark` ID and the frequency that dominated it
+fn tones_lines(page: &PageSummary) -> Vec<String> {
+    let chime = page.audio.chime.as_ref().map_or_else(|| "none".to_string(), |chime| format!("builtin:{}", chime));
+    let watermark = page.audio.watermark.map_or_else(
+        || "none".to_string(),
+        |mark| format!("ID {} (confidence {:.2})", mark.id, mark.confidence),
+    );
+    let content = match (page.audio.content_class, page.audio.content_confidence) {
+        (Some(class), Some(confidence)) => format!("{} ({:.0}% confident)", class, confidence * 100.0),
+        (Some(class), None) => class.to_string(),
+        (None, _) => "-".to_string(),
+    };
+    vec![
+        "┌─────────────────────────────────────────────────────────────────┐".to_string(),
+        format!("│ TONES IN PAGE {:<4}{:>45} │", page.id(), ""),
+        "├─────────────────────────────────────────────────────────────────┤".to_string(),
+        format!("│ Chime:        {:<50} │", chime),
+        format!("│ Watermark:    {:<50} │", watermark),
+        format!("│ Dominant:     {:<50} │", review::format_frequency(page.audio.dominant_freq_hz)),
+        format!("│ Content:      {:<50} │", content),
+        "└─────────────────────────────────────────────────────────────────┘".to_string(),
+    ]
+}
+
+/// Browse the pages until `q`. `Ok(false)` if stdin and stdout aren't
+/// both a terminal, for the caller to print the static review
+pub fn browse(directory: &Path, summary: &TestSummary) -> Result<bool, ReviewError> {
+    #[cfg(unix)]
+    {
+        unix::browse(directory, summary)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (directory, summary);
+        Ok(false)
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::{Action, Browser, DEFAULT_ROWS};
+    use crate::cli::review::ReviewError;
+    use crate::cli::terminal::{self, Modes};
+    use crate::cli::test::TestSummary;
+    use std::io::{self, IsTerminal, Read, Write};
+    use std::path::Path;
+
+    /// Puts the terminal back however browsing ends
+    struct Restore(Modes);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            self.0.restore();
+        }
+    }
+
+    pub(super) fn browse(directory: &Path, summary: &TestSummary) -> Result<bool, ReviewError> {
+        if !io::stdout().is_terminal() {
+            return Ok(false);
+        }
+        let Some(modes) = Modes::capture() else {
+            return Ok(false);
+        };
+        let _restore = Restore(modes);
+        modes.keys();
+
+        let rows = || terminal::rows().unwrap_or(DEFAULT_ROWS);
+        let mut browser = Browser::new(&summary.pages);
+        browser.draw(directory, rows());
+        io::stdout().flush()?;
+        let mut bytes = [0u8; 64];
+        loop {
+            let read = io::stdin().lock().read(&mut bytes)?;
+            if read == 0 {
+                return Ok(true);
+            }
+            for key in terminal::decode_keys(&bytes[..read]) {
+                match browser.handle(key) {
+                    Action::None => continue,
+                    Action::Draw => {}
+                    Action::Play(index) => {
+                        // Ctrl-C stops playback the usual way
+                        modes.restore();
+                        browser.play(directory, index);
+                        modes.keys();
+                    }
+                    Action::Quit => {
+                        println!();
+                        return Ok(true);
+                    }
+                }
+                browser.draw(directory, rows());
+                io::stdout().flush()?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::recorder::WavRecorder;
+    use crate::cli::terminal::decode_keys;
+    use chrono::{DateTime, Duration, Utc};
+
+    fn press(browser: &mut Browser<'_>, keys: &[u8]) -> Vec<Action> {
+        decode_keys(keys).into_iter().map(|key| browser.handle(key)).collect()
+    }
+
+    /// A run of 100 pages taking turns on two endpoints, every fifth losing
+    /// 2.5% of its packets and the rest 0.5%. Each has a short recording
+    /// and ten metrics snapshots
+    fn hundred_pages(dir: &Path) -> TestSummary {
+        let base: DateTime<Utc> = "2026-01-01T00:00:00Z".parse().unwrap();
+        let mut pages = Vec::new();
+        let mut metrics = Vec::new();
+        for id in 1..=100u32 {
+            let endpoint = if id % 2 == 1 { "224.0.1.1:5004" } else { "224.0.1.2:5004" };
+            let page_number = id.div_ceil(2);
+            let start = base + Duration::seconds(i64::from(id) * 10);
+            let recording = format!("page_{:04}.wav", id);
+            let mut recorder = WavRecorder::new(&dir.join(&recording), 8000, 1).unwrap();
+            recorder.write_samples(&[0; 800]).unwrap();
+            recorder.finalize().unwrap();
+            for second in 0..10 {
+                let snapshot = serde_json::json!({
+                    "timestamp": start + Duration::milliseconds(second * 500),
+                    "endpoint": endpoint,
+                    "page_active": true,
+                    "page_number": page_number,
+                    "network": {"packets": 25 * second, "bytes": 4000, "loss_percent": 0.5, "jitter_ms": 1.5},
+                    "audio": {"rms_db": -20.0, "peak_db": -6.0, "dominant_freq_hz": 1000.0, "glitches": 0, "clipped": 0}
+                });
+                metrics.push(snapshot.to_string());
+            }
+            pages.push(serde_json::json!({
+                "page_number": page_number,
+                "page_id": id,
+                "endpoint": endpoint,
+                "start_time": start,
+                "end_time": start + Duration::seconds(5),
+                "duration_secs": 5.0,
+                "recording_file": recording,
+                "network": {
+                    "packets_received": 250, "bytes_received": 40000, "packets_lost": 0,
+                    "loss_percent": if id % 5 == 0 { 2.5 } else { 0.5 }, "jitter_ms": 1.5, "max_gap_ms": 20.0
+                },
+                "audio": {
+                    "peak_rms_db": -15.0, "avg_rms_db": -20.0, "max_peak_db": -6.0,
+                    "dominant_freq_hz": 1000.0, "total_glitches": 0, "total_clipped": 0,
+                    "clipping_percent": 0.0, "avg_zero_crossing_rate": 2000.0,
+                    "chime": "ding", "watermark": {"id": id, "confidence": 0.9}
+                }
+            }));
+        }
+        std::fs::write(dir.join("metrics.jsonl"), metrics.join("\n")).unwrap();
+
+        serde_json::from_value(serde_json::json!({
+            "test_metadata": {
+                "start_time": base,
+                "end_time": base + Duration::seconds(1010),
+                "duration_secs": 1010.0,
+                "pattern": "224.0.1.1-2:5004",
+                "endpoints_monitored": 2,
+                "metrics_interval_ms": 500,
+                "timeout_secs": 1010
+            },
+            "pages": pages,
+            "endpoint_totals": {},
+            "errors": []
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_filter_open_and_play_one_of_a_hundred_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let summary = hundred_pages(dir.path());
+        let mut browser = Browser::new(&summary.pages);
+        assert_eq!(browser.shown.len(), 100);
+
+        press(&mut browser, b"/loss>1%");
+        assert_eq!(&browser.view, &View::Filter("loss>1%".to_string()));
+        press(&mut browser, b"\r");
+        assert_eq!(&browser.view, &View::List);
+        let ids: Vec<u32> = browser.shown.iter().map(|&index| summary.pages[index].id()).collect();
+        assert_eq!(ids, (1..=20).map(|n| n * 5).collect::<Vec<_>>());
+
+        // Down twice to page 15, then its details and its metrics
+        assert_eq!(press(&mut browser, b"\x1b[B\x1b[B\r"), vec![Action::Draw; 3]);
+        assert_eq!(&browser.view, &View::Detail);
+        let page = browser.selected().unwrap();
+        assert_eq!(page.id(), 15);
+        press(&mut browser, b"m");
+        assert_eq!(&browser.view, &View::Metrics);
+
+        // The metrics view reads only the selected page's snapshots
+        let timeline = review::read_page_timeline(dir.path(), page).unwrap();
+        assert_eq!(timeline.snapshots.len(), 10);
+        assert!(timeline.snapshots.iter().all(|s| s.endpoint == page.endpoint && s.page_number == Some(8)));
+
+        let played = press(&mut browser, b"p");
+        let [Action::Play(index)] = played[..] else {
+            panic!("p didn't play the page");
+        };
+        assert_eq!(summary.pages[index].id(), 15);
+        assert!(dir.path().join(&summary.pages[index].recording_file).exists());
+        assert_eq!(press(&mut browser, b"q"), vec![Action::Quit]);
+    }
+
+    #[test]
+    fn test_list_follows_the_cursor() {
+        let dir = tempfile::tempdir().unwrap();
+        let summary = hundred_pages(dir.path());
+        let mut browser = Browser::new(&summary.pages);
+
+        let lines = browser.list_lines(10);
+        assert_eq!(lines.len(), 12);
+        assert_eq!(lines[0], "PAGES: 100 of 100 (all pages)");
+        assert!(lines[2].starts_with(">    1  224.0.1.1:5004"), "{}", lines[2]);
+
+        // Page Down five times is page 51, in the middle of the window
+        press(&mut browser, &b"\x1b[6~".repeat(5));
+        let lines = browser.list_lines(10);
+        assert!(lines[7].starts_with(">   51  "), "{:#?}", lines);
+        press(&mut browser, b"\x1b[F");
+        assert!(browser.list_lines(10).last().unwrap().starts_with(">  100  "));
+        press(&mut browser, b"\x1b[6~k");
+        assert_eq!(browser.selected().unwrap().id(), 99);
+
+        // A filter keeps the cursor on the selected page where it can
+        press(&mut browser, b"/224.0.1.1\r");
+        assert_eq!(browser.selected().unwrap().id(), 99);
+        press(&mut browser, b"/224.0.1.2 loss>2\r");
+        assert_eq!(browser.selected().unwrap().id(), 10);
+        assert_eq!(browser.list_lines(10)[0], "PAGES: 10 of 100 (endpoint 224.0.1.2, loss > 2%)");
+
+        press(&mut browser, b"/loss>9\r");
+        assert!(browser.selected().is_none());
+        assert_eq!(press(&mut browser, b"\rp"), vec![Action::Draw, Action::Draw]);
+        assert_eq!(&browser.view, &View::List);
+        assert_eq!(browser.list_lines(10)[2], "  No pages match.");
+    }
+
+    #[test]
+    fn test_views_and_bad_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let summary = hundred_pages(dir.path());
+        let mut browser = Browser::new(&summary.pages);
+
+        press(&mut browser, b"t");
+        assert_eq!(&browser.view, &View::Tones);
+        let tones = tones_lines(browser.selected().unwrap());
+        assert!(tones[3].contains("builtin:ding") && tones[4].contains("ID 1 (confidence 0.90)"), "{:#?}", tones);
+        assert!(tones[5].contains("1.0kHz"), "{:#?}", tones);
+        // The arrows move between pages without leaving the view
+        press(&mut browser, b"j");
+        assert_eq!((&browser.view, browser.selected().unwrap().id()), (&View::Tones, 2));
+        press(&mut browser, b"t");
+        assert_eq!(&browser.view, &View::List);
+        press(&mut browser, b"\rm\x1b");
+        assert_eq!(&browser.view, &View::List);
+
+        // A bad filter is reported and leaves the list as it was
+        press(&mut browser, b"/loss>lots\r");
+        assert_eq!(browser.status.as_deref(), Some("'lots' is not a loss percentage"));
+        assert_eq!(browser.shown.len(), 100);
+        assert_eq!(press(&mut browser, b"x"), vec![Action::None]);
+        assert!(browser.status.is_some());
+        press(&mut browser, b"j");
+        assert_eq!(browser.status, None);
+        // Esc abandons a filter half typed
+        press(&mut browser, b"/loss>1\x7f\x7f\x7f\x7f\x7f\x7f\x7fx\x1b");
+        assert_eq!((&browser.view, browser.shown.len()), (&View::List, 100));
+
+        assert_eq!(PageFilter::parse("  ").unwrap(), PageFilter::default());
+        assert_eq!(PageFilter::parse("loss>0.5").unwrap().min_loss_percent, Some(0.5));
+        assert!(PageFilter::parse("a b").unwrap_err().contains("only one endpoint"));
+        assert!(PageFilter::parse("loss>-1").is_err());
+    }
+}
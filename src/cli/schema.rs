@@ -0,0 +1,288 @@
+//! JSON Schema for the `--json` event stream and the summaries.
+//!
+//! The schemas are generated from the Rust types, so they can't drift from
+//! what is written. Every event and summary carries `schema_version`. A
+//! removed or renamed field, a changed type or a dropped event is a breaking
+//! change and needs `SCHEMA_VERSION` bumped; added fields and events are not.
+//! The snapshots in `docs/schema/` hold each version's schemas, and a test
+//! fails when the types no longer match the current one.
+
+use crate::cli::monitor::JsonEvent;
+use crate::cli::polycom_monitor::PolycomSummary;
+use crate::cli::reanalysis::Reanalysis;
+use crate::cli::test::TestSummary;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// Version of every schema below; bump it with any breaking change
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A `JsonEvent` as written, tagged with the schema version
+#[derive(Serialize, JsonSchema)]
+pub struct VersionedEvent<'a> {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub event: &'a JsonEvent,
+}
+
+impl<'a> VersionedEvent<'a> {
+    pub fn new(event: &'a JsonEvent) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            event,
+        }
+    }
+}
+
+/// Which schema the `schema` command prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaKind {
+    /// Lines written by `--json` on monitor, transmit and the other commands
+    Events,
+    /// `summary.json` from `test`
+    TestSummary,
+    /// The summary `polycom-monitor --json` prints at the end
+    PolycomSummary,
+    /// `reanalysis.json` from `review --reanalyze --json`
+    Reanalysis,
+}
+
+impl SchemaKind {
+    pub const ALL: [SchemaKind; 4] = [
+        SchemaKind::Events,
+        SchemaKind::TestSummary,
+        SchemaKind::PolycomSummary,
+        SchemaKind::Reanalysis,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SchemaKind::Events => "events",
+            SchemaKind::TestSummary => "test-summary",
+            SchemaKind::PolycomSummary => "polycom-summary",
+            SchemaKind::Reanalysis => "reanalysis",
+        }
+    }
+
+    /// The JSON Schema, generated from the types
+    pub fn schema(self) -> serde_json::Value {
+        let schema = match self {
+            SchemaKind::Events => schemars::schema_for!(VersionedEvent<'static>),
+            SchemaKind::TestSummary => schemars::schema_for!(TestSummary),
+            SchemaKind::PolycomSummary => schemars::schema_for!(PolycomSummary),
+            SchemaKind::Reanalysis => schemars::schema_for!(Reanalysis),
+        };
+        schema.to_value()
+    }
+}
+
+impl fmt::Display for SchemaKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SchemaKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SchemaKind::ALL.into_iter().find(|kind| kind.name() == s).ok_or_else(|| {
+            let names: Vec<&str> = SchemaKind::ALL.iter().map(|kind| kind.name()).collect();
+            format!("unknown schema {:?} (expected one of: {})", s, names.join(", "))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::path::PathBuf;
+
+    fn snapshot_path(kind: SchemaKind, version: u32) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("docs/schema")
+            .join(format!("{}.v{}.json", kind.name(), version))
+    }
+
+    /// Follow `$ref`s into `$defs`
+    fn resolve<'a>(root: &'a Value, mut schema: &'a Value) -> &'a Value {
+        while let Some(name) = schema.get("$ref").and_then(Value::as_str).and_then(|r| r.strip_prefix("#/$defs/")) {
+            schema = &root["$defs"][name];
+        }
+        schema
+    }
+
+    fn types(schema: &Value) -> Vec<&str> {
+        let mut types: Vec<&str> = match schema.get("type") {
+            Some(Value::String(t)) => vec![t.as_str()],
+            Some(Value::Array(list)) => list.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        types.sort_unstable();
+        types
+    }
+
+    /// Alternatives keyed by their `event` tag, or by position
+    fn variants(schema: &Value, root: &Value) -> Vec<(String, Value)> {
+        ["oneOf", "anyOf"]
+            .iter()
+            .filter_map(|key| schema.get(*key).and_then(Value::as_array))
+            .flatten()
+            .enumerate()
+            .map(|(i, variant)| {
+                let variant = resolve(root, variant);
+                let key = variant
+                    .pointer("/properties/event/const")
+                    .and_then(Value::as_str)
+                    .map_or_else(|| format!("#{}", i), |tag| format!("event={}", tag));
+                (key, variant.clone())
+            })
+            .collect()
+    }
+
+    /// Changes from `old` to `new` that could break a reader of `old`
+    fn breaking_changes(old_root: &Value, new_root: &Value) -> Vec<String> {
+        let mut changes = Vec::new();
+        compare(old_root, new_root, old_root, new_root, "", &mut changes);
+        changes
+    }
+
+    fn compare(old_root: &Value, new_root: &Value, old: &Value, new: &Value, path: &str, changes: &mut Vec<String>) {
+        let (old, new) = (resolve(old_root, old), resolve(new_root, new));
+
+        let (old_types, new_types) = (types(old), types(new));
+        if old_types != new_types {
+            changes.push(format!("{}: type changed from {:?} to {:?}", path, old_types, new_types));
+        }
+        if old.get("const") != new.get("const") {
+            changes.push(format!("{}: value changed from {} to {}", path, old["const"], new["const"]));
+        }
+        if let Some(values) = old.get("enum").and_then(Value::as_array) {
+            let new_values = new.get("enum").and_then(Value::as_array).cloned().unwrap_or_default();
+            for value in values.iter().filter(|value| !new_values.contains(value)) {
+                changes.push(format!("{}: value {} removed", path, value));
+            }
+        }
+
+        let required = |schema: &Value| -> Vec<Value> { schema.get("required").and_then(Value::as_array).cloned().unwrap_or_default() };
+        let new_required = required(new);
+        for field in required(old).iter().filter(|field| !new_required.contains(field)) {
+            changes.push(format!("{}.{}: no longer always present", path, field.as_str().unwrap_or_default()));
+        }
+        if let Some(properties) = old.get("properties").and_then(Value::as_object) {
+            for (name, schema) in properties {
+                match new.pointer(&format!("/properties/{}", name)) {
+                    Some(new_schema) => {
+                        compare(old_root, new_root, schema, new_schema, &format!("{}.{}", path, name), changes);
+                    }
+                    None => changes.push(format!("{}.{}: removed", path, name)),
+                }
+            }
+        }
+        if let Some(items) = old.get("items") {
+            if let Some(new_items) = new.get("items") {
+                compare(old_root, new_root, items, new_items, &format!("{}[]", path), changes);
+            }
+        }
+
+        let new_variants = variants(new, new_root);
+        for (key, variant) in variants(old, old_root) {
+            match new_variants.iter().find(|(new_key, _)| *new_key == key) {
+                Some((_, new_variant)) => compare(old_root, new_root, &variant, new_variant, &format!("{}({})", path, key), changes),
+                None => changes.push(format!("{}({}): removed", path, key)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_schemas_match_snapshots() {
+        // UPDATE_SCHEMA=1 rewrites the snapshots, but only for compatible changes
+        let update = std::env::var_os("UPDATE_SCHEMA").is_some();
+        for kind in SchemaKind::ALL {
+            let path = snapshot_path(kind, SCHEMA_VERSION);
+            let current = kind.schema();
+            let snapshot: Option<Value> = std::fs::read_to_string(&path).ok().map(|text| serde_json::from_str(&text).unwrap());
+
+            if let Some(ref snapshot) = snapshot {
+                let breaking = breaking_changes(snapshot, &current);
+                assert!(
+                    breaking.is_empty(),
+                    "Breaking changes to the {} schema need SCHEMA_VERSION bumped past {}:\n  {}",
+                    kind,
+                    SCHEMA_VERSION,
+                    breaking.join("\n  ")
+                );
+            }
+            if update {
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(&path, serde_json::to_string_pretty(&current).unwrap() + "\n").unwrap();
+                continue;
+            }
+            assert_eq!(
+                snapshot.as_ref(),
+                Some(&current),
+                "The {} schema no longer matches {}; run UPDATE_SCHEMA=1 cargo test schema to refresh it",
+                kind,
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn test_breaking_changes() {
+        let old = json!({
+            "type": "object",
+            "properties": {"schema_version": {"type": "integer"}},
+            "oneOf": [
+                {"$ref": "#/$defs/Started"},
+                {"type": "object", "properties": {"event": {"const": "stopped"}, "reason": {"enum": ["done", "error"]}}}
+            ],
+            "$defs": {
+                "Started": {
+                    "type": "object",
+                    "properties": {"event": {"const": "started"}, "port": {"type": "integer"}, "codec": {"type": "string"}},
+                    "required": ["event", "port", "codec"]
+                }
+            }
+        });
+        assert!(breaking_changes(&old, &old).is_empty());
+
+        // Adding fields, values and events is compatible
+        let mut added = old.clone();
+        added["$defs"]["Started"]["properties"]["ttl"] = json!({"type": ["integer", "null"]});
+        added["oneOf"][1]["properties"]["reason"]["enum"] = json!(["done", "error", "timeout"]);
+        added["oneOf"].as_array_mut().unwrap().push(json!({"type": "object", "properties": {"event": {"const": "paused"}}}));
+        assert!(breaking_changes(&old, &added).is_empty());
+
+        let mut changed = old.clone();
+        changed["$defs"]["Started"]["properties"]["port"] = json!({"type": "string"});
+        changed["$defs"]["Started"]["properties"].as_object_mut().unwrap().remove("codec");
+        changed["$defs"]["Started"]["required"] = json!(["event", "port"]);
+        changed["oneOf"][1]["properties"]["reason"]["enum"] = json!(["done"]);
+        assert_eq!(
+            breaking_changes(&old, &changed),
+            [
+                "(event=started).codec: no longer always present",
+                "(event=started).codec: removed",
+                "(event=started).port: type changed from [\"integer\"] to [\"string\"]",
+                "(event=stopped).reason: value \"error\" removed",
+            ]
+        );
+
+        let mut dropped = old.clone();
+        dropped["oneOf"].as_array_mut().unwrap().remove(1);
+        assert_eq!(breaking_changes(&old, &dropped), ["(event=stopped): removed"]);
+    }
+
+    #[test]
+    fn test_events_carry_schema_version() {
+        let event = JsonEvent::Error { message: "boom".to_string() };
+        let line: Value = serde_json::to_value(VersionedEvent::new(&event)).unwrap();
+        assert_eq!(line, json!({"schema_version": SCHEMA_VERSION, "event": "error", "message": "boom"}));
+        assert_eq!("test-summary".parse::<SchemaKind>(), Ok(SchemaKind::TestSummary));
+        assert!("summary".parse::<SchemaKind>().unwrap_err().contains("events, test-summary, polycom-summary"));
+    }
+}
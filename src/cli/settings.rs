@@ -0,0 +1,689 @@
+//! Settings taken from the environment, for containerized deployments.
+//!
+//! The most-used flags can also be set with `MPU_*` variables, or in a
+//! `.env` file in the working directory. Every flag in [`ENV_SETTINGS`] is
+//! resolved the same way, first match wins:
+//!
+//! 1. the command line
+//! 2. the environment
+//! 3. `.env` (never overriding a variable that is already set)
+//! 4. the config file, for the flags it has a key for
+//! 5. the built-in default
+//!
+//! The config file is `--config` (or `MPU_CONFIG`) if given, which must
+//! then exist, otherwise the standard location, where it is optional.
+//!
+//! The environment layers are clap's `env` attribute, added to each flag
+//! here rather than in the derive; the config file's values become the
+//! flags' defaults. Each flag's source is kept so the startup output,
+//! `summary.json` and `--print-effective-config` can say where its value
+//! came from.
+
+use crate::cli::{exit_code, Cli};
+use crate::config::{closest, Config, NetworkConfig, TestConfig};
+use clap::builder::FalseyValueParser;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Read from the working directory, if it exists
+pub const ENV_FILE: &str = ".env";
+
+/// `MPU_*` variables read elsewhere, so not mistaken for misspelt settings.
+/// `MPU_GIT_HASH` is the build's; cargo also sets it under `cargo run` and `cargo test`.
+const OTHER_VARIABLES: &[&str] = &[crate::codec::FFMPEG_ENV, "MPU_GIT_HASH", CONFIG_ENV];
+
+/// Names the config file, like `--config`
+pub const CONFIG_ENV: &str = "MPU_CONFIG";
+
+/// Commands that read the config file themselves, so a missing or bad
+/// `--config` is theirs to report
+const CONFIG_COMMANDS: &[&str] = &["config", "validate-config"];
+
+/// Commands that send or receive plain RTP, where `network.default_port` applies
+const RTP_COMMANDS: &[&str] = &["monitor", "test", "transmit", "igmp-cycle", "bridge"];
+
+/// Flags that take the place of one that can come from the environment,
+/// e.g. `--until` over `--timeout`. clap counts a value from the environment
+/// as given, so `conflicts_with` would refuse `--until` whenever
+/// `MPU_TIMEOUT` is set; only a clash on the command line is refused
+const SUPERSEDING_FLAGS: &[(&str, &str)] = &[("until", "timeout")];
+
+/// The config file's value for a flag on a command, if it sets one. Given
+/// the command's arguments as far as they are known without it.
+type ConfigValue = fn(&Config, &str, Option<&ArgMatches>) -> Option<String>;
+
+/// A flag that can also be set from the environment
+pub struct EnvSetting {
+    pub variable: &'static str,
+    /// Argument id, the field name in `Commands`
+    pub arg: &'static str,
+    pub commands: &'static [&'static str],
+    config: Option<ConfigValue>,
+}
+
+pub const ENV_SETTINGS: &[EnvSetting] = &[
+    EnvSetting {
+        variable: "MPU_ADDRESS",
+        arg: "address",
+        commands: &["monitor", "test", "transmit", "polycom-transmit", "polycom-monitor", "igmp-cycle", "bridge"],
+        config: None,
+    },
+    EnvSetting {
+        variable: "MPU_PORT",
+        arg: "port",
+        commands: &["monitor", "test", "transmit", "polycom-transmit", "polycom-monitor", "igmp-cycle", "bridge"],
+        config: Some(|config, command, _| {
+            let port = config.network.default_port;
+            (RTP_COMMANDS.contains(&command) && port != NetworkConfig::default().default_port).then(|| port.to_string())
+        }),
+    },
+    EnvSetting {
+        variable: "MPU_INTERFACE",
+        arg: "interface",
+        commands: &["monitor", "test", "igmp-cycle", "bridge", "doctor"],
+        config: None,
+    },
+    EnvSetting {
+        variable: "MPU_OUTPUT_DIR",
+        arg: "output",
+        commands: &["monitor", "test", "polycom-monitor", "doctor"],
+        // monitor's --output is a file prefix unless pages are named into it
+        config: Some(|config, command, matches| {
+            if command == "monitor" && matches.and_then(|m| m.get_raw("name_template")).is_none() {
+                return None;
+            }
+            config.output.directory_at(chrono::Local::now())
+        }),
+    },
+    EnvSetting {
+        variable: "MPU_TIMEOUT",
+        arg: "timeout",
+        commands: &["monitor", "test", "polycom-monitor", "igmp-cycle", "bridge"],
+        config: None,
+    },
+    EnvSetting {
+        variable: "MPU_CODEC",
+        arg: "codec",
+        commands: &["monitor", "test", "transmit", "polycom-transmit"],
+        // The receiving commands detect the codec unless told otherwise
+        config: Some(|config, command, _| {
+            let codec = &config.network.default_codec;
+            (command == "transmit" && *codec != NetworkConfig::default().default_codec).then(|| codec.clone())
+        }),
+    },
+    EnvSetting {
+        variable: "MPU_JSON",
+        arg: "json",
+        commands: &["monitor", "transmit", "polycom-transmit", "polycom-monitor", "igmp-cycle", "bridge"],
+        config: None,
+    },
+    EnvSetting {
+        variable: "MPU_METRICS_INTERVAL",
+        arg: "metrics_interval",
+        commands: &["test"],
+        config: Some(|config, _, _| {
+            let interval = config.test.metrics_interval_ms;
+            (interval != TestConfig::default().metrics_interval_ms).then(|| interval.to_string())
+        }),
+    },
+    EnvSetting {
+        variable: "MPU_TTL",
+        arg: "ttl",
+        commands: &["transmit", "polycom-transmit", "igmp-cycle", "bridge"],
+        config: Some(|config, _, _| {
+            let ttl = config.network.default_ttl;
+            (ttl != NetworkConfig::default().default_ttl).then(|| ttl.to_string())
+        }),
+    },
+    EnvSetting {
+        variable: "MPU_SITE",
+        arg: "site",
+        commands: &["monitor", "test", "polycom-monitor"],
+        config: Some(|config, _, _| config.site.code.clone()),
+    },
+    EnvSetting {
+        variable: "MPU_CHANNEL",
+        arg: "channel",
+        commands: &["polycom-transmit", "bridge"],
+        config: None,
+    },
+    EnvSetting {
+        variable: "MPU_CALLER_ID",
+        arg: "caller_id",
+        commands: &["polycom-transmit", "bridge"],
+        // Per channel, so it depends on the channel chosen
+        config: Some(|config, _, matches| {
+            let channel = *matches?.get_one::<u8>("channel")?;
+            config.polycom.caller_id(channel).map(String::from)
+        }),
+    },
+];
+
+/// Where a setting's value came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingSource {
+    CommandLine,
+    Environment,
+    EnvFile,
+    ConfigFile,
+    Default,
+}
+
+impl SettingSource {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::CommandLine => "command line",
+            Self::Environment => "environment",
+            Self::EnvFile => ".env",
+            Self::ConfigFile => "config file",
+            Self::Default => "default",
+        }
+    }
+}
+
+impl fmt::Display for SettingSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// One flag's resolved value
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct EffectiveSetting {
+    /// The flag, without its dashes
+    pub name: String,
+    /// The environment variable that can set it
+    pub variable: String,
+    /// Absent if nothing set it and it has no default
+    pub value: Option<String>,
+    pub source: SettingSource,
+}
+
+impl EffectiveSetting {
+    /// Set by something other than the command line or the built-in default,
+    /// so not visible in the invocation
+    pub fn is_implicit(&self) -> bool {
+        matches!(
+            self.source,
+            SettingSource::Environment | SettingSource::EnvFile | SettingSource::ConfigFile
+        )
+    }
+}
+
+impl fmt::Display for EffectiveSetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "--{} {}", self.name.replace('_', "-"), self.value.as_deref().unwrap_or("(not set)"))
+    }
+}
+
+/// The config file a run read, as recorded in its summary
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ConfigFileRecord {
+    pub path: String,
+    /// SHA-256 of the file, hex
+    pub sha256: String,
+}
+
+/// How the invoked command's settings were resolved
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    pub command: Option<String>,
+    pub settings: Vec<EffectiveSetting>,
+    /// The `.env` file read, if there was one
+    pub env_file: Option<PathBuf>,
+    pub config_file: PathBuf,
+    /// The file was named with `--config` or `MPU_CONFIG`
+    pub config_explicit: bool,
+    /// The configuration in effect: the file's, or the defaults if there is
+    /// none or it couldn't be used
+    pub config: Config,
+    /// Set when the file was read and used
+    pub config_record: Option<ConfigFileRecord>,
+    /// To log once logging is set up: unreadable files and unknown variables
+    pub warnings: Vec<String>,
+}
+
+impl Settings {
+    /// The settings set outside the invocation, for startup output
+    pub fn implicit(&self) -> impl Iterator<Item = &EffectiveSetting> {
+        self.settings.iter().filter(|setting| setting.is_implicit())
+    }
+
+    /// `--print-effective-config`
+    pub fn print(&self) {
+        println!("# Precedence: command line > environment > .env > config file > default");
+        match self.env_file {
+            Some(ref path) => println!("# .env: {}", path.display()),
+            None => println!("# .env: none"),
+        }
+        println!(
+            "# Config file: {}{}",
+            self.config_file.display(),
+            match self.config_record {
+                Some(_) => "",
+                None if self.config_file.exists() => " (not used)",
+                None => " (not found)",
+            }
+        );
+        for warning in &self.warnings {
+            println!("# Warning: {}", warning);
+        }
+        let Some(ref command) = self.command else {
+            println!("# No command given");
+            return;
+        };
+        if self.settings.is_empty() {
+            println!("# {} takes none of its flags from the environment", command);
+            return;
+        }
+        println!("{}", command);
+        let width = self.settings.iter().map(|setting| setting.to_string().len()).max().unwrap_or(0);
+        for setting in &self.settings {
+            let source = match setting.source {
+                SettingSource::Environment | SettingSource::EnvFile => {
+                    format!("{} ({})", setting.source, setting.variable)
+                }
+                source => source.to_string(),
+            };
+            println!("  {:<width$}  # {}", setting.to_string(), source, width = width);
+        }
+    }
+}
+
+/// Parse the command line with the environment, `.env` and config file
+/// layered underneath it. Runs before anything else, while the process has
+/// one thread, because `.env` is read into the environment. With
+/// `--print-effective-config`, prints the settings and exits.
+pub fn parse() -> (Cli, Settings) {
+    let mut warnings = Vec::new();
+    let mut from_env_file = BTreeSet::new();
+    let env_file = Path::new(ENV_FILE);
+    let env_file = match load_env_file(env_file, &mut warnings) {
+        Ok(Some(names)) => {
+            from_env_file = names;
+            Some(env_file.to_path_buf())
+        }
+        Ok(None) => None,
+        Err(e) => {
+            warnings.push(format!("can't read {}: {}", env_file.display(), e));
+            None
+        }
+    };
+    warnings.extend(unknown_variables(std::env::vars().map(|(name, _)| name)));
+
+    let printing = std::env::args_os().any(|arg| arg == "--print-effective-config");
+    let command = with_env(Cli::command());
+    // A first pass finds what a config default can depend on (the Polycom
+    // channel, a name template) and the command being run
+    let first_pass = command.clone().ignore_errors(true).try_get_matches().ok();
+    let invoked = first_pass.as_ref().and_then(|matches| matches.subcommand_name());
+
+    let explicit = config_arg(std::env::args_os()).or_else(|| std::env::var_os(CONFIG_ENV).map(PathBuf::from));
+    let config_explicit = explicit.is_some();
+    let config_file = explicit.unwrap_or_else(Config::config_path);
+    let mut config_record = None;
+    let config = match Config::load_or_default(&config_file, config_explicit) {
+        Ok(config) => {
+            config_record = record_config_file(&config_file);
+            config
+        }
+        // Commands that need the config file report a bad one themselves
+        Err(_) if invoked.is_some_and(|name| CONFIG_COMMANDS.contains(&name)) => Config::default(),
+        // A file asked for by name is not quietly swapped for the defaults
+        Err(e) if config_explicit && !printing => {
+            eprintln!("error: {}", e);
+            std::process::exit(exit_code::USAGE);
+        }
+        Err(e) => {
+            warnings.push(format!("config file ignored: {}", e));
+            Config::default()
+        }
+    };
+    let (command, from_config) = with_config_defaults(command, &config, first_pass.as_ref());
+    let mut command = command.ignore_errors(printing);
+    let matches = command.get_matches_mut();
+
+    let subcommand = matches.subcommand();
+    if let Some((name, sub_matches)) = subcommand {
+        check_superseded(&mut command, name, sub_matches);
+    }
+    let settings = Settings {
+        command: subcommand.map(|(name, _)| name.to_string()),
+        settings: subcommand
+            .map(|(name, matches)| resolve(name, matches, &from_env_file, &from_config))
+            .unwrap_or_default(),
+        env_file,
+        config_file,
+        config_explicit,
+        config,
+        config_record,
+        warnings,
+    };
+    if printing {
+        settings.print();
+        std::process::exit(0);
+    }
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    (cli, settings)
+}
+
+/// The `--config` value, found before the command line is parsed since the
+/// file supplies defaults for it. Arguments after `--` are left alone.
+fn config_arg(args: impl Iterator<Item = OsString>) -> Option<PathBuf> {
+    let mut args = args.skip(1).take_while(|arg| arg != "--");
+    let mut found = None;
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            found = args.next().map(PathBuf::from);
+        } else if let Some(value) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            found = Some(PathBuf::from(value));
+        }
+    }
+    found
+}
+
+/// Path and hash of the config file, if there is one to read
+fn record_config_file(path: &Path) -> Option<ConfigFileRecord> {
+    let content = std::fs::read(path).ok()?;
+    let sha256 = format!("{:x}", Sha256::digest(content));
+    Some(ConfigFileRecord {
+        path: path.display().to_string(),
+        sha256,
+    })
+}
+
+/// Refuse a flag together with the one it takes the place of, when both are
+/// on the command line
+fn check_superseded(command: &mut Command, name: &str, matches: &ArgMatches) {
+    let on_command_line = |id: &str| {
+        matches.try_get_raw(id).ok().flatten().is_some() && matches.value_source(id) == Some(ValueSource::CommandLine)
+    };
+    for &(flag, superseded) in SUPERSEDING_FLAGS {
+        if on_command_line(flag) && on_command_line(superseded) {
+            let sub = command.find_subcommand_mut(name).expect("matched subcommand exists");
+            sub.error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("the argument '--{}' cannot be used with '--{}'", flag, superseded),
+            )
+            .exit();
+        }
+    }
+}
+
+/// Add each setting's variable to its flags
+pub fn with_env(mut command: Command) -> Command {
+    for setting in ENV_SETTINGS {
+        for &name in setting.commands {
+            command = command.mut_subcommand(name, |sub| {
+                sub.mut_arg(setting.arg, |arg| {
+                    let flag = matches!(arg.get_action(), ArgAction::SetTrue);
+                    let arg = arg.env(setting.variable);
+                    // Accept 1/0, yes/no and on/off as well as true/false
+                    if flag {
+                        arg.value_parser(FalseyValueParser::new())
+                    } else {
+                        arg
+                    }
+                })
+            });
+        }
+    }
+    command
+}
+
+/// Make the config file's values the flags' defaults. Returns the
+/// `(command, arg)` pairs it set.
+fn with_config_defaults(
+    mut command: Command,
+    config: &Config,
+    first_pass: Option<&ArgMatches>,
+) -> (Command, BTreeSet<(&'static str, &'static str)>) {
+    let mut set = BTreeSet::new();
+    for setting in ENV_SETTINGS {
+        let Some(value_for) = setting.config else {
+            continue;
+        };
+        for &name in setting.commands {
+            let matches = first_pass
+                .and_then(|matches| matches.subcommand())
+                .filter(|&(invoked, _)| invoked == name)
+                .map(|(_, matches)| matches);
+            if let Some(value) = value_for(config, name, matches) {
+                // A default doesn't satisfy `required` (test's --output), so drop it
+                command = command.mut_subcommand(name, |sub| {
+                    sub.mut_arg(setting.arg, |arg| arg.default_value(value).required(false))
+                });
+                set.insert((name, setting.arg));
+            }
+        }
+    }
+    (command, set)
+}
+
+/// Each setting of `command` with its value and where it came from
+fn resolve(
+    command: &str,
+    matches: &ArgMatches,
+    from_env_file: &BTreeSet<String>,
+    from_config: &BTreeSet<(&'static str, &'static str)>,
+) -> Vec<EffectiveSetting> {
+    ENV_SETTINGS
+        .iter()
+        .filter(|setting| setting.commands.contains(&command))
+        .map(|setting| {
+            let source = match matches.value_source(setting.arg) {
+                Some(ValueSource::CommandLine) => SettingSource::CommandLine,
+                Some(ValueSource::EnvVariable) if from_env_file.contains(setting.variable) => SettingSource::EnvFile,
+                Some(ValueSource::EnvVariable) => SettingSource::Environment,
+                Some(ValueSource::DefaultValue) if from_config.contains(&(command, setting.arg)) => {
+                    SettingSource::ConfigFile
+                }
+                _ => SettingSource::Default,
+            };
+            // A flag's variable may say yes or 1; report what it became
+            let value = match matches.try_get_one::<bool>(setting.arg) {
+                Ok(flag) => flag.map(bool::to_string),
+                Err(_) => matches.get_raw(setting.arg).map(|values| {
+                    values.map(|value| value.to_string_lossy()).collect::<Vec<_>>().join(",")
+                }),
+            };
+            EffectiveSetting {
+                name: setting.arg.to_string(),
+                variable: setting.variable.to_string(),
+                value,
+                source,
+            }
+        })
+        .collect()
+}
+
+/// Read `path` into the environment, leaving variables that are already set
+/// alone. Returns the names it set, or None if there is no such file.
+fn load_env_file(path: &Path, warnings: &mut Vec<String>) -> io::Result<Option<BTreeSet<String>>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut set = BTreeSet::new();
+    for entry in parse_env_file(&content) {
+        match entry {
+            Ok((name, value)) => {
+                if std::env::var_os(&name).is_none() {
+                    std::env::set_var(&name, value);
+                    set.insert(name);
+                }
+            }
+            Err(line) => warnings.push(format!("{}:{}: expected NAME=VALUE", path.display(), line)),
+        }
+    }
+    Ok(Some(set))
+}
+
+/// `NAME=VALUE` lines, optionally prefixed with `export`, with `#` comments
+/// and quoted values. A line that isn't one is an error with its number.
+fn parse_env_file(content: &str) -> Vec<Result<(String, String), usize>> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let Some((name, value)) = line.split_once('=') else {
+                return Some(Err(index + 1));
+            };
+            let name = name.trim();
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Some(Err(index + 1));
+            }
+            let value = value.trim();
+            let value = match value.chars().next() {
+                Some(quote @ ('"' | '\'')) if value.len() > 1 && value.ends_with(quote) => &value[1..value.len() - 1],
+                _ => value.split(" #").next().unwrap_or_default().trim_end(),
+            };
+            Some(Ok((name.to_string(), value.to_string())))
+        })
+        .collect()
+}
+
+/// Warnings for `MPU_*` variables that aren't settings, probably misspelt
+fn unknown_variables(names: impl Iterator<Item = String>) -> Vec<String> {
+    let known = || ENV_SETTINGS.iter().map(|setting| setting.variable).chain(OTHER_VARIABLES.iter().copied());
+    names
+        .filter(|name| name.starts_with("MPU_") && !known().any(|known| known == name))
+        .map(|name| match closest(&name, known()) {
+            Some(suggestion) => format!("{} is not a setting (did you mean {}?)", name, suggestion),
+            None => format!("{} is not a setting", name),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_name_real_flags() {
+        let command = Cli::command();
+        for setting in ENV_SETTINGS {
+            for &name in setting.commands {
+                let sub = command.find_subcommand(name).unwrap_or_else(|| panic!("no command {}", name));
+                assert!(
+                    sub.get_arguments().any(|arg| arg.get_id() == setting.arg),
+                    "{} has no {} for {}",
+                    name,
+                    setting.arg,
+                    setting.variable
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_env_file() {
+        let entries = parse_env_file(
+            "# probe settings\n\
+             MPU_ADDRESS=224.0.1.1:5004\n\
+             export MPU_TIMEOUT = 60 # a minute\n\
+             MPU_CALLER_ID=\"Front Office # 2\"\n\
+             \n\
+             not a setting\n",
+        );
+        assert_eq!(
+            entries,
+            [
+                Ok(("MPU_ADDRESS".to_string(), "224.0.1.1:5004".to_string())),
+                Ok(("MPU_TIMEOUT".to_string(), "60".to_string())),
+                Ok(("MPU_CALLER_ID".to_string(), "Front Office # 2".to_string())),
+                Err(6),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_precedence() {
+        let config = Config::parse(
+            "[site]\ncode = \"HQ\"\n\n[output]\ndirectory = \"runs/%Y\"\n\n\
+             [network]\ndefault_ttl = 8\n\n[test]\nmetrics_interval_ms = 250\n",
+        )
+        .unwrap();
+        let parse = |args: &[&str]| {
+            let command = Cli::command();
+            let first_pass = command.clone().ignore_errors(true).try_get_matches_from(args).ok();
+            let (command, from_config) = with_config_defaults(command, &config, first_pass.as_ref());
+            let matches = command.try_get_matches_from(args).unwrap();
+            let (name, matches) = matches.subcommand().unwrap();
+            resolve(name, matches, &BTreeSet::new(), &from_config)
+        };
+        let find = |settings: &[EffectiveSetting], name: &str| {
+            let setting = settings.iter().find(|setting| setting.name == name).unwrap();
+            (setting.value.clone().unwrap_or_default(), setting.source)
+        };
+
+        // The config file fills in what the command line leaves out, even test's required --output
+        let settings = parse(&["mpu", "test", "-a", "224.0.1.1:5004", "-t", "10"]);
+        assert_eq!(find(&settings, "metrics_interval"), ("250".to_string(), SettingSource::ConfigFile));
+        assert_eq!(find(&settings, "site"), ("HQ".to_string(), SettingSource::ConfigFile));
+        let (output, source) = find(&settings, "output");
+        assert!(output.starts_with("runs/2"), "{}", output);
+        assert_eq!(source, SettingSource::ConfigFile);
+
+        let settings = parse(&[
+            "mpu", "test", "-a", "224.0.1.1:5004", "-t", "10", "-o", "results", "--site", "DC", "--metrics-interval", "100",
+        ]);
+        assert_eq!(find(&settings, "metrics_interval"), ("100".to_string(), SettingSource::CommandLine));
+        assert_eq!(find(&settings, "site"), ("DC".to_string(), SettingSource::CommandLine));
+        assert_eq!(find(&settings, "output"), ("results".to_string(), SettingSource::CommandLine));
+
+        assert_eq!(find(&parse(&["mpu", "igmp-cycle", "-a", "224.0.1.1"]), "ttl"), ("8".to_string(), SettingSource::ConfigFile));
+        let settings = parse(&["mpu", "igmp-cycle", "-a", "224.0.1.1", "--ttl", "2"]);
+        assert_eq!(find(&settings, "ttl"), ("2".to_string(), SettingSource::CommandLine));
+
+        // monitor's --output is only a directory when pages are named into it
+        let settings = parse(&["mpu", "monitor", "-a", "224.0.1.1:5004"]);
+        assert_eq!(find(&settings, "output"), (String::new(), SettingSource::Default));
+        let settings = parse(&["mpu", "monitor", "-a", "224.0.1.1:5004", "--name-template", "{page}.wav"]);
+        assert_eq!(find(&settings, "output").1, SettingSource::ConfigFile);
+    }
+
+    #[test]
+    fn test_config_arg() {
+        let args = |args: &[&str]| config_arg(args.iter().map(OsString::from));
+        assert_eq!(args(&["mpu", "monitor", "-a", "224.0.1.1"]), None);
+        assert_eq!(args(&["mpu", "--config", "site.toml", "monitor"]), Some(PathBuf::from("site.toml")));
+        assert_eq!(args(&["mpu", "monitor", "--config=site.toml"]), Some(PathBuf::from("site.toml")));
+        assert_eq!(args(&["mpu", "transmit", "--", "--config", "site.toml"]), None);
+    }
+
+    #[test]
+    fn test_config_file_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        assert_eq!(record_config_file(&path), None);
+        std::fs::write(&path, "").unwrap();
+        let record = record_config_file(&path).unwrap();
+        assert_eq!(record.sha256, "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(record.path, path.display().to_string());
+    }
+
+    #[test]
+    fn test_unknown_variables() {
+        let names = ["MPU_PROT", "MPU_PORT", "MPU_FFMPEG", "MPU_SOMETHING", "PATH"].map(String::from);
+        assert_eq!(
+            unknown_variables(names.into_iter()),
+            ["MPU_PROT is not a setting (did you mean MPU_PORT?)", "MPU_SOMETHING is not a setting"]
+        );
+    }
+}
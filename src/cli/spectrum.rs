@@ -0,0 +1,421 @@
+//! Spectrum snapshots recorded during monitoring, and their rendering as
+//! spectrograms.
+//!
+//! While a page is active the analyzer's averaged magnitude spectrum is
+//! written periodically as one JSON row per snapshot. `review --spectrogram`
+//! renders the rows as an ASCII waterfall or a PNG image.
+
+use crate::cli::audio_analyzer::{AudioAnalyzer, Spectrum};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Brightness range of the rendered spectrogram below its loudest bin
+const DYNAMIC_RANGE_DB: f32 = 80.0;
+
+/// Lowest frequency on the waterfall's log axis
+const WATERFALL_MIN_HZ: f64 = 20.0;
+
+/// Characters from quiet to loud for the ASCII waterfall
+const WATERFALL_RAMP: &[u8] = b" .:-=+*#%@";
+
+/// Pixel rows per snapshot in PNG exports
+const PNG_ROW_HEIGHT: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum SpectrumError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid spectrum row on line {line}: {source}")]
+    InvalidRow { line: usize, source: serde_json::Error },
+
+    #[error("No spectrum rows in {0}")]
+    Empty(PathBuf),
+}
+
+/// One spectrum snapshot for an active page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpectrumRow {
+    pub timestamp: DateTime<Utc>,
+    pub endpoint: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub page: Option<u32>,
+    pub sample_rate: u32,
+    /// Width of each bin in Hz; bin `i` is centered on `i * bin_hz`
+    pub bin_hz: f64,
+    /// Magnitude per bin in dBFS, rounded to 0.1 dB
+    pub magnitudes_db: Vec<f32>,
+}
+
+impl SpectrumRow {
+    pub fn new(spectrum: &Spectrum, endpoint: String, page: Option<u32>) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            endpoint,
+            page,
+            sample_rate: spectrum.sample_rate,
+            bin_hz: spectrum.bin_width_hz(),
+            magnitudes_db: spectrum
+                .magnitudes_db()
+                .into_iter()
+                .map(|db| (db * 10.0).round() / 10.0)
+                .collect(),
+        }
+    }
+}
+
+/// Appends spectrum rows for one page to a JSONL file
+pub struct SpectrumWriter {
+    writer: BufWriter<File>,
+    endpoint: String,
+    page: Option<u32>,
+}
+
+impl SpectrumWriter {
+    pub fn create(path: &Path, endpoint: String, page: Option<u32>) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            endpoint,
+            page,
+        })
+    }
+
+    /// Write the analyzer's spectrum averaged since the last sample, if any
+    pub fn sample(&mut self, analyzer: &mut AudioAnalyzer) -> io::Result<()> {
+        let Some(spectrum) = analyzer.take_spectrum() else {
+            return Ok(());
+        };
+        let row = SpectrumRow::new(&spectrum, self.endpoint.clone(), self.page);
+        serde_json::to_writer(&mut self.writer, &row)?;
+        writeln!(self.writer)?;
+        // Flushed per row so the file can be followed while monitoring
+        self.writer.flush()
+    }
+}
+
+/// Spectrum file for a test-mode page, named after its recording and in
+/// the same subdirectory
+pub fn page_spectrum_path(dir: &Path, recording_file: &str) -> PathBuf {
+    let recording = Path::new(recording_file);
+    let stem = recording
+        .file_stem()
+        .map_or_else(|| recording_file.to_string(), |s| s.to_string_lossy().into_owned());
+    dir.join(recording.parent().unwrap_or(Path::new(""))).join(format!("{}.spectrum.jsonl", stem))
+}
+
+/// Read all rows from a spectrum file
+pub fn read_spectrum_rows(path: &Path) -> Result<Vec<SpectrumRow>, SpectrumError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut rows = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row = serde_json::from_str(&line).map_err(|source| SpectrumError::InvalidRow { line: i + 1, source })?;
+        rows.push(row);
+    }
+    if rows.is_empty() {
+        return Err(SpectrumError::Empty(path.to_path_buf()));
+    }
+    Ok(rows)
+}
+
+/// Loudest bin across all rows, the top of the rendered range
+fn peak_db(rows: &[SpectrumRow]) -> f32 {
+    rows.iter()
+        .flat_map(|row| row.magnitudes_db.iter().copied())
+        .fold(f32::NEG_INFINITY, f32::max)
+}
+
+/// Position of a level within the rendered range, 0.0 (floor) to 1.0 (peak)
+fn level(db: f32, peak: f32) -> f32 {
+    ((db - (peak - DYNAMIC_RANGE_DB)) / DYNAMIC_RANGE_DB).clamp(0.0, 1.0)
+}
+
+/// Frequency range covered by each column of a log-scaled axis
+fn log_columns(min_hz: f64, max_hz: f64, columns: usize) -> Vec<(f64, f64)> {
+    let ratio = (max_hz / min_hz).powf(1.0 / columns as f64);
+    (0..columns)
+        .map(|c| (min_hz * ratio.powi(c as i32), min_hz * ratio.powi(c as i32 + 1)))
+        .collect()
+}
+
+/// Render rows as an ASCII waterfall: time runs down, frequency runs right on
+/// a log axis so low-frequency hum and its harmonics are visible.
+pub fn render_waterfall(rows: &[SpectrumRow], columns: usize) -> String {
+    let Some(first) = rows.first() else {
+        return String::new();
+    };
+    let peak = peak_db(rows);
+    let nyquist = first.sample_rate as f64 / 2.0;
+    let min_hz = WATERFALL_MIN_HZ.max(first.bin_hz / 2.0);
+    let axis = log_columns(min_hz, nyquist, columns);
+
+    let mut out = String::new();
+    for row in rows {
+        let elapsed = (row.timestamp - first.timestamp).num_milliseconds() as f64 / 1000.0;
+        let _ = write!(out, "{:>7.1}s |", elapsed);
+        for &(low, high) in &axis {
+            // Every column shows at least its nearest bin, even where bins are wider than columns
+            let first_bin = (low / row.bin_hz).round() as usize;
+            let last_bin = ((high / row.bin_hz).round() as usize).max(first_bin);
+            let db = row.magnitudes_db[first_bin.min(row.magnitudes_db.len() - 1)..]
+                .iter()
+                .take(last_bin - first_bin + 1)
+                .copied()
+                .fold(f32::NEG_INFINITY, f32::max);
+            let index = (level(db, peak) * (WATERFALL_RAMP.len() - 1) as f32).round() as usize;
+            out.push(WATERFALL_RAMP[index] as char);
+        }
+        out.push_str("|\n");
+    }
+
+    // Frequency labels under the columns nearest to some landmarks
+    let mut labels = vec![b' '; columns + 10];
+    for hz in [60.0, 120.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0] {
+        if hz >= nyquist || hz < min_hz {
+            continue;
+        }
+        let column = axis.iter().position(|&(low, high)| (low..high).contains(&hz)).unwrap_or(0);
+        let label = if hz >= 1000.0 { format!("^{}k", hz / 1000.0) } else { format!("^{}", hz) };
+        let start = column + 10;
+        if labels[start.saturating_sub(1)..(start + label.len()).min(labels.len())].iter().all(|&c| c == b' ') {
+            for (i, b) in label.bytes().enumerate() {
+                if let Some(slot) = labels.get_mut(start + i) {
+                    *slot = b;
+                }
+            }
+        }
+    }
+    out.push_str(String::from_utf8_lossy(&labels).trim_end());
+    out.push('\n');
+    out
+}
+
+/// Heat colormap: black, blue, red, yellow, white
+fn heat_color(level: f32) -> [u8; 3] {
+    const STOPS: [[f32; 3]; 5] = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 160.0],
+        [200.0, 0.0, 40.0],
+        [255.0, 210.0, 0.0],
+        [255.0, 255.0, 255.0],
+    ];
+    let position = level * (STOPS.len() - 1) as f32;
+    let index = (position.floor() as usize).min(STOPS.len() - 2);
+    let t = position - index as f32;
+    let mut color = [0u8; 3];
+    for (c, out) in color.iter_mut().enumerate() {
+        *out = (STOPS[index][c] + (STOPS[index + 1][c] - STOPS[index][c]) * t).round() as u8;
+    }
+    color
+}
+
+/// Spectrogram as RGB pixels: one column per bin (linear frequency, DC on the
+/// left) and `PNG_ROW_HEIGHT` pixel rows per snapshot, time running down.
+pub fn spectrogram_pixels(rows: &[SpectrumRow]) -> (usize, usize, Vec<u8>) {
+    let width = rows.iter().map(|row| row.magnitudes_db.len()).max().unwrap_or(0);
+    let height = rows.len() * PNG_ROW_HEIGHT;
+    let peak = peak_db(rows);
+
+    let mut pixels = Vec::with_capacity(width * height * 3);
+    for row in rows {
+        let line: Vec<u8> = (0..width)
+            .flat_map(|bin| {
+                let db = row.magnitudes_db.get(bin).copied().unwrap_or(f32::NEG_INFINITY);
+                heat_color(level(db, peak))
+            })
+            .collect();
+        for _ in 0..PNG_ROW_HEIGHT {
+            pixels.extend_from_slice(&line);
+        }
+    }
+    (width, height, pixels)
+}
+
+/// Export rows as a PNG spectrogram
+pub fn write_spectrogram_png(rows: &[SpectrumRow], path: &Path) -> io::Result<()> {
+    let (width, height, pixels) = spectrogram_pixels(rows);
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(&encode_png(width as u32, height as u32, &pixels))?;
+    file.flush()
+}
+
+// ============================================================================
+// Minimal PNG encoder (8-bit RGB, uncompressed deflate blocks)
+// ============================================================================
+
+fn encode_png(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, truecolor, deflate, no filter, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // Each scanline starts with filter type 0 (none)
+    let stride = width as usize * 3;
+    let mut raw = Vec::with_capacity((stride + 1) * height as usize);
+    for line in rgb.chunks(stride.max(1)).take(height as usize) {
+        raw.push(0);
+        raw.extend_from_slice(line);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wrap data in a zlib stream of stored (uncompressed) deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 65535;
+    let mut out = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = if data.is_empty() { vec![&[]] } else { data.chunks(MAX_BLOCK).collect() };
+    for (i, block) in blocks.iter().enumerate() {
+        out.push(u8::from(i == blocks.len() - 1));
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rows from an analyzer fed 60 Hz hum under a 1 kHz tone
+    fn hum_rows(sample_rate: u32) -> Vec<SpectrumRow> {
+        let mut analyzer = AudioAnalyzer::new(sample_rate);
+        let frame = sample_rate as usize / 50;
+        let mut rows = Vec::new();
+        for packet in 0..100 {
+            let samples: Vec<i16> = (0..frame)
+                .map(|i| {
+                    let t = (packet * frame + i) as f64 / sample_rate as f64;
+                    let hum = 6000.0 * (2.0 * std::f64::consts::PI * 60.0 * t).sin();
+                    let tone = 12000.0 * (2.0 * std::f64::consts::PI * 1000.0 * t).sin();
+                    (hum + tone) as i16
+                })
+                .collect();
+            analyzer.analyze(&samples);
+            if packet % 10 == 9 {
+                let spectrum = analyzer.take_spectrum().unwrap();
+                rows.push(SpectrumRow::new(&spectrum, "224.0.1.1:5004".to_string(), Some(1)));
+            }
+        }
+        rows
+    }
+
+    #[test]
+    fn test_spectrogram_shows_hum_and_tone() {
+        for sample_rate in [8000, 16000] {
+            let rows = hum_rows(sample_rate);
+            let (width, height, pixels) = spectrogram_pixels(&rows);
+            assert_eq!(width, 256);
+            assert_eq!(height, rows.len() * PNG_ROW_HEIGHT);
+
+            // Brightness of a column, summed over the image
+            let column = |bin: usize| -> u32 {
+                (0..height)
+                    .map(|y| pixels[(y * width + bin) * 3..][..3].iter().map(|&c| u32::from(c)).sum::<u32>())
+                    .sum()
+            };
+            let bin_hz = rows[0].bin_hz;
+            let hum = (60.0 / bin_hz).round() as usize;
+            let tone = (1000.0 / bin_hz).round() as usize;
+            let quiet = (3000.0 / bin_hz).round() as usize;
+
+            // Both ridges stand out from the empty part of the spectrum
+            assert!(column(hum) > 4 * column(quiet).max(1), "no 60 Hz ridge at {} Hz", sample_rate);
+            assert!(column(tone) > 4 * column(quiet).max(1), "no 1 kHz ridge at {} Hz", sample_rate);
+            assert!(column(tone) > column(tone + 8));
+        }
+    }
+
+    #[test]
+    fn test_waterfall_marks_ridges() {
+        let rows = hum_rows(8000);
+        let waterfall = render_waterfall(&rows, 64);
+        let lines: Vec<&str> = waterfall.lines().collect();
+        assert_eq!(lines.len(), rows.len() + 1);
+
+        // The 1 kHz column is at full brightness in every row
+        let labels = lines.last().unwrap();
+        let tone_column = labels.find("^1k").unwrap();
+        assert!(lines[..rows.len()].iter().all(|line| line.as_bytes()[tone_column] == b'@'));
+        let hum_column = labels.find("^60").unwrap();
+        assert!(lines[..rows.len()].iter().all(|line| line.as_bytes()[hum_column] != b' '));
+    }
+
+    #[test]
+    fn test_rows_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = page_spectrum_path(dir.path(), "page_0001_224_0_1_1_5004.wav");
+        assert!(path.ends_with("page_0001_224_0_1_1_5004.spectrum.jsonl"));
+
+        let mut analyzer = AudioAnalyzer::new(8000);
+        let mut writer = SpectrumWriter::create(&path, "224.0.1.1:5004".to_string(), Some(1)).unwrap();
+        // Nothing analyzed yet - nothing written
+        writer.sample(&mut analyzer).unwrap();
+        analyzer.analyze(&vec![1000; 1024]);
+        writer.sample(&mut analyzer).unwrap();
+        drop(writer);
+
+        let rows = read_spectrum_rows(&path).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].page, Some(1));
+        assert_eq!(rows[0].magnitudes_db.len(), 256);
+        assert!((rows[0].bin_hz - 15.625).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_png_encoding() {
+        let png = encode_png(2, 1, &[255, 0, 0, 0, 255, 0]);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        // IEND chunk with its well-known CRC
+        assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xae\x42\x60\x82");
+
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+}
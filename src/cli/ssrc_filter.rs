@@ -0,0 +1,102 @@
+//! Picking streams out by SSRC.
+//!
+//! When two people page test audio to the same group at once, each wants
+//! their own stream analyzed and not the other's. `monitor` and `test` take
+//! `--ssrc` to allow only the SSRCs given, and `--ignore-ssrc` to drop the
+//! ones given; with `transmit --ssrc` pinning the sender's, a transmitter
+//! and a monitor pair up however many others share the group. A packet the
+//! filter turns away is counted against its endpoint as filtered and goes
+//! no further: it never starts a page, and never reaches the stats, the
+//! decoder or the recording. The filter is the same for every endpoint, but
+//! each endpoint applies it to its own packets, so an allowed SSRC arriving
+//! on several endpoints makes a page on each of them.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Parse an SSRC as given on the command line: hex, with or without `0x`
+pub fn parse_ssrc(s: &str) -> Result<u32, String> {
+    let digits = s.trim();
+    let digits = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")).unwrap_or(digits);
+    u32::from_str_radix(digits, 16).map_err(|_| format!("'{}' is not an SSRC: expected up to 8 hex digits, e.g. 1A2B3C4D", s))
+}
+
+/// SSRCs to allow and to drop
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SsrcFilter {
+    /// Only these SSRCs are let through; any are if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<u32>,
+    /// These SSRCs are dropped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<u32>,
+}
+
+impl SsrcFilter {
+    pub fn new(allow: Vec<u32>, ignore: Vec<u32>) -> Self {
+        Self { allow, ignore }
+    }
+
+    /// Whether a packet with this SSRC goes on to the endpoint
+    pub fn passes(&self, ssrc: u32) -> bool {
+        (self.allow.is_empty() || self.allow.contains(&ssrc)) && !self.ignore.contains(&ssrc)
+    }
+
+    /// Whether any SSRC is turned away
+    pub fn is_active(&self) -> bool {
+        !self.allow.is_empty() || !self.ignore.is_empty()
+    }
+
+    /// The filter, for the JSON, if there is one
+    pub fn record(&self) -> Option<Self> {
+        self.is_active().then(|| self.clone())
+    }
+}
+
+/// `only SSRC 0x1a2b3c4d, ignoring SSRC 0x0badf00d`
+impl fmt::Display for SsrcFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |ssrcs: &[u32]| ssrcs.iter().map(|ssrc| format!("{:#010x}", ssrc)).collect::<Vec<_>>().join(", ");
+        match (self.allow.is_empty(), self.ignore.is_empty()) {
+            (true, true) => write!(f, "any SSRC"),
+            (false, true) => write!(f, "only SSRC {}", list(&self.allow)),
+            (true, false) => write!(f, "ignoring SSRC {}", list(&self.ignore)),
+            (false, false) => write!(f, "only SSRC {}, ignoring SSRC {}", list(&self.allow), list(&self.ignore)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ssrc() {
+        assert_eq!(parse_ssrc("1a2b3c4d"), Ok(0x1a2b_3c4d));
+        assert_eq!(parse_ssrc("0x1A2B3C4D"), Ok(0x1a2b_3c4d));
+        assert_eq!(parse_ssrc("0XFF"), Ok(0xff));
+        for bad in ["", "0x", "1a2b3c4d5", "-1", "xyz"] {
+            assert!(parse_ssrc(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_allow_and_ignore() {
+        let any = SsrcFilter::default();
+        assert!(any.passes(1) && !any.is_active());
+        assert_eq!(any.record(), None);
+
+        let allow = SsrcFilter::new(vec![1, 2], Vec::new());
+        assert!(allow.passes(1) && allow.passes(2) && !allow.passes(3));
+
+        // Ignoring wins over allowing
+        let both = SsrcFilter::new(vec![1, 2], vec![2]);
+        assert!(both.passes(1) && !both.passes(2) && !both.passes(3));
+        assert_eq!(both.to_string(), "only SSRC 0x00000001, 0x00000002, ignoring SSRC 0x00000002");
+
+        let ignore = SsrcFilter::new(Vec::new(), vec![0x0bad_f00d]);
+        assert!(ignore.passes(1) && !ignore.passes(0x0bad_f00d));
+        assert_eq!(serde_json::to_value(&ignore).unwrap(), serde_json::json!({"ignore": [0x0bad_f00d_u32]}));
+    }
+}
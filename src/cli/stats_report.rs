@@ -0,0 +1,480 @@
+//! Periodic stats reports for `monitor`, kept off the receive path.
+//!
+//! The receive loop publishes a [`StatsSnapshot`] per endpoint to a shared
+//! [`StatsBoard`], and a [`StatsReporter`] task turns the board into `stats`
+//! events (or the progress line) every `--stats-interval`. Formatting a
+//! hundred endpoints' worth of JSON, or a stdout pipe that is slow to drain,
+//! then holds up the reporter rather than packet handling: the receive loop
+//! only ever `try_lock`s a snapshot, and skips the refresh if the reporter
+//! happens to be writing it out.
+//!
+//! Page boundaries are the exception. `page_ended` is printed with the
+//! endpoint's snapshot locked and already marked idle, so a report for the
+//! page can't land after it, and a snapshot only goes active once
+//! `page_started` has been printed.
+//!
+//! Reports are held back while the operator types an annotation, so the
+//! line being typed isn't written over.
+//!
+//! The same board backs `--status-listen`, along with how each endpoint's
+//! last page went, recorded as it ends.
+
+use crate::cli::audio_analyzer::{format_db, format_frequency};
+use crate::cli::monitor::{format_ttl, GapHistogram, JsonEvent, TtlSummary};
+use crate::cli::schema::VersionedEvent;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, TryLockError};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant, MissedTickBehavior};
+
+/// An endpoint's figures as of the receive loop's last refresh
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StatsSnapshot {
+    pub page_active: bool,
+    /// Set once the endpoint is no longer monitored
+    #[serde(skip)]
+    pub removed: bool,
+    pub duration_secs: f64,
+    pub packets: u64,
+    pub bytes: u64,
+    pub jitter_ms: f64,
+    pub loss_percent: f64,
+    pub max_gap_ms: f64,
+    pub gap_histogram: GapHistogram,
+    pub continuity_percent: f64,
+    pub ttl: Option<TtlSummary>,
+    pub rms_db: f64,
+    pub peak_db: f64,
+    pub dominant_freq_hz: f64,
+    pub glitches: u64,
+    pub clipped: u64,
+    pub decode_errors: u64,
+    pub truncated_payloads: u64,
+}
+
+impl StatsSnapshot {
+    /// An endpoint between pages
+    pub fn idle() -> Self {
+        Self {
+            rms_db: f64::NEG_INFINITY,
+            peak_db: f64::NEG_INFINITY,
+            ..Self::default()
+        }
+    }
+
+    fn event(&self, address: Ipv4Addr, port: u16) -> JsonEvent {
+        JsonEvent::Stats {
+            address: address.to_string(),
+            port,
+            page_active: self.page_active,
+            duration_secs: self.duration_secs,
+            packets: self.packets,
+            bytes: self.bytes,
+            jitter_ms: self.jitter_ms,
+            loss_percent: self.loss_percent,
+            max_gap_ms: self.max_gap_ms,
+            gap_histogram: self.gap_histogram,
+            continuity_percent: self.continuity_percent,
+            ttl: self.ttl,
+            rms_db: self.rms_db,
+            peak_db: self.peak_db,
+            dominant_freq_hz: self.dominant_freq_hz,
+            glitches: self.glitches,
+            clipped: self.clipped,
+            decode_errors: self.decode_errors,
+            truncated_payloads: self.truncated_payloads,
+        }
+    }
+}
+
+/// How an endpoint's most recent page went
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LastPage {
+    pub ended: DateTime<Utc>,
+    pub duration_secs: f64,
+    pub packets: u64,
+    pub loss_percent: f64,
+    pub jitter_ms: f64,
+    pub avg_rms_db: f64,
+    pub max_peak_db: f64,
+    pub mos_estimate: Option<f64>,
+    /// Why the page was cut short, if it was
+    pub error: Option<String>,
+}
+
+/// One endpoint's snapshot, shared between the receive loop and the reporter
+#[derive(Debug, Clone)]
+pub struct SharedStats {
+    snapshot: Arc<Mutex<StatsSnapshot>>,
+    last_page: Arc<Mutex<Option<LastPage>>>,
+}
+
+impl Default for SharedStats {
+    fn default() -> Self {
+        Self {
+            snapshot: Arc::new(Mutex::new(StatsSnapshot::idle())),
+            last_page: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+impl SharedStats {
+    /// Replace the snapshot, unless the reporter is writing it out right
+    /// now. Never waits; returns whether the snapshot was replaced
+    pub fn publish(&self, snapshot: StatsSnapshot) -> bool {
+        match self.snapshot.try_lock() {
+            Ok(mut current) => *current = snapshot,
+            Err(TryLockError::Poisoned(poisoned)) => *poisoned.into_inner() = snapshot,
+            Err(TryLockError::WouldBlock) => return false,
+        }
+        true
+    }
+
+    /// Mark the page over, waiting out a report in progress. The reporter
+    /// can't see the endpoint again until the guard is dropped, so print
+    /// `page_ended` while holding it
+    pub fn end_page(&self) -> MutexGuard<'_, StatsSnapshot> {
+        let mut snapshot = self.lock();
+        *snapshot = StatsSnapshot::idle();
+        snapshot
+    }
+
+    /// Keep `page` as the endpoint's last, once it has ended
+    pub fn record_page(&self, page: LastPage) {
+        *self.last_page.lock().unwrap_or_else(PoisonError::into_inner) = Some(page);
+    }
+
+    /// Stop reporting the endpoint at all
+    pub fn remove(&self) {
+        self.lock().removed = true;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, StatsSnapshot> {
+        self.snapshot.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn last_page(&self) -> Option<LastPage> {
+        self.last_page.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+}
+
+/// An endpoint as the board has it, copied out
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointStatus {
+    pub address: Ipv4Addr,
+    pub port: u16,
+    #[serde(flatten)]
+    pub current: StatsSnapshot,
+    pub last_page: Option<LastPage>,
+}
+
+/// What gets reported, and how often
+#[derive(Debug, Clone, Copy)]
+pub struct ReportSettings {
+    pub interval: Duration,
+    pub json: bool,
+    /// Skip endpoints without a page in progress. The progress line always does
+    pub active_only: bool,
+}
+
+/// Every monitored endpoint's snapshot, in address order
+#[derive(Debug, Clone, Default)]
+pub struct StatsBoard {
+    endpoints: Arc<RwLock<BTreeMap<(Ipv4Addr, u16), SharedStats>>>,
+    /// Set while an annotation is being typed
+    paused: Arc<AtomicBool>,
+}
+
+impl StatsBoard {
+    /// Replace the set of endpoints, e.g. after a control command
+    pub fn set_endpoints(&self, endpoints: impl IntoIterator<Item = ((Ipv4Addr, u16), SharedStats)>) {
+        *self.endpoints.write().unwrap_or_else(PoisonError::into_inner) = endpoints.into_iter().collect();
+    }
+
+    /// While set, reports are skipped
+    pub fn pause_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.paused)
+    }
+
+    /// Every monitored endpoint as it stands, in address order
+    pub fn endpoint_status(&self) -> Vec<EndpointStatus> {
+        self.handles()
+            .into_iter()
+            .filter_map(|((address, port), shared)| {
+                let current = shared.lock().clone();
+                (!current.removed).then(|| EndpointStatus {
+                    address,
+                    port,
+                    current,
+                    last_page: shared.last_page(),
+                })
+            })
+            .collect()
+    }
+
+    /// Write one report to `out`, returning how many endpoints were in it
+    pub fn report(&self, settings: &ReportSettings, out: &mut impl Write) -> io::Result<usize> {
+        if self.paused.load(Ordering::SeqCst) {
+            return Ok(0);
+        }
+        let endpoints = self.handles();
+        let single_endpoint = endpoints.len() == 1;
+
+        let mut reported = 0;
+        for ((address, port), shared) in endpoints {
+            // Held while writing, so a page can't end in the middle of its report
+            let snapshot = shared.lock();
+            if snapshot.removed || (!snapshot.page_active && (settings.active_only || !settings.json)) {
+                continue;
+            }
+
+            if settings.json {
+                let json = serde_json::to_string(&VersionedEvent::new(&snapshot.event(address, port))).map_err(io::Error::other)?;
+                writeln!(out, "{}", json)?;
+            } else {
+                let prefix = if single_endpoint {
+                    String::new()
+                } else {
+                    format!("[{}:{}] ", address, port)
+                };
+                write!(
+                    out,
+                    "\r{}Time: {:02}:{:02} | RMS: {} | Peak: {} | Freq: {} | Glitch: {} | Loss: {:.1}% | TTL: {}",
+                    prefix,
+                    ((snapshot.duration_secs % 3600.0) / 60.0) as u32,
+                    (snapshot.duration_secs % 60.0) as u32,
+                    format_db(snapshot.rms_db),
+                    format_db(snapshot.peak_db),
+                    format_frequency(snapshot.dominant_freq_hz),
+                    snapshot.glitches,
+                    snapshot.loss_percent,
+                    format_ttl(snapshot.ttl)
+                )?;
+                let damaged = snapshot.decode_errors + snapshot.truncated_payloads;
+                if damaged > 0 {
+                    write!(out, " | Damaged: {}", damaged)?;
+                }
+                write!(out, "   ")?;
+                out.flush()?;
+            }
+            reported += 1;
+        }
+        Ok(reported)
+    }
+
+    /// Copy the handles out, so a control command never waits on a slow write
+    fn handles(&self) -> Vec<((Ipv4Addr, u16), SharedStats)> {
+        self.endpoints
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(key, shared)| (*key, shared.clone()))
+            .collect()
+    }
+}
+
+/// Task writing a report of the board every interval
+pub struct StatsReporter {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl StatsReporter {
+    /// Start reporting to `out`, first after one interval
+    pub fn spawn<W: Write + Send + 'static>(board: StatsBoard, settings: ReportSettings, mut out: W) -> Self {
+        let (stop, mut stopped) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            let mut ticker = time::interval_at(Instant::now() + settings.interval, settings.interval);
+            // A report held up by a slow reader is followed by one interval of quiet, not a burst
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = &mut stopped => break,
+                    _ = ticker.tick() => {}
+                }
+                let board = board.clone();
+                // Writes can block on the reader, so keep them off the runtime's workers
+                let written = tokio::task::spawn_blocking(move || {
+                    board.report(&settings, &mut out).ok();
+                    out
+                })
+                .await;
+                match written {
+                    Ok(returned) => out = returned,
+                    Err(_) => break,
+                }
+            }
+        });
+        Self { stop, task }
+    }
+
+    /// Stop reporting once any report in progress is written
+    pub async fn stop(self) {
+        self.stop.send(()).ok();
+        self.task.await.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Lines written, shared with the test
+    #[derive(Clone, Default)]
+    struct Lines(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Lines {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Lines {
+        fn events(&self) -> Vec<serde_json::Value> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+
+        /// Whole lines written so far
+        fn count(&self) -> usize {
+            String::from_utf8_lossy(&self.0.lock().unwrap()).matches('\n').count()
+        }
+    }
+
+    fn active(packets: u64) -> StatsSnapshot {
+        StatsSnapshot {
+            page_active: true,
+            packets,
+            ..StatsSnapshot::idle()
+        }
+    }
+
+    fn board(snapshots: &[StatsSnapshot]) -> (StatsBoard, Vec<SharedStats>) {
+        let shared: Vec<SharedStats> = snapshots
+            .iter()
+            .map(|snapshot| {
+                let shared = SharedStats::default();
+                shared.publish(snapshot.clone());
+                shared
+            })
+            .collect();
+        let board = StatsBoard::default();
+        board.set_endpoints((1u8..).zip(&shared).map(|(i, s)| ((Ipv4Addr::new(224, 0, 1, i), 5004), s.clone())));
+        (board, shared)
+    }
+
+    const JSON: ReportSettings = ReportSettings {
+        interval: Duration::from_secs(1),
+        json: true,
+        active_only: false,
+    };
+
+    #[test]
+    fn test_active_only_filter() {
+        let (board, shared) = board(&[active(50), StatsSnapshot::idle(), active(7)]);
+
+        let all = Lines::default();
+        assert_eq!(board.report(&JSON, &mut all.clone()).unwrap(), 3);
+        let events = all.events();
+        assert!(events.iter().all(|e| e["event"] == "stats"));
+        assert_eq!(events[1]["address"], "224.0.1.2");
+        assert_eq!(events[1]["page_active"], false);
+        assert!(events[1]["rms_db"].is_null(), "silence is -inf, written as null");
+
+        let active_only = ReportSettings { active_only: true, ..JSON };
+        let filtered = Lines::default();
+        assert_eq!(board.report(&active_only, &mut filtered.clone()).unwrap(), 2);
+        let packets: Vec<_> = filtered.events().iter().map(|e| e["packets"].as_u64().unwrap()).collect();
+        assert_eq!(packets, [50, 7]);
+
+        // Removed endpoints and ended pages drop out
+        shared[0].remove();
+        drop(shared[2].end_page());
+        assert_eq!(board.report(&active_only, &mut io::sink()).unwrap(), 0);
+        assert_eq!(board.report(&JSON, &mut io::sink()).unwrap(), 2);
+
+        // The progress line never covers idle endpoints
+        let text = ReportSettings { json: false, ..JSON };
+        shared[1].publish(active(3));
+        assert_eq!(board.report(&text, &mut io::sink()).unwrap(), 1);
+
+        // Nothing while an annotation is typed
+        board.pause_flag().store(true, Ordering::SeqCst);
+        assert_eq!(board.report(&text, &mut io::sink()).unwrap(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interval_honored() {
+        let (board, _shared) = board(&[active(1)]);
+        let out = Lines::default();
+        let settings = ReportSettings {
+            interval: Duration::from_millis(100),
+            ..JSON
+        };
+        let reporter = StatsReporter::spawn(board, settings, out.clone());
+        // Let it start its clock
+        tokio::task::yield_now().await;
+
+        // One report as each interval passes, none before
+        for reports in 1..=5 {
+            time::advance(Duration::from_millis(99)).await;
+            assert_eq!(out.count(), reports - 1);
+            time::advance(Duration::from_millis(1)).await;
+            while out.count() < reports {
+                tokio::task::yield_now().await;
+            }
+        }
+        reporter.stop().await;
+        assert_eq!(out.events().len(), 5);
+    }
+
+    #[test]
+    fn test_page_end_waits_for_report() {
+        /// Takes its time over every write, like a backed-up pipe
+        struct Slow(Lines);
+        impl Write for Slow {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                thread::sleep(Duration::from_millis(200));
+                self.0.write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let (board, shared) = board(&[active(1)]);
+        let out = Lines::default();
+        let mut slow = Slow(out.clone());
+        let reporting = thread::spawn(move || board.report(&JSON, &mut slow).unwrap());
+        thread::sleep(Duration::from_millis(50));
+
+        // Refreshes mid-report are skipped rather than waiting
+        let started = std::time::Instant::now();
+        assert!(!shared[0].publish(active(2)));
+        assert!(started.elapsed() < Duration::from_millis(50));
+
+        // page_ended goes out after the page's last report, never before it
+        let ended = shared[0].end_page();
+        writeln!(out.clone(), "{{\"event\":\"page_ended\"}}").unwrap();
+        drop(ended);
+        assert_eq!(reporting.join().unwrap(), 1);
+        let events: Vec<_> = out.events().iter().map(|e| e["event"].as_str().unwrap().to_string()).collect();
+        assert_eq!(events, ["stats", "page_ended"]);
+    }
+}
@@ -0,0 +1,498 @@
+//! Read-only status page for `monitor`, with `--status-listen`.
+//!
+//! Serves `/`, an HTML page that refreshes itself, and `/api/status`, the
+//! same as JSON: the endpoints being monitored, whether each has a page in
+//! progress and its figures so far, how its last page went, recent errors
+//! and uptime, with the build's version and codecs at the foot. Both are
+//! built from the stats board the reporter reads, so a request copies out
+//! the receive loop's latest snapshots and never holds it up; pages are
+//! rendered off the runtime's workers, like the reports.
+//!
+//! Nothing can be changed from here, which is what the control socket is
+//! for. There is no authentication either, so a bare port binds to
+//! localhost, and another address has to be given to reach it from
+//! elsewhere.
+
+use crate::capabilities::Capabilities;
+use crate::cli::audio_analyzer::format_db;
+use crate::cli::stats_report::{EndpointStatus, StatsBoard};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Seconds between refreshes of the HTML page
+const REFRESH_SECS: u32 = 2;
+
+/// A request head longer than this is refused
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// How long a client gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Errors kept for the page, oldest dropped first
+const RECENT_ERRORS: usize = 20;
+
+static RECENT: Mutex<VecDeque<RecentError>> = Mutex::new(VecDeque::new());
+
+#[derive(Debug, Error)]
+pub enum StatusPageError {
+    #[error("Unable to serve the status page on {addr}: {source}")]
+    Bind { addr: SocketAddr, source: io::Error },
+}
+
+/// `--status-listen`: ADDRESS:PORT, or a port alone for localhost
+pub fn parse_status_listen(text: &str) -> Result<SocketAddr, String> {
+    if let Ok(port) = text.parse::<u16>() {
+        return Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port));
+    }
+    text.parse()
+        .map_err(|_| format!("{:?} isn't a port or ADDRESS:PORT", text))
+}
+
+/// An error `monitor` reported, as listed on the page
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecentError {
+    pub time: DateTime<Utc>,
+    pub message: String,
+}
+
+/// Keep `message` among the recent errors
+pub fn record_error(message: impl Into<String>) {
+    let mut recent = RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+    if recent.len() == RECENT_ERRORS {
+        recent.pop_front();
+    }
+    recent.push_back(RecentError {
+        time: Utc::now(),
+        message: message.into(),
+    });
+}
+
+/// Recent errors, newest first
+fn recent_errors() -> Vec<RecentError> {
+    RECENT.lock().unwrap_or_else(PoisonError::into_inner).iter().rev().cloned().collect()
+}
+
+/// What the running binary is, from its capabilities
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub git_hash: Option<&'static str>,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub build_features: Vec<&'static str>,
+    /// Codecs that can be both encoded and decoded
+    pub codecs: Vec<&'static str>,
+}
+
+impl BuildInfo {
+    /// Probes for ffmpeg, so call it off the runtime
+    fn collect() -> Self {
+        let capabilities = Capabilities::collect();
+        Self {
+            name: capabilities.name,
+            version: capabilities.version,
+            git_hash: capabilities.git_hash,
+            os: capabilities.platform.os,
+            arch: capabilities.platform.arch,
+            build_features: capabilities.build_features,
+            codecs: capabilities.codecs.iter().filter(|codec| codec.usable).map(|codec| codec.name).collect(),
+        }
+    }
+}
+
+/// Everything the page shows, as served at `/api/status`
+#[derive(Debug, Serialize)]
+pub struct StatusReport<'a> {
+    pub started: DateTime<Utc>,
+    pub uptime_secs: f64,
+    pub endpoints: Vec<EndpointStatus>,
+    /// Newest first
+    pub recent_errors: Vec<RecentError>,
+    pub build: &'a BuildInfo,
+}
+
+impl StatusReport<'_> {
+    /// The page, refreshing itself every few seconds
+    pub fn html(&self) -> String {
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta http-equiv=\"refresh\" content=\"{}\">\n\
+             <title>Monitor status</title>\n<style>\
+             body{{font-family:sans-serif;margin:1em 2em}}\
+             table{{border-collapse:collapse}}\
+             th,td{{border:1px solid #ccc;padding:0.2em 0.6em;text-align:left}}\
+             tr.active{{background:#dfd}}\
+             footer{{margin-top:2em;color:#666;font-size:small}}\
+             </style>\n</head>\n<body>\n<h1>Monitor status</h1>\n<p>Up {}, since {}</p>\n",
+            REFRESH_SECS,
+            format_uptime(self.uptime_secs),
+            self.started.to_rfc3339_opts(SecondsFormat::Secs, true)
+        );
+
+        html.push_str("<h2>Endpoints</h2>\n<table>\n<tr><th>Endpoint</th><th>Page</th><th>Duration</th><th>Packets</th><th>Loss</th><th>Jitter</th><th>RMS</th><th>Last page</th></tr>\n");
+        for endpoint in &self.endpoints {
+            let current = &endpoint.current;
+            let _ = write!(html, "<tr{}><td>{}:{}</td>", if current.page_active { " class=\"active\"" } else { "" }, endpoint.address, endpoint.port);
+            if current.page_active {
+                let _ = write!(
+                    html,
+                    "<td>active</td><td>{:.1}s</td><td>{}</td><td>{:.1}%</td><td>{:.1}ms</td><td>{}</td>",
+                    current.duration_secs,
+                    current.packets,
+                    current.loss_percent,
+                    current.jitter_ms,
+                    escape(&format_db(current.rms_db))
+                );
+            } else {
+                html.push_str("<td>idle</td><td></td><td></td><td></td><td></td><td></td>");
+            }
+            match &endpoint.last_page {
+                Some(page) => {
+                    let _ = write!(
+                        html,
+                        "<td>{}: {:.1}s, {} packets, {:.1}% loss, avg RMS {}",
+                        page.ended.to_rfc3339_opts(SecondsFormat::Secs, true),
+                        page.duration_secs,
+                        page.packets,
+                        page.loss_percent,
+                        escape(&format_db(page.avg_rms_db))
+                    );
+                    if let Some(mos) = page.mos_estimate {
+                        let _ = write!(html, ", MOS {:.2}", mos);
+                    }
+                    if let Some(ref error) = page.error {
+                        let _ = write!(html, "; {}", escape(error));
+                    }
+                    html.push_str("</td></tr>\n");
+                }
+                None => html.push_str("<td>none yet</td></tr>\n"),
+            }
+        }
+        html.push_str("</table>\n<h2>Recent errors</h2>\n");
+
+        if self.recent_errors.is_empty() {
+            html.push_str("<p>None</p>\n");
+        } else {
+            html.push_str("<ul>\n");
+            for error in &self.recent_errors {
+                let _ = writeln!(
+                    html,
+                    "<li>{} {}</li>",
+                    error.time.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    escape(&error.message)
+                );
+            }
+            html.push_str("</ul>\n");
+        }
+
+        let build = self.build;
+        let _ = write!(html, "<footer>{} {}", build.name, build.version);
+        if let Some(hash) = build.git_hash {
+            let _ = write!(html, " ({})", escape(hash));
+        }
+        let _ = write!(html, " on {}/{}; codecs: {}", build.os, build.arch, build.codecs.join(", "));
+        if !build.build_features.is_empty() {
+            let _ = write!(html, "; features: {}", build.build_features.join(", "));
+        }
+        html.push_str("</footer>\n</body>\n</html>\n");
+        html
+    }
+}
+
+/// Uptime as 1h 02m 03s
+fn format_uptime(secs: f64) -> String {
+    let secs = secs as u64;
+    format!("{}h {:02}m {:02}s", secs / 3600, (secs % 3600) / 60, secs % 60)
+}
+
+/// `text` safe to put in an element or attribute
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// What the page is served from
+struct Status {
+    board: StatsBoard,
+    started: DateTime<Utc>,
+    start: Instant,
+    build: BuildInfo,
+}
+
+impl Status {
+    fn report(&self) -> StatusReport<'_> {
+        StatusReport {
+            started: self.started,
+            uptime_secs: self.start.elapsed().as_secs_f64(),
+            endpoints: self.board.endpoint_status(),
+            recent_errors: recent_errors(),
+            build: &self.build,
+        }
+    }
+}
+
+/// A response, written with `Connection: close`
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn text(status: &'static str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", status),
+        }
+    }
+
+    fn bytes(&self, head_only: bool) -> Vec<u8> {
+        let mut bytes = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n",
+            self.status,
+            self.content_type,
+            self.body.len()
+        );
+        if self.status.starts_with("405") {
+            bytes.push_str("Allow: GET, HEAD\r\n");
+        }
+        bytes.push_str("\r\n");
+        if !head_only {
+            bytes.push_str(&self.body);
+        }
+        bytes.into_bytes()
+    }
+}
+
+/// Task serving the page until dropped
+pub struct StatusServer {
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl StatusServer {
+    /// Start serving `board` on `addr`
+    pub async fn bind(addr: SocketAddr, board: StatsBoard) -> Result<Self, StatusPageError> {
+        let bind_error = |source| StatusPageError::Bind { addr, source };
+        let listener = TcpListener::bind(addr).await.map_err(bind_error)?;
+        let local_addr = listener.local_addr().map_err(bind_error)?;
+        let build = tokio::task::spawn_blocking(BuildInfo::collect)
+            .await
+            .map_err(|e| bind_error(io::Error::other(e)))?;
+        let status = Arc::new(Status {
+            board,
+            started: Utc::now(),
+            start: Instant::now(),
+            build,
+        });
+
+        let task = tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    // Out of descriptors, most likely; the loop matters more
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                };
+                tokio::spawn(serve(stream, Arc::clone(&status)));
+            }
+        });
+        Ok(Self { local_addr, task })
+    }
+
+    /// Where the page is served, with the port chosen if 0 was asked for
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for StatusServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Answer one request, then close the connection
+async fn serve(mut stream: TcpStream, status: Arc<Status>) {
+    let Ok(Ok(head)) = tokio::time::timeout(REQUEST_TIMEOUT, read_head(&mut stream)).await else {
+        return;
+    };
+    let (head_only, response) = match head {
+        Some(head) => respond(&head, status).await,
+        None => (false, Response::text("431 Request Header Fields Too Large")),
+    };
+    if stream.write_all(&response.bytes(head_only)).await.is_ok() {
+        stream.shutdown().await.ok();
+    }
+}
+
+/// The request up to its blank line, or `None` if it runs too long
+async fn read_head(stream: &mut TcpStream) -> io::Result<Option<String>> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_BYTES {
+            return Ok(None);
+        }
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+    Ok(Some(String::from_utf8_lossy(&head).into_owned()))
+}
+
+/// The response to a request, and whether to leave its body out
+async fn respond(head: &str, status: Arc<Status>) -> (bool, Response) {
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line.next().unwrap_or_default().split('?').next().unwrap_or_default();
+
+    let head_only = method == "HEAD";
+    if !head_only && method != "GET" {
+        return (false, Response::text("405 Method Not Allowed"));
+    }
+    let json = match path {
+        "/" => false,
+        "/api/status" => true,
+        _ => return (head_only, Response::text("404 Not Found")),
+    };
+
+    // The board's locks are waited on, so keep them off the runtime's workers
+    let rendered = tokio::task::spawn_blocking(move || {
+        let report = status.report();
+        if json {
+            serde_json::to_string(&report).map_err(io::Error::other)
+        } else {
+            Ok(report.html())
+        }
+    })
+    .await;
+    let response = match rendered {
+        Ok(Ok(body)) => Response {
+            status: "200 OK",
+            content_type: if json { "application/json" } else { "text/html; charset=utf-8" },
+            body,
+        },
+        _ => Response::text("500 Internal Server Error"),
+    };
+    (head_only, response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::stats_report::{LastPage, SharedStats, StatsSnapshot};
+
+    async fn get(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    fn body(response: &str) -> &str {
+        response.split_once("\r\n\r\n").unwrap().1
+    }
+
+    #[test]
+    fn test_parse_status_listen() {
+        assert_eq!(parse_status_listen("8080").unwrap(), "127.0.0.1:8080".parse().unwrap());
+        assert_eq!(parse_status_listen("0.0.0.0:9000").unwrap(), "0.0.0.0:9000".parse().unwrap());
+        assert_eq!(parse_status_listen("[::1]:9000").unwrap(), "[::1]:9000".parse().unwrap());
+        assert!(parse_status_listen("localhost").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_status_served() {
+        let active = SharedStats::default();
+        active.publish(StatsSnapshot {
+            page_active: true,
+            packets: 42,
+            ..StatsSnapshot::idle()
+        });
+        let idle = SharedStats::default();
+        idle.record_page(LastPage {
+            ended: Utc::now(),
+            duration_secs: 3.0,
+            packets: 150,
+            loss_percent: 0.0,
+            jitter_ms: 0.2,
+            avg_rms_db: -12.0,
+            max_peak_db: -9.0,
+            mos_estimate: Some(4.2),
+            error: Some("multicast membership <lost>".to_string()),
+        });
+        let board = StatsBoard::default();
+        board.set_endpoints([
+            ((Ipv4Addr::new(224, 0, 1, 1), 5004), active),
+            ((Ipv4Addr::new(224, 0, 1, 2), 5004), idle),
+        ]);
+        let server = StatusServer::bind("127.0.0.1:0".parse().unwrap(), board).await.unwrap();
+        let addr = server.local_addr();
+
+        let response = get(addr, "GET /api/status HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("Content-Type: application/json"));
+        let status: serde_json::Value = serde_json::from_str(body(&response)).unwrap();
+        let endpoints = status["endpoints"].as_array().unwrap();
+        assert_eq!(endpoints.len(), 2);
+        assert_eq!(endpoints[0]["address"], "224.0.1.1");
+        assert_eq!(endpoints[0]["page_active"], true);
+        assert_eq!(endpoints[0]["packets"], 42);
+        assert!(endpoints[0]["last_page"].is_null());
+        assert_eq!(endpoints[1]["page_active"], false);
+        assert_eq!(endpoints[1]["last_page"]["packets"], 150);
+        assert!(status["uptime_secs"].as_f64().unwrap() >= 0.0);
+        assert_eq!(status["build"]["version"], env!("CARGO_PKG_VERSION"));
+
+        let page = get(addr, "GET / HTTP/1.1\r\n\r\n").await;
+        let html = body(&page);
+        assert!(html.contains("<meta http-equiv=\"refresh\""));
+        assert!(html.contains("<tr class=\"active\"><td>224.0.1.1:5004</td>"));
+        assert!(html.contains("224.0.1.2:5004"));
+        assert!(html.contains("multicast membership &lt;lost&gt;"), "messages are escaped");
+        assert!(html.contains(env!("CARGO_PKG_VERSION")));
+
+        // Read-only, and nothing else is served
+        assert!(get(addr, "POST /api/status HTTP/1.1\r\n\r\n").await.starts_with("HTTP/1.1 405"));
+        assert!(get(addr, "GET /favicon.ico HTTP/1.1\r\n\r\n").await.starts_with("HTTP/1.1 404"));
+        let head = get(addr, "HEAD /api/status HTTP/1.1\r\n\r\n").await;
+        assert!(head.starts_with("HTTP/1.1 200 OK") && body(&head).is_empty());
+    }
+
+    #[test]
+    fn test_recent_errors_bounded() {
+        for i in 0..RECENT_ERRORS + 5 {
+            record_error(format!("error {}", i));
+        }
+        let messages: Vec<_> = recent_errors().into_iter().map(|error| error.message).collect();
+        assert_eq!(messages.len(), RECENT_ERRORS);
+        assert!(messages.contains(&format!("error {}", RECENT_ERRORS + 4)));
+        assert!(!messages.contains(&"error 0".to_string()), "the oldest are dropped");
+    }
+}
@@ -0,0 +1,80 @@
+//! Stitching a page back together across an SSRC change.
+//!
+//! Some paging servers restart their RTP sender between the pre-announce
+//! chime and the speech. The announcement then arrives as two streams with
+//! different SSRCs a moment apart, and is counted as two pages with their
+//! duration and quality split between them. With `--stitch-gap-ms`, a new
+//! SSRC from the page's own source address, arriving within the gap of the
+//! page's last packet, continues the page: the recording carries on in the
+//! same file with silence for the gap, and the stats keep adding up with the
+//! new stream's sequence numbers and timestamps taken as a fresh baseline.
+//! Each change is kept with the page. A different source, or a longer
+//! gap, still starts a new page once the old one ends.
+
+use crate::network::RtpPacket;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Where a stitched page moved from one SSRC to the next
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SsrcChange {
+    pub old_ssrc: u32,
+    pub new_ssrc: u32,
+    /// Arrival of the new SSRC's first packet, from the start of the page
+    pub offset_secs: f64,
+    /// Time between the old SSRC's last packet and the new one's first
+    pub gap_ms: f64,
+}
+
+/// The gap before `packet` if it continues the page: its SSRC is new, but
+/// it comes from `page_source` no more than `stitch_gap` after the page's
+/// `last_packet`. `None` when stitching is off or the packet starts a page
+pub fn continuation(
+    stitch_gap: Option<Duration>,
+    page_source: Option<SocketAddr>,
+    last_packet: Option<Instant>,
+    packet: &RtpPacket,
+) -> Option<Duration> {
+    let gap = packet.received_at.saturating_duration_since(last_packet?);
+    (page_source? == packet.source && gap <= stitch_gap?).then_some(gap)
+}
+
+/// Frames of silence standing in for a gap: the wait beyond the audio the
+/// previous packet carried, `packet_secs`
+pub fn gap_frames(gap: Duration, packet_secs: f64, sample_rate: u32) -> u64 {
+    ((gap.as_secs_f64() - packet_secs).max(0.0) * f64::from(sample_rate)).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(source: &str, received_at: Instant) -> RtpPacket {
+        let data = RtpPacket::build(0, 1, 160, 2, &[0xFF; 160], false);
+        RtpPacket::parse_with_time(&data, source.parse().unwrap(), received_at).unwrap()
+    }
+
+    #[test]
+    fn test_continuation() {
+        let last = Instant::now();
+        let source = "10.0.0.5:40000".parse().ok();
+        let gap = Some(Duration::from_millis(250));
+        let next = packet("10.0.0.5:40000", last + Duration::from_millis(170));
+        assert_eq!(continuation(gap, source, Some(last), &next), Some(Duration::from_millis(170)));
+
+        // Off, too late, or from somewhere else
+        assert_eq!(continuation(None, source, Some(last), &next), None);
+        let late = packet("10.0.0.5:40000", last + Duration::from_millis(251));
+        assert_eq!(continuation(gap, source, Some(last), &late), None);
+        let elsewhere = packet("10.0.0.6:40000", last + Duration::from_millis(170));
+        assert_eq!(continuation(gap, source, Some(last), &elsewhere), None);
+        // ... or with no page to continue
+        assert_eq!(continuation(gap, None, None, &next), None);
+
+        // The previous packet's own 20ms isn't silence
+        assert_eq!(gap_frames(Duration::from_millis(170), 0.02, 8000), 1200);
+        assert_eq!(gap_frames(Duration::from_millis(10), 0.02, 8000), 0);
+    }
+}
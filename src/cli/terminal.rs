@@ -0,0 +1,154 @@
+//! The terminal in key-at-a-time mode.
+//!
+//! The `a` key in `monitor` and `test` and the page browser of
+//! `review --interactive` both want keys as they are pressed rather than
+//! lines. [`Modes`] switches stdin between its own settings and a mode with
+//! no line buffering and no echo. Output processing is left alone, so `\n`
+//! still starts a new line. Ctrl-C, Ctrl-\ and Ctrl-Z arrive as bytes
+//! instead of raising their signals; each user decides what they mean.
+//!
+//! [`decode_keys`] turns what one read returned into keys, arrow and paging
+//! keys included. A terminal writes an escape sequence all at once, so one
+//! read holds the whole of it.
+
+/// A key, as decoded from the bytes the terminal sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Escape,
+    Backspace,
+    /// Ctrl-C
+    Interrupt,
+    Char(char),
+}
+
+/// The keys in one read from the terminal; bytes that make no key are
+/// dropped
+pub fn decode_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let (key, used) = match bytes[i] {
+            0x1b => decode_escape(&bytes[i + 1..]),
+            b'\r' | b'\n' => (Some(Key::Enter), 1),
+            0x7f | 0x08 => (Some(Key::Backspace), 1),
+            0x03 => (Some(Key::Interrupt), 1),
+            byte if byte == b' ' || byte.is_ascii_graphic() => (Some(Key::Char(char::from(byte))), 1),
+            _ => (None, 1),
+        };
+        keys.extend(key);
+        i += used;
+    }
+    keys
+}
+
+/// The key an escape sequence makes, and the bytes it took, the escape
+/// included. An escape followed by nothing recognized is the Esc key
+fn decode_escape(rest: &[u8]) -> (Option<Key>, usize) {
+    let key = match rest {
+        [b'[' | b'O', b'A', ..] => Some((Key::Up, 2)),
+        [b'[' | b'O', b'B', ..] => Some((Key::Down, 2)),
+        [b'[' | b'O', b'H', ..] => Some((Key::Home, 2)),
+        [b'[' | b'O', b'F', ..] => Some((Key::End, 2)),
+        [b'[', b'1' | b'7', b'~', ..] => Some((Key::Home, 3)),
+        [b'[', b'4' | b'8', b'~', ..] => Some((Key::End, 3)),
+        [b'[', b'5', b'~', ..] => Some((Key::PageUp, 3)),
+        [b'[', b'6', b'~', ..] => Some((Key::PageDown, 3)),
+        // Left, right and the rest mean nothing here; skip the sequence
+        [b'[', tail @ ..] => {
+            let end = tail.iter().position(|b| (0x40..=0x7e).contains(b)).map_or(tail.len(), |p| p + 1);
+            return (None, 2 + end);
+        }
+        _ => None,
+    };
+    match key {
+        Some((key, len)) => (Some(key), 1 + len),
+        None => (Some(Key::Escape), 1),
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{rows, Modes};
+
+#[cfg(unix)]
+mod unix {
+    use std::io::{self, IsTerminal};
+
+    /// Terminal settings to switch between
+    #[derive(Clone, Copy)]
+    pub struct Modes {
+        original: libc::termios,
+        keys: libc::termios,
+    }
+
+    impl Modes {
+        /// Stdin's settings and the key mode made from them, without
+        /// switching to it; `None` if stdin isn't a terminal
+        pub fn capture() -> Option<Self> {
+            if !io::stdin().is_terminal() {
+                return None;
+            }
+            // SAFETY: a zeroed termios is valid to pass to tcgetattr, which fills it in
+            let mut original: libc::termios = unsafe { std::mem::zeroed() };
+            // SAFETY: stdin is a terminal
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &raw mut original) } != 0 {
+                return None;
+            }
+            let mut keys = original;
+            keys.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            keys.c_cc[libc::VMIN] = 1;
+            keys.c_cc[libc::VTIME] = 0;
+            Some(Self { original, keys })
+        }
+
+        /// Keys one at a time, unechoed
+        pub fn keys(&self) {
+            apply(&self.keys);
+        }
+
+        /// Back to the settings the terminal had
+        pub fn restore(&self) {
+            apply(&self.original);
+        }
+    }
+
+    fn apply(termios: &libc::termios) {
+        // SAFETY: stdin is a terminal and `termios` came from tcgetattr on it
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios);
+        }
+    }
+
+    /// Lines in the terminal on stdout, if it is one
+    pub fn rows() -> Option<usize> {
+        // SAFETY: a zeroed winsize is valid for TIOCGWINSZ to fill in
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        // SAFETY: TIOCGWINSZ writes a winsize, which `size` is
+        let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &raw mut size) };
+        (result == 0 && size.ws_row > 0).then_some(usize::from(size.ws_row))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_keys() {
+        assert_eq!(decode_keys(b"\x1b[A\x1b[B\x1bOA"), vec![Key::Up, Key::Down, Key::Up]);
+        assert_eq!(decode_keys(b"\x1b[5~\x1b[6~\x1b[H\x1b[4~"), vec![Key::PageUp, Key::PageDown, Key::Home, Key::End]);
+        assert_eq!(decode_keys(b"/loss>1\r"), "/loss>1".chars().map(Key::Char).chain([Key::Enter]).collect::<Vec<_>>());
+        assert_eq!(decode_keys(b"a\x7f\x03"), vec![Key::Char('a'), Key::Backspace, Key::Interrupt]);
+        // A lone escape is the Esc key; left and right are skipped whole
+        assert_eq!(decode_keys(b"\x1b"), vec![Key::Escape]);
+        assert_eq!(decode_keys(b"\x1b[D\x1b[1;5Cq"), vec![Key::Char('q')]);
+        assert_eq!(decode_keys(b"\x1bq"), vec![Key::Escape, Key::Char('q')]);
+        assert_eq!(decode_keys(b"\x01\xc3\xa9"), Vec::<Key>::new());
+    }
+}
@@ -3,22 +3,65 @@
 //! This module provides a test command that monitors multicast addresses,
 //! records pages, and outputs structured metrics for automated analysis.
 
-use crate::codec::{create_decoder_for_payload_type, AudioDecoder, CodecType};
-use crate::network::{MulticastSocket, RtpPacket, PayloadType};
+use crate::codec::{
+    create_decoder_for_payload_type, create_decoder_for_spec, AudioDecoder, CodecError, CodecSpec, CodecType,
+    UnavailableDecoder,
+};
+use crate::network::repair::{RepairBuffer, RepairClient, RepairSummary};
+use crate::network::{Dscp, MulticastInterface, MulticastSocket, PcapWriter, RtpPacket, PayloadType};
+use crate::cli::annotation::{Annotation, AnnotationLog};
+use crate::cli::timeout::{EndReason, Timeout, TimeoutMode};
+use crate::cli::analysis_pool::{self, AnalysisPool, AnalysisWorker};
 use crate::cli::audio_analyzer::{AudioAnalyzer, AudioStats, AudioAnalysis};
+use crate::cli::anonymize::Anonymization;
+use crate::cli::classifier::{classify_stats, ClassifierThresholds, ContentClass};
+use crate::cli::transcoding::{BandSplit, TranscodingThresholds};
+use crate::cli::jitter_buffer::{JitterBufferResult, PacketTimeline};
+use crate::cli::logging::limited;
+use crate::cli::decode_policy::DecodeErrorPolicy;
+use crate::cli::drain::{FairReceiver, DRAIN_BUDGET};
+use crate::cli::fd_limit::{self, FdLimitError, FdPlan};
+use crate::cli::fingerprint::SenderFingerprint;
+use crate::cli::igmp_watch::{IgmpObservation, IgmpWatch};
+use crate::cli::settings::{ConfigFileRecord, EffectiveSetting};
+use crate::cli::monitor::{
+    arrival_time, skip_analysis, skipped_audio, truncation_note, GapHistogram, PageStats, PayloadSizes, StreamDiscontinuity, TalkSpurt,
+    DscpSummary, TtlSummary,
+};
+use crate::cli::page_threshold::{PageThreshold, PendingPage};
+use crate::cli::path_compare::{self, EndpointPair, PathComparison};
+use crate::cli::quality::{self, MeanMos, MosBreakdown, QualityInputs};
+use crate::cli::audio_pipe::{AudioPipe, PipeCommand, PipeOutput};
+use crate::cli::finalize::{FinalizeQueue, FinishedRecording, PendingRecording, RecordingKind, FINALIZE_CONCURRENCY};
+use crate::cli::integrity::RecordingIntegrity;
+use crate::cli::control::{resolve_endpoints, ResolvedEndpoints, ControlCommand, ControlError, ControlHandle, ControlRequest, EndpointStatus};
+use crate::cli::payloads::{payloads_path, PayloadWriter};
 use crate::cli::recorder::WavRecorder;
-use crate::utils::range_parser::parse_range;
-use chrono::{DateTime, Utc};
+use crate::cli::spectrum::{page_spectrum_path, SpectrumWriter};
+use crate::cli::ssrc_filter::SsrcFilter;
+use crate::cli::stitch::{self, SsrcChange};
+use crate::cli::timing::{timing_path, TimingGranularity, TimingWriter};
+use crate::cli::trends::{TrendError, TrendWriter};
+use crate::cli::watermark::WatermarkDetection;
+use crate::cli::schema::SCHEMA_VERSION;
+use crate::utils::clock::{ClockStep, ClockWatch, SystemClock};
+use crate::utils::filename::{self, unique_name, FilenameError, NameFields, Naming, Token};
+use crate::utils::range_parser::{EndpointOptions, MulticastEndpoint};
+use chrono::{DateTime, Local, Utc};
+use schemars::JsonSchema;
 use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::Infallible;
 use std::fs::{self, File};
 use std::io::{self, BufWriter, Write};
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, SocketAddrV4};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use thiserror::Error;
+use tracing::info;
 
 #[derive(Error, Debug)]
 pub enum TestError {
@@ -32,27 +75,113 @@ pub enum TestError {
     Io(#[from] io::Error),
 
     #[error("Codec error: {0}")]
-    Codec(#[from] crate::codec::CodecError),
+    Codec(#[from] CodecError),
 
     #[error("Recorder error: {0}")]
     Recorder(#[from] super::recorder::RecorderError),
 
+    #[error("Control error: {0}")]
+    Control(#[from] ControlError),
+
+    #[error("Trend aggregate error: {0}")]
+    Trends(#[from] TrendError),
+
     #[error("No endpoints to monitor")]
     NoEndpoints,
 
+    #[error("None of the {0} endpoint(s) could be joined; see summary.json for the reasons")]
+    NoUsableEndpoints(usize),
+
     #[error("Timeout must be greater than 0")]
     InvalidTimeout,
+
+    #[error("Naming error: {0}")]
+    Naming(#[from] FilenameError),
+
+    #[error("--compare-endpoints {0} is not among the endpoints monitored")]
+    UncomparableEndpoint(SocketAddrV4),
+
+    #[error("{0}")]
+    FdLimit(#[from] FdLimitError),
 }
 
+/// Tokens a test recording name can use
+const RECORDING_TOKENS: &[Token] = &[
+    Token::Site,
+    Token::Endpoint,
+    Token::Address,
+    Token::Port,
+    Token::Page,
+    Token::Start,
+    Token::Ssrc,
+];
+
 /// Options for the test command
 pub struct TestOptions {
     pub pattern: String,
     pub default_port: u16,
-    pub interface: Option<Ipv4Addr>,
-    pub codec: Option<CodecType>,
+    pub interface: Option<MulticastInterface>,
+    pub codec: Option<CodecSpec>,
     pub output_dir: PathBuf,
-    pub timeout: Duration,
+    /// `--timeout` or `--until`; a test always ends by itself
+    pub timeout: Timeout,
     pub metrics_interval: Duration,
+    /// Unix socket accepting runtime add/remove/status commands
+    pub control_socket: Option<PathBuf>,
+    /// Directory for per-page spectrum snapshots, sampled at the metrics interval
+    pub spectrum_dir: Option<PathBuf>,
+    /// Capture of every received datagram
+    pub pcap: Option<PathBuf>,
+    /// What a new stream must reach before it counts as a page
+    pub page_threshold: PageThreshold,
+    /// Longest wait for a new SSRC from the page's sender to carry the page
+    /// on, with `--stitch-gap-ms`
+    pub stitch_gap: Option<Duration>,
+    /// SSRCs to take and to drop, with `--ssrc` and `--ignore-ssrc`
+    pub ssrc_filter: SsrcFilter,
+    /// Time-of-day aggregate file updated as each page ends
+    pub aggregate: Option<PathBuf>,
+    /// `SO_BUSY_POLL` for receive sockets, in microseconds
+    pub busy_poll_us: Option<u32>,
+    /// Threads running analysis FFTs, with `--analysis-threads`; `None`
+    /// picks from the cores and endpoints
+    pub analysis_threads: Option<usize>,
+    /// Page recording name template and site
+    pub naming: Naming,
+    /// Log accumulator sizes at this interval, to catch memory growth
+    pub memory_report: Option<Duration>,
+    /// Also record each talk-spurt to its own WAV segment
+    pub split_spurts: bool,
+    /// What damaged audio becomes in the recording
+    pub decode_error_policy: DecodeErrorPolicy,
+    /// With `--repair`, how long a gap may stay open before it is asked for
+    pub repair_window: Option<Duration>,
+    /// Redundant path pairs compared once the test ends
+    pub compare_endpoints: Vec<EndpointPair>,
+    /// DSCP pages should arrive with
+    pub expect_dscp: Option<Dscp>,
+    /// Write a timing file beside each page recording, at this granularity
+    pub timing_file: Option<TimingGranularity>,
+    /// Keep each page's payloads as received beside its recording
+    pub record_payloads: bool,
+    /// Command each page's audio is piped to, with `--pipe-audio-cmd`
+    pub pipe_audio_cmd: Option<PipeCommand>,
+    /// Content classification thresholds, from the config file
+    pub classifier: ClassifierThresholds,
+    /// Narrowband transcoding thresholds, from the config file
+    pub transcoding: TranscodingThresholds,
+    /// The config file's site name, for the summary
+    pub site_name: Option<String>,
+    /// The config file in effect, if one was read
+    pub config_file: Option<ConfigFileRecord>,
+    /// The flags that can come from the environment, as resolved
+    pub settings: Vec<EffectiveSetting>,
+    /// Soft open file limit to run with, in place of raising it as needed
+    pub max_file_descriptors: Option<u64>,
+    /// Read G.722 timestamps as ticking at 16kHz, for senders that don't follow RFC 3551
+    pub g722_nonstandard_timestamps: bool,
+    /// Watch IGMP for the summary, with `--observe-igmp`
+    pub observe_igmp: bool,
 }
 
 /// Network metrics for a snapshot
@@ -62,18 +191,38 @@ pub struct NetworkMetrics {
     pub bytes: u64,
     pub loss_percent: f64,
     pub jitter_ms: f64,
+    /// Longest time between two consecutive packets so far in the page
+    #[serde(default)]
+    pub max_gap_ms: f64,
+    /// Arriving TTL so far, if the platform reports it
+    #[serde(default)]
+    pub ttl: Option<TtlSummary>,
+    /// Packets the decoder rejected so far in the page
+    #[serde(default)]
+    pub decode_errors: u64,
+    /// Payloads cut short in transit so far in the page
+    #[serde(default)]
+    pub truncated_payloads: u64,
 }
 
 /// Audio metrics for a snapshot
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AudioMetrics {
+    /// Silence is -inf, which JSON writes as null
+    #[serde(deserialize_with = "null_as_silence")]
     pub rms_db: f64,
+    #[serde(deserialize_with = "null_as_silence")]
     pub peak_db: f64,
     pub dominant_freq_hz: f64,
     pub glitches: u64,
     pub clipped: u64,
 }
 
+/// Read back a level that was written as null because it was -inf
+fn null_as_silence<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::NEG_INFINITY))
+}
+
 /// A single metrics snapshot written to JSONL
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MetricSnapshot {
@@ -82,6 +231,9 @@ pub struct MetricSnapshot {
     pub page_active: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub page_number: Option<u32>,
+    /// Run-wide id of the page in progress, as in summary.json
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_id: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub duration_secs: Option<f64>,
     pub network: NetworkMetrics,
@@ -89,122 +241,408 @@ pub struct MetricSnapshot {
 }
 
 /// Network summary for a page
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct NetworkSummary {
     pub packets_received: u64,
     pub bytes_received: u64,
     pub packets_lost: u64,
     pub loss_percent: f64,
     pub jitter_ms: f64,
+    /// Sequence number restarts within the page (e.g. sender reboot)
+    #[serde(default)]
+    pub resets: u64,
+    /// Additional source addresses seen using the page's SSRC
+    #[serde(default)]
+    pub ssrc_collisions: u64,
+    /// Longest time between two consecutive packets
+    #[serde(default)]
+    pub max_gap_ms: f64,
+    #[serde(default)]
+    pub gap_histogram: GapHistogram,
+    /// Share of the page not spent waiting for late or missing packets
+    /// (absent in summaries written before it was measured)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continuity_percent: Option<f64>,
+    /// Arriving IP TTL; null where the platform doesn't report it
+    #[serde(default)]
+    pub ttl: Option<TtlSummary>,
+    /// Arriving DSCP, against `--expect-dscp`; absent where the platform
+    /// doesn't report it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dscp: Option<DscpSummary>,
+    /// Time the sender sent nothing (Opus DTX), excluded from loss and continuity
+    #[serde(default)]
+    pub dtx_gap_secs: f64,
+    /// How far arrivals fell behind the sender's RTP clock over the page,
+    /// from its first packet to its last; absent for a page too short to tell
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock_drift_ms: Option<f64>,
+    #[serde(default)]
+    pub payload_sizes: PayloadSizes,
+    /// Packets the decoder rejected
+    #[serde(default)]
+    pub decode_errors: u64,
+    /// Packets that decoded to fewer samples than their payload size implies
+    #[serde(default)]
+    pub short_decodes: u64,
+    /// Payloads carrying less audio than the usual spacing to the next packet
+    #[serde(default)]
+    pub truncated_payloads: u64,
+    /// Packets with RTP padding after the payload
+    #[serde(default)]
+    pub padded_packets: u64,
+    /// Mean padding of those packets, in bytes; absent if none were padded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_padding_bytes: Option<f64>,
+    /// Losses asked for again and how many came back, with `--repair`.
+    /// Repaired packets are recorded in place and not in `packets_lost`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repair: Option<RepairSummary>,
 }
 
 /// Audio summary for a page
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AudioSummary {
+    /// Silence is -inf, which JSON writes as null
+    #[serde(deserialize_with = "null_as_silence")]
+    #[schemars(with = "Option<f64>")]
     pub peak_rms_db: f64,
     /// Average RMS level - None if no valid (non-silence) samples
     #[serde(skip_serializing_if = "Option::is_none")]
     pub avg_rms_db: Option<f64>,
+    #[serde(deserialize_with = "null_as_silence")]
+    #[schemars(with = "Option<f64>")]
     pub max_peak_db: f64,
     pub dominant_freq_hz: f64,
     pub total_glitches: u64,
     pub total_clipped: u64,
     pub clipping_percent: f64,
     pub avg_zero_crossing_rate: f64,
+    /// Gated loudness over the page (ITU-R BS.1770); absent for pages too
+    /// short or quiet to measure
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrated_lufs: Option<f64>,
+    /// Loudest 400ms of the page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_momentary_lufs: Option<f64>,
+    /// Loudness range in LU (EBU Tech 3342); absent for pages under 3s
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lra: Option<f64>,
+    /// What the page sounded like; absent from summaries written before
+    /// pages were classified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_class: Option<ContentClass>,
+    /// How sure the classifier was, 0-1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_confidence: Option<f64>,
+    /// The `transmit --watermark` ID found in the page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<WatermarkDetection>,
+    /// The built-in `transmit --chime` the page opened with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chime: Option<String>,
+    /// The page's wideband audio split at 4 kHz; absent below 16 kHz and
+    /// for pages too short to measure
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub band_split: Option<BandSplit>,
+    /// A wideband codec carrying audio that looks to have been through an
+    /// 8 kHz μ-law hop; see `band_split` for why
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub suspected_narrowband_transcoding: bool,
+}
+
+impl AudioSummary {
+    /// The figures for a page, classified by `thresholds` and checked for
+    /// a narrowband hop by `transcoding`
+    pub fn new(stats: &AudioStats, thresholds: &ClassifierThresholds, transcoding: &TranscodingThresholds) -> Self {
+        let classification = classify_stats(stats, thresholds);
+        let band_split = stats.band_split();
+        Self {
+            peak_rms_db: stats.peak_rms_db,
+            avg_rms_db: stats.avg_rms_db.is_finite().then_some(stats.avg_rms_db),
+            max_peak_db: stats.max_peak_db,
+            dominant_freq_hz: stats.dominant_freq_hz,
+            total_glitches: stats.total_glitches,
+            total_clipped: stats.total_clipped,
+            clipping_percent: stats.clipping_percent(),
+            avg_zero_crossing_rate: stats.avg_zero_crossing_rate,
+            integrated_lufs: stats.loudness().integrated_lufs(),
+            max_momentary_lufs: stats.loudness().max_momentary_lufs(),
+            lra: stats.loudness().loudness_range_lu(),
+            content_class: classification.map(|c| c.class),
+            content_confidence: classification.map(|c| c.confidence),
+            watermark: stats.watermark(),
+            chime: stats.chime().map(|chime| chime.name().to_string()),
+            band_split,
+            suspected_narrowband_transcoding: band_split.is_some_and(|split| split.suspect_narrowband(transcoding)),
+        }
+    }
 }
 
 /// Summary of a single page
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PageSummary {
+    /// Number of the page on its endpoint, from 1
     pub page_number: u32,
+    /// Unique across the run's endpoints, from 1 in the order pages
+    /// started; absent in summaries written before there were ids
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_id: Option<u32>,
     pub endpoint: String,
     pub start_time: DateTime<Utc>,
     pub end_time: DateTime<Utc>,
     pub duration_secs: f64,
+    /// Relative to the output directory: in the endpoint's subdirectory,
+    /// or directly in it for results written before there were any
     pub recording_file: String,
+    /// Every file the page was recorded to by kind, `recording_file`
+    /// included, relative to the output directory
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub recording_files: BTreeMap<RecordingKind, String>,
+    /// Hashes of the finished recording, checked by `review --verify`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<RecordingIntegrity>,
+    /// What `--pipe-audio-cmd` printed for the page, such as a transcript,
+    /// and how it exited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipe_output: Option<PipeOutput>,
+    /// Raw fields of the first packet, with a guess at the device
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender_fingerprint: Option<SenderFingerprint>,
     pub network: NetworkSummary,
     pub audio: AudioSummary,
+    /// Estimated listening quality, 1-5 (see `cli::quality`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mos_estimate: Option<f64>,
+    /// R-factor and the penalty behind each part of `mos_estimate`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mos_breakdown: Option<MosBreakdown>,
+    /// Simulated receiver playout buffers at several depths
+    #[serde(default)]
+    pub jitter_buffer_analysis: Vec<JitterBufferResult>,
+    /// Packets with the RTP marker bit set
+    #[serde(default)]
+    pub marked_packets: u64,
+    /// Talk-spurts the sender marked, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spurts: Vec<TalkSpurt>,
+    /// Per-spurt segments of the recording (`--split-spurts`), relative to the output directory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spurt_files: Vec<String>,
+    /// Where the page moved to a new SSRC (`--stitch-gap-ms`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ssrc_changes: Vec<SsrcChange>,
+    /// Why the page was cut short (`--on-decode-error abort-page`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The test ended (timeout or Ctrl+C) while the page was still active,
+    /// so it stops where the run did rather than at an idle timeout
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// The wall clock was stepped during the page; its times follow the
+    /// clock as it read before the step
+    #[serde(default)]
+    pub clock_adjusted: bool,
+}
+
+impl PageSummary {
+    /// What `review --page` knows the page by: its id, or in results
+    /// without ids, its number on its endpoint
+    pub fn id(&self) -> u32 {
+        self.page_id.unwrap_or(self.page_number)
+    }
+
+    /// Whether this is the page `--page number` picks: page `number` of
+    /// `endpoint` if one is given, otherwise the page with that id
+    pub fn is_page(&self, number: u32, endpoint: Option<&str>) -> bool {
+        match endpoint {
+            Some(endpoint) => self.endpoint == endpoint && self.page_number == number,
+            None => self.id() == number,
+        }
+    }
 }
 
 /// Totals for a single endpoint
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct EndpointTotal {
     pub pages_detected: u32,
     pub total_duration_secs: f64,
     pub total_packets: u64,
     pub total_bytes: u64,
+    /// Stray bursts that never reached the page threshold
+    #[serde(default)]
+    pub suppressed_bursts: u64,
+    /// Packets turned away by `--ssrc` or `--ignore-ssrc`
+    #[serde(default)]
+    pub filtered_packets: u64,
+    /// Mean of the pages' `mos_estimate`, weighted by duration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mos_estimate: Option<f64>,
 }
 
 /// Test metadata
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct TestMetadata {
+    /// See `cli::schema`; 0 in summaries written before it was added
+    #[serde(default)]
+    pub schema_version: u32,
     pub start_time: DateTime<Utc>,
     pub end_time: DateTime<Utc>,
     pub duration_secs: f64,
     pub pattern: String,
     pub endpoints_monitored: usize,
     pub metrics_interval_ms: u64,
+    /// With `--until`, the seconds from the start to the end time
     pub timeout_secs: u64,
+    #[serde(default)]
+    pub timeout_mode: TimeoutMode,
+    /// The `--until` time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<DateTime<Utc>>,
+    /// Absent in summaries written before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_reason: Option<EndReason>,
+    /// Flags that could have come from the environment, with their values
+    /// and where each came from, so the run can be repeated
+    #[serde(default)]
+    pub settings: Vec<EffectiveSetting>,
+    /// The config file the defaults came from, absent if there was none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_file: Option<ConfigFileRecord>,
+    /// `--site`, or the config file's site code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site: Option<String>,
+    /// The config file's site name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site_name: Option<String>,
+    /// Endpoints given their own settings in the pattern or endpoints file
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub endpoint_overrides: BTreeMap<String, EndpointOptions>,
+    /// SSRCs taken and dropped, with `--ssrc` and `--ignore-ssrc`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssrc_filter: Option<SsrcFilter>,
+    /// Seconds the wall clock was stepped by during the run, summed; absent
+    /// if it wasn't. `duration_secs` is measured on the wall clock, so
+    /// includes them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock_step_secs: Option<f64>,
+    /// Seconds from the end of receiving to the summary being written:
+    /// ending the pages still active, waiting for recordings still being
+    /// finished, and checking them. Absent in summaries written before it
+    /// was measured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finalization_secs: Option<f64>,
+    /// IGMP seen over the run, with `--observe-igmp`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub igmp: Option<IgmpObservation>,
+    /// Set in a copy made by `review --anonymize`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anonymization: Option<Anonymization>,
+}
+
+/// An endpoint that could not be monitored
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UnavailableEndpoint {
+    pub endpoint: String,
+    pub reason: String,
 }
 
 /// Complete test summary
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct TestSummary {
     pub test_metadata: TestMetadata,
     pub pages: Vec<PageSummary>,
     pub endpoint_totals: HashMap<String, EndpointTotal>,
+    #[serde(default)]
+    pub unavailable_endpoints: Vec<UnavailableEndpoint>,
+    /// Pages compared across `--compare-endpoints` pairs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub path_comparisons: Vec<PathComparison>,
     pub errors: Vec<String>,
 }
 
-/// Statistics for a monitored page (reused from monitor)
+impl TestSummary {
+    /// The page `--page number` picks (see [`PageSummary::is_page`])
+    pub fn find_page(&self, number: u32, endpoint: Option<&str>) -> Option<&PageSummary> {
+        self.pages.iter().find(|page| page.is_page(number, endpoint))
+    }
+}
+
+/// Subdirectory of the output directory holding an endpoint's recordings
+/// and its pages.jsonl: `224.0.1.1_5004`, as `{endpoint}` renders it
+pub fn endpoint_dir(endpoint: &str) -> String {
+    filename::sanitize(endpoint)
+}
+
+/// Hands out page ids, unique and increasing across a run's endpoints
 #[derive(Debug, Clone, Default)]
-struct PageStats {
-    packets_received: u64,
-    bytes_received: u64,
-    packets_lost: u64,
-    jitter_ms: f64,
-    last_sequence: Option<u16>,
-    last_timestamp: Option<u32>,
-    last_arrival: Option<Instant>,
-    jitter_accumulator: f64,
-}
-
-impl PageStats {
-    fn update(&mut self, packet: &RtpPacket) {
-        self.packets_received += 1;
-        self.bytes_received += packet.payload.len() as u64;
-
-        // Calculate packet loss
-        if let Some(last_seq) = self.last_sequence {
-            let expected = last_seq.wrapping_add(1);
-            if packet.header.sequence_number != expected {
-                let gap = packet.header.sequence_number.wrapping_sub(last_seq);
-                if gap > 1 && gap < 1000 {
-                    self.packets_lost += (gap - 1) as u64;
-                }
+struct PageIds(Arc<AtomicU32>);
+
+impl PageIds {
+    fn next(&self) -> u32 {
+        self.0.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+/// Silence that ends a page, unless an endpoint is given its own `idle=`
+const PAGE_IDLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Most distinct error messages kept for the summary; later ones are only counted
+const MAX_DISTINCT_ERRORS: usize = 100;
+
+/// Longest error message kept; the rest of a longer one is cut off
+const MAX_ERROR_LEN: usize = 512;
+
+/// Errors collected during a run. Repeats of the same message (e.g. a socket
+/// failing on every receive) are folded into one entry with a count.
+#[derive(Debug, Default)]
+struct ErrorLog {
+    entries: Vec<(String, u64)>,
+    index: HashMap<String, usize>,
+    suppressed: u64,
+}
+
+impl ErrorLog {
+    fn push(&mut self, mut message: String) {
+        if message.len() > MAX_ERROR_LEN {
+            let mut end = MAX_ERROR_LEN;
+            while !message.is_char_boundary(end) {
+                end -= 1;
             }
+            message.truncate(end);
+            message.push('…');
         }
-
-        // Calculate jitter (RFC 3550 algorithm)
-        if let (Some(last_ts), Some(last_arrival)) = (self.last_timestamp, self.last_arrival) {
-            let arrival_diff = packet.received_at.duration_since(last_arrival).as_secs_f64() * 8000.0;
-            let ts_diff = packet.header.timestamp.wrapping_sub(last_ts) as f64;
-            let d = (arrival_diff - ts_diff).abs();
-            self.jitter_accumulator += (d - self.jitter_accumulator) / 16.0;
-            self.jitter_ms = self.jitter_accumulator / 8.0;
+        if let Some(&i) = self.index.get(&message) {
+            self.entries[i].1 += 1;
+        } else if self.entries.len() < MAX_DISTINCT_ERRORS {
+            self.index.insert(message.clone(), self.entries.len());
+            self.entries.push((message, 1));
+        } else {
+            self.suppressed += 1;
         }
+    }
 
-        self.last_sequence = Some(packet.header.sequence_number);
-        self.last_timestamp = Some(packet.header.timestamp);
-        self.last_arrival = Some(packet.received_at);
+    /// Distinct messages held
+    fn len(&self) -> usize {
+        self.entries.len()
     }
 
-    fn loss_percent(&self) -> f64 {
-        if self.packets_received + self.packets_lost == 0 {
-            0.0
-        } else {
-            100.0 * self.packets_lost as f64 / (self.packets_received + self.packets_lost) as f64
+    fn into_messages(self) -> Vec<String> {
+        let mut messages: Vec<String> = self
+            .entries
+            .into_iter()
+            .map(|(message, count)| {
+                if count > 1 {
+                    format!("{} (repeated {} times)", message, count)
+                } else {
+                    message
+                }
+            })
+            .collect();
+        if self.suppressed > 0 {
+            messages.push(format!("{} further error(s) suppressed", self.suppressed));
         }
+        messages
     }
 }
 
@@ -218,6 +656,19 @@ struct TestEndpointState {
     current_audio: AudioAnalysis,
     decoder: Option<Box<dyn AudioDecoder>>,
     recorder: Option<WavRecorder>,
+    /// Segment for the current talk-spurt (`--split-spurts`)
+    spurt_recorder: Option<WavRecorder>,
+    /// Segments of the current page, relative to the output directory
+    spurt_files: Vec<String>,
+    spectrum: Option<SpectrumWriter>,
+    last_spectrum: Option<Instant>,
+    /// Sample offsets and receive times of the recording, with `--timing`
+    timing: Option<TimingWriter>,
+    /// The page's payloads as received, with `--record-payloads`
+    payloads: Option<PayloadWriter>,
+    /// The page's `--pipe-audio-cmd`, taking its audio as the recording does
+    audio_pipe: Option<AudioPipe>,
+    packet_timeline: Option<PacketTimeline>,
     page_active: bool,
     page_start: Option<Instant>,
     page_start_utc: Option<DateTime<Utc>>,
@@ -225,7 +676,47 @@ struct TestEndpointState {
     ssrc: Option<u32>,
     // Test-specific
     page_count: u32,
-    completed_pages: Vec<PageSummary>,
+    /// Run-wide id of the current page
+    page_id: u32,
+    /// Where the page ids come from, shared with the other endpoints
+    page_ids: PageIds,
+    /// Current page's recording, relative to the output directory
+    recording_file: Option<String>,
+    /// Pages ended since they were last written to the page log
+    ended_pages: Vec<PageSummary>,
+    /// Running figures for the summary's endpoint totals
+    totals: PageTotals,
+    /// Start of a new stream, held until it reaches the page threshold
+    pending: PendingPage,
+    /// Removal requested while a page was active; stop listening once it ends
+    pending_removal: bool,
+    /// Why the current page is being ended early
+    interrupted: Option<String>,
+    /// The run is ending with the page still active
+    truncated: bool,
+    /// Packets turned away by `--ssrc` or `--ignore-ssrc`
+    filtered_packets: u64,
+    /// Settings given for this endpoint in place of the command-wide ones
+    overrides: EndpointOptions,
+    /// First packet of the current page
+    fingerprint: Option<SenderFingerprint>,
+    /// Packets held back for loss repair, with `--repair`
+    repair: Option<RepairBuffer>,
+    /// DSCP pages should arrive with, from `--expect-dscp`
+    expect_dscp: Option<Dscp>,
+    /// Thresholds pages are classified by
+    classifier: ClassifierThresholds,
+    /// Thresholds wideband pages are checked for a narrowband hop by
+    transcoding: TranscodingThresholds,
+    /// The wall clock was stepped during the current page
+    clock_stepped: bool,
+    /// Recordings of ended pages, for the finalize queue
+    finalizing: Vec<PendingRecording>,
+    /// Where the current page moved to a new SSRC, with `--stitch-gap-ms`
+    ssrc_changes: Vec<SsrcChange>,
+    /// Analysis pool thread that runs this endpoint's FFTs, with
+    /// `--analysis-threads`
+    analysis_worker: Option<AnalysisWorker>,
 }
 
 impl TestEndpointState {
@@ -239,20 +730,107 @@ impl TestEndpointState {
             current_audio: AudioAnalysis::default(),
             decoder: None,
             recorder: None,
+            spurt_recorder: None,
+            spurt_files: Vec::new(),
+            spectrum: None,
+            last_spectrum: None,
+            timing: None,
+            payloads: None,
+            audio_pipe: None,
+            packet_timeline: None,
             page_active: false,
             page_start: None,
             page_start_utc: None,
             last_packet: None,
             ssrc: None,
             page_count: 0,
-            completed_pages: Vec::new(),
+            page_id: 0,
+            page_ids: PageIds::default(),
+            recording_file: None,
+            ended_pages: Vec::new(),
+            totals: PageTotals::default(),
+            pending: PendingPage::default(),
+            pending_removal: false,
+            interrupted: None,
+            truncated: false,
+            filtered_packets: 0,
+            overrides: EndpointOptions::default(),
+            fingerprint: None,
+            repair: None,
+            expect_dscp: None,
+            classifier: ClassifierThresholds::default(),
+            transcoding: TranscodingThresholds::default(),
+            clock_stepped: false,
+            finalizing: Vec::new(),
+            ssrc_changes: Vec::new(),
+            analysis_worker: None,
         }
     }
 
+    fn with_overrides(mut self, overrides: EndpointOptions) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    fn with_repair(mut self, window: Option<Duration>) -> Self {
+        self.repair = window.map(RepairBuffer::new);
+        self
+    }
+
+    fn with_expect_dscp(mut self, expect_dscp: Option<Dscp>) -> Self {
+        self.expect_dscp = expect_dscp;
+        self
+    }
+
+    fn with_classifier(mut self, classifier: ClassifierThresholds) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
+    fn with_transcoding(mut self, transcoding: TranscodingThresholds) -> Self {
+        self.transcoding = transcoding;
+        self
+    }
+
+    fn with_page_ids(mut self, page_ids: PageIds) -> Self {
+        self.page_ids = page_ids;
+        self
+    }
+
+    fn with_analysis(mut self, worker: Option<AnalysisWorker>) -> Self {
+        self.analysis_worker = worker;
+        self
+    }
+
+    fn page_threshold(&self, options: &TestOptions) -> PageThreshold {
+        options.page_threshold.with_overrides(&self.overrides)
+    }
+
+    /// The gap before `packet` if it carries the active page on under a
+    /// new SSRC, with `--stitch-gap-ms`
+    fn continuation(&self, packet: &RtpPacket, options: &TestOptions) -> Option<Duration> {
+        let page_source = self.stats.source().filter(|_| self.page_active);
+        stitch::continuation(options.stitch_gap, page_source, self.last_packet, packet)
+    }
+
+    /// Whether the active page has gone quiet for long enough to end by `now`
+    fn is_idle(&self, now: Instant) -> bool {
+        let idle_timeout = self.overrides.idle().unwrap_or(PAGE_IDLE_TIMEOUT);
+        self.page_active
+            && self
+                .last_packet
+                .is_some_and(|last| now.saturating_duration_since(last) >= idle_timeout)
+    }
+
     fn endpoint_string(&self) -> String {
         format!("{}:{}", self.address, self.port)
     }
 
+    /// The endpoint's subdirectory of the output directory
+    fn output_subdir(&self) -> String {
+        endpoint_dir(&self.endpoint_string())
+    }
+
     fn reset_page(&mut self) {
         self.page_active = false;
         self.stats = PageStats::default();
@@ -263,9 +841,23 @@ impl TestEndpointState {
         }
         self.decoder = None;
         self.recorder = None;
+        self.spurt_recorder = None;
+        self.spurt_files.clear();
+        self.spectrum = None;
+        self.last_spectrum = None;
+        self.timing = None;
+        self.payloads = None;
+        self.audio_pipe = None;
+        self.packet_timeline = None;
         self.page_start = None;
         self.page_start_utc = None;
         self.ssrc = None;
+        self.recording_file = None;
+        self.interrupted = None;
+        self.truncated = false;
+        self.fingerprint = None;
+        self.clock_stepped = false;
+        self.ssrc_changes.clear();
     }
 }
 
@@ -298,71 +890,310 @@ impl MetricsWriter {
         Ok(())
     }
 
+    /// An operator note, between the snapshots either side of it; flushed
+    /// straight away so it survives a crash
+    fn write_annotation(&mut self, annotation: &Annotation) -> io::Result<()> {
+        let json = serde_json::to_string(annotation).map_err(io::Error::other)?;
+        writeln!(self.writer, "{}", json)?;
+        self.writer.flush()
+    }
+
+    /// A step in the wall clock, flushed like an annotation
+    fn write_clock_step(&mut self, step: &ClockStep) -> io::Result<()> {
+        let record = ClockStepRecord {
+            timestamp: step.at,
+            step_secs: step.step_secs,
+        };
+        let json = serde_json::to_string(&record).map_err(io::Error::other)?;
+        writeln!(self.writer, "{}", json)?;
+        self.writer.flush()
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         self.writer.flush()
     }
 }
 
+/// A `metrics.jsonl` line marking a step in the wall clock; snapshot
+/// timestamps either side of it are on different sides of the step.
+/// Tagged `"type": "clock_step"`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "clock_step")]
+pub struct ClockStepRecord {
+    pub timestamp: DateTime<Utc>,
+    pub step_secs: f64,
+}
+
+/// Running figures for an endpoint's `EndpointTotal`, so ended pages
+/// needn't stay in memory until the summary is written
+#[derive(Debug, Default)]
+struct PageTotals {
+    pages: u32,
+    duration_secs: f64,
+    packets: u64,
+    bytes: u64,
+    mos: MeanMos,
+}
+
+impl PageTotals {
+    fn add(&mut self, page: &PageSummary) {
+        self.pages += 1;
+        self.duration_secs += page.duration_secs;
+        self.packets += page.network.packets_received;
+        self.bytes += page.network.bytes_received;
+        if let Some(ref breakdown) = page.mos_breakdown {
+            self.mos.add(page.duration_secs, breakdown);
+        }
+    }
+}
+
+/// Name of each endpoint's page log, in its subdirectory
+const PAGES_FILE: &str = "pages.jsonl";
+
+/// Every ended page, one JSON line each in its endpoint's pages.jsonl.
+/// Pages are written as they end and only read back to build summary.json,
+/// so a run lasting days doesn't hold all of its pages in memory.
+struct PageLog {
+    output_dir: PathBuf,
+    /// Each endpoint's log, opened at its first page
+    writers: BTreeMap<String, BufWriter<File>>,
+    pages_written: u64,
+}
+
+impl PageLog {
+    fn new(output_dir: &Path) -> Self {
+        Self {
+            output_dir: output_dir.to_path_buf(),
+            writers: BTreeMap::new(),
+            pages_written: 0,
+        }
+    }
+
+    /// The page log of `endpoint` (ADDR:PORT)
+    fn path(&self, endpoint: &str) -> PathBuf {
+        self.output_dir.join(endpoint_dir(endpoint)).join(PAGES_FILE)
+    }
+
+    fn writer(&mut self, endpoint: &str) -> io::Result<&mut BufWriter<File>> {
+        let path = self.path(endpoint);
+        match self.writers.entry(endpoint.to_string()) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                Ok(entry.insert(BufWriter::new(File::create(path)?)))
+            }
+        }
+    }
+
+    /// Append a page; flushed at once, since pages are few and each one matters
+    fn write_page(&mut self, page: &PageSummary) -> io::Result<()> {
+        let json = serde_json::to_string(page).map_err(io::Error::other)?;
+        let writer = self.writer(&page.endpoint)?;
+        writeln!(writer, "{}", json)?;
+        writer.flush()?;
+        self.pages_written += 1;
+        Ok(())
+    }
+
+    /// A page's fingerprint, once its recording is finished
+    fn write_finalized(&mut self, finalized: &RecordingFinalized) -> io::Result<()> {
+        let json = serde_json::to_string(finalized).map_err(io::Error::other)?;
+        let writer = self.writer(&finalized.endpoint)?;
+        writeln!(writer, "{}", json)?;
+        writer.flush()
+    }
+
+    /// Every page written, in the order they started, with the fingerprints,
+    /// pipe output and jitter buffer analysis logged after them filled in
+    fn read_pages(&mut self) -> io::Result<Vec<PageSummary>> {
+        let mut pages = Vec::new();
+        let mut finalized = HashMap::new();
+        for (endpoint, writer) in &mut self.writers {
+            writer.flush()?;
+            let content = fs::read_to_string(self.output_dir.join(endpoint_dir(endpoint)).join(PAGES_FILE))?;
+            for line in content.lines() {
+                match serde_json::from_str::<PageSummary>(line) {
+                    Ok(page) => pages.push(page),
+                    Err(e) => {
+                        let line: RecordingFinalized = serde_json::from_str(line).map_err(|_| io::Error::other(e))?;
+                        finalized.insert((line.endpoint.clone(), line.page_number), line);
+                    }
+                }
+            }
+        }
+        for page in &mut pages {
+            if let Some(line) = finalized.remove(&(page.endpoint.clone(), page.page_number)) {
+                page.integrity = line.integrity;
+                page.pipe_output = line.pipe_output;
+                if let Some(analysis) = line.jitter_buffer_analysis {
+                    page.jitter_buffer_analysis = analysis;
+                }
+            }
+        }
+        pages.sort_by_key(PageSummary::id);
+        Ok(pages)
+    }
+}
+
+/// A pages.jsonl line with a page's fingerprint, what its
+/// `--pipe-audio-cmd` printed and its jitter buffer analysis, written once
+/// its recording has been finished, after the page itself. Tagged
+/// `"type": "recording_finalized"`. The fingerprint is absent if the
+/// recording couldn't be finished.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "recording_finalized")]
+struct RecordingFinalized {
+    endpoint: String,
+    page_number: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    integrity: Option<RecordingIntegrity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pipe_output: Option<PipeOutput>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    jitter_buffer_analysis: Option<Vec<JitterBufferResult>>,
+}
+
 /// Run the test command
 pub async fn run_test(options: TestOptions) -> Result<(), TestError> {
     // Validate timeout
-    if options.timeout == Duration::ZERO {
+    if matches!(options.timeout, Timeout::Indefinite | Timeout::For(Duration::ZERO)) {
         return Err(TestError::InvalidTimeout);
     }
+    options.naming.check("test recording", RECORDING_TOKENS)?;
 
     // Create output directory
     fs::create_dir_all(&options.output_dir)?;
+    if let Some(ref dir) = options.spectrum_dir {
+        fs::create_dir_all(dir)?;
+    }
 
-    // Parse the pattern
-    let pattern = if options.pattern.contains(':') {
-        options.pattern.clone()
-    } else {
-        format!("{}:{}", options.pattern, options.default_port)
-    };
+    // Parse the pattern (or endpoints file)
+    let resolved = resolve_endpoints(&options.pattern, options.default_port)?;
+    for side in options.compare_endpoints.iter().flat_map(|pair| [&pair.a, &pair.b]) {
+        let endpoint = MulticastEndpoint { address: *side.endpoint.ip(), port: side.endpoint.port() };
+        if !resolved.endpoints.contains(&endpoint) {
+            return Err(TestError::UncomparableEndpoint(side.endpoint));
+        }
+    }
+    run_test_endpoints(&options, &resolved).await
+}
 
-    let endpoints = parse_range(&pattern)?;
+/// Run the test on already-resolved endpoints
+async fn run_test_endpoints(options: &TestOptions, resolved: &ResolvedEndpoints) -> Result<(), TestError> {
+    let endpoints = &resolved.endpoints;
     if endpoints.is_empty() {
         return Err(TestError::NoEndpoints);
     }
 
-    let endpoint_count = endpoints.len();
-    let mut errors: Vec<String> = Vec::new();
+    // Fail now rather than part way through opening sockets and files
+    fd_limit::ensure("test", fd_plan(options, endpoints.len()), options.max_file_descriptors)?;
+
+    let test_start_time = Utc::now();
+    let mut errors = ErrorLog::default();
+
+    // Opened before the groups are joined, so their first reports are seen
+    let mut igmp = options
+        .observe_igmp
+        .then(|| IgmpWatch::open(endpoints.iter().map(|ep| ep.address), options.interface));
+    if let Some(reason) = igmp.as_ref().and_then(IgmpWatch::unavailable) {
+        println!("Warning: not watching IGMP: {}", reason);
+    }
 
     // Create sockets - one per endpoint (address:port pair)
     // Each socket is bound to its specific multicast group address to ensure proper filtering
-    // when multiple endpoints share the same port (e.g., 224.1.1.2:5000 and 224.1.1.3:5000)
-    let interface = options.interface.unwrap_or(Ipv4Addr::UNSPECIFIED);
-    let mut sockets: HashMap<(Ipv4Addr, u16), MulticastSocket> = HashMap::new();
-    for ep in &endpoints {
-        let socket = MulticastSocket::bound_to_group(ep.address, ep.port, interface).await?;
-        sockets.insert((ep.address, ep.port), socket);
+    // when multiple endpoints share the same port (e.g., 224.1.1.2:5000 and 224.1.1.3:5000).
+    // An endpoint that can't be joined is reported and skipped rather than failing the run.
+    let (mut sockets, unavailable) = open_test_sockets(endpoints, options.interface, options.busy_poll_us).await;
+    for ep in &unavailable {
+        println!("Warning: unable to monitor {}: {}", ep.endpoint, ep.reason);
+        errors.push(format!("Failed to join {}: {}", ep.endpoint, ep.reason));
     }
 
     // Create endpoint states
-    let mut endpoint_states: HashMap<(Ipv4Addr, u16), TestEndpointState> = HashMap::new();
-    for ep in &endpoints {
-        endpoint_states.insert(
-            (ep.address, ep.port),
-            TestEndpointState::new(ep.address, ep.port),
-        );
+    let page_ids = PageIds::default();
+    let analysis_pool =
+        AnalysisPool::new(options.analysis_threads.unwrap_or_else(|| analysis_pool::default_threads(sockets.len())));
+    let mut endpoint_states: HashMap<(Ipv4Addr, u16), TestEndpointState> = sockets
+        .keys()
+        .map(|&(address, port)| {
+            let overrides = resolved.options(&MulticastEndpoint { address, port });
+            let state = TestEndpointState::new(address, port)
+                .with_overrides(overrides)
+                .with_repair(options.repair_window)
+                .with_expect_dscp(options.expect_dscp)
+                .with_classifier(options.classifier)
+                .with_transcoding(options.transcoding)
+                .with_page_ids(page_ids.clone())
+                .with_analysis(analysis_pool.worker());
+            ((address, port), state)
+        })
+        .collect();
+    let endpoint_count = endpoint_states.len();
+
+    // Nothing to listen on - still leave a summary so CI can see why
+    if sockets.is_empty() {
+        let mut summary = generate_summary(options, test_start_time, Utc::now(), &endpoint_states, Vec::new(), unavailable, errors);
+        summary.test_metadata.end_reason = Some(EndReason::NoUsableEndpoints);
+        write_summary(&options.output_dir, &summary)?;
+        return Err(TestError::NoUsableEndpoints(endpoints.len()));
     }
 
+    // Listen for SIGHUP and control socket commands
+    let mut control = ControlHandle::spawn(options.control_socket.as_deref(), options.default_port)?;
+
     // Create metrics writer
     let mut metrics_writer = MetricsWriter::new(&options.output_dir)?;
+    let mut annotations = AnnotationLog::new(&options.output_dir);
+    let mut page_log = PageLog::new(&options.output_dir);
+    let mut pcap = match options.pcap {
+        Some(ref path) => Some(PcapWriter::create(&options.naming.capture_path(path, Local::now())?)?),
+        None => None,
+    };
+    let mut trends = options.aggregate.as_deref().map(TrendWriter::open).transpose()?;
 
     // Print start message
     println!("Test mode started");
     println!("  Output directory: {}", options.output_dir.display());
     println!("  Monitoring {} endpoint(s)", endpoint_count);
-    println!("  Timeout: {} seconds", options.timeout.as_secs());
+    if !unavailable.is_empty() {
+        println!("  Unavailable: {} endpoint(s)", unavailable.len());
+    }
+    println!("  Timeout: {}", options.timeout);
     println!("  Metrics interval: {}ms", options.metrics_interval.as_millis());
+    for setting in options.settings.iter().filter(|setting| setting.is_implicit()) {
+        println!("  Setting: {} (from {})", setting, setting.source);
+    }
+    for ep in endpoints {
+        if let Some(overrides) = resolved.overrides.get(ep) {
+            println!("  Endpoint {}: {}", ep, overrides);
+        }
+    }
+    if options.ssrc_filter.is_active() {
+        println!("  Streams: {}", options.ssrc_filter);
+    }
+    if let Some(ref pcap) = pcap {
+        println!("  Packet capture: {}", pcap.path().display());
+    }
+    if let Some(ref trends) = trends {
+        println!("  Trend aggregate: {}", trends.path().display());
+    }
     println!();
 
-    let test_start_time = Utc::now();
     let start_instant = Instant::now();
+    let mut clock_watch = ClockWatch::new(&SystemClock);
+    let mut finalize = FinalizeQueue::new(FINALIZE_CONCURRENCY);
     let mut last_metrics_sample = Instant::now();
-    let idle_timeout = Duration::from_secs(2);
-    let mut buf = vec![0u8; 2048];
+    let mut last_memory_report = Instant::now();
+    let mut receiver = FairReceiver::new(DRAIN_BUDGET);
+    let repair_client = match options.repair_window {
+        Some(_) => Some(RepairClient::bind().await?),
+        None => None,
+    };
+
+    // No stats line to hold off while a note is typed
+    let _keyboard = control.listen_keyboard(Arc::default());
 
     // Set up signal handling for graceful shutdown
     let shutdown = Arc::new(AtomicBool::new(false));
@@ -373,35 +1204,94 @@ pub async fn run_test(options: TestOptions) -> Result<(), TestError> {
         }
     });
 
-    loop {
+    let end_reason = loop {
         // Check for shutdown signal
         if shutdown.load(Ordering::SeqCst) {
             println!("Received shutdown signal, finalizing...");
-            break;
+            break EndReason::Interrupted;
         }
 
         // Check for overall timeout
-        if start_instant.elapsed() >= options.timeout {
-            println!("Timeout reached.");
-            break;
+        if options.timeout.expired(start_instant) {
+            println!("{}", options.timeout.reached());
+            break options.timeout.end_reason();
         }
 
-        // Check for page end on all endpoints
-        for state in endpoint_states.values_mut() {
-            if state.page_active {
-                if let Some(last) = state.last_packet {
-                    if last.elapsed() >= idle_timeout {
-                        if let Err(e) = handle_test_page_end(state, &options.output_dir) {
-                            errors.push(format!("Error ending page on {}: {}", state.endpoint_string(), e));
-                        }
+        // Pages in progress across a step get times from the monotonic clock
+        if let Some(step) = clock_watch.check(&SystemClock) {
+            println!(
+                "[{}] Warning: system clock stepped by {:+.3}s",
+                step.at.with_timezone(&Local).format("%H:%M:%S"),
+                step.step_secs
+            );
+            for state in endpoint_states.values_mut().filter(|state| state.page_active) {
+                state.clock_stepped = true;
+            }
+            if let Err(e) = metrics_writer.write_clock_step(&step) {
+                errors.push(format!("Error writing metrics: {}", e));
+            }
+        }
+
+        if let Some(ref mut igmp) = igmp {
+            igmp.poll(Utc::now());
+        }
+
+        // Apply any runtime changes to the endpoint set
+        while let Some(request) = control.try_next() {
+            if let ControlCommand::Annotate(ref annotation) = request.command {
+                println!("[{}] Annotation: {}", annotation.timestamp.with_timezone(&Local).format("%H:%M:%S"), annotation.text);
+                let written = annotations.write(annotation).and_then(|()| metrics_writer.write_annotation(annotation));
+                match written {
+                    Ok(()) => request.respond("ok".to_string()),
+                    Err(e) => {
+                        errors.push(format!("Error writing annotation: {}", e));
+                        request.respond(format!("error: {}", e));
                     }
                 }
+                continue;
+            }
+            handle_test_control(request, &mut sockets, &mut endpoint_states, &page_ids, &analysis_pool, options, &mut errors)
+                .await;
+        }
+
+        // Check for page end on all endpoints, and drop stray bursts that never became a page
+        let now = Instant::now();
+        for state in endpoint_states.values_mut() {
+            if let Some(ref client) = repair_client {
+                if let Err(e) = poll_test_repair(state, client, now, options) {
+                    errors.push(format!("Error handling packet on {}: {}", state.endpoint_string(), e));
+                }
+            }
+            state.pending.expire(state.page_threshold(options), now);
+            if state.is_idle(now) {
+                if let Err(e) = flush_test_repair(state, options) {
+                    errors.push(format!("Error handling packet on {}: {}", state.endpoint_string(), e));
+                }
+                if let Err(e) = handle_test_page_end(state) {
+                    errors.push(format!("Error ending page on {}: {}", state.endpoint_string(), e));
+                }
+            }
+        }
+
+        flush_ended_pages(&mut page_log, trends.as_mut(), &mut endpoint_states, &mut errors);
+        finalize_test_recordings(&mut finalize, &mut page_log, &mut endpoint_states, &mut errors);
+
+        // Stop listening on endpoints whose removal was waiting for a page to finish.
+        // Their state is kept so completed pages still appear in the summary.
+        for (key, state) in &mut endpoint_states {
+            if state.pending_removal && !state.page_active {
+                state.pending_removal = false;
+                sockets.remove(key);
+                println!("Stopped monitoring {}", state.endpoint_string());
             }
         }
 
         // Sample metrics at interval
         if last_metrics_sample.elapsed() >= options.metrics_interval {
-            for state in endpoint_states.values() {
+            for (key, state) in &endpoint_states {
+                if !sockets.contains_key(key) {
+                    continue;
+                }
                 let snapshot = create_metric_snapshot(state);
                 if let Err(e) = metrics_writer.write_snapshot(&snapshot) {
                     errors.push(format!("Error writing metrics: {}", e));
@@ -410,45 +1300,81 @@ pub async fn run_test(options: TestOptions) -> Result<(), TestError> {
             last_metrics_sample = Instant::now();
         }
 
-        // Receive from all sockets
-        // Each socket is bound to a specific multicast group, so packets are pre-filtered
-        let recv_timeout = Duration::from_millis(10);
-
-        for (&endpoint_key, socket) in &sockets {
-            loop {
-                let recv_result = tokio::time::timeout(recv_timeout, socket.recv_from(&mut buf)).await;
+        if options.memory_report.is_some_and(|interval| last_memory_report.elapsed() >= interval) {
+            report_memory(&endpoint_states, &page_log, &errors);
+            last_memory_report = Instant::now();
+        }
 
-                let (len, src_addr) = match recv_result {
-                    Ok(Ok((len, addr))) => (len, addr),
-                    Ok(Err(e)) => {
+        // Receive from all sockets in turn, a budget at a time
+        // Each socket is bound to a specific multicast group, so packets are pre-filtered
+        let Ok(()) = receiver
+            .round(&sockets, |&endpoint_key, socket, received| {
+                let (datagram, data) = match received {
+                    Ok(received) => received,
+                    Err(e) => {
                         let (addr, port) = endpoint_key;
                         errors.push(format!("Receive error on {}:{}: {}", addr, port, e));
-                        break;
+                        return Ok(());
                     }
-                    Err(_) => break,
                 };
 
-                let Ok(packet) = RtpPacket::parse(&buf[..len], src_addr) else {
-                    continue;
+                if let Some(ref mut pcap) = pcap {
+                    pcap.write_packet(datagram.wall_time, datagram.source, socket.destination(), datagram.ttl, data);
+                }
+
+                let mut packet = match RtpPacket::parse_with_time(data, datagram.source, datagram.received_at) {
+                    Ok(packet) => packet,
+                    Err(e) => {
+                        limited!(debug!("[{}] Ignoring a packet from {}: {}", socket.destination(), datagram.source, e));
+                        return Ok(());
+                    }
                 };
+                packet.ttl = datagram.ttl;
+                packet.dscp = datagram.dscp;
 
                 // Directly use the endpoint key since each socket is bound to exactly one endpoint
                 if let Some(state) = endpoint_states.get_mut(&endpoint_key) {
-                    if let Err(e) = handle_test_packet(state, &packet, &options) {
+                    if let Err(e) = receive_test_packet(state, packet, options) {
+                        errors.push(format!("Error handling packet on {}: {}", state.endpoint_string(), e));
+                    }
+                }
+                Ok::<(), Infallible>(())
+            })
+            .await;
+
+        // Put resent packets back in their streams
+        while let Some(packet) = repair_client.as_ref().and_then(RepairClient::try_recv) {
+            let state = endpoint_states
+                .values_mut()
+                .find(|state| state.repair.as_ref().is_some_and(|repair| repair.ssrc() == Some(packet.header.ssrc)));
+            if let Some(state) = state {
+                let ready = state.repair.as_mut().map(|repair| repair.accept_repair(packet, Instant::now())).unwrap_or_default();
+                for packet in &ready {
+                    if let Err(e) = handle_test_packet(state, packet, options) {
                         errors.push(format!("Error handling packet on {}: {}", state.endpoint_string(), e));
                     }
                 }
             }
         }
-    }
+    };
 
     // Finalize any active recordings
+    let finalization_start = Instant::now();
     for state in endpoint_states.values_mut() {
+        if let Err(e) = flush_test_repair(state, options) {
+            errors.push(format!("Error handling packet on {}: {}", state.endpoint_string(), e));
+        }
         if state.page_active {
-            if let Err(e) = handle_test_page_end(state, &options.output_dir) {
+            if let Err(e) = truncate_test_page(state) {
                 errors.push(format!("Error finalizing page on {}: {}", state.endpoint_string(), e));
             }
         }
+        state.pending.discard();
+    }
+    flush_ended_pages(&mut page_log, trends.as_mut(), &mut endpoint_states, &mut errors);
+    finalize_test_recordings(&mut finalize, &mut page_log, &mut endpoint_states, &mut errors);
+    for finished in finalize.drain().await {
+        log_finalized(finished, &mut page_log, &mut errors);
     }
 
     // Flush metrics
@@ -456,13 +1382,34 @@ pub async fn run_test(options: TestOptions) -> Result<(), TestError> {
 
     // Generate and write summary
     let test_end_time = Utc::now();
-    let summary = generate_summary(
-        &options,
+    let pages = page_log.read_pages()?;
+    for problem in check_recordings(&options.output_dir, &pages) {
+        errors.push(problem);
+    }
+    let mut summary = generate_summary(
+        options,
         test_start_time,
         test_end_time,
         &endpoint_states,
+        pages,
+        unavailable,
         errors,
     );
+    summary.test_metadata.end_reason = Some(end_reason);
+    if clock_watch.steps() > 0 {
+        summary.test_metadata.clock_step_secs = Some(clock_watch.total_step_secs());
+    }
+    summary.path_comparisons = path_compare::compare_paths(&options.output_dir, &options.compare_endpoints, &summary.pages);
+    if let Some(ref mut igmp) = igmp {
+        igmp.poll(Utc::now());
+        let observation = igmp.summary(endpoint_states.values().map(|state| (state.endpoint_string(), state.address)));
+        // Where to look when nothing came through
+        if summary.pages.is_empty() {
+            summary.errors.extend(observation.diagnostics());
+        }
+        summary.test_metadata.igmp = Some(observation);
+    }
+    summary.test_metadata.finalization_secs = Some(finalization_start.elapsed().as_secs_f64());
     write_summary(&options.output_dir, &summary)?;
 
     // Print completion message
@@ -471,6 +1418,34 @@ pub async fn run_test(options: TestOptions) -> Result<(), TestError> {
     println!("  Duration: {:.1}s", summary.test_metadata.duration_secs);
     println!("  Pages detected: {}", summary.pages.len());
     println!("  Errors: {}", summary.errors.len());
+    if summary.pages.is_empty() {
+        for diagnostic in summary.test_metadata.igmp.iter().flat_map(IgmpObservation::diagnostics) {
+            println!("  {}", diagnostic);
+        }
+    }
+    if let Some(step_secs) = summary.test_metadata.clock_step_secs {
+        println!("  Clock stepped: {:+.3}s", step_secs);
+    }
+    if let Some(secs) = summary.test_metadata.finalization_secs {
+        println!("  Finalizing: {:.2}s", secs);
+    }
+    if !summary.unavailable_endpoints.is_empty() {
+        println!("  Unavailable endpoints: {}", summary.unavailable_endpoints.len());
+    }
+    let filtered: u64 = summary.endpoint_totals.values().map(|total| total.filtered_packets).sum();
+    if filtered > 0 {
+        println!("  Filtered by SSRC: {} packet(s)", filtered);
+    }
+    for comparison in &summary.path_comparisons {
+        println!(
+            "  {} page {} vs {}: {}{}",
+            comparison.a_label,
+            comparison.a_page,
+            comparison.b_label,
+            comparison.verdict,
+            if comparison.findings.is_empty() { String::new() } else { format!(" ({})", comparison.findings.join("; ")) }
+        );
+    }
     println!();
     println!("Output files:");
     println!("  {}/metrics.jsonl", options.output_dir.display());
@@ -478,10 +1453,148 @@ pub async fn run_test(options: TestOptions) -> Result<(), TestError> {
     for page in &summary.pages {
         println!("  {}/{}", options.output_dir.display(), page.recording_file);
     }
+    if let Some(pcap) = pcap {
+        println!("  {} ({} packets)", pcap.path().display(), pcap.packets());
+    }
+
+    Ok(())
+}
+
+/// Apply a runtime control request and reply to the requester
+async fn handle_test_control(
+    request: ControlRequest,
+    sockets: &mut HashMap<(Ipv4Addr, u16), MulticastSocket>,
+    endpoint_states: &mut HashMap<(Ipv4Addr, u16), TestEndpointState>,
+    page_ids: &PageIds,
+    analysis_pool: &AnalysisPool,
+    options: &TestOptions,
+    errors: &mut ErrorLog,
+) {
+    let result = match request.command.clone() {
+        ControlCommand::Add(endpoints) => {
+            let resolved = ResolvedEndpoints { endpoints, ..ResolvedEndpoints::default() };
+            add_test_endpoints(&resolved, sockets, endpoint_states, page_ids, analysis_pool, options).await
+        }
+        ControlCommand::Remove(endpoints) => {
+            remove_test_endpoints(&endpoints, sockets, endpoint_states);
+            Ok(())
+        }
+        ControlCommand::Reload => {
+            match resolve_endpoints(&options.pattern, options.default_port) {
+                Ok(targets) => {
+                    let wanted: HashSet<(Ipv4Addr, u16)> =
+                        targets.endpoints.iter().map(|ep| (ep.address, ep.port)).collect();
+                    let removed: Vec<MulticastEndpoint> = sockets
+                        .keys()
+                        .filter(|key| !wanted.contains(key))
+                        .map(|&(address, port)| MulticastEndpoint { address, port })
+                        .collect();
+                    remove_test_endpoints(&removed, sockets, endpoint_states);
+                    for ep in &targets.endpoints {
+                        if let Some(state) = endpoint_states.get_mut(&(ep.address, ep.port)) {
+                            state.overrides = targets.options(ep);
+                        }
+                    }
+                    add_test_endpoints(&targets, sockets, endpoint_states, page_ids, analysis_pool, options).await
+                }
+                Err(e) => Err(e.into()),
+            }
+        }
+        // Taken by the receive loop, which owns the metrics file
+        ControlCommand::Annotate(_) => Ok(()),
+        ControlCommand::Status => {
+            let mut keys: Vec<(Ipv4Addr, u16)> = sockets.keys().copied().collect();
+            keys.sort();
+            let table: Vec<EndpointStatus> = keys
+                .iter()
+                .filter_map(|key| endpoint_states.get(key))
+                .map(|state| EndpointStatus {
+                    address: state.address.to_string(),
+                    port: state.port,
+                    page_active: state.page_active,
+                    packets_received: state.stats.packets_received,
+                    pending_removal: state.pending_removal,
+                    suppressed_bursts: state.pending.suppressed,
+                    filtered_packets: state.filtered_packets,
+                })
+                .collect();
+            request.respond(serde_json::to_string(&table).unwrap_or_default());
+            return;
+        }
+    };
+
+    match result {
+        Ok(()) => request.respond("ok".to_string()),
+        Err(e) => {
+            errors.push(format!("Control command failed: {}", e));
+            request.respond(format!("error: {}", e));
+        }
+    }
+}
+
+/// Start listening on endpoints that are not already monitored.
+/// State from an earlier removal of the same endpoint is reused.
+async fn add_test_endpoints(
+    resolved: &ResolvedEndpoints,
+    sockets: &mut HashMap<(Ipv4Addr, u16), MulticastSocket>,
+    endpoint_states: &mut HashMap<(Ipv4Addr, u16), TestEndpointState>,
+    page_ids: &PageIds,
+    analysis_pool: &AnalysisPool,
+    options: &TestOptions,
+) -> Result<(), TestError> {
+    let interface = options.interface.unwrap_or_default();
+
+    for ep in &resolved.endpoints {
+        let key = (ep.address, ep.port);
+        if let Some(state) = endpoint_states.get_mut(&key) {
+            state.pending_removal = false;
+        }
+        if sockets.contains_key(&key) {
+            continue;
+        }
+
+        let socket = MulticastSocket::bound_to_group(ep.address, ep.port, interface).await?;
+        socket.apply_busy_poll(options.busy_poll_us);
+        sockets.insert(key, socket);
+        endpoint_states
+            .entry(key)
+            .or_insert_with(|| {
+                TestEndpointState::new(ep.address, ep.port)
+                    .with_overrides(resolved.options(ep))
+                    .with_repair(options.repair_window)
+                    .with_expect_dscp(options.expect_dscp)
+                    .with_classifier(options.classifier)
+                    .with_transcoding(options.transcoding)
+                    .with_page_ids(page_ids.clone())
+                    .with_analysis(analysis_pool.worker())
+            });
+        println!("Now monitoring {}", ep);
+    }
 
     Ok(())
 }
 
+/// Stop listening on endpoints, deferring any with an active page until it ends
+fn remove_test_endpoints(
+    endpoints: &[MulticastEndpoint],
+    sockets: &mut HashMap<(Ipv4Addr, u16), MulticastSocket>,
+    endpoint_states: &mut HashMap<(Ipv4Addr, u16), TestEndpointState>,
+) {
+    for ep in endpoints {
+        let key = (ep.address, ep.port);
+        if !sockets.contains_key(&key) {
+            continue;
+        }
+        match endpoint_states.get_mut(&key) {
+            Some(state) if state.page_active => state.pending_removal = true,
+            _ => {
+                sockets.remove(&key);
+                println!("Stopped monitoring {}", ep);
+            }
+        }
+    }
+}
+
 fn create_metric_snapshot(state: &TestEndpointState) -> MetricSnapshot {
     let duration_secs = if state.page_active {
         state.page_start.map(|s| {
@@ -498,118 +1611,486 @@ fn create_metric_snapshot(state: &TestEndpointState) -> MetricSnapshot {
         endpoint: state.endpoint_string(),
         page_active: state.page_active,
         page_number: if state.page_active { Some(state.page_count) } else { None },
+        page_id: state.page_active.then_some(state.page_id),
         duration_secs,
         network: NetworkMetrics {
             packets: state.stats.packets_received,
             bytes: state.stats.bytes_received,
             loss_percent: state.stats.loss_percent(),
             jitter_ms: state.stats.jitter_ms,
+            max_gap_ms: state.stats.max_gap_ms,
+            ttl: state.stats.ttl(),
+            decode_errors: state.stats.decode_errors,
+            truncated_payloads: state.stats.truncated_payloads,
         },
         audio: AudioMetrics {
             rms_db: state.current_audio.rms_db,
             peak_db: state.current_audio.peak_db,
-            dominant_freq_hz: state.current_audio.dominant_freq_hz,
+            dominant_freq_hz: state.audio_analyzer.as_ref().map_or(0.0, AudioAnalyzer::dominant_freq_hz),
             glitches: state.audio_stats.total_glitches,
             clipped: state.audio_stats.total_clipped,
         },
     }
 }
 
+/// Handle a packet off the network, through the endpoint's repair buffer
+/// with `--repair`, unless the SSRC filter turns it away
+fn receive_test_packet(state: &mut TestEndpointState, packet: RtpPacket, options: &TestOptions) -> Result<(), TestError> {
+    if !options.ssrc_filter.passes(packet.header.ssrc) {
+        state.filtered_packets += 1;
+        return Ok(());
+    }
+    let Some(ref mut repair) = state.repair else {
+        return handle_test_packet(state, &packet, options);
+    };
+    for packet in &repair.accept(packet, Instant::now()) {
+        handle_test_packet(state, packet, options)?;
+    }
+    Ok(())
+}
+
+/// Hand on packets whose gaps have been given up, and ask for the gaps now due
+fn poll_test_repair(state: &mut TestEndpointState, client: &RepairClient, now: Instant, options: &TestOptions) -> Result<(), TestError> {
+    let Some(ref mut repair) = state.repair else {
+        return Ok(());
+    };
+    if let (Some(nack), Some(source)) = (repair.nack(now), repair.source()) {
+        client.send(&nack, source, state.port);
+    }
+    for packet in &repair.poll(now) {
+        handle_test_packet(state, packet, options)?;
+    }
+    Ok(())
+}
+
+/// Hand on everything the repair buffer holds, before the page ends
+fn flush_test_repair(state: &mut TestEndpointState, options: &TestOptions) -> Result<(), TestError> {
+    let Some(ref mut repair) = state.repair else {
+        return Ok(());
+    };
+    for packet in &repair.flush() {
+        handle_test_packet(state, packet, options)?;
+    }
+    Ok(())
+}
+
 fn handle_test_packet(
     state: &mut TestEndpointState,
     packet: &RtpPacket,
     options: &TestOptions,
 ) -> Result<(), TestError> {
-    // Check if this is a new page (SSRC changed)
-    if state.ssrc.is_none() || state.ssrc != Some(packet.header.ssrc) {
-        // If there was a previous page active, finalize it first
-        if state.page_active {
-            handle_test_page_end(state, &options.output_dir)?;
-        }
+    if state.ssrc == Some(packet.header.ssrc) {
+        return process_test_packet(state, packet, options);
+    }
+    if let Some(gap) = state.continuation(packet, options) {
+        stitch_test_page(state, packet, gap)?;
+        return process_test_packet(state, packet, options);
+    }
 
-        state.page_count += 1;
-        state.ssrc = Some(packet.header.ssrc);
-        state.page_start = Some(Instant::now());
-        state.page_start_utc = Some(Utc::now());
-        state.page_active = true;
-        state.stats = PageStats::default();
+    // A new stream (SSRC changed); it becomes a page once it reaches the threshold,
+    // so a stray packet doesn't cut the current page short
+    let Some(held) = state.pending.offer(packet, state.page_threshold(options)) else {
+        return Ok(());
+    };
+    if state.page_active {
+        handle_test_page_end(state)?;
+    }
+    start_test_page(state, &held[0], options)?;
+    for packet in &held {
+        // An aborted page leaves the rest to start the next one
+        if state.ssrc == Some(packet.header.ssrc) {
+            process_test_packet(state, packet, options)?;
+        } else {
+            handle_test_packet(state, packet, options)?;
+        }
+    }
+    Ok(())
+}
 
-        // Codec type for potential future use (logging, metadata)
-        let _codec_type = options.codec.unwrap_or_else(|| {
-            CodecType::from_payload_type(packet.header.payload_type)
-                .unwrap_or(CodecType::G711Ulaw)
-        });
+/// Carry the active page on to `packet`'s new SSRC, `gap` after the old
+/// one's last packet, with silence in the recording for the wait
+fn stitch_test_page(state: &mut TestEndpointState, packet: &RtpPacket, gap: Duration) -> Result<(), TestError> {
+    let change = SsrcChange {
+        old_ssrc: state.ssrc.unwrap_or_default(),
+        new_ssrc: packet.header.ssrc,
+        offset_secs: state.page_start.map_or(0.0, |start| packet.received_at.saturating_duration_since(start).as_secs_f64()),
+        gap_ms: gap.as_secs_f64() * 1000.0,
+    };
+    if let Some(ref dec) = state.decoder {
+        let frames = stitch::gap_frames(gap, state.stats.nominal_packet_secs(), dec.sample_rate());
+        if let Some(ref mut rec) = state.recorder {
+            rec.write_silence(frames)?;
+        }
+        if let Some(ref mut pipe) = state.audio_pipe {
+            pipe.push_silence(frames);
+        }
+    }
+    state.stats.stitch();
+    // The new stream's timestamps have nothing to do with the old one's
+    if let Some(ref mut timeline) = state.packet_timeline {
+        timeline.resync();
+    }
+    state.ssrc = Some(packet.header.ssrc);
+    state.ssrc_changes.push(change);
+    println!(
+        "[{}] Page {}: SSRC {:08x} -> {:08x} after {:.0}ms; carrying the page on",
+        state.endpoint_string(),
+        state.page_count,
+        change.old_ssrc,
+        change.new_ssrc,
+        change.gap_ms
+    );
+    Ok(())
+}
 
-        let payload_type = PayloadType::from_pt(packet.header.payload_type);
+/// Set up decoding, analysis and recording for a page starting with `packet`
+fn start_test_page(
+    state: &mut TestEndpointState,
+    packet: &RtpPacket,
+    options: &TestOptions,
+) -> Result<(), TestError> {
+    state.page_count += 1;
+    state.page_id = state.page_ids.next();
+    state.ssrc = Some(packet.header.ssrc);
+    state.page_start = Some(packet.received_at);
+    state.page_start_utc = Some(arrival_time(packet.received_at));
+    state.page_active = true;
+    state.fingerprint = Some(SenderFingerprint::from_packet(packet));
+
+    // Dynamic payload types (e.g. AES67 L24) need the codec forced
+    let codec_spec = options.codec.unwrap_or_else(|| {
+        CodecSpec::from_payload_type(packet.header.payload_type).unwrap_or(CodecSpec::new(CodecType::G711Ulaw))
+    });
+    state.stats = PageStats::for_codec(codec_spec).with_g722_nonstandard_timestamps(options.g722_nonstandard_timestamps);
+    state.packet_timeline = Some(PacketTimeline::new(state.stats.timestamp_clock_rate()));
 
-        println!(
-            "[{}] Page {} started (codec: {})",
-            state.endpoint_string(),
-            state.page_count,
-            payload_type.name()
-        );
+    let codec_name = if options.codec.is_some() {
+        codec_spec.to_string()
+    } else {
+        PayloadType::from_pt(packet.header.payload_type).name().to_string()
+    };
 
-        // Create decoder
-        state.decoder = Some(create_decoder_for_payload_type(packet.header.payload_type)?);
+    println!(
+        "[{}] Page {} started (id {}, codec: {})",
+        state.endpoint_string(),
+        state.page_count,
+        state.page_id,
+        codec_name
+    );
+    if let Some(guess) = state.fingerprint.as_ref().and_then(|fingerprint| fingerprint.likely_sender.as_ref()) {
+        println!("  Likely sender: {}", guess);
+    }
 
-        // Create audio analyzer
-        let sample_rate = state.decoder.as_ref().unwrap().sample_rate();
-        state.audio_analyzer = Some(AudioAnalyzer::new(sample_rate));
-        state.audio_stats = AudioStats::new();
+    // Create decoder; without its backend, the page's packets are counted as undecodable
+    let decoder = match options.codec {
+        Some(spec) => create_decoder_for_spec(spec),
+        None => create_decoder_for_payload_type(packet.header.payload_type),
+    };
+    state.decoder = Some(match decoder {
+        Err(CodecError::BackendUnavailable { codec, missing }) => {
+            println!("  ⚠ {} needs the {}, which isn't available; not decoding this page", codec, missing);
+            Box::new(UnavailableDecoder::new(codec, missing))
+        }
+        decoder => decoder?,
+    });
 
-        // Create recorder with numbered filename
-        let filename = format!(
+    // Create audio analyzer
+    let sample_rate = state.decoder.as_ref().unwrap().sample_rate();
+    let channels = state.decoder.as_ref().unwrap().channels();
+    state.audio_analyzer =
+        Some(AudioAnalyzer::with_channels(sample_rate, channels).with_worker(state.analysis_worker.clone()));
+    state.audio_stats = AudioStats::new();
+
+    // Create recorder with numbered filename
+    let filename = page_recording_file(state, options);
+    let path = options.output_dir.join(&filename);
+    fs::create_dir_all(options.output_dir.join(state.output_subdir()))?;
+    let recorder = match WavRecorder::new(&path, sample_rate, channels) {
+        Ok(recorder) => Some(recorder),
+        // Out of file descriptors: the page is still measured, just not recorded
+        Err(e) if e.io_error().is_some_and(fd_limit::is_exhausted) => {
+            println!("[{}] Unable to record page to {}: {}; measuring it without a recording", state.endpoint_string(), path.display(), e);
+            None
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(mut recorder) = recorder {
+        if let Some(start) = state.page_start_utc {
+            recorder.set_start_time(start);
+        }
+        state.recorder = Some(recorder);
+        if let Some(ref command) = options.pipe_audio_cmd {
+            // The page is recorded whether or not its command runs
+            match command.start(sample_rate, channels) {
+                Ok(pipe) => state.audio_pipe = Some(pipe),
+                Err(e) => println!("[{}] {}", state.endpoint_string(), e),
+            }
+        }
+        if let Some(granularity) = options.timing_file {
+            state.timing = Some(TimingWriter::create(&timing_path(&path), granularity)?);
+        }
+        if options.record_payloads {
+            // The payloads are extra; the page is recorded without them
+            let payloads = payloads_path(&path);
+            match PayloadWriter::create(&payloads) {
+                Ok(writer) => state.payloads = Some(writer),
+                Err(e) => println!("[{}] Unable to create {}: {}", state.endpoint_string(), payloads.display(), e),
+            }
+        }
+        state.recording_file = Some(filename.clone());
+        if options.split_spurts {
+            open_spurt_segment(state, options)?;
+        }
+    }
+
+    if let Some(ref dir) = options.spectrum_dir {
+        let path = page_spectrum_path(dir, &filename);
+        fs::create_dir_all(dir.join(state.output_subdir()))?;
+        state.spectrum = Some(SpectrumWriter::create(&path, state.endpoint_string(), Some(state.page_count))?);
+        state.last_spectrum = Some(Instant::now());
+    }
+    Ok(())
+}
+
+/// Recording for the page just started, relative to the output directory:
+/// in the endpoint's subdirectory, named from the template if there is one
+fn page_recording_file(state: &TestEndpointState, options: &TestOptions) -> String {
+    let fields = NameFields {
+        endpoint: Some(SocketAddrV4::new(state.address, state.port)),
+        page: Some(state.page_count),
+        start: state.page_start_utc.map(|start| start.with_timezone(&Local)),
+        ssrc: state.ssrc,
+        ..NameFields::default()
+    };
+    let subdir = state.output_subdir();
+    let name = match options.naming.recording_name(fields) {
+        Some(name) => unique_name(&options.output_dir.join(&subdir), &name),
+        None => format!(
             "page_{:04}_{}_{}.wav",
             state.page_count,
             state.address.to_string().replace('.', "_"),
             state.port
-        );
-        let path = options.output_dir.join(&filename);
-        let channels = state.decoder.as_ref().unwrap().channels();
-        state.recorder = Some(WavRecorder::new(&path, sample_rate, channels)?);
+        ),
+    };
+    format!("{}/{}", subdir, name)
+}
+
+/// Start the next `--split-spurts` segment of the current page's recording,
+/// named after it: `page_0001_..._spurt_02.wav`
+fn open_spurt_segment(state: &mut TestEndpointState, options: &TestOptions) -> Result<(), TestError> {
+    let (Some(recording), Some(decoder)) = (state.recording_file.as_deref(), state.decoder.as_ref()) else {
+        return Ok(());
+    };
+    if let Some(previous) = state.spurt_recorder.take() {
+        previous.finalize()?;
     }
+    let stem = recording.strip_suffix(".wav").unwrap_or(recording);
+    let filename = format!("{}_spurt_{:02}.wav", stem, state.spurt_files.len() + 1);
+    let path = options.output_dir.join(&filename);
+    state.spurt_recorder = Some(WavRecorder::new(&path, decoder.sample_rate(), decoder.channels())?);
+    state.spurt_files.push(filename);
+    Ok(())
+}
 
+/// Update stats, then decode, analyze and record one packet of the current page
+fn process_test_packet(
+    state: &mut TestEndpointState,
+    packet: &RtpPacket,
+    options: &TestOptions,
+) -> Result<(), TestError> {
     // Update stats
-    state.stats.update(packet);
-    state.last_packet = Some(Instant::now());
+    let marked_before = state.stats.marked_packets;
+    match state.stats.update(packet) {
+        Some(StreamDiscontinuity::SequenceReset { old_sequence, new_sequence }) => {
+            println!(
+                "[{}] Stream reset: sequence {} -> {}",
+                state.endpoint_string(),
+                old_sequence,
+                new_sequence
+            );
+            if let Some(ref mut timeline) = state.packet_timeline {
+                timeline.resync();
+            }
+        }
+        Some(StreamDiscontinuity::SsrcCollision { ssrc, original_source, new_source }) => {
+            println!(
+                "[{}] SSRC collision: {:08x} from {} and {}",
+                state.endpoint_string(),
+                ssrc,
+                original_source,
+                new_source
+            );
+        }
+        Some(StreamDiscontinuity::TtlVaried { source, previous_ttl, ttl }) => {
+            println!(
+                "[{}] Warning: TTL changed {} -> {} within the page (from {})",
+                state.endpoint_string(),
+                previous_ttl,
+                ttl,
+                source
+            );
+        }
+        Some(StreamDiscontinuity::NonstandardTimestamps { clock_rate, expected_clock_rate }) => {
+            println!(
+                "[{}] Warning: G.722 timestamps tick at {}Hz, not the {}Hz of RFC 3551; reading them as sent",
+                state.endpoint_string(),
+                clock_rate,
+                expected_clock_rate
+            );
+            if let Some(ref mut timeline) = state.packet_timeline {
+                timeline.set_clock_rate(clock_rate);
+            }
+        }
+        None => {}
+    }
+    state.last_packet = Some(packet.received_at);
+    // A colliding sender's audio would be mixed into the page's
+    if !state.stats.is_stream_source(packet.source) {
+        return Ok(());
+    }
+    if let Some(ref mut timeline) = state.packet_timeline {
+        timeline.record(packet.received_at, packet.header.timestamp);
+    }
 
-    // Decode, analyze, and record
-    if let Some(ref mut dec) = state.decoder {
-        if let Ok(samples) = dec.decode(&packet.payload) {
-            if let Some(ref mut analyzer) = state.audio_analyzer {
-                let analysis = analyzer.analyze(&samples);
-                state.audio_stats.update(&analysis, samples.len() as u64);
-                state.current_audio = analysis;
+    // A marked packet starts a new spurt, unless nothing has been recorded since the last one began
+    let spurt_started = state.stats.marked_packets > marked_before;
+    if spurt_started && state.spurt_recorder.as_ref().is_some_and(|rec| rec.samples_written() > 0) {
+        open_spurt_segment(state, options)?;
+    }
+
+    // Before anything can go wrong with the audio
+    if let Some(ref mut payloads) = state.payloads {
+        let header = &packet.header;
+        if let Err(e) = payloads.record(&packet.payload, header.sequence_number, header.timestamp, header.payload_type) {
+            println!(
+                "[{}:{}] Error writing {}: {}; the page's later payloads are not saved",
+                state.address,
+                state.port,
+                payloads.path().display(),
+                e
+            );
+        }
+    }
+
+    let Some(ref mut dec) = state.decoder else {
+        return Ok(());
+    };
+
+    if let Some(ref mut analyzer) = state.audio_analyzer {
+        skip_analysis(analyzer, &mut state.audio_stats, &state.stats, dec.sample_rate());
+    }
+
+    // Keep the recording on the RTP timeline across audio nobody sent (Opus DTX) or that was lost.
+    // A segment leaves out the silence before its spurt
+    let (concealed, silent_frames) = skipped_audio(dec.as_mut(), &state.stats);
+    let segment = state.spurt_recorder.as_mut().filter(|_| !spurt_started);
+    for rec in state.recorder.as_mut().into_iter().chain(segment) {
+        rec.write_samples(&concealed)?;
+        rec.write_silence(silent_frames)?;
+    }
+    if let Some(ref mut pipe) = state.audio_pipe {
+        pipe.push(&concealed);
+        pipe.push_silence(silent_frames);
+    }
+
+    // Audio cut from the end of the previous payload
+    let truncated_secs = state.stats.truncated_secs();
+    if truncated_secs > 0.0 {
+        match options.decode_error_policy.repair(dec.as_mut(), truncated_secs, || truncation_note(truncated_secs)) {
+            Ok(fill) => record_page_audio(state.recorder.as_mut(), state.spurt_recorder.as_mut(), state.audio_pipe.as_mut(), &fill)?,
+            Err(reason) => {
+                // The packet itself is intact and starts the next page
+                abort_test_page(state, reason)?;
+                return handle_test_packet(state, packet, options);
             }
+        }
+    }
+
+    // Where this packet's audio starts, now the gap before it is filled
+    if let (Some(timing), Some(rec)) = (state.timing.as_mut(), state.recorder.as_ref()) {
+        let offset = rec.samples_written() / u64::from(dec.channels().max(1));
+        timing.record(offset, arrival_time(packet.received_at), packet.header.sequence_number)?;
+    }
+
+    // Decode, analyze, and record
+    let decoded = dec.decode(&packet.payload);
+    state.stats.record_decode(packet.payload.len(), decoded.as_ref().ok().map(Vec::len));
+    let samples = match decoded {
+        Ok(samples) => samples,
+        Err(CodecError::BackendUnavailable { .. }) => return Ok(()),
+        Err(e) => {
+            return match options.decode_error_policy.repair(dec.as_mut(), state.stats.nominal_packet_secs(), || e.to_string()) {
+                Ok(fill) => record_page_audio(state.recorder.as_mut(), state.spurt_recorder.as_mut(), state.audio_pipe.as_mut(), &fill),
+                Err(reason) => abort_test_page(state, reason),
+            };
+        }
+    };
 
-            if let Some(ref mut rec) = state.recorder {
-                rec.write_samples(&samples)?;
+    if let Some(ref mut analyzer) = state.audio_analyzer {
+        let analysis = analyzer.analyze(&samples);
+        state.audio_stats.update(&analysis, samples.len() as u64);
+        state.current_audio = analysis;
+
+        if let Some(ref mut spectrum) = state.spectrum {
+            if state.last_spectrum.is_none_or(|t| t.elapsed() >= options.metrics_interval) {
+                spectrum.sample(analyzer)?;
+                state.last_spectrum = Some(Instant::now());
             }
         }
     }
+    record_page_audio(state.recorder.as_mut(), state.spurt_recorder.as_mut(), state.audio_pipe.as_mut(), &samples)
+}
 
+/// Write samples to the page's recording, its talk-spurt segment if there
+/// is one, and its `--pipe-audio-cmd`
+fn record_page_audio(
+    recorder: Option<&mut WavRecorder>,
+    segment: Option<&mut WavRecorder>,
+    pipe: Option<&mut AudioPipe>,
+    samples: &[i16],
+) -> Result<(), TestError> {
+    for rec in recorder.into_iter().chain(segment) {
+        rec.write_samples(samples)?;
+    }
+    if let Some(pipe) = pipe {
+        pipe.push(samples);
+    }
     Ok(())
 }
 
-fn handle_test_page_end(
-    state: &mut TestEndpointState,
-    _output_dir: &Path,
-) -> Result<(), TestError> {
+/// End the page early under `--on-decode-error abort-page`. The rest of
+/// the stream starts a new page
+fn abort_test_page(state: &mut TestEndpointState, reason: String) -> Result<(), TestError> {
+    println!("[{}] Page {} aborted: {}", state.endpoint_string(), state.page_count, reason);
+    state.interrupted = Some(reason);
+    handle_test_page_end(state)
+}
+
+/// End a page still active when the run stops (timeout, `--until` or
+/// Ctrl+C), marking it as cut short rather than idled out
+fn truncate_test_page(state: &mut TestEndpointState) -> Result<(), TestError> {
+    state.truncated = true;
+    handle_test_page_end(state)
+}
+
+fn handle_test_page_end(state: &mut TestEndpointState) -> Result<(), TestError> {
+    // FFTs still on the analysis pool belong in the page's figures
+    if let Some(ref mut analyzer) = state.audio_analyzer {
+        analyzer.finish(&mut state.audio_stats);
+    }
+
     let duration = match (state.page_start, state.last_packet) {
-        (Some(start), Some(last)) => last.duration_since(start).as_secs_f64(),
+        (Some(start), Some(last)) => last.duration_since(start).as_secs_f64() + state.stats.last_packet_secs(),
         (Some(start), None) => start.elapsed().as_secs_f64(),
         _ => 0.0,
     };
 
-    let end_time = Utc::now();
-    let start_time = state.page_start_utc.unwrap_or(end_time);
+    // The page ends with its last packet's audio, however long after that
+    // it was noticed, so the end is the start plus the duration
+    let start_time = state.page_start_utc.unwrap_or_else(Utc::now);
+    let end_time = ClockWatch::reconcile(start_time, Duration::from_secs_f64(duration));
 
-    let filename = format!(
-        "page_{:04}_{}_{}.wav",
-        state.page_count,
-        state.address.to_string().replace('.', "_"),
-        state.port
-    );
+    let filename = state.recording_file.take().unwrap_or_default();
 
     println!(
         "[{}] Page {} ended (duration: {:.1}s, glitches: {})",
@@ -618,104 +2099,1066 @@ fn handle_test_page_end(
         duration,
         state.audio_stats.total_glitches
     );
+    if state.truncated {
+        println!("[{}] Page {} truncated: the run ended mid-page", state.endpoint_string(), state.page_count);
+    }
+    let payload_sizes = state.stats.payload_sizes();
+    if let Some(anomaly) = payload_sizes.anomaly() {
+        println!("[{}] Page {} payload sizes: {}", state.endpoint_string(), state.page_count, anomaly);
+    }
+    if let Some(avg) = state.stats.avg_padding_bytes() {
+        println!(
+            "[{}] Page {} padding: {} packets, {:.1} bytes on average",
+            state.endpoint_string(),
+            state.page_count,
+            state.stats.padded_packets,
+            avg
+        );
+    }
+    if let Some(mark) = state.audio_stats.watermark() {
+        println!(
+            "[{}] Page {} watermark: ID {} (confidence {:.2})",
+            state.endpoint_string(),
+            state.page_count,
+            mark.id,
+            mark.confidence
+        );
+    }
+    if let Some(chime) = state.audio_stats.chime() {
+        println!("[{}] Page {} chime: builtin:{}", state.endpoint_string(), state.page_count, chime.name());
+    }
+    if let Some(split) = state.audio_stats.band_split().filter(|split| split.suspect_narrowband(&state.transcoding)) {
+        println!(
+            "[{}] Page {} looks narrowband despite a wideband codec: {:.0} dB above 4 kHz, noise floor {:.1} dB down",
+            state.endpoint_string(),
+            state.page_count,
+            split.high_band_db,
+            split.noise_floor_snr_db
+        );
+    }
+    if state.stats.decode_errors > 0 || state.stats.short_decodes > 0 || state.stats.truncated_payloads > 0 {
+        println!(
+            "[{}] Page {}: {} packets failed to decode, {} decoded short, {} truncated in transit",
+            state.endpoint_string(),
+            state.page_count,
+            state.stats.decode_errors,
+            state.stats.short_decodes,
+            state.stats.truncated_payloads
+        );
+    }
+
+    if state.packet_timeline.as_ref().is_some_and(PacketTimeline::is_truncated) {
+        println!(
+            "[{}] Page {} too long to simulate in full; jitter buffer analysis covers its first hour",
+            state.endpoint_string(),
+            state.page_count
+        );
+    }
 
-    // Finalize recording
+    // The recording is finished and fingerprinted off the loop, and the
+    // jitter buffers simulated with it; both follow the page into
+    // pages.jsonl
+    let mut recording_files = BTreeMap::new();
     if let Some(rec) = state.recorder.take() {
+        let pending = PendingRecording::new(SocketAddrV4::new(state.address, state.port), state.page_count, rec)
+            .with_segment(state.spurt_recorder.take())
+            .with_timing(state.timing.take())
+            .with_payloads(state.payloads.take())
+            .with_pipe(state.audio_pipe.take())
+            .with_timeline(state.packet_timeline.take());
+        let subdir = state.output_subdir();
+        recording_files = pending
+            .files()
+            .into_iter()
+            .filter_map(|(kind, path)| Some((kind, format!("{}/{}", subdir, path.file_name()?.to_string_lossy()))))
+            .collect();
+        state.finalizing.push(pending);
+    }
+    if let Some(timing) = state.timing.take() {
+        timing.finish()?;
+    }
+    if let Some(payloads) = state.payloads.take() {
+        payloads.finish()?;
+    }
+    if let Some(rec) = state.spurt_recorder.take() {
         rec.finalize()?;
     }
+    let spurts = state.stats.spurts().to_vec();
+    if spurts.len() > 1 {
+        println!(
+            "[{}] Page {}: {} talk-spurts",
+            state.endpoint_string(),
+            state.page_count,
+            spurts.len()
+        );
+    }
+
+    // Without a recording there's nothing to hand the timeline on with
+    let jitter_buffer_analysis = state.packet_timeline.take().map(|timeline| timeline.analyze());
+
+    let mos_breakdown = state.decoder.as_ref().map(|decoder| {
+        quality::estimate(
+            decoder.codec_type(),
+            &QualityInputs::from_page(&state.stats, &state.audio_stats, duration),
+        )
+    });
+
+    let repair = state.repair.as_mut().map(RepairBuffer::take_summary);
+    if let Some(repair) = repair {
+        println!(
+            "[{}] Page {}: {} lost packets repaired, {} unrepaired",
+            state.endpoint_string(),
+            state.page_count,
+            repair.packets_repaired,
+            repair.packets_unrepaired
+        );
+    }
 
     // Create page summary
     let page_summary = PageSummary {
         page_number: state.page_count,
+        page_id: Some(state.page_id),
         endpoint: state.endpoint_string(),
         start_time,
         end_time,
         duration_secs: duration,
         recording_file: filename,
+        recording_files,
+        integrity: None,
+        pipe_output: None,
+        sender_fingerprint: state.fingerprint.take(),
         network: NetworkSummary {
             packets_received: state.stats.packets_received,
             bytes_received: state.stats.bytes_received,
             packets_lost: state.stats.packets_lost,
             loss_percent: state.stats.loss_percent(),
             jitter_ms: state.stats.jitter_ms,
+            resets: state.stats.resets,
+            ssrc_collisions: state.stats.ssrc_collisions,
+            max_gap_ms: state.stats.max_gap_ms,
+            gap_histogram: state.stats.gap_histogram,
+            continuity_percent: Some(state.stats.continuity_percent(duration)),
+            ttl: state.stats.ttl(),
+            dscp: state.stats.dscp(state.expect_dscp),
+            dtx_gap_secs: state.stats.dtx_gap_secs,
+            clock_drift_ms: state.stats.clock_drift_ms(),
+            payload_sizes,
+            decode_errors: state.stats.decode_errors,
+            short_decodes: state.stats.short_decodes,
+            truncated_payloads: state.stats.truncated_payloads,
+            padded_packets: state.stats.padded_packets,
+            avg_padding_bytes: state.stats.avg_padding_bytes(),
+            repair,
         },
-        audio: AudioSummary {
-            peak_rms_db: state.audio_stats.peak_rms_db,
-            avg_rms_db: if state.audio_stats.avg_rms_db.is_finite() {
-                Some(state.audio_stats.avg_rms_db)
-            } else {
-                None
-            },
-            max_peak_db: state.audio_stats.max_peak_db,
-            dominant_freq_hz: state.audio_stats.dominant_freq_hz,
-            total_glitches: state.audio_stats.total_glitches,
-            total_clipped: state.audio_stats.total_clipped,
-            clipping_percent: state.audio_stats.clipping_percent(),
-            avg_zero_crossing_rate: state.audio_stats.avg_zero_crossing_rate,
-        },
+        audio: AudioSummary::new(&state.audio_stats, &state.classifier, &state.transcoding),
+        mos_estimate: mos_breakdown.as_ref().map(MosBreakdown::mos),
+        mos_breakdown,
+        jitter_buffer_analysis: jitter_buffer_analysis.unwrap_or_default(),
+        marked_packets: state.stats.marked_packets,
+        spurts,
+        spurt_files: std::mem::take(&mut state.spurt_files),
+        ssrc_changes: std::mem::take(&mut state.ssrc_changes),
+        error: state.interrupted.take(),
+        truncated: state.truncated,
+        clock_adjusted: state.clock_stepped,
     };
 
-    state.completed_pages.push(page_summary);
+    state.ended_pages.push(page_summary);
     state.reset_page();
 
     Ok(())
 }
 
+/// Move pages ended since the last call out of memory: into the page log,
+/// the trend aggregate and the endpoints' running totals
+fn flush_ended_pages(
+    page_log: &mut PageLog,
+    mut trends: Option<&mut TrendWriter>,
+    endpoint_states: &mut HashMap<(Ipv4Addr, u16), TestEndpointState>,
+    errors: &mut ErrorLog,
+) {
+    for state in endpoint_states.values_mut() {
+        for page in state.ended_pages.drain(..) {
+            if let Err(e) = page_log.write_page(&page) {
+                errors.push(format!("Error writing {}: {}", page_log.path(&page.endpoint).display(), e));
+            }
+            if let Some(ref mut trends) = trends {
+                if let Err(e) = trends.record(&page) {
+                    errors.push(format!("Error updating {}: {}", trends.path().display(), e));
+                }
+            }
+            state.totals.add(&page);
+        }
+    }
+}
+
+/// Hand ended pages' recordings to the finalize queue, and log the
+/// fingerprints of those it has finished
+fn finalize_test_recordings(
+    queue: &mut FinalizeQueue,
+    page_log: &mut PageLog,
+    endpoint_states: &mut HashMap<(Ipv4Addr, u16), TestEndpointState>,
+    errors: &mut ErrorLog,
+) {
+    for state in endpoint_states.values_mut() {
+        for pending in state.finalizing.drain(..) {
+            queue.push(pending);
+        }
+    }
+    for finished in queue.poll() {
+        log_finalized(finished, page_log, errors);
+    }
+}
+
+/// Log a finished recording's fingerprint and jitter buffer analysis, or
+/// why it couldn't be finished
+fn log_finalized(finished: FinishedRecording, page_log: &mut PageLog, errors: &mut ErrorLog) {
+    for error in finished.file_errors {
+        println!("[{}] Unable to finalize {}", finished.endpoint, error);
+        errors.push(format!("Error finalizing page {} on {}: {}", finished.page, finished.endpoint, error));
+    }
+    if let Some(pipe) = finished.pipe_output.as_ref().filter(|pipe| !pipe.succeeded()) {
+        let exit = if pipe.timed_out {
+            "timed out".to_string()
+        } else {
+            pipe.exit_code.map_or_else(|| "was killed".to_string(), |code| format!("exited with {}", code))
+        };
+        println!("[{}] Page {}: `{}` {}", finished.endpoint, finished.page, pipe.command, exit);
+    }
+    let integrity = match finished.result {
+        Ok(integrity) => Some(integrity),
+        Err(e) => {
+            println!("[{}] Unable to finalize {}: {}", finished.endpoint, finished.path.display(), e);
+            errors.push(format!("Error finalizing page {} on {}: {}", finished.page, finished.endpoint, e));
+            None
+        }
+    };
+    // The jitter buffer analysis stands without the recording
+    if integrity.is_none() && finished.jitter_buffer_analysis.is_none() {
+        return;
+    }
+    let finalized = RecordingFinalized {
+        endpoint: finished.endpoint.to_string(),
+        page_number: finished.page,
+        integrity,
+        pipe_output: finished.pipe_output,
+        jitter_buffer_analysis: finished.jitter_buffer_analysis,
+    };
+    if let Err(e) = page_log.write_finalized(&finalized) {
+        errors.push(format!("Error writing {}: {}", page_log.path(&finalized.endpoint).display(), e));
+    }
+}
+
+/// Check, before the summary is written, that every file the pages name
+/// is there, and that each recording was fingerprinted at its present size.
+/// Hashes aren't taken again; `review --verify` does that. Returns what
+/// doesn't add up, for the summary's errors
+fn check_recordings(output_dir: &Path, pages: &[PageSummary]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for page in pages.iter().filter(|page| !page.recording_file.is_empty()) {
+        let label = format!("Page {} on {}", page.page_number, page.endpoint);
+        let files = page.recording_files.values().chain(&page.spurt_files);
+        for file in std::iter::once(&page.recording_file).chain(files).collect::<BTreeSet<_>>() {
+            if !output_dir.join(file).exists() {
+                problems.push(format!("{}: {} is missing", label, file));
+            }
+        }
+        let Ok(metadata) = fs::metadata(output_dir.join(&page.recording_file)) else {
+            continue;
+        };
+        match page.integrity {
+            None => problems.push(format!("{}: {} has no fingerprint", label, page.recording_file)),
+            Some(ref integrity) if integrity.file_size != metadata.len() => problems.push(format!(
+                "{}: {} is {} bytes but was fingerprinted at {}",
+                label,
+                page.recording_file,
+                metadata.len(),
+                integrity.file_size
+            )),
+            Some(_) => {}
+        }
+    }
+    problems
+}
+
+/// What an endpoint is holding that grows with traffic
+#[derive(Debug, PartialEq, Eq)]
+struct EndpointMemory {
+    freq_bins: usize,
+    held_packets: usize,
+    timeline_packets: usize,
+    unflushed_pages: usize,
+}
+
+impl TestEndpointState {
+    fn memory(&self) -> EndpointMemory {
+        EndpointMemory {
+            freq_bins: self.audio_stats.freq_bin_count(),
+            held_packets: self.pending.held(),
+            timeline_packets: self.packet_timeline.as_ref().map_or(0, PacketTimeline::recorded),
+            unflushed_pages: self.ended_pages.len(),
+        }
+    }
+}
+
+/// Log accumulator sizes for `--memory-report`
+fn report_memory(
+    endpoint_states: &HashMap<(Ipv4Addr, u16), TestEndpointState>,
+    page_log: &PageLog,
+    errors: &ErrorLog,
+) {
+    let mut states: Vec<&TestEndpointState> = endpoint_states.values().collect();
+    states.sort_by_key(|state| (state.address, state.port));
+    for state in states {
+        let memory = state.memory();
+        info!(
+            "Memory {}: {} frequency bins, {} held packets, {} timeline packets, {} unflushed pages",
+            state.endpoint_string(),
+            memory.freq_bins,
+            memory.held_packets,
+            memory.timeline_packets,
+            memory.unflushed_pages
+        );
+    }
+    info!(
+        "Memory: {} endpoints, {} pages logged, {} distinct errors",
+        endpoint_states.len(),
+        page_log.pages_written,
+        errors.len()
+    );
+}
+
+/// The most files and sockets the test can have open at once: a socket per
+/// endpoint, and every endpoint recording a page
+fn fd_plan(options: &TestOptions, endpoints: usize) -> FdPlan {
+    let files_per_page = 1
+        + u64::from(options.split_spurts)
+        + u64::from(options.timing_file.is_some())
+        + 2 * u64::from(options.record_payloads)
+        + 2 * u64::from(options.pipe_audio_cmd.is_some())
+        + u64::from(options.spectrum_dir.is_some());
+    // Metrics, the page log and annotations, besides the optional ones
+    let extra = 3 + [
+        options.pcap.is_some(),
+        options.aggregate.is_some(),
+        options.control_socket.is_some(),
+        options.repair_window.is_some(),
+    ]
+    .into_iter()
+    .filter(|&open| open)
+    .count() as u64;
+    FdPlan::new(endpoints, endpoints, files_per_page, extra)
+}
+
+/// Open one group-bound socket per endpoint, collecting the ones that fail
+async fn open_test_sockets(
+    endpoints: &[MulticastEndpoint],
+    interface: Option<MulticastInterface>,
+    busy_poll_us: Option<u32>,
+) -> (HashMap<(Ipv4Addr, u16), MulticastSocket>, Vec<UnavailableEndpoint>) {
+    let interface = interface.unwrap_or_default();
+    let mut sockets = HashMap::new();
+    let mut unavailable = Vec::new();
+
+    for ep in endpoints {
+        match MulticastSocket::bound_to_group(ep.address, ep.port, interface).await {
+            Ok(socket) => {
+                socket.apply_busy_poll(busy_poll_us);
+                sockets.insert((ep.address, ep.port), socket);
+            }
+            Err(e) => unavailable.push(UnavailableEndpoint {
+                endpoint: ep.to_string(),
+                reason: e.to_string(),
+            }),
+        }
+    }
+
+    (sockets, unavailable)
+}
+
 fn generate_summary(
     options: &TestOptions,
     start_time: DateTime<Utc>,
     end_time: DateTime<Utc>,
     endpoint_states: &HashMap<(Ipv4Addr, u16), TestEndpointState>,
-    errors: Vec<String>,
+    mut all_pages: Vec<PageSummary>,
+    unavailable_endpoints: Vec<UnavailableEndpoint>,
+    errors: ErrorLog,
 ) -> TestSummary {
     let duration_secs = (end_time - start_time).num_milliseconds() as f64 / 1000.0;
 
-    // Collect all pages
-    let mut all_pages: Vec<PageSummary> = Vec::new();
-    let mut endpoint_totals: HashMap<String, EndpointTotal> = HashMap::new();
-
-    for state in endpoint_states.values() {
-        let endpoint_key = state.endpoint_string();
-
-        // Add completed pages
-        all_pages.extend(state.completed_pages.clone());
-
-        // Calculate totals
-        let total = endpoint_totals.entry(endpoint_key).or_default();
-        total.pages_detected = state.completed_pages.len() as u32;
-        total.total_duration_secs = state.completed_pages.iter()
-            .map(|p| p.duration_secs)
-            .sum();
-        total.total_packets = state.completed_pages.iter()
-            .map(|p| p.network.packets_received)
-            .sum();
-        total.total_bytes = state.completed_pages.iter()
-            .map(|p| p.network.bytes_received)
-            .sum();
-    }
+    let endpoint_totals: HashMap<String, EndpointTotal> = endpoint_states
+        .values()
+        .map(|state| {
+            let total = EndpointTotal {
+                pages_detected: state.totals.pages,
+                total_duration_secs: state.totals.duration_secs,
+                total_packets: state.totals.packets,
+                total_bytes: state.totals.bytes,
+                suppressed_bursts: state.pending.suppressed,
+                filtered_packets: state.filtered_packets,
+                mos_estimate: state.totals.mos.mean(),
+            };
+            (state.endpoint_string(), total)
+        })
+        .collect();
 
     // Sort pages by start time
-    all_pages.sort_by(|a, b| a.start_time.cmp(&b.start_time));
+    all_pages.sort_by_key(|p| p.start_time);
 
     TestSummary {
         test_metadata: TestMetadata {
+            schema_version: SCHEMA_VERSION,
             start_time,
             end_time,
             duration_secs,
             pattern: options.pattern.clone(),
             endpoints_monitored: endpoint_states.len(),
             metrics_interval_ms: options.metrics_interval.as_millis() as u64,
-            timeout_secs: options.timeout.as_secs(),
+            timeout_secs: options.timeout.planned_secs(start_time),
+            timeout_mode: options.timeout.mode(),
+            until: options.timeout.until(),
+            end_reason: None,
+            settings: options.settings.clone(),
+            config_file: options.config_file.clone(),
+            site: options.naming.site.clone(),
+            site_name: options.site_name.clone(),
+            endpoint_overrides: endpoint_states
+                .values()
+                .filter(|state| !state.overrides.is_empty())
+                .map(|state| (state.endpoint_string(), state.overrides))
+                .collect(),
+            ssrc_filter: options.ssrc_filter.record(),
+            clock_step_secs: None,
+            finalization_secs: None,
+            igmp: None,
+            anonymization: None,
         },
         pages: all_pages,
         endpoint_totals,
-        errors,
+        unavailable_endpoints,
+        path_comparisons: Vec::new(),
+        errors: errors.into_messages(),
     }
 }
 
-fn write_summary(output_dir: &Path, summary: &TestSummary) -> io::Result<()> {
+pub(crate) fn write_summary(output_dir: &Path, summary: &TestSummary) -> io::Result<()> {
     let path = output_dir.join("summary.json");
     let file = File::create(path)?;
     serde_json::to_writer_pretty(file, summary)
         .map_err(io::Error::other)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(output_dir: &Path) -> TestOptions {
+        TestOptions {
+            pattern: "@endpoints.txt".to_string(),
+            default_port: 15020,
+            interface: None,
+            codec: None,
+            output_dir: output_dir.to_path_buf(),
+            timeout: Timeout::For(Duration::from_millis(300)),
+            metrics_interval: Duration::from_millis(100),
+            control_socket: None,
+            spectrum_dir: None,
+            pcap: None,
+            aggregate: None,
+            page_threshold: PageThreshold::default(),
+            stitch_gap: None,
+            ssrc_filter: SsrcFilter::default(),
+            busy_poll_us: None,
+            analysis_threads: None,
+            naming: Naming::default(),
+            memory_report: None,
+            split_spurts: false,
+            decode_error_policy: DecodeErrorPolicy::default(),
+            repair_window: None,
+            compare_endpoints: Vec::new(),
+            expect_dscp: None,
+            timing_file: None,
+            record_payloads: false,
+            pipe_audio_cmd: None,
+            classifier: ClassifierThresholds::default(),
+            transcoding: TranscodingThresholds::default(),
+            site_name: None,
+            config_file: None,
+            settings: Vec::new(),
+            max_file_descriptors: None,
+            g722_nonstandard_timestamps: false,
+            observe_igmp: false,
+        }
+    }
+
+    #[test]
+    fn test_error_log_folds_repeats() {
+        let mut errors = ErrorLog::default();
+        for _ in 0..500 {
+            errors.push("Receive error on 224.0.1.1:5004: connection refused".to_string());
+        }
+        errors.push("Error writing metrics: disk full".to_string());
+        for i in 0..MAX_DISTINCT_ERRORS + 5 {
+            errors.push(format!("distinct {}", i));
+        }
+
+        let messages = errors.into_messages();
+        assert_eq!(messages.len(), MAX_DISTINCT_ERRORS + 1);
+        assert_eq!(
+            messages[0],
+            "Receive error on 224.0.1.1:5004: connection refused (repeated 500 times)"
+        );
+        assert_eq!(messages[1], "Error writing metrics: disk full");
+        assert_eq!(messages.last().unwrap(), "7 further error(s) suppressed");
+    }
+
+    #[tokio::test]
+    async fn test_join_failure_keeps_other_endpoints() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = options(dir.path());
+        // A unicast address can't be joined; the multicast one still runs
+        let endpoints = ResolvedEndpoints {
+            endpoints: vec![
+                MulticastEndpoint { address: Ipv4Addr::new(10, 0, 0, 1), port: 15020 },
+                MulticastEndpoint { address: Ipv4Addr::new(224, 0, 123, 10), port: 15020 },
+            ],
+            ..ResolvedEndpoints::default()
+        };
+
+        run_test_endpoints(&options, &endpoints).await.unwrap();
+
+        let summary: TestSummary =
+            serde_json::from_str(&fs::read_to_string(dir.path().join("summary.json")).unwrap()).unwrap();
+        assert_eq!(summary.unavailable_endpoints.len(), 1);
+        assert_eq!(summary.unavailable_endpoints[0].endpoint, "10.0.0.1:15020");
+        assert!(summary.unavailable_endpoints[0].reason.contains("not a valid multicast address"));
+        assert_eq!(summary.test_metadata.endpoints_monitored, 1);
+        assert!(summary.endpoint_totals.contains_key("224.0.123.10:15020"));
+        assert_eq!(summary.errors.len(), 1);
+        assert!(dir.path().join("metrics.jsonl").exists());
+        assert_eq!(summary.test_metadata.timeout_mode, TimeoutMode::For);
+        assert_eq!(summary.test_metadata.end_reason, Some(EndReason::Timeout));
+    }
+
+    #[tokio::test]
+    async fn test_runs_until_end_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let until = Utc::now() + chrono::Duration::milliseconds(400);
+        let options = TestOptions {
+            timeout: Timeout::Until(until),
+            ..options(dir.path())
+        };
+        let endpoints = ResolvedEndpoints {
+            endpoints: vec![MulticastEndpoint { address: Ipv4Addr::new(224, 0, 123, 41), port: 15311 }],
+            ..ResolvedEndpoints::default()
+        };
+
+        let started = Instant::now();
+        run_test_endpoints(&options, &endpoints).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+        assert!(Utc::now() >= until);
+
+        let summary: TestSummary =
+            serde_json::from_str(&fs::read_to_string(dir.path().join("summary.json")).unwrap()).unwrap();
+        let metadata = &summary.test_metadata;
+        assert_eq!(metadata.timeout_mode, TimeoutMode::Until);
+        assert_eq!(metadata.until, Some(until));
+        assert_eq!(metadata.end_reason, Some(EndReason::Until));
+        assert!(metadata.end_time >= until);
+    }
+
+    #[tokio::test]
+    async fn test_no_usable_endpoints_still_writes_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = options(dir.path());
+        let endpoints = ResolvedEndpoints {
+            endpoints: vec![MulticastEndpoint { address: Ipv4Addr::new(10, 0, 0, 1), port: 15021 }],
+            ..ResolvedEndpoints::default()
+        };
+
+        let result = run_test_endpoints(&options, &endpoints).await;
+        assert!(matches!(result, Err(TestError::NoUsableEndpoints(1))));
+
+        let summary: TestSummary =
+            serde_json::from_str(&fs::read_to_string(dir.path().join("summary.json")).unwrap()).unwrap();
+        assert_eq!(summary.unavailable_endpoints.len(), 1);
+        assert!(summary.pages.is_empty());
+        assert_eq!(summary.test_metadata.end_reason, Some(EndReason::NoUsableEndpoints));
+    }
+
+    /// Finish ended pages' recordings in place of the finalize queue
+    fn finish_recordings(state: &mut TestEndpointState) {
+        for pending in state.finalizing.drain(..) {
+            pending.finish().result.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_memory_flat_across_many_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = options(dir.path());
+        let mut page_log = PageLog::new(dir.path());
+        let mut errors = ErrorLog::default();
+        let key = (Ipv4Addr::new(224, 0, 123, 11), 15020);
+        let mut endpoint_states = HashMap::from([(key, TestEndpointState::new(key.0, key.1))]);
+        let source = "192.168.1.10:5004".parse().unwrap();
+        let base = Instant::now();
+
+        // Short pages of noise, each a new SSRC, which ends the page before
+        let mut noise = 0x1234_5678u32;
+        let mut usage_after_first_thousand = None;
+        for page in 0..10_000u32 {
+            for seq in 0..5u16 {
+                let payload: Vec<u8> = (0..160)
+                    .map(|_| {
+                        noise ^= noise << 13;
+                        noise ^= noise >> 17;
+                        noise ^= noise << 5;
+                        noise.to_le_bytes()[0]
+                    })
+                    .collect();
+                let data = RtpPacket::build(0, seq, u32::from(seq) * 160, page + 1, &payload, false);
+                let arrival = base + Duration::from_millis(u64::from(page) * 100 + u64::from(seq) * 20);
+                let packet = RtpPacket::parse_with_time(&data, source, arrival).unwrap();
+                handle_test_packet(endpoint_states.get_mut(&key).unwrap(), &packet, &options).unwrap();
+            }
+            flush_ended_pages(&mut page_log, None, &mut endpoint_states, &mut errors);
+            finish_recordings(endpoint_states.get_mut(&key).unwrap());
+
+            let state = &endpoint_states[&key];
+            assert!(state.memory().freq_bins <= 64);
+            assert_eq!(state.memory().unflushed_pages, 0);
+            if page == 1000 {
+                usage_after_first_thousand = Some(state.memory());
+            }
+        }
+
+        let state = &endpoint_states[&key];
+        assert_eq!(Some(state.memory()), usage_after_first_thousand);
+        // The last page is still in progress
+        assert_eq!(state.totals.pages, 9_999);
+        assert_eq!(page_log.pages_written, 9_999);
+        assert_eq!(errors.len(), 0);
+        assert_eq!(page_log.read_pages().unwrap().len(), 9_999);
+    }
+
+    #[tokio::test]
+    async fn test_post_processing_merged_as_pages_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = options(dir.path());
+        let mut page_log = PageLog::new(dir.path());
+        let mut errors = ErrorLog::default();
+        let mut finalize = FinalizeQueue::new(FINALIZE_CONCURRENCY);
+        let key = (Ipv4Addr::new(224, 0, 123, 15), 15020);
+        let mut endpoint_states = HashMap::from([(key, TestEndpointState::new(key.0, key.1))]);
+        let source = "192.168.1.10:5004".parse().unwrap();
+        let base = Instant::now();
+
+        // Many short pages, each a new SSRC, handed to the queue as they
+        // end, as the receive loop does
+        for page in 0..200u32 {
+            for seq in 0..10u16 {
+                let data = RtpPacket::build(0, seq, u32::from(seq) * 160, page + 1, &[0x10; 160], false);
+                let arrival = base + Duration::from_millis(u64::from(page) * 250 + u64::from(seq) * 20);
+                let packet = RtpPacket::parse_with_time(&data, source, arrival).unwrap();
+                handle_test_packet(endpoint_states.get_mut(&key).unwrap(), &packet, &options).unwrap();
+            }
+            flush_ended_pages(&mut page_log, None, &mut endpoint_states, &mut errors);
+            finalize_test_recordings(&mut finalize, &mut page_log, &mut endpoint_states, &mut errors);
+        }
+
+        // The end of the run only has the last pages left to wait for
+        let finalization_start = Instant::now();
+        handle_test_page_end(endpoint_states.get_mut(&key).unwrap()).unwrap();
+        flush_ended_pages(&mut page_log, None, &mut endpoint_states, &mut errors);
+        finalize_test_recordings(&mut finalize, &mut page_log, &mut endpoint_states, &mut errors);
+        for finished in finalize.drain().await {
+            log_finalized(finished, &mut page_log, &mut errors);
+        }
+        let pages = page_log.read_pages().unwrap();
+        let problems = check_recordings(dir.path(), &pages);
+        let elapsed = finalization_start.elapsed();
+        assert!(elapsed < Duration::from_secs(5), "finalizing took {:?}", elapsed);
+
+        assert_eq!(pages.len(), 200);
+        assert_eq!(errors.len(), 0);
+        assert!(problems.is_empty(), "{:?}", problems);
+        for page in &pages {
+            assert!(page.integrity.is_some(), "page {} has no fingerprint", page.page_number);
+            assert!(!page.jitter_buffer_analysis.is_empty(), "page {} has no jitter buffer analysis", page.page_number);
+            assert_eq!(page.jitter_buffer_analysis[0].late_packets, 0);
+        }
+
+        // A recording gone, and one changed since it was fingerprinted
+        fs::remove_file(dir.path().join(&pages[0].recording_file)).unwrap();
+        fs::OpenOptions::new().append(true).open(dir.path().join(&pages[1].recording_file)).unwrap().write_all(&[0; 4]).unwrap();
+        let problems = check_recordings(dir.path(), &pages);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].starts_with("Page 1 on 224.0.123.15:15020:") && problems[0].ends_with("is missing"), "{}", problems[0]);
+        assert!(problems[1].contains("was fingerprinted at"), "{}", problems[1]);
+    }
+
+    #[test]
+    fn test_overlapping_pages_get_run_wide_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = options(dir.path());
+        let mut page_log = PageLog::new(dir.path());
+        let mut errors = ErrorLog::default();
+        let page_ids = PageIds::default();
+        let (a, b) = ((Ipv4Addr::new(224, 0, 123, 11), 15020), (Ipv4Addr::new(224, 0, 123, 12), 15020));
+        let mut endpoint_states: HashMap<_, _> = [a, b]
+            .into_iter()
+            .map(|key| (key, TestEndpointState::new(key.0, key.1).with_page_ids(page_ids.clone())))
+            .collect();
+        let source = "192.168.1.10:5004".parse().unwrap();
+        let base = Instant::now();
+
+        // Both endpoints start their first page together; a new SSRC on
+        // the first then ends its page and starts a second
+        for (i, (key, ssrc)) in [(a, 1), (b, 2), (a, 3)].into_iter().enumerate() {
+            for seq in 0..5u16 {
+                let data = RtpPacket::build(0, seq, u32::from(seq) * 160, ssrc, &[0x10; 160], false);
+                let arrival = base + Duration::from_millis(i as u64 * 10 + u64::from(seq) * 20);
+                let packet = RtpPacket::parse_with_time(&data, source, arrival).unwrap();
+                handle_test_packet(endpoint_states.get_mut(&key).unwrap(), &packet, &options).unwrap();
+            }
+        }
+        for state in endpoint_states.values_mut() {
+            handle_test_page_end(state).unwrap();
+            finish_recordings(state);
+        }
+        flush_ended_pages(&mut page_log, None, &mut endpoint_states, &mut errors);
+
+        let pages = page_log.read_pages().unwrap();
+        let ids: Vec<_> = pages.iter().map(|page| (page.endpoint.as_str(), page.page_number, page.page_id)).collect();
+        assert_eq!(
+            ids,
+            [("224.0.123.11:15020", 1, Some(1)), ("224.0.123.12:15020", 1, Some(2)), ("224.0.123.11:15020", 2, Some(3))]
+        );
+        for page in &pages {
+            let subdir = endpoint_dir(&page.endpoint);
+            assert!(page.recording_file.starts_with(&format!("{}/", subdir)), "{}", page.recording_file);
+            assert!(dir.path().join(&page.recording_file).exists());
+        }
+
+        // Each endpoint keeps a log of its own pages
+        let logged = |endpoint: &str| fs::read_to_string(page_log.path(endpoint)).unwrap().lines().count();
+        assert_eq!(logged("224.0.123.11:15020"), 2);
+        assert_eq!(logged("224.0.123.12:15020"), 1);
+        assert_eq!(errors.len(), 0);
+
+        // `--page 1` is the first page of the run, and with an endpoint its own first
+        let summary = generate_summary(&options, Utc::now(), Utc::now(), &endpoint_states, pages, Vec::new(), errors);
+        assert_eq!(summary.find_page(3, None).map(|page| page.page_number), Some(2));
+        assert_eq!(summary.find_page(1, Some("224.0.123.12:15020")).and_then(|page| page.page_id), Some(2));
+        assert!(summary.find_page(2, Some("224.0.123.12:15020")).is_none());
+    }
+
+    #[test]
+    fn test_ssrc_filter_keeps_only_allowed_stream() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = TestOptions { ssrc_filter: SsrcFilter::new(vec![0xa11c_e000], Vec::new()), ..options(dir.path()) };
+        let mut page_log = PageLog::new(dir.path());
+        let mut errors = ErrorLog::default();
+        let (a, b) = ((Ipv4Addr::new(224, 0, 123, 13), 15020), (Ipv4Addr::new(224, 0, 123, 14), 15020));
+        let mut endpoint_states: HashMap<_, _> = [a, b].into_iter().map(|key| (key, TestEndpointState::new(key.0, key.1))).collect();
+        let (mine, theirs) = ("192.168.1.10:5004".parse().unwrap(), "192.168.1.20:5004".parse().unwrap());
+        let base = Instant::now();
+
+        // Two engineers page the first group at once, the other one's
+        // stream starting first; the allowed stream also reaches the second
+        for seq in 0..50u16 {
+            let arrival = base + Duration::from_millis(u64::from(seq) * 20);
+            let streams = [(a, 0xb0b0_0000, theirs), (a, 0xa11c_e000, mine), (b, 0xa11c_e000, mine)];
+            for (key, ssrc, source) in streams {
+                let data = RtpPacket::build(0, seq, u32::from(seq) * 160, ssrc, &[0x10; 160], false);
+                let packet = RtpPacket::parse_with_time(&data, source, arrival).unwrap();
+                receive_test_packet(endpoint_states.get_mut(&key).unwrap(), packet, &options).unwrap();
+            }
+        }
+        for state in endpoint_states.values_mut() {
+            handle_test_page_end(state).unwrap();
+            finish_recordings(state);
+        }
+        flush_ended_pages(&mut page_log, None, &mut endpoint_states, &mut errors);
+
+        let pages = page_log.read_pages().unwrap();
+        assert_eq!(pages.len(), 2);
+        for page in &pages {
+            assert_eq!(page.sender_fingerprint.as_ref().map(|fingerprint| fingerprint.ssrc), Some(0xa11c_e000));
+            assert_eq!(page.network.packets_received, 50);
+            assert!(dir.path().join(&page.recording_file).exists());
+        }
+
+        let summary = generate_summary(&options, Utc::now(), Utc::now(), &endpoint_states, pages, Vec::new(), errors);
+        assert_eq!(summary.endpoint_totals["224.0.123.13:15020"].filtered_packets, 50);
+        assert_eq!(summary.endpoint_totals["224.0.123.13:15020"].total_packets, 50);
+        assert_eq!(summary.endpoint_totals["224.0.123.14:15020"].filtered_packets, 0);
+        assert_eq!(summary.test_metadata.ssrc_filter, Some(options.ssrc_filter.clone()));
+    }
+
+    #[test]
+    fn test_split_spurts_writes_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = TestOptions {
+            split_spurts: true,
+            ..options(dir.path())
+        };
+        let mut state = TestEndpointState::new(Ipv4Addr::new(224, 0, 123, 11), 15020);
+        let source = "192.168.1.10:5004".parse().unwrap();
+        let base = Instant::now();
+
+        // 10 frames, 1s of silence, then 5 frames
+        for seq in 0..15u16 {
+            let (ts, arrival_ms) = if seq < 10 {
+                (u32::from(seq) * 160, u64::from(seq) * 20)
+            } else {
+                (u32::from(seq) * 160 + 8000, u64::from(seq) * 20 + 1000)
+            };
+            let data = RtpPacket::build(0, seq, ts, 7, &[0xFF; 160], seq == 0 || seq == 10);
+            let packet = RtpPacket::parse_with_time(&data, source, base + Duration::from_millis(arrival_ms)).unwrap();
+            handle_test_packet(&mut state, &packet, &options).unwrap();
+        }
+        handle_test_page_end(&mut state).unwrap();
+        finish_recordings(&mut state);
+
+        let page = &state.ended_pages[0];
+        assert_eq!(page.marked_packets, 2);
+        let fingerprint = page.sender_fingerprint.as_ref().unwrap();
+        assert_eq!((fingerprint.ssrc, fingerprint.initial_sequence, fingerprint.marker), (7, 0, true));
+        assert_eq!(page.spurts.len(), 2);
+        assert_eq!(
+            page.spurt_files,
+            [
+                "224.0.123.11_15020/page_0001_224_0_123_11_15020_spurt_01.wav",
+                "224.0.123.11_15020/page_0001_224_0_123_11_15020_spurt_02.wav"
+            ]
+        );
+        let frames = |name: &str| hound::WavReader::open(dir.path().join(name)).unwrap().duration();
+        assert_eq!(frames(&page.spurt_files[0]), 1600);
+        assert_eq!(frames(&page.spurt_files[1]), 800);
+        assert_eq!(frames(&page.recording_file), 2400);
+    }
+
+    #[test]
+    fn test_cisco_padding_counted_and_not_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = options(dir.path());
+        let mut state = TestEndpointState::new(Ipv4Addr::new(224, 0, 123, 16), 15020);
+        let source = "192.168.1.10:5004".parse().unwrap();
+        let base = Instant::now();
+
+        // A header extension on every packet, and every other one padded
+        for seq in 0..50u16 {
+            let mut data = RtpPacket::build(0, seq, u32::from(seq) * 160, 9, &[0xFF; 160], seq == 0);
+            data.splice(12..12, [0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x2a]);
+            data[0] |= 0x10;
+            if seq % 2 == 0 {
+                data[0] |= 0x20;
+                data.extend_from_slice(&[0x00, 0x00, 0x00, 0x04]);
+            }
+            let packet = RtpPacket::parse_with_time(&data, source, base + Duration::from_millis(u64::from(seq) * 20)).unwrap();
+            handle_test_packet(&mut state, &packet, &options).unwrap();
+        }
+        handle_test_page_end(&mut state).unwrap();
+        finish_recordings(&mut state);
+
+        let page = &state.ended_pages[0];
+        assert_eq!(page.network.padded_packets, 25);
+        assert_eq!(page.network.avg_padding_bytes, Some(4.0));
+        assert_eq!(page.network.bytes_received, 50 * 160);
+        assert_eq!(page.network.payload_sizes.deviating_packets, 0);
+        // Not a sample more than the payloads carry
+        assert_eq!(hound::WavReader::open(dir.path().join(&page.recording_file)).unwrap().duration(), 50 * 160);
+    }
+
+    #[test]
+    fn test_payloads_recorded_beside_wav() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = TestOptions {
+            record_payloads: true,
+            ..options(dir.path())
+        };
+        let mut state = TestEndpointState::new(Ipv4Addr::new(224, 0, 123, 16), 15023);
+        let source = "192.168.1.10:5004".parse().unwrap();
+        let base = Instant::now();
+
+        // 50 frames of a ramp through the μ-law codes, with packet 20 lost
+        for seq in (0..50u16).filter(|&seq| seq != 20) {
+            let payload: Vec<u8> = (0..160u16).map(|i| (i + seq * 3) as u8).collect();
+            let data = RtpPacket::build(0, seq, u32::from(seq) * 160, 7, &payload, false);
+            let packet = RtpPacket::parse_with_time(&data, source, base + Duration::from_millis(u64::from(seq) * 20)).unwrap();
+            handle_test_packet(&mut state, &packet, &options).unwrap();
+        }
+        handle_test_page_end(&mut state).unwrap();
+        finish_recordings(&mut state);
+
+        let page = &state.ended_pages[0];
+        assert_eq!(page.recording_files[&RecordingKind::Wav], page.recording_file);
+        let payloads = crate::cli::payloads::read_payloads(&dir.path().join(&page.recording_files[&RecordingKind::Payloads])).unwrap();
+        assert_eq!(payloads.len(), 49);
+        assert_eq!(payloads[20].0.sequence, 21);
+        assert_eq!(payloads[20].0.timestamp, 21 * 160);
+
+        // The payloads decode to exactly what the recording holds
+        let mut decoder = crate::codec::create_decoder_for_payload_type(payloads[0].0.payload_type).unwrap();
+        let decoded: Vec<i16> = payloads.iter().flat_map(|(_, payload)| decoder.decode(payload).unwrap()).collect();
+        let recorded: Vec<i16> = hound::WavReader::open(dir.path().join(&page.recording_file))
+            .unwrap()
+            .samples::<i16>()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(decoded, recorded);
+    }
+
+    #[test]
+    fn test_timing_file_follows_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = TestOptions {
+            timing_file: Some(TimingGranularity::Packet),
+            ..options(dir.path())
+        };
+        let mut state = TestEndpointState::new(Ipv4Addr::new(224, 0, 123, 15), 15022);
+        let source = "192.168.1.10:5004".parse().unwrap();
+        // In the past, so arrival times map onto the wall clock as given
+        let base = Instant::now().checked_sub(Duration::from_secs(10)).unwrap();
+
+        // 100 frames with packets 40-44 lost; G.711 loss isn't filled, so the
+        // recording runs on from packet 39 to 45
+        for seq in (0..100u16).filter(|seq| !(40..45).contains(seq)) {
+            let data = RtpPacket::build(0, seq, u32::from(seq) * 160, 7, &[0xFF; 160], false);
+            let packet = RtpPacket::parse_with_time(&data, source, base + Duration::from_millis(u64::from(seq) * 20)).unwrap();
+            handle_test_packet(&mut state, &packet, &options).unwrap();
+        }
+        handle_test_page_end(&mut state).unwrap();
+        finish_recordings(&mut state);
+
+        let recording = dir.path().join(&state.ended_pages[0].recording_file);
+        let rows = crate::cli::timing::read_timing_rows(&timing_path(&recording)).unwrap();
+        assert_eq!(rows.len(), 95);
+        assert_eq!(rows[40].sample_offset, 40 * 160);
+        let start = rows[0].received_at;
+        let recorded_start = crate::cli::recorder::read_start_time(&recording).unwrap().unwrap();
+        assert!((recorded_start - start).num_milliseconds().abs() <= 1);
+
+        // 1.5s in is packet 75, five packets short of frame 12000, give or take a packet
+        let offset = crate::cli::timing::locate(&rows, start + chrono::Duration::milliseconds(1500), 8000).unwrap();
+        assert!(offset.abs_diff(11200) <= 160, "{}", offset);
+        assert_eq!(hound::WavReader::open(&recording).unwrap().duration(), 15200);
+    }
+
+    #[test]
+    fn test_abort_page_on_truncated_payload() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = TestOptions {
+            decode_error_policy: DecodeErrorPolicy::AbortPage,
+            ..options(dir.path())
+        };
+        let mut state = TestEndpointState::new(Ipv4Addr::new(224, 0, 123, 12), 15021);
+        let source = "192.168.1.10:5004".parse().unwrap();
+        let base = Instant::now();
+
+        // Frame 5 arrives with only 10ms of its 20ms
+        for seq in 0..10u16 {
+            let size = if seq == 5 { 80 } else { 160 };
+            let data = RtpPacket::build(0, seq, u32::from(seq) * 160, 7, &vec![0xFF; size], false);
+            let packet = RtpPacket::parse_with_time(&data, source, base + Duration::from_millis(u64::from(seq) * 20)).unwrap();
+            handle_test_packet(&mut state, &packet, &options).unwrap();
+        }
+        handle_test_page_end(&mut state).unwrap();
+        finish_recordings(&mut state);
+
+        let [aborted, rest] = &state.ended_pages[..] else {
+            panic!("expected two pages, got {}", state.ended_pages.len());
+        };
+        assert_eq!(aborted.error.as_deref(), Some("truncated payload (10ms of audio missing)"));
+        assert_eq!(aborted.network.truncated_payloads, 1);
+        // Frame 6 showed the damage, so it's counted there and starts the next page
+        assert_eq!(aborted.network.packets_received, 7);
+        assert_eq!(rest.error, None);
+        assert_eq!(rest.network.packets_received, 4);
+        assert_eq!(hound::WavReader::open(dir.path().join(&rest.recording_file)).unwrap().duration(), 640);
+    }
+
+    #[test]
+    fn test_run_end_truncates_active_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = options(dir.path());
+        let mut state = TestEndpointState::new(Ipv4Addr::new(224, 0, 123, 17), 15024);
+        let source = "192.168.1.10:5004".parse().unwrap();
+        // In the past, so the run ends long after the last packet
+        let base = Instant::now().checked_sub(Duration::from_secs(10)).unwrap();
+
+        // A 1s page that idles out, then another the run ends partway through
+        for (ssrc, start_ms) in [(7, 0), (8, 4000)] {
+            for seq in 0..50u16 {
+                let data = RtpPacket::build(0, seq, u32::from(seq) * 160, ssrc, &[0xFF; 160], false);
+                let arrival = base + Duration::from_millis(start_ms + u64::from(seq) * 20);
+                let packet = RtpPacket::parse_with_time(&data, source, arrival).unwrap();
+                if state.is_idle(packet.received_at) {
+                    handle_test_page_end(&mut state).unwrap();
+                }
+                handle_test_packet(&mut state, &packet, &options).unwrap();
+            }
+        }
+        truncate_test_page(&mut state).unwrap();
+        finish_recordings(&mut state);
+
+        let [idled, truncated] = &state.ended_pages[..] else {
+            panic!("expected two pages, got {}", state.ended_pages.len());
+        };
+        assert!(!idled.truncated);
+        assert!(truncated.truncated);
+        for page in [idled, truncated] {
+            assert!((page.duration_secs - 1.0).abs() < 0.05, "{}", page.duration_secs);
+            let span = (page.end_time - page.start_time).to_std().unwrap().as_secs_f64();
+            assert!((span - page.duration_secs).abs() < 1e-3, "{} vs {}", span, page.duration_secs);
+        }
+        // A new page starts out whole
+        assert!(!state.truncated);
+
+        let json = serde_json::to_value(idled).unwrap();
+        assert!(json.get("truncated").is_none());
+        assert_eq!(serde_json::to_value(truncated).unwrap()["truncated"], true);
+    }
+
+    #[test]
+    fn test_endpoint_idle_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = options(dir.path());
+        let patient = EndpointOptions { idle_ms: Some(5000), ..EndpointOptions::default() };
+        let mut states = [
+            TestEndpointState::new(Ipv4Addr::new(224, 0, 123, 13), 15020),
+            TestEndpointState::new(Ipv4Addr::new(224, 0, 123, 14), 15020).with_overrides(patient),
+        ];
+        let source = "192.168.1.10:5004".parse().unwrap();
+        let base = Instant::now();
+
+        // The same stream to both: 10 frames, 3s without packets, 10 more
+        for state in &mut states {
+            for seq in 0..20u16 {
+                let arrival_ms = u64::from(seq) * 20 + if seq < 10 { 0 } else { 3000 };
+                let data = RtpPacket::build(0, seq, u32::from(seq) * 160, 7, &[0xFF; 160], false);
+                let packet = RtpPacket::parse_with_time(&data, source, base + Duration::from_millis(arrival_ms)).unwrap();
+                if state.is_idle(packet.received_at) {
+                    handle_test_page_end(state).unwrap();
+                }
+                handle_test_packet(state, &packet, &options).unwrap();
+            }
+            handle_test_page_end(state).unwrap();
+        }
+
+        let [split, merged] = &states;
+        // The 2s default ends the page during the gap; 5s carries it over
+        assert_eq!(split.ended_pages.len(), 2);
+        assert!(split.ended_pages.iter().all(|page| page.network.packets_received == 10));
+        assert_eq!(merged.ended_pages.len(), 1);
+        assert_eq!(merged.ended_pages[0].network.packets_received, 20);
+
+        let endpoint_states = HashMap::from(states.map(|state| ((state.address, state.port), state)));
+        let summary = generate_summary(&options, Utc::now(), Utc::now(), &endpoint_states, Vec::new(), Vec::new(), ErrorLog::default());
+        assert_eq!(summary.test_metadata.endpoint_overrides.len(), 1);
+        assert_eq!(summary.test_metadata.endpoint_overrides["224.0.123.14:15020"], patient);
+    }
+}
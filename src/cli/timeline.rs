@@ -0,0 +1,346 @@
+//! Per-page timeline from a test run's metrics log.
+//!
+//! `test` appends a [`MetricSnapshot`] per endpoint to metrics.jsonl at every
+//! metrics interval, tagged with the page in progress. `review --timeline`
+//! picks out one page's snapshots (matched on endpoint and page number) and
+//! shows how level, jitter, loss and glitches moved through the page, so a
+//! bad patch can be placed in time rather than only seen in the totals.
+//!
+//! The log is read a line at a time and only the page's own snapshots are
+//! kept, so a multi-gigabyte file from a week-long run costs no more memory
+//! than the page does. Lines that aren't valid snapshots (a write cut short
+//! by a crash, stray bytes) are counted and skipped.
+//!
+//! Operator annotations are interleaved in the log too. They belong to no
+//! endpoint, so the ones made while the page was running are shown between
+//! the snapshots either side of them.
+
+use crate::cli::annotation::Annotation;
+use crate::cli::test::{ClockStepRecord, MetricSnapshot};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::io::{self, BufRead};
+
+/// Width of the RMS bar in the timeline table
+const LEVEL_BAR_WIDTH: usize = 14;
+
+/// RMS level drawn as an empty bar; 0 dBFS is a full one
+const LEVEL_FLOOR_DB: f64 = -60.0;
+
+/// One page's snapshots, in time order
+#[derive(Debug, Serialize)]
+pub struct PageTimeline {
+    pub endpoint: String,
+    pub page_number: u32,
+    /// Lines of the log that couldn't be parsed
+    pub skipped_lines: u64,
+    /// Index into `snapshots` of the worst interval, if there are any
+    pub worst: Option<usize>,
+    pub snapshots: Vec<MetricSnapshot>,
+    /// Operator notes, in time order
+    pub annotations: Vec<Annotation>,
+}
+
+/// What changed over one metrics interval
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    /// Seconds since the page's first snapshot
+    pub offset_secs: f64,
+    pub rms_db: f64,
+    pub jitter_ms: f64,
+    /// Loss so far in the page
+    pub loss_percent: f64,
+    /// Glitches that appeared during this interval
+    pub new_glitches: u64,
+    /// Rise in the page's loss percentage during this interval
+    pub loss_rise: f64,
+}
+
+impl PageTimeline {
+    /// Stream `reader` and keep the snapshots of `endpoint`'s page `page_number`
+    pub fn read(reader: impl BufRead, endpoint: &str, page_number: u32) -> io::Result<Self> {
+        let mut snapshots = Vec::new();
+        let mut annotations = Vec::new();
+        let mut skipped_lines = 0;
+
+        // Split on raw bytes so a line of invalid UTF-8 is skipped, not fatal
+        for line in reader.split(b'\n') {
+            let line = line?;
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            match serde_json::from_slice::<MetricSnapshot>(&line) {
+                Ok(snapshot) => {
+                    if snapshot.page_active && snapshot.page_number == Some(page_number) && snapshot.endpoint == endpoint
+                    {
+                        snapshots.push(snapshot);
+                    }
+                }
+                Err(_) => match serde_json::from_slice::<Annotation>(&line) {
+                    Ok(annotation) => annotations.push(annotation),
+                    Err(_) if serde_json::from_slice::<ClockStepRecord>(&line).is_ok() => {}
+                    Err(_) => skipped_lines += 1,
+                },
+            }
+        }
+
+        // Written in order by one task, but a stable sort costs little and
+        // keeps logs that were concatenated or merged plottable
+        snapshots.sort_by_key(|s| s.timestamp);
+        annotations.sort_by_key(|a| a.timestamp);
+
+        let mut timeline = Self {
+            endpoint: endpoint.to_string(),
+            page_number,
+            skipped_lines,
+            worst: None,
+            snapshots,
+            annotations,
+        };
+        timeline.worst = timeline.worst_interval();
+        Ok(timeline)
+    }
+
+    /// Keep only the notes made between `start` and `end`, the page's span
+    pub fn clip_annotations(&mut self, start: DateTime<Utc>, end: DateTime<Utc>) {
+        self.annotations.retain(|a| (start..=end).contains(&a.timestamp));
+    }
+
+    /// Each snapshot with what changed since the one before
+    pub fn intervals(&self) -> Vec<Interval> {
+        let Some(first) = self.snapshots.first() else {
+            return Vec::new();
+        };
+        let mut previous_glitches = 0;
+        let mut previous_loss = 0.0;
+        self.snapshots
+            .iter()
+            .map(|snapshot| {
+                let interval = Interval {
+                    offset_secs: (snapshot.timestamp - first.timestamp).num_milliseconds() as f64 / 1000.0,
+                    rms_db: snapshot.audio.rms_db,
+                    jitter_ms: snapshot.network.jitter_ms,
+                    loss_percent: snapshot.network.loss_percent,
+                    new_glitches: snapshot.audio.glitches.saturating_sub(previous_glitches),
+                    loss_rise: (snapshot.network.loss_percent - previous_loss).max(0.0),
+                };
+                previous_glitches = snapshot.audio.glitches;
+                previous_loss = snapshot.network.loss_percent;
+                interval
+            })
+            .collect()
+    }
+
+    /// The interval with the most new glitches, then the biggest rise in
+    /// loss, then the highest jitter
+    fn worst_interval(&self) -> Option<usize> {
+        self.intervals()
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| {
+                a.new_glitches
+                    .cmp(&b.new_glitches)
+                    .then(a.loss_rise.total_cmp(&b.loss_rise))
+                    .then(a.jitter_ms.total_cmp(&b.jitter_ms))
+            })
+            .map(|(index, _)| index)
+    }
+}
+
+/// Print the timeline as a table with a bar for the RMS level
+pub fn print_timeline(timeline: &PageTimeline) {
+    println!("┌─────────────────────────────────────────────────────────────────┐");
+    println!("│ PAGE TIMELINE                                                   │");
+    println!("├─────────────────────────────────────────────────────────────────┤");
+    println!("│ {:<63} │", format!("Page {} on {}", timeline.page_number, timeline.endpoint));
+    if timeline.snapshots.is_empty() {
+        println!("│ {:<63} │", "No metrics snapshots for this page.");
+        println!("└─────────────────────────────────────────────────────────────────┘");
+        print_skipped(timeline.skipped_lines);
+        return;
+    }
+    println!("├─────────────────────────────────────────────────────────────────┤");
+    print_row(["Time", "RMS dB", "Jitter", "Loss%", "Glitch"].map(String::from), "Level", "");
+    println!("├─────────────────────────────────────────────────────────────────┤");
+
+    let first = timeline.snapshots[0].timestamp;
+    let mut annotations = timeline.annotations.iter().peekable();
+    for (index, (interval, snapshot)) in timeline.intervals().iter().zip(&timeline.snapshots).enumerate() {
+        while let Some(annotation) = annotations.next_if(|a| a.timestamp <= snapshot.timestamp) {
+            print_annotation(annotation, first);
+        }
+        let rms = if interval.rms_db.is_finite() {
+            format!("{:.1}", interval.rms_db)
+        } else {
+            "-".to_string()
+        };
+        let glitches = if interval.new_glitches > 0 {
+            format!("+{}", interval.new_glitches)
+        } else {
+            "0".to_string()
+        };
+        let marker = if timeline.worst == Some(index) { "◀ worst" } else { "" };
+        print_row(
+            [
+                format!("{:.1}s", interval.offset_secs),
+                rms,
+                format!("{:.1}ms", interval.jitter_ms),
+                format!("{:.2}", interval.loss_percent),
+                glitches,
+            ],
+            &level_bar(interval.rms_db),
+            marker,
+        );
+    }
+    for annotation in annotations {
+        print_annotation(annotation, first);
+    }
+
+    println!("└─────────────────────────────────────────────────────────────────┘");
+    println!("Glitches are new in each interval; loss is for the page so far.");
+    print_skipped(timeline.skipped_lines);
+}
+
+fn print_row(cells: [String; 5], level: &str, marker: &str) {
+    let [time, rms, jitter, loss, glitches] = cells;
+    println!(
+        "│ {:>7} {:>7} {:>7} {:>7} {:>7}  {:<width$} {:<7} │",
+        time,
+        rms,
+        jitter,
+        loss,
+        glitches,
+        level,
+        marker,
+        width = LEVEL_BAR_WIDTH,
+    );
+}
+
+/// A note as a row of its own, timed like the snapshots
+fn print_annotation(annotation: &Annotation, first: DateTime<Utc>) {
+    const TEXT_WIDTH: usize = 52;
+    let offset = (annotation.timestamp - first).num_milliseconds() as f64 / 1000.0;
+    let text = if annotation.text.chars().count() > TEXT_WIDTH {
+        format!("{}…", annotation.text.chars().take(TEXT_WIDTH - 1).collect::<String>())
+    } else {
+        annotation.text.clone()
+    };
+    println!("│ {:<63} │", format!("{:>7}  ✎ {}", format!("{:.1}s", offset), text));
+}
+
+fn print_skipped(skipped_lines: u64) {
+    if skipped_lines > 0 {
+        println!("⚠ Skipped {} unreadable line(s) in metrics.jsonl", skipped_lines);
+    }
+}
+
+/// Bar proportional to the level between [`LEVEL_FLOOR_DB`] and 0 dBFS
+fn level_bar(rms_db: f64) -> String {
+    if !rms_db.is_finite() {
+        return String::new();
+    }
+    let fraction = (1.0 - rms_db / LEVEL_FLOOR_DB).clamp(0.0, 1.0);
+    "█".repeat((fraction * LEVEL_BAR_WIDTH as f64).round() as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn snapshot(second: u32, endpoint: &str, page: Option<u32>, loss: f64, glitches: u64) -> String {
+        let mut value = serde_json::json!({
+            "timestamp": format!("2026-01-15T10:30:{:02}Z", second),
+            "endpoint": endpoint,
+            "page_active": page.is_some(),
+            "network": {"packets": 50 * u64::from(second), "bytes": 8000, "loss_percent": loss, "jitter_ms": 1.5},
+            "audio": {"rms_db": -20.0, "peak_db": -6.0, "dominant_freq_hz": 1000.0, "glitches": glitches, "clipped": 0}
+        });
+        if let Some(page) = page {
+            value["page_number"] = page.into();
+        }
+        value.to_string()
+    }
+
+    /// Two endpoints each running page 1, then page 2 on the first one
+    fn metrics_log() -> String {
+        let a = "224.0.1.1:5004";
+        let b = "224.0.1.2:5004";
+        let mut lines = vec![
+            snapshot(0, a, None, 0.0, 0),
+            snapshot(1, a, Some(1), 0.0, 0),
+            snapshot(1, b, Some(1), 0.0, 0),
+            snapshot(2, a, Some(1), 0.0, 0),
+            "{\"timestamp\": \"2026-01-15T10:30:02Z\", \"endpo".to_string(),
+            snapshot(2, b, Some(1), 0.0, 0),
+            snapshot(3, a, Some(1), 1.0, 0),
+            snapshot(4, a, None, 0.0, 0),
+            snapshot(5, a, Some(2), 0.0, 0),
+            snapshot(6, a, Some(2), 0.5, 4),
+            snapshot(7, a, Some(2), 0.5, 5),
+            String::new(),
+        ];
+        // A silent interval: -inf RMS is written as null
+        lines.insert(10, snapshot(6, a, Some(2), 0.5, 4).replace("-20.0", "null"));
+        lines.join("\n")
+    }
+
+    #[test]
+    fn test_filters_page_in_order() {
+        let mut log = metrics_log().into_bytes();
+        log.extend_from_slice(b"\xff\xfe garbage\n");
+
+        let page_one = PageTimeline::read(Cursor::new(&log), "224.0.1.1:5004", 1).unwrap();
+        let seconds: Vec<_> = page_one.snapshots.iter().map(|s| s.timestamp.format("%S").to_string()).collect();
+        assert_eq!(seconds, ["01", "02", "03"]);
+        assert!(page_one.snapshots.iter().all(|s| s.endpoint == "224.0.1.1:5004"));
+        assert_eq!(page_one.skipped_lines, 2);
+        // Loss first appears in the third interval
+        assert_eq!(page_one.worst, Some(2));
+
+        let other = PageTimeline::read(Cursor::new(&log), "224.0.1.2:5004", 1).unwrap();
+        assert_eq!(other.snapshots.len(), 2);
+
+        let missing = PageTimeline::read(Cursor::new(&log), "224.0.1.2:5004", 2).unwrap();
+        assert!(missing.snapshots.is_empty());
+        assert_eq!(missing.worst, None);
+    }
+
+    #[test]
+    fn test_intervals_and_worst() {
+        let page_two = PageTimeline::read(Cursor::new(metrics_log()), "224.0.1.1:5004", 2).unwrap();
+        assert_eq!(page_two.snapshots.len(), 4);
+
+        let intervals = page_two.intervals();
+        let offsets: Vec<_> = intervals.iter().map(|i| i.offset_secs).collect();
+        assert_eq!(offsets, [0.0, 1.0, 1.0, 2.0]);
+        let glitches: Vec<_> = intervals.iter().map(|i| i.new_glitches).collect();
+        assert_eq!(glitches, [0, 4, 0, 1]);
+        assert_eq!(intervals.iter().filter(|i| !i.rms_db.is_finite()).count(), 1);
+        assert_eq!(page_two.worst, Some(1));
+
+        assert_eq!(level_bar(0.0).chars().count(), LEVEL_BAR_WIDTH);
+        assert_eq!(level_bar(-30.0).chars().count(), LEVEL_BAR_WIDTH / 2);
+        assert!(level_bar(f64::NEG_INFINITY).is_empty());
+    }
+
+    #[test]
+    fn test_annotations_kept_apart() {
+        let note = |second: u32, text: &str| {
+            format!(r#"{{"type":"annotation","timestamp":"2026-01-15T10:30:{:02}.500Z","text":"{}","source":"control"}}"#, second, text)
+        };
+        let step = r#"{"type":"clock_step","timestamp":"2026-01-15T10:30:04.000Z","step_secs":-3.5}"#.to_string();
+        let log = [metrics_log(), note(5, "unplugged uplink"), step, note(2, "started"), note(9, "after")].join("\n");
+
+        let mut page_two = PageTimeline::read(Cursor::new(&log), "224.0.1.1:5004", 2).unwrap();
+        // Notes and clock steps aren't snapshots, nor unreadable
+        assert_eq!(page_two.snapshots.len(), 4);
+        assert_eq!(page_two.skipped_lines, 1);
+        let texts: Vec<_> = page_two.annotations.iter().map(|a| a.text.as_str()).collect();
+        assert_eq!(texts, ["started", "unplugged uplink", "after"]);
+
+        page_two.clip_annotations(page_two.snapshots[0].timestamp, page_two.snapshots[3].timestamp);
+        assert_eq!(page_two.annotations.len(), 1);
+        assert_eq!(page_two.annotations[0].text, "unplugged uplink");
+    }
+}
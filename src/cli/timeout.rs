@@ -0,0 +1,265 @@
+//! How long `monitor`, `test` and `polycom-monitor` run.
+//!
+//! A run lasts until it is stopped, for a number of seconds (`--timeout`), or
+//! until a time of day (`--until 17:30`, or a full date and time). `--until`
+//! is read as local time unless it carries an offset, and is checked against
+//! the wall clock, so a run started at 09:00 with `--until 17:30` ends at
+//! 17:30 even if the machine was suspended in between.
+
+use chrono::{DateTime, Local, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// When a run ends by itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timeout {
+    /// Only when stopped
+    Indefinite,
+    /// After this long
+    For(Duration),
+    /// At this time
+    Until(DateTime<Utc>),
+}
+
+/// Which kind of [`Timeout`] a run had, as recorded in summaries and events
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeoutMode {
+    Indefinite,
+    /// Summaries written before `--until` existed are all `for`
+    #[default]
+    For,
+    Until,
+}
+
+/// What ended a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EndReason {
+    /// `--timeout` ran out
+    Timeout,
+    /// The `--until` time was reached
+    Until,
+    /// Ctrl-C or SIGTERM
+    Interrupted,
+    /// None of the endpoints could be listened on
+    NoUsableEndpoints,
+}
+
+impl EndReason {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Timeout => "timeout",
+            Self::Until => "end time",
+            Self::Interrupted => "interrupted",
+            Self::NoUsableEndpoints => "no usable endpoints",
+        }
+    }
+}
+
+impl fmt::Display for EndReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Timeout {
+    /// From `--timeout` seconds, where 0 is indefinite, and `--until`, which
+    /// takes its place when given
+    pub fn from_args(timeout_secs: u64, until: Option<DateTime<Utc>>) -> Self {
+        match (until, timeout_secs) {
+            (Some(until), _) => Self::Until(until),
+            (None, 0) => Self::Indefinite,
+            (None, secs) => Self::For(Duration::from_secs(secs)),
+        }
+    }
+
+    pub fn mode(self) -> TimeoutMode {
+        match self {
+            Self::Indefinite => TimeoutMode::Indefinite,
+            Self::For(_) => TimeoutMode::For,
+            Self::Until(_) => TimeoutMode::Until,
+        }
+    }
+
+    pub fn until(self) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Until(until) => Some(until),
+            _ => None,
+        }
+    }
+
+    /// What ended the run, once [`Self::expired`]
+    pub fn end_reason(self) -> EndReason {
+        match self {
+            Self::Until(_) => EndReason::Until,
+            _ => EndReason::Timeout,
+        }
+    }
+
+    /// Time left, `elapsed` into a run, with the wall clock at `now`.
+    /// `None` if the run has no end
+    pub fn remaining_at(self, elapsed: Duration, now: DateTime<Utc>) -> Option<Duration> {
+        match self {
+            Self::Indefinite => None,
+            Self::For(length) => Some(length.saturating_sub(elapsed)),
+            Self::Until(until) => Some((until - now).to_std().unwrap_or(Duration::ZERO)),
+        }
+    }
+
+    /// Time left in a run that began at `started`
+    pub fn remaining(self, started: Instant) -> Option<Duration> {
+        self.remaining_at(started.elapsed(), Utc::now())
+    }
+
+    /// Whether a run that began at `started` should end now
+    pub fn expired(self, started: Instant) -> bool {
+        self.remaining(started) == Some(Duration::ZERO)
+    }
+
+    /// Said when the run ends by itself
+    pub fn reached(self) -> String {
+        match self {
+            Self::Until(until) => format!("End time {} reached.", until.with_timezone(&Local).format("%H:%M:%S")),
+            _ => "Timeout reached.".to_string(),
+        }
+    }
+
+    /// Whole seconds the run was set to last from `start`; 0 if indefinite
+    pub fn planned_secs(self, start: DateTime<Utc>) -> u64 {
+        match self {
+            Self::Indefinite => 0,
+            Self::For(length) => length.as_secs(),
+            Self::Until(until) => (until - start).num_seconds().max(0) as u64,
+        }
+    }
+}
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Indefinite => f.write_str("none"),
+            Self::For(length) => write!(f, "{} seconds", length.as_secs()),
+            Self::Until(until) => write!(f, "until {}", until.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")),
+        }
+    }
+}
+
+/// Parse `--until`: a time today (`17:30`, `17:30:15`), a local date and
+/// time (`2024-06-01T17:30:00`, or with a space), or one with an offset
+/// (`2024-06-01T17:30:00+01:00`, `...Z`). It must be after `now`
+pub fn parse_until(s: &str, now: DateTime<Local>) -> Result<DateTime<Utc>, String> {
+    let s = s.trim();
+    let until = if let Ok(time) = NaiveTime::parse_from_str(s, "%H:%M").or_else(|_| NaiveTime::parse_from_str(s, "%H:%M:%S")) {
+        local_time(now.date_naive().and_time(time), s)?
+    } else if let Ok(with_offset) = DateTime::parse_from_rfc3339(s) {
+        with_offset.with_timezone(&Utc)
+    } else {
+        let naive = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+            .or_else(|| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0)))
+            .ok_or_else(|| format!("expected a time like 17:30 or a date and time like 2024-06-01T17:30:00, got '{}'", s))?;
+        local_time(naive, s)?
+    };
+
+    if until <= now {
+        return Err(format!(
+            "{} is in the past (it is now {})",
+            s,
+            now.format(if until.with_timezone(&Local).date_naive() == now.date_naive() { "%H:%M:%S" } else { "%Y-%m-%d %H:%M:%S" })
+        ));
+    }
+    Ok(until)
+}
+
+/// A date and time on the local clock; a time skipped by a clock change is
+/// an error, and one repeated by it is taken the first time round
+fn local_time(naive: NaiveDateTime, given: &str) -> Result<DateTime<Utc>, String> {
+    match Local.from_local_datetime(&naive) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => Ok(time.with_timezone(&Utc)),
+        LocalResult::None => Err(format!("{} doesn't exist in local time (the clocks change)", given)),
+    }
+}
+
+/// clap value parser for `--until`
+pub fn parse_until_arg(s: &str) -> Result<DateTime<Utc>, String> {
+    parse_until(s, Local::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(y: i32, mo: u32, d: u32, h: u32, mi: u32, s: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(y, mo, d, h, mi, s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_until() {
+        let now = local(2024, 6, 1, 9, 0, 0);
+
+        // A time of day is today
+        assert_eq!(parse_until("17:30", now).unwrap(), local(2024, 6, 1, 17, 30, 0));
+        assert_eq!(parse_until("17:30:15", now).unwrap(), local(2024, 6, 1, 17, 30, 15));
+
+        // Dates and times, local unless they say otherwise
+        assert_eq!(parse_until("2024-06-01T17:30:00", now).unwrap(), local(2024, 6, 1, 17, 30, 0));
+        assert_eq!(parse_until("2024-06-02 08:15", now).unwrap(), local(2024, 6, 2, 8, 15, 0));
+        assert_eq!(parse_until("2024-06-03", now).unwrap(), local(2024, 6, 3, 0, 0, 0));
+        assert_eq!(
+            parse_until("2099-06-01T17:30:00Z", now).unwrap(),
+            Utc.with_ymd_and_hms(2099, 6, 1, 17, 30, 0).unwrap()
+        );
+
+        let err = parse_until("08:59", now).unwrap_err();
+        assert_eq!(err, "08:59 is in the past (it is now 09:00:00)");
+        let err = parse_until("2024-05-31T17:30:00", now).unwrap_err();
+        assert_eq!(err, "2024-05-31T17:30:00 is in the past (it is now 2024-06-01 09:00:00)");
+        assert!(parse_until("09:00", now).is_err(), "now is not in the future");
+
+        for bad in ["5pm", "25:00", "17:30:00:00", "2024-13-01T00:00:00", ""] {
+            let err = parse_until(bad, now).unwrap_err();
+            assert!(err.starts_with("expected a time like 17:30"), "{}: {}", bad, err);
+        }
+    }
+
+    #[test]
+    fn test_remaining_time() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 9, 0, 0).unwrap();
+        let second = Duration::from_secs(1);
+
+        assert_eq!(Timeout::from_args(0, None), Timeout::Indefinite);
+        assert_eq!(Timeout::Indefinite.remaining_at(Duration::from_secs(1_000_000), now), None);
+
+        let timeout = Timeout::from_args(30, None);
+        assert_eq!(timeout, Timeout::For(Duration::from_secs(30)));
+        assert_eq!(timeout.remaining_at(Duration::from_secs(10), now), Some(Duration::from_secs(20)));
+        assert_eq!(timeout.remaining_at(Duration::from_secs(45), now), Some(Duration::ZERO));
+        assert_eq!(timeout.end_reason(), EndReason::Timeout);
+
+        // --until wins over --timeout, and follows the wall clock rather than the run's length
+        let until = now + chrono::Duration::minutes(1);
+        let timeout = Timeout::from_args(30, Some(until));
+        assert_eq!(timeout.mode(), TimeoutMode::Until);
+        assert_eq!(timeout.remaining_at(Duration::ZERO, now), Some(Duration::from_mins(1)));
+        assert_eq!(timeout.remaining_at(Duration::ZERO, until - chrono::Duration::seconds(1)), Some(second));
+        assert_eq!(timeout.remaining_at(Duration::ZERO, until + chrono::Duration::hours(1)), Some(Duration::ZERO));
+        assert_eq!(timeout.planned_secs(now), 60);
+        assert_eq!(timeout.end_reason(), EndReason::Until);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let started = Instant::now();
+        assert!(!Timeout::Indefinite.expired(started));
+        assert!(!Timeout::For(Duration::from_mins(1)).expired(started));
+        assert!(!Timeout::Until(Utc::now() + chrono::Duration::minutes(1)).expired(started));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(Timeout::For(Duration::from_millis(20)).expired(started));
+        assert!(Timeout::Until(Utc::now()).expired(started));
+    }
+}
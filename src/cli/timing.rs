@@ -0,0 +1,223 @@
+//! Sidecar files tying a recording's samples to the wall clock.
+//!
+//! With `--timing`, `monitor` and `test` write `<recording>.timing.jsonl`
+//! beside each recording: one row per packet (or per interval) giving the
+//! frame the packet's audio starts at and when the packet was received.
+//! The recording is kept on the RTP timeline, so concealed and filled gaps
+//! move the offsets on without breaking the mapping; rows only ever go
+//! forward in both offset and time. `review --at` uses the rows to find
+//! what was playing at a given instant.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum TimingError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid timing row on line {line}: {source}")]
+    InvalidRow { line: usize, source: serde_json::Error },
+}
+
+/// `--timing`: how often a row is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingGranularity {
+    /// A row for every packet
+    Packet,
+    /// A row for the first packet at least this long after the last row
+    Interval(Duration),
+}
+
+impl fmt::Display for TimingGranularity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Packet => f.write_str("packet"),
+            Self::Interval(interval) => write!(f, "{}ms", interval.as_millis()),
+        }
+    }
+}
+
+impl FromStr for TimingGranularity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        if s == "packet" {
+            return Ok(Self::Packet);
+        }
+        match s.strip_suffix("ms").unwrap_or(&s).parse::<u64>() {
+            Ok(ms) if ms > 0 => Ok(Self::Interval(Duration::from_millis(ms))),
+            _ => Err(format!("invalid timing granularity {:?}: expected packet or milliseconds, e.g. 100", s)),
+        }
+    }
+}
+
+/// Where a packet's audio starts in the recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimingRow {
+    /// Frame (sample per channel) of the recording the audio starts at
+    pub sample_offset: u64,
+    /// When the packet was received
+    pub received_at: DateTime<Utc>,
+    pub sequence: u16,
+}
+
+/// Writes the timing rows of one recording. Rows are buffered rather than
+/// flushed one by one, since there can be fifty a second
+pub struct TimingWriter {
+    writer: BufWriter<File>,
+    granularity: TimingGranularity,
+    last: Option<TimingRow>,
+}
+
+impl TimingWriter {
+    pub fn create(path: &Path, granularity: TimingGranularity) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            granularity,
+            last: None,
+        })
+    }
+
+    /// Note a packet whose audio starts at frame `sample_offset`. Packets
+    /// that recorded nothing since the last row are left out, and a receive
+    /// time behind the last row's is taken as the last row's
+    pub fn record(&mut self, sample_offset: u64, received_at: DateTime<Utc>, sequence: u16) -> io::Result<()> {
+        let mut row = TimingRow { sample_offset, received_at, sequence };
+        if let Some(last) = self.last {
+            if sample_offset <= last.sample_offset {
+                return Ok(());
+            }
+            if let TimingGranularity::Interval(interval) = self.granularity {
+                let since = (received_at - last.received_at).to_std().unwrap_or_default();
+                if since < interval {
+                    return Ok(());
+                }
+            }
+            row.received_at = received_at.max(last.received_at);
+        }
+        serde_json::to_writer(&mut self.writer, &row)?;
+        writeln!(self.writer)?;
+        self.last = Some(row);
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Timing file for a recording: `page_0001.wav` has `page_0001.timing.jsonl`
+pub fn timing_path(recording: &Path) -> PathBuf {
+    let stem = recording
+        .file_stem()
+        .map_or_else(|| recording.to_string_lossy().into_owned(), |s| s.to_string_lossy().into_owned());
+    recording.with_file_name(format!("{}.timing.jsonl", stem))
+}
+
+/// Read all rows from a timing file
+pub fn read_timing_rows(path: &Path) -> Result<Vec<TimingRow>, TimingError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut rows = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row = serde_json::from_str(&line).map_err(|source| TimingError::InvalidRow { line: i + 1, source })?;
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Frame of the recording that was playing at `time`: counted on at
+/// `sample_rate` from the last row at or before it, but never past the
+/// next row's offset. `None` before the first row
+pub fn locate(rows: &[TimingRow], time: DateTime<Utc>, sample_rate: u32) -> Option<u64> {
+    let index = rows.partition_point(|row| row.received_at <= time).checked_sub(1)?;
+    let row = rows[index];
+    let elapsed = (time - row.received_at).num_microseconds().unwrap_or(i64::MAX) as f64 / 1_000_000.0;
+    let offset = row.sample_offset + (elapsed * f64::from(sample_rate)).round() as u64;
+    Some(match rows.get(index + 1) {
+        Some(next) => offset.min(next.sample_offset),
+        None => offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_timing_granularity_from_str() {
+        assert_eq!("packet".parse(), Ok(TimingGranularity::Packet));
+        assert_eq!("100".parse(), Ok(TimingGranularity::Interval(Duration::from_millis(100))));
+        assert_eq!("250ms".parse(), Ok(TimingGranularity::Interval(Duration::from_millis(250))));
+        assert!("0".parse::<TimingGranularity>().is_err());
+        assert!("often".parse::<TimingGranularity>().is_err());
+    }
+
+    #[test]
+    fn test_locate_mid_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = timing_path(&dir.path().join("page_0001.wav"));
+        assert_eq!(path, dir.path().join("page_0001.timing.jsonl"));
+
+        // A 20ms G.711 page with arrival jitter and packets 50-54 lost, the
+        // gap concealed in the recording; a late packet is received out of order
+        let start = Utc.with_ymd_and_hms(2024, 6, 1, 14, 32, 5).unwrap();
+        let mut writer = TimingWriter::create(&path, TimingGranularity::Packet).unwrap();
+        let mut expected = Vec::new();
+        for sequence in (0..150u16).filter(|s| !(50..55).contains(s)) {
+            let jitter_us = i64::from(sequence % 7) * 900;
+            let received_at = start + chrono::Duration::microseconds(i64::from(sequence) * 20_000 + jitter_us);
+            writer.record(u64::from(sequence) * 160, received_at, sequence).unwrap();
+            expected.push((sequence, received_at));
+        }
+        // Nothing new recorded for it, so no row
+        writer.record(100 * 160, start + chrono::Duration::seconds(2), 100).unwrap();
+        writer.finish().unwrap();
+
+        let rows = read_timing_rows(&path).unwrap();
+        assert_eq!(rows.len(), expected.len());
+        assert!(rows.windows(2).all(|w| w[0].sample_offset < w[1].sample_offset && w[0].received_at <= w[1].received_at));
+
+        // 14:32:07.250 is 2.25s in: packet 112, frame 18000
+        let at = start + chrono::Duration::milliseconds(2250);
+        let offset = locate(&rows, at, 8000).unwrap();
+        assert!(offset.abs_diff(18000) <= 160, "{}", offset);
+
+        // Across the lost packets the mapping carries on to the next one received
+        let in_gap = start + chrono::Duration::milliseconds(1050);
+        let offset = locate(&rows, in_gap, 8000).unwrap();
+        assert!(offset.abs_diff(8400) <= 160, "{}", offset);
+
+        assert_eq!(locate(&rows, start - chrono::Duration::seconds(1), 8000), None);
+    }
+
+    #[test]
+    fn test_interval_granularity() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.timing.jsonl");
+        let start = Utc.with_ymd_and_hms(2024, 6, 1, 14, 32, 5).unwrap();
+        let mut writer = TimingWriter::create(&path, TimingGranularity::Interval(Duration::from_millis(100))).unwrap();
+        for sequence in 0..50u16 {
+            let received_at = start + chrono::Duration::milliseconds(i64::from(sequence) * 20);
+            writer.record(u64::from(sequence) * 160, received_at, sequence).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let rows = read_timing_rows(&path).unwrap();
+        assert_eq!(rows.len(), 10);
+        assert_eq!(rows[1].sequence, 5);
+    }
+}
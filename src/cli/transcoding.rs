@@ -0,0 +1,232 @@
+//! Spotting wideband pages that went through a narrowband hop on the way.
+//!
+//! A PBX can claim G.722 end to end while a trunk or gateway in the middle
+//! decodes to 8 kHz G.711 and encodes again. What arrives is "G.722" that
+//! never goes above the telephone band and carries μ-law's quantization
+//! noise. For pages decoded at 16 kHz or more, the analyzer splits every
+//! FFT of a non-silent frame at 4 kHz:
+//!
+//! - `high_band_db` is the page's energy above 4 kHz relative to all of it.
+//!   Speech through a wideband path keeps its fricatives up there; through
+//!   an 8 kHz hop it has nothing left.
+//! - `noise_floor_snr_db` compares the power density of the telephone band
+//!   (300-3400 Hz) with that of the guard band just past it (3450-3650 Hz).
+//!   The hop band-limits the signal to 3.4 kHz and then quantizes it, so its
+//!   μ-law noise, white up to 4 kHz, is all the guard band holds. μ-law's
+//!   companding keeps that noise a roughly fixed 35-40 dB under the signal
+//!   at any level. Band-limited audio that was never quantized leaves the
+//!   guard band 60 dB or more down, and wideband audio fills it.
+//!
+//! A page is suspected when both agree: no wideband content, and a floor in
+//! μ-law's range. It is a heuristic. A steady tone whose frequency divides
+//! 8 kHz puts its μ-law distortion on harmonics rather than spreading it as
+//! noise, so it is missed. At 44.1 and 48 kHz a frame's bins are about
+//! 90 Hz wide, and content just under 3.4 kHz leaks into the guard band,
+//! raising the floor of clean band-limited audio toward μ-law's. The
+//! thresholds come from the `[transcoding]` section of the config file.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Where the telephone band ends and wideband content starts
+pub const NARROWBAND_EDGE_HZ: f64 = 4000.0;
+
+/// The telephone band, where a narrowband hop leaves the signal
+const VOICE_BAND_HZ: (f64, f64) = (300.0, 3400.0);
+
+/// Just past the telephone band and short of 4 kHz, where a narrowband
+/// hop leaves only its quantization noise
+const GUARD_BAND_HZ: (f64, f64) = (3450.0, 3650.0);
+
+/// FFT frames a page needs before it is judged, about half a second
+const MIN_FRAMES: u64 = 25;
+
+/// Where the detector draws its lines. Read from `[transcoding]` in the
+/// config file; anything left out keeps the value below
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TranscodingThresholds {
+    /// A page with no more energy above 4 kHz than this, relative to all of
+    /// it (dB), has no wideband content
+    pub max_high_band_db: f64,
+    /// μ-law's in-band floor sits at least this far under the signal (dB).
+    /// Lower than μ-law alone leaves, as the wideband codec adds its own
+    pub mulaw_min_snr_db: f64,
+    /// ... and no further than this
+    pub mulaw_max_snr_db: f64,
+}
+
+impl Default for TranscodingThresholds {
+    fn default() -> Self {
+        Self {
+            max_high_band_db: -40.0,
+            mulaw_min_snr_db: 20.0,
+            mulaw_max_snr_db: 45.0,
+        }
+    }
+}
+
+/// One FFT frame split at 4 kHz
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BandFrame {
+    /// Power of every bin above DC
+    pub total: f64,
+    /// Power of the bins above 4 kHz
+    pub high: f64,
+    /// Telephone band power density over the guard band's, in dB
+    pub floor_snr_db: f64,
+}
+
+impl BandFrame {
+    /// Split a frame's bin powers, DC to Nyquist, `bin_hz` apart. `None`
+    /// for a frame that can't hold wideband content or has no energy
+    pub fn measure(powers: &[f64], bin_hz: f64) -> Option<Self> {
+        let nyquist = powers.len() as f64 * bin_hz;
+        if nyquist < 2.0 * NARROWBAND_EDGE_HZ {
+            return None;
+        }
+        let frequency = |bin: usize| bin as f64 * bin_hz;
+        let total: f64 = powers.iter().skip(1).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let high = powers
+            .iter()
+            .enumerate()
+            .filter(|&(bin, _)| frequency(bin) >= NARROWBAND_EDGE_HZ)
+            .map(|(_, p)| p)
+            .sum();
+
+        // Mean power per bin in a band
+        let density = |(low, high): (f64, f64)| {
+            let (sum, count) = powers
+                .iter()
+                .enumerate()
+                .filter(|&(bin, _)| (low..=high).contains(&frequency(bin)))
+                .fold((0.0, 0), |(sum, count), (_, p)| (sum + p, count + 1));
+            sum / f64::from(count.max(1))
+        };
+        let (signal, noise) = (density(VOICE_BAND_HZ), density(GUARD_BAND_HZ));
+        // An empty floor is as clean as the arithmetic gets
+        let floor_snr_db = if noise > 0.0 && signal > 0.0 { 10.0 * (signal / noise).log10() } else { f64::INFINITY };
+
+        Some(Self { total, high, floor_snr_db })
+    }
+}
+
+/// A page's split at 4 kHz, the figures it is judged by
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct BandSplit {
+    /// Energy above 4 kHz relative to the whole page's, in dB
+    pub high_band_db: f64,
+    /// Mean distance of the guard band's floor below the telephone band, in dB
+    pub noise_floor_snr_db: f64,
+    /// FFT frames measured
+    pub frames: u64,
+}
+
+impl BandSplit {
+    /// Whether the page looks like narrowband μ-law audio carried by a
+    /// wideband codec
+    pub fn suspect_narrowband(&self, thresholds: &TranscodingThresholds) -> bool {
+        self.high_band_db <= thresholds.max_high_band_db
+            && (thresholds.mulaw_min_snr_db..=thresholds.mulaw_max_snr_db).contains(&self.noise_floor_snr_db)
+    }
+}
+
+/// [`BandFrame`]s of a page's non-silent frames, added up
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BandStats {
+    total: f64,
+    high: f64,
+    /// Sum of the frames' floor SNRs that were finite
+    snr_sum: f64,
+    frames: u64,
+}
+
+impl BandStats {
+    pub fn add(&mut self, frame: &BandFrame) {
+        self.total += frame.total;
+        self.high += frame.high;
+        // A frame with an empty floor (digital silence between tones) says
+        // nothing about the noise
+        if frame.floor_snr_db.is_finite() {
+            self.snr_sum += frame.floor_snr_db;
+            self.frames += 1;
+        }
+    }
+
+    /// The page's split; `None` until enough frames have been measured
+    pub fn split(&self) -> Option<BandSplit> {
+        if self.frames < MIN_FRAMES || self.total <= 0.0 {
+            return None;
+        }
+        // The quietest high band a 16-bit page can show
+        let high_band_db = 10.0 * (self.high / self.total).max(1e-12).log10();
+        Some(BandSplit {
+            high_band_db,
+            noise_floor_snr_db: self.snr_sum / self.frames as f64,
+            frames: self.frames,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::audio_analyzer::{analyze_samples, AnalysisProfile};
+    use crate::cli::audio_input::resample;
+    use crate::codec::{AudioDecoder, AudioEncoder, G711UlawCodec};
+
+    /// A voice-like buzz: harmonics of 150 Hz up to `top_hz`, falling off
+    /// 6 dB an octave, swelling and fading four times a second
+    fn buzz(rate: u32, top_hz: f64, secs: f64) -> Vec<i16> {
+        let harmonics: Vec<f64> = (1..=(top_hz / 150.0) as u32).map(|h| 150.0 * f64::from(h)).collect();
+        (0..(f64::from(rate) * secs) as usize)
+            .map(|i| {
+                let t = i as f64 / f64::from(rate);
+                let swell = 0.6 + 0.4 * (2.0 * std::f64::consts::PI * 4.0 * t).sin();
+                let sum: f64 = harmonics.iter().map(|&f| (2.0 * std::f64::consts::PI * f * t).sin() * 150.0 / f).sum();
+                (sum * swell * 6000.0) as i16
+            })
+            .collect()
+    }
+
+    fn through_mulaw(samples: &[i16]) -> Vec<i16> {
+        let mut codec = G711UlawCodec::new();
+        let encoded = AudioEncoder::encode(&mut codec, samples).unwrap();
+        AudioDecoder::decode(&mut codec, &encoded).unwrap()
+    }
+
+    fn split(samples: &[i16], rate: u32) -> Option<BandSplit> {
+        analyze_samples(samples, rate, 1, AnalysisProfile::Speech).band_split()
+    }
+
+    #[test]
+    fn test_narrowband_hop_suspected() {
+        let thresholds = TranscodingThresholds::default();
+        let voice = buzz(8000, 3400.0, 3.0);
+
+        // Through an 8 kHz μ-law hop and back up to a wideband codec's rate
+        let hop = split(&resample(&through_mulaw(&voice), 8000, 16000), 16000).unwrap();
+        assert!(hop.high_band_db < -60.0, "{:?}", hop);
+        assert!(hop.suspect_narrowband(&thresholds), "{:?}", hop);
+
+        // Wideband all the way
+        let wide = split(&buzz(16000, 7000.0, 3.0), 16000).unwrap();
+        assert!(wide.high_band_db > -30.0, "{:?}", wide);
+        assert!(!wide.suspect_narrowband(&thresholds), "{:?}", wide);
+
+        // Narrowband content, but never quantized by μ-law: nothing above
+        // 4 kHz, and a floor far below what μ-law leaves
+        let clean = split(&resample(&voice, 8000, 16000), 16000).unwrap();
+        assert!(clean.high_band_db < -60.0, "{:?}", clean);
+        assert!(clean.noise_floor_snr_db > 55.0, "{:?}", clean);
+        assert!(!clean.suspect_narrowband(&thresholds), "{:?}", clean);
+
+        // Narrowband codecs have nothing to check
+        assert_eq!(split(&through_mulaw(&voice), 8000), None);
+        // ... and nor do pages too short to judge
+        assert_eq!(split(&resample(&through_mulaw(&voice[..1600]), 8000, 16000), 16000), None);
+    }
+}
@@ -1,16 +1,30 @@
-use crate::codec::{create_encoder, CodecType};
-use crate::network::{create_transmit_socket, RtpPacket};
+use crate::codec::{create_encoder, encode_all, CodecType};
+use crate::cli::audio_input::{
+    measure_file, read_audio_file, scan_file, AudioInput, AudioInputError, AudioLevels, ChannelSelect, LevelCheck,
+};
+use crate::cli::chime::{ChimeError, PageChime};
+use crate::cli::frame_cache::{self, encode_cached, CacheUsage, EncodeSpec, EncodedAudio, FrameCache, FrameCacheError};
+use crate::cli::frame_stream::{FrameSource, FrameStream, FrameStreamError, StreamSettings};
+use crate::cli::monitor::{output_json, JsonEvent};
+use crate::cli::plan::{bandwidth_kbps, ChimePlan, DestinationPlan, TransmitPlan, ZonePlan};
+use crate::cli::watermark;
+use crate::network::repair::{RepairServer, REPAIR_LINGER};
+use crate::network::{
+    create_transmit_socket, print_impairment_summary, Dscp, Impairer, ImpairmentConfig, ImpairmentError,
+    RtpPacket,
+};
+use crate::utils::schedule::Schedule;
+use crate::utils::sequence::{Sequence, SequenceError};
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use serde::Serialize;
 use std::io::{self, Write};
 use std::net::{Ipv4Addr, SocketAddrV4};
-use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::{Duration, Instant};
-use symphonia::core::audio::{AudioBufferRef, Signal};
-use symphonia::core::codecs::DecoderOptions;
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::{MediaSourceStream, MediaSourceStreamOptions};
-use symphonia::core::meta::MetadataOptions;
-use symphonia::core::probe::Hint;
 use thiserror::Error;
+use tracing::warn;
+use schemars::JsonSchema;
 
 #[derive(Error, Debug)]
 pub enum TransmitError {
@@ -21,63 +35,710 @@ pub enum TransmitError {
     #[error("File not found: {0}")]
     FileNotFound(String),
 
-    #[error("Unsupported audio format: {0}")]
-    UnsupportedFormat(String),
-
     #[error("Codec error: {0}")]
     Codec(#[from] crate::codec::CodecError),
 
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
 
-    #[error("Audio decode error: {0}")]
-    AudioDecode(String),
+    #[error(transparent)]
+    AudioInput(#[from] AudioInputError),
+
+    #[error("Impairment error: {0}")]
+    Impairment(#[from] ImpairmentError),
+
+    #[error("Frame cache error: {0}")]
+    FrameCache(#[from] FrameCacheError),
+
+    #[error("--sequence: {0}")]
+    Sequence(#[from] SequenceError),
+
+    #[error("--chime: {0}")]
+    Chime(#[from] ChimeError),
+
+    #[error(transparent)]
+    OutsideWindow(#[from] OutsideWindow),
+
+    #[error("--multi-zone: {0}")]
+    InvalidZone(String),
+
+    #[error("{0}. Use --allow-silent to send it anyway")]
+    SilentAudio(String),
+
+    #[error("Dry run found problems: {}", .0.join("; "))]
+    DryRunFailed(Vec<String>),
+
+    #[error("--repair: {0}")]
+    Repair(io::Error),
+}
+
+// A streamed file fails the same ways as one encoded up front
+impl From<FrameStreamError> for TransmitError {
+    fn from(error: FrameStreamError) -> Self {
+        match error {
+            FrameStreamError::Input(e) => Self::AudioInput(e),
+            FrameStreamError::Codec(e) => Self::Codec(e),
+            FrameStreamError::Io(e) => Self::Io(e),
+        }
+    }
+}
+
+impl TransmitError {
+    /// Stable code for the `transmit_failed` event
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::InvalidAddress(_) => "invalid_address",
+            Self::FileNotFound(_) => "file_not_found",
+            Self::Codec(_) => "codec",
+            Self::Io(_) => "io",
+            Self::AudioInput(_) => "audio_input",
+            Self::Impairment(_) => "invalid_impairment",
+            Self::FrameCache(_) => "frame_cache",
+            Self::Sequence(_) => "sequence",
+            Self::Chime(_) => "chime",
+            Self::OutsideWindow(_) => "outside_window",
+            Self::InvalidZone(_) => "invalid_zone",
+            Self::SilentAudio(_) => "silent_audio",
+            Self::DryRunFailed(_) => "dry_run_failed",
+            Self::Repair(_) => "repair",
+        }
+    }
+}
+
+/// A `--multi-zone` destination: one channel of the file paged to its own group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Zone {
+    pub channel: ChannelSelect,
+    pub address: Ipv4Addr,
+    pub port: u16,
+}
+
+impl FromStr for Zone {
+    type Err = TransmitError;
+
+    /// Parse `channel=address:port`, e.g. `left=224.0.1.1:5004`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| TransmitError::InvalidZone(format!("{} ({})", s, reason));
+
+        let (channel, endpoint) = s.split_once('=').ok_or_else(|| invalid("expected CHANNEL=ADDRESS:PORT"))?;
+        let channel = channel.parse().map_err(|_| invalid("bad channel"))?;
+        let (address, port) = endpoint
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| invalid("expected address:port"))?;
+        let address = address.parse().map_err(|_| invalid("bad address"))?;
+        let port = port.parse().map_err(|_| invalid("bad port"))?;
+
+        Ok(Self { channel, address, port })
+    }
+}
+
+/// Refusal to transmit outside the allowed windows
+#[derive(Error)]
+#[error("Outside allowed transmit windows ({windows}) at {now}; next window opens {next}. Use --wait-for-window to wait for it")]
+pub struct OutsideWindow {
+    pub windows: String,
+    pub now: String,
+    pub next: String,
+}
+
+// main reports errors with Debug formatting; keep the refusal readable there
+impl std::fmt::Debug for OutsideWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(self, f)
+    }
+}
+
+/// Time spent waiting for an allowed window, recorded in the transmit report
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct WindowWait {
+    pub started: DateTime<Utc>,
+    pub waited_secs: f64,
+}
+
+/// Longest single sleep while waiting for a window; the schedule is re-checked
+/// after each one so clock changes (including DST) are picked up
+const WINDOW_POLL_INTERVAL: Duration = Duration::from_mins(1);
+
+pub(crate) fn format_local(time: NaiveDateTime) -> String {
+    time.format("%a %Y-%m-%d %H:%M").to_string()
+}
+
+/// Check the allowed windows before a page, waiting for the next one to open
+/// if `wait` is set and refusing otherwise
+pub(crate) async fn enforce_schedule(
+    schedule: &Schedule,
+    wait: bool,
+    address: Ipv4Addr,
+    port: u16,
+    quiet: bool,
+    json: bool,
+) -> Result<Option<WindowWait>, OutsideWindow> {
+    let now = Local::now().naive_local();
+    if schedule.is_open(now) {
+        return Ok(None);
+    }
+
+    let next = schedule.next_open(now);
+    if !wait {
+        let refusal = OutsideWindow {
+            windows: schedule.to_string(),
+            now: format_local(now),
+            next: format_local(next),
+        };
+        if json {
+            output_json(&JsonEvent::TransmitRefused {
+                timestamp: Utc::now(),
+                address: address.to_string(),
+                port,
+                reason: refusal.to_string(),
+                next_window: refusal.next.clone(),
+            });
+        }
+        return Err(refusal);
+    }
+
+    if !quiet {
+        println!("  Outside allowed windows ({}); waiting until {}", schedule, format_local(next));
+    }
+
+    let started = Utc::now();
+    let wait_start = Instant::now();
+    loop {
+        let now = Local::now().naive_local();
+        if schedule.is_open(now) {
+            break;
+        }
+        let remaining = (schedule.next_open(now) - now).to_std().unwrap_or_default();
+        tokio::time::sleep(remaining.clamp(Duration::from_secs(1), WINDOW_POLL_INTERVAL)).await;
+    }
+
+    let waited_secs = wait_start.elapsed().as_secs_f64();
+    if !quiet {
+        println!("  Window open after waiting {:.0}s", waited_secs);
+    }
+    Ok(Some(WindowWait { started, waited_secs }))
+}
+
+/// Frames between `transmit_progress` events (one second of 20ms frames)
+pub(crate) const PROGRESS_FRAMES: usize = 50;
+
+/// The `--json` event stream of one transmit run: `transmit_started`, then
+/// `transmit_progress` every [`PROGRESS_FRAMES`], then `transmit_completed`
+/// or `transmit_failed`. Does nothing without `--json`.
+pub(crate) struct TransmitEvents {
+    enabled: bool,
+    address: Ipv4Addr,
+    port: u16,
+    /// Frames in one pass through the file
+    frames: usize,
+    pass: u32,
+    frames_sent: u64,
+    /// Latest any frame went out against its schedule
+    max_drift: Duration,
+    start: Instant,
+    warnings: Vec<String>,
+}
+
+impl TransmitEvents {
+    pub(crate) fn new(enabled: bool, address: Ipv4Addr, port: u16) -> Self {
+        Self {
+            enabled,
+            address,
+            port,
+            frames: 0,
+            pass: 0,
+            frames_sent: 0,
+            max_drift: Duration::ZERO,
+            start: Instant::now(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// The plan is ready and sending begins
+    pub(crate) fn started(&mut self, plan: &TransmitPlan) {
+        self.frames = plan.frames;
+        self.warnings.clone_from(&plan.warnings);
+        self.start = Instant::now();
+        if self.enabled {
+            output_json(&JsonEvent::TransmitStarted {
+                timestamp: Utc::now(),
+                address: self.address.to_string(),
+                port: self.port,
+                command: plan.command,
+                file: plan.file.clone(),
+                codec: plan.codec.clone(),
+                channel: plan.polycom.as_ref().map(|polycom| polycom.channel),
+                channel_name: plan.polycom.as_ref().and_then(|polycom| polycom.channel_name.clone()),
+                dscp: plan.destination.dscp,
+                frames: plan.frames,
+                estimated_secs: plan.polycom.as_ref().map_or(plan.audio_secs, |polycom| polycom.page_secs),
+                loop_audio: plan.loop_audio,
+            });
+        }
+    }
+
+    /// A pass through the file (one page) begins
+    pub(crate) fn next_pass(&mut self) {
+        self.pass += 1;
+    }
+
+    /// Frame `index` of this pass went out `drift` after its scheduled time
+    pub(crate) fn frame_sent(&mut self, index: usize, drift: Duration) {
+        self.max_drift = self.max_drift.max(drift);
+        self.frames_sent += 1;
+
+        let sent = index + 1;
+        if self.enabled && (sent.is_multiple_of(PROGRESS_FRAMES) || sent == self.frames) {
+            output_json(&JsonEvent::TransmitProgress {
+                timestamp: Utc::now(),
+                address: self.address.to_string(),
+                port: self.port,
+                pass: self.pass,
+                frames_sent: sent,
+                frames: self.frames,
+                percent: 100.0 * sent as f64 / self.frames.max(1) as f64,
+                drift_ms: drift.as_secs_f64() * 1000.0,
+                max_drift_ms: self.max_drift.as_secs_f64() * 1000.0,
+            });
+        }
+    }
+
+    /// A Polycom phase of this pass finished after `packets` packets
+    pub(crate) fn phase(&self, phase: &'static str, packets: u32) {
+        if self.enabled {
+            output_json(&JsonEvent::PolycomPhase {
+                timestamp: Utc::now(),
+                address: self.address.to_string(),
+                port: self.port,
+                pass: self.pass,
+                phase,
+                packets,
+            });
+        }
+    }
+
+    /// Everything was sent; follows the `transmit_report`
+    pub(crate) fn completed(&self, packets_sent: u64) {
+        if self.enabled {
+            output_json(&JsonEvent::TransmitCompleted {
+                timestamp: Utc::now(),
+                address: self.address.to_string(),
+                port: self.port,
+                passes: self.pass,
+                frames_sent: self.frames_sent,
+                packets_sent,
+                duration_secs: self.start.elapsed().as_secs_f64(),
+                max_drift_ms: self.max_drift.as_secs_f64() * 1000.0,
+                warnings: self.warnings.clone(),
+            });
+        }
+    }
+
+    /// The run stopped on an error, before or during sending
+    pub(crate) fn failed(&self, reason: &'static str, message: String) {
+        if self.enabled {
+            output_json(&JsonEvent::TransmitFailed {
+                timestamp: Utc::now(),
+                address: self.address.to_string(),
+                port: self.port,
+                reason,
+                message,
+                frames_sent: self.frames_sent,
+            });
+        }
+    }
+}
+
+/// What the page is made of
+pub enum PageSource {
+    File(PathBuf),
+    /// Synthesized from `--sequence`
+    Sequence(Sequence),
+}
+
+impl std::fmt::Display for PageSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PageSource::File(path) => write!(f, "{}", path.display()),
+            PageSource::Sequence(sequence) => {
+                write!(f, "test sequence ({} segments, {:.1}s)", sequence.segments.len(), sequence.secs())
+            }
+        }
+    }
 }
 
 pub struct TransmitOptions {
-    pub file: std::path::PathBuf,
+    pub source: PageSource,
+    /// Layout of headerless input files
+    pub input: AudioInput,
+    /// When the audio is too quiet to send
+    pub levels: LevelCheck,
     pub address: Ipv4Addr,
     pub port: u16,
     pub codec: CodecType,
     pub ttl: u8,
+    /// DSCP packets are marked with, if not the system default
+    pub dscp: Option<Dscp>,
     pub loop_audio: bool,
     pub quiet: bool,
+    /// Emit a JSON report when done
+    pub json: bool,
+    /// Simulated network impairments
+    pub impairment: ImpairmentConfig,
+    /// Local-time windows in which pages may be sent (unrestricted if empty)
+    pub schedule: Schedule,
+    /// Wait for the next allowed window instead of refusing
+    pub wait_for_window: bool,
+    /// Validate and print the plan without sending
+    pub dry_run: bool,
+    /// Directory of encoded frames to reuse across runs
+    pub cache_dir: Option<std::path::PathBuf>,
+    /// Page each of these channels to its own group instead of `address`
+    /// (which then names the first zone)
+    pub zones: Vec<Zone>,
+    /// Packets kept for answering `--repair` receivers (off if `None`)
+    pub repair_buffer: Option<usize>,
+    /// Advance G.722 timestamps at 16kHz, for receivers that don't follow RFC 3551
+    pub g722_nonstandard_timestamps: bool,
+    /// ID marked into the audio for receivers to detect, if any
+    pub watermark: Option<u16>,
+    /// Level of the watermark tone, in dBFS
+    pub watermark_level: f64,
+    /// Chime sent ahead of the audio, if any
+    pub chime: Option<PageChime>,
+    /// SSRC to send with, from `--ssrc`; random if `None`
+    pub ssrc: Option<u32>,
+}
+
+/// One encoded stream and where it goes
+struct PreparedStream {
+    dest: SocketAddrV4,
+    frames: FrameSource,
+}
+
+/// The file encoded for sending, or ready to be encoded as it is sent
+struct PreparedAudio {
+    /// The page, or one stream per `--multi-zone` channel, all on one clock
+    streams: Vec<PreparedStream>,
+    /// Frames in each stream; every channel of a file is the same length
+    frames: usize,
+    /// Samples the frames are encoded from, before padding the last one
+    samples: usize,
+    frame_size: usize,
+    sample_rate: u32,
+    /// The `--chime` ahead of the audio, counted in `samples`
+    chime: Option<ChimePlan>,
+    /// Frame cache hits and misses, with `--cache-dir`
+    cache_usage: Option<CacheUsage>,
 }
 
 /// Run the transmit command
 pub async fn run_transmit(options: TransmitOptions) -> Result<(), TransmitError> {
-    if !options.file.exists() {
-        return Err(TransmitError::FileNotFound(
-            options.file.to_string_lossy().to_string(),
-        ));
+    // A dry run reports through its plan instead
+    let mut events = TransmitEvents::new(options.json && !options.dry_run, options.address, options.port);
+    let result = transmit(&options, &mut events).await;
+    if let Err(ref error) = result {
+        events.failed(error.reason(), error.to_string());
     }
+    result
+}
 
-    // Create transmit socket
-    let socket = create_transmit_socket(options.ttl).await?;
-    let dest = SocketAddrV4::new(options.address, options.port);
+async fn transmit(options: &TransmitOptions, events: &mut TransmitEvents) -> Result<(), TransmitError> {
+    let (plan, audio) = plan_transmit(options)?;
+    if options.dry_run {
+        return plan.finish_dry_run(options.json).map_err(TransmitError::DryRunFailed);
+    }
+    for warning in &plan.warnings {
+        warn!("{}", warning);
+    }
+    events.started(&plan);
+    execute_transmit(options, audio, events).await
+}
 
-    // Create encoder
-    let mut encoder = create_encoder(options.codec)?;
+/// Decode and check everything without sending
+fn plan_transmit(options: &TransmitOptions) -> Result<(TransmitPlan, PreparedAudio), TransmitError> {
+    if let PageSource::File(file) = &options.source {
+        if !file.exists() {
+            return Err(TransmitError::FileNotFound(file.to_string_lossy().to_string()));
+        }
+    }
+
+    options.impairment.validate()?;
+    // A held-back or duplicated packet can't be matched to one zone's stream
+    if !options.zones.is_empty() && options.impairment.is_active() {
+        return Err(TransmitError::InvalidZone("impairments need a single destination".to_string()));
+    }
+
+    let encoder = create_encoder(options.codec)?;
     let frame_size = encoder.frame_size();
     let sample_rate = encoder.sample_rate();
+    // The chime is made at the codec's rate and encoded with the audio
+    let (lead_in, chime) = match &options.chime {
+        Some(chime) => {
+            let (lead_in, plan) = chime.lead_in(sample_rate)?;
+            (lead_in, Some(plan))
+        }
+        None => (Vec::new(), None),
+    };
+
+    // With a cache the whole file is encoded before sending, so a cached
+    // copy skips the work; without one it is encoded as it is sent
+    let cache = options.cache_dir.as_deref().map(FrameCache::open).transpose()?;
+    let mut cache_usage = CacheUsage::default();
+    let destinations = if options.zones.is_empty() {
+        vec![(options.input, SocketAddrV4::new(options.address, options.port))]
+    } else {
+        options
+            .zones
+            .iter()
+            .map(|zone| {
+                let input = AudioInput {
+                    channel: zone.channel,
+                    ..options.input
+                };
+                (input, SocketAddrV4::new(zone.address, zone.port))
+            })
+            .collect()
+    };
+    let mut streams = Vec::with_capacity(destinations.len());
+    let mut levels = Vec::with_capacity(destinations.len());
+    let mut samples = 0;
+    for (input, dest) in destinations {
+        let audio = match &options.source {
+            PageSource::File(file) => {
+                let spec = EncodeSpec {
+                    source: file,
+                    codec: options.codec,
+                    encoder: if options.codec == CodecType::G722 { frame_cache::FFMPEG } else { frame_cache::BUILTIN },
+                    sample_rate,
+                    frame_samples: frame_size,
+                    input,
+                };
+                if cache.is_none() {
+                    // Read through once for the plan, then again as it's sent
+                    let scan = scan_file(file, sample_rate, &input)?;
+                    levels.push(scan.levels);
+                    samples = lead_in.len() + scan.samples;
+                    let settings = StreamSettings {
+                        loop_audio: options.loop_audio,
+                        limit: None,
+                        watermark: options.watermark.map(|id| (id, options.watermark_level)),
+                    };
+                    let frames = FrameSource::Streamed(FrameStream::encode(&spec, lead_in.clone(), settings)?);
+                    streams.push(PreparedStream { dest, frames });
+                    continue;
+                }
+                // --chime conflicts with --cache-dir, whose entries are the
+                // file's frames alone
+                let audio = encode_cached(cache.as_ref(), &spec, &mut cache_usage, || {
+                    let mut samples = read_audio_file(file, sample_rate, &input)?;
+                    let measured = AudioLevels::measure(&samples, sample_rate);
+                    mark(options, &mut samples, sample_rate);
+                    encode_stream(options.codec, &samples).map(|audio| audio.with_levels(measured))
+                })?;
+                match audio.levels {
+                    Some(measured) => levels.push(measured),
+                    None => levels.push(measure_file(file, sample_rate, &input)?),
+                }
+                audio
+            }
+            // Synthesizing is cheaper than a cache lookup
+            PageSource::Sequence(sequence) => {
+                let sequence = sequence.synthesize(sample_rate)?;
+                levels.push(AudioLevels::measure(&sequence, sample_rate));
+                let mut samples = [lead_in.as_slice(), &sequence].concat();
+                mark(options, &mut samples, sample_rate);
+                encode_stream(options.codec, &samples)?
+            }
+        };
+        samples = audio.samples;
+        streams.push(PreparedStream {
+            dest,
+            frames: FrameSource::encoded(audio.frames),
+        });
+    }
+    let frames = samples.div_ceil(frame_size);
+
+    let first_payload = match &mut streams[0].frames {
+        FrameSource::Encoded { frames, .. } => frames.first().cloned(),
+        FrameSource::Streamed(stream) => stream.peek()?.map(<[u8]>::to_vec),
+    };
+    let packet_bytes =
+        RtpPacket::build(options.codec.payload_type(), 0, 0, 0, &first_payload.unwrap_or_default(), false).len();
+    let frame_ms = (frame_size as u64 * 1000 / u64::from(sample_rate)) as u32;
+
+    let mut plan = TransmitPlan {
+        command: "transmit",
+        file: options.source.to_string(),
+        destination: DestinationPlan::new(options.address, options.port, options.ttl, options.dscp),
+        codec: options.codec.name().to_string(),
+        payload_type: Some(options.codec.payload_type()),
+        sample_rate,
+        frame_ms,
+        frames,
+        audio_secs: samples as f64 / f64::from(sample_rate),
+        chime: chime.clone(),
+        levels: None,
+        packet_bytes,
+        bandwidth_kbps: bandwidth_kbps(packet_bytes, frame_ms),
+        loop_audio: options.loop_audio,
+        schedule: None,
+        polycom: None,
+        also_rtp: Vec::new(),
+        zones: Vec::new(),
+        warnings: Vec::new(),
+        problems: Vec::new(),
+    };
+    if let PageSource::File(file) = &options.source {
+        if let Some(notice) = options.input.inferred_notice(file) {
+            plan.warnings.push(notice);
+        }
+    }
+    plan.check_destination();
+    let mut silent = None;
+    if options.zones.is_empty() {
+        silent = plan.check_levels(levels[0], options.levels);
+    }
+    for (i, zone) in options.zones.iter().enumerate() {
+        plan.add_zone(ZonePlan::new(zone.channel.to_string(), zone.address, zone.port));
+        let zone_silent = plan.check_zone_levels(i, levels[i], options.levels);
+        silent = silent.or(zone_silent);
+    }
+    plan.check_schedule(&options.schedule, options.wait_for_window);
+    // A dry run reports it as a problem instead
+    if let Some(reason) = silent.filter(|_| !options.dry_run) {
+        return Err(TransmitError::SilentAudio(reason));
+    }
+
+    Ok((
+        plan,
+        PreparedAudio {
+            streams,
+            frames,
+            samples,
+            frame_size,
+            sample_rate,
+            chime,
+            cache_usage: cache.is_some().then_some(cache_usage),
+        },
+    ))
+}
+
+/// Mix the `--watermark` ID into samples about to be encoded. Levels are
+/// measured before, so neither the marker nor a `--chime` can make silent
+/// audio look sendable
+fn mark(options: &TransmitOptions, samples: &mut [i16], sample_rate: u32) {
+    if let Some(id) = options.watermark {
+        watermark::embed(samples, sample_rate, id, options.watermark_level);
+    }
+}
+
+/// Encode a whole file's samples into frames, padding the last one
+fn encode_stream(codec: CodecType, samples: &[i16]) -> Result<EncodedAudio, TransmitError> {
+    Ok(EncodedAudio {
+        frames: encode_all(codec, samples)?,
+        samples: samples.len(),
+        levels: None,
+    })
+}
+
+/// Send the planned page
+async fn execute_transmit(
+    options: &TransmitOptions,
+    audio: PreparedAudio,
+    events: &mut TransmitEvents,
+) -> Result<(), TransmitError> {
+    let PreparedAudio {
+        mut streams,
+        frames: frame_count,
+        samples,
+        frame_size,
+        sample_rate,
+        chime,
+        cache_usage,
+    } = audio;
+
+    // Create transmit socket
+    let socket = create_transmit_socket(options.ttl, options.dscp).await?;
+
+    // Impairments are applied to built packets, so the RTP stream itself stays truthful
+    let frame_duration = Duration::from_secs_f64(frame_size as f64 / sample_rate as f64);
+    let frame_ticks = frame_timestamp_step(options.codec, frame_size, sample_rate, options.g722_nonstandard_timestamps);
+    let mut impairer = options
+        .impairment
+        .is_active()
+        .then(|| Impairer::new(options.impairment.clone(), frame_duration));
+    let mut repair = match options.repair_buffer {
+        Some(capacity) => Some(
+            RepairServer::bind(streams.iter().map(|stream| stream.dest.port()), capacity)
+                .await
+                .map_err(TransmitError::Repair)?,
+        ),
+        None => None,
+    };
+    let mut packets_sent: u64 = 0;
+    let mut window_waits: Vec<WindowWait> = Vec::new();
+    let transmit_start = Instant::now();
 
     if !options.quiet {
-        println!("Transmitting {} to {}:{}", options.file.display(), options.address, options.port);
+        if options.zones.is_empty() {
+            println!("Transmitting {} to {}:{}", options.source, options.address, options.port);
+        } else {
+            println!("Transmitting {} to {} zones", options.source, options.zones.len());
+            for zone in &options.zones {
+                println!("  Zone {}: {}:{}", zone.channel, zone.address, zone.port);
+            }
+        }
         println!("  Codec: {}", options.codec.name());
+        if options.codec == CodecType::G722 && options.g722_nonstandard_timestamps {
+            println!("  RTP clock: {}Hz (nonstandard)", sample_rate);
+        }
         println!("  TTL: {}", options.ttl);
+        if let Some(dscp) = options.dscp {
+            println!("  DSCP: {}", dscp);
+        }
+        if let Some(ssrc) = options.ssrc {
+            println!("  SSRC: {:#010x}", ssrc);
+        }
+        if let Some(id) = options.watermark {
+            println!("  Watermark: ID {} at {} dBFS", id, options.watermark_level);
+        }
+        if let Some(ref chime) = chime {
+            println!("  Chime: {} ({:.2}s, then {:.2}s of silence)", chime.chime, chime.chime_secs, chime.gap_secs);
+        }
+        if options.schedule.is_restricted() {
+            println!("  Allowed windows: {}", options.schedule);
+        }
+        if let Some(usage) = cache_usage {
+            print_cache_usage(&usage);
+        }
         println!();
     }
 
-    // Generate a random SSRC
-    let ssrc: u32 = rand_ssrc();
+    // An SSRC per stream, so each zone is a stream of its own
+    let base_ssrc = options.ssrc.unwrap_or_else(rand_ssrc);
+    let ssrcs: Vec<u32> = (0..streams.len()).map(|i| base_ssrc.wrapping_add(i as u32)).collect();
 
     loop {
-        // Read and decode the audio file
-        let samples = read_audio_file(&options.file, sample_rate)?;
+        // Every page, including each loop iteration, must start inside an allowed window
+        if let Some(wait) = enforce_schedule(
+            &options.schedule,
+            options.wait_for_window,
+            options.address,
+            options.port,
+            options.quiet,
+            options.json,
+        )
+        .await?
+        {
+            window_waits.push(wait);
+        }
+        events.next_pass();
 
         if !options.quiet {
-            let duration = samples.len() as f64 / sample_rate as f64;
-            println!("  Duration: {:.1}s ({} samples)", duration, samples.len());
+            let duration = samples as f64 / sample_rate as f64;
+            println!("  Duration: {:.1}s ({} frames)", duration, frame_count);
         }
 
         // Transmit
@@ -85,53 +746,84 @@ pub async fn run_transmit(options: TransmitOptions) -> Result<(), TransmitError>
         let mut timestamp: u32 = 0;
         let mut samples_sent = 0;
         let start = Instant::now();
+        let mut index = 0;
 
-        // Frame duration in seconds (for potential future pacing)
-        let _frame_duration = Duration::from_secs_f64(frame_size as f64 / sample_rate as f64);
-
-        for chunk in samples.chunks(frame_size) {
-            // Pad last chunk if needed
-            let frame: Vec<i16> = if chunk.len() < frame_size {
-                let mut padded = chunk.to_vec();
-                padded.resize(frame_size, 0);
-                padded
-            } else {
-                chunk.to_vec()
-            };
-
-            // Encode
-            let encoded = encoder.encode(&frame)?;
-
-            // Build RTP packet
-            let packet = RtpPacket::build(
-                options.codec.payload_type(),
-                sequence,
-                timestamp,
-                ssrc,
-                &encoded,
-                false,
-            );
+        loop {
+            // A streamed frame that isn't ready yet counts as drift
+            let mut frames = Vec::with_capacity(streams.len());
+            for stream in &mut streams {
+                frames.push(stream.frames.next_frame()?);
+            }
+            // Every channel of a file is the same length, so the first ends the pass
+            if frames[0].is_none() {
+                break;
+            }
 
-            // Send
-            socket.send_to(&packet, dest).await?;
+            // Pacing drift is measured before any simulated jitter, which is deliberate
+            let due = start + Duration::from_secs_f64(samples_sent as f64 / sample_rate as f64);
+            let drift = Instant::now().saturating_duration_since(due);
 
+            if let Some(ref mut impairer) = impairer {
+                let delay = impairer.jitter();
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+
+            // Every zone's frame goes out on the same tick
+            for ((stream, &ssrc), frame) in streams.iter().zip(&ssrcs).zip(&frames) {
+                let Some(encoded) = frame else {
+                    continue;
+                };
+                let packet = RtpPacket::build(
+                    options.codec.payload_type(),
+                    sequence,
+                    timestamp,
+                    ssrc,
+                    encoded,
+                    false,
+                );
+                if let Some(ref mut repair) = repair {
+                    repair.record(&packet);
+                }
+
+                // Send (through the impairer if configured; only with a single stream)
+                if let Some(ref mut impairer) = impairer {
+                    for wire_packet in impairer.process(packet) {
+                        socket.send_to(&wire_packet, stream.dest).await?;
+                        packets_sent += 1;
+                    }
+                } else {
+                    socket.send_to(&packet, stream.dest).await?;
+                    packets_sent += 1;
+                }
+            }
+
+            if let Some(ref mut repair) = repair {
+                repair.serve();
+            }
+            events.frame_sent(index, drift);
             sequence = sequence.wrapping_add(1);
-            timestamp = timestamp.wrapping_add(frame_size as u32);
-            samples_sent += chunk.len();
+            timestamp = timestamp.wrapping_add(frame_ticks);
+            samples_sent += frame_size;
 
             // Rate limiting - sleep to maintain real-time pace
             let expected_time = Duration::from_secs_f64(samples_sent as f64 / sample_rate as f64);
-            let elapsed = start.elapsed();
-            if expected_time > elapsed {
-                tokio::time::sleep(expected_time - elapsed).await;
+            let wait = expected_time.saturating_sub(start.elapsed());
+            if !wait.is_zero() {
+                tokio::time::sleep(wait).await;
             }
 
             // Progress update
             if !options.quiet && sequence.is_multiple_of(50) {
-                let progress = 100.0 * samples_sent as f64 / samples.len() as f64;
+                let progress = 100.0 * (index + 1) as f64 / frame_count as f64;
                 print!("\r  Progress: {:.1}%   ", progress);
                 io::stdout().flush().ok();
             }
+            index += 1;
+        }
+        for stream in &mut streams {
+            stream.frames.finish_pass()?;
         }
 
         if !options.quiet {
@@ -147,156 +839,74 @@ pub async fn run_transmit(options: TransmitOptions) -> Result<(), TransmitError>
         }
     }
 
-    Ok(())
-}
-
-/// Read an audio file and return samples at the target sample rate
-fn read_audio_file(path: &Path, target_rate: u32) -> Result<Vec<i16>, TransmitError> {
-    let file = std::fs::File::open(path)?;
-    let mss = MediaSourceStream::new(Box::new(file), MediaSourceStreamOptions::default());
-
-    let mut hint = Hint::new();
-    if let Some(ext) = path.extension() {
-        hint.with_extension(&ext.to_string_lossy());
+    // Release a packet still held back for reordering
+    if let Some(packet) = impairer.as_mut().and_then(Impairer::flush) {
+        socket.send_to(&packet, streams[0].dest).await?;
+        packets_sent += 1;
     }
 
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
-        .map_err(|e| TransmitError::UnsupportedFormat(e.to_string()))?;
-
-    let mut format = probed.format;
-
-    let track = format
-        .tracks()
-        .iter()
-        .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
-        .ok_or_else(|| TransmitError::UnsupportedFormat("No audio track found".into()))?;
-
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &DecoderOptions::default())
-        .map_err(|e| TransmitError::UnsupportedFormat(e.to_string()))?;
-
-    let track_id = track.id;
-    let source_rate = track.codec_params.sample_rate.unwrap_or(target_rate);
-    let channels = track.codec_params.channels.map(|c| c.count()).unwrap_or(1);
-
-    let mut samples: Vec<i16> = Vec::new();
-
-    loop {
-        let packet = match format.next_packet() {
-            Ok(p) => p,
-            Err(symphonia::core::errors::Error::IoError(_)) => break, // EOF
-            Err(e) => return Err(TransmitError::AudioDecode(e.to_string())),
-        };
-
-        if packet.track_id() != track_id {
-            continue;
-        }
-
-        let decoded = decoder
-            .decode(&packet)
-            .map_err(|e| TransmitError::AudioDecode(e.to_string()))?;
-
-        // Convert to i16 samples
-        let frame_samples = convert_to_i16(&decoded);
-
-        // Mix to mono if stereo
-        let mono_samples: Vec<i16> = if channels > 1 {
-            frame_samples
-                .chunks(channels)
-                .map(|chunk| {
-                    let sum: i32 = chunk.iter().map(|&s| s as i32).sum();
-                    (sum / channels as i32) as i16
-                })
-                .collect()
-        } else {
-            frame_samples
-        };
-
-        samples.extend(mono_samples);
+    // Losses near the end are noticed a repair window after the last packet
+    if let Some(ref mut repair) = repair {
+        repair.linger(REPAIR_LINGER).await;
     }
 
-    // Resample if needed
-    if source_rate != target_rate {
-        samples = simple_resample(&samples, source_rate, target_rate);
+    let impairments = impairer.as_ref().map(Impairer::stats);
+    let retransmits = repair.as_ref().map(RepairServer::stats);
+    if options.json {
+        output_json(&JsonEvent::TransmitReport {
+            timestamp: Utc::now(),
+            address: options.address.to_string(),
+            port: options.port,
+            codec: options.codec.name().to_string(),
+            packets_sent,
+            duration_secs: transmit_start.elapsed().as_secs_f64(),
+            chime,
+            impairments,
+            window_waits,
+            also_rtp: Vec::new(),
+            frame_cache: cache_usage,
+            retransmits,
+        });
+    } else if !options.quiet {
+        if let Some(ref stats) = impairments {
+            print_impairment_summary(stats);
+        }
+        if let Some(stats) = retransmits {
+            println!(
+                "  Repair: {} NACKs, {} packets requested, {} resent, {} no longer buffered",
+                stats.nacks_received, stats.packets_requested, stats.packets_resent, stats.packets_unavailable
+            );
+        }
     }
+    events.completed(packets_sent);
 
-    Ok(samples)
+    Ok(())
 }
 
-/// Convert audio buffer to i16 samples
-fn convert_to_i16(buffer: &AudioBufferRef) -> Vec<i16> {
-    match buffer {
-        AudioBufferRef::S8(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| (s as i16) * 256)
-            .collect(),
-        AudioBufferRef::S16(buf) => buf.chan(0).to_vec(),
-        AudioBufferRef::S32(buf) => buf.chan(0).iter().map(|&s| (s >> 16) as i16).collect(),
-        AudioBufferRef::F32(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| (s * 32767.0).clamp(-32768.0, 32767.0) as i16)
-            .collect(),
-        AudioBufferRef::F64(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| (s * 32767.0).clamp(-32768.0, 32767.0) as i16)
-            .collect(),
-        AudioBufferRef::U8(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| ((s as i16 - 128) * 256))
-            .collect(),
-        AudioBufferRef::U16(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| (s as i32 - 32768) as i16)
-            .collect(),
-        AudioBufferRef::U24(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| ((s.inner() as i32 - 8_388_608) >> 8) as i16)
-            .collect(),
-        AudioBufferRef::S24(buf) => buf
-            .chan(0)
-            .iter()
-            .map(|&s| (s.inner() >> 8) as i16)
-            .collect(),
-        AudioBufferRef::U32(buf) => buf
-            .chan(0)
-            .iter()
-            // Convert unsigned 32-bit to signed 16-bit: subtract 2^31 to center, then shift
-            .map(|&s| ((s as i64 - (1_i64 << 31)) >> 16) as i16)
-            .collect(),
+/// One line on where the frames came from
+pub(crate) fn print_cache_usage(usage: &CacheUsage) {
+    match (usage.hits, usage.misses) {
+        (_, 0) => println!("  Frame cache: hit, encoding skipped"),
+        (0, _) => println!("  Frame cache: miss, encoded in {:.2}s and stored", usage.encode_secs),
+        (hits, misses) => println!(
+            "  Frame cache: {} streams cached, {} encoded in {:.2}s and stored",
+            hits, misses, usage.encode_secs
+        ),
     }
 }
 
-/// Simple linear interpolation resampling
-fn simple_resample(samples: &[i16], from_rate: u32, to_rate: u32) -> Vec<i16> {
-    let ratio = from_rate as f64 / to_rate as f64;
-    let new_len = (samples.len() as f64 / ratio) as usize;
-
-    (0..new_len)
-        .map(|i| {
-            let pos = i as f64 * ratio;
-            let idx = pos.floor() as usize;
-            let frac = pos.fract();
-
-            if idx + 1 >= samples.len() {
-                samples[idx.min(samples.len() - 1)]
-            } else {
-                let a = samples[idx] as f64;
-                let b = samples[idx + 1] as f64;
-                (a + (b - a) * frac) as i16
-            }
-        })
-        .collect()
+/// RTP timestamp ticks per frame: a sample each, except that G.722 keeps
+/// the 8kHz clock of RFC 3551 though it samples at 16kHz, unless
+/// `g722_nonstandard` asks for the sample rate as some receivers expect
+fn frame_timestamp_step(codec: CodecType, frame_size: usize, sample_rate: u32, g722_nonstandard: bool) -> u32 {
+    if codec != CodecType::G722 || g722_nonstandard {
+        return frame_size as u32;
+    }
+    (frame_size as u64 * u64::from(codec.rtp_clock_rate()) / u64::from(sample_rate.max(1))) as u32
 }
 
 /// Generate a random SSRC
-fn rand_ssrc() -> u32 {
+pub(crate) fn rand_ssrc() -> u32 {
     use std::time::SystemTime;
     let seed = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
@@ -312,15 +922,147 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_simple_resample() {
-        let samples: Vec<i16> = vec![0, 100, 200, 300, 400, 500, 600, 700];
+    fn test_plan_counts_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("page.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&file, spec).unwrap();
+        // 2.01s: the last partial frame is padded into a frame of its own
+        for i in 0..16_080 {
+            writer.write_sample((i % 100) as i16 * 100).unwrap();
+        }
+        writer.finalize().unwrap();
 
-        // Downsample 2:1
-        let resampled = simple_resample(&samples, 16000, 8000);
-        assert_eq!(resampled.len(), 4);
+        let options = TransmitOptions {
+            source: PageSource::File(file),
+            input: AudioInput::default(),
+            levels: LevelCheck::default(),
+            address: Ipv4Addr::LOCALHOST,
+            port: 5004,
+            codec: CodecType::G711Ulaw,
+            ttl: 0,
+            dscp: None,
+            loop_audio: false,
+            quiet: true,
+            json: false,
+            impairment: ImpairmentConfig::default(),
+            schedule: Schedule::default(),
+            wait_for_window: false,
+            dry_run: true,
+            cache_dir: None,
+            zones: Vec::new(),
+            repair_buffer: None,
+            g722_nonstandard_timestamps: false,
+            watermark: None,
+            watermark_level: watermark::DEFAULT_LEVEL_DBFS,
+            chime: None,
+            ssrc: None,
+        };
+        let (plan, audio) = plan_transmit(&options).unwrap();
+        assert_eq!(plan.frames, 101);
+        assert_eq!(audio.frames, 101);
+        assert_eq!(audio.samples, 16_080);
+        assert_eq!(plan.packet_bytes, 172);
+        assert_eq!(plan.payload_type, Some(0));
+        assert_eq!(plan.problems.len(), 1, "TTL 0 should be the only problem: {:?}", plan.problems);
+    }
+
+    #[test]
+    fn test_g722_timestamp_step() {
+        // 20ms frames
+        assert_eq!(frame_timestamp_step(CodecType::G722, 320, 16000, false), 160);
+        assert_eq!(frame_timestamp_step(CodecType::G722, 320, 16000, true), 320);
+        assert_eq!(frame_timestamp_step(CodecType::G711Ulaw, 160, 8000, false), 160);
+        assert_eq!(frame_timestamp_step(CodecType::L16, 960, 48000, true), 960);
+    }
+
+    /// Options to send two seconds of 1kHz tone whose RMS is `dbfs`
+    fn tone_options(dir: &std::path::Path, dbfs: f64) -> TransmitOptions {
+        let file = dir.join("tone.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let amplitude = 32768.0 * 10f64.powf(dbfs / 20.0) * std::f64::consts::SQRT_2;
+        let mut writer = hound::WavWriter::create(&file, spec).unwrap();
+        for i in 0..16_000 {
+            let phase = 2.0 * std::f64::consts::PI * 1000.0 * f64::from(i) / 8000.0;
+            writer.write_sample((amplitude * phase.sin()) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        TransmitOptions {
+            source: PageSource::File(file),
+            input: AudioInput::default(),
+            levels: LevelCheck::default(),
+            address: Ipv4Addr::LOCALHOST,
+            port: 5004,
+            codec: CodecType::G711Ulaw,
+            ttl: 1,
+            dscp: None,
+            loop_audio: false,
+            quiet: true,
+            json: false,
+            impairment: ImpairmentConfig::default(),
+            schedule: Schedule::default(),
+            wait_for_window: false,
+            dry_run: false,
+            cache_dir: None,
+            zones: Vec::new(),
+            repair_buffer: None,
+            g722_nonstandard_timestamps: false,
+            watermark: None,
+            watermark_level: watermark::DEFAULT_LEVEL_DBFS,
+            chime: None,
+            ssrc: None,
+        }
+    }
+
+    #[test]
+    fn test_silent_audio_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut options = tone_options(dir.path(), -60.0);
+        match plan_transmit(&options) {
+            Err(TransmitError::SilentAudio(reason)) => assert!(reason.contains("below -45.0dB"), "{}", reason),
+            other => panic!("expected SilentAudio, got {:?}", other.map(|(plan, _)| plan)),
+        }
+
+        // A dry run lists it as a problem
+        options.dry_run = true;
+        let (plan, _) = plan_transmit(&options).unwrap();
+        assert!(plan.problems.iter().any(|p| p.contains("effectively silent")), "{:?}", plan.problems);
+
+        options.dry_run = false;
+        options.levels.allow_silent = true;
+        let (plan, _) = plan_transmit(&options).unwrap();
+        let levels = plan.levels.unwrap();
+        assert!((levels.rms_dbfs - -60.0).abs() < 1.0, "{}", levels);
+        assert!(plan.problems.is_empty(), "{:?}", plan.problems);
+        assert!(plan.warnings.iter().any(|w| w.contains("--allow-silent")), "{:?}", plan.warnings);
+    }
+
+    #[test]
+    fn test_levels_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let (plan, _) = plan_transmit(&tone_options(dir.path(), -20.0)).unwrap();
+        let levels = plan.levels.unwrap();
+        assert!((levels.rms_dbfs - -20.0).abs() < 1.0, "{}", levels);
+        assert!((levels.peak_dbfs - -17.0).abs() < 1.0, "{}", levels);
+        assert!(levels.silent_percent.abs() < 1e-9);
+        // Only the unicast destination is warned about
+        assert_eq!(plan.warnings.len(), 1, "{:?}", plan.warnings);
+        assert!(plan.problems.is_empty(), "{:?}", plan.problems);
 
-        // Upsample 1:2
-        let resampled = simple_resample(&samples, 8000, 16000);
-        assert_eq!(resampled.len(), 16);
+        // Full scale is warned about, not refused
+        let (plan, _) = plan_transmit(&tone_options(dir.path(), -3.0)).unwrap();
+        assert!(plan.warnings.iter().any(|w| w.contains("may clip")), "{:?}", plan.warnings);
+        assert!(plan.problems.is_empty(), "{:?}", plan.problems);
     }
 }
@@ -0,0 +1,315 @@
+//! Time-of-day trend aggregates for long test runs.
+//!
+//! `test --aggregate FILE` folds every finished page into a small JSON file
+//! keyed by endpoint and local hour of day, so a run left going for weeks
+//! can answer "is it worse at 8 AM?" without replaying the metrics log.
+//! Each bucket keeps counts plus a streaming mean, spread and 95th
+//! percentile per metric (see [`crate::utils::streaming`]), and the file is
+//! replaced atomically after each page so a restart picks up where the last
+//! run left off. `review --trends FILE` renders it.
+
+use crate::cli::test::PageSummary;
+use crate::utils::streaming::{P2Quantile, RunningStats};
+use chrono::{DateTime, Local, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Format version written to the file; older or newer files are refused
+pub const TRENDS_VERSION: u32 = 1;
+
+/// How many standard deviations above the endpoint's mean flags an hour
+pub const OUTLIER_SIGMA: f64 = 2.0;
+
+#[derive(Error, Debug)]
+pub enum TrendError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Unsupported aggregate file version {0} (expected {TRENDS_VERSION})")]
+    UnsupportedVersion(u32),
+}
+
+/// Mean, spread and 95th percentile of one metric
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Distribution {
+    pub stats: RunningStats,
+    p95: P2Quantile,
+}
+
+impl Default for Distribution {
+    fn default() -> Self {
+        Self {
+            stats: RunningStats::default(),
+            p95: P2Quantile::new(0.95),
+        }
+    }
+}
+
+impl Distribution {
+    pub fn push(&mut self, value: f64) {
+        self.stats.push(value);
+        self.p95.push(value);
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.stats.count > 0).then_some(self.stats.mean)
+    }
+
+    pub fn p95(&self) -> Option<f64> {
+        self.p95.value()
+    }
+
+    /// Whether `other`'s mean is more than [`OUTLIER_SIGMA`] standard
+    /// deviations above this one's
+    pub fn is_exceeded_by(&self, other: &Self) -> bool {
+        let sigma = self.stats.std_dev();
+        match other.mean() {
+            Some(mean) if sigma > 0.0 => mean > self.stats.mean + OUTLIER_SIGMA * sigma,
+            _ => false,
+        }
+    }
+}
+
+/// Pages that started in one hour of the day, or all of an endpoint's pages
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HourBucket {
+    pub pages: u64,
+    pub duration_secs: f64,
+    pub loss_percent: Distribution,
+    pub jitter_ms: Distribution,
+    /// Average RMS of pages that weren't silent
+    pub rms_db: Distribution,
+    pub glitches: u64,
+    /// Glitches per minute of page
+    pub glitch_rate: Distribution,
+}
+
+impl HourBucket {
+    fn record(&mut self, page: &PageSummary) {
+        self.pages += 1;
+        self.duration_secs += page.duration_secs;
+        self.loss_percent.push(page.network.loss_percent);
+        self.jitter_ms.push(page.network.jitter_ms);
+        if let Some(rms) = page.audio.avg_rms_db {
+            self.rms_db.push(rms);
+        }
+        self.glitches += page.audio.total_glitches;
+        if page.duration_secs > 0.0 {
+            self.glitch_rate.push(page.audio.total_glitches as f64 * 60.0 / page.duration_secs);
+        }
+    }
+}
+
+/// What stands out about an hour against its endpoint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HourFlags {
+    pub loss: bool,
+    pub glitches: bool,
+}
+
+/// One endpoint's pages, overall and by local hour of day (0-23)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EndpointTrend {
+    pub overall: HourBucket,
+    pub hours: BTreeMap<u8, HourBucket>,
+}
+
+impl EndpointTrend {
+    /// Flag `hour` where its mean loss or glitch rate is well above the
+    /// endpoint's mean over all pages
+    pub fn flags(&self, hour: &HourBucket) -> HourFlags {
+        HourFlags {
+            loss: self.overall.loss_percent.is_exceeded_by(&hour.loss_percent),
+            glitches: self.overall.glitch_rate.is_exceeded_by(&hour.glitch_rate),
+        }
+    }
+}
+
+/// Contents of an aggregate file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrendFile {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated: Option<DateTime<Utc>>,
+    pub endpoints: BTreeMap<String, EndpointTrend>,
+}
+
+impl Default for TrendFile {
+    fn default() -> Self {
+        Self {
+            version: TRENDS_VERSION,
+            updated: None,
+            endpoints: BTreeMap::new(),
+        }
+    }
+}
+
+impl TrendFile {
+    /// Read an aggregate file, or start an empty one if it doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self, TrendError> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let trends: Self = serde_json::from_reader(BufReader::new(file))?;
+        if trends.version != TRENDS_VERSION {
+            return Err(TrendError::UnsupportedVersion(trends.version));
+        }
+        Ok(trends)
+    }
+
+    /// Write to a temporary file beside `path` and rename it into place, so
+    /// a crash mid-write leaves the previous aggregate intact
+    pub fn save(&self, path: &Path) -> Result<(), TrendError> {
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".tmp");
+        let temp = path.with_file_name(temp_name);
+
+        let mut file = File::create(&temp)?;
+        serde_json::to_writer(&mut file, self)?;
+        file.write_all(b"\n")?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+
+    /// Add a page to its endpoint under the given hour of day
+    pub fn record_at(&mut self, page: &PageSummary, hour: u8) {
+        let endpoint = self.endpoints.entry(page.endpoint.clone()).or_default();
+        endpoint.overall.record(page);
+        endpoint.hours.entry(hour).or_default().record(page);
+        self.updated = Some(page.end_time);
+    }
+
+    /// Add a page under the local hour it started in
+    pub fn record(&mut self, page: &PageSummary) {
+        let hour = page.start_time.with_timezone(&Local).hour() as u8;
+        self.record_at(page, hour);
+    }
+}
+
+/// An aggregate file kept up to date as pages finish
+pub struct TrendWriter {
+    path: PathBuf,
+    trends: TrendFile,
+}
+
+impl TrendWriter {
+    /// Load what earlier runs accumulated. A file that exists but can't be
+    /// read is an error rather than being started over.
+    pub fn open(path: &Path) -> Result<Self, TrendError> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            trends: TrendFile::load(path)?,
+        })
+    }
+
+    /// Fold in a finished page and persist
+    pub fn record(&mut self, page: &PageSummary) -> Result<(), TrendError> {
+        self.trends.record(page);
+        self.trends.save(&self.path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn page(endpoint: &str, hour: u32, loss_percent: f64, glitches: u64) -> PageSummary {
+        let start = Local.with_ymd_and_hms(2026, 1, 15, hour, 30, 0).unwrap().with_timezone(&Utc);
+        serde_json::from_value(serde_json::json!({
+            "page_number": 1,
+            "endpoint": endpoint,
+            "start_time": start,
+            "end_time": start + chrono::Duration::seconds(30),
+            "duration_secs": 30.0,
+            "recording_file": "page.wav",
+            "network": {
+                "packets_received": 1500,
+                "bytes_received": 240_000,
+                "packets_lost": 0,
+                "loss_percent": loss_percent,
+                "jitter_ms": 2.0
+            },
+            "audio": {
+                "peak_rms_db": -12.0,
+                "avg_rms_db": -18.0,
+                "max_peak_db": -3.0,
+                "dominant_freq_hz": 1000.0,
+                "total_glitches": glitches,
+                "total_clipped": 0,
+                "clipping_percent": 0.0,
+                "avg_zero_crossing_rate": 2000.0
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_pages_across_hours_accumulate_and_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trends.json");
+
+        // A quiet day, except that 08:00 loses packets and glitches
+        let mut writer = TrendWriter::open(&path).unwrap();
+        for hour in 6..18 {
+            for _ in 0..4 {
+                let (loss, glitches) = if hour == 8 { (3.0, 6) } else { (0.1, 0) };
+                writer.record(&page("224.0.1.1:5004", hour, loss, glitches)).unwrap();
+            }
+        }
+        writer.record(&page("224.0.1.2:5004", 8, 0.0, 0)).unwrap();
+
+        // A restart keeps accumulating
+        let mut writer = TrendWriter::open(&path).unwrap();
+        writer.record(&page("224.0.1.1:5004", 8, 3.0, 6)).unwrap();
+        assert!(!dir.path().join("trends.json.tmp").exists());
+
+        let trends = TrendFile::load(&path).unwrap();
+        assert_eq!(trends.endpoints.len(), 2);
+        let endpoint = &trends.endpoints["224.0.1.1:5004"];
+        assert_eq!(endpoint.overall.pages, 49);
+        assert_eq!(endpoint.hours.keys().copied().collect::<Vec<_>>(), (6..18).collect::<Vec<u8>>());
+
+        let eight = &endpoint.hours[&8];
+        assert_eq!((eight.pages, eight.glitches), (5, 30));
+        assert!((eight.loss_percent.mean().unwrap() - 3.0).abs() < 1e-9);
+        assert!((eight.glitch_rate.mean().unwrap() - 12.0).abs() < 1e-9);
+        assert!((eight.duration_secs - 150.0).abs() < 1e-9);
+        assert_eq!(endpoint.flags(eight), HourFlags { loss: true, glitches: true });
+
+        let nine = &endpoint.hours[&9];
+        assert_eq!(nine.pages, 4);
+        assert!((nine.loss_percent.p95().unwrap() - 0.1).abs() < 1e-9);
+        assert_eq!(endpoint.flags(nine), HourFlags::default());
+
+        // One page has no spread to stand out from
+        let other = &trends.endpoints["224.0.1.2:5004"];
+        assert_eq!(other.flags(&other.hours[&8]), HourFlags::default());
+    }
+
+    #[test]
+    fn test_unreadable_file_is_not_overwritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trends.json");
+        fs::write(&path, "{ not json").unwrap();
+        assert!(matches!(TrendWriter::open(&path), Err(TrendError::Json(_))));
+
+        fs::write(&path, r#"{"version": 99, "endpoints": {}}"#).unwrap();
+        assert!(matches!(TrendFile::load(&path), Err(TrendError::UnsupportedVersion(99))));
+    }
+}
@@ -0,0 +1,668 @@
+//! End-to-end pipeline check (`verify-chain`).
+//!
+//! `codec-check` rules out the codecs on their own; this rules out
+//! everything around them as well. For each codec, a reference multitone is
+//! written to a WAV file, sent by this build's own `transmit` to a multicast
+//! group with TTL 0, so nothing leaves the host, and received, decoded,
+//! analyzed and recorded by its own `test`. Both run as child processes,
+//! exactly as they would from the command line, so the file load,
+//! resampling, encoding, RTP packetization, network loopback, receive,
+//! decode, analysis and recording are the ones a real page goes through.
+//! The result is held to what a working chain gives whatever the codec:
+//!
+//! - The recording is as long as the reference, give or take a frame
+//! - The analyzer found no glitches
+//! - The recording's SNR against the reference is at least the codec's
+//!   `codec-check` floor, once the codec's delay is taken out
+//! - The reference's tones are the recording's strongest frequencies
+//! - Exactly as many packets arrived as the reference makes frames, with
+//!   none lost
+//! - summary.json has the one page, with every field set and finite
+//!
+//! A codec whose backend this host lacks, such as G.722 without ffmpeg, is
+//! skipped with the reason rather than failed.
+
+use crate::cli::codec_check::{align_to, Thresholds, EXACT_SNR_DB};
+use crate::cli::convert::{align, downmix};
+use crate::codec::{create_decoder, create_encoder, AudioEncoder, CodecError, CodecType};
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Default group the reference is sent to; administratively scoped, and
+/// sent with TTL 0 so it never leaves the host
+pub const DEFAULT_ADDRESS: Ipv4Addr = Ipv4Addr::new(239, 255, 77, 1);
+
+/// Port of the first codec checked; each one after it takes the next
+pub const DEFAULT_PORT: u16 = 15770;
+
+/// Length of the reference
+const REFERENCE_SECS: u32 = 2;
+
+/// Rate the reference file is written at, so every codec's rate is reached
+/// through transmit's resampler
+const REFERENCE_RATE: u32 = 48000;
+
+/// Frequencies of the reference's tones, all within G.711's band
+const TONES_HZ: [f64; 4] = [350.0, 800.0, 1500.0, 2700.0];
+
+/// Overall level of the reference
+const REFERENCE_DBFS: f64 = -18.0;
+
+/// The reference fades in and out over this long, so its ends aren't glitches
+const FADE_MS: u32 = 10;
+
+/// How far a peak may be from its tone and still count as the tone
+const TONE_TOLERANCE_HZ: f64 = 10.0;
+
+/// Peaks closer than this to a stronger one are its skirt, not a tone
+const PEAK_SPACING_HZ: f64 = 100.0;
+
+/// How long `test` has to join the group before the codec is failed
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What `test` prints once it is listening
+const READY_LINE: &str = "Test mode started";
+
+/// Time `test` runs for beyond the reference, to start up and end the page
+const RECEIVE_MARGIN_SECS: u32 = 4;
+
+#[derive(Error, Debug)]
+pub enum VerifyChainError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("WAV error: {0}")]
+    Wav(#[from] hound::Error),
+
+    #[error("test didn't start listening within {}s; see {}", READY_TIMEOUT.as_secs(), .0.display())]
+    ReceiverNotReady(PathBuf),
+
+    #[error("test failed; see {}", .0.display())]
+    ReceiverFailed(PathBuf),
+
+    #[error("transmit failed; see {}", .0.display())]
+    TransmitFailed(PathBuf),
+
+    #[error("{failed} of {total} codecs failed the check")]
+    Failed { failed: usize, total: usize },
+}
+
+/// `--codec`: `all`, or a comma-separated list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecSelection(pub Vec<CodecType>);
+
+impl FromStr for CodecSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().eq_ignore_ascii_case("all") {
+            return Ok(Self(CodecType::ALL.to_vec()));
+        }
+        let mut codecs = Vec::new();
+        for name in s.split(',').map(str::trim) {
+            let codec = CodecType::from_str(name).ok_or_else(|| format!("unknown codec '{}'", name))?;
+            if !codecs.contains(&codec) {
+                codecs.push(codec);
+            }
+        }
+        Ok(Self(codecs))
+    }
+}
+
+pub struct VerifyChainOptions {
+    pub codecs: Vec<CodecType>,
+    /// Group the reference is sent to
+    pub address: Ipv4Addr,
+    /// Port of the first codec; each one after it takes the next
+    pub port: u16,
+    /// Where each codec's recordings and logs are kept; a temporary
+    /// directory, removed if every codec passes, if not given
+    pub output: Option<PathBuf>,
+    pub json: bool,
+}
+
+/// One invariant the chain is held to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    Duration,
+    Glitches,
+    Snr,
+    Tones,
+    Packets,
+    Loss,
+    Summary,
+}
+
+impl Check {
+    /// Every check, in the order they are listed
+    pub const ALL: [Check; 7] =
+        [Check::Duration, Check::Glitches, Check::Snr, Check::Tones, Check::Packets, Check::Loss, Check::Summary];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Duration => "Duration",
+            Self::Glitches => "Glitches",
+            Self::Snr => "SNR",
+            Self::Tones => "Tones",
+            Self::Packets => "Packets",
+            Self::Loss => "Loss",
+            Self::Summary => "Summary",
+        }
+    }
+}
+
+/// How one check came out, with what was measured
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckResult {
+    pub check: Check,
+    pub passed: bool,
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn new(check: Check, passed: bool, detail: String) -> Self {
+        Self { check, passed, detail }
+    }
+}
+
+/// How a codec came out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Pass,
+    Fail,
+    /// Its backend is missing on this host
+    Skipped,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pass => "pass",
+            Self::Fail => "FAIL",
+            Self::Skipped => "skipped",
+        })
+    }
+}
+
+/// One codec's run through the chain
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainResult {
+    pub codec: &'static str,
+    pub outcome: Outcome,
+    pub checks: Vec<CheckResult>,
+    /// Why the codec was skipped, or why the chain couldn't be run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl ChainResult {
+    fn checked(codec: CodecType, checks: Vec<CheckResult>) -> Self {
+        let outcome = if checks.iter().all(|check| check.passed) { Outcome::Pass } else { Outcome::Fail };
+        Self { codec: codec.cli_name(), outcome, checks, reason: None }
+    }
+
+    fn not_run(codec: CodecType, outcome: Outcome, reason: String) -> Self {
+        Self { codec: codec.cli_name(), outcome, checks: Vec::new(), reason: Some(reason) }
+    }
+}
+
+/// `verify-chain --json`
+#[derive(Debug, Serialize)]
+pub struct VerifyChainReport<'a> {
+    pub tones_hz: &'a [f64],
+    pub reference_secs: u32,
+    pub codecs: &'a [ChainResult],
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    /// Where the recordings and logs were left, if they were kept
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub results_dir: Option<&'a Path>,
+}
+
+/// Run the verify-chain command
+pub fn run_verify_chain(options: VerifyChainOptions) -> Result<(), VerifyChainError> {
+    let directory = options
+        .output
+        .clone()
+        .unwrap_or_else(|| std::env::temp_dir().join(format!("mpu-verify-chain-{}", std::process::id())));
+    fs::create_dir_all(&directory)?;
+    let reference = directory.join("reference.wav");
+    write_reference(&reference)?;
+    let program = std::env::current_exe()?;
+
+    let results: Vec<ChainResult> = options
+        .codecs
+        .iter()
+        .enumerate()
+        .map(|(i, &codec)| {
+            let group = SocketAddrV4::new(options.address, options.port.wrapping_add(i as u16));
+            verify_codec(&program, codec, group, &directory.join(codec.cli_name()), &reference)
+        })
+        .collect();
+
+    let count = |outcome: Outcome| results.iter().filter(|result| result.outcome == outcome).count();
+    let failed = count(Outcome::Fail);
+    // A temporary directory is only worth keeping to see what went wrong
+    let kept = options.output.is_some() || failed > 0;
+    if !kept {
+        fs::remove_dir_all(&directory)?;
+    }
+    let report = VerifyChainReport {
+        tones_hz: &TONES_HZ,
+        reference_secs: REFERENCE_SECS,
+        codecs: &results,
+        passed: count(Outcome::Pass),
+        failed,
+        skipped: count(Outcome::Skipped),
+        results_dir: kept.then_some(directory.as_path()),
+    };
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_matrix(&results);
+        println!();
+        println!("{} passed, {} failed, {} skipped", report.passed, report.failed, report.skipped);
+        if let Some(directory) = report.results_dir {
+            println!("Recordings and logs: {}", directory.display());
+        }
+    }
+
+    if failed > 0 {
+        return Err(VerifyChainError::Failed { failed, total: results.len() });
+    }
+    Ok(())
+}
+
+/// Send the reference through `codec` to `group` and check what came out,
+/// keeping the run's files in `directory`
+fn verify_codec(program: &Path, codec: CodecType, group: SocketAddrV4, directory: &Path, reference: &Path) -> ChainResult {
+    let encoder = match create_encoder(codec).and_then(|encoder| create_decoder(codec).map(|_| encoder)) {
+        Ok(encoder) => encoder,
+        Err(e @ CodecError::BackendUnavailable { .. }) => {
+            return ChainResult::not_run(codec, Outcome::Skipped, e.to_string())
+        }
+        Err(e) => return ChainResult::not_run(codec, Outcome::Fail, e.to_string()),
+    };
+    match run_chain(program, codec, encoder.as_ref(), group, directory, reference) {
+        Ok(checks) => ChainResult::checked(codec, checks),
+        Err(e) => ChainResult::not_run(codec, Outcome::Fail, e.to_string()),
+    }
+}
+
+fn run_chain(
+    program: &Path,
+    codec: CodecType,
+    encoder: &dyn AudioEncoder,
+    group: SocketAddrV4,
+    directory: &Path,
+    reference: &Path,
+) -> Result<Vec<CheckResult>, VerifyChainError> {
+    fs::create_dir_all(directory)?;
+    let results = directory.join("results");
+    let (address, port) = (group.ip().to_string(), group.port().to_string());
+    // Linear PCM's rate and channels aren't implied by its payload type
+    let spec = if codec.is_linear() {
+        format!("{}:{}:{}", codec.cli_name(), encoder.sample_rate(), encoder.channels())
+    } else {
+        codec.cli_name().to_string()
+    };
+
+    let receiver_log = directory.join("test.log");
+    let timeout = (REFERENCE_SECS + RECEIVE_MARGIN_SECS).to_string();
+    let mut receiver = spawn_logged(
+        program,
+        &["test", "--address", &address, "--port", &port, "--codec", &spec, "--timeout", &timeout, "--output"],
+        &results,
+        &receiver_log,
+    )?;
+    if !wait_until_listening(&mut receiver, &receiver_log)? {
+        let _ = receiver.kill();
+        let _ = receiver.wait();
+        return Err(VerifyChainError::ReceiverNotReady(receiver_log));
+    }
+
+    let sender_log = directory.join("transmit.log");
+    let sent = spawn_logged(
+        program,
+        &["transmit", "--address", &address, "--port", &port, "--codec", codec.cli_name(), "--ttl", "0", "--quiet", "--file"],
+        reference,
+        &sender_log,
+    )?
+    .wait()?;
+    if !sent.success() {
+        let _ = receiver.kill();
+        let _ = receiver.wait();
+        return Err(VerifyChainError::TransmitFailed(sender_log));
+    }
+    if !receiver.wait()?.success() {
+        return Err(VerifyChainError::ReceiverFailed(receiver_log));
+    }
+
+    let summary: Value = serde_json::from_reader(BufReader::new(File::open(results.join("summary.json"))?))?;
+    let recording = match summary["pages"][0]["recording_file"].as_str() {
+        Some(file) => Some(read_recording(&results.join(file))?),
+        None => None,
+    };
+    Ok(evaluate(codec, encoder.frame_size(), encoder.sample_rate(), &summary, recording))
+}
+
+/// Start this build as `args` followed by `last`, its output going to `log`
+fn spawn_logged(program: &Path, args: &[&str], last: &Path, log: &Path) -> io::Result<Child> {
+    let output = File::create(log)?;
+    Command::new(program)
+        .args(args)
+        .arg(last)
+        .stdin(Stdio::null())
+        .stdout(output.try_clone()?)
+        .stderr(output)
+        .spawn()
+}
+
+/// Wait for `test` to say it is listening; false if it didn't in time
+fn wait_until_listening(receiver: &mut Child, log: &Path) -> io::Result<bool> {
+    let started = Instant::now();
+    while started.elapsed() < READY_TIMEOUT {
+        if fs::read_to_string(log)?.contains(READY_LINE) {
+            return Ok(true);
+        }
+        if receiver.try_wait()?.is_some() {
+            return Ok(false);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    Ok(false)
+}
+
+/// A recording's samples, mixed to mono, and its rate
+fn read_recording(path: &Path) -> Result<(Vec<i16>, u32), VerifyChainError> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let samples = reader.samples::<i16>().collect::<Result<Vec<_>, _>>()?;
+    Ok((downmix(samples, usize::from(spec.channels)), spec.sample_rate))
+}
+
+/// The reference's tones at `rate`, each sample the same whatever the rate
+/// it falls on, so a decimated copy matches exactly
+pub fn reference_samples(rate: u32) -> Vec<i16> {
+    let count = TONES_HZ.len() as f64;
+    let amplitude = 32768.0 * 10f64.powf(REFERENCE_DBFS / 20.0) / (count / 2.0).sqrt();
+    let length = REFERENCE_SECS * rate;
+    let fade = f64::from(rate * FADE_MS / 1000);
+    (0..length)
+        .map(|i| {
+            let t = f64::from(i) / f64::from(rate);
+            let sum: f64 = TONES_HZ
+                .iter()
+                .enumerate()
+                .map(|(k, &hz)| {
+                    let phase = -std::f64::consts::PI * (k * k.saturating_sub(1)) as f64 / count;
+                    (2.0 * std::f64::consts::PI * hz * t + phase).sin()
+                })
+                .sum();
+            let envelope = (f64::from(i) / fade).min(f64::from(length - i) / fade).min(1.0);
+            (amplitude * envelope * sum).round().clamp(-32768.0, 32767.0) as i16
+        })
+        .collect()
+}
+
+fn write_reference(path: &Path) -> Result<(), VerifyChainError> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: REFERENCE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for sample in reference_samples(REFERENCE_RATE) {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+/// Hold a run's summary.json and recording (mono, with its rate) to every
+/// check. `frame_size` and `rate` are the codec's encoder's
+pub fn evaluate(
+    codec: CodecType,
+    frame_size: usize,
+    rate: u32,
+    summary: &Value,
+    recording: Option<(Vec<i16>, u32)>,
+) -> Vec<CheckResult> {
+    let pages = summary["pages"].as_array().map_or(&[][..], Vec::as_slice);
+    let page = pages.first().unwrap_or(&Value::Null);
+    let frame_secs = frame_size as f64 / f64::from(rate);
+    let expected_packets = (REFERENCE_SECS * rate).div_ceil(frame_size as u32);
+    let count = |value: &Value| value.as_u64().map_or_else(|| "nothing".to_string(), |n| n.to_string());
+    let mut checks = Vec::new();
+
+    let duration = recording.as_ref().map(|(samples, rate)| samples.len() as f64 / f64::from(*rate));
+    checks.push(match duration {
+        Some(secs) => CheckResult::new(
+            Check::Duration,
+            (secs - f64::from(REFERENCE_SECS)).abs() <= frame_secs + 1e-9,
+            format!("{:.3}s recorded of {}s sent", secs, REFERENCE_SECS),
+        ),
+        None => CheckResult::new(Check::Duration, false, "no recording".to_string()),
+    });
+
+    let glitches = &page["audio"]["total_glitches"];
+    checks.push(CheckResult::new(Check::Glitches, glitches.as_u64() == Some(0), format!("{} glitches", count(glitches))));
+
+    let floor = Thresholds::default_for(codec).min_snr_db;
+    checks.push(match recording {
+        Some((ref samples, rate)) => {
+            let original = reference_samples(rate);
+            let (_, aligned) = align_to(&original, samples, rate);
+            let (_, snr_db) = align(&original, &aligned, 0);
+            let snr_db = snr_db.min(EXACT_SNR_DB);
+            CheckResult::new(Check::Snr, snr_db >= floor, format!("{:.1} dB, floor {:.1} dB", snr_db, floor))
+        }
+        None => CheckResult::new(Check::Snr, false, "no recording".to_string()),
+    });
+
+    checks.push(match recording {
+        Some((ref samples, rate)) => {
+            let peaks = spectral_peaks(samples, rate, TONES_HZ.len());
+            let found = peaks.len() == TONES_HZ.len()
+                && peaks.iter().zip(&TONES_HZ).all(|(peak, tone)| (peak - tone).abs() <= TONE_TOLERANCE_HZ);
+            let listed: Vec<String> = peaks.iter().map(|hz| format!("{:.0}", hz)).collect();
+            CheckResult::new(Check::Tones, found, format!("strongest at {} Hz", listed.join(", ")))
+        }
+        None => CheckResult::new(Check::Tones, false, "no recording".to_string()),
+    });
+
+    let packets = &page["network"]["packets_received"];
+    checks.push(CheckResult::new(
+        Check::Packets,
+        packets.as_u64() == Some(u64::from(expected_packets)),
+        format!("{} received of {} sent", count(packets), expected_packets),
+    ));
+
+    let lost = &page["network"]["packets_lost"];
+    checks.push(CheckResult::new(Check::Loss, lost.as_i64() == Some(0), format!("{} lost", count(lost))));
+
+    let mut missing = Vec::new();
+    unset_fields(page, "", &mut missing);
+    let (passed, detail) = match (pages.len(), missing.first()) {
+        (1, None) => (true, "1 page, every field set".to_string()),
+        (1, Some(_)) => (false, format!("unset or not finite: {}", missing.join(", "))),
+        (n, _) => (false, format!("{} pages recorded, not 1", n)),
+    };
+    checks.push(CheckResult::new(Check::Summary, passed, detail));
+    checks
+}
+
+/// Paths under `value` that are null, which is how JSON is written with a
+/// value that isn't finite
+fn unset_fields(value: &Value, path: &str, unset: &mut Vec<String>) {
+    match value {
+        Value::Null => unset.push(if path.is_empty() { "page".to_string() } else { path.to_string() }),
+        Value::Object(fields) => {
+            for (name, field) in fields {
+                let path = if path.is_empty() { name.clone() } else { format!("{}.{}", path, name) };
+                unset_fields(field, &path, unset);
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                unset_fields(item, &format!("{}[{}]", path, i), unset);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The `count` strongest frequencies in a second of `samples` from a
+/// quarter second in, past the fade, in ascending order. A peak's skirt is
+/// passed over for the next one
+fn spectral_peaks(samples: &[i16], rate: u32, count: usize) -> Vec<f64> {
+    let size = rate as usize;
+    let start = (size / 4).min(samples.len().saturating_sub(size));
+    let mut buffer: Vec<Complex<f64>> = (0..size)
+        .map(|i| {
+            let window = 0.5 - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / size as f64).cos();
+            Complex::new(samples.get(start + i).map_or(0.0, |&s| f64::from(s)) * window, 0.0)
+        })
+        .collect();
+    FftPlanner::<f64>::new().plan_fft_forward(size).process(&mut buffer);
+
+    // A second of audio puts the bins 1 Hz apart
+    let mut power: Vec<f64> = buffer[..size / 2].iter().map(|bin| bin.norm_sqr()).collect();
+    let spacing = PEAK_SPACING_HZ as usize;
+    let mut peaks = Vec::new();
+    for _ in 0..count {
+        let Some((bin, _)) = power.iter().enumerate().skip(1).max_by(|a, b| a.1.total_cmp(b.1)) else {
+            break;
+        };
+        peaks.push(bin as f64);
+        power[bin.saturating_sub(spacing)..(bin + spacing).min(size / 2)].fill(0.0);
+    }
+    peaks.sort_by(f64::total_cmp);
+    peaks
+}
+
+fn print_matrix(results: &[ChainResult]) {
+    print!("{:<10}", "Codec");
+    for check in Check::ALL {
+        print!(" {:>8}", check.label());
+    }
+    println!("  Result");
+    for result in results {
+        print!("{:<10}", result.codec);
+        if result.checks.is_empty() {
+            print!(" {:>62}", "-");
+        }
+        for check in &result.checks {
+            print!(" {:>8}", if check.passed { "ok" } else { "FAIL" });
+        }
+        println!("  {}", result.outcome);
+        for check in result.checks.iter().filter(|check| !check.passed) {
+            println!("{:<10} {}: {}", "", check.check.label(), check.detail);
+        }
+        if let Some(ref reason) = result.reason {
+            println!("{:<10} {}", "", reason);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A summary for a clean run of `packets` packets
+    fn summary(packets: u64) -> Value {
+        json!({
+            "pages": [{
+                "page_number": 1,
+                "recording_file": "page.wav",
+                "network": { "packets_received": packets, "packets_lost": 0, "jitter_ms": 0.4 },
+                "audio": { "total_glitches": 0, "peak_rms_db": -18.0 },
+            }]
+        })
+    }
+
+    #[test]
+    fn test_codec_selection() {
+        assert_eq!("all".parse::<CodecSelection>().unwrap().0, CodecType::ALL);
+        let selection: CodecSelection = "pcmu, l16,g711ulaw".parse().unwrap();
+        assert_eq!(selection.0, [CodecType::G711Ulaw, CodecType::L16]);
+        assert!("g711ulaw,mp3".parse::<CodecSelection>().unwrap_err().contains("mp3"));
+    }
+
+    #[test]
+    fn test_clean_run_passes() {
+        // G.711 carries the reference's tones near enough unchanged
+        let codec = CodecType::G711Ulaw;
+        let mut decoder = create_decoder(codec).unwrap();
+        let mut encoder = create_encoder(codec).unwrap();
+        let decoded: Vec<i16> = reference_samples(8000)
+            .chunks(160)
+            .flat_map(|frame| decoder.decode(&encoder.encode(frame).unwrap()).unwrap())
+            .collect();
+
+        let checks = evaluate(codec, 160, 8000, &summary(100), Some((decoded, 8000)));
+        assert_eq!(checks.iter().map(|check| check.check).collect::<Vec<_>>(), Check::ALL);
+        for check in &checks {
+            assert!(check.passed, "{:?}", check);
+        }
+        assert_eq!(ChainResult::checked(codec, checks).outcome, Outcome::Pass);
+    }
+
+    #[test]
+    fn test_broken_run_flagged() {
+        // One packet short, a stray tone louder than the reference's, and a
+        // level that wasn't finite
+        let mut summary = summary(99);
+        summary["pages"][0]["audio"]["peak_rms_db"] = Value::Null;
+        let interfered: Vec<i16> = reference_samples(8000)
+            .iter()
+            .enumerate()
+            .map(|(i, &s)| {
+                let t = i as f64 / 8000.0;
+                s.saturating_add((8000.0 * (2.0 * std::f64::consts::PI * 1000.0 * t).sin()) as i16)
+            })
+            .collect();
+
+        let checks = evaluate(CodecType::G711Ulaw, 160, 8000, &summary, Some((interfered, 8000)));
+        let failed: Vec<Check> = checks.iter().filter(|check| !check.passed).map(|check| check.check).collect();
+        assert_eq!(failed, [Check::Snr, Check::Tones, Check::Packets, Check::Summary]);
+        let summary_check = checks.iter().find(|check| check.check == Check::Summary).unwrap();
+        assert!(summary_check.detail.contains("audio.peak_rms_db"), "{}", summary_check.detail);
+
+        // Without a recording, everything that needs one fails
+        let checks = evaluate(CodecType::G711Ulaw, 160, 8000, &json!({ "pages": [] }), None);
+        assert!(checks.iter().all(|check| !check.passed));
+    }
+
+    #[test]
+    fn test_reference_tones_found() {
+        let samples = reference_samples(REFERENCE_RATE);
+        assert_eq!(samples.len(), (REFERENCE_SECS * REFERENCE_RATE) as usize);
+        assert_eq!(samples[0], 0);
+        // Decimating to a codec's rate gives that rate's reference exactly
+        let decimated: Vec<i16> = samples.iter().step_by(6).copied().collect();
+        assert_eq!(decimated, reference_samples(8000));
+        assert_eq!(spectral_peaks(&samples, REFERENCE_RATE, TONES_HZ.len()), TONES_HZ);
+    }
+}
@@ -0,0 +1,339 @@
+//! Noticing a socket that has stopped receiving, and replacing it.
+//!
+//! A socket that gets stuck returning errors never receives again, while
+//! the process and its stats task carry on as if nothing were wrong. The
+//! monitor tells a [`SocketWatchdog`] how each socket's reads go: a round
+//! that read packets or found the socket cleanly empty shows it healthy,
+//! and a receive error counts against it. After [`ERROR_LIMIT`] errors in a
+//! row, or `--watchdog` seconds without a healthy round, the socket is
+//! rebuilt: bound afresh and joined to the same groups. Rebuilds back off
+//! exponentially from [`FIRST_BACKOFF`] to [`MAX_BACKOFF`], and once a port
+//! has used up [`MAX_RESTARTS`] without staying healthy for [`SETTLED`]
+//! the run fails rather than carrying on deaf.
+
+use crate::network::{MulticastError, MulticastSocket};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::io;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+/// Receive errors in a row, with no healthy round between, that get a
+/// socket rebuilt
+pub const ERROR_LIMIT: u32 = 50;
+
+/// Rebuilds of one port in a row before the run gives up
+pub const MAX_RESTARTS: u32 = 8;
+
+/// Wait after the first rebuild before another; doubled for each after it
+pub const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest wait between rebuilds
+pub const MAX_BACKOFF: Duration = Duration::from_mins(1);
+
+/// How long a rebuilt socket has to stay healthy before its rebuilds stop
+/// counting towards `MAX_RESTARTS`
+pub const SETTLED: Duration = Duration::from_mins(1);
+
+/// Distinct errors kept for the `socket_restarted` event
+const ERROR_HISTORY: usize = 10;
+
+/// A socket the watchdog can replace; tests wrap a real one to make it fail
+pub trait Rebuild: Sized {
+    /// A new socket on the same port, joined to the same groups
+    fn rebuild(&self, busy_poll_us: Option<u32>) -> impl Future<Output = Result<Self, MulticastError>>;
+
+    /// Groups the socket has joined
+    fn groups(&self) -> Vec<Ipv4Addr>;
+}
+
+impl Rebuild for MulticastSocket {
+    async fn rebuild(&self, busy_poll_us: Option<u32>) -> Result<Self, MulticastError> {
+        let socket = self.reopen().await?;
+        socket.apply_busy_poll(busy_poll_us);
+        Ok(socket)
+    }
+
+    fn groups(&self) -> Vec<Ipv4Addr> {
+        let mut groups: Vec<Ipv4Addr> = self.joined_groups().iter().copied().collect();
+        groups.sort_unstable();
+        groups
+    }
+}
+
+/// Why a socket was rebuilt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartReason {
+    /// `ERROR_LIMIT` receive errors in a row
+    Errors,
+    /// No healthy round for the `--watchdog` time
+    Stalled,
+}
+
+/// A receive error, with how many times it came in a row
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct SocketErrorRecord {
+    /// When it first came
+    pub timestamp: DateTime<Utc>,
+    pub error: String,
+    pub count: u32,
+}
+
+/// What to do about a port that's due a rebuild
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Restart {
+    /// Rebuild it; the attempt number, from 1
+    Attempt(u32),
+    /// `MAX_RESTARTS` rebuilds haven't helped
+    GiveUp { attempts: u32 },
+}
+
+#[derive(Debug)]
+struct PortHealth {
+    /// Last round that read packets, or found the socket empty, without error
+    healthy_at: Instant,
+    consecutive_errors: u32,
+    last_error_at: Option<Instant>,
+    history: VecDeque<SocketErrorRecord>,
+    /// Rebuilds since the port last settled
+    attempts: u32,
+    rebuilt_at: Option<Instant>,
+    next_attempt: Option<Instant>,
+}
+
+/// Per-port receive health for one monitor run
+#[derive(Debug)]
+pub struct SocketWatchdog {
+    stall: Duration,
+    ports: HashMap<u16, PortHealth>,
+}
+
+impl SocketWatchdog {
+    /// `stall`: how long a port may go without a healthy round
+    pub fn new(stall: Duration) -> Self {
+        Self {
+            stall,
+            ports: HashMap::new(),
+        }
+    }
+
+    fn health(&mut self, port: u16, now: Instant) -> &mut PortHealth {
+        self.ports.entry(port).or_insert_with(|| PortHealth {
+            healthy_at: now,
+            consecutive_errors: 0,
+            last_error_at: None,
+            history: VecDeque::new(),
+            attempts: 0,
+            rebuilt_at: None,
+            next_attempt: None,
+        })
+    }
+
+    /// Count a receive error on `port`. True for the first of a run of
+    /// errors, so each run is reported once.
+    pub fn record_error(&mut self, port: u16, error: &io::Error, now: Instant, wall_now: DateTime<Utc>) -> bool {
+        let health = self.health(port, now);
+        health.consecutive_errors += 1;
+        health.last_error_at = Some(now);
+        let error = error.to_string();
+        match health.history.back_mut() {
+            Some(last) if last.error == error => last.count += 1,
+            _ => {
+                if health.history.len() == ERROR_HISTORY {
+                    health.history.pop_front();
+                }
+                health.history.push_back(SocketErrorRecord { timestamp: wall_now, error, count: 1 });
+            }
+        }
+        health.consecutive_errors == 1
+    }
+
+    /// Note a round of reads that began at `started`: each port in `ports`
+    /// that had no error since then was healthy at `now`
+    pub fn record_round(&mut self, ports: impl IntoIterator<Item = u16>, started: Instant, now: Instant) {
+        for port in ports {
+            let health = self.health(port, now);
+            if health.last_error_at.is_some_and(|at| at >= started) {
+                continue;
+            }
+            health.healthy_at = now;
+            health.consecutive_errors = 0;
+            if health.rebuilt_at.is_some_and(|at| now.saturating_duration_since(at) >= SETTLED) {
+                health.attempts = 0;
+                health.rebuilt_at = None;
+                health.next_attempt = None;
+                health.history.clear();
+            }
+        }
+    }
+
+    /// Ports that need rebuilding now, and why. Ports still waiting out
+    /// the backoff from their last rebuild are left for later.
+    pub fn due(&self, now: Instant) -> Vec<(u16, RestartReason)> {
+        let mut due: Vec<(u16, RestartReason)> = self
+            .ports
+            .iter()
+            .filter(|(_, health)| health.next_attempt.is_none_or(|at| now >= at))
+            .filter_map(|(&port, health)| {
+                if health.consecutive_errors >= ERROR_LIMIT {
+                    Some((port, RestartReason::Errors))
+                } else if now.saturating_duration_since(health.healthy_at) >= self.stall {
+                    Some((port, RestartReason::Stalled))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        due.sort_unstable_by_key(|&(port, _)| port);
+        due
+    }
+
+    /// Start a rebuild of `port`: the new socket gets a fresh start, and
+    /// the next rebuild waits out the backoff
+    pub fn restart(&mut self, port: u16, now: Instant) -> Restart {
+        let health = self.health(port, now);
+        if health.attempts >= MAX_RESTARTS {
+            return Restart::GiveUp { attempts: health.attempts };
+        }
+        health.attempts += 1;
+        health.rebuilt_at = Some(now);
+        health.next_attempt = Some(now + backoff(health.attempts));
+        health.healthy_at = now;
+        health.consecutive_errors = 0;
+        Restart::Attempt(health.attempts)
+    }
+
+    /// Errors since the port last settled, oldest first
+    pub fn history(&self, port: u16) -> Vec<SocketErrorRecord> {
+        self.ports.get(&port).map(|health| health.history.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    /// Wait after rebuild number `attempt` before the next
+    pub fn backoff_after(attempt: u32) -> Duration {
+        backoff(attempt)
+    }
+
+    /// Forget ports that are no longer monitored
+    pub fn retain(&mut self, keep: impl Fn(u16) -> bool) {
+        self.ports.retain(|&port, _| keep(port));
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    FIRST_BACKOFF.saturating_mul(1 << attempt.saturating_sub(1).min(16)).min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error() -> io::Error {
+        io::Error::new(io::ErrorKind::NotConnected, "Transport endpoint is not connected")
+    }
+
+    #[test]
+    fn test_repeated_errors_are_due() {
+        let start = Instant::now();
+        let mut watchdog = SocketWatchdog::new(Duration::from_secs(30));
+        watchdog.record_round([5004, 5005], start, start);
+
+        for i in 0..ERROR_LIMIT {
+            let now = start + Duration::from_millis(u64::from(i) * 10);
+            // Only the first of the run is reported
+            assert_eq!(watchdog.record_error(5004, &error(), now, Utc::now()), i == 0);
+            watchdog.record_round([5004, 5005], now, now);
+        }
+        let now = start + Duration::from_secs(1);
+        assert_eq!(watchdog.due(now), [(5004, RestartReason::Errors)]);
+        // The same error over and over is one record
+        let history = watchdog.history(5004);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].count, ERROR_LIMIT);
+    }
+
+    #[test]
+    fn test_a_healthy_round_clears_errors() {
+        let start = Instant::now();
+        let mut watchdog = SocketWatchdog::new(Duration::from_secs(30));
+        for i in 0..ERROR_LIMIT - 1 {
+            let now = start + Duration::from_millis(u64::from(i));
+            watchdog.record_error(5004, &error(), now, Utc::now());
+        }
+        let now = start + Duration::from_millis(100);
+        watchdog.record_round([5004], now, now);
+        watchdog.record_error(5004, &error(), now, Utc::now());
+        assert!(watchdog.due(now).is_empty());
+    }
+
+    #[test]
+    fn test_stall() {
+        let start = Instant::now();
+        let mut watchdog = SocketWatchdog::new(Duration::from_secs(30));
+        watchdog.record_round([5004], start, start);
+        // Errors now and then, never enough in a row, and never a clean round
+        for secs in 1..30 {
+            let now = start + Duration::from_secs(secs);
+            watchdog.record_error(5004, &error(), now, Utc::now());
+            watchdog.record_round([5004], now, now);
+        }
+        assert!(watchdog.due(start + Duration::from_secs(29)).is_empty());
+        assert_eq!(watchdog.due(start + Duration::from_secs(30)), [(5004, RestartReason::Stalled)]);
+    }
+
+    #[test]
+    fn test_backoff_and_give_up() {
+        let start = Instant::now();
+        let mut watchdog = SocketWatchdog::new(Duration::from_secs(30));
+        let mut now = start;
+        let mut waits = Vec::new();
+        for attempt in 1..=MAX_RESTARTS {
+            // The rebuilt socket fails straight away
+            for _ in 0..ERROR_LIMIT {
+                watchdog.record_error(5004, &error(), now, Utc::now());
+            }
+            let due_at = (0..=600)
+                .map(|ms| now + Duration::from_millis(ms * 100))
+                .find(|&at| !watchdog.due(at).is_empty())
+                .unwrap();
+            waits.push(due_at.saturating_duration_since(now));
+            now = due_at;
+            assert_eq!(watchdog.restart(5004, now), Restart::Attempt(attempt));
+        }
+        assert_eq!(waits[0], Duration::ZERO);
+        assert_eq!(waits[1..4], [Duration::from_secs(1), Duration::from_secs(2), Duration::from_secs(4)]);
+        assert_eq!(waits[7], MAX_BACKOFF);
+
+        for _ in 0..ERROR_LIMIT {
+            watchdog.record_error(5004, &error(), now, Utc::now());
+        }
+        let now = now + MAX_BACKOFF;
+        assert_eq!(watchdog.due(now), [(5004, RestartReason::Errors)]);
+        assert_eq!(watchdog.restart(5004, now), Restart::GiveUp { attempts: MAX_RESTARTS });
+    }
+
+    #[test]
+    fn test_settled_port_starts_afresh() {
+        let start = Instant::now();
+        let mut watchdog = SocketWatchdog::new(Duration::from_secs(30));
+        for _ in 0..ERROR_LIMIT {
+            watchdog.record_error(5004, &error(), start, Utc::now());
+        }
+        assert_eq!(watchdog.restart(5004, start), Restart::Attempt(1));
+
+        // Healthy for a minute after the rebuild
+        for secs in 1..=60 {
+            let now = start + Duration::from_secs(secs);
+            watchdog.record_round([5004], now, now);
+        }
+        assert!(watchdog.history(5004).is_empty());
+        let now = start + Duration::from_secs(61);
+        for _ in 0..ERROR_LIMIT {
+            watchdog.record_error(5004, &error(), now, Utc::now());
+        }
+        assert_eq!(watchdog.restart(5004, now), Restart::Attempt(1));
+    }
+}
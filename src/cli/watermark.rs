@@ -0,0 +1,339 @@
+//! An inaudible ID marker mixed into transmitted audio, and its detector.
+//!
+//! `transmit --watermark ID` adds a quiet frequency-shift tone to the page
+//! before it is encoded: 2200 Hz for a 0 bit and 2600 Hz for a 1, 50ms a
+//! bit, at -30 dBFS by default. A frame is an 8-bit preamble, the 16-bit
+//! ID and a CRC-8 of the ID, 1.6s in all, repeated for the whole page. Both
+//! tones sit well inside the G.711 and G.722 passbands.
+//!
+//! The analyzer runs two Goertzel filters over every 10ms hop of decoded
+//! audio and turns them into a soft bit, -1 to 1, from which tone carried
+//! more energy. [`WatermarkStats`] folds the hops of a page onto one
+//! frame's worth, so the repeats add up and a lost packet or a loud word
+//! only dents a few of them. At the end of the page every alignment of the
+//! folded frame is tried; one whose preamble matches and whose CRC checks
+//! out is reported with its confidence, the mean soft bit in the direction
+//! decoded. Pages need at least one whole frame to be checked.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Tones for a 0 and a 1 bit. Whole multiples of the 100 Hz bin spacing
+/// of a 10ms hop, so a steady tone doesn't leak into the other's bin
+pub const FREQUENCIES_HZ: [f64; 2] = [2200.0, 2600.0];
+
+/// Marker level unless `--watermark-level` says otherwise
+pub const DEFAULT_LEVEL_DBFS: f64 = -30.0;
+
+/// Quietest and loudest marker `--watermark-level` takes
+pub const LEVEL_RANGE_DBFS: (f64, f64) = (-50.0, -10.0);
+
+/// Confidence a detection needs to be reported
+pub const DETECTION_THRESHOLD: f64 = 0.5;
+
+const SYMBOL_MS: u32 = 50;
+const HOP_MS: u32 = 10;
+const HOPS_PER_SYMBOL: usize = (SYMBOL_MS / HOP_MS) as usize;
+
+const PREAMBLE: u8 = 0b1011_0010;
+
+/// Preamble, ID and CRC
+const FRAME_BITS: usize = 32;
+const FRAME_HOPS: usize = FRAME_BITS * HOPS_PER_SYMBOL;
+
+/// Tone power, as a -60 dBFS tone would give, added below the soft bit's
+/// ratio so near-silence reads as no bit rather than a random one
+const FLOOR_DBFS: f64 = -60.0;
+
+/// A watermark found in a page
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct WatermarkDetection {
+    pub id: u16,
+    /// Mean soft bit over the frame in the decoded direction, 0-1
+    pub confidence: f64,
+}
+
+/// The bits of one frame, first sent first
+pub fn frame_bits(id: u16) -> [bool; FRAME_BITS] {
+    let mut bits = [false; FRAME_BITS];
+    let [high, low] = id.to_be_bytes();
+    for (byte_index, byte) in [PREAMBLE, high, low, crc8(id)].into_iter().enumerate() {
+        for bit in 0..8 {
+            bits[byte_index * 8 + bit] = byte & (0x80 >> bit) != 0;
+        }
+    }
+    bits
+}
+
+/// CRC-8 (polynomial 0x07) of the ID's two bytes
+fn crc8(id: u16) -> u8 {
+    let mut crc = 0u8;
+    for byte in id.to_be_bytes() {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Mix the marker for `id` into mono `samples` at `level_dbfs` RMS
+pub fn embed(samples: &mut [i16], sample_rate: u32, id: u16, level_dbfs: f64) {
+    Watermarker::new(sample_rate, id, level_dbfs).mix(samples);
+}
+
+/// [`embed`] for a page mixed a block at a time. The phase runs on across
+/// bit changes and blocks, so neither clicks
+pub struct Watermarker {
+    bits: [bool; FRAME_BITS],
+    amplitude: f64,
+    symbol_len: usize,
+    sample_rate: u32,
+    phase: f64,
+    /// Samples mixed so far
+    position: usize,
+}
+
+impl Watermarker {
+    pub fn new(sample_rate: u32, id: u16, level_dbfs: f64) -> Self {
+        Self {
+            bits: frame_bits(id),
+            amplitude: 32768.0 * 10f64.powf(level_dbfs / 20.0) * std::f64::consts::SQRT_2,
+            symbol_len: (sample_rate * SYMBOL_MS / 1000).max(1) as usize,
+            sample_rate,
+            phase: 0.0,
+            position: 0,
+        }
+    }
+
+    /// Mix the marker into the next block of mono samples
+    pub fn mix(&mut self, samples: &mut [i16]) {
+        for sample in samples {
+            let bit = self.bits[(self.position / self.symbol_len) % FRAME_BITS];
+            self.phase = (self.phase + 2.0 * PI * FREQUENCIES_HZ[usize::from(bit)] / f64::from(self.sample_rate)) % (2.0 * PI);
+            let mixed = f64::from(*sample) + self.amplitude * self.phase.sin();
+            *sample = mixed.round().clamp(-32768.0, 32767.0) as i16;
+            self.position += 1;
+        }
+    }
+}
+
+/// Check a `--watermark-level` as it is parsed
+pub fn parse_level(text: &str) -> Result<f64, String> {
+    let level: f64 = text.parse().map_err(|_| format!("{:?} isn't a level in dBFS", text))?;
+    let (min, max) = LEVEL_RANGE_DBFS;
+    if !(min..=max).contains(&level) {
+        return Err(format!("{} dBFS is outside {} to {}", level, min, max));
+    }
+    Ok(level)
+}
+
+/// Soft bits from 10ms hops of mono audio, as it arrives
+pub struct WatermarkMeter {
+    hop_len: usize,
+    /// Goertzel coefficient per tone
+    coefficients: [f64; 2],
+    /// Filter state per tone: the last two outputs
+    state: [[f64; 2]; 2],
+    hop_samples: usize,
+    floor: f64,
+}
+
+impl WatermarkMeter {
+    pub fn new(sample_rate: u32) -> Self {
+        let hop_len = (sample_rate * HOP_MS / 1000).max(1) as usize;
+        let floor_amplitude = 10f64.powf(FLOOR_DBFS / 20.0) * std::f64::consts::SQRT_2;
+        Self {
+            hop_len,
+            coefficients: FREQUENCIES_HZ.map(|hz| 2.0 * (2.0 * PI * hz / f64::from(sample_rate.max(1))).cos()),
+            state: [[0.0; 2]; 2],
+            hop_samples: 0,
+            floor: (floor_amplitude * hop_len as f64 / 2.0).powi(2),
+        }
+    }
+
+    /// Take mono samples, adding a soft bit to `hops` for each hop they complete
+    pub fn push(&mut self, samples: &[i16], hops: &mut Vec<f32>) {
+        for &sample in samples {
+            let x = f64::from(sample) / 32768.0;
+            for (state, coefficient) in self.state.iter_mut().zip(self.coefficients) {
+                let s = x + coefficient * state[0] - state[1];
+                state[1] = state[0];
+                state[0] = s;
+            }
+            self.hop_samples += 1;
+            if self.hop_samples == self.hop_len {
+                hops.push(self.end_hop());
+            }
+        }
+    }
+
+    /// Stand in silence for `frames` samples that were lost or never sent,
+    /// so later hops keep their place in the frame
+    pub fn skip(&mut self, frames: u64, hops: &mut Vec<f32>) {
+        for _ in 0..frames {
+            self.push(&[0], hops);
+        }
+    }
+
+    fn end_hop(&mut self) -> f32 {
+        let [zero, one] = [0, 1].map(|tone| {
+            let [s1, s2] = self.state[tone];
+            (s1 * s1 + s2 * s2 - self.coefficients[tone] * s1 * s2).max(0.0)
+        });
+        self.state = [[0.0; 2]; 2];
+        self.hop_samples = 0;
+        ((one - zero) / (one + zero + self.floor)) as f32
+    }
+
+    /// Start again for a new page
+    pub fn reset(&mut self) {
+        self.state = [[0.0; 2]; 2];
+        self.hop_samples = 0;
+    }
+}
+
+/// A page's soft bits, folded onto one frame
+#[derive(Debug, Clone)]
+pub struct WatermarkStats {
+    sums: Vec<f64>,
+    hops: u64,
+}
+
+impl Default for WatermarkStats {
+    fn default() -> Self {
+        Self {
+            sums: vec![0.0; FRAME_HOPS],
+            hops: 0,
+        }
+    }
+}
+
+impl WatermarkStats {
+    pub fn add(&mut self, soft_bit: f32) {
+        self.sums[(self.hops % FRAME_HOPS as u64) as usize] += f64::from(soft_bit);
+        self.hops += 1;
+    }
+
+    /// The marker carried by the page, if one decodes with enough confidence
+    pub fn detect(&self) -> Option<WatermarkDetection> {
+        if self.hops < FRAME_HOPS as u64 {
+            return None;
+        }
+        let folds = self.hops as f64 / FRAME_HOPS as f64;
+        (0..FRAME_HOPS)
+            .filter_map(|offset| self.decode_at(offset, folds))
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+            .filter(|detection| detection.confidence >= DETECTION_THRESHOLD)
+    }
+
+    /// Decode the frame that starts `offset` hops into the fold
+    fn decode_at(&self, offset: usize, folds: f64) -> Option<WatermarkDetection> {
+        // The middle hops of each bit, clear of its edges
+        let symbol = |index: usize| -> f64 {
+            let start = offset + index * HOPS_PER_SYMBOL;
+            (1..HOPS_PER_SYMBOL - 1).map(|hop| self.sums[(start + hop) % FRAME_HOPS]).sum::<f64>()
+                / ((HOPS_PER_SYMBOL - 2) as f64 * folds)
+        };
+        let soft: Vec<f64> = (0..FRAME_BITS).map(symbol).collect();
+        let byte = |index: usize| -> u8 {
+            soft[index * 8..index * 8 + 8].iter().fold(0, |byte, &bit| (byte << 1) | u8::from(bit > 0.0))
+        };
+        let id = u16::from_be_bytes([byte(1), byte(2)]);
+        if byte(0) != PREAMBLE || byte(3) != crc8(id) {
+            return None;
+        }
+        let confidence = soft.iter().map(|bit| bit.abs()).sum::<f64>() / FRAME_BITS as f64;
+        Some(WatermarkDetection { id, confidence: confidence.min(1.0) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{create_decoder, create_encoder, CodecType};
+
+    /// A 1kHz tone at -12 dBFS
+    fn tone(sample_rate: u32, secs: f64) -> Vec<i16> {
+        let amplitude = 32768.0 * 10f64.powf(-12.0 / 20.0) * std::f64::consts::SQRT_2;
+        (0..(f64::from(sample_rate) * secs) as usize)
+            .map(|i| (amplitude * (2.0 * PI * 1000.0 * i as f64 / f64::from(sample_rate)).sin()) as i16)
+            .collect()
+    }
+
+    fn detect(samples: &[i16], sample_rate: u32) -> Option<WatermarkDetection> {
+        let mut meter = WatermarkMeter::new(sample_rate);
+        let mut stats = WatermarkStats::default();
+        let mut hops = Vec::new();
+        // In 20ms packets, as the analyzer sees them
+        for packet in samples.chunks((sample_rate / 50) as usize) {
+            hops.clear();
+            meter.push(packet, &mut hops);
+            for &hop in &hops {
+                stats.add(hop);
+            }
+        }
+        stats.detect()
+    }
+
+    /// Through G.711 µ-law, with every 50th 20ms packet lost to silence
+    fn through_g711_with_loss(samples: &[i16]) -> Vec<i16> {
+        let mut encoder = create_encoder(CodecType::G711Ulaw).unwrap();
+        let mut decoder = create_decoder(CodecType::G711Ulaw).unwrap();
+        let mut received = Vec::new();
+        for (index, frame) in samples.chunks_exact(160).enumerate() {
+            let payload = encoder.encode(frame).unwrap();
+            if index % 50 == 7 {
+                received.extend([0; 160]);
+            } else {
+                received.extend(decoder.decode(&payload).unwrap());
+            }
+        }
+        received
+    }
+
+    #[test]
+    fn test_frame_layout() {
+        let bits = frame_bits(0x1234);
+        let byte = |index: usize| bits[index * 8..index * 8 + 8].iter().fold(0u8, |byte, &bit| (byte << 1) | u8::from(bit));
+        assert_eq!([byte(0), byte(1), byte(2)], [PREAMBLE, 0x12, 0x34]);
+        assert_eq!(byte(3), crc8(0x1234));
+        assert_ne!(crc8(0x1234), crc8(0x1235));
+        assert!(parse_level("-30").is_ok());
+        assert!(parse_level("-5").is_err());
+        assert!(parse_level("quiet").is_err());
+    }
+
+    #[test]
+    fn test_detected_through_g711_with_loss() {
+        let mut samples = tone(8000, 4.0);
+        embed(&mut samples, 8000, 4660, DEFAULT_LEVEL_DBFS);
+        // Starting part way into a bit, as a page joined late would
+        let received = through_g711_with_loss(&samples[123..]);
+        let detection = detect(&received, 8000).expect("watermark should be found");
+        assert_eq!(detection.id, 4660);
+        assert!(detection.confidence > 0.8, "confidence {}", detection.confidence);
+
+        // The marker is quiet beside the tone
+        let rms = |s: &[i16]| (s.iter().map(|&x| f64::from(x).powi(2)).sum::<f64>() / s.len() as f64).sqrt();
+        let clean = tone(8000, 4.0);
+        let added: Vec<i16> = samples.iter().zip(&clean).map(|(a, b)| a - b).collect();
+        let added_dbfs = 20.0 * (rms(&added) / 32768.0).log10();
+        assert!((added_dbfs - DEFAULT_LEVEL_DBFS).abs() < 0.5, "marker at {} dBFS", added_dbfs);
+    }
+
+    #[test]
+    fn test_not_detected_without_watermark() {
+        assert_eq!(detect(&through_g711_with_loss(&tone(8000, 4.0)), 8000), None);
+        assert_eq!(detect(&vec![0; 32000], 8000), None);
+
+        // Too short for a whole frame
+        let mut short = tone(16000, 1.0);
+        embed(&mut short, 16000, 7, DEFAULT_LEVEL_DBFS);
+        assert_eq!(detect(&short, 16000), None);
+        let mut wideband = tone(16000, 3.0);
+        embed(&mut wideband, 16000, 7, DEFAULT_LEVEL_DBFS);
+        assert_eq!(detect(&wideband, 16000).map(|d| d.id), Some(7));
+    }
+}
@@ -10,7 +10,7 @@ impl G711UlawCodec {
 
     /// Decode a single u-law sample to linear PCM
     #[inline]
-    fn decode_sample(ulaw: u8) -> i16 {
+    pub fn decode_sample(ulaw: u8) -> i16 {
         // Invert all bits (u-law is stored inverted)
         let ulaw = !ulaw;
 
@@ -117,7 +117,7 @@ impl G711AlawCodec {
 
     /// Decode a single A-law sample to linear PCM
     #[inline]
-    fn decode_sample(alaw: u8) -> i16 {
+    pub fn decode_sample(alaw: u8) -> i16 {
         // XOR with 0x55 to undo bit inversion
         let alaw = alaw ^ 0x55;
 
@@ -16,6 +16,12 @@
 
 use super::traits::{AudioEncoder, CodecError, CodecType};
 
+/// Audio carried by a G.722 payload in ticks of its RTP clock. Each byte is
+/// two 16kHz samples, and RFC 3551 keeps G.722's clock at 8kHz: a tick a byte
+pub fn packet_duration(payload: &[u8]) -> Option<u32> {
+    u32::try_from(payload.len()).ok().filter(|&ticks| ticks > 0)
+}
+
 /// G.722 encoder state
 pub struct G722Encoder {
     /// Lower band quantizer state
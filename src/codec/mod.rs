@@ -7,9 +7,14 @@ pub mod traits;
 
 pub use g711::{G711AlawCodec, G711UlawCodec};
 pub use opus::{OpusDecoder, OpusEncoder};
-pub use pcm::L16Codec;
-pub use subprocess::{FfmpegG711AlawEncoder, FfmpegG711UlawEncoder, FfmpegG722Decoder, FfmpegG722Encoder};
-pub use traits::{AudioDecoder, AudioEncoder, CodecError, CodecType};
+pub use pcm::{L16Codec, L24Codec};
+pub use subprocess::{
+    ffmpeg_support, FfmpegG711AlawEncoder, FfmpegG711UlawEncoder, FfmpegG722Decoder, FfmpegG722Encoder,
+    FfmpegStreamEncoder, FFMPEG_CODECS, FFMPEG_ENV,
+};
+pub use traits::{AudioDecoder, AudioEncoder, CodecError, CodecSpec, CodecType};
+
+use crate::capabilities::{Capabilities, CodecCapability};
 
 /// Create a decoder for the given codec type
 pub fn create_decoder(codec_type: CodecType) -> Result<Box<dyn AudioDecoder>, CodecError> {
@@ -19,13 +24,24 @@ pub fn create_decoder(codec_type: CodecType) -> Result<Box<dyn AudioDecoder>, Co
         CodecType::G722 => Ok(Box::new(FfmpegG722Decoder::new()?)),
         CodecType::Opus => Ok(Box::new(OpusDecoder::new_stereo()?)),
         CodecType::L16 => Ok(Box::new(L16Codec::standard_mono())),
+        CodecType::L24 => Ok(Box::new(L24Codec::new(48000, 1))),
+    }
+}
+
+/// Create a decoder for a codec spec, honouring its rate and channel count
+pub fn create_decoder_for_spec(spec: CodecSpec) -> Result<Box<dyn AudioDecoder>, CodecError> {
+    match spec.codec {
+        CodecType::L16 => Ok(Box::new(L16Codec::new(spec.sample_rate, spec.channels))),
+        CodecType::L24 => Ok(Box::new(L24Codec::new(spec.sample_rate, spec.channels))),
+        codec_type => create_decoder(codec_type),
     }
 }
 
-/// Create a decoder based on RTP payload type
+/// Create a decoder based on RTP payload type, with the channel layout the
+/// type implies (see [`CodecSpec::from_payload_type`])
 pub fn create_decoder_for_payload_type(pt: u8) -> Result<Box<dyn AudioDecoder>, CodecError> {
-    match CodecType::from_payload_type(pt) {
-        Some(codec_type) => create_decoder(codec_type),
+    match CodecSpec::from_payload_type(pt) {
+        Some(spec) => create_decoder_for_spec(spec),
         None => Err(CodecError::UnsupportedPayloadType(pt)),
     }
 }
@@ -38,6 +54,84 @@ pub fn create_encoder(codec_type: CodecType) -> Result<Box<dyn AudioEncoder>, Co
         CodecType::G722 => Ok(Box::new(FfmpegG722Encoder::new()?)),
         CodecType::Opus => Ok(Box::new(OpusEncoder::new_mono(24000)?)),
         CodecType::L16 => Ok(Box::new(L16Codec::telephony())),
+        CodecType::L24 => Ok(Box::new(L24Codec::new(48000, 1))),
+    }
+}
+
+/// Encode a whole stream's samples into frames, padding the last one.
+/// G.722 goes through ffmpeg in one pass rather than once per frame.
+pub fn encode_all(codec_type: CodecType, samples: &[i16]) -> Result<Vec<Vec<u8>>, CodecError> {
+    if codec_type == CodecType::G722 {
+        return FfmpegG722Encoder::new()?.encode_all(samples);
+    }
+    let mut encoder = create_encoder(codec_type)?;
+    let frame_size = encoder.frame_size();
+    let mut frames = Vec::with_capacity(samples.len().div_ceil(frame_size));
+    for chunk in samples.chunks(frame_size) {
+        let mut frame = chunk.to_vec();
+        frame.resize(frame_size, 0);
+        frames.push(encoder.encode(&frame)?);
+    }
+    Ok(frames)
+}
+
+/// Stands in for a decoder whose backend is missing. Every packet is
+/// rejected with [`CodecError::BackendUnavailable`], so a receiver counts
+/// the page's packets as undecodable instead of failing the page
+pub struct UnavailableDecoder {
+    codec: CodecType,
+    missing: String,
+}
+
+impl UnavailableDecoder {
+    pub fn new(codec: CodecType, missing: String) -> Self {
+        Self { codec, missing }
+    }
+}
+
+impl AudioDecoder for UnavailableDecoder {
+    fn decode(&mut self, _input: &[u8]) -> Result<Vec<i16>, CodecError> {
+        Err(CodecError::BackendUnavailable { codec: self.codec, missing: self.missing.clone() })
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.codec.sample_rate()
+    }
+
+    fn channels(&self) -> u8 {
+        self.codec.channels()
+    }
+
+    fn codec_type(&self) -> CodecType {
+        self.codec
+    }
+}
+
+/// Register every codec, probing whether each can be used right now
+pub fn register_capabilities(capabilities: &mut Capabilities) {
+    let ffmpeg = ffmpeg_support();
+    capabilities.add_dependency("ffmpeg", ffmpeg.available);
+    for name in FFMPEG_CODECS {
+        capabilities.add_ffmpeg_codec(name, ffmpeg.codec(name));
+    }
+
+    for codec in CodecType::ALL {
+        let encoder = create_encoder(codec);
+        let decoder = create_decoder(codec);
+        let probe = encoder.as_ref().err().or(decoder.as_ref().err());
+        capabilities.add_codec(CodecCapability {
+            name: codec.cli_name(),
+            description: codec.name(),
+            sample_rate: codec.sample_rate(),
+            channels: codec.channels(),
+            rtp_clock_rate: codec.rtp_clock_rate(),
+            configurable: codec.is_linear(),
+            requires: codec.runtime_requirements().to_vec(),
+            can_encode: encoder.is_ok(),
+            can_decode: decoder.is_ok(),
+            usable: probe.is_none(),
+            unavailable_reason: probe.map(ToString::to_string),
+        });
     }
 }
 
@@ -70,6 +164,44 @@ mod tests {
         let decoder = create_decoder_for_payload_type(8);
         assert!(decoder.is_ok());
         assert_eq!(decoder.unwrap().codec_type(), CodecType::G711Alaw);
+
+        // L16 stereo and mono, both 44.1kHz
+        let decoder = create_decoder_for_payload_type(10).unwrap();
+        assert_eq!((decoder.codec_type(), decoder.sample_rate(), decoder.channels()), (CodecType::L16, 44100, 2));
+        let decoder = create_decoder_for_payload_type(11).unwrap();
+        assert_eq!((decoder.codec_type(), decoder.sample_rate(), decoder.channels()), (CodecType::L16, 44100, 1));
+        assert_eq!(CodecSpec::from_payload_type(10).unwrap().rtp_clock_rate(), 44100);
+    }
+
+    #[test]
+    fn test_codec_spec_parse() {
+        let spec = CodecSpec::parse("l24:48000:2").unwrap();
+        assert_eq!(spec.codec, CodecType::L24);
+        assert_eq!(spec.sample_rate, 48000);
+        assert_eq!(spec.channels, 2);
+        assert_eq!(spec.rtp_clock_rate(), 48000);
+
+        // Bare names take the codec defaults
+        assert_eq!(CodecSpec::parse("PCMU"), Some(CodecSpec::new(CodecType::G711Ulaw)));
+        assert_eq!(CodecSpec::parse("l16:16000").unwrap().channels, 1);
+        assert_eq!(CodecSpec::parse("g722").unwrap().rtp_clock_rate(), 8000);
+
+        // Fixed-rate codecs can't be reparameterized
+        assert!(CodecSpec::parse("g711ulaw:16000").is_none());
+        assert!(CodecSpec::parse("g711ulaw:8000:1").is_some());
+
+        assert!(CodecSpec::parse("l24:0").is_none());
+        assert!(CodecSpec::parse("l24:48000:9").is_none());
+        assert!(CodecSpec::parse("l24:48000:2:x").is_none());
+        assert!(CodecSpec::parse("mp3").is_none());
+    }
+
+    #[test]
+    fn test_create_decoder_for_spec() {
+        let decoder = create_decoder_for_spec(CodecSpec::parse("l24:48000:2").unwrap()).unwrap();
+        assert_eq!(decoder.codec_type(), CodecType::L24);
+        assert_eq!(decoder.sample_rate(), 48000);
+        assert_eq!(decoder.channels(), 2);
     }
 
     #[test]
@@ -77,4 +209,36 @@ mod tests {
         let encoder = create_encoder(CodecType::G711Ulaw);
         assert!(encoder.is_ok());
     }
+
+    #[test]
+    fn test_missing_g722_encoder() {
+        let support = subprocess::FfmpegSupport {
+            available: true,
+            g722: subprocess::CodecSupport { decode: true, encode: false },
+            ..subprocess::FfmpegSupport::default()
+        };
+        let _stub = subprocess::stub_ffmpeg_support(support);
+
+        assert!(create_decoder(CodecType::G722).is_ok());
+        let Err(err) = create_encoder(CodecType::G722) else {
+            panic!("G.722 encoder created without an ffmpeg encoder");
+        };
+        assert!(
+            matches!(err, CodecError::BackendUnavailable { codec: CodecType::G722, ref missing } if missing == "ffmpeg g722 encoder"),
+            "{:?}",
+            err
+        );
+
+        let mut capabilities = Capabilities::new();
+        register_capabilities(&mut capabilities);
+        let g722 = capabilities.codecs.iter().find(|codec| codec.name == "g722").unwrap();
+        assert!(g722.can_decode && !g722.can_encode && !g722.usable);
+        assert_eq!(g722.unavailable_reason.as_deref(), Some("G.722 needs the ffmpeg g722 encoder, which isn't available"));
+        assert!(capabilities.ffmpeg_codecs["g722"].decode);
+
+        // The placeholder rejects packets at the codec's rate and layout
+        let mut decoder = UnavailableDecoder::new(CodecType::G722, err.to_string());
+        assert_eq!(decoder.sample_rate(), 16000);
+        assert!(matches!(decoder.decode(&[0; 160]), Err(CodecError::BackendUnavailable { .. })));
+    }
 }
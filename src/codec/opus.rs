@@ -7,6 +7,9 @@ use audiopus::{coder, Channels, MutSignals, SampleRate};
 use audiopus::packet::Packet;
 use std::convert::TryInto;
 
+/// Longest audio an Opus packet may carry, 120ms at 48kHz
+const MAX_PACKET_SAMPLES: u32 = 5760;
+
 /// Opus decoder
 pub struct OpusDecoder {
     decoder: coder::Decoder,
@@ -64,6 +67,32 @@ impl AudioDecoder for OpusDecoder {
         Ok(output)
     }
 
+    /// Packet loss concealment: decoding without a packet extrapolates from
+    /// the audio before it, fading out over a long loss. PLC works in whole
+    /// 2.5ms steps of up to 120ms, so a remainder shorter than a step is silence
+    fn conceal(&mut self, frames: usize) -> Result<Vec<i16>, CodecError> {
+        let channels = self.channels as usize;
+        let step = self.sample_rate as usize / 400;
+        let max_chunk = self.sample_rate as usize * 120 / 1000;
+        let mut output = vec![0i16; frames * channels];
+        let mut done = 0;
+        while frames - done >= step {
+            let chunk = ((frames - done) / step * step).min(max_chunk);
+            let signals: MutSignals<'_, i16> = (&mut output[done * channels..(done + chunk) * channels])
+                .try_into()
+                .map_err(|e| CodecError::DecodeError(format!("Failed to create signals: {:?}", e)))?;
+            let concealed = self
+                .decoder
+                .decode(None::<Packet<'_>>, signals, false)
+                .map_err(|e| CodecError::DecodeError(format!("Opus concealment error: {}", e)))?;
+            if concealed == 0 {
+                break;
+            }
+            done += concealed;
+        }
+        Ok(output)
+    }
+
     fn sample_rate(&self) -> u32 {
         self.sample_rate
     }
@@ -151,6 +180,28 @@ impl AudioEncoder for OpusEncoder {
     }
 }
 
+/// Audio carried by an Opus packet in 48kHz samples (the Opus RTP clock),
+/// read from its TOC byte (RFC 6716 section 3.1). `None` if malformed.
+pub fn packet_duration(packet: &[u8]) -> Option<u32> {
+    let &toc = packet.first()?;
+    let config = toc >> 3;
+    let frame_samples = match config {
+        // SILK: 10, 20, 40, 60ms
+        0..=11 => [480, 960, 1920, 2880][usize::from(config % 4)],
+        // Hybrid: 10, 20ms
+        12..=15 => [480, 960][usize::from(config % 2)],
+        // CELT: 2.5, 5, 10, 20ms
+        _ => [120, 240, 480, 960][usize::from(config % 4)],
+    };
+    let frames = match toc & 0x03 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => u32::from(packet.get(1)? & 0x3f),
+    };
+    let samples = frame_samples * frames;
+    (frames > 0 && samples <= MAX_PACKET_SAMPLES).then_some(samples)
+}
+
 fn sample_rate_to_opus(rate: u32) -> Result<SampleRate, CodecError> {
     match rate {
         8000 => Ok(SampleRate::Hz8000),
@@ -213,6 +264,23 @@ mod tests {
         assert!(encoded.len() < 1000); // Sanity check
     }
 
+    #[test]
+    fn test_packet_duration_from_toc() {
+        // SILK 20ms, one frame
+        assert_eq!(packet_duration(&[(1 << 3), 0xAA]), Some(960));
+        // SILK 60ms, two frames of equal size
+        assert_eq!(packet_duration(&[(3 << 3) | 1, 0xAA]), Some(5760));
+        // Hybrid 10ms
+        assert_eq!(packet_duration(&[12 << 3]), Some(480));
+        // CELT 2.5ms, code 3 with 4 frames
+        assert_eq!(packet_duration(&[(16 << 3) | 3, 4]), Some(480));
+        // CELT 20ms, code 3 with 7 frames is over 120ms
+        assert_eq!(packet_duration(&[(19 << 3) | 3, 7]), None);
+        // Code 3 needs its frame count byte
+        assert_eq!(packet_duration(&[(19 << 3) | 3]), None);
+        assert_eq!(packet_duration(&[]), None);
+    }
+
     #[test]
     fn test_opus_frame_size() {
         let encoder = OpusEncoder::new_mono(24000).unwrap();
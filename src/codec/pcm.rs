@@ -98,6 +98,128 @@ impl AudioEncoder for L16Codec {
     }
 }
 
+/// Linear PCM (L24) codec - big-endian 24-bit signed samples (AES67)
+///
+/// The rest of the pipeline works in 16-bit, so decoding drops the low byte
+/// of each sample. By default it is truncated; with dither enabled, TPDF noise
+/// of one 16-bit LSB is added first to decorrelate the quantization error.
+pub struct L24Codec {
+    sample_rate: u32,
+    channels: u8,
+    dither: bool,
+    rng_state: u32,
+}
+
+impl L24Codec {
+    pub fn new(sample_rate: u32, channels: u8) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            dither: false,
+            rng_state: 0x2545_F491,
+        }
+    }
+
+    /// Create a standard AES67 48kHz stereo L24 codec
+    pub fn aes67_stereo() -> Self {
+        Self::new(48000, 2)
+    }
+
+    /// Add TPDF dither when reducing samples to 16-bit
+    #[must_use]
+    pub fn with_dither(mut self) -> Self {
+        self.dither = true;
+        self
+    }
+
+    /// Triangular noise spanning +/- one 16-bit LSB, in 24-bit units
+    fn tpdf_noise(&mut self) -> i32 {
+        let mut next = || {
+            self.rng_state = self.rng_state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            ((self.rng_state >> 16) & 0xFF) as i32
+        };
+        next() - next()
+    }
+
+    fn reduce(&mut self, sample: i32) -> i16 {
+        let sample = if self.dither {
+            sample + self.tpdf_noise()
+        } else {
+            sample
+        };
+        (sample >> 8).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    }
+}
+
+impl Default for L24Codec {
+    fn default() -> Self {
+        Self::aes67_stereo()
+    }
+}
+
+impl AudioDecoder for L24Codec {
+    fn decode(&mut self, input: &[u8]) -> Result<Vec<i16>, CodecError> {
+        if !input.len().is_multiple_of(3) {
+            return Err(CodecError::InvalidFrame(
+                "L24 data must be a multiple of 3 bytes".into(),
+            ));
+        }
+
+        let samples: Vec<i16> = input
+            .chunks_exact(3)
+            .map(|chunk| {
+                // Sign-extend via the top byte of an i32
+                let sample = i32::from_be_bytes([chunk[0], chunk[1], chunk[2], 0]) >> 8;
+                self.reduce(sample)
+            })
+            .collect();
+
+        Ok(samples)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u8 {
+        self.channels
+    }
+
+    fn codec_type(&self) -> CodecType {
+        CodecType::L24
+    }
+}
+
+impl AudioEncoder for L24Codec {
+    fn encode(&mut self, samples: &[i16]) -> Result<Vec<u8>, CodecError> {
+        let mut output = Vec::with_capacity(samples.len() * 3);
+
+        for &sample in samples {
+            let [hi, lo] = sample.to_be_bytes();
+            output.extend_from_slice(&[hi, lo, 0]);
+        }
+
+        Ok(output)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u8 {
+        self.channels
+    }
+
+    fn codec_type(&self) -> CodecType {
+        CodecType::L24
+    }
+
+    fn frame_size(&self) -> usize {
+        // 20ms worth of samples
+        (self.sample_rate as usize * 20 / 1000) * self.channels as usize
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -147,4 +269,41 @@ mod tests {
         assert_eq!(AudioDecoder::sample_rate(&telephony), 8000);
         assert_eq!(AudioDecoder::channels(&telephony), 1);
     }
+
+    #[test]
+    fn test_l24_decode_truncates() {
+        let mut codec = L24Codec::new(48000, 2);
+
+        // 0x123456 -> 0x1234, -1 (0xFFFFFF) -> -1, min/max keep their extremes
+        let input = [0x12, 0x34, 0x56, 0xFF, 0xFF, 0xFF, 0x80, 0x00, 0x00, 0x7F, 0xFF, 0xFF];
+        let decoded = codec.decode(&input).unwrap();
+        assert_eq!(decoded, vec![0x1234, -1, i16::MIN, i16::MAX]);
+    }
+
+    #[test]
+    fn test_l24_roundtrip() {
+        let mut codec = L24Codec::aes67_stereo();
+
+        let original: Vec<i16> = vec![0, 1000, -1000, 32767, -32768, 12345];
+        let encoded = codec.encode(&original).unwrap();
+        assert_eq!(encoded.len(), original.len() * 3);
+        assert_eq!(codec.decode(&encoded).unwrap(), original);
+    }
+
+    #[test]
+    fn test_l24_dither_stays_within_one_lsb() {
+        let mut codec = L24Codec::new(48000, 1).with_dither();
+
+        // 0x100080 sits halfway between 0x1000 and 0x1001
+        let input: Vec<u8> = [0x10, 0x00, 0x80].repeat(1000);
+        let decoded = codec.decode(&input).unwrap();
+        assert!(decoded.iter().all(|&s| (0x0FFF..=0x1001).contains(&s)));
+        assert!(decoded.contains(&0x1000) && decoded.contains(&0x1001));
+    }
+
+    #[test]
+    fn test_l24_partial_sample() {
+        let mut codec = L24Codec::default();
+        assert!(codec.decode(&[0x00, 0x01, 0x02, 0x03]).is_err());
+    }
 }
@@ -4,18 +4,155 @@
 //! ffmpeg's high-quality codec implementations without complex library bindings.
 
 use super::traits::{AudioEncoder, CodecError, CodecType};
-use std::io::Write;
-use std::process::{Command, Stdio};
-
-/// Check that ffmpeg is available
-fn check_ffmpeg() -> Result<(), CodecError> {
-    Command::new("ffmpeg")
-        .arg("-version")
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .map_err(|_| CodecError::InitError("ffmpeg not found in PATH".into()))?;
-    Ok(())
+use serde::Serialize;
+#[cfg(test)]
+use std::cell::Cell;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::OnceLock;
+
+/// Environment variable naming the ffmpeg to run, e.g. a full path to
+/// `ffmpeg.exe` when it isn't on PATH
+pub const FFMPEG_ENV: &str = "MPU_FFMPEG";
+
+/// The ffmpeg program: `MPU_FFMPEG` if set, otherwise `ffmpeg` looked up on
+/// PATH (which finds `ffmpeg.exe` on Windows)
+pub fn ffmpeg_program() -> &'static OsStr {
+    static PROGRAM: OnceLock<OsString> = OnceLock::new();
+    PROGRAM.get_or_init(|| {
+        std::env::var_os(FFMPEG_ENV)
+            .filter(|program| !program.is_empty())
+            .unwrap_or_else(|| OsString::from("ffmpeg"))
+    })
+}
+
+/// Whether an ffmpeg codec can decode and encode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CodecSupport {
+    pub decode: bool,
+    pub encode: bool,
+}
+
+/// Which way audio goes through an ffmpeg codec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Decode,
+    Encode,
+}
+
+impl Direction {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Decode => "decoder",
+            Self::Encode => "encoder",
+        }
+    }
+}
+
+/// What the installed ffmpeg can do, for the codecs this tool runs through it.
+/// Minimal builds often leave out encoders while keeping the decoders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FfmpegSupport {
+    /// Whether ffmpeg could be run at all
+    pub available: bool,
+    pub g722: CodecSupport,
+    pub pcm_mulaw: CodecSupport,
+    pub pcm_alaw: CodecSupport,
+}
+
+/// The ffmpeg codecs probed, by their ffmpeg names
+pub const FFMPEG_CODECS: [&str; 3] = ["g722", "pcm_mulaw", "pcm_alaw"];
+
+impl FfmpegSupport {
+    /// Read the listing from `ffmpeg -codecs`. Each codec line starts with
+    /// flags, `D` first for decoding and `E` second for encoding:
+    /// ` DEA..S g722    G.722 ADPCM (decoders: g722 ) (encoders: g722 )`.
+    /// The lines before the `-------` rule explain the flags
+    pub fn parse_codecs(listing: &str) -> Self {
+        let mut support = Self { available: true, ..Self::default() };
+        for line in listing.lines().skip_while(|line| !line.trim_start().starts_with("---")).skip(1) {
+            let mut fields = line.split_whitespace();
+            let (Some(flags), Some(name)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let flags = flags.as_bytes();
+            let codec = CodecSupport {
+                decode: flags.first() == Some(&b'D'),
+                encode: flags.get(1) == Some(&b'E'),
+            };
+            match name {
+                "g722" => support.g722 = codec,
+                "pcm_mulaw" => support.pcm_mulaw = codec,
+                "pcm_alaw" => support.pcm_alaw = codec,
+                _ => {}
+            }
+        }
+        support
+    }
+
+    /// Support for one of [`FFMPEG_CODECS`]
+    pub fn codec(&self, ffmpeg_codec: &str) -> CodecSupport {
+        match ffmpeg_codec {
+            "g722" => self.g722,
+            "pcm_mulaw" => self.pcm_mulaw,
+            "pcm_alaw" => self.pcm_alaw,
+            _ => CodecSupport::default(),
+        }
+    }
+
+    /// Fail with [`CodecError::BackendUnavailable`] unless `codec` can go
+    /// `direction` through ffmpeg's `ffmpeg_codec`
+    pub fn check(&self, codec: CodecType, ffmpeg_codec: &str, direction: Direction) -> Result<(), CodecError> {
+        if !self.available {
+            return Err(CodecError::BackendUnavailable { codec, missing: "ffmpeg".to_string() });
+        }
+        let support = self.codec(ffmpeg_codec);
+        let supported = match direction {
+            Direction::Decode => support.decode,
+            Direction::Encode => support.encode,
+        };
+        if supported {
+            Ok(())
+        } else {
+            Err(CodecError::BackendUnavailable { codec, missing: format!("ffmpeg {} {}", ffmpeg_codec, direction.name()) })
+        }
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    static STUB: Cell<Option<FfmpegSupport>> = const { Cell::new(None) };
+}
+
+/// What the installed ffmpeg supports, from running `ffmpeg -codecs` once
+/// per process
+pub fn ffmpeg_support() -> FfmpegSupport {
+    #[cfg(test)]
+    if let Some(stub) = STUB.get() {
+        return stub;
+    }
+
+    static PROBED: OnceLock<FfmpegSupport> = OnceLock::new();
+    *PROBED.get_or_init(|| {
+        match Command::new(ffmpeg_program()).args(["-hide_banner", "-codecs"]).stderr(Stdio::null()).output() {
+            Ok(output) if output.status.success() => FfmpegSupport::parse_codecs(&String::from_utf8_lossy(&output.stdout)),
+            _ => FfmpegSupport::default(),
+        }
+    })
+}
+
+/// Make [`ffmpeg_support`] report `support` on this thread until the
+/// returned guard is dropped
+#[cfg(test)]
+pub fn stub_ffmpeg_support(support: FfmpegSupport) -> impl Drop {
+    struct Restore(Option<FfmpegSupport>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            STUB.set(self.0);
+        }
+    }
+    Restore(STUB.replace(Some(support)))
 }
 
 /// Encode samples using ffmpeg with specified codec
@@ -37,7 +174,7 @@ fn encode_with_ffmpeg(
         .collect();
 
     // Run ffmpeg to encode
-    let mut child = Command::new("ffmpeg")
+    let mut child = Command::new(ffmpeg_program())
         .args([
             "-f", "s16le",
             "-ar", &input_rate.to_string(),
@@ -98,7 +235,7 @@ fn decode_with_ffmpeg(
 
     // Run ffmpeg to decode
     // Note: Don't specify input sample rate - ffmpeg infers it from the codec
-    let mut child = Command::new("ffmpeg")
+    let mut child = Command::new(ffmpeg_program())
         .args([
             "-f", format,
             "-i", "pipe:0",
@@ -150,7 +287,7 @@ pub struct FfmpegG722Decoder {
 
 impl FfmpegG722Decoder {
     pub fn new() -> Result<Self, CodecError> {
-        check_ffmpeg()?;
+        ffmpeg_support().check(CodecType::G722, "g722", Direction::Decode)?;
         Ok(Self {
             buffer: Vec::new(),
             decode_threshold: 1600, // 10 frames worth
@@ -179,6 +316,11 @@ impl super::traits::AudioDecoder for FfmpegG722Decoder {
         decode_with_ffmpeg(&to_decode, "g722", 16000)
     }
 
+    fn flush(&mut self) -> Result<Vec<i16>, CodecError> {
+        let to_decode: Vec<u8> = self.buffer.drain(..).collect();
+        decode_with_ffmpeg(&to_decode, "g722", 16000)
+    }
+
     fn sample_rate(&self) -> u32 {
         16000
     }
@@ -202,7 +344,7 @@ pub struct FfmpegG722Encoder {
 
 impl FfmpegG722Encoder {
     pub fn new() -> Result<Self, CodecError> {
-        check_ffmpeg()?;
+        ffmpeg_support().check(CodecType::G722, "g722", Direction::Encode)?;
         Ok(Self {
             buffer: Vec::new(),
             // G.722: 320 samples (20ms at 16kHz) -> 160 bytes output
@@ -225,7 +367,7 @@ pub struct FfmpegG711UlawEncoder;
 
 impl FfmpegG711UlawEncoder {
     pub fn new() -> Result<Self, CodecError> {
-        check_ffmpeg()?;
+        ffmpeg_support().check(CodecType::G711Ulaw, "pcm_mulaw", Direction::Encode)?;
         Ok(Self)
     }
 
@@ -244,7 +386,7 @@ pub struct FfmpegG711AlawEncoder;
 
 impl FfmpegG711AlawEncoder {
     pub fn new() -> Result<Self, CodecError> {
-        check_ffmpeg()?;
+        ffmpeg_support().check(CodecType::G711Alaw, "pcm_alaw", Direction::Encode)?;
         Ok(Self)
     }
 
@@ -256,6 +398,119 @@ impl FfmpegG711AlawEncoder {
     }
 }
 
+/// ffmpeg encoding PCM written to it a block at a time, for a stream too
+/// long to encode in one piece. One thread writes the PCM while another
+/// reads the frames back, so neither pipe fills and stalls ffmpeg
+pub struct FfmpegStreamEncoder {
+    child: Child,
+    input: Option<ChildStdin>,
+    output: ChildStdout,
+    frame_bytes: usize,
+    finished: bool,
+}
+
+impl FfmpegStreamEncoder {
+    /// ffmpeg's codec and format names for `codec`, and the rate it's encoded at
+    fn format(codec: CodecType) -> Result<(&'static str, &'static str, u32), CodecError> {
+        match codec {
+            CodecType::G722 => Ok(("g722", "g722", 16000)),
+            CodecType::G711Ulaw => Ok(("pcm_mulaw", "mulaw", 8000)),
+            CodecType::G711Alaw => Ok(("pcm_alaw", "alaw", 8000)),
+            other => Err(CodecError::EncodeError(format!("{} isn't encoded through ffmpeg", other))),
+        }
+    }
+
+    /// Check ffmpeg can encode `codec` without starting it
+    pub fn check(codec: CodecType) -> Result<(), CodecError> {
+        let (ffmpeg_codec, _, _) = Self::format(codec)?;
+        ffmpeg_support().check(codec, ffmpeg_codec, Direction::Encode)
+    }
+
+    /// Start ffmpeg encoding G.722 or G.711 in frames of `frame_samples`
+    pub fn spawn(codec: CodecType, frame_samples: usize) -> Result<Self, CodecError> {
+        Self::check(codec)?;
+        let (ffmpeg_codec, format, rate) = Self::format(codec)?;
+        // G.722 packs two samples a byte, G.711 one
+        let frame_bytes = if codec == CodecType::G722 { frame_samples / 2 } else { frame_samples };
+
+        let mut child = Command::new(ffmpeg_program())
+            .args([
+                "-f", "s16le",
+                "-ar", &rate.to_string(),
+                "-ac", "1",
+                "-i", "pipe:0",
+                "-acodec", ffmpeg_codec,
+                "-f", format,
+                "pipe:1",
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| CodecError::EncodeError(format!("Failed to spawn ffmpeg: {}", e)))?;
+        let (Some(input), Some(output)) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            return Err(CodecError::EncodeError("Failed to open ffmpeg pipes".into()));
+        };
+        Ok(Self {
+            child,
+            input: Some(input),
+            output,
+            frame_bytes,
+            finished: false,
+        })
+    }
+
+    /// The pipe the PCM goes into, for the writing thread. Dropping it ends the stream
+    pub fn take_input(&mut self) -> Option<ChildStdin> {
+        self.input.take()
+    }
+
+    /// The next frame, the last one padded with silence, or `None` once ffmpeg is done
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, CodecError> {
+        let mut frame = vec![0u8; self.frame_bytes];
+        let mut filled = 0;
+        while filled < frame.len() {
+            match self.output.read(&mut frame[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(CodecError::EncodeError(format!("Failed to read from ffmpeg: {}", e))),
+            }
+        }
+        Ok((filled > 0).then_some(frame))
+    }
+
+    /// Wait for ffmpeg to exit, and check it succeeded
+    pub fn finish(mut self) -> Result<(), CodecError> {
+        self.finished = true;
+        let status = self
+            .child
+            .wait()
+            .map_err(|e| CodecError::EncodeError(format!("ffmpeg failed: {}", e)))?;
+        if !status.success() {
+            return Err(CodecError::EncodeError("ffmpeg encoding failed".into()));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FfmpegStreamEncoder {
+    fn drop(&mut self) {
+        // Abandoned part way, as when a page is cut short
+        if !self.finished {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Write samples into an ffmpeg stream as the 16-bit little-endian PCM it reads
+pub fn write_pcm(input: &mut impl Write, samples: &[i16]) -> io::Result<()> {
+    let bytes: Vec<u8> = samples.iter().flat_map(|&s| s.to_le_bytes()).collect();
+    input.write_all(&bytes)
+}
+
 impl Default for FfmpegG722Encoder {
     fn default() -> Self {
         Self::new().expect("Failed to create FFmpeg G.722 encoder")
@@ -301,6 +556,58 @@ impl AudioEncoder for FfmpegG722Encoder {
 mod tests {
     use super::*;
 
+    /// `ffmpeg -codecs` from a build with the G.722 and A-law decoders but
+    /// not their encoders
+    const MINIMAL_LISTING: &str = "\
+Codecs:
+ D..... = Decoding supported
+ .E.... = Encoding supported
+ ..V... = Video codec
+ ..A... = Audio codec
+ -------
+ D.VI.S h264                 H.264 / AVC / MPEG-4 AVC / MPEG-4 part 10
+ D.AI.S g722                 G.722 ADPCM (decoders: g722 )
+ DEAIL. opus                 Opus (Opus Interactive Audio Codec) (decoders: opus libopus ) (encoders: opus libopus )
+ D.AIL. pcm_alaw             PCM A-law / G.711 A-law
+ DEAIL. pcm_mulaw            PCM mu-law / G.711 mu-law
+";
+
+    #[test]
+    fn test_parse_codec_listing() {
+        let support = FfmpegSupport::parse_codecs(MINIMAL_LISTING);
+        assert!(support.available);
+        assert_eq!(support.g722, CodecSupport { decode: true, encode: false });
+        assert_eq!(support.pcm_mulaw, CodecSupport { decode: true, encode: true });
+        assert_eq!(support.pcm_alaw, CodecSupport { decode: true, encode: false });
+
+        // The legend's `D.....` lines aren't codecs
+        let legend_only = FfmpegSupport::parse_codecs("Codecs:\n D..... = Decoding supported\n -------\n");
+        assert_eq!(legend_only.g722, CodecSupport::default());
+    }
+
+    #[test]
+    fn test_missing_backend_errors() {
+        let support = FfmpegSupport::parse_codecs(MINIMAL_LISTING);
+        assert!(support.check(CodecType::G722, "g722", Direction::Decode).is_ok());
+        let err = support.check(CodecType::G722, "g722", Direction::Encode).unwrap_err();
+        assert_eq!(err.to_string(), "G.722 needs the ffmpeg g722 encoder, which isn't available");
+        assert_eq!(
+            err.hint().unwrap(),
+            "Your ffmpeg lacks the G.722 encoder: install a full ffmpeg build (or point MPU_FFMPEG at one), or use --codec g711ulaw"
+        );
+
+        let err = FfmpegSupport::default().check(CodecType::G722, "g722", Direction::Decode).unwrap_err();
+        assert!(matches!(err, CodecError::BackendUnavailable { ref missing, .. } if missing == "ffmpeg"), "{:?}", err);
+        assert!(err.hint().unwrap().starts_with("ffmpeg wasn't found"), "{:?}", err.hint());
+
+        // Constructors consult the probe
+        let _stub = stub_ffmpeg_support(support);
+        assert!(FfmpegG722Decoder::new().is_ok());
+        assert!(matches!(FfmpegG722Encoder::new(), Err(CodecError::BackendUnavailable { codec: CodecType::G722, .. })));
+        assert!(FfmpegG711UlawEncoder::new().is_ok());
+        assert!(FfmpegG711AlawEncoder::new().is_err());
+    }
+
     #[test]
     fn test_ffmpeg_g722_encoder() {
         let encoder = FfmpegG722Encoder::new();
@@ -23,6 +23,36 @@ pub enum CodecError {
 
     #[error("Invalid frame size: expected {expected}, got {got}")]
     InvalidFrameSize { expected: usize, got: usize },
+
+    /// The external tool a codec runs through is missing, or lacks the codec
+    #[error("{codec} needs the {missing}, which isn't available")]
+    BackendUnavailable { codec: CodecType, missing: String },
+}
+
+impl CodecError {
+    /// What to do about the error, for errors the user can fix
+    pub fn hint(&self) -> Option<String> {
+        let Self::BackendUnavailable { codec, missing } = self else {
+            return None;
+        };
+        let alternative = match codec {
+            CodecType::G722 => ", or use --codec g711ulaw",
+            _ => "",
+        };
+        Some(match missing.strip_prefix("ffmpeg ") {
+            Some(part) => {
+                let direction = part.rsplit(' ').next().unwrap_or(part);
+                format!(
+                    "Your ffmpeg lacks the {} {}: install a full ffmpeg build (or point {} at one){}",
+                    codec, direction, super::FFMPEG_ENV, alternative
+                )
+            }
+            None => format!(
+                "ffmpeg wasn't found: install it (apt install ffmpeg, winget install ffmpeg) or point {} at it{}",
+                super::FFMPEG_ENV, alternative
+            ),
+        })
+    }
 }
 
 /// Supported codec types
@@ -33,9 +63,20 @@ pub enum CodecType {
     G722,
     Opus,
     L16,
+    L24,
 }
 
 impl CodecType {
+    /// Every supported codec, in the order they are listed to users
+    pub const ALL: [CodecType; 6] = [
+        CodecType::G711Ulaw,
+        CodecType::G711Alaw,
+        CodecType::G722,
+        CodecType::Opus,
+        CodecType::L16,
+        CodecType::L24,
+    ];
+
     /// Get the RTP payload type for this codec
     #[must_use]
     pub const fn payload_type(&self) -> u8 {
@@ -45,6 +86,7 @@ impl CodecType {
             CodecType::G722 => 9,
             CodecType::Opus => 96, // Dynamic, typically 96
             CodecType::L16 => 11,  // Mono
+            CodecType::L24 => 97,  // Dynamic, no static assignment
         }
     }
 
@@ -54,7 +96,7 @@ impl CodecType {
         match self {
             CodecType::G711Ulaw | CodecType::G711Alaw => 8000,
             CodecType::G722 => 16000,
-            CodecType::Opus => 48000,
+            CodecType::Opus | CodecType::L24 => 48000,
             CodecType::L16 => 44100,
         }
     }
@@ -63,7 +105,11 @@ impl CodecType {
     #[must_use]
     pub const fn channels(&self) -> u8 {
         match self {
-            CodecType::G711Ulaw | CodecType::G711Alaw | CodecType::G722 | CodecType::L16 => 1,
+            CodecType::G711Ulaw
+            | CodecType::G711Alaw
+            | CodecType::G722
+            | CodecType::L16
+            | CodecType::L24 => 1,
             CodecType::Opus => 2,
         }
     }
@@ -77,6 +123,29 @@ impl CodecType {
             CodecType::G722 => "G.722",
             CodecType::Opus => "Opus",
             CodecType::L16 => "Linear PCM",
+            CodecType::L24 => "Linear PCM 24-bit",
+        }
+    }
+
+    /// Canonical name accepted by `--codec`
+    #[must_use]
+    pub const fn cli_name(&self) -> &'static str {
+        match self {
+            CodecType::G711Ulaw => "g711ulaw",
+            CodecType::G711Alaw => "g711alaw",
+            CodecType::G722 => "g722",
+            CodecType::Opus => "opus",
+            CodecType::L16 => "l16",
+            CodecType::L24 => "l24",
+        }
+    }
+
+    /// External tools needed to encode or decode this codec
+    #[must_use]
+    pub const fn runtime_requirements(&self) -> &'static [&'static str] {
+        match self {
+            CodecType::G722 => &["ffmpeg"],
+            _ => &[],
         }
     }
 
@@ -94,11 +163,32 @@ impl CodecType {
             Some(CodecType::Opus)
         } else if s.eq_ignore_ascii_case("l16") || s.eq_ignore_ascii_case("pcm") || s.eq_ignore_ascii_case("linear") {
             Some(CodecType::L16)
+        } else if s.eq_ignore_ascii_case("l24") {
+            Some(CodecType::L24)
         } else {
             None
         }
     }
 
+    /// RTP timestamp clock rate for this codec
+    ///
+    /// G.722 keeps the 8 kHz clock for historical reasons (RFC 3551) even
+    /// though it carries 16 kHz audio.
+    #[must_use]
+    pub const fn rtp_clock_rate(&self) -> u32 {
+        match self {
+            CodecType::G722 => 8000,
+            _ => self.sample_rate(),
+        }
+    }
+
+    /// Whether the stream parameters are carried out of band (linear PCM)
+    /// rather than fixed by the codec
+    #[must_use]
+    pub const fn is_linear(&self) -> bool {
+        matches!(self, CodecType::L16 | CodecType::L24)
+    }
+
     /// Detect codec from RTP payload type
     #[must_use]
     pub const fn from_payload_type(pt: u8) -> Option<Self> {
@@ -119,11 +209,146 @@ impl std::fmt::Display for CodecType {
     }
 }
 
+/// A codec together with its stream parameters
+///
+/// Parsed from `name[:rate[:channels]]`, e.g. `l24:48000:2` for an AES67
+/// stereo stream. Rate and channel count can only differ from the codec's
+/// defaults for linear PCM, whose parameters are negotiated out of band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodecSpec {
+    pub codec: CodecType,
+    pub sample_rate: u32,
+    pub channels: u8,
+}
+
+impl CodecSpec {
+    /// Maximum channel count accepted for linear PCM streams (AES67 allows 8)
+    pub const MAX_CHANNELS: u8 = 8;
+
+    /// Spec with the codec's default parameters
+    #[must_use]
+    pub const fn new(codec: CodecType) -> Self {
+        Self {
+            codec,
+            sample_rate: codec.sample_rate(),
+            channels: codec.channels(),
+        }
+    }
+
+    /// Spec for an RTP payload type. Static types fix the channel count as
+    /// well as the codec: PT 10 is L16 stereo and PT 11 L16 mono, both at
+    /// 44.1kHz (RFC 3551)
+    #[must_use]
+    pub const fn from_payload_type(pt: u8) -> Option<Self> {
+        match CodecType::from_payload_type(pt) {
+            Some(CodecType::L16) if pt == 10 => Some(Self {
+                codec: CodecType::L16,
+                sample_rate: 44100,
+                channels: 2,
+            }),
+            Some(codec) => Some(Self::new(codec)),
+            None => None,
+        }
+    }
+
+    /// Parse a `name[:rate[:channels]]` spec (codec name is case-insensitive)
+    #[must_use]
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split(':');
+        let mut spec = Self::new(CodecType::from_str(parts.next()?.trim())?);
+
+        if let Some(rate) = parts.next() {
+            spec.sample_rate = rate.trim().parse().ok().filter(|&r| r > 0)?;
+        }
+        if let Some(channels) = parts.next() {
+            spec.channels = channels
+                .trim()
+                .parse()
+                .ok()
+                .filter(|&c| (1..=Self::MAX_CHANNELS).contains(&c))?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+
+        // Only linear PCM can be reparameterized
+        if !spec.codec.is_linear() && spec != Self::new(spec.codec) {
+            return None;
+        }
+
+        Some(spec)
+    }
+
+    /// Payload bytes per decoded sample, for codecs that map one to the other
+    /// directly (G.711 and linear PCM)
+    #[must_use]
+    pub const fn bytes_per_sample(&self) -> Option<usize> {
+        match self.codec {
+            CodecType::G711Ulaw | CodecType::G711Alaw => Some(1),
+            CodecType::L16 => Some(2),
+            CodecType::L24 => Some(3),
+            CodecType::G722 | CodecType::Opus => None,
+        }
+    }
+
+    /// Size every payload should be a multiple of: a whole millisecond for
+    /// G.711, a whole sample on every channel for linear PCM
+    #[must_use]
+    pub const fn payload_granularity(&self) -> Option<usize> {
+        match self.codec {
+            CodecType::G711Ulaw | CodecType::G711Alaw => Some(self.sample_rate as usize / 1000),
+            CodecType::L16 => Some(2 * self.channels as usize),
+            CodecType::L24 => Some(3 * self.channels as usize),
+            CodecType::G722 | CodecType::Opus => None,
+        }
+    }
+
+    /// RTP timestamp clock rate for this stream
+    #[must_use]
+    pub const fn rtp_clock_rate(&self) -> u32 {
+        if self.codec.is_linear() {
+            self.sample_rate
+        } else {
+            self.codec.rtp_clock_rate()
+        }
+    }
+}
+
+impl From<CodecType> for CodecSpec {
+    fn from(codec: CodecType) -> Self {
+        Self::new(codec)
+    }
+}
+
+impl std::fmt::Display for CodecSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.codec.is_linear() {
+            write!(f, "{} ({} Hz, {} ch)", self.codec.name(), self.sample_rate, self.channels)
+        } else {
+            write!(f, "{}", self.codec.name())
+        }
+    }
+}
+
 /// Trait for audio decoders
 pub trait AudioDecoder: Send {
     /// Decode compressed audio to PCM samples (i16)
     fn decode(&mut self, input: &[u8]) -> Result<Vec<i16>, CodecError>;
 
+    /// Decode any input still buffered by the decoder
+    ///
+    /// Call at the end of a stream, or before inserting audio that must follow
+    /// everything decoded so far. Decoders that don't buffer return nothing.
+    fn flush(&mut self) -> Result<Vec<i16>, CodecError> {
+        Ok(Vec::new())
+    }
+
+    /// Audio to stand in for `frames` frames that were lost or couldn't be
+    /// decoded. Silence, unless the codec can conceal the loss itself
+    fn conceal(&mut self, frames: usize) -> Result<Vec<i16>, CodecError> {
+        Ok(vec![0; frames * usize::from(self.channels())])
+    }
+
     /// Get the native sample rate of decoded audio
     fn sample_rate(&self) -> u32;
 
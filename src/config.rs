@@ -1,42 +1,141 @@
 //! Configuration management for the multicast paging utility.
 //!
 //! This module provides persistent configuration storage for the GUI and
-//! advanced CLI options. Currently reserved for future GUI implementation.
+//! the CLI. The commands take defaults from it through `cli::settings`: the
+//! output directory, TTL, codec, metrics interval and site code, and
+//! `polycom-transmit` its per-channel defaults. The file is read from the
+//! standard location, or wherever `--config` says; `config init` writes a
+//! commented starter file and `validate-config` checks one without using it.
+//!
+//! Loading is strict: a misspelt key is an error, not a silently ignored
+//! line that leaves the default in place. Before deserializing, the file is
+//! walked against [`SCHEMA`] so that every unknown key, wrong type and
+//! out-of-range value is reported at once, each with its line and column
+//! and, for a near miss, the key that was probably meant.
 
 #![allow(dead_code)]
 
+use crate::cli::classifier::ClassifierThresholds;
+use crate::cli::transcoding::TranscodingThresholds;
+use crate::codec::CodecSpec;
+use chrono::format::{Item as FormatItem, StrftimeItems};
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::fmt::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
+use toml_edit::{ImDocument, Item, TableLike, Value};
 
-#[derive(Error, Debug)]
+#[derive(Error)]
 pub enum ConfigError {
     #[error("Failed to read config file: {0}")]
     ReadError(#[from] std::io::Error),
+    #[error("Config file {} not found", .0.display())]
+    NotFound(PathBuf),
+    #[error("{} already exists; use --force to replace it", .0.display())]
+    Exists(PathBuf),
     #[error("Failed to parse config: {0}")]
     ParseError(#[from] toml::de::Error),
     #[error("Failed to serialize config: {0}")]
     SerializeError(#[from] toml::ser::Error),
+    #[error("{} problem(s) in {}:\n{}", .issues.len(), .path.display(), list_issues(.issues))]
+    Invalid { path: PathBuf, issues: Vec<ConfigIssue> },
+}
+
+/// `main` reports errors with `Debug`; keep the issue list readable there
+impl fmt::Debug for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+fn list_issues(issues: &[ConfigIssue]) -> String {
+    issues.iter().map(|issue| format!("  {}", issue)).collect::<Vec<_>>().join("\n")
+}
+
+/// One thing wrong with a config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigIssue {
+    /// Dotted path to the key, e.g. `polycom.channels[0].channel`
+    pub field: String,
+    /// 1-based position in the file
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    /// Known key closest to an unknown one
+    pub suggestion: Option<&'static str>,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        if !self.field.is_empty() {
+            write!(f, "{}: ", self.field)?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, " (did you mean `{}`?)", suggestion)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
-#[serde(default)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
+    pub site: SiteConfig,
+    pub output: OutputConfig,
     pub audio: AudioConfig,
     pub network: NetworkConfig,
     pub monitor: MonitorConfig,
+    pub test: TestConfig,
+    pub polycom: PolycomConfig,
+    /// Thresholds for sorting pages into speech, tone, music, noise and silence
+    pub classifier: ClassifierThresholds,
+    /// Thresholds for suspecting a wideband page went through a narrowband hop
+    pub transcoding: TranscodingThresholds,
     pub monitored_ranges: Vec<String>,
 }
 
+/// Where the utility is running, for recording names and summaries
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+    /// Descriptive name, recorded in test summaries
+    pub name: Option<String>,
+    /// Default `--site`, the code `{site}` stands for
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Default output directory for the recording commands. May use strftime
+    /// tokens, e.g. `/var/lib/mpu/%Y-%m-%d`, filled in when a command starts
+    pub directory: Option<String>,
+}
+
+impl OutputConfig {
+    /// The output directory for a command started at `now`
+    pub fn directory_at(&self, now: DateTime<Local>) -> Option<String> {
+        let template = self.directory.as_deref()?;
+        // The schema refuses a bad token, but don't panic on one here either
+        let mut directory = String::new();
+        write!(directory, "{}", now.format(template)).ok()?;
+        Some(directory)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(default)]
+#[serde(default, deny_unknown_fields)]
 pub struct AudioConfig {
     pub output_device: Option<String>,
     pub buffer_size_ms: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(default)]
+#[serde(default, deny_unknown_fields)]
 pub struct NetworkConfig {
     pub default_port: u16,
     pub default_ttl: u8,
@@ -44,12 +143,58 @@ pub struct NetworkConfig {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(default)]
+#[serde(default, deny_unknown_fields)]
 pub struct MonitorConfig {
     pub idle_timeout_secs: u32,
     pub auto_play_new_pages: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TestConfig {
+    /// Default `test --metrics-interval`
+    pub metrics_interval_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolycomConfig {
+    /// Safety cap on transmission length for non-emergency channels
+    pub max_duration_secs: Option<u64>,
+    /// Per-channel overrides
+    pub channels: Vec<PolycomChannelDefaults>,
+}
+
+/// Defaults applied when transmitting on a specific Polycom channel
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolycomChannelDefaults {
+    pub channel: u8,
+    /// Shown with the channel number by the Polycom commands, e.g. `All Call`
+    pub name: Option<String>,
+    pub caller_id: Option<String>,
+    pub max_duration_secs: Option<u64>,
+}
+
+impl PolycomConfig {
+    /// Get the defaults configured for a channel, if any
+    pub fn channel(&self, channel: u8) -> Option<&PolycomChannelDefaults> {
+        self.channels.iter().find(|c| c.channel == channel)
+    }
+
+    /// Get the default caller ID for a channel
+    pub fn caller_id(&self, channel: u8) -> Option<&str> {
+        self.channel(channel).and_then(|c| c.caller_id.as_deref())
+    }
+
+    /// Get the duration cap for a channel (per-channel value wins over the global one)
+    pub fn max_duration_secs(&self, channel: u8) -> Option<u64> {
+        self.channel(channel)
+            .and_then(|c| c.max_duration_secs)
+            .or(self.max_duration_secs)
+    }
+}
+
 impl Default for AudioConfig {
     fn default() -> Self {
         Self {
@@ -78,6 +223,12 @@ impl Default for MonitorConfig {
     }
 }
 
+impl Default for TestConfig {
+    fn default() -> Self {
+        Self { metrics_interval_ms: 500 }
+    }
+}
+
 impl Config {
     /// Get the path to the configuration file
     pub fn config_path() -> PathBuf {
@@ -87,20 +238,76 @@ impl Config {
             .join("config.toml")
     }
 
-    /// Load configuration from disk, or return defaults if not found
+    /// Load configuration from disk, or return defaults if not found or invalid
     pub fn load() -> Self {
         Self::try_load().unwrap_or_default()
     }
 
-    /// Try to load configuration from disk
+    /// Try to load configuration from disk; defaults if there is no file
     pub fn try_load() -> Result<Self, ConfigError> {
-        let path = Self::config_path();
+        Self::load_or_default(&Self::config_path(), false)
+    }
+
+    /// Load the configuration a command runs with. A file named with
+    /// `--config` (`explicit`) must exist; with none at the standard
+    /// location, the defaults apply.
+    pub fn load_or_default(path: &Path, explicit: bool) -> Result<Self, ConfigError> {
         if !path.exists() {
-            return Ok(Self::default());
+            return if explicit {
+                Err(ConfigError::NotFound(path.to_path_buf()))
+            } else {
+                Ok(Self::default())
+            };
         }
-        let content = std::fs::read_to_string(&path)?;
-        let config: Config = toml::from_str(&content)?;
-        Ok(config)
+        Self::load_from(path)
+    }
+
+    /// Load and strictly check the configuration at `path`
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path)?;
+        Self::parse(&content).map_err(|issues| ConfigError::Invalid {
+            path: path.to_path_buf(),
+            issues,
+        })
+    }
+
+    /// `config init`: write [`STARTER`] to `path`, creating its directory.
+    /// An existing file is only replaced with `force`.
+    pub fn write_starter(path: &Path, force: bool) -> Result<(), ConfigError> {
+        if path.exists() && !force {
+            return Err(ConfigError::Exists(path.to_path_buf()));
+        }
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, STARTER)?;
+        Ok(())
+    }
+
+    /// Parse a config file's contents, reporting everything wrong with it
+    pub fn parse(content: &str) -> Result<Self, Vec<ConfigIssue>> {
+        let document = match ImDocument::parse(content) {
+            Ok(document) => document,
+            Err(e) => {
+                return Err(vec![issue_at(content, e.span(), String::new(), e.message().to_string())]);
+            }
+        };
+
+        let mut issues = Vec::new();
+        check_table(content, document.as_table(), SCHEMA, "", &mut issues);
+        if !issues.is_empty() {
+            return Err(issues);
+        }
+
+        // The schema walk should have caught anything serde would reject
+        toml::from_str(content).map_err(|e: toml::de::Error| {
+            vec![issue_at(content, e.span(), String::new(), e.message().to_string())]
+        })
+    }
+
+    /// The configuration with every default filled in, as TOML
+    pub fn effective(&self) -> Result<String, ConfigError> {
+        Ok(toml::to_string_pretty(self)?)
     }
 
     /// Save configuration to disk
@@ -121,6 +328,314 @@ impl Config {
     }
 }
 
+/// What `config init` writes: every default the commands take from the
+/// file, set to the built-in value or commented out, with what each does.
+/// Loads as [`Config::default`].
+pub const STARTER: &str = r#"# Multicast Paging Utility configuration
+#
+# Flags on the command line win over MPU_* environment variables and .env,
+# which win over this file. `validate-config` checks it, and
+# `--print-effective-config` shows where each setting came from.
+
+[site]
+# Descriptive name, recorded in test summaries
+# name = "Head Office"
+# Default --site: the code {site} stands for in recording and capture names
+# code = "HQ"
+
+[output]
+# Default output directory for test and polycom-monitor, and for monitor
+# with --name-template. strftime tokens are filled in when a command
+# starts, e.g. "/var/lib/mpu/%Y-%m-%d"
+# directory = "recordings/%Y-%m-%d"
+
+[network]
+# Port for addresses given without one, for the plain RTP commands
+default_port = 5004
+# Multicast TTL of transmitted packets
+default_ttl = 32
+# transmit's --codec
+default_codec = "g711ulaw"
+
+[test]
+# test's --metrics-interval, in milliseconds
+metrics_interval_ms = 500
+
+[audio]
+# output_device = "default"
+buffer_size_ms = 100
+
+[monitor]
+idle_timeout_secs = 5
+auto_play_new_pages = false
+
+[polycom]
+# Cap on transmission length for non-emergency channels, in seconds
+# max_duration_secs = 60
+
+# Per-channel defaults for polycom-transmit, and the name polycom-monitor
+# and polycom-transmit show beside the channel number
+# [[polycom.channels]]
+# channel = 26
+# name = "All Call"
+# caller_id = "Reception"
+# max_duration_secs = 10
+"#;
+
+/// What a value in the config file must look like. Mirrors the structs
+/// above; serde still has the final say when the file is deserialized.
+pub enum Shape {
+    Table(&'static [(&'static str, Shape)]),
+    /// `[[name]]` sections, or an inline array of tables
+    Tables(&'static [(&'static str, Shape)]),
+    Bool,
+    Integer { min: i64, max: i64 },
+    /// A float, or an integer standing for one
+    Number { min: f64, max: f64 },
+    String,
+    Strings,
+    /// A codec name as `--codec` takes it
+    Codec,
+    /// A string that may use strftime tokens
+    Strftime,
+}
+
+/// Top-level keys of the config file
+pub const SCHEMA: &[(&str, Shape)] = &[
+    ("site", Shape::Table(&[("name", Shape::String), ("code", Shape::String)])),
+    ("output", Shape::Table(&[("directory", Shape::Strftime)])),
+    (
+        "audio",
+        Shape::Table(&[
+            ("output_device", Shape::String),
+            ("buffer_size_ms", Shape::Integer { min: 1, max: u32::MAX as i64 }),
+        ]),
+    ),
+    (
+        "network",
+        Shape::Table(&[
+            ("default_port", Shape::Integer { min: 1, max: u16::MAX as i64 }),
+            ("default_ttl", Shape::Integer { min: 1, max: u8::MAX as i64 }),
+            ("default_codec", Shape::Codec),
+        ]),
+    ),
+    (
+        "monitor",
+        Shape::Table(&[
+            ("idle_timeout_secs", Shape::Integer { min: 1, max: u32::MAX as i64 }),
+            ("auto_play_new_pages", Shape::Bool),
+        ]),
+    ),
+    (
+        "test",
+        Shape::Table(&[("metrics_interval_ms", Shape::Integer { min: 1, max: i64::MAX })]),
+    ),
+    (
+        "polycom",
+        Shape::Table(&[
+            ("max_duration_secs", Shape::Integer { min: 1, max: i64::MAX }),
+            (
+                "channels",
+                Shape::Tables(&[
+                    ("channel", Shape::Integer { min: 1, max: 50 }),
+                    ("name", Shape::String),
+                    ("caller_id", Shape::String),
+                    ("max_duration_secs", Shape::Integer { min: 1, max: i64::MAX }),
+                ]),
+            ),
+        ]),
+    ),
+    (
+        "classifier",
+        Shape::Table(&[
+            ("silence_percent", Shape::Number { min: 0.0, max: 100.0 }),
+            ("tone_min_pitch_stability", Shape::Number { min: 0.0, max: 1.0 }),
+            ("tone_max_flatness", Shape::Number { min: 0.0, max: 1.0 }),
+            ("noise_min_flatness", Shape::Number { min: 0.0, max: 1.0 }),
+            ("noise_max_level_spread_db", Shape::Number { min: 0.0, max: 100.0 }),
+            ("speech_min_silence_percent", Shape::Number { min: 0.0, max: 100.0 }),
+            ("speech_min_level_spread_db", Shape::Number { min: 0.0, max: 100.0 }),
+        ]),
+    ),
+    (
+        "transcoding",
+        Shape::Table(&[
+            ("max_high_band_db", Shape::Number { min: -120.0, max: 0.0 }),
+            ("mulaw_min_snr_db", Shape::Number { min: 0.0, max: 120.0 }),
+            ("mulaw_max_snr_db", Shape::Number { min: 0.0, max: 120.0 }),
+        ]),
+    ),
+    ("monitored_ranges", Shape::Strings),
+];
+
+fn check_table(
+    content: &str,
+    table: &dyn TableLike,
+    fields: &'static [(&'static str, Shape)],
+    path: &str,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    for (key, item) in table.iter() {
+        let field = if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        };
+        let Some((_, shape)) = fields.iter().find(|(name, _)| *name == key) else {
+            let span = table.get_key_value(key).and_then(|(k, _)| k.span());
+            let mut issue = issue_at(content, span, field, "unknown key".to_string());
+            issue.suggestion = closest(key, fields.iter().map(|(name, _)| *name));
+            issues.push(issue);
+            continue;
+        };
+        check_item(content, item, shape, &field, issues);
+    }
+}
+
+fn check_item(content: &str, item: &Item, shape: &Shape, field: &str, issues: &mut Vec<ConfigIssue>) {
+    match (shape, item) {
+        (Shape::Table(fields), _) if item.is_table_like() => {
+            check_table(content, item.as_table_like().expect("checked"), fields, field, issues);
+        }
+        (Shape::Tables(fields), Item::ArrayOfTables(tables)) => {
+            for (index, table) in tables.iter().enumerate() {
+                check_table(content, table, fields, &format!("{}[{}]", field, index), issues);
+            }
+        }
+        (_, Item::Value(value)) => check_value(content, value, shape, field, issues),
+        _ => issues.push(issue_at(
+            content,
+            item.span(),
+            field.to_string(),
+            format!("expected {}, found {}", shape.expected(), describe_item(item)),
+        )),
+    }
+}
+
+fn check_value(content: &str, value: &Value, shape: &Shape, field: &str, issues: &mut Vec<ConfigIssue>) {
+    let mut problem = |message: String| issues.push(issue_at(content, value.span(), field.to_string(), message));
+    match (shape, value) {
+        (Shape::Bool, Value::Boolean(_)) | (Shape::String, Value::String(_)) => {}
+        (Shape::Integer { min, max }, Value::Integer(n)) => {
+            let n = *n.value();
+            if n < *min || n > *max {
+                problem(format!("{} is out of range ({} to {})", n, min, max));
+            }
+        }
+        (Shape::Number { min, max }, Value::Float(_) | Value::Integer(_)) => {
+            let n = value.as_float().or_else(|| value.as_integer().map(|n| n as f64)).unwrap_or_default();
+            if n < *min || n > *max {
+                problem(format!("{} is out of range ({} to {})", n, min, max));
+            }
+        }
+        (Shape::Codec, Value::String(name)) => {
+            if CodecSpec::parse(name.value()).is_none() {
+                problem(format!("unknown codec \"{}\"", name.value()));
+            }
+        }
+        (Shape::Strftime, Value::String(template)) => {
+            if StrftimeItems::new(template.value()).any(|item| matches!(item, FormatItem::Error)) {
+                problem(format!("invalid strftime format \"{}\"", template.value()));
+            }
+        }
+        (Shape::Strings, Value::Array(array)) => {
+            for (index, element) in array.iter().enumerate() {
+                check_value(content, element, &Shape::String, &format!("{}[{}]", field, index), issues);
+            }
+        }
+        (Shape::Tables(fields), Value::Array(array)) => {
+            for (index, element) in array.iter().enumerate() {
+                let element_field = format!("{}[{}]", field, index);
+                match element.as_inline_table() {
+                    Some(table) => check_table(content, table, fields, &element_field, issues),
+                    None => issues.push(issue_at(
+                        content,
+                        element.span(),
+                        element_field,
+                        format!("expected a table, found {}", describe_value(element)),
+                    )),
+                }
+            }
+        }
+        (Shape::Table(fields), Value::InlineTable(table)) => check_table(content, table, fields, field, issues),
+        _ => problem(format!("expected {}, found {}", shape.expected(), describe_value(value))),
+    }
+}
+
+impl Shape {
+    fn expected(&self) -> &'static str {
+        match self {
+            Shape::Table(_) => "a table",
+            Shape::Tables(_) => "an array of tables",
+            Shape::Bool => "true or false",
+            Shape::Integer { .. } => "an integer",
+            Shape::Number { .. } => "a number",
+            Shape::String | Shape::Codec | Shape::Strftime => "a string",
+            Shape::Strings => "an array of strings",
+        }
+    }
+}
+
+fn describe_item(item: &Item) -> &'static str {
+    match item {
+        Item::None => "nothing",
+        Item::Value(value) => describe_value(value),
+        Item::Table(_) => "a table",
+        Item::ArrayOfTables(_) => "an array of tables",
+    }
+}
+
+fn describe_value(value: &Value) -> &'static str {
+    match value {
+        Value::String(_) => "a string",
+        Value::Integer(_) => "an integer",
+        Value::Float(_) => "a float",
+        Value::Boolean(_) => "a boolean",
+        Value::Datetime(_) => "a date",
+        Value::Array(_) => "an array",
+        Value::InlineTable(_) => "a table",
+    }
+}
+
+/// An issue at byte range `span` of `content` (the start of the file if unknown)
+fn issue_at(content: &str, span: Option<Range<usize>>, field: String, message: String) -> ConfigIssue {
+    let offset = span.map_or(0, |span| span.start.min(content.len()));
+    let before = &content[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    ConfigIssue {
+        field,
+        line,
+        column,
+        message,
+        suggestion: None,
+    }
+}
+
+/// The candidate within a couple of edits of `key`, if there is one
+pub(crate) fn closest(key: &str, candidates: impl Iterator<Item = &'static str>) -> Option<&'static str> {
+    candidates
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|&(distance, candidate)| distance <= 2 && distance < candidate.len())
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance, by characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -141,4 +656,240 @@ mod tests {
         let deserialized: Config = toml::from_str(&serialized).unwrap();
         assert_eq!(config.network.default_port, deserialized.network.default_port);
     }
+
+    #[test]
+    fn test_polycom_channel_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            [polycom]
+            max_duration_secs = 60
+
+            [[polycom.channels]]
+            channel = 26
+            caller_id = "Reception"
+            max_duration_secs = 10
+
+            [[polycom.channels]]
+            channel = 27
+            caller_id = "Warehouse"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.polycom.caller_id(26), Some("Reception"));
+        assert_eq!(config.polycom.caller_id(28), None);
+        assert_eq!(config.polycom.max_duration_secs(26), Some(10));
+        assert_eq!(config.polycom.max_duration_secs(27), Some(60));
+    }
+
+    fn fields(issues: &[ConfigIssue]) -> Vec<&str> {
+        issues.iter().map(|issue| issue.field.as_str()).collect()
+    }
+
+    #[test]
+    fn test_unknown_keys_all_reported() {
+        let issues = Config::parse(
+            "[network]\ndefault_prot = 5006\n\n[[polycom.channels]]\nchanel = 26\n\n[gui]\ntheme = \"dark\"\n",
+        )
+        .unwrap_err();
+        assert_eq!(fields(&issues), ["network.default_prot", "polycom.channels[0].chanel", "gui"]);
+        assert_eq!(issues[0].suggestion, Some("default_port"));
+        assert_eq!(issues[1].suggestion, Some("channel"));
+        assert_eq!(issues[2].suggestion, None, "nothing close to `gui`");
+        assert_eq!((issues[1].line, issues[1].column), (5, 1));
+        assert_eq!(
+            issues[1].to_string(),
+            "5:1: polycom.channels[0].chanel: unknown key (did you mean `channel`?)"
+        );
+    }
+
+    #[test]
+    fn test_type_mismatches_and_ranges() {
+        let issues = Config::parse(
+            r#"
+monitored_ranges = ["224.0.1.1:5004", 7]
+
+[network]
+default_port = "5004"
+default_ttl = 0
+default_codec = "g711mulaw"
+
+[monitor]
+auto_play_new_pages = "yes"
+
+[polycom]
+channels = [{ channel = 26 }, { channel = 51, caller_id = "Yard" }, 3]
+
+[classifier]
+silence_percent = 120
+tone_max_flatness = "low"
+"#,
+        )
+        .unwrap_err();
+        let messages: Vec<String> = issues.iter().map(|issue| format!("{}: {}", issue.field, issue.message)).collect();
+        assert_eq!(
+            messages,
+            [
+                "monitored_ranges[1]: expected a string, found an integer",
+                "network.default_port: expected an integer, found a string",
+                "network.default_ttl: 0 is out of range (1 to 255)",
+                "network.default_codec: unknown codec \"g711mulaw\"",
+                "monitor.auto_play_new_pages: expected true or false, found a string",
+                "polycom.channels[1].channel: 51 is out of range (1 to 50)",
+                "polycom.channels[2]: expected a table, found an integer",
+                "classifier.silence_percent: 120 is out of range (0 to 100)",
+                "classifier.tone_max_flatness: expected a number, found a string",
+            ]
+        );
+        assert_eq!((issues[1].line, issues[1].column), (5, 16));
+    }
+
+    #[test]
+    fn test_syntax_error_position() {
+        let issues = Config::parse("[network]\ndefault_port = \n").unwrap_err();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, 2);
+    }
+
+    #[test]
+    fn test_schema_matches_structs() {
+        // Every field set, so nothing is left out of the serialized form
+        let mut config = Config::default();
+        config.audio.output_device = Some("default".to_string());
+        config.polycom.max_duration_secs = Some(60);
+        config.polycom.channels.push(PolycomChannelDefaults {
+            channel: 26,
+            name: Some("All Call".to_string()),
+            caller_id: Some("Reception".to_string()),
+            max_duration_secs: Some(10),
+        });
+        config.monitored_ranges.push("224.0.1.{1-4}:5004".to_string());
+        config.classifier.noise_min_flatness = 0.35;
+        config.transcoding.mulaw_max_snr_db = 42.0;
+
+        let parsed = Config::parse(&config.effective().unwrap()).unwrap();
+        assert_eq!(parsed.classifier, config.classifier);
+        assert_eq!(parsed.transcoding, config.transcoding);
+        assert_eq!(parsed.polycom.caller_id(26), Some("Reception"));
+        assert_eq!(parsed.polycom.channel(26).and_then(|c| c.name.as_deref()), Some("All Call"));
+        assert_eq!(parsed.audio.output_device.as_deref(), Some("default"));
+    }
+
+    #[test]
+    fn test_effective_config() {
+        let config = Config::parse("[network]\ndefault_ttl = 4\n").unwrap();
+        assert_eq!(
+            config.effective().unwrap(),
+            r#"monitored_ranges = []
+
+[site]
+
+[output]
+
+[audio]
+buffer_size_ms = 100
+
+[network]
+default_port = 5004
+default_ttl = 4
+default_codec = "g711ulaw"
+
+[monitor]
+idle_timeout_secs = 5
+auto_play_new_pages = false
+
+[test]
+metrics_interval_ms = 500
+
+[polycom]
+channels = []
+
+[classifier]
+silence_percent = 90.0
+tone_min_pitch_stability = 0.8
+tone_max_flatness = 0.05
+noise_min_flatness = 0.2
+noise_max_level_spread_db = 3.0
+speech_min_silence_percent = 10.0
+speech_min_level_spread_db = 4.0
+
+[transcoding]
+max_high_band_db = -40.0
+mulaw_min_snr_db = 20.0
+mulaw_max_snr_db = 45.0
+"#
+        );
+    }
+
+    #[test]
+    fn test_load_from_reports_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[monitor]\nidle_timeout = 5\n").unwrap();
+        let error = Config::load_from(&path).unwrap_err();
+        let ConfigError::Invalid { issues, .. } = &error else {
+            panic!("expected a validation error, got {:?}", error);
+        };
+        assert_eq!(issues.len(), 1);
+        assert!(error.to_string().starts_with("1 problem(s) in "));
+        assert!(error.to_string().contains("2:1: monitor.idle_timeout: unknown key"));
+    }
+
+    #[test]
+    fn test_starter_loads_as_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mpu").join("config.toml");
+        Config::write_starter(&path, false).unwrap();
+        let config = Config::load_or_default(&path, true).unwrap();
+        assert_eq!(config.effective().unwrap(), Config::default().effective().unwrap());
+
+        // Uncommenting the examples gives a file that still loads
+        let uncommented: String = STARTER
+            .lines()
+            .map(|line| match line.strip_prefix("# ") {
+                Some(rest) if rest.starts_with('[') || rest.contains(" = ") => rest,
+                _ => line,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let config = Config::parse(&uncommented).unwrap();
+        assert_eq!(config.site.code.as_deref(), Some("HQ"));
+        assert_eq!(config.polycom.caller_id(26), Some("Reception"));
+
+        let error = Config::write_starter(&path, false).unwrap_err();
+        assert!(matches!(error, ConfigError::Exists(_)), "{:?}", error);
+        Config::write_starter(&path, true).unwrap();
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        // Nothing at the standard location is the defaults; a named file must exist
+        let config = Config::load_or_default(&path, false).unwrap();
+        assert_eq!(config.test.metrics_interval_ms, 500);
+        let error = Config::load_or_default(&path, true).unwrap_err();
+        assert!(matches!(error, ConfigError::NotFound(_)), "{:?}", error);
+        assert!(error.to_string().ends_with("config.toml not found"));
+    }
+
+    #[test]
+    fn test_output_directory_tokens() {
+        use chrono::TimeZone;
+        let config = Config::parse("[output]\ndirectory = \"/var/lib/mpu/%Y-%m-%d\"\n").unwrap();
+        let now = Local.with_ymd_and_hms(2024, 6, 1, 14, 32, 5).unwrap();
+        assert_eq!(config.output.directory_at(now).as_deref(), Some("/var/lib/mpu/2024-06-01"));
+        assert_eq!(Config::default().output.directory_at(now), None);
+
+        let issues = Config::parse("[output]\ndirectory = \"pages/%Q\"\n").unwrap_err();
+        assert_eq!(issues[0].to_string(), "2:13: output.directory: invalid strftime format \"pages/%Q\"");
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("chanel", "channel"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(closest("ttl", ["default_ttl"].into_iter()), None);
+    }
 }
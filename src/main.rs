@@ -27,61 +27,138 @@
 #![allow(clippy::struct_field_names)] // Prefixes can clarify intent (e.g., default_)
 #![allow(clippy::enum_variant_names)] // Error suffix is conventional for error enums
 
+mod capabilities;
 mod cli;
 mod codec;
 mod config;
 mod network;
 mod utils;
 
-use clap::Parser;
 use cli::{Cli, Commands};
-use std::process::Command;
+use cli::timeout::Timeout;
+use std::net::SocketAddrV4;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use tracing::warn;
-use tracing_subscriber::EnvFilter;
-
-/// Check if ffmpeg is available in PATH
-fn check_ffmpeg_available() -> bool {
-    Command::new("ffmpeg")
-        .arg("-version")
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .status()
-        .map(|s| s.success())
-        .unwrap_or(false)
+use tracing::{info, warn};
+
+/// Switch to real-time scheduling for `--realtime`. Without the privileges
+/// the run carries on at normal priority, with a warning.
+fn enable_realtime() {
+    let status = utils::realtime::enable();
+    match status.scheduler {
+        Ok(threads) => info!(
+            "Real-time scheduling: SCHED_FIFO priority {} on {} threads",
+            utils::realtime::FIFO_PRIORITY,
+            threads
+        ),
+        Err(e) => warn!("--realtime: can't switch to SCHED_FIFO ({}); running at normal priority", e),
+    }
+    if let Err(e) = status.memory_locked {
+        warn!("--realtime: can't lock memory ({}); page faults may add latency", e);
+    }
 }
 
 /// Check runtime dependencies and warn if missing
 fn check_runtime_dependencies(quiet: bool) {
-    if !check_ffmpeg_available() {
+    let ffmpeg = codec::ffmpeg_support();
+    if !ffmpeg.available {
         if !quiet {
             eprintln!("Warning: ffmpeg not found in PATH");
             eprintln!("  G.722 encoding/decoding will not be available.");
-            eprintln!("  Install ffmpeg: apt install ffmpeg (Debian/Ubuntu)");
+            eprintln!("  Install ffmpeg: apt install ffmpeg (Debian/Ubuntu), winget install ffmpeg (Windows)");
+            eprintln!("  Or point {} at the ffmpeg executable.", codec::FFMPEG_ENV);
             eprintln!();
         }
         warn!("ffmpeg not found - G.722 codec support disabled");
+    } else if !(ffmpeg.g722.encode && ffmpeg.g722.decode) {
+        let missing = match (ffmpeg.g722.encode, ffmpeg.g722.decode) {
+            (false, false) => "G.722 encoder and decoder",
+            (false, true) => "G.722 encoder",
+            _ => "G.722 decoder",
+        };
+        if !quiet {
+            eprintln!("Warning: this ffmpeg build lacks the {}", missing);
+            eprintln!("  Install a full ffmpeg build, or point {} at one.", codec::FFMPEG_ENV);
+            eprintln!();
+        }
+        warn!("ffmpeg lacks the {} - G.722 codec support limited", missing);
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Parse CLI arguments
-    let args = Cli::parse();
+/// What to do about a failed command, if its cause is something the user can fix
+fn error_hint(error: &(dyn std::error::Error + 'static)) -> Option<String> {
+    std::iter::successors(Some(error), |e| e.source()).find_map(|e| {
+        if let Some(e) = e.downcast_ref::<codec::CodecError>() {
+            e.hint()
+        } else {
+            e.downcast_ref::<cli::fd_limit::FdLimitError>().and_then(cli::fd_limit::FdLimitError::hint)
+        }
+    })
+}
+
+/// The exit status for an error that has its own, rather than 1
+fn exit_status(error: &(dyn std::error::Error + 'static)) -> Option<i32> {
+    use cli::monitor::MonitorError;
+    use cli::polycom_monitor::PolycomMonitorError;
+    let pages_not_met = matches!(error.downcast_ref::<MonitorError>(), Some(MonitorError::CountNotMet { .. }))
+        || matches!(error.downcast_ref::<PolycomMonitorError>(), Some(PolycomMonitorError::CountNotMet { .. }));
+    pages_not_met.then_some(cli::exit_code::PAGES_NOT_MET)
+}
 
-    // Initialize logging
-    let filter = if args.verbose {
-        EnvFilter::new("debug")
-    } else if args.quiet {
-        EnvFilter::new("error")
-    } else {
-        EnvFilter::new("info")
-    };
+/// Whether an error's message reads better than its Debug form, wrapped in
+/// another command's error
+fn readable_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    std::iter::successors(Some(error), |e| e.source())
+        .any(|e| {
+            e.is::<cli::fd_limit::FdLimitError>()
+                || e.is::<cli::logging::LoggingError>()
+                || e.is::<cli::channel_names::ChannelNamesError>()
+        })
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Parse CLI arguments before the runtime starts any threads: a .env
+    // file is read into the environment
+    let (args, settings) = cli::settings::parse();
+    let result = tokio::runtime::Runtime::new()?.block_on(run(args, settings));
+    if let Some(hint) = result.as_ref().err().and_then(|e| error_hint(e.as_ref())) {
+        eprintln!("{}", hint);
+    }
+    if let Err(ref e) = result {
+        let status = exit_status(e.as_ref());
+        if status.is_some() || readable_error(e.as_ref()) {
+            eprintln!("Error: {}", e);
+            std::process::exit(status.unwrap_or(1));
+        }
+    }
+    result
+}
+
+async fn run(args: Cli, settings: cli::settings::Settings) -> Result<(), Box<dyn std::error::Error>> {
 
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_target(false)
-        .init();
+    // A transmit or bridge command's --json output is events only, so its logs go to stderr
+    let json_events = matches!(
+        args.command,
+        Some(
+            Commands::Transmit { json: true, .. }
+                | Commands::PolycomTransmit { json: true, .. }
+                | Commands::Bridge { json: true, .. }
+        )
+    );
+    cli::logging::init(&cli::logging::LogOptions {
+        verbose: args.verbose,
+        quiet: args.quiet,
+        filter: args.log_filter.clone(),
+        file: args.log_file.clone(),
+        stderr: json_events,
+    })?;
+
+    for warning in &settings.warnings {
+        warn!("{}", warning);
+    }
+    for setting in settings.implicit() {
+        info!("{} (from {})", setting, setting.source);
+    }
 
     // Check runtime dependencies for commands that need them
     if let Some(
@@ -89,12 +166,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         | Commands::Monitor { .. }
         | Commands::Test { .. }
         | Commands::PolycomTransmit { .. }
-        | Commands::PolycomMonitor { .. },
+        | Commands::PolycomMonitor { .. }
+        | Commands::Bridge { .. },
     ) = &args.command
     {
         check_runtime_dependencies(args.quiet);
     }
 
+    if args.version {
+        if args.json {
+            print_capabilities()?;
+        } else {
+            println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        }
+        return Ok(());
+    }
+
     match args.command {
         Some(Commands::Gui) | None => {
             // Launch GUI
@@ -106,51 +193,155 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             interface,
             codec,
             output,
+            overwrite,
             timeout,
+            until,
             json,
+            control_socket,
+            spectrum_dir,
+            pcap,
+            stats_interval,
+            stats_active_only,
+            memory_report,
+            rejoin_interval,
+            watchdog,
+            heartbeat,
+            on_decode_error,
+            expect_dscp,
+            timing_file,
+            record_payloads,
+            forward,
+            forward_ttl,
+            pipe_audio_cmd,
+            max_file_descriptors,
+            g722_nonstandard_timestamps,
+            status_listen,
+            page_threshold,
+            ssrc_filter,
+            count,
+            timing,
+            repair,
+            inspect,
+            naming,
         }) => {
-            let codec_type = codec.as_ref().and_then(|c| codec::CodecType::from_str(c));
-            let interface_addr = interface
-                .as_ref()
-                .and_then(|s| s.parse::<std::net::Ipv4Addr>().ok());
+            if timing.realtime {
+                enable_realtime();
+            }
 
             let options = cli::monitor::MonitorRangeOptions {
                 pattern: address,
                 default_port: port,
-                interface: interface_addr,
-                codec: codec_type,
+                interface,
+                codec,
                 output,
-                timeout: if timeout == 0 {
-                    Duration::MAX
-                } else {
-                    Duration::from_secs(timeout)
-                },
+                overwrite,
+                timeout: Timeout::from_args(timeout, until),
                 json,
                 quiet: args.quiet,
+                control_socket,
+                spectrum_dir,
+                pcap,
+                stitch_gap: page_threshold.stitch_gap(),
+                page_threshold: page_threshold.into(),
+                ssrc_filter: ssrc_filter.filter(),
+                count: count.page_count(),
+                busy_poll_us: timing.busy_poll_us,
+                analysis_threads: timing.analysis_threads,
+                stats_interval: Duration::from_secs(stats_interval),
+                stats_active_only,
+                naming: naming.into(),
+                memory_report: memory_report.map(Duration::from_secs),
+                rejoin_interval: (rejoin_interval > 0).then(|| Duration::from_secs(rejoin_interval)),
+                decode_error_policy: on_decode_error,
+                repair_window: repair.window(),
+                inspect: inspect.options(),
+                expect_dscp,
+                timing_file,
+                record_payloads,
+                watchdog: (watchdog > 0).then(|| Duration::from_secs(watchdog)),
+                heartbeat: heartbeat.map(Duration::from_secs),
+                forward,
+                forward_ttl,
+                pipe_audio_cmd,
+                transcoding: settings.config.transcoding,
+                max_file_descriptors,
+                g722_nonstandard_timestamps,
+                status_listen,
             };
 
             cli::monitor::run_monitor_range(options).await?;
         }
         Some(Commands::Transmit {
             file,
+            sequence,
+            describe,
             address,
             port,
+            multi_zone,
             codec,
             ttl,
+            dscp,
             r#loop,
+            json,
+            dry_run,
+            cache_dir,
+            input,
+            levels,
+            impairment,
+            schedule,
+            realtime,
+            repair,
+            repair_buffer,
+            g722_nonstandard_timestamps,
+            watermark,
+            watermark_level,
+            chime,
+            chime_gap_ms,
+            ssrc,
         }) => {
-            let addr = cli::monitor::parse_address(&address)?;
-            let codec_type = codec::CodecType::from_str(&codec)
-                .ok_or_else(|| format!("Unknown codec: {}", codec))?;
+            let source = match (file, sequence) {
+                (_, Some(sequence)) if describe => {
+                    print!("{}", sequence);
+                    return Ok(());
+                }
+                (_, Some(sequence)) => cli::transmit::PageSource::Sequence(sequence),
+                (Some(file), None) => cli::transmit::PageSource::File(file),
+                (None, None) => return Err("--file or --sequence is required".into()),
+            };
+            if realtime {
+                enable_realtime();
+            }
+            // With --multi-zone the first zone stands in for --address
+            let (addr, port) = match (address, multi_zone.first()) {
+                (Some(address), _) => (cli::monitor::parse_address(&address)?, port),
+                (None, Some(zone)) => (zone.address, zone.port),
+                (None, None) => return Err("--address or --multi-zone is required".into()),
+            };
 
             let options = cli::transmit::TransmitOptions {
-                file,
+                source,
+                input: input.into(),
+                levels: levels.into(),
                 address: addr,
                 port,
-                codec: codec_type,
+                codec,
                 ttl,
+                dscp,
                 loop_audio: r#loop,
-                quiet: args.quiet,
+                quiet: args.quiet || json,
+                json,
+                impairment: impairment.into(),
+                schedule: schedule.schedule(),
+                wait_for_window: schedule.wait_for_window,
+                dry_run,
+                cache_dir,
+                zones: multi_zone,
+                repair_buffer: repair.then_some(repair_buffer as usize),
+                g722_nonstandard_timestamps,
+                watermark,
+                watermark_level,
+                chime: chime.map(|chime| cli::chime::PageChime { chime, gap_ms: chime_gap_ms }),
+                ssrc,
             };
 
             cli::run_transmit(options).await?;
@@ -162,48 +353,215 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             codec,
             output,
             timeout,
+            until,
             metrics_interval,
+            control_socket,
+            spectrum_dir,
+            pcap,
+            page_threshold,
+            ssrc_filter,
+            timing,
+            repair,
+            aggregate,
+            memory_report,
+            split_spurts,
+            on_decode_error,
+            expect_dscp,
+            timing_file,
+            record_payloads,
+            pipe_audio_cmd,
+            compare_endpoints,
+            max_file_descriptors,
+            g722_nonstandard_timestamps,
+            observe_igmp,
+            naming,
         }) => {
-            let codec_type = codec.as_ref().and_then(|c| codec::CodecType::from_str(c));
-            let interface_addr = interface
-                .as_ref()
-                .and_then(|s| s.parse::<std::net::Ipv4Addr>().ok());
+            if timing.realtime {
+                enable_realtime();
+            }
 
             let options = cli::test::TestOptions {
                 pattern: address,
                 default_port: port,
-                interface: interface_addr,
-                codec: codec_type,
+                interface,
+                codec,
                 output_dir: output,
-                timeout: Duration::from_secs(timeout),
+                timeout: Timeout::from_args(timeout.unwrap_or_default(), until),
                 metrics_interval: Duration::from_millis(metrics_interval),
+                control_socket,
+                spectrum_dir,
+                pcap,
+                stitch_gap: page_threshold.stitch_gap(),
+                page_threshold: page_threshold.into(),
+                ssrc_filter: ssrc_filter.filter(),
+                aggregate,
+                busy_poll_us: timing.busy_poll_us,
+                analysis_threads: timing.analysis_threads,
+                naming: naming.into(),
+                memory_report: memory_report.map(Duration::from_secs),
+                split_spurts,
+                decode_error_policy: on_decode_error,
+                repair_window: repair.window(),
+                compare_endpoints,
+                expect_dscp,
+                timing_file,
+                record_payloads,
+                pipe_audio_cmd,
+                classifier: settings.config.classifier,
+                transcoding: settings.config.transcoding,
+                site_name: settings.config.site.name.clone(),
+                config_file: settings.config_record.clone(),
+                settings: settings.settings,
+                max_file_descriptors,
+                g722_nonstandard_timestamps,
+                observe_igmp,
             };
 
             cli::run_test(options).await?;
         }
         Some(Commands::Review {
             directory,
+            trends,
             play,
             metrics,
             page,
+            endpoint,
+            timeline,
+            timeline_json,
+            jitter_sim,
+            spectrogram,
+            spectrum_dir,
+            png,
+            verify,
+            reanalyze,
+            analysis_profile,
+            json,
+            at,
+            around,
+            clip,
+            anonymize,
+            output,
+            browse,
         }) => {
             let options = cli::review::ReviewOptions {
-                directory,
+                directory: directory.unwrap_or_default(),
+                trends,
                 play_audio: play,
                 show_metrics: metrics,
                 page_number: page,
+                endpoint,
+                timeline,
+                timeline_json,
+                jitter_sim,
+                spectrogram,
+                spectrum_dir,
+                png,
+                verify,
+                reanalyze,
+                analysis_profile,
+                classifier: settings.config.classifier,
+                transcoding: settings.config.transcoding,
+                json,
+                at,
+                around,
+                clip,
+                anonymize: output.filter(|_| anonymize),
+                interactive: browse.interactive,
             };
 
             cli::run_review(options)?;
         }
+        Some(Commands::Clip {
+            directory,
+            output,
+            page,
+            start,
+            end,
+            from,
+            to,
+            endpoint,
+            analyze,
+        }) => {
+            let range = match (page, from, to) {
+                (Some(page), _, _) => cli::clip::ClipRange::Page {
+                    page,
+                    start_secs: start,
+                    end_secs: end,
+                },
+                (None, Some(from), Some(to)) => cli::clip::ClipRange::WallClock { from, to },
+                _ => return Err("Specify either --page or --from/--to".into()),
+            };
+
+            let options = cli::clip::ClipOptions {
+                directory,
+                range,
+                output,
+                endpoint,
+                analyze,
+            };
+
+            cli::run_clip(options)?;
+        }
+        Some(Commands::Convert {
+            input,
+            output,
+            rate,
+            format,
+            codec_roundtrip,
+            input_args,
+        }) => {
+            let options = cli::convert::ConvertOptions {
+                input,
+                output,
+                audio_input: input_args.into(),
+                rate,
+                format,
+                codec_roundtrip,
+                quiet: args.quiet,
+            };
+
+            cli::run_convert(options)?;
+        }
+        Some(Commands::CodecCheck {
+            codecs,
+            reference,
+            min_snr,
+            max_level_change,
+            min_bandwidth,
+            json,
+            input_args,
+        }) => {
+            cli::codec_check::run_codec_check(cli::codec_check::CodecCheckOptions {
+                codecs,
+                reference,
+                audio_input: input_args.into(),
+                min_snr,
+                max_level_change,
+                min_bandwidth,
+                json,
+            })?;
+        }
+        Some(Commands::VerifyChain { codecs, address, port, output, json }) => {
+            cli::verify_chain::run_verify_chain(cli::verify_chain::VerifyChainOptions {
+                codecs: codecs.0,
+                address,
+                port,
+                output,
+                json,
+            })?;
+        }
         Some(Commands::PolycomTransmit {
             file,
             address,
             port,
             channel,
+            channel_names,
             codec,
             caller_id,
+            caller_id_encoding,
+            caller_id_pad,
             ttl,
+            dscp,
             r#loop,
             alert_count,
             end_count,
@@ -214,19 +572,45 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             no_audio_header,
             little_endian,
             raw,
+            allow_priority,
+            max_duration,
+            json,
+            dry_run,
+            cache_dir,
+            also_rtp,
+            await_receipts,
+            receipt_listen,
+            chime,
+            chime_gap_ms,
+            input,
+            levels,
+            impairment,
+            schedule,
         }) => {
             let addr = cli::monitor::parse_address(&address)?;
+            let config = &settings.config;
+            // The channel's caller ID from the config file is already the default
+            let caller_id = caller_id.unwrap_or_else(|| "MPS-IP".to_string());
+            let max_duration_secs =
+                max_duration.or_else(|| config.polycom.max_duration_secs(channel));
+            let channel_names = channel_names.load(&config.polycom)?;
 
             let options = cli::polycom_transmit::PolycomTransmitOptions {
                 file,
+                input: input.into(),
+                levels: levels.into(),
                 address: addr,
                 port,
                 channel,
+                channel_names,
                 codec,
                 caller_id,
+                caller_id_encoding,
+                caller_id_pad: usize::from(caller_id_pad),
                 ttl,
+                dscp,
                 loop_audio: r#loop,
-                quiet: args.quiet,
+                quiet: args.quiet || json,
                 alert_count,
                 end_count,
                 control_interval,
@@ -236,39 +620,191 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 no_audio_header,
                 little_endian,
                 raw,
+                allow_priority,
+                max_duration_secs,
+                json,
+                impairment: impairment.into(),
+                schedule: schedule.schedule(),
+                wait_for_window: schedule.wait_for_window,
+                dry_run,
+                also_rtp,
+                cache_dir,
+                await_receipts: await_receipts.map(|wait| (wait, receipt_listen)),
+                chime: chime.map(|chime| cli::chime::PageChime { chime, gap_ms: chime_gap_ms }),
             };
 
             cli::run_polycom_transmit(options).await?;
         }
+        Some(Commands::Bridge {
+            address,
+            port,
+            interface,
+            source_codec,
+            to,
+            channel,
+            codec,
+            caller_id,
+            caller_id_encoding,
+            caller_id_pad,
+            ttl,
+            dscp,
+            alert_count,
+            end_count,
+            latency,
+            idle,
+            allow_priority,
+            timeout,
+            until,
+            json,
+        }) => {
+            let source = cli::monitor::parse_address(&address)?;
+            let codec = cli::polycom_transmit::parse_polycom_codec(&codec)?;
+            // The channel's caller ID from the config file is already the default
+            let caller_id = caller_id.unwrap_or_else(|| "MPS-IP".to_string());
+
+            let options = cli::bridge::BridgeOptions {
+                source: SocketAddrV4::new(source, port),
+                interface,
+                source_codec,
+                destination: to,
+                channel,
+                codec,
+                caller_id,
+                caller_id_encoding,
+                caller_id_pad: usize::from(caller_id_pad),
+                ttl,
+                dscp,
+                alert_count,
+                end_count,
+                latency: Duration::from_millis(latency),
+                idle: Duration::from_millis(idle),
+                allow_priority,
+                timeout: Timeout::from_args(timeout, until),
+                json,
+                quiet: args.quiet,
+            };
+
+            cli::run_bridge(options).await?;
+        }
         Some(Commands::PolycomMonitor {
             address,
             port,
             channels,
+            channel_names,
             output,
             timeout,
+            until,
             json,
+            pcap,
+            abort_on_bad_alert,
+            receipt,
+            verify_redundancy,
+            count,
+            inspect,
+            naming,
         }) => {
+            let config = &settings.config;
             let options = cli::polycom_monitor::PolycomMonitorOptions {
                 pattern: address,
                 default_port: port,
                 channels,
+                channel_names: channel_names.load(&config.polycom)?,
                 output,
-                timeout: if timeout == 0 {
-                    Duration::MAX
-                } else {
-                    Duration::from_secs(timeout)
-                },
+                timeout: Timeout::from_args(timeout, until),
                 json,
                 quiet: args.quiet,
+                pcap,
+                abort_on_bad_alert,
+                naming: naming.into(),
+                receipt,
+                count: count.page_count(),
+                verify_redundancy,
+                inspect: inspect.options(),
             };
 
             cli::run_polycom_monitor(options).await?;
         }
+        Some(Commands::IgmpCycle {
+            address,
+            port,
+            interface,
+            cycle_interface,
+            cycles,
+            interval,
+            transmit,
+            ttl,
+            timeout,
+            json,
+        }) => {
+            let options = cli::igmp_cycle::IgmpCycleOptions {
+                address: cli::monitor::parse_address(&address)?,
+                port,
+                interface,
+                cycle_interface,
+                cycles,
+                interval: Duration::from_millis(interval),
+                transmit,
+                ttl,
+                wait: Duration::from_secs(timeout),
+                json,
+                quiet: args.quiet,
+            };
+
+            cli::run_igmp_cycle(options).await?;
+        }
+        Some(Commands::Doctor { interface, output, endpoints, json }) => {
+            cli::doctor::run_doctor(cli::doctor::DoctorOptions {
+                interface: interface.unwrap_or_default(),
+                output: output.unwrap_or_else(|| PathBuf::from(".")),
+                endpoints,
+                json,
+            })?;
+        }
+        Some(Commands::Cache { cache_dir, action }) => {
+            cli::frame_cache::run_cache(&cache_dir, &action)?;
+        }
+        Some(Commands::Capabilities) => {
+            print_capabilities()?;
+        }
+        Some(Commands::Config { action: cli::ConfigAction::Init { force } }) => {
+            config::Config::write_starter(&settings.config_file, force)?;
+            println!("Wrote {}", settings.config_file.display());
+        }
+        Some(Commands::ValidateConfig) => {
+            validate_config(&settings.config_file, settings.config_explicit)?;
+        }
+        Some(Commands::Schema { kind }) => {
+            println!("{}", serde_json::to_string_pretty(&kind.schema())?);
+        }
+        Some(Commands::Completions { shell }) => {
+            cli::completions::write_completions(shell, &mut std::io::stdout());
+        }
     }
 
     Ok(())
 }
 
+/// Check a config file the same way the commands load it, and print what
+/// they would use
+fn validate_config(path: &Path, explicit: bool) -> Result<(), config::ConfigError> {
+    let config = config::Config::load_or_default(path, explicit)?;
+    if path.exists() {
+        println!("OK: {}", path.display());
+    } else {
+        println!("OK: no config file at {}, using defaults", path.display());
+    }
+    println!();
+    print!("{}", config.effective()?);
+    Ok(())
+}
+
+/// Print the capabilities document for tooling
+fn print_capabilities() -> Result<(), serde_json::Error> {
+    let capabilities = capabilities::Capabilities::collect();
+    println!("{}", serde_json::to_string_pretty(&capabilities)?);
+    Ok(())
+}
+
 #[allow(clippy::unnecessary_wraps)] // Will return errors when GUI is implemented
 fn run_gui() -> Result<(), Box<dyn std::error::Error>> {
     // For now, print a message that GUI is not yet implemented
@@ -0,0 +1,254 @@
+//! Received TTL, TOS and kernel receive time via `recvmsg` ancillary data.
+//!
+//! Neither std nor tokio expose ancillary data, so on unix the receive path
+//! uses `recvmsg` directly and picks the TTL, TOS and timestamp out of the
+//! control messages. Linux delivers the TTL (`IP_RECVTTL`) as an `int` under
+//! `IP_TTL`, the BSDs and macOS as a single byte under `IP_RECVTTL`. The TOS
+//! (`IP_RECVTOS`) is a single byte, under `IP_TOS` on Linux and
+//! `IP_RECVTOS` elsewhere. The
+//! timestamp is the wall-clock time the kernel queued the datagram: a
+//! `timespec` (`SO_TIMESTAMPNS`) on Linux, a `timeval` (`SO_TIMESTAMP`)
+//! elsewhere. Any of them can be missing, and elsewhere all are unknown.
+//!
+//! On Linux, `recv_batch` reads every waiting datagram, up to `MAX_BATCH`,
+//! with one `recvmmsg` call. Each message has its own control buffer, so
+//! each datagram keeps its own TTL, TOS and timestamp.
+
+use std::io;
+use std::mem;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::os::fd::RawFd;
+use std::ptr;
+use std::time::{Duration, SystemTime};
+
+use super::dscp::Dscp;
+
+/// Socket option turning on receive timestamps, and the control message they arrive in
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const TIMESTAMP_OPTION: (libc::c_int, libc::c_int) = (libc::SO_TIMESTAMPNS, libc::SCM_TIMESTAMPNS);
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const TIMESTAMP_OPTION: (libc::c_int, libc::c_int) = (libc::SO_TIMESTAMP, libc::SCM_TIMESTAMP);
+
+/// What the kernel attached to a datagram
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ancillary {
+    pub ttl: Option<u8>,
+    /// DSCP from the TOS byte it arrived with
+    pub dscp: Option<Dscp>,
+    /// When the kernel received it (wall clock)
+    pub timestamp: Option<SystemTime>,
+}
+
+/// Ask the kernel to attach the TTL to every received datagram
+pub fn enable_ttl(fd: RawFd) -> io::Result<()> {
+    set_flag(fd, libc::IPPROTO_IP, libc::IP_RECVTTL)
+}
+
+/// Ask the kernel to attach the TOS byte to every received datagram
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+pub fn enable_tos(fd: RawFd) -> io::Result<()> {
+    set_flag(fd, libc::IPPROTO_IP, libc::IP_RECVTOS)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios", target_os = "freebsd")))]
+pub fn enable_tos(_fd: RawFd) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "IP_RECVTOS is not available on this platform"))
+}
+
+/// Ask the kernel to attach its receive time to every received datagram
+pub fn enable_timestamps(fd: RawFd) -> io::Result<()> {
+    set_flag(fd, libc::SOL_SOCKET, TIMESTAMP_OPTION.0)
+}
+
+fn set_flag(fd: RawFd, level: libc::c_int, option: libc::c_int) -> io::Result<()> {
+    let on: libc::c_int = 1;
+    // SAFETY: `on` outlives the call and the length matches its type
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            option,
+            ptr::addr_of!(on).cast(),
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Receive one datagram with its source address and whatever the kernel attached
+pub fn recv(fd: RawFd, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, Ancillary)> {
+    // SAFETY: all-zero is a valid sockaddr_storage and msghdr
+    let mut address: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    // Room for a few control messages, aligned for cmsghdr
+    let mut control = [0u64; 16];
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = ptr::addr_of_mut!(address).cast();
+    msg.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    msg.msg_iov = ptr::addr_of_mut!(iov);
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = mem::size_of_val(&control) as _;
+
+    // SAFETY: every pointer in `msg` refers to a live local or `buf`
+    let len = unsafe { libc::recvmsg(fd, ptr::addr_of_mut!(msg), 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok((len as usize, source_address(&address)?, control_messages(&msg)))
+}
+
+/// Most datagrams `recv_batch` reads in one call
+#[cfg(target_os = "linux")]
+pub const MAX_BATCH: usize = 64;
+
+/// Receive the datagrams waiting, one per buffer and at most `MAX_BATCH`,
+/// with a single `recvmmsg` call. Fails with `WouldBlock` on a
+/// non-blocking socket with nothing waiting, as `recv` does.
+#[cfg(target_os = "linux")]
+pub fn recv_batch<B: AsMut<[u8]>>(fd: RawFd, bufs: &mut [B]) -> io::Result<Vec<(usize, SocketAddr, Ancillary)>> {
+    let count = bufs.len().min(MAX_BATCH);
+    // SAFETY: all-zero is a valid sockaddr_storage, iovec and mmsghdr
+    let mut addresses: [libc::sockaddr_storage; MAX_BATCH] = unsafe { mem::zeroed() };
+    let mut iovs: [libc::iovec; MAX_BATCH] = unsafe { mem::zeroed() };
+    let mut headers: [libc::mmsghdr; MAX_BATCH] = unsafe { mem::zeroed() };
+    let mut controls = [[0u64; 16]; MAX_BATCH];
+    for (i, buf) in bufs[..count].iter_mut().enumerate() {
+        let buf = buf.as_mut();
+        iovs[i] = libc::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+        let msg = &mut headers[i].msg_hdr;
+        msg.msg_name = ptr::addr_of_mut!(addresses[i]).cast();
+        msg.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        msg.msg_iov = ptr::addr_of_mut!(iovs[i]);
+        msg.msg_iovlen = 1;
+        msg.msg_control = controls[i].as_mut_ptr().cast();
+        msg.msg_controllen = mem::size_of_val(&controls[i]) as _;
+    }
+
+    // SAFETY: the first `count` headers point at live locals and buffers
+    let received = unsafe { libc::recvmmsg(fd, headers.as_mut_ptr(), count as _, 0, ptr::null_mut()) };
+    if received < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    headers[..received as usize]
+        .iter()
+        .zip(&addresses)
+        .map(|(header, address)| {
+            Ok((
+                header.msg_len as usize,
+                source_address(address)?,
+                control_messages(&header.msg_hdr),
+            ))
+        })
+        .collect()
+}
+
+fn source_address(address: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+    if i32::from(address.ss_family) != libc::AF_INET {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "datagram from a non-IPv4 source"));
+    }
+    // SAFETY: the family says the storage holds a sockaddr_in
+    let source: libc::sockaddr_in = unsafe { ptr::read(ptr::addr_of!(*address).cast()) };
+    Ok(SocketAddr::V4(SocketAddrV4::new(
+        Ipv4Addr::from(u32::from_be(source.sin_addr.s_addr)),
+        u16::from_be(source.sin_port),
+    )))
+}
+
+fn control_messages(msg: &libc::msghdr) -> Ancillary {
+    // SAFETY: CMSG_LEN is arithmetic on its argument
+    let int_len = unsafe { libc::CMSG_LEN(mem::size_of::<libc::c_int>() as u32) } as usize;
+    let mut ancillary = Ancillary::default();
+    // SAFETY: the CMSG macros only walk the control buffer the kernel filled in
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(msg);
+        while !cmsg.is_null() {
+            let header = &*cmsg;
+            let data = libc::CMSG_DATA(cmsg);
+            if header.cmsg_level == libc::IPPROTO_IP
+                && (header.cmsg_type == libc::IP_TTL || header.cmsg_type == libc::IP_RECVTTL)
+            {
+                ancillary.ttl = Some(if header.cmsg_len as usize >= int_len {
+                    data.cast::<libc::c_int>().read_unaligned() as u8
+                } else {
+                    *data
+                });
+            } else if header.cmsg_level == libc::IPPROTO_IP && is_tos_message(header.cmsg_type) {
+                ancillary.dscp = Some(Dscp::from_tos(*data));
+            } else if header.cmsg_level == libc::SOL_SOCKET && header.cmsg_type == TIMESTAMP_OPTION.1 {
+                ancillary.timestamp = read_timestamp(data);
+            }
+            cmsg = libc::CMSG_NXTHDR(msg, cmsg);
+        }
+    }
+    ancillary
+}
+
+/// Whether a control message of this type carries the TOS byte
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn is_tos_message(cmsg_type: libc::c_int) -> bool {
+    cmsg_type == libc::IP_TOS
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+fn is_tos_message(cmsg_type: libc::c_int) -> bool {
+    cmsg_type == libc::IP_RECVTOS
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios", target_os = "freebsd")))]
+fn is_tos_message(_cmsg_type: libc::c_int) -> bool {
+    false
+}
+
+/// # Safety
+/// `data` must point at the payload of an `SCM_TIMESTAMPNS` message
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn read_timestamp(data: *const u8) -> Option<SystemTime> {
+    let time = data.cast::<libc::timespec>().read_unaligned();
+    let since_epoch = Duration::new(u64::try_from(time.tv_sec).ok()?, u32::try_from(time.tv_nsec).ok()?);
+    SystemTime::UNIX_EPOCH.checked_add(since_epoch)
+}
+
+/// # Safety
+/// `data` must point at the payload of an `SCM_TIMESTAMP` message
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+unsafe fn read_timestamp(data: *const u8) -> Option<SystemTime> {
+    let time = data.cast::<libc::timeval>().read_unaligned();
+    let micros = u32::try_from(time.tv_usec).ok()?;
+    let since_epoch = Duration::new(u64::try_from(time.tv_sec).ok()?, micros * 1000);
+    SystemTime::UNIX_EPOCH.checked_add(since_epoch)
+}
+
+/// Have the kernel busy-poll the device queue for up to `micros` before a
+/// read on this socket sleeps. Raising it needs `CAP_NET_ADMIN`.
+#[cfg(target_os = "linux")]
+pub fn set_busy_poll(fd: RawFd, micros: u32) -> io::Result<()> {
+    let value = libc::c_int::try_from(micros).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: `value` outlives the call and the length matches its type
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_BUSY_POLL,
+            ptr::addr_of!(value).cast(),
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
@@ -0,0 +1,128 @@
+//! DSCP (Differentiated Services Code Point) values.
+//!
+//! The top six bits of the IPv4 TOS byte. Voice VLAN quality of service
+//! policies queue on it, so paging audio is normally marked EF (46).
+//! `transmit --dscp` sets it on outgoing packets, and the monitors read it
+//! back from each received datagram (`IP_RECVTOS`) to show whether the
+//! marking survived the path.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Named code points: EF, the assured forwarding classes and class selectors
+const NAMES: [(&str, u8); 22] = [
+    ("ef", 46),
+    ("af11", 10),
+    ("af12", 12),
+    ("af13", 14),
+    ("af21", 18),
+    ("af22", 20),
+    ("af23", 22),
+    ("af31", 26),
+    ("af32", 28),
+    ("af33", 30),
+    ("af41", 34),
+    ("af42", 36),
+    ("af43", 38),
+    ("cs0", 0),
+    ("cs1", 8),
+    ("cs2", 16),
+    ("cs3", 24),
+    ("cs4", 32),
+    ("cs5", 40),
+    ("cs6", 48),
+    ("cs7", 56),
+    ("va", 44),
+];
+
+/// A DSCP value, 0-63. Written to JSON as the number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct Dscp(u8);
+
+impl Dscp {
+    /// From a DSCP value; `None` above 63
+    pub const fn new(value: u8) -> Option<Self> {
+        if value < 64 {
+            Some(Self(value))
+        } else {
+            None
+        }
+    }
+
+    /// From a whole TOS byte, dropping the two ECN bits
+    pub const fn from_tos(tos: u8) -> Self {
+        Self(tos >> 2)
+    }
+
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+
+    /// The TOS byte carrying this DSCP, with ECN clear
+    pub const fn tos(self) -> u8 {
+        self.0 << 2
+    }
+
+    /// The code point's name, as in EF or AF41, if it has one
+    pub fn name(self) -> Option<String> {
+        NAMES
+            .iter()
+            .find(|(_, value)| *value == self.0)
+            .map(|(name, _)| name.to_ascii_uppercase())
+    }
+}
+
+impl fmt::Display for Dscp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{} ({})", self.0, name),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl FromStr for Dscp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        if let Ok(value) = s.parse::<u8>() {
+            return Self::new(value).ok_or_else(|| format!("DSCP {} is out of range (0-63)", value));
+        }
+        let name = if s == "be" || s == "default" { "cs0" } else { s.as_str() };
+        NAMES
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|&(_, value)| Self(value))
+            .ok_or_else(|| format!("unknown DSCP '{}' (expected 0-63, ef, af11-af43, cs0-cs7 or be)", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dscp() {
+        assert_eq!("ef".parse::<Dscp>(), Ok(Dscp(46)));
+        assert_eq!("EF".parse::<Dscp>(), Ok(Dscp(46)));
+        assert_eq!("46".parse::<Dscp>(), Ok(Dscp(46)));
+        assert_eq!("af41".parse::<Dscp>().map(Dscp::value), Ok(34));
+        assert_eq!("cs5".parse::<Dscp>().map(Dscp::value), Ok(40));
+        assert_eq!("be".parse::<Dscp>().map(Dscp::value), Ok(0));
+        assert!("64".parse::<Dscp>().unwrap_err().contains("out of range"));
+        assert!("af44".parse::<Dscp>().is_err());
+    }
+
+    #[test]
+    fn test_tos_byte() {
+        assert_eq!(Dscp(46).tos(), 0xb8);
+        // ECN bits are not part of the DSCP
+        assert_eq!(Dscp::from_tos(0xb9), Dscp(46));
+        assert_eq!(Dscp(46).to_string(), "46 (EF)");
+        assert_eq!(Dscp::from_tos(5 << 2).to_string(), "5");
+    }
+}
@@ -0,0 +1,368 @@
+//! Network impairment simulation for the transmit path
+//!
+//! Impairments are applied to fully built packets, so sequence numbers and
+//! timestamps stay truthful - this simulates a lossy network between a
+//! well-behaved sender and the receivers, not a broken sender.
+
+use serde::Serialize;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+use schemars::JsonSchema;
+
+#[derive(Error, Debug)]
+pub enum ImpairmentError {
+    #[error("{name} must be between 0 and 100 (got {value})")]
+    InvalidPercent { name: &'static str, value: f64 },
+
+    #[error("Invalid drop burst '{0}': expected N@P, e.g. 5@0.2 (5 packets, 0.2 bursts/second)")]
+    InvalidBurst(String),
+
+    #[error("Jitter must be a non-negative number of milliseconds (got {0})")]
+    InvalidJitter(f64),
+}
+
+/// Burst loss: drop `length` consecutive packets, starting on average `per_second` times a second
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DropBurst {
+    pub length: u32,
+    pub per_second: f64,
+}
+
+impl FromStr for DropBurst {
+    type Err = ImpairmentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ImpairmentError::InvalidBurst(s.to_string());
+        let (length, per_second) = s.split_once('@').ok_or_else(err)?;
+        let length: u32 = length.trim().parse().map_err(|_| err())?;
+        let per_second: f64 = per_second.trim().parse().map_err(|_| err())?;
+        if length == 0 || !per_second.is_finite() || per_second < 0.0 {
+            return Err(err());
+        }
+        Ok(Self { length, per_second })
+    }
+}
+
+/// Requested impairments
+#[derive(Debug, Clone, Default)]
+pub struct ImpairmentConfig {
+    /// Random independent loss
+    pub drop_percent: f64,
+    /// Random burst loss
+    pub drop_burst: Option<DropBurst>,
+    /// Maximum extra delay added to each packet's scheduled send time
+    pub jitter_ms: f64,
+    /// Packets sent twice
+    pub duplicate_percent: f64,
+    /// Packets held back and sent after the following one
+    pub reorder_percent: f64,
+    /// Seed for reproducible runs (time-based if not set)
+    pub seed: Option<u64>,
+}
+
+impl ImpairmentConfig {
+    /// Check that all values are in range
+    pub fn validate(&self) -> Result<(), ImpairmentError> {
+        for (name, value) in [
+            ("--drop-percent", self.drop_percent),
+            ("--duplicate-percent", self.duplicate_percent),
+            ("--reorder-percent", self.reorder_percent),
+        ] {
+            if !(0.0..=100.0).contains(&value) {
+                return Err(ImpairmentError::InvalidPercent { name, value });
+            }
+        }
+        if !self.jitter_ms.is_finite() || self.jitter_ms < 0.0 {
+            return Err(ImpairmentError::InvalidJitter(self.jitter_ms));
+        }
+        Ok(())
+    }
+
+    /// Whether any impairment is configured
+    pub fn is_active(&self) -> bool {
+        self.drop_percent > 0.0
+            || self.drop_burst.is_some()
+            || self.jitter_ms > 0.0
+            || self.duplicate_percent > 0.0
+            || self.reorder_percent > 0.0
+    }
+}
+
+/// What the impairer actually did
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct ImpairmentStats {
+    /// Packets handed to the impairer
+    pub packets_in: u64,
+    /// Packets put on the wire (including duplicates)
+    pub packets_sent: u64,
+    /// Packets dropped by random loss
+    pub dropped: u64,
+    /// Packets dropped by loss bursts
+    pub burst_dropped: u64,
+    /// Number of loss bursts started
+    pub bursts: u64,
+    /// Extra copies sent
+    pub duplicated: u64,
+    /// Packets delayed by one slot
+    pub reordered: u64,
+    /// Average jitter delay added (ms)
+    pub avg_jitter_ms: f64,
+    #[serde(skip)]
+    jitter_total_ms: f64,
+}
+
+impl ImpairmentStats {
+    /// Total packets lost (random and burst)
+    pub fn total_dropped(&self) -> u64 {
+        self.dropped + self.burst_dropped
+    }
+}
+
+/// Applies the configured impairments to a stream of packets
+pub struct Impairer {
+    config: ImpairmentConfig,
+    rng: XorShift,
+    burst_chance: f64,
+    burst_remaining: u32,
+    held: Option<Vec<u8>>,
+    stats: ImpairmentStats,
+}
+
+impl Impairer {
+    /// Create an impairer for packets sent every `packet_interval`
+    pub fn new(config: ImpairmentConfig, packet_interval: Duration) -> Self {
+        let seed = config.seed.unwrap_or_else(time_seed);
+        let burst_chance = config
+            .drop_burst
+            .map_or(0.0, |b| (b.per_second * packet_interval.as_secs_f64()).min(1.0));
+        Self {
+            config,
+            rng: XorShift::new(seed),
+            burst_chance,
+            burst_remaining: 0,
+            held: None,
+            stats: ImpairmentStats::default(),
+        }
+    }
+
+    /// Extra delay to add before sending the next packet
+    pub fn jitter(&mut self) -> Duration {
+        if self.config.jitter_ms <= 0.0 {
+            return Duration::ZERO;
+        }
+        let delay_ms = self.rng.next_f64() * self.config.jitter_ms;
+        self.stats.jitter_total_ms += delay_ms;
+        Duration::from_secs_f64(delay_ms / 1000.0)
+    }
+
+    /// Decide what goes on the wire for this packet slot, in send order
+    pub fn process(&mut self, packet: Vec<u8>) -> Vec<Vec<u8>> {
+        self.stats.packets_in += 1;
+        let released = self.held.take();
+        let mut out = Vec::with_capacity(2);
+
+        if self.burst_remaining == 0 && self.burst_chance > 0.0 && self.rng.chance(self.burst_chance) {
+            self.burst_remaining = self.config.drop_burst.map_or(0, |b| b.length);
+            self.stats.bursts += 1;
+        }
+
+        if self.burst_remaining > 0 {
+            self.burst_remaining -= 1;
+            self.stats.burst_dropped += 1;
+        } else if self.rng.percent(self.config.drop_percent) {
+            self.stats.dropped += 1;
+        } else if released.is_none() && self.rng.percent(self.config.reorder_percent) {
+            // Send after the next slot's packet
+            self.held = Some(packet);
+            self.stats.reordered += 1;
+        } else {
+            if self.rng.percent(self.config.duplicate_percent) {
+                out.push(packet.clone());
+                self.stats.duplicated += 1;
+            }
+            out.push(packet);
+        }
+
+        out.extend(released);
+        self.stats.packets_sent += out.len() as u64;
+        out
+    }
+
+    /// Release a packet still held for reordering at the end of the stream
+    pub fn flush(&mut self) -> Option<Vec<u8>> {
+        let packet = self.held.take();
+        if packet.is_some() {
+            self.stats.packets_sent += 1;
+        }
+        packet
+    }
+
+    /// Statistics so far
+    pub fn stats(&self) -> ImpairmentStats {
+        let mut stats = self.stats.clone();
+        if stats.packets_in > 0 {
+            stats.avg_jitter_ms = stats.jitter_total_ms / stats.packets_in as f64;
+        }
+        stats
+    }
+}
+
+/// Print a one-line summary of what was impaired
+pub fn print_impairment_summary(stats: &ImpairmentStats) {
+    let loss = if stats.packets_in > 0 {
+        100.0 * stats.total_dropped() as f64 / stats.packets_in as f64
+    } else {
+        0.0
+    };
+    println!(
+        "  Impairments: {} dropped ({:.1}%, {} in {} bursts), {} duplicated, {} reordered, {:.1}ms avg jitter",
+        stats.total_dropped(),
+        loss,
+        stats.burst_dropped,
+        stats.bursts,
+        stats.duplicated,
+        stats.reordered,
+        stats.avg_jitter_ms
+    );
+}
+
+pub(crate) fn time_seed() -> u64 {
+    use std::time::SystemTime;
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
+
+/// Small xorshift64* generator - plenty for impairment decisions
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+    pub(crate) fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift
+        Self(if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed })
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform value in [0, 1)
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.next_f64() < probability
+    }
+
+    fn percent(&mut self, percent: f64) -> bool {
+        self.chance(percent / 100.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(config: ImpairmentConfig, count: u8) -> (Vec<Vec<u8>>, ImpairmentStats) {
+        let mut impairer = Impairer::new(config, Duration::from_millis(20));
+        let mut wire = Vec::new();
+        for i in 0..count {
+            wire.extend(impairer.process(vec![i]));
+        }
+        wire.extend(impairer.flush());
+        (wire, impairer.stats())
+    }
+
+    #[test]
+    fn test_parse_drop_burst() {
+        assert_eq!(
+            "5@0.2".parse::<DropBurst>().unwrap(),
+            DropBurst { length: 5, per_second: 0.2 }
+        );
+        assert!("5".parse::<DropBurst>().is_err());
+        assert!("0@1".parse::<DropBurst>().is_err());
+        assert!("3@-1".parse::<DropBurst>().is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let config = ImpairmentConfig { drop_percent: 150.0, ..Default::default() };
+        assert!(config.validate().is_err());
+        let config = ImpairmentConfig { jitter_ms: -1.0, ..Default::default() };
+        assert!(config.validate().is_err());
+        assert!(ImpairmentConfig::default().validate().is_ok());
+        assert!(!ImpairmentConfig::default().is_active());
+    }
+
+    #[test]
+    fn test_passthrough() {
+        let (wire, stats) = run(ImpairmentConfig::default(), 100);
+        assert_eq!(wire, (0..100).map(|i| vec![i]).collect::<Vec<_>>());
+        assert_eq!(stats.packets_sent, 100);
+    }
+
+    #[test]
+    fn test_random_drop_rate() {
+        let mut impairer = Impairer::new(
+            ImpairmentConfig { drop_percent: 10.0, seed: Some(42), ..Default::default() },
+            Duration::from_millis(20),
+        );
+        for _ in 0..10_000 {
+            impairer.process(vec![0]);
+        }
+        let stats = impairer.stats();
+        assert!((900..=1100).contains(&stats.dropped), "dropped {}", stats.dropped);
+        assert_eq!(stats.packets_sent, 10_000 - stats.dropped);
+    }
+
+    #[test]
+    fn test_burst_drops_consecutive_packets() {
+        // One burst per second on average at 50 packets/second
+        let config = ImpairmentConfig {
+            drop_burst: Some(DropBurst { length: 5, per_second: 1.0 }),
+            seed: Some(7),
+            ..Default::default()
+        };
+        let (wire, stats) = run(config, 250);
+        assert!(stats.bursts > 0);
+        assert_eq!(stats.burst_dropped as usize, 250 - wire.len());
+        assert!(stats.burst_dropped <= stats.bursts * 5);
+    }
+
+    #[test]
+    fn test_duplicate_and_reorder() {
+        let config = ImpairmentConfig { duplicate_percent: 100.0, seed: Some(1), ..Default::default() };
+        let (wire, stats) = run(config, 10);
+        assert_eq!(wire.len(), 20);
+        assert_eq!(stats.duplicated, 10);
+
+        let config = ImpairmentConfig { reorder_percent: 100.0, seed: Some(1), ..Default::default() };
+        let (wire, stats) = run(config, 4);
+        // Every other packet is held back one slot
+        assert_eq!(wire, vec![vec![1], vec![0], vec![3], vec![2]]);
+        assert_eq!(stats.reordered, 2);
+    }
+
+    #[test]
+    fn test_jitter_bounds() {
+        let mut impairer = Impairer::new(
+            ImpairmentConfig { jitter_ms: 30.0, seed: Some(3), ..Default::default() },
+            Duration::from_millis(20),
+        );
+        for _ in 0..100 {
+            let delay = impairer.jitter();
+            assert!(delay <= Duration::from_millis(30));
+            impairer.process(vec![0]);
+        }
+        let stats = impairer.stats();
+        assert!(stats.avg_jitter_ms > 5.0 && stats.avg_jitter_ms < 25.0);
+    }
+}
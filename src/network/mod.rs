@@ -1,10 +1,106 @@
+#[cfg(unix)]
+mod ancillary;
+pub mod dscp;
+pub mod impairment;
 pub mod multicast;
+pub mod pcap;
 pub mod polycom;
+pub mod repair;
 pub mod rtp;
 
-pub use multicast::{MulticastSocket, MulticastError, create_transmit_socket};
+pub use impairment::{
+    DropBurst, Impairer, ImpairmentConfig, ImpairmentError, ImpairmentStats,
+    print_impairment_summary,
+};
+pub use multicast::{
+    BufSlot, Datagram, MulticastSocket, MulticastError, MulticastInterface, create_transmit_socket, resolve_source_address,
+};
+pub use dscp::Dscp;
+pub use pcap::PcapWriter;
 pub use polycom::{
-    PolycomPacket, PolycomPacketBuilder, PolycomSession, PolycomCodec,
-    PolycomError, PacketType,
+    AlertPhaseSummary, PolycomPacket, PolycomPacketBuilder, PolycomSession, PolycomCodec,
+    PolycomError, PacketType, CallerIdEncoding, PolycomHeader,
+    is_emergency_channel, is_priority_channel,
 };
 pub use rtp::{RtpPacket, PayloadType};
+
+use crate::capabilities::{Capabilities, ProtocolCapability};
+use crate::codec::CodecType;
+
+/// Register the supported protocols and RTP payload type mapping
+pub fn register_capabilities(capabilities: &mut Capabilities) {
+    capabilities.add_protocol(ProtocolCapability {
+        name: "rtp",
+        description: "RTP multicast paging",
+        commands: vec!["monitor", "test", "transmit", "bridge"],
+        codecs: CodecType::ALL.iter().map(|c| c.cli_name()).collect(),
+    });
+    capabilities.add_protocol(ProtocolCapability {
+        name: "polycom",
+        description: "Polycom PTT/Group Paging",
+        commands: vec!["polycom-monitor", "polycom-transmit", "bridge"],
+        codecs: [PolycomCodec::G711U, PolycomCodec::G711A, PolycomCodec::G722]
+            .iter()
+            .map(|&c| polycom_codec_type(c).cli_name())
+            .collect(),
+    });
+
+    for pt in 0..=127 {
+        if let Some(codec) = CodecType::from_payload_type(pt) {
+            let dynamic = matches!(PayloadType::from_pt(pt), PayloadType::Dynamic(_));
+            capabilities.add_payload_type(pt, codec.cli_name(), dynamic);
+        }
+    }
+
+    if cfg!(not(unix)) {
+        capabilities.add_limitation("Received TTL is not reported (network.ttl is absent)");
+        capabilities.add_limitation("Packets are timed when read rather than by the kernel, so jitter includes scheduling delay");
+    }
+    if cfg!(not(target_os = "linux")) {
+        capabilities.add_limitation("--realtime and --busy-poll-us have no effect");
+    }
+    if cfg!(windows) {
+        capabilities.add_limitation(
+            "test binds group sockets to 0.0.0.0; datagrams sent straight to a monitored port's unicast address are counted too",
+        );
+    }
+}
+
+/// Codec used to decode a Polycom audio stream
+pub const fn polycom_codec_type(codec: PolycomCodec) -> CodecType {
+    match codec {
+        PolycomCodec::G711U => CodecType::G711Ulaw,
+        PolycomCodec::G711A => CodecType::G711Alaw,
+        PolycomCodec::G722 => CodecType::G722,
+    }
+}
+
+/// Datagrams for the parsers' never-panic tests, `count` of them up to 2048
+/// bytes. Half are random bytes; the rest start from one of `valid` with
+/// bytes overwritten, cut short or run on, so the lengths and flags in an
+/// otherwise plausible header point anywhere
+#[cfg(test)]
+pub(crate) fn mangled_datagrams(seed: u64, valid: &[Vec<u8>], count: usize) -> Vec<Vec<u8>> {
+    let mut rng = impairment::XorShift::new(seed);
+    let mut below = |limit: usize| (rng.next_u64() % limit as u64) as usize;
+    (0..count)
+        .map(|i| {
+            if i % 2 == 0 {
+                return (0..below(2049)).map(|_| below(256) as u8).collect();
+            }
+            let mut data = valid[below(valid.len())].clone();
+            for _ in 0..=below(4) {
+                if !data.is_empty() {
+                    let at = below(data.len().min(32));
+                    data[at] = below(256) as u8;
+                }
+            }
+            match below(3) {
+                0 => data.truncate(below(data.len() + 1)),
+                1 => data.resize(data.len() + below(2049 - data.len()), below(256) as u8),
+                _ => {}
+            }
+            data
+        })
+        .collect()
+}
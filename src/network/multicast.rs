@@ -1,14 +1,37 @@
 //! Multicast socket management for RTP streams.
+//!
+//! Unix and Windows differ in two ways that matter here. Unix filters
+//! received datagrams on the bound address, so a socket bound to a group
+//! only sees that group even when other sockets share the port. Windows
+//! refuses to bind a multicast address at all; a group socket binds the
+//! port on every address instead, and since Windows only hands a group's
+//! datagrams to sockets that joined it, the join does the filtering.
+//! `IP_MULTICAST_LOOP` also applies on the sending socket on Unix but on the
+//! receiving one on Windows, so both sides enable it.
+//!
+//! Busy receive loops read in batches (`recv_batch`, `try_recv_batch`). On
+//! Linux a batch is one `recvmmsg` call rather than a call per datagram;
+//! elsewhere it's read a datagram at a time. Kernel timestamps are per
+//! datagram either way. Where there are none, each datagram of a batch is
+//! given the time the batch was read, so datagrams that queued together
+//! look like they arrived together.
 
 #![allow(dead_code)]
 
 use socket2::{Domain, Protocol, Socket, Type};
 use std::collections::HashSet;
+use std::fmt;
 use std::io;
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime};
 use thiserror::Error;
 use tokio::net::UdpSocket as TokioUdpSocket;
 
+use super::dscp::Dscp;
+
 #[derive(Error, Debug)]
 pub enum MulticastError {
     #[error("Socket error: {0}")]
@@ -24,39 +47,130 @@ pub enum MulticastError {
     NotMember(Ipv4Addr),
 }
 
+/// Local interface multicast groups are joined on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MulticastInterface {
+    /// An address assigned to the interface; `0.0.0.0` lets the system choose
+    Address(Ipv4Addr),
+    /// The interface's index, as listed by `ip link` or `Get-NetAdapter`
+    Index(u32),
+}
+
+impl Default for MulticastInterface {
+    fn default() -> Self {
+        Self::Address(Ipv4Addr::UNSPECIFIED)
+    }
+}
+
+impl FromStr for MulticastInterface {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(address) = s.parse::<Ipv4Addr>() {
+            Ok(Self::Address(address))
+        } else if let Ok(index) = s.parse::<u32>() {
+            Ok(Self::Index(index))
+        } else {
+            Err(format!("'{}' is neither an IPv4 address nor an interface index", s))
+        }
+    }
+}
+
+impl fmt::Display for MulticastInterface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Address(address) => write!(f, "{}", address),
+            Self::Index(index) => write!(f, "interface #{}", index),
+        }
+    }
+}
+
+/// Kernel receive times older than this are taken to be a wall clock step
+/// rather than a datagram that waited in the queue, and ignored
+const MAX_TIMESTAMP_AGE: Duration = Duration::from_secs(10);
+
+/// A received datagram and when it arrived
+#[derive(Debug, Clone, Copy)]
+pub struct Datagram {
+    pub len: usize,
+    pub source: SocketAddr,
+    /// Arriving TTL, where the platform reports it
+    pub ttl: Option<u8>,
+    /// Arriving DSCP, where the platform reports it
+    pub dscp: Option<Dscp>,
+    /// When the kernel received it, or when it was read where that's unknown
+    pub received_at: Instant,
+    /// The same moment on the wall clock
+    pub wall_time: SystemTime,
+    /// Whether the times came from the kernel
+    pub kernel_timestamp: bool,
+}
+
+/// Buffer for one datagram of a batch, and the datagram last read into it
+#[derive(Debug)]
+pub struct BufSlot {
+    buf: Box<[u8]>,
+    datagram: Option<Datagram>,
+}
+
+impl BufSlot {
+    pub fn new(size: usize) -> Self {
+        Self {
+            buf: vec![0u8; size].into_boxed_slice(),
+            datagram: None,
+        }
+    }
+
+    /// `count` slots of `size` bytes each
+    pub fn batch(count: usize, size: usize) -> Vec<Self> {
+        (0..count).map(|_| Self::new(size)).collect()
+    }
+
+    /// The datagram read into this slot, and its bytes
+    pub fn received(&self) -> Option<(Datagram, &[u8])> {
+        self.datagram.map(|datagram| (datagram, &self.buf[..datagram.len]))
+    }
+}
+
+impl AsMut<[u8]> for BufSlot {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+}
+
 /// A multicast-capable UDP socket
 pub struct MulticastSocket {
     socket: TokioUdpSocket,
     port: u16,
     joined_groups: HashSet<Ipv4Addr>,
-    interface: Ipv4Addr,
+    interface: MulticastInterface,
     /// The multicast group this socket is bound to (for filtering)
     bound_group: Option<Ipv4Addr>,
+    /// Whether the kernel reports each datagram's TTL
+    recv_ttl: bool,
+    /// Whether the kernel reports each datagram's TOS byte
+    recv_tos: bool,
+    /// Whether the kernel timestamps each datagram
+    kernel_timestamps: bool,
 }
 
 impl MulticastSocket {
     /// Create a new multicast socket bound to the specified port
     pub async fn new(port: u16) -> Result<Self, MulticastError> {
-        Self::with_interface(port, Ipv4Addr::UNSPECIFIED).await
+        Self::with_interface(port, MulticastInterface::default()).await
     }
 
     /// Create a new multicast socket bound to a specific interface
     #[allow(clippy::unused_async)] // Async for API consistency with future enhancements
-    pub async fn with_interface(port: u16, interface: Ipv4Addr) -> Result<Self, MulticastError> {
-        // Create socket with socket2 for fine-grained control
-        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
-
-        // Allow multiple processes to bind to same port
-        socket.set_reuse_address(true)?;
-        #[cfg(unix)]
-        socket.set_reuse_port(true)?;
-
-        // Set non-blocking before converting
-        socket.set_nonblocking(true)?;
+    pub async fn with_interface(port: u16, interface: MulticastInterface) -> Result<Self, MulticastError> {
+        let (socket, ancillary) = receive_socket()?;
 
         // Bind to the port on all interfaces
         let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port);
         socket.bind(&addr.into())?;
+        // Port 0 takes whatever the system picks
+        let port = socket.local_addr()?.as_socket_ipv4().map_or(port, |addr| addr.port());
 
         // Convert to std socket, then to tokio
         let std_socket: UdpSocket = socket.into();
@@ -68,40 +182,30 @@ impl MulticastSocket {
             joined_groups: HashSet::new(),
             interface,
             bound_group: None,
+            recv_ttl: ancillary.ttl,
+            recv_tos: ancillary.tos,
+            kernel_timestamps: ancillary.timestamps,
         })
     }
 
-    /// Create a new multicast socket bound to a specific multicast group address.
-    /// This ensures the socket only receives packets destined for this specific group,
-    /// even when multiple sockets share the same port with SO_REUSEPORT.
+    /// Create a new multicast socket that only receives packets sent to `group`,
+    /// even when other sockets share the same port. See the module docs for
+    /// how this differs between Unix and Windows.
     #[allow(clippy::unused_async)]
-    pub async fn bound_to_group(group: Ipv4Addr, port: u16, interface: Ipv4Addr) -> Result<Self, MulticastError> {
+    pub async fn bound_to_group(group: Ipv4Addr, port: u16, interface: MulticastInterface) -> Result<Self, MulticastError> {
         if !group.is_multicast() {
             return Err(MulticastError::NotMulticast(group));
         }
 
-        // Create socket with socket2 for fine-grained control
-        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
-
-        // Allow multiple processes to bind to same port
-        socket.set_reuse_address(true)?;
-        #[cfg(unix)]
-        socket.set_reuse_port(true)?;
-
-        // Set non-blocking before converting
-        socket.set_nonblocking(true)?;
-
-        // Bind to the multicast group address directly.
-        // On Linux, this ensures the socket only receives packets destined for this group.
-        let addr = SocketAddrV4::new(group, port);
-        socket.bind(&addr.into())?;
+        let (socket, ancillary) = receive_socket()?;
+        socket.bind(&group_bind_address(group, port).into())?;
 
         // Convert to std socket, then to tokio
         let std_socket: UdpSocket = socket.into();
         let tokio_socket = TokioUdpSocket::from_std(std_socket)?;
 
         // Join the multicast group
-        tokio_socket.join_multicast_v4(group, interface)?;
+        set_membership(&tokio_socket, group, interface, true)?;
 
         let mut joined_groups = HashSet::new();
         joined_groups.insert(group);
@@ -112,6 +216,9 @@ impl MulticastSocket {
             joined_groups,
             interface,
             bound_group: Some(group),
+            recv_ttl: ancillary.ttl,
+            recv_tos: ancillary.tos,
+            kernel_timestamps: ancillary.timestamps,
         })
     }
 
@@ -130,7 +237,7 @@ impl MulticastSocket {
             return Err(MulticastError::AlreadyJoined(group));
         }
 
-        self.socket.join_multicast_v4(group, self.interface)?;
+        set_membership(&self.socket, group, self.interface, true)?;
         self.joined_groups.insert(group);
 
         Ok(())
@@ -142,7 +249,7 @@ impl MulticastSocket {
             return Err(MulticastError::NotMember(group));
         }
 
-        self.socket.leave_multicast_v4(group, self.interface)?;
+        set_membership(&self.socket, group, self.interface, false)?;
         self.joined_groups.remove(&group);
 
         Ok(())
@@ -152,17 +259,189 @@ impl MulticastSocket {
     pub fn leave_all(&mut self) -> Result<(), MulticastError> {
         let groups: Vec<Ipv4Addr> = self.joined_groups.iter().copied().collect();
         for group in groups {
-            self.socket.leave_multicast_v4(group, self.interface)?;
+            set_membership(&self.socket, group, self.interface, false)?;
         }
         self.joined_groups.clear();
         Ok(())
     }
 
+    /// Re-issue the join for every group in `joined_groups` and return how
+    /// many the kernel had dropped. An interface going down loses its
+    /// memberships without telling the socket; a join that's still in place
+    /// fails with `EADDRINUSE` and is left alone. Any other failure, such as
+    /// no route while the interface is down, is returned.
+    pub fn rejoin(&self) -> Result<usize, io::Error> {
+        let mut restored = 0;
+        for &group in &self.joined_groups {
+            match set_membership(&self.socket, group, self.interface, true) {
+                Ok(()) => restored += 1,
+                Err(e) if e.kind() == io::ErrorKind::AddrInUse => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(restored)
+    }
+
+    /// A new socket on the same port and interface, joined to the same
+    /// groups, to replace this one once an interface is back
+    pub async fn reopen(&self) -> Result<Self, MulticastError> {
+        let mut socket = match self.bound_group {
+            Some(group) => Self::bound_to_group(group, self.port, self.interface).await?,
+            None => Self::with_interface(self.port, self.interface).await?,
+        };
+        for &group in &self.joined_groups {
+            if !socket.is_member(group) {
+                socket.join(group)?;
+            }
+        }
+        Ok(socket)
+    }
+
     /// Receive a packet
     pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), io::Error> {
         self.socket.recv_from(buf).await
     }
 
+    /// Receive a packet with its TTL and arrival time. The arrival time is
+    /// the kernel's where the platform reports it, so time the datagram sat
+    /// in the socket buffer waiting for this task doesn't count as jitter.
+    pub async fn recv_datagram(&self, buf: &mut [u8]) -> Result<Datagram, io::Error> {
+        #[cfg(unix)]
+        if self.recv_ttl || self.recv_tos || self.kernel_timestamps {
+            use std::os::fd::AsRawFd;
+            let fd = self.socket.as_raw_fd();
+            let (len, source, ancillary) = self
+                .socket
+                .async_io(tokio::io::Interest::READABLE, || super::ancillary::recv(fd, buf))
+                .await?;
+            return Ok(Datagram::new(len, source, ancillary.ttl, ancillary.timestamp).with_dscp(ancillary.dscp));
+        }
+        let (len, source) = self.socket.recv_from(buf).await?;
+        Ok(Datagram::new(len, source, None, None))
+    }
+
+    /// Receive a datagram that is already waiting, as `recv_datagram` does,
+    /// without waiting for one; `None` if the socket is empty
+    pub fn try_recv_datagram(&self, buf: &mut [u8]) -> Result<Option<Datagram>, io::Error> {
+        match self.try_recv(buf) {
+            Ok(datagram) => Ok(Some(datagram)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn try_recv(&self, buf: &mut [u8]) -> Result<Datagram, io::Error> {
+        #[cfg(unix)]
+        if self.recv_ttl || self.recv_tos || self.kernel_timestamps {
+            use std::os::fd::AsRawFd;
+            let fd = self.socket.as_raw_fd();
+            let (len, source, ancillary) = self
+                .socket
+                .try_io(tokio::io::Interest::READABLE, || super::ancillary::recv(fd, buf))?;
+            return Ok(Datagram::new(len, source, ancillary.ttl, ancillary.timestamp).with_dscp(ancillary.dscp));
+        }
+        let (len, source) = self.socket.try_recv_from(buf)?;
+        Ok(Datagram::new(len, source, None, None))
+    }
+
+    /// Wait for datagrams, then read as many as are waiting, up to one per
+    /// slot. Returns how many slots were filled, from the first.
+    pub async fn recv_batch(&self, slots: &mut [BufSlot]) -> Result<usize, io::Error> {
+        if slots.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            self.socket.readable().await?;
+            // Readiness can be stale; an empty read clears it and we wait again
+            let count = self.try_recv_batch(slots)?;
+            if count > 0 {
+                return Ok(count);
+            }
+        }
+    }
+
+    /// Read the datagrams already waiting, up to one per slot, without
+    /// waiting; 0 if the socket is empty
+    pub fn try_recv_batch(&self, slots: &mut [BufSlot]) -> Result<usize, io::Error> {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd;
+            let fd = self.socket.as_raw_fd();
+            let received = match self
+                .socket
+                .try_io(tokio::io::Interest::READABLE, || super::ancillary::recv_batch(fd, slots))
+            {
+                Ok(received) => received,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(0),
+                Err(e) => return Err(e),
+            };
+            let (now, wall_now) = (Instant::now(), SystemTime::now());
+            for (slot, (len, source, ancillary)) in slots.iter_mut().zip(&received) {
+                let datagram = Datagram::read_at(*len, *source, ancillary.ttl, ancillary.timestamp, now, wall_now);
+                slot.datagram = Some(datagram.with_dscp(ancillary.dscp));
+            }
+            Ok(received.len())
+        }
+        #[cfg(not(target_os = "linux"))]
+        self.try_recv_each(slots)
+    }
+
+    /// `try_recv_batch` a datagram at a time, where there's no `recvmmsg`
+    #[cfg(any(test, not(target_os = "linux")))]
+    fn try_recv_each(&self, slots: &mut [BufSlot]) -> Result<usize, io::Error> {
+        let mut count = 0;
+        for slot in slots.iter_mut() {
+            match self.try_recv_datagram(&mut slot.buf) {
+                Ok(Some(datagram)) => slot.datagram = Some(datagram),
+                Ok(None) => break,
+                // Keep what was read; a lasting error shows up on the next call
+                Err(_) if count > 0 => break,
+                Err(e) => return Err(e),
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Poll for a datagram to read, so one task can wait on several sockets
+    pub fn poll_recv_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.socket.poll_recv_ready(cx)
+    }
+
+    /// Apply `--busy-poll-us`, if given. Failing is only logged, once per
+    /// run: it's a tuning hint, and every socket would fail the same way.
+    pub fn apply_busy_poll(&self, micros: Option<u32>) {
+        static WARNED: AtomicBool = AtomicBool::new(false);
+        let Some(micros) = micros else {
+            return;
+        };
+        if let Err(e) = self.set_busy_poll(micros) {
+            if !WARNED.swap(true, Ordering::Relaxed) {
+                tracing::warn!("--busy-poll-us: can't set SO_BUSY_POLL ({}); sockets will sleep as usual", e);
+            }
+        }
+    }
+
+    /// Whether received datagrams carry the kernel's receive time
+    pub fn kernel_timestamps(&self) -> bool {
+        self.kernel_timestamps
+    }
+
+    /// Busy-poll the device queue for up to `micros` before a read sleeps
+    /// (`SO_BUSY_POLL`). Linux only; raising it needs `CAP_NET_ADMIN`.
+    pub fn set_busy_poll(&self, micros: u32) -> Result<(), io::Error> {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd;
+            super::ancillary::set_busy_poll(self.socket.as_raw_fd(), micros)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = micros;
+            Err(io::Error::new(io::ErrorKind::Unsupported, "SO_BUSY_POLL is Linux only"))
+        }
+    }
+
     /// Send a packet to a multicast address
     pub async fn send_to(&self, buf: &[u8], addr: SocketAddr) -> Result<usize, io::Error> {
         self.socket.send_to(buf, addr).await
@@ -192,6 +471,175 @@ impl MulticastSocket {
     pub fn is_member(&self, group: Ipv4Addr) -> bool {
         self.joined_groups.contains(&group)
     }
+
+    /// Group and port a received packet was sent to, as far as the socket can
+    /// tell: the bound group, or the only joined one. With several groups on
+    /// one port the lowest is reported.
+    pub fn destination(&self) -> SocketAddrV4 {
+        let group = self
+            .bound_group
+            .or_else(|| self.joined_groups.iter().min().copied())
+            .unwrap_or(Ipv4Addr::UNSPECIFIED);
+        SocketAddrV4::new(group, self.port)
+    }
+}
+
+impl Datagram {
+    /// Place a datagram in time, from the kernel's receive time if there is
+    /// a believable one and otherwise from now
+    fn new(len: usize, source: SocketAddr, ttl: Option<u8>, kernel_time: Option<SystemTime>) -> Self {
+        Self::read_at(len, source, ttl, kernel_time, Instant::now(), SystemTime::now())
+    }
+
+    /// As `new`, for a datagram read at `now` (`wall_now` on the wall clock)
+    fn read_at(
+        len: usize,
+        source: SocketAddr,
+        ttl: Option<u8>,
+        kernel_time: Option<SystemTime>,
+        now: Instant,
+        wall_now: SystemTime,
+    ) -> Self {
+        let kernel = kernel_time.and_then(|time| {
+            let age = wall_now.duration_since(time).ok().filter(|age| *age <= MAX_TIMESTAMP_AGE)?;
+            Some((now.checked_sub(age)?, time))
+        });
+        let (received_at, wall_time) = kernel.unwrap_or((now, wall_now));
+        Self {
+            len,
+            source,
+            ttl,
+            dscp: None,
+            received_at,
+            wall_time,
+            kernel_timestamp: kernel.is_some(),
+        }
+    }
+
+    fn with_dscp(self, dscp: Option<Dscp>) -> Self {
+        Self { dscp, ..self }
+    }
+}
+
+/// Which ancillary data a receive socket was able to turn on
+#[derive(Debug, Clone, Copy)]
+struct AncillarySupport {
+    ttl: bool,
+    tos: bool,
+    timestamps: bool,
+}
+
+/// Non-blocking UDP socket set up for receiving, not yet bound. Also returns
+/// which ancillary data will be reported.
+fn receive_socket() -> Result<(Socket, AncillarySupport), io::Error> {
+    // Create socket with socket2 for fine-grained control
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+
+    // Allow multiple processes to bind to same port
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+
+    // Windows applies loopback on the receiving socket; keep our own transmissions visible
+    #[cfg(windows)]
+    socket.set_multicast_loop_v4(true)?;
+
+    // Set non-blocking before converting
+    socket.set_nonblocking(true)?;
+    let ancillary = enable_ancillary(&socket);
+    Ok((socket, ancillary))
+}
+
+/// Address a group socket binds to: the group itself where the kernel
+/// filters on it, every address on Windows, which won't bind a group
+fn group_bind_address(group: Ipv4Addr, port: u16) -> SocketAddrV4 {
+    if cfg!(windows) {
+        SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)
+    } else {
+        SocketAddrV4::new(group, port)
+    }
+}
+
+/// Join or leave `group` on `interface`
+fn set_membership(
+    socket: &TokioUdpSocket,
+    group: Ipv4Addr,
+    interface: MulticastInterface,
+    join: bool,
+) -> Result<(), io::Error> {
+    match (interface, join) {
+        (MulticastInterface::Address(address), true) => socket.join_multicast_v4(group, address),
+        (MulticastInterface::Address(address), false) => socket.leave_multicast_v4(group, address),
+        (MulticastInterface::Index(index), join) => membership_by_index(socket2::SockRef::from(socket), group, index, join),
+    }
+}
+
+/// Join `group` on `interface` with a plain blocking socket, for one-off
+/// checks and watches outside the receive loops
+pub fn join_group<'s>(socket: impl Into<socket2::SockRef<'s>>, group: Ipv4Addr, interface: MulticastInterface) -> Result<(), io::Error> {
+    let socket = socket.into();
+    match interface {
+        MulticastInterface::Address(address) => socket.join_multicast_v4(&group, &address),
+        MulticastInterface::Index(index) => membership_by_index(socket, group, index, true),
+    }
+}
+
+#[cfg(any(
+    windows,
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+))]
+fn membership_by_index(socket: socket2::SockRef<'_>, group: Ipv4Addr, index: u32, join: bool) -> Result<(), io::Error> {
+    let interface = socket2::InterfaceIndexOrAddress::Index(index);
+    if join {
+        socket.join_multicast_v4_n(&group, &interface)
+    } else {
+        socket.leave_multicast_v4_n(&group, &interface)
+    }
+}
+
+#[cfg(not(any(
+    windows,
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+)))]
+fn membership_by_index(_socket: socket2::SockRef<'_>, _group: Ipv4Addr, _index: u32, _join: bool) -> Result<(), io::Error> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "joining by interface index is not supported on this platform; use the interface's address",
+    ))
+}
+
+/// Turn on TTL, TOS and timestamp reporting; where they aren't supported the
+/// TTL and DSCP are unknown and packets are timed when they are read
+fn enable_ancillary(socket: &Socket) -> AncillarySupport {
+    let mut support = AncillarySupport { ttl: false, tos: false, timestamps: false };
+    #[cfg(unix)]
+    {
+        use std::os::fd::AsRawFd;
+        let fd = socket.as_raw_fd();
+        match super::ancillary::enable_ttl(fd) {
+            Ok(()) => support.ttl = true,
+            Err(e) => tracing::debug!("IP_RECVTTL unavailable, received TTL will be unknown: {}", e),
+        }
+        match super::ancillary::enable_tos(fd) {
+            Ok(()) => support.tos = true,
+            Err(e) => tracing::debug!("IP_RECVTOS unavailable, received DSCP will be unknown: {}", e),
+        }
+        match super::ancillary::enable_timestamps(fd) {
+            Ok(()) => support.timestamps = true,
+            Err(e) => tracing::debug!("Kernel receive timestamps unavailable, timing packets when read: {}", e),
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = socket;
+    support
 }
 
 /// A pool of multicast sockets, one per port
@@ -261,9 +709,13 @@ impl Default for MulticastSocketPool {
     }
 }
 
-/// Create a transmit-only multicast socket
-pub async fn create_transmit_socket(ttl: u8) -> Result<TokioUdpSocket, io::Error> {
+/// Create a transmit-only multicast socket, marking its packets with `dscp`
+/// if given (otherwise the system default, normally 0)
+pub async fn create_transmit_socket(ttl: u8, dscp: Option<Dscp>) -> Result<TokioUdpSocket, io::Error> {
     let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    if let Some(dscp) = dscp {
+        socket.set_tos(u32::from(dscp.tos()))?;
+    }
 
     // Bind to any available port
     let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
@@ -275,20 +727,34 @@ pub async fn create_transmit_socket(ttl: u8) -> Result<TokioUdpSocket, io::Error
     let tokio_socket = TokioUdpSocket::from_std(std_socket)?;
 
     tokio_socket.set_multicast_ttl_v4(ttl as u32)?;
-    // Enable loopback so we can monitor our own transmissions on the same machine
+    // Enable loopback so we can monitor our own transmissions on the same machine.
+    // Windows ignores this on the sender; receive sockets enable it there.
     tokio_socket.set_multicast_loop_v4(true)?;
 
     Ok(tokio_socket)
 }
 
+/// Local address the kernel would send from to reach `dest`.
+///
+/// Connecting a UDP socket sends nothing but makes the kernel pick a route,
+/// so this fails the same way a real send would when there is none.
+pub fn resolve_source_address(dest: SocketAddrV4) -> Result<Ipv4Addr, io::Error> {
+    let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.connect(dest)?;
+    match socket.local_addr()? {
+        SocketAddr::V4(local) => Ok(*local.ip()),
+        SocketAddr::V6(local) => Err(io::Error::other(format!("unexpected IPv6 route via {}", local))),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[tokio::test]
     async fn test_create_socket() {
-        let socket = MulticastSocket::new(0).await;
-        assert!(socket.is_ok());
+        let socket = MulticastSocket::new(0).await.unwrap();
+        assert_ne!(socket.port(), 0, "the port the system picked");
     }
 
     #[tokio::test]
@@ -310,6 +776,288 @@ mod tests {
         assert!(matches!(result, Err(MulticastError::NotMulticast(_))));
     }
 
+    #[tokio::test]
+    async fn test_recv_datagram() {
+        let receiver = MulticastSocket::new(0).await.unwrap();
+        let port = receiver.socket.local_addr().unwrap().port();
+        let sender = create_transmit_socket(32, None).await.unwrap();
+        sender.set_ttl(17).unwrap();
+        let sent = SystemTime::now();
+        sender.send_to(b"ttl", SocketAddrV4::new(Ipv4Addr::LOCALHOST, port)).await.unwrap();
+
+        let mut buf = [0u8; 16];
+        let datagram = receiver.recv_datagram(&mut buf).await.unwrap();
+        assert_eq!(&buf[..datagram.len], b"ttl");
+        assert!(datagram.source.ip().is_loopback());
+        assert!(datagram.received_at <= Instant::now());
+        if cfg!(unix) {
+            assert_eq!(datagram.ttl, Some(17));
+            assert!(datagram.kernel_timestamp);
+            let delay = datagram.wall_time.duration_since(sent).unwrap_or_default();
+            assert!(delay < Duration::from_secs(1), "{:?}", delay);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recv_dscp() {
+        let receiver = MulticastSocket::new(0).await.unwrap();
+        let port = receiver.socket.local_addr().unwrap().port();
+        let marked = create_transmit_socket(1, Dscp::new(46)).await.unwrap();
+        let unmarked = create_transmit_socket(1, None).await.unwrap();
+        let dest = SocketAddrV4::new(Ipv4Addr::LOCALHOST, port);
+        marked.send_to(b"ef", dest).await.unwrap();
+        unmarked.send_to(b"be", dest).await.unwrap();
+
+        let mut buf = [0u8; 16];
+        let first = receiver.recv_datagram(&mut buf).await.unwrap();
+        let second = receiver.recv_datagram(&mut buf).await.unwrap();
+        if cfg!(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd")) {
+            assert_eq!(first.dscp, Dscp::new(46));
+            assert_eq!(second.dscp.map(Dscp::value), Some(0));
+        }
+    }
+
+    /// Queue `count` numbered datagrams on `receiver` from a TTL 17 sender
+    async fn queue_datagrams(receiver: &MulticastSocket, count: u32) {
+        let port = receiver.socket.local_addr().unwrap().port();
+        let sender = create_transmit_socket(32, None).await.unwrap();
+        sender.set_ttl(17).unwrap();
+        for i in 0..count {
+            sender.send_to(&i.to_be_bytes(), SocketAddrV4::new(Ipv4Addr::LOCALHOST, port)).await.unwrap();
+        }
+        // Loopback delivery is synchronous, but give the readiness a moment
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    #[tokio::test]
+    async fn test_recv_batch() {
+        let receiver = MulticastSocket::new(0).await.unwrap();
+        let mut slots = BufSlot::batch(16, 64);
+        assert_eq!(receiver.try_recv_batch(&mut slots).unwrap(), 0);
+
+        queue_datagrams(&receiver, 10).await;
+        let count = receiver.recv_batch(&mut slots).await.unwrap();
+        assert_eq!(count, 10);
+        for (i, slot) in slots[..count].iter().enumerate() {
+            let (datagram, data) = slot.received().unwrap();
+            assert_eq!(data, (i as u32).to_be_bytes());
+            assert!(datagram.source.ip().is_loopback());
+            if cfg!(unix) {
+                // Each datagram keeps its own ancillary data
+                assert_eq!(datagram.ttl, Some(17));
+                assert!(datagram.kernel_timestamp);
+            }
+        }
+        assert_eq!(receiver.try_recv_batch(&mut slots).unwrap(), 0);
+
+        // Fewer slots than datagrams: the rest wait for the next batch
+        queue_datagrams(&receiver, 10).await;
+        assert_eq!(receiver.try_recv_batch(&mut slots[..4]).unwrap(), 4);
+        assert_eq!(receiver.try_recv_batch(&mut slots).unwrap(), 6);
+        assert_eq!(slots[0].received().unwrap().1, 4u32.to_be_bytes());
+    }
+
+    /// A large backlog from two senders comes out whole and in order, each
+    /// datagram with its own source, both in batches and one at a time
+    #[tokio::test]
+    async fn test_recv_batch_backlog() {
+        const BACKLOG: u32 = 1024;
+        let receiver = MulticastSocket::new(0).await.unwrap();
+        socket2::SockRef::from(&receiver.socket).set_recv_buffer_size(4 << 20).unwrap();
+        let dest = SocketAddrV4::new(Ipv4Addr::LOCALHOST, receiver.socket.local_addr().unwrap().port());
+        let senders = [
+            create_transmit_socket(32, None).await.unwrap(),
+            create_transmit_socket(32, None).await.unwrap(),
+        ];
+        let ports = senders.each_ref().map(|sender| sender.local_addr().unwrap().port());
+        let mut slots = BufSlot::batch(64, 64);
+
+        for batched in [true, false] {
+            for i in 0..BACKLOG {
+                senders[i as usize % 2].send_to(&i.to_be_bytes(), dest).await.unwrap();
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+
+            let mut next = 0u32;
+            loop {
+                let count = if batched { receiver.try_recv_batch(&mut slots) } else { receiver.try_recv_each(&mut slots) };
+                let count = count.unwrap();
+                if count == 0 {
+                    break;
+                }
+                for slot in &slots[..count] {
+                    let (datagram, data) = slot.received().unwrap();
+                    assert_eq!(data, next.to_be_bytes(), "batched: {}", batched);
+                    assert_eq!(datagram.source.port(), ports[next as usize % 2], "batched: {}", batched);
+                    next += 1;
+                }
+            }
+            assert_eq!(next, BACKLOG, "batched: {}", batched);
+        }
+    }
+
+    /// Reading in batches gets through a backlog faster than reading it a
+    /// datagram at a time, which is the fallback away from Linux. A timing
+    /// comparison, so it only runs when asked for:
+    /// `cargo test batch_throughput -- --ignored`
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    #[ignore = "compares wall-clock times; run explicitly on a quiet machine"]
+    async fn test_batch_throughput() {
+        const BACKLOG: u32 = 1024;
+        let receiver = MulticastSocket::new(0).await.unwrap();
+        socket2::SockRef::from(&receiver.socket).set_recv_buffer_size(4 << 20).unwrap();
+        let mut slots = BufSlot::batch(64, 64);
+
+        async fn drain(receiver: &MulticastSocket, slots: &mut [BufSlot], batched: bool) -> Duration {
+            queue_datagrams(receiver, BACKLOG).await;
+            let (started, mut read) = (Instant::now(), 0);
+            loop {
+                let count = if batched { receiver.try_recv_batch(slots) } else { receiver.try_recv_each(slots) };
+                match count.unwrap() {
+                    0 => break,
+                    count => read += count,
+                }
+            }
+            assert_eq!(read, BACKLOG as usize);
+            started.elapsed()
+        }
+
+        // Best of many, so a busy machine (or the rest of the test suite)
+        // doesn't decide it
+        let (mut each, mut batched) = (Duration::MAX, Duration::MAX);
+        for _ in 0..20 {
+            each = each.min(drain(&receiver, &mut slots, false).await);
+            batched = batched.min(drain(&receiver, &mut slots, true).await);
+        }
+        let rate = |elapsed: Duration| BACKLOG as f64 / elapsed.as_secs_f64();
+        assert!(
+            batched < each,
+            "batched {:.0} packets/s, one at a time {:.0} packets/s",
+            rate(batched),
+            rate(each)
+        );
+    }
+
+    /// The kernel dropping memberships behind the socket's back, as when an
+    /// interface bounces, stops delivery until `rejoin` restores it
+    #[tokio::test]
+    async fn test_rejoin_restores_dropped_membership() {
+        async fn receive(socket: &MulticastSocket, buf: &mut [u8]) -> Option<usize> {
+            let datagram = tokio::time::timeout(Duration::from_millis(300), socket.recv_datagram(buf)).await;
+            datagram.ok().and_then(Result::ok).map(|datagram| datagram.len)
+        }
+
+        let group = Ipv4Addr::new(239, 255, 77, 1);
+        let port = 15_902;
+        let mut receiver = MulticastSocket::new(port).await.unwrap();
+        receiver.join(group).unwrap();
+        receiver.set_multicast_loop(true).unwrap();
+        let sender = create_transmit_socket(1, None).await.unwrap();
+        let dest = SocketAddrV4::new(group, port);
+        let mut buf = [0u8; 16];
+
+        // Nothing was lost, so nothing is re-joined
+        assert_eq!(receiver.rejoin().unwrap(), 0);
+        sender.send_to(b"before", dest).await.unwrap();
+        assert_eq!(receive(&receiver, &mut buf).await, Some(6));
+
+        set_membership(&receiver.socket, group, receiver.interface, false).unwrap();
+        assert!(receiver.is_member(group));
+        sender.send_to(b"lost", dest).await.unwrap();
+        assert_eq!(receive(&receiver, &mut buf).await, None);
+
+        assert_eq!(receiver.rejoin().unwrap(), 1);
+        assert_eq!(receiver.rejoin().unwrap(), 0);
+        sender.send_to(b"after", dest).await.unwrap();
+        assert_eq!(receive(&receiver, &mut buf).await, Some(5));
+        assert_eq!(&buf[..5], b"after");
+    }
+
+    #[test]
+    fn test_implausible_kernel_time_is_ignored() {
+        let source = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 5004));
+        let queued = Datagram::new(4, source, None, Some(SystemTime::now() - Duration::from_millis(30)));
+        assert!(queued.kernel_timestamp);
+        assert!(queued.received_at.elapsed() >= Duration::from_millis(30));
+
+        // The wall clock stepped since
+        for kernel_time in [SystemTime::now() + Duration::from_mins(1), SystemTime::now() - Duration::from_hours(1)] {
+            let stepped = Datagram::new(4, source, None, Some(kernel_time));
+            assert!(!stepped.kernel_timestamp);
+            assert!(stepped.received_at.elapsed() < Duration::from_secs(1));
+        }
+    }
+
+    /// A reader that only gets to the socket every 23ms sees packets sent
+    /// every 5ms arrive in clumps. Timing them when read makes the gaps
+    /// swing between 0 and 23ms; the kernel's times keep the real spacing.
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_kernel_timestamps_reduce_jitter_spread() {
+        use crate::utils::streaming::RunningStats;
+
+        const PACKETS: usize = 40;
+        let receiver = MulticastSocket::new(0).await.unwrap();
+        assert!(receiver.kernel_timestamps());
+        let port = receiver.socket.local_addr().unwrap().port();
+        let sender = std::thread::spawn(move || {
+            let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)).unwrap();
+            for i in 0..PACKETS {
+                socket.send_to(&[i as u8], SocketAddrV4::new(Ipv4Addr::LOCALHOST, port)).unwrap();
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        });
+
+        let mut kernel = Vec::new();
+        let mut read = Vec::new();
+        let mut buf = [0u8; 16];
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while kernel.len() < PACKETS && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(23)).await;
+            while let Ok(Ok(datagram)) =
+                tokio::time::timeout(Duration::from_millis(1), receiver.recv_datagram(&mut buf)).await
+            {
+                kernel.push(datagram.received_at);
+                read.push(Instant::now());
+            }
+        }
+        sender.join().unwrap();
+        assert_eq!(kernel.len(), PACKETS);
+
+        let spread = |times: &[Instant]| {
+            let mut gaps = RunningStats::default();
+            for pair in times.windows(2) {
+                gaps.push((pair[1] - pair[0]).as_secs_f64() * 1000.0);
+            }
+            gaps.std_dev()
+        };
+        let (kernel_spread, read_spread) = (spread(&kernel), spread(&read));
+        assert!(
+            kernel_spread * 3.0 < read_spread,
+            "gap std dev: kernel {:.2}ms, when read {:.2}ms",
+            kernel_spread,
+            read_spread
+        );
+    }
+
+    #[test]
+    fn test_resolve_source_address() {
+        let source = resolve_source_address(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 5004)).unwrap();
+        assert!(source.is_loopback());
+    }
+
+    #[test]
+    fn test_interface_from_str() {
+        assert_eq!(
+            "192.168.12.65".parse::<MulticastInterface>(),
+            Ok(MulticastInterface::Address(Ipv4Addr::new(192, 168, 12, 65)))
+        );
+        assert_eq!(" 12 ".parse::<MulticastInterface>(), Ok(MulticastInterface::Index(12)));
+        assert!("eth0".parse::<MulticastInterface>().is_err());
+        assert_eq!(MulticastInterface::default(), MulticastInterface::Address(Ipv4Addr::UNSPECIFIED));
+    }
+
     #[tokio::test]
     async fn test_socket_pool() {
         let mut pool = MulticastSocketPool::new();
@@ -319,4 +1067,61 @@ mod tests {
         assert!(pool.get(5004).is_some());
         assert!(pool.get(5004).unwrap().is_member(group));
     }
+
+    /// Windows-specific socket behaviour: group sockets bind the wildcard
+    /// address and rely on their own membership to see only their group
+    #[cfg(windows)]
+    mod windows {
+        use super::*;
+        use std::time::Duration;
+        use tokio::time::timeout;
+
+        async fn recv(socket: &MulticastSocket) -> Option<Vec<u8>> {
+            let mut buf = [0u8; 64];
+            let received = timeout(Duration::from_millis(500), socket.recv_from(&mut buf)).await;
+            received.ok().map(|r| buf[..r.unwrap().0].to_vec())
+        }
+
+        #[tokio::test]
+        async fn test_group_socket_binds_wildcard() {
+            let group = Ipv4Addr::new(239, 255, 73, 1);
+            let socket = MulticastSocket::bound_to_group(group, 0, MulticastInterface::default()).await.unwrap();
+            let local = socket.socket.local_addr().unwrap();
+            assert_eq!(local.ip(), Ipv4Addr::UNSPECIFIED);
+            assert_eq!(socket.bound_group(), Some(group));
+            assert!(socket.is_member(group));
+        }
+
+        #[tokio::test]
+        async fn test_group_sockets_sharing_a_port_see_only_their_group() {
+            let first = Ipv4Addr::new(239, 255, 73, 2);
+            let second = Ipv4Addr::new(239, 255, 73, 3);
+            let a = MulticastSocket::bound_to_group(first, 0, MulticastInterface::default()).await.unwrap();
+            let port = a.socket.local_addr().unwrap().port();
+            let b = MulticastSocket::bound_to_group(second, port, MulticastInterface::default()).await.unwrap();
+
+            let sender = create_transmit_socket(1, None).await.unwrap();
+            sender.send_to(b"first", SocketAddrV4::new(first, port)).await.unwrap();
+            sender.send_to(b"second", SocketAddrV4::new(second, port)).await.unwrap();
+
+            assert_eq!(recv(&a).await.as_deref(), Some(&b"first"[..]));
+            assert_eq!(recv(&b).await.as_deref(), Some(&b"second"[..]));
+            assert_eq!(recv(&a).await, None, "first socket saw the other group");
+        }
+
+        #[tokio::test]
+        async fn test_join_by_interface_index() {
+            let group = Ipv4Addr::new(239, 255, 73, 4);
+            let mut socket = MulticastSocket::with_interface(0, MulticastInterface::Index(0)).await.unwrap();
+            socket.join(group).unwrap();
+            socket.leave(group).unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_recv_ttl_is_unknown() {
+            let receiver = MulticastSocket::new(0).await.unwrap();
+            assert!(!receiver.recv_ttl);
+            assert!(!receiver.kernel_timestamps());
+        }
+    }
 }
@@ -0,0 +1,251 @@
+//! Streaming pcap capture of received datagrams.
+//!
+//! The monitors only see UDP payloads, so each packet is written with
+//! fabricated Ethernet, IPv4 and UDP headers: the true source address and
+//! port, the joined group and local port as destination, the arriving TTL
+//! where the platform reports it, and the receive time. The result opens in
+//! Wireshark like a capture taken on the wire.
+//! Packets are written as they arrive and flushed about once a second.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::warn;
+
+/// Classic pcap magic, microsecond timestamps
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+/// Largest frame stored in full
+const SNAPLEN: u32 = 65_535;
+/// `LINKTYPE_ETHERNET`
+const LINKTYPE_ETHERNET: u32 = 1;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+
+/// Locally administered MAC used as the source of every fabricated frame
+const SOURCE_MAC: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+
+/// IP TTL written when the received one is unknown
+const DEFAULT_TTL: u8 = 64;
+
+/// How long written packets may sit in the buffer
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Writes received datagrams to a pcap file as they arrive
+pub struct PcapWriter {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    ip_id: u16,
+    packets: u64,
+    last_flush: Instant,
+}
+
+impl PcapWriter {
+    /// Create the file and write the pcap header
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&PCAP_MAGIC.to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?; // version 2.4
+        writer.write_all(&4u16.to_le_bytes())?;
+        writer.write_all(&0i32.to_le_bytes())?; // GMT offset
+        writer.write_all(&0u32.to_le_bytes())?; // timestamp accuracy
+        writer.write_all(&SNAPLEN.to_le_bytes())?;
+        writer.write_all(&LINKTYPE_ETHERNET.to_le_bytes())?;
+        writer.flush()?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: Some(writer),
+            ip_id: 0,
+            packets: 0,
+            last_flush: Instant::now(),
+        })
+    }
+
+    /// Record one received datagram. A write error stops the capture with a
+    /// warning; monitoring carries on without it.
+    pub fn write_packet(
+        &mut self,
+        received_at: SystemTime,
+        source: SocketAddr,
+        destination: SocketAddrV4,
+        ttl: Option<u8>,
+        payload: &[u8],
+    ) {
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+        let frame = build_frame(source, destination, ttl, self.ip_id, payload);
+        self.ip_id = self.ip_id.wrapping_add(1);
+
+        let result = write_record(writer, received_at, &frame).and_then(|()| {
+            if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+                self.last_flush = Instant::now();
+                writer.flush()
+            } else {
+                Ok(())
+            }
+        });
+        match result {
+            Ok(()) => self.packets += 1,
+            Err(e) => {
+                warn!("Stopped writing {}: {}", self.path.display(), e);
+                self.writer = None;
+            }
+        }
+    }
+
+    /// Packets written so far
+    pub fn packets(&self) -> u64 {
+        self.packets
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PcapWriter {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(e) = writer.flush() {
+                warn!("Failed to flush {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+fn write_record(writer: &mut impl Write, received_at: SystemTime, frame: &[u8]) -> io::Result<()> {
+    let since_epoch = received_at.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    let captured = frame.len().min(SNAPLEN as usize);
+    writer.write_all(&(since_epoch.as_secs() as u32).to_le_bytes())?;
+    writer.write_all(&since_epoch.subsec_micros().to_le_bytes())?;
+    writer.write_all(&(captured as u32).to_le_bytes())?;
+    writer.write_all(&(frame.len() as u32).to_le_bytes())?;
+    writer.write_all(&frame[..captured])
+}
+
+/// Ethernet + IPv4 + UDP around `payload`
+fn build_frame(source: SocketAddr, destination: SocketAddrV4, ttl: Option<u8>, ip_id: u16, payload: &[u8]) -> Vec<u8> {
+    let (source_ip, source_port) = match source {
+        SocketAddr::V4(v4) => (*v4.ip(), v4.port()),
+        SocketAddr::V6(v6) => (v6.ip().to_ipv4_mapped().unwrap_or(Ipv4Addr::UNSPECIFIED), v6.port()),
+    };
+    let udp_len = (UDP_HEADER_LEN + payload.len()).min(usize::from(u16::MAX)) as u16;
+    let ip_len = (IPV4_HEADER_LEN + usize::from(udp_len)).min(usize::from(u16::MAX)) as u16;
+
+    let mut frame = Vec::with_capacity(ETHERNET_HEADER_LEN + IPV4_HEADER_LEN + UDP_HEADER_LEN + payload.len());
+
+    // Ethernet
+    frame.extend_from_slice(&destination_mac(*destination.ip()));
+    frame.extend_from_slice(&SOURCE_MAC);
+    frame.extend_from_slice(&0x0800u16.to_be_bytes());
+
+    // IPv4
+    let ip_start = frame.len();
+    frame.push(0x45); // version 4, 5-word header
+    frame.push(0);
+    frame.extend_from_slice(&ip_len.to_be_bytes());
+    frame.extend_from_slice(&ip_id.to_be_bytes());
+    frame.extend_from_slice(&0x4000u16.to_be_bytes()); // don't fragment
+    frame.push(ttl.unwrap_or(DEFAULT_TTL));
+    frame.push(17); // UDP
+    frame.extend_from_slice(&[0, 0]);
+    frame.extend_from_slice(&source_ip.octets());
+    frame.extend_from_slice(&destination.ip().octets());
+    let checksum = ipv4_checksum(&frame[ip_start..]);
+    frame[ip_start + 10..ip_start + 12].copy_from_slice(&checksum.to_be_bytes());
+
+    // UDP, without a checksum (optional over IPv4)
+    frame.extend_from_slice(&source_port.to_be_bytes());
+    frame.extend_from_slice(&destination.port().to_be_bytes());
+    frame.extend_from_slice(&udp_len.to_be_bytes());
+    frame.extend_from_slice(&[0, 0]);
+
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Multicast groups map onto 01:00:5e plus the group's low 23 bits
+fn destination_mac(address: Ipv4Addr) -> [u8; 6] {
+    let [_, b, c, d] = address.octets();
+    if address.is_multicast() {
+        [0x01, 0x00, 0x5e, b & 0x7f, c, d]
+    } else {
+        [0x02, 0x00, 0x00, 0x00, 0x00, 0x02]
+    }
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|word| u32::from(u16::from_be_bytes([word[0], word.get(1).copied().unwrap_or(0)])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (seconds, microseconds, original length, captured bytes) of each record
+    fn parse_pcap(bytes: &[u8]) -> Vec<(u32, u32, u32, Vec<u8>)> {
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        assert_eq!(u32_at(0), PCAP_MAGIC);
+        assert_eq!(u32_at(20), LINKTYPE_ETHERNET);
+
+        let mut records = Vec::new();
+        let mut at = 24;
+        while at < bytes.len() {
+            let captured = u32_at(at + 8) as usize;
+            records.push((u32_at(at), u32_at(at + 4), u32_at(at + 12), bytes[at + 16..at + 16 + captured].to_vec()));
+            at += 16 + captured;
+        }
+        assert_eq!(at, bytes.len(), "trailing bytes after the last record");
+        records
+    }
+
+    #[test]
+    fn test_written_packets_parse_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.pcap");
+        let group = SocketAddrV4::new(Ipv4Addr::new(239, 130, 1, 2), 5004);
+        let source: SocketAddr = "10.1.2.3:40000".parse().unwrap();
+        let payloads: [&[u8]; 3] = [b"\x80\x00\x00\x01rtp", b"not rtp at all", &[]];
+        let start = SystemTime::UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456);
+
+        let mut writer = PcapWriter::create(&path).unwrap();
+        for (i, payload) in payloads.iter().enumerate() {
+            writer.write_packet(start + Duration::from_millis(20 * i as u64), source, group, Some(31), payload);
+        }
+        assert_eq!(writer.packets(), 3);
+        drop(writer);
+
+        let records = parse_pcap(&std::fs::read(&path).unwrap());
+        assert_eq!(records.len(), 3);
+        for (i, ((secs, micros, original_len, frame), payload)) in records.iter().zip(payloads).enumerate() {
+            assert_eq!(*secs, 1_700_000_000);
+            assert_eq!(*micros, 123_456 + 20_000 * i as u32);
+            assert_eq!(*original_len as usize, frame.len());
+            assert_eq!(frame.len(), 42 + payload.len());
+
+            assert_eq!(frame[..6], [0x01, 0x00, 0x5e, 0x02, 0x01, 0x02]);
+            let ip = &frame[14..34];
+            assert_eq!(ipv4_checksum(ip), 0, "header checksum should verify");
+            assert_eq!(ip[8], 31);
+            assert_eq!(ip[12..16], [10, 1, 2, 3]);
+            assert_eq!(ip[16..20], [239, 130, 1, 2]);
+            let udp = &frame[34..42];
+            assert_eq!(u16::from_be_bytes([udp[0], udp[1]]), 40000);
+            assert_eq!(u16::from_be_bytes([udp[2], udp[3]]), 5004);
+            assert_eq!(usize::from(u16::from_be_bytes([udp[4], udp[5]])), 8 + payload.len());
+            assert_eq!(&frame[42..], payload);
+        }
+    }
+}
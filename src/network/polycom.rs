@@ -8,9 +8,13 @@
 
 #![allow(dead_code)]
 
+use serde::Serialize;
+use std::fmt;
 use std::net::SocketAddr;
-use std::time::Instant;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use thiserror::Error;
+use schemars::JsonSchema;
 
 // ============================================================================
 // Constants
@@ -52,6 +56,12 @@ pub const CONTROL_PACKET_INTERVAL_MS: u64 = 30;
 /// Delay before sending End packets in milliseconds
 pub const END_DELAY_MS: u64 = 50;
 
+/// Alert spacing above which phones may give up on a page before it starts
+pub const ALERT_MAX_SPACING_MS: f64 = 45.0;
+
+/// Fewest Alert packets phones can be relied on to act on
+pub const ALERT_MIN_COUNT: u32 = 20;
+
 /// Frame size for G.711 at 20ms (8000 Hz * 0.020s = 160 samples)
 pub const G711_FRAME_SIZE: usize = 160;
 
@@ -72,11 +82,24 @@ pub const PAGING_CHANNEL_MAX: u8 = 50;
 pub const PAGING_PRIORITY_CHANNEL: u8 = 49;
 pub const PAGING_EMERGENCY_CHANNEL: u8 = 50;
 
+/// Check if a channel number is an emergency channel (25 or 50)
+pub const fn is_emergency_channel(channel: u8) -> bool {
+    channel == PTT_EMERGENCY_CHANNEL || channel == PAGING_EMERGENCY_CHANNEL
+}
+
+/// Check if a channel number is a priority channel (24 or 49)
+pub const fn is_priority_channel(channel: u8) -> bool {
+    channel == PTT_PRIORITY_CHANNEL || channel == PAGING_PRIORITY_CHANNEL
+}
+
 /// Minimum caller ID length for Polycom compatibility
 /// Polycom phones pad caller ID to 13 bytes with nulls to create a fixed 20-byte header
 /// (1 op + 1 channel + 4 serial + 1 len + 13 `caller_id` = 20 bytes)
 pub const MIN_CALLER_ID_LEN: usize = 13;
 
+/// Longest caller ID field the one-byte length allows
+pub const MAX_CALLER_ID_LEN: usize = 255;
+
 // ============================================================================
 // Error Types
 // ============================================================================
@@ -100,6 +123,92 @@ pub enum PolycomError {
 
     #[error("Caller ID too long (max 255 bytes)")]
     CallerIdTooLong,
+
+    #[error("Caller ID character {character:?} can't be encoded as {encoding}")]
+    UnrepresentableCallerId {
+        character: char,
+        encoding: CallerIdEncoding,
+    },
+
+    #[error("Caller ID is {bytes} bytes as {encoding}, longer than the {pad}-byte field")]
+    CallerIdExceedsPad {
+        bytes: usize,
+        pad: usize,
+        encoding: CallerIdEncoding,
+    },
+}
+
+// ============================================================================
+// Caller ID Encoding
+// ============================================================================
+
+/// Character encoding of the caller ID field.
+///
+/// The protocol doesn't say. Phones display ASCII; what they do with other
+/// bytes varies by model and firmware, so sites with accented names pick
+/// whichever their phones render.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CallerIdEncoding {
+    /// UTF-8, up to 4 bytes a character
+    #[default]
+    Utf8,
+    /// ISO-8859-1, one byte a character, U+0000-U+00FF only
+    Latin1,
+    /// 7-bit ASCII only
+    Ascii,
+}
+
+impl CallerIdEncoding {
+    /// Encode a caller ID, refusing characters this encoding can't carry
+    pub fn encode(self, caller_id: &str) -> Result<Vec<u8>, PolycomError> {
+        let limit = match self {
+            Self::Utf8 => return Ok(caller_id.as_bytes().to_vec()),
+            Self::Latin1 => 0xFF,
+            Self::Ascii => 0x7F,
+        };
+        caller_id
+            .chars()
+            .map(|character| {
+                u8::try_from(u32::from(character))
+                    .ok()
+                    .filter(|&b| u32::from(b) <= limit)
+                    .ok_or(PolycomError::UnrepresentableCallerId { character, encoding: self })
+            })
+            .collect()
+    }
+
+    /// Decode received caller ID bytes. Bytes that aren't valid UTF-8 are
+    /// taken as Latin-1, which every byte is, so nothing is replaced; the
+    /// encoding returned says which was used.
+    pub fn detect(bytes: &[u8]) -> (String, Self) {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => (text.to_string(), Self::Utf8),
+            Err(_) => (bytes.iter().map(|&b| char::from(b)).collect(), Self::Latin1),
+        }
+    }
+}
+
+impl FromStr for CallerIdEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "utf8" | "utf-8" => Ok(Self::Utf8),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Self::Latin1),
+            "ascii" => Ok(Self::Ascii),
+            _ => Err(format!("unknown caller ID encoding '{}' (expected utf8, latin1 or ascii)", s)),
+        }
+    }
+}
+
+impl fmt::Display for CallerIdEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Utf8 => "utf8",
+            Self::Latin1 => "latin1",
+            Self::Ascii => "ascii",
+        })
+    }
 }
 
 // ============================================================================
@@ -118,6 +227,27 @@ pub enum PolycomCodec {
 }
 
 impl PolycomCodec {
+    pub const ALL: [PolycomCodec; 3] = [Self::G722, Self::G711U, Self::G711A];
+
+    /// Canonical name accepted by `polycom-transmit --codec`
+    pub const fn cli_name(&self) -> &'static str {
+        match self {
+            Self::G711U => "g711u",
+            Self::G711A => "g711a",
+            Self::G722 => "g722",
+        }
+    }
+
+    /// Parse a `--codec` name or alias (case-insensitive)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "g711u" | "g711ulaw" | "pcmu" => Some(Self::G711U),
+            "g711a" | "g711alaw" | "pcma" => Some(Self::G711A),
+            "g722" => Some(Self::G722),
+            _ => None,
+        }
+    }
+
     /// Create from codec byte value
     pub const fn from_byte(b: u8) -> Option<Self> {
         match b {
@@ -228,109 +358,147 @@ pub struct PolycomHeader {
     pub host_serial: [u8; 4],
     /// Caller ID string
     pub caller_id: String,
+    /// How the caller ID is encoded; detected when parsing
+    pub caller_id_encoding: CallerIdEncoding,
+    /// Caller ID field length the caller ID is padded to with nulls
+    pub caller_id_pad: usize,
+    /// Non-padding bytes found after the caller ID's terminating null.
+    /// Some senders put extra metadata there. Not sent by `encode`.
+    pub caller_id_trailing: Vec<u8>,
 }
 
 impl PolycomHeader {
-    /// Create a new header
+    /// Create a new header with a UTF-8 caller ID padded to `MIN_CALLER_ID_LEN`
     pub fn new(packet_type: PacketType, channel: u8, host_serial: [u8; 4], caller_id: String) -> Self {
         Self {
             packet_type,
             channel,
             host_serial,
             caller_id,
+            caller_id_encoding: CallerIdEncoding::Utf8,
+            caller_id_pad: MIN_CALLER_ID_LEN,
+            caller_id_trailing: Vec::new(),
         }
     }
 
-    /// Parse header from bytes
+    /// Encode the caller ID as it will be sent, unpadded. Fails if a
+    /// character can't be encoded, the caller ID is longer than the padded
+    /// field (which would make the header longer than phones expect), or
+    /// longer than the protocol allows. Lengths are in bytes.
+    pub fn check_caller_id(caller_id: &str, encoding: CallerIdEncoding, pad: usize) -> Result<Vec<u8>, PolycomError> {
+        let bytes = encoding.encode(caller_id)?;
+        if bytes.len() > MAX_CALLER_ID_LEN {
+            return Err(PolycomError::CallerIdTooLong);
+        }
+        if bytes.len() > pad {
+            return Err(PolycomError::CallerIdExceedsPad {
+                bytes: bytes.len(),
+                pad,
+                encoding,
+            });
+        }
+        Ok(bytes)
+    }
+
+    /// Parse header from bytes; like [`PolycomPacket::parse`], never panics
+    #[deny(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
     pub fn parse(data: &[u8]) -> Result<(Self, usize), PolycomError> {
         // Minimum header: op(1) + channel(1) + serial(4) + caller_id_len(1) = 7 bytes
-        if data.len() < 7 {
+        let Some((fixed, _)) = data.split_first_chunk::<7>() else {
             return Err(PolycomError::TooShort {
                 expected: 7,
                 actual: data.len(),
             });
-        }
+        };
 
-        let op_code = data[0];
+        let op_code = fixed[0];
         let packet_type = PacketType::from_op_code(op_code)
             .ok_or(PolycomError::InvalidOpCode(op_code))?;
 
-        let channel = data[1];
+        let channel = fixed[1];
         if channel == 0 || channel > 50 {
             return Err(PolycomError::InvalidChannel(channel));
         }
 
-        let host_serial = [data[2], data[3], data[4], data[5]];
-        let caller_id_len = data[6] as usize;
+        let host_serial = [fixed[2], fixed[3], fixed[4], fixed[5]];
+        let caller_id_len = usize::from(fixed[6]);
 
-        let header_len = 7 + caller_id_len;
-        if data.len() < header_len {
+        let header_len = 7usize.saturating_add(caller_id_len);
+        let Some(caller_id_bytes) = data.get(7..header_len) else {
             return Err(PolycomError::Truncated {
                 expected: header_len,
                 actual: data.len(),
             });
-        }
-
-        let caller_id = if caller_id_len > 0 {
-            // Trim null padding bytes that Polycom phones add for fixed-size headers
-            let caller_id_bytes = &data[7..7 + caller_id_len];
-            let trimmed_len = caller_id_bytes.iter().position(|&b| b == 0).unwrap_or(caller_id_len);
-            String::from_utf8_lossy(&caller_id_bytes[..trimmed_len]).to_string()
-        } else {
-            String::new()
         };
 
+        // Trim null padding bytes that Polycom phones add for fixed-size headers
+        let trimmed_len = caller_id_bytes.iter().position(|&b| b == 0).unwrap_or(caller_id_len);
+        let (caller_id, caller_id_encoding) = CallerIdEncoding::detect(caller_id_bytes.get(..trimmed_len).unwrap_or_default());
+
+        // Anything but padding after the terminator is kept, e.g. sender metadata
+        let after_terminator = caller_id_bytes.get(trimmed_len.saturating_add(1)..).unwrap_or_default();
+        let trailing = after_terminator
+            .iter()
+            .rposition(|&b| b != 0)
+            .and_then(|last| after_terminator.get(..=last))
+            .unwrap_or_default();
+
         Ok((
             Self {
                 packet_type,
                 channel,
                 host_serial,
                 caller_id,
+                caller_id_encoding,
+                caller_id_pad: caller_id_len,
+                caller_id_trailing: trailing.to_vec(),
             },
             header_len,
         ))
     }
 
     /// Encode header to bytes
-    /// Pads caller ID to `MIN_CALLER_ID_LEN` (13) bytes with nulls to create a fixed 20-byte header
-    /// This matches the format used by real Polycom phones
+    /// Pads caller ID to `caller_id_pad` bytes with nulls; the default 13 gives
+    /// the fixed 20-byte header used by real Polycom phones. A longer caller ID
+    /// makes the header longer rather than being cut off.
     pub fn encode(&self) -> Result<Vec<u8>, PolycomError> {
-        let caller_id_bytes = self.caller_id.as_bytes();
-        if caller_id_bytes.len() > 255 {
+        let caller_id_bytes = self.caller_id_encoding.encode(&self.caller_id)?;
+        if caller_id_bytes.len() > MAX_CALLER_ID_LEN {
             return Err(PolycomError::CallerIdTooLong);
         }
 
-        // Pad caller ID to minimum length (13 bytes) to create fixed 20-byte header
-        let padded_len = caller_id_bytes.len().max(MIN_CALLER_ID_LEN);
+        let padded_len = caller_id_bytes.len().max(self.caller_id_pad).min(MAX_CALLER_ID_LEN);
 
         let mut buf = Vec::with_capacity(7 + padded_len);
         buf.push(self.packet_type.to_op_code());
         buf.push(self.channel);
         buf.extend_from_slice(&self.host_serial);
         buf.push(padded_len as u8);
-        buf.extend_from_slice(caller_id_bytes);
+        buf.extend_from_slice(&caller_id_bytes);
 
-        // Pad with nulls to reach minimum length
-        if caller_id_bytes.len() < MIN_CALLER_ID_LEN {
-            buf.resize(7 + padded_len, 0);
-        }
+        // Pad with nulls to reach the field length
+        buf.resize(7 + padded_len, 0);
 
         Ok(buf)
     }
 
     /// Get the header length in bytes (with padding)
     pub fn len(&self) -> usize {
-        7 + self.caller_id.len().max(MIN_CALLER_ID_LEN)
+        let caller_id_len = self
+            .caller_id_encoding
+            .encode(&self.caller_id)
+            .map_or(self.caller_id.len(), |bytes| bytes.len());
+        7 + caller_id_len.max(self.caller_id_pad).min(MAX_CALLER_ID_LEN)
     }
 
     /// Check if this is an emergency channel
     pub fn is_emergency(&self) -> bool {
-        self.channel == PTT_EMERGENCY_CHANNEL || self.channel == PAGING_EMERGENCY_CHANNEL
+        is_emergency_channel(self.channel)
     }
 
     /// Check if this is a priority channel
     pub fn is_priority(&self) -> bool {
-        self.channel == PTT_PRIORITY_CHANNEL || self.channel == PAGING_PRIORITY_CHANNEL
+        is_priority_channel(self.channel)
     }
 }
 
@@ -360,20 +528,21 @@ impl AudioHeader {
     }
 
     /// Parse audio header from bytes
+    #[deny(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
     pub fn parse(data: &[u8]) -> Result<Self, PolycomError> {
-        if data.len() < 6 {
+        let Some((fixed, _)) = data.split_first_chunk::<6>() else {
             return Err(PolycomError::TooShort {
                 expected: 6,
                 actual: data.len(),
             });
-        }
+        };
 
-        let codec_byte = data[0];
+        let codec_byte = fixed[0];
         let codec = PolycomCodec::from_byte(codec_byte)
             .ok_or(PolycomError::InvalidCodec(codec_byte))?;
 
-        let flags = data[1];
-        let sample_count = u32::from_be_bytes([data[2], data[3], data[4], data[5]]);
+        let flags = fixed[1];
+        let sample_count = u32::from_be_bytes([fixed[2], fixed[3], fixed[4], fixed[5]]);
 
         Ok(Self {
             codec,
@@ -433,7 +602,10 @@ impl PolycomPacket {
         Self::parse_with_time(data, source, Instant::now())
     }
 
-    /// Parse with a specific receive time
+    /// Parse with a specific receive time. Anything can arrive on the
+    /// paging port, so any input must come back as an error rather than a
+    /// panic; the lints hold it to checked slicing and arithmetic
+    #[deny(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
     pub fn parse_with_time(
         data: &[u8],
         source: SocketAddr,
@@ -455,32 +627,29 @@ impl PolycomPacket {
             }
             PacketType::Transmit => {
                 // Transmit packets have audio header + redundant frame + current frame
-                let payload = &data[header_len..];
+                let payload = data.get(header_len..).unwrap_or_default();
 
-                if payload.len() < AudioHeader::len() {
+                let Some(audio_data) = payload.get(AudioHeader::len()..) else {
                     return Err(PolycomError::TooShort {
-                        expected: header_len + AudioHeader::len(),
+                        expected: header_len.saturating_add(AudioHeader::len()),
                         actual: data.len(),
                     });
-                }
+                };
 
                 let audio_header = AudioHeader::parse(payload)?;
-                let audio_data = &payload[AudioHeader::len()..];
                 let frame_size = audio_header.codec.frame_size();
 
                 // First transmit packet has only one frame, subsequent have redundant + current
-                let (redundant_frame, audio_frame) = if audio_data.len() >= frame_size * 2 {
+                let (redundant_frame, audio_frame) = match (
+                    audio_data.get(..frame_size),
+                    audio_data.get(frame_size..frame_size.saturating_mul(2)),
+                ) {
                     // Has redundant frame
-                    (
-                        Some(audio_data[..frame_size].to_vec()),
-                        Some(audio_data[frame_size..frame_size * 2].to_vec()),
-                    )
-                } else if audio_data.len() >= frame_size {
+                    (Some(redundant), Some(current)) => (Some(redundant.to_vec()), Some(current.to_vec())),
                     // Only current frame (first packet)
-                    (None, Some(audio_data[..frame_size].to_vec()))
-                } else {
+                    (Some(current), None) => (None, Some(current.to_vec())),
                     // Incomplete frame
-                    (None, None)
+                    _ => (None, None),
                 };
 
                 Ok(Self {
@@ -509,6 +678,10 @@ pub struct PolycomPacketBuilder {
     host_serial: [u8; 4],
     /// Caller ID string
     caller_id: String,
+    /// Caller ID encoding
+    caller_id_encoding: CallerIdEncoding,
+    /// Caller ID field length
+    caller_id_pad: usize,
     /// Codec to use
     codec: PolycomCodec,
     /// Current sample count (timestamp)
@@ -535,6 +708,8 @@ impl PolycomPacketBuilder {
             channel,
             host_serial,
             caller_id,
+            caller_id_encoding: CallerIdEncoding::Utf8,
+            caller_id_pad: MIN_CALLER_ID_LEN,
             codec,
             sample_count: Self::generate_initial_sample_count(),
             previous_frame: None,
@@ -583,25 +758,29 @@ impl PolycomPacketBuilder {
         self.little_endian = little_endian;
     }
 
+    /// Set the caller ID encoding and the field length it is padded to
+    pub fn set_caller_id_format(&mut self, encoding: CallerIdEncoding, pad: usize) {
+        self.caller_id_encoding = encoding;
+        self.caller_id_pad = pad;
+    }
+
+    fn header(&self, packet_type: PacketType) -> PolycomHeader {
+        PolycomHeader {
+            caller_id_encoding: self.caller_id_encoding,
+            caller_id_pad: self.caller_id_pad,
+            ..PolycomHeader::new(packet_type, self.channel, self.host_serial, self.caller_id.clone())
+        }
+    }
+
     /// Build an Alert packet
     pub fn build_alert(&self) -> Result<Vec<u8>, PolycomError> {
-        let header = PolycomHeader::new(
-            PacketType::Alert,
-            self.channel,
-            self.host_serial,
-            self.caller_id.clone(),
-        );
+        let header = self.header(PacketType::Alert);
         header.encode()
     }
 
     /// Build a Transmit packet with audio data
     pub fn build_transmit(&mut self, audio_frame: &[u8]) -> Result<Vec<u8>, PolycomError> {
-        let header = PolycomHeader::new(
-            PacketType::Transmit,
-            self.channel,
-            self.host_serial,
-            self.caller_id.clone(),
-        );
+        let header = self.header(PacketType::Transmit);
 
         let mut packet = header.encode()?;
 
@@ -630,12 +809,7 @@ impl PolycomPacketBuilder {
 
     /// Build an End packet
     pub fn build_end(&self) -> Result<Vec<u8>, PolycomError> {
-        let header = PolycomHeader::new(
-            PacketType::End,
-            self.channel,
-            self.host_serial,
-            self.caller_id.clone(),
-        );
+        let header = self.header(PacketType::End);
         header.encode()
     }
 
@@ -696,6 +870,50 @@ pub struct PolycomSession {
     pub audio_packet_count: u32,
     /// Number of End packets received
     pub end_count: u32,
+    /// Arrival of the latest Alert packet
+    pub last_alert_at: Instant,
+    /// Widest gap between consecutive Alert packets
+    pub max_alert_spacing: Duration,
+    /// How the Alert phase went, once it is over
+    pub alert_phase: Option<AlertPhaseSummary>,
+}
+
+/// Timing of a session's Alert phase. Phones decide during it whether to
+/// play the page, so a sender too busy to keep up loses pages even though
+/// its audio is fine.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+pub struct AlertPhaseSummary {
+    pub count: u32,
+    /// First Alert to last
+    pub duration_ms: f64,
+    pub mean_spacing_ms: f64,
+    pub max_spacing_ms: f64,
+    /// Spacing exceeded [`ALERT_MAX_SPACING_MS`]
+    pub slow: bool,
+    /// Fewer than [`ALERT_MIN_COUNT`] Alerts
+    pub short: bool,
+}
+
+impl AlertPhaseSummary {
+    /// Whether phones might not have played the page
+    pub fn is_malformed(&self) -> bool {
+        self.slow || self.short
+    }
+
+    /// What was wrong with the phase, for display
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.slow {
+            problems.push(format!(
+                "Alert spacing reached {:.1}ms (limit {:.0}ms)",
+                self.max_spacing_ms, ALERT_MAX_SPACING_MS
+            ));
+        }
+        if self.short {
+            problems.push(format!("only {} Alert packets (at least {} expected)", self.count, ALERT_MIN_COUNT));
+        }
+        problems
+    }
 }
 
 impl PolycomSession {
@@ -712,16 +930,26 @@ impl PolycomSession {
             alert_count: 1,
             audio_packet_count: 0,
             end_count: 0,
+            last_alert_at: packet.received_at,
+            max_alert_spacing: Duration::ZERO,
+            alert_phase: None,
         }
     }
 
-    /// Update session with a new packet
-    pub fn update(&mut self, packet: &PolycomPacket) {
+    /// Update session with a new packet. Returns the Alert phase's summary
+    /// when this packet is the one that ended it
+    pub fn update(&mut self, packet: &PolycomPacket) -> Option<AlertPhaseSummary> {
         self.last_packet_at = packet.received_at;
+        let was_alerting = self.state == SessionState::Alerting;
 
         match packet.header.packet_type {
             PacketType::Alert => {
                 self.alert_count += 1;
+                if was_alerting {
+                    let spacing = packet.received_at.saturating_duration_since(self.last_alert_at);
+                    self.max_alert_spacing = self.max_alert_spacing.max(spacing);
+                    self.last_alert_at = packet.received_at;
+                }
             }
             PacketType::Transmit => {
                 if self.state == SessionState::Alerting {
@@ -737,6 +965,30 @@ impl PolycomSession {
                 self.end_count += 1;
             }
         }
+
+        if was_alerting && self.state != SessionState::Alerting {
+            self.alert_phase = Some(self.summarize_alerts());
+            return self.alert_phase;
+        }
+        None
+    }
+
+    fn summarize_alerts(&self) -> AlertPhaseSummary {
+        let duration = self.last_alert_at.saturating_duration_since(self.started_at);
+        let duration_ms = duration.as_secs_f64() * 1000.0;
+        let max_spacing_ms = self.max_alert_spacing.as_secs_f64() * 1000.0;
+        AlertPhaseSummary {
+            count: self.alert_count,
+            duration_ms,
+            mean_spacing_ms: if self.alert_count > 1 {
+                duration_ms / f64::from(self.alert_count - 1)
+            } else {
+                0.0
+            },
+            max_spacing_ms,
+            slow: max_spacing_ms > ALERT_MAX_SPACING_MS,
+            short: self.alert_count < ALERT_MIN_COUNT,
+        }
     }
 
     /// Check if the session has timed out
@@ -926,6 +1178,123 @@ mod tests {
         assert!(matches!(result, Err(PolycomError::InvalidOpCode(0x00))));
     }
 
+    #[test]
+    fn test_parse_malformed_lengths() {
+        // Caller ID length claiming the most a byte can, in a short packet
+        let data = [OP_TRANSMIT, 26, 1, 2, 3, 4, 255, b'A'];
+        assert!(matches!(
+            PolycomPacket::parse(&data, test_source()),
+            Err(PolycomError::Truncated { expected: 262, actual: 8 })
+        ));
+
+        // A caller ID of nothing but terminators
+        let (header, len) = PolycomHeader::parse(&[OP_ALERT, 26, 1, 2, 3, 4, 3, 0, 0, 0]).unwrap();
+        assert_eq!((header.caller_id.as_str(), len), ("", 10));
+        assert!(header.caller_id_trailing.is_empty());
+
+        // Transmit cut off inside its audio header
+        let data = [OP_TRANSMIT, 26, 1, 2, 3, 4, 0, CODEC_G711U, 0, 0];
+        assert!(matches!(
+            PolycomPacket::parse(&data, test_source()),
+            Err(PolycomError::TooShort { expected: 13, actual: 10 })
+        ));
+
+        // A whole audio header and less than a frame is kept without audio
+        let mut data = vec![OP_TRANSMIT, 26, 1, 2, 3, 4, 0, CODEC_G722, 0, 0, 0, 0, 0];
+        data.extend_from_slice(&[0xAA; 159]);
+        let packet = PolycomPacket::parse(&data, test_source()).unwrap();
+        assert!(packet.audio_frame.is_none() && packet.redundant_frame.is_none());
+        // ... and a frame and a half as just the current frame
+        data.extend_from_slice(&[0xBB; 81]);
+        let packet = PolycomPacket::parse(&data, test_source()).unwrap();
+        assert!(packet.redundant_frame.is_none());
+        assert_eq!(packet.audio_frame.unwrap().len(), 160);
+    }
+
+    #[test]
+    fn test_parse_never_panics() {
+        let mut builder = PolycomPacketBuilder::new(26, [0x12, 0x34, 0x56, 0x78], "MPS-IP".to_string(), PolycomCodec::G722);
+        let first = builder.build_transmit(&[0xAA; 160]).unwrap();
+        let valid = [builder.build_alert().unwrap(), first, builder.build_transmit(&[0xBB; 160]).unwrap()];
+        for data in crate::network::mangled_datagrams(0x9A6E, &valid, 20_000) {
+            match PolycomPacket::parse(&data, test_source()) {
+                Ok(packet) => assert!(packet.audio_frame.map_or(0, |frame| frame.len()) <= data.len()),
+                Err(e) => assert!(!e.to_string().is_empty()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_caller_id_encodings_roundtrip() {
+        let caller_id = "Büro 3";
+        for (encoding, field) in [
+            (CallerIdEncoding::Utf8, &b"B\xc3\xbcro 3"[..]),
+            (CallerIdEncoding::Latin1, &b"B\xfcro 3"[..]),
+        ] {
+            let header = PolycomHeader {
+                caller_id_encoding: encoding,
+                ..PolycomHeader::new(PacketType::Alert, 26, [1, 2, 3, 4], caller_id.to_string())
+            };
+            let encoded = header.encode().unwrap();
+            assert_eq!(encoded.len(), 20, "{}", encoding);
+            assert_eq!(&encoded[7..7 + field.len()], field);
+
+            let (decoded, _) = PolycomHeader::parse(&encoded).unwrap();
+            assert_eq!(decoded.caller_id, caller_id);
+            assert_eq!(decoded.caller_id_encoding, encoding);
+            assert!(decoded.caller_id_trailing.is_empty());
+        }
+
+        let ascii = PolycomHeader {
+            caller_id_encoding: CallerIdEncoding::Ascii,
+            ..PolycomHeader::new(PacketType::Alert, 26, [1, 2, 3, 4], caller_id.to_string())
+        };
+        assert!(matches!(
+            ascii.encode(),
+            Err(PolycomError::UnrepresentableCallerId { character: 'ü', encoding: CallerIdEncoding::Ascii })
+        ));
+        assert!(CallerIdEncoding::Latin1.encode("Büro ☎").is_err());
+    }
+
+    #[test]
+    fn test_caller_id_length_is_in_bytes() {
+        // 13 characters, 14 bytes in UTF-8
+        let caller_id = "Empfang Süd 1";
+        assert_eq!(caller_id.chars().count(), 13);
+        assert!(matches!(
+            PolycomHeader::check_caller_id(caller_id, CallerIdEncoding::Utf8, MIN_CALLER_ID_LEN),
+            Err(PolycomError::CallerIdExceedsPad { bytes: 14, pad: 13, .. })
+        ));
+        assert_eq!(
+            PolycomHeader::check_caller_id(caller_id, CallerIdEncoding::Latin1, MIN_CALLER_ID_LEN).unwrap().len(),
+            13
+        );
+
+        // A 16-byte field fits it and gives a 23-byte header
+        let mut builder = PolycomPacketBuilder::new(26, [1, 2, 3, 4], caller_id.to_string(), PolycomCodec::G711U);
+        builder.set_caller_id_format(CallerIdEncoding::Utf8, 16);
+        let alert = builder.build_alert().unwrap();
+        assert_eq!(alert.len(), 23);
+        let (header, len) = PolycomHeader::parse(&alert).unwrap();
+        assert_eq!((header.caller_id.as_str(), header.caller_id_pad, len), (caller_id, 16, 23));
+    }
+
+    #[test]
+    fn test_caller_id_trailing_bytes() {
+        let mut data = vec![OP_ALERT, 26, 1, 2, 3, 4, 13];
+        data.extend_from_slice(b"Lobby\0\x01\x7f\0\0\0\0\0");
+        let (header, len) = PolycomHeader::parse(&data).unwrap();
+        assert_eq!(len, 20);
+        assert_eq!(header.caller_id, "Lobby");
+        assert_eq!(header.caller_id_trailing, [0x01, 0x7f]);
+
+        // Invalid UTF-8 is read as Latin-1 rather than replaced
+        data[8] = 0xE9;
+        let (header, _) = PolycomHeader::parse(&data).unwrap();
+        assert_eq!(header.caller_id, "Lébby");
+        assert_eq!(header.caller_id_encoding, CallerIdEncoding::Latin1);
+    }
+
     #[test]
     fn test_session_state_transitions() {
         let alert_header = PolycomHeader::new(
@@ -968,4 +1337,69 @@ mod tests {
         assert_eq!(session.audio_packet_count, 1);
         assert_eq!(session.codec, Some(PolycomCodec::G711U));
     }
+
+    /// A packet of `packet_type` on channel 26 arriving `ms` after `base`
+    fn packet_at(packet_type: PacketType, base: Instant, ms: u64) -> PolycomPacket {
+        let transmit = packet_type == PacketType::Transmit;
+        PolycomPacket {
+            header: PolycomHeader::new(packet_type, 26, [0; 4], "Test".to_string()),
+            audio_header: transmit.then(|| AudioHeader::new(PolycomCodec::G711U, 0, 0)),
+            redundant_frame: None,
+            audio_frame: transmit.then(|| vec![0; 160]),
+            received_at: base + Duration::from_millis(ms),
+            source: test_source(),
+        }
+    }
+
+    /// Alerts arriving at `times_ms`, then the first audio packet
+    fn alert_phase(times_ms: &[u64]) -> AlertPhaseSummary {
+        let base = Instant::now();
+        let mut session = PolycomSession::from_alert(&packet_at(PacketType::Alert, base, times_ms[0]));
+        for &ms in &times_ms[1..] {
+            assert_eq!(session.update(&packet_at(PacketType::Alert, base, ms)), None);
+        }
+        let last = *times_ms.last().unwrap();
+        let phase = session.update(&packet_at(PacketType::Transmit, base, last + 20)).unwrap();
+        // Only the packet that ends the phase reports it
+        assert_eq!(session.update(&packet_at(PacketType::Transmit, base, last + 40)), None);
+        assert_eq!(session.alert_phase, Some(phase));
+        phase
+    }
+
+    #[test]
+    fn test_alert_phase_on_time() {
+        let times: Vec<u64> = (0..ALERT_PACKET_COUNT).map(|i| u64::from(i) * CONTROL_PACKET_INTERVAL_MS).collect();
+        let phase = alert_phase(&times);
+        assert_eq!(phase.count, 31);
+        assert!((phase.duration_ms - 900.0).abs() < 1e-6);
+        assert!((phase.mean_spacing_ms - 30.0).abs() < 1e-6);
+        assert!((phase.max_spacing_ms - 30.0).abs() < 1e-6);
+        assert!(!phase.is_malformed());
+        assert!(phase.problems().is_empty());
+    }
+
+    #[test]
+    fn test_alert_phase_slow_and_short() {
+        // A sender stalling for 60ms partway through
+        let mut times: Vec<u64> = (0..31).map(|i| i * 30).collect();
+        for time in &mut times[15..] {
+            *time += 30;
+        }
+        let slow = alert_phase(&times);
+        assert!(slow.slow && !slow.short);
+        assert!((slow.max_spacing_ms - 60.0).abs() < 1e-6);
+        assert_eq!(slow.problems(), ["Alert spacing reached 60.0ms (limit 45ms)"]);
+
+        let short = alert_phase(&[0, 30, 60, 90, 120]);
+        assert!(short.short && !short.slow);
+        assert_eq!(short.count, 5);
+
+        // Ended by End packets with no audio at all
+        let base = Instant::now();
+        let mut session = PolycomSession::from_alert(&packet_at(PacketType::Alert, base, 0));
+        let phase = session.update(&packet_at(PacketType::End, base, 80)).unwrap();
+        assert_eq!(phase.count, 1);
+        assert!(phase.mean_spacing_ms.abs() < 1e-9);
+        assert!(phase.short);
+    }
 }
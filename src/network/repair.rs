@@ -0,0 +1,562 @@
+//! Loss repair between our own sender and receivers (`--repair`).
+//!
+//! This is not a standard protocol, and nothing but this tool speaks it. It
+//! is meant for qualifying a lossy link tool-to-tool while still archiving
+//! complete audio. `transmit --repair` keeps the packets it sent most
+//! recently and listens on the destination port + 2 for NACKs. A receiver
+//! with `--repair` holds packets back in `RepairBuffer` so they are handled
+//! in sequence order. A gap still open after the repair window is asked for
+//! with a NACK to the sender's address on port + 2, and the resent packet is
+//! put back in its place. A gap still open after four windows is given up and handled as
+//! ordinary loss.
+//!
+//! Datagrams are a 4-byte magic followed by:
+//! - NACK (`MPUN`): SSRC (u32), count (u16), then that many sequence numbers (u16)
+//! - Repair (`MPUR`): the RTP packet exactly as first sent
+//!
+//! All integers are big-endian.
+
+use crate::network::RtpPacket;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+/// Repair traffic uses the stream's port plus this
+pub const REPAIR_PORT_OFFSET: u16 = 2;
+
+/// A gap is given up this many repair windows after it is noticed
+pub const GIVE_UP_WINDOWS: u32 = 4;
+
+/// How long a sender keeps answering NACKs after its last packet
+pub const REPAIR_LINGER: Duration = Duration::from_secs(1);
+
+/// Most sequence numbers in one NACK, and most missing packets tracked at once
+pub const MAX_NACK_SEQUENCES: usize = 64;
+
+/// A jump further than this ahead is a sequence reset, not a gap
+const MAX_TRACKED_GAP: u16 = 512;
+
+const NACK_MAGIC: &[u8; 4] = b"MPUN";
+const REPAIR_MAGIC: &[u8; 4] = b"MPUR";
+
+/// Request to resend packets of one stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nack {
+    pub ssrc: u32,
+    pub sequences: Vec<u16>,
+}
+
+impl Nack {
+    pub fn encode(&self) -> Vec<u8> {
+        let count = self.sequences.len().min(MAX_NACK_SEQUENCES);
+        let mut data = Vec::with_capacity(10 + 2 * count);
+        data.extend_from_slice(NACK_MAGIC);
+        data.extend_from_slice(&self.ssrc.to_be_bytes());
+        data.extend_from_slice(&(count as u16).to_be_bytes());
+        for sequence in &self.sequences[..count] {
+            data.extend_from_slice(&sequence.to_be_bytes());
+        }
+        data
+    }
+
+    /// Parse a NACK; anything else (or a truncated one) is `None`
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let body = data.strip_prefix(NACK_MAGIC)?;
+        let ssrc = u32::from_be_bytes(body.get(..4)?.try_into().ok()?);
+        let count = usize::from(u16::from_be_bytes(body.get(4..6)?.try_into().ok()?));
+        let sequences = body
+            .get(6..6 + 2 * count)?
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        Some(Self { ssrc, sequences })
+    }
+}
+
+/// Wrap a packet being resent
+pub fn encode_repair(rtp: &[u8]) -> Vec<u8> {
+    [REPAIR_MAGIC.as_slice(), rtp].concat()
+}
+
+/// The RTP packet inside a repair datagram
+pub fn decode_repair(data: &[u8]) -> Option<&[u8]> {
+    data.strip_prefix(REPAIR_MAGIC)
+}
+
+/// What a sender did with the NACKs it received
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct RetransmitStats {
+    pub nacks_received: u64,
+    pub packets_requested: u64,
+    pub packets_resent: u64,
+    /// Requested packets already gone from the buffer, or never sent
+    pub packets_unavailable: u64,
+}
+
+/// The sender's side: the last packets sent, and the sockets NACKs arrive on
+pub struct RepairServer {
+    sockets: Vec<UdpSocket>,
+    capacity: usize,
+    /// Oldest first: SSRC, sequence number and the packet as sent
+    sent: VecDeque<(u32, u16, Vec<u8>)>,
+    stats: RetransmitStats,
+}
+
+impl RepairServer {
+    /// Listen for NACKs on each destination port + 2, keeping the last `capacity` packets
+    pub async fn bind(ports: impl IntoIterator<Item = u16>, capacity: usize) -> io::Result<Self> {
+        let mut ports: Vec<u16> = ports.into_iter().collect();
+        ports.sort_unstable();
+        ports.dedup();
+        let mut sockets = Vec::with_capacity(ports.len());
+        for port in ports {
+            let repair_port = port.checked_add(REPAIR_PORT_OFFSET).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("port {} leaves no room for repair", port))
+            })?;
+            let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, repair_port)).await?;
+            // The non-blocking sends below fail until the reactor has seen the socket writable
+            socket.writable().await?;
+            sockets.push(socket);
+        }
+        Ok(Self {
+            sockets,
+            capacity: capacity.max(1),
+            sent: VecDeque::with_capacity(capacity),
+            stats: RetransmitStats::default(),
+        })
+    }
+
+    /// Keep a packet that has just been sent (before any impairment)
+    pub fn record(&mut self, rtp: &[u8]) {
+        let Some(header) = rtp.get(..12) else {
+            return;
+        };
+        let sequence = u16::from_be_bytes([header[2], header[3]]);
+        let ssrc = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+        if self.sent.len() == self.capacity {
+            self.sent.pop_front();
+        }
+        self.sent.push_back((ssrc, sequence, rtp.to_vec()));
+    }
+
+    /// Answer every NACK waiting on the sockets, without blocking
+    pub fn serve(&mut self) {
+        let mut buf = [0u8; 2048];
+        for socket in &self.sockets {
+            while let Ok((len, from)) = socket.try_recv_from(&mut buf) {
+                let Some(nack) = Nack::decode(&buf[..len]) else {
+                    continue;
+                };
+                self.stats.nacks_received += 1;
+                for sequence in nack.sequences {
+                    self.stats.packets_requested += 1;
+                    let packet = self
+                        .sent
+                        .iter()
+                        .rev()
+                        .find(|(ssrc, seq, _)| *ssrc == nack.ssrc && *seq == sequence);
+                    match packet {
+                        Some((_, _, rtp)) if socket.try_send_to(&encode_repair(rtp), from).is_ok() => {
+                            self.stats.packets_resent += 1;
+                        }
+                        Some(_) => {}
+                        None => self.stats.packets_unavailable += 1,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Keep answering NACKs for `duration`, for losses near the end of the page
+    pub async fn linger(&mut self, duration: Duration) {
+        let until = Instant::now() + duration;
+        while Instant::now() < until {
+            self.serve();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        self.serve();
+    }
+
+    pub fn stats(&self) -> RetransmitStats {
+        self.stats
+    }
+}
+
+/// How a page's losses fared with `--repair`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct RepairSummary {
+    pub nacks_sent: u64,
+    pub packets_requested: u64,
+    /// Lost packets resent in time and recorded in place
+    pub packets_repaired: u64,
+    /// Lost packets given up on; these are the page's `packets_lost`
+    pub packets_unrepaired: u64,
+}
+
+/// The receiver's socket for sending NACKs and receiving what is resent
+pub struct RepairClient {
+    socket: UdpSocket,
+}
+
+impl RepairClient {
+    pub async fn bind() -> io::Result<Self> {
+        let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)).await?;
+        socket.writable().await?;
+        Ok(Self { socket })
+    }
+
+    /// Send a NACK to the sender of a stream on `port`; a full send buffer just drops it
+    pub fn send(&self, nack: &Nack, source: SocketAddr, port: u16) {
+        let dest = SocketAddr::new(source.ip(), port.wrapping_add(REPAIR_PORT_OFFSET));
+        self.socket.try_send_to(&nack.encode(), dest).ok();
+    }
+
+    /// A resent packet, if one is waiting
+    pub fn try_recv(&self) -> Option<RtpPacket> {
+        let mut buf = [0u8; 2048];
+        loop {
+            let (len, from) = self.socket.try_recv_from(&mut buf).ok()?;
+            if let Some(packet) = decode_repair(&buf[..len]).and_then(|rtp| RtpPacket::parse(rtp, from).ok()) {
+                return Some(packet);
+            }
+        }
+    }
+}
+
+struct Held {
+    packet: RtpPacket,
+    repaired: bool,
+}
+
+struct Missing {
+    noticed: Instant,
+    nacked: bool,
+}
+
+/// Holds a stream's packets back until they can be handled in sequence
+/// order, tracking the gaps between them
+pub struct RepairBuffer {
+    window: Duration,
+    ssrc: Option<u32>,
+    source: Option<SocketAddr>,
+    /// The next sequence number to hand on
+    next: Option<u16>,
+    highest: Option<u16>,
+    held: HashMap<u16, Held>,
+    missing: HashMap<u16, Missing>,
+    /// Sequence number and arrival of the packet handed on last
+    last_released: Option<(u16, Instant)>,
+    summary: RepairSummary,
+}
+
+impl RepairBuffer {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            ssrc: None,
+            source: None,
+            next: None,
+            highest: None,
+            held: HashMap::new(),
+            missing: HashMap::new(),
+            last_released: None,
+            summary: RepairSummary::default(),
+        }
+    }
+
+    /// The stream being buffered
+    pub fn ssrc(&self) -> Option<u32> {
+        self.ssrc
+    }
+
+    /// Where the stream comes from, and so where NACKs go
+    pub fn source(&self) -> Option<SocketAddr> {
+        self.source
+    }
+
+    /// Take a packet off the network. Returns the packets now ready, in
+    /// sequence order. A packet from another stream first flushes this one.
+    pub fn accept(&mut self, packet: RtpPacket, now: Instant) -> Vec<RtpPacket> {
+        let mut ready = Vec::new();
+        if self.ssrc != Some(packet.header.ssrc) {
+            ready = self.flush();
+            self.start(&packet);
+        }
+
+        let sequence = packet.header.sequence_number;
+        if let Some(next) = self.next {
+            let ahead = sequence.wrapping_sub(next);
+            if ahead >= 0x8000 && next.wrapping_sub(sequence) <= MAX_TRACKED_GAP {
+                // Just behind what was handed on: late after its gap was given
+                // up, or a duplicate. Handled as it would be without repair
+                ready.push(packet);
+                return ready;
+            }
+            if ahead > MAX_TRACKED_GAP {
+                // Far ahead or far behind: the sender started over
+                ready.extend(self.flush());
+                self.start(&packet);
+            } else if self.held.contains_key(&sequence) {
+                return ready;
+            }
+        }
+
+        let highest = *self.highest.get_or_insert(sequence);
+        self.next.get_or_insert(sequence);
+        let beyond = sequence.wrapping_sub(highest);
+        if beyond > 0 && beyond < 0x8000 {
+            // Gaps past the tracking limit aren't asked for; they are given
+            // up as soon as they hold up the stream
+            for step in 1..beyond {
+                if self.missing.len() >= MAX_NACK_SEQUENCES {
+                    break;
+                }
+                self.missing.insert(highest.wrapping_add(step), Missing { noticed: now, nacked: false });
+            }
+            self.highest = Some(sequence);
+        }
+        self.missing.remove(&sequence);
+        self.held.insert(sequence, Held { packet, repaired: false });
+        ready.extend(self.release(now));
+        ready
+    }
+
+    /// Take a resent packet. One that is no longer wanted is dropped.
+    pub fn accept_repair(&mut self, packet: RtpPacket, now: Instant) -> Vec<RtpPacket> {
+        let sequence = packet.header.sequence_number;
+        if self.ssrc != Some(packet.header.ssrc) || self.missing.remove(&sequence).is_none() {
+            return Vec::new();
+        }
+        let mut packet = packet;
+        // It came from the sender's repair socket, not the stream's source
+        if let Some(source) = self.source {
+            packet.source = source;
+        }
+        self.summary.packets_repaired += 1;
+        self.held.insert(sequence, Held { packet, repaired: true });
+        self.release(now)
+    }
+
+    /// Give up gaps that have waited long enough, returning the packets that frees
+    pub fn poll(&mut self, now: Instant) -> Vec<RtpPacket> {
+        self.release(now)
+    }
+
+    /// Gaps due a NACK, each asked for once
+    pub fn nack(&mut self, now: Instant) -> Option<Nack> {
+        let ssrc = self.ssrc?;
+        let next = self.next?;
+        let mut sequences: Vec<u16> = self
+            .missing
+            .iter_mut()
+            .filter(|(_, gap)| !gap.nacked && now.saturating_duration_since(gap.noticed) >= self.window)
+            .map(|(&sequence, gap)| {
+                gap.nacked = true;
+                sequence
+            })
+            .collect();
+        if sequences.is_empty() {
+            return None;
+        }
+        sequences.sort_unstable_by_key(|sequence| sequence.wrapping_sub(next));
+        self.summary.nacks_sent += 1;
+        self.summary.packets_requested += sequences.len() as u64;
+        Some(Nack { ssrc, sequences })
+    }
+
+    /// Hand on everything held, giving up every gap; for the end of a page
+    pub fn flush(&mut self) -> Vec<RtpPacket> {
+        let mut ready = Vec::with_capacity(self.held.len());
+        while let (false, Some(next)) = (self.held.is_empty(), self.next) {
+            match self.held.remove(&next) {
+                Some(held) => ready.push(self.hand_on(next, held)),
+                None => self.summary.packets_unrepaired += 1,
+            }
+            self.next = Some(next.wrapping_add(1));
+        }
+        self.missing.clear();
+        self.ssrc = None;
+        self.source = None;
+        self.next = None;
+        self.highest = None;
+        self.last_released = None;
+        ready
+    }
+
+    /// The page's figures so far, starting afresh
+    pub fn take_summary(&mut self) -> RepairSummary {
+        std::mem::take(&mut self.summary)
+    }
+
+    fn start(&mut self, packet: &RtpPacket) {
+        self.ssrc = Some(packet.header.ssrc);
+        self.source = Some(packet.source);
+    }
+
+    fn release(&mut self, now: Instant) -> Vec<RtpPacket> {
+        let give_up = self.window * GIVE_UP_WINDOWS;
+        let mut ready = Vec::new();
+        while let (false, Some(next)) = (self.held.is_empty(), self.next) {
+            if let Some(held) = self.held.remove(&next) {
+                ready.push(self.hand_on(next, held));
+            } else if let Some(gap) = self.missing.get(&next) {
+                if now.saturating_duration_since(gap.noticed) < give_up {
+                    break;
+                }
+                self.missing.remove(&next);
+                self.summary.packets_unrepaired += 1;
+            } else {
+                self.summary.packets_unrepaired += 1;
+            }
+            self.next = Some(next.wrapping_add(1));
+        }
+        ready
+    }
+
+    /// A resent packet arrives late; it is given the arrival time it would
+    /// have had, between its neighbours, so it doesn't show up as jitter
+    fn hand_on(&mut self, sequence: u16, held: Held) -> RtpPacket {
+        let mut packet = held.packet;
+        if held.repaired {
+            if let Some(arrival) = self.interpolated_arrival(sequence) {
+                packet.received_at = arrival;
+            }
+        }
+        self.last_released = Some((sequence, packet.received_at));
+        packet
+    }
+
+    fn interpolated_arrival(&self, sequence: u16) -> Option<Instant> {
+        let (previous, previous_at) = self.last_released?;
+        let (after, next_at) = (1..=MAX_NACK_SEQUENCES as u16).find_map(|step| {
+            self.held
+                .get(&sequence.wrapping_add(step))
+                .filter(|held| !held.repaired)
+                .map(|held| (step, held.packet.received_at))
+        })?;
+        let before = u32::from(sequence.wrapping_sub(previous));
+        let span = next_at.saturating_duration_since(previous_at);
+        Some(previous_at + span * before / (before + u32::from(after)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(sequence: u16, arrival_ms: u64, base: Instant) -> RtpPacket {
+        let data = RtpPacket::build(0, sequence, u32::from(sequence) * 160, 42, &[0xFF; 160], false);
+        RtpPacket::parse_with_time(&data, "192.168.1.10:40000".parse().unwrap(), base + Duration::from_millis(arrival_ms))
+            .unwrap()
+    }
+
+    fn sequences(packets: &[RtpPacket]) -> Vec<u16> {
+        packets.iter().map(|packet| packet.header.sequence_number).collect()
+    }
+
+    #[test]
+    fn test_nack_round_trip() {
+        let nack = Nack { ssrc: 0xDEAD_BEEF, sequences: vec![65535, 0, 7] };
+        assert_eq!(Nack::decode(&nack.encode()), Some(nack.clone()));
+        assert_eq!(Nack::decode(&nack.encode()[..11]), None);
+        assert_eq!(Nack::decode(b"RTP?"), None);
+
+        let rtp = RtpPacket::build(0, 9, 1440, 42, &[1, 2, 3], false);
+        assert_eq!(decode_repair(&encode_repair(&rtp)), Some(rtp.as_slice()));
+        assert_eq!(decode_repair(&nack.encode()), None);
+    }
+
+    #[test]
+    fn test_gap_repaired_in_place() {
+        let base = Instant::now();
+        let window = Duration::from_millis(50);
+        let mut buffer = RepairBuffer::new(window);
+
+        assert_eq!(sequences(&buffer.accept(packet(10, 0, base), base)), [10]);
+        assert_eq!(sequences(&buffer.accept(packet(11, 20, base), base)), [11]);
+        // 12 and 13 are lost; 14 and 15 wait for them
+        assert!(buffer.accept(packet(14, 80, base), base + Duration::from_millis(80)).is_empty());
+        assert!(buffer.accept(packet(15, 100, base), base + Duration::from_millis(100)).is_empty());
+        assert_eq!(buffer.nack(base + Duration::from_millis(100)), None, "inside the window");
+
+        let nack = buffer.nack(base + Duration::from_millis(130)).unwrap();
+        assert_eq!(nack, Nack { ssrc: 42, sequences: vec![12, 13] });
+        assert_eq!(buffer.nack(base + Duration::from_millis(140)), None, "each gap is asked for once");
+
+        // 13 comes back first; nothing moves until 12 does
+        assert!(buffer.accept_repair(packet(13, 150, base), base + Duration::from_millis(150)).is_empty());
+        let ready = buffer.accept_repair(packet(12, 155, base), base + Duration::from_millis(155));
+        assert_eq!(sequences(&ready), [12, 13, 14, 15]);
+        // Resent packets get arrival times between their neighbours
+        assert_eq!(ready[0].received_at, base + Duration::from_millis(40));
+        assert_eq!(ready[1].received_at, base + Duration::from_millis(60));
+
+        // A second copy is no longer wanted
+        assert!(buffer.accept_repair(packet(12, 160, base), base + Duration::from_millis(160)).is_empty());
+        assert_eq!(
+            buffer.take_summary(),
+            RepairSummary { nacks_sent: 1, packets_requested: 2, packets_repaired: 2, packets_unrepaired: 0 }
+        );
+    }
+
+    #[test]
+    fn test_gap_given_up() {
+        let base = Instant::now();
+        let mut buffer = RepairBuffer::new(Duration::from_millis(50));
+
+        buffer.accept(packet(65534, 0, base), base);
+        // 65535 and 0 lost, across the wrap
+        assert!(buffer.accept(packet(1, 60, base), base + Duration::from_millis(60)).is_empty());
+        assert!(buffer.nack(base + Duration::from_millis(110)).is_some());
+        assert!(buffer.poll(base + Duration::from_millis(200)).is_empty());
+        assert_eq!(sequences(&buffer.poll(base + Duration::from_millis(260))), [1]);
+
+        // Arriving after all: handed on as a late packet
+        assert_eq!(sequences(&buffer.accept(packet(0, 270, base), base + Duration::from_millis(270))), [0]);
+
+        let summary = buffer.take_summary();
+        assert_eq!((summary.packets_repaired, summary.packets_unrepaired), (0, 2));
+    }
+
+    #[test]
+    fn test_flush_and_new_stream() {
+        let base = Instant::now();
+        let mut buffer = RepairBuffer::new(Duration::from_millis(50));
+        buffer.accept(packet(1, 0, base), base);
+        buffer.accept(packet(3, 40, base), base + Duration::from_millis(40));
+
+        // Another SSRC flushes what was held, giving up the gap
+        let data = RtpPacket::build(0, 500, 0, 43, &[0xFF; 160], false);
+        let other = RtpPacket::parse_with_time(&data, "192.168.1.11:40000".parse().unwrap(), base).unwrap();
+        assert_eq!(sequences(&buffer.accept(other, base + Duration::from_millis(45))), [3, 500]);
+        assert_eq!(buffer.ssrc(), Some(43));
+        assert_eq!(buffer.source(), Some("192.168.1.11:40000".parse().unwrap()));
+        assert_eq!(buffer.take_summary().packets_unrepaired, 1);
+    }
+
+    #[tokio::test]
+    async fn test_server_resends_requested_packets() {
+        let mut server = RepairServer::bind([15310], 2).await.unwrap();
+        for sequence in 0..3u16 {
+            server.record(&RtpPacket::build(0, sequence, u32::from(sequence) * 160, 42, &[0xFF; 160], false));
+        }
+        let client = RepairClient::bind().await.unwrap();
+        let source: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        // 0 has already been pushed out of the two-packet buffer
+        client.send(&Nack { ssrc: 42, sequences: vec![0, 2] }, source, 15310);
+
+        server.linger(Duration::from_millis(50)).await;
+        let stats = server.stats();
+        assert_eq!(
+            stats,
+            RetransmitStats { nacks_received: 1, packets_requested: 2, packets_resent: 1, packets_unavailable: 1 }
+        );
+        let resent = client.try_recv().unwrap();
+        assert_eq!((resent.header.ssrc, resent.header.sequence_number), (42, 2));
+        assert!(client.try_recv().is_none());
+    }
+}
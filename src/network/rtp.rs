@@ -2,6 +2,7 @@
 
 #![allow(dead_code)]
 
+use super::dscp::Dscp;
 use std::net::SocketAddr;
 use std::time::Instant;
 use thiserror::Error;
@@ -53,6 +54,14 @@ pub struct RtpPacket {
     pub payload: Vec<u8>,
     pub received_at: Instant,
     pub source: SocketAddr,
+    /// IP TTL the packet arrived with, where the platform reports it
+    pub ttl: Option<u8>,
+    /// DSCP the packet arrived with, where the platform reports it
+    pub dscp: Option<Dscp>,
+    /// Datagram length, header and padding included
+    pub size: usize,
+    /// Padding after the payload, its count byte included; 0 without
+    pub padding_len: usize,
 }
 
 impl RtpPacket {
@@ -61,14 +70,19 @@ impl RtpPacket {
         Self::parse_with_time(data, source, Instant::now())
     }
 
-    /// Parse an RTP packet with a specific receive time
+    /// Parse an RTP packet with a specific receive time. Every datagram on a
+    /// monitored port comes through here, so any input must come back as an
+    /// error rather than a panic, which would stop every endpoint. The lints
+    /// hold it to checked slicing and arithmetic that can't overflow
+    #[deny(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
     pub fn parse_with_time(data: &[u8], source: SocketAddr, received_at: Instant) -> Result<Self, RtpError> {
-        if data.len() < 12 {
+        let Some((fixed, _)) = data.split_first_chunk::<12>() else {
             return Err(RtpError::TooShort(data.len()));
-        }
+        };
+        let truncated = |expected: usize| RtpError::Truncated { expected, actual: data.len() };
 
         // First byte: V(2) P(1) X(1) CC(4)
-        let first = data[0];
+        let first = fixed[0];
         let version = (first >> 6) & 0x03;
         if version != 2 {
             return Err(RtpError::InvalidVersion(version));
@@ -79,75 +93,57 @@ impl RtpPacket {
         let csrc_count = first & 0x0F;
 
         // Second byte: M(1) PT(7)
-        let second = data[1];
+        let second = fixed[1];
         let marker = (second >> 7) & 0x01 != 0;
         let payload_type = second & 0x7F;
 
         // Sequence number (bytes 2-3)
-        let sequence_number = u16::from_be_bytes([data[2], data[3]]);
+        let sequence_number = u16::from_be_bytes([fixed[2], fixed[3]]);
 
         // Timestamp (bytes 4-7)
-        let timestamp = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        let timestamp = u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
 
         // SSRC (bytes 8-11)
-        let ssrc = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
-
-        // Calculate header length
-        let mut header_len = 12 + (csrc_count as usize * 4);
+        let ssrc = u32::from_be_bytes([fixed[8], fixed[9], fixed[10], fixed[11]]);
 
-        if data.len() < header_len {
-            return Err(RtpError::Truncated {
-                expected: header_len,
-                actual: data.len(),
-            });
-        }
-
-        // Parse CSRC list
-        let mut csrc = Vec::with_capacity(csrc_count as usize);
-        for i in 0..csrc_count as usize {
-            let offset = 12 + i * 4;
-            let val = u32::from_be_bytes([
-                data[offset],
-                data[offset + 1],
-                data[offset + 2],
-                data[offset + 3],
-            ]);
-            csrc.push(val);
-        }
+        // CSRC list. At most 15 entries, so no length here comes near
+        // overflowing; saturating keeps that true without a proof at each step
+        let mut header_len = 12usize.saturating_add(usize::from(csrc_count).saturating_mul(4));
+        let csrc_bytes = data.get(12..header_len).ok_or(truncated(header_len))?;
+        let csrc = csrc_bytes.as_chunks::<4>().0.iter().map(|id| u32::from_be_bytes(*id)).collect();
 
         // Handle extension header
         if extension {
-            if data.len() < header_len + 4 {
-                return Err(RtpError::Truncated {
-                    expected: header_len + 4,
-                    actual: data.len(),
-                });
-            }
             // Extension header: 2 bytes profile, 2 bytes length (in 32-bit words)
-            let ext_length = u16::from_be_bytes([data[header_len + 2], data[header_len + 3]]) as usize * 4;
-            header_len += 4 + ext_length;
+            let ext_start = header_len.saturating_add(4);
+            let ext_header = data.get(header_len..ext_start).ok_or(truncated(ext_start))?;
+            let ext_length = ext_header
+                .last_chunk::<2>()
+                .map_or(0, |length| usize::from(u16::from_be_bytes(*length)).saturating_mul(4));
+            header_len = ext_start.saturating_add(ext_length);
 
             if data.len() < header_len {
-                return Err(RtpError::Truncated {
-                    expected: header_len,
-                    actual: data.len(),
-                });
+                return Err(truncated(header_len));
             }
         }
 
-        // Handle padding
-        // Note: data.len() >= 12 is already validated above, so no empty check needed
-        let payload_end = if padding {
-            let padding_len = data[data.len() - 1] as usize;
-            if padding_len == 0 || padding_len > data.len() - header_len {
+        // Handle padding: the last byte counts the padding, itself included,
+        // and may take the whole payload but no more. `header_len` already
+        // covers any CSRCs and extension, so the payload is what lies between
+        // the two whichever of them a packet has
+        let padding_len = if padding {
+            let padding_len = data.last().map_or(0, |&count| usize::from(count));
+            let payload_len = data.len().saturating_sub(header_len);
+            if padding_len == 0 || padding_len > payload_len {
                 return Err(RtpError::InvalidPadding);
             }
-            data.len() - padding_len
+            padding_len
         } else {
-            data.len()
+            0
         };
+        let payload_end = data.len().saturating_sub(padding_len);
 
-        let payload = data[header_len..payload_end].to_vec();
+        let payload = data.get(header_len..payload_end).ok_or(RtpError::InvalidPadding)?.to_vec();
 
         Ok(RtpPacket {
             header: RtpHeader {
@@ -165,6 +161,10 @@ impl RtpPacket {
             payload,
             received_at,
             source,
+            ttl: None,
+            dscp: None,
+            size: data.len(),
+            padding_len,
         })
     }
 
@@ -293,6 +293,37 @@ impl std::fmt::Display for PayloadType {
     }
 }
 
+/// `sequence` extended to 64 bits with a count of the times it has wrapped,
+/// as RFC 3550 (A.1) extends the highest sequence number. `last` is the
+/// extended number of the packet before; see [`extend_timestamp`]
+pub fn extend_sequence(last: Option<u64>, sequence: u16) -> u64 {
+    extend(last, u64::from(sequence), 16)
+}
+
+/// `timestamp` extended to 64 bits against `last`, the extended timestamp
+/// before it. The value nearest `last` is taken, so a count that wraps keeps
+/// counting up and one from just before the wrap still reads as earlier.
+/// The first value is put in the second cycle, leaving room below it. Also
+/// extends other 32-bit counts, such as Polycom's `sample_count`
+pub fn extend_timestamp(last: Option<u64>, timestamp: u32) -> u64 {
+    extend(last, u64::from(timestamp), 32)
+}
+
+fn extend(last: Option<u64>, value: u64, bits: u32) -> u64 {
+    let cycle = 1u64 << bits;
+    let Some(last) = last else {
+        return cycle | value;
+    };
+    let candidate = (last & !(cycle - 1)) | value;
+    if candidate + cycle / 2 <= last {
+        candidate + cycle
+    } else if candidate > last + cycle / 2 && candidate >= cycle {
+        candidate - cycle
+    } else {
+        candidate
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -322,10 +353,32 @@ mod tests {
         assert_eq!(packet.header.payload_type, 0);
         assert_eq!(packet.header.sequence_number, 1);
         assert_eq!(packet.header.timestamp, 160);
-        assert_eq!(packet.header.ssrc, 0x12345678);
+        assert_eq!(packet.header.ssrc, 0x1234_5678);
         assert_eq!(packet.payload, vec![0xAA, 0xBB]);
     }
 
+    #[test]
+    fn test_extend_across_wrap() {
+        // Up through the wrap, with a late packet from before it
+        let mut last = None;
+        let extended: Vec<u64> = [65534u16, 65535, 0, 65533, 1, 2]
+            .into_iter()
+            .map(|sequence| {
+                let extended = extend_sequence(last, sequence);
+                last = Some(extended);
+                extended
+            })
+            .collect();
+        let base = 65536 + 65534;
+        assert_eq!(extended, [base, base + 1, base + 2, base - 1, base + 3, base + 4]);
+
+        let first = extend_timestamp(None, u32::MAX - 159);
+        let next = extend_timestamp(Some(first), 0);
+        assert_eq!(next - first, 160);
+        assert_eq!(extend_timestamp(Some(next), u32::MAX - 159), first);
+        assert_eq!(next >> 32, 2);
+    }
+
     #[test]
     fn test_parse_with_csrc() {
         // RTP packet with 2 CSRC entries
@@ -342,8 +395,8 @@ mod tests {
         let packet = RtpPacket::parse(&data, test_source()).unwrap();
         assert_eq!(packet.header.csrc_count, 2);
         assert_eq!(packet.header.csrc.len(), 2);
-        assert_eq!(packet.header.csrc[0], 0x11111111);
-        assert_eq!(packet.header.csrc[1], 0x22222222);
+        assert_eq!(packet.header.csrc[0], 0x1111_1111);
+        assert_eq!(packet.header.csrc[1], 0x2222_2222);
         assert_eq!(packet.payload, vec![0xAA]);
     }
 
@@ -376,10 +429,139 @@ mod tests {
         assert!(matches!(result, Err(RtpError::InvalidVersion(0))));
     }
 
+    #[test]
+    fn test_parse_malformed_lengths() {
+        let header = |first: u8| vec![first, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xA0, 0x12, 0x34, 0x56, 0x78];
+
+        // Extension length claiming more than is there, with padding too
+        let mut data = header(0xB0);
+        data.extend_from_slice(&[0xBE, 0xDE, 0x00, 0x10, 0xAA, 0x01]);
+        assert!(matches!(
+            RtpPacket::parse(&data, test_source()),
+            Err(RtpError::Truncated { expected: 80, actual: 18 })
+        ));
+        // The largest extension a packet can claim
+        let mut data = header(0x90);
+        data.extend_from_slice(&[0xBE, 0xDE, 0xFF, 0xFF]);
+        assert!(matches!(
+            RtpPacket::parse(&data, test_source()),
+            Err(RtpError::Truncated { expected: 262_156, actual: 16 })
+        ));
+        // Extension header itself cut short
+        let mut data = header(0x90);
+        data.extend_from_slice(&[0xBE, 0xDE]);
+        assert!(matches!(RtpPacket::parse(&data, test_source()), Err(RtpError::Truncated { expected: 16, .. })));
+
+        // Fifteen CSRCs in a short packet
+        let mut data = header(0x8F);
+        data.extend_from_slice(&[0x11; 20]);
+        assert!(matches!(
+            RtpPacket::parse(&data, test_source()),
+            Err(RtpError::Truncated { expected: 72, actual: 32 })
+        ));
+
+        // Padding taking the whole payload leaves it empty; one more is too many
+        let mut data = header(0xA0);
+        data.extend_from_slice(&[0x00, 0x00, 0x03]);
+        assert!(RtpPacket::parse(&data, test_source()).unwrap().payload.is_empty());
+        *data.last_mut().unwrap() = 4;
+        assert!(matches!(RtpPacket::parse(&data, test_source()), Err(RtpError::InvalidPadding)));
+        // Padding with no payload at all, counted by the SSRC's last byte
+        assert!(matches!(RtpPacket::parse(&header(0xA0), test_source()), Err(RtpError::InvalidPadding)));
+        // ... and padding that would reach back into an extension
+        let mut data = header(0xB0);
+        data.extend_from_slice(&[0xBE, 0xDE, 0x00, 0x01, 0xAA, 0xBB, 0xCC, 0xDD, 0x02]);
+        assert!(matches!(RtpPacket::parse(&data, test_source()), Err(RtpError::InvalidPadding)));
+    }
+
+    /// Bytes from a hex dump, whitespace ignored
+    fn hex(dump: &str) -> Vec<u8> {
+        let digits: Vec<u8> = dump.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        digits.chunks(2).map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap()).collect()
+    }
+
+    /// A 20ms G.711 payload whose first and last bytes stand out, so a
+    /// payload running a byte long or short shows
+    fn ulaw_payload() -> String {
+        format!("7e {} 7d", "ff ".repeat(158))
+    }
+
+    /// Packets laid out as a Cisco IOS gateway sends them: a one-word
+    /// header extension, and the payload padded out to a 4-byte boundary
+    /// and beyond
+    #[test]
+    fn test_parse_extension_with_padding() {
+        let data = hex(&format!(
+            "b0 00 1a 2b 00 00 3e 80 8c 5e 01 00
+             01 00 00 01  00 00 00 2a
+             {}
+             00 00 00 04",
+            ulaw_payload()
+        ));
+        assert_eq!(data.len(), 184);
+        let packet = RtpPacket::parse(&data, test_source()).unwrap();
+        assert!(packet.header.extension && packet.header.padding);
+        assert_eq!((packet.header.sequence_number, packet.header.timestamp, packet.header.ssrc), (0x1a2b, 16_000, 0x8c5e_0100));
+        assert_eq!(packet.payload.len(), 160);
+        assert_eq!((packet.payload[0], packet.payload[159]), (0x7e, 0x7d));
+        assert_eq!((packet.padding_len, packet.size), (4, 184));
+
+        // Padding that doesn't keep to the boundary comes off just the same
+        let data = hex(&format!("b0 00 1a 2c 00 00 3f 20 8c 5e 01 00  01 00 00 01 00 00 00 2a  {}  00 02", ulaw_payload()));
+        let packet = RtpPacket::parse(&data, test_source()).unwrap();
+        assert_eq!((packet.payload.len(), packet.padding_len), (160, 2));
+        assert_eq!(packet.payload.last(), Some(&0x7d));
+    }
+
+    #[test]
+    fn test_parse_csrc_extension_and_padding() {
+        let data = hex(&format!(
+            "b2 80 1a 2b 00 00 3e 80 8c 5e 01 00
+             0a 0b 0c 0d  1a 1b 1c 1d
+             01 00 00 02  00 00 00 2a 00 00 00 2b
+             {}
+             00 00 03",
+            ulaw_payload()
+        ));
+        let packet = RtpPacket::parse(&data, test_source()).unwrap();
+        assert!(packet.header.marker);
+        assert_eq!(packet.header.csrc, [0x0a0b_0c0d, 0x1a1b_1c1d]);
+        assert_eq!(packet.payload.len(), 160);
+        assert_eq!((packet.payload[0], packet.payload[159]), (0x7e, 0x7d));
+        assert_eq!(packet.padding_len, 3);
+    }
+
+    #[test]
+    fn test_parse_padding_takes_whole_payload() {
+        // After a CSRC and an extension, the padding is all there is
+        let data = hex("b1 00 1a 2b 00 00 3e 80 8c 5e 01 00  0a 0b 0c 0d  01 00 00 01 00 00 00 2a  00 00 00 00 00 06");
+        let packet = RtpPacket::parse(&data, test_source()).unwrap();
+        assert!(packet.payload.is_empty());
+        assert_eq!(packet.padding_len, 6);
+        // One more and it would eat into the extension
+        let mut data = data;
+        *data.last_mut().unwrap() = 7;
+        assert!(matches!(RtpPacket::parse(&data, test_source()), Err(RtpError::InvalidPadding)));
+    }
+
+    #[test]
+    fn test_parse_never_panics() {
+        let mut with_everything = vec![0xBF, 0x80, 0x00, 0x01, 0x00, 0x00, 0x00, 0xA0, 0x12, 0x34, 0x56, 0x78];
+        with_everything.extend_from_slice(&[0x11; 60]);
+        with_everything.extend_from_slice(&[0xBE, 0xDE, 0x00, 0x01, 0xAA, 0xBB, 0xCC, 0xDD, 0xAA, 0x00, 0x02]);
+        let valid = [RtpPacket::build(0, 1, 160, 0x1234_5678, &[0xAA; 160], false), with_everything];
+        for data in crate::network::mangled_datagrams(0x5EED, &valid, 20_000) {
+            match RtpPacket::parse(&data, test_source()) {
+                Ok(packet) => assert!(packet.payload.len() <= data.len()),
+                Err(e) => assert!(!e.to_string().is_empty()),
+            }
+        }
+    }
+
     #[test]
     fn test_build_rtp_packet() {
         let payload = vec![0xAA, 0xBB, 0xCC];
-        let packet = RtpPacket::build(0, 1, 160, 0x12345678, &payload, false);
+        let packet = RtpPacket::build(0, 1, 160, 0x1234_5678, &payload, false);
 
         assert_eq!(packet.len(), 12 + 3);
         assert_eq!(packet[0], 0x80); // V=2
@@ -390,13 +572,13 @@ mod tests {
     #[test]
     fn test_roundtrip() {
         let payload = vec![0x01, 0x02, 0x03, 0x04];
-        let built = RtpPacket::build(8, 100, 16000, 0xABCDEF00, &payload, true);
+        let built = RtpPacket::build(8, 100, 16000, 0xABCD_EF00, &payload, true);
         let parsed = RtpPacket::parse(&built, test_source()).unwrap();
 
         assert_eq!(parsed.header.payload_type, 8);
         assert_eq!(parsed.header.sequence_number, 100);
         assert_eq!(parsed.header.timestamp, 16000);
-        assert_eq!(parsed.header.ssrc, 0xABCDEF00);
+        assert_eq!(parsed.header.ssrc, 0xABCD_EF00);
         assert!(parsed.header.marker);
         assert_eq!(parsed.payload, payload);
     }
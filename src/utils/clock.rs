@@ -0,0 +1,214 @@
+//! Noticing when the wall clock is stepped during a run.
+//!
+//! Page durations come from the monotonic clock, but start and end times are
+//! wall-clock times. If NTP or an operator steps the wall clock mid-page, the
+//! two disagree and `end_time - start_time` no longer matches `duration_secs`.
+//! A [`ClockWatch`] reads both clocks together every so often and compares
+//! how far each has moved since the last reading. Slewing moves the wall
+//! clock by at most half a millisecond a second, so a difference past
+//! [`STEP_THRESHOLD`] is a step (or a suspend, which stops the monotonic
+//! clock but not the wall clock).
+
+use chrono::{DateTime, Utc};
+use std::time::{Duration, Instant};
+
+/// Smallest difference between the clocks reported as a step
+pub const STEP_THRESHOLD: Duration = Duration::from_millis(100);
+
+/// A monotonic and a wall-clock reading, so the comparison can be tested
+/// with readings that never happened
+pub trait Clock {
+    fn now(&self) -> Instant;
+    fn utc(&self) -> DateTime<Utc>;
+}
+
+/// The system's clocks
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn utc(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A step in the wall clock, found by [`ClockWatch::check`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockStep {
+    /// Wall-clock time after the step
+    pub at: DateTime<Utc>,
+    /// Seconds the wall clock moved beyond the monotonic clock; negative if
+    /// it went back
+    pub step_secs: f64,
+}
+
+/// Compares the monotonic and wall clocks between readings
+#[derive(Debug)]
+pub struct ClockWatch {
+    threshold: Duration,
+    last: (Instant, DateTime<Utc>),
+    /// Sum of the steps found so far, in seconds
+    total_step_secs: f64,
+    steps: u32,
+}
+
+impl ClockWatch {
+    pub fn new(clock: &impl Clock) -> Self {
+        Self::with_threshold(clock, STEP_THRESHOLD)
+    }
+
+    pub fn with_threshold(clock: &impl Clock, threshold: Duration) -> Self {
+        Self {
+            threshold,
+            last: (clock.now(), clock.utc()),
+            total_step_secs: 0.0,
+            steps: 0,
+        }
+    }
+
+    /// Read both clocks, returning the step if the wall clock has moved
+    /// more than the threshold away from the monotonic clock since the last
+    /// check. Each check starts afresh, so slewing over a long run never
+    /// adds up to a step.
+    pub fn check(&mut self, clock: &impl Clock) -> Option<ClockStep> {
+        let (now, utc) = (clock.now(), clock.utc());
+        let (last_now, last_utc) = std::mem::replace(&mut self.last, (now, utc));
+        let monotonic = now.saturating_duration_since(last_now).as_secs_f64();
+        let wall = (utc - last_utc).num_microseconds().unwrap_or(i64::MAX) as f64 / 1e6;
+        let step_secs = wall - monotonic;
+        if step_secs.abs() < self.threshold.as_secs_f64() {
+            return None;
+        }
+
+        self.total_step_secs += step_secs;
+        self.steps += 1;
+        Some(ClockStep { at: utc, step_secs })
+    }
+
+    /// Sum of the steps found, in seconds; positive if the wall clock ended
+    /// up ahead of where the monotonic clock put it
+    pub fn total_step_secs(&self) -> f64 {
+        self.total_step_secs
+    }
+
+    /// Steps found
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+
+    /// `end` moved onto the monotonic timeline: `start` plus the `elapsed`
+    /// the monotonic clock measured, so a page's times agree with its
+    /// duration whatever the wall clock did meanwhile
+    pub fn reconcile(start: DateTime<Utc>, elapsed: Duration) -> DateTime<Utc> {
+        start + chrono::Duration::from_std(elapsed).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::cell::Cell;
+
+    /// Clocks moved by hand
+    struct FakeClock {
+        start: Instant,
+        monotonic: Cell<Duration>,
+        utc: Cell<DateTime<Utc>>,
+    }
+
+    impl FakeClock {
+        fn new() -> Self {
+            Self {
+                start: Instant::now(),
+                monotonic: Cell::new(Duration::ZERO),
+                utc: Cell::new(Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap()),
+            }
+        }
+
+        /// Both clocks move on together
+        fn advance(&self, by: Duration) {
+            self.monotonic.set(self.monotonic.get() + by);
+            self.utc.set(self.utc.get() + chrono::Duration::from_std(by).unwrap());
+        }
+
+        /// Only the wall clock moves
+        fn step(&self, by: chrono::Duration) {
+            self.utc.set(self.utc.get() + by);
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            self.start + self.monotonic.get()
+        }
+
+        fn utc(&self) -> DateTime<Utc> {
+            self.utc.get()
+        }
+    }
+
+    #[test]
+    fn test_no_step() {
+        let clock = FakeClock::new();
+        let mut watch = ClockWatch::new(&clock);
+        for _ in 0..10 {
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(watch.check(&clock), None);
+        }
+        assert_eq!((watch.steps(), watch.total_step_secs()), (0, 0.0));
+    }
+
+    #[test]
+    fn test_steps_forward_and_back() {
+        let clock = FakeClock::new();
+        let mut watch = ClockWatch::new(&clock);
+        clock.advance(Duration::from_secs(1));
+        clock.step(chrono::Duration::seconds(5));
+        let step = watch.check(&clock).unwrap();
+        assert!((step.step_secs - 5.0).abs() < 1e-6);
+        assert_eq!(step.at, clock.utc());
+
+        clock.advance(Duration::from_secs(1));
+        clock.step(chrono::Duration::milliseconds(-2500));
+        let step = watch.check(&clock).unwrap();
+        assert!((step.step_secs + 2.5).abs() < 1e-6);
+
+        assert_eq!(watch.steps(), 2);
+        assert!((watch.total_step_secs() - 2.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_slew_is_not_a_step() {
+        // 500 ppm, the most NTP slews by, for a minute between checks
+        let clock = FakeClock::new();
+        let mut watch = ClockWatch::new(&clock);
+        clock.advance(Duration::from_mins(1));
+        clock.step(chrono::Duration::milliseconds(30));
+        assert_eq!(watch.check(&clock), None);
+    }
+
+    #[test]
+    fn test_slew_does_not_add_up() {
+        // A day of slewing, checked every second
+        let clock = FakeClock::new();
+        let mut watch = ClockWatch::new(&clock);
+        for _ in 0..86_400 {
+            clock.advance(Duration::from_secs(1));
+            clock.step(chrono::Duration::microseconds(500));
+            assert_eq!(watch.check(&clock), None);
+        }
+        assert_eq!(watch.steps(), 0);
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let start = Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap();
+        let end = ClockWatch::reconcile(start, Duration::from_millis(12_500));
+        assert_eq!(end, Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 12).unwrap() + chrono::Duration::milliseconds(500));
+    }
+}
@@ -0,0 +1,509 @@
+//! Output filename templates.
+//!
+//! A template is a file name with `{token}` fields, for example
+//! `{site}_{endpoint}_{page:04}_{start:%Y%m%dT%H%M%S}.wav`. Numeric tokens
+//! take a zero-padded width (`{page:04}`), `{start}` takes a strftime format
+//! (local time, `%Y%m%d_%H%M%S` by default), and `{{`/`}}` are literal
+//! braces. Every rendered value is sanitized so a caller ID or a format with
+//! `:` in it can't produce a path the filesystem rejects or escapes the
+//! output directory, and the template itself may not contain path
+//! separators.
+//!
+//! Rendered names never overwrite an existing file: a name that is already
+//! taken gets `-1`, `-2`, ... appended to its stem.
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use std::fmt;
+use std::net::SocketAddrV4;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Default strftime format for `{start}`
+const DEFAULT_START_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+/// Characters replaced in rendered values, besides whitespace and controls
+const PATH_HOSTILE: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Rendered in place of a value the page doesn't have
+const MISSING: &str = "unknown";
+
+/// A field that can appear in a template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    /// `--site`, as given
+    Site,
+    /// Multicast group and port, e.g. `224.0.1.1_5004`
+    Endpoint,
+    /// Multicast group
+    Address,
+    Port,
+    /// Page number within the run, from 1
+    Page,
+    /// Page start, local time
+    Start,
+    /// RTP SSRC, as 8 hex digits
+    Ssrc,
+    /// Polycom channel
+    Channel,
+    /// Polycom channel's name, or its number if it has none
+    ChannelName,
+    /// Polycom caller ID
+    Caller,
+}
+
+impl Token {
+    pub const ALL: [Token; 10] = [
+        Token::Site,
+        Token::Endpoint,
+        Token::Address,
+        Token::Port,
+        Token::Page,
+        Token::Start,
+        Token::Ssrc,
+        Token::Channel,
+        Token::ChannelName,
+        Token::Caller,
+    ];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Token::Site => "site",
+            Token::Endpoint => "endpoint",
+            Token::Address => "address",
+            Token::Port => "port",
+            Token::Page => "page",
+            Token::Start => "start",
+            Token::Ssrc => "ssrc",
+            Token::Channel => "channel",
+            Token::ChannelName => "channel_name",
+            Token::Caller => "caller",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|token| token.name() == name)
+    }
+
+    fn takes_width(self) -> bool {
+        matches!(self, Token::Port | Token::Page | Token::Channel)
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{{}}}", self.name())
+    }
+}
+
+/// Every token, for error messages
+fn valid_tokens() -> String {
+    Token::ALL.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum FilenameError {
+    #[error("Unknown token {{{0}}} in name template (valid tokens: {valid})", valid = valid_tokens())]
+    UnknownToken(String),
+
+    #[error("Invalid format {spec:?} for {token} in name template")]
+    InvalidFormat { token: Token, spec: String },
+
+    #[error("Syntax error in name template: {0}")]
+    SyntaxError(String),
+
+    #[error("{token} isn't available in {purpose} names")]
+    Unavailable { token: Token, purpose: &'static str },
+
+    #[error("{{site}} in a name template needs --site")]
+    MissingSite,
+}
+
+/// One piece of a parsed template
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Field { token: Token, width: usize, format: Option<String> },
+}
+
+/// A parsed `--name-template`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameTemplate {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl NameTemplate {
+    /// Tokens the template uses
+    pub fn tokens(&self) -> impl Iterator<Item = Token> + '_ {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Field { token, .. } => Some(*token),
+            Segment::Literal(_) => None,
+        })
+    }
+
+    /// Render a name for one output; missing values render as `unknown`
+    #[must_use]
+    pub fn render(&self, fields: &NameFields) -> String {
+        let mut name = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => name.push_str(text),
+                Segment::Field { token, width, format } => {
+                    let value = fields.value(*token, format.as_deref()).unwrap_or_else(|| MISSING.to_string());
+                    let value = if value.bytes().all(|b| b.is_ascii_digit()) {
+                        format!("{:0>width$}", value, width = *width)
+                    } else {
+                        value
+                    };
+                    name.push_str(&sanitize(&value));
+                }
+            }
+        }
+        name
+    }
+}
+
+impl fmt::Display for NameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = FilenameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(['/', '\\']) {
+            return Err(FilenameError::SyntaxError("a template names a file, so it can't contain / or \\".to_string()));
+        }
+
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err(FilenameError::SyntaxError("unmatched '}' (write }} for a literal brace)".to_string())),
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => return Err(FilenameError::SyntaxError(format!("unclosed '{{{}'", field))),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(parse_field(&field)?);
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        if segments.is_empty() {
+            return Err(FilenameError::SyntaxError("empty template".to_string()));
+        }
+
+        Ok(Self { source: s.to_string(), segments })
+    }
+}
+
+/// Parse the inside of a `{...}` field
+fn parse_field(field: &str) -> Result<Segment, FilenameError> {
+    let (name, spec) = match field.split_once(':') {
+        Some((name, spec)) => (name, Some(spec)),
+        None => (field, None),
+    };
+    let token = Token::from_name(name.trim()).ok_or_else(|| FilenameError::UnknownToken(name.to_string()))?;
+    let invalid = || FilenameError::InvalidFormat { token, spec: spec.unwrap_or_default().to_string() };
+
+    match spec {
+        None => Ok(Segment::Field {
+            token,
+            width: 0,
+            format: (token == Token::Start).then(|| DEFAULT_START_FORMAT.to_string()),
+        }),
+        Some(spec) if token == Token::Start => {
+            if spec.is_empty() || StrftimeItems::new(spec).any(|item| matches!(item, Item::Error)) {
+                return Err(invalid());
+            }
+            Ok(Segment::Field { token, width: 0, format: Some(spec.to_string()) })
+        }
+        Some(spec) if token.takes_width() => {
+            let width = spec.parse().map_err(|_| invalid())?;
+            Ok(Segment::Field { token, width, format: None })
+        }
+        Some(_) => Err(invalid()),
+    }
+}
+
+/// What one output has to fill a template with
+#[derive(Debug, Clone, Default)]
+pub struct NameFields<'a> {
+    pub site: Option<&'a str>,
+    pub endpoint: Option<SocketAddrV4>,
+    pub page: Option<u32>,
+    pub start: Option<DateTime<Local>>,
+    pub ssrc: Option<u32>,
+    pub channel: Option<u8>,
+    pub channel_name: Option<&'a str>,
+    pub caller: Option<&'a str>,
+}
+
+impl NameFields<'_> {
+    fn value(&self, token: Token, format: Option<&str>) -> Option<String> {
+        match token {
+            Token::Site => self.site.map(str::to_string),
+            Token::Endpoint => self.endpoint.map(|ep| format!("{}_{}", ep.ip(), ep.port())),
+            Token::Address => self.endpoint.map(|ep| ep.ip().to_string()),
+            Token::Port => self.endpoint.map(|ep| ep.port().to_string()),
+            Token::Page => self.page.map(|page| page.to_string()),
+            Token::Start => self
+                .start
+                .map(|start| start.format(format.unwrap_or(DEFAULT_START_FORMAT)).to_string()),
+            Token::Ssrc => self.ssrc.map(|ssrc| format!("{:08x}", ssrc)),
+            Token::Channel => self.channel.map(|channel| channel.to_string()),
+            Token::ChannelName => self.channel_name.map(str::to_string),
+            Token::Caller => self.caller.map(str::to_string),
+        }
+    }
+}
+
+/// Template and site for a run's output names
+#[derive(Debug, Clone, Default)]
+pub struct Naming {
+    /// `--name-template`; without it each command keeps its own naming
+    pub template: Option<NameTemplate>,
+    pub site: Option<String>,
+}
+
+impl Naming {
+    /// Fail at startup if the template uses a token `purpose` can't fill
+    pub fn check(&self, purpose: &'static str, available: &[Token]) -> Result<(), FilenameError> {
+        let Some(ref template) = self.template else {
+            return Ok(());
+        };
+        check_tokens(template, purpose, available, self.site.is_some())
+    }
+
+    /// Name for one recording, if there is a template
+    #[must_use]
+    pub fn recording_name(&self, fields: NameFields) -> Option<String> {
+        let fields = NameFields { site: self.site.as_deref(), ..fields };
+        self.template.as_ref().map(|template| template.render(&fields))
+    }
+
+    /// Resolve a capture path whose file name may use `{site}` and
+    /// `{start}`. A name without tokens is used as given.
+    pub fn capture_path(&self, path: &Path, start: DateTime<Local>) -> Result<PathBuf, FilenameError> {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            return Ok(path.to_path_buf());
+        };
+        let template: NameTemplate = name.parse()?;
+        check_tokens(&template, "capture", &[Token::Site, Token::Start], self.site.is_some())?;
+        if template.tokens().next().is_none() {
+            return Ok(path.to_path_buf());
+        }
+
+        let name = template.render(&NameFields { site: self.site.as_deref(), start: Some(start), ..NameFields::default() });
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Ok(dir.join(unique_name(dir, &name)))
+    }
+}
+
+fn check_tokens(template: &NameTemplate, purpose: &'static str, available: &[Token], has_site: bool) -> Result<(), FilenameError> {
+    for token in template.tokens() {
+        if !available.contains(&token) {
+            return Err(FilenameError::Unavailable { token, purpose });
+        }
+        if token == Token::Site && !has_site {
+            return Err(FilenameError::MissingSite);
+        }
+    }
+    Ok(())
+}
+
+/// Make a value safe to use inside a file name
+#[must_use]
+pub fn sanitize(value: &str) -> String {
+    let sanitized: String = value
+        .chars()
+        .map(|c| {
+            if PATH_HOSTILE.contains(&c) || c.is_whitespace() || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    // Keep `.` and `..` from naming a directory
+    if sanitized.chars().all(|c| c == '.') {
+        sanitized.replace('.', "_")
+    } else {
+        sanitized
+    }
+}
+
+/// `name`, or the first of `name-1`, `name-2`, ... not already in `dir`
+#[must_use]
+pub fn unique_name(dir: &Path, name: &str) -> String {
+    if !dir.join(name).exists() {
+        return name.to_string();
+    }
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+        _ => (name, None),
+    };
+    let mut n = 1;
+    loop {
+        let candidate = match extension {
+            Some(extension) => format!("{}-{}.{}", stem, n, extension),
+            None => format!("{}-{}", stem, n),
+        };
+        if !dir.join(&candidate).exists() {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::net::Ipv4Addr;
+
+    fn start() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 10, 12, 8, 30, 5).unwrap()
+    }
+
+    fn render(template: &str, fields: &NameFields) -> String {
+        template.parse::<NameTemplate>().unwrap().render(fields)
+    }
+
+    #[test]
+    fn test_render_monitor_page() {
+        let fields = NameFields {
+            site: Some("HQ"),
+            endpoint: Some(SocketAddrV4::new(Ipv4Addr::new(224, 0, 1, 1), 5004)),
+            page: Some(7),
+            start: Some(start()),
+            ssrc: Some(0xBEEF),
+            ..NameFields::default()
+        };
+
+        assert_eq!(
+            render("{site}_{endpoint}_{page:04}_{start:%Y%m%dT%H%M%S}.wav", &fields),
+            "HQ_224.0.1.1_5004_0007_20261012T083005.wav"
+        );
+        assert_eq!(render("{address}-{port}-{ssrc}-{start}.wav", &fields), "224.0.1.1-5004-0000beef-20261012_083005.wav");
+        assert_eq!(render("{{{page}}}.wav", &fields), "{7}.wav");
+    }
+
+    #[test]
+    fn test_render_polycom_page() {
+        let fields = NameFields {
+            start: Some(start()),
+            channel: Some(26),
+            channel_name: Some("All Call: Lobby"),
+            caller: Some("Front Desk/Lobby"),
+            ..NameFields::default()
+        };
+
+        assert_eq!(render("ch{channel:03}_{caller}_{start:%H:%M}.wav", &fields), "ch026_Front_Desk_Lobby_08_30.wav");
+        assert_eq!(render("{channel_name}.wav", &fields), "All_Call__Lobby.wav");
+        // Nothing to fill a token with
+        assert_eq!(render("{page}_{ssrc}.wav", &fields), "unknown_unknown.wav");
+    }
+
+    #[test]
+    fn test_unknown_token_lists_valid_tokens() {
+        let error = "{site}_{zone}.wav".parse::<NameTemplate>().unwrap_err();
+        assert_eq!(error, FilenameError::UnknownToken("zone".to_string()));
+        let message = error.to_string();
+        assert!(message.contains("{zone}"), "{}", message);
+        for token in Token::ALL {
+            assert!(message.contains(&token.to_string()), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert!(matches!("{page:x4}.wav".parse::<NameTemplate>(), Err(FilenameError::InvalidFormat { .. })));
+        assert!(matches!("{caller:04}.wav".parse::<NameTemplate>(), Err(FilenameError::InvalidFormat { .. })));
+        assert!(matches!("{start:%Q}.wav".parse::<NameTemplate>(), Err(FilenameError::InvalidFormat { .. })));
+        assert!(matches!("{page.wav".parse::<NameTemplate>(), Err(FilenameError::SyntaxError(_))));
+        assert!(matches!("page}.wav".parse::<NameTemplate>(), Err(FilenameError::SyntaxError(_))));
+        assert!(matches!("pages/{page}.wav".parse::<NameTemplate>(), Err(FilenameError::SyntaxError(_))));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("Front Desk"), "Front_Desk");
+        assert_eq!(sanitize("../etc/passwd"), ".._etc_passwd");
+        assert_eq!(sanitize(r#"a\b:c*d?e"f<g>h|i"#), "a_b_c_d_e_f_g_h_i");
+        assert_eq!(sanitize("tab\there\n"), "tab_here_");
+        assert_eq!(sanitize(".."), "__");
+        assert_eq!(sanitize("Zoë-1.2"), "Zoë-1.2");
+    }
+
+    #[test]
+    fn test_collision_suffix() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(unique_name(dir.path(), "page.wav"), "page.wav");
+
+        std::fs::write(dir.path().join("page.wav"), b"").unwrap();
+        assert_eq!(unique_name(dir.path(), "page.wav"), "page-1.wav");
+
+        std::fs::write(dir.path().join("page-1.wav"), b"").unwrap();
+        assert_eq!(unique_name(dir.path(), "page.wav"), "page-2.wav");
+
+        std::fs::write(dir.path().join("capture"), b"").unwrap();
+        assert_eq!(unique_name(dir.path(), "capture"), "capture-1");
+    }
+
+    #[test]
+    fn test_check_tokens() {
+        let naming = Naming { template: Some("{caller}_{page}.wav".parse().unwrap()), site: None };
+        assert_eq!(
+            naming.check("monitor recording", &[Token::Page]),
+            Err(FilenameError::Unavailable { token: Token::Caller, purpose: "monitor recording" })
+        );
+        assert_eq!(naming.check("Polycom recording", &[Token::Page, Token::Caller]), Ok(()));
+
+        let naming = Naming { template: Some("{site}.wav".parse().unwrap()), site: None };
+        assert_eq!(naming.check("monitor recording", &Token::ALL), Err(FilenameError::MissingSite));
+    }
+
+    #[test]
+    fn test_capture_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let naming = Naming { template: None, site: Some("HQ".to_string()) };
+
+        let plain = dir.path().join("capture.pcap");
+        std::fs::write(&plain, b"").unwrap();
+        assert_eq!(naming.capture_path(&plain, start()).unwrap(), plain);
+
+        let templated = dir.path().join("{site}_{start:%Y%m%d}.pcap");
+        assert_eq!(naming.capture_path(&templated, start()).unwrap(), dir.path().join("HQ_20261012.pcap"));
+        std::fs::write(dir.path().join("HQ_20261012.pcap"), b"").unwrap();
+        assert_eq!(naming.capture_path(&templated, start()).unwrap(), dir.path().join("HQ_20261012-1.pcap"));
+
+        let per_page = dir.path().join("{page}.pcap");
+        assert!(matches!(naming.capture_path(&per_page, start()), Err(FilenameError::Unavailable { .. })));
+    }
+}
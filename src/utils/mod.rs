@@ -1 +1,7 @@
+pub mod clock;
+pub mod filename;
 pub mod range_parser;
+pub mod realtime;
+pub mod schedule;
+pub mod sequence;
+pub mod streaming;
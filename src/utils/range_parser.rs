@@ -2,7 +2,11 @@
 
 #![allow(dead_code)]
 
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::net::Ipv4Addr;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -30,6 +34,18 @@ pub enum RangeParseError {
 
     #[error("Syntax error: {0}")]
     SyntaxError(String),
+
+    #[error("Invalid endpoint option: {0}")]
+    InvalidOption(String),
+}
+
+/// An error on one line of an endpoints file
+#[derive(Error, Debug, PartialEq)]
+#[error("line {line}: {source}")]
+pub struct EndpointListError {
+    /// 1-based line number
+    pub line: usize,
+    pub source: RangeParseError,
 }
 
 /// A parsed multicast endpoint
@@ -145,6 +161,121 @@ fn parse_range_component(s: &str) -> Result<Vec<u32>, RangeParseError> {
     }
 }
 
+/// Settings for one endpoint that take the place of the command-wide ones,
+/// given as `key=value` after its pattern:
+///
+/// - `idle=10s` - silence that ends a page
+/// - `min_packets=3` - packets a new stream needs to become a page
+/// - `min_duration=200ms` - span a new stream needs to become a page
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct EndpointOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_packets: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_duration_ms: Option<u64>,
+}
+
+impl EndpointOptions {
+    /// Whether nothing is overridden
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn idle(&self) -> Option<Duration> {
+        self.idle_ms.map(Duration::from_millis)
+    }
+
+    pub fn min_duration(&self) -> Option<Duration> {
+        self.min_duration_ms.map(Duration::from_millis)
+    }
+
+    /// Apply one `key=value` option
+    fn set(&mut self, option: &str) -> Result<(), RangeParseError> {
+        let invalid = |reason: &str| RangeParseError::InvalidOption(format!("{}: {}", option, reason));
+        let Some((key, value)) = option.split_once('=') else {
+            return Err(invalid("expected key=value"));
+        };
+        match key {
+            "idle" => match parse_millis(value) {
+                Some(0) => return Err(invalid("must be longer than 0ms")),
+                Some(ms) => self.idle_ms = Some(ms),
+                None => return Err(invalid("expected a duration such as 10s or 500ms")),
+            },
+            "min_packets" => match value.parse() {
+                Ok(0) | Err(_) => return Err(invalid("expected a whole number of at least 1")),
+                Ok(packets) => self.min_packets = Some(packets),
+            },
+            "min_duration" => {
+                self.min_duration_ms =
+                    Some(parse_millis(value).ok_or_else(|| invalid("expected a duration such as 10s or 500ms"))?);
+            }
+            _ => return Err(invalid("unknown option (expected idle, min_packets or min_duration)")),
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for EndpointOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = |ms: u64| {
+            if ms.is_multiple_of(1000) {
+                format!("{}s", ms / 1000)
+            } else {
+                format!("{}ms", ms)
+            }
+        };
+        let mut options = Vec::new();
+        if let Some(ms) = self.idle_ms {
+            options.push(format!("idle={}", millis(ms)));
+        }
+        if let Some(packets) = self.min_packets {
+            options.push(format!("min_packets={}", packets));
+        }
+        if let Some(ms) = self.min_duration_ms {
+            options.push(format!("min_duration={}", millis(ms)));
+        }
+        f.write_str(&options.join(" "))
+    }
+}
+
+/// A whole number of `s` or `ms`
+fn parse_millis(text: &str) -> Option<u64> {
+    if let Some(ms) = text.strip_suffix("ms") {
+        ms.parse().ok()
+    } else {
+        text.strip_suffix('s')?.parse::<u64>().ok()?.checked_mul(1000)
+    }
+}
+
+/// Split an endpoint specification into its pattern and options, e.g.
+/// `224.0.2.5:5004 idle=10s min_packets=3`
+pub fn parse_endpoint_spec(spec: &str) -> Result<(&str, EndpointOptions), RangeParseError> {
+    let mut parts = spec.split_whitespace();
+    let pattern = parts.next().ok_or(RangeParseError::InvalidIpFormat)?;
+    let mut options = EndpointOptions::default();
+    for option in parts {
+        options.set(option)?;
+    }
+    Ok((pattern, options))
+}
+
+/// The specifications in an endpoints file with the line each is on,
+/// skipping blank lines and `#` comments
+pub fn parse_endpoint_list(content: &str) -> Result<Vec<(usize, &str, EndpointOptions)>, EndpointListError> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, spec)| {
+            let (pattern, options) = parse_endpoint_spec(spec).map_err(|source| EndpointListError { line, source })?;
+            Ok((line, pattern, options))
+        })
+        .collect()
+}
+
 /// Count the number of endpoints that would be generated by a pattern
 pub fn count_endpoints(pattern: &str) -> Result<usize, RangeParseError> {
     let pattern = pattern.trim();
@@ -255,4 +386,41 @@ mod tests {
         };
         assert_eq!(format!("{}", endpoint), "224.0.1.1:5004");
     }
+
+    #[test]
+    fn test_parse_endpoint_options() {
+        let (pattern, options) = parse_endpoint_spec("224.0.2.5:5004 idle=10s min_packets=3 min_duration=250ms").unwrap();
+        assert_eq!(pattern, "224.0.2.5:5004");
+        assert_eq!(options.idle(), Some(Duration::from_secs(10)));
+        assert_eq!(options.min_packets, Some(3));
+        assert_eq!(options.min_duration(), Some(Duration::from_millis(250)));
+        assert_eq!(options.to_string(), "idle=10s min_packets=3 min_duration=250ms");
+
+        let (_, options) = parse_endpoint_spec("224.0.2.5:5004").unwrap();
+        assert!(options.is_empty());
+
+        for bad in ["idle=10", "idle=0s", "min_packets=0", "loud=yes", "idle"] {
+            let spec = format!("224.0.2.5:5004 {}", bad);
+            assert!(
+                matches!(parse_endpoint_spec(&spec), Err(RangeParseError::InvalidOption(ref message)) if message.starts_with(bad)),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_endpoint_list_line_numbers() {
+        let list = parse_endpoint_list("# zones\n224.0.1.1:5004\n\n224.0.1.2:5004 idle=8s\n").unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!((list[1].0, list[1].1), (4, "224.0.1.2:5004"));
+        assert_eq!(list[1].2.idle_ms, Some(8000));
+
+        let err = parse_endpoint_list("224.0.1.1:5004\n# next\n224.0.1.2:5004 idle=soon\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert_eq!(
+            err.to_string(),
+            "line 3: Invalid endpoint option: idle=soon: expected a duration such as 10s or 500ms"
+        );
+    }
 }
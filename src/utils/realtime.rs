@@ -0,0 +1,71 @@
+//! Soft real-time scheduling for timing-sensitive runs.
+//!
+//! On a loaded probe box the measuring process itself gets descheduled for
+//! milliseconds at a time, and that shows up as jitter that was never on the
+//! wire. `--realtime` moves every thread of the process to `SCHED_FIFO` at a
+//! modest priority, so it preempts ordinary work but not the kernel's own
+//! real-time threads, and locks its memory so a page fault can't stall a
+//! receive. Threads started later (tokio's blocking pool) inherit the policy
+//! from the thread that creates them.
+//!
+//! Both need privileges (`CAP_SYS_NICE` and a large enough `RLIMIT_MEMLOCK`,
+//! or root); without them the run carries on at normal priority. Linux only.
+
+use std::io;
+
+/// `SCHED_FIFO` priority used, out of 1-99
+pub const FIFO_PRIORITY: i32 = 10;
+
+/// What `--realtime` managed to change
+#[derive(Debug)]
+pub struct RealtimeStatus {
+    /// Threads moved to `SCHED_FIFO`
+    pub scheduler: io::Result<usize>,
+    pub memory_locked: io::Result<()>,
+}
+
+/// Switch the process to real-time scheduling and lock its memory
+#[cfg(target_os = "linux")]
+pub fn enable() -> RealtimeStatus {
+    RealtimeStatus {
+        scheduler: set_fifo_all_threads(),
+        // SAFETY: mlockall takes flags only
+        memory_locked: if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        },
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn enable() -> RealtimeStatus {
+    let unsupported = || io::Error::new(io::ErrorKind::Unsupported, "only supported on Linux");
+    RealtimeStatus {
+        scheduler: Err(unsupported()),
+        memory_locked: Err(unsupported()),
+    }
+}
+
+/// `sched_setscheduler` applies to one thread, so walk them all
+#[cfg(target_os = "linux")]
+fn set_fifo_all_threads() -> io::Result<usize> {
+    let param = libc::sched_param { sched_priority: FIFO_PRIORITY };
+    let mut threads = 0;
+    for entry in std::fs::read_dir("/proc/self/task")? {
+        let Some(tid) = entry?.file_name().to_str().and_then(|name| name.parse::<libc::pid_t>().ok()) else {
+            continue;
+        };
+        // SAFETY: `param` outlives the call
+        if unsafe { libc::sched_setscheduler(tid, libc::SCHED_FIFO, std::ptr::addr_of!(param)) } != 0 {
+            let error = io::Error::last_os_error();
+            // A thread that exited since the listing doesn't matter
+            if error.raw_os_error() == Some(libc::ESRCH) {
+                continue;
+            }
+            return Err(error);
+        }
+        threads += 1;
+    }
+    Ok(threads)
+}
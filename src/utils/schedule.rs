@@ -0,0 +1,359 @@
+//! Allowed-window schedules for gating transmissions by local time.
+//!
+//! A window is written as `[DAYS ]HH:MM-HH:MM`, for example `08:00-17:00`,
+//! `Mon-Fri 08:00-17:00` or `Sat,Sun 10:00-12:00`. Day ranges may wrap
+//! (`Fri-Mon`), and `24:00` is accepted as an end time. A window whose end is
+//! before its start runs overnight (`22:00-06:00`) and belongs to the day it
+//! starts on, so `Fri 22:00-06:00` covers Friday night into Saturday morning.
+//!
+//! Windows are evaluated against local wall-clock time, which has two
+//! consequences around DST changes:
+//! - Spring forward: wall-clock times in the skipped hour never occur, so a
+//!   window starting inside it opens when the clock jumps past its start.
+//! - Fall back: the repeated hour is seen twice, so a window ending inside it
+//!   closes at the first pass and opens again for the second.
+
+use chrono::{Datelike, Duration, NaiveDateTime, Timelike, Weekday};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ScheduleError {
+    #[error("Invalid day: {0} (expected Mon, Tue, Wed, Thu, Fri, Sat or Sun)")]
+    InvalidDay(String),
+
+    #[error("Invalid time: {0} (expected HH:MM)")]
+    InvalidTime(String),
+
+    #[error("Window {0} has the same start and end time")]
+    EmptyWindow(String),
+
+    #[error("Syntax error: {0}")]
+    SyntaxError(String),
+}
+
+/// A daily time window, optionally restricted to certain days of the week
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeWindow {
+    /// Bitmask of allowed start days, bit 0 = Monday
+    days: u8,
+    /// Start, in minutes after midnight
+    start: u16,
+    /// End, in minutes after midnight (up to 24:00)
+    end: u16,
+}
+
+impl TimeWindow {
+    const ALL_DAYS: u8 = 0x7F;
+
+    fn starts_on(&self, day: Weekday) -> bool {
+        self.days & (1 << day.num_days_from_monday()) != 0
+    }
+
+    fn is_overnight(&self) -> bool {
+        self.end < self.start
+    }
+
+    /// Whether the window is open at the given local time
+    #[must_use]
+    pub fn contains(&self, time: NaiveDateTime) -> bool {
+        let secs = time.num_seconds_from_midnight();
+        let start = u32::from(self.start) * 60;
+        let end = u32::from(self.end) * 60;
+        let today = time.weekday();
+
+        if self.is_overnight() {
+            (self.starts_on(today) && secs >= start) || (self.starts_on(today.pred()) && secs < end)
+        } else {
+            self.starts_on(today) && secs >= start && secs < end
+        }
+    }
+
+    /// The first time at or after `time` that the window opens
+    fn next_start(&self, time: NaiveDateTime) -> NaiveDateTime {
+        let start = time.date().and_hms_opt(0, 0, 0).unwrap() + Duration::minutes(i64::from(self.start));
+        (0..=7)
+            .map(|offset| start + Duration::days(offset))
+            .find(|candidate| *candidate >= time && self.starts_on(candidate.weekday()))
+            .expect("every window starts at least once a week")
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = ScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let (days, times) = match parts.as_slice() {
+            [times] => (Self::ALL_DAYS, *times),
+            [days, times] => (parse_days(days)?, *times),
+            _ => return Err(ScheduleError::SyntaxError(format!("expected [DAYS ]HH:MM-HH:MM, got {:?}", s))),
+        };
+
+        let (start, end) = times
+            .split_once('-')
+            .ok_or_else(|| ScheduleError::SyntaxError(format!("missing '-' in {:?}", times)))?;
+        let start = parse_time(start)?;
+        let end = parse_time(end)?;
+
+        if start == MINUTES_PER_DAY {
+            return Err(ScheduleError::InvalidTime("24:00".to_string()));
+        }
+        if start == end {
+            return Err(ScheduleError::EmptyWindow(s.trim().to_string()));
+        }
+
+        Ok(Self { days, start, end })
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.days != Self::ALL_DAYS {
+            let days: Vec<String> = WEEKDAYS
+                .iter()
+                .filter(|day| self.starts_on(**day))
+                .map(ToString::to_string)
+                .collect();
+            write!(f, "{} ", days.join(","))?;
+        }
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+/// A set of allowed windows; an empty schedule is always open
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schedule {
+    windows: Vec<TimeWindow>,
+}
+
+impl Schedule {
+    #[must_use]
+    pub fn new(windows: Vec<TimeWindow>) -> Self {
+        Self { windows }
+    }
+
+    /// Whether any windows are configured
+    #[must_use]
+    pub fn is_restricted(&self) -> bool {
+        !self.windows.is_empty()
+    }
+
+    /// Whether transmission is allowed at the given local time
+    #[must_use]
+    pub fn is_open(&self, time: NaiveDateTime) -> bool {
+        self.windows.is_empty() || self.windows.iter().any(|w| w.contains(time))
+    }
+
+    /// The next time at or after `time` that the schedule is open
+    #[must_use]
+    pub fn next_open(&self, time: NaiveDateTime) -> NaiveDateTime {
+        if self.is_open(time) {
+            return time;
+        }
+        self.windows
+            .iter()
+            .map(|w| w.next_start(time))
+            .min()
+            .unwrap_or(time)
+    }
+}
+
+impl From<Vec<TimeWindow>> for Schedule {
+    fn from(windows: Vec<TimeWindow>) -> Self {
+        Self::new(windows)
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let windows: Vec<String> = self.windows.iter().map(ToString::to_string).collect();
+        write!(f, "{}", windows.join(", "))
+    }
+}
+
+/// Parse `HH:MM` into minutes after midnight (24:00 allowed)
+fn parse_time(s: &str) -> Result<u16, ScheduleError> {
+    let invalid = || ScheduleError::InvalidTime(s.to_string());
+    let (hours, minutes) = s.trim().split_once(':').ok_or_else(invalid)?;
+    let hours: u16 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u16 = minutes.parse().map_err(|_| invalid())?;
+
+    if minutes >= 60 || hours > 24 || (hours == 24 && minutes != 0) {
+        return Err(invalid());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+/// Parse a comma-separated list of days and day ranges into a bitmask
+fn parse_days(s: &str) -> Result<u8, ScheduleError> {
+    let mut mask = 0u8;
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let mut day = parse_day(first)?;
+                let last = parse_day(last)?;
+                // Ranges may wrap past Sunday, e.g. Fri-Mon
+                loop {
+                    mask |= 1 << day.num_days_from_monday();
+                    if day == last {
+                        break;
+                    }
+                    day = day.succ();
+                }
+            }
+            None => mask |= 1 << parse_day(part)?.num_days_from_monday(),
+        }
+    }
+    Ok(mask)
+}
+
+fn parse_day(s: &str) -> Result<Weekday, ScheduleError> {
+    let s = s.trim();
+    // chrono accepts both short (Mon) and full (Monday) names, case-insensitively
+    s.parse::<Weekday>().map_err(|_| ScheduleError::InvalidDay(s.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    /// 2026-10-12 is a Monday
+    fn at(day_of_month: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, day_of_month)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn schedule_of(windows: &[&str]) -> Schedule {
+        windows.iter().map(|w| w.parse().unwrap()).collect::<Vec<TimeWindow>>().into()
+    }
+
+    #[test]
+    fn test_parse_window() {
+        let window: TimeWindow = "08:00-17:00".parse().unwrap();
+        assert_eq!(window, TimeWindow { days: TimeWindow::ALL_DAYS, start: 480, end: 1020 });
+        assert_eq!(window.to_string(), "08:00-17:00");
+
+        let window: TimeWindow = "Mon-Fri 08:00-17:30".parse().unwrap();
+        assert_eq!(window.days, 0b001_1111);
+        assert_eq!(window.to_string(), "Mon,Tue,Wed,Thu,Fri 08:00-17:30");
+
+        let window: TimeWindow = "sat,Sunday 00:00-24:00".parse().unwrap();
+        assert_eq!(window.days, 0b110_0000);
+        assert_eq!(window.end, MINUTES_PER_DAY);
+
+        // Wrapping day range
+        let window: TimeWindow = "Fri-Mon 09:00-10:00".parse().unwrap();
+        assert_eq!(window.days, 0b111_0001);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!("25:00-26:00".parse::<TimeWindow>(), Err(ScheduleError::InvalidTime("25:00".into())));
+        assert_eq!("08:60-09:00".parse::<TimeWindow>(), Err(ScheduleError::InvalidTime("08:60".into())));
+        assert_eq!("24:00-08:00".parse::<TimeWindow>(), Err(ScheduleError::InvalidTime("24:00".into())));
+        assert_eq!("Funday 08:00-09:00".parse::<TimeWindow>(), Err(ScheduleError::InvalidDay("Funday".into())));
+        assert_eq!("08:00-08:00".parse::<TimeWindow>(), Err(ScheduleError::EmptyWindow("08:00-08:00".into())));
+        assert!(matches!("08:00".parse::<TimeWindow>(), Err(ScheduleError::SyntaxError(_))));
+        assert!(matches!("Mon Tue 08:00-09:00".parse::<TimeWindow>(), Err(ScheduleError::SyntaxError(_))));
+    }
+
+    #[test]
+    fn test_daytime_window() {
+        let schedule = schedule_of(&["Mon-Fri 08:00-17:00"]);
+        assert!(!schedule.is_open(at(12, 7, 59)));
+        assert!(schedule.is_open(at(12, 8, 0)));
+        assert!(schedule.is_open(at(12, 16, 59)));
+        // End is exclusive
+        assert!(!schedule.is_open(at(12, 17, 0)));
+        // Saturday
+        assert!(!schedule.is_open(at(17, 12, 0)));
+    }
+
+    #[test]
+    fn test_overnight_window() {
+        let schedule = schedule_of(&["Fri 22:00-06:00"]);
+        // Friday evening and the small hours of Saturday
+        assert!(schedule.is_open(at(16, 22, 0)));
+        assert!(schedule.is_open(at(17, 5, 59)));
+        assert!(!schedule.is_open(at(17, 6, 0)));
+        // Friday morning belongs to a Thursday-night window, which isn't allowed
+        assert!(!schedule.is_open(at(16, 5, 0)));
+        assert!(!schedule.is_open(at(17, 22, 0)));
+
+        let nightly = schedule_of(&["22:00-06:00"]);
+        assert!(nightly.is_open(at(12, 23, 30)));
+        assert!(nightly.is_open(at(13, 0, 30)));
+        assert!(!nightly.is_open(at(13, 12, 0)));
+    }
+
+    #[test]
+    fn test_multiple_windows() {
+        let schedule = schedule_of(&["Mon-Fri 07:00-08:00", "Mon-Fri 15:30-16:00", "Sat 10:00-11:00"]);
+        assert!(schedule.is_open(at(13, 7, 30)));
+        assert!(!schedule.is_open(at(13, 12, 0)));
+        assert!(schedule.is_open(at(13, 15, 45)));
+        assert!(schedule.is_open(at(17, 10, 15)));
+        assert!(!schedule.is_open(at(18, 10, 15)));
+    }
+
+    #[test]
+    fn test_next_open() {
+        let schedule = schedule_of(&["Mon-Fri 08:00-17:00", "Sat 10:00-11:00"]);
+
+        // Already open
+        assert_eq!(schedule.next_open(at(12, 9, 0)), at(12, 9, 0));
+        // Later the same day
+        assert_eq!(schedule.next_open(at(12, 6, 0)), at(12, 8, 0));
+        // After hours: next morning
+        assert_eq!(schedule.next_open(at(12, 18, 0)), at(13, 8, 0));
+        // Friday evening: Saturday's short window comes first
+        assert_eq!(schedule.next_open(at(16, 18, 0)), at(17, 10, 0));
+        // Saturday afternoon: wait for Monday
+        assert_eq!(schedule.next_open(at(17, 12, 0)), at(19, 8, 0));
+
+        // A single-day window a week away
+        let weekly = schedule_of(&["Mon 08:00-09:00"]);
+        assert_eq!(weekly.next_open(at(12, 9, 0)), at(19, 8, 0));
+    }
+
+    #[test]
+    fn test_empty_schedule_always_open() {
+        let schedule = Schedule::default();
+        assert!(!schedule.is_restricted());
+        assert!(schedule.is_open(at(12, 3, 0)));
+        assert_eq!(schedule.next_open(at(12, 3, 0)), at(12, 3, 0));
+    }
+
+    #[test]
+    fn test_dst_skipped_hour() {
+        // Wall-clock evaluation: on a spring-forward night 02:00-02:59 never
+        // occurs, so a 02:30 window start is first seen as open at 03:00
+        let schedule = schedule_of(&["02:30-04:00"]);
+        assert!(!schedule.is_open(at(12, 1, 59)));
+        assert!(schedule.is_open(at(12, 3, 0)));
+    }
+}
@@ -0,0 +1,400 @@
+//! Test tone sequences for commissioning.
+//!
+//! A sequence is a list of segments separated by `;` or newlines, for
+//! example `tone:1000:10s:-20db; sweep:100-8000:15s:-12db; silence:5s`:
+//! - `tone:FREQ:DURATION:LEVEL` - a sine at FREQ Hz
+//! - `sweep:FROM-TO:DURATION:LEVEL` - a logarithmic sweep from FROM to TO Hz
+//! - `silence:DURATION`
+//!
+//! Frequencies may end in `hz` or `khz`, durations take `s` or `ms`, and
+//! levels are dBFS (peak of the sine) written with `db` or `dbfs`. `#`
+//! starts a comment, and `@FILE` reads the spec from a file.
+//!
+//! Each segment starts on the sample nearest its nominal start time, so
+//! boundaries don't drift over a long sequence, and tones and sweeps fade in
+//! and out over `RAMP_MS` so segment joins don't click.
+
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Fade at the start and end of each tone or sweep
+pub const RAMP_MS: f64 = 5.0;
+
+/// Longest sequence synthesized, to keep the PCM in memory reasonable
+const MAX_SEQUENCE_SECS: f64 = 3600.0;
+
+#[derive(Error, Debug)]
+pub enum SequenceError {
+    #[error("Segment {index} ({text:?}): {problem}")]
+    Segment { index: usize, text: String, problem: String },
+
+    #[error("Sequence has no segments")]
+    Empty,
+
+    #[error("Sequence is {0:.0}s long; the limit is {MAX_SEQUENCE_SECS:.0}s")]
+    TooLong(f64),
+
+    #[error("Segment {index}: {freq_hz} Hz can't be sent at {sample_rate} Hz (the limit is {} Hz)", sample_rate / 2)]
+    AboveNyquist { index: usize, freq_hz: f64, sample_rate: u32 },
+
+    #[error("Unable to read sequence file {path}: {error}")]
+    Io { path: String, error: std::io::Error },
+}
+
+/// One part of a sequence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Segment {
+    Tone { freq_hz: f64, secs: f64, level_db: f64 },
+    Sweep { from_hz: f64, to_hz: f64, secs: f64, level_db: f64 },
+    Silence { secs: f64 },
+}
+
+impl Segment {
+    pub fn secs(&self) -> f64 {
+        match *self {
+            Segment::Tone { secs, .. } | Segment::Sweep { secs, .. } | Segment::Silence { secs } => secs,
+        }
+    }
+
+    /// Highest frequency the segment reaches
+    fn max_freq_hz(&self) -> f64 {
+        match *self {
+            Segment::Tone { freq_hz, .. } => freq_hz,
+            Segment::Sweep { from_hz, to_hz, .. } => from_hz.max(to_hz),
+            Segment::Silence { .. } => 0.0,
+        }
+    }
+
+    /// Sample `n` of `len`, before fading
+    fn sample(&self, n: usize, sample_rate: f64) -> f64 {
+        let t = n as f64 / sample_rate;
+        match *self {
+            Segment::Tone { freq_hz, level_db, .. } => amplitude(level_db) * (2.0 * PI * freq_hz * t).sin(),
+            Segment::Sweep { from_hz, to_hz, secs, level_db } => {
+                // Exponential sweep: the phase is the integral of from * (to/from)^(t/secs)
+                let k = (to_hz / from_hz).ln();
+                let phase = if k.abs() < f64::EPSILON {
+                    2.0 * PI * from_hz * t
+                } else {
+                    2.0 * PI * from_hz * secs / k * ((k * t / secs).exp() - 1.0)
+                };
+                amplitude(level_db) * phase.sin()
+            }
+            Segment::Silence { .. } => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Segment::Tone { freq_hz, secs, level_db } => {
+                write!(f, "tone    {} Hz for {:.3}s at {:.1} dBFS", freq_hz, secs, level_db)
+            }
+            Segment::Sweep { from_hz, to_hz, secs, level_db } => {
+                write!(f, "sweep   {}-{} Hz (log) over {:.3}s at {:.1} dBFS", from_hz, to_hz, secs, level_db)
+            }
+            Segment::Silence { secs } => write!(f, "silence {:.3}s", secs),
+        }
+    }
+}
+
+/// Peak sample value for a level in dBFS
+fn amplitude(level_db: f64) -> f64 {
+    f64::from(i16::MAX) * 10f64.powf(level_db / 20.0)
+}
+
+/// A parsed `--sequence`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sequence {
+    pub segments: Vec<Segment>,
+}
+
+impl Sequence {
+    /// Total length in seconds
+    pub fn secs(&self) -> f64 {
+        self.segments.iter().map(Segment::secs).sum()
+    }
+
+    /// Synthesize the whole sequence as mono PCM
+    pub fn synthesize(&self, sample_rate: u32) -> Result<Vec<i16>, SequenceError> {
+        for (i, segment) in self.segments.iter().enumerate() {
+            let freq_hz = segment.max_freq_hz();
+            if freq_hz >= f64::from(sample_rate) / 2.0 {
+                return Err(SequenceError::AboveNyquist { index: i + 1, freq_hz, sample_rate });
+            }
+        }
+
+        let rate = f64::from(sample_rate);
+        let ramp = (RAMP_MS / 1000.0 * rate).round() as usize;
+        let total = (self.secs() * rate).round() as usize;
+        let mut samples = Vec::with_capacity(total);
+        let mut start_secs = 0.0;
+        for segment in &self.segments {
+            // Boundaries from the running time, not summed lengths, so rounding never accumulates
+            let end_secs = start_secs + segment.secs();
+            let len = (end_secs * rate).round() as usize - (start_secs * rate).round() as usize;
+            let ramp = ramp.min(len / 2);
+            samples.extend((0..len).map(|n| {
+                let from_edge = n.min(len - 1 - n);
+                let gain = if from_edge < ramp {
+                    // Raised cosine from 0 to 1
+                    0.5 - 0.5 * (PI * from_edge as f64 / ramp as f64).cos()
+                } else {
+                    1.0
+                };
+                (segment.sample(n, rate) * gain).round() as i16
+            }));
+            start_secs = end_secs;
+        }
+        Ok(samples)
+    }
+}
+
+/// The parsed plan, as printed by `--describe`
+impl fmt::Display for Sequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Sequence: {} segment(s), {:.3}s", self.segments.len(), self.secs())?;
+        let mut start = 0.0;
+        for (i, segment) in self.segments.iter().enumerate() {
+            writeln!(f, "  {:>3}  {:>9.3}s  {}", i + 1, start, segment)?;
+            start += segment.secs();
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Sequence {
+    type Err = SequenceError;
+
+    /// Parse a spec, or read one from `@FILE`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix('@') {
+            let spec = std::fs::read_to_string(path).map_err(|error| SequenceError::Io { path: path.to_string(), error })?;
+            return parse_spec(&spec);
+        }
+        parse_spec(s)
+    }
+}
+
+fn parse_spec(spec: &str) -> Result<Sequence, SequenceError> {
+    let segments = spec
+        .lines()
+        .map(|line| line.split_once('#').map_or(line, |(before, _)| before))
+        .flat_map(|line| line.split(';'))
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .enumerate()
+        .map(|(i, text)| {
+            parse_segment(text).map_err(|problem| SequenceError::Segment {
+                index: i + 1,
+                text: text.to_string(),
+                problem,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if segments.is_empty() {
+        return Err(SequenceError::Empty);
+    }
+    let sequence = Sequence { segments };
+    if sequence.secs() > MAX_SEQUENCE_SECS {
+        return Err(SequenceError::TooLong(sequence.secs()));
+    }
+    Ok(sequence)
+}
+
+fn parse_segment(text: &str) -> Result<Segment, String> {
+    let fields: Vec<&str> = text.split(':').map(str::trim).collect();
+    match fields.as_slice() {
+        ["tone", freq, duration, level] => Ok(Segment::Tone {
+            freq_hz: parse_freq(freq)?,
+            secs: parse_duration(duration)?,
+            level_db: parse_level(level)?,
+        }),
+        ["sweep", range, duration, level] => {
+            let (from, to) = range
+                .split_once('-')
+                .ok_or_else(|| format!("sweep range {:?} should be FROM-TO, e.g. 100-8000", range))?;
+            Ok(Segment::Sweep {
+                from_hz: parse_freq(from)?,
+                to_hz: parse_freq(to)?,
+                secs: parse_duration(duration)?,
+                level_db: parse_level(level)?,
+            })
+        }
+        ["silence", duration] => Ok(Segment::Silence { secs: parse_duration(duration)? }),
+        ["tone", ..] => Err("expected tone:FREQ:DURATION:LEVEL, e.g. tone:1000:10s:-20db".to_string()),
+        ["sweep", ..] => Err("expected sweep:FROM-TO:DURATION:LEVEL, e.g. sweep:100-8000:15s:-12db".to_string()),
+        ["silence", ..] => Err("expected silence:DURATION, e.g. silence:5s".to_string()),
+        [kind, ..] => Err(format!("unknown segment type {:?} (expected tone, sweep or silence)", kind)),
+        [] => unreachable!("split always yields a field"),
+    }
+}
+
+fn parse_freq(text: &str) -> Result<f64, String> {
+    let lower = text.trim().to_ascii_lowercase();
+    let (number, scale) = match lower.strip_suffix("khz") {
+        Some(khz) => (khz, 1000.0),
+        None => (lower.strip_suffix("hz").unwrap_or(&lower), 1.0),
+    };
+    match number.trim().parse::<f64>() {
+        Ok(freq) if freq.is_finite() && freq > 0.0 => Ok(freq * scale),
+        _ => Err(format!("frequency {:?} should be a positive number of Hz", text)),
+    }
+}
+
+fn parse_duration(text: &str) -> Result<f64, String> {
+    let lower = text.to_ascii_lowercase();
+    let (number, scale) = if let Some(ms) = lower.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(s) = lower.strip_suffix('s') {
+        (s, 1.0)
+    } else {
+        return Err(format!("duration {:?} needs a unit, e.g. 10s or 500ms", text));
+    };
+    match number.trim().parse::<f64>() {
+        Ok(value) if value.is_finite() && value > 0.0 => Ok(value * scale),
+        _ => Err(format!("duration {:?} should be a positive number of s or ms", text)),
+    }
+}
+
+fn parse_level(text: &str) -> Result<f64, String> {
+    let lower = text.to_ascii_lowercase();
+    let Some(number) = lower.strip_suffix("dbfs").or_else(|| lower.strip_suffix("db")) else {
+        return Err(format!("level {:?} needs a unit, e.g. -20db", text));
+    };
+    match number.trim().parse::<f64>() {
+        Ok(level) if level.is_finite() && level <= 0.0 => Ok(level),
+        Ok(_) => Err(format!("level {:?} is above full scale; use 0db or less", text)),
+        Err(_) => Err(format!("level {:?} should be a number of dB, e.g. -20db", text)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMISSIONING: &str = "tone:1000:10s:-20db; tone:1000:10s:-6db; sweep:100-8000:15s:-12db; silence:5s";
+
+    fn problem(spec: &str) -> String {
+        match spec.parse::<Sequence>() {
+            Err(SequenceError::Segment { problem, .. }) => problem,
+            other => panic!("expected a segment error for {:?}, got {:?}", spec, other),
+        }
+    }
+
+    #[test]
+    fn test_parse_commissioning_sequence() {
+        let sequence: Sequence = COMMISSIONING.parse().unwrap();
+        assert_eq!(
+            sequence.segments,
+            [
+                Segment::Tone { freq_hz: 1000.0, secs: 10.0, level_db: -20.0 },
+                Segment::Tone { freq_hz: 1000.0, secs: 10.0, level_db: -6.0 },
+                Segment::Sweep { from_hz: 100.0, to_hz: 8000.0, secs: 15.0, level_db: -12.0 },
+                Segment::Silence { secs: 5.0 },
+            ]
+        );
+        assert!((sequence.secs() - 40.0).abs() < 1e-9);
+
+        let described = sequence.to_string();
+        assert!(described.starts_with("Sequence: 4 segment(s), 40.000s"), "{}", described);
+        assert!(described.contains("   20.000s  sweep   100-8000 Hz"), "{}", described);
+    }
+
+    #[test]
+    fn test_parse_file_style_spec() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("commissioning.txt");
+        std::fs::write(&path, "# Level steps\ntone:1kHz:500ms:-20dBFS\n\nsilence:250MS ; tone:440hz:1s:0db\n").unwrap();
+
+        let sequence: Sequence = format!("@{}", path.display()).parse().unwrap();
+        assert_eq!(sequence.segments.len(), 3);
+        assert_eq!(sequence.segments[1], Segment::Silence { secs: 0.25 });
+
+        let missing = format!("@{}", dir.path().join("missing.txt").display()).parse::<Sequence>();
+        assert!(matches!(missing, Err(SequenceError::Io { .. })));
+    }
+
+    #[test]
+    fn test_malformed_specs() {
+        assert!(problem("tone:1000:10:-20db").contains("needs a unit, e.g. 10s or 500ms"));
+        assert!(problem("tone:1000:10s:-20").contains("needs a unit, e.g. -20db"));
+        assert!(problem("tone:1000:10s:3db").contains("above full scale"));
+        assert!(problem("tone:1000:10s").contains("expected tone:FREQ:DURATION:LEVEL"));
+        assert!(problem("tone:-5:10s:-20db").contains("positive number of Hz"));
+        assert!(problem("tone:1000:0s:-20db").contains("positive number of s or ms"));
+        assert!(problem("sweep:100:10s:-20db").contains("should be FROM-TO"));
+        assert!(problem("silence").contains("expected silence:DURATION"));
+        assert!(problem("beep:1000:1s:-20db").contains("unknown segment type \"beep\" (expected tone, sweep or silence)"));
+
+        // The error names the segment it's about
+        let error = "tone:1000:1s:-20db; silence:5".parse::<Sequence>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Segment 2 (\"silence:5\"): duration \"5\" needs a unit, e.g. 10s or 500ms"
+        );
+
+        assert!(matches!(" ; # nothing".parse::<Sequence>(), Err(SequenceError::Empty)));
+        assert!(matches!("silence:2h".parse::<Sequence>(), Err(SequenceError::Segment { .. })));
+        assert!(matches!("silence:3601s".parse::<Sequence>(), Err(SequenceError::TooLong(_))));
+    }
+
+    #[test]
+    fn test_segment_boundaries_are_sample_accurate() {
+        // A third of a second doesn't divide evenly into samples; the total still comes out exact
+        let sequence: Sequence = "tone:1000:333.333ms:-20db; silence:333.333ms; tone:500:333.334ms:-20db"
+            .parse()
+            .unwrap();
+        let samples = sequence.synthesize(8000).unwrap();
+        assert_eq!(samples.len(), 8000);
+        // The silence sits exactly between its boundaries
+        assert!(samples[2667..5333].iter().all(|&s| s == 0));
+        assert_ne!(samples[2666 - 40], 0);
+    }
+
+    #[test]
+    fn test_joins_are_ramped() {
+        let sequence: Sequence = "tone:1000:1s:-6db; tone:1000:1s:-6db".parse().unwrap();
+        let samples = sequence.synthesize(8000).unwrap();
+        let peak = amplitude(-6.0);
+        let ramp = (RAMP_MS / 1000.0 * 8000.0) as usize;
+
+        // Silent at the edges and the join, full level once the ramp is over
+        assert_eq!(samples[0], 0);
+        assert!(samples[7999].abs() < 50, "{}", samples[7999]);
+        assert!(samples[8000].abs() < 50, "{}", samples[8000]);
+        let steady = samples[ramp..8000 - ramp].iter().map(|s| f64::from(s.abs())).fold(0.0, f64::max);
+        assert!((steady - peak).abs() / peak < 0.01, "{} vs {}", steady, peak);
+
+        // No sample-to-sample jump bigger than the tone's own slope
+        let max_step = samples.windows(2).map(|w| (i32::from(w[1]) - i32::from(w[0])).abs()).max().unwrap();
+        assert!(f64::from(max_step) <= peak * 2.0 * PI * 1000.0 / 8000.0 + 1.0);
+    }
+
+    #[test]
+    fn test_sweep_covers_its_range() {
+        let sequence: Sequence = "sweep:100-4000:2s:-12db".parse().unwrap();
+        let samples = sequence.synthesize(16000).unwrap();
+        assert_eq!(samples.len(), 32000);
+
+        // Zero crossings per 100ms window: about 2f, so the rate climbs from ~20 to ~800
+        let crossings = |window: &[i16]| window.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count();
+        let start = crossings(&samples[800..2400]);
+        let end = crossings(&samples[29600..31200]);
+        assert!(start < 60, "{}", start);
+        assert!(end > 600, "{}", end);
+    }
+
+    #[test]
+    fn test_above_nyquist() {
+        let sequence: Sequence = COMMISSIONING.parse().unwrap();
+        let error = sequence.synthesize(8000).unwrap_err();
+        assert!(matches!(error, SequenceError::AboveNyquist { index: 3, sample_rate: 8000, .. }));
+        assert!(error.to_string().contains("the limit is 4000 Hz"));
+        assert_eq!(sequence.synthesize(16001).unwrap().len(), 40 * 16001);
+    }
+}
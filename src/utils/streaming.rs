@@ -0,0 +1,222 @@
+//! Streaming statistics that fit in a few numbers and survive a restart.
+//!
+//! Long-running aggregates can't keep every observation, so these keep a
+//! fixed-size summary that serializes to JSON and carries on accumulating
+//! after being loaded back:
+//! - [`RunningStats`]: count, mean, variance (Welford's method), min and max.
+//! - [`P2Quantile`]: one quantile estimated with the P² algorithm (Jain and
+//!   Chlamtac, 1985). Five markers track the minimum, the quantile, the
+//!   maximum and two points between; each new value moves them toward their
+//!   ideal positions with a parabolic fit. Exact for the first five values,
+//!   and typically within a few percent of the true quantile after that.
+
+use serde::{Deserialize, Serialize};
+
+/// Count, mean, variance, min and max of a stream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunningStats {
+    pub count: u64,
+    pub mean: f64,
+    /// Sum of squared differences from the mean
+    m2: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl RunningStats {
+    pub fn push(&mut self, value: f64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Population standard deviation; 0 until there are two values
+    pub fn std_dev(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            (self.m2 / self.count as f64).sqrt()
+        }
+    }
+}
+
+/// Streaming estimate of one quantile
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct P2Quantile {
+    /// Quantile tracked, 0-1
+    p: f64,
+    count: u64,
+    /// Marker heights; the first values seen until there are five
+    heights: [f64; 5],
+    /// Marker positions, 1-based
+    positions: [f64; 5],
+    /// Where the markers should be
+    desired: [f64; 5],
+}
+
+impl P2Quantile {
+    pub fn new(p: f64) -> Self {
+        let p = p.clamp(0.0, 1.0);
+        Self {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+        }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.count < 5 {
+            self.heights[self.count as usize] = value;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+
+        // Cell the value falls in, stretching the ends to take it
+        let cell = if value < self.heights[0] {
+            self.heights[0] = value;
+            0
+        } else if value >= self.heights[4] {
+            self.heights[4] = value;
+            3
+        } else {
+            (0..4).rfind(|&i| self.heights[i] <= value).unwrap_or(0)
+        };
+        for position in &mut self.positions[cell + 1..] {
+            *position += 1.0;
+        }
+        let p = self.p;
+        for (desired, step) in self.desired.iter_mut().zip([0.0, p / 2.0, p, f64::midpoint(1.0, p), 1.0]) {
+            *desired += step;
+        }
+
+        // Move the middle markers a step toward where they should be
+        for i in 1..4 {
+            let offset = self.desired[i] - self.positions[i];
+            let room_up = self.positions[i + 1] - self.positions[i] > 1.0;
+            let room_down = self.positions[i - 1] - self.positions[i] < -1.0;
+            if (offset >= 1.0 && room_up) || (offset <= -1.0 && room_down) {
+                let step = offset.signum();
+                let parabolic = self.parabolic(i, step);
+                self.heights[i] = if self.heights[i - 1] < parabolic && parabolic < self.heights[i + 1] {
+                    parabolic
+                } else {
+                    self.linear(i, step)
+                };
+                self.positions[i] += step;
+            }
+        }
+    }
+
+    fn parabolic(&self, i: usize, step: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + step / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + step) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - step) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    fn linear(&self, i: usize, step: f64) -> f64 {
+        let j = if step > 0.0 { i + 1 } else { i - 1 };
+        self.heights[i] + step * (self.heights[j] - self.heights[i]) / (self.positions[j] - self.positions[i])
+    }
+
+    /// Current estimate; exact (nearest rank) until five values are in.
+    /// None before the first value.
+    pub fn value(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            1..=4 => {
+                let mut seen = self.heights[..self.count as usize].to_vec();
+                seen.sort_by(f64::total_cmp);
+                let rank = (self.p * self.count as f64).ceil().max(1.0) as usize;
+                Some(seen[rank - 1])
+            }
+            _ => Some(self.heights[2]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic values spread over 0-100
+    fn scrambled(count: u64) -> impl Iterator<Item = f64> {
+        (0..count).map(move |i| (i * 7919 % count) as f64 * 100.0 / count as f64)
+    }
+
+    #[test]
+    fn test_running_stats() {
+        let mut stats = RunningStats::default();
+        assert!(stats.std_dev().abs() < 1e-12);
+        for value in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            stats.push(value);
+        }
+        assert_eq!(stats.count, 8);
+        assert!((stats.mean - 5.0).abs() < 1e-12);
+        assert!((stats.std_dev() - 2.0).abs() < 1e-12);
+        assert_eq!((stats.min, stats.max), (2.0, 9.0));
+    }
+
+    #[test]
+    fn test_p2_small_counts_are_exact() {
+        let mut p95 = P2Quantile::new(0.95);
+        assert_eq!(p95.value(), None);
+        for value in [3.0, 1.0, 2.0] {
+            p95.push(value);
+        }
+        assert_eq!(p95.value(), Some(3.0));
+
+        let mut median = P2Quantile::new(0.5);
+        for value in [5.0, 1.0, 4.0, 2.0, 3.0] {
+            median.push(value);
+        }
+        assert_eq!(median.value(), Some(3.0));
+    }
+
+    #[test]
+    fn test_p2_tracks_quantiles() {
+        for (p, expected) in [(0.5, 50.0), (0.95, 95.0)] {
+            let mut quantile = P2Quantile::new(p);
+            for value in scrambled(10_000) {
+                quantile.push(value);
+            }
+            let estimate = quantile.value().unwrap();
+            assert!((estimate - expected).abs() < 1.0, "p{} = {}", p, estimate);
+        }
+    }
+
+    #[test]
+    fn test_survives_serialization() {
+        let mut stats = RunningStats::default();
+        let mut p95 = P2Quantile::new(0.95);
+        let mut values = scrambled(2000);
+        for value in values.by_ref().take(1000) {
+            stats.push(value);
+            p95.push(value);
+        }
+
+        let mut stats: RunningStats = serde_json::from_str(&serde_json::to_string(&stats).unwrap()).unwrap();
+        let mut p95: P2Quantile = serde_json::from_str(&serde_json::to_string(&p95).unwrap()).unwrap();
+        for value in values {
+            stats.push(value);
+            p95.push(value);
+        }
+        assert_eq!(stats.count, 2000);
+        assert!((stats.mean - 49.975).abs() < 1e-9);
+        assert!((p95.value().unwrap() - 95.0).abs() < 1.0);
+    }
+}
@@ -8,7 +8,7 @@
 
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::thread;
 use std::time::Duration;
@@ -19,7 +19,7 @@ fn binary_path() -> PathBuf {
     let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     path.push("target");
     path.push("debug");
-    path.push("multicast-paging-utility");
+    path.push(format!("multicast-paging-utility{}", std::env::consts::EXE_SUFFIX));
     path
 }
 
@@ -65,12 +65,34 @@ fn generate_test_wav(path: &std::path::Path, frequency_hz: u32, duration_secs: f
     }
 }
 
+/// Whether the ffmpeg the binary would run (`MPU_FFMPEG` or PATH) is there
+fn ffmpeg_available() -> bool {
+    let program = std::env::var_os("MPU_FFMPEG").unwrap_or_else(|| "ffmpeg".into());
+    Command::new(program).arg("-version").output().is_ok()
+}
+
 /// Parse the summary.json file and extract key metrics
 fn parse_summary(path: &std::path::Path) -> serde_json::Value {
     let content = fs::read_to_string(path).expect("Failed to read summary.json");
     serde_json::from_str(&content).expect("Failed to parse summary.json")
 }
 
+/// Encode a linear sample as G.711 u-law (ITU-T G.711, with the 0x84 bias)
+fn linear_to_ulaw(sample: i16) -> u8 {
+    const BIAS: i32 = 0x84;
+    let mut value = i32::from(sample);
+    let sign = if value < 0 {
+        value = -value;
+        0x80
+    } else {
+        0
+    };
+    value = value.min(32635) + BIAS;
+    let exponent = (7 - (value.leading_zeros() as i32 - 17)).clamp(0, 7);
+    let mantissa = (value >> (exponent + 3)) & 0x0F;
+    !(sign | (exponent << 4) as u8 | mantissa as u8)
+}
+
 #[test]
 fn test_transmit_and_monitor_1khz_tone() {
     // Skip if binary doesn't exist (not built yet)
@@ -148,7 +170,7 @@ fn test_transmit_and_monitor_1khz_tone() {
     // Verify duration is approximately 3 seconds (with some tolerance)
     let duration = page["duration_secs"].as_f64().expect("duration should be f64");
     assert!(
-        duration >= 2.5 && duration <= 3.5,
+        (2.5..=3.5).contains(&duration),
         "Duration {} should be approximately 3 seconds",
         duration
     );
@@ -165,19 +187,32 @@ fn test_transmit_and_monitor_1khz_tone() {
     let glitches = page["audio"]["total_glitches"].as_u64().expect("glitches should be u64");
     assert_eq!(glitches, 0, "Should have no glitches");
 
+    // Looped-back multicast keeps the transmit socket's TTL (default 32)
+    if cfg!(unix) {
+        assert_eq!(page["network"]["ttl"]["min"], 32, "TTL: {}", page["network"]["ttl"]);
+        assert_eq!(page["network"]["ttl"]["max"], 32, "TTL: {}", page["network"]["ttl"]);
+    }
+
+    // Jitter buffer simulation covers every depth
+    let analysis = page["jitter_buffer_analysis"].as_array().expect("jitter_buffer_analysis should be array");
+    let depths: Vec<u64> = analysis.iter().filter_map(|r| r["depth_ms"].as_u64()).collect();
+    assert_eq!(depths, [20, 40, 60, 80, 120]);
+
     // Verify dominant frequency is approximately 1kHz
     let freq = page["audio"]["dominant_freq_hz"].as_f64().expect("freq should be f64");
     assert!(
-        freq >= 900.0 && freq <= 1100.0,
+        (900.0..=1100.0).contains(&freq),
         "Dominant frequency {} should be approximately 1000 Hz",
         freq
     );
+    assert_eq!(page["audio"]["content_class"], "tone");
+    assert!(page["audio"].get("watermark").is_none(), "Unmarked page reported a watermark: {}", page["audio"]);
 
-    // Verify a WAV file was created
-    let wav_files: Vec<_> = fs::read_dir(&output_dir)
+    // Verify a WAV file was created in the endpoint's subdirectory
+    let wav_files: Vec<_> = fs::read_dir(output_dir.join("224.0.123.1_15004"))
         .unwrap()
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().extension().map_or(false, |ext| ext == "wav"))
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "wav"))
         .collect();
     assert_eq!(wav_files.len(), 1, "Should have exactly 1 WAV recording");
 }
@@ -248,7 +283,7 @@ fn test_transmit_and_monitor_440hz_tone() {
     // Verify dominant frequency is approximately 440Hz
     let freq = page["audio"]["dominant_freq_hz"].as_f64().expect("freq should be f64");
     assert!(
-        freq >= 400.0 && freq <= 500.0,
+        (400.0..=500.0).contains(&freq),
         "Dominant frequency {} should be approximately 440 Hz",
         freq
     );
@@ -256,7 +291,7 @@ fn test_transmit_and_monitor_440hz_tone() {
     // Verify zero crossing rate matches 440Hz (should be ~880/s)
     let zcr = page["audio"]["avg_zero_crossing_rate"].as_f64().expect("zcr should be f64");
     assert!(
-        zcr >= 800.0 && zcr <= 1000.0,
+        (800.0..=1000.0).contains(&zcr),
         "Zero crossing rate {} should be approximately 880/s for 440Hz",
         zcr
     );
@@ -365,3 +400,3272 @@ fn test_review_command() {
     assert!(stdout.contains("TEST RESULTS REVIEW"), "Review should show results header");
     assert!(stdout.contains("PAGES DETECTED: 1"), "Review should show 1 page detected");
 }
+
+#[test]
+fn test_review_verify_flags_tampered_recording() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 1.0, 8000);
+
+    let multicast_addr = "224.0.123.16";
+    let port = "15017";
+
+    let mut monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "7",
+            "--codec", "g711ulaw",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    // Two transmissions, each with its own SSRC, make two pages
+    for _ in 0..2 {
+        let status = Command::new(&binary)
+            .args([
+                "transmit",
+                "--file", wav_path.to_str().unwrap(),
+                "--address", multicast_addr,
+                "--port", port,
+                "--codec", "g711ulaw",
+                "--quiet",
+            ])
+            .status()
+            .expect("Failed to run transmit");
+        assert!(status.success(), "Transmit command failed");
+        thread::sleep(Duration::from_millis(500));
+    }
+
+    monitor.wait().expect("Failed to wait for monitor");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 2, "Expected two pages");
+    for page in pages {
+        assert_eq!(page["integrity"]["sha256"].as_str().map(str::len), Some(64), "Page should have a checksum");
+    }
+
+    // Flip one sample byte in the second recording
+    let recording = output_dir.join(pages[1]["recording_file"].as_str().unwrap());
+    let mut bytes = fs::read(&recording).expect("Failed to read recording");
+    let middle = bytes.len() / 2;
+    bytes[middle] ^= 0x01;
+    fs::write(&recording, &bytes).expect("Failed to write recording");
+
+    let review_output = Command::new(&binary)
+        .args([
+            "review",
+            "--directory", output_dir.to_str().unwrap(),
+            "--verify",
+        ])
+        .output()
+        .expect("Failed to run review");
+
+    let stdout = String::from_utf8_lossy(&review_output.stdout);
+    assert!(!review_output.status.success(), "Verification should fail:\n{}", stdout);
+
+    let row = |page: u64| {
+        stdout
+            .lines()
+            .find(|line| line.starts_with(&format!("│ {:>4} │", page)))
+            .unwrap_or_else(|| panic!("No verification row for page {}:\n{}", page, stdout))
+    };
+    assert!(row(pages[0]["page_number"].as_u64().unwrap()).contains("OK"), "{}", stdout);
+    assert!(row(pages[1]["page_number"].as_u64().unwrap()).contains("MODIFIED (audio)"), "{}", stdout);
+    assert!(stdout.contains("Verified 2 recording(s): 1 failed"), "{}", stdout);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_control_socket_add_endpoint() {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+    let socket_path = temp_dir.path().join("control.sock");
+
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 2.0, 8000);
+
+    // Start monitoring one endpoint; the second is added at runtime
+    let initial_addr = "224.0.123.5";
+    let added_addr = "224.0.123.6";
+    let port = "15008";
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", initial_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "8",
+            "--codec", "g711ulaw",
+            "--control-socket", socket_path.to_str().unwrap(),
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let stream = UnixStream::connect(&socket_path).expect("Failed to connect to control socket");
+    let mut writer = stream.try_clone().unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut reply = String::new();
+
+    writeln!(writer, "add {}:{}", added_addr, port).unwrap();
+    reader.read_line(&mut reply).unwrap();
+    assert_eq!(reply.trim(), "ok", "add should succeed");
+
+    reply.clear();
+    writeln!(writer, "status").unwrap();
+    reader.read_line(&mut reply).unwrap();
+    let table: serde_json::Value = serde_json::from_str(&reply).expect("status should be JSON");
+    let table = table.as_array().expect("status should be an array");
+    assert_eq!(table.len(), 2, "Both endpoints should be monitored");
+    assert!(table.iter().any(|ep| ep["address"] == added_addr));
+
+    let transmit_status = Command::new(&binary)
+        .args([
+            "transmit",
+            "--file", wav_path.to_str().unwrap(),
+            "--address", added_addr,
+            "--port", port,
+            "--codec", "g711ulaw",
+            "--quiet",
+        ])
+        .status()
+        .expect("Failed to run transmit");
+    assert!(transmit_status.success(), "Transmit command failed");
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1, "Should detect exactly 1 page");
+    assert_eq!(pages[0]["endpoint"], format!("{}:{}", added_addr, port));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_control_socket_annotation() {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+    let socket_path = temp_dir.path().join("control.sock");
+
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 4.0, 8000);
+
+    let multicast_addr = "224.0.123.26";
+    let port = "15034";
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "12",
+            "--codec", "g711ulaw",
+            "--control-socket", socket_path.to_str().unwrap(),
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit = Command::new(&binary)
+        .args([
+            "transmit",
+            "--file", wav_path.to_str().unwrap(),
+            "--address", multicast_addr,
+            "--port", port,
+            "--codec", "g711ulaw",
+            "--quiet",
+        ])
+        .spawn()
+        .expect("Failed to run transmit");
+
+    // Halfway through the page
+    thread::sleep(Duration::from_secs(2));
+    let stream = UnixStream::connect(&socket_path).expect("Failed to connect to control socket");
+    let mut writer = stream.try_clone().unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut reply = String::new();
+    writeln!(writer, "annotate uplink unplugged").unwrap();
+    reader.read_line(&mut reply).unwrap();
+    assert_eq!(reply.trim(), "ok", "annotate should succeed");
+
+    reply.clear();
+    writeln!(writer, "annotate").unwrap();
+    reader.read_line(&mut reply).unwrap();
+    assert!(reply.starts_with("error:"), "an empty note should be refused: {}", reply);
+
+    assert!(transmit.wait_with_output().unwrap().status.success(), "Transmit command failed");
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+    assert!(String::from_utf8_lossy(&monitor_output.stdout).contains("Annotation: uplink unplugged"));
+
+    // annotations.jsonl holds the note, stamped within the page
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let page = &summary["pages"][0];
+    let notes = fs::read_to_string(output_dir.join("annotations.jsonl")).expect("annotations.jsonl should be written");
+    let notes: Vec<serde_json::Value> = notes.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0]["text"], "uplink unplugged");
+    assert_eq!(notes[0]["source"], "control");
+    let stamp = |value: &serde_json::Value| value.as_str().unwrap().parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+    let noted = stamp(&notes[0]["timestamp"]);
+    assert!(stamp(&page["start_time"]) <= noted && noted <= stamp(&page["end_time"]), "{} outside {}", noted, page);
+
+    // ...and metrics.jsonl has it between the snapshots
+    let metrics = fs::read_to_string(output_dir.join("metrics.jsonl")).unwrap();
+    assert!(metrics.lines().any(|line| line.contains("\"type\":\"annotation\"") && line.contains("uplink unplugged")));
+
+    let review_output = Command::new(&binary)
+        .args(["review", "--directory", output_dir.to_str().unwrap(), "--page", "1", "--timeline"])
+        .output()
+        .expect("Failed to run review");
+    assert!(review_output.status.success(), "Review command should succeed");
+    let stdout = String::from_utf8_lossy(&review_output.stdout);
+    assert!(stdout.contains("✎ uplink unplugged"), "{}", stdout);
+    assert!(!stdout.contains("unreadable line"), "{}", stdout);
+}
+
+#[test]
+fn test_transmit_drop_impairment() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    // 4 seconds = 200 packets at 20ms
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 4.0, 8000);
+
+    let multicast_addr = "224.0.123.7";
+    let port = "15009";
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "9",
+            "--codec", "g711ulaw",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit_output = Command::new(&binary)
+        .args([
+            "transmit",
+            "--file", wav_path.to_str().unwrap(),
+            "--address", multicast_addr,
+            "--port", port,
+            "--codec", "g711ulaw",
+            "--drop-percent", "10",
+            "--impair-seed", "12345",
+            "--json",
+        ])
+        .output()
+        .expect("Failed to run transmit");
+    assert!(transmit_output.status.success(), "Transmit command failed");
+
+    // With --json stdout carries events only, ending in transmit_completed
+    let stdout = String::from_utf8_lossy(&transmit_output.stdout);
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("every stdout line should be a JSON event"))
+        .collect();
+    assert_eq!(events.first().map(|e| &e["event"]), Some(&"transmit_started".into()));
+    let completed = events.last().expect("no events");
+    assert_eq!(completed["event"], "transmit_completed");
+    assert_eq!(completed["frames_sent"], 200);
+    let progress = events.iter().filter(|e| e["event"] == "transmit_progress").count();
+    assert_eq!(progress, 4, "One progress event per 50 frames");
+
+    // The transmit report records what was actually dropped
+    let report = events
+        .iter()
+        .find(|event| event["event"] == "transmit_report")
+        .expect("transmit_report event not found");
+    let dropped = report["impairments"]["dropped"].as_u64().expect("dropped should be u64");
+    assert!((10..=30).contains(&dropped), "Dropped {} of 200 packets", dropped);
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1, "Drops must not split the page");
+
+    let loss_percent = pages[0]["network"]["loss_percent"].as_f64().expect("loss_percent should be f64");
+    assert!(
+        (5.0..=15.0).contains(&loss_percent),
+        "Loss {} should be approximately 10%",
+        loss_percent
+    );
+}
+
+#[test]
+fn test_repair_recovers_dropped_packets() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    // 4 seconds = 200 packets at 20ms
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 4.0, 8000);
+
+    let multicast_addr = "224.0.123.25";
+    let port = "15030";
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "10",
+            "--codec", "g711ulaw",
+            "--repair",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit_output = Command::new(&binary)
+        .args([
+            "transmit",
+            "--file", wav_path.to_str().unwrap(),
+            "--address", multicast_addr,
+            "--port", port,
+            "--codec", "g711ulaw",
+            "--drop-percent", "5",
+            "--impair-seed", "4242",
+            "--repair",
+            "--json",
+        ])
+        .output()
+        .expect("Failed to run transmit");
+    assert!(transmit_output.status.success(), "Transmit command failed");
+
+    let stdout = String::from_utf8_lossy(&transmit_output.stdout);
+    let report: serde_json::Value = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("every stdout line should be a JSON event"))
+        .find(|event: &serde_json::Value| event["event"] == "transmit_report")
+        .expect("transmit_report event not found");
+    let dropped = report["impairments"]["dropped"].as_u64().expect("dropped should be u64");
+    assert!(dropped > 0, "The seed should drop some packets");
+    let resent = report["retransmits"]["packets_resent"].as_u64().expect("retransmits should be reported");
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1, "Drops must not split the page");
+
+    let network = &pages[0]["network"];
+    let repaired = network["repair"]["packets_repaired"].as_u64().expect("repair should be reported");
+    let unrepaired = network["repair"]["packets_unrepaired"].as_u64().unwrap();
+    assert!(repaired > 0 && repaired <= resent, "{} repaired, {} resent", repaired, resent);
+    assert!(unrepaired <= 1, "{} of {} dropped packets unrepaired", unrepaired, dropped);
+    assert_eq!(network["packets_lost"].as_u64(), Some(unrepaired), "Repaired packets aren't lost");
+    assert!(network["packets_received"].as_u64().unwrap() >= 199);
+}
+
+#[test]
+fn test_transmit_failed_event() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let output = Command::new(&binary)
+        .args(["transmit", "--file", "/nonexistent/page.wav", "--address", "224.0.123.19", "--json"])
+        .output()
+        .expect("Failed to run transmit");
+    assert!(!output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let event: serde_json::Value = serde_json::from_str(stdout.trim()).expect("one JSON event expected");
+    assert_eq!(event["event"], "transmit_failed");
+    assert_eq!(event["reason"], "file_not_found");
+    assert_eq!(event["frames_sent"], 0);
+}
+
+#[test]
+fn test_transmit_multi_zone() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    // 500 Hz on the left (English), 2 kHz on the right (Spanish)
+    let wav_path = temp_dir.path().join("zones.wav");
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: 8000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&wav_path, spec).expect("Failed to create WAV");
+    for i in 0..24_000 {
+        let t = f64::from(i) / 8000.0;
+        for frequency in [500.0, 2000.0] {
+            let sample = (t * 2.0 * std::f64::consts::PI * frequency).sin() * 16000.0;
+            writer.write_sample(sample as i16).expect("Failed to write sample");
+        }
+    }
+    writer.finalize().expect("Failed to finalize WAV");
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", "224.0.123.{20-21}:15021",
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "8",
+            "--codec", "g711ulaw",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit_status = Command::new(&binary)
+        .args([
+            "transmit",
+            "--file", wav_path.to_str().unwrap(),
+            "--multi-zone", "left=224.0.123.20:15021,right=224.0.123.21:15021",
+            "--codec", "g711ulaw",
+            "--quiet",
+        ])
+        .status()
+        .expect("Failed to run transmit");
+    assert!(transmit_status.success(), "Transmit command failed");
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 2, "Each zone should record one page");
+    let frequency = |endpoint: &str| -> f64 {
+        let page = pages
+            .iter()
+            .find(|page| page["endpoint"] == endpoint)
+            .unwrap_or_else(|| panic!("No page on {}", endpoint));
+        page["audio"]["dominant_freq_hz"].as_f64().expect("freq should be f64")
+    };
+    let left = frequency("224.0.123.20:15021");
+    let right = frequency("224.0.123.21:15021");
+    assert!((450.0..=550.0).contains(&left), "Left zone heard {} Hz", left);
+    assert!((1900.0..=2100.0).contains(&right), "Right zone heard {} Hz", right);
+
+    // The zones' pages overlap: each is its endpoint's page 1, told apart
+    // by run-wide ids, with its recording in the endpoint's subdirectory
+    let mut ids: Vec<_> = pages.iter().map(|page| page["page_id"].as_u64().expect("page_id should be set")).collect();
+    ids.sort_unstable();
+    assert_eq!(ids, [1, 2]);
+    for page in pages {
+        assert_eq!(page["page_number"], 1);
+        let subdir = page["endpoint"].as_str().unwrap().replace(':', "_");
+        let recording = page["recording_file"].as_str().expect("recording_file should be set");
+        assert!(recording.starts_with(&format!("{}/", subdir)), "{} isn't under {}", recording, subdir);
+        assert!(output_dir.join(recording).exists(), "{} is missing", recording);
+        let logged = fs::read_to_string(output_dir.join(&subdir).join("pages.jsonl")).expect("No endpoint page log");
+        let logged_pages = logged.lines().filter(|line| !line.contains("recording_finalized")).count();
+        assert_eq!(logged_pages, 1, "{}", logged);
+        assert!(logged.lines().all(|line| line.contains(page["endpoint"].as_str().unwrap())), "{}", logged);
+    }
+    assert!(!output_dir.join("pages.jsonl").exists());
+
+    let review = |args: &[&str]| -> String {
+        let output = Command::new(&binary)
+            .args(["review", "--directory", output_dir.to_str().unwrap()])
+            .args(args)
+            .output()
+            .expect("Failed to run review");
+        assert!(output.status.success(), "Review failed: {}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    let listing = review(&[]);
+    assert!(listing.contains("PAGES DETECTED: 2"), "{}", listing);
+    // Piped, --interactive has no terminal to browse on and prints the usual review
+    let piped = review(&["--interactive"]);
+    assert!(piped.contains("PAGES DETECTED: 2") && piped.contains("ENDPOINT TOTALS"), "{}", piped);
+    let second = pages.iter().find(|page| page["page_id"] == 2).unwrap();
+    let endpoint = second["endpoint"].as_str().unwrap();
+    let detail = review(&["--page", "2"]);
+    assert!(detail.contains(&format!("{} (its page 1)", endpoint)), "{}", detail);
+    let detail = review(&["--page", "1", "--endpoint", endpoint]);
+    assert!(detail.contains("PAGE 2"), "{}", detail);
+
+    // Asking for a channel the file doesn't have is an error, not a silent mix
+    let output = Command::new(&binary)
+        .args([
+            "transmit",
+            "--file", wav_path.to_str().unwrap(),
+            "--address", "224.0.123.20",
+            "--port", "15021",
+            "--channel-select", "3",
+            "--dry-run",
+        ])
+        .output()
+        .expect("Failed to run transmit");
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("ChannelOutOfRange"),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_l24_stereo_stream() {
+    use std::net::UdpSocket;
+    use std::time::Instant;
+
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    let multicast_addr = "224.0.123.8";
+    let port = "15010";
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "7",
+            "--codec", "l24:48000:2",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    // AES67-style sender: 2 seconds of 1kHz stereo L24 in 1ms packets on dynamic PT 97
+    let socket = UdpSocket::bind("0.0.0.0:0").expect("Failed to bind sender");
+    socket.set_multicast_loop_v4(true).unwrap();
+    socket.set_multicast_ttl_v4(1).unwrap();
+    let dest = format!("{}:{}", multicast_addr, port);
+    let start = Instant::now();
+    for packet in 0..2000u32 {
+        let mut data = vec![0x80, 97];
+        data.extend_from_slice(&(packet as u16).to_be_bytes());
+        data.extend_from_slice(&(packet * 48).to_be_bytes());
+        data.extend_from_slice(&0x0AE6_7000u32.to_be_bytes());
+        for i in 0..48 {
+            let t = (packet * 48 + i) as f64 / 48000.0;
+            let sample = (0.3 * 8_388_607.0 * (2.0 * std::f64::consts::PI * 1000.0 * t).sin()) as i32;
+            let bytes = sample.to_be_bytes();
+            // Left and right carry the same tone
+            data.extend_from_slice(&bytes[1..]);
+            data.extend_from_slice(&bytes[1..]);
+        }
+        socket.send_to(&data, &dest).expect("Failed to send packet");
+
+        let due = start + Duration::from_millis(u64::from(packet + 1));
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1, "Should detect exactly 1 page");
+    let page = &pages[0];
+
+    let loss_percent = page["network"]["loss_percent"].as_f64().expect("loss_percent should be f64");
+    assert!(loss_percent < 1.0, "Packet loss {} should be less than 1%", loss_percent);
+
+    let freq = page["audio"]["dominant_freq_hz"].as_f64().expect("freq should be f64");
+    assert!(
+        (900.0..=1100.0).contains(&freq),
+        "Dominant frequency {} should be approximately 1000 Hz",
+        freq
+    );
+
+    // 0.3 full scale sine = -13.5 dBFS RMS
+    let rms = page["audio"]["avg_rms_db"].as_f64().expect("rms should be f64");
+    assert!((-15.0..=-12.0).contains(&rms), "RMS {} should be about -13.5 dB", rms);
+
+    // Recording is 16-bit stereo at 48kHz with the full 2 seconds of audio
+    let wav_path = fs::read_dir(output_dir.join("224.0.123.8_15010"))
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| p.extension().is_some_and(|ext| ext == "wav"))
+        .expect("No WAV recording");
+    let reader = hound::WavReader::open(&wav_path).expect("Failed to open recording");
+    let spec = reader.spec();
+    assert_eq!(spec.channels, 2);
+    assert_eq!(spec.sample_rate, 48000);
+    assert_eq!(spec.bits_per_sample, 16);
+    let recorded_secs = reader.duration() as f64 / 48000.0;
+    assert!(
+        (1.98..=2.01).contains(&recorded_secs),
+        "Recording is {} seconds, expected 2",
+        recorded_secs
+    );
+}
+
+#[test]
+fn test_transmit_allowed_window() {
+    use chrono::{Datelike, Local};
+
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 0.5, 8000);
+
+    let transmit = |window: &str| {
+        Command::new(&binary)
+            .args([
+                "transmit",
+                "--file", wav_path.to_str().unwrap(),
+                "--address", "224.0.123.9",
+                "--port", "15011",
+                "--allowed-window", window,
+                "--json",
+            ])
+            .output()
+            .expect("Failed to run transmit")
+    };
+
+    // A window covering only tomorrow is closed right now
+    let tomorrow = format!("{} 00:00-24:00", Local::now().weekday().succ());
+    let refused = transmit(&tomorrow);
+    assert!(!refused.status.success(), "Out-of-window transmit should fail");
+    let stderr = String::from_utf8_lossy(&refused.stderr);
+    assert!(stderr.contains("Outside allowed transmit windows"), "stderr: {}", stderr);
+    let stdout = String::from_utf8_lossy(&refused.stdout);
+    assert!(stdout.contains("\"event\":\"transmit_refused\""), "stdout: {}", stdout);
+    assert!(!stdout.contains("transmit_report"), "Nothing should have been sent");
+
+    let allowed = transmit("00:00-24:00");
+    assert!(allowed.status.success(), "In-window transmit failed");
+    let stdout = String::from_utf8_lossy(&allowed.stdout);
+    assert!(stdout.contains("\"event\":\"transmit_report\""), "stdout: {}", stdout);
+}
+
+#[test]
+fn test_transmit_dry_run() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 3.0, 8000);
+
+    let multicast_addr = "224.0.123.14";
+    let port = "15015";
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "5",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let dry_run = |extra: &[&str]| {
+        Command::new(&binary)
+            .args([
+                "transmit",
+                "--file", wav_path.to_str().unwrap(),
+                "--address", multicast_addr,
+                "--port", port,
+                "--codec", "g711ulaw",
+                "--dry-run",
+                "--json",
+            ])
+            .args(extra)
+            .output()
+            .expect("Failed to run transmit")
+    };
+    // Log lines share stdout with the JSON event
+    let parse_plan = |stdout: &[u8]| -> serde_json::Value {
+        let stdout = String::from_utf8_lossy(stdout);
+        let line = stdout.lines().find(|line| line.starts_with('{')).expect("No JSON plan in output");
+        serde_json::from_str(line).expect("Failed to parse plan")
+    };
+
+    let output = dry_run(&[]);
+    assert!(output.status.success(), "Dry run should pass: {}", String::from_utf8_lossy(&output.stdout));
+    let plan = parse_plan(&output.stdout);
+    assert_eq!(plan["event"], "transmit_plan");
+    assert_eq!(plan["frames"], 150, "3s of 20ms frames");
+    assert_eq!(plan["packet_bytes"], 172);
+    assert_eq!(plan["destination"]["multicast"], true);
+    assert!(plan["problems"].as_array().expect("problems should be array").is_empty());
+
+    // Problems are reported and fail the run
+    let output = dry_run(&["--ttl", "0"]);
+    assert!(!output.status.success(), "Dry run with TTL 0 should fail");
+    let plan = parse_plan(&output.stdout);
+    assert!(plan["problems"].to_string().contains("TTL 0"));
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    // Nothing was sent
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    assert_eq!(summary["pages"].as_array().expect("pages should be array").len(), 0);
+}
+
+#[test]
+fn test_transmit_headerless_ulaw() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    // 3 seconds of a 600Hz tone as bare u-law bytes; the extension is the only hint
+    let ulaw_path = temp_dir.path().join("tone_600hz.ulaw");
+    let bytes: Vec<u8> = (0..24_000)
+        .map(|i| {
+            let t = i as f32 / 8000.0;
+            linear_to_ulaw((0.5 * (2.0 * std::f32::consts::PI * 600.0 * t).sin() * 32767.0) as i16)
+        })
+        .collect();
+    fs::write(&ulaw_path, bytes).expect("Failed to write u-law file");
+
+    let multicast_addr = "224.0.123.15";
+    let port = "15016";
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "8",
+            "--codec", "g711ulaw",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit = Command::new(&binary)
+        .args([
+            "transmit",
+            "--file", ulaw_path.to_str().unwrap(),
+            "--address", multicast_addr,
+            "--port", port,
+            "--codec", "g711ulaw",
+        ])
+        .output()
+        .expect("Failed to run transmit");
+    assert!(transmit.status.success(), "Transmit command failed");
+    assert!(
+        String::from_utf8_lossy(&transmit.stdout).contains("headerless ulaw"),
+        "Inferring the format should print a notice"
+    );
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1, "Should detect exactly 1 page");
+
+    let duration = pages[0]["duration_secs"].as_f64().expect("duration should be f64");
+    assert!((2.5..=3.5).contains(&duration), "Duration {} should be approximately 3 seconds", duration);
+
+    let freq = pages[0]["audio"]["dominant_freq_hz"].as_f64().expect("freq should be f64");
+    assert!(
+        (540.0..=660.0).contains(&freq),
+        "Dominant frequency {} should be approximately 600 Hz",
+        freq
+    );
+}
+
+#[test]
+fn test_polycom_g722_round_trip() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+    if !ffmpeg_available() {
+        eprintln!("Skipping test: ffmpeg not available");
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("recordings");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 3.0, 16000);
+
+    let multicast_addr = "224.0.123.11";
+    let port = "15012";
+
+    let monitor = Command::new(&binary)
+        .args([
+            "polycom-monitor",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "10",
+            "--json",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start polycom monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit_status = Command::new(&binary)
+        .args([
+            "polycom-transmit",
+            "--file", wav_path.to_str().unwrap(),
+            "--address", multicast_addr,
+            "--port", port,
+            "--channel", "26",
+            "--codec", "g722",
+        ])
+        .stdout(Stdio::null())
+        .status()
+        .expect("Failed to run polycom transmit");
+    assert!(transmit_status.success(), "Polycom transmit command failed");
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Polycom monitor command failed");
+
+    let summary: serde_json::Value =
+        serde_json::from_slice(&monitor_output.stdout).expect("Failed to parse monitor summary");
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1, "Expected exactly one page");
+
+    // The timeline is rebuilt from sample_count, so it covers the whole tone
+    let audio_secs = pages[0]["audio_secs"].as_f64().expect("audio_secs should be f64");
+    assert!((2.9..=3.1).contains(&audio_secs), "Audio timeline {}s should be ~3s", audio_secs);
+
+    // The buffered tail is flushed into the recording
+    let recording = pages[0]["recording_file"].as_str().expect("recording_file should be set");
+    let reader = hound::WavReader::open(output_dir.join(recording)).expect("Failed to open recording");
+    let seconds = f64::from(reader.duration()) / f64::from(reader.spec().sample_rate);
+    assert_eq!(reader.spec().sample_rate, 16000);
+    assert!((2.9..=3.1).contains(&seconds), "Recording {}s should be ~3s", seconds);
+}
+
+#[test]
+fn test_polycom_transmit_also_rtp() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+    if !ffmpeg_available() {
+        eprintln!("Skipping test: ffmpeg not available");
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let polycom_dir = temp_dir.path().join("polycom");
+    let rtp_dir = temp_dir.path().join("rtp");
+    fs::create_dir_all(&polycom_dir).expect("Failed to create output dir");
+    fs::create_dir_all(&rtp_dir).expect("Failed to create output dir");
+
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 3.0, 8000);
+
+    let polycom_monitor = Command::new(&binary)
+        .args([
+            "polycom-monitor",
+            "--address", "224.0.123.12",
+            "--port", "15013",
+            "--output", polycom_dir.to_str().unwrap(),
+            "--timeout", "10",
+            "--json",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start polycom monitor");
+    let rtp_monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", "224.0.123.13",
+            "--port", "15014",
+            "--output", rtp_dir.to_str().unwrap(),
+            "--timeout", "10",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start RTP monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit_status = Command::new(&binary)
+        .args([
+            "polycom-transmit",
+            "--file", wav_path.to_str().unwrap(),
+            "--address", "224.0.123.12",
+            "--port", "15013",
+            "--channel", "26",
+            "--codec", "g711u",
+            "--also-rtp", "224.0.123.13:15014,g711ulaw",
+        ])
+        .stdout(Stdio::null())
+        .status()
+        .expect("Failed to run polycom transmit");
+    assert!(transmit_status.success(), "Polycom transmit command failed");
+
+    let polycom_output = polycom_monitor.wait_with_output().expect("Failed to wait for polycom monitor");
+    assert!(polycom_output.status.success(), "Polycom monitor command failed");
+    let rtp_output = rtp_monitor.wait_with_output().expect("Failed to wait for RTP monitor");
+    assert!(rtp_output.status.success(), "RTP monitor command failed");
+
+    let wav_seconds = |path: PathBuf| -> f64 {
+        let reader = hound::WavReader::open(path).expect("Failed to open recording");
+        f64::from(reader.duration()) / f64::from(reader.spec().sample_rate)
+    };
+
+    let summary: serde_json::Value =
+        serde_json::from_slice(&polycom_output.stdout).expect("Failed to parse monitor summary");
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1, "Expected exactly one Polycom page");
+    let recording = pages[0]["recording_file"].as_str().expect("recording_file should be set");
+    let polycom_secs = wav_seconds(polycom_dir.join(recording));
+
+    let recordings: Vec<PathBuf> = fs::read_dir(&rtp_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wav"))
+        .collect();
+    assert_eq!(recordings.len(), 1, "Expected exactly one RTP recording");
+    let rtp_secs = wav_seconds(recordings[0].clone());
+
+    // Both legs carry the same frames, so the recordings differ by at most one 20ms frame
+    assert!(
+        (polycom_secs - rtp_secs).abs() <= 0.02 + 1e-6,
+        "Polycom recording {}s and RTP recording {}s should match",
+        polycom_secs,
+        rtp_secs
+    );
+}
+
+#[test]
+fn test_validate_config() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, "[polycom]\nmax_duration_secs = 30\n\n[[polycom.channels]]\nchanel = 26\n").unwrap();
+    let output = Command::new(&binary)
+        .args(["validate-config", "--config"])
+        .arg(&path)
+        .output()
+        .expect("Failed to run validate-config");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("5:1: polycom.channels[0].chanel: unknown key (did you mean `channel`?)"), "{}", stderr);
+
+    fs::write(&path, "[polycom]\nmax_duration_secs = 30\n\n[[polycom.channels]]\nchannel = 26\n").unwrap();
+    let output = Command::new(&binary)
+        .args(["validate-config", "--config"])
+        .arg(&path)
+        .output()
+        .expect("Failed to run validate-config");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("OK: "), "{}", stdout);
+    assert!(stdout.contains("default_port = 5004"), "defaults are filled in");
+    assert!(stdout.contains("max_duration_secs = 30"));
+}
+
+#[test]
+fn test_doctor() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let dir = TempDir::new().unwrap();
+    let doctor = |output: &std::path::Path| -> (bool, serde_json::Value) {
+        let output = Command::new(&binary)
+            .args(["doctor", "--json", "--endpoints", "50", "--output"])
+            .arg(output)
+            .output()
+            .expect("Failed to run doctor");
+        (output.status.success(), serde_json::from_slice(&output.stdout).expect("doctor --json should be JSON"))
+    };
+    let check = |report: &serde_json::Value, name: &str| -> serde_json::Value {
+        report["checks"]
+            .as_array()
+            .expect("checks should be array")
+            .iter()
+            .find(|check| check["name"] == name)
+            .unwrap_or_else(|| panic!("no {} check", name))
+            .clone()
+    };
+
+    // Warnings alone don't fail the run
+    let (success, report) = doctor(dir.path());
+    assert_eq!(check(&report, "Output directory")["status"], "pass");
+    assert_eq!(success, report["failures"] == 0);
+
+    // A file where the output directory should be is a hard failure
+    let file = dir.path().join("not-a-dir");
+    fs::write(&file, "").unwrap();
+    let (success, report) = doctor(&file);
+    assert!(!success);
+    let output_check = check(&report, "Output directory");
+    assert_eq!(output_check["status"], "fail");
+    assert!(output_check["hint"].as_str().is_some_and(|hint| !hint.is_empty()));
+}
+
+#[test]
+fn test_capabilities_follow_ffmpeg_availability() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let run = |args: &[&str], path: Option<&std::path::Path>| -> serde_json::Value {
+        let mut command = Command::new(&binary);
+        command.args(args);
+        if let Some(path) = path {
+            command.env("PATH", path);
+        }
+        let output = command.output().expect("Failed to run capabilities");
+        assert!(output.status.success(), "{:?} failed", args);
+        serde_json::from_slice(&output.stdout).expect("Capabilities should be JSON")
+    };
+    let g722_usable = |capabilities: &serde_json::Value| -> bool {
+        capabilities["codecs"]
+            .as_array()
+            .expect("codecs should be array")
+            .iter()
+            .find(|codec| codec["name"] == "g722")
+            .expect("g722 should be listed")["usable"]
+            .as_bool()
+            .expect("usable should be bool")
+    };
+
+    let capabilities = run(&["capabilities"], None);
+    assert_eq!(capabilities["schema_version"], 1);
+    for key in ["version", "codecs", "protocols", "payload_types", "commands", "platform", "dependencies"] {
+        assert!(capabilities.get(key).is_some(), "capabilities missing {}", key);
+    }
+    let g722_backend = &capabilities["ffmpeg_codecs"]["g722"];
+    assert_eq!(g722_usable(&capabilities), g722_backend["encode"] == true && g722_backend["decode"] == true);
+
+    // --version --json is the same document
+    let versioned = run(&["--version", "--json"], None);
+    let names = |document: &serde_json::Value, key: &str| -> Vec<String> {
+        document[key]
+            .as_array()
+            .expect("should be array")
+            .iter()
+            .map(|entry| entry["name"].as_str().expect("name should be string").to_string())
+            .collect()
+    };
+    assert_eq!(versioned["schema_version"], capabilities["schema_version"]);
+    assert_eq!(names(&versioned, "codecs"), names(&capabilities, "codecs"));
+    assert_eq!(versioned["commands"], capabilities["commands"]);
+
+    // With ffmpeg out of PATH, G.722 is reported unusable
+    let empty_path = TempDir::new().expect("Failed to create temp dir");
+    let capabilities = run(&["capabilities"], Some(empty_path.path()));
+    assert_eq!(capabilities["dependencies"]["ffmpeg"], false);
+    assert!(!g722_usable(&capabilities));
+    let g711 = &capabilities["codecs"][0];
+    assert_eq!(g711["name"], "g711ulaw");
+    assert_eq!(g711["usable"], true);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_minimal_ffmpeg_build() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    // An ffmpeg that has the G.722 decoder but not the encoder
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let ffmpeg = temp_dir.path().join("ffmpeg");
+    fs::write(
+        &ffmpeg,
+        "#!/bin/sh\n\
+         echo 'Codecs:'\n\
+         echo ' D..... = Decoding supported'\n\
+         echo ' -------'\n\
+         echo ' D.AI.S g722                 G.722 ADPCM (decoders: g722 )'\n\
+         echo ' DEAIL. pcm_mulaw            PCM mu-law / G.711 mu-law'\n",
+    )
+    .expect("Failed to write fake ffmpeg");
+    fs::set_permissions(&ffmpeg, fs::Permissions::from_mode(0o755)).expect("Failed to make fake ffmpeg executable");
+
+    let output = Command::new(&binary)
+        .arg("capabilities")
+        .env("MPU_FFMPEG", &ffmpeg)
+        .output()
+        .expect("Failed to run capabilities");
+    assert!(output.status.success());
+    let capabilities: serde_json::Value = serde_json::from_slice(&output.stdout).expect("Capabilities should be JSON");
+    assert_eq!(capabilities["dependencies"]["ffmpeg"], true);
+    assert_eq!(capabilities["ffmpeg_codecs"]["g722"], serde_json::json!({"decode": true, "encode": false}));
+    let g722 = capabilities["codecs"]
+        .as_array()
+        .expect("codecs should be array")
+        .iter()
+        .find(|codec| codec["name"] == "g722")
+        .expect("g722 should be listed");
+    assert_eq!(g722["can_decode"], true);
+    assert_eq!(g722["can_encode"], false);
+    assert_eq!(g722["unavailable_reason"], "G.722 needs the ffmpeg g722 encoder, which isn't available");
+
+    // Transmitting G.722 fails up front, saying what to do about it
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 0.5, 16000);
+    let output = Command::new(&binary)
+        .args([
+            "transmit",
+            "--file", wav_path.to_str().unwrap(),
+            "--address", "224.0.123.46",
+            "--port", "15046",
+            "--codec", "g722",
+        ])
+        .env("MPU_FFMPEG", &ffmpeg)
+        .output()
+        .expect("Failed to run transmit");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Your ffmpeg lacks the G.722 encoder: install a full ffmpeg build (or point MPU_FFMPEG at one), or use --codec g711ulaw"),
+        "stderr: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_igmp_cycle_over_loopback() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    // Our own stream keeps flowing while the second socket leaves and re-joins
+    let output = Command::new(&binary)
+        .args([
+            "igmp-cycle",
+            "--address", "224.0.123.17",
+            "--port", "15018",
+            "--transmit",
+            "--cycles", "3",
+            "--interval", "300",
+            "--timeout", "5",
+            "--json",
+        ])
+        .output()
+        .expect("Failed to run igmp-cycle");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "igmp-cycle failed: {}{}", stdout, String::from_utf8_lossy(&output.stderr));
+
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .map(|line| serde_json::from_str(line).expect("Failed to parse event"))
+        .collect();
+    let cycles: Vec<&serde_json::Value> = events.iter().filter(|e| e["event"] == "igmp_cycle").collect();
+    assert_eq!(cycles.len(), 3);
+    for cycle in cycles {
+        assert_eq!(cycle["interrupted"], false, "{}", cycle);
+        assert!(cycle["packets"].as_u64().expect("packets should be number") > 0);
+    }
+
+    let report = events.last().expect("No report");
+    assert_eq!(report["event"], "igmp_cycle_report");
+    assert_eq!(report["verdict"], "pass");
+    assert_eq!(report["same_interface"], true);
+    let spacing = report["spacing_ms"].as_f64().expect("spacing_ms should be number");
+    assert!((15.0..30.0).contains(&spacing), "spacing {}ms", spacing);
+}
+
+#[test]
+fn test_transmit_frame_cache() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    let cache_dir = temp_dir.path().join("cache");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 2.0, 8000);
+
+    let multicast_addr = "224.0.123.18";
+    let port = "15019";
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "10",
+            "--codec", "g711ulaw",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit = || -> serde_json::Value {
+        let output = Command::new(&binary)
+            .args([
+                "transmit",
+                "--file", wav_path.to_str().unwrap(),
+                "--address", multicast_addr,
+                "--port", port,
+                "--codec", "g711ulaw",
+                "--cache-dir", cache_dir.to_str().unwrap(),
+                "--json",
+            ])
+            .output()
+            .expect("Failed to run transmit");
+        assert!(output.status.success(), "Transmit command failed");
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .find(|event: &serde_json::Value| event["event"] == "transmit_report")
+            .expect("transmit_report event not found")
+    };
+
+    // The first run encodes and stores, the second skips the encode
+    let first = transmit();
+    assert_eq!(first["frame_cache"]["hits"], 0);
+    assert_eq!(first["frame_cache"]["misses"], 1);
+    let second = transmit();
+    assert_eq!(second["frame_cache"]["hits"], 1);
+    assert_eq!(second["frame_cache"]["misses"], 0);
+    assert_eq!(second["frame_cache"]["encode_secs"], 0.0);
+    assert_eq!(first["packets_sent"], second["packets_sent"]);
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    // Both pages carried the same audio
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 2, "Each transmit should be its own page");
+    assert_eq!(pages[0]["network"]["packets_received"], 100);
+    assert_eq!(pages[0]["integrity"]["pcm_sha256"], pages[1]["integrity"]["pcm_sha256"]);
+
+    let list = Command::new(&binary)
+        .args(["cache", "--cache-dir", cache_dir.to_str().unwrap(), "list", "--json"])
+        .output()
+        .expect("Failed to run cache list");
+    let entries: serde_json::Value = serde_json::from_slice(&list.stdout).expect("cache list should be JSON");
+    let entries = entries.as_array().expect("entries should be array");
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["status"], "current");
+    assert_eq!(entries[0]["frame_count"], 100);
+    assert_eq!(entries[0]["key"]["codec"], "g711ulaw");
+
+    let evict = Command::new(&binary)
+        .args(["cache", "--cache-dir", cache_dir.to_str().unwrap(), "evict", "--all"])
+        .output()
+        .expect("Failed to run cache evict");
+    assert!(evict.status.success());
+    assert_eq!(fs::read_dir(&cache_dir).expect("cache dir should exist").count(), 0);
+}
+
+#[test]
+fn test_name_template() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+
+    // An unknown token is refused before anything starts
+    let refused = Command::new(&binary)
+        .args(["test", "--address", "224.0.123.22", "--timeout", "1", "--name-template", "{zone}.wav", "--output"])
+        .arg(&output_dir)
+        .output()
+        .expect("Failed to run test");
+    assert!(!refused.status.success());
+    let stderr = String::from_utf8_lossy(&refused.stderr);
+    assert!(stderr.contains("{zone}") && stderr.contains("{caller}"), "{}", stderr);
+
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 1.0, 8000);
+    let multicast_addr = "224.0.123.22";
+    let port = "15022";
+
+    // A recording from an earlier run already has the first page's name
+    let endpoint_dir = output_dir.join("224.0.123.22_15022");
+    fs::create_dir_all(&endpoint_dir).unwrap();
+    fs::write(endpoint_dir.join("HQ_West_224.0.123.22_15022_0001.wav"), b"").unwrap();
+
+    let pcap_template = temp_dir.path().join("{site}.pcap");
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "5",
+            "--name-template", "{site}_{endpoint}_{page:04}.wav",
+            "--site", "HQ West",
+            "--pcap", pcap_template.to_str().unwrap(),
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit_status = Command::new(&binary)
+        .args(["transmit", "--file", wav_path.to_str().unwrap(), "--address", multicast_addr, "--port", port, "--quiet"])
+        .status()
+        .expect("Failed to run transmit");
+    assert!(transmit_status.success(), "Transmit command failed");
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1);
+    assert_eq!(pages[0]["recording_file"], "224.0.123.22_15022/HQ_West_224.0.123.22_15022_0001-1.wav");
+    assert!(fs::metadata(endpoint_dir.join("HQ_West_224.0.123.22_15022_0001-1.wav")).unwrap().len() > 44);
+    assert!(temp_dir.path().join("HQ_West.pcap").exists());
+}
+
+#[test]
+fn test_transmit_sequence() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    let sequence = "tone:800:2s:-6db; silence:250ms; tone:800:1s:-20db";
+    let describe = Command::new(&binary)
+        .args(["transmit", "--sequence", sequence, "--describe"])
+        .output()
+        .expect("Failed to run transmit");
+    assert!(describe.status.success(), "Describe failed");
+    let plan = String::from_utf8_lossy(&describe.stdout);
+    assert!(plan.contains("Sequence: 3 segment(s), 3.250s"), "plan: {}", plan);
+    assert!(plan.contains("2.250s  tone    800 Hz for 1.000s at -20.0 dBFS"), "plan: {}", plan);
+
+    let multicast_addr = "224.0.123.23";
+    let port = "15023";
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "8",
+            "--codec", "g711ulaw",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit_status = Command::new(&binary)
+        .args([
+            "transmit",
+            "--sequence", sequence,
+            "--address", multicast_addr,
+            "--port", port,
+            "--codec", "g711ulaw",
+            "--quiet",
+        ])
+        .status()
+        .expect("Failed to run transmit");
+    assert!(transmit_status.success(), "Transmit command failed");
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1, "The short silence should stay within one page");
+
+    let duration = pages[0]["duration_secs"].as_f64().expect("duration should be f64");
+    assert!((2.9..=3.6).contains(&duration), "Duration {} should be approximately 3.25 seconds", duration);
+
+    let freq = pages[0]["audio"]["dominant_freq_hz"].as_f64().expect("freq should be f64");
+    assert!((740.0..=860.0).contains(&freq), "Dominant frequency {} should be approximately 800 Hz", freq);
+}
+
+#[test]
+fn test_polycom_delivery_receipt() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    // Pre-encoded u-law frames, so neither side needs ffmpeg
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let raw_path = temp_dir.path().join("tone.ulaw");
+    let bytes: Vec<u8> = (0..16_000)
+        .map(|i| {
+            let t = i as f32 / 8000.0;
+            linear_to_ulaw((0.5 * (2.0 * std::f32::consts::PI * 1000.0 * t).sin() * 32767.0) as i16)
+        })
+        .collect();
+    fs::write(&raw_path, bytes).expect("Failed to write u-law file");
+
+    let multicast_addr = "224.0.123.24";
+    let port = "15024";
+    let receipt_addr = "127.0.0.1:15025";
+
+    let monitor = Command::new(&binary)
+        .args([
+            "polycom-monitor",
+            "--address", multicast_addr,
+            "--port", port,
+            "--timeout", "8",
+            "--receipt", receipt_addr,
+            "--json",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start polycom monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit = Command::new(&binary)
+        .args([
+            "polycom-transmit",
+            "--file", raw_path.to_str().unwrap(),
+            "--raw",
+            "--address", multicast_addr,
+            "--port", port,
+            "--channel", "26",
+            "--codec", "g711u",
+            "--await-receipts", "1:5",
+            "--receipt-listen", receipt_addr,
+            "--json",
+        ])
+        .output()
+        .expect("Failed to run polycom transmit");
+    assert!(
+        transmit.status.success(),
+        "Polycom transmit failed: {}",
+        String::from_utf8_lossy(&transmit.stderr)
+    );
+
+    let events: Vec<serde_json::Value> = String::from_utf8_lossy(&transmit.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Failed to parse event"))
+        .collect();
+    let receipts = events
+        .iter()
+        .find(|event| event["event"] == "receipts")
+        .expect("No receipts event");
+    let completed = events.last().expect("No events");
+    assert_eq!(completed["event"], "transmit_completed");
+
+    let receipts = receipts["receipts"].as_array().expect("receipts should be array");
+    assert_eq!(receipts.len(), 1, "Expected exactly one receipt: {:?}", receipts);
+    assert_eq!(receipts[0]["channel"], 26);
+    assert_eq!(receipts[0]["audio_packets"], completed["frames_sent"]);
+    assert_eq!(receipts[0]["frames_concealed"], 0);
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Polycom monitor command failed");
+
+    // Nobody answers this time, so the transmitter fails
+    let transmit = Command::new(&binary)
+        .args([
+            "polycom-transmit",
+            "--file", raw_path.to_str().unwrap(),
+            "--raw",
+            "--address", multicast_addr,
+            "--port", port,
+            "--codec", "g711u",
+            "--max-duration", "1",
+            "--await-receipts", "1:1",
+            "--receipt-listen", receipt_addr,
+            "--quiet",
+        ])
+        .output()
+        .expect("Failed to run polycom transmit");
+    assert!(!transmit.status.success(), "Transmit without receipts should fail");
+    assert!(
+        String::from_utf8_lossy(&transmit.stderr).contains("MissingReceipts"),
+        "stderr: {}",
+        String::from_utf8_lossy(&transmit.stderr)
+    );
+}
+
+/// `monitor --print-effective-config` run in `dir`, whose `config` directory
+/// holds the config file, with `env` set
+fn effective_port(dir: &std::path::Path, args: &[&str], env: &[(&str, &str)]) -> String {
+    let output = Command::new(binary_path())
+        .args(["monitor", "--address", "224.0.1.1"])
+        .args(args)
+        .arg("--print-effective-config")
+        .current_dir(dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .envs(env.iter().copied())
+        .output()
+        .expect("Failed to run monitor");
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    stdout
+        .lines()
+        .find(|line| line.trim_start().starts_with("--port "))
+        .unwrap_or_else(|| panic!("no --port in {}", stdout))
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[test]
+fn test_setting_precedence() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let dir = temp_dir.path();
+    assert_eq!(effective_port(dir, &[], &[]), "--port 5004 # default");
+
+    let config_dir = dir.join("config").join("multicast-paging-utility");
+    fs::create_dir_all(&config_dir).expect("Failed to create config dir");
+    fs::write(config_dir.join("config.toml"), "[network]\ndefault_port = 5555\n").expect("Failed to write config");
+
+    // Config file over default, environment over config file, command line over environment
+    assert_eq!(effective_port(dir, &[], &[]), "--port 5555 # config file");
+    assert_eq!(effective_port(dir, &[], &[("MPU_PORT", "6000")]), "--port 6000 # environment (MPU_PORT)");
+    assert_eq!(
+        effective_port(dir, &["--port", "7000"], &[("MPU_PORT", "6000")]),
+        "--port 7000 # command line"
+    );
+
+    // .env sits between the environment and the config file
+    fs::write(dir.join(".env"), "# probe\nMPU_PORT=6100\n").expect("Failed to write .env");
+    assert_eq!(effective_port(dir, &[], &[]), "--port 6100 # .env (MPU_PORT)");
+    assert_eq!(effective_port(dir, &[], &[("MPU_PORT", "6000")]), "--port 6000 # environment (MPU_PORT)");
+}
+
+#[test]
+fn test_summary_records_settings() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    // Everything but the address from the environment
+    let output = Command::new(&binary)
+        .args(["test", "--address", "224.0.123.40"])
+        .current_dir(temp_dir.path())
+        .env("MPU_PORT", "15040")
+        .env("MPU_OUTPUT_DIR", &output_dir)
+        .env("MPU_TIMEOUT", "1")
+        .env("MPU_METRICS_INTERVAL", "250")
+        .output()
+        .expect("Failed to run test");
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Setting: --timeout 1 (from environment)"));
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let metadata = &summary["test_metadata"];
+    assert_eq!(metadata["metrics_interval_ms"], 250);
+    let settings = metadata["settings"].as_array().expect("settings should be array");
+    let setting = |name: &str| settings.iter().find(|s| s["name"] == name).expect("setting missing").clone();
+    assert_eq!(setting("address")["source"], "command_line");
+    assert_eq!(setting("port")["value"], "15040");
+    assert_eq!(setting("port")["source"], "environment");
+    assert_eq!(setting("metrics_interval")["variable"], "MPU_METRICS_INTERVAL");
+    assert_eq!(setting("codec")["source"], "default");
+    assert!(setting("codec")["value"].is_null());
+}
+
+#[test]
+fn test_config_file_defaults() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let config_path = temp_dir.path().join("mpu.toml");
+
+    // config init writes a starter file that validates, and won't replace it
+    let init = || {
+        Command::new(&binary)
+            .args(["config", "init", "--config"])
+            .arg(&config_path)
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run config init")
+    };
+    let output = init();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let output = init();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already exists"));
+    let output = Command::new(&binary)
+        .args(["validate-config", "--config"])
+        .arg(&config_path)
+        .output()
+        .expect("Failed to run validate-config");
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+
+    let runs = temp_dir.path().join("runs");
+    fs::write(
+        &config_path,
+        format!(
+            "[site]\nname = \"Head Office\"\ncode = \"HQ\"\n\n[output]\ndirectory = '{}/%Y'\n\n[test]\nmetrics_interval_ms = 250\n",
+            runs.display()
+        ),
+    )
+    .unwrap();
+    let test = |config: &Path| {
+        Command::new(&binary)
+            .args(["test", "--address", "224.0.123.41", "--port", "15041", "--timeout", "1", "--config"])
+            .arg(config)
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("Failed to run test")
+    };
+    let output = test(&config_path);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+
+    // The output directory's %Y was filled in
+    let run_dir = fs::read_dir(&runs).unwrap().next().expect("no output directory").unwrap().path();
+    let summary = parse_summary(&run_dir.join("summary.json"));
+    let metadata = &summary["test_metadata"];
+    assert_eq!(metadata["metrics_interval_ms"], 250);
+    assert_eq!(metadata["site"], "HQ");
+    assert_eq!(metadata["site_name"], "Head Office");
+    assert_eq!(metadata["config_file"]["path"], config_path.display().to_string());
+    assert_eq!(metadata["config_file"]["sha256"].as_str().map(str::len), Some(64));
+
+    // A config file asked for by name has to be there
+    let output = test(&temp_dir.path().join("missing.toml"));
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not found"));
+}
+
+#[test]
+fn test_compare_redundant_paths() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    // The same program on both channels, the right one 6 dB down and inverted.
+    // Tones plus noise, so the polarity can't be mistaken for a half-period shift
+    let wav_path = temp_dir.path().join("paths.wav");
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: 8000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&wav_path, spec).expect("Failed to create WAV");
+    let mut seed = 7u32;
+    for i in 0..24_000 {
+        let t = f64::from(i) / 8000.0;
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let noise = f64::from(seed >> 16) / 32768.0 - 1.0;
+        let tones: f64 = [400.0, 900.0, 1700.0].iter().map(|f| (t * 2.0 * std::f64::consts::PI * f).sin()).sum();
+        let sample = 4000.0 * (tones + noise);
+        writer.write_sample(sample as i16).expect("Failed to write sample");
+        writer.write_sample((-0.5 * sample) as i16).expect("Failed to write sample");
+    }
+    writer.finalize().expect("Failed to finalize WAV");
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", "224.0.123.{44-45}:15044",
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "8",
+            "--codec", "g711ulaw",
+            "--compare-endpoints", "A=224.0.123.44:15044,B=224.0.123.45:15044",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit_status = Command::new(&binary)
+        .args([
+            "transmit",
+            "--file", wav_path.to_str().unwrap(),
+            "--multi-zone", "left=224.0.123.44:15044,right=224.0.123.45:15044",
+            "--codec", "g711ulaw",
+            "--quiet",
+        ])
+        .status()
+        .expect("Failed to run transmit");
+    assert!(transmit_status.success(), "Transmit command failed");
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let comparisons = summary["path_comparisons"].as_array().expect("path_comparisons should be array");
+    assert_eq!(comparisons.len(), 1, "{:#?}", comparisons);
+    let comparison = &comparisons[0];
+    assert_eq!(comparison["b_page"], 1);
+    assert_eq!(comparison["polarity_inverted"], true, "{:#?}", comparison);
+    let level = comparison["level_difference_db"].as_f64().expect("level should be f64");
+    assert!((level + 6.0).abs() < 0.5, "Level difference {} dB", level);
+    assert_eq!(comparison["verdict"], "FAIL");
+
+    // An endpoint that isn't monitored can't be compared
+    let output = Command::new(&binary)
+        .args([
+            "test",
+            "--address", "224.0.123.44:15044",
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "1",
+            "--compare-endpoints", "A=224.0.123.44:15044,B=224.0.123.46:15044",
+        ])
+        .output()
+        .expect("Failed to run test");
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("UncomparableEndpoint(224.0.123.46:15044)"),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_dscp_marking_reaches_monitor() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 2.0, 8000);
+
+    let multicast_addr = "224.0.123.47";
+    let port = "15047";
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "6",
+            "--codec", "g711ulaw",
+            "--expect-dscp", "ef",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit_output = Command::new(&binary)
+        .args([
+            "transmit",
+            "--file", wav_path.to_str().unwrap(),
+            "--address", multicast_addr,
+            "--port", port,
+            "--codec", "g711ulaw",
+            "--dscp", "ef",
+            "--json",
+        ])
+        .output()
+        .expect("Failed to run transmit");
+    assert!(transmit_output.status.success(), "Transmit command failed");
+
+    let stdout = String::from_utf8_lossy(&transmit_output.stdout);
+    let started: serde_json::Value =
+        serde_json::from_str(stdout.lines().next().expect("no events")).expect("first line should be JSON");
+    assert_eq!(started["event"], "transmit_started");
+    assert_eq!(started["dscp"], 46);
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1);
+    let dscp = &pages[0]["network"]["dscp"];
+    if dscp.is_null() {
+        eprintln!("Platform doesn't report received DSCP; skipping the arrival check");
+        return;
+    }
+    assert_eq!(dscp["most_common"], 46, "EF marking should survive loopback: {}", dscp);
+    assert_eq!(dscp["expected"], 46);
+    assert_eq!(dscp["unexpected_packets"], 0);
+}
+
+#[test]
+fn test_bridge_rtp_to_polycom() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("polycom");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 3.0, 8000);
+
+    let polycom_monitor = Command::new(&binary)
+        .args([
+            "polycom-monitor",
+            "--address", "224.0.123.49",
+            "--port", "15049",
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "12",
+            "--json",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start polycom monitor");
+    let bridge = Command::new(&binary)
+        .args([
+            "bridge",
+            "--address", "224.0.123.48",
+            "--port", "15048",
+            "--to", "224.0.123.49:15049",
+            "--channel", "26",
+            "--codec", "g711u",
+            "--timeout", "10",
+            "--json",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start bridge");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit_status = Command::new(&binary)
+        .args([
+            "transmit",
+            "--file", wav_path.to_str().unwrap(),
+            "--address", "224.0.123.48",
+            "--port", "15048",
+            "--codec", "g711alaw",
+        ])
+        .stdout(Stdio::null())
+        .status()
+        .expect("Failed to run transmit");
+    assert!(transmit_status.success(), "Transmit command failed");
+
+    let bridge_output = bridge.wait_with_output().expect("Failed to wait for bridge");
+    assert!(bridge_output.status.success(), "Bridge command failed");
+    let monitor_output = polycom_monitor.wait_with_output().expect("Failed to wait for polycom monitor");
+    assert!(monitor_output.status.success(), "Polycom monitor command failed");
+
+    let events: Vec<serde_json::Value> = String::from_utf8_lossy(&bridge_output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let ended: Vec<&serde_json::Value> = events.iter().filter(|e| e["event"] == "bridge_page_ended").collect();
+    assert_eq!(ended.len(), 1, "Expected one bridged page, got {:?}", events);
+    assert_eq!(ended[0]["source_codec"], "G.711 A-law");
+    assert_eq!(ended[0]["underruns"], 0);
+    let frames = ended[0]["frames_bridged"].as_u64().unwrap();
+    assert!((145..=155).contains(&frames), "Expected about 150 frames, bridged {}", frames);
+
+    // The summary follows any log lines
+    let stdout = String::from_utf8_lossy(&monitor_output.stdout);
+    let summary_start = stdout.find("\n{").map_or(0, |at| at + 1);
+    let summary: serde_json::Value =
+        serde_json::from_str(&stdout[summary_start..]).expect("Failed to parse monitor summary");
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1, "Expected exactly one Polycom page");
+    let recording = pages[0]["recording_file"].as_str().expect("recording_file should be set");
+
+    let mut reader = hound::WavReader::open(output_dir.join(recording)).expect("Failed to open recording");
+    let rate = f64::from(reader.spec().sample_rate);
+    let samples: Vec<i16> = reader.samples::<i16>().map(|s| s.unwrap()).collect();
+    let secs = samples.len() as f64 / rate;
+    assert!((secs - 3.0).abs() < 0.1, "Expected about 3s of audio, recorded {}s", secs);
+
+    // Zero crossings of the middle second give the tone's frequency
+    let middle = &samples[rate as usize..2 * rate as usize];
+    let crossings = middle.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count();
+    let frequency = crossings as f64 / 2.0;
+    assert!((frequency - 1000.0).abs() < 20.0, "Expected a 1kHz tone, measured {}Hz", frequency);
+}
+
+#[test]
+fn test_monitor_count() {
+    use std::time::Instant;
+
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 1.0, 8000);
+
+    // A page ends after a second without packets. The second page is
+    // ignored until the first has ended, and by then the count is reached
+    let started = Instant::now();
+    let monitor = Command::new(&binary)
+        .args([
+            "monitor",
+            "--address", "224.0.123.50:15050 idle=1s",
+            "--output", output_dir.to_str().unwrap(),
+            "--name-template", "page_{page}.wav",
+            "--timeout", "30",
+            "--count", "1",
+            "--json",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    for _ in 0..2 {
+        let transmit_status = Command::new(&binary)
+            .args(["transmit", "--file", wav_path.to_str().unwrap(), "--address", "224.0.123.50", "--port", "15050", "--quiet"])
+            .status()
+            .expect("Failed to run transmit");
+        assert!(transmit_status.success(), "Transmit command failed");
+    }
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+    assert!(started.elapsed() < Duration::from_secs(10), "Monitor ran for {:?}", started.elapsed());
+
+    let recordings: Vec<_> = fs::read_dir(&output_dir)
+        .unwrap()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "wav"))
+        .collect();
+    assert_eq!(recordings.len(), 1, "Expected exactly one recording");
+
+    let events: Vec<serde_json::Value> = String::from_utf8_lossy(&monitor_output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let last = events.last().expect("Expected JSON events");
+    assert_eq!(last["event"], "count_summary");
+    assert_eq!(last["pages_captured"], 1);
+    assert_eq!(last["pages_requested"], 1);
+    assert_eq!(last["met"], true);
+
+    // Timing out first is its own exit status
+    let output = Command::new(&binary)
+        .args(["monitor", "--address", "224.0.123.50", "--port", "15050", "--timeout", "1", "--count", "1", "--quiet"])
+        .output()
+        .expect("Failed to run monitor");
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Only 0 of 1 pages received"), "{}", stderr);
+}
+
+#[test]
+fn test_monitor_forward() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 3.0, 8000);
+
+    // The first monitor sends what it hears on to the second, as A-law
+    let monitor = |address: &str, forward: Option<&str>| {
+        let mut command = Command::new(&binary);
+        command.args(["monitor", "--address", address, "--timeout", "10", "--json"]);
+        if let Some(forward) = forward {
+            command.args(["--forward", forward]);
+        }
+        command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Failed to start monitor")
+    };
+    let forwarding = monitor("224.0.123.51:15051 idle=1s", Some("224.0.123.52:15052,g711alaw"));
+    let receiving = monitor("224.0.123.52:15052 idle=1s", None);
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit_status = Command::new(&binary)
+        .args(["transmit", "--file", wav_path.to_str().unwrap(), "--address", "224.0.123.51", "--port", "15051", "--quiet"])
+        .status()
+        .expect("Failed to run transmit");
+    assert!(transmit_status.success(), "Transmit command failed");
+
+    let page_ended = |child: std::process::Child| -> Vec<serde_json::Value> {
+        let output = child.wait_with_output().expect("Failed to wait for monitor");
+        assert!(output.status.success(), "Monitor command failed");
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .filter(|event| event["event"] == "page_ended")
+            .collect()
+    };
+    let sent = page_ended(forwarding);
+    let received = page_ended(receiving);
+    assert_eq!(sent.len(), 1, "Expected one forwarded page");
+    assert_eq!(received.len(), 1, "Expected one page at the forward destination");
+
+    let forward = &sent[0]["forward"];
+    assert_eq!(forward["destination"], "224.0.123.52:15052");
+    assert_eq!(forward["codec"], "G.711 A-law");
+    assert_eq!(forward["converted_from"], "G.711 u-law");
+    assert_eq!(forward["send_failures"], 0);
+    let packets = forward["packets_forwarded"].as_u64().unwrap();
+    assert!((148..=152).contains(&packets), "Expected about 150 packets forwarded, sent {}", packets);
+
+    // The same page, in the new codec
+    assert_eq!(received[0]["total_packets"].as_u64(), Some(packets));
+    let frequency = received[0]["dominant_freq_hz"].as_f64().unwrap();
+    assert!((900.0..=1100.0).contains(&frequency), "Expected a 1kHz tone, measured {}Hz", frequency);
+    assert_eq!(received[0]["dominant_freq_hz"], sent[0]["dominant_freq_hz"]);
+    let duration = received[0]["duration_secs"].as_f64().unwrap();
+    assert!((duration - 3.0).abs() < 0.1, "Expected about 3s, received {}s", duration);
+}
+
+/// Run the binary with `ulimit` options applied first, as a login limit would be
+#[cfg(unix)]
+fn command_with_ulimit(binary: &Path, ulimit: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.args(["-c", &format!("ulimit {} && exec \"$0\" \"$@\"", ulimit)]).arg(binary);
+    command
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fd_limit_too_low() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("pages");
+
+    // 100 ports, each recording a page, can't fit in 64 files
+    let output = command_with_ulimit(&binary, "-n 64")
+        .args([
+            "monitor",
+            "--address", "224.0.123.53:{15100-15199}",
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "1",
+        ])
+        .output()
+        .expect("Failed to run monitor");
+
+    assert!(!output.status.success(), "Monitor should refuse to start");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("monitor needs up to 217 open files (100 sockets, 100 page files, 17 other), but only 64 are allowed"),
+        "Expected the planned and allowed counts, got: {}",
+        stderr
+    );
+    assert!(stderr.contains("Raise the hard open file limit (64) to at least 217"), "Expected a hint, got: {}", stderr);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fd_limit_raised_to_hard_limit() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    // Only the soft limit is low; the test raises it and runs
+    let output = command_with_ulimit(&binary, "-Sn 64")
+        .args([
+            "test",
+            "--address", "224.0.123.54:{15200-15299}",
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "2",
+            "--codec", "g711ulaw",
+        ])
+        .output()
+        .expect("Failed to run test");
+
+    assert!(
+        output.status.success(),
+        "Test should raise its open file limit and run: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    assert_eq!(summary["pages"].as_array().expect("pages should be array").len(), 0);
+}
+
+#[test]
+fn test_review_anonymize() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    let shared_dir = temp_dir.path().join("shared");
+    fs::create_dir_all(&output_dir).expect("Failed to create output dir");
+
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 1.0, 8000);
+
+    let multicast_addr = "224.0.123.55";
+    let port = "15055";
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "6",
+            "--codec", "g711ulaw",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let status = Command::new(&binary)
+        .args(["transmit", "--file", wav_path.to_str().unwrap(), "--address", multicast_addr, "--port", port, "--quiet"])
+        .status()
+        .expect("Failed to run transmit");
+    assert!(status.success(), "Transmit command failed");
+    monitor.wait_with_output().expect("Failed to wait for monitor");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    assert_eq!(summary["pages"].as_array().map(Vec::len), Some(1), "Expected one page");
+    let source = summary["pages"][0]["sender_fingerprint"]["source"].as_str().unwrap().to_string();
+    let source = source.split(':').next().unwrap().to_string();
+
+    let review = Command::new(&binary)
+        .args([
+            "review",
+            "--directory", output_dir.to_str().unwrap(),
+            "--anonymize",
+            "--output", shared_dir.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run review");
+    let stdout = String::from_utf8_lossy(&review.stdout);
+    assert!(review.status.success(), "Anonymize failed: {}", String::from_utf8_lossy(&review.stderr));
+    assert!(stdout.contains("1 recording(s) replaced"), "{}", stdout);
+
+    // Neither the group nor the sender shows up anywhere, dotted or in a file name
+    let mut forms = Vec::new();
+    for address in [multicast_addr, source.as_str()] {
+        forms.push(address.to_string());
+        forms.push(address.replace('.', "_"));
+    }
+    let mut pending = vec![shared_dir.clone()];
+    while let Some(directory) = pending.pop() {
+        for entry in fs::read_dir(&directory).expect("Failed to list copy") {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            for form in &forms {
+                assert!(!name.contains(form.as_str()), "{} is in the name {}", form, name);
+            }
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let contents = String::from_utf8_lossy(&fs::read(&path).unwrap()).into_owned();
+            for form in &forms {
+                assert!(!contents.contains(form.as_str()), "{} is in {}", form, name);
+            }
+        }
+    }
+
+    let shared = parse_summary(&shared_dir.join("summary.json"));
+    assert_eq!(shared["test_metadata"]["anonymization"]["recordings"], 1);
+    let original = &summary["pages"][0];
+    let copy = &shared["pages"][0];
+    assert_eq!(copy["endpoint"], "239.255.0.1:15055");
+    assert_eq!(copy["network"], original["network"]);
+    let recording = shared_dir.join(copy["recording_file"].as_str().unwrap());
+    let samples = |path: &Path| hound::WavReader::open(path).unwrap().samples::<i16>().count();
+    assert_eq!(samples(&recording), samples(&output_dir.join(original["recording_file"].as_str().unwrap())));
+
+    // The copy's checksums are for its own recordings
+    let verify = Command::new(&binary)
+        .args(["review", "--directory", shared_dir.to_str().unwrap(), "--verify"])
+        .output()
+        .expect("Failed to run review");
+    assert!(verify.status.success(), "{}", String::from_utf8_lossy(&verify.stdout));
+}
+
+/// Send three seconds of G.722 tone through `test`, the sender's timestamps
+/// ticking at 16kHz if `nonstandard`. Returns the page and what `test` printed
+fn g722_loopback(multicast_addr: &str, port: &str, nonstandard: bool) -> Option<(serde_json::Value, String)> {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return None;
+    }
+    if !ffmpeg_available() {
+        eprintln!("Skipping test: ffmpeg not available");
+        return None;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 3.0, 16000);
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "8",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+    thread::sleep(Duration::from_secs(2));
+
+    let mut transmit = Command::new(&binary);
+    transmit.args([
+        "transmit",
+        "--file", wav_path.to_str().unwrap(),
+        "--address", multicast_addr,
+        "--port", port,
+        "--codec", "g722",
+        "--quiet",
+    ]);
+    if nonstandard {
+        transmit.arg("--g722-nonstandard-timestamps");
+    }
+    assert!(transmit.status().expect("Failed to run transmit").success(), "Transmit command failed");
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1, "Should detect exactly 1 page");
+    let page = pages[0].clone();
+
+    // Timestamps read at the wrong rate would pad the recording with silence
+    let recording = page["recording_file"].as_str().expect("recording_file should be set");
+    let reader = hound::WavReader::open(output_dir.join(recording)).expect("Failed to open recording");
+    let seconds = f64::from(reader.duration()) / f64::from(reader.spec().sample_rate);
+    assert!((2.9..=3.2).contains(&seconds), "Recording {}s should be ~3s", seconds);
+    let duration = page["duration_secs"].as_f64().expect("duration should be f64");
+    assert!((2.5..=3.5).contains(&duration), "Duration {} should be approximately 3 seconds", duration);
+    let jitter = page["network"]["jitter_ms"].as_f64().expect("jitter_ms should be f64");
+    assert!(jitter < 5.0, "Jitter {}ms should be near zero", jitter);
+    let late: u64 = page["jitter_buffer_analysis"]
+        .as_array()
+        .expect("jitter_buffer_analysis should be array")
+        .iter()
+        .filter(|result| result["depth_ms"].as_u64() >= Some(60))
+        .map(|result| result["late_packets"].as_u64().unwrap())
+        .sum();
+    assert_eq!(late, 0, "No packet should miss a 60ms buffer: {}", page["jitter_buffer_analysis"]);
+
+    Some((page, String::from_utf8_lossy(&monitor_output.stdout).to_string()))
+}
+
+#[test]
+fn test_g722_rfc3551_timestamps() {
+    let Some((_, stdout)) = g722_loopback("224.0.123.56", "15056", false) else {
+        return;
+    };
+    assert!(!stdout.contains("G.722 timestamps tick"), "{}", stdout);
+}
+
+#[test]
+fn test_g722_nonstandard_timestamps_detected() {
+    let Some((_, stdout)) = g722_loopback("224.0.123.57", "15057", true) else {
+        return;
+    };
+    assert!(stdout.contains("G.722 timestamps tick at 16000Hz, not the 8000Hz of RFC 3551"), "{}", stdout);
+}
+
+#[test]
+fn test_log_file_ignores_quiet() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let log_path = temp_dir.path().join("monitor.log");
+    let output = Command::new(&binary)
+        .args(["monitor", "--address", "224.0.123.58", "--timeout", "1", "-q"])
+        .args(["--log-file", log_path.to_str().unwrap()])
+        .env("MPU_PORT", "15058")
+        .output()
+        .expect("Failed to run monitor");
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("from environment"), "-q should quiet the console: {}", stdout);
+
+    // The file still gets info lines, as JSON
+    let log = fs::read_to_string(&log_path).expect("Failed to read log file");
+    let lines: Vec<serde_json::Value> = log.lines().map(|line| serde_json::from_str(line).expect("log line should be JSON")).collect();
+    let setting = lines
+        .iter()
+        .find(|line| line["fields"]["message"] == "--port 15058 (from environment)")
+        .unwrap_or_else(|| panic!("no setting line in {}", log));
+    assert_eq!(setting["level"], "INFO");
+    assert_eq!(setting["target"], "multicast_paging_utility");
+
+    // A directive that doesn't parse is refused up front
+    let output = Command::new(&binary)
+        .args(["monitor", "--address", "224.0.123.58", "--log-filter", "polycom=loud"])
+        .output()
+        .expect("Failed to run monitor");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--log-filter"));
+}
+
+/// Send a watermarked tone with 2% of packets dropped and return the page
+/// `test` summarized
+fn watermark_loopback(multicast_addr: &str, port: &str, codec: &str, sample_rate: u32) -> Option<serde_json::Value> {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return None;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 4.0, sample_rate);
+
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "9",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit_status = Command::new(&binary)
+        .args([
+            "transmit",
+            "--file", wav_path.to_str().unwrap(),
+            "--address", multicast_addr,
+            "--port", port,
+            "--codec", codec,
+            "--watermark", "4660",
+            "--drop-percent", "2",
+            "--impair-seed", "7",
+            "--quiet",
+        ])
+        .status()
+        .expect("Failed to run transmit");
+    assert!(transmit_status.success(), "Transmit command failed");
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1, "Should detect exactly 1 page");
+    Some(pages[0].clone())
+}
+
+#[test]
+fn test_watermark_through_g711_with_loss() {
+    let Some(page) = watermark_loopback("224.0.123.59", "15059", "g711ulaw", 8000) else {
+        return;
+    };
+    let watermark = &page["audio"]["watermark"];
+    assert_eq!(watermark["id"], 4660, "{}", page["audio"]);
+    let confidence = watermark["confidence"].as_f64().expect("confidence should be f64");
+    assert!(confidence >= 0.5, "Confidence {} too low", confidence);
+}
+
+#[test]
+fn test_watermark_through_g722_with_loss() {
+    if !ffmpeg_available() {
+        eprintln!("Skipping test: ffmpeg not available");
+        return;
+    }
+    let Some(page) = watermark_loopback("224.0.123.60", "15060", "g722", 16000) else {
+        return;
+    };
+    let watermark = &page["audio"]["watermark"];
+    assert_eq!(watermark["id"], 4660, "{}", page["audio"]);
+    let confidence = watermark["confidence"].as_f64().expect("confidence should be f64");
+    assert!(confidence >= 0.5, "Confidence {} too low", confidence);
+}
+
+#[test]
+fn test_monitor_status_page() {
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let wav_path = temp_dir.path().join("tone.wav");
+    generate_test_wav(&wav_path, 1000, 4.0, 8000);
+
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let monitor = Command::new(&binary)
+        .args([
+            "monitor",
+            "--address", "224.0.123.53:15053 idle=1s",
+            "--timeout", "10",
+            "--status-listen", &port.to_string(),
+            "--quiet",
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let get = |path: &str| -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).expect("Status page not listening");
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        response.split_once("\r\n\r\n").unwrap().1.to_string()
+    };
+
+    let transmit = Command::new(&binary)
+        .args(["transmit", "--file", wav_path.to_str().unwrap(), "--address", "224.0.123.53", "--port", "15053", "--quiet"])
+        .spawn()
+        .expect("Failed to run transmit");
+    thread::sleep(Duration::from_secs(2));
+
+    // Mid-page, the endpoint is active and counting
+    let status: serde_json::Value = serde_json::from_str(&get("/api/status")).expect("Expected JSON status");
+    let endpoint = &status["endpoints"][0];
+    assert_eq!(endpoint["address"], "224.0.123.53");
+    assert_eq!(endpoint["port"], 15053);
+    assert_eq!(endpoint["page_active"], true, "{}", status);
+    assert!(endpoint["packets"].as_u64().unwrap() > 0, "{}", status);
+
+    let html = get("/");
+    assert!(html.contains("224.0.123.53:15053"), "{}", html);
+    assert!(html.contains(env!("CARGO_PKG_VERSION")), "Expected the version in the footer");
+
+    assert!(transmit.wait_with_output().unwrap().status.success(), "Transmit command failed");
+    thread::sleep(Duration::from_millis(1500));
+
+    // Once it has ended, it's the last page
+    let status: serde_json::Value = serde_json::from_str(&get("/api/status")).unwrap();
+    let endpoint = &status["endpoints"][0];
+    assert_eq!(endpoint["page_active"], false, "{}", status);
+    let packets = endpoint["last_page"]["packets"].as_u64().unwrap();
+    assert!((198..=202).contains(&packets), "Expected about 200 packets, got {}", packets);
+
+    let output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(output.status.success(), "Monitor command failed: {}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn test_verify_chain() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("chain");
+
+    let output = Command::new(&binary)
+        .args([
+            "verify-chain",
+            "--codec", "g711ulaw,l24",
+            "--address", "224.0.123.60",
+            "--port", "15060",
+            "--output", output_dir.to_str().unwrap(),
+            "--json",
+        ])
+        .output()
+        .expect("Failed to run verify-chain");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "verify-chain failed: {}", stdout);
+
+    let report: serde_json::Value = serde_json::from_str(&stdout).expect("Failed to parse report");
+    assert_eq!(report["passed"], 2);
+    for result in report["codecs"].as_array().unwrap() {
+        assert_eq!(result["outcome"], "pass", "{}", result);
+        let checks: Vec<_> = result["checks"].as_array().unwrap().iter().map(|check| check["check"].clone()).collect();
+        assert_eq!(checks, ["duration", "glitches", "snr", "tones", "packets", "loss", "summary"]);
+    }
+
+    // Each codec's run is kept where it was asked to be
+    assert_eq!(report["results_dir"], output_dir.to_str().unwrap());
+    for codec in ["g711ulaw", "l24"] {
+        assert!(output_dir.join(codec).join("results").join("summary.json").exists());
+        assert!(output_dir.join(codec).join("test.log").exists());
+    }
+
+    let output = Command::new(&binary)
+        .args(["verify-chain", "--codec", "g711ulaw,mp3"])
+        .output()
+        .expect("Failed to run verify-chain");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown codec 'mp3'"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_pipe_audio_cmd() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let wav_path = temp_dir.path().join("tone.wav");
+    let output_dir = temp_dir.path().join("output");
+    let piped = temp_dir.path().join("piped.raw");
+    generate_test_wav(&wav_path, 1000, 1.0, 8000);
+    let multicast_addr = "224.0.123.61";
+    let port = "15061";
+
+    let command = format!("cat > '{}' && echo heard {{rate}} Hz x {{channels}}", piped.display());
+    let monitor = Command::new(&binary)
+        .args([
+            "test",
+            "--address", multicast_addr,
+            "--port", port,
+            "--output", output_dir.to_str().unwrap(),
+            "--timeout", "5",
+            "--pipe-audio-cmd", &command,
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit_status = Command::new(&binary)
+        .args(["transmit", "--file", wav_path.to_str().unwrap(), "--address", multicast_addr, "--port", port, "--quiet"])
+        .status()
+        .expect("Failed to run transmit");
+    assert!(transmit_status.success(), "Transmit command failed");
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1);
+    let pipe = &pages[0]["pipe_output"];
+    assert_eq!(pipe["output"], "heard 8000 Hz x 1\n");
+    assert_eq!(pipe["exit_code"], 0);
+    assert_eq!(pipe["samples_dropped"], 0);
+
+    // The command heard exactly what was recorded
+    let recording = pages[0]["recording_file"].as_str().unwrap();
+    let mut reader = hound::WavReader::open(output_dir.join(recording)).expect("Failed to open recording");
+    let recorded: Vec<u8> = reader.samples::<i16>().flat_map(|s| s.unwrap().to_le_bytes()).collect();
+    assert!(!recorded.is_empty());
+    assert_eq!(pipe["samples_piped"], recorded.len() as u64 / 2);
+    assert_eq!(fs::read(&piped).unwrap(), recorded);
+}
+
+#[test]
+fn test_monitor_output_file_per_page() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).unwrap();
+    let tones = [temp_dir.path().join("low.wav"), temp_dir.path().join("high.wav")];
+    generate_test_wav(&tones[0], 500, 1.0, 8000);
+    generate_test_wav(&tones[1], 1500, 1.0, 8000);
+
+    let monitor = Command::new(&binary)
+        .args([
+            "monitor",
+            "--address", "224.0.123.62:15062 idle=1s",
+            "--output", output_dir.join("lobby.wav").to_str().unwrap(),
+            "--timeout", "8",
+            "--json",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+
+    thread::sleep(Duration::from_secs(2));
+
+    for tone in &tones {
+        let transmit_status = Command::new(&binary)
+            .args(["transmit", "--file", tone.to_str().unwrap(), "--address", "224.0.123.62", "--port", "15062", "--quiet"])
+            .status()
+            .expect("Failed to run transmit");
+        assert!(transmit_status.success(), "Transmit command failed");
+        thread::sleep(Duration::from_millis(1500));
+    }
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    let events: Vec<serde_json::Value> = String::from_utf8_lossy(&monitor_output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let paths = |event: &str| -> Vec<PathBuf> {
+        events
+            .iter()
+            .filter(|e| e["event"] == event)
+            .map(|e| PathBuf::from(e["path"].as_str().unwrap()))
+            .collect()
+    };
+    let saved = paths("recording_saved");
+    assert_eq!(saved.len(), 2, "Expected a recording per page");
+    assert_eq!(paths("recording_finalized"), saved);
+    assert_ne!(saved[0], saved[1]);
+    for (page, path) in saved.iter().enumerate() {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with(&format!("lobby_{:04}_", page + 1)), "{}", name);
+    }
+    assert!(!output_dir.join("lobby.wav").exists());
+
+    // Each file has its own page: the higher tone crosses zero more often
+    let crossings = |path: &Path| {
+        let samples: Vec<i16> = hound::WavReader::open(path).unwrap().samples().map(Result::unwrap).collect();
+        assert!(samples.len() > 4000);
+        samples.windows(2).filter(|pair| (pair[0] < 0) != (pair[1] < 0)).count()
+    };
+    assert!(crossings(&saved[1]) > 2 * crossings(&saved[0]));
+}
+
+/// A voice-like buzz: harmonics of 150Hz up to `top_hz`, falling off 6dB an
+/// octave, swelling and fading four times a second
+fn write_buzz_wav(path: &Path, sample_rate: u32, top_hz: f64, duration_secs: f64) {
+    let harmonics: Vec<f64> = (1..=(top_hz / 150.0) as u32).map(|h| 150.0 * f64::from(h)).collect();
+    let spec = hound::WavSpec { channels: 1, sample_rate, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+    let mut writer = hound::WavWriter::create(path, spec).expect("Failed to create WAV file");
+    for i in 0..(f64::from(sample_rate) * duration_secs) as usize {
+        let t = i as f64 / f64::from(sample_rate);
+        let swell = 0.6 + 0.4 * (2.0 * std::f64::consts::PI * 4.0 * t).sin();
+        let sum: f64 = harmonics.iter().map(|&f| (2.0 * std::f64::consts::PI * f * t).sin() * 150.0 / f).sum();
+        writer.write_sample((sum * swell * 6000.0) as i16).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+/// Monitor two pages sent by `send`, a wideband buzz and then the same
+/// through an 8kHz u-law hop brought back up to 16kHz, and return their
+/// `page_ended` events
+fn narrowband_hop_pages(pattern: &str, codec: Option<&str>, send: impl Fn(&Path)) -> Option<Vec<serde_json::Value>> {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return None;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let wide_path = temp_dir.path().join("wide.wav");
+    let narrow_path = temp_dir.path().join("narrow.wav");
+    let hop_path = temp_dir.path().join("hop.wav");
+    let hop16_path = temp_dir.path().join("hop16.wav");
+    write_buzz_wav(&wide_path, 16000, 7000.0, 3.0);
+    write_buzz_wav(&narrow_path, 8000, 3400.0, 3.0);
+    for args in [
+        ["--input", narrow_path.to_str().unwrap(), "--output", hop_path.to_str().unwrap(), "--codec-roundtrip", "g711ulaw"],
+        ["--input", hop_path.to_str().unwrap(), "--output", hop16_path.to_str().unwrap(), "--rate", "16000"],
+    ] {
+        let status = Command::new(&binary).arg("convert").args(args).stdout(Stdio::null()).status().expect("Failed to run convert");
+        assert!(status.success(), "Convert command failed");
+    }
+
+    let mut command = Command::new(&binary);
+    command.args(["monitor", "--address", pattern, "--timeout", "14", "--json"]);
+    if let Some(codec) = codec {
+        command.args(["--codec", codec]);
+    }
+    let monitor = command.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().expect("Failed to start monitor");
+    thread::sleep(Duration::from_secs(2));
+
+    send(&wide_path);
+    thread::sleep(Duration::from_secs(2));
+    send(&hop16_path);
+
+    let output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(output.status.success(), "Monitor command failed");
+    let pages: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|event| event["event"] == "page_ended")
+        .collect();
+    assert_eq!(pages.len(), 2, "Expected a wideband page and a narrowband one");
+    Some(pages)
+}
+
+fn assert_narrowband_hop_flagged(pages: &[serde_json::Value]) {
+    let (wide, hop) = (&pages[0], &pages[1]);
+    assert!(wide["band_split"]["high_band_db"].as_f64().unwrap() > -30.0, "{}", wide["band_split"]);
+    assert_eq!(wide.get("suspected_narrowband_transcoding"), None, "{}", wide["band_split"]);
+    assert!(hop["band_split"]["high_band_db"].as_f64().unwrap() < -40.0, "{}", hop["band_split"]);
+    assert_eq!(hop["suspected_narrowband_transcoding"], true, "{}", hop["band_split"]);
+}
+
+#[test]
+fn test_narrowband_transcoding_l16() {
+    use std::net::UdpSocket;
+    use std::time::Instant;
+
+    // transmit sends L16 as 8kHz telephony audio, so the 16kHz pages go out
+    // by hand in 20ms packets on dynamic PT 96
+    let send = |path: &Path| {
+        let samples: Vec<i16> = hound::WavReader::open(path).unwrap().samples().map(Result::unwrap).collect();
+        let socket = UdpSocket::bind("0.0.0.0:0").expect("Failed to bind sender");
+        socket.set_multicast_loop_v4(true).unwrap();
+        socket.set_multicast_ttl_v4(1).unwrap();
+        let start = Instant::now();
+        for (packet, frame) in samples.chunks(320).enumerate() {
+            let packet = packet as u32;
+            let mut data = vec![0x80, 96];
+            data.extend_from_slice(&(packet as u16).to_be_bytes());
+            data.extend_from_slice(&(packet * 320).to_be_bytes());
+            data.extend_from_slice(&0x1624_0001u32.to_be_bytes());
+            for sample in frame {
+                data.extend_from_slice(&sample.to_be_bytes());
+            }
+            socket.send_to(&data, "224.0.123.63:15063").expect("Failed to send packet");
+
+            let due = start + Duration::from_millis(20 * u64::from(packet + 1));
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+        }
+    };
+    let Some(pages) = narrowband_hop_pages("224.0.123.63:15063 idle=1s", Some("l16:16000:1"), send) else {
+        return;
+    };
+    assert_narrowband_hop_flagged(&pages);
+}
+
+#[test]
+fn test_narrowband_transcoding_g722() {
+    if !ffmpeg_available() {
+        eprintln!("Skipping test: ffmpeg not available");
+        return;
+    }
+    let send = |path: &Path| {
+        let status = Command::new(binary_path())
+            .args(["transmit", "--file", path.to_str().unwrap(), "--address", "224.0.123.64", "--port", "15064", "--codec", "g722", "--quiet"])
+            .status()
+            .expect("Failed to run transmit");
+        assert!(status.success(), "Transmit command failed");
+    };
+    let Some(pages) = narrowband_hop_pages("224.0.123.64:15064 idle=1s", None, send) else {
+        return;
+    };
+    assert_narrowband_hop_flagged(&pages);
+}
+
+#[test]
+fn test_stitch_gap_carries_page_across_ssrc_change() {
+    use std::net::UdpSocket;
+    use std::time::Instant;
+
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let prefix = temp_dir.path().join("page.wav");
+    let monitor = Command::new(&binary)
+        .args(["monitor", "--address", "224.0.123.65:15065 idle=1s", "--timeout", "8", "--json", "--stitch-gap-ms", "500"])
+        .args(["--output", prefix.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+    thread::sleep(Duration::from_secs(2));
+
+    // A 1s chime and 2s of speech from the same socket, as a server that
+    // restarts its sender in between: new SSRC, sequence and timestamps
+    let socket = UdpSocket::bind("0.0.0.0:0").expect("Failed to bind sender");
+    socket.set_multicast_loop_v4(true).unwrap();
+    socket.set_multicast_ttl_v4(1).unwrap();
+    let send = |ssrc: u32, first_sequence: u16, packets: u16| {
+        let start = Instant::now();
+        for packet in 0..packets {
+            let sequence = first_sequence.wrapping_add(packet);
+            let mut data = vec![0x80, 0];
+            data.extend_from_slice(&sequence.to_be_bytes());
+            data.extend_from_slice(&(u32::from(sequence) * 160).to_be_bytes());
+            data.extend_from_slice(&ssrc.to_be_bytes());
+            data.extend_from_slice(&[0x20; 160]);
+            socket.send_to(&data, "224.0.123.65:15065").expect("Failed to send packet");
+
+            let due = start + Duration::from_millis(20 * u64::from(packet + 1));
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+        }
+    };
+    send(0x0c41_0001, 100, 50);
+    thread::sleep(Duration::from_millis(150));
+    send(0x5bee_0002, 30000, 100);
+
+    let output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(output.status.success(), "Monitor command failed");
+    let events: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .collect();
+    let of = |kind: &str| events.iter().filter(|event| event["event"] == kind).collect::<Vec<_>>();
+
+    // One page, stitched once
+    let pages = of("page_ended");
+    assert_eq!(pages.len(), 1, "Expected the chime and speech as one page");
+    assert_eq!(of("page_started").len(), 1);
+    let changed = of("ssrc_changed");
+    assert_eq!(changed.len(), 1);
+    assert_eq!(changed[0]["old_ssrc"], 0x0c41_0001);
+    assert_eq!(changed[0]["new_ssrc"], 0x5bee_0002);
+
+    let page = pages[0];
+    assert_eq!(page["total_packets"], 150);
+    let change = &page["ssrc_changes"][0];
+    assert_eq!(change["new_ssrc"], 0x5bee_0002);
+    let gap_ms = change["gap_ms"].as_f64().unwrap();
+    assert!((140.0..400.0).contains(&gap_ms), "gap {}ms", gap_ms);
+    let offset = change["offset_secs"].as_f64().unwrap();
+    assert!((1.1..1.5).contains(&offset), "offset {}s", offset);
+    let duration = page["duration_secs"].as_f64().unwrap();
+    assert!((3.1..3.6).contains(&duration), "duration {}s", duration);
+
+    // The recording runs across the gap: both streams' audio and the silence between
+    let saved = of("recording_saved");
+    assert_eq!(saved.len(), 1);
+    let reader = hound::WavReader::open(saved[0]["path"].as_str().unwrap()).expect("Failed to open recording");
+    let recorded = f64::from(reader.duration()) / 8000.0;
+    assert!((recorded - duration).abs() < 0.15, "recorded {}s of a {}s page", recorded, duration);
+}
+
+#[test]
+fn test_observe_igmp_diagnoses_empty_run() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output = Command::new(&binary)
+        .args(["test", "--address", "224.0.123.66:15066", "--timeout", "3", "--observe-igmp"])
+        .args(["--output", temp_dir.path().to_str().unwrap()])
+        .output()
+        .expect("Failed to run test");
+    assert!(output.status.success(), "Test command failed");
+
+    let summary = parse_summary(&temp_dir.path().join("summary.json"));
+    let igmp = &summary["test_metadata"]["igmp"];
+    let errors: Vec<&str> = summary["errors"].as_array().unwrap().iter().filter_map(|e| e.as_str()).collect();
+
+    // Without CAP_NET_RAW the run goes ahead unwatched, and says so
+    if let Some(reason) = igmp["unavailable"].as_str() {
+        assert!(errors.contains(&format!("IGMP health: not observed ({})", reason).as_str()), "{:?}", errors);
+        return;
+    }
+    // The join was seen leaving; no sender, so nothing came back
+    let endpoint = &igmp["endpoints"]["224.0.123.66:15066"];
+    assert!(endpoint["reports_sent"].as_u64().unwrap() >= 1, "{}", igmp);
+    assert!(errors.iter().any(|e| e.starts_with("IGMP health for 224.0.123.66:15066: joins sent")), "{:?}", errors);
+}
+
+/// FFTs run on analysis threads still reach the page's figures: the tone's
+/// frequency and its classification both come from them
+#[test]
+fn test_analysis_threads() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    let wav_path = temp_dir.path().join("tone_1khz.wav");
+    generate_test_wav(&wav_path, 1000, 2.0, 8000);
+
+    let monitor = Command::new(&binary)
+        .args(["test", "--address", "224.0.123.67:15067", "--timeout", "6", "--analysis-threads", "2"])
+        .args(["--output", output_dir.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+    thread::sleep(Duration::from_secs(2));
+
+    let transmit_status = Command::new(&binary)
+        .args(["transmit", "--file", wav_path.to_str().unwrap(), "--address", "224.0.123.67", "--port", "15067", "--quiet"])
+        .status()
+        .expect("Failed to run transmit");
+    assert!(transmit_status.success(), "Transmit command failed");
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1, "Should detect exactly 1 page");
+    let freq = pages[0]["audio"]["dominant_freq_hz"].as_f64().expect("freq should be f64");
+    assert!((900.0..=1100.0).contains(&freq), "Dominant frequency {} should be approximately 1000 Hz", freq);
+    assert_eq!(pages[0]["audio"]["content_class"], "tone");
+}
+
+/// Power of `freq_hz` in a block of samples (Goertzel)
+fn tone_power(samples: &[i16], freq_hz: f64, sample_rate: u32) -> f64 {
+    let coeff = 2.0 * (2.0 * std::f64::consts::PI * freq_hz / f64::from(sample_rate)).cos();
+    let (mut s1, mut s2) = (0.0, 0.0);
+    for &sample in samples {
+        let s0 = f64::from(sample) + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+    }
+    s1 * s1 + s2 * s2 - coeff * s1 * s2
+}
+
+/// A page sent with the built-in ding opens with its two notes, then the
+/// file's tone after the gap, and the monitor names the chime it heard
+#[test]
+fn test_transmit_with_chime() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    let wav_path = temp_dir.path().join("tone_1khz.wav");
+    generate_test_wav(&wav_path, 1000, 2.0, 8000);
+    let transmit = |extra: &[&str]| {
+        Command::new(&binary)
+            .args(["transmit", "--file", wav_path.to_str().unwrap(), "--address", "224.0.123.68", "--port", "15068"])
+            .args(["--chime", "builtin:ding", "--chime-gap-ms", "100", "--json"])
+            .args(extra)
+            .output()
+            .expect("Failed to run transmit")
+    };
+
+    // The plan shows the chime apart from the audio it leads
+    let dry_run = transmit(&["--dry-run"]);
+    assert!(dry_run.status.success(), "Dry run failed");
+    let plan: serde_json::Value = serde_json::from_slice(&dry_run.stdout).expect("plan should be JSON");
+    assert_eq!(plan["chime"]["chime"], "builtin:ding");
+    assert!((plan["chime"]["chime_secs"].as_f64().unwrap() - 0.75).abs() < 1e-6, "{}", plan);
+    assert!((plan["chime"]["gap_secs"].as_f64().unwrap() - 0.1).abs() < 1e-6, "{}", plan);
+    assert!((plan["audio_secs"].as_f64().unwrap() - 2.85).abs() < 0.01, "{}", plan);
+
+    let monitor = Command::new(&binary)
+        .args(["test", "--address", "224.0.123.68:15068", "--timeout", "6"])
+        .args(["--output", output_dir.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+    thread::sleep(Duration::from_secs(2));
+
+    let sent = transmit(&[]);
+    assert!(sent.status.success(), "Transmit command failed");
+    let report = String::from_utf8_lossy(&sent.stdout)
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).expect("every stdout line should be a JSON event"))
+        .find(|event| event["event"] == "transmit_report")
+        .expect("transmit_report event not found");
+    assert_eq!(report["chime"]["chime"], "builtin:ding");
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1, "The gap should stay within one page");
+    assert_eq!(pages[0]["audio"]["chime"], "ding");
+
+    // The loudest of E5, C5 and the 1 kHz tone in each 50ms of the first
+    // second, in the order they took over
+    let recording = pages[0]["recording_file"].as_str().expect("recording_file should be set");
+    let mut reader = hound::WavReader::open(output_dir.join(recording)).expect("Failed to open recording");
+    let sample_rate = reader.spec().sample_rate;
+    let samples: Vec<i16> = reader.samples::<i16>().take(sample_rate as usize).map(Result::unwrap).collect();
+    let frequencies = [659.26, 523.25, 1000.0];
+    let mut heard: Vec<f64> = Vec::new();
+    for block in samples.chunks(sample_rate as usize / 20) {
+        let powers = frequencies.map(|freq_hz| tone_power(block, freq_hz, sample_rate));
+        let (loudest, power) =
+            frequencies.iter().zip(powers).max_by(|a, b| a.1.total_cmp(&b.1)).expect("three frequencies");
+        // Blocks of the gap carry nothing worth naming
+        let energy: f64 = block.iter().map(|&s| f64::from(s).powi(2)).sum();
+        if power > energy * block.len() as f64 / 8.0 && heard.last() != Some(loudest) {
+            heard.push(*loudest);
+        }
+    }
+    assert_eq!(heard, frequencies, "first second of the recording");
+}
+
+/// Two pages to one group at once, each transmit pinned to its own SSRC:
+/// a test taking one SSRC records that stream alone and counts the other's
+/// packets as filtered
+#[test]
+fn test_ssrc_filter_pairs_transmit_and_test() {
+    let binary = binary_path();
+    if !binary.exists() {
+        eprintln!("Skipping test: binary not found at {:?}", binary);
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let output_dir = temp_dir.path().join("output");
+    let mine = temp_dir.path().join("tone_1khz.wav");
+    let theirs = temp_dir.path().join("tone_440hz.wav");
+    generate_test_wav(&mine, 1000, 2.0, 8000);
+    generate_test_wav(&theirs, 440, 2.0, 8000);
+
+    let monitor = Command::new(&binary)
+        .args(["test", "--address", "224.0.123.69:15069", "--timeout", "6", "--ssrc", "5eed0001"])
+        .args(["--output", output_dir.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start monitor");
+    thread::sleep(Duration::from_secs(2));
+
+    let transmits: Vec<_> = [(&theirs, "0x5EED0002"), (&mine, "0x5EED0001")]
+        .into_iter()
+        .map(|(wav, ssrc)| {
+            Command::new(&binary)
+                .args(["transmit", "--file", wav.to_str().unwrap(), "--address", "224.0.123.69", "--port", "15069"])
+                .args(["--ssrc", ssrc, "--quiet"])
+                .spawn()
+                .expect("Failed to start transmit")
+        })
+        .collect();
+    for mut transmit in transmits {
+        assert!(transmit.wait().expect("Failed to wait for transmit").success(), "Transmit command failed");
+    }
+
+    let monitor_output = monitor.wait_with_output().expect("Failed to wait for monitor");
+    assert!(monitor_output.status.success(), "Monitor command failed");
+
+    let summary = parse_summary(&output_dir.join("summary.json"));
+    let pages = summary["pages"].as_array().expect("pages should be array");
+    assert_eq!(pages.len(), 1, "Only the allowed stream should make a page");
+    assert_eq!(pages[0]["sender_fingerprint"]["ssrc"], 0x5EED_0001u32);
+    let freq = pages[0]["audio"]["dominant_freq_hz"].as_f64().expect("freq should be f64");
+    assert!((900.0..=1100.0).contains(&freq), "Dominant frequency {} should be approximately 1000 Hz", freq);
+
+    let filtered = summary["endpoint_totals"]["224.0.123.69:15069"]["filtered_packets"].as_u64().unwrap();
+    assert!(filtered >= 90, "The other stream's packets should be filtered, got {}", filtered);
+    assert_eq!(summary["test_metadata"]["ssrc_filter"]["allow"], serde_json::json!([0x5EED_0001u32]));
+}